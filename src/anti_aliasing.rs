@@ -0,0 +1,553 @@
+use std::{fmt, time::Duration};
+
+use glow::HasContext;
+
+use crate::{
+    diagnostics,
+    resources::{self, ResourceKind},
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    workarounds,
+};
+
+const FXAA_FRAGMENT_SRC: &str = include_str!("anti_aliasing/fxaa.frag");
+
+/// The number of samples that `AaMode::next` picks when going to an MSAA mode
+pub const DEFAULT_MSAA_SAMPLES: u32 = 4;
+
+/// How much of the sub-pixel aliasing FXAA removes, from 0 ( none ) to 1 ( softer )
+const FXAA_SUBPIXEL: f32 = 0.75;
+/// How much more contrast than its neighbours a pixel needs for FXAA to treat it as an edge
+const FXAA_EDGE_THRESHOLD: f32 = 0.166;
+/// The contrast below which FXAA leaves dark pixels alone
+const FXAA_EDGE_THRESHOLD_MIN: f32 = 0.0833;
+
+/// How the loop smooths the jagged edges of what the handler draws
+///
+/// Set `RenderSettings::anti_aliasing` to pick one. The handler then draws into one of the loop's
+/// framebuffers through `AppContext::surface_framebuffer` like it would with a virtual
+/// resolution, and the loop resolves it into the window after `draw`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AaMode {
+    #[default]
+    Off,
+    /// Draw into a multisampled framebuffer with this many samples and resolve it, which smooths
+    /// every edge but costs memory and fill rate for each sample
+    Msaa(u32),
+    /// Run FXAA over the finished image, which is cheap but blurs a little and can't bring back
+    /// details thinner than a pixel
+    Fxaa,
+    /// Multisample with this many samples, then run FXAA over the resolved image
+    MsaaPlusFxaa(u32),
+}
+
+impl AaMode {
+    /// MSAA with the given number of samples, or `Off` for 0 or 1 samples, like
+    /// `Config::msaa_samples`
+    pub fn from_msaa_samples(samples: u32) -> Self {
+        if samples > 1 {
+            AaMode::Msaa(samples)
+        } else {
+            AaMode::Off
+        }
+    }
+
+    /// The number of samples per pixel, which is 0 without multisampling
+    pub fn samples(&self) -> u32 {
+        match *self {
+            AaMode::Msaa(samples) | AaMode::MsaaPlusFxaa(samples) => samples,
+            AaMode::Off | AaMode::Fxaa => 0,
+        }
+    }
+
+    /// Whether or not FXAA runs over the image
+    pub fn uses_fxaa(&self) -> bool {
+        matches!(self, AaMode::Fxaa | AaMode::MsaaPlusFxaa(_))
+    }
+
+    /// The mode after this one, for cycling through them with a key
+    ///
+    /// The MSAA modes keep the sample count of the current mode if it has one.
+    pub fn next(self) -> Self {
+        let samples = match self.samples() {
+            0 => DEFAULT_MSAA_SAMPLES,
+            samples => samples,
+        };
+        match self {
+            AaMode::Off => AaMode::Msaa(samples),
+            AaMode::Msaa(_) => AaMode::Fxaa,
+            AaMode::Fxaa => AaMode::MsaaPlusFxaa(samples),
+            AaMode::MsaaPlusFxaa(_) => AaMode::Off,
+        }
+    }
+}
+
+impl fmt::Display for AaMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AaMode::Off => write!(f, "off"),
+            AaMode::Msaa(samples) => write!(f, "MSAA {}x", samples),
+            AaMode::Fxaa => write!(f, "FXAA"),
+            AaMode::MsaaPlusFxaa(samples) => write!(f, "MSAA {}x + FXAA", samples),
+        }
+    }
+}
+
+/// A multisampled framebuffer with a color and a depth and stencil renderbuffer
+#[derive(Debug)]
+struct MsaaTarget {
+    framebuffer: u32,
+    color: u32,
+    depth_stencil: u32,
+}
+
+impl MsaaTarget {
+    fn new(
+        gl: &mut glow::Context,
+        (width, height): (u32, u32),
+        samples: u32,
+        depth_format: u32,
+    ) -> Self {
+        unsafe {
+            let color = gl.create_renderbuffer().unwrap();
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(color));
+            gl.renderbuffer_storage_multisample(
+                glow::RENDERBUFFER,
+                samples as i32,
+                glow::RGBA8,
+                width as i32,
+                height as i32,
+            );
+            let depth_stencil = gl.create_renderbuffer().unwrap();
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth_stencil));
+            gl.renderbuffer_storage_multisample(
+                glow::RENDERBUFFER,
+                samples as i32,
+                depth_format,
+                width as i32,
+                height as i32,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::RENDERBUFFER,
+                Some(color),
+            );
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_STENCIL_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(depth_stencil),
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                diagnostics::report_problem("The MSAA framebuffer is incomplete");
+            }
+
+            let label = format!("MSAA {}x {}x{}", samples, width, height);
+            let bytes = |format| resources::texture_bytes(width, height, format, 1, 1, samples);
+            resources::track_sized(
+                ResourceKind::Renderbuffer,
+                color,
+                &label,
+                bytes(glow::RGBA8),
+            );
+            resources::track_sized(
+                ResourceKind::Renderbuffer,
+                depth_stencil,
+                &label,
+                bytes(depth_format),
+            );
+            resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+
+            Self {
+                framebuffer,
+                color,
+                depth_stencil,
+            }
+        }
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_renderbuffer(self.color);
+            gl.delete_renderbuffer(self.depth_stencil);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Renderbuffer, self.color);
+        resources::untrack(ResourceKind::Renderbuffer, self.depth_stencil);
+    }
+}
+
+/// The framebuffer that FXAA reads from, with a color texture, and a depth and stencil buffer of
+/// the given format if the handler draws straight into it
+#[derive(Debug)]
+struct FxaaTarget {
+    framebuffer: u32,
+    texture: u32,
+    depth_stencil: Option<u32>,
+}
+
+impl FxaaTarget {
+    fn new(gl: &mut glow::Context, (width, height): (u32, u32), depth: Option<u32>) -> Self {
+        unsafe {
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+            // FXAA samples between pixels to blend across the edges
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::LINEAR as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::LINEAR as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_WRAP_S,
+                glow::CLAMP_TO_EDGE as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_WRAP_T,
+                glow::CLAMP_TO_EDGE as i32,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+
+            let label = format!("FXAA source {}x{}", width, height);
+            let depth_stencil = if let Some(depth_format) = depth {
+                let depth_stencil = gl.create_renderbuffer().unwrap();
+                gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth_stencil));
+                gl.renderbuffer_storage(
+                    glow::RENDERBUFFER,
+                    depth_format,
+                    width as i32,
+                    height as i32,
+                );
+                gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+                gl.framebuffer_renderbuffer(
+                    glow::FRAMEBUFFER,
+                    glow::DEPTH_STENCIL_ATTACHMENT,
+                    glow::RENDERBUFFER,
+                    Some(depth_stencil),
+                );
+                resources::track_sized(
+                    ResourceKind::Renderbuffer,
+                    depth_stencil,
+                    &label,
+                    resources::texture_bytes(width, height, depth_format, 1, 1, 1),
+                );
+                Some(depth_stencil)
+            } else {
+                None
+            };
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                diagnostics::report_problem("The FXAA framebuffer is incomplete");
+            }
+
+            resources::track_sized(
+                ResourceKind::Texture,
+                texture,
+                &label,
+                resources::texture_bytes(width, height, glow::RGBA8, 1, 1, 1),
+            );
+            resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+
+            Self {
+                framebuffer,
+                texture,
+                depth_stencil,
+            }
+        }
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_texture(self.texture);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Texture, self.texture);
+        if let Some(depth_stencil) = self.depth_stencil {
+            unsafe { gl.delete_renderbuffer(depth_stencil) };
+            resources::untrack(ResourceKind::Renderbuffer, depth_stencil);
+        }
+    }
+}
+
+/// The framebuffers and FXAA pass that the loop uses for `RenderSettings::anti_aliasing`
+///
+/// `prepare` makes the framebuffers for the mode and gives back the one the handler should draw
+/// into, and `resolve` turns it into the final image in the output framebuffer after `draw`.
+#[derive(Debug)]
+pub(crate) struct AntiAliasing {
+    /// The mode the framebuffers were made for, with the sample count the driver supports
+    mode: AaMode,
+    /// The mode that was asked for, to know when to make the framebuffers again
+    requested: AaMode,
+    size: (u32, u32),
+    /// The internal format of the depth and stencil buffer the handler draws into
+    depth_format: u32,
+    /// The framebuffer that the final image goes to, like the window or virtual resolution one
+    output: Option<u32>,
+    msaa: Option<MsaaTarget>,
+    fxaa: Option<FxaaTarget>,
+    /// The FXAA program and an empty vertex array for the fullscreen triangle, made the first time
+    /// FXAA is used
+    fxaa_pass: Option<(ShaderProgram, u32)>,
+    /// A timer query for the resolve and FXAA pass
+    query: Option<u32>,
+    /// Whether the query has been started and not yet read back
+    query_pending: bool,
+    gpu_time: Option<Duration>,
+}
+
+impl AntiAliasing {
+    pub fn new() -> Self {
+        Self {
+            mode: AaMode::Off,
+            requested: AaMode::Off,
+            size: (0, 0),
+            depth_format: glow::DEPTH24_STENCIL8,
+            output: None,
+            msaa: None,
+            fxaa: None,
+            fxaa_pass: None,
+            query: None,
+            query_pending: false,
+            gpu_time: None,
+        }
+    }
+
+    /// The mode that is being used, which can have fewer samples than the one that was asked for
+    pub fn mode(&self) -> AaMode {
+        self.mode
+    }
+
+    /// The last GPU time measured for the resolve and FXAA pass, if timer queries are supported
+    pub fn gpu_time(&self) -> Option<Duration> {
+        self.gpu_time
+    }
+
+    /// Make the framebuffers for a mode, depth format, and size if they changed, and return the
+    /// framebuffer that the handler should draw into. `output` is where the final image goes.
+    pub fn prepare(
+        &mut self,
+        gl: &mut glow::Context,
+        mode: AaMode,
+        depth_format: u32,
+        size: (u32, u32),
+        output: Option<u32>,
+    ) -> Option<u32> {
+        self.output = output;
+        if mode != self.requested || depth_format != self.depth_format || size != self.size {
+            self.delete_targets(gl);
+            self.requested = mode;
+            self.depth_format = depth_format;
+            self.size = size;
+            self.gpu_time = None;
+
+            // Drivers have a limit on the samples of a renderbuffer, which some overstate
+            let max_samples = workarounds::current()
+                .max_samples(unsafe { gl.get_parameter_i32(glow::MAX_SAMPLES) }.max(0) as u32);
+            let samples = mode.samples().min(max_samples);
+            if samples < mode.samples() {
+                eprintln!(
+                    "Warning: Using {} MSAA samples instead of {}, which is the most the driver supports",
+                    samples,
+                    mode.samples()
+                );
+            }
+            self.mode = match mode {
+                AaMode::Msaa(_) => AaMode::Msaa(samples),
+                AaMode::MsaaPlusFxaa(_) => AaMode::MsaaPlusFxaa(samples),
+                mode => mode,
+            };
+
+            let size = (size.0.max(1), size.1.max(1));
+            if samples > 0 {
+                self.msaa = Some(MsaaTarget::new(gl, size, samples, depth_format));
+            }
+            if mode.uses_fxaa() {
+                // The handler only draws into the FXAA framebuffer without multisampling
+                let depth = Some(depth_format).filter(|_| samples == 0);
+                self.fxaa = Some(FxaaTarget::new(gl, size, depth));
+            }
+        }
+
+        match (&self.msaa, &self.fxaa) {
+            (Some(msaa), _) => Some(msaa.framebuffer),
+            (None, Some(fxaa)) => Some(fxaa.framebuffer),
+            (None, None) => output,
+        }
+    }
+
+    /// Resolve the multisampled image and run FXAA over it, leaving the output framebuffer bound
+    pub fn resolve(&mut self, gl: &mut glow::Context, timer_query: bool) {
+        let (width, height) = (self.size.0 as i32, self.size.1 as i32);
+        unsafe {
+            let timing = timer_query && self.begin_query(gl);
+
+            // Blits are limited by the scissor box, and FXAA has to cover the whole image
+            let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+            gl.disable(glow::SCISSOR_TEST);
+
+            if let Some(msaa) = &self.msaa {
+                let resolved = match &self.fxaa {
+                    Some(fxaa) => Some(fxaa.framebuffer),
+                    None => self.output,
+                };
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(msaa.framebuffer));
+                gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, resolved);
+                gl.blit_framebuffer(
+                    0,
+                    0,
+                    width,
+                    height,
+                    0,
+                    0,
+                    width,
+                    height,
+                    glow::COLOR_BUFFER_BIT,
+                    glow::NEAREST,
+                );
+            }
+
+            if let Some(fxaa) = &self.fxaa {
+                let (program, vao) = self.fxaa_pass.get_or_insert_with(|| {
+                    let program =
+                        ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, FXAA_FRAGMENT_SRC).unwrap();
+                    let vao = gl.create_vertex_array().unwrap();
+                    resources::track(ResourceKind::VertexArray, vao, "FXAA vertex array");
+                    (program, vao)
+                });
+
+                // Keep the handler's bindings, since it may have only set them up once
+                let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+                let cull_face = gl.is_enabled(glow::CULL_FACE);
+                let blend = gl.is_enabled(glow::BLEND);
+                let current_program = gl.get_parameter_i32(glow::CURRENT_PROGRAM) as u32;
+                let active_texture = gl.get_parameter_i32(glow::ACTIVE_TEXTURE) as u32;
+                gl.active_texture(glow::TEXTURE0);
+                let texture = gl.get_parameter_i32(glow::TEXTURE_BINDING_2D) as u32;
+                gl.disable(glow::DEPTH_TEST);
+                gl.disable(glow::CULL_FACE);
+                gl.disable(glow::BLEND);
+
+                gl.bind_framebuffer(glow::FRAMEBUFFER, self.output);
+                gl.viewport(0, 0, width, height);
+                program.bind(gl);
+                program.set_uniform(gl, "source", 0);
+                program.set_uniform(gl, "inverseSize", [1. / width as f32, 1. / height as f32]);
+                program.set_uniform(gl, "subpixel", FXAA_SUBPIXEL);
+                program.set_uniform(gl, "edgeThreshold", FXAA_EDGE_THRESHOLD);
+                program.set_uniform(gl, "edgeThresholdMin", FXAA_EDGE_THRESHOLD_MIN);
+                gl.bind_texture(glow::TEXTURE_2D, Some(fxaa.texture));
+                gl.bind_vertex_array(Some(*vao));
+                gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                gl.bind_vertex_array(None);
+
+                // Put the state back the way the handler had it
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture).filter(|&id| id != 0));
+                gl.active_texture(active_texture);
+                gl.use_program(Some(current_program).filter(|&id| id != 0));
+                if depth_test {
+                    gl.enable(glow::DEPTH_TEST);
+                }
+                if cull_face {
+                    gl.enable(glow::CULL_FACE);
+                }
+                if blend {
+                    gl.enable(glow::BLEND);
+                }
+            }
+
+            if scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+            gl.bind_framebuffer(glow::FRAMEBUFFER, self.output);
+
+            if timing {
+                gl.end_query(glow::TIME_ELAPSED);
+                self.query_pending = true;
+            }
+        }
+    }
+
+    /// Collect the time from the last resolve, and start a new query if the old one is done
+    ///
+    /// Returns whether a query was started.
+    unsafe fn begin_query(&mut self, gl: &mut glow::Context) -> bool {
+        let query = match self.query {
+            Some(query) => query,
+            None => {
+                let query = gl.create_query().unwrap();
+                resources::track(ResourceKind::Query, query, "Anti-aliasing timer");
+                self.query = Some(query);
+                query
+            }
+        };
+        if self.query_pending
+            && gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) != 0
+        {
+            let nanos = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+            self.gpu_time = Some(Duration::from_nanos(nanos as u64));
+            self.query_pending = false;
+        }
+        if self.query_pending {
+            return false;
+        }
+        gl.begin_query(glow::TIME_ELAPSED, query);
+        true
+    }
+
+    /// Delete the framebuffers, but keep the FXAA program and timer query
+    fn delete_targets(&mut self, gl: &mut glow::Context) {
+        if let Some(msaa) = self.msaa.take() {
+            msaa.delete(gl);
+        }
+        if let Some(fxaa) = self.fxaa.take() {
+            fxaa.delete(gl);
+        }
+    }
+
+    /// Delete all of the GL objects
+    pub fn delete(mut self, gl: &mut glow::Context) {
+        self.delete_targets(gl);
+        if let Some((mut program, vao)) = self.fxaa_pass.take() {
+            program.delete(gl);
+            unsafe { gl.delete_vertex_array(vao) };
+            resources::untrack(ResourceKind::VertexArray, vao);
+        }
+        if let Some(query) = self.query.take() {
+            unsafe { gl.delete_query(query) };
+            resources::untrack(ResourceKind::Query, query);
+        }
+    }
+}
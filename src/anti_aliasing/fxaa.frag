@@ -0,0 +1,145 @@
+#version 330 core
+
+// FXAA 3.11 by Timothy Lottes, the PC quality version with the preset 12 search steps. It finds
+// the edges in the image by the contrast of the luma around each pixel, searches along them to
+// find where they end, and blends each pixel across the edge by how far it is from the ends.
+
+in vec2 texCoord;
+
+out vec4 color;
+
+// The resolved image, with linear filtering
+uniform sampler2D source;
+// The size of a pixel of the image in texture coordinates
+uniform vec2 inverseSize;
+// How much of the sub-pixel aliasing to remove, from 0 ( none ) to 1 ( softer )
+uniform float subpixel;
+// How much more contrast than its neighbours a pixel needs to be on an edge
+uniform float edgeThreshold;
+// The contrast below which dark pixels are never on an edge
+uniform float edgeThresholdMin;
+
+// The preset 12 search steps, in pixels
+const int SEARCH_STEPS = 5;
+const float STEP_SIZES[SEARCH_STEPS] = float[](1.0, 1.5, 2.0, 4.0, 12.0);
+
+float luma(vec4 rgba) {
+    // The image is already gamma encoded, which is what FXAA expects
+    return dot(rgba.rgb, vec3(0.299, 0.587, 0.114));
+}
+
+float lumaAt(vec2 position, vec2 offset) {
+    return luma(textureLod(source, position + offset * inverseSize, 0.0));
+}
+
+void main() {
+    vec2 position = texCoord;
+    vec4 center = textureLod(source, position, 0.0);
+    float lumaM = luma(center);
+    float lumaN = lumaAt(position, vec2(0.0, 1.0));
+    float lumaS = lumaAt(position, vec2(0.0, -1.0));
+    float lumaE = lumaAt(position, vec2(1.0, 0.0));
+    float lumaW = lumaAt(position, vec2(-1.0, 0.0));
+
+    // Leave pixels without enough contrast around them alone
+    float maxLuma = max(max(max(lumaN, lumaS), max(lumaE, lumaW)), lumaM);
+    float minLuma = min(min(min(lumaN, lumaS), min(lumaE, lumaW)), lumaM);
+    float range = maxLuma - minLuma;
+    if (range < max(edgeThresholdMin, maxLuma * edgeThreshold)) {
+        color = center;
+        return;
+    }
+
+    float lumaNW = lumaAt(position, vec2(-1.0, 1.0));
+    float lumaNE = lumaAt(position, vec2(1.0, 1.0));
+    float lumaSW = lumaAt(position, vec2(-1.0, -1.0));
+    float lumaSE = lumaAt(position, vec2(1.0, -1.0));
+
+    // How much the pixel stands out from the average of its neighbours, for the sub-pixel blend
+    float average = (2.0 * (lumaN + lumaS + lumaE + lumaW) + lumaNW + lumaNE + lumaSW + lumaSE) / 12.0;
+    float subpixelBlend = clamp(abs(average - lumaM) / range, 0.0, 1.0);
+    subpixelBlend = smoothstep(0.0, 1.0, subpixelBlend);
+    subpixelBlend = subpixelBlend * subpixelBlend * subpixel;
+
+    // Whether the edge runs horizontally or vertically
+    float horizontal = abs(lumaNW + lumaNE - 2.0 * lumaN)
+        + 2.0 * abs(lumaW + lumaE - 2.0 * lumaM)
+        + abs(lumaSW + lumaSE - 2.0 * lumaS);
+    float vertical = abs(lumaNW + lumaSW - 2.0 * lumaW)
+        + 2.0 * abs(lumaN + lumaS - 2.0 * lumaM)
+        + abs(lumaNE + lumaSE - 2.0 * lumaE);
+    bool isHorizontal = horizontal >= vertical;
+
+    // Which side of the pixel the edge is on
+    float lumaPositive = isHorizontal ? lumaN : lumaE;
+    float lumaNegative = isHorizontal ? lumaS : lumaW;
+    float gradientPositive = abs(lumaPositive - lumaM);
+    float gradientNegative = abs(lumaNegative - lumaM);
+    float stepLength = isHorizontal ? inverseSize.y : inverseSize.x;
+    float lumaEdge;
+    float gradient;
+    if (gradientPositive >= gradientNegative) {
+        lumaEdge = (lumaPositive + lumaM) * 0.5;
+        gradient = gradientPositive;
+    } else {
+        lumaEdge = (lumaNegative + lumaM) * 0.5;
+        gradient = gradientNegative;
+        stepLength = -stepLength;
+    }
+
+    // Search both ways along the edge, half a pixel over, until the luma changes too much
+    vec2 edgePosition = position;
+    vec2 edgeStep;
+    if (isHorizontal) {
+        edgePosition.y += stepLength * 0.5;
+        edgeStep = vec2(inverseSize.x, 0.0);
+    } else {
+        edgePosition.x += stepLength * 0.5;
+        edgeStep = vec2(0.0, inverseSize.y);
+    }
+    float gradientScaled = gradient * 0.25;
+
+    vec2 positivePosition = edgePosition + edgeStep * STEP_SIZES[0];
+    vec2 negativePosition = edgePosition - edgeStep * STEP_SIZES[0];
+    float lumaEndPositive = luma(textureLod(source, positivePosition, 0.0)) - lumaEdge;
+    float lumaEndNegative = luma(textureLod(source, negativePosition, 0.0)) - lumaEdge;
+    bool donePositive = abs(lumaEndPositive) >= gradientScaled;
+    bool doneNegative = abs(lumaEndNegative) >= gradientScaled;
+    for (int i = 1; i < SEARCH_STEPS && !(donePositive && doneNegative); i++) {
+        if (!donePositive) {
+            positivePosition += edgeStep * STEP_SIZES[i];
+            lumaEndPositive = luma(textureLod(source, positivePosition, 0.0)) - lumaEdge;
+            donePositive = abs(lumaEndPositive) >= gradientScaled;
+        }
+        if (!doneNegative) {
+            negativePosition -= edgeStep * STEP_SIZES[i];
+            lumaEndNegative = luma(textureLod(source, negativePosition, 0.0)) - lumaEdge;
+            doneNegative = abs(lumaEndNegative) >= gradientScaled;
+        }
+    }
+
+    // Blend towards the edge by how close the pixel is to the nearer end, but only if that end
+    // goes the other way than the pixel does from the edge
+    float distancePositive = isHorizontal
+        ? positivePosition.x - position.x
+        : positivePosition.y - position.y;
+    float distanceNegative = isHorizontal
+        ? position.x - negativePosition.x
+        : position.y - negativePosition.y;
+    bool positiveIsNearer = distancePositive < distanceNegative;
+    float nearest = min(distancePositive, distanceNegative);
+    float lumaEnd = positiveIsNearer ? lumaEndPositive : lumaEndNegative;
+    bool centerIsSmaller = lumaM - lumaEdge < 0.0;
+    float edgeBlend = (lumaEnd < 0.0) != centerIsSmaller
+        ? 0.5 - nearest / (distancePositive + distanceNegative)
+        : 0.0;
+
+    float blend = max(edgeBlend, subpixelBlend);
+    vec2 blendPosition = position;
+    if (isHorizontal) {
+        blendPosition.y += blend * stepLength;
+    } else {
+        blendPosition.x += blend * stepLength;
+    }
+    color = vec4(textureLod(source, blendPosition, 0.0).rgb, center.a);
+}
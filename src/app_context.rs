@@ -0,0 +1,507 @@
+use std::path::PathBuf;
+
+use winit::WindowId;
+
+use crate::{
+    anti_aliasing::AaMode,
+    config::Config,
+    console::Console,
+    context_report::ContextReport,
+    cursor::Cursor,
+    damage::Damage,
+    demo_state::DemoState,
+    features::Features,
+    frame_arena::FrameArena,
+    input::Input,
+    render_settings::RenderSettings,
+    theme::Theme,
+    timing::Timing,
+    viewport::{Rect, ViewportRegistry},
+    workarounds::Workarounds,
+};
+
+/// An A/B capture that a handler or the console asked for, from `request_ab_capture`
+#[derive(Debug, Clone)]
+pub(crate) struct AbCaptureRequest {
+    pub before: String,
+    pub after: String,
+    pub prefix: String,
+}
+
+/// The per-window state that the loop passes to a window's `RenderHandler`
+///
+/// Every window gets its own context so that input and timing for one window never leak into the
+/// handler of another.
+#[derive(Debug)]
+pub struct AppContext {
+    /// The id of the window that this context belongs to
+    window_id: WindowId,
+    /// The size of the window in physical pixels, clamped to a size framebuffers can be made at
+    window_size: (u32, u32),
+    /// Whether or not the window system last reported the window as zero-sized, like when it is
+    /// minimized
+    minimized: bool,
+    /// The window's hidpi factor
+    hidpi_factor: f64,
+    /// What the driver gave us when it created this window's GL context
+    context_report: ContextReport,
+    /// The optional GL features supported by this window's GL context
+    features: Features,
+    /// The driver quirks that are worked around in this window's GL context
+    workarounds: Workarounds,
+    /// The framebuffer of the window surface, or `None` for the default framebuffer
+    surface_framebuffer: Option<u32>,
+    /// The settings loaded from the config file, environment, and command line
+    config: Config,
+    /// Whether or not the handler asked for another frame
+    redraw_requested: bool,
+    /// Whether or not the window should be closed after this frame
+    close_requested: bool,
+    /// Where to save a screenshot of the next frame
+    screenshot_requested: Option<PathBuf>,
+    /// Whether to copy the next frame to the clipboard, and whether with the overlays
+    clipboard_requested: Option<bool>,
+    /// The console commands to compare the next frame with, and the file prefix to save it to
+    ab_capture_requested: Option<AbCaptureRequest>,
+    /// Whether or not to write a frame report after the next frame
+    frame_report_requested: bool,
+    /// Whether or not the handler should reload its shaders this frame
+    shader_reload_requested: bool,
+    /// The size in physical pixels that the handler asked the window to be
+    window_size_requested: Option<(u32, u32)>,
+    /// Whether or not to pretend that the window surface was lost when this frame is presented
+    surface_loss_requested: bool,
+    /// Whether or not to pretend that the driver reset the GL context after this frame
+    context_reset_requested: bool,
+    /// What the handler said changed for the frame about to be drawn
+    damage: Damage,
+    /// How many times the loop found the window surface a different size than the window without
+    /// being told about a resize
+    size_corrections: u64,
+    /// Frame timing for this window
+    pub timing: Timing,
+    /// Keyboard and mouse input for this window
+    pub input: Input,
+    /// Settings for how the loop renders this window, such as the clear color
+    pub render_settings: RenderSettings,
+    /// The console opened with the backtick key, which handlers can add commands to
+    pub console: Console,
+    /// Memory for data that only lives for one frame, which the loop resets before every `draw`
+    pub arena: FrameArena,
+    /// The views drawn in the window and their cameras, for finding the one under the cursor
+    pub viewports: ViewportRegistry,
+    /// What the handler saved the last time the window closed, which it restores in `init`
+    pub saved_state: DemoState,
+    cursor: Cursor,
+    cursor_grabbed: bool,
+}
+
+impl AppContext {
+    pub(crate) fn new(
+        window_id: WindowId,
+        window_size: (u32, u32),
+        hidpi_factor: f64,
+        context_report: ContextReport,
+        features: Features,
+        workarounds: Workarounds,
+        config: Config,
+    ) -> Self {
+        let anti_aliasing = AaMode::from_msaa_samples(config.msaa_samples);
+        let latency_mode = config.latency_mode;
+        let theme = config
+            .find_theme(&config.theme)
+            .cloned()
+            .unwrap_or_else(|| {
+                eprintln!("Warning: Unknown theme `{}`", config.theme);
+                Theme::default()
+            });
+        Self {
+            window_id,
+            window_size: features.clamp_framebuffer_size(window_size),
+            minimized: window_size.0 == 0 || window_size.1 == 0,
+            hidpi_factor,
+            context_report,
+            features,
+            workarounds,
+            surface_framebuffer: None,
+            config,
+            redraw_requested: false,
+            close_requested: false,
+            screenshot_requested: None,
+            clipboard_requested: None,
+            ab_capture_requested: None,
+            frame_report_requested: false,
+            shader_reload_requested: false,
+            window_size_requested: None,
+            surface_loss_requested: false,
+            context_reset_requested: false,
+            damage: Damage::new(),
+            size_corrections: 0,
+            timing: Timing::new(),
+            input: Input::default(),
+            render_settings: RenderSettings {
+                clear_color: Some(theme.clear_color),
+                anti_aliasing,
+                latency_mode,
+                theme,
+                ..Default::default()
+            },
+            console: Console::new(),
+            arena: FrameArena::default(),
+            viewports: ViewportRegistry::new(),
+            saved_state: DemoState::default(),
+            cursor: Cursor::default(),
+            cursor_grabbed: false,
+        }
+    }
+
+    /// The id of the window that this context belongs to
+    pub fn window_id(&self) -> WindowId {
+        self.window_id
+    }
+
+    /// The size of the window in physical pixels
+    ///
+    /// This is always at least 1x1 and at most the largest framebuffer the context can make, so
+    /// it can be used for framebuffers and aspect ratios as is. The loop doesn't draw while the
+    /// window is really zero-sized, see `is_minimized`.
+    pub fn window_size(&self) -> (u32, u32) {
+        self.window_size
+    }
+
+    /// Whether or not the window is zero-sized, like when it is minimized, in which case the loop
+    /// skips its frames and the animation time doesn't advance
+    pub fn is_minimized(&self) -> bool {
+        self.minimized
+    }
+
+    /// The size that the handler draws at in pixels, which is the virtual resolution if the render
+    /// settings have one and the window size otherwise
+    pub fn render_size(&self) -> (u32, u32) {
+        match &self.render_settings.virtual_resolution {
+            Some(resolution) => self
+                .features
+                .clamp_framebuffer_size((resolution.width, resolution.height)),
+            None => self.window_size,
+        }
+    }
+
+    /// The window's hidpi factor
+    pub fn hidpi_factor(&self) -> f64 {
+        self.hidpi_factor
+    }
+
+    /// How much to scale overlays by, which is the hidpi factor times the config's `ui_scale`
+    ///
+    /// Sizes of overlays are given in physical pixels at a scale of 1, so multiplying them by this
+    /// makes them look the same size on every monitor. It changes when the window moves to a
+    /// monitor with a different hidpi factor.
+    pub fn ui_scale(&self) -> f32 {
+        self.hidpi_factor as f32 * self.config.ui_scale
+    }
+
+    /// Scale an overlay size in pixels by the `ui_scale`, rounding to whole physical pixels
+    pub fn scale_ui(&self, pixels: i32) -> i32 {
+        (pixels as f32 * self.ui_scale()).round() as i32
+    }
+
+    /// What the driver actually gave us when it created this window's GL context
+    ///
+    /// Handlers that depend on things like a stencil buffer can check for them here in `init`.
+    pub fn context_report(&self) -> &ContextReport {
+        &self.context_report
+    }
+
+    /// The optional GL features supported by this window's GL context
+    pub fn features(&self) -> &Features {
+        &self.features
+    }
+
+    /// The driver quirks that are worked around in this window's GL context
+    pub fn workarounds(&self) -> Workarounds {
+        self.workarounds
+    }
+
+    /// The framebuffer of the window surface, or `None` for the default framebuffer
+    ///
+    /// When drawing at a virtual resolution this is the offscreen framebuffer that is copied to
+    /// the window after `draw`. The loop binds this before calling `draw`. Handlers that render to their own framebuffers
+    /// have to bind it again before drawing to the window.
+    pub fn surface_framebuffer(&self) -> Option<u32> {
+        self.surface_framebuffer
+    }
+
+    /// The settings loaded from the config file, environment, and command line
+    pub fn config(&self) -> &Config {
+        &self.config
+    }
+
+    /// The active theme, which handlers can use to match their colors to the loop's
+    pub fn theme(&self) -> &Theme {
+        &self.render_settings.theme
+    }
+
+    /// Switch to one of the config's themes by name
+    ///
+    /// The clear color changes with it, unless the handler set its own.
+    pub fn set_theme(&mut self, name: &str) -> Result<(), String> {
+        let theme = self
+            .config
+            .find_theme(name)
+            .cloned()
+            .ok_or_else(|| format!("Unknown theme `{}`", name))?;
+        let settings = &mut self.render_settings;
+        if settings.clear_color == Some(settings.theme.clear_color) {
+            settings.clear_color = Some(theme.clear_color);
+        }
+        settings.theme = theme;
+        Ok(())
+    }
+
+    /// Switch to the config's theme after the active one, returning its name
+    pub fn next_theme(&mut self) -> String {
+        let themes = &self.config.themes;
+        let index = themes
+            .iter()
+            .position(|theme| theme.name == self.render_settings.theme.name)
+            .map_or(0, |index| (index + 1) % themes.len());
+        let name = match themes.get(index) {
+            Some(theme) => theme.name.clone(),
+            None => return self.render_settings.theme.name.clone(),
+        };
+        self.set_theme(&name).unwrap();
+        name
+    }
+
+    /// Ask the loop to draw another frame, even if the redraw policy wouldn't otherwise draw one
+    ///
+    /// With `RedrawPolicy::OnEvent`, a handler can keep an animation going by calling this from
+    /// every `draw` until the animation is done.
+    pub fn request_redraw(&mut self) {
+        self.redraw_requested = true;
+    }
+
+    /// Clear the redraw request, returning whether there was one
+    pub(crate) fn take_redraw_request(&mut self) -> bool {
+        std::mem::take(&mut self.redraw_requested)
+    }
+
+    /// Mark a rect in window pixels, with the origin in the bottom-left corner, as changed in the
+    /// frame about to be drawn, from `RenderHandler::damage`
+    ///
+    /// Only used with `RenderSettings::partial_redraw`.
+    pub fn add_damage(&mut self, rect: Rect) {
+        self.damage.add(rect);
+    }
+
+    /// Mark the whole frame about to be drawn as changed
+    pub fn damage_all(&mut self) {
+        self.damage.add_full();
+    }
+
+    /// What changed in the frame about to be drawn so far
+    pub fn damage(&self) -> &Damage {
+        &self.damage
+    }
+
+    /// Clear the damage, returning what it was
+    pub(crate) fn take_damage(&mut self) -> Damage {
+        std::mem::take(&mut self.damage)
+    }
+
+    /// Close the window after this frame
+    pub fn request_close(&mut self) {
+        self.close_requested = true;
+    }
+
+    /// Clear the close request, returning whether there was one
+    pub(crate) fn take_close_request(&mut self) -> bool {
+        std::mem::take(&mut self.close_requested)
+    }
+
+    /// Save the window's contents to a PNG file after the handler draws the next frame, without
+    /// the console
+    pub fn request_screenshot(&mut self, path: PathBuf) {
+        self.screenshot_requested = Some(path);
+    }
+
+    /// Clear the screenshot request, returning where to save the screenshot if there was one
+    pub(crate) fn take_screenshot_request(&mut self) -> Option<PathBuf> {
+        self.screenshot_requested.take()
+    }
+
+    /// Copy the window's contents to the system clipboard as an image after the handler draws
+    /// the next frame, like Ctrl+F12 does, with the console and the frame graph if `overlays` is
+    /// true like Ctrl+Shift+F12
+    ///
+    /// The frame is copied a frame or two later without waiting for it, and saved to a PNG file
+    /// instead if the clipboard can't take images ( `clipboard::ClipboardCapture` ).
+    pub fn request_clipboard_copy(&mut self, overlays: bool) {
+        self.clipboard_requested = Some(overlays);
+    }
+
+    /// Clear the clipboard request, returning whether to copy with the overlays if there was one
+    pub(crate) fn take_clipboard_request(&mut self) -> Option<bool> {
+        self.clipboard_requested.take()
+    }
+
+    /// Draw the next frame twice, after running the console command `before` and then `after`,
+    /// and save both with a heatmap of their differences as `<prefix>-a.png`, `<prefix>-b.png`,
+    /// and `<prefix>-diff.png`
+    ///
+    /// `before` is run again afterwards, so the frame that is shown has the setting it sets.
+    pub fn request_ab_capture(&mut self, before: String, after: String, prefix: String) {
+        self.ab_capture_requested = Some(AbCaptureRequest {
+            before,
+            after,
+            prefix,
+        });
+    }
+
+    /// Clear the A/B capture request, returning it if there was one
+    pub(crate) fn take_ab_capture_request(&mut self) -> Option<AbCaptureRequest> {
+        self.ab_capture_requested.take()
+    }
+
+    /// Write a report of the live GL objects and the pipeline state to a text file after the
+    /// handler draws the next frame, like F12 does
+    pub fn request_frame_report(&mut self) {
+        self.frame_report_requested = true;
+    }
+
+    /// Clear the frame report request, returning whether there was one
+    pub(crate) fn take_frame_report_request(&mut self) -> bool {
+        std::mem::take(&mut self.frame_report_requested)
+    }
+
+    /// Ask the window system to resize the window to the given size in physical pixels after this
+    /// frame
+    ///
+    /// The window system may pick another size, and the new size shows up in `window_size` once it
+    /// has been resized.
+    pub fn request_window_size(&mut self, size: (u32, u32)) {
+        self.window_size_requested = Some(size);
+    }
+
+    /// Clear the window size request, returning the size if there was one
+    pub(crate) fn take_window_size_request(&mut self) -> Option<(u32, u32)> {
+        self.window_size_requested.take()
+    }
+
+    /// Pretend that the window surface was lost when this frame is presented, like F9 does
+    ///
+    /// The loop calls `RenderHandler::device_lost`, recreates the surface, and calls
+    /// `RenderHandler::device_restored`, so that the recovery can be tested without a driver that
+    /// loses surfaces.
+    pub fn simulate_surface_loss(&mut self) {
+        self.surface_loss_requested = true;
+    }
+
+    /// Clear the surface loss request, returning whether there was one
+    pub(crate) fn take_surface_loss_request(&mut self) -> bool {
+        std::mem::take(&mut self.surface_loss_requested)
+    }
+
+    /// Pretend that the driver reset the GL context after this frame
+    ///
+    /// The loop tears the handler down, creates a new context, and initializes the handler again
+    /// with `init`, the same as after a real reset.
+    pub fn simulate_context_reset(&mut self) {
+        self.context_reset_requested = true;
+    }
+
+    /// Clear the context reset request, returning whether there was one
+    pub(crate) fn take_context_reset_request(&mut self) -> bool {
+        std::mem::take(&mut self.context_reset_requested)
+    }
+
+    /// How many times the loop found the window and its surface to be different sizes without a
+    /// resize event, and resized them before `draw`
+    ///
+    /// This stays at 0 on most platforms. It is shown in the frame stats ( F3 ) once it isn't.
+    pub fn size_corrections(&self) -> u64 {
+        self.size_corrections
+    }
+
+    pub(crate) fn count_size_correction(&mut self) {
+        self.size_corrections += 1;
+    }
+
+    /// Change what the mouse cursor looks like over the window, like
+    /// `ctx.set_cursor(CursorIcon::Crosshair)`
+    ///
+    /// This can be called every frame, since the window system is only told when the cursor
+    /// changes.
+    pub fn set_cursor<C: Into<Cursor>>(&mut self, cursor: C) {
+        self.cursor = cursor.into();
+    }
+
+    /// What the mouse cursor looks like over the window
+    pub fn cursor(&self) -> &Cursor {
+        &self.cursor
+    }
+
+    /// Keep the cursor in the window and hide it, for looking around with the mouse
+    ///
+    /// The cursor set with `set_cursor` comes back when it is released.
+    pub fn set_cursor_grab(&mut self, grabbed: bool) {
+        self.cursor_grabbed = grabbed;
+    }
+
+    /// Whether or not the cursor is grabbed
+    pub fn is_cursor_grabbed(&self) -> bool {
+        self.cursor_grabbed
+    }
+
+    /// Ask the handler to reload its shaders, which `reload shaders` in the console does
+    pub fn request_shader_reload(&mut self) {
+        self.shader_reload_requested = true;
+    }
+
+    /// Whether or not the handler should reload its shaders in this frame's `draw`
+    ///
+    /// Handlers that load their shaders from files check this. The request is cleared after the
+    /// frame.
+    pub fn shader_reload_requested(&self) -> bool {
+        self.shader_reload_requested
+    }
+
+    pub(crate) fn clear_shader_reload_request(&mut self) {
+        self.shader_reload_requested = false;
+    }
+
+    /// Set the size of the window as the window system reported it, which may be zero-sized or
+    /// bigger than the context can draw
+    pub(crate) fn set_window_size(&mut self, window_size: (u32, u32)) {
+        self.minimized = window_size.0 == 0 || window_size.1 == 0;
+        let clamped = self.features.clamp_framebuffer_size(window_size);
+        if !self.minimized && clamped != window_size && clamped != self.window_size {
+            eprintln!(
+                "Error: The window is {}x{}, but the context can't draw more than {}x{}, so the \
+                 frame only covers part of it",
+                window_size.0, window_size.1, clamped.0, clamped.1
+            );
+        }
+        self.window_size = clamped;
+    }
+
+    pub(crate) fn set_hidpi_factor(&mut self, hidpi_factor: f64) {
+        self.hidpi_factor = hidpi_factor;
+    }
+
+    pub(crate) fn set_context_report(&mut self, context_report: ContextReport) {
+        self.context_report = context_report;
+    }
+
+    pub(crate) fn set_features(&mut self, features: Features) {
+        self.features = features;
+    }
+
+    pub(crate) fn set_workarounds(&mut self, workarounds: Workarounds) {
+        self.workarounds = workarounds;
+    }
+
+    pub(crate) fn set_surface_framebuffer(&mut self, surface_framebuffer: Option<u32>) {
+        self.surface_framebuffer = surface_framebuffer;
+    }
+}
@@ -0,0 +1,586 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write as _},
+    fs::File,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    time::UNIX_EPOCH,
+};
+
+use crate::config::{self, Config, ConfigError};
+#[cfg(not(feature = "xxhash"))]
+use crate::program_cache::Fnv1a;
+
+/// The name of the file that the asset manifest is saved in, which goes next to the config file,
+/// or in the working directory if there isn't one
+pub const MANIFEST_FILE_NAME: &str = "me_learning_opengl_assets.toml";
+
+/// Files at least this big are hashed on the manifest's worker thread instead of in `check`
+pub const LARGE_FILE_BYTES: u64 = 1024 * 1024;
+
+/// The name of the hash that content hashes are made with, which is saved in the manifest so that
+/// switching the `xxhash` feature on or off doesn't compare hashes of two different kinds
+#[cfg(feature = "xxhash")]
+pub const HASH_ALGORITHM: &str = "xxh3";
+#[cfg(not(feature = "xxhash"))]
+pub const HASH_ALGORITHM: &str = "fnv1a";
+
+/// The prefix of the table names of derived data in the manifest file
+const DERIVED_PREFIX: &str = "derived:";
+
+/// A 64 bit hash of the contents of a file, from `hash_bytes` or `hash_file`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ContentHash(pub u64);
+
+impl ContentHash {
+    /// Read a hash written with `Display`
+    pub fn parse(hex: &str) -> Option<Self> {
+        u64::from_str_radix(hex, 16).ok().map(ContentHash)
+    }
+}
+
+impl fmt::Display for ContentHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
+
+/// The hash of `HASH_ALGORITHM`, which can be fed a file a piece at a time
+struct Hasher {
+    #[cfg(feature = "xxhash")]
+    state: xxhash_rust::xxh3::Xxh3,
+    #[cfg(not(feature = "xxhash"))]
+    state: Fnv1a,
+}
+
+impl Hasher {
+    fn new() -> Self {
+        Self {
+            #[cfg(feature = "xxhash")]
+            state: xxhash_rust::xxh3::Xxh3::new(),
+            #[cfg(not(feature = "xxhash"))]
+            state: Fnv1a::new(),
+        }
+    }
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.state.update(bytes);
+    }
+
+    #[cfg(feature = "xxhash")]
+    fn finish(&self) -> ContentHash {
+        ContentHash(self.state.digest())
+    }
+
+    #[cfg(not(feature = "xxhash"))]
+    fn finish(&self) -> ContentHash {
+        ContentHash(self.state.finish())
+    }
+}
+
+/// Hash some bytes that are already in memory, like a file that was read to be decoded
+pub fn hash_bytes(bytes: &[u8]) -> ContentHash {
+    let mut hasher = Hasher::new();
+    hasher.update(bytes);
+    hasher.finish()
+}
+
+/// Hash the contents of a file, reading it a piece at a time
+pub fn hash_file<P: AsRef<Path>>(path: P) -> io::Result<ContentHash> {
+    let mut file = File::open(path)?;
+    let mut hasher = Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finish());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
+
+/// The size of a file and when it was last modified, in nanoseconds since the Unix epoch or 0 if
+/// the file system doesn't say
+fn file_stamp(path: &Path) -> io::Result<(u64, u64)> {
+    let metadata = std::fs::metadata(path)?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos() as u64);
+    Ok((metadata.len(), modified))
+}
+
+/// What the manifest knows about one asset file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AssetRecord {
+    pub hash: ContentHash,
+    pub size: u64,
+    /// When the file was last modified, in nanoseconds since the Unix epoch
+    pub modified: u64,
+}
+
+/// How an asset compares with what the manifest recorded for it, from `AssetManifest::check`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetStatus {
+    /// The size and modification time are the same, so the file wasn't read again
+    Unchanged,
+    /// The file was modified, but its contents are the same, like after a `touch` or a checkout
+    /// that wrote the same bytes
+    Touched,
+    /// The contents are different
+    Changed,
+    /// The manifest didn't have the file
+    New,
+    /// The file is big and is being hashed on the worker thread. Its status comes out of
+    /// `AssetManifest::poll` once it has been hashed.
+    Pending,
+}
+
+impl AssetStatus {
+    /// Whether or not whatever was made from the asset has to be made again. Pending assets
+    /// aren't known yet and don't.
+    pub fn needs_rebuild(self) -> bool {
+        matches!(self, AssetStatus::Changed | AssetStatus::New)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AssetStatus::Unchanged => "unchanged",
+            AssetStatus::Touched => "touched, same content",
+            AssetStatus::Changed => "changed",
+            AssetStatus::New => "new",
+            AssetStatus::Pending => "hashing",
+        }
+    }
+}
+
+/// Data that was made from an asset, like a baked cubemap or a simplified mesh, and the hash of
+/// the asset when it was made
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct DerivedRecord {
+    source: String,
+    hash: ContentHash,
+}
+
+/// A big file for the worker to hash
+#[derive(Debug)]
+struct HashJob {
+    path: PathBuf,
+    size: u64,
+    modified: u64,
+}
+
+/// The channels to the thread that hashes big files, which is only started once there is one to
+/// hash. The thread stops when the manifest is dropped.
+#[derive(Debug)]
+struct HashWorker {
+    jobs: Sender<HashJob>,
+    results: Receiver<(HashJob, io::Result<ContentHash>)>,
+}
+
+/// The content hashes of asset files, saved between runs so that what was made from them is only
+/// made again when their contents change
+///
+/// `check` looks at a file's size and modification time first, and only reads the file when
+/// those changed. A file that was modified but hashes the same is `Touched`, so editors that save
+/// without changes, checkouts, and copies don't make anything load again. Files of at least
+/// `LARGE_FILE_BYTES` are hashed on a worker thread so that checking them never stalls a frame;
+/// `poll` picks up their statuses. The texture streamer hashes the files it reads on its own
+/// worker, which `record_hash` takes without reading them again.
+///
+/// Data made from an asset is recorded with `record_derived` and checked with
+/// `is_derived_current`, which compare the hash the data was made from with the asset's current
+/// one. Program binaries don't need that, since the program binary cache keys them by the hash of
+/// their sources and never loads a binary of a source that changed.
+///
+/// `report` lists what was reused and what had to be rebuilt in this run, for printing at startup.
+#[derive(Debug, Default)]
+pub struct AssetManifest {
+    /// The records of asset files, by path
+    assets: BTreeMap<String, AssetRecord>,
+    /// The records of derived data, by name
+    derived: BTreeMap<String, DerivedRecord>,
+    /// The status of every asset checked in this run, for the report
+    checked: BTreeMap<String, AssetStatus>,
+    /// Whether or not every piece of derived data checked in this run was current
+    derived_checked: BTreeMap<String, bool>,
+    worker: Option<HashWorker>,
+}
+
+impl AssetManifest {
+    /// The file that the manifest is saved in
+    pub fn path() -> PathBuf {
+        let dir = Config::find_file()
+            .and_then(|path| Some(path.parent()?.to_path_buf()))
+            .unwrap_or_default();
+        dir.join(MANIFEST_FILE_NAME)
+    }
+
+    /// Load the saved manifest if there is one
+    ///
+    /// Problems are printed as warnings, and start over with an empty manifest.
+    pub fn load() -> Self {
+        let path = Self::path();
+        if !path.is_file() {
+            return Self::default();
+        }
+        Self::open(&path).unwrap_or_else(|error| {
+            eprintln!("Warning: {}: {}", path.display(), error);
+            Self::default()
+        })
+    }
+
+    /// Read a manifest file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Read a manifest from the `hash` it was made with, followed by a table for each asset and
+    /// each piece of derived data
+    ///
+    /// A manifest made with a different hash is read as empty, so everything is rebuilt once.
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        let mut algorithm = None;
+        let mut assets = BTreeMap::new();
+        let mut derived = BTreeMap::new();
+        config::parse_toml(toml, |name, key, value| {
+            let hash = || {
+                ContentHash::parse(value)
+                    .ok_or_else(|| format!("Expected a hex hash for `{}`, got `{}`", key, value))
+            };
+            let number = || {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| format!("Expected a number for `{}`, got `{}`", key, value))
+            };
+            let name = match name {
+                Some(name) => name,
+                None if key == "hash" => {
+                    algorithm = Some(value.to_string());
+                    return Ok(true);
+                }
+                None => return Ok(false),
+            };
+
+            if let Some(name) = name.strip_prefix(DERIVED_PREFIX) {
+                let record = derived.entry(name.to_string()).or_insert(DerivedRecord {
+                    source: String::new(),
+                    hash: ContentHash(0),
+                });
+                match key {
+                    "source" => record.source = value.to_string(),
+                    "hash" => record.hash = hash()?,
+                    _ => return Ok(false),
+                }
+            } else {
+                let record = assets.entry(name.to_string()).or_insert(AssetRecord {
+                    hash: ContentHash(0),
+                    size: 0,
+                    modified: 0,
+                });
+                match key {
+                    "hash" => record.hash = hash()?,
+                    "size" => record.size = number()?,
+                    "modified" => record.modified = number()?,
+                    _ => return Ok(false),
+                }
+            }
+            Ok(true)
+        })?;
+
+        if algorithm.as_deref() != Some(HASH_ALGORITHM) {
+            return Ok(Self::default());
+        }
+        // Leave out derived data that is missing its source
+        derived.retain(|_, record| !record.source.is_empty());
+        Ok(Self {
+            assets,
+            derived,
+            ..Self::default()
+        })
+    }
+
+    /// Write the manifest in the format that `from_toml` reads
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        writeln!(toml, "hash = {:?}", HASH_ALGORITHM).unwrap();
+        for (path, record) in &self.assets {
+            writeln!(toml).unwrap();
+            writeln!(toml, "[{:?}]", path).unwrap();
+            writeln!(toml, "hash = \"{}\"", record.hash).unwrap();
+            writeln!(toml, "size = {}", record.size).unwrap();
+            writeln!(toml, "modified = {}", record.modified).unwrap();
+        }
+        for (name, record) in &self.derived {
+            writeln!(toml).unwrap();
+            writeln!(toml, "[{:?}]", format!("{}{}", DERIVED_PREFIX, name)).unwrap();
+            writeln!(toml, "source = {:?}", record.source).unwrap();
+            writeln!(toml, "hash = \"{}\"", record.hash).unwrap();
+        }
+        toml
+    }
+
+    /// Save the manifest to `path()`
+    ///
+    /// Files that are still being hashed are left out, and are checked again in the next run.
+    pub fn save(&self) -> io::Result<()> {
+        std::fs::write(Self::path(), self.to_toml())
+    }
+
+    /// Compare an asset file with its record, recording it as it is now
+    ///
+    /// Assets are recorded by their path as it is given, so the same file should be checked with
+    /// the same path every time.
+    pub fn check<P: AsRef<Path>>(&mut self, path: P) -> io::Result<AssetStatus> {
+        let path = path.as_ref();
+        let key = path.display().to_string();
+        let (size, modified) = file_stamp(path)?;
+        if let Some(record) = self.assets.get(&key) {
+            if record.size == size && record.modified == modified {
+                self.checked.insert(key, AssetStatus::Unchanged);
+                return Ok(AssetStatus::Unchanged);
+            }
+        }
+
+        if size >= LARGE_FILE_BYTES {
+            let job = HashJob {
+                path: path.to_owned(),
+                size,
+                modified,
+            };
+            if self.hash_worker().jobs.send(job).is_ok() {
+                self.checked.insert(key, AssetStatus::Pending);
+                return Ok(AssetStatus::Pending);
+            }
+            eprintln!("Warning: The asset hashing worker has stopped");
+        }
+        let hash = hash_file(path)?;
+        Ok(self.update(
+            key,
+            AssetRecord {
+                hash,
+                size,
+                modified,
+            },
+        ))
+    }
+
+    /// Record the hash of a file that the caller already read, like the texture streamer does,
+    /// returning how it compares with the file's record
+    pub fn record_hash<P: AsRef<Path>>(
+        &mut self,
+        path: P,
+        hash: ContentHash,
+    ) -> io::Result<AssetStatus> {
+        let path = path.as_ref();
+        let (size, modified) = file_stamp(path)?;
+        Ok(self.update(
+            path.display().to_string(),
+            AssetRecord {
+                hash,
+                size,
+                modified,
+            },
+        ))
+    }
+
+    /// Take the statuses of the big files that the worker has hashed since the last poll
+    ///
+    /// Files that couldn't be read are printed as warnings and left out.
+    pub fn poll(&mut self) -> Vec<(PathBuf, AssetStatus)> {
+        let mut finished = Vec::new();
+        loop {
+            let result = match &self.worker {
+                Some(worker) => worker.results.try_recv(),
+                None => break,
+            };
+            match result {
+                Ok((job, hash)) => {
+                    if let Some(status) = self.finish_job(job, hash) {
+                        finished.push(status);
+                    }
+                }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+        finished
+    }
+
+    /// Wait for the worker to hash every big file that it was given, returning their statuses
+    /// like `poll`
+    pub fn wait(&mut self) -> Vec<(PathBuf, AssetStatus)> {
+        let mut finished = self.poll();
+        while self.pending_count() > 0 {
+            let result = match &self.worker {
+                Some(worker) => worker.results.recv(),
+                None => break,
+            };
+            match result {
+                Ok((job, hash)) => {
+                    if let Some(status) = self.finish_job(job, hash) {
+                        finished.push(status);
+                    }
+                }
+                Err(_) => break,
+            }
+        }
+        finished
+    }
+
+    /// How many files are still being hashed
+    pub fn pending_count(&self) -> usize {
+        self.checked
+            .values()
+            .filter(|&&status| status == AssetStatus::Pending)
+            .count()
+    }
+
+    /// The record of an asset, if it has one
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<&AssetRecord> {
+        self.assets.get(&path.as_ref().display().to_string())
+    }
+
+    /// Drop the record of an asset, so that it is `New` the next time it is checked, e.g. when
+    /// what was made from it failed and has to be tried again even if it doesn't change
+    pub fn forget<P: AsRef<Path>>(&mut self, path: P) {
+        self.assets.remove(&path.as_ref().display().to_string());
+    }
+
+    /// Whether or not the data named `name` was made from the current contents of `source`
+    ///
+    /// `source` should be checked first, so that its record is up to date. Data that was never
+    /// recorded, or was made from another file, isn't current.
+    pub fn is_derived_current<P: AsRef<Path>>(&mut self, name: &str, source: P) -> bool {
+        let source = source.as_ref().display().to_string();
+        let current = match (self.derived.get(name), self.assets.get(&source)) {
+            (Some(derived), Some(asset)) => derived.source == source && derived.hash == asset.hash,
+            _ => false,
+        };
+        self.derived_checked.insert(name.to_string(), current);
+        current
+    }
+
+    /// Record that the data named `name` was just made from `source`, which has to have been
+    /// checked
+    ///
+    /// If `source` has no record the data's record is dropped instead, so it is made again next
+    /// time.
+    pub fn record_derived<P: AsRef<Path>>(&mut self, name: &str, source: P) {
+        let source = source.as_ref().display().to_string();
+        match self.assets.get(&source) {
+            Some(asset) => {
+                let hash = asset.hash;
+                self.derived
+                    .insert(name.to_string(), DerivedRecord { source, hash });
+            }
+            None => {
+                self.derived.remove(name);
+            }
+        }
+    }
+
+    /// A report of the assets and derived data that were checked in this run, with how many were
+    /// reused and how many had to be rebuilt, and a line for each of them
+    pub fn report(&self) -> String {
+        let (mut reused, mut rebuilt) = (0, 0);
+        for &status in self.checked.values() {
+            match status {
+                AssetStatus::Unchanged | AssetStatus::Touched => reused += 1,
+                AssetStatus::Changed | AssetStatus::New => rebuilt += 1,
+                AssetStatus::Pending => {}
+            }
+        }
+        for &current in self.derived_checked.values() {
+            if current {
+                reused += 1;
+            } else {
+                rebuilt += 1;
+            }
+        }
+
+        let mut report = String::new();
+        write!(report, "Assets: {} reused, {} rebuilt", reused, rebuilt).unwrap();
+        match self.pending_count() {
+            0 => writeln!(report).unwrap(),
+            pending => writeln!(report, ", {} still hashing", pending).unwrap(),
+        }
+        for (path, status) in &self.checked {
+            let action = match status {
+                AssetStatus::Unchanged | AssetStatus::Touched => "reused",
+                AssetStatus::Changed | AssetStatus::New => "rebuilt",
+                AssetStatus::Pending => "waiting",
+            };
+            writeln!(report, "  {:<8} {} ( {} )", action, path, status.name()).unwrap();
+        }
+        for (name, &current) in &self.derived_checked {
+            let (action, why) = if current {
+                ("reused", "source unchanged")
+            } else {
+                ("rebuilt", "source changed")
+            };
+            writeln!(report, "  {:<8} {} ( derived, {} )", action, name, why).unwrap();
+        }
+        report
+    }
+
+    /// Record a hashed asset, returning how it compares with its old record
+    fn update(&mut self, key: String, record: AssetRecord) -> AssetStatus {
+        let status = match self.assets.get(&key) {
+            None => AssetStatus::New,
+            Some(old) if old.hash != record.hash => AssetStatus::Changed,
+            Some(old) if old.size == record.size && old.modified == record.modified => {
+                AssetStatus::Unchanged
+            }
+            Some(_) => AssetStatus::Touched,
+        };
+        self.assets.insert(key.clone(), record);
+        self.checked.insert(key, status);
+        status
+    }
+
+    /// Record a file that the worker hashed
+    fn finish_job(
+        &mut self,
+        job: HashJob,
+        hash: io::Result<ContentHash>,
+    ) -> Option<(PathBuf, AssetStatus)> {
+        let key = job.path.display().to_string();
+        match hash {
+            Ok(hash) => {
+                let record = AssetRecord {
+                    hash,
+                    size: job.size,
+                    modified: job.modified,
+                };
+                Some((job.path, self.update(key, record)))
+            }
+            Err(error) => {
+                eprintln!("Warning: Couldn't hash {}: {}", key, error);
+                self.checked.remove(&key);
+                None
+            }
+        }
+    }
+
+    /// The worker that hashes big files, starting it if it hasn't been started
+    fn hash_worker(&mut self) -> &HashWorker {
+        self.worker.get_or_insert_with(|| {
+            let (jobs, job_receiver) = mpsc::channel::<HashJob>();
+            let (result_sender, results) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("Asset hashing".into())
+                .spawn(move || {
+                    for job in job_receiver {
+                        let hash = hash_file(&job.path);
+                        if result_sender.send((job, hash)).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .unwrap();
+            HashWorker { jobs, results }
+        })
+    }
+}
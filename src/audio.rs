@@ -0,0 +1,472 @@
+use std::{
+    io::Read,
+    process::{Child, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
+};
+
+use glow::HasContext;
+
+use crate::{
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+    SliceAsBytes,
+};
+
+/// The number of frequency bins in the spectrum, which is the width of the spectrum texture
+pub const SPECTRUM_SIZE: usize = 512;
+/// The sample rate audio is captured at
+pub const SAMPLE_RATE: u32 = 44_100;
+
+/// The number of samples each FFT runs over, which gives `SPECTRUM_SIZE` useful bins
+const FFT_SIZE: usize = SPECTRUM_SIZE * 2;
+/// How many new samples are read between updates of the spectrum, about 86 updates a second
+const HOP_SIZE: usize = 512;
+
+/// The frequency ranges of the bass, mid, and treble bands in Hz
+const BASS_RANGE: (f32, f32) = (20., 250.);
+const MID_RANGE: (f32, f32) = (250., 4000.);
+const TREBLE_RANGE: (f32, f32) = (4000., 16000.);
+
+/// Where audio is captured from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AudioInput {
+    /// What the system is playing, through the PulseAudio monitor of the default output
+    System,
+    /// The default recording device, like a microphone
+    Microphone,
+    /// Nothing, which always gives a silent spectrum
+    Silence,
+}
+
+impl AudioInput {
+    /// The commands that can capture this input, in the order they are tried. Each writes mono
+    /// 32-bit float samples to its standard output.
+    fn commands(self) -> Vec<Vec<String>> {
+        let rate = SAMPLE_RATE.to_string();
+        let parec = |device: Option<&str>| {
+            let mut command = vec![
+                "parec".to_string(),
+                "--format=float32le".to_string(),
+                "--channels=1".to_string(),
+                format!("--rate={}", rate),
+                "--latency-msec=20".to_string(),
+            ];
+            command.extend(device.map(|device| format!("--device={}", device)));
+            command
+        };
+        let arecord = [
+            "arecord", "-q", "-t", "raw", "-f", "FLOAT_LE", "-c", "1", "-r", &rate,
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        match self {
+            AudioInput::System => vec![parec(Some("@DEFAULT_MONITOR@"))],
+            AudioInput::Microphone => vec![parec(None), arecord],
+            AudioInput::Silence => Vec::new(),
+        }
+    }
+}
+
+/// The settings of an audio analyzer
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioParams {
+    pub input: AudioInput,
+    /// How much of the previous spectrum is kept in each update, from 0 to just below 1. Higher
+    /// values keep the bins from flickering, but react to the music later.
+    pub smoothing: f32,
+    /// The level in decibels that maps to 0 in the spectrum
+    pub min_decibels: f32,
+    /// The level in decibels that maps to 1 in the spectrum
+    pub max_decibels: f32,
+}
+
+impl Default for AudioParams {
+    fn default() -> Self {
+        Self {
+            input: AudioInput::System,
+            smoothing: 0.8,
+            min_decibels: -90.,
+            max_decibels: -20.,
+        }
+    }
+}
+
+/// The frequency spectrum of the latest audio, with every level between 0 and 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spectrum {
+    /// The level of each frequency, evenly spaced from 0 Hz up to half the sample rate
+    pub bins: [f32; SPECTRUM_SIZE],
+    /// The average level of the bass frequencies
+    pub bass: f32,
+    /// The average level of the mid frequencies
+    pub mid: f32,
+    /// The average level of the treble frequencies
+    pub treble: f32,
+    /// The root mean square of the latest samples
+    pub volume: f32,
+}
+
+impl Default for Spectrum {
+    /// A silent spectrum
+    fn default() -> Self {
+        Self {
+            bins: [0.; SPECTRUM_SIZE],
+            bass: 0.,
+            mid: 0.,
+            treble: 0.,
+            volume: 0.,
+        }
+    }
+}
+
+/// The spectrum the worker thread shares with the render loop
+#[derive(Debug, Default)]
+struct SharedSpectrum {
+    spectrum: Spectrum,
+    /// Counts the updates, so the render loop can tell when the spectrum changed
+    generation: u64,
+}
+
+/// Computes the spectrum of live audio on a worker thread, for visualizers that react to music
+///
+/// The audio is captured by running `parec` or `arecord`, so it needs PulseAudio or ALSA. If none
+/// of them can capture the input, or the device goes away, the spectrum stays silent instead.
+///
+/// `update` never waits on the worker: if the worker is busy writing a new spectrum, the frame
+/// keeps the last one. The spectrum is uploaded to a `SPECTRUM_SIZE`x1 `R32F` texture and set on
+/// programs with `set_uniforms`, in the style of ShaderToy's audio inputs.
+///
+/// Dropping the spectrum kills the capture command and waits for it, so it never outlives the
+/// demo; `delete` only frees the texture.
+#[derive(Debug)]
+pub struct AudioSpectrum {
+    params: AudioParams,
+    spectrum: Spectrum,
+    shared: Arc<Mutex<SharedSpectrum>>,
+    /// The generation of the spectrum in the texture
+    generation: u64,
+    texture: u32,
+    capture: Option<Child>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl AudioSpectrum {
+    /// Start capturing and analyzing audio
+    pub fn new(gl: &mut glow::Context, params: AudioParams) -> Self {
+        let texture = unsafe {
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::R32F as i32,
+                SPECTRUM_SIZE as i32,
+                1,
+                0,
+                glow::RED,
+                glow::FLOAT,
+                None,
+            );
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            resources::track_sized(
+                ResourceKind::Texture,
+                texture,
+                "Audio spectrum",
+                resources::texture_bytes(SPECTRUM_SIZE as u32, 1, glow::R32F, 1, 1, 1),
+            );
+            texture
+        };
+
+        let mut audio = Self {
+            params,
+            spectrum: Spectrum::default(),
+            shared: Arc::new(Mutex::new(SharedSpectrum::default())),
+            generation: 0,
+            texture,
+            capture: None,
+            worker: None,
+        };
+        // Start the texture out silent
+        audio.upload(gl);
+        audio.start_capture();
+        audio
+    }
+
+    /// Start the first capture command that runs, and the worker that analyzes its samples
+    fn start_capture(&mut self) {
+        let commands = self.params.input.commands();
+        if commands.is_empty() {
+            return;
+        }
+        for command in &commands {
+            let child = Command::new(&command[0])
+                .args(&command[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::null())
+                .spawn();
+            let mut child = match child {
+                Ok(child) => child,
+                Err(_) => continue,
+            };
+            let stdout = child.stdout.take().unwrap();
+            let (params, shared) = (self.params, self.shared.clone());
+            let program = command[0].clone();
+            self.worker = Some(
+                std::thread::Builder::new()
+                    .name("audio analyzer".into())
+                    .spawn(move || analyze(stdout, params, &shared, &program))
+                    .unwrap(),
+            );
+            self.capture = Some(child);
+            return;
+        }
+        eprintln!(
+            "Warning: Couldn't capture {:?} audio, because none of {} could be run. The audio \
+             spectrum will stay silent.",
+            self.params.input,
+            commands
+                .iter()
+                .map(|command| format!("`{}`", command[0]))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        );
+    }
+
+    /// The settings the analyzer was started with
+    pub fn params(&self) -> AudioParams {
+        self.params
+    }
+
+    /// Whether audio is being captured. This is false if capturing failed to start or stopped,
+    /// and the spectrum is silent.
+    pub fn is_capturing(&self) -> bool {
+        self.worker
+            .as_ref()
+            .is_some_and(|worker| !worker.is_finished())
+    }
+
+    /// The latest spectrum picked up by `update`
+    pub fn spectrum(&self) -> &Spectrum {
+        &self.spectrum
+    }
+
+    /// The `SPECTRUM_SIZE`x1 `R32F` texture holding the spectrum bins
+    pub fn texture(&self) -> u32 {
+        self.texture
+    }
+
+    /// Pick up the latest spectrum from the worker and upload it to the texture if it changed
+    ///
+    /// This never blocks. If the worker is writing a new spectrum at the moment, the last one is
+    /// kept until the next frame.
+    pub fn update(&mut self, gl: &mut glow::Context) {
+        let changed = match self.shared.try_lock() {
+            Ok(shared) if shared.generation != self.generation => {
+                self.spectrum = shared.spectrum;
+                self.generation = shared.generation;
+                true
+            }
+            _ => false,
+        };
+        if changed {
+            self.upload(gl);
+        }
+    }
+
+    /// Upload the spectrum bins to the texture
+    fn upload(&self, gl: &mut glow::Context) {
+        let bins = &self.spectrum.bins[..];
+        let bytes = bins.as_mem_bytes();
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            gl.tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                0,
+                0,
+                SPECTRUM_SIZE as i32,
+                1,
+                glow::RED,
+                glow::FLOAT,
+                glow::PixelUnpackData::Slice(bytes),
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+    }
+
+    /// Bind the spectrum texture to `texture_unit` and set the audio uniforms of a program, which
+    /// must be current: the `iAudio` sampler, and the `iBass`, `iMid`, `iTreble`, and `iVolume`
+    /// levels
+    pub fn set_uniforms(
+        &self,
+        gl: &mut glow::Context,
+        program: &mut ShaderProgram,
+        texture_unit: u32,
+    ) {
+        unsafe {
+            gl.active_texture(glow::TEXTURE0 + texture_unit);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+        }
+        let spectrum = &self.spectrum;
+        program.set_uniform(gl, "iAudio", texture_unit as i32);
+        program.set_uniform(gl, "iBass", spectrum.bass);
+        program.set_uniform(gl, "iMid", spectrum.mid);
+        program.set_uniform(gl, "iTreble", spectrum.treble);
+        program.set_uniform(gl, "iVolume", spectrum.volume);
+    }
+
+    /// Delete the texture. The capture stops when the spectrum is dropped
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe { gl.delete_texture(self.texture) };
+        resources::untrack(ResourceKind::Texture, self.texture);
+    }
+}
+
+impl Drop for AudioSpectrum {
+    fn drop(&mut self) {
+        // Killing the capture closes its output, which ends the worker
+        if let Some(mut capture) = self.capture.take() {
+            capture.kill().ok();
+            capture.wait().ok();
+        }
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+    }
+}
+
+/// Read samples from a capture command and publish their spectrum until it stops
+fn analyze(
+    mut stdout: ChildStdout,
+    params: AudioParams,
+    shared: &Mutex<SharedSpectrum>,
+    program: &str,
+) {
+    let window = hann_window();
+    let mut samples = vec![0.; FFT_SIZE];
+    let mut bytes = [0; HOP_SIZE * 4];
+    let mut magnitudes = [0.; SPECTRUM_SIZE];
+    let mut fft = vec![(0., 0.); FFT_SIZE];
+
+    let mut received_any = false;
+    while stdout.read_exact(&mut bytes).is_ok() {
+        received_any = true;
+
+        // Slide the new samples into the end of the window
+        samples.drain(..HOP_SIZE);
+        samples.extend(
+            bytes
+                .chunks_exact(4)
+                .map(|sample| f32::from_le_bytes([sample[0], sample[1], sample[2], sample[3]])),
+        );
+
+        for ((value, &sample), &weight) in fft.iter_mut().zip(&samples).zip(&window) {
+            *value = (sample * weight, 0.);
+        }
+        fft_in_place(&mut fft);
+
+        // Smooth the magnitudes over time, then map them from decibels to 0 to 1
+        let smoothing = params.smoothing.clamp(0., 0.99);
+        let mut spectrum = Spectrum::default();
+        for ((bin, magnitude), &(re, im)) in spectrum
+            .bins
+            .iter_mut()
+            .zip(magnitudes.iter_mut())
+            .zip(&fft)
+        {
+            let current = (re * re + im * im).sqrt() / SPECTRUM_SIZE as f32;
+            *magnitude = *magnitude * smoothing + current * (1. - smoothing);
+            let decibels = 20. * magnitude.max(1e-12).log10();
+            *bin = ((decibels - params.min_decibels) / (params.max_decibels - params.min_decibels))
+                .clamp(0., 1.);
+        }
+        spectrum.bass = band_level(&spectrum.bins, BASS_RANGE);
+        spectrum.mid = band_level(&spectrum.bins, MID_RANGE);
+        spectrum.treble = band_level(&spectrum.bins, TREBLE_RANGE);
+        let recent = &samples[FFT_SIZE - HOP_SIZE..];
+        spectrum.volume =
+            (recent.iter().map(|sample| sample * sample).sum::<f32>() / HOP_SIZE as f32).sqrt();
+
+        let mut shared = shared.lock().unwrap();
+        shared.spectrum = spectrum;
+        shared.generation += 1;
+    }
+
+    // Fall back to silence once the capture stops
+    if !received_any {
+        eprintln!(
+            "Warning: `{}` didn't capture any audio. The audio spectrum will stay silent.",
+            program
+        );
+    }
+    let mut shared = shared.lock().unwrap();
+    shared.spectrum = Spectrum::default();
+    shared.generation += 1;
+}
+
+/// The average level of the bins between two frequencies
+fn band_level(bins: &[f32; SPECTRUM_SIZE], (low, high): (f32, f32)) -> f32 {
+    let bin_width = SAMPLE_RATE as f32 / FFT_SIZE as f32;
+    let first = ((low / bin_width) as usize).max(1);
+    let last = ((high / bin_width) as usize).clamp(first + 1, SPECTRUM_SIZE);
+    bins[first..last].iter().sum::<f32>() / (last - first) as f32
+}
+
+/// The Hann window, which tapers the ends of the samples so the FFT doesn't see a jump between
+/// them
+fn hann_window() -> Vec<f32> {
+    (0..FFT_SIZE)
+        .map(|i| {
+            let phase = i as f32 / (FFT_SIZE - 1) as f32;
+            0.5 - 0.5 * (2. * std::f32::consts::PI * phase).cos()
+        })
+        .collect()
+}
+
+/// An iterative radix-2 FFT of complex `(real, imaginary)` values, whose count must be a power
+/// of two
+fn fft_in_place(values: &mut [(f32, f32)]) {
+    let n = values.len();
+
+    // Put the values in bit reversed order
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            values.swap(i, j);
+        }
+    }
+
+    // Combine ever larger transforms with butterflies
+    let mut len = 2;
+    while len <= n {
+        let angle = -2. * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (re, im) = values[start + k + len / 2];
+                let twiddled = (re * cos - im * sin, re * sin + im * cos);
+                let even = values[start + k];
+                values[start + k] = (even.0 + twiddled.0, even.1 + twiddled.1);
+                values[start + k + len / 2] = (even.0 - twiddled.0, even.1 - twiddled.1);
+            }
+        }
+        len <<= 1;
+    }
+}
@@ -0,0 +1,272 @@
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    diagnostics,
+    readback::{AsyncReadback, DEFAULT_SLOT_COUNT},
+    resources::{self, ResourceKind},
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+};
+
+const LOG_LUMINANCE_FRAGMENT_SRC: &str = include_str!("auto_exposure/log_luminance.frag");
+
+/// The size of the luminance texture, which is averaged down to one pixel by its mip chain
+const LUMINANCE_SIZE: u32 = 256;
+
+/// The settings of automatic exposure, which can be changed between frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AutoExposureParams {
+    /// How bright the average of the scene ends up after exposure, where 0.18 is middle gray
+    pub key_value: f32,
+    /// The lowest exposure, which keeps very bright scenes from turning gray
+    pub min_exposure: f32,
+    /// The highest exposure, which keeps dark scenes dark instead of turning them into noise
+    pub max_exposure: f32,
+    /// How fast the exposure rises when the scene gets darker. After one second `1 - e^-speed`
+    /// of the way has been covered, so 3 is most of the way in a second.
+    pub speed_up: f32,
+    /// How fast the exposure falls when the scene gets brighter. Eyes adjust to bright light
+    /// faster than to darkness, so this is usually the faster one.
+    pub speed_down: f32,
+}
+
+impl Default for AutoExposureParams {
+    fn default() -> Self {
+        Self {
+            key_value: 0.18,
+            min_exposure: 0.05,
+            max_exposure: 20.,
+            speed_up: 2.5,
+            speed_down: 4.,
+        }
+    }
+}
+
+/// Picks the exposure of an HDR image from its average brightness, and adapts it smoothly over
+/// time like an eye does
+///
+/// Every `update` draws the log luminance of the scene into a small texture, generates its mips
+/// to average it down to one pixel, and reads that pixel back with an `AsyncReadback`. The reads
+/// come back a frame or two later without ever waiting for the GPU, and the exposure moves
+/// towards `key_value / average` at the speeds in the params.
+#[derive(Debug)]
+pub struct AutoExposure {
+    pub params: AutoExposureParams,
+    /// The exposure to use instead of the adapted one, if any
+    override_exposure: Option<f32>,
+    /// The adapted exposure in stops, which is what is smoothed
+    stops: f32,
+    /// The geometric mean of the scene's luminance from the newest read
+    average_luminance: Option<f32>,
+    program: ShaderProgram,
+    /// An empty vertex array for the fullscreen triangle
+    vao: u32,
+    texture: u32,
+    framebuffer: u32,
+    readback: AsyncReadback,
+}
+
+impl AutoExposure {
+    pub fn new(gl: &mut glow::Context, params: AutoExposureParams) -> Self {
+        let program =
+            ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, LOG_LUMINANCE_FRAGMENT_SRC).unwrap();
+        let levels = crate::texture::mip_level_count(LUMINANCE_SIZE, LUMINANCE_SIZE);
+        unsafe {
+            let vao = gl.create_vertex_array().unwrap();
+            resources::track(ResourceKind::VertexArray, vao, "Auto exposure vertex array");
+
+            // A float texture with room for a full mip chain, since the log luminance is negative
+            // in the dark
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            for level in 0..levels {
+                let size = (LUMINANCE_SIZE >> level).max(1) as i32;
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    level as i32,
+                    glow::R16F as i32,
+                    size,
+                    size,
+                    0,
+                    glow::RED,
+                    glow::FLOAT,
+                    None,
+                );
+            }
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR_MIPMAP_NEAREST),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            resources::track_sized(
+                ResourceKind::Texture,
+                texture,
+                "Auto exposure luminance",
+                resources::texture_bytes(LUMINANCE_SIZE, LUMINANCE_SIZE, glow::R16F, levels, 1, 1),
+            );
+
+            let previous_framebuffer = gl.get_parameter_i32(glow::DRAW_FRAMEBUFFER_BINDING) as u32;
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                diagnostics::report_problem("The auto exposure framebuffer is incomplete");
+            }
+            gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                Some(previous_framebuffer).filter(|&id| id != 0),
+            );
+            resources::track(
+                ResourceKind::Framebuffer,
+                framebuffer,
+                "Auto exposure luminance",
+            );
+
+            // The last mip level is read back as one float
+            let readback = AsyncReadback::new(
+                gl,
+                std::mem::size_of::<f32>(),
+                DEFAULT_SLOT_COUNT,
+                "Auto exposure readback",
+            );
+
+            Self {
+                params,
+                override_exposure: None,
+                stops: 0.,
+                average_luminance: None,
+                program,
+                vao,
+                texture,
+                framebuffer,
+                readback,
+            }
+        }
+    }
+
+    /// Measure the HDR `scene` texture and adapt the exposure over `delta` seconds
+    ///
+    /// The framebuffer that was bound is bound again afterwards, but the viewport is left at the
+    /// size of the luminance texture. The measurement is still taken while the exposure is
+    /// overridden, so that it doesn't jump when the override is turned off.
+    pub fn update(&mut self, gl: &mut glow::Context, scene: u32, delta: f32) {
+        let _group = DebugGroup::push(gl, "Auto exposure");
+
+        // Collect the newest finished measurement
+        if let Some(bytes) = self.readback.poll(gl) {
+            let log_luminance = f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            if log_luminance.is_finite() {
+                self.average_luminance = Some(log_luminance.exp2());
+            }
+        }
+
+        unsafe {
+            let previous_framebuffer = gl.get_parameter_i32(glow::DRAW_FRAMEBUFFER_BINDING) as u32;
+            let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+            let blend = gl.is_enabled(glow::BLEND);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::BLEND);
+
+            // Draw the log luminance, then average it down to one pixel
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            gl.viewport(0, 0, LUMINANCE_SIZE as i32, LUMINANCE_SIZE as i32);
+            self.program.bind(gl);
+            self.program.set_uniform(gl, "scene", 0);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(scene));
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            gl.bind_vertex_array(None);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            gl.generate_mipmap(glow::TEXTURE_2D);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            // Read the last level back a frame or two from now
+            let last_level = crate::texture::mip_level_count(LUMINANCE_SIZE, LUMINANCE_SIZE) - 1;
+            self.readback
+                .read_texture(gl, self.texture, last_level, glow::RED, glow::FLOAT);
+
+            gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                Some(previous_framebuffer).filter(|&id| id != 0),
+            );
+            if depth_test {
+                gl.enable(glow::DEPTH_TEST);
+            }
+            if blend {
+                gl.enable(glow::BLEND);
+            }
+        }
+
+        self.adapt(delta);
+    }
+
+    /// Move the adapted exposure towards the one for the newest measurement
+    fn adapt(&mut self, delta: f32) {
+        let average = match self.average_luminance {
+            Some(average) => average,
+            None => return,
+        };
+        let params = self.params;
+        let min = params.min_exposure.max(f32::MIN_POSITIVE).log2();
+        let max = params.max_exposure.max(params.min_exposure).log2();
+        let target = (params.key_value / average).log2().clamp(min, max);
+        let speed = if target > self.stops {
+            params.speed_up
+        } else {
+            params.speed_down
+        };
+        // Covering the same fraction of the way every second doesn't depend on the frame rate
+        self.stops += (target - self.stops) * (1. - (-speed * delta.max(0.)).exp());
+        self.stops = self.stops.clamp(min, max);
+    }
+
+    /// The exposure to multiply the HDR colors by before tone mapping
+    pub fn exposure(&self) -> f32 {
+        self.override_exposure.unwrap_or_else(|| self.stops.exp2())
+    }
+
+    /// The exposure that the eye has adapted to, even while it is overridden
+    pub fn adapted_exposure(&self) -> f32 {
+        self.stops.exp2()
+    }
+
+    /// Use a fixed exposure instead of the adapted one, or `None` to adapt again
+    pub fn set_override(&mut self, exposure: Option<f32>) {
+        self.override_exposure = exposure;
+    }
+
+    pub fn override_exposure(&self) -> Option<f32> {
+        self.override_exposure
+    }
+
+    /// The geometric mean of the scene's luminance, from a frame or two ago, or `None` before the
+    /// first measurement comes back
+    pub fn average_luminance(&self) -> Option<f32> {
+        self.average_luminance
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.program.delete(gl);
+        self.readback.delete(gl);
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_texture(self.texture);
+        }
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Texture, self.texture);
+    }
+}
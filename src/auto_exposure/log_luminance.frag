@@ -0,0 +1,19 @@
+#version 330 core
+
+in vec2 texCoord;
+
+out float logLuminance;
+
+// The HDR scene, in linear colors
+uniform sampler2D scene;
+
+// Keeps black pixels from pulling the average down to minus infinity
+const float MIN_LUMINANCE = 0.0001;
+
+void main() {
+    vec3 color = texture(scene, texCoord).rgb;
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    // The mips average the log, which gives the geometric mean of the luminance, so a few very
+    // bright pixels don't make the whole image dark
+    logLuminance = log2(max(luminance, MIN_LUMINANCE));
+}
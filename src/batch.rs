@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform, Vector3};
+
+use crate::{
+    debug_group::DebugGroup,
+    mesh::{Mesh, MeshData},
+};
+
+/// A mesh placed in the world that can be merged into a batch
+#[derive(Clone, Debug)]
+pub struct StaticNode {
+    /// An id chosen by the caller to find the node again
+    pub id: usize,
+    /// The material that the node is drawn with. Nodes are only merged with nodes that have the
+    /// same material.
+    pub material: usize,
+    pub mesh: MeshData,
+    /// The transform from the mesh's local space to world space
+    pub transform: Matrix4<f32>,
+    /// Whether or not the node never moves. Only static nodes are batched.
+    pub is_static: bool,
+}
+
+/// Where a node's vertices and indices ended up in its batch
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchedNode {
+    pub id: usize,
+    pub first_vertex: u32,
+    pub vertex_count: u32,
+    pub first_index: u32,
+    pub index_count: u32,
+}
+
+/// The merged meshes of all of the static nodes with one material
+#[derive(Clone, Debug)]
+pub struct Batch {
+    pub material: usize,
+    pub mesh: Mesh,
+    /// The nodes that were merged into the batch, in order
+    pub nodes: Vec<BatchedNode>,
+}
+
+/// The error returned when nodes can't be merged into one batch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BatchError {
+    /// The node has different vertex attributes, e.g. no texture coordinates, than the first node
+    /// with the same material
+    IncompatibleLayout { material: usize, node: usize },
+}
+
+impl std::fmt::Display for BatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BatchError::IncompatibleLayout { material, node } => write!(
+                f,
+                "Node {} has different vertex attributes than the other nodes with material {}",
+                node, material
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+/// Merges static meshes that share a material so they can be drawn with one draw call
+///
+/// The vertices are transformed to world space on the CPU, so the batches should be drawn with an
+/// identity model matrix. The batcher keeps the source nodes so that a node can be taken back out
+/// with `unbatch` if it needs to move.
+#[derive(Debug, Default)]
+pub struct StaticBatcher {
+    /// The static nodes of each material
+    nodes: BTreeMap<usize, Vec<StaticNode>>,
+    /// The batch of each material
+    batches: BTreeMap<usize, Batch>,
+}
+
+impl StaticBatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a node to be batched by the next `build`, or give it back if it isn't static
+    pub fn add(&mut self, node: StaticNode) -> Option<StaticNode> {
+        if node.is_static {
+            self.nodes.entry(node.material).or_default().push(node);
+            None
+        } else {
+            Some(node)
+        }
+    }
+
+    /// Merge the nodes into one batch per material, replacing any existing batches
+    pub fn build(&mut self, gl: &mut glow::Context) -> Result<(), BatchError> {
+        let materials = self.nodes.keys().copied().collect::<Vec<_>>();
+        for material in materials {
+            self.build_material(gl, material)?;
+        }
+        Ok(())
+    }
+
+    /// Take a node back out of its batch, e.g. because it is about to move
+    ///
+    /// The batch it was in gets rebuilt without it.
+    pub fn unbatch(&mut self, gl: &mut glow::Context, id: usize) -> Option<StaticNode> {
+        let (material, index) = self.nodes.iter().find_map(|(&material, nodes)| {
+            nodes
+                .iter()
+                .position(|node| node.id == id)
+                .map(|index| (material, index))
+        })?;
+
+        let node = self.nodes.get_mut(&material).unwrap().remove(index);
+        // Removing a node can't make the remaining nodes incompatible
+        self.build_material(gl, material).unwrap();
+        Some(node)
+    }
+
+    /// The batches, in material order
+    pub fn batches(&self) -> impl Iterator<Item = &Batch> {
+        self.batches.values()
+    }
+
+    /// The number of draw calls that the batched nodes would take if they were drawn one by one
+    pub fn unbatched_draw_calls(&self) -> usize {
+        self.nodes.values().map(Vec::len).sum()
+    }
+
+    /// Draw every batch, calling `bind_material` before each one, and return the number of draw
+    /// calls
+    pub fn draw<F: FnMut(&mut glow::Context, usize)>(
+        &self,
+        gl: &mut glow::Context,
+        mut bind_material: F,
+    ) -> usize {
+        let _group = DebugGroup::push(gl, "Static batches");
+        for batch in self.batches.values() {
+            bind_material(gl, batch.material);
+            batch.mesh.draw(gl);
+        }
+        self.batches.len()
+    }
+
+    /// Delete the GL objects of all of the batches
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for batch in self.batches.values() {
+            batch.mesh.delete(gl);
+        }
+        self.batches.clear();
+    }
+
+    /// Rebuild the batch for one material
+    fn build_material(
+        &mut self,
+        gl: &mut glow::Context,
+        material: usize,
+    ) -> Result<(), BatchError> {
+        if let Some(old_batch) = self.batches.remove(&material) {
+            old_batch.mesh.delete(gl);
+        }
+
+        let nodes = match self.nodes.get(&material) {
+            Some(nodes) if !nodes.is_empty() => nodes,
+            _ => return Ok(()),
+        };
+
+        // Every node has to have the same attributes as the first one, or the merged vertices
+        // wouldn't line up
+        let has_attributes = |mesh: &MeshData| (!mesh.normals.is_empty(), !mesh.uvs.is_empty());
+        let layout = has_attributes(&nodes[0].mesh);
+        if let Some(node) = nodes
+            .iter()
+            .find(|node| has_attributes(&node.mesh) != layout)
+        {
+            return Err(BatchError::IncompatibleLayout {
+                material,
+                node: node.id,
+            });
+        }
+
+        let mut merged = MeshData::default();
+        let mut batched_nodes = Vec::with_capacity(nodes.len());
+        for node in nodes {
+            let first_vertex = merged.positions.len() as u32;
+            let first_index = merged.indices.len() as u32;
+
+            // Transform the positions to world space
+            merged
+                .positions
+                .extend(node.mesh.positions.iter().map(|&position| -> [f32; 3] {
+                    node.transform
+                        .transform_point(Point3::from(position))
+                        .into()
+                }));
+
+            // Normals have to be transformed by the inverse transpose so that non-uniform scales
+            // don't skew them
+            let normal_matrix = Matrix3::from_cols(
+                node.transform.x.truncate(),
+                node.transform.y.truncate(),
+                node.transform.z.truncate(),
+            )
+            .invert()
+            .unwrap_or_else(Matrix3::identity)
+            .transpose();
+            merged
+                .normals
+                .extend(node.mesh.normals.iter().map(|&normal| -> [f32; 3] {
+                    (normal_matrix * Vector3::from(normal)).normalize().into()
+                }));
+
+            merged.uvs.extend_from_slice(&node.mesh.uvs);
+
+            // Point the indices at the node's vertices in the merged mesh
+            merged
+                .indices
+                .extend(node.mesh.indices.iter().map(|&index| index + first_vertex));
+
+            batched_nodes.push(BatchedNode {
+                id: node.id,
+                first_vertex,
+                vertex_count: node.mesh.positions.len() as u32,
+                first_index,
+                index_count: node.mesh.indices.len() as u32,
+            });
+        }
+
+        // `Mesh` switches to 32 bit indices when there are too many vertices for 16 bit ones
+        self.batches.insert(
+            material,
+            Batch {
+                material,
+                mesh: Mesh::new(gl, &merged),
+                nodes: batched_nodes,
+            },
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,22 @@
+use me_learning_opengl::{
+    color::Color, render_settings::RedrawPolicy, AppContext, RenderHandler, WindowConfig,
+};
+
+/// The simplest handler there is: the loop clears the window to a color every frame and the
+/// handler doesn't draw anything
+struct HelloWindow;
+
+impl RenderHandler for HelloWindow {
+    fn init(_gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some(Color::CORNFLOWER_BLUE);
+        // Nothing changes between frames, so only draw when the window gets an event
+        ctx.render_settings.redraw = RedrawPolicy::OnEvent;
+        HelloWindow
+    }
+}
+
+fn main() {
+    me_learning_opengl::with_window_config::<HelloWindow>(
+        WindowConfig::new().title("Hello Window").size(640, 480),
+    );
+}
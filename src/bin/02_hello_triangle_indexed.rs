@@ -1,5 +1,5 @@
 use glow::HasContext;
-use me_learning_opengl::{RenderHandler, SliceAsBytes};
+use me_learning_opengl::{shader, AppContext, RenderHandler, SliceAsBytes};
 
 const VERTEX_SHADER_SRC: &str = include_str!("hello_triangle/vertex.glsl");
 const FRAGMENT_SHADER_SRC: &str = include_str!("hello_triangle/fragment.glsl");
@@ -26,7 +26,10 @@ struct HelloTriangle {
 }
 
 impl RenderHandler for HelloTriangle {
-    fn init(gl: &mut glow::Context) -> Self {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // Have the loop clear the window to our background color before every frame
+        ctx.render_settings.clear_color = Some([0., 0.8, 0.8, 1.].into());
+
         unsafe {
             //
             // Create and link shaders
@@ -34,8 +37,8 @@ impl RenderHandler for HelloTriangle {
 
             // Create a vertex shader
             let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
-            // Load the shader's GLSL source
-            gl.shader_source(vertex_shader, VERTEX_SHADER_SRC);
+            // Load the shader's GLSL source, rewritten for the context's version of GLSL
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
             // Compile the vertex shader
             gl.compile_shader(vertex_shader);
             // Check for shader compile errors
@@ -43,8 +46,11 @@ impl RenderHandler for HelloTriangle {
 
             // Create a fragment shader
             let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
-            // Load the shader's GLSL source
-            gl.shader_source(fragment_shader, FRAGMENT_SHADER_SRC);
+            // Load the shader's GLSL source, rewritten for the context's version of GLSL
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
             // Compile the fragment shader
             gl.compile_shader(fragment_shader);
             handle_shader_compile_errors(gl, fragment_shader);
@@ -129,12 +135,8 @@ impl RenderHandler for HelloTriangle {
         }
     }
 
-    fn draw(&mut self, gl: &mut glow::Context) {
+    fn draw(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
         unsafe {
-            // Clear the screen
-            gl.clear_color(0., 0.8, 0.8, 1.);
-            gl.clear(glow::COLOR_BUFFER_BIT);
-
             // Make the linked shader program our current shader program used for
             // draw operations.
             gl.use_program(Some(self.shader_program));
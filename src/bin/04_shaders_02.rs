@@ -1,6 +1,5 @@
 use glow::HasContext;
-use me_learning_opengl::{RenderHandler, SliceAsBytes};
-use std::time::Instant;
+use me_learning_opengl::{shader, AppContext, RenderHandler, SliceAsBytes};
 
 const VERTEX_SHADER_SRC: &str = include_str!("shaders_02/vertex.glsl");
 const FRAGMENT_SHADER_SRC: &str = include_str!("shaders_02/fragment.glsl");
@@ -27,12 +26,13 @@ struct Shaders02 {
     vao: u32,
     /// The shader program uniform for the time the program has been running
     time_uniform: u32,
-    /// The instant that the renderer was initialized
-    start_time: Instant,
 }
 
 impl RenderHandler for Shaders02 {
-    fn init(gl: &mut glow::Context) -> Self {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // Have the loop clear the window to our background color before every frame
+        ctx.render_settings.clear_color = Some([0., 0.2, 0.2, 1.].into());
+
         unsafe {
             //
             // Create and link shaders
@@ -40,8 +40,8 @@ impl RenderHandler for Shaders02 {
 
             // Create a vertex shader
             let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
-            // Load the shader's GLSL source
-            gl.shader_source(vertex_shader, VERTEX_SHADER_SRC);
+            // Load the shader's GLSL source, rewritten for the context's version of GLSL
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
             // Compile the vertex shader
             gl.compile_shader(vertex_shader);
             // Check for shader compile errors
@@ -49,8 +49,11 @@ impl RenderHandler for Shaders02 {
 
             // Create a fragment shader
             let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
-            // Load the shader's GLSL source
-            gl.shader_source(fragment_shader, FRAGMENT_SHADER_SRC);
+            // Load the shader's GLSL source, rewritten for the context's version of GLSL
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
             // Compile the fragment shader
             gl.compile_shader(fragment_shader);
             handle_shader_compile_errors(gl, fragment_shader);
@@ -158,26 +161,18 @@ impl RenderHandler for Shaders02 {
                 shader_program,
                 vao,
                 time_uniform,
-                start_time: Instant::now(),
             }
         }
     }
 
-    fn draw(&mut self, gl: &mut glow::Context) {
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
         unsafe {
-            // Clear the screen
-            gl.clear_color(0., 0.2, 0.2, 1.);
-            gl.clear(glow::COLOR_BUFFER_BIT);
-
             // Make the linked shader program our current shader program used for
             // draw operations.
             gl.use_program(Some(self.shader_program));
 
             // Update the time uniform for our shader program
-            gl.uniform_1_f32(
-                Some(&self.time_uniform),
-                self.start_time.elapsed().as_secs_f32(),
-            );
+            gl.uniform_1_f32(Some(&self.time_uniform), ctx.timing.time());
 
             // Bind our VAO which contains our vertex attribute and buffer information
             gl.bind_vertex_array(Some(self.vao));
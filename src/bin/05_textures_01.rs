@@ -1,6 +1,14 @@
 use glow::HasContext;
-use me_learning_opengl::{RenderHandler, SliceAsBytes};
-use std::{path::Path, time::Instant};
+use me_learning_opengl::{
+    color::Color,
+    debug_text::{DebugText, LINE_HEIGHT},
+    shader,
+    shader_variants::{ShaderVariants, VariantKey},
+    texture::{create_texture_2d, ImageData, Texture, TextureParams, TexturePurpose},
+    texture_debug::{TextureDebug, TextureDebugView, TEXTURE_DEBUG_CHUNK, TEXTURE_DEBUG_KEYS},
+    AppContext, RenderHandler, SliceAsBytes,
+};
+use winit::VirtualKeyCode;
 
 const VERTEX_SHADER_SRC: &str = include_str!("textures_01/vertex.glsl");
 const FRAGMENT_SHADER_SRC: &str = include_str!("textures_01/fragment.glsl");
@@ -25,16 +33,50 @@ struct Textures01 {
     /// Vertex Array Object: It's like a vertex attributes configuration
     /// "preset"
     vao: u32,
-    texture0: u32,
-    texture1: u32,
+    texture0: Texture,
+    texture1: Texture,
+    /// The mip level that the textures are clamped to, or `None` to use all of them ( cycled with
+    /// the M key )
+    clamped_mip_level: Option<u32>,
     /// The shader program uniform for the time the program has been running
     time_uniform: u32,
-    /// The instant that the renderer was initialized
-    start_time: Instant,
+    /// The variants of the shader program with the texture debug views
+    variants: ShaderVariants,
+    /// Which texture debug view is shown, instead of the textures themselves
+    texture_debug: TextureDebug,
+    /// The overlay listing the keys
+    text: DebugText,
+}
+
+impl Textures01 {
+    /// Clamp the textures to the next mip level, going back to using all of them after the last
+    fn cycle_mip_level(&mut self, gl: &mut glow::Context) {
+        let mip_levels = self.texture0.mip_levels.max(self.texture1.mip_levels);
+        self.clamped_mip_level = match self.clamped_mip_level {
+            None => Some(0),
+            Some(level) if level + 1 < mip_levels => Some(level + 1),
+            Some(_) => None,
+        };
+
+        for texture in &[&self.texture0, &self.texture1] {
+            match self.clamped_mip_level {
+                Some(level) => texture.set_level_range(gl, level, level),
+                None => texture.set_level_range(gl, 0, texture.mip_levels - 1),
+            }
+        }
+
+        match self.clamped_mip_level {
+            Some(level) => eprintln!("Showing mip level {}", level),
+            None => eprintln!("Showing all mip levels"),
+        }
+    }
 }
 
 impl RenderHandler for Textures01 {
-    fn init(gl: &mut glow::Context) -> Self {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // Have the loop clear the window to our background color before every frame
+        ctx.render_settings.clear_color = Some([0., 0.2, 0.2, 1.].into());
+
         unsafe {
             //
             // Create and link shaders
@@ -42,8 +84,8 @@ impl RenderHandler for Textures01 {
 
             // Create a vertex shader
             let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
-            // Load the shader's GLSL source
-            gl.shader_source(vertex_shader, VERTEX_SHADER_SRC);
+            // Load the shader's GLSL source, rewritten for the context's version of GLSL
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
             // Compile the vertex shader
             gl.compile_shader(vertex_shader);
             // Check for shader compile errors
@@ -51,8 +93,11 @@ impl RenderHandler for Textures01 {
 
             // Create a fragment shader
             let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
-            // Load the shader's GLSL source
-            gl.shader_source(fragment_shader, FRAGMENT_SHADER_SRC);
+            // Load the shader's GLSL source, rewritten for the context's version of GLSL
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
             // Compile the fragment shader
             gl.compile_shader(fragment_shader);
             handle_shader_compile_errors(gl, fragment_shader);
@@ -168,8 +213,25 @@ impl RenderHandler for Textures01 {
             // Enable the texture coordinate vertex attribute
             gl.enable_vertex_attrib_array(2);
 
-            let texture0 = load_and_bind_texture(gl, glow::TEXTURE0, "./assets/awesomeface.png");
-            let texture1 = load_and_bind_texture(gl, glow::TEXTURE1, "./assets/wall.jpg");
+            // Load our textures
+            let texture_params = TextureParams {
+                purpose: Some(TexturePurpose::Albedo),
+                ..Default::default()
+            };
+            gl.active_texture(glow::TEXTURE0);
+            let face = ImageData::open(ctx.config().asset_path("awesomeface.png"));
+            let texture0 = create_texture_2d(gl, ctx.features(), &[face], &texture_params);
+            gl.active_texture(glow::TEXTURE1);
+            let wall = ImageData::open(ctx.config().asset_path("wall.jpg"));
+            let texture1 = create_texture_2d(gl, ctx.features(), &[wall], &texture_params);
+
+            // The debug views are variants of the same shaders with the texture debug chunk
+            let mut variants = ShaderVariants::new();
+            variants.add_source(
+                "textures",
+                VERTEX_SHADER_SRC,
+                &shader::include_chunk(FRAGMENT_SHADER_SRC, TEXTURE_DEBUG_CHUNK),
+            );
 
             // Draw wireframe instead of solid
             // gl.polygon_mode(glow::FRONT_AND_BACK, glow::LINE);
@@ -180,42 +242,64 @@ impl RenderHandler for Textures01 {
                 time_uniform,
                 texture0,
                 texture1,
-                start_time: Instant::now(),
+                clamped_mip_level: None,
+                variants,
+                texture_debug: TextureDebug::default(),
+                text: DebugText::new(gl),
             }
         }
     }
 
-    fn draw(&mut self, gl: &mut glow::Context) {
-        unsafe {
-            // Clear the screen
-            gl.clear_color(0., 0.2, 0.2, 1.);
-            gl.clear(glow::COLOR_BUFFER_BIT);
-
-            // Make the linked shader program our current shader program used for
-            // draw operations.
-            gl.use_program(Some(self.shader_program));
-
-            // Update the time uniform for our shader program
-            gl.uniform_1_f32(
-                Some(&self.time_uniform),
-                self.start_time.elapsed().as_secs_f32(),
-            );
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        // Cycle through showing only one mip level at a time with the M key
+        if ctx.input.was_key_pressed(VirtualKeyCode::M) {
+            self.cycle_mip_level(gl);
+        }
+        let mip_levels = self.texture0.mip_levels.max(self.texture1.mip_levels);
+        self.texture_debug.handle_keys(&ctx.input, mip_levels);
 
+        unsafe {
             gl.active_texture(glow::TEXTURE0);
-            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture0));
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture0.texture));
             gl.active_texture(glow::TEXTURE1);
-            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture1));
-
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.shader_program, "imageTexture1")
-                    .as_ref(),
-                0,
-            );
-            gl.uniform_1_i32(
-                gl.get_uniform_location(self.shader_program, "imageTexture2")
-                    .as_ref(),
-                1,
-            );
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture1.texture));
+
+            if self.texture_debug.view == TextureDebugView::Off {
+                // Make the linked shader program our current shader program used for
+                // draw operations.
+                gl.use_program(Some(self.shader_program));
+
+                // Update the time uniform for our shader program
+                gl.uniform_1_f32(Some(&self.time_uniform), ctx.timing.time());
+
+                gl.uniform_1_i32(
+                    gl.get_uniform_location(self.shader_program, "imageTexture1")
+                        .as_ref(),
+                    0,
+                );
+                gl.uniform_1_i32(
+                    gl.get_uniform_location(self.shader_program, "imageTexture2")
+                        .as_ref(),
+                    1,
+                );
+            } else {
+                // Draw with the variant of the program for the debug view instead
+                let key = self
+                    .texture_debug
+                    .variant_key(&VariantKey::new("textures", &[]));
+                let program = self.variants.get(gl, &key).unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                });
+                program.bind(gl);
+                program.set_uniform(gl, "time", ctx.timing.time());
+                program.set_uniform(gl, "imageTexture1", 0);
+                program.set_uniform(gl, "imageTexture2", 1);
+                self.texture_debug.set_uniforms(gl, program);
+                // Both textures have the same format, and neither of them is lit
+                self.texture_debug
+                    .set_texture(gl, program, &self.texture0, false);
+            }
 
             // Bind our VAO which contains our vertex attribute and buffer information
             gl.bind_vertex_array(Some(self.vao));
@@ -223,6 +307,30 @@ impl RenderHandler for Textures01 {
             // Draw the triangle!
             gl.draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0);
         }
+
+        // List the keys, and what is being shown
+        let mut y = 4.;
+        for (key, action) in [("M", "clamp the textures to a mip level")]
+            .iter()
+            .chain(TEXTURE_DEBUG_KEYS)
+        {
+            self.text
+                .text(4., y, 1, Color::WHITE, &format!("{}: {}", key, action));
+            y += LINE_HEIGHT as f32;
+        }
+        self.text.text(
+            4.,
+            y + LINE_HEIGHT as f32,
+            1,
+            Color::YELLOW,
+            &format!("Showing: {}", self.texture_debug.status()),
+        );
+        self.text.draw(gl, &ctx.arena, ctx.render_size());
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.variants.delete(gl);
+        self.text.delete(gl);
     }
 }
 
@@ -247,58 +355,3 @@ fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
         }
     }
 }
-
-fn load_and_bind_texture<P: AsRef<Path>>(gl: &mut glow::Context, unit: u32, path: P) -> u32 {
-    unsafe {
-        // Select the texture unit
-        gl.active_texture(unit);
-
-        // Open the texture image
-        let img = image::open(path).unwrap();
-        let (width, height, pixels, format) = match img {
-            image::DynamicImage::ImageRgb8(img) => {
-                (img.width(), img.height(), img.into_raw(), glow::RGB)
-            }
-            image::DynamicImage::ImageRgba8(img) => {
-                (img.width(), img.height(), img.into_raw(), glow::RGBA)
-            }
-            _ => unimplemented!("Image format not implemented"),
-        };
-
-        // Create the GL texture for our rectangle
-        let texture = gl.create_texture().unwrap();
-        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
-
-        // Set our texure parameters
-        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, glow::REPEAT as i32);
-        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, glow::REPEAT as i32);
-        gl.tex_parameter_i32(
-            glow::TEXTURE_2D,
-            glow::TEXTURE_MIN_FILTER,
-            glow::LINEAR as i32,
-        );
-        gl.tex_parameter_i32(
-            glow::TEXTURE_2D,
-            glow::TEXTURE_MAG_FILTER,
-            glow::LINEAR as i32,
-        );
-
-        // Set our image data
-        gl.tex_image_2d(
-            glow::TEXTURE_2D,
-            0,
-            format as i32,
-            width as i32,
-            height as i32,
-            0,
-            format,
-            glow::UNSIGNED_BYTE,
-            Some(&pixels),
-        );
-
-        // Generate mipmaps
-        gl.generate_mipmap(glow::TEXTURE_2D);
-
-        texture
-    }
-}
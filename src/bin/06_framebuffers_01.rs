@@ -1,4 +1,6 @@
+use euclid::default::Size2D;
 use glow::HasContext;
+use me_learning_opengl::blit::{BlitFit, BlitRect};
 use surfman::{
     Connection, ContextAttributeFlags, ContextAttributes, GLVersion, SurfaceAccess, SurfaceType,
 };
@@ -9,20 +11,8 @@ use winit::{
 
 surfman::declare_surfman!();
 
-pub trait SliceAsBytes<T> {
-    fn as_mem_bytes(&self) -> &[u8];
-}
-
-impl<T: AsRef<[U]>, U> SliceAsBytes<U> for T {
-    fn as_mem_bytes(&self) -> &[u8] {
-        unsafe {
-            std::slice::from_raw_parts(
-                self.as_ref().as_ptr() as *const u8,
-                std::mem::size_of::<T>() * self.as_ref().len(),
-            )
-        }
-    }
-}
+/// The size of the image we render, which is copied into the window whatever size it is
+const IMAGE_SIZE: (u32, u32) = (800, 600);
 
 // From GFX:
 // https://github.com/katharostech/gfx/blob/77c3e28331f8ab593e57425b47db344f0e9e8112/src/backend/gl/src/lib.rs#L162
@@ -115,7 +105,14 @@ pub fn main() {
 
     // Loop through render events
     let mut exit = false;
+    // The new size of the window in physical pixels, if it was resized since the last frame
+    let mut resized = None;
     while !exit {
+        // Ask surfman how big the window surface really is, instead of assuming it is the size we
+        // asked for
+        let surface_size = device.context_surface_info(&context).unwrap().unwrap().size;
+        let surface_size = (surface_size.width as u32, surface_size.height as u32);
+
         // Draw the graphics
         unsafe {
             // Create and bind framebuffer
@@ -124,7 +121,12 @@ pub fn main() {
             // Create and bind renderbuffer
             let rbo = gl.create_renderbuffer().unwrap();
             gl.bind_renderbuffer(glow::RENDERBUFFER, Some(rbo));
-            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGB, 800, 600);
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::RGB,
+                IMAGE_SIZE.0 as i32,
+                IMAGE_SIZE.1 as i32,
+            );
 
             // Attach renderbuffer to framebuffer
             gl.framebuffer_renderbuffer(
@@ -153,18 +155,18 @@ pub fn main() {
                 Some(rbo),
             );
 
-            gl.blit_framebuffer(
-                0,
-                0,
-                800,
-                600,
-                0,
-                0,
-                800,
-                600,
-                glow::COLOR_BUFFER_BIT,
-                glow::LINEAR,
-            );
+            // Clear the window black, for the bars around the image if the window is a different
+            // shape than it
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+
+            // Copy the whole image into the middle of the window, as big as it fits without
+            // stretching it
+            let blit = BlitRect::fit(IMAGE_SIZE, surface_size, BlitFit::Fit);
+            if let Err(error) = blit.validate(IMAGE_SIZE, surface_size) {
+                panic!("{}", error);
+            }
+            blit.blit(&mut gl, glow::COLOR_BUFFER_BIT, glow::LINEAR);
 
             let ecode = gl.get_error();
             if ecode != glow::NO_ERROR {
@@ -177,6 +179,10 @@ pub fn main() {
             .unwrap()
             .unwrap();
         device.present_surface(&context, &mut surface).unwrap();
+        // Resize the surface to match the window, which has to happen while it is unbound
+        if let Some(size) = resized.take() {
+            device.resize_surface(&context, &mut surface, size).unwrap();
+        }
         device
             .bind_surface_to_context(&mut context, surface)
             .unwrap();
@@ -199,6 +205,13 @@ pub fn main() {
                     }),
                 ..
             } => exit = true,
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                let size = size.to_physical(window.get_hidpi_factor());
+                resized = Some(Size2D::new(size.width as i32, size.height as i32));
+            }
             _ => {}
         });
     }
@@ -1,4 +1,10 @@
+use euclid::default::Size2D;
 use glow::HasContext;
+use me_learning_opengl::{
+    blit::{BlitFit, BlitRect},
+    workarounds::Workarounds,
+    Config,
+};
 use surfman::{
     Connection, ContextAttributeFlags, ContextAttributes, GLVersion, SurfaceAccess, SurfaceType,
 };
@@ -9,20 +15,8 @@ use winit::{
 
 surfman::declare_surfman!();
 
-pub trait SliceAsBytes<T> {
-    fn as_mem_bytes(&self) -> &[u8];
-}
-
-impl<T: AsRef<[U]>, U> SliceAsBytes<U> for T {
-    fn as_mem_bytes(&self) -> &[u8] {
-        unsafe {
-            std::slice::from_raw_parts(
-                self.as_ref().as_ptr() as *const u8,
-                std::mem::size_of::<T>() * self.as_ref().len(),
-            )
-        }
-    }
-}
+/// The size of the image we render, which is copied into the window whatever size it is
+const IMAGE_SIZE: (u32, u32) = (800, 600);
 
 // From GFX:
 // https://github.com/katharostech/gfx/blob/77c3e28331f8ab593e57425b47db344f0e9e8112/src/backend/gl/src/lib.rs#L162
@@ -116,15 +110,38 @@ pub fn main() {
     device.make_context_current(&root_context).unwrap();
 
     // Get a pointer to the OpenGL functions
-    let gl = unsafe {
+    let mut gl = unsafe {
         glow::Context::from_loader_function(|s| {
             device.get_proc_address(&surface_context, s) as *const _
         })
     };
 
+    // Check which driver quirks we have to work around, since this example doesn't go through the
+    // loop that normally does that
+    let workarounds = unsafe {
+        Workarounds::for_driver(
+            "Framebuffers 02",
+            &gl.get_parameter_string(glow::VENDOR),
+            &gl.get_parameter_string(glow::RENDERER),
+            &gl.get_parameter_string(glow::VERSION),
+            &Config::load().workarounds,
+        )
+    };
+
     // Loop through render events
     let mut exit = false;
+    // The new size of the window in physical pixels, if it was resized since the last frame
+    let mut resized = None;
     while !exit {
+        // Ask surfman how big the window surface really is, instead of assuming it is the size we
+        // asked for
+        let surface_size = device
+            .context_surface_info(&surface_context)
+            .unwrap()
+            .unwrap()
+            .size;
+        let surface_size = (surface_size.width as u32, surface_size.height as u32);
+
         // Draw the graphics
         unsafe {
             // Create and bind a framebuffer ( this is like our swapchain framebuffer )
@@ -134,7 +151,12 @@ pub fn main() {
             // Create and bind renderbuffer
             let rbo = gl.create_renderbuffer().unwrap();
             gl.bind_renderbuffer(glow::RENDERBUFFER, Some(rbo));
-            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGB, 800, 600);
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::RGB,
+                IMAGE_SIZE.0 as i32,
+                IMAGE_SIZE.1 as i32,
+            );
 
             // Attach renderbuffer to framebuffer
             gl.framebuffer_renderbuffer(
@@ -157,7 +179,11 @@ pub fn main() {
             // OK, blit time
             //
 
-            // Now we need to switch to our surface context
+            // Now we need to switch to our surface context. Some drivers don't let the surface
+            // context see what the root context drew until the root context is flushed.
+            if workarounds.flush_before_context_switch {
+                gl.flush();
+            }
             device.make_context_current(&surface_context).unwrap();
 
             // We need to create a framebuffer that we can blit from. We need to create this FBO instead of
@@ -185,18 +211,18 @@ pub fn main() {
             // Now we can blit from our surface_tmp_fbo and, because it is bound to the RBO that we rendered
             // to in the root context through the swapchain_fbo, we will should get an orange screen feed
             // to our window surface.
-            gl.blit_framebuffer(
-                0,
-                0,
-                800,
-                600,
-                0,
-                0,
-                800,
-                600,
-                glow::COLOR_BUFFER_BIT,
-                glow::LINEAR,
-            );
+            // Clear the window black, for the bars around the image if the window is a different
+            // shape than it
+            gl.clear_color(0.0, 0.0, 0.0, 1.0);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+
+            // Copy the whole image into the middle of the window, as big as it fits without
+            // stretching it
+            let blit = BlitRect::fit(IMAGE_SIZE, surface_size, BlitFit::Fit);
+            if let Err(error) = blit.validate(IMAGE_SIZE, surface_size) {
+                panic!("{}", error);
+            }
+            blit.blit(&mut gl, glow::COLOR_BUFFER_BIT, glow::LINEAR);
 
             gl.delete_framebuffer(surface_tmp_fbo);
             gl.delete_framebuffer(swapchain_fbo);
@@ -214,6 +240,12 @@ pub fn main() {
         device
             .present_surface(&surface_context, &mut surface)
             .unwrap();
+        // Resize the surface to match the window, which has to happen while it is unbound
+        if let Some(size) = resized.take() {
+            device
+                .resize_surface(&surface_context, &mut surface, size)
+                .unwrap();
+        }
         device
             .bind_surface_to_context(&mut surface_context, surface)
             .unwrap();
@@ -236,6 +268,13 @@ pub fn main() {
                     }),
                 ..
             } => exit = true,
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            } => {
+                let size = size.to_physical(window.get_hidpi_factor());
+                resized = Some(Size2D::new(size.width as i32, size.height as i32));
+            }
             _ => {}
         });
     }
@@ -0,0 +1,168 @@
+use glow::HasContext;
+use me_learning_opengl::{
+    handler_factory, shader, with_windows_and_config, AppContext, DemoArgs, RenderHandler,
+    SliceAsBytes, WindowConfig,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("hello_triangle/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("hello_triangle/fragment.glsl");
+const TRI_VERTICES: &[f32] = &[-0.5, -0.5, 0.0, 0.5, -0.5, 0.0, 0.0, 0.5, 0.0];
+
+/// Renders the triangle filled in, like the hello triangle example
+struct Scene {
+    shader_program: u32,
+    vao: u32,
+}
+
+/// Renders the same triangle as a wireframe in a second window
+struct DebugView {
+    shader_program: u32,
+    vao: u32,
+}
+
+impl RenderHandler for Scene {
+    fn init(gl: &mut glow::Context, _ctx: &mut AppContext) -> Self {
+        let (shader_program, vao) = create_triangle(gl);
+        Self {
+            shader_program,
+            vao,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        // Pulse the background using this window's own timing. The loop clears the window before
+        // calling `draw`, so the new color shows up on the next frame.
+        let pulse = (ctx.timing.time().sin() + 1.) / 2.;
+        ctx.render_settings.clear_color = Some([0., 0.8 * pulse, 0.8, 1.].into());
+
+        unsafe {
+            gl.use_program(Some(self.shader_program));
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+    }
+}
+
+impl RenderHandler for DebugView {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.1, 1.].into());
+
+        let (shader_program, vao) = create_triangle(gl);
+        Self {
+            shader_program,
+            vao,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        unsafe {
+            // Draw wireframe instead of solid. Polygon mode is context state, so this doesn't
+            // affect the scene window.
+            gl.polygon_mode(glow::FRONT_AND_BACK, glow::LINE);
+
+            gl.use_program(Some(self.shader_program));
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+    }
+}
+
+fn main() {
+    // Each window's placement is remembered separately with `--remember-window true`
+    let args = DemoArgs::parse();
+    with_windows_and_config(
+        args.config,
+        vec![
+            (
+                WindowConfig {
+                    title: "Scene".into(),
+                    reset_placement: args.reset_window,
+                    fresh_state: args.fresh,
+                    ..Default::default()
+                },
+                handler_factory::<Scene>(),
+            ),
+            (
+                WindowConfig {
+                    title: "Debug View".into(),
+                    width: 400,
+                    height: 300,
+                    reset_placement: args.reset_window,
+                    fresh_state: args.fresh,
+                    ..Default::default()
+                },
+                handler_factory::<DebugView>(),
+            ),
+        ],
+    );
+}
+
+/// Compile the triangle shader program and upload the triangle to a new VAO
+fn create_triangle(gl: &mut glow::Context) -> (u32, u32) {
+    unsafe {
+        // Create and compile the shaders
+        let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+        gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+        gl.compile_shader(vertex_shader);
+        handle_shader_compile_errors(gl, vertex_shader);
+
+        let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+        gl.shader_source(
+            fragment_shader,
+            &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+        );
+        gl.compile_shader(fragment_shader);
+        handle_shader_compile_errors(gl, fragment_shader);
+
+        // Link the shader program
+        let shader_program = gl.create_program().unwrap();
+        gl.attach_shader(shader_program, vertex_shader);
+        gl.attach_shader(shader_program, fragment_shader);
+        gl.link_program(shader_program);
+        handle_program_link_errors(gl, shader_program);
+
+        gl.delete_shader(vertex_shader);
+        gl.delete_shader(fragment_shader);
+
+        // Create the VAO and VBO. Each window has its own GL context and VAOs are never shared
+        // between contexts, so every window has to create its own.
+        let vao = gl.create_vertex_array().unwrap();
+        let vbo = gl.create_buffer().unwrap();
+        gl.bind_vertex_array(Some(vao));
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+        gl.buffer_data_u8_slice(
+            glow::ARRAY_BUFFER,
+            TRI_VERTICES.as_mem_bytes(),
+            glow::STATIC_DRAW,
+        );
+        gl.vertex_attrib_pointer_f32(
+            0,
+            3,
+            glow::FLOAT,
+            false,
+            3 * std::mem::size_of::<f32>() as i32,
+            0,
+        );
+        gl.enable_vertex_attrib_array(0);
+
+        (shader_program, vao)
+    }
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use glow::HasContext;
+use me_learning_opengl::{
+    color::Color,
+    shader,
+    viewport::{render_inset, Rect},
+    AppContext, RenderHandler, SliceAsBytes,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("shaders_02/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("shaders_02/fragment.glsl");
+
+// Make a square
+const TRI_VERTICES: &[f32] = &[
+    // Positions        // Colors
+    -0.5, -0.5, 0.0, 1., 0., 0., 1., // bottom left
+    0.5, -0.5, 0.0, 0., 1., 0., 1., // bottom right
+    0.5, 0.5, 0.0, 0., 0., 1., 1., // top right
+    -0.5, 0.5, 0.0, 0.5, 0.5, 0.5, 1., // top left
+];
+const TRI_VERTICE_INDEXES: &[u32] = &[
+    0, 1, 2, // First triangle
+    0, 2, 3, // Second triangle
+];
+
+/// How far behind the left view the right view is in its animation
+const RIGHT_VIEW_TIME_OFFSET: f32 = 1.5;
+/// The size of the minimap inset as a fraction of the window height
+const MINIMAP_SCALE: f32 = 0.3;
+/// The distance between the minimap and the window corner in physical pixels, at a UI scale of 1
+const MINIMAP_MARGIN: i32 = 10;
+
+struct SplitScreen {
+    shader_program: u32,
+    vao: u32,
+    time_uniform: u32,
+}
+
+impl SplitScreen {
+    /// Draw the animated square at the given point in time into the current viewport
+    fn draw_scene(&self, gl: &mut glow::Context, time: f32) {
+        unsafe {
+            gl.use_program(Some(self.shader_program));
+            gl.uniform_1_f32(Some(&self.time_uniform), time);
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0);
+        }
+    }
+}
+
+impl RenderHandler for SplitScreen {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // Have the loop clear the window to our background color before every frame
+        ctx.render_settings.clear_color = Some([0., 0.2, 0.2, 1.].into());
+
+        unsafe {
+            // Create and compile the shaders
+            let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+            gl.compile_shader(vertex_shader);
+            handle_shader_compile_errors(gl, vertex_shader);
+
+            let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(fragment_shader);
+            handle_shader_compile_errors(gl, fragment_shader);
+
+            // Link the shader program
+            let shader_program = gl.create_program().unwrap();
+            gl.attach_shader(shader_program, vertex_shader);
+            gl.attach_shader(shader_program, fragment_shader);
+            gl.link_program(shader_program);
+            handle_program_link_errors(gl, shader_program);
+
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+
+            let time_uniform = gl.get_uniform_location(shader_program, "time").unwrap();
+
+            // Create the VAO, VBO, and EBO for our square
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                TRI_VERTICES.as_mem_bytes(),
+                glow::STATIC_DRAW,
+            );
+
+            let ebo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+            gl.buffer_data_u8_slice(
+                glow::ELEMENT_ARRAY_BUFFER,
+                TRI_VERTICE_INDEXES.as_mem_bytes(),
+                glow::STATIC_DRAW,
+            );
+
+            // Position attribute
+            gl.vertex_attrib_pointer_f32(
+                0,
+                3,
+                glow::FLOAT,
+                false,
+                7 * std::mem::size_of::<f32>() as i32,
+                0,
+            );
+            gl.enable_vertex_attrib_array(0);
+            // Color attribute
+            gl.vertex_attrib_pointer_f32(
+                1,
+                4,
+                glow::FLOAT,
+                false,
+                7 * std::mem::size_of::<f32>() as i32,
+                3 * std::mem::size_of::<f32>() as i32,
+            );
+            gl.enable_vertex_attrib_array(1);
+
+            Self {
+                shader_program,
+                vao,
+                time_uniform,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let (width, height) = ctx.window_size();
+        let (width, height) = (width as i32, height as i32);
+        let time = ctx.timing.time();
+
+        // Draw the left and right halves of the split screen
+        let left = Rect::new(0, 0, width / 2, height);
+        let right = Rect::new(width / 2, 0, width - width / 2, height);
+        left.set_viewport(gl);
+        self.draw_scene(gl, time);
+        right.set_viewport(gl);
+        self.draw_scene(gl, time - RIGHT_VIEW_TIME_OFFSET);
+
+        // Draw the minimap in the top right corner, over the top of both halves
+        let minimap_size = (height as f32 * MINIMAP_SCALE) as i32;
+        let margin = ctx.scale_ui(MINIMAP_MARGIN);
+        let minimap = Rect::new(
+            width - minimap_size - margin,
+            height - minimap_size - margin,
+            minimap_size,
+            minimap_size,
+        );
+        render_inset(gl, ctx, minimap, Some(Color::WHITE), |gl| {
+            // The minimap runs at double speed so it's easy to tell apart
+            self.draw_scene(gl, time * 2.);
+        });
+    }
+}
+
+fn main() {
+    me_learning_opengl::with_window::<SplitScreen>();
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+use cgmath::{perspective, Deg, Matrix4, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    color::Color,
+    mipmap::MipmapMode,
+    procedural, shader,
+    texture::{create_texture_2d, Texture, TextureParams},
+    viewport::Rect,
+    AppContext, RenderHandler, SliceAsBytes,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("mipmap_filters/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("mipmap_filters/fragment.glsl");
+
+// A long floor that stretches away from the camera
+const FLOOR_VERTICES: &[f32] = &[
+    // Positions (3)    // TexCoords (2)
+    -1., 0., 0., 0., 0., // near left
+    1., 0., 0., 8., 0., // near right
+    1., 0., -40., 8., 320., // far right
+    -1., 0., -40., 0., 320., // far left
+];
+const FLOOR_INDEXES: &[u32] = &[
+    0, 1, 2, // First triangle
+    0, 2, 3, // Second triangle
+];
+
+/// The size of the checker texture in pixels
+const CHECKER_SIZE: u32 = 256;
+
+/// The mipmap modes to compare, from left to right
+const MODES: &[MipmapMode] = &[
+    MipmapMode::None,
+    MipmapMode::DriverGenerate,
+    MipmapMode::Box,
+    MipmapMode::Lanczos,
+];
+
+struct MipmapFilters {
+    shader_program: u32,
+    vao: u32,
+    transform_uniform: u32,
+    /// One checker texture for each of the `MODES`
+    textures: Vec<Texture>,
+}
+
+impl RenderHandler for MipmapFilters {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.2, 0.2, 0.2, 1.].into());
+
+        // A checker pattern with single pixel checks is about as high frequency as a texture gets,
+        // so it shows off the differences between the mipmap filters
+        let checker =
+            procedural::checkerboard(CHECKER_SIZE, CHECKER_SIZE, 1, Color::WHITE, Color::BLACK);
+
+        // Create a texture with each of the mipmap modes
+        let textures = MODES
+            .iter()
+            .map(|&mipmaps| {
+                create_texture_2d(
+                    gl,
+                    ctx.features(),
+                    std::slice::from_ref(&checker),
+                    &TextureParams {
+                        mipmaps,
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        eprintln!("Mipmap modes from left to right: {:?}", MODES);
+
+        unsafe {
+            // Create and compile the shaders
+            let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+            gl.compile_shader(vertex_shader);
+            handle_shader_compile_errors(gl, vertex_shader);
+
+            let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(fragment_shader);
+            handle_shader_compile_errors(gl, fragment_shader);
+
+            // Link the shader program
+            let shader_program = gl.create_program().unwrap();
+            gl.attach_shader(shader_program, vertex_shader);
+            gl.attach_shader(shader_program, fragment_shader);
+            gl.link_program(shader_program);
+            handle_program_link_errors(gl, shader_program);
+
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+
+            let transform_uniform = gl
+                .get_uniform_location(shader_program, "transform")
+                .unwrap();
+
+            // Create the VAO, VBO, and EBO for the floor
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                FLOOR_VERTICES.as_mem_bytes(),
+                glow::STATIC_DRAW,
+            );
+
+            let ebo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+            gl.buffer_data_u8_slice(
+                glow::ELEMENT_ARRAY_BUFFER,
+                FLOOR_INDEXES.as_mem_bytes(),
+                glow::STATIC_DRAW,
+            );
+
+            // Position attribute
+            gl.vertex_attrib_pointer_f32(
+                0,
+                3,
+                glow::FLOAT,
+                false,
+                5 * std::mem::size_of::<f32>() as i32,
+                0,
+            );
+            gl.enable_vertex_attrib_array(0);
+            // Texture coordinate attribute
+            gl.vertex_attrib_pointer_f32(
+                1,
+                2,
+                glow::FLOAT,
+                false,
+                5 * std::mem::size_of::<f32>() as i32,
+                3 * std::mem::size_of::<f32>() as i32,
+            );
+            gl.enable_vertex_attrib_array(1);
+
+            Self {
+                shader_program,
+                vao,
+                transform_uniform,
+                textures,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let (width, height) = ctx.window_size();
+        let (width, height) = (width as i32, height as i32);
+        let column_width = width / self.textures.len() as i32;
+
+        for (i, texture) in self.textures.iter().enumerate() {
+            // Draw each texture in its own column of the window
+            let column = Rect::new(i as i32 * column_width, 0, column_width, height);
+            column.set_viewport(gl);
+
+            // Look down the floor from just above it
+            let projection: Matrix4<f32> = perspective(Deg(60.), column.aspect_ratio(), 0.1, 100.);
+            let view = Matrix4::from_translation(Vector3::new(0., -0.5, 0.));
+            let transform = projection * view;
+            let transform: &[f32; 16] = transform.as_ref();
+
+            unsafe {
+                gl.use_program(Some(self.shader_program));
+                gl.uniform_matrix_4_f32_slice(Some(&self.transform_uniform), false, transform);
+                gl.active_texture(glow::TEXTURE0);
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                gl.bind_vertex_array(Some(self.vao));
+                gl.draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0);
+            }
+        }
+
+        // Put the viewport back for the rest of the frame
+        Rect::from_window_size(ctx.window_size()).set_viewport(gl);
+    }
+}
+
+fn main() {
+    me_learning_opengl::with_window::<MipmapFilters>();
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
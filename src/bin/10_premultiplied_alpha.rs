@@ -0,0 +1,239 @@
+use glow::HasContext;
+use me_learning_opengl::{
+    blend::BlendMode,
+    shader,
+    texture::{create_texture_2d, AlphaMode, ImageData, Texture, TextureParams, TexturePurpose},
+    viewport::Rect,
+    AppContext, RenderHandler, SliceAsBytes,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("premultiplied_alpha/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("premultiplied_alpha/fragment.glsl");
+
+// A square sprite
+const SPRITE_VERTICES: &[f32] = &[
+    // Positions (2)  // TexCoords (2)
+    -1., -1., 0., 0., // bottom left
+    1., -1., 1., 0., // bottom right
+    1., 1., 1., 1., // top right
+    -1., 1., 0., 1., // top left
+];
+const SPRITE_INDEXES: &[u32] = &[
+    0, 1, 2, // First triangle
+    0, 2, 3, // Second triangle
+];
+
+/// The size of the sprite texture in pixels. It's tiny so that the magnified edges are easy to see.
+const SPRITE_SIZE: u32 = 16;
+
+/// The offset, scale, and tint of each of the overlapping sprites
+const SPRITES: &[([f32; 2], f32, [f32; 3])] = &[
+    ([-0.3, -0.2], 0.5, [1., 0.2, 0.2]),
+    ([0.2, -0.3], 0.45, [0.2, 1., 0.2]),
+    ([0., 0.25], 0.55, [0.2, 0.3, 1.]),
+];
+
+struct PremultipliedAlpha {
+    shader_program: u32,
+    vao: u32,
+    offset_uniform: u32,
+    scale_uniform: u32,
+    tint_uniform: u32,
+    /// The sprite with straight alpha, drawn on the left with straight blending
+    straight: Texture,
+    /// The sprite with premultiplied alpha, drawn on the right with premultiplied blending
+    premultiplied: Texture,
+}
+
+impl PremultipliedAlpha {
+    /// Draw all of the sprites with the given texture and blend mode into the current viewport
+    fn draw_sprites(&self, gl: &mut glow::Context, texture: &Texture, blend_mode: BlendMode) {
+        blend_mode.apply(gl);
+
+        unsafe {
+            gl.use_program(Some(self.shader_program));
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+            gl.bind_vertex_array(Some(self.vao));
+
+            for &([x, y], scale, [r, g, b]) in SPRITES {
+                gl.uniform_2_f32(Some(&self.offset_uniform), x, y);
+                gl.uniform_1_f32(Some(&self.scale_uniform), scale);
+                gl.uniform_3_f32(Some(&self.tint_uniform), r, g, b);
+                gl.draw_elements(glow::TRIANGLES, 6, glow::UNSIGNED_INT, 0);
+            }
+        }
+
+        BlendMode::Opaque.apply(gl);
+    }
+}
+
+impl RenderHandler for PremultipliedAlpha {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // Fringes show up best against a bright background
+        ctx.render_settings.clear_color = Some([1., 1., 0.9, 1.].into());
+
+        // A translucent white circle. Like most image editors export them, the fully transparent pixels
+        // are black, which is what gets blended in as a dark fringe with straight alpha.
+        let sprite = ImageData {
+            width: SPRITE_SIZE,
+            height: SPRITE_SIZE,
+            format: glow::RGBA,
+            alpha: AlphaMode::Straight,
+            pixels: (0..SPRITE_SIZE * SPRITE_SIZE)
+                .flat_map(|i| {
+                    let center = SPRITE_SIZE as f32 / 2.;
+                    let x = (i % SPRITE_SIZE) as f32 + 0.5 - center;
+                    let y = (i / SPRITE_SIZE) as f32 + 0.5 - center;
+                    let distance = (x * x + y * y).sqrt() / center;
+                    if distance < 0.8 {
+                        vec![255, 255, 255, 200]
+                    } else {
+                        vec![0, 0, 0, 0]
+                    }
+                })
+                .collect(),
+        };
+
+        // Magnify the sprite with linear filtering so that the edge pixels get blended with their
+        // transparent neighbors
+        let params = TextureParams {
+            wrap_s: glow::CLAMP_TO_EDGE,
+            wrap_t: glow::CLAMP_TO_EDGE,
+            purpose: Some(TexturePurpose::Ui),
+            ..Default::default()
+        };
+        let straight =
+            create_texture_2d(gl, ctx.features(), std::slice::from_ref(&sprite), &params);
+        let premultiplied = create_texture_2d(
+            gl,
+            ctx.features(),
+            &[sprite],
+            &TextureParams {
+                premultiply: true,
+                ..params
+            },
+        );
+
+        // Make sure we paired our textures with the right blend modes
+        BlendMode::Straight.check_texture(&straight);
+        BlendMode::Premultiplied.check_texture(&premultiplied);
+
+        unsafe {
+            // Create and compile the shaders
+            let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+            gl.compile_shader(vertex_shader);
+            handle_shader_compile_errors(gl, vertex_shader);
+
+            let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(fragment_shader);
+            handle_shader_compile_errors(gl, fragment_shader);
+
+            // Link the shader program
+            let shader_program = gl.create_program().unwrap();
+            gl.attach_shader(shader_program, vertex_shader);
+            gl.attach_shader(shader_program, fragment_shader);
+            gl.link_program(shader_program);
+            handle_program_link_errors(gl, shader_program);
+
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+
+            let offset_uniform = gl.get_uniform_location(shader_program, "offset").unwrap();
+            let scale_uniform = gl.get_uniform_location(shader_program, "scale").unwrap();
+            let tint_uniform = gl.get_uniform_location(shader_program, "tint").unwrap();
+
+            // Create the VAO, VBO, and EBO for the sprite
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                SPRITE_VERTICES.as_mem_bytes(),
+                glow::STATIC_DRAW,
+            );
+
+            let ebo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+            gl.buffer_data_u8_slice(
+                glow::ELEMENT_ARRAY_BUFFER,
+                SPRITE_INDEXES.as_mem_bytes(),
+                glow::STATIC_DRAW,
+            );
+
+            // Position attribute
+            gl.vertex_attrib_pointer_f32(
+                0,
+                2,
+                glow::FLOAT,
+                false,
+                4 * std::mem::size_of::<f32>() as i32,
+                0,
+            );
+            gl.enable_vertex_attrib_array(0);
+            // Texture coordinate attribute
+            gl.vertex_attrib_pointer_f32(
+                1,
+                2,
+                glow::FLOAT,
+                false,
+                4 * std::mem::size_of::<f32>() as i32,
+                2 * std::mem::size_of::<f32>() as i32,
+            );
+            gl.enable_vertex_attrib_array(1);
+
+            Self {
+                shader_program,
+                vao,
+                offset_uniform,
+                scale_uniform,
+                tint_uniform,
+                straight,
+                premultiplied,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let (width, height) = ctx.window_size();
+        let (width, height) = (width as i32, height as i32);
+
+        // Straight alpha on the left, premultiplied alpha on the right
+        Rect::new(0, 0, width / 2, height).set_viewport(gl);
+        self.draw_sprites(gl, &self.straight, BlendMode::Straight);
+        Rect::new(width / 2, 0, width - width / 2, height).set_viewport(gl);
+        self.draw_sprites(gl, &self.premultiplied, BlendMode::Premultiplied);
+
+        // Put the viewport back for the rest of the frame
+        Rect::from_window_size(ctx.window_size()).set_viewport(gl);
+    }
+}
+
+fn main() {
+    me_learning_opengl::with_window::<PremultipliedAlpha>();
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
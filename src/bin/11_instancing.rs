@@ -0,0 +1,335 @@
+use std::time::Duration;
+
+use glow::HasContext;
+use me_learning_opengl::{
+    instance_buffer::{BufferUsage, InstanceBuffer, DEFAULT_BUFFER_COUNT},
+    shader,
+    vertex::{f32_to_f16, pack_snorm_10_10_10_2, pack_unorm8x4, VertexFormat, VertexLayout},
+    AppContext, DemoArgs, RenderHandler, SliceAsBytes,
+};
+use rand::Rng;
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("instancing/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("instancing/fragment.glsl");
+
+/// The number of instances along each side of the grid, which makes about 100k instances
+const GRID_SIZE: usize = 316;
+/// The size of each instance's square in normalized device coordinates
+const TILE_SIZE: f32 = 1.6 / GRID_SIZE as f32;
+
+// A small square, drawn once for each instance
+const TILE_VERTICES: &[f32] = &[
+    0., 0., // bottom left
+    TILE_SIZE, 0., // bottom right
+    TILE_SIZE, TILE_SIZE, // top right
+    0., 0., // bottom left
+    TILE_SIZE, TILE_SIZE, // top right
+    0., TILE_SIZE, // top left
+];
+
+/// How far the tiles bob up and down, in normalized device coordinates
+const WAVE_HEIGHT: f32 = 0.02;
+/// How many frames to wait between printing the frame and GPU times
+const REPORT_INTERVAL: u64 = 120;
+
+/// The data for one instance before it is put in a buffer
+struct Instance {
+    offset: [f32; 2],
+    color: [f32; 4],
+    normal: [f32; 3],
+}
+
+struct Instancing {
+    shader_program: u32,
+    instances: Vec<Instance>,
+    /// The VAO reading the tile vertices, with an instance buffer attached before drawing
+    vao: u32,
+    /// The instances stored as full floats
+    full_buffer: InstanceBuffer,
+    /// The instances stored in packed formats
+    packed_buffer: InstanceBuffer,
+    light_direction_uniform: u32,
+    /// Whether or not to draw with the packed instance buffer ( toggled with the space bar )
+    use_packed: bool,
+    /// Times how long the GPU takes to draw the instances, if timer queries are supported
+    timer_query: Option<u32>,
+    /// Whether or not the timer query has a result on the way
+    timer_pending: bool,
+    /// The last time measured by the timer query
+    gpu_time: Option<Duration>,
+}
+
+impl RenderHandler for Instancing {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.1, 1.].into());
+
+        // Make a grid of instances with random colors and normals
+        let mut rng = rand::thread_rng();
+        let instances = (0..GRID_SIZE * GRID_SIZE)
+            .map(|i| {
+                let normal = [
+                    rng.gen_range(-1., 1.),
+                    rng.gen_range(-1., 1.),
+                    rng.gen_range(0.2, 1.),
+                ];
+                let length = normal.iter().map(|x| x * x).sum::<f32>().sqrt();
+                Instance {
+                    offset: [
+                        (i % GRID_SIZE) as f32 * TILE_SIZE - 0.8,
+                        (i / GRID_SIZE) as f32 * TILE_SIZE - 0.8,
+                    ],
+                    color: [rng.gen(), rng.gen(), rng.gen(), 1.],
+                    normal: [normal[0] / length, normal[1] / length, normal[2] / length],
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // The same instance data stored two ways: as full floats, and packed into half floats,
+        // normalized bytes, and 10-10-10-2 normals
+        let full_layout = VertexLayout::new(&[
+            (1, VertexFormat::Float32x2),
+            (2, VertexFormat::Float32x4),
+            (3, VertexFormat::Float32x3),
+        ])
+        .per_instance();
+        let packed_layout = VertexLayout::new(&[
+            (1, VertexFormat::Float16x2),
+            (2, VertexFormat::Unorm8x4),
+            (3, VertexFormat::Snorm10_10_10_2),
+        ])
+        .per_instance();
+
+        eprintln!(
+            "Bytes per instance: {} full, {} packed ( {} vs {} bytes for {} instances )",
+            full_layout.stride(),
+            packed_layout.stride(),
+            full_layout.stride() * instances.len(),
+            packed_layout.stride() * instances.len(),
+            instances.len()
+        );
+        eprintln!("Press space to switch between the full and packed instance buffers");
+        eprintln!("Press B to switch between single and multiple buffered instance buffers");
+
+        // The instances move every frame, so the buffers are rewritten every frame
+        let features = ctx.features();
+        let full_buffer = InstanceBuffer::new(features, full_layout, BufferUsage::Dynamic)
+            .with_label("Full instance buffer");
+        let packed_buffer = InstanceBuffer::new(features, packed_layout, BufferUsage::Dynamic)
+            .with_label("Packed instance buffer");
+        if !full_buffer.is_fenced() {
+            eprintln!("Warning: glBufferStorage isn't supported, so the instance buffers are orphaned instead of fenced");
+        }
+        let timer_query = if features.timer_query {
+            Some(unsafe { gl.create_query().unwrap() })
+        } else {
+            None
+        };
+
+        unsafe {
+            // Create and compile the shaders
+            let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+            gl.compile_shader(vertex_shader);
+            handle_shader_compile_errors(gl, vertex_shader);
+
+            let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(fragment_shader);
+            handle_shader_compile_errors(gl, fragment_shader);
+
+            // Link the shader program
+            let shader_program = gl.create_program().unwrap();
+            gl.attach_shader(shader_program, vertex_shader);
+            gl.attach_shader(shader_program, fragment_shader);
+            gl.link_program(shader_program);
+            handle_program_link_errors(gl, shader_program);
+
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+
+            let light_direction_uniform = gl
+                .get_uniform_location(shader_program, "lightDirection")
+                .unwrap();
+
+            // Both VAOs share the tile vertices
+            let tile_vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(tile_vbo));
+            gl.buffer_data_u8_slice(
+                glow::ARRAY_BUFFER,
+                TILE_VERTICES.as_mem_bytes(),
+                glow::STATIC_DRAW,
+            );
+            let tile_layout = VertexLayout::new(&[(0, VertexFormat::Float32x2)]);
+
+            // The instance buffers are attached to the VAO when drawing, because a dynamic buffer
+            // is a different buffer every frame
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(tile_vbo));
+            tile_layout.apply(gl);
+            gl.bind_vertex_array(None);
+
+            Self {
+                shader_program,
+                instances,
+                vao,
+                full_buffer,
+                packed_buffer,
+                light_direction_uniform,
+                use_packed: true,
+                timer_query,
+                timer_pending: false,
+                gpu_time: None,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        // Switch between the instance buffers with the space bar. The picture shouldn't change.
+        if ctx.input.was_key_pressed(VirtualKeyCode::Space) {
+            self.use_packed = !self.use_packed;
+            eprintln!(
+                "Drawing {} instance buffer",
+                if self.use_packed { "packed" } else { "full" }
+            );
+        }
+
+        // Switch between one buffer, which makes every write wait for the GPU to finish the last
+        // frame, and several buffers, which let the GPU draw from one while the next is written
+        if ctx.input.was_key_pressed(VirtualKeyCode::B) {
+            let buffer_count = if self.full_buffer.buffer_count() == 1 {
+                DEFAULT_BUFFER_COUNT
+            } else {
+                1
+            };
+            for buffer in [&mut self.full_buffer, &mut self.packed_buffer] {
+                buffer.delete(gl);
+                *buffer =
+                    InstanceBuffer::new(ctx.features(), buffer.layout().clone(), buffer.usage())
+                        .with_label(buffer.label())
+                        .with_buffer_count(buffer_count);
+            }
+            eprintln!(
+                "Cycling through {} instance buffers",
+                self.full_buffer.buffer_count()
+            );
+        }
+
+        // Swing the light around so the normals are easy to see
+        let time = ctx.timing.time();
+        let (x, y, z) = (time.cos() * 0.6, time.sin() * 0.6, 0.8);
+
+        // Bob the tiles up and down in a wave, and write them into this frame's instance buffer
+        let buffer = if self.use_packed {
+            &mut self.packed_buffer
+        } else {
+            &mut self.full_buffer
+        };
+        let stride = buffer.layout().stride();
+        let data = ctx.arena.alloc_slice::<u8>(self.instances.len() * stride);
+        for (instance, out) in self.instances.iter().zip(data.chunks_mut(stride)) {
+            let [offset_x, offset_y] = instance.offset;
+            let wave = (time * 2. + offset_x * 6. + offset_y * 4.).sin() * WAVE_HEIGHT;
+            let offset = [offset_x, offset_y + wave];
+            if self.use_packed {
+                write_packed(out, offset, instance);
+            } else {
+                write_full(out, offset, instance);
+            }
+        }
+        buffer.write(gl, data);
+
+        unsafe {
+            // Collect the GPU time of the last draw, and only start timing again once it is in
+            if let Some(query) = self.timer_query {
+                if self.timer_pending
+                    && gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) != 0
+                {
+                    let nanos = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+                    self.gpu_time = Some(Duration::from_nanos(nanos as u64));
+                    self.timer_pending = false;
+                }
+                if !self.timer_pending {
+                    gl.begin_query(glow::TIME_ELAPSED, query);
+                }
+            }
+
+            gl.use_program(Some(self.shader_program));
+            gl.uniform_3_f32(Some(&self.light_direction_uniform), x, y, z);
+            gl.bind_vertex_array(Some(self.vao));
+            buffer.bind(gl);
+            gl.draw_arrays_instanced(glow::TRIANGLES, 0, 6, buffer.instance_count() as i32);
+            gl.bind_vertex_array(None);
+
+            if self.timer_query.is_some() && !self.timer_pending {
+                gl.end_query(glow::TIME_ELAPSED);
+                self.timer_pending = true;
+            }
+        }
+
+        if ctx.timing.frame_count().is_multiple_of(REPORT_INTERVAL) {
+            let stats = buffer.stats();
+            eprintln!(
+                "{} buffer(s): frame {:.2} ms, GPU draw {}, {} waits for the GPU ( {:.2} ms total )",
+                buffer.buffer_count(),
+                ctx.timing.average_delta() as f64 * 1000.,
+                match self.gpu_time {
+                    Some(time) => format!("{:.2} ms", time.as_secs_f64() * 1000.),
+                    None => "unknown".into(),
+                },
+                stats.waits,
+                stats.wait_time.as_secs_f64() * 1000.
+            );
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.full_buffer.delete(gl);
+        self.packed_buffer.delete(gl);
+        if let Some(query) = self.timer_query {
+            unsafe { gl.delete_query(query) };
+        }
+    }
+}
+
+/// Write an instance as full floats
+fn write_full(out: &mut [u8], offset: [f32; 2], instance: &Instance) {
+    let floats = offset.iter().chain(&instance.color).chain(&instance.normal);
+    for (x, out) in floats.zip(out.chunks_mut(4)) {
+        out.copy_from_slice(&x.to_ne_bytes());
+    }
+}
+
+/// Write an instance packed into half floats, normalized bytes, and 10-10-10-2 normals
+fn write_packed(out: &mut [u8], offset: [f32; 2], instance: &Instance) {
+    out[0..2].copy_from_slice(&f32_to_f16(offset[0]).to_ne_bytes());
+    out[2..4].copy_from_slice(&f32_to_f16(offset[1]).to_ne_bytes());
+    out[4..8].copy_from_slice(&pack_unorm8x4(instance.color));
+    out[8..12].copy_from_slice(&pack_snorm_10_10_10_2(instance.normal, 0.).to_ne_bytes());
+}
+
+fn main() {
+    DemoArgs::parse().run::<Instancing>();
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use cgmath::{perspective, Deg, InnerSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    heightmap::Heightmap,
+    mesh::{Mesh, MeshData, NormalMode},
+    shader,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("terrain/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("terrain/fragment.glsl");
+
+/// The width and depth of the terrain in world units
+const TERRAIN_SIZE: f32 = 10.;
+/// The height of the highest point of the terrain in world units
+const TERRAIN_HEIGHT: f32 = 2.;
+
+struct Terrain {
+    shader_program: u32,
+    view_projection_uniform: u32,
+    light_direction_uniform: u32,
+    max_height_uniform: u32,
+    /// The triangulated heightmap, before normals are computed
+    grid: MeshData,
+    mesh: Mesh,
+    /// How the normals of the mesh were computed ( toggled with the N key )
+    normal_mode: NormalMode,
+}
+
+/// Turn the heightmap into a grid of triangles, one vertex per pixel
+fn heightmap_grid(heightmap: &Heightmap) -> MeshData {
+    let (width, height) = (heightmap.width, heightmap.height);
+    let mut data = MeshData::default();
+
+    for y in 0..height {
+        for x in 0..width {
+            let u = x as f32 / (width - 1) as f32;
+            let v = y as f32 / (height - 1) as f32;
+            data.positions.push([
+                (u - 0.5) * TERRAIN_SIZE,
+                heightmap.get(x, y) * TERRAIN_HEIGHT,
+                (v - 0.5) * TERRAIN_SIZE,
+            ]);
+            data.uvs.push([u, v]);
+        }
+    }
+
+    // Two counter-clockwise triangles, seen from above, for each square between the vertices
+    for y in 0..height - 1 {
+        for x in 0..width - 1 {
+            let top_left = y * width + x;
+            let bottom_left = top_left + width;
+            data.indices.extend_from_slice(&[
+                top_left,
+                bottom_left,
+                top_left + 1,
+                top_left + 1,
+                bottom_left,
+                bottom_left + 1,
+            ]);
+        }
+    }
+
+    data
+}
+
+impl RenderHandler for Terrain {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.6, 0.8, 1., 1.].into());
+
+        // Build the terrain mesh. The heightmap doesn't come with normals, so we compute them.
+        let heightmap = Heightmap::open(ctx.config().asset_path("heightmap.png"));
+        let grid = heightmap_grid(&heightmap);
+        let normal_mode = NormalMode::Smooth;
+        let mut data = grid.clone();
+        data.compute_normals(normal_mode);
+        let mesh = Mesh::new(gl, &data);
+        eprintln!("Press N to switch between smooth and flat normals");
+
+        unsafe {
+            gl.enable(glow::DEPTH_TEST);
+
+            // Create and compile the shaders
+            let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+            gl.compile_shader(vertex_shader);
+            handle_shader_compile_errors(gl, vertex_shader);
+
+            let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(fragment_shader);
+            handle_shader_compile_errors(gl, fragment_shader);
+
+            // Link the shader program
+            let shader_program = gl.create_program().unwrap();
+            gl.attach_shader(shader_program, vertex_shader);
+            gl.attach_shader(shader_program, fragment_shader);
+            gl.link_program(shader_program);
+            handle_program_link_errors(gl, shader_program);
+
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+
+            let view_projection_uniform = gl
+                .get_uniform_location(shader_program, "viewProjection")
+                .unwrap();
+            let light_direction_uniform = gl
+                .get_uniform_location(shader_program, "lightDirection")
+                .unwrap();
+            let max_height_uniform = gl
+                .get_uniform_location(shader_program, "maxHeight")
+                .unwrap();
+
+            Self {
+                shader_program,
+                view_projection_uniform,
+                light_direction_uniform,
+                max_height_uniform,
+                grid,
+                mesh,
+                normal_mode,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        // Switch between smooth and flat normals with the N key
+        if ctx.input.was_key_pressed(VirtualKeyCode::N) {
+            self.normal_mode = match self.normal_mode {
+                NormalMode::Smooth => NormalMode::Flat,
+                NormalMode::Flat => NormalMode::Smooth,
+            };
+            eprintln!("Using {:?} normals", self.normal_mode);
+
+            let mut data = self.grid.clone();
+            data.compute_normals(self.normal_mode);
+            self.mesh.delete(gl);
+            self.mesh = Mesh::new(gl, &data);
+        }
+
+        // Slowly circle around the terrain
+        let angle = ctx.timing.time() * 0.2;
+        let eye = Point3::new(angle.cos() * 9., 6., angle.sin() * 9.);
+        let view = Matrix4::look_at(eye, Point3::new(0., 0., 0.), Vector3::unit_y());
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let projection = perspective(Deg(45.), aspect_ratio, 0.1, 100.);
+        let view_projection: Matrix4<f32> = projection * view;
+        let view_projection: &[f32; 16] = view_projection.as_ref();
+
+        // Light coming from low in the sky, so the hills cast strong shading
+        let light_direction = Vector3::new(0.6, 0.5, 0.3).normalize();
+
+        unsafe {
+            gl.use_program(Some(self.shader_program));
+            gl.uniform_matrix_4_f32_slice(
+                Some(&self.view_projection_uniform),
+                false,
+                view_projection,
+            );
+            gl.uniform_3_f32(
+                Some(&self.light_direction_uniform),
+                light_direction.x,
+                light_direction.y,
+                light_direction.z,
+            );
+            gl.uniform_1_f32(Some(&self.max_height_uniform), TERRAIN_HEIGHT);
+        }
+        self.mesh.draw(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<Terrain>();
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,505 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    camera_path::CameraPath,
+    frustum::Frustum,
+    mesh::Mesh,
+    planar_reflection::{BelowPlane, PlanarReflection, PlanarReflectionParams},
+    primitives,
+    procedural::{self, NoiseParams},
+    render_graph::{MaterialKind, PassTarget, RenderGraph, RenderPass},
+    shader::ShaderProgram,
+    terrain::{Terrain, TerrainParams},
+    texture::{create_texture_2d, Texture, TextureParams, TexturePurpose},
+    texture_audit,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("terrain/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("terrain/fragment.glsl");
+const DEPTH_FRAGMENT_SHADER_SRC: &str = include_str!("terrain/depth_fragment.glsl");
+const WATER_VERTEX_SHADER_SRC: &str = include_str!("terrain/water_vertex.glsl");
+const WATER_FRAGMENT_SHADER_SRC: &str = include_str!("terrain/water_fragment.glsl");
+
+/// The height of a white heightmap pixel in world units
+const TERRAIN_HEIGHT: f32 = 4.;
+/// The height of the water surface in world units
+const WATER_HEIGHT: f32 = 1.;
+
+/// Where the camera path is saved between runs
+const CAMERA_PATH_FILE: &str = "./camera_path.ron";
+/// How many seconds after the previous keyframe new keyframes are placed
+const KEYFRAME_SPACING: f32 = 2.;
+
+/// The terrain covers every pixel of its triangles
+const TERRAIN_MATERIAL: MaterialKind = MaterialKind::Opaque;
+/// The water lets the terrain under it show through a little
+const WATER_MATERIAL: MaterialKind = MaterialKind::Transparent;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pass {
+    Terrain,
+    Water,
+}
+
+struct TerrainFly {
+    shader_program: u32,
+    view_projection_uniform: u32,
+    /// The program that only writes depth, for the depth pre-pass
+    depth_program: u32,
+    depth_view_projection_uniform: u32,
+    graph: RenderGraph<Pass>,
+    light_direction_uniform: u32,
+    max_height_uniform: u32,
+    terrain: Terrain,
+    water_program: ShaderProgram,
+    /// A plane covering the terrain at the water height
+    water_mesh: Mesh,
+    /// The ripples on the water
+    water_normals: Texture,
+    /// The terrain mirrored in the water
+    reflection: PlanarReflection,
+    /// Whether or not to draw the reflection ( toggled with R )
+    show_reflection: bool,
+    camera: FlyCamera,
+    camera_path: CameraPath,
+    /// How far along the camera path we are, while it is playing
+    camera_path_time: Option<f32>,
+}
+
+impl TerrainFly {
+    /// Edit and play the camera path, or fly the camera by hand when the path isn't playing
+    fn update_camera_path(&mut self, ctx: &mut AppContext) {
+        let input = &ctx.input;
+        let mut path_changed = false;
+        if input.was_key_pressed(VirtualKeyCode::K) {
+            self.camera_path
+                .add_camera_keyframe(&self.camera, KEYFRAME_SPACING);
+            path_changed = true;
+        }
+        if input.was_key_pressed(VirtualKeyCode::Back) {
+            self.camera_path.clear();
+            self.camera_path_time = None;
+            path_changed = true;
+        }
+        if path_changed {
+            eprintln!(
+                "Camera path has {} keyframes",
+                self.camera_path.keyframes().len()
+            );
+            if let Err(error) = self.camera_path.save(CAMERA_PATH_FILE) {
+                eprintln!("Could not save the camera path: {}", error);
+            }
+        }
+        if input.was_key_pressed(VirtualKeyCode::Return) {
+            self.camera_path_time = match self.camera_path_time {
+                Some(_) => None,
+                None => Some(0.),
+            };
+        }
+
+        match &mut self.camera_path_time {
+            // Advance with the frame time so that the path plays smoothly with a fixed time step
+            Some(time) => {
+                *time += ctx.timing.delta();
+                self.camera_path.apply(*time, &mut self.camera);
+                if *time >= self.camera_path.duration() {
+                    self.camera_path_time = None;
+                }
+            }
+            None => self.camera.update(ctx),
+        }
+    }
+}
+
+impl RenderHandler for TerrainFly {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.6, 0.8, 1., 1.].into());
+
+        // Build the terrain in small chunks so that there is something to cull
+        let terrain = Terrain::from_heightmap_with_params(
+            gl,
+            ctx.config().asset_path("heightmap.png"),
+            &TerrainParams {
+                scale: 0.1,
+                height_scale: TERRAIN_HEIGHT,
+                chunk_size: 32,
+                decimation: 1,
+            },
+        );
+        let camera = FlyCamera::new(Point3::new(0., 6., 16.), 0., -20.);
+        let camera_path = CameraPath::load(CAMERA_PATH_FILE).unwrap_or_default();
+        eprintln!(
+            "Terrain has {} chunks. Fly with WASD, Q, and E, and look around by holding the \
+             right mouse button. Press F to toggle wireframe, C to show the number of chunks \
+             drawn, P to toggle the depth pre-pass, and T to show the GPU time of the terrain.",
+            terrain.chunks.len()
+        );
+        eprintln!(
+            "The camera path has {} keyframes. Press K to add a keyframe at the camera, Enter to \
+             play or stop the path, and Backspace to clear it.",
+            camera_path.keyframes().len()
+        );
+        eprintln!(
+            "Press R to toggle the reflection in the water, and U to switch between flipping and \
+             skipping the reflection while the camera is under water."
+        );
+        eprintln!(
+            "Press L to switch between the throughput and low latency modes, and F2 to compare \
+             how long input takes to reach the screen in each."
+        );
+
+        // Cover the whole terrain with water, with the ripples repeating every few units
+        let (min, max) =
+            terrain
+                .chunks
+                .iter()
+                .fold(([f32::MAX; 3], [f32::MIN; 3]), |(min, max), chunk| {
+                    (
+                        [
+                            min[0].min(chunk.bounds.min[0]),
+                            0.,
+                            min[2].min(chunk.bounds.min[2]),
+                        ],
+                        [
+                            max[0].max(chunk.bounds.max[0]),
+                            0.,
+                            max[2].max(chunk.bounds.max[2]),
+                        ],
+                    )
+                });
+        let water_size = (max[0] - min[0]).max(max[2] - min[2]);
+        let mut water_data = primitives::plane(water_size, water_size, water_size / 8.);
+        for position in &mut water_data.positions {
+            position[0] += (min[0] + max[0]) / 2.;
+            position[2] += (min[2] + max[2]) / 2.;
+        }
+        let water_mesh = Mesh::new(gl, &water_data);
+        let water_normals = create_texture_2d(
+            gl,
+            ctx.features(),
+            &[procedural::noise_normal_map(
+                256,
+                256,
+                &NoiseParams {
+                    frequency: 4,
+                    tileable: true,
+                    ..Default::default()
+                },
+                0.02,
+            )],
+            &TextureParams {
+                purpose: Some(TexturePurpose::NormalMap),
+                label: Some("Water normals".into()),
+                ..Default::default()
+            },
+        );
+        let water_program =
+            ShaderProgram::new(gl, WATER_VERTEX_SHADER_SRC, WATER_FRAGMENT_SHADER_SRC)
+                .unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                });
+
+        unsafe {
+            gl.enable(glow::DEPTH_TEST);
+
+            // Create and compile the shaders
+            let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+            gl.compile_shader(vertex_shader);
+            handle_shader_compile_errors(gl, vertex_shader);
+
+            let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(fragment_shader);
+            handle_shader_compile_errors(gl, fragment_shader);
+
+            // Link the shader program
+            let shader_program = gl.create_program().unwrap();
+            gl.attach_shader(shader_program, vertex_shader);
+            gl.attach_shader(shader_program, fragment_shader);
+            gl.link_program(shader_program);
+            handle_program_link_errors(gl, shader_program);
+
+            // Link the depth-only program with the same vertex shader
+            let depth_fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                depth_fragment_shader,
+                &shader::rewrite(DEPTH_FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(depth_fragment_shader);
+            handle_shader_compile_errors(gl, depth_fragment_shader);
+
+            let depth_program = gl.create_program().unwrap();
+            gl.attach_shader(depth_program, vertex_shader);
+            gl.attach_shader(depth_program, depth_fragment_shader);
+            gl.link_program(depth_program);
+            handle_program_link_errors(gl, depth_program);
+
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+            gl.delete_shader(depth_fragment_shader);
+
+            let view_projection_uniform = gl
+                .get_uniform_location(shader_program, "viewProjection")
+                .unwrap();
+            let light_direction_uniform = gl
+                .get_uniform_location(shader_program, "lightDirection")
+                .unwrap();
+            let max_height_uniform = gl
+                .get_uniform_location(shader_program, "maxHeight")
+                .unwrap();
+            let depth_view_projection_uniform = gl
+                .get_uniform_location(depth_program, "viewProjection")
+                .unwrap();
+
+            // Draw the terrain with a depth pre-pass so that hidden hillsides aren't shaded, then
+            // the water over it
+            let graph = RenderGraph::new(vec![
+                RenderPass::new(Pass::Terrain, PassTarget::Surface).depth_prepass(),
+                RenderPass::new(Pass::Water, PassTarget::Surface),
+            ])
+            .unwrap();
+
+            Self {
+                shader_program,
+                view_projection_uniform,
+                depth_program,
+                depth_view_projection_uniform,
+                graph,
+                light_direction_uniform,
+                max_height_uniform,
+                terrain,
+                water_program,
+                water_mesh,
+                water_normals,
+                reflection: PlanarReflection::new(PlanarReflectionParams::default()),
+                show_reflection: true,
+                camera,
+                camera_path,
+                camera_path_time: None,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.update_camera_path(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::F) {
+            self.terrain.wireframe = !self.terrain.wireframe;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::R) {
+            self.show_reflection = !self.show_reflection;
+            eprintln!(
+                "Reflection {}",
+                if self.show_reflection {
+                    "enabled"
+                } else {
+                    "disabled"
+                }
+            );
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::U) {
+            let params = &mut self.reflection.params;
+            params.below_plane = match params.below_plane {
+                BelowPlane::Flip => BelowPlane::Skip,
+                BelowPlane::Skip => BelowPlane::Flip,
+            };
+            eprintln!("Under water the reflection is {:?}", params.below_plane);
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::L) {
+            let mode = &mut ctx.render_settings.latency_mode;
+            *mode = mode.next();
+            eprintln!("Latency mode: {}", mode);
+        }
+
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let view = self.camera.view_matrix();
+        let projection = self.camera.projection_matrix(aspect_ratio);
+        let view_projection: Matrix4<f32> = projection * view;
+        let frustum = Frustum::from_view_projection(&view_projection);
+        let water_view_projection = view_projection;
+        let view_projection: &[f32; 16] = view_projection.as_ref();
+
+        // The sun
+        let light_direction = Vector3::new(0.6, 0.5, 0.3).normalize();
+
+        // Draw the terrain mirrored in the water first, culled to what the mirrored camera sees
+        let reflected = self.show_reflection && {
+            let Self {
+                shader_program,
+                view_projection_uniform,
+                light_direction_uniform,
+                max_height_uniform,
+                terrain,
+                ..
+            } = self;
+            self.reflection.render(
+                gl,
+                WATER_HEIGHT,
+                view,
+                projection,
+                ctx.render_size(),
+                |gl, mirrored_view_projection| unsafe {
+                    gl.use_program(Some(*shader_program));
+                    gl.uniform_matrix_4_f32_slice(
+                        Some(view_projection_uniform),
+                        false,
+                        AsRef::<[f32; 16]>::as_ref(&mirrored_view_projection),
+                    );
+                    gl.uniform_3_f32(
+                        Some(light_direction_uniform),
+                        light_direction.x,
+                        light_direction.y,
+                        light_direction.z,
+                    );
+                    gl.uniform_1_f32(Some(max_height_uniform), TERRAIN_HEIGHT);
+                    terrain.draw(
+                        gl,
+                        Some(&Frustum::from_view_projection(&mirrored_view_projection)),
+                    );
+                },
+            )
+        };
+
+        if ctx.input.was_key_pressed(VirtualKeyCode::P) {
+            let enabled = !self.graph.depth_prepass_enabled();
+            self.graph.set_depth_prepass_enabled(enabled);
+            eprintln!(
+                "Depth pre-pass {}",
+                if enabled { "enabled" } else { "disabled" }
+            );
+        }
+
+        let Self {
+            shader_program,
+            view_projection_uniform,
+            depth_program,
+            depth_view_projection_uniform,
+            light_direction_uniform,
+            max_height_uniform,
+            terrain,
+            graph,
+            water_program,
+            water_mesh,
+            water_normals,
+            reflection,
+            camera,
+            ..
+        } = self;
+        let time = ctx.timing.time();
+        let mut drawn = 0;
+        graph.execute(gl, ctx, |gl, pass, phase| match pass {
+            Pass::Terrain => unsafe {
+                // Opaque terrain only needs its depth in the pre-pass, so skip the lighting
+                if phase.use_depth_only_shader(TERRAIN_MATERIAL) {
+                    gl.use_program(Some(*depth_program));
+                    gl.uniform_matrix_4_f32_slice(
+                        Some(depth_view_projection_uniform),
+                        false,
+                        view_projection,
+                    );
+                } else {
+                    gl.use_program(Some(*shader_program));
+                    gl.uniform_matrix_4_f32_slice(
+                        Some(view_projection_uniform),
+                        false,
+                        view_projection,
+                    );
+                    gl.uniform_3_f32(
+                        Some(light_direction_uniform),
+                        light_direction.x,
+                        light_direction.y,
+                        light_direction.z,
+                    );
+                    gl.uniform_1_f32(Some(max_height_uniform), TERRAIN_HEIGHT);
+                }
+
+                // Only draw the chunks that the camera can see
+                drawn = terrain.draw(gl, Some(&frustum));
+            },
+            Pass::Water => unsafe {
+                water_program.bind(gl);
+                water_program.set_uniform(gl, "viewProjection", water_view_projection);
+                water_program.set_uniform(gl, "waterHeight", WATER_HEIGHT);
+                water_program.set_uniform(gl, "reflection", 0);
+                water_program.set_uniform(gl, "normalMap", 1);
+                water_program.set_uniform(
+                    gl,
+                    "reflectionStrength",
+                    if reflected { 1. } else { 0. },
+                );
+                let eye = camera.position;
+                water_program.set_uniform(gl, "cameraPosition", Vector3::new(eye.x, eye.y, eye.z));
+                water_program.set_uniform(gl, "lightDirection", light_direction);
+                water_program.set_uniform(gl, "time", time);
+                gl.active_texture(glow::TEXTURE0);
+                gl.bind_texture(glow::TEXTURE_2D, reflection.texture());
+                gl.active_texture(glow::TEXTURE1);
+                gl.bind_texture(glow::TEXTURE_2D, Some(water_normals.texture));
+                texture_audit::check_sampled(water_normals, "Water", true);
+                gl.active_texture(glow::TEXTURE0);
+
+                phase.apply_depth_state(gl, WATER_MATERIAL);
+                gl.enable(glow::BLEND);
+                gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+                water_mesh.draw(gl);
+                gl.disable(glow::BLEND);
+            },
+        });
+
+        if ctx.input.was_key_pressed(VirtualKeyCode::C) {
+            eprintln!("Drew {} of {} chunks", drawn, self.terrain.chunks.len());
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::T) {
+            for (pass, time) in self.graph.pass_times() {
+                match time {
+                    Some(time) => eprintln!("{:?}: {:.3} ms", pass, time.as_secs_f64() * 1000.),
+                    None => eprintln!("{:?}: not measured", pass),
+                }
+            }
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.terrain.delete(gl);
+        self.graph.delete(gl);
+        self.reflection.delete(gl);
+        self.water_mesh.delete(gl);
+        self.water_normals.delete(gl);
+        self.water_program.delete(gl);
+        unsafe {
+            gl.delete_program(self.shader_program);
+            gl.delete_program(self.depth_program);
+        }
+    }
+}
+
+fn main() {
+    // Pass `--record <file>` to record a flight and `--replay <file>` to play it back
+    DemoArgs::parse().run::<TerrainFly>();
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use std::time::Instant;
+
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    batch::{StaticBatcher, StaticNode},
+    camera::FlyCamera,
+    mesh::{compute_normals, Mesh, NormalMode},
+    program_cache::ProgramBinaryCache,
+    shader::{self, ShaderProgram},
+    shader_variants::{ShaderVariants, VariantKey},
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use rand::Rng;
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("static_batching/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("static_batching/fragment.glsl");
+
+/// The number of cubes along each side of the grid
+const GRID_WIDTH: usize = 25;
+const GRID_DEPTH: usize = 20;
+
+/// The name of the shader sources that the materials' variants are compiled from
+const SHADER_SOURCE: &str = "static_batching";
+
+/// The color of a material and the shader features it is drawn with
+struct Material {
+    color: [f32; 3],
+    features: &'static [&'static str],
+}
+
+const MATERIALS: [Material; 4] = [
+    Material {
+        color: [0.9, 0.3, 0.3],
+        features: &["LIGHTING"],
+    },
+    Material {
+        color: [0.3, 0.8, 0.3],
+        features: &["LIGHTING", "TOON"],
+    },
+    Material {
+        color: [0.3, 0.4, 0.9],
+        features: &["FOG", "LIGHTING"],
+    },
+    Material {
+        color: [0.9, 0.8, 0.3],
+        features: &[],
+    },
+];
+
+#[rustfmt::skip]
+const CUBE_POSITIONS: [[f32; 3]; 8] = [
+    [-0.5, -0.5, -0.5], [0.5, -0.5, -0.5], [0.5, 0.5, -0.5], [-0.5, 0.5, -0.5],
+    [-0.5, -0.5,  0.5], [0.5, -0.5,  0.5], [0.5, 0.5,  0.5], [-0.5, 0.5,  0.5],
+];
+
+#[rustfmt::skip]
+const CUBE_INDICES: [u32; 36] = [
+    4, 5, 6, 4, 6, 7, // front
+    1, 0, 3, 1, 3, 2, // back
+    0, 4, 7, 0, 7, 3, // left
+    5, 1, 2, 5, 2, 6, // right
+    7, 6, 2, 7, 2, 3, // top
+    0, 1, 5, 0, 5, 4, // bottom
+];
+
+struct StaticBatching {
+    variants: ShaderVariants,
+    /// The shader variant of each material
+    material_shaders: Vec<VariantKey>,
+    /// Whether or not the programs skip uploads of unchanged uniforms ( toggled with V )
+    uniform_caching: bool,
+    /// The cube mesh used to draw nodes one by one
+    cube: Mesh,
+    /// Every node in the scene, which is what gets drawn when batching is off
+    nodes: Vec<StaticNode>,
+    batcher: StaticBatcher,
+    /// The nodes that have been taken out of their batches, which spin around
+    unbatched: Vec<StaticNode>,
+    /// Whether or not to draw the batches ( toggled with B )
+    use_batches: bool,
+    camera: FlyCamera,
+}
+
+/// The shader state of a frame, which switches programs as the materials change
+struct Shading<'a> {
+    variants: &'a mut ShaderVariants,
+    material_shaders: &'a [VariantKey],
+    view_projection: Matrix4<f32>,
+    light_direction: Vector3<f32>,
+    /// The material whose program is bound
+    bound: Option<usize>,
+}
+
+impl Shading<'_> {
+    /// Bind the program of a material and set its uniforms
+    fn use_material(
+        &mut self,
+        gl: &mut glow::Context,
+        material: usize,
+        model: Matrix4<f32>,
+    ) -> &mut ShaderProgram {
+        let program = self
+            .variants
+            .get(gl, &self.material_shaders[material])
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        if self.bound != Some(material) {
+            program.bind(gl);
+            self.bound = Some(material);
+        }
+        program.set_uniform(gl, "viewProjection", self.view_projection);
+        program.set_uniform(gl, "lightDirection", self.light_direction);
+        program.set_uniform(gl, "model", model);
+        program.set_uniform(gl, "color", MATERIALS[material].color);
+        program
+    }
+
+    /// Draw one node with its own draw call
+    fn draw_node(
+        &mut self,
+        gl: &mut glow::Context,
+        cube: &Mesh,
+        node: &StaticNode,
+        model: Matrix4<f32>,
+    ) {
+        self.use_material(gl, node.material, model);
+        cube.draw(gl);
+    }
+}
+
+impl RenderHandler for StaticBatching {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.12, 1.].into());
+
+        // Scatter small cubes over a grid with random materials, rotations, and sizes
+        let cube_data = compute_normals(&CUBE_POSITIONS, &CUBE_INDICES, NormalMode::Flat);
+        let mut rng = rand::thread_rng();
+        let nodes = (0..GRID_WIDTH * GRID_DEPTH)
+            .map(|id| {
+                let position = Vector3::new(
+                    (id % GRID_WIDTH) as f32 - GRID_WIDTH as f32 / 2.,
+                    0.,
+                    (id / GRID_WIDTH) as f32 - GRID_DEPTH as f32 / 2.,
+                );
+                let axis = Vector3::new(rng.gen_range(-1., 1.), 1., rng.gen_range(-1., 1.));
+                StaticNode {
+                    id,
+                    material: rng.gen_range(0, MATERIALS.len()),
+                    mesh: cube_data.clone(),
+                    transform: Matrix4::from_translation(position)
+                        * Matrix4::from_axis_angle(axis.normalize(), Deg(rng.gen_range(0., 90.)))
+                        * Matrix4::from_scale(rng.gen_range(0.3, 0.6)),
+                    is_static: true,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Merge the cubes into one mesh per material
+        let mut batcher = StaticBatcher::new();
+        for node in &nodes {
+            batcher.add(node.clone());
+        }
+        batcher.build(gl).unwrap();
+        eprintln!(
+            "Batched {} cubes into {} meshes. Press B to toggle batching and U to take a random \
+             cube out of its batch. Press V to toggle uniform caching and show the shader variants.",
+            nodes.len(),
+            batcher.batches().count()
+        );
+
+        let cube = Mesh::new(gl, &cube_data);
+        let camera = FlyCamera::new(Point3::new(0., 8., 18.), 0., -25.);
+
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        // Compile the shader variant of every material up front so that there is no hitch the first
+        // time one is drawn. The programs remember their uniforms so that the colors of cubes
+        // with the same material aren't uploaded again. Linked programs are cached on disk, so
+        // later runs start faster.
+        let mut variants = ShaderVariants::new();
+        variants.set_binary_cache(ProgramBinaryCache::new(ctx));
+        variants.add_source(SHADER_SOURCE, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC);
+        let material_shaders = MATERIALS
+            .iter()
+            .map(|material| VariantKey::new(SHADER_SOURCE, material.features))
+            .collect::<Vec<_>>();
+        for key in &material_shaders {
+            variants.queue_warm_up(key.clone());
+        }
+        let warm_up_start = Instant::now();
+        variants.warm_up(gl, None).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        eprintln!(
+            "Shader warm up took {:.1} ms",
+            warm_up_start.elapsed().as_secs_f64() * 1000.
+        );
+        eprint!("{}", variants.report());
+
+        Self {
+            variants,
+            material_shaders,
+            uniform_caching: true,
+            cube,
+            nodes,
+            batcher,
+            unbatched: Vec::new(),
+            use_batches: true,
+            camera,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        let mut report = false;
+        if ctx.input.was_key_pressed(VirtualKeyCode::B) {
+            self.use_batches = !self.use_batches;
+            report = true;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::V) {
+            self.uniform_caching = !self.uniform_caching;
+            for program in self.variants.programs_mut() {
+                program.set_caching_enabled(self.uniform_caching);
+            }
+            eprint!("{}", self.variants.report());
+            report = true;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::U) {
+            // "Edit" a random cube, which takes it out of its batch so that it can move
+            let id = rand::thread_rng().gen_range(0, self.nodes.len());
+            if let Some(node) = self.batcher.unbatch(gl, id) {
+                self.unbatched.push(node);
+            }
+            report = true;
+        }
+
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let view_projection: Matrix4<f32> =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+        let light_direction = Vector3::new(0.4, 0.8, 0.5).normalize();
+
+        let mut shading = Shading {
+            variants: &mut self.variants,
+            material_shaders: &self.material_shaders,
+            view_projection,
+            light_direction,
+            bound: None,
+        };
+
+        let mut draw_calls = 0;
+        if self.use_batches {
+            // The batches are already in world space
+            draw_calls += self.batcher.draw(gl, |gl, material| {
+                shading.use_material(gl, material, Matrix4::identity());
+            });
+        } else {
+            let unbatched = &self.unbatched;
+            for node in self
+                .nodes
+                .iter()
+                .filter(|node| !unbatched.iter().any(|unbatched| unbatched.id == node.id))
+            {
+                shading.draw_node(gl, &self.cube, node, node.transform);
+                draw_calls += 1;
+            }
+        }
+
+        // The cubes that were taken out of their batches are drawn one by one, spinning
+        let spin = Matrix4::from_angle_y(Deg(ctx.timing.time() * 90.));
+        for node in &self.unbatched {
+            shading.draw_node(gl, &self.cube, node, node.transform * spin);
+            draw_calls += 1;
+        }
+
+        if report {
+            eprintln!(
+                "Batching {}: {} draw calls ( {} without batching )",
+                if self.use_batches { "on" } else { "off" },
+                draw_calls,
+                self.batcher.unbatched_draw_calls() + self.unbatched.len()
+            );
+            let uniforms = shader::frame_uniform_stats();
+            eprintln!(
+                "Uniform caching {}: {} uniforms set, {} skipped",
+                if self.uniform_caching { "on" } else { "off" },
+                uniforms.issued,
+                uniforms.skipped
+            );
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.batcher.delete(gl);
+        self.cube.delete(gl);
+        self.variants.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<StaticBatching>();
+}
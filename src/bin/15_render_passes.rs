@@ -0,0 +1,355 @@
+use glow::HasContext;
+use me_learning_opengl::{
+    diagnostics::FrameReport,
+    render_graph::{PassTarget, RenderGraph, RenderPass, TargetSize},
+    render_settings::RenderSettings,
+    render_target::RenderTarget,
+    shader::{self, FULLSCREEN_VERTEX_SRC},
+    tonemap::TONEMAP_CHUNK,
+    upsample::UPSAMPLE_CHUNK,
+    viewport::Rect,
+    AppContext, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const SCENE_FRAGMENT_SHADER_SRC: &str = include_str!("render_passes/scene.frag");
+const BRIGHT_FRAGMENT_SHADER_SRC: &str = include_str!("render_passes/bright.frag");
+const BLUR_FRAGMENT_SHADER_SRC: &str = include_str!("render_passes/blur.frag");
+const COMPOSITE_FRAGMENT_SHADER_SRC: &str = include_str!("render_passes/composite.frag");
+
+/// How bright a pixel has to be to bloom
+const BLOOM_THRESHOLD: f32 = 1.;
+
+/// The passes of the bloom chain
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pass {
+    Scene,
+    Bright,
+    BlurHorizontal,
+    BlurVertical,
+    Composite,
+}
+
+/// The resolution of the bloom chain compared to the window ( switched with Q )
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum BloomQuality {
+    Full,
+    /// Half the width and height, which blurs a quarter as many pixels and looks about the same,
+    /// since the bloom is blurry anyway
+    Half,
+}
+
+impl BloomQuality {
+    fn size(self) -> TargetSize {
+        match self {
+            BloomQuality::Full => TargetSize::Window,
+            BloomQuality::Half => TargetSize::Relative(0.5),
+        }
+    }
+}
+
+/// The render targets, which follow the window size. The bloom targets use floating point
+/// textures, so colors can go above 1.0.
+struct Targets {
+    scene: RenderTarget,
+    /// The two targets that the blur ping-pongs between
+    bloom: [RenderTarget; 2],
+}
+
+impl Targets {
+    fn new(gl: &mut glow::Context, window_size: (u32, u32), quality: BloomQuality) -> Self {
+        Self {
+            scene: RenderTarget::new(gl, "Scene", TargetSize::Window, glow::RGBA16F, window_size),
+            bloom: [
+                RenderTarget::new(gl, "Bloom", quality.size(), glow::RGBA16F, window_size),
+                RenderTarget::new(gl, "Bloom", quality.size(), glow::RGBA16F, window_size),
+            ],
+        }
+    }
+
+    /// Describe the bloom chain in terms of these targets
+    ///
+    /// The passes get their viewport sizes from the targets, so the graph only has to be made
+    /// again when the bloom quality changes, not when the window is resized.
+    fn graph(&self) -> RenderGraph<Pass> {
+        let [bloom_a, bloom_b] = &self.bloom;
+        RenderGraph::new(vec![
+            self.scene.pass(Pass::Scene),
+            bloom_a.pass(Pass::Bright).reads(self.scene.texture),
+            bloom_b.pass(Pass::BlurHorizontal).reads(bloom_a.texture),
+            bloom_a.pass(Pass::BlurVertical).reads(bloom_b.texture),
+            RenderPass::new(Pass::Composite, PassTarget::Surface)
+                .reads(self.scene.texture)
+                .reads(bloom_a.texture),
+        ])
+        .unwrap()
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        self.scene.delete(gl);
+        for target in &self.bloom {
+            target.delete(gl);
+        }
+    }
+}
+
+struct Programs {
+    scene: u32,
+    scene_time_uniform: u32,
+    scene_aspect_ratio_uniform: u32,
+    bright: u32,
+    blur: u32,
+    blur_direction_uniform: u32,
+    composite: u32,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+}
+
+impl Programs {
+    /// Draw a triangle that covers the whole target with the given program, sampling the given
+    /// textures
+    fn draw_fullscreen(&self, gl: &mut glow::Context, program: u32, textures: &[u32]) {
+        unsafe {
+            gl.use_program(Some(program));
+            for (unit, &texture) in textures.iter().enumerate() {
+                gl.active_texture(glow::TEXTURE0 + unit as u32);
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            }
+            gl.bind_vertex_array(Some(self.empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+    }
+}
+
+struct RenderPasses {
+    programs: Programs,
+    targets: Targets,
+    graph: RenderGraph<Pass>,
+    quality: BloomQuality,
+}
+
+impl RenderHandler for RenderPasses {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // The composite pass covers the whole window, so there is no need to clear it
+        ctx.render_settings = RenderSettings::no_clear();
+
+        unsafe {
+            let scene = create_program(gl, SCENE_FRAGMENT_SHADER_SRC);
+            let bright = create_program(gl, BRIGHT_FRAGMENT_SHADER_SRC);
+            let blur = create_program(gl, BLUR_FRAGMENT_SHADER_SRC);
+            let composite = create_program(
+                gl,
+                &shader::include_chunk(
+                    COMPOSITE_FRAGMENT_SHADER_SRC,
+                    &format!("{}\n{}", TONEMAP_CHUNK, UPSAMPLE_CHUNK),
+                ),
+            );
+
+            // The textures and thresholds never change, so set them once
+            gl.use_program(Some(bright));
+            gl.uniform_1_i32(gl.get_uniform_location(bright, "scene").as_ref(), 0);
+            gl.uniform_1_f32(
+                gl.get_uniform_location(bright, "threshold").as_ref(),
+                BLOOM_THRESHOLD,
+            );
+            gl.use_program(Some(blur));
+            gl.uniform_1_i32(gl.get_uniform_location(blur, "image").as_ref(), 0);
+            gl.use_program(Some(composite));
+            gl.uniform_1_i32(gl.get_uniform_location(composite, "scene").as_ref(), 0);
+            gl.uniform_1_i32(gl.get_uniform_location(composite, "bloom").as_ref(), 1);
+
+            let programs = Programs {
+                scene,
+                scene_time_uniform: gl.get_uniform_location(scene, "time").unwrap(),
+                scene_aspect_ratio_uniform: gl.get_uniform_location(scene, "aspectRatio").unwrap(),
+                bright,
+                blur,
+                blur_direction_uniform: gl.get_uniform_location(blur, "direction").unwrap(),
+                composite,
+                empty_vao: gl.create_vertex_array().unwrap(),
+            };
+
+            let quality = BloomQuality::Half;
+            let targets = Targets::new(gl, ctx.window_size(), quality);
+            let graph = targets.graph();
+            eprintln!(
+                "Passes run in the order {:?}. Press T to show how long each pass takes, and Q to \
+                 switch the bloom between full and half resolution.",
+                graph
+                    .passes()
+                    .iter()
+                    .map(|pass| pass.id)
+                    .collect::<Vec<_>>()
+            );
+
+            Self {
+                programs,
+                targets,
+                graph,
+                quality,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        // Switch the bloom resolution, which changes the sizes of the bloom passes
+        let window_size = ctx.window_size();
+        if ctx.input.was_key_pressed(VirtualKeyCode::Q) {
+            self.quality = match self.quality {
+                BloomQuality::Full => BloomQuality::Half,
+                BloomQuality::Half => BloomQuality::Full,
+            };
+            for target in &mut self.targets.bloom {
+                target.set_size(gl, self.quality.size(), window_size);
+            }
+            self.graph.delete(gl);
+            self.graph = self.targets.graph();
+            eprintln!("Bloom at {:?} resolution", self.quality);
+        }
+
+        // Keep the targets the right size for the window
+        self.targets.scene.resize(gl, window_size);
+        for target in &mut self.targets.bloom {
+            target.resize(gl, window_size);
+        }
+
+        let programs = &self.programs;
+        let targets = &self.targets;
+        let time = ctx.timing.time();
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        self.graph.execute(gl, ctx, |gl, pass, _| unsafe {
+            match pass {
+                Pass::Scene => {
+                    gl.use_program(Some(programs.scene));
+                    gl.uniform_1_f32(Some(&programs.scene_time_uniform), time);
+                    gl.uniform_1_f32(Some(&programs.scene_aspect_ratio_uniform), aspect_ratio);
+                    programs.draw_fullscreen(gl, programs.scene, &[]);
+                }
+                Pass::Bright => {
+                    programs.draw_fullscreen(gl, programs.bright, &[targets.scene.texture])
+                }
+                Pass::BlurHorizontal => {
+                    let source = &targets.bloom[0];
+                    gl.use_program(Some(programs.blur));
+                    gl.uniform_2_f32(
+                        Some(&programs.blur_direction_uniform),
+                        1. / source.pixels().0 as f32,
+                        0.,
+                    );
+                    programs.draw_fullscreen(gl, programs.blur, &[source.texture]);
+                }
+                Pass::BlurVertical => {
+                    let source = &targets.bloom[1];
+                    gl.use_program(Some(programs.blur));
+                    gl.uniform_2_f32(
+                        Some(&programs.blur_direction_uniform),
+                        0.,
+                        1. / source.pixels().1 as f32,
+                    );
+                    programs.draw_fullscreen(gl, programs.blur, &[source.texture]);
+                }
+                Pass::Composite => programs.draw_fullscreen(
+                    gl,
+                    programs.composite,
+                    &[targets.scene.texture, targets.bloom[0].texture],
+                ),
+            }
+        });
+
+        if ctx.input.was_key_pressed(VirtualKeyCode::T) {
+            let mut bloom_time = 0.;
+            for (pass, time) in self.graph.pass_times() {
+                match time {
+                    Some(time) => {
+                        let millis = time.as_secs_f64() * 1000.;
+                        if matches!(
+                            pass,
+                            Pass::Bright | Pass::BlurHorizontal | Pass::BlurVertical
+                        ) {
+                            bloom_time += millis;
+                        }
+                        eprintln!("{:?}: {:.3} ms", pass, millis)
+                    }
+                    None => eprintln!("{:?}: not measured", pass),
+                }
+            }
+            let (width, height) = self.targets.bloom[0].pixels();
+            eprintln!(
+                "Bloom at {:?} resolution ( {}x{} ): {:.3} ms",
+                self.quality, width, height, bloom_time
+            );
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.graph.delete(gl);
+        self.targets.delete(gl);
+        unsafe {
+            gl.delete_program(self.programs.scene);
+            gl.delete_program(self.programs.bright);
+            gl.delete_program(self.programs.blur);
+            gl.delete_program(self.programs.composite);
+            gl.delete_vertex_array(self.programs.empty_vao);
+        }
+    }
+
+    fn describe(&self, report: &mut FrameReport) {
+        report.render_graph("Render graph", &self.graph);
+    }
+}
+
+fn main() {
+    me_learning_opengl::with_window::<RenderPasses>();
+}
+
+/// Compile and link a program from the fullscreen vertex shader and the given fragment shader
+fn create_program(gl: &mut glow::Context, fragment_shader_src: &str) -> u32 {
+    unsafe {
+        // Create and compile the shaders
+        let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+        gl.shader_source(
+            vertex_shader,
+            &shader::rewrite(FULLSCREEN_VERTEX_SRC).unwrap(),
+        );
+        gl.compile_shader(vertex_shader);
+        handle_shader_compile_errors(gl, vertex_shader);
+
+        let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+        gl.shader_source(
+            fragment_shader,
+            &shader::rewrite(fragment_shader_src).unwrap(),
+        );
+        gl.compile_shader(fragment_shader);
+        handle_shader_compile_errors(gl, fragment_shader);
+
+        // Link the shader program
+        let shader_program = gl.create_program().unwrap();
+        gl.attach_shader(shader_program, vertex_shader);
+        gl.attach_shader(shader_program, fragment_shader);
+        gl.link_program(shader_program);
+        handle_program_link_errors(gl, shader_program);
+
+        gl.delete_shader(vertex_shader);
+        gl.delete_shader(fragment_shader);
+
+        shader_program
+    }
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use cgmath::Vector2;
+use glow::HasContext;
+use me_learning_opengl::{
+    color::Color,
+    shader,
+    tween::{Animator, Easing, Repeat, Tween, TweenId},
+    AppContext, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("tweening/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("tweening/fragment.glsl");
+
+// A square drawn as a triangle fan
+const QUAD_VERTICES: [f32; 8] = [-1., -1., 1., -1., 1., 1., -1., 1.];
+
+/// How long it takes a quad to cross the window in seconds
+const CROSSING_TIME: f32 = 2.;
+
+/// One row of the grid, showing off one easing
+struct Row {
+    easing: Easing,
+    /// Moves the quad back and forth across the window
+    position: TweenId,
+    /// Fades the quad in when the example starts
+    fade_in: TweenId,
+}
+
+struct Tweening {
+    shader_program: u32,
+    vao: u32,
+    offset_uniform: u32,
+    scale_uniform: u32,
+    color_uniform: u32,
+    positions: Animator<Vector2<f32>>,
+    colors: Animator<Color>,
+    rows: Vec<Row>,
+}
+
+impl RenderHandler for Tweening {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.12, 1.].into());
+
+        // Give each easing a row, with the quads fading in one after the other
+        let mut positions = Animator::new();
+        let mut colors = Animator::new();
+        let row_height = 2. / Easing::ALL.len() as f32;
+        let rows = Easing::ALL
+            .iter()
+            .enumerate()
+            .map(|(i, &easing)| {
+                let y = 1. - row_height * (i as f32 + 0.5);
+                let hue = 360. * i as f32 / Easing::ALL.len() as f32;
+                let color = Color::from_hsv(hue, 0.6, 0.9);
+                Row {
+                    easing,
+                    position: positions.add(
+                        Tween::new(Vector2::new(-0.9, y), Vector2::new(0.9, y), CROSSING_TIME)
+                            .easing(easing)
+                            .repeat(Repeat::Forever)
+                            .ping_pong(),
+                    ),
+                    fade_in: colors.add(
+                        Tween::new(color.with_alpha(0.), color, 0.5)
+                            .easing(Easing::QuadOut)
+                            .delay(i as f32 * 0.15),
+                    ),
+                }
+            })
+            .collect();
+        eprintln!("Press R to fade the quads in again.");
+
+        unsafe {
+            gl.enable(glow::BLEND);
+            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+
+            // Create and compile the shaders
+            let vertex_shader = gl.create_shader(glow::VERTEX_SHADER).unwrap();
+            gl.shader_source(vertex_shader, &shader::rewrite(VERTEX_SHADER_SRC).unwrap());
+            gl.compile_shader(vertex_shader);
+            handle_shader_compile_errors(gl, vertex_shader);
+
+            let fragment_shader = gl.create_shader(glow::FRAGMENT_SHADER).unwrap();
+            gl.shader_source(
+                fragment_shader,
+                &shader::rewrite(FRAGMENT_SHADER_SRC).unwrap(),
+            );
+            gl.compile_shader(fragment_shader);
+            handle_shader_compile_errors(gl, fragment_shader);
+
+            // Link the shader program
+            let shader_program = gl.create_program().unwrap();
+            gl.attach_shader(shader_program, vertex_shader);
+            gl.attach_shader(shader_program, fragment_shader);
+            gl.link_program(shader_program);
+            handle_program_link_errors(gl, shader_program);
+
+            gl.delete_shader(vertex_shader);
+            gl.delete_shader(fragment_shader);
+
+            let offset_uniform = gl.get_uniform_location(shader_program, "offset").unwrap();
+            let scale_uniform = gl.get_uniform_location(shader_program, "scale").unwrap();
+            let color_uniform = gl.get_uniform_location(shader_program, "color").unwrap();
+
+            // Upload the quad
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            let vertex_bytes = QUAD_VERTICES
+                .iter()
+                .flat_map(|x| x.to_ne_bytes().to_vec())
+                .collect::<Vec<_>>();
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &vertex_bytes, glow::STATIC_DRAW);
+            gl.vertex_attrib_pointer_f32(0, 2, glow::FLOAT, false, 0, 0);
+            gl.enable_vertex_attrib_array(0);
+
+            Self {
+                shader_program,
+                vao,
+                offset_uniform,
+                scale_uniform,
+                color_uniform,
+                positions,
+                colors,
+                rows,
+            }
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if ctx.input.was_key_pressed(VirtualKeyCode::R) {
+            for row in &self.rows {
+                self.colors.get_mut(row.fade_in).unwrap().restart();
+            }
+        }
+
+        // Move all of the tweens forward and report the ones that just finished
+        self.positions.update(&ctx.timing);
+        for finished in self.colors.update(&ctx.timing) {
+            if let Some(row) = self.rows.iter().find(|row| row.fade_in == finished) {
+                eprintln!("{:?} faded in", row.easing);
+            }
+        }
+
+        let scale = 0.8 / self.rows.len() as f32;
+        unsafe {
+            gl.use_program(Some(self.shader_program));
+            gl.bind_vertex_array(Some(self.vao));
+            gl.uniform_1_f32(Some(&self.scale_uniform), scale);
+
+            for row in &self.rows {
+                let position = self.positions.value(row.position).unwrap();
+                let [r, g, b, a] = self.colors.value(row.fade_in).unwrap().to_srgb();
+                gl.uniform_2_f32(Some(&self.offset_uniform), position.x, position.y);
+                gl.uniform_4_f32(Some(&self.color_uniform), r, g, b, a);
+                gl.draw_arrays(glow::TRIANGLE_FAN, 0, 4);
+            }
+        }
+    }
+}
+
+fn main() {
+    me_learning_opengl::with_window::<Tweening>();
+}
+
+fn handle_shader_compile_errors(gl: &mut glow::Context, shader: u32) {
+    unsafe {
+        if !gl.get_shader_compile_status(shader) {
+            eprintln!("Shader compile error: {}", gl.get_shader_info_log(shader));
+            std::process::exit(1);
+        }
+    }
+}
+
+fn handle_program_link_errors(gl: &mut glow::Context, program: u32) {
+    unsafe {
+        if !gl.get_program_link_status(program) {
+            eprintln!("Shader link error: {}", gl.get_program_info_log(program));
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use glow::HasContext;
+use me_learning_opengl::{
+    color::Color, render_settings::RedrawPolicy, tween::Lerp, viewport::Rect, AppContext,
+    RenderHandler, WindowConfig,
+};
+
+/// The preset colors, shown in the top row
+const PRESETS: &[(&str, Color)] = &[
+    ("BLACK", Color::BLACK),
+    ("WHITE", Color::WHITE),
+    ("GRAY", Color::GRAY),
+    ("RED", Color::RED),
+    ("GREEN", Color::GREEN),
+    ("BLUE", Color::BLUE),
+    ("YELLOW", Color::YELLOW),
+    ("CYAN", Color::CYAN),
+    ("MAGENTA", Color::MAGENTA),
+    ("ORANGE", Color::ORANGE),
+    ("CORNFLOWER_BLUE", Color::CORNFLOWER_BLUE),
+];
+
+/// Hex codes, shown in the second row so they can be checked against a color picker
+const HEX_CODES: &[&str] = &[
+    "#ffcc00", "#ff6f61", "#6b5b95", "#88b04b", "#f7cac9", "#92a8d1", "#955251", "#b565a7",
+    "#009b77", "#dd4124", "#45b8ac",
+];
+
+/// The number of swatches in the gradient rows
+const GRADIENT_STEPS: usize = 32;
+
+/// The gap between swatches in pixels
+const GAP: i32 = 4;
+
+/// Draws rows of color swatches:
+///
+/// 1. The preset colors
+/// 2. Colors parsed from hex codes
+/// 3. A hue sweep made with `from_hsv`
+/// 4. Red to green blended in linear space with `Lerp`
+/// 5. Red to green blended naively in sRGB space, which goes through a muddy dark band
+struct ColorSwatches {
+    rows: Vec<Vec<Color>>,
+}
+
+impl RenderHandler for ColorSwatches {
+    fn init(_gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some(Color::from_hex("#1e1e24").unwrap());
+        // The swatches never change, so only draw when the window gets an event
+        ctx.render_settings.redraw = RedrawPolicy::OnEvent;
+
+        // Print what each swatch should be so it can be compared with a color picker
+        eprintln!("Presets:");
+        for (name, color) in PRESETS {
+            eprintln!("    {:16} {}", name, color.to_hex());
+        }
+        let hex_colors = HEX_CODES
+            .iter()
+            .map(|hex| {
+                let color = Color::from_hex(hex).unwrap();
+                // Parsing and printing a hex code should give back the same code
+                assert_eq!(&color.to_hex(), hex);
+                color
+            })
+            .collect::<Vec<_>>();
+        eprintln!("Hex codes: {}", HEX_CODES.join(" "));
+
+        let progress = |i: usize| i as f32 / (GRADIENT_STEPS - 1) as f32;
+        let hues = (0..GRADIENT_STEPS)
+            .map(|i| Color::from_hsv(360. * progress(i), 0.8, 0.9))
+            .collect();
+        let linear_blend = (0..GRADIENT_STEPS)
+            .map(|i| Color::RED.lerp(&Color::GREEN, progress(i)))
+            .collect();
+        let srgb_blend = (0..GRADIENT_STEPS)
+            .map(|i| {
+                Color::from(
+                    Color::RED
+                        .to_srgb()
+                        .lerp(&Color::GREEN.to_srgb(), progress(i)),
+                )
+            })
+            .collect();
+
+        Self {
+            rows: vec![
+                PRESETS.iter().map(|&(_, color)| color).collect(),
+                hex_colors,
+                hues,
+                linear_blend,
+                srgb_blend,
+            ],
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let (width, height) = ctx.window_size();
+        let (width, height) = (width as i32, height as i32);
+        let row_height = height / self.rows.len() as i32;
+
+        unsafe {
+            // Each swatch is just a scissored clear
+            gl.enable(glow::SCISSOR_TEST);
+            for (row_index, row) in self.rows.iter().enumerate() {
+                // The first row is at the top of the window
+                let y = height - row_height * (row_index as i32 + 1);
+                let swatch_width = width / row.len() as i32;
+                for (i, color) in row.iter().enumerate() {
+                    Rect::new(
+                        swatch_width * i as i32 + GAP / 2,
+                        y + GAP / 2,
+                        swatch_width - GAP,
+                        row_height - GAP,
+                    )
+                    .set_scissor(gl);
+                    let [r, g, b, a] = color.to_srgb();
+                    gl.clear_color(r, g, b, a);
+                    gl.clear(glow::COLOR_BUFFER_BIT);
+                }
+            }
+            gl.disable(glow::SCISSOR_TEST);
+        }
+    }
+}
+
+fn main() {
+    me_learning_opengl::with_window_config::<ColorSwatches>(
+        WindowConfig::new().title("Color Swatches").size(1100, 400),
+    );
+}
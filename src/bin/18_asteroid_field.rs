@@ -0,0 +1,305 @@
+use std::{cell::Cell, path::Path, rc::Rc};
+
+use cgmath::{InnerSpace, Matrix4, MetricSpace, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    asset_manifest::AssetManifest,
+    camera::FlyCamera,
+    instance_buffer::BufferUsage,
+    lod::{LodInstanceBuffers, LodMesh},
+    mesh::Mesh,
+    primitives,
+    shader::ShaderProgram,
+    vertex::{VertexFormat, VertexLayout},
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use rand::Rng;
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("asteroid_field/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("asteroid_field/fragment.glsl");
+/// Where the shaders are in the source tree, for reloading them from the console
+const SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/bin/asteroid_field");
+/// The shader files in `SHADER_DIR`
+const SHADER_FILES: [&str; 2] = ["vertex.glsl", "fragment.glsl"];
+
+/// The number of asteroids in the field
+const ASTEROID_COUNT: usize = 20_000;
+/// How far the field reaches from the origin
+const FIELD_RADIUS: f32 = 150.;
+
+/// The segments around the most detailed asteroid, which halve for each level after it
+const SPHERE_SEGMENTS: u32 = 32;
+/// The distance up to which each level is used, for an asteroid with a radius of 1. Smaller
+/// asteroids switch sooner.
+const LOD_DISTANCES: [f32; 4] = [15., 40., 90., f32::INFINITY];
+/// The tint of each level while the levels are being shown ( with T )
+const LOD_TINTS: [[f32; 3]; 4] = [
+    [1., 0.4, 0.4],
+    [0.4, 1., 0.4],
+    [0.4, 0.6, 1.],
+    [1., 1., 0.4],
+];
+
+struct AsteroidField {
+    program: ShaderProgram,
+    lod: LodMesh,
+    instances: LodInstanceBuffers,
+    /// The offset and scale of every asteroid, followed by its color
+    instance_data: Vec<u8>,
+    positions: Vec<Point3<f32>>,
+    radii: Vec<f32>,
+    /// The distance of each asteroid from the camera divided by its radius, updated every frame
+    distances: Vec<f32>,
+    /// Whether or not to pick levels by distance ( toggled with L or `lods on|off` in the
+    /// console ), or draw everything in full detail
+    use_lods: Rc<Cell<bool>>,
+    /// Whether or not to tint each level differently ( toggled with T )
+    tint_levels: bool,
+    camera: FlyCamera,
+    /// The hashes of the shader files, so that reloading shaders that haven't changed does
+    /// nothing
+    assets: AssetManifest,
+}
+
+impl RenderHandler for AsteroidField {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.02, 0.02, 0.05, 1.].into());
+
+        // The same sphere at less and less detail
+        let lod = LodMesh::new(
+            primitives::sphere_lods(1., SPHERE_SEGMENTS, LOD_DISTANCES.len())
+                .iter()
+                .zip(&LOD_DISTANCES)
+                .map(|(data, &distance)| (Mesh::new(gl, data), distance))
+                .collect(),
+        );
+
+        // Scatter asteroids of random sizes and shades of gray through a ball
+        let mut rng = rand::thread_rng();
+        let mut positions = Vec::with_capacity(ASTEROID_COUNT);
+        let mut radii = Vec::with_capacity(ASTEROID_COUNT);
+        let mut instance_data = Vec::new();
+        while positions.len() < ASTEROID_COUNT {
+            let offset = Vector3::new(
+                rng.gen_range(-1., 1.),
+                rng.gen_range(-1., 1.),
+                rng.gen_range(-1., 1.),
+            );
+            if offset.magnitude2() > 1. {
+                continue;
+            }
+            let position = Point3::new(0., 0., 0.) + offset * FIELD_RADIUS;
+            let radius = rng.gen_range(0.2, 1.5);
+            let shade = rng.gen_range(0.4, 0.8);
+            let color = [shade, shade * 0.9, shade * 0.8];
+            for x in [position.x, position.y, position.z, radius]
+                .iter()
+                .chain(&color)
+            {
+                instance_data.extend_from_slice(&x.to_ne_bytes());
+            }
+            positions.push(position);
+            radii.push(radius);
+        }
+
+        let layout =
+            VertexLayout::new(&[(3, VertexFormat::Float32x4), (4, VertexFormat::Float32x3)])
+                .per_instance();
+        let instances =
+            LodInstanceBuffers::new(gl, ctx.features(), &lod, layout, BufferUsage::Dynamic);
+
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        let triangles = lod
+            .levels
+            .iter()
+            .map(|level| level.mesh.triangle_count().to_string())
+            .collect::<Vec<_>>();
+        eprintln!(
+            "{} asteroids with {} triangles per level. Press L to toggle LODs, T to tint the \
+             levels, and C to show the draw calls and triangles.",
+            ASTEROID_COUNT,
+            triangles.join(" / ")
+        );
+
+        // Let the console turn the LODs on and off too
+        let use_lods = Rc::new(Cell::new(true));
+        let lods = use_lods.clone();
+        ctx.console.register(
+            "lods",
+            "Pick levels by distance: lods on|off",
+            move |args, _| {
+                let on = match args {
+                    ["on"] => true,
+                    ["off"] => false,
+                    _ => return Err("Usage: lods on|off".into()),
+                };
+                lods.set(on);
+                Ok(String::new())
+            },
+        );
+
+        let mut camera = FlyCamera::new(Point3::new(0., 0., FIELD_RADIUS + 20.), 0., 0.);
+        camera.move_speed = 20.;
+
+        // Record the shaders as they are on disk, which is what was built in
+        let mut assets = AssetManifest::load();
+        for name in &SHADER_FILES {
+            if let Err(error) = assets.check(Path::new(SHADER_DIR).join(name)) {
+                eprintln!("Warning: Couldn't check the shader {}: {}", name, error);
+            }
+        }
+        eprint!("{}", assets.report());
+        if let Err(error) = assets.save() {
+            eprintln!("Warning: Couldn't save the asset manifest: {}", error);
+        }
+
+        Self {
+            program,
+            lod,
+            instances,
+            instance_data,
+            distances: vec![0.; positions.len()],
+            positions,
+            radii,
+            use_lods,
+            tint_levels: false,
+            camera,
+            assets,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.shader_reload_requested() {
+            self.reload_shaders(gl, ctx);
+        }
+        let mut report = ctx.input.was_key_pressed(VirtualKeyCode::C);
+        if ctx.input.was_key_pressed(VirtualKeyCode::L) {
+            self.use_lods.set(!self.use_lods.get());
+            report = true;
+        }
+        let use_lods = self.use_lods.get();
+        if ctx.input.was_key_pressed(VirtualKeyCode::T) {
+            self.tint_levels = !self.tint_levels;
+        }
+
+        // Pick each asteroid's level by how big it is on screen, which goes with its distance
+        // over its radius
+        for ((distance, position), radius) in self
+            .distances
+            .iter_mut()
+            .zip(&self.positions)
+            .zip(&self.radii)
+        {
+            *distance = self.camera.position.distance(*position) / radius;
+        }
+        self.instances.update(
+            gl,
+            &ctx.arena,
+            &self.lod,
+            &self.instance_data,
+            &self.distances,
+            if use_lods { None } else { Some(0) },
+        );
+
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let view_projection: Matrix4<f32> =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+
+        let program = &mut self.program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        program.set_uniform(
+            gl,
+            "lightDirection",
+            Vector3::new(0.5, 0.7, 0.4).normalize(),
+        );
+        let tint_levels = self.tint_levels;
+        let stats = self.instances.draw(gl, &self.lod, |gl, level| {
+            let tint = if tint_levels {
+                LOD_TINTS[level % LOD_TINTS.len()]
+            } else {
+                [1.; 3]
+            };
+            program.set_uniform(gl, "tint", tint);
+        });
+
+        if report {
+            let buffers = self.instances.buffer_stats();
+            eprintln!(
+                "LODs {}: {} draw calls, {} triangles ( {} in full detail ), asteroids per level {:?}, {} instance buffer waits ( {:.2} ms )",
+                if use_lods { "on" } else { "off" },
+                stats.draw_calls,
+                stats.triangles,
+                stats.full_detail_triangles,
+                stats.per_level,
+                buffers.waits,
+                buffers.wait_time.as_secs_f64() * 1000.
+            );
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.instances.delete(gl);
+        self.lod.delete(gl);
+        self.program.delete(gl);
+    }
+}
+
+impl AsteroidField {
+    /// Compile the shaders again from the source tree, keeping the old program if they don't
+    /// compile, or if their contents haven't changed
+    fn reload_shaders(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let path = |name: &str| Path::new(SHADER_DIR).join(name);
+        let mut changed = false;
+        for name in &SHADER_FILES {
+            match self.assets.check(path(name)) {
+                Ok(status) => changed |= status.needs_rebuild(),
+                Err(error) => {
+                    ctx.console.print_error(&format!("{}: {}", name, error));
+                    return;
+                }
+            }
+        }
+        if !changed {
+            ctx.console.print("The asteroid shaders haven't changed");
+            return;
+        }
+
+        let read = |name: &str| std::fs::read_to_string(path(name));
+        let program = match (read("vertex.glsl"), read("fragment.glsl")) {
+            (Ok(vertex), Ok(fragment)) => ShaderProgram::new(gl, &vertex, &fragment),
+            (Err(error), _) | (_, Err(error)) => Err(error.to_string()),
+        };
+        match program {
+            Ok(program) => {
+                self.program.delete(gl);
+                self.program = program;
+                ctx.console.print("Reloaded the asteroid shaders");
+            }
+            Err(error) => {
+                // Try again on the next reload, even if the files are the same by then
+                for name in &SHADER_FILES {
+                    self.assets.forget(path(name));
+                }
+                ctx.console.print_error(&error);
+            }
+        }
+        if let Err(error) = self.assets.save() {
+            ctx.console
+                .print_error(&format!("Couldn't save the asset manifest: {}", error));
+        }
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<AsteroidField>();
+}
@@ -0,0 +1,113 @@
+use me_learning_opengl::{
+    color::Color, debug_text::DebugText, virtual_resolution::VirtualResolution, AppContext,
+    DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+/// The resolution the scene is designed for, which is scaled up to fit the window
+const VIRTUAL_WIDTH: u32 = 320;
+const VIRTUAL_HEIGHT: u32 = 180;
+
+/// The size of the squares of the checkered floor in virtual pixels
+const TILE_SIZE: u32 = 16;
+/// How many rows of floor tiles there are
+const FLOOR_ROWS: u32 = 3;
+
+const SKY_COLOR: Color = Color::rgb(0.35, 0.55, 0.85);
+const BAR_COLOR: Color = Color::rgb(0.08, 0.08, 0.1);
+const FLOOR_COLORS: [Color; 2] = [Color::rgb(0.3, 0.6, 0.25), Color::rgb(0.25, 0.5, 0.2)];
+
+struct PixelArt {
+    text: DebugText,
+}
+
+impl RenderHandler for PixelArt {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some(SKY_COLOR);
+        ctx.render_settings.virtual_resolution =
+            Some(VirtualResolution::new(VIRTUAL_WIDTH, VIRTUAL_HEIGHT).with_bar_color(BAR_COLOR));
+        eprintln!(
+            "Drawing at {}x{}. Press I to toggle integer scaling and F11 for fullscreen.",
+            VIRTUAL_WIDTH, VIRTUAL_HEIGHT
+        );
+
+        Self {
+            text: DebugText::new(gl),
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let resolution = match &mut ctx.render_settings.virtual_resolution {
+            Some(resolution) => resolution,
+            None => return,
+        };
+        if ctx.input.was_key_pressed(VirtualKeyCode::I) {
+            resolution.integer_scaling = !resolution.integer_scaling;
+        }
+        let resolution = *resolution;
+        let scale = resolution.fit(ctx.window_size()).scale;
+        let (width, height) = (VIRTUAL_WIDTH as f32, VIRTUAL_HEIGHT as f32);
+        let text = &mut self.text;
+
+        // A checkered floor along the bottom
+        for row in 0..FLOOR_ROWS {
+            for column in 0..VIRTUAL_WIDTH / TILE_SIZE {
+                let tile = TILE_SIZE as f32;
+                text.rect(
+                    column as f32 * tile,
+                    height - (row + 1) as f32 * tile,
+                    tile,
+                    tile,
+                    FLOOR_COLORS[((row + column) % 2) as usize],
+                );
+            }
+        }
+
+        // A one pixel frame around the edge, which shows whether any pixels are cut off
+        text.rect(0., 0., width, 1., Color::WHITE);
+        text.rect(0., height - 1., width, 1., Color::WHITE);
+        text.rect(0., 0., 1., height, Color::WHITE);
+        text.rect(width - 1., 0., 1., height, Color::WHITE);
+
+        text.text(
+            4.,
+            4.,
+            1,
+            Color::WHITE,
+            &format!(
+                "{}x{} scaled {:.2}x ( {} )",
+                VIRTUAL_WIDTH,
+                VIRTUAL_HEIGHT,
+                scale,
+                if resolution.integer_scaling {
+                    "integer"
+                } else {
+                    "fractional"
+                }
+            ),
+        );
+
+        // A box under the cursor, which only exists while the cursor is over the image
+        match ctx.input.cursor_position() {
+            Some((x, y)) => {
+                let (x, y) = (x.floor() as f32, y.floor() as f32);
+                text.rect(x - 2., y - 2., 5., 5., Color::YELLOW);
+                text.rect(x, y, 1., 1., Color::BLACK);
+                text.text(4., 14., 1, Color::WHITE, &format!("Cursor {}, {}", x, y));
+            }
+            None => {
+                text.text(4., 14., 1, Color::WHITE, "Cursor outside of the image");
+            }
+        }
+
+        text.draw(gl, &ctx.arena, ctx.render_size());
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.text.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<PixelArt>();
+}
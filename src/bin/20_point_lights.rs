@@ -0,0 +1,286 @@
+use cgmath::{Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    mesh::Mesh,
+    point_shadow::{self, PointShadow, PointShadowParams, ShadowUpdate},
+    primitives,
+    shader::{self, ShaderProgram},
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("point_lights/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("point_lights/fragment.glsl");
+
+/// The shadow map resolutions that R cycles through
+const SHADOW_RESOLUTIONS: [u32; 4] = [128, 256, 512, 1024];
+
+/// How far the light that casts shadows circles from the middle of the room
+const ORBIT_RADIUS: f32 = 2.5;
+/// How far the lights reach
+const LIGHT_RADIUS: f32 = 14.;
+
+/// A point light as its position and color, where brighter colors go past 1
+struct Light {
+    position: Point3<f32>,
+    color: [f32; 3],
+}
+
+/// Something in the scene, drawn with one of the meshes
+struct Object {
+    mesh: usize,
+    model: Matrix4<f32>,
+    albedo: [f32; 3],
+}
+
+struct PointLights {
+    program: ShaderProgram,
+    /// A cube and a sphere
+    meshes: Vec<Mesh>,
+    objects: Vec<Object>,
+    /// The index of the ball that rolls around the room, which moves the shadows it casts
+    rolling_ball: usize,
+    lights: Vec<Light>,
+    /// The shadow of the first light
+    shadow: PointShadow,
+    /// Whether or not the shadow casting light circles the room ( toggled with space )
+    move_light: bool,
+    /// Whether or not the ball rolls around the room ( toggled with B )
+    move_ball: bool,
+    /// How far around its circle the light has gone, and the ball, in radians
+    light_angle: f32,
+    ball_angle: f32,
+    camera: FlyCamera,
+}
+
+impl RenderHandler for PointLights {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.01, 0.01, 0.015, 1.].into());
+
+        let fragment_source =
+            shader::include_chunk(FRAGMENT_SHADER_SRC, point_shadow::SHADOW_CHUNK);
+        let program =
+            ShaderProgram::new(gl, VERTEX_SHADER_SRC, &fragment_source).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        let meshes = vec![
+            Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            Mesh::new(gl, &primitives::uv_sphere(1., 32, 16)),
+        ];
+
+        // A floor, a ring of pillars, and some balls between them
+        let mut objects = vec![Object {
+            mesh: 0,
+            model: Matrix4::from_translation(Vector3::new(0., -0.1, 0.))
+                * Matrix4::from_nonuniform_scale(20., 0.2, 20.),
+            albedo: [0.6, 0.6, 0.6],
+        }];
+        for i in 0..8 {
+            let angle = i as f32 / 8. * std::f32::consts::PI * 2.;
+            objects.push(Object {
+                mesh: 0,
+                model: Matrix4::from_translation(Vector3::new(
+                    angle.cos() * 5.,
+                    1.5,
+                    angle.sin() * 5.,
+                )) * Matrix4::from_nonuniform_scale(0.6, 3., 0.6),
+                albedo: [0.8, 0.75, 0.7],
+            });
+        }
+        for (position, radius, albedo) in [
+            ([1., 0.5, 1.], 0.5, [0.8, 0.2, 0.2]),
+            ([-1.5, 0.7, 0.5], 0.7, [0.2, 0.6, 0.8]),
+            ([0.5, 0.35, -1.5], 0.35, [0.3, 0.8, 0.3]),
+        ] {
+            objects.push(Object {
+                mesh: 1,
+                model: Matrix4::from_translation(Vector3::from(position))
+                    * Matrix4::from_scale(radius),
+                albedo,
+            });
+        }
+        let rolling_ball = objects.len();
+        objects.push(Object {
+            mesh: 1,
+            model: Matrix4::from_scale(0.4),
+            albedo: [0.9, 0.8, 0.3],
+        });
+
+        // The first light casts shadows and the other two don't
+        let lights = vec![
+            Light {
+                position: Point3::new(ORBIT_RADIUS, 1.5, 0.),
+                color: [12., 11., 9.],
+            },
+            Light {
+                position: Point3::new(-6., 2., -6.),
+                color: [6., 1.5, 1.],
+            },
+            Light {
+                position: Point3::new(6., 2.5, 6.),
+                color: [1., 2., 6.],
+            },
+        ];
+
+        let shadow = PointShadow::new(gl, PointShadowParams::default());
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "Press space to stop the light, B to stop the rolling ball, R to change the shadow \
+             resolution, and U to switch between drawing the shadow every frame and only when \
+             something moves."
+        );
+
+        let mut camera = FlyCamera::new(Point3::new(0., 5., 11.), 0., -25.);
+        camera.move_speed = 5.;
+
+        Self {
+            program,
+            meshes,
+            objects,
+            rolling_ball,
+            lights,
+            shadow,
+            move_light: true,
+            move_ball: true,
+            light_angle: 0.,
+            ball_angle: 0.,
+            camera,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        let mut report = false;
+        if ctx.input.was_key_pressed(VirtualKeyCode::Space) {
+            self.move_light = !self.move_light;
+            report = true;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::B) {
+            self.move_ball = !self.move_ball;
+            report = true;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::R) {
+            let resolution = self.shadow.params().resolution;
+            let next = SHADOW_RESOLUTIONS
+                .iter()
+                .position(|&r| r == resolution)
+                .map_or(0, |i| (i + 1) % SHADOW_RESOLUTIONS.len());
+            self.shadow.set_resolution(gl, SHADOW_RESOLUTIONS[next]);
+            report = true;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::U) {
+            self.shadow.set_update(match self.shadow.params().update {
+                ShadowUpdate::EveryFrame => ShadowUpdate::WhenChanged,
+                ShadowUpdate::WhenChanged => ShadowUpdate::EveryFrame,
+            });
+            report = true;
+        }
+
+        // Move the light and the ball. Moving the light draws the shadow again by itself, but the
+        // shadow has to be told when a shadow caster moves.
+        let delta = ctx.timing.delta();
+        if self.move_light {
+            self.light_angle += delta * 0.7;
+            self.lights[0].position = Point3::new(
+                self.light_angle.cos() * ORBIT_RADIUS,
+                1.5 + (self.light_angle * 2.).sin() * 0.5,
+                self.light_angle.sin() * ORBIT_RADIUS,
+            );
+        }
+        if self.move_ball {
+            self.ball_angle -= delta * 1.3;
+            self.objects[self.rolling_ball].model = Matrix4::from_translation(Vector3::new(
+                self.ball_angle.cos() * 3.5,
+                0.4,
+                self.ball_angle.sin() * 3.5,
+            )) * Matrix4::from_scale(0.4);
+            self.shadow.invalidate();
+        }
+
+        // Draw the shadow of the first light
+        let (meshes, objects) = (&self.meshes, &self.objects);
+        self.shadow
+            .render(gl, self.lights[0].position, LIGHT_RADIUS, |gl, program| {
+                for object in objects {
+                    program.set_uniform(gl, "model", object.model);
+                    meshes[object.mesh].draw(gl);
+                }
+            });
+        let (width, height) = ctx.render_size();
+        unsafe { gl.viewport(0, 0, width as i32, height as i32) };
+
+        // Then the scene, lit by every light
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let view_projection: Matrix4<f32> =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+        let program = &mut self.program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        let eye = self.camera.position;
+        program.set_uniform(gl, "viewPosition", Vector3::new(eye.x, eye.y, eye.z));
+        program.set_uniform(gl, "lightCount", self.lights.len() as i32);
+        program.set_uniform(gl, "shadowLight", 0);
+        for (i, light) in self.lights.iter().enumerate() {
+            let position = light.position;
+            program.set_uniform(
+                gl,
+                &format!("lights[{}].position", i),
+                Vector3::new(position.x, position.y, position.z),
+            );
+            program.set_uniform(gl, &format!("lights[{}].color", i), light.color);
+            program.set_uniform(gl, &format!("lights[{}].radius", i), LIGHT_RADIUS);
+        }
+        self.shadow.bind(gl, program, 0);
+
+        program.set_uniform(gl, "emissive", 0);
+        for object in &self.objects {
+            program.set_uniform(gl, "model", object.model);
+            program.set_uniform(gl, "albedo", object.albedo);
+            self.meshes[object.mesh].draw(gl);
+        }
+
+        // Show where the lights are with small glowing balls
+        program.set_uniform(gl, "emissive", 1);
+        for light in &self.lights {
+            let position = light.position;
+            program.set_uniform(
+                gl,
+                "model",
+                Matrix4::from_translation(Vector3::new(position.x, position.y, position.z))
+                    * Matrix4::from_scale(0.08),
+            );
+            let brightest = light.color.iter().cloned().fold(1., f32::max);
+            program.set_uniform(gl, "albedo", light.color.map(|c| c / brightest));
+            self.meshes[1].draw(gl);
+        }
+        unsafe { gl.bind_vertex_array(None) };
+
+        if report {
+            eprintln!(
+                "Light {}, ball {}, shadow {}x{} drawn {:?}, {} times so far",
+                if self.move_light { "moving" } else { "still" },
+                if self.move_ball { "moving" } else { "still" },
+                self.shadow.params().resolution,
+                self.shadow.params().resolution,
+                self.shadow.params().update,
+                self.shadow.render_count()
+            );
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.shadow.delete(gl);
+        for mesh in &self.meshes {
+            mesh.delete(gl);
+        }
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<PointLights>();
+}
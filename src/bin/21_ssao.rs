@@ -0,0 +1,537 @@
+use std::{cell::Cell, rc::Rc};
+
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Vector4};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    gbuffer::{GBuffer, GBufferLayout, GBUFFER_CHUNK},
+    mesh::Mesh,
+    msaa_resolve::MsaaQuality,
+    outline::{OutlineParams, OutlinePass},
+    primitives,
+    render_graph::{PassTarget, RenderGraph, RenderPass},
+    render_settings::RenderSettings,
+    shader::{self, ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    ssao::{SsaoParams, SsaoPass, SsaoResolution, MAX_KERNEL_SIZE},
+    upsample::UPSAMPLE_CHUNK,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const GBUFFER_VERTEX_SHADER_SRC: &str = include_str!("ssao/gbuffer.vert");
+const GBUFFER_FRAGMENT_SHADER_SRC: &str = include_str!("ssao/gbuffer.frag");
+const LIGHTING_FRAGMENT_SHADER_SRC: &str = include_str!("ssao/lighting.frag");
+
+/// How many frames go by between GPU time reports
+const REPORT_INTERVAL: u64 = 120;
+
+/// The passes of the frame, in the order they run
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pass {
+    GBuffer,
+    Occlusion,
+    Lighting,
+    Outline,
+}
+
+/// What the render graph was made for, so that it is made again when any of it changes
+#[derive(Clone, Debug, PartialEq)]
+struct GraphKey {
+    gbuffer_framebuffer: u32,
+    resolution: SsaoResolution,
+    blur: bool,
+    quality: MsaaQuality,
+    outline: bool,
+}
+
+/// What the lighting pass shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DebugView {
+    /// The scene lit with ambient occlusion
+    Lit,
+    /// The raw ambient occlusion buffer
+    Occlusion,
+    /// The scene lit without ambient occlusion, to compare against
+    NoOcclusion,
+}
+
+impl DebugView {
+    fn next(self) -> Self {
+        match self {
+            DebugView::Lit => DebugView::Occlusion,
+            DebugView::Occlusion => DebugView::NoOcclusion,
+            DebugView::NoOcclusion => DebugView::Lit,
+        }
+    }
+}
+
+/// Compile the programs that draw into and light a G-buffer
+fn compile_programs(gl: &mut glow::Context, gbuffer: &GBuffer) -> (ShaderProgram, ShaderProgram) {
+    let mut compile = |vertex: &str, fragment: &str, defines: &[&str]| {
+        let fragment = shader::include_chunk(fragment, GBUFFER_CHUNK);
+        ShaderProgram::with_defines(gl, vertex, &fragment, defines).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        })
+    };
+    (
+        // Drawing into a multisampled G-buffer is no different
+        compile(
+            GBUFFER_VERTEX_SHADER_SRC,
+            GBUFFER_FRAGMENT_SHADER_SRC,
+            gbuffer.layout.defines(),
+        ),
+        // The occlusion may be half resolution, so the lighting samples it with `textureUpsampled`
+        compile(
+            FULLSCREEN_VERTEX_SRC,
+            &shader::include_chunk(LIGHTING_FRAGMENT_SHADER_SRC, UPSAMPLE_CHUNK),
+            &gbuffer.defines(),
+        ),
+    )
+}
+
+/// Describe the frame: the G-buffer, the occlusion and outlines worked out from it, and the
+/// lighting
+///
+/// With a multisampled G-buffer, the lighting always shades every sample, since that is what
+/// smooths the edges. The occlusion and outlines read the samples too with
+/// `MsaaQuality::PerSample`, and otherwise read the resolved G-buffer, which the graph resolves
+/// before the first of them.
+fn build_graph(
+    gbuffer: &GBuffer,
+    ssao: &SsaoPass,
+    quality: MsaaQuality,
+    outline: bool,
+) -> RenderGraph<Pass> {
+    let mut gbuffer_pass =
+        RenderPass::new(Pass::GBuffer, PassTarget::Framebuffer(gbuffer.framebuffer)).clear(
+            RenderSettings {
+                clear_color: Some([0., 0., 0., 0.].into()),
+                clear_depth: true,
+                ..RenderSettings::no_clear()
+            },
+        );
+    if gbuffer.is_multisampled() {
+        for texture in gbuffer.multisampled_textures() {
+            gbuffer_pass = gbuffer_pass.writes_multisampled(texture);
+        }
+    } else {
+        let textures = [gbuffer.normals, gbuffer.albedos, gbuffer.depth];
+        for texture in gbuffer.positions.into_iter().chain(textures) {
+            gbuffer_pass = gbuffer_pass.writes(texture);
+        }
+    }
+
+    let reads_samples = gbuffer.is_multisampled();
+    let effect_reads_samples = reads_samples && quality == MsaaQuality::PerSample;
+    let read = |pass: RenderPass<Pass>, textures: Vec<u32>, samples: bool| {
+        textures.into_iter().fold(pass, |pass, texture| {
+            if samples {
+                pass.reads_samples(texture)
+            } else {
+                pass.reads(texture)
+            }
+        })
+    };
+
+    let mut passes = vec![
+        gbuffer_pass,
+        read(
+            ssao.pass(Pass::Occlusion).unwrap(),
+            gbuffer.geometry_textures(),
+            effect_reads_samples,
+        ),
+        read(
+            RenderPass::new(Pass::Lighting, PassTarget::Surface)
+                .reads(ssao.occlusion_texture().unwrap()),
+            [gbuffer.geometry_textures(), vec![gbuffer.albedos]].concat(),
+            reads_samples,
+        ),
+    ];
+    if outline {
+        passes.push(read(
+            RenderPass::new(Pass::Outline, PassTarget::Surface),
+            gbuffer.geometry_textures(),
+            effect_reads_samples,
+        ));
+    }
+    RenderGraph::new(passes)
+        .unwrap_or_else(|error| panic!("Error scheduling the passes: {}", error))
+}
+
+/// Something in the scene, drawn with one of the meshes
+struct Object {
+    mesh: usize,
+    model: Matrix4<f32>,
+    albedo: [f32; 3],
+}
+
+struct Ssao {
+    gbuffer_program: ShaderProgram,
+    lighting_program: ShaderProgram,
+    /// A cube and a sphere
+    meshes: Vec<Mesh>,
+    objects: Vec<Object>,
+    gbuffer: GBuffer,
+    ssao: SsaoPass,
+    outline: OutlinePass,
+    graph: RenderGraph<Pass>,
+    /// What `graph` was made for
+    graph_key: GraphKey,
+    /// The SSAO settings, which the `ssao` console command changes
+    params: Rc<Cell<SsaoParams>>,
+    /// What to show ( cycled with V or set with `ssao view` )
+    debug_view: Rc<Cell<DebugView>>,
+    /// The G-buffer layout to draw with ( switched with G or set with `ssao gbuffer` ), which
+    /// starts as the `gbuffer_layout` in the config
+    layout: Rc<Cell<GBufferLayout>>,
+    /// How the occlusion and outlines read a multisampled G-buffer ( switched with M or set with
+    /// `ssao msaa` )
+    quality: Rc<Cell<MsaaQuality>>,
+    /// Whether or not to outline the scene ( toggled with O or set with `ssao outline` )
+    outline_enabled: Rc<Cell<bool>>,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+    camera: FlyCamera,
+}
+
+impl RenderHandler for Ssao {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // The lighting pass covers the whole window, so there is no need to clear it
+        ctx.render_settings = RenderSettings::no_clear();
+
+        let layout = ctx.config().gbuffer_layout;
+        // The G-buffer has as many samples as the window, so that `--msaa 4` multisamples it
+        let samples = ctx.render_settings.anti_aliasing.samples();
+        let gbuffer = GBuffer::with_samples(gl, ctx.render_size(), layout, samples);
+        let (gbuffer_program, lighting_program) = compile_programs(gl, &gbuffer);
+        let meshes = vec![
+            Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            Mesh::new(gl, &primitives::uv_sphere(1., 32, 16)),
+        ];
+
+        // Lots of corners and contact points for the occlusion to show up in: a floor with a back
+        // wall, steps of boxes, and balls resting against them
+        let mut objects = vec![
+            Object {
+                mesh: 0,
+                model: Matrix4::from_translation(Vector3::new(0., -0.1, 0.))
+                    * Matrix4::from_nonuniform_scale(12., 0.2, 12.),
+                albedo: [0.7, 0.7, 0.7],
+            },
+            Object {
+                mesh: 0,
+                model: Matrix4::from_translation(Vector3::new(0., 2., -3.))
+                    * Matrix4::from_nonuniform_scale(12., 4., 0.2),
+                albedo: [0.75, 0.7, 0.65],
+            },
+        ];
+        for step in 0..4 {
+            let height = (step + 1) as f32 * 0.4;
+            objects.push(Object {
+                mesh: 0,
+                model: Matrix4::from_translation(Vector3::new(
+                    -3. + step as f32 * 0.8,
+                    height / 2.,
+                    -2.,
+                )) * Matrix4::from_nonuniform_scale(0.8, height, 1.8),
+                albedo: [0.8, 0.6, 0.4],
+            });
+        }
+        for (position, radius, albedo) in [
+            ([1.5, 0.6, -1.8], 0.6, [0.3, 0.5, 0.8]),
+            ([2.6, 0.4, -0.6], 0.4, [0.8, 0.3, 0.3]),
+            ([0.3, 0.3, 0.], 0.3, [0.4, 0.8, 0.4]),
+            ([-1.2, 1.55, -1.6], 0.35, [0.9, 0.8, 0.3]),
+        ] {
+            objects.push(Object {
+                mesh: 1,
+                model: Matrix4::from_translation(Vector3::from(position))
+                    * Matrix4::from_scale(radius),
+                albedo,
+            });
+        }
+
+        let params = Rc::new(Cell::new(SsaoParams::default()));
+        let debug_view = Rc::new(Cell::new(DebugView::Lit));
+        let mut ssao = SsaoPass::new(gl, params.get());
+        ssao.resize(gl, gbuffer.size);
+        let outline = OutlinePass::new(gl, OutlineParams::default());
+        let layout = Rc::new(Cell::new(layout));
+        let quality = Rc::new(Cell::new(MsaaQuality::default()));
+        let outline_enabled = Rc::new(Cell::new(true));
+        let graph_key = GraphKey {
+            gbuffer_framebuffer: gbuffer.framebuffer,
+            resolution: params.get().resolution,
+            blur: params.get().blur,
+            quality: quality.get(),
+            outline: outline_enabled.get(),
+        };
+        let graph = build_graph(&gbuffer, &ssao, graph_key.quality, graph_key.outline);
+        let empty_vao = unsafe { gl.create_vertex_array().unwrap() };
+
+        // Let the console change the settings while the scene is running
+        let (command_params, command_view, command_layout, command_quality, command_outline) = (
+            params.clone(),
+            debug_view.clone(),
+            layout.clone(),
+            quality.clone(),
+            outline_enabled.clone(),
+        );
+        ctx.console.register(
+            "ssao",
+            "Change the ambient occlusion: ssao [kernel N | radius R | bias B | blur on|off | \
+             resolution full|half | view lit|ao|off | gbuffer fat|packed | \
+             msaa resolve|per-sample | outline on|off]",
+            move |args, _| {
+                let mut params = command_params.get();
+                match args {
+                    [] => {}
+                    ["kernel", size] => {
+                        let size = size.parse::<u32>().map_err(|error| error.to_string())?;
+                        params.kernel_size = size.clamp(1, MAX_KERNEL_SIZE);
+                    }
+                    ["radius", radius] => {
+                        params.radius = radius.parse().map_err(|_| "Invalid radius")?;
+                    }
+                    ["bias", bias] => {
+                        params.bias = bias.parse().map_err(|_| "Invalid bias")?;
+                    }
+                    ["blur", "on"] => params.blur = true,
+                    ["blur", "off"] => params.blur = false,
+                    ["resolution", "full"] => params.resolution = SsaoResolution::Full,
+                    ["resolution", "half"] => params.resolution = SsaoResolution::Half,
+                    ["view", "lit"] => command_view.set(DebugView::Lit),
+                    ["view", "ao"] => command_view.set(DebugView::Occlusion),
+                    ["view", "off"] => command_view.set(DebugView::NoOcclusion),
+                    ["gbuffer", name] => command_layout.set(
+                        GBufferLayout::parse(name)
+                            .ok_or("Expected a G-buffer layout of fat or packed")?,
+                    ),
+                    ["msaa", name] => command_quality.set(
+                        MsaaQuality::parse(name)
+                            .ok_or("Expected an MSAA quality of resolve or per-sample")?,
+                    ),
+                    ["outline", "on"] => command_outline.set(true),
+                    ["outline", "off"] => command_outline.set(false),
+                    _ => return Err("Unknown SSAO setting, see `help`".into()),
+                }
+                command_params.set(params);
+                Ok(format!(
+                    "{:?}, showing {:?} with the {} G-buffer, {} MSAA effects, and outlines {}",
+                    params,
+                    command_view.get(),
+                    command_layout.get().name(),
+                    command_quality.get().name(),
+                    if command_outline.get() { "on" } else { "off" }
+                ))
+            },
+        );
+        eprintln!(
+            "Press V to switch between the lit scene, the occlusion buffer, and the scene without \
+             occlusion, G to switch between the fat and packed G-buffers, and H to switch the \
+             occlusion between full and half resolution. With MSAA on ( e.g. `--msaa 4` ), M \
+             switches the occlusion and outlines between reading the resolved G-buffer and every \
+             sample. O turns the outlines on and off. Change the settings with the `ssao` console \
+             command."
+        );
+
+        let mut camera = FlyCamera::new(Point3::new(0., 2.5, 5.), 0., -20.);
+        camera.move_speed = 3.;
+
+        Self {
+            gbuffer_program,
+            lighting_program,
+            meshes,
+            objects,
+            gbuffer,
+            ssao,
+            outline,
+            graph,
+            graph_key,
+            params,
+            debug_view,
+            layout,
+            quality,
+            outline_enabled,
+            empty_vao,
+            camera,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::V) {
+            self.debug_view.set(self.debug_view.get().next());
+            eprintln!("Showing {:?}", self.debug_view.get());
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::G) {
+            self.layout.set(self.layout.get().next());
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::H) {
+            let mut params = self.params.get();
+            params.resolution = match params.resolution {
+                SsaoResolution::Full => SsaoResolution::Half,
+                SsaoResolution::Half => SsaoResolution::Full,
+            };
+            self.params.set(params);
+            eprintln!("Occlusion at {:?} resolution", params.resolution);
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::M) {
+            self.quality.set(self.quality.get().next());
+            eprintln!("MSAA effects: {}", self.quality.get().name());
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::O) {
+            self.outline_enabled.set(!self.outline_enabled.get());
+        }
+        self.ssao.params = self.params.get();
+
+        // Recreate the G-buffer when the window is resized or its layout or samples change, along
+        // with the programs that draw into and read from it. The SSAO pass follows its size and
+        // layout.
+        let size = ctx.render_size();
+        let layout = self.layout.get();
+        let samples = ctx.render_settings.anti_aliasing.samples().max(1);
+        if size != self.gbuffer.size
+            || layout != self.gbuffer.layout
+            || samples != self.gbuffer.samples
+        {
+            let defines = self.gbuffer.defines();
+            self.gbuffer.delete(gl);
+            self.gbuffer = GBuffer::with_samples(gl, size, layout, samples);
+            if self.gbuffer.defines() != defines {
+                self.gbuffer_program.delete(gl);
+                self.lighting_program.delete(gl);
+                let (gbuffer_program, lighting_program) = compile_programs(gl, &self.gbuffer);
+                self.gbuffer_program = gbuffer_program;
+                self.lighting_program = lighting_program;
+                eprintln!(
+                    "Drawing with the {} G-buffer at {} samples per pixel",
+                    layout.name(),
+                    samples
+                );
+            }
+        }
+        self.ssao.resize(gl, size);
+
+        // Make the graph again when the textures it schedules or the passes in it change
+        let key = GraphKey {
+            gbuffer_framebuffer: self.gbuffer.framebuffer,
+            resolution: self.ssao.params.resolution,
+            blur: self.ssao.params.blur,
+            quality: self.quality.get(),
+            outline: self.outline_enabled.get(),
+        };
+        if key != self.graph_key {
+            self.graph.delete(gl);
+            self.graph = build_graph(&self.gbuffer, &self.ssao, key.quality, key.outline);
+            self.graph_key = key;
+        }
+
+        let aspect_ratio = Rect::from_window_size(size).aspect_ratio();
+        let projection = self.camera.projection_matrix(aspect_ratio);
+        let view = self.camera.view_matrix();
+        let debug_view = self.debug_view.get();
+        let light_direction = (view * Vector4::new(0.4, 1., 0.6, 0.).normalize()).truncate();
+
+        // The effects read the samples themselves, or the G-buffer they were resolved into
+        let gbuffer = &self.gbuffer;
+        let effect_gbuffer = match self.quality.get() {
+            MsaaQuality::PerSample => gbuffer,
+            MsaaQuality::Resolve => gbuffer.single_sample(),
+        };
+        let (gbuffer_program, lighting_program) =
+            (&mut self.gbuffer_program, &mut self.lighting_program);
+        let (ssao, outline) = (&mut self.ssao, &mut self.outline);
+        let (meshes, objects, empty_vao) = (&self.meshes, &self.objects, self.empty_vao);
+        self.graph.execute(gl, ctx, |gl, pass, _| match pass {
+            Pass::GBuffer => {
+                unsafe { gl.enable(glow::DEPTH_TEST) };
+                let program = &mut *gbuffer_program;
+                program.bind(gl);
+                program.set_uniform(gl, "view", view);
+                program.set_uniform(gl, "projection", projection);
+                for object in objects {
+                    program.set_uniform(gl, "model", object.model);
+                    program.set_uniform(gl, "albedo", object.albedo);
+                    meshes[object.mesh].draw(gl);
+                }
+                unsafe { gl.disable(glow::DEPTH_TEST) };
+            }
+            Pass::Occlusion => ssao.render(gl, effect_gbuffer, projection),
+            Pass::Lighting => {
+                let occlusion = match debug_view {
+                    DebugView::Occlusion => ssao.raw_occlusion_texture(),
+                    _ => ssao.occlusion_texture(),
+                };
+                let program = &mut *lighting_program;
+                program.bind(gl);
+                let occlusion_unit = gbuffer.bind(gl, program, 0, projection);
+                unsafe {
+                    gl.active_texture(glow::TEXTURE0 + occlusion_unit);
+                    gl.bind_texture(glow::TEXTURE_2D, occlusion);
+                }
+                program.set_uniform(gl, "occlusion", occlusion_unit as i32);
+                program.set_uniform(gl, "lightDirection", light_direction);
+                program.set_uniform(
+                    gl,
+                    "debugView",
+                    match debug_view {
+                        DebugView::Lit => 0,
+                        DebugView::Occlusion => 1,
+                        DebugView::NoOcclusion => 2,
+                    },
+                );
+                unsafe {
+                    gl.bind_vertex_array(Some(empty_vao));
+                    gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                    gl.bind_vertex_array(None);
+                }
+            }
+            Pass::Outline => {
+                // Only over the lit scene, since lines on the occlusion would hide it
+                if debug_view != DebugView::Occlusion {
+                    outline.draw(gl, effect_gbuffer, projection);
+                }
+            }
+        });
+
+        if ctx.timing.frame_count().is_multiple_of(REPORT_INTERVAL) {
+            let times = self
+                .graph
+                .pass_times()
+                .map(|(pass, time)| match time {
+                    Some(time) => format!("{:?} {:.2} ms", pass, time.as_secs_f64() * 1000.),
+                    None => format!("{:?} unknown", pass),
+                })
+                .collect::<Vec<_>>();
+            eprintln!(
+                "{} G-buffer ({:.1} MiB, {} samples, {} resolves), {:?} resolution occlusion: {}",
+                layout.name(),
+                self.gbuffer.bytes() as f64 / (1024. * 1024.),
+                self.gbuffer.samples,
+                self.graph.resolves().count(),
+                self.ssao.params.resolution,
+                times.join(", ")
+            );
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.ssao.delete(gl);
+        self.outline.delete(gl);
+        self.graph.delete(gl);
+        self.gbuffer.delete(gl);
+        for mesh in &self.meshes {
+            mesh.delete(gl);
+        }
+        self.gbuffer_program.delete(gl);
+        self.lighting_program.delete(gl);
+        unsafe { gl.delete_vertex_array(self.empty_vao) };
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<Ssao>();
+}
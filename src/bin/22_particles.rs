@@ -0,0 +1,260 @@
+use std::{cell::RefCell, rc::Rc};
+
+use cgmath::{Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    blend::BlendMode,
+    camera::FlyCamera,
+    mesh::Mesh,
+    particles::{Curve, EmitterId, EmitterParams, ParticleSystem, Simulation},
+    primitives,
+    shader::ShaderProgram,
+    tween::Easing,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const FLOOR_VERTEX_SHADER_SRC: &str = include_str!("particles/floor.vert");
+const FLOOR_FRAGMENT_SHADER_SRC: &str = include_str!("particles/floor.frag");
+
+/// How many frames to average the frame time over before printing it
+const REPORT_FRAMES: u64 = 120;
+
+/// The settings the `particles` console command changes, which are applied at the start of the
+/// next frame
+struct Settings {
+    smoke: EmitterParams,
+    sparks: EmitterParams,
+    simulation: Simulation,
+}
+
+/// A column of slowly rising smoke with 120k particles
+fn smoke() -> EmitterParams {
+    EmitterParams {
+        position: Point3::new(0., 0., 0.),
+        max_particles: 120_000,
+        spawn_rate: 30_000.,
+        lifetime: 4.,
+        lifetime_variance: 0.25,
+        direction: Vector3::unit_y(),
+        cone_angle: 15.,
+        speed: 1.,
+        speed_variance: 0.5,
+        // Rising and drifting with the wind
+        gravity: Vector3::new(0.3, 0.4, 0.),
+        drag: 0.3,
+        color: Curve::new([0.5, 0.45, 0.4, 0.04], [0.3, 0.3, 0.3, 0.], Easing::QuadOut),
+        size: Curve::new(0.15, 1.2, Easing::CubicOut),
+        blend: BlendMode::Additive,
+    }
+}
+
+/// A fountain of 80k sparks that fall back down
+fn sparks() -> EmitterParams {
+    EmitterParams {
+        position: Point3::new(0., 0.1, 0.),
+        max_particles: 80_000,
+        spawn_rate: 40_000.,
+        lifetime: 2.,
+        lifetime_variance: 0.4,
+        direction: Vector3::unit_y(),
+        cone_angle: 35.,
+        speed: 6.,
+        speed_variance: 0.3,
+        gravity: Vector3::new(0., -9.8, 0.),
+        drag: 0.5,
+        color: Curve::new([1., 0.8, 0.3, 1.], [1., 0.2, 0., 0.], Easing::QuadIn),
+        size: Curve::new(0.05, 0.01, Easing::Linear),
+        blend: BlendMode::Additive,
+    }
+}
+
+/// Change a setting of an emitter from the console
+fn set_emitter_param(params: &mut EmitterParams, name: &str, value: &str) -> Result<(), String> {
+    let value = value
+        .parse::<f32>()
+        .map_err(|_| format!("Invalid number: {}", value))?;
+    match name {
+        "rate" => params.spawn_rate = value,
+        "lifetime" => params.lifetime = value,
+        "speed" => params.speed = value,
+        "cone" => params.cone_angle = value,
+        "gravity" => params.gravity.y = value,
+        "drag" => params.drag = value,
+        "size" => {
+            // Scale the whole size curve
+            let scale = value / params.size.start.max(f32::EPSILON);
+            params.size.start = value;
+            params.size.end *= scale;
+        }
+        "max" => params.max_particles = value as u32,
+        _ => return Err(format!("Unknown emitter setting: {}", name)),
+    }
+    Ok(())
+}
+
+struct Particles {
+    particles: ParticleSystem,
+    smoke: EmitterId,
+    sparks: EmitterId,
+    settings: Rc<RefCell<Settings>>,
+    floor: Mesh,
+    floor_program: ShaderProgram,
+    camera: FlyCamera,
+    /// The frame time added up since the last report, in seconds
+    frame_time: f32,
+}
+
+impl Particles {
+    /// Apply the settings from the console to the particle system
+    fn apply_settings(&mut self, gl: &mut glow::Context) {
+        let mut settings = self.settings.borrow_mut();
+        if settings.simulation != self.particles.gpu_simulation() {
+            // Keep the setting in line with what the system falls back to
+            self.particles.set_gpu_simulation(gl, settings.simulation);
+            settings.simulation = self.particles.gpu_simulation();
+        }
+        for (id, params) in [(self.smoke, settings.smoke), (self.sparks, settings.sparks)] {
+            if let Some(current) = self.particles.emitter_mut(id) {
+                *current = params;
+            }
+        }
+    }
+}
+
+impl RenderHandler for Particles {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.01, 0.01, 0.02, 1.].into());
+
+        let mut particles = ParticleSystem::new(gl, ctx.features());
+        let smoke = particles.add_emitter(gl, smoke());
+        let sparks = particles.add_emitter(gl, sparks());
+
+        let settings = Rc::new(RefCell::new(Settings {
+            smoke: *particles.emitter(smoke).unwrap(),
+            sparks: *particles.emitter(sparks).unwrap(),
+            simulation: particles.gpu_simulation(),
+        }));
+        let command_settings = settings.clone();
+        ctx.console.register(
+            "particles",
+            "Change the particles: particles [sim compute|feedback|cpu] [smoke|sparks rate|lifetime|\
+             speed|cone|gravity|drag|size|max <value>]",
+            move |args, _| {
+                let mut settings = command_settings.borrow_mut();
+                match args {
+                    [] => {}
+                    ["sim", simulation] => {
+                        settings.simulation = match *simulation {
+                            "compute" => Simulation::Compute,
+                            "feedback" => Simulation::TransformFeedback,
+                            "cpu" => Simulation::Cpu,
+                            _ => return Err(format!("Unknown simulation: {}", simulation)),
+                        }
+                    }
+                    ["smoke", name, value] => set_emitter_param(&mut settings.smoke, name, value)?,
+                    ["sparks", name, value] => {
+                        set_emitter_param(&mut settings.sparks, name, value)?
+                    }
+                    _ => return Err("Unknown particle setting, see `help`".into()),
+                }
+                Ok(format!(
+                    "{:?} simulation, smoke {} particles at {}/s, sparks {} particles at {}/s",
+                    settings.simulation,
+                    settings.smoke.max_particles,
+                    settings.smoke.spawn_rate,
+                    settings.sparks.max_particles,
+                    settings.sparks.spawn_rate
+                ))
+            },
+        );
+
+        let floor = Mesh::new(gl, &primitives::plane(60., 60., 1.));
+        let floor_program =
+            ShaderProgram::new(gl, FLOOR_VERTEX_SHADER_SRC, FLOOR_FRAGMENT_SHADER_SRC)
+                .unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                });
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "{} particles, simulated with {:?}. Press M to switch between compute shaders, \
+             transform feedback, and the CPU, or change the emitters with the `particles` console \
+             command.",
+            particles.capacity(),
+            particles.gpu_simulation()
+        );
+
+        let mut camera = FlyCamera::new(Point3::new(0., 3., 10.), 0., -10.);
+        camera.move_speed = 5.;
+
+        Self {
+            particles,
+            smoke,
+            sparks,
+            settings,
+            floor,
+            floor_program,
+            camera,
+            frame_time: 0.,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        let switch_simulation = ctx.input.was_key_pressed(VirtualKeyCode::M);
+        if switch_simulation {
+            let mut settings = self.settings.borrow_mut();
+            settings.simulation = match settings.simulation {
+                Simulation::Compute => Simulation::TransformFeedback,
+                Simulation::TransformFeedback => Simulation::Cpu,
+                Simulation::Cpu => Simulation::Compute,
+            };
+        }
+        self.apply_settings(gl);
+        if switch_simulation {
+            eprintln!("Simulating with {:?}", self.particles.gpu_simulation());
+        }
+
+        let delta = ctx.timing.delta();
+        self.particles.update(gl, delta);
+
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let projection = self.camera.projection_matrix(aspect_ratio);
+        let view_projection = projection * self.camera.view_matrix();
+
+        // Draw the floor first, so the particles are hidden behind it
+        self.floor_program.bind(gl);
+        self.floor_program
+            .set_uniform(gl, "viewProjection", view_projection);
+        self.floor.draw(gl);
+
+        let (_, height) = ctx.render_size();
+        self.particles
+            .draw(gl, view_projection, projection.y.y, height);
+
+        // Print how long the frames take every so often
+        self.frame_time += delta;
+        if ctx.timing.frame_count().is_multiple_of(REPORT_FRAMES) {
+            eprintln!(
+                "{} particles with {:?}: {:.2} ms per frame",
+                self.particles.capacity(),
+                self.particles.gpu_simulation(),
+                self.frame_time * 1000. / REPORT_FRAMES as f32
+            );
+            self.frame_time = 0.;
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.particles.delete(gl);
+        self.floor.delete(gl);
+        self.floor_program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<Particles>();
+}
@@ -0,0 +1,80 @@
+use glow::HasContext;
+use me_learning_opengl::{
+    audio::{AudioInput, AudioParams, AudioSpectrum},
+    render_settings::RenderSettings,
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const FRAGMENT_SHADER_SRC: &str = include_str!("audio_visualizer/visualizer.frag");
+
+struct AudioVisualizer {
+    audio: AudioSpectrum,
+    program: ShaderProgram,
+    empty_vao: u32,
+}
+
+impl RenderHandler for AudioVisualizer {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        // The visualizer covers the whole window, so there is no need to clear it
+        ctx.render_settings = RenderSettings::no_clear();
+
+        let audio = AudioSpectrum::new(gl, AudioParams::default());
+        let program = ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        eprintln!(
+            "Visualizing {:?} audio. Press I to switch between the system audio, the microphone, \
+             and silence.",
+            audio.params().input
+        );
+
+        Self {
+            audio,
+            program,
+            empty_vao: unsafe { gl.create_vertex_array().unwrap() },
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if ctx.input.was_key_pressed(VirtualKeyCode::I) {
+            // Restart the capture with the next input
+            let mut params = self.audio.params();
+            params.input = match params.input {
+                AudioInput::System => AudioInput::Microphone,
+                AudioInput::Microphone => AudioInput::Silence,
+                AudioInput::Silence => AudioInput::System,
+            };
+            self.audio.delete(gl);
+            self.audio = AudioSpectrum::new(gl, params);
+            eprintln!("Visualizing {:?} audio", params.input);
+        }
+
+        // Pick up the latest spectrum, without waiting for the audio
+        self.audio.update(gl);
+
+        let (width, height) = ctx.render_size();
+        self.program.bind(gl);
+        self.program.set_uniform(gl, "iTime", ctx.timing.time());
+        self.program
+            .set_uniform(gl, "iResolution", [width as f32, height as f32]);
+        self.audio.set_uniforms(gl, &mut self.program, 0);
+        unsafe {
+            gl.bind_vertex_array(Some(self.empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.audio.delete(gl);
+        self.program.delete(gl);
+        unsafe { gl.delete_vertex_array(self.empty_vao) };
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<AudioVisualizer>();
+}
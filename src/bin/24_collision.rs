@@ -0,0 +1,313 @@
+use std::collections::VecDeque;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3, Zero};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    collision::{self, KinematicBody, SlideResult},
+    color::Color,
+    debug_draw::DebugDraw,
+    frustum::Aabb,
+    mesh::Mesh,
+    primitives,
+    shader::ShaderProgram,
+    timing::FixedTimestep,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("collision/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("collision/fragment.glsl");
+
+/// The length of a physics step in seconds
+const STEP: f32 = 1. / 60.;
+
+/// Half of the size of the character, which is a bit shorter than 2 units tall
+const CHARACTER_HALF_EXTENTS: [f32; 3] = [0.3, 0.9, 0.3];
+/// How far below the top of the character the eyes are
+const EYE_DEPTH: f32 = 0.15;
+/// How far behind the character the camera is in third person
+const THIRD_PERSON_DISTANCE: f32 = 5.;
+
+/// The walking speed in units per second, which shift doubles
+const WALK_SPEED: f32 = 4.;
+/// The upward speed of a jump
+const JUMP_SPEED: f32 = 5.;
+/// The speed of a dash, which moves further than the thin wall is thick in one step, so only the
+/// swept tests keep it from going through
+const DASH_SPEED: f32 = 80.;
+/// How quickly the character gets to the walking speed, in 1 / seconds
+const ACCELERATION: f32 = 10.;
+
+/// How many steps of the character's path are drawn while debugging
+const TRAIL_STEPS: usize = 60;
+
+/// The number of cubes scattered around the floor
+const CUBE_COUNT: usize = 40;
+/// How far the floor goes from the middle in each direction
+const FLOOR_HALF_SIZE: f32 = 20.;
+
+const FLOOR_COLOR: [f32; 3] = [0.35, 0.35, 0.38];
+const WALL_COLOR: [f32; 3] = [0.8, 0.3, 0.25];
+const CHARACTER_COLOR: [f32; 3] = [0.9, 0.8, 0.3];
+
+/// A box that the character collides with
+struct Obstacle {
+    aabb: Aabb,
+    color: [f32; 3],
+}
+
+/// The floor, a thin wall, and a scattering of cubes to walk around and jump on
+fn build_scene() -> Vec<Obstacle> {
+    let mut obstacles = vec![
+        Obstacle {
+            aabb: Aabb {
+                min: [-FLOOR_HALF_SIZE, -1., -FLOOR_HALF_SIZE],
+                max: [FLOOR_HALF_SIZE, 0., FLOOR_HALF_SIZE],
+            },
+            color: FLOOR_COLOR,
+        },
+        // A wall thinner than a dash moves in a single step
+        Obstacle {
+            aabb: Aabb {
+                min: [-8., 0., -10.05],
+                max: [8., 3., -10.],
+            },
+            color: WALL_COLOR,
+        },
+    ];
+
+    let mut rng = SmallRng::seed_from_u64(7);
+    while obstacles.len() < CUBE_COUNT + 2 {
+        let size = Vector3::new(
+            rng.gen_range(0.5, 3.),
+            rng.gen_range(0.3, 2.5),
+            rng.gen_range(0.5, 3.),
+        );
+        let center = Point3::new(
+            rng.gen_range(-FLOOR_HALF_SIZE + 2., FLOOR_HALF_SIZE - 2.),
+            size.y / 2.,
+            rng.gen_range(-FLOOR_HALF_SIZE + 2., FLOOR_HALF_SIZE - 2.),
+        );
+        // Keep the spawn point clear
+        if center.to_vec().magnitude() < 3. {
+            continue;
+        }
+        let color = Color::from_hsv(rng.gen_range(0., 360.), 0.4, 0.8);
+        obstacles.push(Obstacle {
+            aabb: Aabb::from_center(center, size / 2.),
+            color: [color.r, color.g, color.b],
+        });
+    }
+    obstacles
+}
+
+struct Collision {
+    obstacles: Vec<Obstacle>,
+    /// The boxes of the obstacles, for the collision tests
+    obstacle_aabbs: Vec<Aabb>,
+    character: KinematicBody,
+    /// Where the character was before the last step, for smoothing its movement between steps
+    previous_position: Point3<f32>,
+    timestep: FixedTimestep,
+    /// The camera, which follows the character and is only turned by the mouse
+    camera: FlyCamera,
+    third_person: bool,
+    /// Whether the swept paths and contacts are drawn ( toggled with C )
+    show_debug: bool,
+    /// The last steps of the character, newest last
+    trail: VecDeque<SlideResult>,
+    debug_draw: DebugDraw,
+    cube: Mesh,
+    program: ShaderProgram,
+}
+
+impl Collision {
+    /// Move the character by one physics step with the movement keys that are held
+    fn step(&mut self, movement: Vector3<f32>, run: bool, jump: bool, dash: bool) {
+        let character = &mut self.character;
+
+        // Ease the horizontal velocity towards the walking speed, which also slows down dashes
+        let speed = if run { WALK_SPEED * 2. } else { WALK_SPEED };
+        let target = movement * speed;
+        let blend = 1. - (-ACCELERATION * STEP).exp();
+        character.velocity.x += (target.x - character.velocity.x) * blend;
+        character.velocity.z += (target.z - character.velocity.z) * blend;
+
+        if jump && character.grounded {
+            character.velocity.y = JUMP_SPEED;
+        }
+        if dash {
+            let forward = horizontal_forward(&self.camera);
+            character.velocity.x = forward.x * DASH_SPEED;
+            character.velocity.z = forward.z * DASH_SPEED;
+        }
+
+        self.previous_position = character.position;
+        let result = character.step(STEP, &self.obstacle_aabbs);
+        if self.trail.len() == TRAIL_STEPS {
+            self.trail.pop_front();
+        }
+        self.trail.push_back(result);
+
+        // Start over if we fall off of the edge
+        if character.position.y < -20. {
+            character.position = Point3::new(0., 2., 0.);
+            character.velocity = Vector3::zero();
+            self.previous_position = character.position;
+        }
+    }
+
+    /// Draw a box with one of the colors
+    fn draw_box(&mut self, gl: &mut glow::Context, aabb: &Aabb, color: [f32; 3]) {
+        let model = Matrix4::from_translation(aabb.center().to_vec())
+            * Matrix4::from_nonuniform_scale(
+                aabb.max[0] - aabb.min[0],
+                aabb.max[1] - aabb.min[1],
+                aabb.max[2] - aabb.min[2],
+            );
+        self.program.set_uniform(gl, "model", model);
+        self.program.set_uniform(gl, "color", color);
+        self.cube.draw(gl);
+    }
+}
+
+/// The direction the camera is looking, flattened onto the ground
+fn horizontal_forward(camera: &FlyCamera) -> Vector3<f32> {
+    let yaw = camera.yaw.to_radians();
+    Vector3::new(yaw.sin(), 0., -yaw.cos())
+}
+
+impl RenderHandler for Collision {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.55, 0.7, 0.85, 1.].into());
+
+        let obstacles = build_scene();
+        let obstacle_aabbs = obstacles.iter().map(|obstacle| obstacle.aabb).collect();
+        let character = KinematicBody::new(Point3::new(0., 2., 0.), CHARACTER_HALF_EXTENTS.into());
+
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "Walk with WASD, run with shift, jump with space, and dash with F, which the swept \
+             tests stop at the thin red wall. Press V for a third person view and C to show the swept paths and contacts."
+        );
+
+        Self {
+            obstacles,
+            obstacle_aabbs,
+            previous_position: character.position,
+            character,
+            timestep: FixedTimestep::new(STEP),
+            camera: FlyCamera::new(Point3::new(0., 2., 0.), 0., 0.),
+            third_person: false,
+            show_debug: false,
+            trail: VecDeque::with_capacity(TRAIL_STEPS),
+            debug_draw: DebugDraw::new(gl),
+            cube: Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            program,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if ctx.input.was_key_pressed(VirtualKeyCode::V) {
+            self.third_person = !self.third_person;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::C) {
+            self.show_debug = !self.show_debug;
+        }
+
+        // Walk along the ground in the direction the camera is looking, ignoring Q and E
+        self.camera.update_look(ctx);
+        let mut movement = self
+            .camera
+            .movement_input(ctx, horizontal_forward(&self.camera));
+        movement.y = 0.;
+        if movement.magnitude2() > 0. {
+            movement = movement.normalize();
+        }
+        let run = ctx.input.modifiers().shift;
+        let jump = ctx.input.is_key_pressed(VirtualKeyCode::Space);
+        let mut dash = ctx.input.was_key_pressed(VirtualKeyCode::F);
+
+        // Step the physics at a fixed rate, and only while the animation is running
+        let delta = if ctx.timing.is_paused() {
+            0.
+        } else {
+            ctx.timing.delta()
+        };
+        for _ in 0..self.timestep.advance(delta) {
+            self.step(movement, run, jump, dash);
+            // Only dash once, no matter how many steps there are this frame
+            dash = false;
+        }
+
+        // Put the camera in the character's eyes, or behind it, between the last two steps
+        let alpha = self.timestep.alpha();
+        let position =
+            self.previous_position + (self.character.position - self.previous_position) * alpha;
+        let eyes = position + Vector3::unit_y() * (CHARACTER_HALF_EXTENTS[1] - EYE_DEPTH);
+        self.camera.position = if self.third_person {
+            eyes - self.camera.forward() * THIRD_PERSON_DISTANCE
+        } else {
+            eyes
+        };
+
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let view_projection =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+
+        self.program.bind(gl);
+        self.program
+            .set_uniform(gl, "viewProjection", view_projection);
+        for i in 0..self.obstacles.len() {
+            let Obstacle { aabb, color } = self.obstacles[i];
+            self.draw_box(gl, &aabb, color);
+        }
+        if self.third_person {
+            let character = Aabb::from_center(position, CHARACTER_HALF_EXTENTS.into());
+            self.draw_box(gl, &character, CHARACTER_COLOR);
+        }
+
+        if self.show_debug {
+            // Show the obstacles near the character, the path it took, and what it touched
+            let near = self.character.aabb();
+            let reach = Aabb::from_center(near.center(), near.half_extents() * 6.);
+            let wireframe_color = ctx.theme().wireframe_color;
+            for aabb in &self.obstacle_aabbs {
+                if collision::aabb_overlaps(aabb, &reach) {
+                    self.debug_draw.aabb(aabb, wireframe_color);
+                }
+            }
+            for result in &self.trail {
+                self.debug_draw
+                    .swept_path(&result.path, Color::rgb(0.2, 0.6, 1.));
+                for contact in &result.contacts {
+                    self.debug_draw.contact(contact, Color::rgb(1., 0.1, 0.1));
+                }
+            }
+
+            // Draw the lines through the walls
+            unsafe { gl.disable(glow::DEPTH_TEST) };
+            self.debug_draw.draw(gl, &ctx.arena, view_projection);
+            unsafe { gl.enable(glow::DEPTH_TEST) };
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.debug_draw.delete(gl);
+        self.cube.delete(gl);
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<Collision>();
+}
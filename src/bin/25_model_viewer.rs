@@ -0,0 +1,849 @@
+use std::{
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::Duration,
+};
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::{FlyCamera, ProjectionMode},
+    cli::Flag,
+    color::Color,
+    cursor::{Cursor, CursorIcon, CustomCursor},
+    debug_text::{DebugText, LINE_HEIGHT},
+    demo_state::{Persist, StateBlob},
+    diagnostics::FrameReport,
+    features::Features,
+    frustum::Aabb,
+    gizmo::{AxisGizmo, GizmoCorner},
+    grid::{GridParams, GroundGrid},
+    material::{self, DefaultTextures, MaterialTextures, DOUBLE_SIDED_CHUNK},
+    mesh::{LoadOptions, Mesh, MeshData, Winding},
+    primitives, procedural,
+    render_graph::{DrawPhase, Material, MaterialKind},
+    shader,
+    shader_variants::{ShaderVariants, VariantKey},
+    texture::{create_texture_2d, Texture, TextureParams, TexturePurpose},
+    texture_debug::{TextureDebug, TEXTURE_DEBUG_CHUNK, TEXTURE_DEBUG_KEYS},
+    texture_streaming::{StreamingParams, TextureStreamer},
+    viewport::{Ray, Rect},
+    wireframe::{WireframeMesh, WireframeParams, WireframePass},
+    with_windows_and_config, AppContext, DemoArgs, RenderHandler,
+};
+use winit::{MouseButton, VirtualKeyCode};
+
+const VERTEX_SHADER_SRC: &str = include_str!("model_viewer/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("model_viewer/fragment.glsl");
+
+const FLAGS: &[Flag] = &[
+    Flag::with_value(
+        "model",
+        "<file>",
+        "The OBJ file to view, instead of the built-in shapes",
+    ),
+    Flag::with_value(
+        "cursor",
+        "<file>",
+        "An image to use as the cursor over the models, instead of a crosshair",
+    ),
+    Flag::with_value(
+        "cursor-hotspot",
+        "<x,y>",
+        "The pixel of the cursor image that points, from its top left ( 0,0 by default )",
+    ),
+    Flag::switch(
+        "optimize",
+        "Also load the model optimized for the vertex cache, to compare the GPU time with O",
+    ),
+    Flag::switch(
+        "stream",
+        "Stream the model's textures in, smallest mip levels first, and list what is loaded",
+    ),
+    Flag::switch(
+        "double-sided",
+        "Draw both sides of the model's triangles, for single-sheet leaves and cloth",
+    ),
+    Flag::switch(
+        "clockwise",
+        "The model's triangles are clockwise, like some exporters write them",
+    ),
+];
+
+/// Loaded models are scaled to fit in a box this big
+const MODEL_SIZE: f32 = 4.;
+/// How many frames go by between GPU time reports while comparing optimized meshes
+const REPORT_INTERVAL: u64 = 120;
+/// The keys of the viewer itself, which are listed in the overlay along with the texture debug
+/// keys
+const KEYS: &[(&str, &str)] = &[
+    ("G", "toggle the grid"),
+    ("B", "toggle back face culling"),
+    ("X", "toggle the axis gizmo"),
+    ("C", "move the gizmo to another corner"),
+    ("M", "show the wireframe, then with the hidden lines dimmed"),
+    (
+        "Click",
+        "an axis of the gizmo to look along it, or the floor to print where it is",
+    ),
+    (
+        "V",
+        "switch to orthographic and back, shift for no animation",
+    ),
+];
+/// How long switching between perspective and orthographic takes in seconds
+const PROJECTION_TRANSITION: f32 = 0.6;
+/// Roughly the middle of the models, which stays the same size when switching projections
+const FOCUS_POINT: [f32; 3] = [0., 1., 0.];
+/// The texture unit of the debug image, with the material textures on the units after it
+const DEBUG_IMAGE_UNIT: u32 = 0;
+
+/// A mesh and where it sits in the scene
+struct Model {
+    mesh: Mesh,
+    /// The same mesh with its triangles and vertices reordered, when comparing them
+    optimized: Option<Mesh>,
+    /// The triangles of the mesh for drawing its wireframe
+    wireframe: WireframeMesh,
+    transform: Matrix4<f32>,
+    color: [f32; 3],
+    /// The index of the model's material, if it has one
+    material: Option<usize>,
+    /// Whether both sides of the model's triangles are drawn, even with back face culling on
+    double_sided: bool,
+}
+
+/// How to load the model given on the command line
+#[derive(Clone, Copy, Debug, Default)]
+struct ModelOptions {
+    /// Also load the model optimized, to compare them
+    optimize: bool,
+    /// Stream in the textures of the model's materials instead of loading them up front
+    stream: bool,
+    /// Draw both sides of the model's triangles
+    double_sided: bool,
+    /// Which way round the model's triangles are
+    winding: Winding,
+}
+
+/// Load the models in an OBJ file, scaled to fit `MODEL_SIZE` and standing on the ground in the
+/// middle of the grid, and optimized copies of them too if the options say so, along with the
+/// textures of their materials, which are streamed in if there is a `streamer`
+fn load_models(
+    gl: &mut glow::Context,
+    features: &Features,
+    path: &Path,
+    options: ModelOptions,
+    mut streamer: Option<&mut TextureStreamer>,
+) -> (Vec<Model>, Vec<MaterialTextures>) {
+    let (meshes, materials) = MeshData::load_obj_with_materials(path, &LoadOptions::default());
+    let (meshes, material_ids): (Vec<_>, Vec<_>) = meshes.into_iter().unzip();
+    let optimized = if options.optimize {
+        MeshData::load_obj_with(path, &LoadOptions { optimize: true })
+            .into_iter()
+            .map(Some)
+            .collect()
+    } else {
+        vec![None; meshes.len()]
+    };
+    let bounds =
+        Aabb::from_points(meshes.iter().flat_map(|mesh| &mesh.positions)).unwrap_or_else(|| {
+            eprintln!("{} doesn't have any vertices", path.display());
+            std::process::exit(1);
+        });
+    let size = bounds.half_extents() * 2.;
+    eprintln!(
+        "Loaded {} with {} meshes, {:.2} x {:.2} x {:.2} units",
+        path.display(),
+        meshes.len(),
+        size.x,
+        size.y,
+        size.z
+    );
+
+    let scale = MODEL_SIZE / size.x.max(size.y).max(size.z).max(f32::EPSILON);
+    let center = bounds.center();
+    let transform = Matrix4::from_scale(scale)
+        * Matrix4::from_translation(Vector3::new(-center.x, -bounds.min[1], -center.z));
+    let models = meshes
+        .iter()
+        .zip(&optimized)
+        .zip(material_ids)
+        .map(|((data, optimized), material)| Model {
+            mesh: Mesh::new(gl, data).with_winding(options.winding),
+            optimized: optimized
+                .as_ref()
+                .map(|data| Mesh::new(gl, data).with_winding(options.winding)),
+            wireframe: WireframeMesh::new(gl, data),
+            transform,
+            color: [0.75, 0.72, 0.68],
+            material,
+            double_sided: options.double_sided,
+        })
+        .collect();
+    let materials = materials
+        .iter()
+        .map(|source| match streamer.as_deref_mut() {
+            Some(streamer) => MaterialTextures::stream(gl, streamer, source),
+            None => MaterialTextures::load(gl, features, source),
+        })
+        .collect();
+    (models, materials)
+}
+
+/// Some shapes resting on the ground, including a flat, double-sided square right on the grid
+/// plane
+fn default_models(gl: &mut glow::Context) -> Vec<Model> {
+    let sphere = primitives::uv_sphere(1., 32, 16);
+    let cube = primitives::cuboid(1.5, 1.5, 1.5);
+    let square = primitives::plane(2., 2., 1.);
+    vec![
+        Model {
+            mesh: Mesh::new(gl, &sphere),
+            optimized: None,
+            wireframe: WireframeMesh::new(gl, &sphere),
+            transform: Matrix4::from_translation(Vector3::new(-2.5, 1., 0.)),
+            color: [0.8, 0.35, 0.3],
+            material: None,
+            double_sided: false,
+        },
+        Model {
+            mesh: Mesh::new(gl, &cube),
+            optimized: None,
+            wireframe: WireframeMesh::new(gl, &cube),
+            transform: Matrix4::from_translation(Vector3::new(0., 0.75, 0.)),
+            color: [0.35, 0.7, 0.4],
+            material: None,
+            double_sided: false,
+        },
+        Model {
+            mesh: Mesh::new(gl, &square),
+            optimized: None,
+            wireframe: WireframeMesh::new(gl, &square),
+            transform: Matrix4::from_translation(Vector3::new(2.5, 0., 0.)),
+            color: [0.35, 0.45, 0.8],
+            material: None,
+            // A single sheet, which would vanish from below with back face culling
+            double_sided: true,
+        },
+    ]
+}
+
+/// What the viewer keeps between runs, apart from the camera and the texture debug view
+#[derive(Clone, Debug, PartialEq)]
+struct ViewerSettings {
+    /// The model that was open, which is opened again if no other one is given
+    model: Option<PathBuf>,
+    show_grid: bool,
+    show_gizmo: bool,
+    cull_back_faces: bool,
+    gizmo_corner: GizmoCorner,
+    wireframe: Option<WireframeParams>,
+}
+
+impl Default for ViewerSettings {
+    fn default() -> Self {
+        Self {
+            model: None,
+            show_grid: true,
+            show_gizmo: true,
+            cull_back_faces: true,
+            gizmo_corner: GizmoCorner::BottomLeft,
+            wireframe: None,
+        }
+    }
+}
+
+impl Persist for ViewerSettings {
+    fn save_to(&self, blob: &mut StateBlob) {
+        if let Some(model) = &self.model {
+            blob.set("model", model.display());
+        }
+        blob.set("show_grid", self.show_grid);
+        blob.set("show_gizmo", self.show_gizmo);
+        blob.set("cull_back_faces", self.cull_back_faces);
+        blob.set("gizmo_corner", self.gizmo_corner.name());
+        // The line width and how bright the hidden lines are, if the wireframe is shown
+        if let Some(params) = &self.wireframe {
+            blob.set_floats("wireframe", &[params.line_width, params.hidden_alpha]);
+        }
+    }
+
+    fn restore_from(&mut self, blob: &StateBlob) -> Result<(), String> {
+        let model = if blob.has("model") {
+            Some(PathBuf::from(blob.get::<String>("model")?))
+        } else {
+            None
+        };
+        let show_grid = blob.get("show_grid")?;
+        let show_gizmo = blob.get("show_gizmo")?;
+        let cull_back_faces = blob.get("cull_back_faces")?;
+        let gizmo_corner = blob.get::<String>("gizmo_corner")?;
+        let gizmo_corner = GizmoCorner::parse(&gizmo_corner)
+            .ok_or_else(|| format!("Unknown gizmo corner `{}`", gizmo_corner))?;
+        let wireframe = if blob.has("wireframe") {
+            let [line_width, hidden_alpha] = blob.get_floats("wireframe")?;
+            Some(WireframeParams {
+                line_width,
+                hidden_alpha,
+                ..WireframeParams::default()
+            })
+        } else {
+            None
+        };
+
+        *self = Self {
+            model,
+            show_grid,
+            show_gizmo,
+            cull_back_faces,
+            gizmo_corner,
+            wireframe,
+        };
+        Ok(())
+    }
+}
+
+struct ModelViewer {
+    /// The model file that was loaded, or `None` for the built-in shapes
+    model_path: Option<PathBuf>,
+    models: Vec<Model>,
+    /// The textures of the loaded model's materials
+    materials: Vec<MaterialTextures>,
+    /// What models without a material are drawn with, which is all default textures
+    no_material: MaterialTextures,
+    /// The textures sampled for the slots that a material doesn't have
+    default_textures: DefaultTextures,
+    /// The model shader and its texture debug variants
+    variants: ShaderVariants,
+    /// A checkerboard with transparent cells for the texture debug views to show on the models
+    debug_texture: Texture,
+    /// Which texture debug view is shown, instead of the lit models
+    texture_debug: TextureDebug,
+    /// The overlay listing the keys
+    text: DebugText,
+    /// Streams in the textures of the materials, if the model's textures are streamed
+    streamer: Option<TextureStreamer>,
+    camera: FlyCamera,
+    grid: GroundGrid,
+    gizmo: AxisGizmo,
+    /// Draws the edges of the models while `RenderSettings::wireframe` is set ( cycled with M )
+    wireframe: WireframePass,
+    show_grid: bool,
+    show_gizmo: bool,
+    /// Whether back faces are culled, except for double-sided models ( toggled with B )
+    cull_back_faces: bool,
+    /// The cursor over the models, which turns into a hand over the gizmo
+    cursor: Cursor,
+    /// Whether the optimized meshes are drawn, if there are any ( toggled with O )
+    use_optimized: bool,
+    /// Times how long the GPU takes to draw the models while comparing optimized meshes
+    timer_query: Option<u32>,
+    /// Whether or not the timer query has a result on the way
+    timer_pending: bool,
+    /// The last time measured by the timer query
+    gpu_time: Option<Duration>,
+}
+
+impl ModelViewer {
+    /// Start viewing the model at `path`, or the one that was open last time, or the built-in
+    /// shapes if there is neither, with a custom cursor or a crosshair
+    fn load(
+        gl: &mut glow::Context,
+        ctx: &mut AppContext,
+        path: Option<&Path>,
+        cursor: Option<Rc<CustomCursor>>,
+        options: ModelOptions,
+    ) -> Self {
+        let mut settings = ViewerSettings::default();
+        ctx.saved_state.restore("viewer", &mut settings);
+        let saved_model = settings.model.filter(|saved| {
+            let found = saved.is_file();
+            if !found && path.is_none() {
+                eprintln!("The last model, {}, isn't there anymore", saved.display());
+            }
+            found
+        });
+        let model_path = path.map(Path::to_path_buf).or(saved_model);
+        let path = model_path.as_deref();
+
+        let mut streamer = match path {
+            Some(_) if options.stream => Some(TextureStreamer::new(StreamingParams::default())),
+            _ => None,
+        };
+        let (models, materials) = match path {
+            Some(path) => load_models(gl, ctx.features(), path, options, streamer.as_mut()),
+            None => (default_models(gl), Vec::new()),
+        };
+        let mut variants = ShaderVariants::new();
+        variants.add_source(
+            "model",
+            VERTEX_SHADER_SRC,
+            &shader::include_chunk(
+                &shader::include_chunk(FRAGMENT_SHADER_SRC, TEXTURE_DEBUG_CHUNK),
+                DOUBLE_SIDED_CHUNK,
+            ),
+        );
+        // Compile the plain variant up front, so that a broken shader is found right away
+        match variants.get(gl, &VariantKey::new("model", &[])) {
+            Ok(program) => {
+                material::check_samplers(gl, program, "model", &["debugImage"]);
+            }
+            Err(error) => {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+        }
+        let checkerboard = procedural::checkerboard(256, 256, 32, Color::WHITE, Color::TRANSPARENT);
+        // Black and white are the same in sRGB and linear, so the checkerboard can be sRGB like
+        // any other albedo
+        let debug_texture = create_texture_2d(
+            gl,
+            ctx.features(),
+            &[checkerboard],
+            &TextureParams {
+                srgb: true,
+                purpose: Some(TexturePurpose::Albedo),
+                label: Some("Model viewer checkerboard".into()),
+                ..Default::default()
+            },
+        );
+        unsafe {
+            gl.enable(glow::DEPTH_TEST);
+            if settings.cull_back_faces {
+                gl.enable(glow::CULL_FACE);
+            }
+        }
+        ctx.render_settings.wireframe = settings.wireframe;
+
+        // Look at the models from where the camera was last time
+        let mut camera = FlyCamera::new(Point3::new(0., 3., 8.), 0., -15.);
+        ctx.saved_state.restore("camera", &mut camera);
+        let mut texture_debug = TextureDebug::default();
+        ctx.saved_state.restore("texture_debug", &mut texture_debug);
+
+        eprintln!(
+            "Press G to toggle the grid, B to toggle back face culling, X to toggle the axis \
+             gizmo, C to move the gizmo to another corner, M to show the wireframe, V to switch \
+             between perspective and orthographic, and F7 to switch between the light and dark \
+             themes. The texture debug keys are listed on screen."
+        );
+        if models.iter().any(|model| model.optimized.is_some()) {
+            eprintln!(
+                "Press O to switch between the original and optimized meshes and compare how long \
+                 they take the GPU to draw."
+            );
+        }
+
+        Self {
+            model_path,
+            models,
+            materials,
+            no_material: MaterialTextures::new(),
+            default_textures: DefaultTextures::new(gl, ctx.features()),
+            variants,
+            debug_texture,
+            texture_debug,
+            text: DebugText::new(gl),
+            streamer,
+            camera,
+            grid: GroundGrid::new(gl, GridParams::default()),
+            gizmo: AxisGizmo::new(gl).with_corner(settings.gizmo_corner),
+            wireframe: WireframePass::new(gl),
+            show_grid: settings.show_grid,
+            show_gizmo: settings.show_gizmo,
+            cull_back_faces: settings.cull_back_faces,
+            cursor: match cursor {
+                Some(cursor) => Cursor::Custom(cursor),
+                None => Cursor::Icon(CursorIcon::Crosshair),
+            },
+            use_optimized: true,
+            timer_query: None,
+            timer_pending: false,
+            gpu_time: None,
+        }
+    }
+
+    /// Whether there are optimized meshes to compare with the original ones
+    fn comparing(&self) -> bool {
+        self.models.iter().any(|model| model.optimized.is_some())
+    }
+}
+
+impl RenderHandler for ModelViewer {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        Self::load(gl, ctx, None, None, ModelOptions::default())
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if let Some(streamer) = &mut self.streamer {
+            streamer.update(gl);
+        }
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::G) {
+            self.show_grid = !self.show_grid;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::X) {
+            self.show_gizmo = !self.show_gizmo;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::B) {
+            self.cull_back_faces = !self.cull_back_faces;
+            unsafe {
+                if self.cull_back_faces {
+                    gl.enable(glow::CULL_FACE);
+                } else {
+                    gl.disable(glow::CULL_FACE);
+                }
+            }
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::V) {
+            let mode = match self.camera.projection_mode() {
+                ProjectionMode::Perspective => ProjectionMode::Orthographic,
+                ProjectionMode::Orthographic => ProjectionMode::Perspective,
+            };
+            // Keep the models the same size, wherever the camera has flown to
+            let to_focus = Point3::from(FOCUS_POINT) - self.camera.position;
+            self.camera.focus_distance = to_focus.dot(self.camera.forward()).max(1.);
+            if ctx.input.modifiers().shift {
+                self.camera.set_projection_mode(mode);
+            } else {
+                self.camera
+                    .animate_projection_mode(mode, PROJECTION_TRANSITION);
+            }
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::M) {
+            // Off, then the lines in front, then the hidden ones too
+            let wireframe = &mut ctx.render_settings.wireframe;
+            *wireframe = match wireframe {
+                None => Some(WireframeParams::default()),
+                Some(params) if params.hidden_alpha == 0. => Some(WireframeParams::dimmed()),
+                Some(_) => None,
+            };
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::C) {
+            self.gizmo.corner = match self.gizmo.corner {
+                GizmoCorner::BottomLeft => GizmoCorner::BottomRight,
+                GizmoCorner::BottomRight => GizmoCorner::TopRight,
+                GizmoCorner::TopRight => GizmoCorner::TopLeft,
+                GizmoCorner::TopLeft => GizmoCorner::BottomLeft,
+            };
+        }
+        self.texture_debug
+            .handle_keys(&ctx.input, self.debug_texture.mip_levels);
+        let comparing = self.comparing();
+        if comparing && ctx.input.was_key_pressed(VirtualKeyCode::O) {
+            self.use_optimized = !self.use_optimized;
+            eprintln!(
+                "Drawing the {} meshes",
+                if self.use_optimized {
+                    "optimized"
+                } else {
+                    "original"
+                }
+            );
+        }
+        if comparing && self.timer_query.is_none() && ctx.features().timer_query {
+            self.timer_query = Some(unsafe { gl.create_query().unwrap() });
+        }
+
+        // Point at the models with the crosshair, and show that the gizmo is something to click
+        let cursor_ray = ctx.viewports.cursor_ray(&ctx.input);
+        let over_gizmo = cursor_ray.is_some_and(|(view, _)| view.name == "gizmo");
+        if ctx.input.was_mouse_pressed(MouseButton::Left) {
+            match cursor_ray {
+                Some((view, ray)) if view.name == "gizmo" => {
+                    if let Some(axis) = gizmo_axis(ray) {
+                        self.camera.look_in_direction(-axis);
+                    }
+                }
+                Some((view, ray)) if view.name == "scene" => match ray.distance_to_height(0.) {
+                    Some(distance) => {
+                        let point = ray.at(distance);
+                        eprintln!(
+                            "The floor under the cursor is at ({:.2}, {:.2}, {:.2})",
+                            point.x, point.y, point.z
+                        );
+                    }
+                    None => eprintln!("The floor isn't under the cursor"),
+                },
+                _ => (),
+            }
+        }
+        if over_gizmo {
+            ctx.set_cursor(CursorIcon::Hand);
+        } else {
+            ctx.set_cursor(self.cursor.clone());
+        }
+
+        // Let the depth view ( F8 ) turn the depth back into distances
+        let (near, far, orthographic) = self.camera.clip_planes();
+        ctx.render_settings
+            .depth_view
+            .set_clip_planes(near, far, orthographic);
+
+        let rect = Rect::from_window_size(ctx.render_size());
+        let view = self.camera.view_matrix();
+        let projection = self.camera.projection_matrix(rect.aspect_ratio());
+        let view_projection = projection * view;
+        ctx.viewports.register("scene", rect, view, projection);
+
+        // Collect the GPU time of the last draw, and only start timing again once it is in
+        if let Some(query) = self.timer_query {
+            unsafe {
+                if self.timer_pending
+                    && gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) != 0
+                {
+                    let nanos = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+                    self.gpu_time = Some(Duration::from_nanos(nanos as u64));
+                    self.timer_pending = false;
+                }
+                if !self.timer_pending {
+                    gl.begin_query(glow::TIME_ELAPSED, query);
+                }
+            }
+        }
+
+        // Draw the models first, so the grid can be blended over the floor around them
+        let key = self
+            .texture_debug
+            .variant_key(&VariantKey::new("model", &[]));
+        let program = self.variants.get(gl, &key).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        unsafe {
+            gl.active_texture(glow::TEXTURE0 + DEBUG_IMAGE_UNIT);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.debug_texture.texture));
+        }
+        program.set_uniform(gl, "debugImage", DEBUG_IMAGE_UNIT as i32);
+        self.texture_debug.set_uniforms(gl, program);
+        self.texture_debug
+            .set_texture(gl, program, &self.debug_texture, false);
+        for model in &self.models {
+            program.set_uniform(gl, "model", model.transform);
+            program.set_uniform(gl, "color", model.color);
+            let material = model
+                .material
+                .and_then(|index| self.materials.get(index))
+                .unwrap_or(&self.no_material);
+            material.bind(gl, program, &self.default_textures, DEBUG_IMAGE_UNIT + 1);
+            let mesh = match &model.optimized {
+                Some(optimized) if self.use_optimized => optimized,
+                _ => &model.mesh,
+            };
+            Material::new(MaterialKind::Opaque)
+                .with_double_sided(model.double_sided)
+                .draw(gl, DrawPhase::Shade, |gl| mesh.draw(gl));
+        }
+
+        if self.timer_query.is_some() && !self.timer_pending {
+            unsafe { gl.end_query(glow::TIME_ELAPSED) };
+            self.timer_pending = true;
+        }
+        if comparing && ctx.timing.frame_count().is_multiple_of(REPORT_INTERVAL) {
+            eprintln!(
+                "{} meshes: GPU draw {}",
+                if self.use_optimized {
+                    "Optimized"
+                } else {
+                    "Original"
+                },
+                match self.gpu_time {
+                    Some(time) => format!("{:.2} ms", time.as_secs_f64() * 1000.),
+                    None => "unknown".into(),
+                }
+            );
+        }
+
+        if self.show_grid {
+            self.grid
+                .draw(gl, ctx, view_projection, self.camera.position);
+        }
+        if let Some(params) = ctx.render_settings.wireframe {
+            let meshes = self
+                .models
+                .iter()
+                .map(|model| (&model.wireframe, model.transform))
+                .collect::<Vec<_>>();
+            self.wireframe
+                .draw(gl, ctx, &params, view_projection, &meshes);
+        }
+        if self.show_gizmo {
+            self.gizmo.draw(gl, ctx, view);
+        }
+
+        // List the keys, and what is being shown
+        let mut y = 4.;
+        for (key, action) in KEYS.iter().chain(TEXTURE_DEBUG_KEYS) {
+            self.text
+                .text(4., y, 1, Color::WHITE, &format!("{}: {}", key, action));
+            y += LINE_HEIGHT as f32;
+        }
+        self.text.text(
+            4.,
+            y + LINE_HEIGHT as f32,
+            1,
+            Color::YELLOW,
+            &format!(
+                "Showing: {}, {}",
+                self.texture_debug.status(),
+                self.camera.projection_mode().name()
+            ),
+        );
+        if let Some(streamer) = &self.streamer {
+            streamer.draw_overlay(&mut self.text, 4., y + LINE_HEIGHT as f32 * 3., 1);
+        }
+        self.text.draw(gl, &ctx.arena, ctx.render_size());
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        for model in &self.models {
+            model.mesh.delete(gl);
+            if let Some(optimized) = &model.optimized {
+                optimized.delete(gl);
+            }
+            model.wireframe.delete(gl);
+        }
+        if let Some(query) = self.timer_query {
+            unsafe { gl.delete_query(query) };
+        }
+        // Stop streaming before the textures are deleted
+        if let Some(streamer) = &mut self.streamer {
+            streamer.delete();
+        }
+        for material in &self.materials {
+            material.delete(gl);
+        }
+        self.default_textures.delete(gl);
+        self.variants.delete(gl);
+        self.debug_texture.delete(gl);
+        self.text.delete(gl);
+        self.grid.delete(gl);
+        self.gizmo.delete(gl);
+        self.wireframe.delete(gl);
+    }
+
+    fn describe(&self, report: &mut FrameReport) {
+        report.section(
+            "Camera",
+            format!(
+                "At {:?}, {} projection",
+                self.camera.position,
+                self.camera.projection_mode().name()
+            ),
+        );
+        report.matrix("View matrix", self.camera.view_matrix());
+        let models = self
+            .models
+            .iter()
+            .enumerate()
+            .map(|(index, model)| {
+                format!(
+                    "Model {}: {} indices, material {:?}, {:?}{}",
+                    index,
+                    model.mesh.index_count,
+                    model.material,
+                    model.mesh.winding,
+                    if model.double_sided {
+                        ", double-sided"
+                    } else {
+                        ""
+                    }
+                )
+            })
+            .collect::<Vec<_>>();
+        report.section("Models", models.join("\n"));
+    }
+
+    fn save_state(&self, ctx: &mut AppContext) {
+        let settings = ViewerSettings {
+            // The whole path, so the model is found from any directory
+            model: self
+                .model_path
+                .as_ref()
+                .map(|path| path.canonicalize().unwrap_or_else(|_| path.clone())),
+            show_grid: self.show_grid,
+            show_gizmo: self.show_gizmo,
+            cull_back_faces: self.cull_back_faces,
+            gizmo_corner: self.gizmo.corner,
+            wireframe: ctx.render_settings.wireframe,
+        };
+        ctx.saved_state.save("viewer", &settings);
+        ctx.saved_state.save("camera", &self.camera);
+        ctx.saved_state.save("texture_debug", &self.texture_debug);
+    }
+}
+
+/// The axis of the gizmo whose dot a ray through the gizmo's view passes over, pointing to the
+/// dot
+fn gizmo_axis(ray: Ray) -> Option<Vector3<f32>> {
+    let axes = [
+        Vector3::unit_x(),
+        Vector3::unit_y(),
+        Vector3::unit_z(),
+        -Vector3::unit_x(),
+        -Vector3::unit_y(),
+        -Vector3::unit_z(),
+    ];
+    // The gizmo's axes are 1 long, so its dots are about this big
+    let dot_radius = 0.2;
+    axes.iter()
+        .map(|&axis| {
+            let to_dot = Point3::from_vec(axis) - ray.origin;
+            let distance = (to_dot - ray.direction * to_dot.dot(ray.direction)).magnitude();
+            (axis, distance)
+        })
+        .filter(|(_, distance)| *distance < dot_radius)
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(axis, _)| axis)
+}
+
+fn main() {
+    let args = DemoArgs::parse_with(FLAGS);
+    let model = args.value("model").map(PathBuf::from);
+    let options = ModelOptions {
+        optimize: args.flag("optimize"),
+        stream: args.flag("stream"),
+        double_sided: args.flag("double-sided"),
+        winding: if args.flag("clockwise") {
+            Winding::Clockwise
+        } else {
+            Winding::CounterClockwise
+        },
+    };
+    let hotspot = args.value("cursor-hotspot").map(|hotspot| {
+        let parse = || {
+            let (x, y) = hotspot.split_once(',')?;
+            Some((x.trim().parse().ok()?, y.trim().parse().ok()?))
+        };
+        parse().unwrap_or_else(|| {
+            eprintln!("The cursor hotspot should look like 4,4, not {}", hotspot);
+            std::process::exit(1);
+        })
+    });
+    let cursor = args.value("cursor").map(|path| {
+        let cursor = CustomCursor::open(path, hotspot.unwrap_or((0, 0))).unwrap_or_else(|error| {
+            eprintln!("Couldn't load the cursor {}: {}", path, error);
+            std::process::exit(1);
+        });
+        Rc::new(cursor)
+    });
+    let window_config = args.window_config();
+    with_windows_and_config(
+        args.config,
+        vec![(
+            window_config,
+            Box::new(move |gl, ctx| {
+                Box::new(ModelViewer::load(
+                    gl,
+                    ctx,
+                    model.as_deref(),
+                    cursor.clone(),
+                    options,
+                ))
+            }),
+        )],
+    );
+}
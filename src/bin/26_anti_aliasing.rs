@@ -0,0 +1,147 @@
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera, mesh::Mesh, primitives, shader::ShaderProgram, stereo::render_eyes,
+    AppContext, DemoArgs, RenderHandler,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("anti_aliasing/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("anti_aliasing/fragment.glsl");
+
+/// How many cubes there are along each side of the grid
+const GRID_SIZE: i32 = 3;
+/// The distance between the cubes
+const SPACING: f32 = 3.;
+/// The size of the cage around each cube
+const CAGE_SIZE: f32 = 1.6;
+/// The thickness of the cage bars and the poles, which is thinner than a pixel from afar
+const BAR_THICKNESS: f32 = 0.015;
+/// How many poles stand in a row behind the cubes
+const POLE_COUNT: i32 = 40;
+/// How fast the cubes turn in degrees per second
+const TURN_SPEED: f32 = 20.;
+
+const CUBE_COLOR: [f32; 3] = [0.85, 0.45, 0.3];
+const BAR_COLOR: [f32; 3] = [0.9, 0.9, 0.85];
+const POLE_COLOR: [f32; 3] = [0.3, 0.3, 0.35];
+
+/// The transforms of the twelve edges of a cage of the given size, as unit cubes scaled into
+/// thin bars
+fn cage_edges(size: f32) -> Vec<Matrix4<f32>> {
+    let half = size / 2.;
+    let mut edges = Vec::with_capacity(12);
+    for axis in 0..3 {
+        let mut scale = [BAR_THICKNESS; 3];
+        scale[axis] = size + BAR_THICKNESS;
+        for &(a, b) in &[(-1., -1.), (-1., 1.), (1., -1.), (1., 1.)] {
+            // Put the bar at one of the four corners around the axis
+            let mut offset = [0.; 3];
+            offset[(axis + 1) % 3] = a * half;
+            offset[(axis + 2) % 3] = b * half;
+            edges.push(
+                Matrix4::from_translation(offset.into())
+                    * Matrix4::from_nonuniform_scale(scale[0], scale[1], scale[2]),
+            );
+        }
+    }
+    edges
+}
+
+struct AntiAliasingDemo {
+    cube: Mesh,
+    program: ShaderProgram,
+    camera: FlyCamera,
+    /// The edges of a cage around the origin
+    cage: Vec<Matrix4<f32>>,
+}
+
+impl AntiAliasingDemo {
+    /// Draw the unit cube with a transform and a color
+    fn draw_cube(&mut self, gl: &mut glow::Context, model: Matrix4<f32>, color: [f32; 3]) {
+        self.program.set_uniform(gl, "model", model);
+        self.program.set_uniform(gl, "color", color);
+        self.cube.draw(gl);
+    }
+
+    /// Draw the cubes, their cages, and the poles with the view projection that is set
+    fn draw_scene(&mut self, gl: &mut glow::Context, time: f32) {
+        // A grid of turning cubes, each in a cage of thin bars, which shows off the stair steps
+        // along their edges and the bars breaking up as they turn
+        let half_grid = (GRID_SIZE - 1) as f32 * SPACING / 2.;
+        for i in 0..GRID_SIZE * GRID_SIZE {
+            let position = Vector3::new(
+                (i % GRID_SIZE) as f32 * SPACING - half_grid,
+                1.,
+                -((i / GRID_SIZE) as f32) * SPACING,
+            );
+            let turn = Matrix4::from_translation(position)
+                * Matrix4::from_angle_y(Deg(time * TURN_SPEED + i as f32 * 10.))
+                * Matrix4::from_angle_x(Deg(20.));
+            self.draw_cube(gl, turn, CUBE_COLOR);
+            for edge in 0..self.cage.len() {
+                let model = turn * self.cage[edge];
+                self.draw_cube(gl, model, BAR_COLOR);
+            }
+        }
+
+        // A row of poles behind the cubes, which get thinner than a pixel in the distance
+        for i in 0..POLE_COUNT {
+            let x = (i - POLE_COUNT / 2) as f32 * 0.75;
+            let model = Matrix4::from_translation(Vector3::new(x, 2., -12. - i as f32 * 0.5))
+                * Matrix4::from_nonuniform_scale(BAR_THICKNESS * 2., 4., BAR_THICKNESS * 2.);
+            self.draw_cube(gl, model, POLE_COLOR);
+        }
+    }
+}
+
+impl RenderHandler for AntiAliasingDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.55, 0.65, 0.75, 1.].into());
+
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "Anti-aliasing: {}. Press F6 to cycle through the anti-aliasing modes, F3 to show \
+             their GPU time in the title, and F10 for side by side stereo.",
+            ctx.render_settings.anti_aliasing
+        );
+
+        Self {
+            cube: Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            program,
+            camera: FlyCamera::new(Point3::new(0., 2., 9.), 0., -10.),
+            cage: cage_edges(CAGE_SIZE),
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+
+        // Draw for each eye when stereo is on ( F10 ), with the eyes converging on the cubes
+        let time = ctx.timing.time();
+        render_eyes(gl, ctx, |gl, view| {
+            let projection = self.camera.projection_matrix(view.aspect_ratio());
+            let eye_view = view.camera_view(&self.camera);
+            view.register(ctx, eye_view, projection);
+            let view_projection = projection * eye_view;
+            self.program.bind(gl);
+            self.program
+                .set_uniform(gl, "viewProjection", view_projection);
+            self.draw_scene(gl, time);
+        });
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.cube.delete(gl);
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<AntiAliasingDemo>();
+}
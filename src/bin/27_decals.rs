@@ -0,0 +1,228 @@
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    color::Color,
+    mesh::Mesh,
+    primitives,
+    procedural::{self, NoiseParams},
+    render_graph::{Material, MaterialKind, PassTarget, PolygonOffset, RenderGraph, RenderPass},
+    shader::ShaderProgram,
+    texture::{create_texture_2d, Texture, TextureParams, TexturePurpose},
+    tween::Lerp,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("decals/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("decals/fragment.glsl");
+
+/// The size of the scorch mark texture
+const DECAL_TEXTURE_SIZE: u32 = 128;
+/// The size of the floor on each side
+const FLOOR_SIZE: f32 = 40.;
+
+const FLOOR_COLOR: [f32; 3] = [0.6, 0.58, 0.52];
+const BOX_COLOR: [f32; 3] = [0.45, 0.55, 0.65];
+const DECAL_COLOR: [f32; 3] = [1., 1., 1.];
+
+/// The floor and the boxes, which fill in the depth pre-pass
+const SCENE_MATERIAL: Material = Material::new(MaterialKind::Opaque);
+/// The scorch marks, which lie right on the surfaces under them
+const DECAL_MATERIAL: Material =
+    Material::new(MaterialKind::AlphaTested).with_polygon_offset(PolygonOffset::DECAL);
+
+/// The boxes standing on the floor, as their centers and sizes
+const BOXES: [([f32; 3], f32); 3] = [
+    ([3., 0.75, -2.], 1.5),
+    ([-4., 1., -6.], 2.),
+    ([1., 0.5, -10.], 1.),
+];
+
+/// The scorch marks, as their centers, turns in degrees, and sizes. The last one is on top of the
+/// first box.
+const DECALS: [([f32; 3], f32, f32); 7] = [
+    ([0., 0., 0.], 0., 3.),
+    ([-2.5, 0., -2.], 40., 2.),
+    ([2., 0., -5.], 75., 2.5),
+    ([-6., 0., -10.], 120., 4.),
+    ([6., 0., -14.], 200., 3.),
+    ([-1., 0., -20.], 300., 5.),
+    ([3., 1.5, -2.], 30., 1.2),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Pass {
+    Scene,
+}
+
+/// A scorch mark: black in the middle, fading to brown, with ragged edges from noise
+fn scorch_texture(gl: &mut glow::Context, ctx: &AppContext) -> Texture {
+    let size = DECAL_TEXTURE_SIZE;
+    let noise = NoiseParams {
+        seed: 7,
+        frequency: 6,
+        ..Default::default()
+    };
+    let image = procedural::from_fn(size, size, |x, y| {
+        let (u, v) = (
+            (x as f32 + 0.5) / size as f32 * 2. - 1.,
+            (y as f32 + 0.5) / size as f32 * 2. - 1.,
+        );
+        let distance = (u * u + v * v).sqrt();
+        let ragged = distance + (procedural::noise_value(x, y, size, size, &noise) - 0.5) * 0.6;
+        let color =
+            Color::rgb(0.05, 0.04, 0.04).lerp(&Color::rgb(0.35, 0.25, 0.18), ragged.clamp(0., 1.));
+        color.with_alpha(if ragged < 0.85 { 1. } else { 0. })
+    });
+    create_texture_2d(
+        gl,
+        ctx.features(),
+        &[image],
+        &TextureParams {
+            wrap_s: glow::CLAMP_TO_EDGE,
+            wrap_t: glow::CLAMP_TO_EDGE,
+            purpose: Some(TexturePurpose::Albedo),
+            label: Some("Decal".into()),
+            ..Default::default()
+        },
+    )
+}
+
+struct Decals {
+    program: ShaderProgram,
+    floor: Mesh,
+    cube: Mesh,
+    /// A unit square facing up, which each decal is scaled and turned from
+    quad: Mesh,
+    scorch: Texture,
+    graph: RenderGraph<Pass>,
+    camera: FlyCamera,
+    /// Whether the decals are drawn with their polygon offset, to compare with the z-fighting
+    /// without it
+    use_offset: bool,
+}
+
+impl RenderHandler for Decals {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.55, 0.65, 0.75, 1.].into());
+
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        // The loop clears the window, so the pass only has to draw
+        let graph = RenderGraph::new(vec![
+            RenderPass::new(Pass::Scene, PassTarget::Surface).depth_prepass()
+        ])
+        .unwrap();
+
+        eprintln!(
+            "Press O to toggle the polygon offset of the decals and P to toggle the depth \
+             pre-pass. Without the offset the decals z-fight with the floor, especially far away."
+        );
+
+        Self {
+            program,
+            floor: Mesh::new(gl, &primitives::plane(FLOOR_SIZE, FLOOR_SIZE, 1.)),
+            cube: Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            quad: Mesh::new(gl, &primitives::plane(1., 1., 1.)),
+            scorch: scorch_texture(gl, ctx),
+            graph,
+            camera: FlyCamera::new(Point3::new(0., 1.5, 6.), 0., -10.),
+            use_offset: true,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::O) {
+            self.use_offset = !self.use_offset;
+            eprintln!(
+                "Polygon offset: {}",
+                if self.use_offset { "on" } else { "off" }
+            );
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::P) {
+            let enabled = !self.graph.depth_prepass_enabled();
+            self.graph.set_depth_prepass_enabled(enabled);
+            eprintln!("Depth pre-pass: {}", if enabled { "on" } else { "off" });
+        }
+
+        let aspect_ratio = Rect::from_window_size(ctx.render_size()).aspect_ratio();
+        let view_projection =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+        let decal_material = if self.use_offset {
+            DECAL_MATERIAL
+        } else {
+            Material {
+                polygon_offset: None,
+                ..DECAL_MATERIAL
+            }
+        };
+
+        let Self {
+            program,
+            floor,
+            cube,
+            quad,
+            scorch,
+            graph,
+            ..
+        } = self;
+        graph.execute(gl, ctx, |gl, Pass::Scene, phase| {
+            program.bind(gl);
+            program.set_uniform(gl, "viewProjection", view_projection);
+
+            SCENE_MATERIAL.draw(gl, phase, |gl| {
+                program.set_uniform(gl, "useTexture", 0);
+                program.set_uniform(gl, "model", Matrix4::from_scale(1.));
+                program.set_uniform(gl, "color", FLOOR_COLOR);
+                floor.draw(gl);
+                program.set_uniform(gl, "color", BOX_COLOR);
+                for &(center, size) in &BOXES {
+                    let model =
+                        Matrix4::from_translation(center.into()) * Matrix4::from_scale(size);
+                    program.set_uniform(gl, "model", model);
+                    cube.draw(gl);
+                }
+            });
+
+            // Without the offset the decals are the same distance away as the surfaces under
+            // them, so which one wins the depth test changes from pixel to pixel
+            decal_material.draw(gl, phase, |gl| {
+                program.set_uniform(gl, "useTexture", 1);
+                program.set_uniform(gl, "decal", 0);
+                program.set_uniform(gl, "color", DECAL_COLOR);
+                unsafe {
+                    gl.active_texture(glow::TEXTURE0);
+                    gl.bind_texture(glow::TEXTURE_2D, Some(scorch.texture));
+                }
+                for &(center, turn, size) in &DECALS {
+                    let model = Matrix4::from_translation(Vector3::from(center))
+                        * Matrix4::from_angle_y(Deg(turn))
+                        * Matrix4::from_scale(size);
+                    program.set_uniform(gl, "model", model);
+                    quad.draw(gl);
+                }
+            });
+        });
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.floor.delete(gl);
+        self.cube.delete(gl);
+        self.quad.delete(gl);
+        self.scorch.delete(gl);
+        self.program.delete(gl);
+        self.graph.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<Decals>();
+}
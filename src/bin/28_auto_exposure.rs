@@ -0,0 +1,346 @@
+use cgmath::{Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    auto_exposure::{AutoExposure, AutoExposureParams},
+    camera::FlyCamera,
+    mesh::Mesh,
+    primitives,
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    tonemap::TONEMAP_CHUNK,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const SCENE_VERTEX_SHADER_SRC: &str = include_str!("auto_exposure/scene.vert");
+const SCENE_FRAGMENT_SHADER_SRC: &str = include_str!("auto_exposure/scene.frag");
+const TONEMAP_FRAGMENT_SHADER_SRC: &str = include_str!("auto_exposure/tonemap.frag");
+
+/// Where the walk starts in the bright room and ends in the dark corridor, along X
+const WALK_START: f32 = -6.;
+const WALK_END: f32 = 24.;
+/// How long the walk takes each way, and how long the camera waits at each end, in seconds
+const WALK_TIME: f32 = 8.;
+const WAIT_TIME: f32 = 3.;
+/// The exposure used while auto exposure is overridden
+const MANUAL_EXPOSURE: f32 = 1.;
+
+const WALL_COLOR: [f32; 3] = [0.8, 0.78, 0.72];
+const FLOOR_COLOR: [f32; 3] = [0.5, 0.45, 0.4];
+const BLACK: [f32; 3] = [0., 0., 0.];
+
+/// A box of the level, as its center, size, color, and emitted light
+struct Block {
+    center: [f32; 3],
+    size: [f32; 3],
+    color: [f32; 3],
+    emissive: [f32; 3],
+}
+
+const fn block(center: [f32; 3], size: [f32; 3], color: [f32; 3]) -> Block {
+    Block {
+        center,
+        size,
+        color,
+        emissive: BLACK,
+    }
+}
+
+/// A bright room from X -10 to 0, with a doorway into a long dark corridor that goes to X 30
+const BLOCKS: [Block; 14] = [
+    // The room
+    block([-5., -0.05, 0.], [10., 0.1, 10.], FLOOR_COLOR),
+    block([-5., 4.05, 0.], [10., 0.1, 10.], WALL_COLOR),
+    block([-10.05, 2., 0.], [0.1, 4., 10.], WALL_COLOR),
+    block([-5., 2., 5.05], [10., 4., 0.1], WALL_COLOR),
+    block([-5., 2., -5.05], [10., 4., 0.1], WALL_COLOR),
+    // The wall with the doorway
+    block([0., 2., 3.25], [0.1, 4., 3.5], WALL_COLOR),
+    block([0., 2., -3.25], [0.1, 4., 3.5], WALL_COLOR),
+    block([0., 3.5, 0.], [0.1, 1., 3.], WALL_COLOR),
+    // The corridor
+    block([15., -0.05, 0.], [30., 0.1, 3.], FLOOR_COLOR),
+    block([15., 3.05, 0.], [30., 0.1, 3.], WALL_COLOR),
+    block([15., 1.5, 1.55], [30., 3., 0.1], WALL_COLOR),
+    block([15., 1.5, -1.55], [30., 3., 0.1], WALL_COLOR),
+    block([30.05, 1.5, 0.], [0.1, 3., 3.], WALL_COLOR),
+    // A table in the room
+    block([-6., 0.5, -2.], [2., 1., 1.2], [0.6, 0.3, 0.2]),
+];
+
+/// The light panels, which glow but don't light anything themselves
+const PANELS: [Block; 2] = [
+    Block {
+        center: [-5., 3.97, 0.],
+        size: [4., 0.05, 4.],
+        color: BLACK,
+        emissive: [40., 38., 34.],
+    },
+    Block {
+        center: [20., 2.97, 0.],
+        size: [0.4, 0.05, 0.4],
+        color: BLACK,
+        emissive: [2., 2.2, 2.6],
+    },
+];
+
+/// The lights under the panels, as positions and colors
+const LIGHTS: [([f32; 3], [f32; 3]); 2] = [
+    ([-5., 3.5, 0.], [300., 285., 255.]),
+    ([20., 2.7, 0.], [0.6, 0.65, 0.8]),
+];
+
+/// A framebuffer with a floating point color texture and a depth buffer, so colors can go above
+/// 1.0
+struct HdrTarget {
+    framebuffer: u32,
+    texture: u32,
+    depth: u32,
+    size: (u32, u32),
+}
+
+impl HdrTarget {
+    fn new(gl: &mut glow::Context, size: (u32, u32)) -> Self {
+        let (width, height) = (size.0.max(1) as i32, size.1.max(1) as i32);
+        unsafe {
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA16F as i32,
+                width,
+                height,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                None,
+            );
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let depth = gl.create_renderbuffer().unwrap();
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::DEPTH_COMPONENT24, width, height);
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(depth),
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                panic!("Error creating framebuffer!");
+            }
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+
+            let label = format!("HDR target {}x{}", width, height);
+            resources::track_sized(
+                ResourceKind::Texture,
+                texture,
+                &label,
+                resources::texture_bytes(width as u32, height as u32, glow::RGBA16F, 1, 1, 1),
+            );
+            resources::track(ResourceKind::Renderbuffer, depth, &label);
+            resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+
+            Self {
+                framebuffer,
+                texture,
+                depth,
+                size,
+            }
+        }
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_renderbuffer(self.depth);
+            gl.delete_texture(self.texture);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Renderbuffer, self.depth);
+        resources::untrack(ResourceKind::Texture, self.texture);
+    }
+}
+
+/// Where along the walk the camera is at a time, going back and forth and waiting at each end
+fn walk_position(time: f32) -> f32 {
+    let leg = WAIT_TIME + WALK_TIME;
+    let t = time % (leg * 2.);
+    let (t, forward) = if t < leg { (t, true) } else { (t - leg, false) };
+    let progress = ((t - WAIT_TIME) / WALK_TIME).clamp(0., 1.);
+    // Ease in and out of each end
+    let progress = progress * progress * (3. - 2. * progress);
+    let progress = if forward { progress } else { 1. - progress };
+    WALK_START + (WALK_END - WALK_START) * progress
+}
+
+struct AutoExposureDemo {
+    scene_program: ShaderProgram,
+    tonemap_program: ShaderProgram,
+    cube: Mesh,
+    /// An empty vertex array for the fullscreen triangle
+    empty_vao: u32,
+    target: HdrTarget,
+    auto_exposure: AutoExposure,
+    camera: FlyCamera,
+    /// Whether the camera walks between the room and the corridor by itself
+    walking: bool,
+}
+
+impl AutoExposureDemo {
+    fn draw_scene(&mut self, gl: &mut glow::Context, view_projection: Matrix4<f32>) {
+        let program = &mut self.scene_program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        for (i, (position, color)) in LIGHTS.iter().enumerate() {
+            program.set_uniform(gl, &format!("lightPositions[{}]", i), *position);
+            program.set_uniform(gl, &format!("lightColors[{}]", i), *color);
+        }
+        for block in BLOCKS.iter().chain(&PANELS) {
+            let model = Matrix4::from_translation(Vector3::from(block.center))
+                * Matrix4::from_nonuniform_scale(block.size[0], block.size[1], block.size[2]);
+            program.set_uniform(gl, "model", model);
+            program.set_uniform(gl, "color", block.color);
+            program.set_uniform(gl, "emissive", block.emissive);
+            self.cube.draw(gl);
+        }
+    }
+}
+
+impl RenderHandler for AutoExposureDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        let compile = |gl: &mut glow::Context, vertex, fragment| {
+            ShaderProgram::new(gl, vertex, fragment).unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            })
+        };
+        let scene_program = compile(gl, SCENE_VERTEX_SHADER_SRC, SCENE_FRAGMENT_SHADER_SRC);
+        let tonemap_program = compile(
+            gl,
+            FULLSCREEN_VERTEX_SRC,
+            &shader::include_chunk(TONEMAP_FRAGMENT_SHADER_SRC, TONEMAP_CHUNK),
+        );
+        let empty_vao = unsafe { gl.create_vertex_array().unwrap() };
+        resources::track(ResourceKind::VertexArray, empty_vao, "Empty vertex array");
+
+        eprintln!(
+            "The camera walks from a bright room into a dark corridor and back. Press E to \
+             toggle auto exposure, Space to stop walking and fly around, and L to print the \
+             average luminance and exposure."
+        );
+
+        Self {
+            scene_program,
+            tonemap_program,
+            cube: Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            empty_vao,
+            target: HdrTarget::new(gl, ctx.render_size()),
+            auto_exposure: AutoExposure::new(gl, AutoExposureParams::default()),
+            camera: FlyCamera::new(Point3::new(WALK_START, 1.7, 0.), 90., 0.),
+            walking: true,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if ctx.input.was_key_pressed(VirtualKeyCode::E) {
+            let manual = self.auto_exposure.override_exposure().is_none();
+            self.auto_exposure
+                .set_override(Some(MANUAL_EXPOSURE).filter(|_| manual));
+            eprintln!("Auto exposure: {}", if manual { "off" } else { "on" });
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::Space) {
+            self.walking = !self.walking;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::L) {
+            match self.auto_exposure.average_luminance() {
+                Some(luminance) => eprintln!(
+                    "Average luminance {:.4}, exposure {:.3}",
+                    luminance,
+                    self.auto_exposure.exposure()
+                ),
+                None => eprintln!("No luminance measured yet"),
+            }
+        }
+        if self.walking {
+            self.camera.position = Point3::new(walk_position(ctx.timing.time()), 1.7, 0.);
+            self.camera.yaw = 90.;
+            self.camera.pitch = 0.;
+        } else {
+            self.camera.update(ctx);
+        }
+
+        // Make the target again when the window changes size
+        let size = ctx.render_size();
+        if self.target.size != size {
+            self.target.delete(gl);
+            self.target = HdrTarget::new(gl, size);
+        }
+
+        // Draw the scene in HDR
+        let aspect_ratio = Rect::from_window_size(size).aspect_ratio();
+        let view_projection =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.target.framebuffer));
+            gl.viewport(0, 0, size.0 as i32, size.1 as i32);
+            gl.clear_color(0., 0., 0., 1.);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            gl.enable(glow::DEPTH_TEST);
+        }
+        self.draw_scene(gl, view_projection);
+
+        // Measure it, then tone map it into the window with the exposure
+        self.auto_exposure
+            .update(gl, self.target.texture, ctx.timing.delta());
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, ctx.surface_framebuffer());
+            gl.viewport(0, 0, size.0 as i32, size.1 as i32);
+            gl.disable(glow::DEPTH_TEST);
+            self.tonemap_program.bind(gl);
+            self.tonemap_program.set_uniform(gl, "scene", 0);
+            self.tonemap_program
+                .set_uniform(gl, "exposure", self.auto_exposure.exposure());
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.target.texture));
+            gl.bind_vertex_array(Some(self.empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            gl.bind_vertex_array(None);
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.scene_program.delete(gl);
+        self.tonemap_program.delete(gl);
+        self.cube.delete(gl);
+        self.target.delete(gl);
+        self.auto_exposure.delete(gl);
+        unsafe { gl.delete_vertex_array(self.empty_vao) };
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<AutoExposureDemo>();
+}
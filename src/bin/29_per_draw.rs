@@ -0,0 +1,412 @@
+use std::time::{Duration, Instant};
+
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    color::Color,
+    draw_list::{self, DrawCommand, DrawList, DrawMaterial, DrawMesh},
+    handle::{self, Handle},
+    mesh::Mesh,
+    per_draw::{PerDrawBuffer, PerDrawData, PER_DRAW_CHUNK},
+    primitives, procedural,
+    shader::{self, ShaderProgram},
+    texture::{create_texture_2d, Texture, TextureParams},
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use rand::Rng;
+use rayon::prelude::*;
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("per_draw/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("per_draw/fragment.glsl");
+
+/// The number of cubes along each side of the grid, which makes 50k cubes with one draw each
+const GRID_SIZE: [usize; 3] = [50, 40, 25];
+/// The distance between the middles of neighbouring cubes
+const SPACING: f32 = 2.;
+/// How many frames to wait between printing the frame and GPU times
+const REPORT_INTERVAL: u64 = 120;
+
+/// How many textures the cubes take turns binding when texture binds are on
+const TEXTURE_COUNT: usize = 4;
+
+/// How each cube binds its texture, to compare the cost of resolving handles with binding raw
+/// texture names ( cycled with H )
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TextureBinds {
+    Off,
+    RawNames,
+    Handles,
+}
+
+impl TextureBinds {
+    fn next(self) -> Self {
+        match self {
+            TextureBinds::Off => TextureBinds::RawNames,
+            TextureBinds::RawNames => TextureBinds::Handles,
+            TextureBinds::Handles => TextureBinds::Off,
+        }
+    }
+}
+
+/// How the cubes' draws are made ( switched with L )
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Submission {
+    /// One cube after another on the render thread, making each draw's data right before its GL
+    /// calls
+    Immediate,
+    /// Recording a draw list on rayon's threads, then sorting it and submitting it on the render
+    /// thread. Always uses the per-draw buffer.
+    DrawList,
+}
+
+/// A cube, which is drawn with a draw call of its own
+struct Cube {
+    position: Vector3<f32>,
+    axis: Vector3<f32>,
+    /// How fast the cube spins and pulses, in degrees and radians per second
+    speed: f32,
+    color: [f32; 4],
+}
+
+impl Cube {
+    /// The cube's per-draw data at the given time
+    fn data(&self, time: f32) -> PerDrawData {
+        let pulse = (time * self.speed.to_radians()).sin() * 0.5 + 0.5;
+        PerDrawData::new(
+            Matrix4::from_translation(self.position)
+                * Matrix4::from_axis_angle(self.axis, Deg(time * self.speed)),
+        )
+        .with_color(self.color)
+        .with_params([pulse, 0., 0., 0.])
+    }
+}
+
+struct PerDraw {
+    /// The program that reads its per-draw data from separate uniforms
+    uniform_program: ShaderProgram,
+    /// The program that reads its per-draw data from the `PerDraw` block
+    per_draw_program: ShaderProgram,
+    per_draw: PerDrawBuffer,
+    cube: Mesh,
+    cubes: Vec<Cube>,
+    /// Whether or not to draw with the per-draw buffer ( toggled with P )
+    use_per_draw: bool,
+    /// The textures that the cubes bind, which the shaders don't sample, so only the cost of
+    /// binding them shows
+    textures: Vec<Texture>,
+    texture_handles: Vec<Handle<Texture>>,
+    texture_binds: TextureBinds,
+    submission: Submission,
+    draw_list: DrawList,
+    /// The material of the cubes in the draw list without a texture, and with each texture
+    plain_material: DrawMaterial,
+    textured_materials: Vec<DrawMaterial>,
+    cube_mesh: DrawMesh,
+    /// Times how long the GPU takes to draw the cubes, if timer queries are supported
+    timer_query: Option<u32>,
+    /// Whether or not the timer query has a result on the way
+    timer_pending: bool,
+    /// The last time measured by the timer query
+    gpu_time: Option<Duration>,
+    camera: FlyCamera,
+}
+
+impl PerDraw {
+    /// Draw every cube on its own, which is what the per-draw buffer is for. Instancing would be
+    /// faster still, but only works for copies of the same mesh.
+    fn draw_immediate(&mut self, gl: &mut glow::Context, view_projection: Matrix4<f32>, time: f32) {
+        let program = if self.use_per_draw {
+            &mut self.per_draw_program
+        } else {
+            &mut self.uniform_program
+        };
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        program.set_uniform(gl, "lightDirection", Vector3::new(0.4, 1., 0.6).normalize());
+        unsafe { gl.active_texture(glow::TEXTURE0) };
+        for (i, cube) in self.cubes.iter().enumerate() {
+            match self.texture_binds {
+                TextureBinds::Off => (),
+                TextureBinds::RawNames => unsafe {
+                    let texture = self.textures[i % TEXTURE_COUNT].texture;
+                    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                },
+                TextureBinds::Handles => {
+                    handle::bind_texture(gl, self.texture_handles[i % TEXTURE_COUNT])
+                }
+            }
+            let data = cube.data(time);
+
+            // The model matrix goes through the per-draw buffer if the program has the block, and
+            // the rest has to be set by hand if it doesn't
+            self.per_draw.apply(gl, program, &data);
+            if !self.use_per_draw {
+                program.set_uniform(gl, "tint", data.color);
+                program.set_uniform(gl, "params", data.params);
+            }
+            self.cube.draw(gl);
+        }
+    }
+
+    /// Record a draw for every cube into the draw list on rayon's threads, then sort and submit
+    /// it, returning how long recording took
+    ///
+    /// Sorting groups the cubes by texture, so there are only as many texture binds as textures.
+    fn draw_with_list(
+        &mut self,
+        gl: &mut glow::Context,
+        view_projection: Matrix4<f32>,
+        time: f32,
+    ) -> Duration {
+        let start = Instant::now();
+        let (plain, textured) = (self.plain_material, &self.textured_materials);
+        let (mesh, binds) = (self.cube_mesh, self.texture_binds);
+        self.draw_list.clear();
+        self.draw_list
+            .par_extend(self.cubes.par_iter().enumerate().map(|(i, cube)| {
+                let material = if binds == TextureBinds::Off {
+                    plain
+                } else {
+                    textured[i % TEXTURE_COUNT]
+                };
+                DrawCommand::new(0, material, mesh, cube.data(time))
+            }));
+        let record_time = start.elapsed();
+        self.draw_list.sort();
+
+        // The frame uniforms are set before submitting, which binds the program by its handle
+        self.per_draw_program.bind(gl);
+        self.per_draw_program
+            .set_uniform(gl, "viewProjection", view_projection);
+        self.per_draw_program.set_uniform(
+            gl,
+            "lightDirection",
+            Vector3::new(0.4, 1., 0.6).normalize(),
+        );
+        self.draw_list.submit(gl, &mut self.per_draw, 0);
+        record_time
+    }
+}
+
+impl RenderHandler for PerDraw {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.05, 0.05, 0.08, 1.].into());
+
+        let uniform_program =
+            ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+        let mut per_draw_program = ShaderProgram::with_defines(
+            gl,
+            &shader::include_chunk(VERTEX_SHADER_SRC, PER_DRAW_CHUNK),
+            FRAGMENT_SHADER_SRC,
+            &["PER_DRAW"],
+        )
+        .unwrap();
+
+        // Fill the grid with cubes spinning around random axes
+        let mut rng = rand::thread_rng();
+        let [width, height, depth] = GRID_SIZE;
+        let center = Vector3::new(width as f32, height as f32, depth as f32) * SPACING / 2.;
+        let mut cubes = Vec::with_capacity(width * height * depth);
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let axis = Vector3::new(
+                        rng.gen_range(-1., 1.),
+                        rng.gen_range(-1., 1.),
+                        rng.gen_range(-1., 1.),
+                    );
+                    cubes.push(Cube {
+                        position: Vector3::new(x as f32, y as f32, z as f32) * SPACING - center,
+                        axis: if axis.magnitude2() > 0. {
+                            axis.normalize()
+                        } else {
+                            Vector3::unit_y()
+                        },
+                        speed: rng.gen_range(20., 120.),
+                        color: [
+                            rng.gen_range(0.3, 1.),
+                            rng.gen_range(0.3, 1.),
+                            rng.gen_range(0.3, 1.),
+                            1.,
+                        ],
+                    });
+                }
+            }
+        }
+
+        let timer_query = if ctx.features().timer_query {
+            Some(unsafe { gl.create_query().unwrap() })
+        } else {
+            None
+        };
+        let mut camera = FlyCamera::new(Point3::new(0., 0., depth as f32 * SPACING + 20.), 0., 0.);
+        camera.move_speed = 15.;
+        let textures = (0..TEXTURE_COUNT)
+            .map(|i| {
+                let color = Color::from_hsv(i as f32 / TEXTURE_COUNT as f32 * 360., 0.6, 1.);
+                create_texture_2d(
+                    gl,
+                    ctx.features(),
+                    &[procedural::checkerboard(4, 4, 1, color, Color::WHITE)],
+                    &TextureParams::default(),
+                )
+            })
+            .collect::<Vec<_>>();
+        let texture_handles = textures
+            .iter()
+            .map(|texture| texture.handle().unwrap())
+            .collect::<Vec<_>>();
+        let cube = Mesh::new(gl, &primitives::cuboid(1., 1., 1.));
+        let plain_material =
+            DrawMaterial::new(draw_list::prepare_program(gl, &mut per_draw_program).unwrap());
+        let textured_materials = texture_handles
+            .iter()
+            .map(|&texture| plain_material.with_texture(texture))
+            .collect();
+        eprintln!(
+            "Drawing {} cubes with a draw call each. Press P to switch between setting uniforms \
+             and the per-draw buffer, H to bind a texture for each cube by raw name or by \
+             handle, and L to record the draws into a draw list on worker threads.",
+            cubes.len()
+        );
+
+        Self {
+            uniform_program,
+            per_draw_program,
+            per_draw: PerDrawBuffer::new(gl, ctx.features()),
+            cube_mesh: DrawMesh::new(&cube).unwrap(),
+            cube,
+            cubes,
+            use_per_draw: true,
+            textures,
+            texture_handles,
+            texture_binds: TextureBinds::Off,
+            submission: Submission::Immediate,
+            draw_list: DrawList::new(),
+            plain_material,
+            textured_materials,
+            timer_query,
+            timer_pending: false,
+            gpu_time: None,
+            camera,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::P) {
+            self.use_per_draw = !self.use_per_draw;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::H) {
+            self.texture_binds = self.texture_binds.next();
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::L) {
+            self.submission = match self.submission {
+                Submission::Immediate => Submission::DrawList,
+                Submission::DrawList => Submission::Immediate,
+            };
+        }
+        self.per_draw.begin_frame(gl);
+
+        let aspect_ratio = Rect::from_window_size(ctx.render_size()).aspect_ratio();
+        let view_projection: Matrix4<f32> =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+        let time = ctx.timing.time();
+
+        // Collect the GPU time of the last draw, and only start timing again once it is in
+        if let Some(query) = self.timer_query {
+            unsafe {
+                if self.timer_pending
+                    && gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) != 0
+                {
+                    let nanos = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+                    self.gpu_time = Some(Duration::from_nanos(nanos as u64));
+                    self.timer_pending = false;
+                }
+                if !self.timer_pending {
+                    gl.begin_query(glow::TIME_ELAPSED, query);
+                }
+            }
+        }
+
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+        let start = Instant::now();
+        let uniforms_before = shader::frame_uniform_stats().issued;
+        let record_time = match self.submission {
+            Submission::Immediate => {
+                self.draw_immediate(gl, view_projection, time);
+                None
+            }
+            Submission::DrawList => Some(self.draw_with_list(gl, view_projection, time)),
+        };
+        let cpu_time = start.elapsed();
+        let uniforms = shader::frame_uniform_stats().issued - uniforms_before;
+
+        if self.timer_query.is_some() && !self.timer_pending {
+            unsafe { gl.end_query(glow::TIME_ELAPSED) };
+            self.timer_pending = true;
+        }
+        if ctx.timing.frame_count().is_multiple_of(REPORT_INTERVAL) {
+            let stats = self.per_draw.stats();
+            eprintln!(
+                "{}, texture binds {:?}: {} uniforms set, {} per-draw writes ( {} bytes apart ), \
+                 CPU {:.2} ms, GPU {}",
+                if self.use_per_draw || self.submission == Submission::DrawList {
+                    "Per-draw buffer"
+                } else {
+                    "Uniforms"
+                },
+                self.texture_binds,
+                uniforms,
+                stats.frame_draws,
+                self.per_draw.stride(),
+                cpu_time.as_secs_f64() * 1000.,
+                match self.gpu_time {
+                    Some(time) => format!("{:.2} ms", time.as_secs_f64() * 1000.),
+                    None => "unknown".into(),
+                }
+            );
+            if let Some(record_time) = record_time {
+                let list = self.draw_list.stats();
+                eprintln!(
+                    "Draw list: recorded in {:.2} ms, sorted in {:.2} ms, submitted in {:.2} ms \
+                     with {} program, {} texture, and {} mesh binds",
+                    record_time.as_secs_f64() * 1000.,
+                    list.sort_time.as_secs_f64() * 1000.,
+                    list.submit_time.as_secs_f64() * 1000.,
+                    list.program_binds,
+                    list.texture_binds,
+                    list.mesh_binds
+                );
+            }
+            if stats.waits > 0 || stats.reallocations > 0 {
+                eprintln!(
+                    "Per-draw buffer: waited {} times for {:.2} ms, grown {} times",
+                    stats.waits,
+                    stats.wait_time.as_secs_f64() * 1000.,
+                    stats.reallocations
+                );
+            }
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.per_draw.delete(gl);
+        self.cube.delete(gl);
+        for texture in &self.textures {
+            texture.delete(gl);
+        }
+        self.uniform_program.delete(gl);
+        self.per_draw_program.delete(gl);
+        if let Some(query) = self.timer_query {
+            unsafe { gl.delete_query(query) };
+        }
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<PerDraw>();
+}
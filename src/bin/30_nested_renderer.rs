@@ -0,0 +1,216 @@
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera, cli::Flag, mesh::Mesh, nested::NestedRenderer, primitives,
+    shader::ShaderProgram, viewport::Rect, with_windows_and_config, AppContext, DemoArgs,
+    HandlerFactory, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const TRIANGLE_VERTEX_SHADER_SRC: &str = include_str!("nested_renderer/triangle_vertex.glsl");
+const TRIANGLE_FRAGMENT_SHADER_SRC: &str = include_str!("nested_renderer/triangle_fragment.glsl");
+const VERTEX_SHADER_SRC: &str = include_str!("nested_renderer/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("nested_renderer/fragment.glsl");
+const CRT_SHADER_SRC: &str = include_str!("nested_renderer/crt.glsl");
+
+const FLAGS: &[Flag] = &[Flag::switch(
+    "recursive",
+    "Show the room itself on the TV, instead of the spinning triangle",
+)];
+
+/// The sizes of the TV picture that R cycles through
+const SCREEN_SIZES: [(u32, u32); 3] = [(320, 240), (160, 120), (640, 480)];
+
+/// The demo shown on the TV: a spinning triangle, drawn like it would be drawn in a window of its
+/// own
+struct Triangle {
+    program: ShaderProgram,
+    /// An empty vertex array, since the vertices come from `gl_VertexID`
+    vao: u32,
+}
+
+impl RenderHandler for Triangle {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.12, 1.].into());
+        Self {
+            program: ShaderProgram::new(
+                gl,
+                TRIANGLE_VERTEX_SHADER_SRC,
+                TRIANGLE_FRAGMENT_SHADER_SRC,
+            )
+            .unwrap(),
+            vao: unsafe { gl.create_vertex_array().unwrap() },
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.program.bind(gl);
+        self.program.set_uniform(gl, "time", ctx.timing.time());
+        unsafe {
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.program.delete(gl);
+        unsafe { gl.delete_vertex_array(self.vao) };
+    }
+}
+
+/// An old TV in a room, showing another handler on its screen, and a cube spinning in front of it
+/// with the same picture on every face
+struct TvRoom {
+    program: ShaderProgram,
+    crt_program: ShaderProgram,
+    floor: Mesh,
+    cube: Mesh,
+    screen: Mesh,
+    tv: NestedRenderer,
+    /// Which of `SCREEN_SIZES` the picture is ( cycled with R )
+    screen_size: usize,
+    camera: FlyCamera,
+}
+
+impl TvRoom {
+    /// Make the room, with the triangle on the TV, or another room if `recursive` is set
+    fn new(gl: &mut glow::Context, ctx: &mut AppContext, recursive: bool) -> Self {
+        ctx.render_settings.clear_color = Some([0.02, 0.02, 0.03, 1.].into());
+
+        // The room on the TV is made the same way, so it has a room on its TV too, and so on
+        // until the nested renderers give up
+        let factory: HandlerFactory = if recursive {
+            Box::new(|gl, ctx| Box::new(TvRoom::new(gl, ctx, true)))
+        } else {
+            Box::new(|gl, ctx| Box::new(Triangle::init(gl, ctx)))
+        };
+        let tv = NestedRenderer::new(gl, ctx, SCREEN_SIZES[0], &factory);
+
+        Self {
+            program: ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap(),
+            crt_program: ShaderProgram::new(gl, VERTEX_SHADER_SRC, CRT_SHADER_SRC).unwrap(),
+            floor: Mesh::new(gl, &primitives::plane(20., 20., 1.)),
+            cube: Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            screen: Mesh::new(gl, &primitives::plane(2., 1.5, 1.)),
+            tv,
+            screen_size: 0,
+            camera: FlyCamera::new(Point3::new(0., 1.6, 6.), 0., -8.),
+        }
+    }
+}
+
+impl RenderHandler for TvRoom {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        Self::new(gl, ctx, false)
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::R) {
+            self.screen_size = (self.screen_size + 1) % SCREEN_SIZES.len();
+            let (width, height) = SCREEN_SIZES[self.screen_size];
+            self.tv.resize((width, height));
+            eprintln!("The TV picture is {}x{}", width, height);
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::P) {
+            let timing = &mut self.tv.context_mut().timing;
+            let paused = !timing.is_paused();
+            timing.set_paused(paused);
+        }
+
+        // Draw the picture first, which leaves the room's GL state alone
+        self.tv.draw(gl, ctx);
+
+        let aspect_ratio = Rect::from_window_size(ctx.render_size()).aspect_ratio();
+        let view_projection =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+        let time = ctx.timing.time();
+        unsafe {
+            gl.enable(glow::DEPTH_TEST);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, self.tv.texture());
+        }
+
+        // The floor, the TV's cabinet, and the cube with the picture on it
+        self.program.bind(gl);
+        self.program
+            .set_uniform(gl, "viewProjection", view_projection);
+        self.program.set_uniform(gl, "screen", 0);
+        let solids = [
+            (&self.floor, Matrix4::from_scale(1.), [0.35, 0.3, 0.25], 0.),
+            (
+                &self.cube,
+                Matrix4::from_translation(Vector3::new(0., 1., -0.2))
+                    * Matrix4::from_nonuniform_scale(2.4, 2., 1.6),
+                [0.3, 0.2, 0.12],
+                0.,
+            ),
+            (
+                &self.cube,
+                Matrix4::from_translation(Vector3::new(2.2, 0.8 + (time * 0.8).sin() * 0.2, 1.5))
+                    * Matrix4::from_angle_y(Deg(time * 40.))
+                    * Matrix4::from_angle_x(Deg(time * 25.))
+                    * Matrix4::from_scale(0.8),
+                [1., 1., 1.],
+                1.,
+            ),
+        ];
+        for (mesh, model, color, textured) in solids.iter() {
+            self.program.set_uniform(gl, "model", *model);
+            self.program.set_uniform(gl, "color", *color);
+            self.program.set_uniform(gl, "textured", *textured);
+            mesh.draw(gl);
+        }
+
+        // The screen, standing up just in front of the cabinet
+        let (width, height) = self.tv.size();
+        self.crt_program.bind(gl);
+        self.crt_program
+            .set_uniform(gl, "viewProjection", view_projection);
+        self.crt_program.set_uniform(
+            gl,
+            "model",
+            Matrix4::from_translation(Vector3::new(0., 1.1, 0.61))
+                * Matrix4::from_angle_x(Deg(90.)),
+        );
+        self.crt_program.set_uniform(gl, "screen", 0);
+        self.crt_program
+            .set_uniform(gl, "screenSize", [width as f32, height as f32]);
+        self.crt_program.set_uniform(gl, "time", time);
+        self.screen.draw(gl);
+    }
+
+    fn device_lost(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.tv.device_lost(gl);
+    }
+
+    fn device_restored(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.tv.device_restored(gl);
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.tv.exit(gl);
+        self.program.delete(gl);
+        self.crt_program.delete(gl);
+        self.floor.delete(gl);
+        self.cube.delete(gl);
+        self.screen.delete(gl);
+    }
+}
+
+fn main() {
+    let args = DemoArgs::parse_with(FLAGS);
+    let recursive = args.flag("recursive");
+    eprintln!(
+        "Press R to change the size of the TV picture and P to pause it. Start with --recursive to \
+         show the room on the TV."
+    );
+    let window_config = args.window_config();
+    with_windows_and_config(
+        args.config,
+        vec![(
+            window_config,
+            Box::new(move |gl, ctx| Box::new(TvRoom::new(gl, ctx, recursive))),
+        )],
+    );
+}
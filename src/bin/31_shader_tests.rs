@@ -0,0 +1,117 @@
+use me_learning_opengl::{
+    gbuffer::GBUFFER_CHUNK, shader_test, shader_test::ShaderTest, tonemap::TONEMAP_CHUNK,
+};
+
+/// How many points of the sphere the normal tests try, spread out along a spiral
+const SPHERE_POINTS: usize = 1000;
+
+/// Unit vectors spread evenly over the sphere, with the axes and the edges of the octahedron's
+/// folds added, since those are where encoding mistakes show up
+fn sphere_points() -> Vec<[f32; 4]> {
+    let golden_angle = std::f32::consts::PI * (3. - 5f32.sqrt());
+    let mut points = (0..SPHERE_POINTS)
+        .map(|i| {
+            let z = 1. - (i as f32 + 0.5) / SPHERE_POINTS as f32 * 2.;
+            let radius = (1. - z * z).sqrt();
+            let angle = golden_angle * i as f32;
+            [radius * angle.cos(), radius * angle.sin(), z, 0.]
+        })
+        .collect::<Vec<_>>();
+    let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+    points.extend_from_slice(&[
+        [1., 0., 0., 0.],
+        [-1., 0., 0., 0.],
+        [0., 1., 0., 0.],
+        [0., -1., 0., 0.],
+        [0., 0., 1., 0.],
+        [0., 0., -1., 0.],
+        [diagonal, diagonal, 0., 0.],
+        [-diagonal, diagonal, 0., 0.],
+        [diagonal, 0., -diagonal, 0.],
+        [0., -diagonal, -diagonal, 0.],
+    ]);
+    points
+}
+
+/// HDR color values from 0 to about 1000, closer together near 0 where tone mapping changes the
+/// most
+fn hdr_values() -> impl Iterator<Item = [f32; 4]> {
+    (0..256).map(|i| {
+        let value = (i as f32 / 255. * 10.).exp2() - 1.;
+        [value, value * 0.5, value * 2., 0.]
+    })
+}
+
+/// The CPU version of `encodeNormal` in the G-buffer chunk
+fn encode_normal(normal: [f32; 4]) -> [f32; 2] {
+    let length = normal[0].abs() + normal[1].abs() + normal[2].abs();
+    let (x, y, z) = (normal[0] / length, normal[1] / length, normal[2] / length);
+    if z >= 0. {
+        return [x, y];
+    }
+    let sign = |value: f32| if value >= 0. { 1. } else { -1. };
+    [(1. - y.abs()) * sign(x), (1. - x.abs()) * sign(y)]
+}
+
+/// The CPU version of `tonemapReinhard` in the tone mapping chunk
+fn reinhard(value: f32) -> f32 {
+    value / (value + 1.)
+}
+
+fn tests() -> Vec<ShaderTest> {
+    vec![
+        shader_test!(octahedral_encode {
+            chunk = GBUFFER_CHUNK,
+            probe = "vec4 probe(vec4 value) { return vec4(encodeNormal(value.xyz), 0.0, 0.0); }",
+            cases = sphere_points(),
+            reference = |normal| {
+                let [x, y] = encode_normal(normal);
+                [x, y, 0., 0.]
+            },
+            tolerance = 1e-5,
+        }),
+        shader_test!(octahedral_round_trip {
+            chunk = GBUFFER_CHUNK,
+            probe = "vec4 probe(vec4 value) { \
+                     return vec4(decodeNormal(encodeNormal(value.xyz)), 0.0); }",
+            cases = sphere_points(),
+            reference = |normal| normal,
+            tolerance = 1e-5,
+        }),
+        shader_test!(octahedral_round_trip_8_bit {
+            // Stored in an 8 bit signed normalized texture, like a packed G-buffer can be
+            chunk = GBUFFER_CHUNK,
+            probe = "vec4 probe(vec4 value) { \
+                     vec2 stored = round(encodeNormal(value.xyz) * 127.0) / 127.0; \
+                     return vec4(decodeNormal(stored), 0.0); }",
+            cases = sphere_points(),
+            reference = |normal| normal,
+            tolerance = 0.02,
+        }),
+        shader_test!(tonemap_reinhard {
+            chunk = TONEMAP_CHUNK,
+            probe = "vec4 probe(vec4 value) { return vec4(tonemapReinhard(value.rgb), 0.0); }",
+            cases = hdr_values(),
+            reference = |[r, g, b, _]: [f32; 4]| [reinhard(r), reinhard(g), reinhard(b), 0.],
+            tolerance = 1e-5,
+        }),
+        shader_test!(encode_gamma {
+            chunk = TONEMAP_CHUNK,
+            probe = "vec4 probe(vec4 value) { \
+                     return vec4(encodeGamma(tonemapReinhard(value.rgb)), 0.0); }",
+            cases = hdr_values(),
+            reference = |[r, g, b, _]: [f32; 4]| {
+                let encode = |value: f32| reinhard(value).powf(1. / 2.2);
+                [encode(r), encode(g), encode(b), 0.]
+            },
+            tolerance = 1e-4,
+        }),
+    ]
+}
+
+fn main() {
+    eprintln!("Checking the library's shader chunks against their CPU versions");
+    if !shader_test::run_tests(&tests()) {
+        std::process::exit(1);
+    }
+}
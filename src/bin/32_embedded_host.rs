@@ -0,0 +1,253 @@
+use euclid::default::Size2D;
+use glow::HasContext;
+use me_learning_opengl::{
+    embedded::EmbeddedRenderer, handler_factory, shader::ShaderProgram, AppContext, Config,
+    RenderHandler,
+};
+use surfman::{
+    Connection, Context, ContextAttributeFlags, ContextAttributes, Device, GLVersion,
+    SurfaceAccess, SurfaceType,
+};
+use winit::{Event, EventsLoop, WindowBuilder, WindowEvent};
+
+const TRIANGLE_VERTEX_SHADER_SRC: &str = include_str!("nested_renderer/triangle_vertex.glsl");
+const TRIANGLE_FRAGMENT_SHADER_SRC: &str = include_str!("nested_renderer/triangle_fragment.glsl");
+
+/// How wide the border the host draws around the embedded picture is, in pixels
+const BORDER: i32 = 24;
+
+/// The embedded handler: a spinning triangle, which changes plenty of GL state that the host
+/// doesn't expect to change
+struct Triangle {
+    program: ShaderProgram,
+    /// An empty vertex array, since the vertices come from `gl_VertexID`
+    vao: u32,
+}
+
+impl RenderHandler for Triangle {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.12, 1.].into());
+        Self {
+            program: ShaderProgram::new(
+                gl,
+                TRIANGLE_VERTEX_SHADER_SRC,
+                TRIANGLE_FRAGMENT_SHADER_SRC,
+            )
+            .unwrap(),
+            vao: unsafe { gl.create_vertex_array().unwrap() },
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.program.bind(gl);
+        self.program.set_uniform(gl, "time", ctx.timing.time());
+        unsafe {
+            gl.enable(glow::BLEND);
+            gl.blend_func(glow::ONE, glow::ONE);
+            gl.clear_color(1., 0., 1., 1.);
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.program.delete(gl);
+        unsafe { gl.delete_vertex_array(self.vao) };
+    }
+}
+
+/// The host state that the embedded renderer promises to leave alone
+#[derive(Debug, PartialEq)]
+struct HostState {
+    viewport: [i32; 4],
+    scissor_test: bool,
+    blend: bool,
+    program: i32,
+    vertex_array: i32,
+}
+
+impl HostState {
+    fn query(gl: &glow::Context) -> Self {
+        unsafe {
+            let mut viewport = [0; 4];
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            Self {
+                viewport,
+                scissor_test: gl.is_enabled(glow::SCISSOR_TEST),
+                blend: gl.is_enabled(glow::BLEND),
+                program: gl.get_parameter_i32(glow::CURRENT_PROGRAM),
+                vertex_array: gl.get_parameter_i32(glow::VERTEX_ARRAY_BINDING),
+            }
+        }
+    }
+}
+
+/// The framebuffer of the host's window surface, which surfman reports as 0 when it is the
+/// default framebuffer
+fn surface_framebuffer(device: &Device, context: &Context) -> u32 {
+    device
+        .context_surface_info(context)
+        .unwrap()
+        .unwrap()
+        .framebuffer_object
+}
+
+/// Stands in for an application with its own event loop and GL context, like a Qt app, which
+/// draws a border of its own and has the crate draw a handler inside of it
+///
+/// Everything here is done with winit and surfman directly, without any of the crate's windowing.
+/// After every frame the host checks that the state it set up is still the way it left it.
+fn main() {
+    let mut event_loop = EventsLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("Embedded host")
+        .build(&event_loop)
+        .unwrap();
+    let physical_size = |window: &winit::Window| {
+        let size = window
+            .get_inner_size()
+            .unwrap()
+            .to_physical(window.get_hidpi_factor());
+        (size.width as u32, size.height as u32)
+    };
+
+    // The host's own context and window surface
+    let conn = Connection::from_winit_window(&window).unwrap();
+    let adapter = conn.create_hardware_adapter().unwrap();
+    let mut device = conn.create_device(&adapter).unwrap();
+    let context_descriptor = device
+        .create_context_descriptor(&ContextAttributes {
+            version: GLVersion::new(3, 3),
+            flags: ContextAttributeFlags::ALPHA | ContextAttributeFlags::DEPTH,
+        })
+        .unwrap();
+    let mut context = device.create_context(&context_descriptor, None).unwrap();
+    let native_widget = conn
+        .create_native_widget_from_winit_window(&window)
+        .unwrap();
+    let surface = device
+        .create_surface(
+            &context,
+            SurfaceAccess::GPUOnly,
+            SurfaceType::Widget { native_widget },
+        )
+        .unwrap();
+    device
+        .bind_surface_to_context(&mut context, surface)
+        .map_err(|(error, _)| error)
+        .unwrap();
+    device.make_context_current(&context).unwrap();
+    let host_gl =
+        unsafe { glow::Context::from_loader_function(|s| device.get_proc_address(&context, s)) };
+
+    let size = physical_size(&window);
+    let mut renderer = unsafe {
+        EmbeddedRenderer::new(
+            |s| device.get_proc_address(&context, s),
+            surface_framebuffer(&device, &context),
+            size,
+            Config::load(),
+            &handler_factory::<Triangle>(),
+        )
+    };
+
+    let mut running = true;
+    let mut frame = 0u64;
+    while running {
+        event_loop.poll_events(|event| {
+            if let Event::WindowEvent { event, .. } = event {
+                if let WindowEvent::CloseRequested = event {
+                    running = false;
+                }
+                renderer.handle_window_event(&event);
+            }
+        });
+        if renderer.close_requested() {
+            running = false;
+        }
+
+        let (width, height) = physical_size(&window);
+        let framebuffer = surface_framebuffer(&device, &context);
+        unsafe {
+            // The host draws its border, and leaves some state of its own set up
+            host_gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                if framebuffer == 0 {
+                    None
+                } else {
+                    Some(framebuffer)
+                },
+            );
+            host_gl.viewport(0, 0, width as i32, height as i32);
+            host_gl.disable(glow::BLEND);
+            host_gl.disable(glow::SCISSOR_TEST);
+            host_gl.clear_color(0.9, 0.6, 0.2, 1.);
+            host_gl.clear(glow::COLOR_BUFFER_BIT);
+            host_gl.enable(glow::SCISSOR_TEST);
+            host_gl.scissor(
+                BORDER,
+                BORDER,
+                width as i32 - BORDER * 2,
+                height as i32 - BORDER * 2,
+            );
+        }
+        let before = HostState::query(&host_gl);
+
+        // The renderer draws over the whole framebuffer, but the host's scissor box is its own
+        // business and is put back afterwards, so the border is drawn again below
+        renderer.draw(framebuffer, (width, height));
+
+        let after = HostState::query(&host_gl);
+        if before != after {
+            eprintln!(
+                "Frame {}: The embedded renderer changed the host's state from {:?} to {:?}",
+                frame, before, after
+            );
+        }
+        unsafe {
+            // Draw the border again with the clear color the host set, which should still be set
+            host_gl.disable(glow::SCISSOR_TEST);
+            for (x, y, w, h) in [
+                (0, 0, width as i32, BORDER),
+                (0, height as i32 - BORDER, width as i32, BORDER),
+                (0, 0, BORDER, height as i32),
+                (width as i32 - BORDER, 0, BORDER, height as i32),
+            ] {
+                host_gl.enable(glow::SCISSOR_TEST);
+                host_gl.scissor(x, y, w, h);
+                host_gl.clear(glow::COLOR_BUFFER_BIT);
+            }
+            host_gl.disable(glow::SCISSOR_TEST);
+        }
+
+        // Present the frame the way the host always does
+        let mut surface = device
+            .unbind_surface_from_context(&mut context)
+            .unwrap()
+            .unwrap();
+        device.present_surface(&context, &mut surface).unwrap();
+        let surface_size = device.surface_info(&surface).size;
+        if (width as i32, height as i32) != (surface_size.width, surface_size.height) {
+            device
+                .resize_surface(
+                    &context,
+                    &mut surface,
+                    Size2D::new(width as i32, height as i32),
+                )
+                .unwrap();
+        }
+        device
+            .bind_surface_to_context(&mut context, surface)
+            .map_err(|(error, _)| error)
+            .unwrap();
+        frame += 1;
+    }
+
+    renderer.exit();
+    let mut surface = device
+        .unbind_surface_from_context(&mut context)
+        .unwrap()
+        .unwrap();
+    device.destroy_surface(&mut context, &mut surface).unwrap();
+    device.destroy_context(&mut context).unwrap();
+}
@@ -0,0 +1,217 @@
+use cgmath::Point3;
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    cli::Flag,
+    color::Color,
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    viewport::Rect,
+    with_windows_and_config, AppContext, DemoArgs, RenderHandler,
+};
+
+const SOLID_FRAGMENT_SHADER_SRC: &str = include_str!("resize_stress/solid.frag");
+
+const FLAGS: &[Flag] = &[Flag::with_value(
+    "frames",
+    "N",
+    "How many frames to resize the window for before reporting and closing ( default 600 )",
+)];
+
+const DEFAULT_FRAMES: u64 = 600;
+
+/// The absurdly big width and height that the window and the renderbuffer are asked to be
+const HUGE_SIZE: u32 = 16384;
+
+/// The sizes in physical pixels that the window is asked to be, one after the other every frame
+///
+/// A zero-sized window stops getting frames until it is restored, so the smallest size asked for
+/// is 1x1. The window system may not allow the biggest one.
+const SIZES: [(u32, u32); 8] = [
+    (640, 480),
+    (801, 599),
+    (512, 700),
+    (1, 1),
+    (1024, 576),
+    (HUGE_SIZE, HUGE_SIZE),
+    (333, 222),
+    (900, 900),
+];
+
+/// The sizes that the offscreen renderbuffer is resized to, one after the other every frame,
+/// through the same clamping the loop does for the window
+///
+/// The huge size is clamped to the driver's limit, but an RGBA8 renderbuffer that big can still
+/// be more memory than the GPU has, so `GL_OUT_OF_MEMORY` is allowed for it.
+const OFFSCREEN_SIZES: [(u32, u32); 5] =
+    [(0, 0), (1, 1), (0, 480), (HUGE_SIZE, HUGE_SIZE), (640, 480)];
+
+/// Resizes the window every frame while checking that each frame covers the whole window
+///
+/// The handler never sets the viewport, so it relies on the loop to keep it, and the window
+/// surface, the size of the window through resizes, including ones the window system does without
+/// telling us. Every frame a green triangle is drawn over the viewport and the corners of the
+/// window are read back: a corner that isn't green was drawn with a viewport or surface of the
+/// wrong size.
+///
+/// Every frame also resizes a renderbuffer through absurd sizes, including 0x0 and 16384x16384,
+/// and builds a projection for the window's aspect ratio. Frames with a GL error, other than
+/// running out of memory at the huge size, or a projection with NaNs count as failed too. The process exits with an error if any frame failed.
+struct ResizeStress {
+    program: ShaderProgram,
+    /// An empty vertex array, since the vertices come from `gl_VertexID`
+    vao: u32,
+    /// Resized every frame to one of `OFFSCREEN_SIZES`
+    renderbuffer: u32,
+    camera: FlyCamera,
+    frames: u64,
+    mismatched_frames: u64,
+    failed_frames: u64,
+}
+
+impl ResizeStress {
+    fn new(gl: &mut glow::Context, ctx: &mut AppContext, frames: u64) -> Self {
+        ctx.render_settings.clear_color = Some(Color::BLACK);
+        Self {
+            program: ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, SOLID_FRAGMENT_SHADER_SRC)
+                .unwrap(),
+            vao: unsafe { gl.create_vertex_array().unwrap() },
+            renderbuffer: unsafe { gl.create_renderbuffer().unwrap() },
+            camera: FlyCamera::new(Point3::new(0., 0., 3.), 0., 0.),
+            frames,
+            mismatched_frames: 0,
+            failed_frames: 0,
+        }
+    }
+}
+
+impl RenderHandler for ResizeStress {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        Self::new(gl, ctx, DEFAULT_FRAMES)
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let frame = ctx.timing.frame_count();
+        let (width, height) = ctx.window_size();
+
+        self.program.bind(gl);
+        let mut viewport = [0; 4];
+        let mut corners = [[0u8; 4]; 4];
+        unsafe {
+            gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            gl.bind_vertex_array(None);
+
+            // Reading outside of the surface leaves the pixel at zero, so a surface that is too
+            // small shows up too
+            let (right, top) = (width as i32 - 1, height as i32 - 1);
+            for (corner, (x, y)) in
+                corners
+                    .iter_mut()
+                    .zip([(0, 0), (right, 0), (0, top), (right, top)])
+            {
+                gl.read_pixels(
+                    x,
+                    y,
+                    1,
+                    1,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    glow::PixelPackData::Slice(corner),
+                );
+            }
+        }
+
+        let viewport_matches = viewport == [0, 0, width as i32, height as i32];
+        let corners_match = corners.iter().all(|&[r, g, b, _]| (r, g, b) == (0, 255, 0));
+        if !viewport_matches || !corners_match {
+            self.mismatched_frames += 1;
+            eprintln!(
+                "Frame {}: window {}x{}, viewport {:?}, corners {:?}",
+                frame, width, height, viewport, corners
+            );
+        }
+
+        // Resize the renderbuffer the way a handler resizes its own framebuffers
+        let requested = OFFSCREEN_SIZES[frame as usize % OFFSCREEN_SIZES.len()];
+        let (offscreen_width, offscreen_height) = ctx.features().clamp_framebuffer_size(requested);
+        let error = unsafe {
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(self.renderbuffer));
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::RGBA8,
+                offscreen_width as i32,
+                offscreen_height as i32,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+            gl.get_error()
+        };
+        let aspect_ratio = Rect::from_window_size((width, height)).aspect_ratio();
+        let empty_aspect_ratio = Rect::new(0, 0, 0, 0).aspect_ratio();
+        let projections_finite =
+            [aspect_ratio, empty_aspect_ratio, f32::NAN, 0.]
+                .iter()
+                .all(|&aspect_ratio| {
+                    let projection = self.camera.projection_matrix(aspect_ratio);
+                    AsRef::<[f32; 16]>::as_ref(&projection)
+                        .iter()
+                        .all(|value| value.is_finite())
+                });
+        let huge = requested == (HUGE_SIZE, HUGE_SIZE);
+        let error_allowed = error == glow::NO_ERROR || (huge && error == glow::OUT_OF_MEMORY);
+        if !error_allowed || !projections_finite {
+            self.failed_frames += 1;
+            eprintln!(
+                "Frame {}: renderbuffer {:?} as {}x{}, GL error {:#x}, projection finite: {}",
+                frame, requested, offscreen_width, offscreen_height, error, projections_finite
+            );
+        }
+
+        if frame >= self.frames {
+            ctx.request_close();
+        } else {
+            ctx.request_window_size(SIZES[frame as usize % SIZES.len()]);
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.program.delete(gl);
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            gl.delete_renderbuffer(self.renderbuffer);
+        }
+        eprintln!(
+            "{} of {} frames were drawn with a mismatched viewport or surface, {} had a GL error \
+             or a projection with NaNs, and the loop corrected the size {} times without a resize \
+             event",
+            self.mismatched_frames,
+            ctx.timing.frame_count(),
+            self.failed_frames,
+            ctx.size_corrections()
+        );
+        if self.mismatched_frames > 0 || self.failed_frames > 0 {
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args = DemoArgs::parse_with(FLAGS);
+    let frames = args
+        .value("frames")
+        .map(|frames| {
+            frames.parse().unwrap_or_else(|_| {
+                eprintln!("The number of frames should be a number, not {}", frames);
+                std::process::exit(1);
+            })
+        })
+        .unwrap_or(DEFAULT_FRAMES);
+    let window_config = args.window_config();
+    with_windows_and_config(
+        args.config,
+        vec![(
+            window_config,
+            Box::new(move |gl, ctx| Box::new(ResizeStress::new(gl, ctx, frames))),
+        )],
+    );
+}
@@ -0,0 +1,267 @@
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    camera_path::CameraPath,
+    cli::Flag,
+    mesh::Mesh,
+    primitives,
+    shader::ShaderProgram,
+    texture::{create_texture_2d, ImageData, Texture, TextureParams, TexturePurpose},
+    timeline::{Timeline, TimelineScript},
+    viewport::Rect,
+    with_windows_and_config, AppContext, DemoArgs, HandlerFactory, RenderHandler,
+};
+
+const TRIANGLE_VERTEX_SHADER_SRC: &str = include_str!("nested_renderer/triangle_vertex.glsl");
+const TRIANGLE_FRAGMENT_SHADER_SRC: &str = include_str!("nested_renderer/triangle_fragment.glsl");
+const VERTEX_SHADER_SRC: &str = include_str!("nested_renderer/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("nested_renderer/fragment.glsl");
+const SCRIPT_SRC: &str = include_str!("timeline/demo.ron");
+const CAMERA_PATH_SRC: &str = include_str!("timeline/camera_path.ron");
+
+const FLAGS: &[Flag] = &[Flag::with_value(
+    "script",
+    "<file>",
+    "A timeline RON file to play, instead of the built-in one",
+)];
+
+/// The first scene: a spinning triangle
+struct Triangle {
+    program: ShaderProgram,
+    /// An empty vertex array, since the vertices come from `gl_VertexID`
+    vao: u32,
+}
+
+impl RenderHandler for Triangle {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.12, 1.].into());
+        Self {
+            program: ShaderProgram::new(
+                gl,
+                TRIANGLE_VERTEX_SHADER_SRC,
+                TRIANGLE_FRAGMENT_SHADER_SRC,
+            )
+            .unwrap(),
+            vao: unsafe { gl.create_vertex_array().unwrap() },
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.program.bind(gl);
+        self.program.set_uniform(gl, "time", ctx.timing.time());
+        unsafe {
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.program.delete(gl);
+        unsafe { gl.delete_vertex_array(self.vao) };
+    }
+}
+
+/// The second scene: the wall texture on a quad, swinging from side to side
+struct TexturedQuad {
+    program: ShaderProgram,
+    quad: Mesh,
+    texture: Texture,
+    camera: FlyCamera,
+}
+
+impl RenderHandler for TexturedQuad {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0., 0.2, 0.2, 1.].into());
+        let wall = ImageData::open(ctx.config().asset_path("wall.jpg"));
+        let texture = create_texture_2d(
+            gl,
+            ctx.features(),
+            &[wall],
+            &TextureParams {
+                purpose: Some(TexturePurpose::Albedo),
+                ..Default::default()
+            },
+        );
+        Self {
+            program: ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap(),
+            quad: Mesh::new(gl, &primitives::plane(2., 2., 1.)),
+            texture,
+            camera: FlyCamera::new(Point3::new(0., 0., 3.), 0., 0.),
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        let time = ctx.timing.time();
+        let aspect_ratio = Rect::from_window_size(ctx.render_size()).aspect_ratio();
+        unsafe {
+            gl.enable(glow::DEPTH_TEST);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture.texture));
+        }
+        self.program.bind(gl);
+        self.program.set_uniform(
+            gl,
+            "viewProjection",
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix(),
+        );
+        self.program.set_uniform(
+            gl,
+            "model",
+            Matrix4::from_angle_y(Deg((time * 0.8).sin() * 35.)) * Matrix4::from_angle_x(Deg(90.)),
+        );
+        self.program.set_uniform(gl, "color", [1., 1., 1.]);
+        self.program.set_uniform(gl, "textured", 1.);
+        self.program.set_uniform(gl, "screen", 0);
+        self.quad.draw(gl);
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.program.delete(gl);
+        self.quad.delete(gl);
+        self.texture.delete(gl);
+    }
+}
+
+/// The third scene: a few lit boxes on a floor, seen from a camera flying along a path
+struct LitScene {
+    program: ShaderProgram,
+    floor: Mesh,
+    cube: Mesh,
+    camera: FlyCamera,
+    camera_path: CameraPath,
+}
+
+impl RenderHandler for LitScene {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.45, 0.55, 0.7, 1.].into());
+        Self {
+            program: ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap(),
+            floor: Mesh::new(gl, &primitives::plane(20., 20., 1.)),
+            cube: Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            camera: FlyCamera::new(Point3::new(0., 1.6, 6.), 0., -8.),
+            camera_path: CameraPath::from_ron(CAMERA_PATH_SRC).unwrap(),
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        // The camera only depends on the scene's time, so a seek puts it in the same place
+        let time = ctx.timing.time();
+        self.camera_path.apply(time, &mut self.camera);
+
+        let aspect_ratio = Rect::from_window_size(ctx.render_size()).aspect_ratio();
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+        self.program.bind(gl);
+        self.program.set_uniform(
+            gl,
+            "viewProjection",
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix(),
+        );
+        self.program.set_uniform(gl, "textured", 0.);
+        let solids = [
+            (&self.floor, Matrix4::from_scale(1.), [0.35, 0.3, 0.25]),
+            (
+                &self.cube,
+                Matrix4::from_translation(Vector3::new(0., 0.5, 0.))
+                    * Matrix4::from_angle_y(Deg(time * 20.)),
+                [0.8, 0.3, 0.2],
+            ),
+            (
+                &self.cube,
+                Matrix4::from_translation(Vector3::new(2., 0.75, -1.5)) * Matrix4::from_scale(1.5),
+                [0.2, 0.5, 0.8],
+            ),
+            (
+                &self.cube,
+                Matrix4::from_translation(Vector3::new(-1.8, 1.2 + (time * 1.5).sin() * 0.3, 1.))
+                    * Matrix4::from_angle_x(Deg(time * 45.))
+                    * Matrix4::from_scale(0.6),
+                [0.9, 0.8, 0.3],
+            ),
+        ];
+        for (mesh, model, color) in solids.iter() {
+            self.program.set_uniform(gl, "model", *model);
+            self.program.set_uniform(gl, "color", *color);
+            mesh.draw(gl);
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.program.delete(gl);
+        self.floor.delete(gl);
+        self.cube.delete(gl);
+    }
+}
+
+/// Plays the scenes as the timeline script says
+struct Sequence {
+    timeline: Timeline,
+}
+
+impl Sequence {
+    fn new(gl: &mut glow::Context, ctx: &mut AppContext, script: TimelineScript) -> Self {
+        let scene = |name: &str, factory: HandlerFactory| (name.to_owned(), factory);
+        let scenes = vec![
+            scene(
+                "triangle",
+                Box::new(|gl, ctx| Box::new(Triangle::init(gl, ctx))),
+            ),
+            scene(
+                "quad",
+                Box::new(|gl, ctx| Box::new(TexturedQuad::init(gl, ctx))),
+            ),
+            scene("lit", Box::new(|gl, ctx| Box::new(LitScene::init(gl, ctx)))),
+        ];
+        let timeline = Timeline::new(gl, ctx, script, scenes).unwrap_or_else(|error| {
+            eprintln!("Couldn't start the timeline: {}", error);
+            std::process::exit(1);
+        });
+        Self { timeline }
+    }
+}
+
+impl RenderHandler for Sequence {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        Self::new(gl, ctx, TimelineScript::from_ron(SCRIPT_SRC).unwrap())
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.timeline.draw(gl, ctx);
+    }
+
+    fn device_lost(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.timeline.device_lost(gl);
+    }
+
+    fn device_restored(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.timeline.device_restored(gl);
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.timeline.exit(gl, ctx);
+    }
+}
+
+fn main() {
+    let args = DemoArgs::parse_with(FLAGS);
+    let script = match args.value("script") {
+        Some(path) => TimelineScript::load(path).unwrap_or_else(|error| {
+            eprintln!("Couldn't load the timeline {}: {}", path, error);
+            std::process::exit(1);
+        }),
+        None => TimelineScript::from_ron(SCRIPT_SRC).unwrap(),
+    };
+    eprintln!(
+        "The timeline plays at a fixed {} frames per second. Type `seek <seconds>` in the console \
+         to jump around it, or use the time keys.",
+        script.frame_rate
+    );
+    let window_config = args.window_config();
+    with_windows_and_config(
+        args.config,
+        vec![(
+            window_config,
+            Box::new(move |gl, ctx| Box::new(Sequence::new(gl, ctx, script.clone()))),
+        )],
+    );
+}
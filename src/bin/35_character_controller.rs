@@ -0,0 +1,435 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3, Zero};
+use glow::HasContext;
+use me_learning_opengl::{
+    bvh::TriangleBvh,
+    camera::FlyCamera,
+    character_controller::{CharacterController, CharacterParams},
+    cli::Flag,
+    color::Color,
+    debug_draw::DebugDraw,
+    mesh::{self, Mesh, MeshData, NormalMode},
+    primitives,
+    shader::ShaderProgram,
+    timing::FixedTimestep,
+    viewport::Rect,
+    with_windows_and_config, AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+// The shading of the collision example works for any static mesh
+const VERTEX_SHADER_SRC: &str = include_str!("collision/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("collision/fragment.glsl");
+
+const FLAGS: &[Flag] = &[
+    Flag::with_value(
+        "model",
+        "<file>",
+        "An OBJ scene to walk around, instead of the built-in hills, steps, and ramps",
+    ),
+    Flag::with_value(
+        "scale",
+        "<factor>",
+        "How much to scale the scene by, for scenes that aren't in meters ( 1 by default )",
+    ),
+];
+
+/// The length of a physics step in seconds
+const STEP: f32 = 1. / 60.;
+
+/// How far below the top of the capsule the eyes are
+const EYE_DEPTH: f32 = 0.15;
+/// How far behind the character the camera is in third person
+const THIRD_PERSON_DISTANCE: f32 = 4.;
+/// The walking speed in units per second, which shift doubles
+const WALK_SPEED: f32 = 4.;
+/// The upward speed of a jump
+const JUMP_SPEED: f32 = 5.;
+/// How quickly the character gets to the walking speed, in 1 / seconds
+const ACCELERATION: f32 = 10.;
+/// How far the ray from the middle of the view reaches
+const PICK_DISTANCE: f32 = 100.;
+
+/// The cells along each side of the built-in ground, which make a little over 100,000 triangles
+const GROUND_CELLS: u32 = 224;
+/// How far the built-in ground goes from the middle in each direction
+const GROUND_HALF_SIZE: f32 = 40.;
+
+const GROUND_COLOR: [f32; 3] = [0.4, 0.5, 0.35];
+const STEP_COLOR: [f32; 3] = [0.75, 0.7, 0.6];
+const TALL_STEP_COLOR: [f32; 3] = [0.8, 0.45, 0.3];
+const RAMP_COLOR: [f32; 3] = [0.45, 0.55, 0.75];
+const STEEP_RAMP_COLOR: [f32; 3] = [0.8, 0.3, 0.25];
+const CHARACTER_COLOR: [f32; 3] = [0.9, 0.8, 0.3];
+
+/// A mesh of the scene and where it is, which the character collides with
+#[derive(Clone)]
+struct Piece {
+    data: MeshData,
+    transform: Matrix4<f32>,
+    color: [f32; 3],
+}
+
+impl Piece {
+    /// A box of the given size, turned about the Z axis and then moved
+    fn cuboid(size: [f32; 3], angle: f32, position: [f32; 3], color: [f32; 3]) -> Self {
+        Self {
+            data: primitives::cuboid(size[0], size[1], size[2]),
+            transform: Matrix4::from_translation(position.into())
+                * Matrix4::from_angle_z(Deg(angle)),
+            color,
+        }
+    }
+}
+
+/// The height of the built-in ground, some low rolling hills that are flat in the middle
+fn ground_height(x: f32, z: f32) -> f32 {
+    let distance = (x * x + z * z).sqrt();
+    let hills = (x * 0.21).sin() * (z * 0.17).cos() + 0.5 * (x * 0.43 + z * 0.31).sin();
+    hills * ((distance - 12.) / 10.).clamp(0., 1.) * 1.5
+}
+
+/// Rolling ground with stairs of low and of too high steps, and ramps shallow enough to walk up
+/// and too steep to
+fn build_scene() -> Vec<Piece> {
+    let mut positions = Vec::new();
+    let cell = GROUND_HALF_SIZE * 2. / GROUND_CELLS as f32;
+    for row in 0..=GROUND_CELLS {
+        for column in 0..=GROUND_CELLS {
+            let x = column as f32 * cell - GROUND_HALF_SIZE;
+            let z = row as f32 * cell - GROUND_HALF_SIZE;
+            positions.push([x, ground_height(x, z), z]);
+        }
+    }
+    let mut indices = Vec::new();
+    for row in 0..GROUND_CELLS {
+        for column in 0..GROUND_CELLS {
+            let corner = row * (GROUND_CELLS + 1) + column;
+            let below = corner + GROUND_CELLS + 1;
+            indices.extend_from_slice(&[corner, below, corner + 1, corner + 1, below, below + 1]);
+        }
+    }
+    let mut pieces = vec![Piece {
+        data: mesh::compute_normals(&positions, &indices, NormalMode::Smooth),
+        transform: Matrix4::from_scale(1.),
+        color: GROUND_COLOR,
+    }];
+
+    // Stairs with steps lower than the step height, and then higher
+    for (steps, rise, z, color) in [(8, 0.25, -4., STEP_COLOR), (4, 0.5, -7., TALL_STEP_COLOR)] {
+        for step in 0..steps {
+            let height = rise * (step + 1) as f32;
+            pieces.push(Piece::cuboid(
+                [0.6, height, 2.],
+                0.,
+                [4. + step as f32 * 0.6, height / 2., z],
+                color,
+            ));
+        }
+    }
+
+    // A ramp of 25 degrees and one of 60, both going up towards -X
+    for (angle, z, color) in [(25f32, 3., RAMP_COLOR), (60., 6., STEEP_RAMP_COLOR)] {
+        let (length, thickness) = (6., 0.4);
+        let radians = angle.to_radians();
+        let center = [
+            -4. - length / 2. * radians.cos(),
+            length / 2. * radians.sin() - thickness / 2.,
+            z,
+        ];
+        pieces.push(Piece::cuboid(
+            [length, thickness, 2.],
+            -angle,
+            center,
+            color,
+        ));
+    }
+    pieces
+}
+
+/// An OBJ scene, scaled by `scale`
+fn load_scene(path: &Path, scale: f32) -> Vec<Piece> {
+    MeshData::load_obj(path)
+        .into_iter()
+        .map(|data| Piece {
+            data,
+            transform: Matrix4::from_scale(scale),
+            color: [0.75, 0.72, 0.68],
+        })
+        .collect()
+}
+
+struct Walk {
+    pieces: Vec<(Mesh, Matrix4<f32>, [f32; 3])>,
+    world: TriangleBvh,
+    character: CharacterController,
+    /// Where the character's feet were before the last step, for smoothing its movement
+    /// between steps
+    previous_position: Point3<f32>,
+    timestep: FixedTimestep,
+    /// How long the character's steps took since the last report, and how many there were
+    step_time: Duration,
+    steps: u32,
+    /// The camera, which follows the character and is only turned by the mouse
+    camera: FlyCamera,
+    third_person: bool,
+    /// Whether the capsule, its contacts, and the picked point are drawn ( toggled with C )
+    show_debug: bool,
+    debug_draw: DebugDraw,
+    capsule: Mesh,
+    program: ShaderProgram,
+}
+
+impl Walk {
+    fn new(gl: &mut glow::Context, ctx: &mut AppContext, pieces: Vec<Piece>) -> Self {
+        ctx.render_settings.clear_color = Some([0.55, 0.7, 0.85, 1.].into());
+
+        let start = Instant::now();
+        let world =
+            TriangleBvh::from_meshes(pieces.iter().map(|piece| (&piece.data, piece.transform)));
+        eprintln!(
+            "Built the collision hierarchy over {} triangles in {:.1} ms",
+            world.triangle_count(),
+            start.elapsed().as_secs_f64() * 1000.
+        );
+
+        // Drop the character onto whatever is under the middle of the scene
+        if world.triangle_count() == 0 {
+            eprintln!("The scene doesn't have any triangles");
+            std::process::exit(1);
+        }
+        let above = respawn_point(&world);
+        let spawn = world
+            .raycast(above, -Vector3::unit_y(), f32::INFINITY)
+            .map_or(above, |hit| hit.point + Vector3::unit_y() * 0.1);
+        let params = CharacterParams::default();
+        let character = CharacterController::new(spawn, params);
+
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "Walk with WASD, run with shift, and jump with space. Press V for a third person view, \
+             C to show the capsule, its contacts, and what the middle of the view points at, and \
+             T to print how long the character's steps take."
+        );
+
+        Self {
+            pieces: pieces
+                .iter()
+                .map(|piece| (Mesh::new(gl, &piece.data), piece.transform, piece.color))
+                .collect(),
+            world,
+            previous_position: character.position,
+            character,
+            timestep: FixedTimestep::new(STEP),
+            step_time: Duration::ZERO,
+            steps: 0,
+            camera: FlyCamera::new(spawn, 0., 0.),
+            third_person: false,
+            show_debug: false,
+            debug_draw: DebugDraw::new(gl),
+            capsule: Mesh::new(gl, &primitives::uv_sphere(params.radius, 16, 8)),
+            program,
+        }
+    }
+
+    /// Move the character by one physics step with the movement keys that are held
+    fn step(&mut self, movement: Vector3<f32>, run: bool, jump: bool) {
+        let character = &mut self.character;
+
+        // Ease the horizontal velocity towards the walking speed
+        let speed = if run { WALK_SPEED * 2. } else { WALK_SPEED };
+        let target = movement * speed;
+        let blend = 1. - (-ACCELERATION * STEP).exp();
+        character.velocity.x += (target.x - character.velocity.x) * blend;
+        character.velocity.z += (target.z - character.velocity.z) * blend;
+        if jump && character.grounded {
+            character.velocity.y = JUMP_SPEED;
+        }
+
+        self.previous_position = character.position;
+        let start = Instant::now();
+        character.step(STEP, &self.world);
+        self.step_time += start.elapsed();
+        self.steps += 1;
+
+        // Start over if we fall off of the edge
+        let bottom = self.world.aabb().map_or(0., |bounds| bounds.min[1]);
+        if character.position.y < bottom - 20. {
+            character.position = respawn_point(&self.world);
+            character.velocity = Vector3::zero();
+            self.previous_position = character.position;
+        }
+    }
+}
+
+/// Where the character starts over after falling off of the scene, above its middle
+fn respawn_point(world: &TriangleBvh) -> Point3<f32> {
+    let bounds = world.aabb().unwrap();
+    Point3::new(bounds.center().x, bounds.max[1] + 1., bounds.center().z)
+}
+
+/// The direction the camera is looking, flattened onto the ground
+fn horizontal_forward(camera: &FlyCamera) -> Vector3<f32> {
+    let yaw = camera.yaw.to_radians();
+    Vector3::new(yaw.sin(), 0., -yaw.cos())
+}
+
+impl RenderHandler for Walk {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        Self::new(gl, ctx, build_scene())
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if ctx.input.was_key_pressed(VirtualKeyCode::V) {
+            self.third_person = !self.third_person;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::C) {
+            self.show_debug = !self.show_debug;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::T) && self.steps > 0 {
+            eprintln!(
+                "{} steps took {:.1} microseconds on average",
+                self.steps,
+                self.step_time.as_secs_f64() * 1e6 / self.steps as f64
+            );
+            self.step_time = Duration::ZERO;
+            self.steps = 0;
+        }
+
+        // Walk along the ground in the direction the camera is looking, ignoring Q and E
+        self.camera.update_look(ctx);
+        let mut movement = self
+            .camera
+            .movement_input(ctx, horizontal_forward(&self.camera));
+        movement.y = 0.;
+        if movement.magnitude2() > 0. {
+            movement = movement.normalize();
+        }
+        let run = ctx.input.modifiers().shift;
+        let jump = ctx.input.is_key_pressed(VirtualKeyCode::Space);
+
+        // Step the physics at a fixed rate, and only while the animation is running
+        let delta = if ctx.timing.is_paused() {
+            0.
+        } else {
+            ctx.timing.delta()
+        };
+        for _ in 0..self.timestep.advance(delta) {
+            self.step(movement, run, jump);
+        }
+
+        // Put the camera in the character's eyes, or behind it, between the last two steps
+        let alpha = self.timestep.alpha();
+        let feet =
+            self.previous_position + (self.character.position - self.previous_position) * alpha;
+        let capsule = self.character.capsule_at(feet);
+        let eyes = capsule.b + Vector3::unit_y() * (capsule.radius - EYE_DEPTH);
+        self.camera.position = if self.third_person {
+            eyes - self.camera.forward() * THIRD_PERSON_DISTANCE
+        } else {
+            eyes
+        };
+
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let view_projection =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+
+        self.program.bind(gl);
+        self.program
+            .set_uniform(gl, "viewProjection", view_projection);
+        for (mesh, transform, color) in &self.pieces {
+            self.program.set_uniform(gl, "model", *transform);
+            self.program.set_uniform(gl, "color", *color);
+            mesh.draw(gl);
+        }
+        if self.third_person {
+            // The capsule as a sphere at each end of its segment
+            self.program.set_uniform(gl, "color", CHARACTER_COLOR);
+            for end in [capsule.a, capsule.b] {
+                let model = Matrix4::from_translation(end.to_vec());
+                self.program.set_uniform(gl, "model", model);
+                self.capsule.draw(gl);
+            }
+        }
+
+        if self.show_debug {
+            let grounded = if self.character.grounded {
+                Color::rgb(0.2, 1., 0.3)
+            } else {
+                Color::rgb(1., 0.6, 0.1)
+            };
+            self.debug_draw.capsule(&capsule, grounded);
+            for contact in &self.character.contacts {
+                self.debug_draw.contact(contact, Color::rgb(1., 0.1, 0.1));
+            }
+            if let Some(normal) = self.character.ground_normal {
+                self.debug_draw
+                    .line(feet, feet + normal, Color::rgb(0.2, 1., 0.3));
+            }
+
+            // Pick what the middle of the view points at through the same hierarchy
+            if let Some(hit) =
+                self.world
+                    .raycast(self.camera.position, self.camera.forward(), PICK_DISTANCE)
+            {
+                let color = Color::rgb(0.2, 0.6, 1.);
+                self.debug_draw.cross(hit.point, 0.3, color);
+                self.debug_draw
+                    .line(hit.point, hit.point + hit.normal, color);
+                let [a, b, c] = self.world.triangle(hit.triangle);
+                for (from, to) in [(a, b), (b, c), (c, a)] {
+                    self.debug_draw.line(from, to, color);
+                }
+            }
+
+            // Draw the lines through the walls
+            unsafe { gl.disable(glow::DEPTH_TEST) };
+            self.debug_draw.draw(gl, &ctx.arena, view_projection);
+            unsafe { gl.enable(glow::DEPTH_TEST) };
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        for (mesh, _, _) in &self.pieces {
+            mesh.delete(gl);
+        }
+        self.debug_draw.delete(gl);
+        self.capsule.delete(gl);
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    let args = DemoArgs::parse_with(FLAGS);
+    let scale = match args.value("scale") {
+        Some(scale) => scale.parse::<f32>().unwrap_or_else(|_| {
+            eprintln!("The scale should be a number, not {}", scale);
+            std::process::exit(1);
+        }),
+        None => 1.,
+    };
+    let pieces = args
+        .value("model")
+        .map(|path| load_scene(Path::new(path), scale));
+    let window_config = args.window_config();
+    with_windows_and_config(
+        args.config,
+        vec![(
+            window_config,
+            Box::new(move |gl, ctx| {
+                let pieces = pieces.clone().unwrap_or_else(build_scene);
+                Box::new(Walk::new(gl, ctx, pieces))
+            }),
+        )],
+    );
+}
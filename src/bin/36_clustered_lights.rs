@@ -0,0 +1,328 @@
+use cgmath::{Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    clustered_lights::{ClusterLight, ClusterStorage, ClusteredLights},
+    color::Color,
+    features::Features,
+    mesh::Mesh,
+    primitives,
+    shader::ShaderProgram,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("point_lights/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("clustered_lights/fragment.glsl");
+
+/// The numbers of lights that N cycles through, starting from the third
+const LIGHT_COUNTS: [usize; 5] = [100, 250, 1000, 2000, 4000];
+/// How far the floor reaches from the middle of the scene along each axis
+const HALF_SIZE: f32 = 30.;
+/// How far apart the pillars are
+const PILLAR_SPACING: f32 = 7.5;
+
+/// A light that circles around a point of its own, bobbing up and down as it goes
+struct MovingLight {
+    center: Point3<f32>,
+    orbit_radius: f32,
+    /// How fast it goes around its circle in radians per second, which is negative for the
+    /// lights that go the other way
+    speed: f32,
+    /// Where on its circle it starts, in radians
+    phase: f32,
+    color: [f32; 3],
+    radius: f32,
+}
+
+impl MovingLight {
+    fn position(&self, time: f32) -> Point3<f32> {
+        let angle = self.phase + time * self.speed;
+        self.center
+            + Vector3::new(
+                angle.cos() * self.orbit_radius,
+                (angle * 2.).sin() * 0.4,
+                angle.sin() * self.orbit_radius,
+            )
+    }
+}
+
+/// Something in the scene, drawn with one of the meshes
+struct Object {
+    mesh: usize,
+    model: Matrix4<f32>,
+    albedo: [f32; 3],
+}
+
+struct ClusteredLightsDemo {
+    program: ShaderProgram,
+    clustered: ClusteredLights,
+    /// A cube, a sphere, and a small sphere for the lights
+    meshes: Vec<Mesh>,
+    objects: Vec<Object>,
+    /// Every light that N can show, of which the first `LIGHT_COUNTS[light_count]` are shown
+    moving_lights: Vec<MovingLight>,
+    light_count: usize,
+    /// The shown lights where they are this frame, which is reused between frames
+    lights: Vec<ClusterLight>,
+    /// Whether or not the lights move ( toggled with space )
+    move_lights: bool,
+    /// How far the lights have moved along, in seconds
+    light_time: f32,
+    camera: FlyCamera,
+}
+
+impl ClusteredLightsDemo {
+    /// Set up the clustered lights with the lists in the given kind of buffer, if the context
+    /// has it, and compile the program for them
+    fn set_storage(&mut self, gl: &mut glow::Context, ctx: &AppContext, storage: ClusterStorage) {
+        let (clustered, program) = clustered_program(gl, ctx, storage);
+        let heatmap = self.clustered.heatmap();
+        self.clustered.delete(gl);
+        self.program.delete(gl);
+        self.clustered = clustered;
+        self.clustered.set_heatmap(heatmap);
+        self.program = program;
+    }
+}
+
+/// The clustered lights with the lists in the given kind of buffer, or in uniform buffers if the
+/// context doesn't have storage buffers, and the program that reads them
+fn clustered_program(
+    gl: &mut glow::Context,
+    ctx: &AppContext,
+    storage: ClusterStorage,
+) -> (ClusteredLights, ShaderProgram) {
+    // Pretending that storage buffers are missing is what makes it fall back to uniform buffers
+    let features = match storage {
+        ClusterStorage::StorageBuffers => ctx.features().clone(),
+        ClusterStorage::UniformBuffers => Features::default(),
+    };
+    let clustered = ClusteredLights::new(gl, &features, ctx.config().cluster_grid);
+    let program = ShaderProgram::new(
+        gl,
+        VERTEX_SHADER_SRC,
+        &clustered.shader_source(FRAGMENT_SHADER_SRC),
+    )
+    .unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    });
+    (clustered, program)
+}
+
+impl RenderHandler for ClusteredLightsDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.01, 0.01, 0.015, 1.].into());
+
+        let (clustered, program) = clustered_program(gl, ctx, ClusterStorage::StorageBuffers);
+        let meshes = vec![
+            Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            Mesh::new(gl, &primitives::uv_sphere(1., 32, 16)),
+            Mesh::new(gl, &primitives::uv_sphere(1., 8, 6)),
+        ];
+
+        // A big floor with a grid of pillars on it, and balls scattered between them
+        let mut rng = SmallRng::seed_from_u64(36);
+        let mut objects = vec![Object {
+            mesh: 0,
+            model: Matrix4::from_translation(Vector3::new(0., -0.1, 0.))
+                * Matrix4::from_nonuniform_scale(HALF_SIZE * 2., 0.2, HALF_SIZE * 2.),
+            albedo: [0.6, 0.6, 0.6],
+        }];
+        let pillars = (HALF_SIZE / PILLAR_SPACING) as i32;
+        for x in -pillars..=pillars {
+            for z in -pillars..=pillars {
+                objects.push(Object {
+                    mesh: 0,
+                    model: Matrix4::from_translation(Vector3::new(
+                        x as f32 * PILLAR_SPACING,
+                        2.,
+                        z as f32 * PILLAR_SPACING,
+                    )) * Matrix4::from_nonuniform_scale(0.8, 4., 0.8),
+                    albedo: [0.8, 0.75, 0.7],
+                });
+            }
+        }
+        for _ in 0..60 {
+            let radius = rng.gen_range(0.4, 1.2);
+            objects.push(Object {
+                mesh: 1,
+                model: Matrix4::from_translation(Vector3::new(
+                    rng.gen_range(-HALF_SIZE, HALF_SIZE),
+                    radius,
+                    rng.gen_range(-HALF_SIZE, HALF_SIZE),
+                )) * Matrix4::from_scale(radius),
+                albedo: [0.7, 0.7, 0.75],
+            });
+        }
+
+        // Small lights of every color, low over the floor
+        let most_lights = LIGHT_COUNTS[LIGHT_COUNTS.len() - 1];
+        let moving_lights = (0..most_lights)
+            .map(|_| {
+                let color = Color::from_hsv(rng.gen_range(0., 360.), 0.8, 1.);
+                let brightness = rng.gen_range(1.5, 3.);
+                MovingLight {
+                    center: Point3::new(
+                        rng.gen_range(-HALF_SIZE, HALF_SIZE),
+                        rng.gen_range(0.4, 3.),
+                        rng.gen_range(-HALF_SIZE, HALF_SIZE),
+                    ),
+                    orbit_radius: rng.gen_range(0.5, 4.),
+                    speed: rng.gen_range(0.3, 1.2) * if rng.gen() { 1. } else { -1. },
+                    phase: rng.gen_range(0., std::f32::consts::PI * 2.),
+                    color: [
+                        color.r * brightness,
+                        color.g * brightness,
+                        color.b * brightness,
+                    ],
+                    radius: rng.gen_range(2., 5.),
+                }
+            })
+            .collect();
+
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "Press H to show the heatmap of lights per cluster, N to change the number of lights, \
+             space to stop the lights, U to switch between storage and uniform buffers, and T to \
+             print how the lights are spread over the clusters."
+        );
+        eprintln!(
+            "{} clusters, with the lists in {}",
+            clustered.grid(),
+            clustered.storage().name()
+        );
+
+        let mut camera = FlyCamera::new(Point3::new(0., 8., HALF_SIZE + 6.), 0., -20.);
+        camera.move_speed = 8.;
+        camera.far = 200.;
+
+        Self {
+            program,
+            clustered,
+            meshes,
+            objects,
+            moving_lights,
+            light_count: 2,
+            lights: Vec::new(),
+            move_lights: true,
+            light_time: 0.,
+            camera,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::H) {
+            self.clustered.set_heatmap(!self.clustered.heatmap());
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::Space) {
+            self.move_lights = !self.move_lights;
+        }
+        let mut report = ctx.input.was_key_pressed(VirtualKeyCode::T);
+        if ctx.input.was_key_pressed(VirtualKeyCode::N) {
+            self.light_count = (self.light_count + 1) % LIGHT_COUNTS.len();
+            report = true;
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::U) {
+            let storage = match self.clustered.storage() {
+                ClusterStorage::StorageBuffers => ClusterStorage::UniformBuffers,
+                ClusterStorage::UniformBuffers => ClusterStorage::StorageBuffers,
+            };
+            self.set_storage(gl, ctx, storage);
+            if self.clustered.storage() != storage {
+                eprintln!("This context doesn't have storage buffers");
+            }
+            report = true;
+        }
+
+        if self.move_lights {
+            self.light_time += ctx.timing.delta();
+        }
+        let time = self.light_time;
+        self.lights.clear();
+        self.lights.extend(
+            self.moving_lights[..LIGHT_COUNTS[self.light_count]]
+                .iter()
+                .map(|light| ClusterLight {
+                    position: light.position(time),
+                    color: light.color,
+                    radius: light.radius,
+                }),
+        );
+
+        // Spread the lights over the clusters of this frame's view
+        let aspect_ratio = Rect::from_window_size(ctx.window_size()).aspect_ratio();
+        let view = self.camera.view_matrix();
+        let projection = self.camera.projection_matrix(aspect_ratio);
+        let (near, far, _) = self.camera.clip_planes();
+        self.clustered
+            .update(gl, &self.lights, view, projection, near, far);
+
+        let program = &mut self.program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", projection * view);
+        let eye = self.camera.position;
+        program.set_uniform(gl, "viewPosition", Vector3::new(eye.x, eye.y, eye.z));
+        self.clustered.bind(gl, program);
+
+        program.set_uniform(gl, "emissive", 0);
+        for object in &self.objects {
+            program.set_uniform(gl, "model", object.model);
+            program.set_uniform(gl, "albedo", object.albedo);
+            self.meshes[object.mesh].draw(gl);
+        }
+
+        // Show where the lights are with small glowing balls
+        program.set_uniform(gl, "emissive", 1);
+        for light in &self.lights {
+            let position = light.position;
+            program.set_uniform(
+                gl,
+                "model",
+                Matrix4::from_translation(Vector3::new(position.x, position.y, position.z))
+                    * Matrix4::from_scale(0.06),
+            );
+            let brightest = light.color.iter().cloned().fold(1., f32::max);
+            program.set_uniform(gl, "albedo", light.color.map(|c| c / brightest));
+            self.meshes[2].draw(gl);
+        }
+        unsafe { gl.bind_vertex_array(None) };
+
+        if report {
+            let stats = self.clustered.stats();
+            eprintln!(
+                "{} lights with {} in view, in {} list entries over {} of the {} clusters in {}: \
+                 {:.1} lights per lit cluster on average and {} at most, {} dropped. Assigned in \
+                 {:.2} ms, frame {:.2} ms",
+                stats.lights,
+                stats.visible_lights,
+                stats.assignments,
+                stats.occupied_clusters,
+                self.clustered.grid().cluster_count(),
+                self.clustered.storage().name(),
+                stats.average_cluster_lights(),
+                stats.max_cluster_lights,
+                stats.dropped,
+                stats.assign_time.as_secs_f64() * 1000.,
+                ctx.timing.average_delta() as f64 * 1000.
+            );
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.clustered.delete(gl);
+        for mesh in &self.meshes {
+            mesh.delete(gl);
+        }
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<ClusteredLightsDemo>();
+}
@@ -0,0 +1,164 @@
+use cgmath::{Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera, depth_mode::DepthMode, mesh::Mesh, primitives, shader::ShaderProgram,
+    viewport::Rect, AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("reverse_z/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("reverse_z/fragment.glsl");
+
+/// How far away the pairs of walls are along the view
+const WALL_DISTANCES: [f32; 6] = [50., 250., 1000., 3000., 8000., 15000.];
+/// How far the front wall of each pair is in front of the back one, in world units
+const WALL_GAP: f32 = 1.;
+/// How far the stripes on the ground are above it, in world units
+const STRIPE_HEIGHT: f32 = 0.05;
+/// How far the ground and the stripes reach ahead of the camera's start
+const GROUND_LENGTH: f32 = 20000.;
+
+/// Something in the scene, drawn with one of the meshes
+struct Object {
+    mesh: usize,
+    model: Matrix4<f32>,
+    albedo: [f32; 3],
+}
+
+struct ReverseZDemo {
+    program: ShaderProgram,
+    /// A cube and a plane
+    meshes: Vec<Mesh>,
+    objects: Vec<Object>,
+    camera: FlyCamera,
+}
+
+/// Print the depth mode, and why reversing it won't help much when the context can't clip depth
+/// from 0 to 1
+fn report_depth_mode(ctx: &AppContext) {
+    let mode = ctx.render_settings.depth_mode;
+    eprintln!("Depth mode: {}", mode);
+    if mode == DepthMode::ReverseZ && ctx.features().clip_control.is_none() {
+        eprintln!(
+            "This context doesn't have glClipControl, so the reversed depth still goes through \
+             -1 to 1 and the distant walls still fight"
+        );
+    }
+}
+
+impl RenderHandler for ReverseZDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.55, 0.7, 0.9, 1.].into());
+
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        let meshes = vec![
+            Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            Mesh::new(gl, &primitives::plane(1., 1., 1.)),
+        ];
+
+        // Ground that reaches far ahead, with stripes painted just above it
+        let mut objects = vec![Object {
+            mesh: 1,
+            model: Matrix4::from_translation(Vector3::new(0., 0., -GROUND_LENGTH / 2.))
+                * Matrix4::from_nonuniform_scale(GROUND_LENGTH, 1., GROUND_LENGTH),
+            albedo: [0.35, 0.45, 0.3],
+        }];
+        for x in &[-30., 30.] {
+            objects.push(Object {
+                mesh: 1,
+                model: Matrix4::from_translation(Vector3::new(
+                    *x,
+                    STRIPE_HEIGHT,
+                    -GROUND_LENGTH / 2.,
+                )) * Matrix4::from_nonuniform_scale(4., 1., GROUND_LENGTH),
+                albedo: [0.9, 0.85, 0.4],
+            });
+        }
+
+        // Pairs of red and white walls, scaled with their distance so that they all look about as
+        // big, with the white one just in front of the red one
+        for (i, distance) in WALL_DISTANCES.iter().enumerate() {
+            let size = distance * 0.15;
+            let x = (i as f32 - (WALL_DISTANCES.len() - 1) as f32 / 2.) * size * 1.2;
+            let back = Vector3::new(x, size / 2., -distance);
+            objects.push(Object {
+                mesh: 0,
+                model: Matrix4::from_translation(back)
+                    * Matrix4::from_nonuniform_scale(size, size, 0.1),
+                albedo: [0.85, 0.15, 0.1],
+            });
+            // Smaller so that a red border is left around it
+            objects.push(Object {
+                mesh: 0,
+                model: Matrix4::from_translation(back + Vector3::new(0., 0., WALL_GAP))
+                    * Matrix4::from_nonuniform_scale(size * 0.8, size * 0.8, 0.1),
+                albedo: [0.9, 0.9, 0.9],
+            });
+        }
+
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "Press R ( or F5 ) to switch between standard and reverse-Z depth. The white walls are \
+             {} unit in front of the red ones and the stripes {} above the ground, which standard \
+             depth can't tell apart in the distance. F8 shows the depth buffer.",
+            WALL_GAP, STRIPE_HEIGHT
+        );
+        report_depth_mode(ctx);
+
+        let mut camera = FlyCamera::new(Point3::new(0., 10., 20.), 0., -2.);
+        camera.move_speed = 200.;
+        camera.near = 0.1;
+        camera.far = GROUND_LENGTH;
+
+        Self {
+            program,
+            meshes,
+            objects,
+            camera,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::R) {
+            ctx.render_settings.depth_mode = ctx.render_settings.depth_mode.next();
+            report_depth_mode(ctx);
+        }
+
+        // Let the depth view ( F8 ) turn the depth back into distances
+        let (near, far, orthographic) = self.camera.clip_planes();
+        ctx.render_settings
+            .depth_view
+            .set_clip_planes(near, far, orthographic);
+
+        let aspect_ratio = Rect::from_window_size(ctx.render_size()).aspect_ratio();
+        let view_projection =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+
+        let program = &mut self.program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        for object in &self.objects {
+            program.set_uniform(gl, "model", object.model);
+            program.set_uniform(gl, "albedo", object.albedo);
+            self.meshes[object.mesh].draw(gl);
+        }
+        unsafe { gl.bind_vertex_array(None) };
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        for mesh in &self.meshes {
+            mesh.delete(gl);
+        }
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<ReverseZDemo>();
+}
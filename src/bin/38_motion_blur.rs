@@ -0,0 +1,289 @@
+use std::{cell::Cell, rc::Rc};
+
+use cgmath::{Deg, InnerSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    draw_list::{self, DrawCommand, DrawList, DrawMaterial, DrawMesh, TransformHistory},
+    mesh::Mesh,
+    motion_blur::{MotionBlurParams, MotionBlurPass, VelocityTarget, MAX_SAMPLES, VELOCITY_CHUNK},
+    per_draw::{PerDrawBuffer, PER_DRAW_CHUNK},
+    primitives,
+    shader::{self, ShaderProgram},
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("motion_blur/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("motion_blur/fragment.glsl");
+
+const CLEAR_COLOR: [f32; 4] = [0.05, 0.05, 0.08, 1.];
+
+/// How fast the camera orbits the cubes, in degrees per second, and how far out and up it is
+const ORBIT_SPEED: f32 = 90.;
+const ORBIT_RADIUS: f32 = 14.;
+const ORBIT_HEIGHT: f32 = 5.;
+
+/// The places the teleporting cube jumps between, further apart than
+/// `TransformHistory::teleport_distance`, and how long it stays at each, in seconds
+const TELEPORT_SPOTS: [[f32; 3]; 4] = [[-6., 1., -6.], [6., 1., -6.], [6., 1., 6.], [-6., 1., 6.]];
+const TELEPORT_INTERVAL: f32 = 1.5;
+
+/// A cube, which spins around its axis and may orbit the middle of the scene
+struct Cube {
+    position: Vector3<f32>,
+    axis: Vector3<f32>,
+    /// How fast the cube spins and orbits, in degrees per second
+    spin_speed: f32,
+    orbit_speed: f32,
+    scale: Vector3<f32>,
+    color: [f32; 4],
+}
+
+impl Cube {
+    fn model(&self, time: f32) -> Matrix4<f32> {
+        Matrix4::from_angle_y(Deg(time * self.orbit_speed))
+            * Matrix4::from_translation(self.position)
+            * Matrix4::from_axis_angle(self.axis, Deg(time * self.spin_speed))
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// The cubes of the scene: a ring of spinning cubes, a few fast ones orbiting inside it, and the
+/// ground, which is a flat cube that doesn't move
+fn scene_cubes() -> Vec<Cube> {
+    let mut cubes = vec![Cube {
+        position: Vector3::new(0., -0.1, 0.),
+        axis: Vector3::unit_y(),
+        spin_speed: 0.,
+        orbit_speed: 0.,
+        scale: Vector3::new(24., 0.2, 24.),
+        color: [0.35, 0.4, 0.35, 1.],
+    }];
+    let ring = 12;
+    for i in 0..ring {
+        let angle = (i as f32 / ring as f32 * 360.).to_radians();
+        let hue = i as f32 / ring as f32;
+        cubes.push(Cube {
+            position: Vector3::new(angle.cos() * 8., 1., angle.sin() * 8.),
+            axis: Vector3::new(angle.sin(), 1., angle.cos()).normalize(),
+            spin_speed: 90. + 30. * i as f32,
+            orbit_speed: 0.,
+            scale: Vector3::new(1., 1., 1.),
+            color: [0.4 + 0.6 * hue, 0.9 - 0.5 * hue, 0.5, 1.],
+        });
+    }
+    for i in 0..3 {
+        cubes.push(Cube {
+            position: Vector3::new(2. + i as f32 * 1.5, 0.6 + i as f32, 0.),
+            axis: Vector3::unit_x(),
+            spin_speed: 360.,
+            orbit_speed: 180. + 120. * i as f32,
+            scale: Vector3::new(0.6, 0.6, 0.6),
+            color: [1., 0.85, 0.3, 1.],
+        });
+    }
+    cubes
+}
+
+struct MotionBlurDemo {
+    program: ShaderProgram,
+    per_draw: PerDrawBuffer,
+    cube: Mesh,
+    cubes: Vec<Cube>,
+    draw_list: DrawList,
+    material: DrawMaterial,
+    cube_mesh: DrawMesh,
+    /// The model matrices of last frame, by the index of the cube, with the teleporting cube
+    /// after the others
+    history: TransformHistory,
+    target: VelocityTarget,
+    blur: MotionBlurPass,
+    /// The settings of the blur, and whether it is on, shared with the console command
+    params: Rc<Cell<MotionBlurParams>>,
+    enabled: Rc<Cell<bool>>,
+    camera: FlyCamera,
+    /// Whether the camera orbits the cubes by itself ( toggled with Space )
+    orbiting: bool,
+}
+
+impl RenderHandler for MotionBlurDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        let mut program = ShaderProgram::new(
+            gl,
+            &shader::include_chunk(VERTEX_SHADER_SRC, PER_DRAW_CHUNK),
+            &shader::include_chunk(FRAGMENT_SHADER_SRC, VELOCITY_CHUNK),
+        )
+        .unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        });
+        let material = DrawMaterial::new(draw_list::prepare_program(gl, &mut program).unwrap());
+        let cube = Mesh::new(gl, &primitives::cuboid(1., 1., 1.));
+
+        let params = Rc::new(Cell::new(MotionBlurParams::default()));
+        let enabled = Rc::new(Cell::new(true));
+        let (command_params, command_enabled) = (params.clone(), enabled.clone());
+        ctx.console.register(
+            "motion_blur",
+            "Change the motion blur: motion_blur [on|off | intensity I | samples N | radius R]",
+            move |args, _| {
+                let mut params = command_params.get();
+                match args {
+                    [] => {}
+                    ["on"] => command_enabled.set(true),
+                    ["off"] => command_enabled.set(false),
+                    ["intensity", intensity] => {
+                        params.intensity = intensity.parse().map_err(|_| "Invalid intensity")?;
+                    }
+                    ["samples", samples] => {
+                        let samples = samples.parse::<u32>().map_err(|error| error.to_string())?;
+                        params.samples = samples.clamp(1, MAX_SAMPLES);
+                    }
+                    ["radius", radius] => {
+                        params.max_radius = radius.parse().map_err(|_| "Invalid radius")?;
+                    }
+                    _ => return Err("Unknown motion blur setting, see `help`".into()),
+                }
+                command_params.set(params);
+                Ok(format!(
+                    "Motion blur {}, {:?}",
+                    if command_enabled.get() { "on" } else { "off" },
+                    params
+                ))
+            },
+        );
+        eprintln!(
+            "The camera orbits a ring of spinning cubes, with faster cubes orbiting inside it and \
+             one that teleports between the corners. Press B to turn the motion blur on and off, \
+             and Space to stop orbiting and fly around. Change the blur with the `motion_blur` \
+             console command."
+        );
+
+        Self {
+            program,
+            per_draw: PerDrawBuffer::new(gl, ctx.features()),
+            cube_mesh: DrawMesh::new(&cube).unwrap(),
+            cube,
+            cubes: scene_cubes(),
+            draw_list: DrawList::new(),
+            material,
+            history: TransformHistory::new(),
+            target: VelocityTarget::new(gl, ctx.render_size()),
+            blur: MotionBlurPass::new(gl, params.get()),
+            params,
+            enabled,
+            camera: FlyCamera::new(Point3::new(0., ORBIT_HEIGHT, ORBIT_RADIUS), 0., 0.),
+            orbiting: true,
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if ctx.input.was_key_pressed(VirtualKeyCode::B) {
+            self.enabled.set(!self.enabled.get());
+            eprintln!(
+                "Motion blur: {}",
+                if self.enabled.get() { "on" } else { "off" }
+            );
+        }
+        if ctx.input.was_key_pressed(VirtualKeyCode::Space) {
+            self.orbiting = !self.orbiting;
+            // Going back to the orbit jumps the camera, which shouldn't blur the frame
+            self.camera.forget_previous_frame();
+        }
+        let time = ctx.timing.time();
+        if self.orbiting {
+            let angle = (time * ORBIT_SPEED).to_radians();
+            self.camera.position = Point3::new(
+                angle.sin() * ORBIT_RADIUS,
+                ORBIT_HEIGHT,
+                angle.cos() * ORBIT_RADIUS,
+            );
+            self.camera
+                .look_in_direction(Point3::new(0., 0., 0.) - self.camera.position);
+        } else {
+            self.camera.update(ctx);
+        }
+        self.per_draw.begin_frame(gl);
+
+        // Make the target again when the window changes size
+        let size = ctx.render_size();
+        if self.target.size != size {
+            self.target.delete(gl);
+            self.target = VelocityTarget::new(gl, size);
+        }
+
+        // Record the cubes with where they were last frame. The teleporting cube jumps further
+        // than the history's teleport distance, so it doesn't blur across the scene when it does.
+        let (material, mesh) = (self.material, self.cube_mesh);
+        self.draw_list.clear();
+        for (i, cube) in self.cubes.iter().enumerate() {
+            let data = self.history.data(i as u64, cube.model(time));
+            self.draw_list.push(DrawCommand::new(
+                0,
+                material,
+                mesh,
+                data.with_color(cube.color),
+            ));
+        }
+        let spot = TELEPORT_SPOTS[(time / TELEPORT_INTERVAL) as usize % TELEPORT_SPOTS.len()];
+        let model = Matrix4::from_translation(Vector3::from(spot))
+            * Matrix4::from_angle_y(Deg(time * 120.));
+        let data = self.history.data(self.cubes.len() as u64, model);
+        self.draw_list.push(DrawCommand::new(
+            0,
+            material,
+            mesh,
+            data.with_color([0.3, 0.7, 1., 1.]),
+        ));
+        self.history.end_frame();
+        self.draw_list.sort();
+
+        // Draw the colors and velocities, with last frame's view-projection, which is this
+        // frame's on the first frame so that nothing moves
+        let aspect_ratio = Rect::from_window_size(size).aspect_ratio();
+        let view_projection: Matrix4<f32> =
+            self.camera.projection_matrix(aspect_ratio) * self.camera.view_matrix();
+        let previous_view_projection = self
+            .camera
+            .previous_view_projection()
+            .unwrap_or(view_projection);
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.target.framebuffer));
+            gl.viewport(0, 0, size.0 as i32, size.1 as i32);
+            self.target.clear(gl, CLEAR_COLOR);
+            gl.enable(glow::DEPTH_TEST);
+        }
+        let program = &mut self.program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        program.set_uniform(gl, "previousViewProjection", previous_view_projection);
+        program.set_uniform(gl, "lightDirection", Vector3::new(0.4, 1., 0.6).normalize());
+        self.draw_list.submit(gl, &mut self.per_draw, 0);
+        self.camera.end_frame(view_projection);
+
+        // Blur it into the window, which only copies it while the blur is off
+        self.blur.params = self.params.get();
+        if !self.enabled.get() {
+            self.blur.params.intensity = 0.;
+        }
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, ctx.surface_framebuffer());
+            gl.viewport(0, 0, size.0 as i32, size.1 as i32);
+        }
+        self.blur.draw(gl, &self.target);
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.blur.delete(gl);
+        self.target.delete(gl);
+        self.per_draw.delete(gl);
+        self.cube.delete(gl);
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<MotionBlurDemo>();
+}
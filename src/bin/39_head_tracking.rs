@@ -0,0 +1,148 @@
+use cgmath::{Deg, EuclideanSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use me_learning_opengl::{
+    camera::FlyCamera,
+    frustum::Aabb,
+    mesh::Mesh,
+    pose::{MousePose, PoseProvider},
+    primitives,
+    shader::ShaderProgram,
+    stereo::{self, StereoMode},
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("anti_aliasing/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("anti_aliasing/fragment.glsl");
+
+/// How many cubes go around each ring, the heights of the rings, and how far out they are
+const RING_CUBES: usize = 16;
+const RING_HEIGHTS: [f32; 3] = [-1.5, 0., 1.5];
+const RING_RADIUS: f32 = 6.;
+/// How big the cubes are, and how fast they turn in degrees per second
+const CUBE_SIZE: f32 = 0.8;
+const TURN_SPEED: f32 = 30.;
+
+/// A cube of the rings around the viewer
+struct Cube {
+    position: Point3<f32>,
+    color: [f32; 3],
+}
+
+/// The rings of cubes all the way around the viewer, so turning the head always finds more
+fn ring_cubes() -> Vec<Cube> {
+    let mut cubes = Vec::with_capacity(RING_CUBES * RING_HEIGHTS.len());
+    for (ring, &height) in RING_HEIGHTS.iter().enumerate() {
+        for i in 0..RING_CUBES {
+            // Each ring is turned half a cube from the one below, so the cubes don't line up
+            let turn = (i as f32 + ring as f32 * 0.5) / RING_CUBES as f32;
+            let angle = (turn * 360.).to_radians();
+            cubes.push(Cube {
+                position: Point3::new(
+                    angle.sin() * RING_RADIUS,
+                    height,
+                    -angle.cos() * RING_RADIUS,
+                ),
+                color: [0.4 + 0.5 * turn, 0.85 - 0.4 * turn, 0.3 + 0.2 * ring as f32],
+            });
+        }
+    }
+    cubes
+}
+
+struct HeadTrackingDemo {
+    cube: Mesh,
+    program: ShaderProgram,
+    camera: FlyCamera,
+    pose: MousePose,
+    cubes: Vec<Cube>,
+    /// How many cubes each eye drew last frame, after culling them against its own frustum
+    drawn: Vec<(&'static str, usize)>,
+}
+
+impl RenderHandler for HeadTrackingDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.clear_color = Some([0.1, 0.1, 0.14, 1.].into());
+        ctx.render_settings.stereo = StereoMode::side_by_side();
+
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC)
+            .unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            });
+        unsafe { gl.enable(glow::DEPTH_TEST) };
+
+        eprintln!(
+            "Rings of cubes around the viewer, drawn side by side for cross-eyed viewing. Move the \
+             mouse to turn your head, with the middle of the window looking straight ahead, and \
+             WASD to walk. Press C to show how many cubes each eye drew, and F10 to turn the \
+             stereo off."
+        );
+
+        Self {
+            cube: Mesh::new(gl, &primitives::cuboid(1., 1., 1.)),
+            program,
+            camera: FlyCamera::new(Point3::new(0., 0., 0.), 0., 0.),
+            pose: MousePose::default(),
+            cubes: ring_cubes(),
+            drawn: Vec::new(),
+        }
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        if ctx.input.was_key_pressed(VirtualKeyCode::C) {
+            let head = self.pose.head();
+            eprintln!(
+                "The head is turned {:.1} degrees right and {:.1} up",
+                head.yaw, head.pitch
+            );
+            for (eye, count) in &self.drawn {
+                eprintln!("The {} drew {} of {} cubes", eye, count, self.cubes.len());
+            }
+        }
+
+        // Everything else is done, so the head is sampled as late as it can be. Each eye culls
+        // the cubes against its own frustum, since turning the head shows each eye a different
+        // part of the rings.
+        let time = ctx.timing.time();
+        let Self {
+            cube: mesh,
+            program,
+            camera,
+            pose,
+            cubes,
+            drawn,
+        } = self;
+        drawn.clear();
+        let bounds = Vector3::new(CUBE_SIZE, CUBE_SIZE, CUBE_SIZE) * 0.75;
+        stereo::render_eyes_with_pose(gl, ctx, pose, camera, |gl, view, matrices| {
+            let frustum = matrices.frustum();
+            program.bind(gl);
+            program.set_uniform(gl, "viewProjection", matrices.view_projection());
+            let mut count = 0;
+            for (i, cube) in cubes.iter().enumerate() {
+                if !frustum.intersects_aabb(&Aabb::from_center(cube.position, bounds)) {
+                    continue;
+                }
+                let model = Matrix4::from_translation(cube.position.to_vec())
+                    * Matrix4::from_angle_y(Deg(time * TURN_SPEED + i as f32 * 20.))
+                    * Matrix4::from_scale(CUBE_SIZE);
+                program.set_uniform(gl, "model", model);
+                program.set_uniform(gl, "color", cube.color);
+                mesh.draw(gl);
+                count += 1;
+            }
+            drawn.push((view.name(), count));
+        });
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.cube.delete(gl);
+        self.program.delete(gl);
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<HeadTrackingDemo>();
+}
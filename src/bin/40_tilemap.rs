@@ -0,0 +1,201 @@
+use cgmath::Point2;
+use me_learning_opengl::{
+    camera::OrthoCamera,
+    cli::Flag,
+    color::Color,
+    debug_text::{DebugText, LINE_HEIGHT},
+    procedural::{self, NoiseParams},
+    texture::ImageData,
+    tilemap::{TileAnimation, TileAtlas, TileLayer, Tilemap},
+    with_windows_and_config, AppContext, DemoArgs, RenderHandler,
+};
+
+const FLAGS: &[Flag] = &[Flag::with_value(
+    "map",
+    "<file>",
+    "A CSV file or Tiled map to draw with the built-in tiles, instead of the generated island",
+)];
+
+/// How many tiles go across and down the generated map
+const MAP_SIZE: u32 = 1024;
+/// The size of the tiles in pixels, and how many pixels each tile covers when the demo starts
+const TILE_SIZE: u32 = 16;
+const START_ZOOM: f32 = 16.;
+
+/// The tiles of the built-in atlas, which has them in two rows of four
+const DEEP_WATER: u32 = 0;
+/// The three frames of the water animation
+const WATER: [u32; 3] = [1, 2, 3];
+const SAND: u32 = 4;
+const GRASS: u32 = 5;
+const FOREST: u32 = 6;
+const ROCK: u32 = 7;
+const ATLAS_COLUMNS: u32 = 4;
+const ATLAS_ROWS: u32 = 2;
+
+/// The built-in tiles, drawn with a few shapes so the edges between them show any bleeding
+fn atlas_image() -> ImageData {
+    let water = Color::from_hex("#3a78c8").unwrap();
+    let foam = Color::from_hex("#8cc0f0").unwrap();
+    let grass = Color::from_hex("#4c9a3c").unwrap();
+    procedural::from_fn(TILE_SIZE * ATLAS_COLUMNS, TILE_SIZE * ATLAS_ROWS, |x, y| {
+        // The first row of pixels is the top of the atlas, and of each tile
+        let tile = y / TILE_SIZE * ATLAS_COLUMNS + x / TILE_SIZE;
+        let (x, y) = (x % TILE_SIZE, y % TILE_SIZE);
+        let center = TILE_SIZE as f32 / 2.;
+        let from_center =
+            ((x as f32 + 0.5 - center).powi(2) + (y as f32 + 0.5 - center).powi(2)).sqrt() / center;
+        match tile {
+            DEEP_WATER => Color::from_hex("#1f4a8a").unwrap(),
+            // A wave that moves down the tile from frame to frame
+            1..=3 => {
+                if (y + (tile - 1) * TILE_SIZE / 3) % TILE_SIZE < 2 && x % 8 < 5 {
+                    foam
+                } else {
+                    water
+                }
+            }
+            SAND => {
+                if (x * 7 + y * 13) % 11 == 0 {
+                    Color::from_hex("#c8b070").unwrap()
+                } else {
+                    Color::from_hex("#e0cc8c").unwrap()
+                }
+            }
+            GRASS => {
+                if (x * 5 + y * 3) % 9 == 0 {
+                    Color::from_hex("#5cb04a").unwrap()
+                } else {
+                    grass
+                }
+            }
+            // A round tree top over the grass
+            FOREST => {
+                if from_center < 0.75 {
+                    Color::from_hex("#1e5a24").unwrap()
+                } else {
+                    grass
+                }
+            }
+            // A bright border, which would bleed into the neighboring tiles without the padding
+            _ => {
+                if x == 0 || y == 0 || x == TILE_SIZE - 1 || y == TILE_SIZE - 1 {
+                    Color::WHITE
+                } else {
+                    Color::from_hex("#7a7a80").unwrap()
+                }
+            }
+        }
+    })
+}
+
+/// An island made of noise, with the middle raised and the edges sunk into the sea
+fn generate_island(size: u32) -> TileLayer {
+    let params = NoiseParams {
+        frequency: 16,
+        octaves: 5,
+        ..Default::default()
+    };
+    let mut layer = TileLayer::new(size, size);
+    for y in 0..size {
+        for x in 0..size {
+            let u = (x as f32 + 0.5) / size as f32 * 2. - 1.;
+            let v = (y as f32 + 0.5) / size as f32 * 2. - 1.;
+            let falloff = 1. - (u * u + v * v).sqrt();
+            let height = procedural::noise_value(x, y, size, size, &params) * 0.6 + falloff * 0.5;
+            let tile = match height {
+                h if h < 0.35 => DEEP_WATER,
+                h if h < 0.45 => WATER[0],
+                h if h < 0.5 => SAND,
+                h if h < 0.65 => GRASS,
+                h if h < 0.78 => FOREST,
+                _ => ROCK,
+            };
+            layer.set(x, y, Some(tile));
+        }
+    }
+    layer
+}
+
+struct TilemapDemo {
+    tilemap: Tilemap,
+    camera: OrthoCamera,
+    text: DebugText,
+}
+
+impl TilemapDemo {
+    fn new(gl: &mut glow::Context, ctx: &mut AppContext, map: Option<&str>) -> Self {
+        ctx.render_settings.clear_color = Some(Color::from_hex("#10203a").unwrap());
+        let atlas = TileAtlas::new(gl, ctx.features(), &atlas_image(), TILE_SIZE);
+        let mut tilemap = match map {
+            Some(path) => {
+                Tilemap::from_file(gl, ctx.features(), path, atlas).unwrap_or_else(|error| {
+                    eprintln!("Couldn't load the map {}: {}", path, error);
+                    std::process::exit(1);
+                })
+            }
+            None => Tilemap::new(gl, ctx.features(), &generate_island(MAP_SIZE), atlas),
+        };
+        tilemap.animate(WATER[0], TileAnimation::new(WATER.to_vec(), 0.25));
+
+        let (width, height) = tilemap.size();
+        eprintln!(
+            "A {}x{} tile map in chunks, where only the chunks in view are drawn. Pan with WASD or \
+             the arrow keys, hold shift to pan faster, and scroll to zoom.",
+            width, height
+        );
+
+        Self {
+            tilemap,
+            camera: OrthoCamera::new(
+                Point2::new(width as f32 / 2., height as f32 / 2.),
+                START_ZOOM,
+            ),
+            text: DebugText::new(gl),
+        }
+    }
+}
+
+impl RenderHandler for TilemapDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        Self::new(gl, ctx, None)
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.camera.update(ctx);
+        self.tilemap.update(gl, ctx.timing.time());
+        let stats = self.tilemap.draw(gl, &self.camera);
+
+        let lines = [
+            format!(
+                "{} draw calls for the chunks in view, of {} chunks",
+                stats.draw_calls, stats.chunks
+            ),
+            format!("{} tiles drawn", stats.tiles),
+            format!("{:.1} pixels per tile", self.camera.zoom),
+        ];
+        for (i, line) in lines.iter().enumerate() {
+            let y = 4. + (i as u32 * LINE_HEIGHT * 2) as f32;
+            self.text.text(4., y, 2, Color::WHITE, line);
+        }
+        self.text.draw(gl, &ctx.arena, ctx.render_size());
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.tilemap.delete(gl);
+        self.text.delete(gl);
+    }
+}
+
+fn main() {
+    let args = DemoArgs::parse_with(FLAGS);
+    let map = args.value("map").map(str::to_owned);
+    let window_config = args.window_config();
+    with_windows_and_config(
+        args.config,
+        vec![(
+            window_config,
+            Box::new(move |gl, ctx| Box::new(TilemapDemo::new(gl, ctx, map.as_deref()))),
+        )],
+    );
+}
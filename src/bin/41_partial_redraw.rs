@@ -0,0 +1,218 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use glow::HasContext;
+use me_learning_opengl::{
+    color::Color,
+    debug_text::{DebugText, CHAR_ADVANCE, LINE_HEIGHT},
+    shader::ShaderProgram,
+    viewport::Rect,
+    AppContext, DemoArgs, RenderHandler,
+};
+use winit::VirtualKeyCode;
+
+const VERTEX_SHADER_SRC: &str = include_str!("partial_redraw/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("partial_redraw/fragment.glsl");
+
+/// How many characters fit across the clock, and the gap around its text, in pixels at a UI scale
+/// of 1
+const WIDGET_CHARS: u32 = 30;
+const WIDGET_PADDING: u32 = 8;
+/// The text scales of the time and the lines under it, at a UI scale of 1
+const TIME_SCALE: u32 = 4;
+const LINE_SCALE: u32 = 2;
+
+/// The seconds since midnight UTC
+fn clock_second() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs() % (24 * 60 * 60))
+}
+
+/// Where the clock goes in a window of a size, in the middle of it, as a rect in pixels from the
+/// top-left corner of the window
+fn widget_rect(window_size: (u32, u32), ui_scale: f32) -> Rect {
+    let scale = ui_scale.round().max(1.) as u32;
+    let padding = WIDGET_PADDING * scale;
+    let width = WIDGET_CHARS * CHAR_ADVANCE * LINE_SCALE * scale + padding * 2;
+    let height = (TIME_SCALE + LINE_SCALE * 3) * LINE_HEIGHT * scale + padding * 2;
+    Rect::new(
+        (window_size.0 as i32 - width as i32) / 2,
+        (window_size.1 as i32 - height as i32) / 2,
+        width as i32,
+        height as i32,
+    )
+}
+
+/// A clock over a background that is slow to draw, which only redraws the clock once a second
+struct PartialRedrawDemo {
+    program: ShaderProgram,
+    /// An empty vertex array, since the vertices come from `gl_VertexID`
+    vao: u32,
+    text: DebugText,
+    /// The second that the clock shows, or `None` when it has to be drawn again anyway
+    shown_second: Option<u64>,
+    /// The second read for the frame in `damage`, so the clock shows the one it damaged for
+    sampled_second: Option<u64>,
+    /// Times how long the GPU takes to draw a frame, if timer queries are supported
+    timer_query: Option<u32>,
+    /// Whether the timer query has a result on the way, and whether the clock changed in the
+    /// frame it is timing
+    timer_pending: Option<bool>,
+    /// The last GPU times of a frame between the clock's updates and of one that updated it, in
+    /// milliseconds
+    idle_gpu_ms: Option<f32>,
+    update_gpu_ms: Option<f32>,
+}
+
+impl PartialRedrawDemo {
+    /// Collect the GPU time of the last timed frame, and start timing this one once it is in,
+    /// returning whether this one is timed
+    fn begin_timer(&mut self, gl: &mut glow::Context, updating: bool) -> bool {
+        let query = match self.timer_query {
+            Some(query) => query,
+            None => return false,
+        };
+        unsafe {
+            if let Some(updated) = self.timer_pending {
+                if gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) == 0 {
+                    return false;
+                }
+                let ms = gl.get_query_parameter_u32(query, glow::QUERY_RESULT) as f32 / 1e6;
+                if updated {
+                    self.update_gpu_ms = Some(ms);
+                } else {
+                    self.idle_gpu_ms = Some(ms);
+                }
+            }
+            gl.begin_query(glow::TIME_ELAPSED, query);
+        }
+        self.timer_pending = Some(updating);
+        true
+    }
+}
+
+impl RenderHandler for PartialRedrawDemo {
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self {
+        ctx.render_settings.partial_redraw = true;
+        ctx.render_settings.clear_color = Some(Color::BLACK);
+
+        let timer_query = if ctx.features().timer_query {
+            Some(unsafe { gl.create_query().unwrap() })
+        } else {
+            None
+        };
+        eprintln!(
+            "A clock over a background that is slow to draw. With partial redraw only the clock \
+             is redrawn, once a second, so the frames in between take almost no GPU time. Press \
+             space to turn partial redraw on and off, and F3 to see how much of the window is \
+             redrawn in the title."
+        );
+
+        Self {
+            program: ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap(),
+            vao: unsafe { gl.create_vertex_array().unwrap() },
+            text: DebugText::new(gl),
+            shown_second: None,
+            sampled_second: None,
+            timer_query,
+            timer_pending: None,
+            idle_gpu_ms: None,
+            update_gpu_ms: None,
+        }
+    }
+
+    fn damage(&mut self, ctx: &mut AppContext) {
+        let second = clock_second();
+        self.sampled_second = Some(second);
+        if self.shown_second == Some(second) {
+            return;
+        }
+        // The rect is flipped over for the damage, which goes up from the bottom of the window
+        let window_size = ctx.window_size();
+        let widget = widget_rect(window_size, ctx.ui_scale());
+        ctx.add_damage(Rect::new(
+            widget.x,
+            window_size.1 as i32 - widget.y - widget.height,
+            widget.width,
+            widget.height,
+        ));
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if ctx.input.was_key_pressed(VirtualKeyCode::Space) {
+            let settings = &mut ctx.render_settings;
+            settings.partial_redraw = !settings.partial_redraw;
+            self.shown_second = None;
+        }
+        let second = self.sampled_second.take().unwrap_or_else(clock_second);
+        let updating = self.shown_second != Some(second);
+        self.shown_second = Some(second);
+        let timed = self.begin_timer(gl, updating);
+
+        let (width, height) = ctx.render_size();
+        self.program.bind(gl);
+        self.program
+            .set_uniform(gl, "resolution", [width as f32, height as f32]);
+        unsafe {
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+        }
+
+        // Everything is drawn every frame, and the scissor box leaves out what didn't change
+        let scale = ctx.ui_scale().round().max(1.) as u32;
+        let widget = widget_rect((width, height), ctx.ui_scale());
+        let theme = ctx.theme();
+        self.text.rect(
+            widget.x as f32,
+            widget.y as f32,
+            widget.width as f32,
+            widget.height as f32,
+            theme.panel_color,
+        );
+        let gpu_ms = |ms: Option<f32>| ms.map_or("-".into(), |ms| format!("{:.2} ms", ms));
+        let lines = [
+            format!(
+                "{:02}:{:02}:{:02} UTC",
+                second / 3600,
+                second / 60 % 60,
+                second % 60
+            ),
+            format!("GPU {} between updates", gpu_ms(self.idle_gpu_ms)),
+            format!("GPU {} on updates", gpu_ms(self.update_gpu_ms)),
+            format!(
+                "partial redraw {} ( space )",
+                if ctx.render_settings.partial_redraw {
+                    "on"
+                } else {
+                    "off"
+                }
+            ),
+        ];
+        let x = (widget.x as u32 + WIDGET_PADDING * scale) as f32;
+        let mut y = (widget.y as u32 + WIDGET_PADDING * scale) as f32;
+        for (i, line) in lines.iter().enumerate() {
+            let text_scale = if i == 0 { TIME_SCALE } else { LINE_SCALE } * scale;
+            self.text.text(x, y, text_scale, theme.text_color, line);
+            y += (LINE_HEIGHT * text_scale) as f32;
+        }
+        self.text.draw(gl, &ctx.arena, (width, height));
+        if timed {
+            unsafe { gl.end_query(glow::TIME_ELAPSED) };
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.program.delete(gl);
+        self.text.delete(gl);
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            if let Some(query) = self.timer_query {
+                gl.delete_query(query);
+            }
+        }
+    }
+}
+
+fn main() {
+    DemoArgs::parse().run::<PartialRedrawDemo>();
+}
@@ -0,0 +1,16 @@
+#version 330 core
+in vec3 normal;
+
+uniform vec3 color;
+
+out vec4 FragColor;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.5, 1.0, 0.3));
+
+void main() {
+    // A key light and a dimmer fill light from below, so the undersides aren't flat black
+    vec3 n = normalize(normal);
+    float key = max(dot(n, LIGHT_DIRECTION), 0.0);
+    float fill = max(dot(n, -LIGHT_DIRECTION), 0.0) * 0.2;
+    FragColor = vec4(color * (0.2 + 0.8 * key + fill), 1.0);
+}
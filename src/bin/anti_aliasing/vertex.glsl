@@ -0,0 +1,15 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+out vec3 normal;
+
+void main() {
+    // The boxes are only scaled along their own axes, which keeps their face normals pointing the
+    // right way, so the normals just need normalizing
+    normal = normalize(mat3(model) * aNormal);
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+}
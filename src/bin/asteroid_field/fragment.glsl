@@ -0,0 +1,15 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 normal;
+in vec3 color;
+
+uniform vec3 lightDirection;
+// Multiplied with the color, to show which level of detail is drawn
+uniform vec3 tint;
+
+void main() {
+    // Simple directional light with some ambient light
+    float diffuse = max(dot(normalize(normal), lightDirection), 0.0);
+    FragColor = vec4(color * tint * (diffuse * 0.85 + 0.15), 1.0);
+}
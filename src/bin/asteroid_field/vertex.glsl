@@ -0,0 +1,18 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 3) in vec4 aOffsetScale;
+layout (location = 4) in vec3 aColor;
+
+out vec3 normal;
+out vec3 color;
+
+uniform mat4 viewProjection;
+
+void main() {
+    // Asteroids are only moved and uniformly scaled, so the normal doesn't need a transform
+    normal = aNormal;
+    color = aColor;
+    gl_Position = viewProjection * vec4(aPos * aOffsetScale.w + aOffsetScale.xyz, 1.0);
+}
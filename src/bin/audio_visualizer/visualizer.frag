@@ -0,0 +1,46 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+// The same names as ShaderToy, so visualizers can be copied over
+uniform float iTime;
+uniform vec2 iResolution;
+uniform sampler2D iAudio;
+uniform float iBass;
+uniform float iMid;
+uniform float iTreble;
+uniform float iVolume;
+
+const float PI = 3.14159265;
+
+// Look up the spectrum on a roughly logarithmic scale, so the bass isn't squeezed into a sliver
+float level(float x) {
+    return texture(iAudio, vec2(x * x, 0.5)).r;
+}
+
+void main() {
+    vec2 uv = (gl_FragCoord.xy - 0.5 * iResolution) / iResolution.y;
+    float radius = length(uv);
+    float angle = atan(uv.y, uv.x);
+
+    // A ring of bars around the middle, mirrored left and right
+    float around = abs(angle) / PI;
+    float bar = level(around);
+    float ringRadius = 0.2 + 0.15 * iBass;
+    float ring = smoothstep(0.006, 0.0, abs(radius - ringRadius));
+    float bars = step(ringRadius, radius) * step(radius, ringRadius + bar * 0.25);
+
+    // A disc in the middle that pulses with the bass
+    float disc = smoothstep(ringRadius * 0.9, ringRadius * 0.85, radius);
+
+    // Colors that drift with time and brighten with the treble
+    vec3 color = 0.5 + 0.5 * cos(iTime * 0.3 + around * 3.0 + vec3(0.0, 2.0, 4.0));
+    vec3 background = vec3(0.02, 0.02, 0.05) + 0.15 * iMid * vec3(0.2, 0.1, 0.4) * (1.0 - radius);
+
+    vec3 result = background;
+    result += color * bars * (0.6 + iTreble);
+    result += vec3(1.0) * ring * 0.5;
+    result = mix(result, color * (0.3 + iBass + iVolume), disc);
+    FragColor = vec4(result, 1.0);
+}
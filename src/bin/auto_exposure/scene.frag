@@ -0,0 +1,28 @@
+#version 330 core
+in vec3 worldPosition;
+in vec3 normal;
+
+// The number of lights, which matches the size of the arrays below
+const int LIGHT_COUNT = 2;
+
+uniform vec3 color;
+// Light given off by the surface itself, in the same units as the lights
+uniform vec3 emissive;
+uniform vec3 lightPositions[LIGHT_COUNT];
+uniform vec3 lightColors[LIGHT_COUNT];
+
+out vec4 FragColor;
+
+void main() {
+    vec3 n = normalize(normal);
+    // A little light everywhere, so the corridor isn't completely black
+    vec3 light = vec3(0.002);
+    for (int i = 0; i < LIGHT_COUNT; i++) {
+        vec3 toLight = lightPositions[i] - worldPosition;
+        float distance = length(toLight);
+        float facing = max(dot(n, toLight / distance), 0.0);
+        light += lightColors[i] * facing / (1.0 + distance * distance);
+    }
+    // The colors are linear and can go far above 1.0
+    FragColor = vec4(color * light + emissive, 1.0);
+}
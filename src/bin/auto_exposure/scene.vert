@@ -0,0 +1,17 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+out vec3 worldPosition;
+out vec3 normal;
+
+void main() {
+    vec4 world = model * vec4(aPos, 1.0);
+    worldPosition = world.xyz;
+    // The boxes are only moved and scaled, so the normals only need the inverse of the scale
+    normal = normalize(transpose(inverse(mat3(model))) * aNormal);
+    gl_Position = viewProjection * world;
+}
@@ -0,0 +1,14 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+uniform sampler2D scene;
+uniform float exposure;
+
+void main() {
+    vec3 color = texture(scene, texCoord).rgb * exposure;
+    // Reinhard tone mapping to bring the HDR colors back into range, then gamma encode them for
+    // the window
+    FragColor = vec4(encodeGamma(tonemapReinhard(color)), 1.0);
+}
@@ -0,0 +1,43 @@
+#version 330 core
+
+in vec3 worldPosition;
+in vec3 worldNormal;
+
+out vec4 FragColor;
+
+uniform vec3 viewPosition;
+uniform vec3 albedo;
+// Draws the surface in its own color without lighting, for the lights themselves
+uniform bool emissive;
+
+void main() {
+    if (emissive) {
+        FragColor = vec4(albedo, 1.0);
+        return;
+    }
+
+    vec3 normal = normalize(worldNormal);
+    vec3 toView = normalize(viewPosition - worldPosition);
+    vec3 color = albedo * 0.02;
+
+    // Only the lights that reach the fragment's cluster, out of all of them
+    uint cluster = clusterIndex(worldPosition);
+    uvec2 range = clusterRange(cluster);
+    for (uint i = 0u; i < range.y; i++) {
+        ClusterLight light = clusterLight(range, i);
+        vec3 toLight = light.position - worldPosition;
+        float lightDistance = length(toLight);
+        toLight /= lightDistance;
+
+        // Inverse square falloff that reaches zero at the light's radius
+        float fade = clamp(1.0 - pow(lightDistance / light.radius, 4.0), 0.0, 1.0);
+        float attenuation = fade * fade / (lightDistance * lightDistance + 1.0);
+
+        float diffuse = max(dot(normal, toLight), 0.0);
+        vec3 halfway = normalize(toLight + toView);
+        float specular = pow(max(dot(normal, halfway), 0.0), 32.0) * 0.3;
+
+        color += (albedo * diffuse + specular) * light.color * attenuation;
+    }
+    FragColor = vec4(clusterHeatmapColor(color, cluster), 1.0);
+}
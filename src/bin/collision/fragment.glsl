@@ -0,0 +1,18 @@
+#version 330 core
+in vec3 normal;
+in vec3 worldPosition;
+
+uniform vec3 color;
+
+out vec4 FragColor;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.4, 1.0, 0.3));
+
+void main() {
+    // A sun and some ambient light, with a faint grid so that movement is easy to see
+    float diffuse = max(dot(normalize(normal), LIGHT_DIRECTION), 0.0);
+    vec3 cell = abs(fract(worldPosition) - 0.5);
+    float grid = smoothstep(0.46, 0.5, max(max(cell.x * (1.0 - abs(normal.x)), cell.y * (1.0 - abs(normal.y))), cell.z * (1.0 - abs(normal.z))));
+    vec3 lit = color * (0.25 + 0.75 * diffuse) * (1.0 - 0.15 * grid);
+    FragColor = vec4(lit, 1.0);
+}
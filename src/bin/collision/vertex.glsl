@@ -0,0 +1,16 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+out vec3 normal;
+out vec3 worldPosition;
+
+void main() {
+    // The models are only scaled and moved, so the normals just need normalizing
+    normal = normalize(mat3(model) * aNormal);
+    worldPosition = vec3(model * vec4(aPos, 1.0));
+    gl_Position = viewProjection * vec4(worldPosition, 1.0);
+}
@@ -0,0 +1,28 @@
+#version 330 core
+in vec3 normal;
+in vec2 texCoord;
+
+// The color of the surface, which is multiplied by the decal texture if there is one
+uniform vec3 color;
+uniform bool useTexture;
+uniform sampler2D decal;
+
+out vec4 FragColor;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.5, 1.0, 0.3));
+
+void main() {
+    vec3 albedo = color;
+    if (useTexture) {
+        // The decals are alpha tested, so the pre-pass gets the same ragged edges
+        vec4 texel = texture(decal, texCoord);
+        if (texel.a < 0.5) {
+            discard;
+        }
+        albedo *= texel.rgb;
+    }
+
+    vec3 n = normalize(normal);
+    float light = 0.3 + 0.7 * max(dot(n, LIGHT_DIRECTION), 0.0);
+    FragColor = vec4(albedo * light, 1.0);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoord;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+out vec3 normal;
+out vec2 texCoord;
+
+// The depth pre-pass and the shading after it must compute exactly the same depth
+invariant gl_Position;
+
+void main() {
+    normal = normalize(mat3(model) * aNormal);
+    texCoord = aTexCoord;
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+}
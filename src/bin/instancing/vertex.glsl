@@ -0,0 +1,19 @@
+#version 330 core
+
+// Per vertex
+layout (location = 0) in vec2 aPos;
+// Per instance
+layout (location = 1) in vec2 aOffset;
+layout (location = 2) in vec4 aColor;
+layout (location = 3) in vec3 aNormal;
+
+out vec4 vertexColor;
+
+uniform vec3 lightDirection;
+
+void main() {
+    // Shade each instance as if it were a small tile facing along its normal
+    float light = max(dot(normalize(aNormal), lightDirection), 0.0) * 0.8 + 0.2;
+    vertexColor = vec4(aColor.rgb * light, aColor.a);
+    gl_Position = vec4(aPos + aOffset, 0.0, 1.0);
+}
@@ -0,0 +1,10 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 textureCoord;
+
+uniform sampler2D checkerTexture;
+
+void main() {
+    FragColor = texture(checkerTexture, textureCoord);
+}
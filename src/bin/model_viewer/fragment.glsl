@@ -0,0 +1,32 @@
+#version 330 core
+in vec3 normal;
+in vec2 uv;
+
+uniform vec3 color;
+// A test texture that the texture debug views show on the models
+uniform sampler2D debugImage;
+// The model's material textures, which are 1x1 defaults for the maps it doesn't have
+uniform sampler2D albedoMap;
+uniform sampler2D emissiveMap;
+uniform sampler2D occlusionMap;
+
+out vec4 FragColor;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.5, 1.0, 0.3));
+
+void main() {
+#ifdef TEXTURE_DEBUG
+    // Show the debug view unlit, so the colors can be read off as they are
+    FragColor = debugTexture(debugImage, uv);
+#else
+    // A key light and a dimmer fill light from below, so the undersides aren't flat black
+    // Back faces are only drawn for double-sided models, and are lit like the side they show
+    vec3 n = facingNormal(normalize(normal));
+    float key = max(dot(n, LIGHT_DIRECTION), 0.0);
+    float fill = max(dot(n, -LIGHT_DIRECTION), 0.0) * 0.2;
+    vec3 albedo = color * texture(albedoMap, uv).rgb;
+    float occlusion = texture(occlusionMap, uv).r;
+    vec3 emission = texture(emissiveMap, uv).rgb;
+    FragColor = vec4(albedo * (0.2 * occlusion + 0.8 * key + fill) + emission, 1.0);
+#endif
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+in vec3 normal;
+in vec3 color;
+in vec4 clipPosition;
+in vec4 previousClipPosition;
+
+layout (location = 0) out vec4 FragColor;
+layout (location = 1) out vec2 Velocity;
+
+uniform vec3 lightDirection;
+
+// `screenVelocity` comes from `VELOCITY_CHUNK`, which is included above
+
+void main() {
+    float diffuse = max(dot(normalize(normal), lightDirection), 0.0);
+    FragColor = vec4(color * (0.3 + diffuse * 0.7), 1.0);
+    Velocity = screenVelocity(clipPosition, previousClipPosition);
+}
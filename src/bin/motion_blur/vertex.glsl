@@ -0,0 +1,25 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+out vec3 normal;
+out vec3 color;
+// Where the vertex is this frame and where it was last frame, in clip space
+out vec4 clipPosition;
+out vec4 previousClipPosition;
+
+uniform mat4 viewProjection;
+uniform mat4 previousViewProjection;
+
+// The model matrices of this frame and last, and the color, come from the `PerDraw` block
+
+void main() {
+    // The cubes are only rotated, and the ground is flat, so the normals don't need the inverse
+    // transpose
+    normal = mat3(perDrawModel) * aNormal;
+    color = perDrawColor.rgb;
+    clipPosition = viewProjection * perDrawModel * vec4(aPos, 1.0);
+    previousClipPosition = previousViewProjection * perDrawPreviousModel * vec4(aPos, 1.0);
+    gl_Position = clipPosition;
+}
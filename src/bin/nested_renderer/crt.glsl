@@ -0,0 +1,32 @@
+#version 330 core
+in vec3 normal;
+in vec2 uv;
+
+uniform sampler2D screen;
+// The size of the nested renderer's texture, which sets how many scanlines there are
+uniform vec2 screenSize;
+uniform float time;
+
+out vec4 FragColor;
+
+// How far the picture bulges out from the middle of the tube
+const float CURVATURE = 0.12;
+
+void main() {
+    // Bend the picture like the glass of a tube, which leaves the corners black
+    vec2 centered = uv * 2.0 - 1.0;
+    centered *= 1.0 + CURVATURE * dot(centered, centered);
+    vec2 tube = centered * 0.5 + 0.5;
+    if (any(lessThan(tube, vec2(0.0))) || any(greaterThan(tube, vec2(1.0)))) {
+        FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec3 picture = texture(screen, tube).rgb;
+    // Dark gaps between the scanlines, a dimmer edge, and a little flicker
+    float scanline = 0.75 + 0.25 * sin(tube.y * screenSize.y * 3.14159);
+    float vignette = 1.0 - 0.35 * dot(centered, centered);
+    float flicker = 0.97 + 0.03 * sin(time * 60.0);
+    // The screen glows, so it isn't lit by the room
+    FragColor = vec4(picture * scanline * vignette * flicker * 1.2, 1.0);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+in vec3 normal;
+in vec2 uv;
+
+uniform vec3 color;
+// How much of the nested renderer's texture to show instead of the color
+uniform float textured;
+uniform sampler2D screen;
+
+out vec4 FragColor;
+
+const vec3 LIGHT_DIRECTION = normalize(vec3(0.4, 1.0, 0.6));
+
+void main() {
+    float light = 0.25 + 0.75 * max(dot(normalize(normal), LIGHT_DIRECTION), 0.0);
+    vec3 albedo = mix(color, texture(screen, uv).rgb, textured);
+    FragColor = vec4(albedo * light, 1.0);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+// The triangle's corners and colors come from the vertex index, so it needs no vertex buffer
+
+uniform float time;
+
+out vec3 vertexColor;
+
+const vec3 COLORS[3] = vec3[3](vec3(1.0, 0.0, 0.2), vec3(0.2, 1.0, 0.3), vec3(0.2, 0.4, 1.0));
+
+void main() {
+    float angle = time + float(gl_VertexID) * 2.0944;
+    vertexColor = COLORS[gl_VertexID];
+    gl_Position = vec4(vec2(sin(angle), cos(angle)) * 0.8, 0.0, 1.0);
+}
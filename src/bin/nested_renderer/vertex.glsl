@@ -0,0 +1,16 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aUv;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+out vec3 normal;
+out vec2 uv;
+
+void main() {
+    normal = normalize(mat3(model) * aNormal);
+    uv = aUv;
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+}
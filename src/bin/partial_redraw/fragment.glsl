@@ -0,0 +1,21 @@
+#version 330 core
+
+uniform vec2 resolution;
+
+out vec4 FragColor;
+
+const int LAYERS = 64;
+
+// Layers of waves, which cost enough for each pixel that skipping the pixels that didn't change
+// shows in the GPU time
+void main() {
+    vec2 uv = gl_FragCoord.xy / resolution.y;
+    vec3 color = vec3(0.0);
+    for (int i = 0; i < LAYERS; i++) {
+        float layer = float(i) / float(LAYERS);
+        vec2 p = uv * (2.0 + layer * 6.0) + vec2(layer * 13.0, layer * 7.0);
+        float wave = sin(p.x + sin(p.y * 1.3 + layer * 5.0)) * cos(p.y - cos(p.x * 0.7));
+        color += mix(vec3(0.1, 0.2, 0.4), vec3(0.5, 0.3, 0.6), layer) * (wave * 0.5 + 0.5);
+    }
+    FragColor = vec4(color / float(LAYERS), 1.0);
+}
@@ -0,0 +1,13 @@
+#version 330 core
+
+in vec3 worldPosition;
+
+out vec4 FragColor;
+
+void main() {
+    // A dark floor with faint grid lines, fading out into the distance
+    vec2 cell = abs(fract(worldPosition.xz) - 0.5);
+    float line = 1.0 - smoothstep(0.47, 0.5, max(cell.x, cell.y));
+    float fade = 1.0 - smoothstep(5.0, 25.0, length(worldPosition.xz));
+    FragColor = vec4(vec3(0.04 + (1.0 - line) * 0.04 * fade), 1.0);
+}
@@ -0,0 +1,12 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+
+out vec3 worldPosition;
+
+uniform mat4 viewProjection;
+
+void main() {
+    worldPosition = aPos;
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 330 core
+
+in vec3 normal;
+in vec3 color;
+
+out vec4 FragColor;
+
+uniform vec3 lightDirection;
+
+void main() {
+    float diffuse = max(dot(normalize(normal), lightDirection), 0.0);
+    FragColor = vec4(color * (0.3 + diffuse * 0.7), 1.0);
+}
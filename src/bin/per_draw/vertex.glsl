@@ -0,0 +1,31 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+out vec3 normal;
+out vec3 color;
+
+uniform mat4 viewProjection;
+
+#ifdef PER_DRAW
+// The model matrix, color, and params come from the `PerDraw` block instead
+#else
+uniform mat4 model;
+uniform vec4 tint;
+// How much each cube pulses, in x
+uniform vec4 params;
+#endif
+
+void main() {
+#ifdef PER_DRAW
+    mat4 model = perDrawModel;
+    vec4 tint = perDrawColor;
+    vec4 params = perDrawParams;
+#endif
+    // The models are only rotated and scaled evenly, so the normals don't need the inverse
+    // transpose
+    normal = mat3(model) * aNormal;
+    color = tint.rgb * (1.0 - params.x * 0.5);
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+}
@@ -0,0 +1,52 @@
+#version 330 core
+
+in vec3 worldPosition;
+in vec3 worldNormal;
+
+out vec4 FragColor;
+
+struct PointLight {
+    vec3 position;
+    vec3 color;
+    // Where the light fades out completely
+    float radius;
+};
+
+const int MAX_LIGHTS = 4;
+uniform PointLight lights[MAX_LIGHTS];
+uniform int lightCount;
+// Which of the lights casts shadows, or -1 for none
+uniform int shadowLight;
+
+uniform vec3 viewPosition;
+uniform vec3 albedo;
+// Draws the surface in its own color without lighting, for the lights themselves
+uniform bool emissive;
+
+void main() {
+    if (emissive) {
+        FragColor = vec4(albedo, 1.0);
+        return;
+    }
+
+    vec3 normal = normalize(worldNormal);
+    vec3 toView = normalize(viewPosition - worldPosition);
+    vec3 color = albedo * 0.02;
+    for (int i = 0; i < lightCount; i++) {
+        vec3 toLight = lights[i].position - worldPosition;
+        float lightDistance = length(toLight);
+        toLight /= lightDistance;
+
+        // Inverse square falloff that reaches zero at the light's radius
+        float fade = clamp(1.0 - pow(lightDistance / lights[i].radius, 4.0), 0.0, 1.0);
+        float attenuation = fade * fade / (lightDistance * lightDistance + 1.0);
+
+        float diffuse = max(dot(normal, toLight), 0.0);
+        vec3 halfway = normalize(toLight + toView);
+        float specular = pow(max(dot(normal, halfway), 0.0), 32.0) * 0.3;
+
+        float shadow = i == shadowLight ? pointShadow(worldPosition) : 1.0;
+        color += (albedo * diffuse + specular) * lights[i].color * attenuation * shadow;
+    }
+    FragColor = vec4(color, 1.0);
+}
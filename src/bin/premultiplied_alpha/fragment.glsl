@@ -0,0 +1,13 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 textureCoord;
+
+uniform sampler2D spriteTexture;
+// Multiplies only the color channels, which works for both straight and premultiplied alpha
+uniform vec3 tint;
+
+void main() {
+    vec4 color = texture(spriteTexture, textureCoord);
+    FragColor = vec4(color.rgb * tint, color.a);
+}
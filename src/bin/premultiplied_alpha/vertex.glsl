@@ -0,0 +1,14 @@
+#version 330 core
+
+layout (location = 0) in vec2 aPos;
+layout (location = 1) in vec2 aTexCoord;
+
+out vec2 textureCoord;
+
+uniform vec2 offset;
+uniform float scale;
+
+void main() {
+    textureCoord = aTexCoord;
+    gl_Position = vec4(aPos * scale + offset, 0.0, 1.0);
+}
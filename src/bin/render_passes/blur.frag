@@ -0,0 +1,20 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+uniform sampler2D image;
+// One texel in the direction to blur in
+uniform vec2 direction;
+
+// A 9 tap gaussian blur, sampled on one side of the center
+const float weights[5] = float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+void main() {
+    vec3 color = texture(image, texCoord).rgb * weights[0];
+    for (int i = 1; i < 5; i++) {
+        color += texture(image, texCoord + direction * float(i)).rgb * weights[i];
+        color += texture(image, texCoord - direction * float(i)).rgb * weights[i];
+    }
+    FragColor = vec4(color, 1.0);
+}
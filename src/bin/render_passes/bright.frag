@@ -0,0 +1,14 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+uniform sampler2D scene;
+uniform float threshold;
+
+void main() {
+    // Keep only the parts of the scene that are brighter than the threshold
+    vec3 color = texture(scene, texCoord).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    FragColor = vec4(color * max(brightness - threshold, 0.0) / max(brightness, 0.0001), 1.0);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+uniform sampler2D scene;
+// May be a lower resolution than the scene, so it is sampled with `textureUpsampled`
+uniform sampler2D bloom;
+
+void main() {
+    vec3 color = texture(scene, texCoord).rgb + textureUpsampled(bloom, texCoord).rgb;
+    // Reinhard tone mapping to bring the HDR colors back into range
+    FragColor = vec4(tonemapReinhard(color), 1.0);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec2 texCoord;
+
+uniform float time;
+uniform float aspectRatio;
+
+void main() {
+    vec2 position = (texCoord - 0.5) * vec2(aspectRatio, 1.0);
+
+    // A dark background with a few orbiting lights that are brighter than 1.0
+    vec3 color = vec3(0.02, 0.02, 0.05) + vec3(0.05) * (1.0 - length(position));
+    for (int i = 0; i < 5; i++) {
+        float angle = time * (0.3 + 0.1 * float(i)) + float(i) * 1.3;
+        vec2 center = vec2(cos(angle), sin(angle * 1.3)) * 0.3;
+        vec3 lightColor = 0.5 + 0.5 * cos(vec3(0.0, 2.0, 4.0) + float(i));
+        float distance = length(position - center);
+        color += lightColor * 4.0 * smoothstep(0.035, 0.03, distance);
+    }
+
+    FragColor = vec4(color, 1.0);
+}
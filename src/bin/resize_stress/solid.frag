@@ -0,0 +1,8 @@
+#version 330 core
+
+out vec4 FragColor;
+
+void main() {
+    // Pure green, so a corner that isn't covered stands out against the black clear color
+    FragColor = vec4(0.0, 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec3 worldNormal;
+
+out vec4 FragColor;
+
+uniform vec3 albedo;
+
+const vec3 SUN_DIRECTION = normalize(vec3(0.4, 1.0, 0.3));
+
+void main() {
+    float diffuse = max(dot(normalize(worldNormal), SUN_DIRECTION), 0.0);
+    FragColor = vec4(albedo * (0.3 + diffuse * 0.7), 1.0);
+}
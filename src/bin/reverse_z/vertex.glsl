@@ -0,0 +1,16 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+out vec3 worldNormal;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+void main() {
+    // The models are only moved and scaled along their axes, so the normals stay pointing the
+    // right way without the inverse transpose
+    worldNormal = mat3(model) * aNormal;
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+}
@@ -0,0 +1,63 @@
+use me_learning_opengl::{resources, selfcheck};
+
+const HELP: &str = "\
+Checks that every subsystem works on this machine, without showing a window
+
+Usage: selfcheck [--json <file>]
+
+    --json <file>  Also write the results as JSON to a file, or to stdout for `-`
+    --help         Print this help and exit
+
+Exits with 1 if any check failed. Checks that can't run here are skipped and don't count.
+";
+
+/// The file to write JSON to, `-` for stdout, parsed from the arguments
+fn parse_args() -> Result<Option<String>, String> {
+    let mut json = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--help" => {
+                print!("{}", HELP);
+                std::process::exit(0);
+            }
+            "--json" => json = Some(args.next().ok_or("`--json` needs a file, or `-`")?),
+            _ => return Err(format!("Unexpected argument `{}`", arg)),
+        }
+    }
+    Ok(json)
+}
+
+fn main() {
+    let json = match parse_args() {
+        Ok(json) => json,
+        Err(error) => {
+            eprint!("{}\n\n{}", error, HELP);
+            std::process::exit(1);
+        }
+    };
+
+    // Capture where tracked objects are created, so that the leak report check can see it
+    if resources::ENABLED
+        && std::env::var_os("RUST_BACKTRACE").is_none()
+        && std::env::var_os("RUST_LIB_BACKTRACE").is_none()
+    {
+        std::env::set_var("RUST_LIB_BACKTRACE", "1");
+    }
+
+    let check = selfcheck::run_all();
+    match json.as_deref() {
+        Some("-") => print!("{}", check.to_json()),
+        Some(path) => {
+            print!("{}", check.table());
+            if let Err(error) = std::fs::write(path, check.to_json()) {
+                eprintln!("Couldn't write the results to {}: {}", path, error);
+                std::process::exit(1);
+            }
+        }
+        None => print!("{}", check.table()),
+    }
+    if !check.passed() {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,27 @@
+#version 330 core
+
+in vec3 viewPosition;
+in vec3 viewNormal;
+
+#ifdef GBUFFER_PACKED
+layout (location = 0) out vec2 gNormal;
+layout (location = 1) out vec4 gAlbedo;
+#else
+layout (location = 0) out vec4 gPosition;
+layout (location = 1) out vec4 gNormal;
+layout (location = 2) out vec4 gAlbedo;
+#endif
+
+uniform vec3 albedo;
+
+void main() {
+#ifdef GBUFFER_PACKED
+    // The position is worked out from the depth buffer instead
+    gNormal = encodeNormal(normalize(viewNormal));
+#else
+    // The alpha marks where something was drawn
+    gPosition = vec4(viewPosition, 1.0);
+    gNormal = vec4(normalize(viewNormal), 0.0);
+#endif
+    gAlbedo = vec4(albedo, 1.0);
+}
@@ -0,0 +1,22 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+out vec3 viewPosition;
+out vec3 viewNormal;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+void main() {
+    // The SSAO pass works in view space, so the G-buffer is stored in view space too
+    mat4 modelView = view * model;
+    vec4 position = modelView * vec4(aPos, 1.0);
+    viewPosition = position.xyz;
+    // The models are only moved and scaled along the axes of the boxes, so the normals stay
+    // pointing the right way without the inverse transpose
+    viewNormal = mat3(modelView) * aNormal;
+    gl_Position = projection * position;
+}
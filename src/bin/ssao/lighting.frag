@@ -0,0 +1,52 @@
+#version 330 core
+
+in vec2 texCoord;
+
+out vec4 FragColor;
+
+// The G-buffer is read with the functions from `GBUFFER_CHUNK`, which is included above
+// May be half resolution, so it is sampled with `textureUpsampled` from `UPSAMPLE_CHUNK`
+uniform sampler2D occlusion;
+
+// The direction towards the light in view space
+uniform vec3 lightDirection;
+// 0 to light the scene with occlusion, 1 to show the occlusion by itself, and 2 to light the
+// scene without occlusion
+uniform int debugView;
+
+// The color of the current G-buffer sample
+vec3 shadeSample(float ambientOcclusion) {
+    vec4 position = gbufferPosition(texCoord);
+    if (position.a == 0.0) {
+        return vec3(0.05, 0.06, 0.08);
+    }
+    vec3 normal = gbufferNormal(texCoord);
+    vec3 albedo = gbufferAlbedo(texCoord);
+    vec3 ambient = albedo * 0.4 * ambientOcclusion;
+    float diffuse = max(dot(normal, lightDirection), 0.0);
+    return ambient + albedo * diffuse * 0.6;
+}
+
+void main() {
+    float ambientOcclusion = textureUpsampled(occlusion, texCoord).r;
+    if (debugView == 1) {
+        FragColor = vec4(vec3(ambientOcclusion), 1.0);
+        return;
+    }
+    if (debugView == 2) {
+        ambientOcclusion = 1.0;
+    }
+
+    // Lighting every sample of a multisampled G-buffer and averaging them is what smooths the
+    // edges, since the window only gets one color per pixel from this pass
+    vec3 color = vec3(0.0);
+    int samples = gbufferSamples();
+#ifdef GBUFFER_MULTISAMPLE
+    for (gSample = 0; gSample < samples; gSample++) {
+        color += shadeSample(ambientOcclusion);
+    }
+#else
+    color = shadeSample(ambientOcclusion);
+#endif
+    FragColor = vec4(color / float(samples), 1.0);
+}
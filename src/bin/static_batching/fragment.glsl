@@ -0,0 +1,29 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 normal;
+
+uniform vec3 color;
+uniform vec3 lightDirection;
+
+void main() {
+#ifdef LIGHTING
+    // Simple directional light with some ambient light
+    float diffuse = max(dot(normalize(normal), lightDirection), 0.0);
+#ifdef TOON
+    // Snap the light to a few bands
+    diffuse = floor(diffuse * 3.0 + 0.5) / 3.0;
+#endif
+    vec3 lit = color * (diffuse * 0.8 + 0.2);
+#else
+    vec3 lit = color;
+#endif
+
+#ifdef FOG
+    // Fade into the clear color with the distance from the camera
+    float distance = 1.0 / gl_FragCoord.w;
+    lit = mix(lit, vec3(0.1, 0.1, 0.12), smoothstep(10.0, 30.0, distance));
+#endif
+
+    FragColor = vec4(lit, 1.0);
+}
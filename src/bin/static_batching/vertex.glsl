@@ -0,0 +1,16 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+out vec3 normal;
+
+uniform mat4 viewProjection;
+uniform mat4 model;
+
+void main() {
+    // Batched meshes are already in world space and are drawn with an identity model matrix. The
+    // models here are only rotated and uniformly scaled, so the model matrix works for normals.
+    normal = mat3(model) * aNormal;
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+}
@@ -0,0 +1,4 @@
+#version 330 core
+
+// Only the depth of the terrain is needed in the depth pre-pass
+void main() {}
@@ -0,0 +1,19 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec3 normal;
+in float height;
+
+uniform vec3 lightDirection;
+uniform float maxHeight;
+
+void main() {
+    // Grass in the valleys and rock on the peaks
+    vec3 grass = vec3(0.3, 0.55, 0.2);
+    vec3 rock = vec3(0.55, 0.5, 0.45);
+    vec3 color = mix(grass, rock, smoothstep(0.3, 0.8, height / maxHeight));
+
+    // Simple directional light with some ambient light
+    float diffuse = max(dot(normalize(normal), lightDirection), 0.0);
+    FragColor = vec4(color * (diffuse * 0.85 + 0.15), 1.0);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoord;
+
+out vec3 normal;
+out float height;
+
+uniform mat4 viewProjection;
+
+// The depth pre-pass and the shading pass must compute exactly the same depths
+invariant gl_Position;
+
+void main() {
+    normal = aNormal;
+    height = aPos.y;
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+}
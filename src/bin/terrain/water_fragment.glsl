@@ -0,0 +1,46 @@
+#version 330 core
+out vec4 FragColor;
+
+in vec4 clipPosition;
+in vec3 worldPosition;
+in vec2 texCoord;
+
+uniform sampler2D reflection;
+uniform sampler2D normalMap;
+// How much of the reflection shows, which is 0 when it wasn't drawn
+uniform float reflectionStrength;
+uniform vec3 cameraPosition;
+uniform vec3 lightDirection;
+uniform float time;
+
+// How far the ripples push the reflection around, in screen space
+const float DISTORTION = 0.02;
+const vec3 WATER_COLOR = vec3(0.05, 0.2, 0.3);
+
+void main() {
+    // Two layers of ripples scrolling in different directions
+    vec3 ripple = texture(normalMap, texCoord + vec2(time * 0.02, time * 0.01)).rgb
+        + texture(normalMap, texCoord * 1.7 + vec2(-time * 0.015, time * 0.025)).rgb;
+    ripple = ripple - 1.0;
+    // The normal map's Z is the plane's Y, and its normal flips over when seen from below
+    vec3 up = gl_FrontFacing ? vec3(0.0, 1.0, 0.0) : vec3(0.0, -1.0, 0.0);
+    vec3 normal = normalize(up * ripple.z * 2.0 + vec3(ripple.x, 0.0, -ripple.y));
+
+    // The reflection lines up with the screen, so look it up at this fragment's screen position,
+    // pushed around by the ripples
+    vec2 screenCoord = clipPosition.xy / clipPosition.w * 0.5 + 0.5;
+    vec2 reflectionCoord = clamp(screenCoord + ripple.xy * DISTORTION, 0.001, 0.999);
+    vec3 reflected = texture(reflection, reflectionCoord).rgb;
+
+    // Water reflects more the flatter it is seen
+    vec3 toCamera = normalize(cameraPosition - worldPosition);
+    float fresnel = pow(1.0 - max(dot(toCamera, normal), 0.0), 3.0);
+    float reflectance = mix(0.3, 1.0, fresnel) * reflectionStrength;
+    vec3 color = mix(WATER_COLOR, reflected, reflectance);
+
+    // A glint of the sun
+    vec3 halfway = normalize(toCamera + lightDirection);
+    color += pow(max(dot(normal, halfway), 0.0), 200.0) * 0.8;
+
+    FragColor = vec4(color, mix(0.75, 1.0, fresnel));
+}
@@ -0,0 +1,18 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 2) in vec2 aTexCoord;
+
+out vec4 clipPosition;
+out vec3 worldPosition;
+out vec2 texCoord;
+
+uniform mat4 viewProjection;
+uniform float waterHeight;
+
+void main() {
+    worldPosition = vec3(aPos.x, waterHeight, aPos.z);
+    texCoord = aTexCoord;
+    clipPosition = viewProjection * vec4(worldPosition, 1.0);
+    gl_Position = clipPosition;
+}
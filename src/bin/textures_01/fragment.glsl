@@ -9,5 +9,10 @@ uniform sampler2D imageTexture1;
 uniform sampler2D imageTexture2;
 
 void main() {
+#ifdef TEXTURE_DEBUG
+    // Show the debug view of the textures without the vertex colors tinting it
+    FragColor = mix(debugTexture(imageTexture1, textureCoord), debugTexture(imageTexture2, textureCoord), 0.2);
+#else
     FragColor = mix(texture(imageTexture1, textureCoord), texture(imageTexture2, textureCoord), 0.2) * vertexColor;
-}
\ No newline at end of file
+#endif
+}
@@ -0,0 +1,73 @@
+use glow::HasContext;
+
+use crate::texture::{AlphaMode, Texture};
+
+/// Blending presets for drawing transparent things like sprites
+///
+/// The default is `Premultiplied`, which is what textures loaded with `premultiply` set need.
+/// Mixing up straight and premultiplied alpha gives dark or bright fringes around the edges of
+/// sprites, so use `check_texture` to get a warning when a texture doesn't match the blend mode.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    /// No blending, the source replaces the destination
+    Opaque,
+    /// Blending for straight alpha colors: `src * src_alpha + dst * ( 1 - src_alpha )`
+    Straight,
+    /// Blending for premultiplied alpha colors: `src + dst * ( 1 - src_alpha )`
+    #[default]
+    Premultiplied,
+    /// Add the source on top of the destination, for things like glows and particles
+    Additive,
+}
+
+impl BlendMode {
+    /// Set the GL blend state for this mode
+    pub fn apply(self, gl: &mut glow::Context) {
+        unsafe {
+            match self {
+                BlendMode::Opaque => {
+                    gl.disable(glow::BLEND);
+                    return;
+                }
+                BlendMode::Straight => {
+                    // Blend the alpha channel as premultiplied so the destination alpha stays
+                    // correct when drawing into a transparent framebuffer
+                    gl.blend_func_separate(
+                        glow::SRC_ALPHA,
+                        glow::ONE_MINUS_SRC_ALPHA,
+                        glow::ONE,
+                        glow::ONE_MINUS_SRC_ALPHA,
+                    )
+                }
+                BlendMode::Premultiplied => gl.blend_func(glow::ONE, glow::ONE_MINUS_SRC_ALPHA),
+                BlendMode::Additive => gl.blend_func(glow::ONE, glow::ONE),
+            }
+            gl.blend_equation(glow::FUNC_ADD);
+            gl.enable(glow::BLEND);
+        }
+    }
+
+    /// Whether or not colors with the given alpha mode blend correctly with this mode
+    pub fn is_compatible_with(self, alpha: AlphaMode) -> bool {
+        !matches!(
+            (self, alpha),
+            (BlendMode::Straight, AlphaMode::Premultiplied)
+                | (BlendMode::Premultiplied, AlphaMode::Straight)
+        )
+    }
+
+    /// Check that the texture's alpha matches this blend mode, printing a warning if it doesn't
+    ///
+    /// This is meant to be called once when pairing a texture with a blend mode, e.g. in a
+    /// handler's `init`, rather than every frame.
+    pub fn check_texture(self, texture: &Texture) -> bool {
+        let compatible = self.is_compatible_with(texture.alpha);
+        if !compatible {
+            eprintln!(
+                "Warning: texture {} has {:?} alpha but is drawn with {:?} blending",
+                texture.texture, texture.alpha, self
+            );
+        }
+        compatible
+    }
+}
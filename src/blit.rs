@@ -0,0 +1,156 @@
+use std::fmt;
+
+use glow::HasContext;
+
+use crate::viewport::Rect;
+
+/// How an image is scaled when it is copied into a framebuffer of a different shape
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BlitFit {
+    /// Scale the whole image to cover the whole destination, stretching it if the shapes differ
+    Stretch,
+    /// Scale the whole image to fit inside the destination, keeping its shape, and center it
+    /// with bars around it
+    #[default]
+    Fit,
+    /// Scale the image to cover the whole destination, keeping its shape, and crop off the edges
+    /// that don't fit
+    Fill,
+}
+
+/// A rectangle that doesn't lie within the framebuffer it is used on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BlitRectError {
+    Source { rect: Rect, size: (u32, u32) },
+    Destination { rect: Rect, size: (u32, u32) },
+}
+
+impl fmt::Display for BlitRectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (name, rect, (width, height)) = match self {
+            BlitRectError::Source { rect, size } => ("source", rect, size),
+            BlitRectError::Destination { rect, size } => ("destination", rect, size),
+        };
+        write!(
+            f,
+            "The {} rect {}x{} at ({}, {}) isn't inside of the {}x{} framebuffer",
+            name, rect.width, rect.height, rect.x, rect.y, width, height
+        )
+    }
+}
+
+impl std::error::Error for BlitRectError {}
+
+/// The part of the read framebuffer to copy, and the part of the draw framebuffer to copy it to,
+/// for `glBlitFramebuffer`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlitRect {
+    pub source: Rect,
+    pub destination: Rect,
+}
+
+impl BlitRect {
+    pub fn new(source: Rect, destination: Rect) -> Self {
+        Self {
+            source,
+            destination,
+        }
+    }
+
+    /// Copy all of a framebuffer of `source_size` into a framebuffer of `destination_size`,
+    /// scaled with `fit`
+    pub fn fit(source_size: (u32, u32), destination_size: (u32, u32), fit: BlitFit) -> Self {
+        let source = Rect::from_window_size(source_size);
+        let destination = Rect::from_window_size(destination_size);
+        let (width, height) = (source_size.0.max(1) as f64, source_size.1.max(1) as f64);
+        let (scale_x, scale_y) = (
+            destination_size.0 as f64 / width,
+            destination_size.1 as f64 / height,
+        );
+        match fit {
+            BlitFit::Stretch => Self::new(source, destination),
+            BlitFit::Fit => {
+                let scale = scale_x.min(scale_y);
+                Self::new(
+                    source,
+                    centered(destination_size, width * scale, height * scale),
+                )
+            }
+            BlitFit::Fill => {
+                let scale = scale_x.max(scale_y);
+                let cropped = centered(
+                    source_size,
+                    destination_size.0 as f64 / scale,
+                    destination_size.1 as f64 / scale,
+                );
+                Self::new(cropped, destination)
+            }
+        }
+    }
+
+    /// Make sure the rects lie within framebuffers of the given sizes
+    pub fn validate(
+        &self,
+        source_size: (u32, u32),
+        destination_size: (u32, u32),
+    ) -> Result<(), BlitRectError> {
+        if !within(&self.source, source_size) {
+            return Err(BlitRectError::Source {
+                rect: self.source,
+                size: source_size,
+            });
+        }
+        if !within(&self.destination, destination_size) {
+            return Err(BlitRectError::Destination {
+                rect: self.destination,
+                size: destination_size,
+            });
+        }
+        Ok(())
+    }
+
+    /// Copy the source rect of the bound read framebuffer into the destination rect of the bound
+    /// draw framebuffer
+    ///
+    /// `mask` is the buffers to copy, like `COLOR_BUFFER_BIT`, and `filter` is `NEAREST` or
+    /// `LINEAR`. Depth and stencil can only be copied with `NEAREST`.
+    pub fn blit(&self, gl: &mut glow::Context, mask: u32, filter: u32) {
+        let (source, destination) = (self.source, self.destination);
+        unsafe {
+            gl.blit_framebuffer(
+                source.x,
+                source.y,
+                source.x + source.width,
+                source.y + source.height,
+                destination.x,
+                destination.y,
+                destination.x + destination.width,
+                destination.y + destination.height,
+                mask,
+                filter,
+            );
+        }
+    }
+}
+
+/// A rect of the given size in the middle of a framebuffer, rounded to whole pixels
+fn centered((width, height): (u32, u32), rect_width: f64, rect_height: f64) -> Rect {
+    let rect_width = (rect_width.round() as i32).clamp(0, width as i32);
+    let rect_height = (rect_height.round() as i32).clamp(0, height as i32);
+    Rect::new(
+        (width as i32 - rect_width) / 2,
+        (height as i32 - rect_height) / 2,
+        rect_width,
+        rect_height,
+    )
+}
+
+/// Whether or not a rect lies within a framebuffer of the given size
+fn within(rect: &Rect, (width, height): (u32, u32)) -> bool {
+    rect.x >= 0
+        && rect.y >= 0
+        && rect.width >= 0
+        && rect.height >= 0
+        && rect.x + rect.width <= width as i32
+        && rect.y + rect.height <= height as i32
+}
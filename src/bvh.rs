@@ -0,0 +1,321 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3};
+
+use crate::{frustum::Aabb, mesh::MeshData};
+
+/// The most triangles in a leaf of a `TriangleBvh`
+const MAX_LEAF_TRIANGLES: usize = 4;
+
+/// A node of a `TriangleBvh`
+#[derive(Clone, Copy, Debug)]
+struct Node {
+    aabb: Aabb,
+    /// For a leaf, where its triangles start in `TriangleBvh::order`. For any other node, the
+    /// index of its second child, the first one being right after it.
+    start: u32,
+    /// The number of triangles in a leaf, which is 0 for every other node
+    count: u32,
+}
+
+/// Where a ray hit a triangle, from `TriangleBvh::raycast`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RayHit {
+    /// How far along the ray the hit is
+    pub distance: f32,
+    pub point: Point3<f32>,
+    /// The normal of the triangle, facing back towards where the ray came from
+    pub normal: Vector3<f32>,
+    /// The index of the triangle, in the order the triangles were given in
+    pub triangle: usize,
+}
+
+/// A bounding volume hierarchy over the triangles of static meshes, for finding the triangles
+/// near a shape or along a ray without testing every one of them
+///
+/// The triangles are copied in world space when the hierarchy is built, and split in half at the
+/// median of their centers along the longest axis of each node until a few are left in each leaf.
+/// Building over a hundred thousand triangles takes tens of milliseconds, so it is meant to be
+/// done once when a scene is loaded. Meshes that move need their own hierarchy each, with the
+/// queries moved into their space.
+#[derive(Clone, Debug, Default)]
+pub struct TriangleBvh {
+    /// The corners of every triangle, in the order they were given in
+    triangles: Vec<[Point3<f32>; 3]>,
+    nodes: Vec<Node>,
+    /// The indices of the triangles, grouped by leaf
+    order: Vec<u32>,
+}
+
+impl TriangleBvh {
+    /// Build a hierarchy over some triangles
+    pub fn new(triangles: Vec<[Point3<f32>; 3]>) -> Self {
+        let centroids = triangles
+            .iter()
+            .map(|[a, b, c]| Point3::centroid(&[*a, *b, *c]))
+            .collect::<Vec<_>>();
+        let mut bvh = Self {
+            order: (0..triangles.len() as u32).collect(),
+            nodes: Vec::with_capacity(2 * triangles.len() / MAX_LEAF_TRIANGLES + 1),
+            triangles,
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.build(&centroids, 0, bvh.order.len());
+        }
+        bvh
+    }
+
+    /// Build a hierarchy over the triangles of some meshes, each moved into the world by its
+    /// transform
+    ///
+    /// The triangles are numbered in the order of the meshes, so the triangle of a `RayHit` can
+    /// be traced back to its mesh by counting the triangles of the meshes before it.
+    pub fn from_meshes<'a, I>(meshes: I) -> Self
+    where
+        I: IntoIterator<Item = (&'a MeshData, Matrix4<f32>)>,
+    {
+        let mut triangles = Vec::new();
+        for (data, transform) in meshes {
+            let corner =
+                |index: u32| transform.transform_point(data.positions[index as usize].into());
+            triangles.extend(data.indices.chunks_exact(3).map(|triangle| {
+                [
+                    corner(triangle[0]),
+                    corner(triangle[1]),
+                    corner(triangle[2]),
+                ]
+            }));
+        }
+        Self::new(triangles)
+    }
+
+    /// Make the node for the triangles in `order[start..end]` and everything under it, returning
+    /// its index
+    fn build(&mut self, centroids: &[Point3<f32>], start: usize, end: usize) -> usize {
+        let triangles = &self.triangles;
+        let aabb = bounds(
+            self.order[start..end]
+                .iter()
+                .flat_map(|&triangle| triangles[triangle as usize].iter().copied()),
+        );
+        let node = self.nodes.len();
+        self.nodes.push(Node {
+            aabb,
+            start: start as u32,
+            count: (end - start) as u32,
+        });
+        if end - start <= MAX_LEAF_TRIANGLES {
+            return node;
+        }
+
+        // Split along the axis that the centers are most spread out on
+        let extent = bounds(
+            self.order[start..end]
+                .iter()
+                .map(|&triangle| centroids[triangle as usize]),
+        )
+        .half_extents();
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        if extent[axis] == 0. {
+            // Every center is in the same place, so there is no splitting them
+            return node;
+        }
+        let middle = (start + end) / 2;
+        self.order[start..end].select_nth_unstable_by(middle - start, |&a, &b| {
+            centroids[a as usize][axis].total_cmp(&centroids[b as usize][axis])
+        });
+
+        self.build(centroids, start, middle);
+        let second = self.build(centroids, middle, end);
+        self.nodes[node].start = second as u32;
+        self.nodes[node].count = 0;
+        node
+    }
+
+    /// The number of triangles
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    /// The number of nodes, including the leaves
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// The corners of a triangle, by its index in the order the triangles were given in
+    pub fn triangle(&self, index: usize) -> [Point3<f32>; 3] {
+        self.triangles[index]
+    }
+
+    /// The box around every triangle, or `None` if there aren't any
+    pub fn aabb(&self) -> Option<Aabb> {
+        self.nodes.first().map(|node| node.aabb)
+    }
+
+    /// Call `visit` with the index and corners of every triangle in a leaf whose box touches
+    /// `aabb`, which includes every triangle that touches it and some that are only near it
+    pub fn query_aabb<F: FnMut(usize, &[Point3<f32>; 3])>(&self, aabb: &Aabb, mut visit: F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if !touches(&node.aabb, aabb) {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.start as usize);
+                stack.push(index + 1);
+                continue;
+            }
+            let start = node.start as usize;
+            for &triangle in &self.order[start..start + node.count as usize] {
+                visit(triangle as usize, &self.triangles[triangle as usize]);
+            }
+        }
+    }
+
+    /// Find the closest triangle that a ray hits within `max_distance` of its origin, from either
+    /// side, e.g. for picking what is under the cursor
+    pub fn raycast(
+        &self,
+        origin: Point3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+    ) -> Option<RayHit> {
+        if self.nodes.is_empty() || direction.magnitude2() == 0. {
+            return None;
+        }
+        let direction = direction.normalize();
+        let inverse = Vector3::new(1. / direction.x, 1. / direction.y, 1. / direction.z);
+        let mut closest: Option<(f32, usize)> = None;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let limit = closest.map_or(max_distance, |(distance, _)| distance);
+            let node = &self.nodes[index];
+            match ray_aabb(origin, inverse, &node.aabb) {
+                Some(entry) if entry <= limit => {}
+                _ => continue,
+            }
+            if node.count == 0 {
+                // Visit the nearer child first, so that the further one can often be skipped
+                let (first, second) = (index + 1, node.start as usize);
+                let near = |child: usize| {
+                    ray_aabb(origin, inverse, &self.nodes[child].aabb).unwrap_or(f32::INFINITY)
+                };
+                if near(first) <= near(second) {
+                    stack.push(second);
+                    stack.push(first);
+                } else {
+                    stack.push(first);
+                    stack.push(second);
+                }
+                continue;
+            }
+            let start = node.start as usize;
+            for &triangle in &self.order[start..start + node.count as usize] {
+                let triangle = triangle as usize;
+                if let Some(distance) = ray_triangle(origin, direction, &self.triangles[triangle]) {
+                    let limit = closest.map_or(max_distance, |(distance, _)| distance);
+                    if distance <= limit {
+                        closest = Some((distance, triangle));
+                    }
+                }
+            }
+        }
+
+        let (distance, triangle) = closest?;
+        let [a, b, c] = self.triangles[triangle];
+        let mut normal = (b - a).cross(c - a).normalize();
+        if normal.dot(direction) > 0. {
+            normal = -normal;
+        }
+        Some(RayHit {
+            distance,
+            point: origin + direction * distance,
+            normal,
+            triangle,
+        })
+    }
+}
+
+/// The box around some points, which there has to be at least one of
+fn bounds<I: Iterator<Item = Point3<f32>>>(points: I) -> Aabb {
+    let mut aabb = Aabb {
+        min: [f32::INFINITY; 3],
+        max: [f32::NEG_INFINITY; 3],
+    };
+    for point in points {
+        for axis in 0..3 {
+            aabb.min[axis] = aabb.min[axis].min(point[axis]);
+            aabb.max[axis] = aabb.max[axis].max(point[axis]);
+        }
+    }
+    aabb
+}
+
+/// Whether two boxes overlap or touch
+fn touches(a: &Aabb, b: &Aabb) -> bool {
+    (0..3).all(|axis| a.min[axis] <= b.max[axis] && b.min[axis] <= a.max[axis])
+}
+
+/// How far along a ray it enters a box, or 0 if it starts inside, or `None` if it misses it.
+/// The ray is given by its origin and 1 / its direction.
+fn ray_aabb(origin: Point3<f32>, inverse: Vector3<f32>, aabb: &Aabb) -> Option<f32> {
+    let (mut entry, mut exit) = (0f32, f32::INFINITY);
+    for axis in 0..3 {
+        if inverse[axis].is_infinite() {
+            // The ray runs along the slab, so it is either always in it or never
+            if origin[axis] < aabb.min[axis] || origin[axis] > aabb.max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let near = (aabb.min[axis] - origin[axis]) * inverse[axis];
+        let far = (aabb.max[axis] - origin[axis]) * inverse[axis];
+        entry = entry.max(near.min(far));
+        exit = exit.min(near.max(far));
+    }
+    if entry <= exit {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+/// How far along a ray it hits a triangle from either side, or `None` if it misses it
+fn ray_triangle(
+    origin: Point3<f32>,
+    direction: Vector3<f32>,
+    [a, b, c]: &[Point3<f32>; 3],
+) -> Option<f32> {
+    // Moller-Trumbore, solving for the distance and two barycentric coordinates at once
+    let (edge1, edge2) = (b - a, c - a);
+    let p = direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None;
+    }
+    let inverse = 1. / determinant;
+    let offset = origin - a;
+    let u = offset.dot(p) * inverse;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+    let q = offset.cross(edge1);
+    let v = direction.dot(q) * inverse;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+    let distance = edge2.dot(q) * inverse;
+    if distance >= 0. {
+        Some(distance)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,496 @@
+use cgmath::{ortho, Deg, InnerSpace, Matrix4, Point2, Point3, Rad, Vector2, Vector3};
+use winit::{MouseButton, VirtualKeyCode};
+
+use crate::{
+    demo_state::{Persist, StateBlob},
+    depth_mode,
+    stereo::{self, Eye},
+    tween::{Easing, Tween},
+    AppContext,
+};
+
+/// How much perspective is left when a transition to orthographic switches to the real
+/// orthographic projection, which is small enough that the switch can't be seen
+const MIN_PERSPECTIVE: f32 = 0.01;
+
+/// Where the projection of a `FlyCamera` clips, which moves while it switches modes
+struct ProjectionPlanes {
+    /// The distance that things are the same size at in both modes
+    focus: f32,
+    near: f32,
+    far: f32,
+    /// How far behind the camera the projection looks from
+    pullback: f32,
+    orthographic: bool,
+}
+
+/// How a camera projects the scene onto the screen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ProjectionMode {
+    /// Things further away look smaller
+    #[default]
+    Perspective,
+    /// Things look the same size no matter how far away they are, for technical views of models
+    Orthographic,
+}
+
+impl ProjectionMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ProjectionMode::Perspective => "perspective",
+            ProjectionMode::Orthographic => "orthographic",
+        }
+    }
+
+    /// The mode with the given name, like `orthographic`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "perspective" => Some(ProjectionMode::Perspective),
+            "orthographic" => Some(ProjectionMode::Orthographic),
+            _ => None,
+        }
+    }
+
+    /// How much perspective the mode has, from 1 for perspective to 0 for orthographic
+    fn perspective_amount(self) -> f32 {
+        match self {
+            ProjectionMode::Perspective => 1.,
+            ProjectionMode::Orthographic => 0.,
+        }
+    }
+}
+
+/// A camera that flies around with WASD and looks around with the mouse
+///
+/// Hold the right mouse button to look around, which grabs and hides the cursor until it is
+/// released. Q and E move down and up, and holding shift moves faster.
+#[derive(Clone, Debug)]
+pub struct FlyCamera {
+    pub position: Point3<f32>,
+    /// The rotation around the vertical axis in degrees, where 0 looks along -Z
+    pub yaw: f32,
+    /// The rotation up or down in degrees
+    pub pitch: f32,
+    /// The vertical field of view in degrees
+    pub fov: f32,
+    /// How fast the camera moves in world units per second
+    pub move_speed: f32,
+    /// How far the camera turns in degrees for each unit of mouse movement
+    pub look_sensitivity: f32,
+    /// The distance to the near clipping plane
+    pub near: f32,
+    /// The distance to the far clipping plane
+    pub far: f32,
+    /// The distance to what the camera is looking at, where things are the same size on screen in
+    /// both projection modes
+    pub focus_distance: f32,
+    /// How far in front of and behind the focus distance the orthographic projection reaches,
+    /// within the near and far planes
+    ///
+    /// Depth is linear in orthographic projection, so its precision is spread evenly between the
+    /// planes. Using the far plane of a big scene would leave too little of it for the things
+    /// being looked at.
+    pub ortho_depth: f32,
+    /// Whether or not to grab the cursor while looking around
+    pub grab_cursor: bool,
+    /// Whether the camera grabbed the cursor and hasn't released it yet
+    grabbing: bool,
+    projection_mode: ProjectionMode,
+    /// How much perspective the projection has right now, from 1 for perspective to 0 for
+    /// orthographic
+    perspective_amount: f32,
+    /// The animation of `perspective_amount` while switching modes
+    transition: Option<Tween<f32>>,
+    /// The view-projection matrix that last frame was drawn with, as given to `end_frame`
+    previous_view_projection: Option<Matrix4<f32>>,
+}
+
+impl FlyCamera {
+    pub fn new(position: Point3<f32>, yaw: f32, pitch: f32) -> Self {
+        Self {
+            position,
+            yaw,
+            pitch,
+            fov: 60.,
+            move_speed: 5.,
+            look_sensitivity: 0.15,
+            near: 0.1,
+            far: 1000.,
+            focus_distance: 10.,
+            ortho_depth: 100.,
+            grab_cursor: true,
+            grabbing: false,
+            projection_mode: ProjectionMode::Perspective,
+            perspective_amount: 1.,
+            transition: None,
+            previous_view_projection: None,
+        }
+    }
+
+    /// The projection mode that the camera is in, or is switching to
+    pub fn projection_mode(&self) -> ProjectionMode {
+        self.projection_mode
+    }
+
+    /// Switch to another projection mode right away
+    ///
+    /// Things at `focus_distance` stay the same size on screen: the orthographic view is as tall
+    /// as the perspective frustum is at that distance.
+    pub fn set_projection_mode(&mut self, mode: ProjectionMode) {
+        self.projection_mode = mode;
+        self.perspective_amount = mode.perspective_amount();
+        self.transition = None;
+    }
+
+    /// Switch to another projection mode over `duration` seconds
+    ///
+    /// The field of view narrows or widens while the projection moves back or forward to keep
+    /// things at `focus_distance` the same size, like a dolly zoom, so the view changes smoothly
+    /// instead of the projection matrices being blended, which would warp it. The transition
+    /// moves with the real frame time in `update`.
+    pub fn animate_projection_mode(&mut self, mode: ProjectionMode, duration: f32) {
+        if duration <= 0. {
+            self.set_projection_mode(mode);
+            return;
+        }
+        self.projection_mode = mode;
+        self.transition = Some(
+            Tween::new(self.perspective_amount, mode.perspective_amount(), duration)
+                .easing(Easing::CubicInOut),
+        );
+    }
+
+    /// Whether or not the camera is switching between projection modes
+    pub fn is_changing_projection(&self) -> bool {
+        self.transition.is_some()
+    }
+
+    /// The direction that the camera is looking
+    pub fn forward(&self) -> Vector3<f32> {
+        let (yaw, pitch) = (self.yaw.to_radians(), self.pitch.to_radians());
+        Vector3::new(
+            yaw.sin() * pitch.cos(),
+            pitch.sin(),
+            -yaw.cos() * pitch.cos(),
+        )
+    }
+
+    /// Turn the camera to look in the given direction
+    pub fn look_in_direction(&mut self, direction: Vector3<f32>) {
+        let direction = direction.normalize();
+        self.yaw = direction.x.atan2(-direction.z).to_degrees();
+        self.pitch = direction.y.clamp(-1., 1.).asin().to_degrees();
+    }
+
+    /// Move and turn the camera from the input of the last frame
+    pub fn update(&mut self, ctx: &mut AppContext) {
+        self.update_look(ctx);
+        self.update_projection(ctx.timing.delta());
+
+        let movement = self.movement_input(ctx, self.forward());
+        if movement.magnitude2() > 0. {
+            let speed = if ctx.input.modifiers().shift {
+                self.move_speed * 4.
+            } else {
+                self.move_speed
+            };
+            // Use the real frame time so the camera still moves while the animation is paused
+            self.position += movement.normalize() * speed * ctx.timing.delta();
+        }
+    }
+
+    /// Move a projection mode transition forward by some seconds, for when something else moves
+    /// the camera
+    pub fn update_projection(&mut self, seconds: f32) {
+        if let Some(transition) = &mut self.transition {
+            transition.advance(seconds);
+            self.perspective_amount = transition.value();
+            if transition.is_finished() {
+                self.transition = None;
+            }
+        }
+    }
+
+    /// Turn the camera from the mouse movement of the last frame, for when something else moves
+    /// it
+    pub fn update_look(&mut self, ctx: &mut AppContext) {
+        // Look around while the right mouse button is held, with the cursor out of the way. Only
+        // touch the grab when looking starts or stops, so the handler can grab it otherwise.
+        let looking = ctx.input.is_mouse_pressed(MouseButton::Right);
+        let grab = self.grab_cursor && looking;
+        if grab != self.grabbing {
+            ctx.set_cursor_grab(grab);
+            self.grabbing = grab;
+        }
+
+        let input = &ctx.input;
+        if looking {
+            let (dx, dy) = input.mouse_delta();
+            self.yaw += dx as f32 * self.look_sensitivity;
+            // Don't let the camera flip over the top
+            self.pitch = (self.pitch - dy as f32 * self.look_sensitivity).clamp(-89., 89.);
+        }
+    }
+
+    /// The direction the held movement keys point in, with W and S moving along `forward`, A and D
+    /// to the side of it, and Q and E down and up. This isn't normalized, and is zero when no
+    /// keys are held.
+    pub fn movement_input(&self, ctx: &AppContext, forward: Vector3<f32>) -> Vector3<f32> {
+        let input = &ctx.input;
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let mut movement = Vector3::new(0., 0., 0.);
+        if input.is_key_pressed(VirtualKeyCode::W) {
+            movement += forward;
+        }
+        if input.is_key_pressed(VirtualKeyCode::S) {
+            movement -= forward;
+        }
+        if input.is_key_pressed(VirtualKeyCode::D) {
+            movement += right;
+        }
+        if input.is_key_pressed(VirtualKeyCode::A) {
+            movement -= right;
+        }
+        if input.is_key_pressed(VirtualKeyCode::E) {
+            movement += Vector3::unit_y();
+        }
+        if input.is_key_pressed(VirtualKeyCode::Q) {
+            movement -= Vector3::unit_y();
+        }
+        movement
+    }
+
+    /// The view matrix of the camera
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::look_at_dir(self.position, self.forward(), Vector3::unit_y())
+    }
+
+    /// The view matrix of one of the camera's eyes, for stereo, with the eyes `separation` apart
+    /// and their views lining up at `convergence` in front of the camera
+    pub fn eye_view(&self, eye: Eye, separation: f32, convergence: f32) -> Matrix4<f32> {
+        stereo::eye_offset(eye, separation, convergence) * self.view_matrix()
+    }
+
+    /// The projection matrix of the camera for a viewport with the given aspect ratio
+    ///
+    /// An aspect ratio that isn't a positive number, like one from a zero-sized window, is taken
+    /// as 1 instead of filling the matrix with NaNs. The matrix is built for the current
+    /// `depth_mode::DepthSetup`, so it follows `RenderSettings::depth_mode` while the loop draws.
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Matrix4<f32> {
+        let aspect_ratio = if aspect_ratio.is_finite() && aspect_ratio > 0. {
+            aspect_ratio
+        } else {
+            1.
+        };
+        let depth = depth_mode::current();
+        let planes = self.projection_planes();
+        if self.perspective_amount >= 1. {
+            return depth.perspective(Deg(self.fov), aspect_ratio, planes.near, planes.far);
+        }
+
+        // Half of the height of the view at the focus distance, which both modes keep
+        let tan_half_fov = Rad::from(Deg(self.fov / 2.)).0.tan();
+        let half_height = planes.focus * tan_half_fov;
+        if planes.orthographic {
+            let half_width = half_height * aspect_ratio;
+            return depth.projection(ortho(
+                -half_width,
+                half_width,
+                -half_height,
+                half_height,
+                planes.near,
+                planes.far,
+            ));
+        }
+
+        // Narrow the field of view and move the eye back until the focus distance is the same
+        // height. The clipping planes move back with it, so they stay at the same depths in the
+        // scene and don't lose depth precision to the empty space behind the camera.
+        let fov = Rad(2. * (tan_half_fov * self.perspective_amount).atan());
+        depth.perspective(fov, aspect_ratio, planes.near, planes.far)
+            * Matrix4::from_translation(Vector3::new(0., 0., -planes.pullback))
+    }
+
+    /// The near and far planes of the projection, measured from where the projection is looking
+    /// from, and whether it is orthographic, for turning its depth back into distances
+    ///
+    /// While switching modes the projection looks from behind the camera, so the planes are
+    /// further away than `near` and `far`.
+    pub fn clip_planes(&self) -> (f32, f32, bool) {
+        let planes = self.projection_planes();
+        (planes.near, planes.far, planes.orthographic)
+    }
+
+    /// The view-projection matrix that last frame was drawn with, or `None` on the first frame or
+    /// after `forget_previous_frame`, where nothing should look like it moved
+    pub fn previous_view_projection(&self) -> Option<Matrix4<f32>> {
+        self.previous_view_projection
+    }
+
+    /// Remember the view-projection matrix that this frame was drawn with, for
+    /// `previous_view_projection` to give back next frame
+    pub fn end_frame(&mut self, view_projection: Matrix4<f32>) {
+        self.previous_view_projection = Some(view_projection);
+    }
+
+    /// Forget last frame's view-projection matrix, e.g. after the camera jumped somewhere, so
+    /// that the jump doesn't show up as movement
+    pub fn forget_previous_frame(&mut self) {
+        self.previous_view_projection = None;
+    }
+
+    fn projection_planes(&self) -> ProjectionPlanes {
+        let focus = self.focus_distance.max(self.near);
+        let amount = self.perspective_amount;
+        if amount >= 1. {
+            return ProjectionPlanes {
+                focus,
+                near: self.near,
+                far: self.far,
+                pullback: 0.,
+                orthographic: false,
+            };
+        }
+
+        // The orthographic planes hug the focus distance, and the transition moves between them
+        // and the perspective ones
+        let ortho_near = self.near.max(focus - self.ortho_depth);
+        let ortho_far = self.far.min(focus + self.ortho_depth).max(ortho_near + 1.);
+        let near = ortho_near + (self.near - ortho_near) * amount;
+        let far = ortho_far + (self.far - ortho_far) * amount;
+        if amount <= MIN_PERSPECTIVE {
+            return ProjectionPlanes {
+                focus,
+                near,
+                far,
+                pullback: 0.,
+                orthographic: true,
+            };
+        }
+        let pullback = focus / amount - focus;
+        ProjectionPlanes {
+            focus,
+            near: near + pullback,
+            far: far + pullback,
+            pullback,
+            orthographic: false,
+        }
+    }
+}
+
+/// A camera that looks straight down at a flat 2D world, like a tilemap
+///
+/// The world has +X to the right and +Y up. WASD or the arrow keys pan, holding shift pans faster,
+/// and scrolling zooms in and out. The view is the size of the viewport that `update` last saw, so
+/// one world unit always covers `zoom` pixels.
+#[derive(Clone, Debug)]
+pub struct OrthoCamera {
+    /// The point of the world in the middle of the view
+    pub center: Point2<f32>,
+    /// How many pixels each world unit covers
+    pub zoom: f32,
+    /// How far the camera can zoom out and in
+    pub min_zoom: f32,
+    pub max_zoom: f32,
+    /// How fast the camera pans in pixels per second, so it feels the same at every zoom
+    pub pan_speed: f32,
+    /// The size of the viewport in pixels
+    pub viewport_size: (u32, u32),
+}
+
+impl OrthoCamera {
+    pub fn new(center: Point2<f32>, zoom: f32) -> Self {
+        Self {
+            center,
+            zoom,
+            min_zoom: 0.25,
+            max_zoom: 256.,
+            pan_speed: 600.,
+            viewport_size: (1, 1),
+        }
+    }
+
+    /// Pan and zoom from the frame's input, and follow the size of the render target
+    pub fn update(&mut self, ctx: &mut AppContext) {
+        self.viewport_size = ctx.render_size();
+
+        let input = &ctx.input;
+        let (_, scroll) = input.scroll_delta();
+        if scroll != 0. {
+            self.zoom = (self.zoom * 1.25f32.powf(scroll)).clamp(self.min_zoom, self.max_zoom);
+        }
+
+        let held = |key, arrow| input.is_key_pressed(key) || input.is_key_pressed(arrow);
+        let mut movement = Vector2::new(0., 0.);
+        if held(VirtualKeyCode::W, VirtualKeyCode::Up) {
+            movement += Vector2::unit_y();
+        }
+        if held(VirtualKeyCode::S, VirtualKeyCode::Down) {
+            movement -= Vector2::unit_y();
+        }
+        if held(VirtualKeyCode::D, VirtualKeyCode::Right) {
+            movement += Vector2::unit_x();
+        }
+        if held(VirtualKeyCode::A, VirtualKeyCode::Left) {
+            movement -= Vector2::unit_x();
+        }
+        if movement.magnitude2() > 0. {
+            let speed = if input.modifiers().shift {
+                self.pan_speed * 4.
+            } else {
+                self.pan_speed
+            };
+            // Use the real frame time so the camera still moves while the animation is paused
+            self.center += movement.normalize() * speed / self.zoom * ctx.timing.delta();
+        }
+    }
+
+    /// The bottom left and top right corners of the part of the world in view
+    pub fn visible_bounds(&self) -> (Point2<f32>, Point2<f32>) {
+        let (width, height) = self.viewport_size;
+        let half_size = Vector2::new(width.max(1) as f32, height.max(1) as f32) / (2. * self.zoom);
+        (self.center - half_size, self.center + half_size)
+    }
+
+    /// The matrix from the world to clip space
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        let (min, max) = self.visible_bounds();
+        ortho(min.x, max.x, min.y, max.y, -1., 1.)
+    }
+}
+
+/// Keeps where the camera is, where it looks, and how it projects, but not its speeds and planes,
+/// which handlers set up for their scenes
+impl Persist for FlyCamera {
+    fn save_to(&self, blob: &mut StateBlob) {
+        blob.set_floats(
+            "position",
+            &[self.position.x, self.position.y, self.position.z],
+        );
+        blob.set("yaw", self.yaw);
+        blob.set("pitch", self.pitch);
+        blob.set("fov", self.fov);
+        blob.set("focus_distance", self.focus_distance);
+        blob.set("projection", self.projection_mode.name());
+    }
+
+    fn restore_from(&mut self, blob: &StateBlob) -> Result<(), String> {
+        let [x, y, z] = blob.get_floats("position")?;
+        let yaw = blob.get_float("yaw")?;
+        let pitch = blob.get_float("pitch")?;
+        let fov = blob.get_float("fov")?;
+        let focus_distance = blob.get_float("focus_distance")?;
+        let projection = blob.get::<String>("projection")?;
+        let projection = ProjectionMode::parse(&projection)
+            .ok_or_else(|| format!("Unknown projection `{}`", projection))?;
+
+        self.position = Point3::new(x, y, z);
+        self.yaw = yaw;
+        self.pitch = pitch.clamp(-89., 89.);
+        self.fov = fov;
+        self.focus_distance = focus_distance;
+        self.set_projection_mode(projection);
+        self.forget_previous_frame();
+        Ok(())
+    }
+}
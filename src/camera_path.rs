@@ -0,0 +1,311 @@
+use std::{fmt::Write as _, io, path::Path};
+
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Point3, Quaternion, Rad, Rotation, Rotation3, Vector3,
+};
+
+use crate::camera::FlyCamera;
+
+/// A point on a camera path
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CameraKeyframe {
+    /// The time in seconds from the start of the path
+    pub time: f32,
+    pub position: Point3<f32>,
+    /// The point that the camera looks at
+    pub target: Point3<f32>,
+}
+
+impl CameraKeyframe {
+    /// The orientation of a camera at the keyframe's position looking at its target
+    fn orientation(&self) -> Quaternion<f32> {
+        let direction = (self.target - self.position).normalize();
+        // The same yaw and pitch as `FlyCamera`, so that paths never roll the camera
+        let yaw = Deg::from(Rad(direction.x.atan2(-direction.z)));
+        let pitch = Deg::from(Rad(direction.y.clamp(-1., 1.).asin()));
+        Quaternion::from_angle_y(-yaw) * Quaternion::from_angle_x(pitch)
+    }
+}
+
+/// A smooth camera flight through a list of keyframes
+///
+/// Positions are interpolated with a Catmull-Rom spline and orientations with slerp. The camera
+/// comes to a stop at both ends of the path, so a path with only two keyframes eases from one to
+/// the other.
+///
+/// The path is driven by whatever time is passed to `sample`. Advancing it with
+/// `Timing::delta` while `Timing::set_fixed_delta` is in use gives the same smooth motion no
+/// matter how fast the frames are actually drawn.
+///
+/// Paths can be saved to and loaded from RON files.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CameraPath {
+    /// The keyframes, sorted by time
+    keyframes: Vec<CameraKeyframe>,
+}
+
+impl CameraPath {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The keyframes, sorted by time
+    pub fn keyframes(&self) -> &[CameraKeyframe] {
+        &self.keyframes
+    }
+
+    /// Add a keyframe, keeping the keyframes sorted by time
+    pub fn add_keyframe(&mut self, keyframe: CameraKeyframe) {
+        let index = self
+            .keyframes
+            .iter()
+            .position(|k| k.time > keyframe.time)
+            .unwrap_or(self.keyframes.len());
+        self.keyframes.insert(index, keyframe);
+    }
+
+    /// Add a keyframe at the camera's current pose, `seconds_after` the last keyframe
+    pub fn add_camera_keyframe(&mut self, camera: &FlyCamera, seconds_after: f32) {
+        let time = self.duration()
+            + if self.keyframes.is_empty() {
+                0.
+            } else {
+                seconds_after
+            };
+        self.add_keyframe(CameraKeyframe {
+            time,
+            position: camera.position,
+            target: camera.position + camera.forward(),
+        });
+    }
+
+    /// Remove all of the keyframes
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+
+    /// The time of the last keyframe
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0., |k| k.time)
+    }
+
+    /// The camera position and orientation at the given time, or `None` if the path has no
+    /// keyframes
+    ///
+    /// Times before the first keyframe or after the last one are clamped to the ends of the path.
+    /// The orientation rotates -Z to the camera's forward direction.
+    pub fn sample(&self, time: f32) -> Option<(Point3<f32>, Quaternion<f32>)> {
+        let keyframes = &self.keyframes;
+        let last = keyframes.len().checked_sub(1)?;
+
+        if last == 0 || time <= keyframes[0].time {
+            return Some((keyframes[0].position, keyframes[0].orientation()));
+        }
+        if time >= keyframes[last].time {
+            return Some((keyframes[last].position, keyframes[last].orientation()));
+        }
+
+        // Find the segment that the time is in
+        let segment = keyframes
+            .iter()
+            .rposition(|k| k.time <= time)
+            .unwrap_or(0)
+            .min(last - 1);
+
+        let (start, end) = (&keyframes[segment], &keyframes[segment + 1]);
+        let t = ((time - start.time) / (end.time - start.time).max(f32::EPSILON)).clamp(0., 1.);
+
+        // Catmull-Rom needs a point on either side of the segment. At the ends of the path, using
+        // the point on the other side as the missing neighbor gives the spline a zero tangent
+        // there, which eases in and out.
+        let before = if segment == 0 {
+            end.position
+        } else {
+            keyframes[segment - 1].position
+        };
+        let after = if segment + 1 == last {
+            start.position
+        } else {
+            keyframes[segment + 2].position
+        };
+        let position = catmull_rom(before, start.position, end.position, after, t);
+
+        // Ease the rotation the same way at the ends of the path
+        let rotation_t = match (segment == 0, segment + 1 == last) {
+            (true, true) => t * t * (3. - 2. * t),
+            (true, false) => t * t * (2. - t),
+            (false, true) => t * (1. + t - t * t),
+            (false, false) => t,
+        };
+        let orientation = start
+            .orientation()
+            .slerp(end.orientation(), rotation_t)
+            .normalize();
+
+        Some((position, orientation))
+    }
+
+    /// Move the camera to where the path is at the given time
+    pub fn apply(&self, time: f32, camera: &mut FlyCamera) {
+        if let Some((position, orientation)) = self.sample(time) {
+            camera.position = position;
+            camera.look_in_direction(orientation.rotate_vector(-Vector3::unit_z()));
+        }
+    }
+
+    /// Write the path in RON format
+    pub fn to_ron(&self) -> String {
+        let mut ron = String::from("CameraPath(\n    keyframes: [\n");
+        for k in &self.keyframes {
+            writeln!(
+                ron,
+                "        (time: {:?}, position: ({:?}, {:?}, {:?}), target: ({:?}, {:?}, {:?})),",
+                k.time,
+                k.position.x,
+                k.position.y,
+                k.position.z,
+                k.target.x,
+                k.target.y,
+                k.target.z
+            )
+            .unwrap();
+        }
+        ron.push_str("    ],\n)\n");
+        ron
+    }
+
+    /// Read a path written by `to_ron`
+    ///
+    /// Every keyframe needs a `time`, a `position`, and a `target`, in any order. Comments
+    /// starting with `//` are skipped.
+    pub fn from_ron(ron: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let ron = ron
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let keyframes = ron
+            .find("keyframes")
+            .ok_or_else(|| invalid("The camera path has no keyframes".into()))?;
+        let list = ron[keyframes..]
+            .split_once('[')
+            .and_then(|(_, rest)| rest.split_once(']'))
+            .map(|(list, _)| list)
+            .ok_or_else(|| invalid("The camera path's keyframes aren't a list".into()))?;
+
+        // Every keyframe is a tuple of `name: value` fields
+        let mut path = Self::new();
+        for entry in split_fields(list) {
+            let fields = entry
+                .strip_prefix('(')
+                .and_then(|fields| fields.strip_suffix(')'))
+                .ok_or_else(|| invalid(format!("Invalid keyframe `{}`", entry)))?;
+            path.add_keyframe(parse_keyframe(fields).map_err(invalid)?);
+        }
+        Ok(path)
+    }
+
+    /// Save the path to a RON file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, self.to_ron())
+    }
+
+    /// Load a path from a RON file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_ron(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Split comma separated fields, leaving the commas inside parentheses alone
+fn split_fields(text: &str) -> impl Iterator<Item = &str> {
+    let mut depth = 0;
+    text.split(move |c: char| {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => (),
+        }
+        c == ',' && depth == 0
+    })
+    .map(str::trim)
+    .filter(|field| !field.is_empty())
+}
+
+/// Read the comma separated fields of a keyframe
+fn parse_keyframe(fields: &str) -> Result<CameraKeyframe, String> {
+    let mut time = None;
+    let mut position = None;
+    let mut target = None;
+    for field in split_fields(fields) {
+        let (name, value) = field
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid keyframe field `{}`", field))?;
+        let (name, value) = (name.trim(), value.trim());
+        let number = |value: &str| {
+            value
+                .trim()
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid `{}` of a keyframe: {}", name, value))
+        };
+        let point = || {
+            let numbers = value
+                .strip_prefix('(')
+                .and_then(|value| value.strip_suffix(')'))
+                .map(|value| {
+                    split_fields(value)
+                        .map(number)
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .unwrap_or_else(|| Err(format!("Invalid `{}` of a keyframe: {}", name, value)))?;
+            match numbers[..] {
+                [x, y, z] => Ok(Point3::new(x, y, z)),
+                _ => Err(format!(
+                    "The `{}` of a keyframe needs 3 numbers: {}",
+                    name, value
+                )),
+            }
+        };
+        match name {
+            "time" => time = Some(number(value)?),
+            "position" => position = Some(point()?),
+            "target" => target = Some(point()?),
+            _ => return Err(format!("Unknown keyframe field `{}`", name)),
+        }
+    }
+    match (time, position, target) {
+        (Some(time), Some(position), Some(target)) => Ok(CameraKeyframe {
+            time,
+            position,
+            target,
+        }),
+        _ => Err(format!(
+            "A keyframe is missing its `time`, `position`, or `target`: ({})",
+            fields
+        )),
+    }
+}
+
+/// Interpolate between `p1` and `p2` on a Catmull-Rom spline through `p0`, `p1`, `p2`, and `p3`
+fn catmull_rom(
+    p0: Point3<f32>,
+    p1: Point3<f32>,
+    p2: Point3<f32>,
+    p3: Point3<f32>,
+    t: f32,
+) -> Point3<f32> {
+    let (t2, t3) = (t * t, t * t * t);
+    let weights = [
+        -0.5 * t3 + t2 - 0.5 * t,
+        1.5 * t3 - 2.5 * t2 + 1.,
+        -1.5 * t3 + 2. * t2 + 0.5 * t,
+        0.5 * t3 - 0.5 * t2,
+    ];
+    Point3::from_vec(
+        p0.to_vec() * weights[0]
+            + p1.to_vec() * weights[1]
+            + p2.to_vec() * weights[2]
+            + p3.to_vec() * weights[3],
+    )
+}
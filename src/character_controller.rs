@@ -0,0 +1,239 @@
+use cgmath::{InnerSpace, Point3, Vector3, Zero};
+
+use crate::{
+    bvh::TriangleBvh,
+    collision::{self, Capsule, Contact, SKIN},
+};
+
+/// How many times a move pushes the capsule out of the deepest thing it is in before it gives up
+const MAX_RESOLVE_ITERATIONS: usize = 8;
+
+/// The most pieces that a step's motion is split into, so that a very fast character doesn't
+/// take forever
+const MAX_SUBSTEPS: usize = 16;
+
+/// How far below the feet the ground is looked for while in the air, so that landing on it is
+/// noticed before the capsule sinks into it
+const GROUND_PROBE: f32 = 0.05;
+
+/// The shape of a `CharacterController` and how it gets around
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CharacterParams {
+    /// The radius of the capsule
+    pub radius: f32,
+    /// The height of the capsule from the bottom of its feet to the top of its head
+    pub height: f32,
+    /// The highest ledge that the character walks up onto without jumping
+    pub step_height: f32,
+    /// The steepest slope in degrees that the character can stand on and walk up. It slides down
+    /// anything steeper.
+    pub max_slope: f32,
+    /// The acceleration from gravity in units per second squared
+    pub gravity: Vector3<f32>,
+}
+
+impl Default for CharacterParams {
+    fn default() -> Self {
+        Self {
+            radius: 0.3,
+            height: 1.8,
+            step_height: 0.35,
+            max_slope: 45.,
+            gravity: Vector3::new(0., -9.8, 0.),
+        }
+    }
+}
+
+/// A capsule that walks around static triangle meshes: it stands on slopes up to `max_slope`
+/// without sliding, walks up steps up to `step_height`, sticks to the ground walking down them,
+/// and slides along walls
+///
+/// Step it with a fixed time step, e.g. from `FixedTimestep`, like a `KinematicBody`. The world is
+/// a `TriangleBvh` built once over the scene's meshes, so each step only tests the triangles near
+/// the capsule. Contacts with ground that can be stood on push the capsule straight up, which is
+/// what keeps it from creeping down slopes, and contacts with anything steeper push it sideways,
+/// which is what keeps it from climbing them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CharacterController {
+    pub params: CharacterParams,
+    /// The bottom of the capsule, where the character's feet are
+    pub position: Point3<f32>,
+    /// The velocity in units per second
+    pub velocity: Vector3<f32>,
+    /// Whether the character was standing on ground it can walk on after the last step
+    pub grounded: bool,
+    /// The normal of the ground the character is standing on
+    pub ground_normal: Option<Vector3<f32>>,
+    /// Where the capsule touched the world in the last step, for drawing while tuning
+    pub contacts: Vec<Contact>,
+}
+
+impl CharacterController {
+    pub fn new(position: Point3<f32>, params: CharacterParams) -> Self {
+        Self {
+            params,
+            position,
+            velocity: Vector3::zero(),
+            grounded: false,
+            ground_normal: None,
+            contacts: Vec::new(),
+        }
+    }
+
+    /// The capsule with its feet at `position`
+    pub fn capsule_at(&self, position: Point3<f32>) -> Capsule {
+        let radius = self.params.radius;
+        let height = self.params.height.max(radius * 2.);
+        Capsule {
+            a: position + Vector3::unit_y() * radius,
+            b: position + Vector3::unit_y() * (height - radius),
+            radius,
+        }
+    }
+
+    /// The capsule the character takes up
+    pub fn capsule(&self) -> Capsule {
+        self.capsule_at(self.position)
+    }
+
+    /// Whether a surface with this normal is ground that can be stood on
+    pub fn is_walkable(&self, normal: Vector3<f32>) -> bool {
+        normal.y >= self.params.max_slope.to_radians().cos()
+    }
+
+    /// Move the character by its velocity and gravity over one step of `delta` seconds
+    ///
+    /// The motion is split into pieces no longer than half of the radius, so that fast
+    /// characters can't pass through thin walls. Landing on walkable ground stops the fall and
+    /// makes the character `grounded`, and walking off of a ledge no higher than `step_height`
+    /// keeps it on the ground.
+    pub fn step(&mut self, delta: f32, world: &TriangleBvh) {
+        let was_grounded = self.grounded;
+        self.velocity += self.params.gravity * delta;
+        self.contacts.clear();
+
+        let motion = self.velocity * delta;
+        let longest = (self.params.radius * 0.5).max(SKIN);
+        let substeps = ((motion.magnitude() / longest).ceil() as usize).clamp(1, MAX_SUBSTEPS);
+        for _ in 0..substeps {
+            self.position = self.move_by(world, motion / substeps as f32, was_grounded);
+        }
+
+        // Stop moving into whatever was hit
+        for contact in &self.contacts {
+            let push = self.push_direction(contact.normal);
+            self.velocity -= push * self.velocity.dot(push).min(0.);
+        }
+
+        // Look for ground under the feet, reaching down a whole step while walking so that the
+        // character follows the ground down steps and slopes instead of flying off of them
+        self.ground_normal = None;
+        if self.velocity.y <= 0. {
+            let reach = if was_grounded {
+                self.params.step_height
+            } else {
+                GROUND_PROBE
+            };
+            if let Some((drop, normal)) = self.find_ground(world, self.position, reach) {
+                self.position.y -= drop;
+                self.velocity.y = 0.;
+                self.ground_normal = Some(normal);
+            }
+        }
+        self.grounded = self.ground_normal.is_some();
+    }
+
+    /// Where the feet end up moving from the current position by `motion`, trying to step up
+    /// onto whatever blocks the way if the character is on the ground
+    fn move_by(
+        &mut self,
+        world: &TriangleBvh,
+        motion: Vector3<f32>,
+        grounded: bool,
+    ) -> Point3<f32> {
+        let start = self.position;
+        let mut contacts = Vec::new();
+        let moved = self.resolve(world, start + motion, &mut contacts);
+
+        let horizontal = Vector3::new(motion.x, 0., motion.z);
+        let progress = |position: Point3<f32>| (position - start).dot(horizontal);
+        let blocked = progress(moved) < horizontal.magnitude2() * 0.9;
+        if grounded && blocked && horizontal.magnitude2() > 0. {
+            // Go up a step, across, and back down onto the top of the step
+            let mut step_contacts = Vec::new();
+            let up = Vector3::unit_y() * self.params.step_height;
+            let raised = self.resolve(world, start + up, &mut step_contacts);
+            let across = self.resolve(world, raised + horizontal, &mut step_contacts);
+            let reach = across.y - start.y + GROUND_PROBE;
+            if let Some((drop, _)) = self.find_ground(world, across, reach) {
+                let stepped = across - Vector3::unit_y() * drop;
+                if progress(stepped) > progress(moved) + SKIN {
+                    self.contacts.extend(step_contacts);
+                    return stepped;
+                }
+            }
+        }
+        self.contacts.extend(contacts);
+        moved
+    }
+
+    /// Push the capsule with its feet at `position` out of the world, deepest contact first,
+    /// adding the contacts to `contacts`
+    fn resolve(
+        &self,
+        world: &TriangleBvh,
+        mut position: Point3<f32>,
+        contacts: &mut Vec<Contact>,
+    ) -> Point3<f32> {
+        for _ in 0..MAX_RESOLVE_ITERATIONS {
+            let deepest = match collision::capsule_mesh_contacts(&self.capsule_at(position), world)
+                .into_iter()
+                .next()
+            {
+                Some(deepest) => deepest,
+                None => break,
+            };
+            // Move far enough along the push direction to cover the depth along the normal
+            let push = self.push_direction(deepest.normal);
+            let along = push.dot(deepest.normal).max(0.1);
+            position += push * ((deepest.depth + SKIN) / along);
+            contacts.push(deepest);
+        }
+        position
+    }
+
+    /// The direction that a contact with this normal pushes the character: straight up for
+    /// walkable ground, sideways for slopes that are too steep, and along the normal for
+    /// ceilings and overhangs
+    fn push_direction(&self, normal: Vector3<f32>) -> Vector3<f32> {
+        if self.is_walkable(normal) {
+            return Vector3::unit_y();
+        }
+        let sideways = Vector3::new(normal.x, 0., normal.z);
+        if normal.y > 0. && sideways.magnitude2() > 0. {
+            sideways.normalize()
+        } else {
+            normal
+        }
+    }
+
+    /// How far the feet at `position` can drop, up to `reach`, before they land on walkable
+    /// ground, and the normal of that ground, or `None` if there is no walkable ground within
+    /// reach
+    fn find_ground(
+        &self,
+        world: &TriangleBvh,
+        position: Point3<f32>,
+        reach: f32,
+    ) -> Option<(f32, Vector3<f32>)> {
+        let lowered = self.capsule_at(position - Vector3::unit_y() * reach);
+        collision::capsule_mesh_contacts(&lowered, world)
+            .into_iter()
+            .filter(|contact| self.is_walkable(contact.normal))
+            .map(|contact| {
+                let rise = contact.depth / contact.normal.y;
+                ((reach - rise).max(0.), contact.normal)
+            })
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+    }
+}
@@ -0,0 +1,268 @@
+use std::{collections::BTreeMap, fmt::Write as _, path::PathBuf};
+
+use crate::{handler_factory, with_windows_and_config, Config, RenderHandler, WindowConfig};
+
+/// A command line flag that an example adds to the common ones
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Flag {
+    /// The name of the flag without the leading dashes, like `model`
+    pub name: &'static str,
+    /// What the value of the flag is, like `<file>`, or `None` if the flag doesn't take a value
+    pub value: Option<&'static str>,
+    pub help: &'static str,
+}
+
+impl Flag {
+    /// A flag that takes a value, like `--model <file>`
+    pub const fn with_value(name: &'static str, value: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            value: Some(value),
+            help,
+        }
+    }
+
+    /// A flag that is either there or not, like `--wireframe`
+    pub const fn switch(name: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            value: None,
+            help,
+        }
+    }
+}
+
+/// The flags that every example understands, apart from the config keys
+const COMMON_FLAGS: &[Flag] = &[
+    Flag::with_value(
+        "bench",
+        "<frames>",
+        "Draw this many frames, print the average frame time, and exit",
+    ),
+    Flag::with_value("record", "<file>", "Record the input to a file"),
+    Flag::with_value(
+        "replay",
+        "<file>",
+        "Play back recorded input instead of the live input",
+    ),
+    Flag::switch(
+        "headless",
+        "Keep the window hidden and don't present frames",
+    ),
+    Flag::switch(
+        "reset-window",
+        "Don't restore the saved window placement this time",
+    ),
+    Flag::switch(
+        "fresh",
+        "Don't restore the example's saved state, like its camera, this time",
+    ),
+    Flag::switch("help", "Print this help and exit"),
+];
+
+/// The flags that set config keys, which `Config::apply_args` applies
+const CONFIG_FLAGS: &[Flag] = &[
+    Flag::with_value("width", "<pixels>", "The width of the window"),
+    Flag::with_value("height", "<pixels>", "The height of the window"),
+    Flag::with_value("title", "<title>", "The title of the window"),
+    Flag::with_value("vsync", "<true|false>", "Whether or not to wait for vsync"),
+    Flag::with_value(
+        "msaa",
+        "<samples>",
+        "The number of samples for multisampled render targets",
+    ),
+    Flag::with_value("asset-dir", "<dir>", "The directory to load assets from"),
+    Flag::with_value(
+        "ui-scale",
+        "<scale>",
+        "A multiplier for the size of overlays",
+    ),
+    Flag::with_value(
+        "shader-cache-dir",
+        "<dir>",
+        "The directory to cache linked shaders in, or \"\" to not cache them",
+    ),
+    Flag::with_value(
+        "remember-window",
+        "<true|false>",
+        "Whether or not to save the window's placement on exit and restore it",
+    ),
+    Flag::with_value(
+        "theme",
+        "<name>",
+        "The theme to start with, like dark or light ( F7 switches )",
+    ),
+];
+
+/// The command line arguments of an example, parsed once and merged with the config file
+///
+/// Every example understands the config keys, like `--width`, and the common flags, like
+/// `--bench` and `--record`. Examples can add flags of their own with `parse_with` and read them
+/// with `flag` and `value`.
+#[derive(Clone, Debug)]
+pub struct DemoArgs {
+    /// The config file and environment variables, with the command line overrides applied
+    pub config: Config,
+    /// The number of frames to draw before printing the average frame time and exiting
+    pub bench_frames: Option<u64>,
+    pub record_input: Option<PathBuf>,
+    pub replay_input: Option<PathBuf>,
+    /// Whether or not to keep the window hidden and skip presenting
+    pub headless: bool,
+    /// Whether or not to skip restoring the saved window placement
+    pub reset_window: bool,
+    /// Whether or not to skip restoring the handler's saved state
+    pub fresh: bool,
+    /// The example's own flags that were given, with their values
+    extra: BTreeMap<&'static str, Option<String>>,
+}
+
+impl DemoArgs {
+    /// Parse the common flags, printing the help and exiting if they are wrong or `--help` is
+    /// given
+    pub fn parse() -> Self {
+        Self::parse_with(&[])
+    }
+
+    /// Parse the common flags and the example's own flags, printing the help and exiting if they
+    /// are wrong or `--help` is given
+    pub fn parse_with(extra_flags: &[Flag]) -> Self {
+        let program = std::env::args()
+            .next()
+            .map(PathBuf::from)
+            .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .unwrap_or_default();
+
+        match Self::try_parse(std::env::args().skip(1), extra_flags) {
+            Ok(Some(args)) => args,
+            Ok(None) => {
+                print!("{}", Self::help(&program, extra_flags));
+                std::process::exit(0);
+            }
+            Err(error) => {
+                eprintln!("{}\n", error);
+                eprint!("{}", Self::help(&program, extra_flags));
+                std::process::exit(1);
+            }
+        }
+    }
+
+    /// Parse arguments, not including the program name, returning `None` if `--help` was given
+    pub fn try_parse<I: IntoIterator<Item = String>>(
+        args: I,
+        extra_flags: &[Flag],
+    ) -> Result<Option<Self>, String> {
+        let args = args.into_iter().collect::<Vec<_>>();
+        let mut config = Config::load_without_args();
+        config.apply_args(args.iter().cloned());
+
+        let mut parsed = Self {
+            config,
+            bench_frames: None,
+            record_input: None,
+            replay_input: None,
+            headless: false,
+            reset_window: false,
+            fresh: false,
+            extra: BTreeMap::new(),
+        };
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let name = arg
+                .strip_prefix("--")
+                .ok_or_else(|| format!("Unexpected argument `{}`", arg))?;
+            let flag = COMMON_FLAGS
+                .iter()
+                .chain(CONFIG_FLAGS)
+                .chain(extra_flags)
+                .find(|flag| flag.name == name)
+                .ok_or_else(|| format!("Unknown flag `{}`", arg))?;
+            let value = match flag.value {
+                Some(_) => Some(
+                    args.next()
+                        .ok_or_else(|| format!("`{}` needs a value", arg))?,
+                ),
+                None => None,
+            };
+
+            match (flag.name, value) {
+                ("help", _) => return Ok(None),
+                ("headless", _) => parsed.headless = true,
+                ("reset-window", _) => parsed.reset_window = true,
+                ("fresh", _) => parsed.fresh = true,
+                ("bench", Some(frames)) => {
+                    parsed.bench_frames = Some(
+                        frames
+                            .parse()
+                            .map_err(|_| format!("Expected a frame count, got `{}`", frames))?,
+                    )
+                }
+                ("record", Some(path)) => parsed.record_input = Some(path.into()),
+                ("replay", Some(path)) => parsed.replay_input = Some(path.into()),
+                // The config flags were applied by `Config::apply_args`
+                _ if CONFIG_FLAGS.contains(flag) => (),
+                (name, value) => {
+                    parsed.extra.insert(name, value);
+                }
+            }
+        }
+        Ok(Some(parsed))
+    }
+
+    /// The help text listing the common flags and the example's own flags
+    pub fn help(program: &str, extra_flags: &[Flag]) -> String {
+        let mut help = format!("Usage: {} [flags]\n", program);
+        let sections = [
+            ("Flags", extra_flags),
+            ("Common flags", COMMON_FLAGS),
+            ("Config flags", CONFIG_FLAGS),
+        ];
+        for (title, flags) in sections {
+            if flags.is_empty() {
+                continue;
+            }
+            writeln!(help, "\n{}:", title).unwrap();
+            for flag in flags {
+                let usage = match flag.value {
+                    Some(value) => format!("--{} {}", flag.name, value),
+                    None => format!("--{}", flag.name),
+                };
+                writeln!(help, "    {:28} {}", usage, flag.help).unwrap();
+            }
+        }
+        help
+    }
+
+    /// Whether or not one of the example's own flags was given
+    pub fn flag(&self, name: &str) -> bool {
+        self.extra.contains_key(name)
+    }
+
+    /// The value of one of the example's own flags, if it was given
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.extra.get(name)?.as_deref()
+    }
+
+    /// The settings for a window created from the arguments
+    pub fn window_config(&self) -> WindowConfig {
+        WindowConfig {
+            record_input: self.record_input.clone(),
+            replay_input: self.replay_input.clone(),
+            bench_frames: self.bench_frames,
+            headless: self.headless,
+            reset_placement: self.reset_window,
+            fresh_state: self.fresh,
+            ..self.config.window_config()
+        }
+    }
+
+    /// Open a window for the arguments and render to it with the given handler until it is closed
+    pub fn run<RndrHndlr: RenderHandler + 'static>(self) {
+        let window_config = self.window_config();
+        with_windows_and_config(
+            self.config,
+            vec![(window_config, handler_factory::<RndrHndlr>())],
+        );
+    }
+}
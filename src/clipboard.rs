@@ -0,0 +1,216 @@
+use std::{
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    readback::{self, AsyncReadback},
+    viewport::Rect,
+};
+
+/// How many frames can be read back at once, which is more than anyone can press Ctrl+F12 in the
+/// frames it takes to read one
+const SLOT_COUNT: usize = 2;
+
+/// The system clipboard, which is opened the first time a frame is copied
+#[cfg(feature = "clipboard")]
+type SystemClipboard = Option<arboard::Clipboard>;
+#[cfg(not(feature = "clipboard"))]
+#[derive(Default)]
+struct SystemClipboard;
+
+/// A frame read back from a window, as RGBA rows from the top down
+struct ClipboardImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+/// What became of a frame that was copied
+#[derive(Debug, Clone, PartialEq)]
+pub enum CopyOutcome {
+    /// It is on the clipboard
+    Copied { width: u32, height: u32 },
+    /// The clipboard couldn't take it, so it was saved to a PNG file instead
+    Saved { path: PathBuf, reason: String },
+    /// It couldn't be copied or saved
+    Failed { reason: String },
+}
+
+/// The thread that puts frames on the clipboard, and the channels to it
+struct ClipboardWorker {
+    images: Sender<ClipboardImage>,
+    outcomes: Receiver<CopyOutcome>,
+}
+
+/// Copies frames of a window to the system clipboard as images ( Ctrl+F12 )
+///
+/// The frame is read back with an `AsyncReadback`, so taking it doesn't wait for the GPU, and the
+/// image is put on the clipboard by a thread of its own, since that can take a while for a big
+/// frame. Where the clipboard can't hold images, like on some Wayland setups or when built without
+/// the `clipboard` feature, the frame is saved to a PNG file instead.
+///
+/// On X11 the image is only on the clipboard while the program runs, since the program is what
+/// hands it to whoever pastes it.
+#[derive(Default)]
+pub struct ClipboardCapture {
+    /// The readback for frames of its size, remade when the window changes size, which drops the
+    /// frames that are still being read
+    readback: Option<(AsyncReadback, (u32, u32))>,
+    /// Started the first time a frame is read back
+    worker: Option<ClipboardWorker>,
+}
+
+impl ClipboardCapture {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start reading back the frame in a framebuffer of the given size, or in the default
+    /// framebuffer
+    ///
+    /// Returns false if the frame was skipped because the frames before it are still being read.
+    pub fn capture(
+        &mut self,
+        gl: &mut glow::Context,
+        framebuffer: Option<u32>,
+        size: (u32, u32),
+    ) -> bool {
+        if let Some((readback, readback_size)) = &mut self.readback {
+            if *readback_size != size {
+                readback.delete(gl);
+                self.readback = None;
+            }
+        }
+        let (readback, _) = self.readback.get_or_insert_with(|| {
+            let bytes = size.0 as usize * size.1 as usize * 4;
+            let readback = AsyncReadback::new(gl, bytes, SLOT_COUNT, "Clipboard readback");
+            (readback, size)
+        });
+        readback.read_framebuffer(
+            gl,
+            framebuffer,
+            Rect::from_window_size(size),
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+        )
+    }
+
+    /// Hand the frames that finished reading to the clipboard thread, and return what became of
+    /// the last one that it finished with
+    ///
+    /// This never waits, for the GPU or for the clipboard, so it can be called every frame.
+    pub fn poll(&mut self, gl: &mut glow::Context) -> Option<CopyOutcome> {
+        if let Some((readback, (width, height))) = &mut self.readback {
+            if let Some(pixels) = readback.poll(gl) {
+                let image = ClipboardImage {
+                    width: *width,
+                    height: *height,
+                    pixels: readback::framebuffer_image(&pixels, *width),
+                };
+                // The worker only stops if it panicked, which has already been printed
+                let _ = self.worker().images.send(image);
+            }
+        }
+
+        let mut outcome = None;
+        if let Some(worker) = &self.worker {
+            loop {
+                match worker.outcomes.try_recv() {
+                    Ok(finished) => outcome = Some(finished),
+                    Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+                }
+            }
+        }
+        outcome
+    }
+
+    /// Delete the readback buffers, dropping the frames that are still being read
+    ///
+    /// The frames that the clipboard thread already has are still copied.
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        if let Some((mut readback, _)) = self.readback.take() {
+            readback.delete(gl);
+        }
+    }
+
+    /// The clipboard thread, starting it if it hasn't been started
+    fn worker(&mut self) -> &ClipboardWorker {
+        self.worker.get_or_insert_with(|| {
+            let (images, image_receiver) = mpsc::channel::<ClipboardImage>();
+            let (outcome_sender, outcomes) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("Clipboard".into())
+                .spawn(move || {
+                    let mut clipboard = SystemClipboard::default();
+                    for image in image_receiver {
+                        let outcome = copy_or_save(&mut clipboard, &image);
+                        if outcome_sender.send(outcome).is_err() {
+                            break;
+                        }
+                    }
+                })
+                .unwrap();
+            ClipboardWorker { images, outcomes }
+        })
+    }
+}
+
+/// Put an image on the clipboard, or save it to a file if the clipboard can't take it
+fn copy_or_save(clipboard: &mut SystemClipboard, image: &ClipboardImage) -> CopyOutcome {
+    let reason = match copy_image(clipboard, image) {
+        Ok(()) => {
+            return CopyOutcome::Copied {
+                width: image.width,
+                height: image.height,
+            }
+        }
+        Err(reason) => reason,
+    };
+
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs());
+    let path = PathBuf::from(format!("screenshot-{}.png", seconds));
+    match image::save_buffer(
+        &path,
+        &image.pixels,
+        image.width,
+        image.height,
+        image::ColorType::Rgba8,
+    ) {
+        Ok(()) => CopyOutcome::Saved { path, reason },
+        Err(error) => CopyOutcome::Failed {
+            reason: format!(
+                "{}, and saving it to {} failed: {}",
+                reason,
+                path.display(),
+                error
+            ),
+        },
+    }
+}
+
+#[cfg(feature = "clipboard")]
+fn copy_image(clipboard: &mut SystemClipboard, image: &ClipboardImage) -> Result<(), String> {
+    let clipboard = match clipboard {
+        Some(clipboard) => clipboard,
+        None => clipboard.get_or_insert(
+            arboard::Clipboard::new()
+                .map_err(|error| format!("Couldn't open the clipboard: {}", error))?,
+        ),
+    };
+    clipboard
+        .set_image(arboard::ImageData {
+            width: image.width as usize,
+            height: image.height as usize,
+            bytes: image.pixels.as_slice().into(),
+        })
+        .map_err(|error| format!("The clipboard doesn't take images here: {}", error))
+}
+
+#[cfg(not(feature = "clipboard"))]
+fn copy_image(_clipboard: &mut SystemClipboard, _image: &ClipboardImage) -> Result<(), String> {
+    Err("This was built without the `clipboard` feature".into())
+}
@@ -0,0 +1,749 @@
+use std::{
+    fmt,
+    time::{Duration, Instant},
+};
+
+use cgmath::{Matrix4, Point3, SquareMatrix, Transform, Vector4};
+use glow::HasContext;
+
+use crate::{
+    features::Features,
+    frustum::Aabb,
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram},
+};
+
+/// The GLSL for finding the lights that reach a fragment, which declares `clusterIndex`,
+/// `clusterRange`, `clusterLight`, and `clusterHeatmapColor`. Add it to a fragment shader with
+/// `ClusteredLights::shader_source`, which also picks the kind of buffer it reads from.
+pub const CLUSTERED_LIGHTS_CHUNK: &str = include_str!("clustered_lights/clustered_lights.glsl");
+
+/// The first of the three buffer binding points that the lights, the clusters' ranges, and the
+/// light index lists are bound to, right after `PER_DRAW_BINDING`
+pub const CLUSTER_BINDING: u32 = 1;
+
+/// The most lights that can be drawn, since the index lists hold 16 bit indices
+pub const MAX_LIGHTS: usize = 1 << 16;
+
+/// How many lights in a cluster the heatmap shows as red
+pub const HEATMAP_MAX_LIGHTS: u32 = 32;
+
+/// The names of the blocks in `CLUSTERED_LIGHTS_CHUNK`, in the order of their binding points
+const BLOCK_NAMES: [&str; 3] = ["ClusterLightData", "ClusterRanges", "ClusterIndices"];
+
+/// The sizes in bytes of a light, a cluster's range, and an index list entry in the buffers
+const ITEM_SIZES: [usize; 3] = [32, 8, 2];
+
+/// How many clusters the view is split into across, down, and deep
+///
+/// The slices in depth get thicker further away, so that the clusters are about as deep as they
+/// are wide.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ClusterGrid {
+    pub x: u32,
+    pub y: u32,
+    pub z: u32,
+}
+
+impl Default for ClusterGrid {
+    fn default() -> Self {
+        Self::new(16, 9, 24)
+    }
+}
+
+impl fmt::Display for ClusterGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}x{}x{}", self.x, self.y, self.z)
+    }
+}
+
+impl ClusterGrid {
+    pub fn new(x: u32, y: u32, z: u32) -> Self {
+        Self {
+            x: x.max(1),
+            y: y.max(1),
+            z: z.max(1),
+        }
+    }
+
+    /// Read a grid written like `16x9x24`, returning `None` if it isn't one
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut sizes = text
+            .split('x')
+            .map(|size| size.trim().parse::<u32>().ok().filter(|&size| size > 0));
+        let grid = Self::new(sizes.next()??, sizes.next()??, sizes.next()??);
+        if sizes.next().is_some() {
+            return None;
+        }
+        Some(grid)
+    }
+
+    /// The number of clusters
+    pub fn cluster_count(self) -> usize {
+        self.x as usize * self.y as usize * self.z as usize
+    }
+
+    /// The index of a cluster by its column, row, and slice
+    pub fn index(self, x: u32, y: u32, z: u32) -> usize {
+        ((z as usize * self.y as usize) + y as usize) * self.x as usize + x as usize
+    }
+
+    /// The biggest grid that is no bigger than this one on any side and has at most `max`
+    /// clusters, taking clusters away from the longest side first
+    pub fn fit(self, max: usize) -> Self {
+        let mut grid = self;
+        while grid.cluster_count() > max.max(1) {
+            if grid.z >= grid.x && grid.z >= grid.y {
+                grid.z -= 1;
+            } else if grid.x >= grid.y {
+                grid.x -= 1;
+            } else {
+                grid.y -= 1;
+            }
+        }
+        grid
+    }
+}
+
+/// A point light as `ClusteredLights` sees it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClusterLight {
+    pub position: Point3<f32>,
+    /// The color, where brighter colors go past 1
+    pub color: [f32; 3],
+    /// Where the light fades out completely, which is how far it reaches into other clusters
+    pub radius: f32,
+}
+
+/// Counts of how the lights were spread over the clusters in the last update
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ClusterStats {
+    /// The lights that were given
+    pub lights: usize,
+    /// The lights that reach at least one cluster
+    pub visible_lights: usize,
+    /// The entries in all of the clusters' lists together
+    pub assignments: usize,
+    /// The clusters with at least one light
+    pub occupied_clusters: usize,
+    /// The most lights in one cluster
+    pub max_cluster_lights: usize,
+    /// The lights and list entries left out because they didn't fit in the buffers
+    pub dropped: usize,
+    /// How long working out the lists took on the CPU
+    pub assign_time: Duration,
+}
+
+impl ClusterStats {
+    /// The average number of lights in the clusters that have any, which is about how many lights
+    /// a lit fragment loops over
+    pub fn average_cluster_lights(&self) -> f32 {
+        if self.occupied_clusters == 0 {
+            return 0.;
+        }
+        self.assignments as f32 / self.occupied_clusters as f32
+    }
+}
+
+/// Which lights reach each cluster of a view, worked out on the CPU
+///
+/// The clusters are the cells of a `ClusterGrid` over a perspective projection, with slices that
+/// are evenly spaced in the logarithm of the depth between the near and far planes. Each light
+/// is tested against the clusters under the screen rectangle and the depth range of its sphere,
+/// and only the ones whose box the sphere touches get it. Orthographic projections aren't
+/// supported.
+#[derive(Clone, Debug)]
+pub struct ClusterAssignment {
+    grid: ClusterGrid,
+    projection: Matrix4<f32>,
+    near: f32,
+    far: f32,
+    /// The box around each cluster in view space
+    bounds: Vec<Aabb>,
+    /// Where each cluster's lights start in `indices`, and how many there are
+    ranges: Vec<[u32; 2]>,
+    /// The lights of every cluster, one cluster after another
+    indices: Vec<u16>,
+    /// The cluster and light of every overlap, before they are grouped by cluster
+    pairs: Vec<(u32, u16)>,
+    stats: ClusterStats,
+}
+
+impl ClusterAssignment {
+    pub fn new(grid: ClusterGrid) -> Self {
+        Self {
+            grid,
+            projection: Matrix4::identity(),
+            near: 0.,
+            far: 0.,
+            bounds: Vec::new(),
+            ranges: Vec::new(),
+            indices: Vec::new(),
+            pairs: Vec::new(),
+            stats: ClusterStats::default(),
+        }
+    }
+
+    pub fn grid(&self) -> ClusterGrid {
+        self.grid
+    }
+
+    /// Change the grid, which takes effect in the next `assign`
+    pub fn set_grid(&mut self, grid: ClusterGrid) {
+        if grid != self.grid {
+            self.grid = grid;
+            self.bounds.clear();
+        }
+    }
+
+    /// Work out the lights of every cluster of a view, keeping at most `max_entries` entries in
+    /// the lists together
+    ///
+    /// `near` and `far` are the distances to the planes of `projection`. The boxes of the clusters
+    /// are only worked out again when the grid or the projection changes.
+    pub fn assign(
+        &mut self,
+        lights: &[ClusterLight],
+        view: Matrix4<f32>,
+        projection: Matrix4<f32>,
+        near: f32,
+        far: f32,
+        max_entries: usize,
+    ) {
+        let start = Instant::now();
+        let grid = self.grid;
+        let far = far.max(near * 1.001);
+        if projection != self.projection
+            || near != self.near
+            || far != self.far
+            || self.bounds.is_empty()
+        {
+            self.projection = projection;
+            self.near = near;
+            self.far = far;
+            self.update_bounds();
+        }
+
+        let slices_per_log = grid.z as f32 / (far / near).ln();
+        let slice = |depth: f32| {
+            ((depth / near).ln() * slices_per_log).clamp(0., (grid.z - 1) as f32) as u32
+        };
+        let tile = |ndc: f32, count: u32| {
+            ((ndc * 0.5 + 0.5) * count as f32).clamp(0., (count - 1) as f32) as u32
+        };
+
+        self.pairs.clear();
+        let mut visible_lights = 0;
+        for (index, light) in lights.iter().enumerate().take(MAX_LIGHTS) {
+            let center = view.transform_point(light.position);
+            let (depth, radius) = (-center.z, light.radius);
+            if radius <= 0. || depth + radius < near || depth - radius > far {
+                continue;
+            }
+
+            // The clusters under the box around the sphere, cut off at the near and far planes
+            let depths = [(depth - radius).max(near), (depth + radius).min(far)];
+            let (mut low, mut high) = ([f32::INFINITY; 2], [f32::NEG_INFINITY; 2]);
+            for &x in &[center.x - radius, center.x + radius] {
+                for &y in &[center.y - radius, center.y + radius] {
+                    for &depth in &depths {
+                        let clip = projection * Vector4::new(x, y, -depth, 1.);
+                        for axis in 0..2 {
+                            let ndc = clip[axis] / clip.w;
+                            low[axis] = low[axis].min(ndc);
+                            high[axis] = high[axis].max(ndc);
+                        }
+                    }
+                }
+            }
+            if (0..2).any(|axis| high[axis] < -1. || low[axis] > 1.) {
+                continue;
+            }
+
+            let mut reaches_any = false;
+            for z in slice(depths[0])..=slice(depths[1]) {
+                for y in tile(low[1], grid.y)..=tile(high[1], grid.y) {
+                    for x in tile(low[0], grid.x)..=tile(high[0], grid.x) {
+                        let cluster = grid.index(x, y, z);
+                        if sphere_touches(&self.bounds[cluster], center, radius) {
+                            self.pairs.push((cluster as u32, index as u16));
+                            reaches_any = true;
+                        }
+                    }
+                }
+            }
+            if reaches_any {
+                visible_lights += 1;
+            }
+        }
+
+        // Group the pairs by cluster with a counting sort, which keeps each list in light order
+        self.ranges.clear();
+        self.ranges.resize(grid.cluster_count(), [0, 0]);
+        for &(cluster, _) in &self.pairs {
+            self.ranges[cluster as usize][1] += 1;
+        }
+        let mut offset = 0;
+        for range in &mut self.ranges {
+            range[0] = offset;
+            offset += range[1];
+            range[1] = 0;
+        }
+        self.indices.clear();
+        self.indices.resize(self.pairs.len(), 0);
+        for &(cluster, light) in &self.pairs {
+            let range = &mut self.ranges[cluster as usize];
+            self.indices[(range[0] + range[1]) as usize] = light;
+            range[1] += 1;
+        }
+
+        // Cut the lists off where the buffers end
+        let mut dropped = lights.len().saturating_sub(MAX_LIGHTS);
+        if self.indices.len() > max_entries {
+            dropped += self.indices.len() - max_entries;
+            self.indices.truncate(max_entries);
+            for range in &mut self.ranges {
+                let end = (range[0] + range[1]).min(max_entries as u32);
+                range[1] = end.saturating_sub(range[0]);
+            }
+        }
+
+        self.stats = ClusterStats {
+            lights: lights.len(),
+            visible_lights,
+            assignments: self.indices.len(),
+            occupied_clusters: self.ranges.iter().filter(|range| range[1] > 0).count(),
+            max_cluster_lights: self.ranges.iter().map(|range| range[1]).max().unwrap_or(0)
+                as usize,
+            dropped,
+            assign_time: start.elapsed(),
+        };
+    }
+
+    /// Work out the box around each cluster from the corners of its tile on the near plane
+    fn update_bounds(&mut self) {
+        let grid = self.grid;
+        let inverse = self.projection.invert().unwrap_or_else(Matrix4::identity);
+
+        // The points at a depth of 1 under each corner of the tiles
+        let mut corners = Vec::with_capacity((grid.x as usize + 1) * (grid.y as usize + 1));
+        for y in 0..=grid.y {
+            for x in 0..=grid.x {
+                let ndc = Vector4::new(
+                    x as f32 / grid.x as f32 * 2. - 1.,
+                    y as f32 / grid.y as f32 * 2. - 1.,
+                    -1.,
+                    1.,
+                );
+                let point = inverse * ndc;
+                let point = point.truncate() / point.w;
+                corners.push(point / -point.z);
+            }
+        }
+
+        let (near, far) = (self.near, self.far);
+        let depth = |slice: u32| near * (far / near).powf(slice as f32 / grid.z as f32);
+        let row = grid.x as usize + 1;
+        self.bounds.clear();
+        for z in 0..grid.z {
+            let depths = [depth(z), depth(z + 1)];
+            for y in 0..grid.y as usize {
+                for x in 0..grid.x as usize {
+                    let mut aabb = Aabb {
+                        min: [f32::INFINITY; 3],
+                        max: [f32::NEG_INFINITY; 3],
+                    };
+                    for corner in &[
+                        corners[y * row + x],
+                        corners[y * row + x + 1],
+                        corners[(y + 1) * row + x],
+                        corners[(y + 1) * row + x + 1],
+                    ] {
+                        for &depth in &depths {
+                            let point = corner * depth;
+                            for axis in 0..3 {
+                                aabb.min[axis] = aabb.min[axis].min(point[axis]);
+                                aabb.max[axis] = aabb.max[axis].max(point[axis]);
+                            }
+                        }
+                    }
+                    self.bounds.push(aabb);
+                }
+            }
+        }
+    }
+
+    pub fn stats(&self) -> ClusterStats {
+        self.stats
+    }
+
+    /// The indices of the lights that reach a cluster
+    pub fn cluster_lights(&self, cluster: usize) -> &[u16] {
+        match self.ranges.get(cluster) {
+            Some(&[start, count]) => &self.indices[start as usize..(start + count) as usize],
+            None => &[],
+        }
+    }
+
+    /// The cluster that a point in view space is in, the same way the shader chunk finds it, or
+    /// `None` if the point is off the screen or outside of the depth range
+    pub fn cluster_at(&self, point: Point3<f32>) -> Option<usize> {
+        let depth = -point.z;
+        if self.bounds.is_empty() || depth < self.near || depth > self.far {
+            return None;
+        }
+        let clip = self.projection * point.to_homogeneous();
+        let mut cell = [0; 2];
+        for (axis, count) in [self.grid.x, self.grid.y].iter().enumerate() {
+            let screen = clip[axis] / clip.w * 0.5 + 0.5;
+            if !(0. ..=1.).contains(&screen) {
+                return None;
+            }
+            cell[axis] = ((screen * *count as f32) as u32).min(count - 1);
+        }
+        let slice = (depth / self.near).ln() / (self.far / self.near).ln() * self.grid.z as f32;
+        let slice = (slice as u32).min(self.grid.z - 1);
+        Some(self.grid.index(cell[0], cell[1], slice))
+    }
+}
+
+/// Whether a sphere touches a box
+fn sphere_touches(aabb: &Aabb, center: Point3<f32>, radius: f32) -> bool {
+    let distance2: f32 = (0..3)
+        .map(|axis| {
+            let outside = (aabb.min[axis] - center[axis])
+                .max(center[axis] - aabb.max[axis])
+                .max(0.);
+            outside * outside
+        })
+        .sum();
+    distance2 <= radius * radius
+}
+
+/// The kind of buffer that `ClusteredLights` puts the lights and their lists in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ClusterStorage {
+    /// Shader storage buffers, which hold any number of lights ( GL 4.3, see `Features::compute` )
+    StorageBuffers,
+    /// Uniform buffers, which hold as many lights and list entries as fit in
+    /// `GL_MAX_UNIFORM_BLOCK_SIZE`, which is only 16 KiB on some drivers
+    UniformBuffers,
+}
+
+impl ClusterStorage {
+    pub fn name(self) -> &'static str {
+        match self {
+            ClusterStorage::StorageBuffers => "storage buffers",
+            ClusterStorage::UniformBuffers => "uniform buffers",
+        }
+    }
+}
+
+/// How many lights, clusters, and list entries fit in the uniform buffers
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct UniformLimits {
+    lights: usize,
+    clusters: usize,
+    entries: usize,
+}
+
+impl UniformLimits {
+    /// The number of items that each of the buffers holds, in the order of their binding points
+    fn items(&self) -> [usize; 3] {
+        [self.lights, self.clusters, self.entries]
+    }
+}
+
+/// Clustered forward lighting, for scenes with far more point lights than a shader can loop over
+/// for every fragment
+///
+/// Every frame, `update` splits the view into the clusters of a `ClusterGrid`, works out which
+/// lights reach each one on the CPU, and uploads the lights and the clusters' lists. A fragment
+/// shader with `CLUSTERED_LIGHTS_CHUNK` in it then only loops over the lights of the cluster the
+/// fragment is in:
+///
+/// ```glsl
+/// uint cluster = clusterIndex(worldPosition);
+/// uvec2 range = clusterRange(cluster);
+/// for (uint i = 0u; i < range.y; i++) {
+///     ClusterLight light = clusterLight(range, i);
+///     // ...
+/// }
+/// FragColor = vec4(clusterHeatmapColor(color, cluster), 1.0);
+/// ```
+///
+/// The lists go in shader storage buffers where the context has them, and in uniform buffers
+/// otherwise, which fit fewer lights. Either way the data is laid out the same, so the chunk reads
+/// it the same way. The heatmap colors each fragment by how many lights its cluster has, to see
+/// how well the lights are culled.
+#[derive(Debug)]
+pub struct ClusteredLights {
+    storage: ClusterStorage,
+    /// The limits of the uniform buffers, or `None` for storage buffers
+    limits: Option<UniformLimits>,
+    assignment: ClusterAssignment,
+    /// The buffers of the lights, the clusters' ranges, and the index lists
+    buffers: [Option<u32>; 3],
+    /// The sizes of the buffers in bytes
+    sizes: [usize; 3],
+    /// Reused for packing the data before uploading it
+    bytes: Vec<u8>,
+    view: Matrix4<f32>,
+    projection: Matrix4<f32>,
+    near: f32,
+    far: f32,
+    /// The lights that didn't fit in the uniform buffers in the last update
+    dropped_lights: usize,
+    heatmap: bool,
+}
+
+impl ClusteredLights {
+    pub fn new(gl: &mut glow::Context, features: &Features, grid: ClusterGrid) -> Self {
+        let (storage, limits) = if features.compute.is_some() {
+            (ClusterStorage::StorageBuffers, None)
+        } else {
+            let block_size =
+                unsafe { gl.get_parameter_i32(glow::MAX_UNIFORM_BLOCK_SIZE) }.max(16_384) as usize;
+            // The arrays in the blocks are of whole vec4s, so round down to fill them exactly
+            let limits = UniformLimits {
+                lights: (block_size / ITEM_SIZES[0]).min(MAX_LIGHTS),
+                clusters: block_size / ITEM_SIZES[1] / 2 * 2,
+                entries: block_size / ITEM_SIZES[2] / 8 * 8,
+            };
+            (ClusterStorage::UniformBuffers, Some(limits))
+        };
+        let mut lights = Self {
+            storage,
+            limits,
+            assignment: ClusterAssignment::new(grid),
+            buffers: [None; 3],
+            sizes: [0; 3],
+            bytes: Vec::new(),
+            view: Matrix4::identity(),
+            projection: Matrix4::identity(),
+            near: 0.1,
+            far: 1000.,
+            dropped_lights: 0,
+            heatmap: false,
+        };
+        lights.set_grid(grid);
+        lights
+    }
+
+    pub fn storage(&self) -> ClusterStorage {
+        self.storage
+    }
+
+    /// The grid that the view is split into
+    pub fn grid(&self) -> ClusterGrid {
+        self.assignment.grid()
+    }
+
+    /// Change the grid, which is shrunk to fit if the lists are in uniform buffers and it has
+    /// more clusters than they hold
+    pub fn set_grid(&mut self, grid: ClusterGrid) {
+        let fitted = match self.limits {
+            Some(limits) => grid.fit(limits.clusters),
+            None => grid,
+        };
+        if fitted != grid {
+            eprintln!(
+                "Warning: The {} cluster grid doesn't fit in a uniform buffer, using {} instead",
+                grid, fitted
+            );
+        }
+        self.assignment.set_grid(fitted);
+    }
+
+    /// Whether or not the heatmap of lights per cluster is drawn over the lit colors
+    pub fn heatmap(&self) -> bool {
+        self.heatmap
+    }
+
+    pub fn set_heatmap(&mut self, heatmap: bool) {
+        self.heatmap = heatmap;
+    }
+
+    /// How the lights were spread over the clusters in the last update
+    pub fn stats(&self) -> ClusterStats {
+        let mut stats = self.assignment.stats();
+        stats.lights += self.dropped_lights;
+        stats.dropped += self.dropped_lights;
+        stats
+    }
+
+    /// The lights of every cluster from the last update
+    pub fn assignment(&self) -> &ClusterAssignment {
+        &self.assignment
+    }
+
+    /// Add `CLUSTERED_LIGHTS_CHUNK` to the source of a fragment shader, set up for the kind of
+    /// buffer the lists are in
+    ///
+    /// Storage buffers need GLSL 4.30, so the shader's version is raised to it for them.
+    pub fn shader_source(&self, fragment_source: &str) -> String {
+        let source = shader::include_chunk(fragment_source, CLUSTERED_LIGHTS_CHUNK);
+        match self.limits {
+            None => {
+                let source = match source.split_once('\n') {
+                    Some((first, rest)) if first.trim_start().starts_with("#version") => {
+                        format!("#version 430 core\n{}", rest)
+                    }
+                    _ => source,
+                };
+                shader::inject_defines(&source, &["CLUSTERED_LIGHTS_STORAGE"])
+            }
+            Some(limits) => {
+                let defines = [
+                    format!("CLUSTERED_LIGHTS_MAX_LIGHTS {}", limits.lights),
+                    format!("CLUSTERED_LIGHTS_MAX_CLUSTERS {}", limits.clusters),
+                    format!("CLUSTERED_LIGHTS_MAX_ENTRIES {}", limits.entries),
+                ];
+                let defines = defines.iter().map(String::as_str).collect::<Vec<_>>();
+                shader::inject_defines(&source, &defines)
+            }
+        }
+    }
+
+    /// Work out the lights of every cluster for a view and upload them
+    ///
+    /// `near` and `far` are the distances to the planes of `projection`, which has to be a
+    /// perspective projection, e.g. from `FlyCamera::clip_planes`.
+    pub fn update(
+        &mut self,
+        gl: &mut glow::Context,
+        lights: &[ClusterLight],
+        view: Matrix4<f32>,
+        projection: Matrix4<f32>,
+        near: f32,
+        far: f32,
+    ) {
+        let (light_count, max_entries) = match self.limits {
+            Some(limits) => (lights.len().min(limits.lights), limits.entries),
+            None => (lights.len(), usize::MAX),
+        };
+        self.dropped_lights = lights.len() - light_count;
+        let lights = &lights[..light_count];
+        self.assignment
+            .assign(lights, view, projection, near, far, max_entries);
+        self.view = view;
+        self.projection = projection;
+        self.near = near;
+        self.far = self.assignment.far;
+
+        self.bytes.clear();
+        for light in lights {
+            let position = light.position;
+            let floats = [
+                position.x,
+                position.y,
+                position.z,
+                light.radius,
+                light.color[0],
+                light.color[1],
+                light.color[2],
+                0.,
+            ];
+            for value in &floats {
+                self.bytes.extend_from_slice(&value.to_ne_bytes());
+            }
+        }
+        self.upload(gl, 0);
+        for range in &self.assignment.ranges {
+            self.bytes.extend_from_slice(&range[0].to_ne_bytes());
+            self.bytes.extend_from_slice(&range[1].to_ne_bytes());
+        }
+        self.upload(gl, 1);
+        for index in &self.assignment.indices {
+            self.bytes.extend_from_slice(&index.to_ne_bytes());
+        }
+        self.upload(gl, 2);
+    }
+
+    /// Upload the packed bytes into one of the buffers and clear them
+    fn upload(&mut self, gl: &mut glow::Context, slot: usize) {
+        // Uniform buffers have to be as big as the blocks that read them, and storage buffers
+        // can't be empty
+        let size = match self.limits {
+            Some(limits) => limits.items()[slot] * ITEM_SIZES[slot],
+            None => self.bytes.len().max(16).next_multiple_of(16),
+        };
+        self.bytes.resize(size, 0);
+        let target = self.target();
+        unsafe {
+            let buffer = match self.buffers[slot] {
+                Some(buffer) => buffer,
+                None => {
+                    let buffer = gl.create_buffer().unwrap();
+                    self.buffers[slot] = Some(buffer);
+                    buffer
+                }
+            };
+            gl.bind_buffer(target, Some(buffer));
+            gl.buffer_data_u8_slice(target, &self.bytes, glow::STREAM_DRAW);
+            gl.bind_buffer(target, None);
+            if self.sizes[slot] != size {
+                self.sizes[slot] = size;
+                resources::track_sized(
+                    ResourceKind::Buffer,
+                    buffer,
+                    "Clustered lights buffer",
+                    size as u64,
+                );
+            }
+        }
+        self.bytes.clear();
+    }
+
+    /// The buffer target that the lists are bound to
+    fn target(&self) -> u32 {
+        match self.storage {
+            ClusterStorage::StorageBuffers => glow::SHADER_STORAGE_BUFFER,
+            ClusterStorage::UniformBuffers => glow::UNIFORM_BUFFER,
+        }
+    }
+
+    /// Bind the lists from the last update and set the chunk's uniforms, for a program made with
+    /// `shader_source` that is bound
+    pub fn bind(&self, gl: &mut glow::Context, program: &mut ShaderProgram) {
+        let target = self.target();
+        for (slot, buffer) in self.buffers.iter().enumerate() {
+            let binding = CLUSTER_BINDING + slot as u32;
+            if self.storage == ClusterStorage::UniformBuffers {
+                program.bind_uniform_block(gl, BLOCK_NAMES[slot], binding);
+            }
+            unsafe { gl.bind_buffer_base(target, binding, *buffer) };
+        }
+
+        let grid = self.grid();
+        program.set_uniform(gl, "clusterView", self.view);
+        program.set_uniform(gl, "clusterProjection", self.projection);
+        program.set_uniform(
+            gl,
+            "clusterGrid",
+            [grid.x as f32, grid.y as f32, grid.z as f32],
+        );
+        program.set_uniform(
+            gl,
+            "clusterDepth",
+            [self.near, grid.z as f32 / (self.far / self.near).ln()],
+        );
+        let heatmap_max = if self.heatmap {
+            HEATMAP_MAX_LIGHTS as f32
+        } else {
+            0.
+        };
+        program.set_uniform(gl, "clusterHeatmapMax", heatmap_max);
+    }
+
+    /// Delete the buffers
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for (buffer, size) in self.buffers.iter_mut().zip(&mut self.sizes) {
+            if let Some(buffer) = buffer.take() {
+                unsafe { gl.delete_buffer(buffer) };
+                resources::untrack(ResourceKind::Buffer, buffer);
+            }
+            *size = 0;
+        }
+    }
+}
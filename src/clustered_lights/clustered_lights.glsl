@@ -0,0 +1,91 @@
+// The lights and the lists of the lights that reach each cluster, uploaded by
+// `ClusteredLights::update`. Both kinds of block lay the arrays out the same way: each light is two
+// vec4s, each cluster's range is two uints, and the list entries are 16 bit light indices, two
+// to a uint.
+#ifdef CLUSTERED_LIGHTS_STORAGE
+// The bindings start at `CLUSTER_BINDING`
+layout (std430, binding = 1) readonly buffer ClusterLightData {
+    vec4 clusterLightData[];
+};
+layout (std430, binding = 2) readonly buffer ClusterRanges {
+    uvec4 clusterRanges[];
+};
+layout (std430, binding = 3) readonly buffer ClusterIndices {
+    uvec4 clusterIndices[];
+};
+#else
+layout (std140) uniform ClusterLightData {
+    vec4 clusterLightData[CLUSTERED_LIGHTS_MAX_LIGHTS * 2];
+};
+layout (std140) uniform ClusterRanges {
+    uvec4 clusterRanges[CLUSTERED_LIGHTS_MAX_CLUSTERS / 2];
+};
+layout (std140) uniform ClusterIndices {
+    uvec4 clusterIndices[CLUSTERED_LIGHTS_MAX_ENTRIES / 8];
+};
+#endif
+
+struct ClusterLight {
+    vec3 position;
+    // Where the light fades out completely
+    float radius;
+    vec3 color;
+};
+
+// The view that the lights were assigned to clusters for
+uniform mat4 clusterView;
+uniform mat4 clusterProjection;
+// The number of clusters across, down, and deep
+uniform vec3 clusterGrid;
+// The distance to the near plane, and how many slices there are per unit of the log of the depth
+uniform vec2 clusterDepth;
+// How many lights in a cluster the heatmap shows as red, or 0 to not draw the heatmap
+uniform float clusterHeatmapMax;
+
+// The cluster that a point is in
+uint clusterIndex(vec3 worldPosition) {
+    vec4 viewPosition = clusterView * vec4(worldPosition, 1.0);
+    vec4 clip = clusterProjection * viewPosition;
+    vec2 screen = clamp(clip.xy / clip.w * 0.5 + 0.5, 0.0, 1.0);
+    uvec3 size = uvec3(clusterGrid);
+    uvec2 tile = min(uvec2(screen * clusterGrid.xy), size.xy - 1u);
+    float depth = max(-viewPosition.z, clusterDepth.x);
+    uint slice = min(uint(log(depth / clusterDepth.x) * clusterDepth.y), size.z - 1u);
+    return (slice * size.y + tile.y) * size.x + tile.x;
+}
+
+// Where a cluster's lights start in the lists, and how many there are
+uvec2 clusterRange(uint cluster) {
+    uvec4 pair = clusterRanges[cluster / 2u];
+    return (cluster & 1u) == 0u ? pair.xy : pair.zw;
+}
+
+// One of the lights of a cluster, from 0 up to the count in its range
+ClusterLight clusterLight(uvec2 range, uint i) {
+    uint entry = range.x + i;
+    uint pair = clusterIndices[entry / 8u][(entry / 2u) % 4u];
+    uint light = (entry & 1u) == 0u ? pair & 0xFFFFu : pair >> 16u;
+    vec4 positionRadius = clusterLightData[light * 2u];
+    vec4 color = clusterLightData[light * 2u + 1u];
+    return ClusterLight(positionRadius.xyz, positionRadius.w, color.rgb);
+}
+
+// The lit color of a fragment, or the heatmap of how many lights its cluster has over it when the
+// heatmap is on: blue for a few lights, through green and yellow, to red for
+// `clusterHeatmapMax` or more, and nothing for none
+vec3 clusterHeatmapColor(vec3 color, uint cluster) {
+    if (clusterHeatmapMax <= 0.0) {
+        return color;
+    }
+    uint count = clusterRange(cluster).y;
+    if (count == 0u) {
+        return color * 0.25;
+    }
+    float heat = clamp(float(count) / clusterHeatmapMax, 0.0, 1.0);
+    vec3 heatColor = clamp(
+        vec3(heat * 3.0 - 1.0, 2.0 - abs(heat * 3.0 - 1.5) * 2.0, 1.5 - heat * 3.0),
+        0.0,
+        1.0
+    );
+    return mix(color, heatColor, 0.75);
+}
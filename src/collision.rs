@@ -0,0 +1,512 @@
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3, Zero};
+
+use crate::{bvh::TriangleBvh, frustum::Aabb};
+
+/// How far moving boxes stop short of what they hit, so that rounding errors don't leave them
+/// inside of it
+pub const SKIN: f32 = 0.001;
+
+/// How many times `move_and_slide` changes direction along the surfaces it hits before it stops
+const MAX_SLIDES: usize = 4;
+
+/// The smallest upward normal that counts as standing on the ground, which is a slope of about
+/// 45 degrees
+const GROUND_NORMAL_Y: f32 = 0.7;
+
+/// Where two shapes touch
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Contact {
+    /// A point where the shapes touch
+    pub point: Point3<f32>,
+    /// The direction to push the first shape to separate them
+    pub normal: Vector3<f32>,
+    /// How far the shapes overlap along the normal, which is 0 when they only touch
+    pub depth: f32,
+}
+
+/// Whether two boxes overlap. Boxes that only touch don't count.
+pub fn aabb_overlaps(a: &Aabb, b: &Aabb) -> bool {
+    (0..3).all(|axis| a.min[axis] < b.max[axis] && b.min[axis] < a.max[axis])
+}
+
+/// The contact between two overlapping boxes, pushing `a` out of `b` the shortest way, or `None`
+/// if they don't overlap
+pub fn aabb_contact(a: &Aabb, b: &Aabb) -> Option<Contact> {
+    let mut point = Point3::new(0., 0., 0.);
+    let mut shallowest: Option<(usize, f32)> = None;
+    for axis in 0..3 {
+        let (low, high) = (a.min[axis].max(b.min[axis]), a.max[axis].min(b.max[axis]));
+        let depth = high - low;
+        if depth <= 0. {
+            return None;
+        }
+        point[axis] = (low + high) / 2.;
+        if shallowest.is_none_or(|(_, shallowest)| depth < shallowest) {
+            shallowest = Some((axis, depth));
+        }
+    }
+
+    // Push `a` towards the side of `b` its center is on
+    let (axis, depth) = shallowest.unwrap();
+    let mut normal = Vector3::zero();
+    normal[axis] = if a.center()[axis] < b.center()[axis] {
+        -1.
+    } else {
+        1.
+    };
+    Some(Contact {
+        point,
+        normal,
+        depth,
+    })
+}
+
+/// The point in the box closest to a point, which is the point itself if it is inside
+fn closest_point(aabb: &Aabb, point: Point3<f32>) -> Point3<f32> {
+    let mut closest = point;
+    for axis in 0..3 {
+        closest[axis] = point[axis].clamp(aabb.min[axis], aabb.max[axis]);
+    }
+    closest
+}
+
+/// Whether a sphere overlaps a box
+pub fn sphere_overlaps_aabb(center: Point3<f32>, radius: f32, aabb: &Aabb) -> bool {
+    (center - closest_point(aabb, center)).magnitude2() < radius * radius
+}
+
+/// The contact between a sphere and a box, pushing the sphere out of the box, or `None` if they
+/// don't overlap
+pub fn sphere_aabb_contact(center: Point3<f32>, radius: f32, aabb: &Aabb) -> Option<Contact> {
+    let closest = closest_point(aabb, center);
+    let offset = center - closest;
+    let distance2 = offset.magnitude2();
+    if distance2 >= radius * radius {
+        return None;
+    }
+    if distance2 > 0. {
+        let distance = distance2.sqrt();
+        return Some(Contact {
+            point: closest,
+            normal: offset / distance,
+            depth: radius - distance,
+        });
+    }
+
+    // The center is inside the box, so push it out through the closest face
+    let mut nearest = (0, 1., f32::INFINITY);
+    for axis in 0..3 {
+        for (sign, distance) in [
+            (-1., center[axis] - aabb.min[axis]),
+            (1., aabb.max[axis] - center[axis]),
+        ] {
+            if distance < nearest.2 {
+                nearest = (axis, sign, distance);
+            }
+        }
+    }
+    let (axis, sign, distance) = nearest;
+    let mut normal = Vector3::zero();
+    normal[axis] = sign;
+    let mut point = center;
+    point[axis] += sign * distance;
+    Some(Contact {
+        point,
+        normal,
+        depth: radius + distance,
+    })
+}
+
+/// Every point within `radius` of the segment from `a` to `b`, which is the usual shape of a
+/// character: it slides along walls and over the edges of steps without catching on them
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Capsule {
+    pub a: Point3<f32>,
+    pub b: Point3<f32>,
+    pub radius: f32,
+}
+
+impl Capsule {
+    /// The box around the capsule
+    pub fn aabb(&self) -> Aabb {
+        let mut aabb = Aabb {
+            min: [0.; 3],
+            max: [0.; 3],
+        };
+        for axis in 0..3 {
+            aabb.min[axis] = self.a[axis].min(self.b[axis]) - self.radius;
+            aabb.max[axis] = self.a[axis].max(self.b[axis]) + self.radius;
+        }
+        aabb
+    }
+
+    /// The capsule moved by an offset
+    pub fn translated(&self, offset: Vector3<f32>) -> Self {
+        Self {
+            a: self.a + offset,
+            b: self.b + offset,
+            radius: self.radius,
+        }
+    }
+}
+
+/// The point on the segment from `a` to `b` closest to a point
+fn closest_point_on_segment(a: Point3<f32>, b: Point3<f32>, point: Point3<f32>) -> Point3<f32> {
+    let ab = b - a;
+    let length2 = ab.magnitude2();
+    if length2 == 0. {
+        return a;
+    }
+    a + ab * ((point - a).dot(ab) / length2).clamp(0., 1.)
+}
+
+/// The closest points between two segments, on the first one and then the second one
+fn closest_points_on_segments(
+    (p1, q1): (Point3<f32>, Point3<f32>),
+    (p2, q2): (Point3<f32>, Point3<f32>),
+) -> (Point3<f32>, Point3<f32>) {
+    let (d1, d2, r) = (q1 - p1, q2 - p2, p1 - p2);
+    let (a, e, f) = (d1.magnitude2(), d2.magnitude2(), d2.dot(r));
+    if a == 0. {
+        return (p1, closest_point_on_segment(p2, q2, p1));
+    }
+    if e == 0. {
+        return (closest_point_on_segment(p1, q1, p2), p2);
+    }
+    let (b, c) = (d1.dot(d2), d1.dot(r));
+    let denominator = a * e - b * b;
+    // Parallel segments have every point as close as any other, so start from the first one
+    let mut s = if denominator > 0. {
+        ((b * f - c * e) / denominator).clamp(0., 1.)
+    } else {
+        0.
+    };
+    let mut t = (b * s + f) / e;
+    if t < 0. {
+        t = 0.;
+        s = (-c / a).clamp(0., 1.);
+    } else if t > 1. {
+        t = 1.;
+        s = ((b - c) / a).clamp(0., 1.);
+    }
+    (p1 + d1 * s, p2 + d2 * t)
+}
+
+/// The point on a triangle closest to a point
+pub fn closest_point_on_triangle(point: Point3<f32>, [a, b, c]: &[Point3<f32>; 3]) -> Point3<f32> {
+    // Find which of the triangle's corners, edges, or face the point is nearest to from the
+    // barycentric coordinates of its projection
+    let (ab, ac, ap) = (b - a, c - a, point - a);
+    let (d1, d2) = (ab.dot(ap), ac.dot(ap));
+    if d1 <= 0. && d2 <= 0. {
+        return *a;
+    }
+    let bp = point - b;
+    let (d3, d4) = (ab.dot(bp), ac.dot(bp));
+    if d3 >= 0. && d4 <= d3 {
+        return *b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0. && d1 >= 0. && d3 <= 0. {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = point - c;
+    let (d5, d6) = (ab.dot(cp), ac.dot(cp));
+    if d6 >= 0. && d5 <= d6 {
+        return *c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0. && d2 >= 0. && d6 <= 0. {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0. && d4 - d3 >= 0. && d5 - d6 >= 0. {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denominator = 1. / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+/// The contact between a capsule and a triangle, pushing the capsule out of the triangle, or
+/// `None` if they don't overlap
+///
+/// Triangles are solid from both sides. A capsule whose segment goes through a triangle is
+/// pushed out of the side that its middle is on.
+pub fn capsule_triangle_contact(capsule: &Capsule, triangle: &[Point3<f32>; 3]) -> Option<Contact> {
+    let [a, b, c] = *triangle;
+    let face_normal = (b - a).cross(c - a);
+    if face_normal.magnitude2() == 0. {
+        return None;
+    }
+    let face_normal = face_normal.normalize();
+    let (height_a, height_b) = (
+        (capsule.a - a).dot(face_normal),
+        (capsule.b - a).dot(face_normal),
+    );
+    let radius = capsule.radius;
+    if height_a.min(height_b) >= radius || height_a.max(height_b) <= -radius {
+        // The whole capsule is on one side of the triangle's plane
+        return None;
+    }
+
+    // The closest points are between an end of the segment and the face, between the segment
+    // and an edge, or where the segment goes through the face
+    let mut closest = (capsule.a, a, f32::INFINITY);
+    let mut consider = |on_capsule: Point3<f32>, on_triangle: Point3<f32>| {
+        let distance2 = (on_capsule - on_triangle).magnitude2();
+        if distance2 < closest.2 {
+            closest = (on_capsule, on_triangle, distance2);
+        }
+    };
+    for end in [capsule.a, capsule.b] {
+        consider(end, closest_point_on_triangle(end, triangle));
+    }
+    for edge in [(a, b), (b, c), (c, a)] {
+        let (on_capsule, on_triangle) = closest_points_on_segments((capsule.a, capsule.b), edge);
+        consider(on_capsule, on_triangle);
+    }
+    if height_a * height_b < 0. {
+        let crossing = capsule.a + (capsule.b - capsule.a) * (height_a / (height_a - height_b));
+        if (closest_point_on_triangle(crossing, triangle) - crossing).magnitude2() < 1e-10 {
+            consider(crossing, crossing);
+        }
+    }
+
+    let (on_capsule, on_triangle, distance2) = closest;
+    if distance2 >= radius * radius {
+        return None;
+    }
+    let distance = distance2.sqrt();
+    if distance > 1e-5 {
+        return Some(Contact {
+            point: on_triangle,
+            normal: (on_capsule - on_triangle) / distance,
+            depth: radius - distance,
+        });
+    }
+
+    // The segment touches the triangle, so push it out along the face normal, as far as the end
+    // on the other side is past the face
+    let middle = capsule.a.midpoint(capsule.b);
+    let (normal, below) = if (middle - a).dot(face_normal) >= 0. {
+        (face_normal, height_a.min(height_b))
+    } else {
+        (-face_normal, (-height_a).min(-height_b))
+    };
+    Some(Contact {
+        point: on_triangle,
+        normal,
+        depth: radius - below.min(0.),
+    })
+}
+
+/// The contacts between a capsule and every triangle of a hierarchy that it overlaps, deepest
+/// first
+pub fn capsule_mesh_contacts(capsule: &Capsule, bvh: &TriangleBvh) -> Vec<Contact> {
+    let mut contacts = Vec::new();
+    bvh.query_aabb(&capsule.aabb(), |_, triangle| {
+        contacts.extend(capsule_triangle_contact(capsule, triangle));
+    });
+    contacts.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+    contacts
+}
+
+/// Where a moving box first touches another box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SweepHit {
+    /// The fraction of the motion that can be made before touching, from 0 to 1
+    pub time: f32,
+    /// The normal of the face that was hit, pointing back towards the moving box
+    pub normal: Vector3<f32>,
+}
+
+/// Find where a box moving by `motion` first touches `target`, or `None` if it misses it
+///
+/// The whole path of the box is tested, so fast boxes can't skip over thin ones between frames.
+/// Boxes that already overlap `target` don't hit it, so that they can move out of it.
+pub fn sweep_aabb(moving: &Aabb, motion: Vector3<f32>, target: &Aabb) -> Option<SweepHit> {
+    // Find when the box enters and leaves the target's slab along each axis
+    let (mut entry, mut exit) = (f32::NEG_INFINITY, f32::INFINITY);
+    let mut entry_axis = 0;
+    for axis in 0..3 {
+        let (axis_entry, axis_exit) = if motion[axis] == 0. {
+            // Never entering the slab, or always in it
+            if moving.max[axis] <= target.min[axis] || moving.min[axis] >= target.max[axis] {
+                return None;
+            }
+            (f32::NEG_INFINITY, f32::INFINITY)
+        } else if motion[axis] > 0. {
+            (
+                (target.min[axis] - moving.max[axis]) / motion[axis],
+                (target.max[axis] - moving.min[axis]) / motion[axis],
+            )
+        } else {
+            (
+                (target.max[axis] - moving.min[axis]) / motion[axis],
+                (target.min[axis] - moving.max[axis]) / motion[axis],
+            )
+        };
+        if axis_entry > entry {
+            entry = axis_entry;
+            entry_axis = axis;
+        }
+        exit = exit.min(axis_exit);
+    }
+
+    // The box is inside every slab at once between the entry and exit
+    if entry > exit || !(0. ..=1.).contains(&entry) {
+        return None;
+    }
+    let mut normal = Vector3::zero();
+    normal[entry_axis] = -motion[entry_axis].signum();
+    Some(SweepHit {
+        time: entry,
+        normal,
+    })
+}
+
+/// The result of `move_and_slide`
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlideResult {
+    /// The box at the start, at every place it changed direction, and at the end
+    pub path: Vec<Aabb>,
+    /// Where the box touched the obstacles it slid along
+    pub contacts: Vec<Contact>,
+}
+
+impl SlideResult {
+    /// How far the box moved in the end
+    pub fn motion(&self) -> Vector3<f32> {
+        self.path.last().unwrap().center() - self.path[0].center()
+    }
+}
+
+/// Move a box as far as it can go by `motion`, sliding along the obstacles that it hits
+///
+/// The box stops `SKIN` away from the obstacles, and whatever motion is left when it hits one
+/// continues along the obstacle's face, up to a few times.
+pub fn move_and_slide(aabb: &Aabb, motion: Vector3<f32>, obstacles: &[Aabb]) -> SlideResult {
+    let mut current = *aabb;
+    let mut remaining = motion;
+    let mut result = SlideResult {
+        path: vec![current],
+        contacts: Vec::new(),
+    };
+    for _ in 0..MAX_SLIDES {
+        if remaining.magnitude2() == 0. {
+            break;
+        }
+        let earliest = obstacles
+            .iter()
+            .filter_map(|obstacle| {
+                sweep_aabb(&current, remaining, obstacle).map(|hit| (hit, obstacle))
+            })
+            .min_by(|(a, _), (b, _)| a.time.total_cmp(&b.time));
+        let (hit, obstacle) = match earliest {
+            Some(earliest) => earliest,
+            None => {
+                current = current.translated(remaining);
+                break;
+            }
+        };
+
+        // Move up to the obstacle, then back off from it a little
+        current = current.translated(remaining * hit.time + hit.normal * SKIN);
+        result.path.push(current);
+        result
+            .contacts
+            .push(face_contact(&current, obstacle, hit.normal));
+
+        // Keep going with the rest of the motion along the face
+        remaining *= 1. - hit.time;
+        remaining -= hit.normal * remaining.dot(hit.normal);
+    }
+    if result.path.last() != Some(&current) {
+        result.path.push(current);
+    }
+    result
+}
+
+/// The middle of where a box touches the face of an obstacle with the given normal
+fn face_contact(aabb: &Aabb, obstacle: &Aabb, normal: Vector3<f32>) -> Contact {
+    let mut point = Point3::origin();
+    for axis in 0..3 {
+        point[axis] = if normal[axis] > 0. {
+            obstacle.max[axis]
+        } else if normal[axis] < 0. {
+            obstacle.min[axis]
+        } else {
+            (aabb.min[axis].max(obstacle.min[axis]) + aabb.max[axis].min(obstacle.max[axis])) / 2.
+        };
+    }
+    Contact {
+        point,
+        normal,
+        depth: 0.,
+    }
+}
+
+/// A box that moves with a velocity and gravity, and collides with static boxes
+///
+/// Step it with a fixed time step, e.g. from `FixedTimestep`, so that jumps and falls come out the
+/// same no matter the frame rate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KinematicBody {
+    /// The center of the body
+    pub position: Point3<f32>,
+    /// Half of the size of the body along each axis
+    pub half_extents: Vector3<f32>,
+    /// The velocity in units per second
+    pub velocity: Vector3<f32>,
+    /// The acceleration from gravity in units per second squared
+    pub gravity: Vector3<f32>,
+    /// Whether the body was standing on something after the last step
+    pub grounded: bool,
+}
+
+impl KinematicBody {
+    pub fn new(position: Point3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self {
+            position,
+            half_extents,
+            velocity: Vector3::zero(),
+            gravity: Vector3::new(0., -9.8, 0.),
+            grounded: false,
+        }
+    }
+
+    /// The box the body takes up
+    pub fn aabb(&self) -> Aabb {
+        Aabb::from_center(self.position, self.half_extents)
+    }
+
+    /// Move the body by its velocity and gravity, without colliding with anything
+    pub fn integrate(&mut self, delta: f32) {
+        self.velocity += self.gravity * delta;
+        self.position += self.velocity * delta;
+    }
+
+    /// Move the body by its velocity and gravity, sliding along the obstacles it hits
+    ///
+    /// The body is pushed out of any obstacle it starts inside of first. Hitting an obstacle
+    /// stops the velocity going into it, and landing on top of one makes the body `grounded`.
+    pub fn step(&mut self, delta: f32, obstacles: &[Aabb]) -> SlideResult {
+        self.velocity += self.gravity * delta;
+
+        // Get out of anything we are stuck in, like something that was placed on top of us
+        for obstacle in obstacles {
+            if let Some(contact) = aabb_contact(&self.aabb(), obstacle) {
+                self.position += contact.normal * (contact.depth + SKIN);
+            }
+        }
+
+        let result = move_and_slide(&self.aabb(), self.velocity * delta, obstacles);
+        self.position += result.motion();
+        self.grounded = false;
+        for contact in &result.contacts {
+            self.velocity -= contact.normal * self.velocity.dot(contact.normal).min(0.);
+            self.grounded |= contact.normal.y >= GROUND_NORMAL_Y;
+        }
+        result
+    }
+}
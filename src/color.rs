@@ -0,0 +1,280 @@
+use crate::tween::Lerp;
+
+/// An RGBA color
+///
+/// The components are stored sRGB encoded, which is how colors are written down almost
+/// everywhere: in hex codes, color pickers, and image files. Use `to_linear` for values that take
+/// part in lighting math in a shader, and `to_srgb` for values that are written straight to a
+/// framebuffer that isn't sRGB, like clear colors. Alpha is always linear.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::rgba(0., 0., 0., 0.);
+    pub const BLACK: Color = Color::rgb(0., 0., 0.);
+    pub const WHITE: Color = Color::rgb(1., 1., 1.);
+    pub const GRAY: Color = Color::rgb(0.5, 0.5, 0.5);
+    pub const RED: Color = Color::rgb(1., 0., 0.);
+    pub const GREEN: Color = Color::rgb(0., 1., 0.);
+    pub const BLUE: Color = Color::rgb(0., 0., 1.);
+    pub const YELLOW: Color = Color::rgb(1., 1., 0.);
+    pub const CYAN: Color = Color::rgb(0., 1., 1.);
+    pub const MAGENTA: Color = Color::rgb(1., 0., 1.);
+    pub const ORANGE: Color = Color::rgb(1., 0.5, 0.);
+    pub const CORNFLOWER_BLUE: Color = Color::rgb(0.392, 0.584, 0.929);
+
+    /// An opaque color from sRGB components between 0 and 1
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Self::rgba(r, g, b, 1.)
+    }
+
+    /// A color from sRGB components and alpha between 0 and 1
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    /// A color from sRGB components and alpha between 0 and 255
+    pub fn from_srgb_u8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        Self::rgba(
+            r as f32 / 255.,
+            g as f32 / 255.,
+            b as f32 / 255.,
+            a as f32 / 255.,
+        )
+    }
+
+    /// A color from linear components and alpha between 0 and 1
+    pub fn from_linear(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::rgba(linear_to_srgb(r), linear_to_srgb(g), linear_to_srgb(b), a)
+    }
+
+    /// A color from a hex code like `#ffcc00`, `ffcc00`, `#fc0`, or `#ffcc0080` with alpha
+    ///
+    /// Returns `None` if the code isn't valid.
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.strip_prefix('#').unwrap_or(hex);
+        if !hex.is_ascii() {
+            return None;
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..i + 1], 16).ok();
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+
+        match hex.len() {
+            // Short codes repeat each digit, so `f` is `ff`
+            3 => Some(Self::from_srgb_u8(
+                digit(0)? * 17,
+                digit(1)? * 17,
+                digit(2)? * 17,
+                255,
+            )),
+            6 => Some(Self::from_srgb_u8(byte(0)?, byte(2)?, byte(4)?, 255)),
+            8 => Some(Self::from_srgb_u8(byte(0)?, byte(2)?, byte(4)?, byte(6)?)),
+            _ => None,
+        }
+    }
+
+    /// An opaque color from a hue in degrees and a saturation and value between 0 and 1
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue.rem_euclid(360.) / 60.;
+        let chroma = value * saturation;
+        let x = chroma * (1. - (hue % 2. - 1.).abs());
+        let (r, g, b) = match hue as u32 {
+            0 => (chroma, x, 0.),
+            1 => (x, chroma, 0.),
+            2 => (0., chroma, x),
+            3 => (0., x, chroma),
+            4 => (x, 0., chroma),
+            _ => (chroma, 0., x),
+        };
+        let m = value - chroma;
+        Self::rgb(r + m, g + m, b + m)
+    }
+
+    /// The same color with a different alpha
+    pub fn with_alpha(self, a: f32) -> Self {
+        Self { a, ..self }
+    }
+
+    /// The hex code of the color, like `#ffcc00`, with the alpha added if it isn't opaque
+    pub fn to_hex(&self) -> String {
+        let [r, g, b, a] = self.to_srgb_u8();
+        if a == 255 {
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        } else {
+            format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a)
+        }
+    }
+
+    /// The sRGB components and alpha between 0 and 255
+    pub fn to_srgb_u8(&self) -> [u8; 4] {
+        let byte = |x: f32| (x.clamp(0., 1.) * 255.).round() as u8;
+        [byte(self.r), byte(self.g), byte(self.b), byte(self.a)]
+    }
+
+    /// The sRGB components and alpha, for writing straight to a framebuffer that isn't sRGB
+    pub fn to_srgb(&self) -> [f32; 4] {
+        [self.r, self.g, self.b, self.a]
+    }
+
+    /// The linear components and alpha, for uploading to shaders that do lighting math
+    pub fn to_linear(&self) -> [f32; 4] {
+        [
+            srgb_to_linear(self.r),
+            srgb_to_linear(self.g),
+            srgb_to_linear(self.b),
+            self.a,
+        ]
+    }
+}
+
+impl Default for Color {
+    fn default() -> Self {
+        Color::BLACK
+    }
+}
+
+/// sRGB components and alpha
+impl From<[f32; 4]> for Color {
+    fn from([r, g, b, a]: [f32; 4]) -> Self {
+        Self::rgba(r, g, b, a)
+    }
+}
+
+/// Opaque sRGB components
+impl From<[f32; 3]> for Color {
+    fn from([r, g, b]: [f32; 3]) -> Self {
+        Self::rgb(r, g, b)
+    }
+}
+
+/// sRGB components and alpha
+impl From<(f32, f32, f32, f32)> for Color {
+    fn from((r, g, b, a): (f32, f32, f32, f32)) -> Self {
+        Self::rgba(r, g, b, a)
+    }
+}
+
+/// Opaque sRGB components
+impl From<(f32, f32, f32)> for Color {
+    fn from((r, g, b): (f32, f32, f32)) -> Self {
+        Self::rgb(r, g, b)
+    }
+}
+
+/// Blends in linear space, which avoids the dark band between colors that blending sRGB values
+/// gives
+impl Lerp for Color {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let [r, g, b, a] = self.to_linear().lerp(&other.to_linear(), t);
+        Self::from_linear(r, g, b, a)
+    }
+}
+
+/// Convert an sRGB encoded component to linear
+pub fn srgb_to_linear(x: f32) -> f32 {
+    if x <= 0.04045 {
+        x / 12.92
+    } else {
+        ((x + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Convert a linear component to sRGB encoded
+pub fn linear_to_srgb(x: f32) -> f32 {
+    if x <= 0.0031308 {
+        x * 12.92
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How far apart floats that went through the transfer functions may be
+    const EPSILON: f32 = 1e-5;
+
+    fn assert_close(actual: f32, expected: f32) {
+        assert!(
+            (actual - expected).abs() <= EPSILON,
+            "{} is not {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn srgb_linear_round_trip() {
+        for i in 0..=255 {
+            let x = i as f32 / 255.;
+            assert_close(linear_to_srgb(srgb_to_linear(x)), x);
+            assert_close(srgb_to_linear(linear_to_srgb(x)), x);
+        }
+        // Half way in sRGB is much darker than half way in linear light
+        assert_close(srgb_to_linear(0.5), 0.214_041_14);
+        assert_close(linear_to_srgb(0.5), 0.735_356_6);
+        // The linear segment near black meets the curve
+        assert_close(srgb_to_linear(0.04045), 0.04045 / 12.92);
+        assert_close(linear_to_srgb(0.0031308), 0.0031308 * 12.92);
+
+        let color = Color::rgba(0.25, 0.5, 0.75, 0.5);
+        let [r, g, b, a] = color.to_linear();
+        assert_eq!(a, 0.5, "Alpha is linear");
+        let round_trip = Color::from_linear(r, g, b, a);
+        for (actual, expected) in [
+            (round_trip.r, color.r),
+            (round_trip.g, color.g),
+            (round_trip.b, color.b),
+            (round_trip.a, color.a),
+        ] {
+            assert_close(actual, expected);
+        }
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for hex in [
+            "#000000",
+            "#ffffff",
+            "#ffcc00",
+            "#6495ed",
+            "#12345678",
+            "#ff000000",
+        ] {
+            assert_eq!(Color::from_hex(hex).unwrap().to_hex(), hex);
+        }
+        assert_eq!(Color::from_hex("#fc0"), Color::from_hex("#ffcc00"));
+        assert_eq!(Color::from_hex("ffcc00"), Color::from_hex("#ffcc00"));
+        assert_eq!(Color::from_hex("#FFCC00"), Color::from_hex("#ffcc00"));
+        assert_eq!(Color::from_hex("#ff0000"), Some(Color::RED));
+        assert_eq!(Color::RED.with_alpha(0.5).to_hex(), "#ff000080");
+
+        for invalid in ["", "#", "#ff", "#ffcc0", "#gg0000", "#ffcc00ff00", "#ééé"] {
+            assert_eq!(Color::from_hex(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn lerp_in_linear_space() {
+        let middle = Color::BLACK.lerp(&Color::WHITE, 0.5);
+        // Blending sRGB values would give 0.5, which looks too dark
+        assert_close(middle.r, linear_to_srgb(0.5));
+        assert_close(middle.g, middle.r);
+        assert_close(middle.b, middle.r);
+        assert_eq!(middle.to_srgb_u8(), [188, 188, 188, 255]);
+
+        let start = Color::from_hex("#6495ed").unwrap();
+        let end = Color::from_hex("#ffcc00").unwrap();
+        assert_eq!(start.lerp(&end, 0.).to_hex(), start.to_hex());
+        assert_eq!(start.lerp(&end, 1.).to_hex(), end.to_hex());
+
+        // Alpha isn't encoded, so it blends evenly
+        assert_close(Color::TRANSPARENT.lerp(&Color::WHITE, 0.25).a, 0.25);
+    }
+}
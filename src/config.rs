@@ -0,0 +1,664 @@
+use std::{
+    fmt::{self, Write as _},
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    clustered_lights::ClusterGrid,
+    gbuffer::GBufferLayout,
+    latency::LatencyMode,
+    theme::Theme,
+    workarounds::{self, Workaround},
+    WindowConfig,
+};
+
+/// The name of the config file, which is looked for next to the executable and then in the
+/// working directory
+pub const CONFIG_FILE_NAME: &str = "me_learning_opengl.toml";
+
+/// The prefix of the environment variables that override the config file, like `MLO_WIDTH`
+const ENV_PREFIX: &str = "MLO_";
+
+/// The keys of the settings, including the short `msaa` for `msaa_samples`
+const KEYS: &[&str] = &[
+    "title",
+    "width",
+    "height",
+    "vsync",
+    "msaa_samples",
+    "msaa",
+    "asset_dir",
+    "ui_scale",
+    "shader_cache_dir",
+    "remember_window",
+    "theme",
+    "gbuffer_layout",
+    "workarounds",
+    "texture_audit",
+    "capture_first_frame",
+    "cluster_grid",
+    "latency_mode",
+];
+
+/// Settings for the examples that can be changed without recompiling
+///
+/// The settings come from three places, each overriding the one before it:
+///
+/// 1. `me_learning_opengl.toml`, a flat file of `key = value` lines
+/// 2. Environment variables named after the keys, like `MLO_WIDTH=1280` or `MLO_ASSET_DIR=...`
+/// 3. Command line arguments named after the keys, like `--width 1280` or `--asset-dir ...`
+///
+/// Unknown keys in the file print a warning instead of failing, so that old config files keep
+/// working.
+///
+/// The file is read by `parse_toml` instead of `serde` and the `toml` crate. The config is only
+/// flat keys and the theme tables, and those would be the examples' heaviest dependencies for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// The title of the window
+    pub title: String,
+    /// The width of the window in physical pixels
+    pub width: u32,
+    /// The height of the window in physical pixels
+    pub height: u32,
+    /// Whether or not presenting should wait for vsync
+    ///
+    /// The window surface can't change its swap interval, so this is only a hint for handlers,
+    /// for example to skip presenting with `RenderSettings::present` when benchmarking.
+    pub vsync: bool,
+    /// The number of samples to use for multisampling, or 0 to turn it off
+    ///
+    /// The window surface can't be multisampled, so this is the starting
+    /// `RenderSettings::anti_aliasing`, which draws into a multisampled framebuffer instead.
+    pub msaa_samples: u32,
+    /// The directory that assets are loaded from
+    pub asset_dir: PathBuf,
+    /// A multiplier for the size of overlays like insets, on top of the window's hidpi factor
+    pub ui_scale: f32,
+    /// The directory that linked shader programs are cached in, or empty to not cache them
+    pub shader_cache_dir: PathBuf,
+    /// Whether or not to save the windows' positions, sizes, and fullscreen states when they
+    /// close, and put them back when they open again
+    pub remember_window: bool,
+    /// The name of the theme that windows start with
+    pub theme: String,
+    /// The themes that can be switched between, which are the built-in ones changed or added to
+    /// by `["theme.<name>"]` tables of hex colors in the config file
+    pub themes: Vec<Theme>,
+    /// How the deferred examples lay out their G-buffers, `fat` or `packed`, to compare the two
+    pub gbuffer_layout: GBufferLayout,
+    /// Driver workarounds to turn on or off, over the ones picked for the driver, written like
+    /// `clamp_max_samples=on, pad_unpack_rows=off`
+    pub workarounds: Vec<(Workaround, bool)>,
+    /// Whether or not to warn about textures whose sRGB or linear format doesn't suit their
+    /// purpose, which is on in debug builds by default
+    pub texture_audit: bool,
+    /// Whether or not to save each window's first frame to `first_frame.png`, with a frame report
+    /// in `first_frame.txt`, before it is presented, for when something goes wrong at startup
+    pub capture_first_frame: bool,
+    /// How many clusters across, down, and deep clustered lighting splits the view into, written
+    /// like `16x9x24`
+    pub cluster_grid: ClusterGrid,
+    /// Whether windows start out drawing as many frames as they can, `throughput`, or waiting for
+    /// the GPU to finish each frame so the next one reads newer input, `low_latency`
+    pub latency_mode: LatencyMode,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let window = WindowConfig::default();
+        Self {
+            title: window.title,
+            width: window.width,
+            height: window.height,
+            vsync: true,
+            msaa_samples: 0,
+            asset_dir: PathBuf::from("./assets"),
+            ui_scale: 1.,
+            shader_cache_dir: PathBuf::from("./shader_cache"),
+            remember_window: false,
+            theme: Theme::default().name,
+            themes: Theme::built_in(),
+            gbuffer_layout: GBufferLayout::default(),
+            workarounds: Vec::new(),
+            texture_audit: cfg!(debug_assertions),
+            capture_first_frame: false,
+            cluster_grid: ClusterGrid::default(),
+            latency_mode: LatencyMode::default(),
+        }
+    }
+}
+
+/// An error in a config file
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    /// A line that couldn't be read, counting from 1
+    InvalidLine {
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(error) => write!(f, "Couldn't read the config file: {}", error),
+            ConfigError::InvalidLine { line, message } => {
+                write!(f, "Invalid config on line {}: {}", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(error: io::Error) -> Self {
+        ConfigError::Io(error)
+    }
+}
+
+impl Config {
+    /// Load the config file if there is one, then apply the environment variable and command line
+    /// overrides
+    ///
+    /// Problems are printed as warnings and the defaults are used for anything that couldn't be
+    /// read.
+    pub fn load() -> Self {
+        Self::load_over(Self::default())
+    }
+
+    /// Like `load`, but with a window's title and size in place of the default ones, so that the
+    /// config file, environment variables, and command line can still change them
+    pub fn load_for_window(window_config: &WindowConfig) -> Self {
+        Self::load_over(Self {
+            title: window_config.title.clone(),
+            width: window_config.width,
+            height: window_config.height,
+            ..Self::default()
+        })
+    }
+
+    /// Load the config file if there is one, then apply the environment variable overrides
+    pub fn load_without_args() -> Self {
+        Self::load_without_args_over(Self::default())
+    }
+
+    /// Load the config over the given defaults, with every override
+    fn load_over(defaults: Self) -> Self {
+        let mut config = Self::load_without_args_over(defaults);
+        config.apply_args(std::env::args().skip(1));
+        config
+    }
+
+    /// Load the config file over the given defaults if there is one, then apply the environment
+    /// variable overrides
+    fn load_without_args_over(defaults: Self) -> Self {
+        let mut config = match Self::find_file() {
+            Some(path) => {
+                let read = std::fs::read_to_string(&path)
+                    .map_err(ConfigError::from)
+                    .and_then(|toml| Self::from_toml_over(defaults.clone(), &toml));
+                match read {
+                    Ok(config) => config,
+                    Err(error) => {
+                        eprintln!("Warning: {}: {}", path.display(), error);
+                        defaults
+                    }
+                }
+            }
+            None => defaults,
+        };
+        config.apply_env();
+        config
+    }
+
+    /// The config file next to the executable or in the working directory, if there is one
+    pub fn find_file() -> Option<PathBuf> {
+        let next_to_exe = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.parent()?.join(CONFIG_FILE_NAME)));
+        next_to_exe
+            .into_iter()
+            .chain(Some(PathBuf::from(CONFIG_FILE_NAME)))
+            .find(|path| path.is_file())
+    }
+
+    /// Read a config file, without applying any overrides
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Read a config from `key = value` lines, without applying any overrides
+    ///
+    /// This understands the small part of TOML that the config needs: comments, string, integer,
+    /// and boolean values, and the `["theme.<name>"]` tables of themes.
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        Self::from_toml_over(Self::default(), toml)
+    }
+
+    /// Read a config from `key = value` lines over the given defaults
+    fn from_toml_over(mut config: Self, toml: &str) -> Result<Self, ConfigError> {
+        parse_toml(toml, |section, key, value| match section {
+            Some(section) => match section.strip_prefix("theme.") {
+                Some(name) => config.theme_mut(name).set(key, value),
+                None => Ok(false),
+            },
+            None => config.set(key, value),
+        })?;
+        Ok(config)
+    }
+
+    /// Write the config as `key = value` lines that `from_toml` can read
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        writeln!(toml, "title = {:?}", self.title).unwrap();
+        writeln!(toml, "width = {}", self.width).unwrap();
+        writeln!(toml, "height = {}", self.height).unwrap();
+        writeln!(toml, "vsync = {}", self.vsync).unwrap();
+        writeln!(toml, "msaa_samples = {}", self.msaa_samples).unwrap();
+        writeln!(
+            toml,
+            "asset_dir = {:?}",
+            self.asset_dir.display().to_string()
+        )
+        .unwrap();
+        writeln!(toml, "ui_scale = {:?}", self.ui_scale).unwrap();
+        writeln!(
+            toml,
+            "shader_cache_dir = {:?}",
+            self.shader_cache_dir.display().to_string()
+        )
+        .unwrap();
+        writeln!(toml, "remember_window = {}", self.remember_window).unwrap();
+        writeln!(toml, "theme = {:?}", self.theme).unwrap();
+        writeln!(toml, "gbuffer_layout = {:?}", self.gbuffer_layout.name()).unwrap();
+        writeln!(
+            toml,
+            "workarounds = {:?}",
+            workarounds::format_overrides(&self.workarounds)
+        )
+        .unwrap();
+        writeln!(toml, "texture_audit = {}", self.texture_audit).unwrap();
+        writeln!(toml, "capture_first_frame = {}", self.capture_first_frame).unwrap();
+        writeln!(toml, "cluster_grid = \"{}\"", self.cluster_grid).unwrap();
+        writeln!(toml, "latency_mode = {:?}", self.latency_mode.name()).unwrap();
+        for theme in &self.themes {
+            toml.push('\n');
+            toml.push_str(&theme.to_toml());
+        }
+        toml
+    }
+
+    /// Write a config file with the default settings, as a starting point for editing
+    pub fn write_default<P: AsRef<Path>>(path: P) -> io::Result<()> {
+        std::fs::write(path, Self::default().to_toml())
+    }
+
+    /// Apply overrides from `MLO_` environment variables
+    pub fn apply_env(&mut self) {
+        for (name, value) in std::env::vars() {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                match self.set(&key.to_lowercase(), &value) {
+                    Ok(true) => (),
+                    Ok(false) => eprintln!("Warning: Unknown config variable `{}`", name),
+                    Err(message) => eprintln!("Warning: Ignoring `{}`: {}", name, message),
+                }
+            }
+        }
+    }
+
+    /// Apply overrides from `--key value` command line arguments
+    ///
+    /// Arguments that aren't config keys are skipped, so that examples can have arguments of
+    /// their own.
+    pub fn apply_args<I: IntoIterator<Item = String>>(&mut self, args: I) {
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let key = match arg.strip_prefix("--") {
+                Some(key) => key.replace('-', "_"),
+                None => continue,
+            };
+            if !KEYS.contains(&key.as_str()) {
+                continue;
+            }
+            match args.next() {
+                Some(value) => {
+                    if let Err(message) = self.set(&key, &value) {
+                        eprintln!("Warning: Ignoring `{}`: {}", arg, message);
+                    }
+                }
+                None => eprintln!("Warning: `{}` needs a value", arg),
+            }
+        }
+    }
+
+    /// Whether or not a command line argument, like `--width`, is one of the config's
+    pub fn is_arg(arg: &str) -> bool {
+        arg.strip_prefix("--")
+            .is_some_and(|key| KEYS.contains(&key.replace('-', "_").as_str()))
+    }
+
+    /// The settings for a window created from this config
+    pub fn window_config(&self) -> WindowConfig {
+        WindowConfig {
+            title: self.title.clone(),
+            width: self.width,
+            height: self.height,
+            remember_placement: self.remember_window,
+            ..Default::default()
+        }
+    }
+
+    /// The theme with the given name, if there is one
+    pub fn find_theme(&self, name: &str) -> Option<&Theme> {
+        self.themes.iter().find(|theme| theme.name == name)
+    }
+
+    /// The theme with the given name, adding a copy of the default theme by that name if there
+    /// isn't one yet
+    fn theme_mut(&mut self, name: &str) -> &mut Theme {
+        let index = match self.themes.iter().position(|theme| theme.name == name) {
+            Some(index) => index,
+            None => {
+                self.themes.push(Theme::default().named(name));
+                self.themes.len() - 1
+            }
+        };
+        &mut self.themes[index]
+    }
+
+    /// The path of an asset in the asset directory
+    pub fn asset_path<P: AsRef<Path>>(&self, asset: P) -> PathBuf {
+        self.asset_dir.join(asset)
+    }
+
+    /// Set a setting from its text value, returning false if the key isn't a setting
+    fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        let number = || {
+            value
+                .parse::<u32>()
+                .map_err(|_| format!("Expected a number for `{}`, got `{}`", key, value))
+        };
+        let boolean = || match value {
+            "true" | "1" => Ok(true),
+            "false" | "0" => Ok(false),
+            _ => Err(format!(
+                "Expected true or false for `{}`, got `{}`",
+                key, value
+            )),
+        };
+        match key {
+            "title" => self.title = value.into(),
+            "width" => self.width = number()?,
+            "height" => self.height = number()?,
+            "vsync" => self.vsync = boolean()?,
+            "msaa_samples" | "msaa" => self.msaa_samples = number()?,
+            "asset_dir" => self.asset_dir = value.into(),
+            "ui_scale" => {
+                self.ui_scale = value
+                    .parse::<f32>()
+                    .ok()
+                    .filter(|scale| *scale > 0.)
+                    .ok_or_else(|| {
+                        format!("Expected a positive number for `ui_scale`, got `{}`", value)
+                    })?
+            }
+            "shader_cache_dir" => self.shader_cache_dir = value.into(),
+            "remember_window" => self.remember_window = boolean()?,
+            "theme" => self.theme = value.into(),
+            "gbuffer_layout" => {
+                self.gbuffer_layout = GBufferLayout::parse(value).ok_or_else(|| {
+                    format!(
+                        "Expected fat or packed for `gbuffer_layout`, got `{}`",
+                        value
+                    )
+                })?
+            }
+            "workarounds" => self.workarounds = workarounds::parse_overrides(value)?,
+            "texture_audit" => self.texture_audit = boolean()?,
+            "capture_first_frame" => self.capture_first_frame = boolean()?,
+            "cluster_grid" => {
+                self.cluster_grid = ClusterGrid::parse(value).ok_or_else(|| {
+                    format!(
+                        "Expected a grid like 16x9x24 for `cluster_grid`, got `{}`",
+                        value
+                    )
+                })?
+            }
+            "latency_mode" => {
+                self.latency_mode = LatencyMode::parse(value).ok_or_else(|| {
+                    format!(
+                        "Expected throughput or low_latency for `latency_mode`, got `{}`",
+                        value
+                    )
+                })?
+            }
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Read `key = value` lines and `["table"]` headers, calling `set` with the table, key, and value
+/// of every line
+///
+/// `set` returns false for unknown keys, which print a warning, or an error for invalid values.
+pub(crate) fn parse_toml<F>(toml: &str, mut set: F) -> Result<(), ConfigError>
+where
+    F: FnMut(Option<&str>, &str, &str) -> Result<bool, String>,
+{
+    let mut section = None;
+    for (index, line) in toml.lines().enumerate() {
+        let invalid = |message: String| ConfigError::InvalidLine {
+            line: index + 1,
+            message,
+        };
+
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        // The keys after a table header belong to it, and table names can be quoted
+        if let Some(name) = line
+            .strip_prefix('[')
+            .and_then(|line| line.strip_suffix(']'))
+        {
+            let name = name.trim();
+            section = Some(match name.strip_prefix('"') {
+                Some(quoted) => quoted
+                    .strip_suffix('"')
+                    .map(unescape)
+                    .ok_or_else(|| invalid("Unterminated table name".into()))?,
+                None => name.to_string(),
+            });
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| invalid("Expected `key = value`".into()))?;
+        let (key, value) = (key.trim(), value.trim());
+
+        // Strings are quoted in TOML
+        let value = if value.starts_with('"') {
+            value
+                .strip_prefix('"')
+                .and_then(|value| value.strip_suffix('"'))
+                .map(unescape)
+                .ok_or_else(|| invalid(format!("Unterminated string for `{}`", key)))?
+        } else {
+            value.to_string()
+        };
+
+        if !set(section.as_deref(), key, &value).map_err(invalid)? {
+            match &section {
+                Some(section) => eprintln!(
+                    "Warning: Unknown config key `{}` in `[{}]` on line {}",
+                    key,
+                    section,
+                    index + 1
+                ),
+                None => eprintln!(
+                    "Warning: Unknown config key `{}` on line {}",
+                    key,
+                    index + 1
+                ),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Undo the escaping of quotes and backslashes in a string written by `to_toml`
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Remove a `#` comment from the end of a line, unless it's inside of a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => (),
+        }
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::color::Color;
+
+    /// A config with every setting changed from its default
+    fn changed_config() -> Config {
+        let mut solarized = Theme::light().named("solarized");
+        solarized.clear_color = Color::from_hex("#fdf6e3").unwrap();
+        solarized.panel_color = Color::from_hex("#eee8d580").unwrap();
+        let mut themes = Theme::built_in();
+        themes.push(solarized);
+
+        Config {
+            title: r#"A "quoted" \ title"#.into(),
+            width: 1280,
+            height: 720,
+            vsync: false,
+            msaa_samples: 4,
+            asset_dir: PathBuf::from("../assets"),
+            ui_scale: 1.25,
+            shader_cache_dir: PathBuf::new(),
+            remember_window: true,
+            theme: "solarized".into(),
+            themes,
+            gbuffer_layout: GBufferLayout::Packed,
+            workarounds: vec![
+                (Workaround::PadUnpackRows, true),
+                (Workaround::FlushBeforeContextSwitch, false),
+            ],
+            texture_audit: !cfg!(debug_assertions),
+            capture_first_frame: true,
+            cluster_grid: ClusterGrid::new(8, 4, 12),
+            latency_mode: LatencyMode::LowLatency,
+        }
+    }
+
+    /// The config with its theme colors rounded to the 8 bit hex codes that the file stores
+    fn with_hex_colors(mut config: Config) -> Config {
+        let round = |color: &mut Color| *color = Color::from_hex(&color.to_hex()).unwrap();
+        for theme in &mut config.themes {
+            round(&mut theme.clear_color);
+            round(&mut theme.grid_minor_color);
+            round(&mut theme.grid_major_color);
+            round(&mut theme.text_color);
+            round(&mut theme.panel_color);
+            round(&mut theme.wireframe_color);
+        }
+        config
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let config = with_hex_colors(changed_config());
+        assert_eq!(Config::from_toml(&config.to_toml()).unwrap(), config);
+
+        let defaults = with_hex_colors(Config::default());
+        assert_eq!(Config::from_toml(&defaults.to_toml()).unwrap(), defaults);
+    }
+
+    #[test]
+    fn default_file_reads_back() {
+        let path = std::env::temp_dir().join(format!(
+            "me_learning_opengl-default-{}.toml",
+            std::process::id()
+        ));
+        Config::write_default(&path).unwrap();
+        let read = Config::open(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.unwrap(), with_hex_colors(Config::default()));
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let mut config = Config::from_toml("width = 640\nheight = 480\nvsync = false\n").unwrap();
+
+        // Only this test sets these variables, so the other tests don't see them
+        std::env::set_var("MLO_WIDTH", "800");
+        std::env::set_var("MLO_HEIGHT", "600");
+        config.apply_env();
+        std::env::remove_var("MLO_WIDTH");
+        std::env::remove_var("MLO_HEIGHT");
+        assert_eq!(
+            (config.width, config.height, config.vsync),
+            (800, 600, false)
+        );
+
+        config.apply_args(
+            [
+                "example-arg",
+                "--height",
+                "900",
+                "--asset-dir",
+                "/tmp/assets",
+            ]
+            .iter()
+            .map(|arg| arg.to_string()),
+        );
+        assert_eq!((config.width, config.height), (800, 900));
+        assert_eq!(config.asset_dir, PathBuf::from("/tmp/assets"));
+    }
+
+    #[test]
+    fn unknown_keys_are_not_errors() {
+        let config = Config::from_toml(
+            "widht = 3\nwidth = 640\n\n[window]\nheight = 1\n\n[\"theme.dark\"]\nglow = \"#fff\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.width, 640);
+        assert_eq!(config.height, Config::default().height);
+        assert_eq!(config.themes, Theme::built_in());
+
+        // Values that don't fit a known key are still errors
+        match Config::from_toml("title = \"Demo\"\nwidth = wide\n") {
+            Err(ConfigError::InvalidLine { line: 2, .. }) => (),
+            result => panic!("Expected an error on line 2, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,780 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use winit::VirtualKeyCode;
+
+use crate::{
+    color::Color,
+    debug_text::{DebugText, LINE_HEIGHT},
+    demo_state::DemoStates,
+    depth_mode::DepthMode,
+    depth_view::DepthViewMode,
+    latency::LatencyMode,
+    stereo::StereoMode,
+    texture_audit,
+    texture_viewer::{self, PickAt, ViewChannel},
+    theme::Theme,
+    viewport::Rect,
+    wireframe::WireframeParams,
+    AppContext,
+};
+
+/// How many lines of output the console keeps
+const MAX_OUTPUT_LINES: usize = 200;
+/// How many commands the console remembers for Up and Down
+const MAX_HISTORY: usize = 100;
+/// How much of the window the console covers, from the top
+const HEIGHT_FRACTION: f32 = 0.5;
+/// The space around the text in pixels, at a UI scale of 1
+const PADDING: f32 = 6.;
+
+const ERROR_COLOR: Color = Color::rgb(0.9, 0.3, 0.25);
+
+/// A command that can be run from the console
+///
+/// It is given the words after the command's name and the window's context, and returns a line
+/// to print on success or an error message.
+pub type CommandFn = Box<dyn FnMut(&[&str], &mut AppContext) -> Result<String, String>>;
+
+struct Command {
+    help: String,
+    run: CommandFn,
+}
+
+/// A line of console output
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct OutputLine {
+    text: String,
+    error: bool,
+}
+
+/// A quake-style console for running commands while an example is running
+///
+/// The console is toggled with the backtick key. While it is open, typed characters go to the
+/// console instead of to `AppContext::input`, Up and Down go through the previous commands, and
+/// Tab completes command names. The loop draws it over the top half of the window after the
+/// handler's `draw`.
+///
+/// It starts with a few built-in commands: `help`, `clear`, `set clear_color r g b [a]`,
+/// `reload shaders`, `stereo [off|on]`, `wireframe [off|on|dimmed]`, `screenshot [file]`,
+/// `copy [overlays]`, `ab <command...> <a> <b>`, `report`, `theme [name]`, `view [texture]`,
+/// `latency [throughput|low_latency]`, `state [clear]`, and `quit`.
+/// Handlers can add their own with `register`.
+pub struct Console {
+    open: bool,
+    /// The line being typed
+    input: String,
+    history: Vec<String>,
+    /// The history entry being shown, while going through it with Up and Down
+    history_index: Option<usize>,
+    /// What was typed before going through the history, shown again after the newest entry
+    draft: String,
+    output: VecDeque<OutputLine>,
+    commands: BTreeMap<String, Command>,
+}
+
+impl Default for Console {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Console {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Console")
+            .field("open", &self.open)
+            .field("input", &self.input)
+            .field("history", &self.history)
+            .field("commands", &self.commands.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Console {
+    /// A closed console with the built-in commands
+    pub fn new() -> Self {
+        let mut console = Self {
+            open: false,
+            input: String::new(),
+            history: Vec::new(),
+            history_index: None,
+            draft: String::new(),
+            output: VecDeque::new(),
+            commands: BTreeMap::new(),
+        };
+
+        console.register("help", "List the commands", |_, ctx| {
+            let commands = &ctx.console.commands;
+            let width = commands.keys().map(|name| name.len()).max().unwrap_or(0);
+            Ok(commands
+                .iter()
+                .map(|(name, command)| format!("{:width$}  {}", name, command.help, width = width))
+                .collect::<Vec<_>>()
+                .join("\n"))
+        });
+        console.register("clear", "Clear the console output", |_, ctx| {
+            ctx.console.output.clear();
+            Ok(String::new())
+        });
+        console.register(
+            "set",
+            "Set a value: clear_color r g b [a]",
+            |args, ctx| match args {
+                ["clear_color", components @ ..]
+                    if components.len() == 3 || components.len() == 4 =>
+                {
+                    let mut color = [1.; 4];
+                    for (value, arg) in color.iter_mut().zip(components) {
+                        *value = arg
+                            .parse()
+                            .map_err(|_| format!("Expected a number, got `{}`", arg))?;
+                    }
+                    ctx.render_settings.clear_color = Some(color.into());
+                    Ok(String::new())
+                }
+                ["clear_color", ..] => Err("Usage: set clear_color r g b [a]".into()),
+                [name, ..] => Err(format!("Unknown value `{}`", name)),
+                [] => Err("Usage: set <name> <value>".into()),
+            },
+        );
+        console.register(
+            "reload",
+            "Reload the handler's shaders: reload shaders",
+            |args, ctx| match args {
+                ["shaders"] => {
+                    ctx.request_shader_reload();
+                    Ok("Reloading shaders".into())
+                }
+                _ => Err("Usage: reload shaders".into()),
+            },
+        );
+        console.register(
+            "depth",
+            "Show the depth buffer: depth [off|linear|derivative], depth range [start end], or \
+             store it another way: depth mode [standard|reverse-z]",
+            |args, ctx| {
+                let settings = &mut ctx.render_settings;
+                let view = &mut settings.depth_view;
+                match args {
+                    [] => {
+                        let (start, end) = view.range();
+                        Ok(format!(
+                            "Depth view: {}, from {} to {}",
+                            view.mode, start, end
+                        ))
+                    }
+                    ["range"] => {
+                        view.range = None;
+                        Ok(format!("Depth range: {} to {}", view.near, view.far))
+                    }
+                    ["range", start, end] => {
+                        let parse = |arg: &str| {
+                            arg.parse::<f32>()
+                                .map_err(|_| format!("Expected a number, got `{}`", arg))
+                        };
+                        let (start, end) = (parse(*start)?, parse(*end)?);
+                        if end <= start {
+                            return Err("The end of the range has to be after its start".into());
+                        }
+                        view.range = Some((start, end));
+                        Ok(format!("Depth range: {} to {}", start, end))
+                    }
+                    ["mode"] => Ok(format!("Depth mode: {}", settings.depth_mode)),
+                    ["mode", name] => {
+                        settings.depth_mode = DepthMode::parse(name)
+                            .ok_or_else(|| format!("Unknown depth mode `{}`", name))?;
+                        Ok(format!("Depth mode: {}", settings.depth_mode))
+                    }
+                    [mode] => {
+                        view.mode = match *mode {
+                            "off" => DepthViewMode::Off,
+                            "linear" => DepthViewMode::Linear,
+                            "derivative" => DepthViewMode::Derivative,
+                            _ => return Err(format!("Unknown depth view `{}`", mode)),
+                        };
+                        Ok(format!("Depth view: {}", view.mode))
+                    }
+                    _ => Err(
+                        "Usage: depth [off|linear|derivative], depth range [start end], or \
+                              depth mode [standard|reverse-z]"
+                            .into(),
+                    ),
+                }
+            },
+        );
+        console.register(
+            "stereo",
+            "Draw the scene for both eyes side by side: stereo [off|on], or stereo <separation> \
+             <convergence>",
+            |args, ctx| {
+                let stereo = &mut ctx.render_settings.stereo;
+                match args {
+                    [] => {}
+                    ["off"] => *stereo = StereoMode::Off,
+                    ["on"] => {
+                        if *stereo == StereoMode::Off {
+                            *stereo = StereoMode::side_by_side();
+                        }
+                    }
+                    [separation, convergence] => {
+                        let parse = |arg: &str| {
+                            arg.parse::<f32>()
+                                .ok()
+                                .filter(|value| value.is_finite() && *value >= 0.)
+                                .ok_or_else(|| {
+                                    format!("Expected a number of at least 0, got `{}`", arg)
+                                })
+                        };
+                        *stereo = StereoMode::SideBySide {
+                            eye_separation: parse(*separation)?,
+                            convergence: parse(*convergence)?,
+                        };
+                    }
+                    _ => {
+                        return Err(
+                            "Usage: stereo [off|on], or stereo <separation> <convergence>".into(),
+                        )
+                    }
+                }
+                Ok(format!("Stereo: {}", stereo))
+            },
+        );
+        console.register(
+            "wireframe",
+            "Draw the edges of the scene's triangles: wireframe [off|on|dimmed], wireframe width \
+             <pixels>, or wireframe hidden <alpha>",
+            |args, ctx| {
+                let wireframe = &mut ctx.render_settings.wireframe;
+                let parse = |arg: &str| {
+                    arg.parse::<f32>()
+                        .ok()
+                        .filter(|value| value.is_finite() && *value >= 0.)
+                        .ok_or_else(|| format!("Expected a number of at least 0, got `{}`", arg))
+                };
+                match args {
+                    [] => {}
+                    ["off"] => *wireframe = None,
+                    ["on"] => {
+                        wireframe.get_or_insert_with(WireframeParams::default);
+                    }
+                    ["dimmed"] => *wireframe = Some(WireframeParams::dimmed()),
+                    ["width", width] => {
+                        let width = parse(*width)?;
+                        wireframe
+                            .get_or_insert_with(WireframeParams::default)
+                            .line_width = width;
+                    }
+                    ["hidden", alpha] => {
+                        let alpha = parse(*alpha)?.min(1.);
+                        wireframe
+                            .get_or_insert_with(WireframeParams::default)
+                            .hidden_alpha = alpha;
+                    }
+                    _ => {
+                        return Err(
+                            "Usage: wireframe [off|on|dimmed], wireframe width <pixels>, \
+                             or wireframe hidden <alpha>"
+                                .into(),
+                        )
+                    }
+                }
+                Ok(match wireframe {
+                    Some(params) => format!(
+                        "Wireframe: {} pixel lines, hidden lines at {}",
+                        params.line_width, params.hidden_alpha
+                    ),
+                    None => "Wireframe: off".into(),
+                })
+            },
+        );
+        console.register(
+            "latency",
+            "Trade throughput for input latency: latency [throughput|low_latency]",
+            |args, ctx| {
+                let mode = &mut ctx.render_settings.latency_mode;
+                match args {
+                    [] => {}
+                    [name] => {
+                        *mode = LatencyMode::parse(name).ok_or_else(|| {
+                            format!("Expected throughput or low_latency, got `{}`", name)
+                        })?;
+                    }
+                    _ => return Err("Usage: latency [throughput|low_latency]".into()),
+                }
+                Ok(format!(
+                    "Latency mode: {}, see the frame graph ( F2 ) for the latency",
+                    mode
+                ))
+            },
+        );
+        console.register(
+            "screenshot",
+            "Save the next frame to a PNG file: screenshot [file]",
+            |args, ctx| {
+                let path = match args {
+                    [] => {
+                        let seconds = SystemTime::now()
+                            .duration_since(UNIX_EPOCH)
+                            .map_or(0, |time| time.as_secs());
+                        PathBuf::from(format!("screenshot-{}.png", seconds))
+                    }
+                    [path] => PathBuf::from(path),
+                    _ => return Err("Usage: screenshot [file]".into()),
+                };
+                ctx.request_screenshot(path);
+                Ok(String::new())
+            },
+        );
+        console.register(
+            "copy",
+            "Copy the next frame to the clipboard, with the console if asked to: copy [overlays]",
+            |args, ctx| {
+                match args {
+                    [] => ctx.request_clipboard_copy(false),
+                    ["overlays"] => ctx.request_clipboard_copy(true),
+                    _ => return Err("Usage: copy [overlays]".into()),
+                }
+                Ok(String::new())
+            },
+        );
+        console.register(
+            "ab",
+            "Compare the next frame with a setting's command run with a and then b, saving both \
+             and a heatmap of their differences: ab <command...> <a> <b>",
+            |args, ctx| match args {
+                [command @ .., a, b] if !command.is_empty() && command[0] != "ab" => {
+                    let command = command.join(" ");
+                    let seconds = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |time| time.as_secs());
+                    let prefix = format!("ab-{}-{}", command.replace(' ', "-"), seconds);
+                    let (before, after) =
+                        (format!("{} {}", command, a), format!("{} {}", command, b));
+                    let message = format!("Comparing `{}` with `{}`", before, after);
+                    ctx.request_ab_capture(before, after, prefix);
+                    Ok(message)
+                }
+                _ => Err("Usage: ab <command...> <a> <b>, like `ab depth off linear`".into()),
+            },
+        );
+        console.register(
+            "report",
+            "Write the live GL objects and the pipeline state of the next frame to a text file",
+            |_, ctx| {
+                ctx.request_frame_report();
+                Ok(String::new())
+            },
+        );
+        console.register(
+            "theme",
+            "List the themes, or switch to one: theme [name]",
+            |args, ctx| match args {
+                [] => Ok(ctx
+                    .config()
+                    .themes
+                    .iter()
+                    .map(|theme| {
+                        let active = if theme.name == ctx.theme().name {
+                            " (active)"
+                        } else {
+                            ""
+                        };
+                        format!("{}{}", theme.name, active)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n")),
+                [name] => {
+                    ctx.set_theme(name)?;
+                    Ok(format!("Theme: {}", name))
+                }
+                _ => Err("Usage: theme [name]".into()),
+            },
+        );
+        console.register(
+            "textures",
+            "List the textures with their purposes and sRGB or linear formats",
+            |_, _| {
+                if !texture_audit::is_enabled() {
+                    return Err("The texture audit is off, set `texture_audit = true`".into());
+                }
+                match texture_audit::report() {
+                    report if report.is_empty() => Ok("No textures".into()),
+                    report => Ok(report),
+                }
+            },
+        );
+        console.register(
+            "view",
+            "Show a texture in a panel over the frame, and click on it to read a texel: view \
+             [list|off|<name or label>], view channel [rgba|rgb|r|g|b|a], view range <min> <max>, \
+             view flip, view slice <n>, or view pick <x> <y>",
+            |args, ctx| {
+                let view = &mut ctx.render_settings.texture_view;
+                let parse = |arg: &str| {
+                    arg.parse::<u32>()
+                        .map_err(|_| format!("Expected a whole number, got `{}`", arg))
+                };
+                match args {
+                    [] => {}
+                    ["list"] => {
+                        let textures = texture_viewer::viewable_textures();
+                        if textures.is_empty() {
+                            return Ok("No textures".into());
+                        }
+                        let mut lines = textures
+                            .iter()
+                            .map(|texture| {
+                                format!(
+                                    "{:>5}  {} ( {} )",
+                                    texture.id,
+                                    texture.label,
+                                    texture.target_name()
+                                )
+                            })
+                            .collect::<Vec<_>>();
+                        lines.push("Renderbuffers can't be shown, only textures".into());
+                        return Ok(lines.join("\n"));
+                    }
+                    ["off"] => view.texture = None,
+                    ["channel", name] => {
+                        view.channel = ViewChannel::parse(name)
+                            .ok_or_else(|| format!("Unknown channel `{}`", name))?;
+                    }
+                    ["range"] => view.range = (0., 1.),
+                    ["range", min, max] => {
+                        let parse = |arg: &str| {
+                            arg.parse::<f32>()
+                                .ok()
+                                .filter(|value| value.is_finite())
+                                .ok_or_else(|| format!("Expected a number, got `{}`", arg))
+                        };
+                        let (min, max) = (parse(*min)?, parse(*max)?);
+                        if max <= min {
+                            return Err("The end of the range has to be after its start".into());
+                        }
+                        view.range = (min, max);
+                    }
+                    ["flip"] => view.flip_y = !view.flip_y,
+                    ["slice", slice] => {
+                        let slice = parse(*slice)?;
+                        let count = view.find().and_then(|texture| texture.slice_count());
+                        if count.is_some_and(|count| slice >= count) {
+                            return Err(format!("The texture only has {} slices", count.unwrap()));
+                        }
+                        view.slice = slice;
+                    }
+                    ["pick", x, y] => {
+                        if view.find().is_none() {
+                            return Err("No texture is shown".into());
+                        }
+                        view.pick = Some(PickAt::Texel(parse(*x)?, parse(*y)?));
+                        return Ok(String::new());
+                    }
+                    name => {
+                        let name = name.join(" ");
+                        let texture = texture_viewer::find_texture(&name).ok_or_else(|| {
+                            format!("No texture matches `{}`, see `view list`", name)
+                        })?;
+                        if !texture.is_supported() {
+                            return Err(format!(
+                                "{} is a {} texture, which can't be shown",
+                                texture.label,
+                                texture.target_name()
+                            ));
+                        }
+                        view.texture = Some(name);
+                        view.slice = 0;
+                        view.pick = None;
+                    }
+                }
+                Ok(view.status())
+            },
+        );
+        console.register(
+            "state",
+            "List what the example saved the last time it closed, or forget it so that the next \
+             run starts fresh: state [clear]",
+            |args, ctx| {
+                let state = &mut ctx.saved_state;
+                let name = match state.name() {
+                    Some(name) => name.to_string(),
+                    None => return Err("This window's state isn't saved".into()),
+                };
+                match args {
+                    [] if state.is_cleared() => Ok(format!("The state of {} was cleared", name)),
+                    [] => {
+                        let keys = state.keys().collect::<Vec<_>>();
+                        if keys.is_empty() {
+                            return Ok(format!("Nothing was saved for {}", name));
+                        }
+                        Ok(format!(
+                            "Saved for {} in {}: {}",
+                            name,
+                            DemoStates::path().display(),
+                            keys.join(", ")
+                        ))
+                    }
+                    ["clear"] => {
+                        state.clear();
+                        Ok(format!(
+                            "Forgot the saved state of {}, which won't be saved when it closes",
+                            name
+                        ))
+                    }
+                    _ => Err("Usage: state [clear]".into()),
+                }
+            },
+        );
+        console.register("quit", "Close the window", |_, ctx| {
+            ctx.request_close();
+            Ok(String::new())
+        });
+
+        console
+    }
+
+    /// Add a command, replacing any command with the same name
+    pub fn register<F>(&mut self, name: &str, help: &str, run: F)
+    where
+        F: FnMut(&[&str], &mut AppContext) -> Result<String, String> + 'static,
+    {
+        self.commands.insert(
+            name.to_owned(),
+            Command {
+                help: help.to_owned(),
+                run: Box::new(run),
+            },
+        );
+    }
+
+    /// Remove a command, returning whether there was one
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.commands.remove(name).is_some()
+    }
+
+    /// The names of the commands, in order
+    pub fn command_names(&self) -> impl Iterator<Item = &str> {
+        self.commands.keys().map(String::as_str)
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.open
+    }
+
+    pub fn set_open(&mut self, open: bool) {
+        self.open = open;
+    }
+
+    /// Add lines to the output, dropping the oldest ones once there are too many
+    pub fn print(&mut self, text: &str) {
+        self.push_output(text, false);
+    }
+
+    /// Add lines to the output in the error color
+    pub fn print_error(&mut self, text: &str) {
+        self.push_output(text, true);
+    }
+
+    fn push_output(&mut self, text: &str, error: bool) {
+        for line in text.lines() {
+            if self.output.len() == MAX_OUTPUT_LINES {
+                self.output.pop_front();
+            }
+            self.output.push_back(OutputLine {
+                text: line.to_owned(),
+                error,
+            });
+        }
+    }
+
+    /// Add a typed character to the input line
+    pub(crate) fn type_char(&mut self, c: char) {
+        // The backtick toggles the console, so it is never typed
+        if !c.is_control() && c != '`' {
+            self.input.push(c);
+        }
+    }
+
+    /// Handle a key press while the console is open, returning the input line if Enter was
+    /// pressed
+    pub(crate) fn handle_key(&mut self, key: VirtualKeyCode) -> Option<String> {
+        match key {
+            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                let line = std::mem::take(&mut self.input);
+                self.history_index = None;
+                if !line.trim().is_empty() && self.history.last() != Some(&line) {
+                    if self.history.len() == MAX_HISTORY {
+                        self.history.remove(0);
+                    }
+                    self.history.push(line.clone());
+                }
+                return Some(line);
+            }
+            VirtualKeyCode::Back => {
+                self.input.pop();
+            }
+            VirtualKeyCode::Escape => self.open = false,
+            VirtualKeyCode::Tab => self.complete(),
+            VirtualKeyCode::Up => {
+                let index = match self.history_index {
+                    Some(index) => index.saturating_sub(1),
+                    None if self.history.is_empty() => return None,
+                    None => {
+                        self.draft = self.input.clone();
+                        self.history.len() - 1
+                    }
+                };
+                self.history_index = Some(index);
+                self.input = self.history[index].clone();
+            }
+            VirtualKeyCode::Down => {
+                if let Some(index) = self.history_index {
+                    if index + 1 < self.history.len() {
+                        self.history_index = Some(index + 1);
+                        self.input = self.history[index + 1].clone();
+                    } else {
+                        self.history_index = None;
+                        self.input = std::mem::take(&mut self.draft);
+                    }
+                }
+            }
+            _ => (),
+        }
+        None
+    }
+
+    /// Complete the command name being typed as far as the matching names agree, listing them if
+    /// there is more than one
+    fn complete(&mut self) {
+        let typed = self.input.trim_start();
+        if typed.contains(' ') {
+            return;
+        }
+        let matches = self
+            .commands
+            .keys()
+            .filter(|name| name.starts_with(typed))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        match matches.as_slice() {
+            [] => (),
+            [name] => self.input = format!("{} ", name),
+            [first, rest @ ..] => {
+                // Extend the input to the longest prefix that every match shares
+                let mut prefix = first.as_str();
+                for name in rest {
+                    let common = prefix
+                        .char_indices()
+                        .zip(name.chars())
+                        .find(|((_, a), b)| a != b)
+                        .map_or(prefix.len().min(name.len()), |((i, _), _)| i);
+                    prefix = &prefix[..common];
+                }
+                self.input = prefix.to_owned();
+                self.print(&matches.join("  "));
+            }
+        }
+    }
+
+    /// The part of a window of the given size that the console covers while it is open, with the
+    /// line under it, in window pixels from the bottom-left
+    pub fn rect(&self, window_size: (u32, u32), ui_scale: f32) -> Option<Rect> {
+        if !self.open {
+            return None;
+        }
+        let scale = ui_scale.round().max(1.) as i32;
+        let height = Self::height(window_size, ui_scale) as i32 + scale;
+        Some(Rect::new(
+            0,
+            window_size.1 as i32 - height,
+            window_size.0 as i32,
+            height,
+        ))
+    }
+
+    /// The height of the console's background in a window of the given size
+    fn height(window_size: (u32, u32), ui_scale: f32) -> f32 {
+        let scale = ui_scale.round().max(1.) as u32;
+        let padding = PADDING * scale as f32;
+        let line_height = (LINE_HEIGHT * scale) as f32;
+        (window_size.1 as f32 * HEIGHT_FRACTION).max(line_height + padding * 2.)
+    }
+
+    /// Queue the console's background, output, and input line for drawing over a window of the
+    /// given size, in the colors of a theme
+    pub fn queue_draw(
+        &self,
+        text: &mut DebugText,
+        window_size: (u32, u32),
+        ui_scale: f32,
+        theme: &Theme,
+    ) {
+        let scale = ui_scale.round().max(1.) as u32;
+        let padding = PADDING * scale as f32;
+        let line_height = (LINE_HEIGHT * scale) as f32;
+        let width = window_size.0 as f32;
+        let height = Self::height(window_size, ui_scale);
+        text.rect(0., 0., width, height, theme.panel_color);
+        text.rect(0., height, width, scale as f32, Color::GRAY);
+
+        // The input line goes at the bottom, with the newest output right above it
+        let input_y = height - padding - line_height;
+        text.text(
+            padding,
+            input_y,
+            scale,
+            theme.text_color,
+            &format!("> {}_", self.input),
+        );
+        let mut y = input_y - line_height;
+        for line in self.output.iter().rev() {
+            if y < 0. {
+                break;
+            }
+            let color = if line.error {
+                ERROR_COLOR
+            } else {
+                theme.text_color.with_alpha(0.85)
+            };
+            text.text(padding, y, scale, color, &line.text);
+            y -= line_height;
+        }
+    }
+}
+
+/// Run a line of console input against the window's console, printing the line and what the
+/// command returned to the console
+pub fn run_command(ctx: &mut AppContext, line: &str) {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let (name, args) = match words.split_first() {
+        Some((name, args)) => (*name, args),
+        None => return,
+    };
+    ctx.console.print(&format!("> {}", line.trim()));
+
+    // Take the command out of the console while it runs, so that it can use the console through
+    // the context
+    let mut command = match ctx.console.commands.remove(name) {
+        Some(command) => command,
+        None => {
+            ctx.console
+                .print_error(&format!("Unknown command `{}`, try `help`", name));
+            return;
+        }
+    };
+    let result = (command.run)(args, ctx);
+    ctx.console
+        .commands
+        .entry(name.to_owned())
+        .or_insert(command);
+
+    match result {
+        Ok(output) => ctx.console.print(&output),
+        Err(error) => ctx.console.print_error(&error),
+    }
+}
@@ -0,0 +1,252 @@
+use glow::HasContext;
+use surfman::{Context, ContextAttributeFlags, Device};
+
+use crate::features::Loader;
+
+/// The signature of `glGetFramebufferAttachmentParameteriv`, which glow doesn't expose
+type GetFramebufferAttachmentParameter =
+    extern "system" fn(target: u32, attachment: u32, pname: u32, params: *mut i32);
+
+/// What the driver actually gave us when we created a GL context
+///
+/// The context attributes we ask surfman for are only a request, so this is the place to check
+/// whether we really got, e.g., a stencil buffer before relying on it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ContextReport {
+    /// The GL vendor string
+    pub vendor: String,
+    /// The GL renderer string, usually the name of the graphics card
+    pub renderer: String,
+    /// The full GL version string
+    pub version: String,
+    /// The GL version of the context as `( major, minor )`
+    pub gl_version: (u8, u8),
+    /// Whether or not surfman created a compatibility profile context
+    pub compatibility_profile: bool,
+    /// The number of bits in each of the red, green, blue, and alpha channels of the window
+    /// surface
+    pub color_bits: [i32; 4],
+    /// The number of bits in the depth buffer of the window surface, or 0 if it has none
+    pub depth_bits: i32,
+    /// The number of bits in the stencil buffer of the window surface, or 0 if it has none
+    pub stencil_bits: i32,
+    /// The number of multisampling samples of the window surface, or 0 if it isn't multisampled
+    pub samples: i32,
+    /// Whether or not the window surface stores colors as sRGB
+    pub srgb_capable: bool,
+}
+
+/// The error returned when the context doesn't meet one of a handler's requirements
+#[derive(Clone, Debug)]
+pub struct RequirementError {
+    /// A description of the requirement that wasn't met
+    pub requirement: String,
+    /// The report of the context that didn't meet it
+    pub report: Box<ContextReport>,
+}
+
+impl std::fmt::Display for RequirementError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "GL context does not meet requirement `{}`\n{}",
+            self.requirement, self.report
+        )
+    }
+}
+
+impl std::error::Error for RequirementError {}
+
+impl ContextReport {
+    /// Query the attributes of the given context, which must be current
+    pub(crate) fn query(gl: &glow::Context, device: &Device, context: &Context) -> Self {
+        // Find out which version and profile surfman actually created
+        let attributes = device.context_descriptor_attributes(&device.context_descriptor(context));
+
+        // Figure out which framebuffer belongs to the window surface. Surfman reports 0 when that
+        // is the default framebuffer.
+        let surface_fbo = device
+            .context_surface_info(context)
+            .ok()
+            .flatten()
+            .map(|info| info.framebuffer_object)
+            .unwrap_or(0);
+        Self::query_framebuffer(
+            gl,
+            &|symbol| device.get_proc_address(context, symbol),
+            surface_fbo,
+            (attributes.version.major, attributes.version.minor),
+            attributes
+                .flags
+                .contains(ContextAttributeFlags::COMPATIBILITY_PROFILE),
+        )
+    }
+
+    /// Query the attributes of the current context and a framebuffer that something other than
+    /// surfman created, like a host application's, asking GL for the version and profile
+    pub(crate) fn query_with_loader(gl: &glow::Context, loader: Loader, framebuffer: u32) -> Self {
+        let (gl_version, compatibility_profile) = unsafe {
+            (
+                (
+                    gl.get_parameter_i32(glow::MAJOR_VERSION) as u8,
+                    gl.get_parameter_i32(glow::MINOR_VERSION) as u8,
+                ),
+                gl.get_parameter_i32(glow::CONTEXT_PROFILE_MASK) as u32
+                    & glow::CONTEXT_COMPATIBILITY_PROFILE_BIT
+                    != 0,
+            )
+        };
+        Self::query_framebuffer(gl, loader, framebuffer, gl_version, compatibility_profile)
+    }
+
+    /// Query the attributes of the framebuffer that is drawn to, which is left bound. Its
+    /// attachments have different names when it is the default framebuffer.
+    fn query_framebuffer(
+        gl: &glow::Context,
+        loader: Loader,
+        surface_fbo: u32,
+        gl_version: (u8, u8),
+        compatibility_profile: bool,
+    ) -> Self {
+        let (color_attachment, depth_attachment, stencil_attachment) = if surface_fbo == 0 {
+            (glow::BACK_LEFT, glow::DEPTH, glow::STENCIL)
+        } else {
+            (
+                glow::COLOR_ATTACHMENT0,
+                glow::DEPTH_ATTACHMENT,
+                glow::STENCIL_ATTACHMENT,
+            )
+        };
+
+        // The attachment parameters can't be queried through glow, so load the function ourselves
+        let ptr = loader("glGetFramebufferAttachmentParameteriv");
+        let get_attachment_parameter = if ptr.is_null() {
+            None
+        } else {
+            Some(unsafe {
+                std::mem::transmute::<*const std::os::raw::c_void, GetFramebufferAttachmentParameter>(
+                    ptr,
+                )
+            })
+        };
+        // Get a parameter of a surface attachment, or 0 if the attachment doesn't exist
+        let attachment_parameter = |attachment, pname| {
+            let get = match get_attachment_parameter {
+                Some(get) => get,
+                None => return 0,
+            };
+            let mut object_type = 0;
+            get(
+                glow::DRAW_FRAMEBUFFER,
+                attachment,
+                glow::FRAMEBUFFER_ATTACHMENT_OBJECT_TYPE,
+                &mut object_type,
+            );
+            if object_type as u32 == glow::NONE {
+                return 0;
+            }
+            let mut value = 0;
+            get(glow::DRAW_FRAMEBUFFER, attachment, pname, &mut value);
+            value
+        };
+
+        unsafe {
+            gl.bind_framebuffer(
+                glow::DRAW_FRAMEBUFFER,
+                if surface_fbo == 0 {
+                    None
+                } else {
+                    Some(surface_fbo)
+                },
+            );
+
+            Self {
+                vendor: gl.get_parameter_string(glow::VENDOR),
+                renderer: gl.get_parameter_string(glow::RENDERER),
+                version: gl.get_parameter_string(glow::VERSION),
+                gl_version,
+                compatibility_profile,
+                color_bits: [
+                    attachment_parameter(color_attachment, glow::FRAMEBUFFER_ATTACHMENT_RED_SIZE),
+                    attachment_parameter(color_attachment, glow::FRAMEBUFFER_ATTACHMENT_GREEN_SIZE),
+                    attachment_parameter(color_attachment, glow::FRAMEBUFFER_ATTACHMENT_BLUE_SIZE),
+                    attachment_parameter(color_attachment, glow::FRAMEBUFFER_ATTACHMENT_ALPHA_SIZE),
+                ],
+                depth_bits: attachment_parameter(
+                    depth_attachment,
+                    glow::FRAMEBUFFER_ATTACHMENT_DEPTH_SIZE,
+                ),
+                stencil_bits: attachment_parameter(
+                    stencil_attachment,
+                    glow::FRAMEBUFFER_ATTACHMENT_STENCIL_SIZE,
+                ),
+                samples: gl.get_parameter_i32(glow::SAMPLES),
+                srgb_capable: attachment_parameter(
+                    color_attachment,
+                    glow::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING,
+                ) as u32
+                    == glow::SRGB,
+            }
+        }
+    }
+
+    /// Return an error describing the requirement if `met` is false
+    ///
+    /// For example: `report.require(report.samples >= 4, "at least 4 samples")?`
+    pub fn require(&self, met: bool, requirement: &str) -> Result<(), RequirementError> {
+        if met {
+            Ok(())
+        } else {
+            Err(RequirementError {
+                requirement: requirement.into(),
+                report: Box::new(self.clone()),
+            })
+        }
+    }
+
+    /// Require the window surface to have at least the given number of depth bits
+    pub fn require_depth_bits(&self, bits: i32) -> Result<(), RequirementError> {
+        self.require(self.depth_bits >= bits, &format!("depth_bits >= {}", bits))
+    }
+
+    /// Require the window surface to have at least the given number of stencil bits
+    pub fn require_stencil_bits(&self, bits: i32) -> Result<(), RequirementError> {
+        self.require(
+            self.stencil_bits >= bits,
+            &format!("stencil_bits >= {}", bits),
+        )
+    }
+}
+
+impl std::fmt::Display for ContextReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "GL context:")?;
+        writeln!(f, "  Vendor:   {}", self.vendor)?;
+        writeln!(f, "  Renderer: {}", self.renderer)?;
+        writeln!(
+            f,
+            "  Version:  {} ( {}.{} {} profile )",
+            self.version,
+            self.gl_version.0,
+            self.gl_version.1,
+            if self.compatibility_profile {
+                "compatibility"
+            } else {
+                "core"
+            }
+        )?;
+        let [r, g, b, a] = self.color_bits;
+        writeln!(
+            f,
+            "  Color:    R{} G{} B{} A{}{}",
+            r,
+            g,
+            b,
+            a,
+            if self.srgb_capable { " sRGB" } else { "" }
+        )?;
+        writeln!(f, "  Depth:    {} bits", self.depth_bits)?;
+        writeln!(f, "  Stencil:  {} bits", self.stencil_bits)?;
+        write!(f, "  Samples:  {}", self.samples)
+    }
+}
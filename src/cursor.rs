@@ -0,0 +1,285 @@
+use std::{path::Path, rc::Rc};
+
+use glow::HasContext;
+use winit::Window;
+
+use crate::{
+    debug_group::DebugGroup,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+};
+
+/// The shapes of the system cursor, like `CursorIcon::Crosshair` and `CursorIcon::Hand`
+pub use winit::MouseCursor as CursorIcon;
+
+const VERTEX_SHADER_SRC: &str = include_str!("cursor/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("cursor/fragment.glsl");
+
+/// A cursor made from an image
+///
+/// The window system can't be given cursor images in this version of winit, so the loop hides the
+/// system cursor and draws the image over the frame where the cursor is. It is drawn at the UI
+/// scale, and it can lag behind the mouse by a frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CustomCursor {
+    pub width: u32,
+    pub height: u32,
+    /// Tightly packed straight alpha RGBA pixels, starting at the top left
+    pub pixels: Vec<u8>,
+    /// The pixel of the image that points at the cursor position, from the top left
+    pub hotspot: (u32, u32),
+}
+
+impl CustomCursor {
+    /// Make a cursor from straight alpha RGBA pixels, starting at the top left
+    pub fn from_rgba(width: u32, height: u32, pixels: Vec<u8>, hotspot: (u32, u32)) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width as usize * height as usize * 4,
+            "The cursor pixels don't match its size"
+        );
+        Self {
+            width,
+            height,
+            pixels,
+            hotspot: (
+                hotspot.0.min(width.saturating_sub(1)),
+                hotspot.1.min(height.saturating_sub(1)),
+            ),
+        }
+    }
+
+    /// Load and decode a cursor image, returning an error if it is missing or can't be decoded
+    pub fn open<P: AsRef<Path>>(path: P, hotspot: (u32, u32)) -> image::ImageResult<Self> {
+        let image = image::open(path)?.into_rgba();
+        let (width, height) = image.dimensions();
+        Ok(Self::from_rgba(width, height, image.into_raw(), hotspot))
+    }
+}
+
+/// What the mouse cursor looks like over a window
+#[derive(Clone, Debug, PartialEq)]
+pub enum Cursor {
+    /// One of the system cursors
+    Icon(CursorIcon),
+    /// An image, which is shared so that setting it every frame is cheap
+    Custom(Rc<CustomCursor>),
+    Hidden,
+}
+
+impl Default for Cursor {
+    fn default() -> Self {
+        Cursor::Icon(CursorIcon::Default)
+    }
+}
+
+impl From<CursorIcon> for Cursor {
+    fn from(icon: CursorIcon) -> Self {
+        Cursor::Icon(icon)
+    }
+}
+
+impl From<Rc<CustomCursor>> for Cursor {
+    fn from(cursor: Rc<CustomCursor>) -> Self {
+        Cursor::Custom(cursor)
+    }
+}
+
+impl From<CustomCursor> for Cursor {
+    fn from(cursor: CustomCursor) -> Self {
+        Cursor::Custom(Rc::new(cursor))
+    }
+}
+
+/// Keeps a window's cursor the way the handler asked for, and draws custom cursors
+///
+/// The window system is only called when the cursor or grab changes, since handlers set them
+/// every frame.
+#[derive(Debug, Default)]
+pub(crate) struct CursorState {
+    /// The cursor and grab that were last given to the window system
+    applied: Option<(Cursor, bool)>,
+    /// The texture of the custom cursor being drawn, with the cursor it was made from
+    texture: Option<(Rc<CustomCursor>, u32)>,
+    /// The program and empty vertex array for drawing custom cursors, made the first time one is
+    /// drawn
+    pass: Option<(ShaderProgram, u32)>,
+}
+
+impl CursorState {
+    /// Give the cursor and grab to the window system if they changed
+    pub fn apply(&mut self, window: &Window, cursor: &Cursor, grabbed: bool) {
+        if let Some((applied_cursor, applied_grab)) = &self.applied {
+            if applied_cursor == cursor && *applied_grab == grabbed {
+                return;
+            }
+        }
+
+        if let Err(error) = window.grab_cursor(grabbed) {
+            eprintln!("Warning: Couldn't grab the cursor: {}", error);
+        }
+        // Custom cursors are drawn in place of the system one
+        let hidden = grabbed || !matches!(cursor, Cursor::Icon(_));
+        window.hide_cursor(hidden);
+        if let Cursor::Icon(icon) = cursor {
+            window.set_cursor(*icon);
+        }
+        self.applied = Some((cursor.clone(), grabbed));
+    }
+
+    /// Draw the custom cursor into the window framebuffer at a position in physical pixels from
+    /// the top-left of the window, if the handler asked for one and the cursor isn't grabbed
+    pub fn draw(
+        &mut self,
+        gl: &mut glow::Context,
+        window_framebuffer: Option<u32>,
+        window_size: (u32, u32),
+        position: Option<(f64, f64)>,
+        scale: f32,
+    ) {
+        let cursor = match &self.applied {
+            Some((Cursor::Custom(cursor), false)) => cursor.clone(),
+            _ => return,
+        };
+        let (x, y) = match position {
+            Some(position) => position,
+            None => return,
+        };
+        let _group = DebugGroup::push(gl, "Custom cursor");
+
+        // Upload the image when the cursor changes
+        if self
+            .texture
+            .as_ref()
+            .is_none_or(|(uploaded, _)| !Rc::ptr_eq(uploaded, &cursor))
+        {
+            self.delete_texture(gl);
+            let texture = unsafe { create_texture(gl, &cursor) };
+            self.texture = Some((cursor.clone(), texture));
+        }
+        let texture = self.texture.as_ref().unwrap().1;
+
+        // Whole pixels keep the image sharp
+        let scale = scale.round().max(1.);
+        let left = (x as f32 - cursor.hotspot.0 as f32 * scale).round();
+        let top = (y as f32 - cursor.hotspot.1 as f32 * scale).round();
+        let (program, vao) = self.pass.get_or_insert_with(|| {
+            let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+            let vao = unsafe { gl.create_vertex_array().unwrap() };
+            resources::track(ResourceKind::VertexArray, vao, "Custom cursor vertex array");
+            (program, vao)
+        });
+        unsafe {
+            // The handler may have drawn to one of its own framebuffers or a part of the window
+            gl.bind_framebuffer(glow::FRAMEBUFFER, window_framebuffer);
+            gl.viewport(0, 0, window_size.0 as i32, window_size.1 as i32);
+
+            // Keep the handler's bindings, since the cursor is drawn every frame
+            let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+            let cull_face = gl.is_enabled(glow::CULL_FACE);
+            let blend = gl.is_enabled(glow::BLEND);
+            let current_program = gl.get_parameter_i32(glow::CURRENT_PROGRAM) as u32;
+            let active_texture = gl.get_parameter_i32(glow::ACTIVE_TEXTURE) as u32;
+            gl.active_texture(glow::TEXTURE0);
+            let bound_texture = gl.get_parameter_i32(glow::TEXTURE_BINDING_2D) as u32;
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::CULL_FACE);
+            gl.enable(glow::BLEND);
+            gl.blend_func_separate(
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            );
+
+            program.bind(gl);
+            program.set_uniform(
+                gl,
+                "rect",
+                [
+                    left,
+                    top,
+                    left + cursor.width as f32 * scale,
+                    top + cursor.height as f32 * scale,
+                ],
+            );
+            program.set_uniform(
+                gl,
+                "screenSize",
+                [window_size.0 as f32, window_size.1 as f32],
+            );
+            program.set_uniform(gl, "image", 0);
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.bind_vertex_array(Some(*vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 6);
+            gl.bind_vertex_array(None);
+
+            // Put the state back the way the handler had it
+            gl.bind_texture(glow::TEXTURE_2D, Some(bound_texture).filter(|&id| id != 0));
+            gl.active_texture(active_texture);
+            gl.use_program(Some(current_program).filter(|&id| id != 0));
+            if depth_test {
+                gl.enable(glow::DEPTH_TEST);
+            }
+            if cull_face {
+                gl.enable(glow::CULL_FACE);
+            }
+            if !blend {
+                gl.disable(glow::BLEND);
+            }
+        }
+    }
+
+    fn delete_texture(&mut self, gl: &mut glow::Context) {
+        if let Some((_, texture)) = self.texture.take() {
+            unsafe { gl.delete_texture(texture) };
+            resources::untrack(ResourceKind::Texture, texture);
+        }
+    }
+
+    /// Delete the GL objects, which are made again the next time a custom cursor is drawn
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.delete_texture(gl);
+        if let Some((mut program, vao)) = self.pass.take() {
+            program.delete(gl);
+            unsafe { gl.delete_vertex_array(vao) };
+            resources::untrack(ResourceKind::VertexArray, vao);
+        }
+    }
+}
+
+/// Upload a cursor image to a texture with nearest filtering
+unsafe fn create_texture(gl: &mut glow::Context, cursor: &CustomCursor) -> u32 {
+    let texture = gl.create_texture().unwrap();
+    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+    gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+    gl.tex_image_2d(
+        glow::TEXTURE_2D,
+        0,
+        glow::RGBA8 as i32,
+        cursor.width as i32,
+        cursor.height as i32,
+        0,
+        glow::RGBA,
+        glow::UNSIGNED_BYTE,
+        Some(&cursor.pixels),
+    );
+    gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+    for (parameter, value) in [
+        (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+        (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+        (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+        (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+    ] {
+        gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+    }
+    gl.bind_texture(glow::TEXTURE_2D, None);
+    resources::track_sized(
+        ResourceKind::Texture,
+        texture,
+        "Custom cursor",
+        resources::texture_bytes(cursor.width, cursor.height, glow::RGBA8, 1, 1, 1),
+    );
+    texture
+}
@@ -0,0 +1,23 @@
+#version 330 core
+
+// The top-left and bottom-right of the cursor in pixels from the top-left of the window
+uniform vec4 rect;
+// The size of the window in pixels
+uniform vec2 screenSize;
+
+out vec2 uv;
+
+const vec2 CORNERS[6] = vec2[](
+    vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0),
+    vec2(0.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0)
+);
+
+void main()
+{
+    // A quad made from the vertex index, with the image rows going from the top down
+    vec2 corner = CORNERS[gl_VertexID];
+    uv = corner;
+    vec2 position = mix(rect.xy, rect.zw, corner);
+    vec2 ndc = position / screenSize * 2.0 - 1.0;
+    gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+}
@@ -0,0 +1,207 @@
+use crate::viewport::Rect;
+
+/// How many rects a `Damage` keeps apart before it merges the two closest ones
+pub const MAX_DAMAGE_RECTS: usize = 4;
+
+/// How many buffers the window surface is assumed to swap between
+///
+/// Surfman doesn't say how old the back buffer is, so the loop assumes double buffering, where
+/// the buffer drawn into last had the frame before the last one. Each frame redraws what changed
+/// in the last `SWAP_BUFFERS` frames so that both buffers catch up.
+const SWAP_BUFFERS: usize = 2;
+
+/// The parts of a frame that changed since the last one, in window pixels with the origin in the
+/// bottom-left corner like `Rect`s
+///
+/// Added rects are merged with the ones they overlap or touch whenever the rect covering both
+/// isn't bigger than the two of them, and once there are more than `MAX_DAMAGE_RECTS` the two
+/// that grow the least from being merged are, so the list stays short.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Damage {
+    rects: Vec<Rect>,
+    /// Whether the whole frame changed, which covers all of the rects
+    full: bool,
+}
+
+impl Damage {
+    /// No damage
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Damage over the whole frame
+    pub fn full() -> Self {
+        Self {
+            rects: Vec::new(),
+            full: true,
+        }
+    }
+
+    /// Add a rect that changed, merging it with the rects it overlaps
+    pub fn add(&mut self, rect: Rect) {
+        if self.full || rect.is_empty() {
+            return;
+        }
+        let mut rect = rect;
+        // A merged rect can reach rects that the smaller ones didn't, so keep going until nothing
+        // merges
+        while let Some(index) = self
+            .rects
+            .iter()
+            .position(|other| rect.union(other).area() <= rect.area() + other.area())
+        {
+            rect = rect.union(&self.rects.swap_remove(index));
+        }
+        self.rects.push(rect);
+
+        while self.rects.len() > MAX_DAMAGE_RECTS {
+            let mut best = (0, 1, i64::MAX);
+            for i in 0..self.rects.len() {
+                for j in i + 1..self.rects.len() {
+                    let (a, b) = (self.rects[i], self.rects[j]);
+                    let growth = a.union(&b).area() - a.area() - b.area();
+                    if growth < best.2 {
+                        best = (i, j, growth);
+                    }
+                }
+            }
+            let merged = self.rects.swap_remove(best.1);
+            self.rects[best.0] = self.rects[best.0].union(&merged);
+        }
+    }
+
+    /// Mark the whole frame as changed
+    pub fn add_full(&mut self) {
+        self.full = true;
+        self.rects.clear();
+    }
+
+    /// Add all of the damage of another frame
+    pub fn merge(&mut self, other: &Damage) {
+        if other.full {
+            self.add_full();
+        }
+        for &rect in &other.rects {
+            self.add(rect);
+        }
+    }
+
+    /// Whether the whole frame changed
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// Whether nothing changed
+    pub fn is_empty(&self) -> bool {
+        !self.full && self.rects.is_empty()
+    }
+
+    /// The rects that changed, which are empty when the whole frame did
+    pub fn rects(&self) -> &[Rect] {
+        &self.rects
+    }
+
+    /// The rect covering all of the damage in a frame of a size, or `None` if nothing changed
+    pub fn bounds(&self, frame_size: (u32, u32)) -> Option<Rect> {
+        let frame = Rect::from_window_size(frame_size);
+        if self.full {
+            return Some(frame);
+        }
+        self.rects
+            .iter()
+            .fold(Rect::new(0, 0, 0, 0), |bounds, rect| bounds.union(rect))
+            .intersect(&frame)
+    }
+
+    /// How many pixels changed in a frame of a size
+    ///
+    /// The rects never overlap by much, since overlapping ones are merged, so this counts the
+    /// pixels where they do overlap twice.
+    pub fn area(&self, frame_size: (u32, u32)) -> i64 {
+        let frame = Rect::from_window_size(frame_size);
+        if self.full {
+            return frame.area();
+        }
+        self.rects
+            .iter()
+            .filter_map(|rect| rect.intersect(&frame))
+            .map(|rect| rect.area())
+            .sum::<i64>()
+            .min(frame.area())
+    }
+
+    /// Forget all of the damage
+    pub fn clear(&mut self) {
+        self.rects.clear();
+        self.full = false;
+    }
+}
+
+/// Keeps the damage of a window's last few frames, to find what each frame has to redraw when
+/// the surface's buffers are swapped instead of copied
+#[derive(Debug)]
+pub(crate) struct DamageTracker {
+    /// The damage of the last frames, newest last
+    history: Vec<Damage>,
+    /// The size of the last frame, since every buffer has to be redrawn after a resize
+    size: Option<(u32, u32)>,
+    /// How much of the last frame was redrawn, from 0 to 1
+    redrawn: f32,
+}
+
+impl Default for DamageTracker {
+    fn default() -> Self {
+        Self {
+            history: Vec::new(),
+            size: None,
+            redrawn: 1.,
+        }
+    }
+}
+
+impl DamageTracker {
+    /// Record a frame's damage and return the rect to redraw, or `None` to redraw all of it
+    ///
+    /// The rect includes the damage of the frames before it that the back buffer missed, and is
+    /// empty when nothing has to be redrawn at all. A frame of a new size is redrawn whole, as
+    /// are the frames after it until every buffer has been.
+    pub fn frame_region(&mut self, damage: Damage, size: (u32, u32)) -> Option<Rect> {
+        let damage = if self.size != Some(size) {
+            self.size = Some(size);
+            self.history.clear();
+            Damage::full()
+        } else {
+            damage
+        };
+        self.history.push(damage);
+        if self.history.len() > SWAP_BUFFERS {
+            self.history.remove(0);
+        }
+        let mut region = Damage::new();
+        for damage in &self.history {
+            region.merge(damage);
+        }
+        let frame = Rect::from_window_size(size);
+        if region.is_full() {
+            self.redrawn = 1.;
+            return None;
+        }
+        // A frame is drawn once, so everything in the rect covering the damage is redrawn
+        let bounds = region.bounds(size).unwrap_or(Rect::new(0, 0, 0, 0));
+        self.redrawn = bounds.area() as f32 / frame.area() as f32;
+        Some(bounds)
+    }
+
+    /// Redraw the whole frame until every buffer has been, like after the contents of the
+    /// surface were lost
+    pub fn reset(&mut self) {
+        self.size = None;
+        self.history.clear();
+        self.redrawn = 1.;
+    }
+
+    /// How much of the last frame was redrawn, from 0 to 1
+    pub fn redrawn(&self) -> f32 {
+        self.redrawn
+    }
+}
@@ -0,0 +1,254 @@
+use std::f32::consts::{PI, TAU};
+
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+
+use crate::{
+    collision::{Capsule, Contact},
+    color::Color,
+    debug_group::DebugGroup,
+    frame_arena::FrameArena,
+    frustum::Aabb,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+    vertex::{pack_unorm8x4, VertexFormat, VertexLayout},
+    SliceAsBytes,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("debug_draw/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("debug_draw/fragment.glsl");
+
+/// The pairs of corners of an `Aabb::corners` that are joined by an edge
+const BOX_EDGES: [(usize, usize); 12] = [
+    (0, 1),
+    (2, 3),
+    (4, 5),
+    (6, 7),
+    (0, 2),
+    (1, 3),
+    (4, 6),
+    (5, 7),
+    (0, 4),
+    (1, 5),
+    (2, 6),
+    (3, 7),
+];
+
+/// How long the normal of a contact is drawn, in world units
+const CONTACT_NORMAL_LENGTH: f32 = 0.5;
+
+/// How many lines a whole circle is drawn with
+const CIRCLE_SEGMENTS: usize = 24;
+
+/// A vertex laid out like `DebugDraw::vertex_layout`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct LineVertex {
+    position: [f32; 3],
+    color: [u8; 4],
+}
+
+/// Draws lines in the world, like bounding boxes, contacts, and the paths of moving things, for
+/// seeing what the code is doing
+///
+/// Lines are queued in world space and drawn all at once with `draw`, using the depth test the way
+/// the handler has it set up, so turn it off first to see the lines through walls. Since lines are
+/// queued every frame, colors taken from `AppContext::theme`, like its `wireframe_color`, follow
+/// the theme when it is switched.
+#[derive(Debug)]
+pub struct DebugDraw {
+    program: ShaderProgram,
+    vao: u32,
+    vbo: u32,
+    /// The ends of every line queued since the last draw
+    vertices: Vec<LineVertex>,
+    /// The size of the vertex buffer in bytes
+    capacity: usize,
+}
+
+impl DebugDraw {
+    pub fn new(gl: &mut glow::Context) -> Self {
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+        unsafe {
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            Self::vertex_layout().apply(gl);
+            gl.bind_vertex_array(None);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            resources::track(ResourceKind::VertexArray, vao, "Debug draw vertex array");
+            resources::track(ResourceKind::Buffer, vbo, "Debug draw vertex buffer");
+
+            Self {
+                program,
+                vao,
+                vbo,
+                vertices: Vec::new(),
+                capacity: 0,
+            }
+        }
+    }
+
+    /// The position and color of every vertex
+    fn vertex_layout() -> VertexLayout {
+        VertexLayout::new(&[(0, VertexFormat::Float32x3), (1, VertexFormat::Unorm8x4)])
+    }
+
+    /// Queue a line between two points
+    pub fn line(&mut self, from: Point3<f32>, to: Point3<f32>, color: Color) {
+        let color = pack_unorm8x4(color.to_srgb());
+        for position in [from, to] {
+            self.vertices.push(LineVertex {
+                position: position.into(),
+                color,
+            });
+        }
+    }
+
+    /// Queue the edges of a box
+    pub fn aabb(&mut self, aabb: &Aabb, color: Color) {
+        let corners = aabb.corners();
+        for &(a, b) in &BOX_EDGES {
+            self.line(corners[a].into(), corners[b].into(), color);
+        }
+    }
+
+    /// Queue a cross along the three axes marking a point
+    pub fn cross(&mut self, point: Point3<f32>, size: f32, color: Color) {
+        for axis in [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()] {
+            let offset = axis * size / 2.;
+            self.line(point - offset, point + offset, color);
+        }
+    }
+
+    /// Queue a contact as a cross at its point with a line along its normal
+    pub fn contact(&mut self, contact: &Contact, color: Color) {
+        self.cross(contact.point, 0.1, color);
+        self.line(
+            contact.point,
+            contact.point + contact.normal.normalize() * CONTACT_NORMAL_LENGTH,
+            color,
+        );
+    }
+
+    /// Queue the outline of a capsule: a ring around each end of its segment, the lines joining
+    /// them, and the half circles over the ends
+    pub fn capsule(&mut self, capsule: &Capsule, color: Color) {
+        let axis = capsule.b - capsule.a;
+        let up = if axis.magnitude2() > 0. {
+            axis.normalize()
+        } else {
+            Vector3::unit_y()
+        };
+        // Two directions across the axis, starting from whichever world axis is furthest from it
+        let helper = if up.x.abs() < 0.9 {
+            Vector3::unit_x()
+        } else {
+            Vector3::unit_z()
+        };
+        let side = up.cross(helper).normalize() * capsule.radius;
+        let front = side.cross(up);
+        for (center, end) in [(capsule.a, -up), (capsule.b, up)] {
+            let end = end * capsule.radius;
+            self.arc(center, side, front, 0., TAU, color);
+            self.arc(center, side, end, 0., PI, color);
+            self.arc(center, front, end, 0., PI, color);
+        }
+        for offset in [side, -side, front, -front] {
+            self.line(capsule.a + offset, capsule.b + offset, color);
+        }
+    }
+
+    /// Queue the part of the ellipse `center + u * cos(angle) + v * sin(angle)` between two
+    /// angles in radians
+    fn arc(
+        &mut self,
+        center: Point3<f32>,
+        u: Vector3<f32>,
+        v: Vector3<f32>,
+        from: f32,
+        to: f32,
+        color: Color,
+    ) {
+        let segments = ((CIRCLE_SEGMENTS as f32 * (to - from).abs() / TAU).ceil() as usize).max(1);
+        let point = |i: usize| {
+            let angle = from + (to - from) * i as f32 / segments as f32;
+            center + u * angle.cos() + v * angle.sin()
+        };
+        for i in 0..segments {
+            self.line(point(i), point(i + 1), color);
+        }
+    }
+
+    /// Queue the path of a moving box, like the `path` of a `SlideResult`: the box at every point
+    /// of the path, and the lines its corners moved along
+    pub fn swept_path(&mut self, path: &[Aabb], color: Color) {
+        for aabb in path {
+            self.aabb(aabb, color);
+        }
+        for pair in path.windows(2) {
+            let (from, to) = (pair[0].corners(), pair[1].corners());
+            for (from, to) in from.iter().zip(&to) {
+                self.line((*from).into(), (*to).into(), color);
+            }
+        }
+    }
+
+    /// Draw every line that was queued and clear the queue. The vertices are staged in `arena`
+    /// before they are uploaded.
+    pub fn draw(
+        &mut self,
+        gl: &mut glow::Context,
+        arena: &FrameArena,
+        view_projection: Matrix4<f32>,
+    ) {
+        if self.vertices.is_empty() {
+            return;
+        }
+        let _group = DebugGroup::push(gl, "Debug draw");
+
+        let vertices = arena.alloc_slice_copy(&self.vertices);
+        let bytes = vertices.as_mem_bytes();
+        debug_assert_eq!(
+            std::mem::size_of::<LineVertex>(),
+            Self::vertex_layout().stride()
+        );
+
+        unsafe {
+            // Upload the vertices, only reallocating the buffer when it grows
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+            if bytes.len() > self.capacity {
+                self.capacity = bytes.len().next_power_of_two();
+                gl.buffer_data_size(glow::ARRAY_BUFFER, self.capacity as i32, glow::STREAM_DRAW);
+                resources::track_sized(
+                    ResourceKind::Buffer,
+                    self.vbo,
+                    "Debug draw vertex buffer",
+                    self.capacity as u64,
+                );
+            }
+            gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, bytes);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+            self.program.bind(gl);
+            self.program
+                .set_uniform(gl, "viewProjection", view_projection);
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::LINES, 0, vertices.len() as i32);
+            gl.bind_vertex_array(None);
+        }
+        self.vertices.clear();
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.program.delete(gl);
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+        }
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+        resources::untrack(ResourceKind::Buffer, self.vbo);
+    }
+}
@@ -0,0 +1,118 @@
+use std::{cell::Cell, marker::PhantomData, os::raw::c_void};
+
+use glow::HasContext;
+use surfman::{Context, Device};
+
+use crate::features::{Features, Loader};
+
+/// The signature of `glPopDebugGroup`
+///
+/// Glow needs the context to pop a group, which a guard can't hold on to while the scope uses the
+/// context mutably, so the function is loaded separately.
+pub(crate) type PopDebugGroup = extern "system" fn();
+
+thread_local! {
+    /// The function that pops debug groups in the GL context that is current on this thread, or
+    /// `None` if the context doesn't support debug groups
+    static CURRENT: Cell<Option<PopDebugGroup>> = const { Cell::new(None) };
+}
+
+/// Load `glPopDebugGroup`, returning `None` if the context doesn't support debug groups
+pub(crate) fn load_pop_fn(
+    features: &Features,
+    device: &Device,
+    context: &Context,
+) -> Option<PopDebugGroup> {
+    load_pop_fn_with_loader(features, &|symbol| device.get_proc_address(context, symbol))
+}
+
+/// Load `glPopDebugGroup` with a loader of our own, for a context that surfman didn't create
+pub(crate) fn load_pop_fn_with_loader(
+    features: &Features,
+    loader: Loader,
+) -> Option<PopDebugGroup> {
+    if !features.debug_output {
+        return None;
+    }
+    let ptr = loader("glPopDebugGroup");
+    if ptr.is_null() {
+        None
+    } else {
+        Some(unsafe { std::mem::transmute::<*const c_void, PopDebugGroup>(ptr) })
+    }
+}
+
+/// Send debug groups to the given context's functions, which should follow the current GL context
+pub(crate) fn make_current(pop: Option<PopDebugGroup>) {
+    CURRENT.with(|current| current.set(pop));
+}
+
+/// Whether or not the current GL context supports debug groups
+pub fn is_enabled() -> bool {
+    CURRENT.with(|current| current.get().is_some())
+}
+
+/// Start a named group of GL commands, which frame capture tools like RenderDoc show as a node in
+/// a tree. Does nothing if the context doesn't support `GL_KHR_debug`.
+///
+/// Every push needs a matching `pop_debug_group`. `DebugGroup` and `debug_scope!` pop the group
+/// automatically.
+pub fn push_debug_group(gl: &glow::Context, name: &str) {
+    if is_enabled() {
+        unsafe { gl.push_debug_group(glow::DEBUG_SOURCE_APPLICATION, 0, name) };
+    }
+}
+
+/// End the group started by the last `push_debug_group`
+pub fn pop_debug_group(gl: &glow::Context) {
+    if is_enabled() {
+        unsafe { gl.pop_debug_group() };
+    }
+}
+
+/// A debug group that ends when the guard is dropped, so that groups stay balanced even when a
+/// scope returns early
+#[must_use = "the debug group ends as soon as the guard is dropped"]
+pub struct DebugGroup {
+    pop: Option<PopDebugGroup>,
+    /// The group has to end on the thread and context it was started in
+    _not_send: PhantomData<*const ()>,
+}
+
+impl DebugGroup {
+    /// Start a group that lasts until the guard is dropped
+    pub fn push(gl: &glow::Context, name: &str) -> Self {
+        push_debug_group(gl, name);
+        Self {
+            pop: CURRENT.with(|current| current.get()),
+            _not_send: PhantomData,
+        }
+    }
+}
+
+impl Drop for DebugGroup {
+    fn drop(&mut self) {
+        if let Some(pop) = self.pop {
+            pop();
+        }
+    }
+}
+
+impl std::fmt::Debug for DebugGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DebugGroup")
+    }
+}
+
+/// Run a block inside a named debug group, e.g.
+/// `debug_scope!(gl, "shadow pass", { draw_shadows(gl) })`
+///
+/// The group ends when the block does, even if it returns early or uses `?`. The block's value is
+/// the value of the macro.
+#[macro_export]
+macro_rules! debug_scope {
+    ($gl:expr, $name:expr, $body:block) => {{
+        let _debug_group = $crate::debug_group::DebugGroup::push(&$gl, $name);
+        $body
+    }};
+}
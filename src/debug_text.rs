@@ -0,0 +1,653 @@
+use std::ops::Range;
+
+use glow::HasContext;
+
+use crate::{
+    color::Color,
+    debug_group::DebugGroup,
+    frame_arena::FrameArena,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+    vertex::{pack_unorm8x4, VertexFormat, VertexLayout},
+    SliceAsBytes,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("debug_text/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("debug_text/fragment.glsl");
+
+/// The size of a glyph of the built-in font in pixels, at a scale of 1
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+/// How far the pen moves after each character, and after each line, at a scale of 1
+pub const CHAR_ADVANCE: u32 = GLYPH_WIDTH + 1;
+pub const LINE_HEIGHT: u32 = GLYPH_HEIGHT + 3;
+
+/// The first character in the font, which has a glyph for every printable ASCII character
+const FIRST_CHAR: u8 = b' ';
+
+/// The rows of each glyph from the top down, with the leftmost pixel in the highest of the five
+/// bits
+#[rustfmt::skip]
+const GLYPHS: [[u8; 7]; 95] = [
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000], // ' '
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00000, 0b00100], // !
+    [0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000], // "
+    [0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010], // #
+    [0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100], // $
+    [0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011], // %
+    [0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101], // &
+    [0b01100, 0b00100, 0b01000, 0b00000, 0b00000, 0b00000, 0b00000], // '
+    [0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010], // (
+    [0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000], // )
+    [0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000], // *
+    [0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000], // +
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000], // ,
+    [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000], // -
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100], // .
+    [0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000], // /
+    [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110], // 0
+    [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // 1
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111], // 2
+    [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110], // 3
+    [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010], // 4
+    [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110], // 5
+    [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110], // 6
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000], // 7
+    [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110], // 8
+    [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100], // 9
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000], // :
+    [0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000], // ;
+    [0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010], // <
+    [0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000], // =
+    [0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000], // >
+    [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100], // ?
+    [0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110], // @
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001], // A
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110], // B
+    [0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110], // C
+    [0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100], // D
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111], // E
+    [0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000], // F
+    [0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111], // G
+    [0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001], // H
+    [0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // I
+    [0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100], // J
+    [0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001], // K
+    [0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111], // L
+    [0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001], // M
+    [0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001], // N
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // O
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000], // P
+    [0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101], // Q
+    [0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001], // R
+    [0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110], // S
+    [0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // T
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110], // U
+    [0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // V
+    [0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010], // W
+    [0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001], // X
+    [0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100], // Y
+    [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111], // Z
+    [0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110], // [
+    [0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000], // \
+    [0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110], // ]
+    [0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000], // ^
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111], // _
+    [0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000], // `
+    [0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111], // a
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110], // b
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110], // c
+    [0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111], // d
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110], // e
+    [0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000], // f
+    [0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // g
+    [0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // h
+    [0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110], // i
+    [0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100], // j
+    [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010], // k
+    [0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110], // l
+    [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001], // m
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001], // n
+    [0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110], // o
+    [0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000], // p
+    [0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001], // q
+    [0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000], // r
+    [0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110], // s
+    [0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110], // t
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101], // u
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100], // v
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010], // w
+    [0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001], // x
+    [0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110], // y
+    [0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111], // z
+    [0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010], // {
+    [0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100], // |
+    [0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000], // }
+    [0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000], // ~
+];
+
+/// Glyphs for the characters past ASCII that show up in stats and paths, which go after `GLYPHS`
+/// in the font
+#[rustfmt::skip]
+const EXTRA_GLYPHS: [(char, [u8; 7]); 5] = [
+    ('°', [0b01100, 0b10010, 0b10010, 0b01100, 0b00000, 0b00000, 0b00000]),
+    ('µ', [0b00000, 0b00000, 0b10001, 0b10001, 0b10011, 0b11101, 0b10000]),
+    ('×', [0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b00000]),
+    ('·', [0b00000, 0b00000, 0b00000, 0b00100, 0b00000, 0b00000, 0b00000]),
+    ('…', [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b10101]),
+];
+
+/// The box drawn for characters the font doesn't have, which goes last in the font
+#[rustfmt::skip]
+const MISSING_GLYPH: [u8; 7] = [0b11111, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11111];
+
+/// How many glyphs are in the font, with the missing glyph box
+const FONT_GLYPHS: usize = GLYPHS.len() + EXTRA_GLYPHS.len() + 1;
+
+/// How text is lined up against the position it is drawn at
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextAlign {
+    /// Lines start at the position
+    #[default]
+    Left,
+    /// Lines are centered on the position
+    Center,
+    /// Lines end at the position
+    Right,
+}
+
+/// How to lay out text with `DebugText::text_with` and `DebugText::measure_text`
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TextOptions {
+    pub align: TextAlign,
+    /// Wrap lines at spaces to keep them this many pixels wide, or `None` to only start new
+    /// lines at `\n`. Words too long for a line of their own are broken between characters.
+    pub max_width: Option<f32>,
+}
+
+/// A line of laid out text
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextLine {
+    /// Where the line is in the text, in bytes, without the line break or space it ended at
+    pub range: Range<usize>,
+    /// How many characters wide the line is, which leaves out characters that take no space like
+    /// accents that go over the character before them
+    pub chars: usize,
+    /// The left and top of the line from the position of the text, in pixels
+    pub x: f32,
+    pub y: f32,
+    /// How wide the line is from the left of its first glyph to the right of its last, in pixels
+    pub width: f32,
+}
+
+/// Where the lines of some text go, from `DebugText::measure_text`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextLayout {
+    pub lines: Vec<TextLine>,
+    /// The left of the box around all of the lines from the position of the text, which is
+    /// negative for centered and right-aligned text, and the size of the box, in pixels
+    pub left: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A rectangle waiting to be drawn, given as left, top, right, and bottom, with texture
+/// coordinates that are negative for a filled rectangle
+#[derive(Clone, Copy, Debug)]
+struct Quad {
+    rect: [f32; 4],
+    uv: [f32; 4],
+    color: [u8; 4],
+}
+
+/// A vertex laid out like `DebugText::vertex_layout`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct TextVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [u8; 4],
+}
+
+/// Draws text with a small built-in bitmap font, and filled rectangles behind it, on top of
+/// whatever is in the framebuffer
+///
+/// Text and rectangles are queued in pixels from the top-left of the viewport and drawn all at
+/// once with `draw`. The font has the printable ASCII characters and a few others like `°`, and
+/// anything else is drawn as a box. Scales are whole numbers so that the glyphs stay sharp.
+#[derive(Debug)]
+pub struct DebugText {
+    program: ShaderProgram,
+    font: u32,
+    vao: u32,
+    vbo: u32,
+    /// The rectangles queued since the last draw
+    quads: Vec<Quad>,
+    /// The size of the vertex buffer in bytes
+    capacity: usize,
+}
+
+impl DebugText {
+    pub fn new(gl: &mut glow::Context) -> Self {
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+
+        // Put every glyph side by side in a single row texture
+        let width = FONT_GLYPHS as u32 * GLYPH_WIDTH;
+        let mut pixels = vec![0u8; (width * GLYPH_HEIGHT) as usize];
+        let glyphs = GLYPHS
+            .iter()
+            .chain(EXTRA_GLYPHS.iter().map(|(_, glyph)| glyph))
+            .chain(Some(&MISSING_GLYPH));
+        for (i, glyph) in glyphs.enumerate() {
+            for (y, row) in glyph.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                        pixels[y * width as usize + i * GLYPH_WIDTH as usize + x as usize] = 255;
+                    }
+                }
+            }
+        }
+
+        unsafe {
+            let font = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(font));
+            // The rows of the texture aren't a multiple of 4 bytes
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 1);
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::R8 as i32,
+                width as i32,
+                GLYPH_HEIGHT as i32,
+                0,
+                glow::RED,
+                glow::UNSIGNED_BYTE,
+                Some(&pixels),
+            );
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            for &(parameter, value) in &[
+                (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+                (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            resources::track_sized(
+                ResourceKind::Texture,
+                font,
+                "Debug text font",
+                pixels.len() as u64,
+            );
+
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            Self::vertex_layout().apply(gl);
+            gl.bind_vertex_array(None);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            resources::track(ResourceKind::VertexArray, vao, "Debug text vertex array");
+            resources::track(ResourceKind::Buffer, vbo, "Debug text vertex buffer");
+
+            Self {
+                program,
+                font,
+                vao,
+                vbo,
+                quads: Vec::new(),
+                capacity: 0,
+            }
+        }
+    }
+
+    /// The position, texture coordinates, and color of every vertex
+    fn vertex_layout() -> VertexLayout {
+        VertexLayout::new(&[
+            (0, VertexFormat::Float32x2),
+            (1, VertexFormat::Float32x2),
+            (2, VertexFormat::Unorm8x4),
+        ])
+    }
+
+    /// The size in pixels of some text at a scale, with a line for each `\n`
+    pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+        let layout = Self::measure_text(text, scale, TextOptions::default());
+        (layout.width as u32, layout.height as u32)
+    }
+
+    /// Lay out text at a scale without drawing it, into the same lines that `text_with` draws
+    ///
+    /// Every glyph of the font is as wide as the others, so a line's width only depends on how
+    /// many characters it has.
+    pub fn measure_text(text: &str, scale: u32, options: TextOptions) -> TextLayout {
+        let scale = scale.max(1) as f32;
+        // A line is as wide as the advances of its characters, less the gap after the last one
+        let max_chars = options
+            .max_width
+            .map(|width| ((width / scale + 1.) / CHAR_ADVANCE as f32).floor().max(1.) as usize);
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for paragraph in text.split('\n') {
+            let range = start..start + paragraph.len();
+            start = range.end + 1;
+            wrap_line(text, range, max_chars, &mut lines);
+        }
+
+        let width_of = |chars: usize| (chars * CHAR_ADVANCE as usize).saturating_sub(1) as f32;
+        let lines = lines
+            .into_iter()
+            .enumerate()
+            .map(|(i, (range, chars))| {
+                let width = width_of(chars) * scale;
+                // Whole pixels keep the glyphs sharp
+                let x = match options.align {
+                    TextAlign::Left => 0.,
+                    TextAlign::Center => (-width / 2.).round(),
+                    TextAlign::Right => -width,
+                };
+                TextLine {
+                    range,
+                    chars,
+                    x,
+                    y: (i as u32 * LINE_HEIGHT) as f32 * scale,
+                    width,
+                }
+            })
+            .collect::<Vec<_>>();
+        let left = lines.iter().map(|line| line.x).fold(0., f32::min);
+        let right = lines
+            .iter()
+            .map(|line| line.x + line.width)
+            .fold(left, f32::max);
+        let height = lines.len() as u32 * LINE_HEIGHT - (LINE_HEIGHT - GLYPH_HEIGHT);
+        TextLayout {
+            lines,
+            left,
+            width: right - left,
+            height: height as f32 * scale,
+        }
+    }
+
+    /// Where each glyph of laid out text goes from the position of the text, as left, top,
+    /// right, and bottom, along with where its glyph is in the font. Spaces are left out.
+    pub(crate) fn place_glyphs(
+        text: &str,
+        scale: u32,
+        layout: &TextLayout,
+    ) -> Vec<([f32; 4], usize)> {
+        let scale = scale.max(1) as f32;
+        let mut glyphs = Vec::new();
+        for line in &layout.lines {
+            let mut x = line.x;
+            for c in text[line.range.clone()].chars() {
+                if is_zero_width(c) {
+                    continue;
+                }
+                if c != ' ' {
+                    glyphs.push((
+                        [
+                            x,
+                            line.y,
+                            x + GLYPH_WIDTH as f32 * scale,
+                            line.y + GLYPH_HEIGHT as f32 * scale,
+                        ],
+                        glyph_index(c),
+                    ));
+                }
+                x += CHAR_ADVANCE as f32 * scale;
+            }
+        }
+        glyphs
+    }
+
+    /// Queue a filled rectangle
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: Color) {
+        self.quad([x, y, x + width, y + height], [-1., -1., -1., -1.], color);
+    }
+
+    /// Queue some text with its top-left corner at `x`, `y`, starting a new line at each `\n`
+    ///
+    /// Returns the position after the last character, so that more text can be queued after it.
+    pub fn text(&mut self, x: f32, y: f32, scale: u32, color: Color, text: &str) -> (f32, f32) {
+        let layout = self.text_with(x, y, scale, color, text, TextOptions::default());
+        match layout.lines.last() {
+            Some(line) => (
+                x + (line.chars as u32 * CHAR_ADVANCE * scale.max(1)) as f32,
+                y + line.y,
+            ),
+            None => (x, y),
+        }
+    }
+
+    /// Queue some text at `x`, `y`, lined up and wrapped as the options say, and return where
+    /// its lines went
+    ///
+    /// `y` is the top of the first line, and `x` is where lines start, are centered, or end,
+    /// depending on `TextOptions::align`. The layout is the same one that `measure_text` gives.
+    pub fn text_with(
+        &mut self,
+        x: f32,
+        y: f32,
+        scale: u32,
+        color: Color,
+        text: &str,
+        options: TextOptions,
+    ) -> TextLayout {
+        let layout = Self::measure_text(text, scale, options);
+        let glyph_width = 1. / FONT_GLYPHS as f32;
+        for (rect, index) in Self::place_glyphs(text, scale, &layout) {
+            let u = index as f32 * glyph_width;
+            self.quad(
+                [rect[0] + x, rect[1] + y, rect[2] + x, rect[3] + y],
+                [u, 0., u + glyph_width, 1.],
+                color,
+            );
+        }
+        layout
+    }
+
+    /// Queue a rectangle, given as left, top, right, and bottom
+    fn quad(&mut self, rect: [f32; 4], uv: [f32; 4], color: Color) {
+        self.quads.push(Quad {
+            rect,
+            uv,
+            color: pack_unorm8x4(color.to_srgb()),
+        });
+    }
+
+    /// Draw everything that was queued over the whole viewport of the given size, and clear the
+    /// queue
+    ///
+    /// Depth testing and face culling are turned off while drawing and blending is set up for the
+    /// translucent colors, and they are all put back afterwards. The vertices are staged in
+    /// `arena` before they are uploaded.
+    pub fn draw(&mut self, gl: &mut glow::Context, arena: &FrameArena, viewport_size: (u32, u32)) {
+        if self.quads.is_empty() {
+            return;
+        }
+        let _group = DebugGroup::push(gl, "Debug text");
+
+        // Build the two triangles of every rectangle
+        let vertices = arena.alloc_slice::<TextVertex>(self.quads.len() * 6);
+        for (quad, vertices) in self.quads.iter().zip(vertices.chunks_mut(6)) {
+            let [left, top, right, bottom] = quad.rect;
+            let uv = quad.uv;
+            let corners = [
+                ([left, top], [uv[0], uv[1]]),
+                ([left, bottom], [uv[0], uv[3]]),
+                ([right, bottom], [uv[2], uv[3]]),
+                ([left, top], [uv[0], uv[1]]),
+                ([right, bottom], [uv[2], uv[3]]),
+                ([right, top], [uv[2], uv[1]]),
+            ];
+            for (vertex, (position, uv)) in vertices.iter_mut().zip(corners) {
+                *vertex = TextVertex {
+                    position,
+                    uv,
+                    color: quad.color,
+                };
+            }
+        }
+        let vertex_count = vertices.len();
+        let bytes = vertices.as_mem_bytes();
+        debug_assert_eq!(
+            std::mem::size_of::<TextVertex>(),
+            Self::vertex_layout().stride()
+        );
+
+        unsafe {
+            // Upload the vertices, only reallocating the buffer when it grows
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(self.vbo));
+            if bytes.len() > self.capacity {
+                self.capacity = bytes.len().next_power_of_two();
+                gl.buffer_data_size(glow::ARRAY_BUFFER, self.capacity as i32, glow::STREAM_DRAW);
+                resources::track_sized(
+                    ResourceKind::Buffer,
+                    self.vbo,
+                    "Debug text vertex buffer",
+                    self.capacity as u64,
+                );
+            }
+            gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, bytes);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+            // Draw on top of everything with straight alpha blending
+            let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+            let cull_face = gl.is_enabled(glow::CULL_FACE);
+            let blend = gl.is_enabled(glow::BLEND);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::CULL_FACE);
+            gl.enable(glow::BLEND);
+            gl.blend_func_separate(
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            );
+
+            self.program.bind(gl);
+            self.program.set_uniform(
+                gl,
+                "screenSize",
+                [viewport_size.0 as f32, viewport_size.1 as f32],
+            );
+            self.program.set_uniform(gl, "font", 0);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.font));
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, vertex_count as i32);
+            gl.bind_vertex_array(None);
+
+            // Put the state back the way the handler had it
+            if depth_test {
+                gl.enable(glow::DEPTH_TEST);
+            }
+            if cull_face {
+                gl.enable(glow::CULL_FACE);
+            }
+            if !blend {
+                gl.disable(glow::BLEND);
+            }
+        }
+        self.quads.clear();
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.program.delete(gl);
+        unsafe {
+            gl.delete_texture(self.font);
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+        }
+        resources::untrack(ResourceKind::Texture, self.font);
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+        resources::untrack(ResourceKind::Buffer, self.vbo);
+    }
+}
+
+/// Where a character's glyph is in the font, which is the missing glyph box for characters the
+/// font doesn't have
+fn glyph_index(c: char) -> usize {
+    match c {
+        ' '..='~' => (c as u8 - FIRST_CHAR) as usize,
+        _ => EXTRA_GLYPHS
+            .iter()
+            .position(|(extra, _)| *extra == c)
+            .map_or(FONT_GLYPHS - 1, |i| GLYPHS.len() + i),
+    }
+}
+
+/// Whether a character takes no space, like control characters, zero width spaces, and the
+/// accents that combine with the character before them
+fn is_zero_width(c: char) -> bool {
+    c.is_control() || matches!(c, '\u{300}'..='\u{36f}' | '\u{200b}'..='\u{200d}' | '\u{feff}')
+}
+
+/// How many characters wide some text is
+fn char_count(text: &str) -> usize {
+    text.chars().filter(|&c| !is_zero_width(c)).count()
+}
+
+/// Split a line of text without line breaks into the lines it wraps into, as their ranges in
+/// `text` and how many characters wide they are
+///
+/// Lines are broken at the spaces between words, which aren't a part of either line, and words
+/// longer than a line are broken between characters. The spaces at the start of the text are
+/// kept, since they indent it.
+fn wrap_line(
+    text: &str,
+    range: Range<usize>,
+    max_chars: Option<usize>,
+    lines: &mut Vec<(Range<usize>, usize)>,
+) {
+    let max_chars = match max_chars {
+        Some(max_chars) => max_chars,
+        None => {
+            let chars = char_count(&text[range.clone()]);
+            lines.push((range, chars));
+            return;
+        }
+    };
+    // The line being filled, as where it starts and ends and how many characters wide it is
+    let mut line: Option<(usize, usize, usize)> = None;
+    let mut wrapped = false;
+    let mut word_start = range.start;
+    for word in text[range].split(' ') {
+        let (mut start, end) = (word_start, word_start + word.len());
+        word_start = end + 1;
+        let mut chars = char_count(word);
+        if let Some((line_start, line_end, line_chars)) = line {
+            if line_chars + 1 + chars <= max_chars {
+                line = Some((line_start, end, line_chars + 1 + chars));
+                continue;
+            }
+            lines.push((line_start..line_end, line_chars));
+            line = None;
+            wrapped = true;
+        }
+        // The spaces that a line wrapped at don't start the next one
+        if wrapped && word.is_empty() {
+            continue;
+        }
+        while chars > max_chars {
+            // Accents stay with the character before them
+            let split = start
+                + text[start..end]
+                    .char_indices()
+                    .filter(|&(_, c)| !is_zero_width(c))
+                    .nth(max_chars)
+                    .map_or(end - start, |(i, _)| i);
+            lines.push((start..split, max_chars));
+            wrapped = true;
+            start = split;
+            chars -= max_chars;
+        }
+        line = Some((start, end, chars));
+    }
+    if let Some((start, end, chars)) = line {
+        lines.push((start..end, chars));
+    }
+}
@@ -0,0 +1,14 @@
+#version 330 core
+in vec2 uv;
+in vec4 color;
+
+uniform sampler2D font;
+
+out vec4 FragColor;
+
+void main()
+{
+    // Rectangles have negative texture coordinates and are filled with their color
+    float coverage = uv.x < 0.0 ? 1.0 : texture(font, uv).r;
+    FragColor = vec4(color.rgb, color.a * coverage);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+layout (location = 0) in vec2 aPos;
+layout (location = 1) in vec2 aUv;
+layout (location = 2) in vec4 aColor;
+
+// The size of the viewport in pixels
+uniform vec2 screenSize;
+
+out vec2 uv;
+out vec4 color;
+
+void main()
+{
+    // Go from pixels with the origin at the top-left to normalized device coordinates
+    vec2 ndc = aPos / screenSize * 2.0 - 1.0;
+    gl_Position = vec4(ndc.x, -ndc.y, 0.0, 1.0);
+    uv = aUv;
+    color = aColor;
+}
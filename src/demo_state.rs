@@ -0,0 +1,331 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{Display, Write as _},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::config::{self, Config, ConfigError};
+
+/// The name of the file that the state of demos is saved in, which goes next to the config file,
+/// or in the working directory if there isn't one
+pub const STATE_FILE_NAME: &str = "me_learning_opengl_state.toml";
+
+/// The version of the state file's layout. Files of other versions are discarded.
+pub const STATE_FILE_VERSION: u32 = 1;
+
+/// Something a handler keeps between runs, like a camera's pose or which debug views are on
+///
+/// Handlers restore it in `init` with `DemoState::restore`, and save it in
+/// `RenderHandler::save_state`, which the loop calls before the window closes.
+pub trait Persist {
+    /// The version of the saved values. Bump it when they change meaning, so that state saved by
+    /// an older build is discarded instead of misread.
+    const VERSION: u32 = 1;
+
+    /// Write the values to keep into a blob
+    fn save_to(&self, blob: &mut StateBlob);
+
+    /// Read back the values that `save_to` wrote
+    ///
+    /// Read every value before changing anything, so that a blob that can't be read leaves
+    /// everything as it was.
+    fn restore_from(&mut self, blob: &StateBlob) -> Result<(), String>;
+}
+
+/// The saved values of one `Persist`, as text under keys
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StateBlob {
+    /// The `Persist::VERSION` it was saved with
+    version: u32,
+    values: BTreeMap<String, String>,
+}
+
+impl StateBlob {
+    pub fn set<T: Display>(&mut self, key: &str, value: T) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    /// Save numbers, like a position, separated by spaces
+    pub fn set_floats(&mut self, key: &str, values: &[f32]) {
+        let values = values
+            .iter()
+            .map(|value| value.to_string())
+            .collect::<Vec<_>>();
+        self.set(key, values.join(" "));
+    }
+
+    /// Whether there is a value under a key
+    pub fn has(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    /// Read a value, or say why it can't be read
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<T, String> {
+        let value = self
+            .values
+            .get(key)
+            .ok_or_else(|| format!("`{}` is missing", key))?;
+        value
+            .parse()
+            .map_err(|_| format!("`{}` can't be read from `{}`", key, value))
+    }
+
+    /// Read a number, which has to be finite
+    pub fn get_float(&self, key: &str) -> Result<f32, String> {
+        let value = self.get::<String>(key)?;
+        value
+            .parse()
+            .ok()
+            .filter(|float: &f32| float.is_finite())
+            .ok_or_else(|| format!("`{}` isn't a number: `{}`", key, value))
+    }
+
+    /// Read `N` numbers saved with `set_floats`
+    pub fn get_floats<const N: usize>(&self, key: &str) -> Result<[f32; N], String> {
+        let value = self.get::<String>(key)?;
+        let invalid = || format!("`{}` isn't {} numbers: `{}`", key, N, value);
+        let mut floats = [0.; N];
+        let mut words = value.split_whitespace();
+        for float in floats.iter_mut() {
+            *float = words
+                .next()
+                .and_then(|word| word.parse().ok())
+                .filter(|float: &f32| float.is_finite())
+                .ok_or_else(invalid)?;
+        }
+        if words.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(floats)
+    }
+}
+
+/// The saved state of one demo's window, which handlers read and write through `AppContext`
+///
+/// It is only saved for windows with a name, which the loop gives every window except headless
+/// ones, benchmarks, and ones recording or replaying input, since those have to start the same
+/// way every time. The `--fresh` flag starts with nothing restored, and the `state clear` console
+/// command forgets what was saved.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DemoState {
+    /// The name the state is saved under, or `None` if it isn't saved
+    name: Option<String>,
+    blobs: BTreeMap<String, StateBlob>,
+    /// Whether it was cleared, so it shouldn't be saved again when the window closes
+    cleared: bool,
+}
+
+impl DemoState {
+    /// An empty state that is saved under a name
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: Some(name.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// The name the state is saved under, or `None` if it isn't saved
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The names of the saved blobs
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.blobs.keys().map(String::as_str)
+    }
+
+    /// Whether the state was cleared with `clear`
+    pub fn is_cleared(&self) -> bool {
+        self.cleared
+    }
+
+    /// Restore something from the blob saved under `key`, returning whether there was one that
+    /// could be read
+    ///
+    /// A blob saved with another `Persist::VERSION`, or that can't be read, is discarded with a
+    /// warning.
+    pub fn restore<T: Persist>(&mut self, key: &str, state: &mut T) -> bool {
+        let blob = match self.blobs.get(key) {
+            Some(blob) => blob,
+            None => return false,
+        };
+        let name = self.name.as_deref().unwrap_or_default();
+        let result = if blob.version != T::VERSION {
+            Err(format!(
+                "it is version {} of it, and this build saves version {}",
+                blob.version,
+                T::VERSION
+            ))
+        } else {
+            state.restore_from(blob)
+        };
+        match result {
+            Ok(()) => true,
+            Err(error) => {
+                eprintln!(
+                    "Warning: {}: Discarding the saved `{}` state, since {}",
+                    name, key, error
+                );
+                self.blobs.remove(key);
+                false
+            }
+        }
+    }
+
+    /// Save something as the blob under `key`, replacing the one that was there, unless the
+    /// state was cleared
+    ///
+    /// Blob names can't have dots in them, since the file uses them to separate the blob's name
+    /// from its keys.
+    pub fn save<T: Persist>(&mut self, key: &str, state: &T) {
+        debug_assert!(!key.contains('.'), "Blob names can't have dots: {}", key);
+        if self.cleared {
+            return;
+        }
+        let mut blob = StateBlob {
+            version: T::VERSION,
+            values: BTreeMap::new(),
+        };
+        state.save_to(&mut blob);
+        self.blobs.insert(key.to_string(), blob);
+    }
+
+    /// Forget the saved blobs, and don't save them again when the window closes, so that the next
+    /// run starts fresh
+    pub fn clear(&mut self) {
+        self.blobs.clear();
+        self.cleared = true;
+    }
+}
+
+/// The saved state of every demo, by the name of its window
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DemoStates {
+    demos: BTreeMap<String, BTreeMap<String, StateBlob>>,
+}
+
+impl DemoStates {
+    /// The file that the state of demos is saved in
+    pub fn path() -> PathBuf {
+        let dir = Config::find_file()
+            .and_then(|path| Some(path.parent()?.to_path_buf()))
+            .unwrap_or_default();
+        dir.join(STATE_FILE_NAME)
+    }
+
+    /// Load the saved state if there is any
+    ///
+    /// A file that can't be read, or that is of another version, is discarded with a warning, and
+    /// is replaced the next time the state is saved.
+    pub fn load() -> Self {
+        let path = Self::path();
+        if !path.is_file() {
+            return Self::default();
+        }
+        Self::open(&path).unwrap_or_else(|error| {
+            eprintln!(
+                "Warning: {}: {}, so the saved state is discarded",
+                path.display(),
+                error
+            );
+            Self::default()
+        })
+    }
+
+    /// Read a state file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Read the state of demos from a `version` line and a table for each demo, with a
+    /// `blob.version` line and `blob.key = value` lines for each of its blobs
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        let mut version = None;
+        let mut demos = BTreeMap::<String, BTreeMap<String, StateBlob>>::new();
+        config::parse_toml(toml, |demo, key, value| {
+            let demo = match demo {
+                Some(demo) => demo,
+                None if key == "version" => {
+                    let number: u32 = value
+                        .parse()
+                        .map_err(|_| format!("Expected a version number, got `{}`", value))?;
+                    if number != STATE_FILE_VERSION {
+                        return Err(format!(
+                            "The file is version {}, and this build reads version {}",
+                            number, STATE_FILE_VERSION
+                        ));
+                    }
+                    version = Some(number);
+                    return Ok(true);
+                }
+                None => return Ok(false),
+            };
+            if version.is_none() {
+                return Err("The file doesn't start with its version".into());
+            }
+            let (name, key) = match key.split_once('.') {
+                Some(split) => split,
+                None => return Ok(false),
+            };
+            let blob = demos
+                .entry(demo.to_string())
+                .or_default()
+                .entry(name.to_string())
+                .or_default();
+            if key == "version" {
+                blob.version = value
+                    .parse()
+                    .map_err(|_| format!("Expected a version number, got `{}`", value))?;
+            } else {
+                blob.set(key, value);
+            }
+            Ok(true)
+        })?;
+        Ok(Self { demos })
+    }
+
+    /// Write the state of the demos as a table for each demo that `from_toml` can read
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        writeln!(toml, "version = {}", STATE_FILE_VERSION).unwrap();
+        for (demo, blobs) in &self.demos {
+            writeln!(toml, "\n[{:?}]", demo).unwrap();
+            for (name, blob) in blobs {
+                writeln!(toml, "{}.version = {}", name, blob.version).unwrap();
+                for (key, value) in &blob.values {
+                    writeln!(toml, "{}.{} = {:?}", name, key, value).unwrap();
+                }
+            }
+        }
+        toml
+    }
+
+    /// The saved state of a demo, which is empty if it wasn't saved
+    pub fn get(&self, name: &str) -> DemoState {
+        DemoState {
+            blobs: self.demos.get(name).cloned().unwrap_or_default(),
+            ..DemoState::new(name)
+        }
+    }
+
+    /// Replace the saved state of a demo, or forget it if it was cleared or isn't saved under a
+    /// name
+    pub fn set(&mut self, state: &DemoState) {
+        let name = match &state.name {
+            Some(name) => name,
+            None => return,
+        };
+        if state.cleared || state.blobs.is_empty() {
+            self.demos.remove(name);
+        } else {
+            self.demos.insert(name.clone(), state.blobs.clone());
+        }
+    }
+
+    /// Save the state to `path()`
+    pub fn save(&self) -> io::Result<()> {
+        std::fs::write(Self::path(), self.to_toml())
+    }
+}
@@ -0,0 +1,231 @@
+use std::{cell::Cell, fmt};
+
+use cgmath::{Matrix4, Rad, Vector4};
+use glow::HasContext;
+
+use crate::features::{ClipControlFns, Features};
+
+/// How depth is stored in the depth buffer, in `RenderSettings::depth_mode`
+///
+/// Cycle through them with F5.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepthMode {
+    /// From 0 at the near plane to 1 at the far plane, with GL's defaults
+    #[default]
+    Standard,
+    /// From 1 at the near plane to 0 at the far plane, in a floating point depth buffer
+    ///
+    /// Perspective crowds most depth values up close to the near plane, and floats crowd most of
+    /// theirs close to 0, so reversing the depth spreads the precision out evenly over the whole
+    /// view. Distant surfaces that are close together stop fighting, which they do with
+    /// `Standard` depth once the far plane is a few thousand times further than the near one.
+    ReverseZ,
+}
+
+impl DepthMode {
+    /// The name of the mode in the console
+    pub fn name(self) -> &'static str {
+        match self {
+            DepthMode::Standard => "standard",
+            DepthMode::ReverseZ => "reverse-z",
+        }
+    }
+
+    /// The mode with the given name, like `reverse-z`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "standard" => Some(DepthMode::Standard),
+            "reverse-z" => Some(DepthMode::ReverseZ),
+            _ => None,
+        }
+    }
+
+    /// The mode after this one, for cycling through them with a key
+    pub fn next(self) -> Self {
+        match self {
+            DepthMode::Standard => DepthMode::ReverseZ,
+            DepthMode::ReverseZ => DepthMode::Standard,
+        }
+    }
+
+    /// The depth test that keeps the closest surface
+    pub fn depth_func(self) -> u32 {
+        match self {
+            DepthMode::Standard => glow::LESS,
+            DepthMode::ReverseZ => glow::GREATER,
+        }
+    }
+
+    /// The depth that the depth buffer is cleared to, which is the far plane's
+    pub fn clear_depth(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.,
+            DepthMode::ReverseZ => 0.,
+        }
+    }
+
+    /// The internal format of a depth and stencil buffer, since reversing only helps with
+    /// floating point depth
+    pub fn depth_stencil_format(self) -> u32 {
+        match self {
+            DepthMode::Standard => glow::DEPTH24_STENCIL8,
+            DepthMode::ReverseZ => glow::DEPTH32F_STENCIL8,
+        }
+    }
+
+    /// The pixel type to give `glTexImage2D` along with `depth_stencil_format`
+    pub fn depth_stencil_type(self) -> u32 {
+        match self {
+            DepthMode::Standard => glow::UNSIGNED_INT_24_8,
+            DepthMode::ReverseZ => glow::FLOAT_32_UNSIGNED_INT_24_8_REV,
+        }
+    }
+
+    /// 1, or -1 when the depth is reversed, to multiply a `glPolygonOffset` with so that it still
+    /// pulls surfaces the same way
+    pub fn offset_sign(self) -> f32 {
+        match self {
+            DepthMode::Standard => 1.,
+            DepthMode::ReverseZ => -1.,
+        }
+    }
+}
+
+impl fmt::Display for DepthMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// A depth mode along with how the context can draw it, which the projections of the frame have
+/// to be built for
+///
+/// The loop makes one for `RenderSettings::depth_mode` before each frame and makes it current
+/// while the handler draws. Cameras build their projections with `current`, so switching the mode
+/// doesn't need anything else from the handler, as long as it draws with a camera's projection,
+/// or with one passed through `projection`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DepthSetup {
+    pub mode: DepthMode,
+    /// Whether clip space depth goes from 0 to 1 instead of GL's -1 to 1, which reverse-Z uses
+    /// when the context has `glClipControl`. Without it the reversed depth goes through the
+    /// usual mapping to 0 to 1, which loses most of the precision that reversing gains.
+    pub zero_to_one: bool,
+    clip_control: Option<ClipControlFns>,
+}
+
+impl DepthSetup {
+    /// GL's defaults, which is what is current outside of the loop's frames
+    pub const STANDARD: DepthSetup = DepthSetup {
+        mode: DepthMode::Standard,
+        zero_to_one: false,
+        clip_control: None,
+    };
+
+    /// The setup for drawing a depth mode in a context with the given features
+    pub fn new(mode: DepthMode, features: &Features) -> Self {
+        Self {
+            mode,
+            zero_to_one: mode == DepthMode::ReverseZ && features.clip_control.is_some(),
+            clip_control: features.clip_control,
+        }
+    }
+
+    /// Whether the near plane is at a depth of 1 and the far plane at 0
+    pub fn is_reversed(&self) -> bool {
+        self.mode == DepthMode::ReverseZ
+    }
+
+    /// A perspective projection for this setup, like `cgmath::perspective`
+    ///
+    /// The reversed projections are built directly instead of changing a standard one with
+    /// `projection`, which would round away some of their precision.
+    pub fn perspective<A: Into<Rad<f32>>>(
+        &self,
+        fovy: A,
+        aspect: f32,
+        near: f32,
+        far: f32,
+    ) -> Matrix4<f32> {
+        let mut projection = cgmath::perspective(fovy, aspect, near, far);
+        let (z, w) = match (self.mode, self.zero_to_one) {
+            (DepthMode::Standard, _) => return projection,
+            (DepthMode::ReverseZ, false) => {
+                ((far + near) / (far - near), 2. * far * near / (far - near))
+            }
+            (DepthMode::ReverseZ, true) => (near / (far - near), far * near / (far - near)),
+        };
+        projection.z.z = z;
+        projection.w.z = w;
+        projection
+    }
+
+    /// A projection built for standard depth, like `cgmath::ortho`, changed to this setup
+    pub fn projection(&self, standard: Matrix4<f32>) -> Matrix4<f32> {
+        match (self.mode, self.zero_to_one) {
+            (DepthMode::Standard, _) => standard,
+            // Negating the depth turns -1 to 1 around
+            (DepthMode::ReverseZ, false) => Matrix4::from_nonuniform_scale(1., 1., -1.) * standard,
+            // Moving -1 to 1 and 1 to 0
+            (DepthMode::ReverseZ, true) => {
+                Matrix4::from_cols(
+                    Vector4::unit_x(),
+                    Vector4::unit_y(),
+                    Vector4::new(0., 0., -0.5, 0.),
+                    Vector4::new(0., 0., 0.5, 1.),
+                ) * standard
+            }
+        }
+    }
+
+    /// A projection of this setup turned back into one for standard depth, e.g. to clip it with
+    /// `planar_reflection::oblique_projection`
+    pub fn standard_projection(&self, projection: Matrix4<f32>) -> Matrix4<f32> {
+        match (self.mode, self.zero_to_one) {
+            (DepthMode::Standard, _) => projection,
+            (DepthMode::ReverseZ, false) => {
+                Matrix4::from_nonuniform_scale(1., 1., -1.) * projection
+            }
+            (DepthMode::ReverseZ, true) => {
+                Matrix4::from_cols(
+                    Vector4::unit_x(),
+                    Vector4::unit_y(),
+                    Vector4::new(0., 0., -2., 0.),
+                    Vector4::new(0., 0., 1., 1.),
+                ) * projection
+            }
+        }
+    }
+
+    /// Set the depth test, the clear depth, and the range of clip space depth for the setup
+    pub(crate) fn apply(&self, gl: &glow::Context) {
+        unsafe {
+            gl.depth_func(self.mode.depth_func());
+            gl.clear_depth_f32(self.mode.clear_depth());
+        }
+        if let Some(clip_control) = self.clip_control {
+            let depth = if self.zero_to_one {
+                glow::ZERO_TO_ONE
+            } else {
+                glow::NEGATIVE_ONE_TO_ONE
+            };
+            clip_control.clip_control(glow::LOWER_LEFT, depth);
+        }
+    }
+}
+
+thread_local! {
+    /// The depth setup of the frame that is being drawn on this thread
+    static CURRENT: Cell<DepthSetup> = const { Cell::new(DepthSetup::STANDARD) };
+}
+
+/// Make a depth setup current on this thread, which the loop does around each handler's `draw`
+pub(crate) fn make_current(setup: DepthSetup) {
+    CURRENT.with(|current| current.set(setup));
+}
+
+/// The depth setup of the frame that is being drawn on this thread, for code that isn't given an
+/// `AppContext`
+pub fn current() -> DepthSetup {
+    CURRENT.with(|current| current.get())
+}
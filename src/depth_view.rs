@@ -0,0 +1,375 @@
+use std::fmt;
+
+use glow::HasContext;
+
+use crate::{
+    depth_mode::DepthMode,
+    diagnostics,
+    nested::SavedState,
+    resources::{self, ResourceKind},
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    viewport::Rect,
+};
+
+const DEPTH_VIEW_FRAGMENT_SRC: &str = include_str!("depth_view/depth_view.frag");
+
+/// How bright depth bends show up in `DepthViewMode::Derivative` by default
+pub const DEFAULT_DERIVATIVE_SCALE: f32 = 200.;
+
+/// What the loop shows in place of the handler's image, for debugging depth
+///
+/// Cycle through them with F8.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DepthViewMode {
+    #[default]
+    Off,
+    /// The distance from the camera in grayscale, from white at the start of the range to black
+    /// at its end
+    Linear,
+    /// How much the depth bends between neighbouring pixels, which lights up where surfaces fight
+    /// over the same depth
+    Derivative,
+}
+
+impl DepthViewMode {
+    /// The mode after this one, for cycling through them with a key
+    pub fn next(self) -> Self {
+        match self {
+            DepthViewMode::Off => DepthViewMode::Linear,
+            DepthViewMode::Linear => DepthViewMode::Derivative,
+            DepthViewMode::Derivative => DepthViewMode::Off,
+        }
+    }
+}
+
+impl fmt::Display for DepthViewMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DepthViewMode::Off => write!(f, "off"),
+            DepthViewMode::Linear => write!(f, "linear"),
+            DepthViewMode::Derivative => write!(f, "derivative"),
+        }
+    }
+}
+
+/// How to show the depth buffer that the handler draws, in `RenderSettings::depth_view`
+///
+/// The depth buffer only holds values from 0 to 1, so the handler has to tell the loop its
+/// camera's clipping planes to turn them back into distances, like
+/// `settings.depth_view.set_clip_planes(near, far, false)` every frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthView {
+    pub mode: DepthViewMode,
+    /// The distance to the near clipping plane of the handler's camera
+    pub near: f32,
+    /// The distance to the far clipping plane of the handler's camera
+    pub far: f32,
+    /// Whether the handler's camera is orthographic, whose depth is already linear
+    pub orthographic: bool,
+    /// The distances that are shown from white to black, or `None` for the near to the far plane
+    ///
+    /// Most scenes only fill a little of the range up to the far plane, so narrowing this brings
+    /// out the detail. PageUp and PageDown move the end of it while the depth is shown, and
+    /// `depth range` sets it from the console.
+    pub range: Option<(f32, f32)>,
+    /// How bright depth bends show up in `DepthViewMode::Derivative`
+    pub derivative_scale: f32,
+}
+
+impl Default for DepthView {
+    fn default() -> Self {
+        Self {
+            mode: DepthViewMode::Off,
+            near: 0.1,
+            far: 1000.,
+            orthographic: false,
+            range: None,
+            derivative_scale: DEFAULT_DERIVATIVE_SCALE,
+        }
+    }
+}
+
+impl DepthView {
+    /// Set the clipping planes of the camera that the depth was drawn with
+    pub fn set_clip_planes(&mut self, near: f32, far: f32, orthographic: bool) {
+        self.near = near;
+        self.far = far;
+        self.orthographic = orthographic;
+    }
+
+    /// The distances that are shown from white to black
+    pub fn range(&self) -> (f32, f32) {
+        self.range.unwrap_or((self.near, self.far))
+    }
+
+    /// Scale the end of the range, keeping its start
+    pub fn scale_range(&mut self, factor: f32) {
+        let (start, end) = self.range();
+        let end = start + (end - start) * factor;
+        self.range = Some((start, end.max(start + f32::EPSILON)));
+    }
+}
+
+/// The framebuffer with a depth texture that the loop reads the depth from
+///
+/// Without anti-aliasing the handler draws straight into it, in place of the window or virtual
+/// resolution framebuffer. With anti-aliasing the depth is copied into it from the anti-aliasing
+/// framebuffer after `draw`, since multisampled depth can't be sampled like a texture.
+#[derive(Debug)]
+struct DepthTarget {
+    framebuffer: u32,
+    color: u32,
+    depth_stencil: u32,
+    size: (u32, u32),
+    depth_mode: DepthMode,
+}
+
+impl DepthTarget {
+    fn new(gl: &mut glow::Context, (width, height): (u32, u32), depth_mode: DepthMode) -> Self {
+        unsafe {
+            let color = gl.create_renderbuffer().unwrap();
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(color));
+            gl.renderbuffer_storage(glow::RENDERBUFFER, glow::RGBA8, width as i32, height as i32);
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            // The same format as the other framebuffers of the loop, so the depth can be blitted
+            // from them
+            let depth_stencil = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(depth_stencil));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                depth_mode.depth_stencil_format() as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::DEPTH_STENCIL,
+                depth_mode.depth_stencil_type(),
+                None,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::RENDERBUFFER,
+                Some(color),
+            );
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_STENCIL_ATTACHMENT,
+                glow::TEXTURE_2D,
+                Some(depth_stencil),
+                0,
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                diagnostics::report_problem("The depth view framebuffer is incomplete");
+            }
+
+            let label = format!("Depth view {}x{}", width, height);
+            resources::track_sized(
+                ResourceKind::Renderbuffer,
+                color,
+                &label,
+                width as u64 * height as u64 * 4,
+            );
+            resources::track_sized(
+                ResourceKind::Texture,
+                depth_stencil,
+                &label,
+                resources::texture_bytes(width, height, depth_mode.depth_stencil_format(), 1, 1, 1),
+            );
+            resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+
+            Self {
+                framebuffer,
+                color,
+                depth_stencil,
+                size: (width, height),
+                depth_mode,
+            }
+        }
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_renderbuffer(self.color);
+            gl.delete_texture(self.depth_stencil);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Renderbuffer, self.color);
+        resources::untrack(ResourceKind::Texture, self.depth_stencil);
+    }
+}
+
+/// The framebuffer and pass that the loop uses for `RenderSettings::depth_view`
+///
+/// `prepare` gives back the framebuffer that the handler should draw into, `capture` copies the
+/// depth out of the anti-aliasing framebuffer after `draw` if there is one, and `draw` shows the
+/// depth in the output framebuffer once the image has been resolved into it.
+#[derive(Debug)]
+pub(crate) struct DepthViewPass {
+    target: Option<DepthTarget>,
+    /// The framebuffer that the handler draws into when the depth has to be copied out of it
+    source: Option<u32>,
+    /// The framebuffer that the depth is shown in
+    output: Option<u32>,
+    /// The program and an empty vertex array for the fullscreen triangle
+    pass: Option<(ShaderProgram, u32)>,
+}
+
+impl DepthViewPass {
+    pub fn new() -> Self {
+        Self {
+            target: None,
+            source: None,
+            output: None,
+            pass: None,
+        }
+    }
+
+    /// Make the depth framebuffer for a size and depth mode if they changed, and return the
+    /// framebuffer that the handler should draw into
+    ///
+    /// `framebuffer` is the one the handler would draw into otherwise, and `output` the one the
+    /// image ends up in, which are the same without anti-aliasing.
+    pub fn prepare(
+        &mut self,
+        gl: &mut glow::Context,
+        size: (u32, u32),
+        depth_mode: DepthMode,
+        framebuffer: Option<u32>,
+        output: Option<u32>,
+    ) -> Option<u32> {
+        let size = (size.0.max(1), size.1.max(1));
+        let target = self
+            .target
+            .as_ref()
+            .map(|target| (target.size, target.depth_mode));
+        if target != Some((size, depth_mode)) {
+            if let Some(target) = self.target.take() {
+                target.delete(gl);
+            }
+            self.target = Some(DepthTarget::new(gl, size, depth_mode));
+        }
+        let target = self.target.as_ref().unwrap();
+        self.output = output;
+        if framebuffer == output {
+            self.source = None;
+            Some(target.framebuffer)
+        } else {
+            self.source = framebuffer;
+            framebuffer
+        }
+    }
+
+    /// Copy the depth out of the framebuffer the handler drew into, if it didn't draw into the
+    /// depth framebuffer
+    pub fn capture(&mut self, gl: &mut glow::Context) {
+        let (source, target) = match (self.source, &self.target) {
+            (Some(source), Some(target)) => (source, target),
+            _ => return,
+        };
+        let (width, height) = (target.size.0 as i32, target.size.1 as i32);
+        unsafe {
+            let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(source));
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(target.framebuffer));
+            // Multisampled depth is resolved by taking one of the samples, which is fine for
+            // looking at it
+            gl.blit_framebuffer(
+                0,
+                0,
+                width,
+                height,
+                0,
+                0,
+                width,
+                height,
+                glow::DEPTH_BUFFER_BIT,
+                glow::NEAREST,
+            );
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(source));
+            if scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+        }
+    }
+
+    /// Draw the depth over the whole output framebuffer, leaving it bound
+    pub fn draw(&mut self, gl: &mut glow::Context, view: &DepthView) {
+        let target = match &self.target {
+            Some(target) => target,
+            None => return,
+        };
+        let (program, vao) = self.pass.get_or_insert_with(|| {
+            let program =
+                ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, DEPTH_VIEW_FRAGMENT_SRC).unwrap();
+            let vao = unsafe { gl.create_vertex_array().unwrap() };
+            resources::track(ResourceKind::VertexArray, vao, "Depth view vertex array");
+            (program, vao)
+        });
+        unsafe {
+            // Keep the handler's state, since it may have only set it up once
+            let state = SavedState::save(gl);
+            gl.active_texture(glow::TEXTURE0);
+            let texture = gl.get_parameter_i32(glow::TEXTURE_BINDING_2D) as u32;
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::CULL_FACE);
+            gl.disable(glow::BLEND);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.disable(glow::STENCIL_TEST);
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, self.output);
+            Rect::from_window_size(target.size).set_viewport(gl);
+            program.bind(gl);
+            program.set_uniform(gl, "depth", 0);
+            program.set_uniform(gl, "near", view.near);
+            program.set_uniform(gl, "far", view.far);
+            program.set_uniform(gl, "orthographic", view.orthographic as i32);
+            let reversed = target.depth_mode == DepthMode::ReverseZ;
+            program.set_uniform(gl, "reversed", reversed as i32);
+            let (start, end) = view.range();
+            program.set_uniform(gl, "range", [start, end]);
+            program.set_uniform(
+                gl,
+                "derivative",
+                (view.mode == DepthViewMode::Derivative) as i32,
+            );
+            program.set_uniform(gl, "derivativeScale", view.derivative_scale);
+            gl.bind_texture(glow::TEXTURE_2D, Some(target.depth_stencil));
+            gl.bind_vertex_array(Some(*vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture).filter(|&id| id != 0));
+            state.restore(gl);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, self.output);
+        }
+    }
+
+    /// Delete all of the GL objects
+    pub fn delete(mut self, gl: &mut glow::Context) {
+        if let Some(target) = self.target.take() {
+            target.delete(gl);
+        }
+        if let Some((mut program, vao)) = self.pass.take() {
+            program.delete(gl);
+            unsafe { gl.delete_vertex_array(vao) };
+            resources::untrack(ResourceKind::VertexArray, vao);
+        }
+    }
+}
@@ -0,0 +1,83 @@
+#version 330 core
+in vec2 texCoord;
+
+// The depth buffer that the handler drew, from 0 at the near plane to 1 at the far plane, or the
+// other way around when it is reversed
+uniform sampler2D depth;
+uniform float near;
+uniform float far;
+uniform bool orthographic;
+uniform bool reversed;
+// The linear depths that are shown from white to black
+uniform vec2 range;
+// Whether to show how much the depth bends between neighbouring pixels instead of the depth
+uniform bool derivative;
+// How bright the bends show up in the derivative view
+uniform float derivativeScale;
+
+out vec4 FragColor;
+
+// Pixels where nothing was drawn, which would otherwise look like the far end of the range
+const vec3 BACKGROUND = vec3(0.05, 0.05, 0.2);
+
+// The distance from the camera of a depth buffer value
+float linearDepth(float d) {
+    if (reversed) {
+        // Worked out straight from the reversed value, since turning it around first would lose
+        // the precision that reversing gains
+        if (orthographic) {
+            return far - d * (far - near);
+        }
+        return near * far / (near + d * (far - near));
+    }
+    if (orthographic) {
+        return near + d * (far - near);
+    }
+    float z = d * 2.0 - 1.0;
+    return 2.0 * near * far / (far + near - z * (far - near));
+}
+
+// Whether nothing was drawn at a depth buffer value, which is left at the far plane
+bool isBackground(float d) {
+    return reversed ? d <= 0.0 : d >= 1.0;
+}
+
+float fetchDepth(ivec2 pixel) {
+    pixel = clamp(pixel, ivec2(0), textureSize(depth, 0) - 1);
+    return texelFetch(depth, pixel, 0).r;
+}
+
+void main() {
+    ivec2 pixel = ivec2(gl_FragCoord.xy);
+    float d = fetchDepth(pixel);
+    if (isBackground(d)) {
+        FragColor = vec4(BACKGROUND, 1.0);
+        return;
+    }
+    float center = linearDepth(d);
+    float shade = 1.0 - clamp((center - range.x) / max(range.y - range.x, 1e-6), 0.0, 1.0);
+    if (!derivative) {
+        FragColor = vec4(vec3(shade), 1.0);
+        return;
+    }
+
+    // A flat or evenly sloped surface has no second derivative, while fighting surfaces flip
+    // between two depths from one pixel to the next, which shows up as bright speckles. Edges
+    // against the background are left out, since they would all light up.
+    float left = fetchDepth(pixel + ivec2(-1, 0));
+    float right = fetchDepth(pixel + ivec2(1, 0));
+    float down = fetchDepth(pixel + ivec2(0, -1));
+    float up = fetchDepth(pixel + ivec2(0, 1));
+    if (isBackground(left) || isBackground(right) || isBackground(down) || isBackground(up)) {
+        FragColor = vec4(vec3(shade * 0.25), 1.0);
+        return;
+    }
+    float bend = abs(linearDepth(left) + linearDepth(right) - 2.0 * center)
+        + abs(linearDepth(down) + linearDepth(up) - 2.0 * center);
+    float heat = clamp(bend / center * derivativeScale, 0.0, 1.0);
+    // Dim depth underneath, so the shapes can still be made out, with the bends from red to yellow
+    vec3 color = vec3(shade * 0.25);
+    color = mix(color, vec3(1.0, 0.0, 0.0), smoothstep(0.0, 0.5, heat));
+    color = mix(color, vec3(1.0, 1.0, 0.0), smoothstep(0.5, 1.0, heat));
+    FragColor = vec4(color, 1.0);
+}
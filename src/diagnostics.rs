@@ -0,0 +1,278 @@
+use std::{cell::RefCell, fmt::Debug, fmt::Write as _, path::Path};
+
+use cgmath::Matrix4;
+use glow::HasContext;
+
+use crate::{
+    render_graph::{PassTarget, RenderGraph},
+    resources::{self, format_bytes},
+    shader, texture_audit, AppContext, RenderHandler,
+};
+
+/// How many reports the loop writes by itself for GL errors and incomplete framebuffers, so that
+/// a problem that comes back every frame doesn't fill the disk with reports
+pub const MAX_AUTOMATIC_REPORTS: u32 = 3;
+
+thread_local! {
+    /// The problems found on this thread since the loop last looked, like incomplete framebuffers
+    static PROBLEMS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Record a problem that a frame report should be written for, like an incomplete framebuffer
+///
+/// The problem is printed as a warning right away, and the loop writes a frame report with it at
+/// the top after the handler's `draw`.
+pub fn report_problem<S: Into<String>>(message: S) {
+    let message = message.into();
+    eprintln!("Warning: {}", message);
+    PROBLEMS.with(|problems| problems.borrow_mut().push(message));
+}
+
+/// Clear the problems recorded with `report_problem`, returning them
+pub(crate) fn take_problems() -> Vec<String> {
+    PROBLEMS.with(|problems| std::mem::take(&mut *problems.borrow_mut()))
+}
+
+/// The name of a `glGetError` code, like `GL_INVALID_VALUE`
+pub fn error_name(error: u32) -> &'static str {
+    match error {
+        glow::NO_ERROR => "GL_NO_ERROR",
+        glow::INVALID_ENUM => "GL_INVALID_ENUM",
+        glow::INVALID_VALUE => "GL_INVALID_VALUE",
+        glow::INVALID_OPERATION => "GL_INVALID_OPERATION",
+        glow::INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+        glow::OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+        glow::STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+        glow::STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+        _ => "unknown error",
+    }
+}
+
+/// A text report of what went into a frame, to explain why it looks the way it does
+///
+/// The report is a list of titled sections. `dump_frame_report` fills in what the loop knows
+/// about, and handlers add their passes, cameras, and whatever else they have in
+/// `RenderHandler::describe`.
+#[derive(Clone, Debug, Default)]
+pub struct FrameReport {
+    sections: Vec<(String, String)>,
+}
+
+impl FrameReport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a section with some text under a title
+    pub fn section<S: Into<String>>(&mut self, title: &str, body: S) {
+        self.sections.push((title.to_owned(), body.into()));
+    }
+
+    /// Add a section with a matrix, one row to a line
+    pub fn matrix(&mut self, title: &str, matrix: Matrix4<f32>) {
+        let mut body = String::new();
+        for row in 0..4 {
+            writeln!(
+                body,
+                "{:>10.4} {:>10.4} {:>10.4} {:>10.4}",
+                matrix[0][row], matrix[1][row], matrix[2][row], matrix[3][row]
+            )
+            .unwrap();
+        }
+        self.section(title, body);
+    }
+
+    /// Add a section with the passes of a render graph in the order that they run, with their
+    /// targets, the textures they read and write, and their last GPU times
+    pub fn render_graph<P: Copy + Debug>(&mut self, title: &str, graph: &RenderGraph<P>) {
+        let mut body = String::new();
+        for (pass, (_, time)) in graph.passes().iter().zip(graph.pass_times()) {
+            let target = match pass.target {
+                PassTarget::Surface => "the surface".to_owned(),
+                PassTarget::Framebuffer(framebuffer) => format!("framebuffer {}", framebuffer),
+            };
+            writeln!(
+                body,
+                "{:?}: into {} at {:?}, reads {:?}, writes {:?}{}, GPU time {}",
+                pass.id,
+                target,
+                pass.size,
+                pass.reads,
+                pass.writes,
+                if pass.depth_prepass && graph.depth_prepass_enabled() {
+                    ", with a depth pre-pass"
+                } else {
+                    ""
+                },
+                match time {
+                    Some(time) => format!("{:.3} ms", time.as_secs_f64() * 1000.),
+                    None => "unknown".into(),
+                }
+            )
+            .unwrap();
+        }
+        for (pass, texture) in graph.resolves() {
+            writeln!(
+                body,
+                "Texture {} is resolved into {} before {:?}",
+                texture.texture, texture.resolved, pass
+            )
+            .unwrap();
+        }
+        self.section(title, body);
+    }
+
+    /// The sections in the order they were added, as `( title, body )`
+    pub fn sections(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.sections
+            .iter()
+            .map(|(title, body)| (title.as_str(), body.as_str()))
+    }
+
+    /// Write the report to a text file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        std::fs::write(path, self.to_string())
+    }
+}
+
+impl std::fmt::Display for FrameReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (title, body) in &self.sections {
+            writeln!(f, "== {} ==", title)?;
+            writeln!(f, "{}", body.trim_end())?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Put together a report of the frame that was just drawn: the problems that set it off, if
+/// any, the frame's stats, the render settings, the GL state the handler left, the context, the
+/// live GL objects, and then whatever the handler adds in `RenderHandler::describe`
+///
+/// The texture formats are only listed while the texture audit is on.
+pub fn dump_frame_report(
+    gl: &glow::Context,
+    ctx: &AppContext,
+    handler: &dyn RenderHandler,
+    problems: &[String],
+) -> FrameReport {
+    let mut report = FrameReport::new();
+    if !problems.is_empty() {
+        report.section("Problems", problems.join("\n"));
+    }
+
+    let timing = &ctx.timing;
+    let uniforms = shader::frame_uniform_stats();
+    let mut frame = String::new();
+    writeln!(
+        frame,
+        "Frame {} at {:.3} s",
+        timing.frame_count(),
+        timing.time()
+    )
+    .unwrap();
+    writeln!(
+        frame,
+        "Frame time {:.2} ms, {:.2} ms on average",
+        timing.delta() * 1000.,
+        timing.average_delta() * 1000.
+    )
+    .unwrap();
+    if let Some(present) = timing.last_present() {
+        writeln!(frame, "Last present {:?}", present).unwrap();
+    }
+    writeln!(
+        frame,
+        "Uniforms set {}, skipped as unchanged {}",
+        uniforms.issued, uniforms.skipped
+    )
+    .unwrap();
+    writeln!(
+        frame,
+        "Window {:?}, drawn at {:?}, hidpi factor {}",
+        ctx.window_size(),
+        ctx.render_size(),
+        ctx.hidpi_factor()
+    )
+    .unwrap();
+    report.section("Frame", frame);
+    report.section("Render settings", format!("{:#?}", ctx.render_settings));
+    report.section("GL state", gl_state(gl));
+
+    let features = ctx.features();
+    report.section(
+        "Context",
+        format!(
+            "{}\nMax texture size {}, max renderbuffer size {}",
+            ctx.context_report(),
+            features.max_texture_size,
+            features.max_renderbuffer_size
+        ),
+    );
+    let usage = resources::memory_usage();
+    report.section(
+        "GL objects",
+        format!(
+            "Textures {}, buffers {}, renderbuffers {}\n{}",
+            format_bytes(usage.textures),
+            format_bytes(usage.buffers),
+            format_bytes(usage.renderbuffers),
+            resources::live_report()
+        ),
+    );
+    if texture_audit::is_enabled() {
+        report.section("Textures", texture_audit::report());
+    }
+
+    handler.describe(&mut report);
+    report
+}
+
+/// The GL state that most often explains a wrong frame: what is bound, and what is enabled
+fn gl_state(gl: &glow::Context) -> String {
+    let mut state = String::new();
+    unsafe {
+        let mut viewport = [0; 4];
+        gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+        let mut scissor = [0; 4];
+        gl.get_parameter_i32_slice(glow::SCISSOR_BOX, &mut scissor);
+        writeln!(
+            state,
+            "Draw framebuffer {}, read framebuffer {}, program {}, vertex array {}",
+            gl.get_parameter_i32(glow::DRAW_FRAMEBUFFER_BINDING),
+            gl.get_parameter_i32(glow::READ_FRAMEBUFFER_BINDING),
+            gl.get_parameter_i32(glow::CURRENT_PROGRAM),
+            gl.get_parameter_i32(glow::VERTEX_ARRAY_BINDING)
+        )
+        .unwrap();
+        writeln!(state, "Viewport {:?}, scissor box {:?}", viewport, scissor).unwrap();
+        let capabilities = [
+            ("depth test", glow::DEPTH_TEST),
+            ("blend", glow::BLEND),
+            ("cull face", glow::CULL_FACE),
+            ("scissor test", glow::SCISSOR_TEST),
+            ("stencil test", glow::STENCIL_TEST),
+            ("polygon offset", glow::POLYGON_OFFSET_FILL),
+            ("framebuffer sRGB", glow::FRAMEBUFFER_SRGB),
+        ];
+        let enabled = capabilities
+            .iter()
+            .filter(|(_, capability)| gl.is_enabled(*capability))
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        writeln!(state, "Enabled: {}", enabled.join(", ")).unwrap();
+        writeln!(
+            state,
+            "Depth func {:#x}, front face {}",
+            gl.get_parameter_i32(glow::DEPTH_FUNC),
+            if gl.get_parameter_i32(glow::FRONT_FACE) as u32 == glow::CW {
+                "clockwise"
+            } else {
+                "counter-clockwise"
+            }
+        )
+        .unwrap();
+    }
+    state
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use cgmath::{InnerSpace, Matrix4};
+use glow::HasContext;
+use rayon::{
+    iter::{IntoParallelIterator, ParallelExtend},
+    slice::ParallelSliceMut,
+};
+
+use crate::{
+    handle::{self, Handle},
+    mesh::Mesh,
+    per_draw::{PerDrawBuffer, PerDrawData, PER_DRAW_BINDING, PER_DRAW_BLOCK},
+    shader::ShaderProgram,
+    texture::Texture,
+};
+
+/// How many bits of each handle's index go into a sort key. Handles with more indices than
+/// this still draw correctly, they just might not be grouped as well.
+const KEY_INDEX_BITS: u32 = 16;
+
+/// A mesh as a draw command refers to it, with what's needed to draw it without the `Mesh`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawMesh {
+    pub handle: Handle<Mesh>,
+    pub index_count: i32,
+    pub index_type: u32,
+}
+
+impl DrawMesh {
+    /// The mesh's draw info, or `None` if the current context isn't tracked
+    pub fn new(mesh: &Mesh) -> Option<Self> {
+        Some(Self {
+            handle: mesh.handle()?,
+            index_count: mesh.index_count,
+            index_type: mesh.index_type,
+        })
+    }
+}
+
+/// What a draw command is drawn with: a program, prepared with `prepare_program`, and a texture
+/// bound to texture unit 0
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DrawMaterial {
+    pub program: Handle<ShaderProgram>,
+    pub texture: Option<Handle<Texture>>,
+}
+
+impl DrawMaterial {
+    pub fn new(program: Handle<ShaderProgram>) -> Self {
+        Self {
+            program,
+            texture: None,
+        }
+    }
+
+    pub fn with_texture(mut self, texture: Handle<Texture>) -> Self {
+        self.texture = Some(texture);
+        self
+    }
+}
+
+/// One draw recorded into a `DrawList`
+///
+/// It is plain data that doesn't touch GL, so it can be made on any thread, e.g. by rayon workers
+/// walking the scene. The handles have to be made on the thread the context is current on first.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrawCommand {
+    /// The pass the command is drawn in, which `DrawList::submit` is called with
+    pub pass: u8,
+    pub material: DrawMaterial,
+    pub mesh: DrawMesh,
+    /// The model matrix and the rest of the draw's per-draw data
+    pub data: PerDrawData,
+}
+
+impl DrawCommand {
+    pub fn new(pass: u8, material: DrawMaterial, mesh: DrawMesh, data: PerDrawData) -> Self {
+        Self {
+            pass,
+            material,
+            mesh,
+            data,
+        }
+    }
+
+    /// The key commands are sorted by, which puts them in pass order with the draws that share a
+    /// program, then a texture, then a mesh next to each other, so the fewest binds are needed
+    ///
+    /// From the top bits down it is the pass, then the low bits of the program's, texture's and
+    /// mesh's handle indices. Draws without a texture come before the textured ones.
+    pub fn sort_key(&self) -> u64 {
+        let index = |index: u32| u64::from(index) & ((1 << KEY_INDEX_BITS) - 1);
+        let texture = self
+            .material
+            .texture
+            .map_or(0, |texture| index(texture.index().wrapping_add(1)));
+        (u64::from(self.pass) << (KEY_INDEX_BITS * 3))
+            | (index(self.material.program.index()) << (KEY_INDEX_BITS * 2))
+            | (texture << KEY_INDEX_BITS)
+            | index(self.mesh.handle.index())
+    }
+}
+
+/// Counts of what submitting a draw list did
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct DrawListStats {
+    /// The commands that were drawn
+    pub draws: u64,
+    pub program_binds: u64,
+    pub texture_binds: u64,
+    pub mesh_binds: u64,
+    /// The commands that were skipped because their program or mesh had been deleted
+    pub skipped: u64,
+    /// How long the last `sort` took
+    pub sort_time: Duration,
+    /// How long submitting took, summed over the passes
+    pub submit_time: Duration,
+}
+
+/// A list of draws recorded ahead of time and turned into GL calls later
+///
+/// Building the list is the part that scales with the scene, and since commands are plain data
+/// it can be done on worker threads and the lists appended together. `sort` then groups the
+/// commands by pass and state, and `submit` draws one pass's commands on the context thread,
+/// binding programs, textures, and meshes only when they change and writing each draw's data into
+/// a `PerDrawBuffer`.
+#[derive(Clone, Debug, Default)]
+pub struct DrawList {
+    commands: Vec<DrawCommand>,
+    /// Whether or not the commands are in sort key order
+    sorted: bool,
+    stats: DrawListStats,
+    /// Whether or not stale handles have been warned about, so it is only done once
+    warned: bool,
+}
+
+impl From<Vec<DrawCommand>> for DrawList {
+    fn from(commands: Vec<DrawCommand>) -> Self {
+        Self {
+            commands,
+            ..Default::default()
+        }
+    }
+}
+
+impl Extend<DrawCommand> for DrawList {
+    fn extend<I: IntoIterator<Item = DrawCommand>>(&mut self, commands: I) {
+        self.commands.extend(commands);
+        self.sorted = false;
+    }
+}
+
+/// Record commands made on rayon's threads, e.g. with `par_iter().map(..)` over the scene
+impl ParallelExtend<DrawCommand> for DrawList {
+    fn par_extend<I: IntoParallelIterator<Item = DrawCommand>>(&mut self, commands: I) {
+        self.commands.par_extend(commands);
+        self.sorted = false;
+    }
+}
+
+impl DrawList {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, command: DrawCommand) {
+        self.commands.push(command);
+        self.sorted = false;
+    }
+
+    /// Move the commands of another list, e.g. one built on another thread, into this one
+    pub fn append(&mut self, other: &mut DrawList) {
+        self.commands.append(&mut other.commands);
+        self.sorted = false;
+    }
+
+    /// Forget the commands, keeping the memory for the next frame's
+    pub fn clear(&mut self) {
+        self.commands.clear();
+        self.sorted = true;
+    }
+
+    pub fn commands(&self) -> &[DrawCommand] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// The counts of the last `sort` and of the passes submitted since
+    pub fn stats(&self) -> DrawListStats {
+        self.stats
+    }
+
+    /// Put the commands in sort key order, on rayon's threads
+    ///
+    /// This also starts the counts in `stats` over, so call it once a frame before submitting.
+    pub fn sort(&mut self) {
+        let start = Instant::now();
+        if !self.sorted {
+            self.commands
+                .par_sort_unstable_by_key(DrawCommand::sort_key);
+            self.sorted = true;
+        }
+        self.stats = DrawListStats {
+            sort_time: start.elapsed(),
+            ..Default::default()
+        };
+    }
+
+    /// Draw the commands of a pass, which have to have been sorted
+    ///
+    /// The programs should have been given their frame uniforms, like the view and projection,
+    /// beforehand, since they are bound by handle here. Texture handles are bound like
+    /// `handle::bind_texture` does, so stale ones panic in debug builds. Commands whose program or
+    /// mesh is stale are skipped with a warning. Leaves no vertex array bound.
+    pub fn submit(&mut self, gl: &mut glow::Context, per_draw: &mut PerDrawBuffer, pass: u8) {
+        if !self.sorted {
+            eprintln!("Warning: Submitted a draw list that wasn't sorted, sorting it first");
+            self.sort();
+        }
+        let start = Instant::now();
+
+        // The pass's commands are next to each other, since the pass is the top of the sort key
+        let first = self.commands.partition_point(|command| command.pass < pass);
+        let count = self.commands[first..].partition_point(|command| command.pass == pass);
+
+        let mut program = None;
+        let mut texture = None;
+        let mut mesh = None;
+        let mut skipped = 0;
+        unsafe { gl.active_texture(glow::TEXTURE0) };
+        for command in &self.commands[first..first + count] {
+            // Resolve and bind what changed since the last command
+            if program != Some(command.material.program) {
+                match command.material.program.resolve() {
+                    Ok(id) => unsafe {
+                        gl.use_program(Some(id));
+                        self.stats.program_binds += 1;
+                    },
+                    Err(_) => {
+                        skipped += 1;
+                        continue;
+                    }
+                }
+                program = Some(command.material.program);
+            }
+            if texture != command.material.texture {
+                if let Some(handle) = command.material.texture {
+                    handle::bind_texture(gl, handle);
+                    self.stats.texture_binds += 1;
+                }
+                texture = command.material.texture;
+            }
+            if mesh != Some(command.mesh.handle) {
+                match command.mesh.handle.resolve() {
+                    Ok(vao) => unsafe {
+                        gl.bind_vertex_array(Some(vao));
+                        self.stats.mesh_binds += 1;
+                    },
+                    Err(_) => {
+                        skipped += 1;
+                        continue;
+                    }
+                }
+                mesh = Some(command.mesh.handle);
+            }
+
+            per_draw.push(gl, &command.data);
+            unsafe {
+                gl.draw_elements(
+                    glow::TRIANGLES,
+                    command.mesh.index_count,
+                    command.mesh.index_type,
+                    0,
+                );
+            }
+            self.stats.draws += 1;
+        }
+        unsafe { gl.bind_vertex_array(None) };
+
+        if skipped > 0 && !self.warned {
+            eprintln!(
+                "Warning: Skipped {} draws in pass {} whose program or mesh has been deleted",
+                skipped, pass
+            );
+            self.warned = true;
+        }
+        self.stats.skipped += skipped;
+        self.stats.submit_time += start.elapsed();
+    }
+}
+
+/// The model matrices that things were drawn with last frame, for filling in
+/// `PerDrawData::previous_model`
+///
+/// Things are told apart by an id that the caller picks, like their index in the scene, and that
+/// has to stay the same from frame to frame. Things that weren't drawn last frame, or that jumped
+/// further than `teleport_distance` since, get their current matrix as the previous one, so that
+/// they don't smear across the screen on the frame they appear or teleport.
+#[derive(Clone, Debug)]
+pub struct TransformHistory {
+    /// How far something can move in one frame before it's taken to have teleported, in world
+    /// units
+    pub teleport_distance: f32,
+    /// The matrices of last frame
+    previous: HashMap<u64, Matrix4<f32>>,
+    /// The matrices recorded this frame, which become `previous` in `end_frame`
+    current: HashMap<u64, Matrix4<f32>>,
+}
+
+impl Default for TransformHistory {
+    fn default() -> Self {
+        Self {
+            teleport_distance: 5.,
+            previous: HashMap::new(),
+            current: HashMap::new(),
+        }
+    }
+}
+
+impl TransformHistory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record something's model matrix for this frame, and return the one it had last frame
+    pub fn previous_model(&mut self, id: u64, model: Matrix4<f32>) -> Matrix4<f32> {
+        self.current.insert(id, model);
+        match self.previous.get(&id) {
+            Some(previous) if (model.w - previous.w).magnitude() <= self.teleport_distance => {
+                *previous
+            }
+            _ => model,
+        }
+    }
+
+    /// Record something's model matrix for this frame, and return its per-draw data with the
+    /// previous one filled in
+    pub fn data(&mut self, id: u64, model: Matrix4<f32>) -> PerDrawData {
+        let previous_model = self.previous_model(id, model);
+        PerDrawData::new(model).with_previous_model(previous_model)
+    }
+
+    /// Forget where something was last frame, so it doesn't look like it moved, e.g. when it's
+    /// moved somewhere close by without passing through the places in between
+    pub fn teleport(&mut self, id: u64) {
+        self.previous.remove(&id);
+    }
+
+    /// Make this frame's matrices the previous ones, dropping the things that weren't drawn
+    pub fn end_frame(&mut self) {
+        std::mem::swap(&mut self.previous, &mut self.current);
+        self.current.clear();
+    }
+
+    /// Forget every matrix, e.g. when the scene is replaced
+    pub fn clear(&mut self) {
+        self.previous.clear();
+        self.current.clear();
+    }
+}
+
+/// Get a program ready to draw commands with, returning its handle
+///
+/// The program's `PerDraw` block is bound to `PER_DRAW_BINDING`, since `DrawList::submit` writes
+/// every draw's data into the per-draw buffer. Returns `None`, with a warning if it is the
+/// block that's missing, if the program doesn't declare the block or the current context isn't
+/// tracked.
+pub fn prepare_program(
+    gl: &mut glow::Context,
+    program: &mut ShaderProgram,
+) -> Option<Handle<ShaderProgram>> {
+    if !program.bind_uniform_block(gl, PER_DRAW_BLOCK, PER_DRAW_BINDING) {
+        eprintln!(
+            "Warning: Program {} can't draw commands, since it doesn't declare the `{}` block",
+            program.id, PER_DRAW_BLOCK
+        );
+        return None;
+    }
+    program.handle()
+}
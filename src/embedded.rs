@@ -0,0 +1,308 @@
+use std::os::raw::c_void;
+
+use glow::HasContext;
+use winit::{WindowEvent, WindowId};
+
+use crate::{
+    context_report::ContextReport,
+    debug_group::{self, PopDebugGroup},
+    debug_scope,
+    features::Features,
+    nested::SavedState,
+    resources,
+    shader::{self, ShaderTarget},
+    viewport::Rect,
+    workarounds::{self, Workarounds},
+    AppContext, Config, HandlerFactory, RenderHandler,
+};
+
+/// The signature of `glGetFloatv`, which glow doesn't expose
+type GetFloat = extern "system" fn(pname: u32, data: *mut f32);
+
+/// How many texture units get their `TEXTURE_2D` binding back after a frame. Handlers rarely use
+/// more, and asking for every unit the driver has would be slow.
+const SAVED_TEXTURE_UNITS: u32 = 8;
+
+/// The GL state of the host that the embedded handler may change, on top of what nested renderers
+/// save
+struct HostState {
+    saved: SavedState,
+    viewport: [i32; 4],
+    clear_color: Option<[f32; 4]>,
+    color_mask: [i32; 4],
+    array_buffer: u32,
+    unpack_alignment: i32,
+    textures: [u32; SAVED_TEXTURE_UNITS as usize],
+}
+
+impl HostState {
+    unsafe fn save(gl: &glow::Context, get_float: Option<GetFloat>) -> Self {
+        let mut viewport = [0; 4];
+        gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport);
+        let mut color_mask = [0; 4];
+        gl.get_parameter_i32_slice(glow::COLOR_WRITEMASK, &mut color_mask);
+        let clear_color = get_float.map(|get_float| {
+            let mut color = [0.; 4];
+            get_float(glow::COLOR_CLEAR_VALUE, color.as_mut_ptr());
+            color
+        });
+
+        let active_texture = gl.get_parameter_i32(glow::ACTIVE_TEXTURE) as u32;
+        let mut textures = [0; SAVED_TEXTURE_UNITS as usize];
+        for (unit, texture) in textures.iter_mut().enumerate() {
+            gl.active_texture(glow::TEXTURE0 + unit as u32);
+            *texture = gl.get_parameter_i32(glow::TEXTURE_BINDING_2D) as u32;
+        }
+        gl.active_texture(active_texture);
+
+        Self {
+            saved: SavedState::save(gl),
+            viewport,
+            clear_color,
+            color_mask,
+            array_buffer: gl.get_parameter_i32(glow::ARRAY_BUFFER_BINDING) as u32,
+            unpack_alignment: gl.get_parameter_i32(glow::UNPACK_ALIGNMENT),
+            textures,
+        }
+    }
+
+    unsafe fn restore(&self, gl: &glow::Context) {
+        let object = |name: u32| if name == 0 { None } else { Some(name) };
+        for (unit, &texture) in self.textures.iter().enumerate() {
+            gl.active_texture(glow::TEXTURE0 + unit as u32);
+            gl.bind_texture(glow::TEXTURE_2D, object(texture));
+        }
+        // Sets the active texture unit back too
+        self.saved.restore(gl);
+        let [x, y, width, height] = self.viewport;
+        gl.viewport(x, y, width, height);
+        if let Some([r, g, b, a]) = self.clear_color {
+            gl.clear_color(r, g, b, a);
+        }
+        let [r, g, b, a] = self.color_mask.map(|mask| mask != 0);
+        gl.color_mask(r, g, b, a);
+        gl.bind_buffer(glow::ARRAY_BUFFER, object(self.array_buffer));
+        gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, self.unpack_alignment);
+    }
+}
+
+/// Runs a `RenderHandler` in a GL context that another application owns, like a Qt
+/// `QOpenGLWidget`, without any of the windowing
+///
+/// The host keeps its own event loop and calls `draw` whenever it wants a frame, with the
+/// framebuffer to draw into and its size, while its context is current. The handler gets the same
+/// timing, resource tracking, debug groups, and driver workarounds as it would in a window of its
+/// own, but no console, overlays, anti-aliasing, virtual resolution, screenshots, or presenting,
+/// which are the host's business. Its render settings are followed for clearing the framebuffer.
+///
+/// The host's GL state is saved before the handler runs and restored afterwards: the framebuffer
+/// bindings, program, vertex array, array buffer, viewport, clear color, color and depth masks,
+/// front face, blend factors, unpack alignment, the depth, blend, cull, scissor, and stencil
+/// capabilities, and the `TEXTURE_2D` bindings of the first `SAVED_TEXTURE_UNITS` units. Anything
+/// else the handler changes, like uniform buffer bindings, is left as it is.
+pub struct EmbeddedRenderer {
+    gl: glow::Context,
+    /// The handler, or `None` once it has exited
+    handler: Option<Box<dyn RenderHandler>>,
+    ctx: AppContext,
+    resource_key: u64,
+    pop_debug_group: Option<PopDebugGroup>,
+    get_float: Option<GetFloat>,
+    close_requested: bool,
+}
+
+impl EmbeddedRenderer {
+    /// Load the GL functions with `loader` and create the handler with `factory`
+    ///
+    /// `framebuffer` is the one the host will have the handler draw into, or 0 for the default
+    /// framebuffer, and `size` its size in pixels. They are only used to describe the context to
+    /// the handler in `init`, since `draw` is given them every frame.
+    ///
+    /// # Safety
+    ///
+    /// The host's GL context must be current, and `loader` must return the functions of that
+    /// context, or null for the ones it doesn't have.
+    pub unsafe fn new<F: Fn(&str) -> *const c_void>(
+        loader: F,
+        framebuffer: u32,
+        size: (u32, u32),
+        config: Config,
+        factory: &HandlerFactory,
+    ) -> Self {
+        let gl = glow::Context::from_loader_function(|symbol| loader(symbol));
+        let features = Features::query_with_loader(&gl, &loader);
+        let pop_debug_group = debug_group::load_pop_fn_with_loader(&features, &loader);
+        let get_float = {
+            let ptr = loader("glGetFloatv");
+            if ptr.is_null() {
+                None
+            } else {
+                Some(std::mem::transmute::<*const c_void, GetFloat>(ptr))
+            }
+        };
+
+        // Describing the framebuffer binds it, so keep the host's bindings
+        let state = HostState::save(&gl, get_float);
+        let context_report = ContextReport::query_with_loader(&gl, &loader, framebuffer);
+        state.restore(&gl);
+        eprintln!("Embedded renderer: {}", context_report);
+        let workarounds = Workarounds::for_driver(
+            "Embedded renderer",
+            &context_report.vendor,
+            &context_report.renderer,
+            &context_report.version,
+            &config.workarounds,
+        );
+
+        let resource_key = resources::start_context();
+        let mut ctx = AppContext::new(
+            // There is no window, and handlers only compare ids with each other
+            WindowId::dummy(),
+            (size.0.max(1), size.1.max(1)),
+            1.,
+            context_report,
+            features,
+            workarounds,
+            config,
+        );
+        ctx.set_surface_framebuffer(if framebuffer == 0 {
+            None
+        } else {
+            Some(framebuffer)
+        });
+
+        let mut renderer = Self {
+            gl,
+            handler: None,
+            ctx,
+            resource_key,
+            pop_debug_group,
+            get_float,
+            close_requested: false,
+        };
+        renderer.make_current();
+        let state = HostState::save(&renderer.gl, get_float);
+        renderer.handler = Some(factory(&mut renderer.gl, &mut renderer.ctx));
+        state.restore(&renderer.gl);
+        renderer
+    }
+
+    /// The handler's context, e.g. for pausing its time or changing its clear color
+    pub fn context(&self) -> &AppContext {
+        &self.ctx
+    }
+
+    pub fn context_mut(&mut self) -> &mut AppContext {
+        &mut self.ctx
+    }
+
+    /// Pass a winit window event on to the handler's input, for hosts that use winit
+    ///
+    /// Other hosts can skip this, and the handler sees no input.
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        self.ctx
+            .input
+            .handle_window_event(event, self.ctx.hidpi_factor());
+    }
+
+    /// Whether the handler has asked to close with `AppContext::request_close`, which the host can
+    /// act on however it likes
+    pub fn close_requested(&self) -> bool {
+        self.close_requested
+    }
+
+    /// Draw a frame of the handler into `framebuffer`, or the default framebuffer if it is 0, which
+    /// is `size` pixels big
+    ///
+    /// The host's GL context must be current. A change of size is seen by the handler as its
+    /// window being resized.
+    pub fn draw(&mut self, framebuffer: u32, size: (u32, u32)) {
+        if self.handler.is_none() {
+            return;
+        }
+        let size = (size.0.max(1), size.1.max(1));
+        let framebuffer = if framebuffer == 0 {
+            None
+        } else {
+            Some(framebuffer)
+        };
+        self.ctx.set_window_size(size);
+        self.ctx.set_surface_framebuffer(framebuffer);
+        self.make_current();
+
+        // Start the frame like the loop starts a window's frame
+        self.ctx.timing.begin_frame();
+        shader::reset_frame_uniform_stats();
+        self.ctx.arena.reset();
+
+        let (gl, ctx) = (&mut self.gl, &mut self.ctx);
+        let handler = self.handler.as_mut().unwrap();
+        unsafe {
+            let state = HostState::save(gl, self.get_float);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+            Rect::from_window_size(size).set_viewport(gl);
+            let settings = &ctx.render_settings;
+            let clear_mask = settings.clear_mask();
+            if clear_mask != 0 {
+                if let Some(color) = settings.clear_color {
+                    let [r, g, b, a] = color.to_srgb();
+                    gl.clear_color(r, g, b, a);
+                }
+                // The host may have turned off depth or color writes
+                gl.depth_mask(true);
+                gl.color_mask(true, true, true, true);
+                gl.disable(glow::SCISSOR_TEST);
+                gl.clear(clear_mask);
+            }
+
+            debug_scope!(gl, "Embedded renderer", { handler.draw(gl, ctx) });
+
+            state.restore(gl);
+        }
+
+        self.ctx.viewports.end_frame(size, size);
+        self.ctx.clear_shader_reload_request();
+        self.ctx.input.end_frame();
+        if self.ctx.take_close_request() {
+            self.close_requested = true;
+        }
+        self.ctx.take_redraw_request();
+        if self.ctx.take_screenshot_request().is_some() {
+            eprintln!("Warning: An embedded renderer can't take screenshots, the host has to");
+        }
+        if self.ctx.take_clipboard_request().is_some() {
+            eprintln!("Warning: An embedded renderer can't copy frames, the host has to");
+        }
+    }
+
+    /// Let the handler clean up, and report the GL objects it never deleted if resource tracking
+    /// is enabled
+    ///
+    /// The host's GL context must be current. Nothing is drawn after this.
+    pub fn exit(&mut self) {
+        let mut handler = match self.handler.take() {
+            Some(handler) => handler,
+            None => return,
+        };
+        self.make_current();
+        unsafe {
+            let state = HostState::save(&self.gl, self.get_float);
+            handler.exit(&mut self.gl, &mut self.ctx);
+            state.restore(&self.gl);
+        }
+        if let Some(tracker) = resources::finish_context(self.resource_key) {
+            if resources::ENABLED && !tracker.is_empty() {
+                eprintln!("Embedded renderer: {}", tracker.report());
+            }
+        }
+    }
+
+    /// Point the thread's resource tracking, debug groups, workarounds, and shader target at this
+    /// renderer
+    fn make_current(&self) {
+        resources::make_current(self.resource_key);
+        debug_group::make_current(self.pop_debug_group);
+        workarounds::make_current(self.ctx.workarounds());
+        shader::make_current_target(ShaderTarget::for_features(self.ctx.features()));
+    }
+}
@@ -0,0 +1,166 @@
+use std::path::Path;
+
+use crate::{
+    color::Color,
+    heightmap::Heightmap,
+    mesh::MeshData,
+    primitives,
+    procedural::{self, NoiseParams},
+    texture::ImageData,
+};
+
+/// How big the fallback images are, and how wide their border is, in pixels
+pub const IMAGE_SIZE: u32 = 64;
+pub const BORDER_WIDTH: u32 = 2;
+/// The color of the border around fallback images, which is meant to stand out in any scene
+pub const BORDER_COLOR: Color = Color::MAGENTA;
+
+/// How many heights go across each side of the fallback heightmap
+const HEIGHTMAP_SIZE: u32 = 128;
+
+/// The repo's assets, built into the binary with the `embedded-assets` feature, by file name
+#[cfg(feature = "embedded-assets")]
+const EMBEDDED: &[(&str, &[u8])] = &[
+    (
+        "awesomeface.png",
+        include_bytes!("../assets/awesomeface.png"),
+    ),
+    ("heightmap.png", include_bytes!("../assets/heightmap.png")),
+    ("wall.jpg", include_bytes!("../assets/wall.jpg")),
+];
+#[cfg(not(feature = "embedded-assets"))]
+const EMBEDDED: &[(&str, &[u8])] = &[];
+
+/// The built-in copy of a missing asset with the same file name, if there is one
+pub fn embedded(path: &Path) -> Option<&'static [u8]> {
+    let name = path.file_name()?.to_str()?;
+    EMBEDDED
+        .iter()
+        .find(|(embedded, _)| *embedded == name)
+        .map(|(_, bytes)| *bytes)
+}
+
+/// A stand-in for a missing image
+///
+/// With the `embedded-assets` feature the repo's own images are built in and used as they are.
+/// Anything else gets a generated image with a magenta border, so it can't be mistaken for the
+/// real one: images named like a face get a smiley face, and everything else a checkerboard.
+pub fn image(path: &Path) -> ImageData {
+    warn(path, "image");
+    if let Some(image) = embedded(path).and_then(|bytes| ImageData::from_memory(bytes).ok()) {
+        return image;
+    }
+    let face = file_stem(path).contains("face");
+    let mut image = if face {
+        smiley(IMAGE_SIZE)
+    } else {
+        procedural::checkerboard(
+            IMAGE_SIZE,
+            IMAGE_SIZE,
+            IMAGE_SIZE / 8,
+            Color::GRAY,
+            Color::from_hex("#404040").unwrap(),
+        )
+    };
+    add_border(&mut image);
+    image
+}
+
+/// A stand-in for a missing heightmap, which is the built-in copy with the `embedded-assets`
+/// feature, or gentle hills made of noise
+pub fn heightmap(path: &Path) -> Heightmap {
+    warn(path, "heightmap");
+    if let Some(heightmap) = embedded(path).and_then(|bytes| Heightmap::from_memory(bytes).ok()) {
+        return heightmap;
+    }
+    let params = NoiseParams {
+        frequency: 4,
+        ..Default::default()
+    };
+    let size = HEIGHTMAP_SIZE;
+    Heightmap {
+        width: size,
+        height: size,
+        heights: (0..size)
+            .flat_map(|y| (0..size).map(move |x| (x, y)))
+            .map(|(x, y)| procedural::noise_value(x, y, size, size, &params))
+            .collect(),
+    }
+}
+
+/// A stand-in for a missing model, which is a unit cube
+pub fn model(path: &Path) -> MeshData {
+    warn(path, "model");
+    primitives::cuboid(1., 1., 1.)
+}
+
+/// Whether every pixel on the edges of an image is the fallback border color, which is how the
+/// fallbacks can be told apart from real images
+pub fn has_border(image: &ImageData) -> bool {
+    let border = BORDER_COLOR.to_srgb_u8();
+    let channels = if image.format == glow::RGB { 3 } else { 4 };
+    let (width, height) = (image.width, image.height);
+    if width == 0 || height == 0 || image.pixels.len() < (width * height * channels) as usize {
+        return false;
+    }
+    let is_border = |x: u32, y: u32| {
+        let start = ((y * width + x) * channels) as usize;
+        image.pixels[start..start + channels as usize] == border[..channels as usize]
+    };
+    (0..width).all(|x| is_border(x, 0) && is_border(x, height - 1))
+        && (0..height).all(|y| is_border(0, y) && is_border(width - 1, y))
+}
+
+fn warn(path: &Path, kind: &str) {
+    eprintln!(
+        "Warning: The {} {} is missing, using a built-in {} instead",
+        kind,
+        path.display(),
+        if embedded(path).is_some() {
+            "copy"
+        } else {
+            "fallback"
+        }
+    );
+}
+
+fn file_stem(path: &Path) -> String {
+    path.file_stem()
+        .map(|stem| stem.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+/// A yellow smiley face on a transparent background, with the size of the image
+fn smiley(size: u32) -> ImageData {
+    procedural::from_fn(size, size, |x, y| {
+        // -1 to 1 across the image, with up being positive
+        let u = (x as f32 + 0.5) / size as f32 * 2. - 1.;
+        let v = (y as f32 + 0.5) / size as f32 * 2. - 1.;
+        let distance = (u * u + v * v).sqrt();
+        if distance > 0.8 {
+            return Color::TRANSPARENT;
+        }
+        let eye = |center: f32| ((u - center).powi(2) + (v - 0.25).powi(2)).sqrt() < 0.12;
+        let mouth = v < 0. && (distance - 0.45).abs() < 0.07 && u.abs() < 0.4;
+        if eye(-0.3) || eye(0.3) || mouth {
+            Color::BLACK
+        } else {
+            Color::YELLOW
+        }
+    })
+}
+
+/// Paint the fallback border around the edges of an RGBA image
+fn add_border(image: &mut ImageData) {
+    let border = BORDER_COLOR.to_srgb_u8();
+    let (width, height) = (image.width, image.height);
+    for y in 0..height {
+        for x in 0..width {
+            let edge = x.min(width - 1 - x).min(y).min(height - 1 - y);
+            if edge < BORDER_WIDTH {
+                let start = ((y * width + x) * 4) as usize;
+                image.pixels[start..start + 4].copy_from_slice(&border);
+            }
+        }
+    }
+}
@@ -0,0 +1,490 @@
+use std::{collections::HashSet, os::raw::c_void};
+
+use glow::HasContext;
+use surfman::{Context, Device};
+
+/// The signature of `glGetFloatv`, which glow doesn't expose
+type GetFloat = extern "system" fn(pname: u32, data: *mut f32);
+/// The signatures of the program binary functions, which glow doesn't expose either
+type GetProgramBinary = extern "system" fn(
+    program: u32,
+    buffer_size: i32,
+    length: *mut i32,
+    binary_format: *mut u32,
+    binary: *mut c_void,
+);
+type ProgramBinary =
+    extern "system" fn(program: u32, binary_format: u32, binary: *const c_void, length: i32);
+type ProgramParameter = extern "system" fn(program: u32, pname: u32, value: i32);
+type GetProgram = extern "system" fn(program: u32, pname: u32, params: *mut i32);
+/// The signature of `glMemoryBarrier`, which compute shaders need and glow doesn't expose
+type MemoryBarrier = extern "system" fn(barriers: u32);
+/// The signature of `glClipControl`, which reverse-Z depth needs and glow doesn't expose
+type ClipControl = extern "system" fn(origin: u32, depth: u32);
+/// A function that looks up a GL function by name, returning null if the context doesn't have it
+pub(crate) type Loader<'a> = &'a dyn Fn(&str) -> *const c_void;
+
+/// The functions for saving and loading linked programs ( GL 4.1 or `GL_ARB_get_program_binary` )
+#[derive(Clone, Copy)]
+pub struct ProgramBinaryFns {
+    get_program_binary: GetProgramBinary,
+    program_binary: ProgramBinary,
+    program_parameter: ProgramParameter,
+    get_program: GetProgram,
+}
+
+impl ProgramBinaryFns {
+    /// Load the functions, returning `None` if any of them are missing
+    fn load(loader: Loader) -> Option<Self> {
+        let load = |symbol| {
+            let ptr = loader(symbol);
+            if ptr.is_null() {
+                None
+            } else {
+                Some(ptr)
+            }
+        };
+        unsafe {
+            Some(Self {
+                get_program_binary: std::mem::transmute::<*const c_void, GetProgramBinary>(load(
+                    "glGetProgramBinary",
+                )?),
+                program_binary: std::mem::transmute::<*const c_void, ProgramBinary>(load(
+                    "glProgramBinary",
+                )?),
+                program_parameter: std::mem::transmute::<*const c_void, ProgramParameter>(load(
+                    "glProgramParameteri",
+                )?),
+                get_program: std::mem::transmute::<*const c_void, GetProgram>(load(
+                    "glGetProgramiv",
+                )?),
+            })
+        }
+    }
+
+    /// Ask the driver to keep the binary of a program around. This has to be called before the
+    /// program is linked.
+    pub fn set_retrievable(&self, program: u32) {
+        (self.program_parameter)(program, glow::PROGRAM_BINARY_RETRIEVABLE_HINT, 1);
+    }
+
+    /// The binary of a linked program and its format, or `None` if the driver doesn't have one
+    pub fn get(&self, program: u32) -> Option<(u32, Vec<u8>)> {
+        let mut length = 0;
+        (self.get_program)(program, glow::PROGRAM_BINARY_LENGTH, &mut length);
+        if length <= 0 {
+            return None;
+        }
+        let mut binary = vec![0u8; length as usize];
+        let mut format = 0;
+        let mut written = 0;
+        (self.get_program_binary)(
+            program,
+            length,
+            &mut written,
+            &mut format,
+            binary.as_mut_ptr() as *mut c_void,
+        );
+        binary.truncate(written.max(0) as usize);
+        if binary.is_empty() {
+            None
+        } else {
+            Some((format, binary))
+        }
+    }
+
+    /// Load a binary into a program. The driver may reject it, e.g. after a driver update, so
+    /// check the link status afterwards.
+    pub fn load_binary(&self, program: u32, format: u32, binary: &[u8]) {
+        (self.program_binary)(
+            program,
+            format,
+            binary.as_ptr() as *const c_void,
+            binary.len() as i32,
+        );
+    }
+}
+
+impl std::fmt::Debug for ProgramBinaryFns {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ProgramBinaryFns")
+    }
+}
+
+impl PartialEq for ProgramBinaryFns {
+    fn eq(&self, other: &Self) -> bool {
+        self.get_program_binary as usize == other.get_program_binary as usize
+    }
+}
+
+/// The functions needed to run compute shaders that glow doesn't expose ( GL 4.3 or
+/// `GL_ARB_compute_shader` )
+#[derive(Clone, Copy)]
+pub struct ComputeFns {
+    memory_barrier: MemoryBarrier,
+}
+
+impl ComputeFns {
+    /// Load the functions, returning `None` if any of them are missing
+    fn load(loader: Loader) -> Option<Self> {
+        let memory_barrier = loader("glMemoryBarrier");
+        if memory_barrier.is_null() {
+            return None;
+        }
+        Some(Self {
+            memory_barrier: unsafe {
+                std::mem::transmute::<*const c_void, MemoryBarrier>(memory_barrier)
+            },
+        })
+    }
+
+    /// Make the writes of earlier compute dispatches visible to the kinds of reads in `barriers`,
+    /// e.g. `glow::VERTEX_ATTRIB_ARRAY_BARRIER_BIT` to draw from a buffer a compute shader wrote
+    pub fn memory_barrier(&self, barriers: u32) {
+        (self.memory_barrier)(barriers);
+    }
+}
+
+impl std::fmt::Debug for ComputeFns {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ComputeFns")
+    }
+}
+
+impl PartialEq for ComputeFns {
+    fn eq(&self, other: &Self) -> bool {
+        self.memory_barrier as usize == other.memory_barrier as usize
+    }
+}
+
+/// The function for changing how clip space maps to the window, which glow doesn't expose ( GL 4.5
+/// or `GL_ARB_clip_control` )
+#[derive(Clone, Copy)]
+pub struct ClipControlFns {
+    clip_control: ClipControl,
+}
+
+impl ClipControlFns {
+    /// Load the function, returning `None` if it is missing
+    fn load(loader: Loader) -> Option<Self> {
+        let clip_control = loader("glClipControl");
+        if clip_control.is_null() {
+            return None;
+        }
+        Some(Self {
+            clip_control: unsafe {
+                std::mem::transmute::<*const c_void, ClipControl>(clip_control)
+            },
+        })
+    }
+
+    /// Set where the window's origin is and the range of clip space depth, e.g.
+    /// `glow::LOWER_LEFT` and `glow::ZERO_TO_ONE`
+    pub fn clip_control(&self, origin: u32, depth: u32) {
+        (self.clip_control)(origin, depth);
+    }
+}
+
+impl std::fmt::Debug for ClipControlFns {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ClipControlFns")
+    }
+}
+
+impl PartialEq for ClipControlFns {
+    fn eq(&self, other: &Self) -> bool {
+        self.clip_control as usize == other.clip_control as usize
+    }
+}
+
+/// The optional GL features that are available in a context
+///
+/// This is queried once when the context is created, so helpers and handlers can cheaply check it
+/// to decide between the fast path and a fallback. Because the fields are public, a handler can
+/// also pretend features are missing, e.g. with `Features::default()`, to try out the fallbacks.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Features {
+    /// The GL version of the context as `( major, minor )`
+    pub gl_version: (u32, u32),
+    /// Whether the context is OpenGL ES, whose shaders are GLSL ES, see `shader::ShaderTarget`
+    pub es: bool,
+    /// Immutable texture storage with `glTexStorage*` ( GL 4.2 or `GL_ARB_texture_storage` )
+    pub texture_storage: bool,
+    /// The maximum anisotropic filtering level, or `None` if anisotropic filtering isn't supported
+    /// ( GL 4.6, `GL_ARB_texture_filter_anisotropic`, or `GL_EXT_texture_filter_anisotropic` )
+    pub anisotropy_max: Option<f32>,
+    /// Debug output callbacks, object labels, and debug groups ( GL 4.3 or `GL_KHR_debug` )
+    pub debug_output: bool,
+    /// Immutable buffer storage with `glBufferStorage` ( GL 4.4 or `GL_ARB_buffer_storage` )
+    pub buffer_storage: bool,
+    /// Timer queries with `GL_TIME_ELAPSED` ( GL 3.3 or `GL_ARB_timer_query` )
+    pub timer_query: bool,
+    /// Saving and loading linked programs, or `None` if the context can't or the driver has no
+    /// binary formats ( GL 4.1 or `GL_ARB_get_program_binary` )
+    pub program_binary: Option<ProgramBinaryFns>,
+    /// Compute shaders and shader storage buffers, or `None` if the context doesn't support them
+    /// ( GL 4.3, or `GL_ARB_compute_shader` with `GL_ARB_shader_storage_buffer_object` )
+    pub compute: Option<ComputeFns>,
+    /// Changing the range of clip space depth to 0 to 1, which reverse-Z depth is most precise
+    /// with, or `None` if the context can't ( GL 4.5 or `GL_ARB_clip_control` )
+    pub clip_control: Option<ClipControlFns>,
+    /// The largest width or height of a texture ( `GL_MAX_TEXTURE_SIZE` ), or 0 if unknown
+    pub max_texture_size: u32,
+    /// The largest width or height of a renderbuffer ( `GL_MAX_RENDERBUFFER_SIZE` ), or 0 if
+    /// unknown
+    pub max_renderbuffer_size: u32,
+    /// All of the extensions supported by the context
+    pub extensions: HashSet<String>,
+}
+
+impl Features {
+    /// Query the features of the given context, which must be current
+    pub(crate) fn query(gl: &glow::Context, device: &Device, context: &Context) -> Self {
+        Self::query_with_loader(gl, &|symbol| device.get_proc_address(context, symbol))
+    }
+
+    /// Query the features of the current context, loading the functions glow doesn't expose with
+    /// `loader`, e.g. for a context that something other than surfman created
+    pub(crate) fn query_with_loader(gl: &glow::Context, loader: Loader) -> Self {
+        let (gl_version, es, extensions) = unsafe {
+            (
+                (
+                    gl.get_parameter_i32(glow::MAJOR_VERSION) as u32,
+                    gl.get_parameter_i32(glow::MINOR_VERSION) as u32,
+                ),
+                // ES contexts say so at the start of their version string
+                gl.get_parameter_string(glow::VERSION)
+                    .starts_with("OpenGL ES"),
+                (0..gl.get_parameter_i32(glow::NUM_EXTENSIONS) as u32)
+                    .map(|i| gl.get_parameter_indexed_string(glow::EXTENSIONS, i))
+                    .collect::<HashSet<_>>(),
+            )
+        };
+
+        let mut features = Self::from_extensions(gl_version, es, extensions);
+        unsafe {
+            features.max_texture_size = gl.get_parameter_i32(glow::MAX_TEXTURE_SIZE).max(0) as u32;
+            features.max_renderbuffer_size =
+                gl.get_parameter_i32(glow::MAX_RENDERBUFFER_SIZE).max(0) as u32;
+        }
+
+        // The anisotropy limit is a float, which glow can't query, so load `glGetFloatv` ourselves
+        let get_float = loader("glGetFloatv");
+        if features.has_anisotropy() && !get_float.is_null() {
+            let get_float = unsafe { std::mem::transmute::<*const c_void, GetFloat>(get_float) };
+            let mut anisotropy_max = 0.;
+            get_float(glow::MAX_TEXTURE_MAX_ANISOTROPY, &mut anisotropy_max);
+            features.anisotropy_max = Some(anisotropy_max);
+        }
+
+        // Some drivers support the functions without having any formats to save to
+        if features.has_program_binary()
+            && unsafe { gl.get_parameter_i32(glow::NUM_PROGRAM_BINARY_FORMATS) } > 0
+        {
+            features.program_binary = ProgramBinaryFns::load(loader);
+        }
+
+        if features.has_compute() {
+            features.compute = ComputeFns::load(loader);
+        }
+
+        if features.has_clip_control() {
+            features.clip_control = ClipControlFns::load(loader);
+        }
+
+        features
+    }
+
+    /// The features that follow from a GL version and extensions alone, without the functions
+    /// and limits that have to be loaded or queried from the context
+    fn from_extensions(gl_version: (u32, u32), es: bool, extensions: HashSet<String>) -> Self {
+        let mut features = Self {
+            gl_version,
+            es,
+            extensions,
+            ..Default::default()
+        };
+        features.texture_storage =
+            features.has_version(4, 2) || features.has_extension("GL_ARB_texture_storage");
+        features.debug_output =
+            features.has_version(4, 3) || features.has_extension("GL_KHR_debug");
+        features.buffer_storage =
+            features.has_version(4, 4) || features.has_extension("GL_ARB_buffer_storage");
+        features.timer_query =
+            features.has_version(3, 3) || features.has_extension("GL_ARB_timer_query");
+        features
+    }
+
+    /// Whether or not the context can filter anisotropically, so the limit is worth querying
+    fn has_anisotropy(&self) -> bool {
+        self.has_version(4, 6)
+            || self.has_extension("GL_ARB_texture_filter_anisotropic")
+            || self.has_extension("GL_EXT_texture_filter_anisotropic")
+    }
+
+    /// Whether or not the context has the program binary functions
+    fn has_program_binary(&self) -> bool {
+        self.has_version(4, 1) || self.has_extension("GL_ARB_get_program_binary")
+    }
+
+    /// Whether or not the context has compute shaders and the storage buffers they write to
+    fn has_compute(&self) -> bool {
+        self.has_version(4, 3)
+            || (self.has_extension("GL_ARB_compute_shader")
+                && self.has_extension("GL_ARB_shader_storage_buffer_object"))
+    }
+
+    /// Whether or not the context has `glClipControl`
+    fn has_clip_control(&self) -> bool {
+        self.has_version(4, 5) || self.has_extension("GL_ARB_clip_control")
+    }
+
+    /// The largest width or height that a framebuffer with both texture and renderbuffer
+    /// attachments can have, or `None` if the limits weren't queried
+    pub fn max_framebuffer_size(&self) -> Option<u32> {
+        match (self.max_texture_size, self.max_renderbuffer_size) {
+            (0, 0) => None,
+            (0, size) | (size, 0) => Some(size),
+            (texture, renderbuffer) => Some(texture.min(renderbuffer)),
+        }
+    }
+
+    /// Clamp a framebuffer size to one that the context can make: at least 1x1, and at most
+    /// `max_framebuffer_size` on each side
+    ///
+    /// Zero-sized framebuffers and ones past the limits fail with `GL_INVALID_VALUE`, so sizes
+    /// that come from outside, like the window's, go through this before anything is made with
+    /// them.
+    pub fn clamp_framebuffer_size(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        let max = self.max_framebuffer_size().unwrap_or(u32::MAX).max(1);
+        (width.clamp(1, max), height.clamp(1, max))
+    }
+
+    /// Whether or not the context is at least the given GL version
+    pub fn has_version(&self, major: u32, minor: u32) -> bool {
+        self.gl_version >= (major, minor)
+    }
+
+    /// Whether or not the context supports the given extension, e.g. `"GL_KHR_debug"`
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.contains(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        depth_mode::{DepthMode, DepthSetup},
+        instance_buffer::{BufferUsage, InstanceBuffer},
+        shader::ShaderTarget,
+        vertex::{VertexFormat, VertexLayout},
+    };
+
+    fn features(gl_version: (u32, u32), es: bool, extensions: &[&str]) -> Features {
+        Features::from_extensions(
+            gl_version,
+            es,
+            extensions.iter().map(|name| name.to_string()).collect(),
+        )
+    }
+
+    /// Whether a dynamic instance buffer cycles through fenced buffers instead of orphaning one
+    fn fences_instances(features: &Features) -> bool {
+        let layout = VertexLayout::new(&[(0, VertexFormat::Float32x4)]);
+        InstanceBuffer::new(features, layout, BufferUsage::Dynamic).is_fenced()
+    }
+
+    #[test]
+    fn gl_3_3_without_extensions() {
+        let features = features((3, 3), false, &[]);
+        assert!(features.timer_query);
+        assert!(!features.texture_storage);
+        assert!(!features.debug_output);
+        assert!(!features.buffer_storage);
+        assert!(!features.has_anisotropy());
+        assert!(!features.has_program_binary());
+        assert!(!features.has_compute());
+        assert!(!features.has_clip_control());
+
+        assert_eq!(
+            ShaderTarget::for_features(&features),
+            ShaderTarget::BASELINE
+        );
+        assert_eq!(
+            ShaderTarget::supported(&features),
+            vec![ShaderTarget::BASELINE]
+        );
+        // Reverse-Z falls back to GL's -1 to 1 depth, and instances to an orphaned buffer
+        assert!(!DepthSetup::new(DepthMode::ReverseZ, &features).zero_to_one);
+        assert!(!fences_instances(&features));
+    }
+
+    #[test]
+    fn gl_3_3_with_extensions() {
+        let features = features(
+            (3, 3),
+            false,
+            &[
+                "GL_ARB_texture_storage",
+                "GL_KHR_debug",
+                "GL_ARB_buffer_storage",
+                "GL_EXT_texture_filter_anisotropic",
+                "GL_ARB_clip_control",
+                "GL_ARB_compute_shader",
+            ],
+        );
+        assert!(features.texture_storage);
+        assert!(features.debug_output);
+        assert!(features.buffer_storage);
+        assert!(features.has_anisotropy());
+        assert!(features.has_clip_control());
+        // Compute shaders are no use without storage buffers to write to
+        assert!(!features.has_compute());
+        assert!(fences_instances(&features));
+
+        let mut features = features;
+        features
+            .extensions
+            .insert("GL_ARB_shader_storage_buffer_object".into());
+        assert!(features.has_compute());
+    }
+
+    #[test]
+    fn gles_3_0() {
+        let features = features((3, 0), true, &[]);
+        assert!(!features.timer_query);
+        assert!(!features.texture_storage);
+        assert!(!features.debug_output);
+        assert!(!features.buffer_storage);
+        assert!(!features.has_compute());
+        assert!(!features.has_clip_control());
+
+        assert_eq!(ShaderTarget::for_features(&features), ShaderTarget::Es(300));
+        assert_eq!(
+            ShaderTarget::supported(&features),
+            vec![ShaderTarget::Es(300)]
+        );
+        assert!(!DepthSetup::new(DepthMode::ReverseZ, &features).zero_to_one);
+        assert!(!fences_instances(&features));
+    }
+
+    #[test]
+    fn gl_4_6_has_everything() {
+        let features = features((4, 6), false, &[]);
+        assert!(features.texture_storage);
+        assert!(features.debug_output);
+        assert!(features.buffer_storage);
+        assert!(features.timer_query);
+        assert!(features.has_anisotropy());
+        assert!(features.has_program_binary());
+        assert!(features.has_compute());
+        assert!(features.has_clip_control());
+        assert_eq!(
+            ShaderTarget::for_features(&features),
+            ShaderTarget::Core(460)
+        );
+        assert!(fences_instances(&features));
+    }
+}
@@ -0,0 +1,189 @@
+use std::{
+    alloc::{self, Layout},
+    cell::{Cell, UnsafeCell},
+    mem,
+    ptr::NonNull,
+};
+
+/// The space the arena starts with
+pub const DEFAULT_CAPACITY: usize = 1024 * 1024;
+
+/// Every allocation starts on a multiple of this, which covers every type up to `u128` and SIMD
+/// vectors
+const ALIGN: usize = 16;
+
+/// How much of the arena a frame has used
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameArenaStats {
+    /// The bytes used so far this frame
+    pub used: usize,
+    /// The most bytes any frame has used
+    pub peak: usize,
+    /// The bytes the arena can hand out before it has to grow
+    pub capacity: usize,
+}
+
+/// A block of memory that the arena hands out pieces of
+struct Chunk {
+    ptr: NonNull<u8>,
+    size: usize,
+}
+
+impl Chunk {
+    fn new(size: usize) -> Self {
+        let layout = Layout::from_size_align(size.max(ALIGN), ALIGN).unwrap();
+        let ptr = NonNull::new(unsafe { alloc::alloc(layout) })
+            .unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Self {
+            ptr,
+            size: layout.size(),
+        }
+    }
+}
+
+impl Drop for Chunk {
+    fn drop(&mut self) {
+        unsafe {
+            alloc::dealloc(
+                self.ptr.as_ptr(),
+                Layout::from_size_align(self.size, ALIGN).unwrap(),
+            )
+        };
+    }
+}
+
+/// A bump allocator for data that only lives for one frame, like vertices staged before they are
+/// uploaded
+///
+/// Allocating just moves a pointer forward, and the loop resets the whole arena at the start of
+/// every frame. Allocations borrow the arena, and resetting needs it mutably, so the borrow
+/// checker makes sure nothing allocated in one frame is used in the next.
+///
+/// If a frame needs more than the arena has, it grows by adding another block and logs it. The
+/// blocks are merged into one big enough for the whole frame at the next reset, so a steady
+/// workload stops growing after the first frame.
+pub struct FrameArena {
+    /// The blocks of memory, which never move while the arena is borrowed
+    chunks: UnsafeCell<Vec<Chunk>>,
+    /// The bytes used in the last block
+    offset: Cell<usize>,
+    /// The bytes used in the blocks before the last one
+    used_before: Cell<usize>,
+    peak: usize,
+}
+
+impl Default for FrameArena {
+    fn default() -> Self {
+        Self::with_capacity(DEFAULT_CAPACITY)
+    }
+}
+
+impl std::fmt::Debug for FrameArena {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FrameArena")
+            .field("stats", &self.stats())
+            .finish()
+    }
+}
+
+impl FrameArena {
+    /// An arena that can hand out `capacity` bytes per frame before it has to grow
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            chunks: UnsafeCell::new(vec![Chunk::new(capacity)]),
+            offset: Cell::new(0),
+            used_before: Cell::new(0),
+            peak: 0,
+        }
+    }
+
+    /// A slice of `len` default values that lasts until the end of the frame
+    ///
+    /// Every allocation is a different part of the arena, so handing out mutable slices from a
+    /// shared borrow is sound.
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice<T: Copy + Default>(&self, len: usize) -> &mut [T] {
+        let ptr = self.alloc_raw::<T>(len);
+        unsafe {
+            for i in 0..len {
+                ptr.as_ptr().add(i).write(T::default());
+            }
+            std::slice::from_raw_parts_mut(ptr.as_ptr(), len)
+        }
+    }
+
+    /// A copy of a slice that lasts until the end of the frame
+    #[allow(clippy::mut_from_ref)]
+    pub fn alloc_slice_copy<T: Copy>(&self, values: &[T]) -> &mut [T] {
+        let ptr = self.alloc_raw::<T>(values.len());
+        unsafe {
+            std::ptr::copy_nonoverlapping(values.as_ptr(), ptr.as_ptr(), values.len());
+            std::slice::from_raw_parts_mut(ptr.as_ptr(), values.len())
+        }
+    }
+
+    /// Room for `len` values of `T`, which the caller has to initialize
+    fn alloc_raw<T>(&self, len: usize) -> NonNull<T> {
+        assert!(
+            mem::align_of::<T>() <= ALIGN,
+            "Frame arena allocations can't be aligned to more than {} bytes",
+            ALIGN
+        );
+        let size = mem::size_of::<T>()
+            .checked_mul(len)
+            .expect("Frame arena allocation is too large");
+        if size == 0 {
+            return NonNull::dangling();
+        }
+        // Keep the next allocation aligned too
+        let size = (size + ALIGN - 1) & !(ALIGN - 1);
+
+        let capacity = self.capacity();
+        // Only the list of blocks is changed here, never the memory that was already handed out
+        let chunks = unsafe { &mut *self.chunks.get() };
+        let last_size = chunks.last().map_or(0, |chunk| chunk.size);
+        if self.offset.get() + size > last_size {
+            let chunk_size = size.max(last_size).next_power_of_two();
+            eprintln!(
+                "Warning: The frame arena ran out of its {} bytes and grew by {} bytes",
+                capacity, chunk_size
+            );
+            self.used_before
+                .set(self.used_before.get() + self.offset.get());
+            self.offset.set(0);
+            chunks.push(Chunk::new(chunk_size));
+        }
+
+        let chunk = chunks.last().unwrap();
+        let ptr = unsafe { chunk.ptr.as_ptr().add(self.offset.get()) };
+        self.offset.set(self.offset.get() + size);
+        NonNull::new(ptr as *mut T).unwrap()
+    }
+
+    /// The bytes the arena has in all of its blocks
+    fn capacity(&self) -> usize {
+        let chunks = unsafe { &*self.chunks.get() };
+        chunks.iter().map(|chunk| chunk.size).sum()
+    }
+
+    pub fn stats(&self) -> FrameArenaStats {
+        let used = self.used_before.get() + self.offset.get();
+        FrameArenaStats {
+            used,
+            peak: self.peak.max(used),
+            capacity: self.capacity(),
+        }
+    }
+
+    /// Free everything allocated this frame, merging the blocks into one if the arena grew
+    pub(crate) fn reset(&mut self) {
+        let stats = self.stats();
+        self.peak = stats.peak;
+        let chunks = self.chunks.get_mut();
+        if chunks.len() > 1 {
+            *chunks = vec![Chunk::new(stats.capacity.next_power_of_two())];
+        }
+        self.offset.set(0);
+        self.used_before.set(0);
+    }
+}
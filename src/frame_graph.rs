@@ -0,0 +1,454 @@
+use std::{
+    collections::VecDeque,
+    ffi::c_void,
+    time::{Duration, Instant},
+};
+
+use cgmath::{Matrix4, Point3};
+use glow::HasContext;
+use surfman::{Context, Device};
+
+use crate::{
+    color::Color,
+    debug_draw::DebugDraw,
+    debug_text::{DebugText, LINE_HEIGHT},
+    features::Features,
+    frame_arena::FrameArena,
+    latency::{LatencyMode, LatencyStats},
+    theme::Theme,
+    viewport::Rect,
+};
+
+/// How many frames the graph shows, one pixel wide each at a UI scale of 1
+pub const FRAME_GRAPH_FRAMES: usize = 300;
+
+/// The height of the graph in pixels at a UI scale of 1
+const GRAPH_HEIGHT: f32 = 100.;
+/// The frame time at the top of the graph, in milliseconds
+const GRAPH_MAX_MS: f32 = 50.;
+/// The frame times of 60 and 30 frames a second, which get guide lines
+const GUIDE_MS: [f32; 2] = [1000. / 60., 1000. / 30.];
+/// The gap between the graph and the edges of the window, in pixels at a UI scale of 1
+const MARGIN: f32 = 8.;
+/// How many pairs of timestamp queries can be waiting for their results at once
+const QUERY_PAIRS: usize = 4;
+
+/// The signature of `glQueryCounter`, which glow doesn't expose
+pub type QueryCounter = extern "system" fn(id: u32, target: u32);
+
+/// Load `glQueryCounter` for timing the GPU, if the context has timer queries
+///
+/// Timestamps are used instead of a `TIME_ELAPSED` query so that handlers can still time their
+/// own draws, since only one `TIME_ELAPSED` query can be running at a time.
+pub(crate) fn load_query_counter(
+    features: &Features,
+    device: &Device,
+    context: &Context,
+) -> Option<QueryCounter> {
+    if !features.timer_query {
+        return None;
+    }
+    let ptr = device.get_proc_address(context, "glQueryCounter");
+    if ptr.is_null() {
+        None
+    } else {
+        Some(unsafe { std::mem::transmute::<*const c_void, QueryCounter>(ptr) })
+    }
+}
+
+/// Something the loop did in a frame that can make it take longer than the others
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FrameEvent {
+    Screenshot,
+    Resize,
+    ShaderReload,
+}
+
+impl FrameEvent {
+    const ALL: [FrameEvent; 3] = [
+        FrameEvent::Screenshot,
+        FrameEvent::Resize,
+        FrameEvent::ShaderReload,
+    ];
+
+    /// The color of the event's marker over the graph
+    fn color(self) -> Color {
+        match self {
+            FrameEvent::Screenshot => Color::CORNFLOWER_BLUE,
+            FrameEvent::Resize => Color::ORANGE,
+            FrameEvent::ShaderReload => Color::WHITE,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            FrameEvent::Screenshot => "screenshot",
+            FrameEvent::Resize => "resize",
+            FrameEvent::ShaderReload => "shader reload",
+        }
+    }
+}
+
+/// The times of one frame, in milliseconds
+#[derive(Clone, Copy, Debug)]
+struct FrameSample {
+    frame: u64,
+    /// The whole frame, from the end of the last frame to the end of this one
+    frame_ms: f32,
+    /// The handler's `draw` on the CPU
+    cpu_ms: f32,
+    /// The handler's `draw` on the GPU, once the timestamps are in
+    gpu_ms: Option<f32>,
+    event: Option<FrameEvent>,
+}
+
+/// Timestamps taken around a frame's `draw`, waiting for the GPU to get to them
+#[derive(Clone, Copy, Debug)]
+struct PendingQueries {
+    frame: u64,
+    start: u32,
+    end: u32,
+}
+
+/// The scrolling frame time graph in the corner of a window ( toggled with F2 )
+///
+/// Each frame is a bar as tall as the whole frame took, colored green up to 60 frames a second,
+/// yellow up to 30, and red past that, with the time the handler's `draw` took on the CPU and GPU
+/// drawn over the bars as lines. Frames where the loop took a screenshot, resized the surface, or
+/// reloaded shaders get a marker over their bar, so the spikes they cause can be told apart.
+///
+/// The CPU and GPU times only cover the handler, so drawing the graph doesn't show up in them. It
+/// does count towards the frame times, so how long it took is shown next to the graph. Under the
+/// key is the estimated latency from input to the GPU finishing a frame, with the latency mode.
+#[derive(Debug)]
+pub struct FrameGraph {
+    pub visible: bool,
+    samples: VecDeque<FrameSample>,
+    last_frame_end: Option<Instant>,
+    draw_start: Option<Instant>,
+    cpu_ms: f32,
+    event: Option<FrameEvent>,
+    query_counter: Option<QueryCounter>,
+    free_queries: Vec<(u32, u32)>,
+    current_queries: Option<(u32, u32)>,
+    pending: VecDeque<PendingQueries>,
+    lines: Option<DebugDraw>,
+    /// How long the graph took to draw on the CPU the last time
+    graph_time: Duration,
+    /// The latency of the finished frames, if any have finished, and the loop's latency mode
+    latency: Option<LatencyStats>,
+    latency_mode: LatencyMode,
+}
+
+impl FrameGraph {
+    pub(crate) fn new(query_counter: Option<QueryCounter>) -> Self {
+        Self {
+            visible: false,
+            samples: VecDeque::with_capacity(FRAME_GRAPH_FRAMES),
+            last_frame_end: None,
+            draw_start: None,
+            cpu_ms: 0.,
+            event: None,
+            query_counter,
+            free_queries: Vec::new(),
+            current_queries: None,
+            pending: VecDeque::new(),
+            lines: None,
+            graph_time: Duration::from_secs(0),
+            latency: None,
+            latency_mode: LatencyMode::Throughput,
+        }
+    }
+
+    /// Set the latency shown under the key
+    pub(crate) fn set_latency(&mut self, latency: Option<LatencyStats>, mode: LatencyMode) {
+        self.latency = latency;
+        self.latency_mode = mode;
+    }
+
+    /// Start timing the handler's `draw`
+    pub(crate) fn begin_draw(&mut self, gl: &mut glow::Context) {
+        self.draw_start = Some(Instant::now());
+
+        // Only time the GPU while the graph is showing, and when there is a free pair of queries
+        let query_counter = match self.query_counter {
+            Some(query_counter) if self.visible => query_counter,
+            _ => return,
+        };
+        if self.free_queries.is_empty() && self.pending.len() + 1 < QUERY_PAIRS {
+            let queries = unsafe { (gl.create_query().unwrap(), gl.create_query().unwrap()) };
+            self.free_queries.push(queries);
+        }
+        if let Some((start, end)) = self.free_queries.pop() {
+            query_counter(start, glow::TIMESTAMP);
+            self.current_queries = Some((start, end));
+        }
+    }
+
+    /// Stop timing the handler's `draw`
+    pub(crate) fn end_draw(&mut self, frame: u64) {
+        if let Some(start) = self.draw_start.take() {
+            self.cpu_ms = start.elapsed().as_secs_f32() * 1000.;
+        }
+        if let (Some(query_counter), Some((start, end))) =
+            (self.query_counter, self.current_queries.take())
+        {
+            query_counter(end, glow::TIMESTAMP);
+            self.pending.push_back(PendingQueries { frame, start, end });
+        }
+    }
+
+    /// Note that the loop did something this frame that may make it slow
+    pub(crate) fn mark(&mut self, event: FrameEvent) {
+        self.event = Some(event);
+    }
+
+    /// Add the frame to the graph, and collect the GPU times that have come in
+    pub(crate) fn end_frame(&mut self, gl: &mut glow::Context, frame: u64) {
+        let now = Instant::now();
+        let frame_ms = self
+            .last_frame_end
+            .map_or(0., |last| (now - last).as_secs_f32() * 1000.);
+        self.last_frame_end = Some(now);
+        if self.samples.len() == FRAME_GRAPH_FRAMES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(FrameSample {
+            frame,
+            frame_ms,
+            cpu_ms: self.cpu_ms,
+            gpu_ms: None,
+            event: self.event.take(),
+        });
+
+        while let Some(queries) = self.pending.front().copied() {
+            let nanos = unsafe {
+                if gl.get_query_parameter_u32(queries.end, glow::QUERY_RESULT_AVAILABLE) == 0 {
+                    break;
+                }
+                // Only the low 32 bits of the timestamps come through, which is still enough for
+                // the difference between them
+                gl.get_query_parameter_u32(queries.end, glow::QUERY_RESULT)
+                    .wrapping_sub(gl.get_query_parameter_u32(queries.start, glow::QUERY_RESULT))
+            };
+            self.pending.pop_front();
+            self.free_queries.push((queries.start, queries.end));
+            if let Some(sample) = self.samples.iter_mut().find(|s| s.frame == queries.frame) {
+                sample.gpu_ms = Some(nanos as f32 / 1_000_000.);
+            }
+        }
+    }
+
+    /// The rect in window pixels, with the origin in the bottom-left corner, that the graph covers
+    /// in a window of the given size, or `None` if it isn't visible
+    pub(crate) fn rect(&self, window_size: (u32, u32), ui_scale: f32) -> Option<Rect> {
+        if !self.visible {
+            return None;
+        }
+        let (left, top, width, height) = Self::panel(window_size, ui_scale);
+        let (left, top) = (left.floor() as i32, top.floor() as i32);
+        Some(Rect::new(
+            left,
+            window_size.1 as i32 - (top + height.ceil() as i32 + 1),
+            width.ceil() as i32 + 1,
+            height.ceil() as i32 + 1,
+        ))
+    }
+
+    /// Where the graph's panel goes in a window of the given size, as its left, top, width, and
+    /// height in pixels from the top-left corner
+    fn panel(window_size: (u32, u32), ui_scale: f32) -> (f32, f32, f32, f32) {
+        let graph_width = FRAME_GRAPH_FRAMES as f32 * ui_scale;
+        let text_scale = ui_scale.round().max(1.) as u32;
+        let line_height = (LINE_HEIGHT * text_scale) as f32;
+        let left = window_size.0 as f32 - graph_width - MARGIN * ui_scale;
+        let bottom = MARGIN * ui_scale + line_height * 3. + GRAPH_HEIGHT * ui_scale;
+        let padding = 4. * ui_scale;
+        (
+            left - padding,
+            MARGIN * ui_scale - padding,
+            graph_width + padding * 2.,
+            bottom - MARGIN * ui_scale + padding * 2.,
+        )
+    }
+
+    /// Draw the graph in the top right corner of the window, if it is visible
+    ///
+    /// The window framebuffer should be bound, with the viewport covering the window.
+    pub(crate) fn draw(
+        &mut self,
+        gl: &mut glow::Context,
+        text: &mut DebugText,
+        arena: &FrameArena,
+        window_size: (u32, u32),
+        ui_scale: f32,
+        theme: &Theme,
+    ) {
+        if !self.visible {
+            return;
+        }
+        let start = Instant::now();
+        let lines = self.lines.get_or_insert_with(|| DebugDraw::new(gl));
+
+        let (width, height) = (window_size.0 as f32, window_size.1 as f32);
+        let (graph_width, graph_height) = (
+            FRAME_GRAPH_FRAMES as f32 * ui_scale,
+            GRAPH_HEIGHT * ui_scale,
+        );
+        let text_scale = ui_scale.round().max(1.) as u32;
+        let line_height = (LINE_HEIGHT * text_scale) as f32;
+        let left = width - graph_width - MARGIN * ui_scale;
+        let top = MARGIN * ui_scale + line_height * 3.;
+        let bottom = top + graph_height;
+        // The height of a time in milliseconds, clamped to the top of the graph
+        let y = |ms: f32| bottom - (ms / GRAPH_MAX_MS).min(1.) * graph_height;
+        let point = |x: f32, y: f32| Point3::new(x, y, 0.);
+
+        // The panel behind everything, with the latest times, a key, and the latency above the
+        // graph
+        let (panel_left, panel_top, panel_width, panel_height) = Self::panel(window_size, ui_scale);
+        text.rect(
+            panel_left,
+            panel_top,
+            panel_width,
+            panel_height,
+            theme.panel_color,
+        );
+        let latest = self
+            .samples
+            .iter()
+            .rev()
+            .find(|sample| sample.gpu_ms.is_some());
+        let last = self.samples.back();
+        text.text(
+            left,
+            MARGIN * ui_scale,
+            text_scale,
+            theme.text_color,
+            &format!(
+                "frame {:.1} ms  cpu {:.2} ms  gpu {}  graph {:.2} ms",
+                last.map_or(0., |sample| sample.frame_ms),
+                last.map_or(0., |sample| sample.cpu_ms),
+                match latest.and_then(|sample| sample.gpu_ms) {
+                    Some(ms) => format!("{:.2} ms", ms),
+                    None => "-".into(),
+                },
+                self.graph_time.as_secs_f64() * 1000.
+            ),
+        );
+        let mut x = left;
+        for (name, color) in [("cpu", Color::CYAN), ("gpu", Color::MAGENTA)]
+            .iter()
+            .copied()
+            .chain(
+                FrameEvent::ALL
+                    .iter()
+                    .map(|event| (event.name(), event.color())),
+            )
+        {
+            let (end, _) = text.text(x, MARGIN * ui_scale + line_height, text_scale, color, name);
+            x = end + 8. * ui_scale;
+        }
+        text.text(
+            left,
+            MARGIN * ui_scale + line_height * 2.,
+            text_scale,
+            theme.text_color,
+            &match self.latency {
+                Some(latency) => format!(
+                    "latency {:.1} ms  {} queued  {}",
+                    latency.input_to_gpu, latency.frames_in_flight, self.latency_mode
+                ),
+                None => format!("latency -  {}", self.latency_mode),
+            },
+        );
+
+        // A bar for each frame, with the oldest frame on the left
+        let first = left + graph_width - self.samples.len() as f32 * ui_scale;
+        let column = |index: usize| first + (index as f32 + 0.5) * ui_scale;
+        for (index, sample) in self.samples.iter().enumerate() {
+            let color = if sample.frame_ms <= GUIDE_MS[0] {
+                Color::GREEN
+            } else if sample.frame_ms <= GUIDE_MS[1] {
+                Color::YELLOW
+            } else {
+                Color::RED
+            };
+            let x = column(index);
+            lines.line(
+                point(x, bottom),
+                point(x, y(sample.frame_ms)),
+                color.with_alpha(0.7),
+            );
+            if let Some(event) = sample.event {
+                lines.line(point(x, top), point(x, top + 6. * ui_scale), event.color());
+            }
+        }
+
+        // The guide lines, and the CPU and GPU times over the bars
+        for &ms in GUIDE_MS.iter() {
+            lines.line(
+                point(left, y(ms)),
+                point(left + graph_width, y(ms)),
+                theme.text_color.with_alpha(0.5),
+            );
+        }
+        for (index, (from, to)) in self
+            .samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .enumerate()
+        {
+            let (x0, x1) = (column(index), column(index + 1));
+            lines.line(
+                point(x0, y(from.cpu_ms)),
+                point(x1, y(to.cpu_ms)),
+                Color::CYAN,
+            );
+            if let (Some(from), Some(to)) = (from.gpu_ms, to.gpu_ms) {
+                lines.line(point(x0, y(from)), point(x1, y(to)), Color::MAGENTA);
+            }
+        }
+
+        // Pixels from the top left, like the text
+        let projection: Matrix4<f32> = cgmath::ortho(0., width, height, 0., -1., 1.);
+        unsafe {
+            let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+            let blend = gl.is_enabled(glow::BLEND);
+            gl.disable(glow::DEPTH_TEST);
+            text.draw(gl, arena, window_size);
+            gl.enable(glow::BLEND);
+            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+            lines.draw(gl, arena, projection);
+            if depth_test {
+                gl.enable(glow::DEPTH_TEST);
+            }
+            if !blend {
+                gl.disable(glow::BLEND);
+            }
+        }
+        self.graph_time = start.elapsed();
+    }
+
+    /// Delete the GL objects
+    pub(crate) fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe {
+            for (start, end) in self
+                .free_queries
+                .drain(..)
+                .chain(self.current_queries.take())
+                .chain(
+                    self.pending
+                        .drain(..)
+                        .map(|queries| (queries.start, queries.end)),
+                )
+            {
+                gl.delete_query(start);
+                gl.delete_query(end);
+            }
+        }
+        if let Some(mut lines) = self.lines.take() {
+            lines.delete(gl);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use cgmath::{EuclideanSpace, Matrix4, Point3, Vector3, Vector4};
+
+/// An axis aligned bounding box
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+impl Aabb {
+    /// The box with the given center and half of its size along each axis
+    pub fn from_center(center: Point3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self {
+            min: (center - half_extents).into(),
+            max: (center + half_extents).into(),
+        }
+    }
+
+    /// The point in the middle of the box
+    pub fn center(&self) -> Point3<f32> {
+        Point3::from(self.min).midpoint(Point3::from(self.max))
+    }
+
+    /// Half of the size of the box along each axis
+    pub fn half_extents(&self) -> Vector3<f32> {
+        (Point3::from(self.max) - Point3::from(self.min)) / 2.
+    }
+
+    /// The box moved by an offset
+    pub fn translated(&self, offset: Vector3<f32>) -> Self {
+        Self {
+            min: (Point3::from(self.min) + offset).into(),
+            max: (Point3::from(self.max) + offset).into(),
+        }
+    }
+
+    /// The eight corners of the box, with bit 0, 1, and 2 of the index picking the max of x, y,
+    /// and z
+    pub fn corners(&self) -> [[f32; 3]; 8] {
+        let mut corners = [[0.; 3]; 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            for (axis, value) in corner.iter_mut().enumerate() {
+                *value = if i & (1 << axis) == 0 {
+                    self.min[axis]
+                } else {
+                    self.max[axis]
+                };
+            }
+        }
+        corners
+    }
+
+    /// The smallest box containing all of the points, or `None` if there are no points
+    pub fn from_points<'a, I: IntoIterator<Item = &'a [f32; 3]>>(points: I) -> Option<Self> {
+        let mut points = points.into_iter();
+        let first = *points.next()?;
+        Some(points.fold(
+            Self {
+                min: first,
+                max: first,
+            },
+            |aabb, point| {
+                let mut aabb = aabb;
+                for ((min, max), value) in aabb.min.iter_mut().zip(&mut aabb.max).zip(point) {
+                    *min = min.min(*value);
+                    *max = max.max(*value);
+                }
+                aabb
+            },
+        ))
+    }
+}
+
+/// The six planes bounding the volume that a camera can see
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Frustum {
+    /// The left, right, bottom, top, near, and far planes as `( a, b, c, d )` where points with
+    /// `a * x + b * y + c * z + d >= 0` are on the inside of the plane
+    pub planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Get the frustum of a combined projection and view matrix
+    pub fn from_view_projection(m: &Matrix4<f32>) -> Self {
+        // The planes are sums and differences of the rows of the matrix. cgmath matrices are
+        // column major, so put the rows together from the columns.
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+
+        Self {
+            planes: [w + x, w - x, w + y, w - y, w + z, w - z],
+        }
+    }
+
+    /// Whether or not any part of the box might be visible
+    ///
+    /// This can return true for some boxes near the corners of the frustum that aren't actually
+    /// visible, which is fine for culling.
+    pub fn intersects_aabb(&self, aabb: &Aabb) -> bool {
+        self.planes.iter().all(|plane| {
+            // Check the corner of the box that is furthest inside of the plane. If even that is
+            // outside, the whole box is.
+            let pick = |direction: f32, axis: usize| {
+                if direction >= 0. {
+                    aabb.max[axis]
+                } else {
+                    aabb.min[axis]
+                }
+            };
+            let corner = [pick(plane.x, 0), pick(plane.y, 1), pick(plane.z, 2)];
+            plane.x * corner[0] + plane.y * corner[1] + plane.z * corner[2] + plane.w >= 0.
+        })
+    }
+}
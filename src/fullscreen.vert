@@ -0,0 +1,11 @@
+#version 330 core
+
+out vec2 texCoord;
+
+void main() {
+    // One triangle that covers the whole screen, made from the vertex index so that no vertex
+    // buffer is needed
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    texCoord = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
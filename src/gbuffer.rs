@@ -0,0 +1,401 @@
+use cgmath::{Matrix4, SquareMatrix};
+use glow::HasContext;
+
+use crate::{
+    depth_mode,
+    msaa_resolve::{MultisampledTexture, ResolveKind},
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+};
+
+/// The GLSL for reading a G-buffer, which declares the uniforms set by `GBuffer::bind` and
+/// `gbufferPosition`, `gbufferNormal`, and `gbufferAlbedo` functions, along with the
+/// `encodeNormal` and `decodeNormal` functions for writing and reading packed normals. Add it to a
+/// shader with `shader::include_chunk` and compile it with `GBuffer::defines`.
+///
+/// For a multisampled G-buffer the functions fetch the sample `gSample`, a global that shaders
+/// shading every sample loop over from 0 to `gbufferSamples()`.
+pub const GBUFFER_CHUNK: &str = include_str!("gbuffer/gbuffer.glsl");
+
+/// How a G-buffer stores the surfaces on screen
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GBufferLayout {
+    /// View space positions and normals in RGBA16F, and albedos in RGBA8, which is 20 bytes a
+    /// pixel before the depth buffer
+    #[default]
+    Fat,
+    /// Octahedral normals in RG16F and albedos in RGBA8, with positions worked out from the depth
+    /// buffer, which is 8 bytes a pixel before the depth buffer
+    Packed,
+}
+
+impl GBufferLayout {
+    /// The layout with the given name, `fat` or `packed`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "fat" => Some(GBufferLayout::Fat),
+            "packed" => Some(GBufferLayout::Packed),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            GBufferLayout::Fat => "fat",
+            GBufferLayout::Packed => "packed",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            GBufferLayout::Fat => GBufferLayout::Packed,
+            GBufferLayout::Packed => GBufferLayout::Fat,
+        }
+    }
+
+    /// The defines to compile shaders that include `GBUFFER_CHUNK` with
+    pub fn defines(self) -> &'static [&'static str] {
+        match self {
+            GBufferLayout::Fat => &[],
+            GBufferLayout::Packed => &["GBUFFER_PACKED"],
+        }
+    }
+}
+
+/// The view space positions, view space normals, and colors of everything on screen, which
+/// lighting and post processing passes read instead of drawing the scene again
+///
+/// The color attachments depend on the layout. Shaders that draw into a G-buffer write their
+/// outputs in this order, compiled with `GBufferLayout::defines`:
+///
+/// - `Fat`: the position with an alpha of 1, the normal, and the albedo
+/// - `Packed`: the normal from `encodeNormal`, and the albedo
+///
+/// The depth buffer is a texture in both layouts, so that it can be sampled.
+///
+/// A multisampled G-buffer has `TEXTURE_2D_MULTISAMPLE` attachments, which shaders compiled with
+/// `GBuffer::defines` read one sample at a time, and a single-sample G-buffer of the same layout,
+/// `resolved`, that they can be resolved into for passes that only need one value per pixel.
+#[derive(Debug)]
+pub struct GBuffer {
+    pub size: (u32, u32),
+    pub layout: GBufferLayout,
+    /// The number of samples per pixel, which is 1 unless the G-buffer is multisampled
+    pub samples: u32,
+    pub framebuffer: u32,
+    /// The view space positions, which only the fat layout has
+    pub positions: Option<u32>,
+    pub normals: u32,
+    pub albedos: u32,
+    pub depth: u32,
+    /// The G-buffer that a multisampled G-buffer is resolved into
+    pub resolved: Option<Box<GBuffer>>,
+}
+
+impl GBuffer {
+    pub fn new(gl: &mut glow::Context, size: (u32, u32), layout: GBufferLayout) -> Self {
+        Self::with_samples(gl, size, layout, 1)
+    }
+
+    /// Make a G-buffer with a number of samples per pixel, which is multisampled for more than 1
+    pub fn with_samples(
+        gl: &mut glow::Context,
+        (width, height): (u32, u32),
+        layout: GBufferLayout,
+        samples: u32,
+    ) -> Self {
+        let (width, height) = (width.max(1), height.max(1));
+        let samples = samples.max(1);
+        let resolved = if samples > 1 {
+            Some(Box::new(Self::new(gl, (width, height), layout)))
+        } else {
+            None
+        };
+        let target = if samples > 1 {
+            glow::TEXTURE_2D_MULTISAMPLE
+        } else {
+            glow::TEXTURE_2D
+        };
+        let label = if samples > 1 {
+            format!("G-buffer {}x MSAA", samples)
+        } else {
+            "G-buffer".to_string()
+        };
+        unsafe {
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+
+            // Create each attachment
+            let attach = |attachment: u32, internal_format: u32, format: u32, data_type: u32| {
+                let texture = gl.create_texture().unwrap();
+                gl.bind_texture(target, Some(texture));
+                if samples > 1 {
+                    // Multisampled textures are only read with `texelFetch`, so they have no
+                    // filtering or wrapping
+                    gl.tex_image_2d_multisample(
+                        target,
+                        samples as i32,
+                        internal_format as i32,
+                        width as i32,
+                        height as i32,
+                        true,
+                    );
+                } else {
+                    gl.tex_image_2d(
+                        target,
+                        0,
+                        internal_format as i32,
+                        width as i32,
+                        height as i32,
+                        0,
+                        format,
+                        data_type,
+                        None,
+                    );
+                    // The SSAO pass samples around the edges, which shouldn't wrap around
+                    for (parameter, value) in [
+                        (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+                        (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+                        (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                        (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+                    ] {
+                        gl.tex_parameter_i32(target, parameter, value as i32);
+                    }
+                }
+                gl.framebuffer_texture_2d(glow::FRAMEBUFFER, attachment, target, Some(texture), 0);
+                resources::track_sized(
+                    ResourceKind::Texture,
+                    texture,
+                    &label,
+                    resources::texture_bytes(width, height, internal_format, 1, 1, samples),
+                );
+                resources::set_texture_target(texture, target);
+                texture
+            };
+            let (positions, normals, albedos) = match layout {
+                GBufferLayout::Fat => (
+                    Some(attach(
+                        glow::COLOR_ATTACHMENT0,
+                        glow::RGBA16F,
+                        glow::RGBA,
+                        glow::FLOAT,
+                    )),
+                    attach(
+                        glow::COLOR_ATTACHMENT1,
+                        glow::RGBA16F,
+                        glow::RGBA,
+                        glow::FLOAT,
+                    ),
+                    attach(
+                        glow::COLOR_ATTACHMENT2,
+                        glow::RGBA8,
+                        glow::RGBA,
+                        glow::UNSIGNED_BYTE,
+                    ),
+                ),
+                GBufferLayout::Packed => (
+                    None,
+                    attach(glow::COLOR_ATTACHMENT0, glow::RG16F, glow::RG, glow::FLOAT),
+                    attach(
+                        glow::COLOR_ATTACHMENT1,
+                        glow::RGBA8,
+                        glow::RGBA,
+                        glow::UNSIGNED_BYTE,
+                    ),
+                ),
+            };
+            // Floating point depth, which reverse-Z needs for its precision, takes as much memory
+            // as 24 bit depth
+            let depth = attach(
+                glow::DEPTH_ATTACHMENT,
+                glow::DEPTH_COMPONENT32F,
+                glow::DEPTH_COMPONENT,
+                glow::FLOAT,
+            );
+            let attachments = [
+                glow::COLOR_ATTACHMENT0,
+                glow::COLOR_ATTACHMENT1,
+                glow::COLOR_ATTACHMENT2,
+            ];
+            let color_count = if positions.is_some() { 3 } else { 2 };
+            gl.draw_buffers(&attachments[..color_count]);
+
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                panic!("Error creating the G-buffer!");
+            }
+            gl.bind_texture(target, None);
+            resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+
+            Self {
+                size: (width, height),
+                layout,
+                samples,
+                framebuffer,
+                positions,
+                normals,
+                albedos,
+                depth,
+                resolved,
+            }
+        }
+    }
+
+    pub fn is_multisampled(&self) -> bool {
+        self.samples > 1
+    }
+
+    /// The defines to compile shaders that include `GBUFFER_CHUNK` with, for reading this
+    /// G-buffer
+    pub fn defines(&self) -> Vec<&'static str> {
+        let mut defines = self.layout.defines().to_vec();
+        if self.is_multisampled() {
+            defines.push("GBUFFER_MULTISAMPLE");
+        }
+        defines
+    }
+
+    /// The single-sample G-buffer to read one value per pixel from, which is this one unless it
+    /// is multisampled
+    ///
+    /// A multisampled G-buffer has to be resolved first, e.g. by a `RenderGraph` pass that reads
+    /// its `multisampled_textures`.
+    pub fn single_sample(&self) -> &GBuffer {
+        self.resolved.as_deref().unwrap_or(self)
+    }
+
+    /// The attachments of a multisampled G-buffer along with the textures of `resolved` that they
+    /// are resolved into, which is empty for a single-sample G-buffer
+    ///
+    /// Only the albedos are averaged. Positions, normals, and depth keep their first sample, since
+    /// an average of the surfaces on both sides of an edge is a surface that isn't there.
+    pub fn multisampled_textures(&self) -> Vec<MultisampledTexture> {
+        let resolved = match &self.resolved {
+            Some(resolved) => resolved,
+            None => return Vec::new(),
+        };
+        let texture = |texture, resolved, kind| MultisampledTexture {
+            texture,
+            resolved,
+            kind,
+        };
+        let mut textures = Vec::new();
+        if let (Some(positions), Some(resolved_positions)) = (self.positions, resolved.positions) {
+            textures.push(texture(
+                positions,
+                resolved_positions,
+                ResolveKind::FirstSample,
+            ));
+        }
+        textures.push(texture(
+            self.normals,
+            resolved.normals,
+            ResolveKind::FirstSample,
+        ));
+        textures.push(texture(
+            self.albedos,
+            resolved.albedos,
+            ResolveKind::Average,
+        ));
+        textures.push(texture(self.depth, resolved.depth, ResolveKind::Depth));
+        textures
+    }
+
+    /// The textures that passes read the G-buffer from: the positions or depth, and the normals
+    pub fn geometry_textures(&self) -> Vec<u32> {
+        match self.positions {
+            Some(positions) => vec![positions, self.normals],
+            None => vec![self.depth, self.normals],
+        }
+    }
+
+    /// How many bytes the G-buffer takes up, including the depth buffer and the resolved G-buffer
+    pub fn bytes(&self) -> u64 {
+        let (width, height) = self.size;
+        let formats: &[u32] = match self.layout {
+            GBufferLayout::Fat => &[glow::RGBA16F, glow::RGBA16F, glow::RGBA8],
+            GBufferLayout::Packed => &[glow::RG16F, glow::RGBA8],
+        };
+        let bytes = formats
+            .iter()
+            .chain(&[glow::DEPTH_COMPONENT32F])
+            .map(|&format| resources::texture_bytes(width, height, format, 1, 1, self.samples))
+            .sum::<u64>();
+        bytes
+            + self
+                .resolved
+                .as_ref()
+                .map_or(0, |resolved| resolved.bytes())
+    }
+
+    /// Bind the G-buffer's textures to texture units starting at `first_unit`, and set the
+    /// uniforms declared by `GBUFFER_CHUNK` on a program, which should be bound
+    ///
+    /// `projection` is the projection the G-buffer was drawn with, for the current
+    /// `depth_mode::DepthSetup`. A multisampled G-buffer is bound as `sampler2DMS` textures, for a
+    /// program compiled with `defines`. This returns the first texture unit after the ones it
+    /// used.
+    pub fn bind(
+        &self,
+        gl: &mut glow::Context,
+        program: &mut ShaderProgram,
+        first_unit: u32,
+        projection: Matrix4<f32>,
+    ) -> u32 {
+        let textures = match self.positions {
+            Some(positions) => [
+                ("gPositions", positions),
+                ("gNormals", self.normals),
+                ("gAlbedos", self.albedos),
+            ],
+            None => [
+                ("gDepth", self.depth),
+                ("gNormals", self.normals),
+                ("gAlbedos", self.albedos),
+            ],
+        };
+        let target = if self.is_multisampled() {
+            glow::TEXTURE_2D_MULTISAMPLE
+        } else {
+            glow::TEXTURE_2D
+        };
+        for (unit, (name, texture)) in (first_unit..).zip(textures.iter()) {
+            unsafe {
+                gl.active_texture(glow::TEXTURE0 + unit);
+                gl.bind_texture(target, Some(*texture));
+            }
+            program.set_uniform(gl, name, unit as i32);
+        }
+        if self.is_multisampled() {
+            program.set_uniform(gl, "gSamples", self.samples as i32);
+        }
+        if self.layout == GBufferLayout::Packed {
+            let inverse_projection = projection.invert().unwrap_or_else(Matrix4::identity);
+            program.set_uniform(gl, "gInverseProjection", inverse_projection);
+            let depth = depth_mode::current();
+            program.set_uniform(gl, "gDepthReversed", depth.is_reversed() as i32);
+            program.set_uniform(gl, "gDepthZeroToOne", depth.zero_to_one as i32);
+        }
+        first_unit + textures.len() as u32
+    }
+
+    /// Delete the GL objects, along with the resolved G-buffer
+    pub fn delete(&self, gl: &mut glow::Context) {
+        if let Some(resolved) = &self.resolved {
+            resolved.delete(gl);
+        }
+        let textures = self
+            .positions
+            .iter()
+            .chain(&[self.normals, self.albedos, self.depth])
+            .copied()
+            .collect::<Vec<_>>();
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            for &texture in &textures {
+                gl.delete_texture(texture);
+                resources::untrack(ResourceKind::Texture, texture);
+            }
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+    }
+}
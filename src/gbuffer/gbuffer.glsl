@@ -0,0 +1,102 @@
+// Reading the G-buffer drawn into a `GBuffer`, which sets these uniforms with `GBuffer::bind`.
+// With GBUFFER_PACKED defined the normals are octahedral and the positions come from the depth.
+// With GBUFFER_MULTISAMPLE defined the textures are multisampled, and the functions below fetch
+// the sample `gSample` of the pixel under the texture coordinate.
+#ifdef GBUFFER_MULTISAMPLE
+#define GBUFFER_SAMPLER sampler2DMS
+uniform int gSamples;
+// The sample that the functions read, which shaders that shade every sample loop over
+int gSample = 0;
+#else
+#define GBUFFER_SAMPLER sampler2D
+#endif
+
+uniform GBUFFER_SAMPLER gNormals;
+uniform GBUFFER_SAMPLER gAlbedos;
+#ifdef GBUFFER_PACKED
+uniform GBUFFER_SAMPLER gDepth;
+uniform mat4 gInverseProjection;
+// Whether the depth goes from 1 at the near plane to 0 at the far plane
+uniform bool gDepthReversed;
+// Whether clip space depth went from 0 to 1 instead of -1 to 1 when the depth was drawn
+uniform bool gDepthZeroToOne;
+#else
+uniform GBUFFER_SAMPLER gPositions;
+#endif
+
+// How many samples each pixel of the G-buffer has
+int gbufferSamples() {
+#ifdef GBUFFER_MULTISAMPLE
+    return gSamples;
+#else
+    return 1;
+#endif
+}
+
+#ifdef GBUFFER_MULTISAMPLE
+// The pixel under a texture coordinate, clamped to the edges like the single-sample textures
+ivec2 gbufferPixel(vec2 texCoord) {
+    ivec2 size = textureSize(gNormals);
+    return clamp(ivec2(texCoord * vec2(size)), ivec2(0), size - 1);
+}
+#define GBUFFER_READ(sampler, texCoord) texelFetch(sampler, gbufferPixel(texCoord), gSample)
+#else
+#define GBUFFER_READ(sampler, texCoord) texture(sampler, texCoord)
+#endif
+
+// Fold a unit normal onto an octahedron and flatten it, with the back half folded over the
+// corners of the front half, so that it fits in two channels from -1 to 1
+vec2 encodeNormal(vec3 normal) {
+    normal /= abs(normal.x) + abs(normal.y) + abs(normal.z);
+    if (normal.z >= 0.0) {
+        return normal.xy;
+    }
+    vec2 signs = vec2(normal.x >= 0.0 ? 1.0 : -1.0, normal.y >= 0.0 ? 1.0 : -1.0);
+    return (1.0 - abs(normal.yx)) * signs;
+}
+
+// Unfold a normal from `encodeNormal`
+vec3 decodeNormal(vec2 encoded) {
+    vec3 normal = vec3(encoded, 1.0 - abs(encoded.x) - abs(encoded.y));
+    float fold = max(-normal.z, 0.0);
+    normal.x += normal.x >= 0.0 ? -fold : fold;
+    normal.y += normal.y >= 0.0 ? -fold : fold;
+    return normalize(normal);
+}
+
+// The view space position of a point on screen from the depth buffer's value there, for depth
+// drawn with clip space depth from -1 to 1, or from 0 to 1 when `zeroToOne` is set
+vec3 viewPositionFromDepth(vec2 texCoord, float depth, mat4 inverseProjection, bool zeroToOne) {
+    float z = zeroToOne ? depth : depth * 2.0 - 1.0;
+    vec4 position = inverseProjection * vec4(texCoord * 2.0 - 1.0, z, 1.0);
+    return position.xyz / position.w;
+}
+
+// The view space position of a point on screen, with an alpha of 0 where nothing was drawn
+vec4 gbufferPosition(vec2 texCoord) {
+#ifdef GBUFFER_PACKED
+    float depth = GBUFFER_READ(gDepth, texCoord).r;
+    if (depth == (gDepthReversed ? 0.0 : 1.0)) {
+        return vec4(0.0);
+    }
+    return vec4(
+        viewPositionFromDepth(texCoord, depth, gInverseProjection, gDepthZeroToOne),
+        1.0
+    );
+#else
+    return GBUFFER_READ(gPositions, texCoord);
+#endif
+}
+
+// The view space normal of a point on screen
+vec3 gbufferNormal(vec2 texCoord) {
+#ifdef GBUFFER_PACKED
+    return decodeNormal(GBUFFER_READ(gNormals, texCoord).rg);
+#else
+    return normalize(GBUFFER_READ(gNormals, texCoord).xyz);
+#endif
+}
+
+vec3 gbufferAlbedo(vec2 texCoord) {
+    return GBUFFER_READ(gAlbedos, texCoord).rgb;
+}
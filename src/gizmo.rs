@@ -0,0 +1,312 @@
+use cgmath::{ortho, InnerSpace, Matrix3, Matrix4, Vector2, Vector3};
+use glow::HasContext;
+
+use crate::{
+    color::Color,
+    debug_group::DebugGroup,
+    depth_mode,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+    vertex::{pack_unorm8x4, VertexFormat, VertexLayout},
+    viewport::{render_inset, Rect},
+    AppContext, SliceAsBytes,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("gizmo/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("gizmo/fragment.glsl");
+
+/// The colors of the X, Y, and Z axes
+const AXIS_COLORS: [Color; 3] = [
+    Color::rgb(0.9, 0.25, 0.25),
+    Color::rgb(0.35, 0.8, 0.3),
+    Color::rgb(0.25, 0.45, 0.95),
+];
+/// The opacity of the dots at the negative ends of the axes
+const NEGATIVE_ALPHA: f32 = 0.45;
+
+/// The corner of the window an axis gizmo sits in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GizmoCorner {
+    BottomLeft,
+    BottomRight,
+    TopLeft,
+    TopRight,
+}
+
+impl GizmoCorner {
+    /// The name of the corner, like `bottom-left`
+    pub fn name(self) -> &'static str {
+        match self {
+            GizmoCorner::BottomLeft => "bottom-left",
+            GizmoCorner::BottomRight => "bottom-right",
+            GizmoCorner::TopLeft => "top-left",
+            GizmoCorner::TopRight => "top-right",
+        }
+    }
+
+    /// The corner with the given name
+    pub fn parse(name: &str) -> Option<Self> {
+        [
+            GizmoCorner::BottomLeft,
+            GizmoCorner::BottomRight,
+            GizmoCorner::TopLeft,
+            GizmoCorner::TopRight,
+        ]
+        .iter()
+        .copied()
+        .find(|corner| corner.name() == name)
+    }
+}
+
+/// A vertex laid out like `AxisGizmo::vertex_layout`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct GizmoVertex {
+    position: [f32; 2],
+    /// The distance from the middle of the line or dot in pixels
+    offset: [f32; 2],
+    /// The half width of the line or the radius of the dot in pixels
+    radius: f32,
+    color: [u8; 4],
+}
+
+/// The X, Y, and Z axes drawn in a small inset in a corner of the window, turning with the camera
+/// to show which way it is looking
+///
+/// The sizes are in pixels at a UI scale of 1 and are rounded to whole physical pixels, so the
+/// gizmo keeps its size and sharpness on hidpi screens. Draw it last, since the inset clears the
+/// depth buffer under it.
+#[derive(Debug)]
+pub struct AxisGizmo {
+    pub corner: GizmoCorner,
+    /// The width and height of the inset
+    pub size: i32,
+    /// The distance between the inset and the edges of the window
+    pub margin: i32,
+    /// The width of the axis lines
+    pub line_width: f32,
+    /// The radius of the dots at the ends of the axes
+    pub dot_radius: f32,
+    program: ShaderProgram,
+    vao: u32,
+    vbo: u32,
+    /// The vertices of the current frame
+    vertices: Vec<GizmoVertex>,
+}
+
+impl AxisGizmo {
+    pub fn new(gl: &mut glow::Context) -> Self {
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+        unsafe {
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            Self::vertex_layout().apply(gl);
+            gl.bind_vertex_array(None);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+            resources::track(ResourceKind::VertexArray, vao, "Axis gizmo vertex array");
+            resources::track(ResourceKind::Buffer, vbo, "Axis gizmo vertex buffer");
+
+            Self {
+                corner: GizmoCorner::BottomLeft,
+                size: 96,
+                margin: 12,
+                line_width: 2.5,
+                dot_radius: 6.,
+                program,
+                vao,
+                vbo,
+                vertices: Vec::new(),
+            }
+        }
+    }
+
+    /// Put the gizmo in a different corner of the window
+    pub fn with_corner(mut self, corner: GizmoCorner) -> Self {
+        self.corner = corner;
+        self
+    }
+
+    /// The position, offset, radius, and color of every vertex
+    fn vertex_layout() -> VertexLayout {
+        VertexLayout::new(&[
+            (0, VertexFormat::Float32x2),
+            (1, VertexFormat::Float32x2),
+            (2, VertexFormat::Float32),
+            (3, VertexFormat::Unorm8x4),
+        ])
+    }
+
+    /// The inset the gizmo is drawn in, in physical pixels
+    pub fn rect(&self, ctx: &AppContext) -> Rect {
+        let size = ctx.scale_ui(self.size);
+        let margin = ctx.scale_ui(self.margin);
+        let (width, height) = ctx.render_size();
+        let (left, bottom) = (margin, margin);
+        let right = width as i32 - margin - size;
+        let top = height as i32 - margin - size;
+        match self.corner {
+            GizmoCorner::BottomLeft => Rect::new(left, bottom, size, size),
+            GizmoCorner::BottomRight => Rect::new(right, bottom, size, size),
+            GizmoCorner::TopLeft => Rect::new(left, top, size, size),
+            GizmoCorner::TopRight => Rect::new(right, top, size, size),
+        }
+    }
+
+    /// Draw the axes turned like they are seen through the camera with the given view matrix
+    ///
+    /// The gizmo registers itself in `AppContext::viewports` as `gizmo`, with a camera that looks
+    /// at the axes from the same direction, so that the axes are 1 unit long around the origin
+    /// there.
+    pub fn draw(&mut self, gl: &mut glow::Context, ctx: &AppContext, view: Matrix4<f32>) {
+        let _group = DebugGroup::push(gl, "Axis gizmo");
+        let rect = self.rect(ctx);
+        let ui_scale = ctx.ui_scale();
+        let half_size = rect.width as f32 / 2.;
+        let half_width = self.line_width * ui_scale / 2.;
+        let dot_radius = self.dot_radius * ui_scale;
+        let length = half_size - dot_radius - 2.;
+
+        // Only the camera's rotation, looking at the axes from outside of them
+        let rotation = Matrix3::from_cols(view.x.truncate(), view.y.truncate(), view.z.truncate());
+        let extent = half_size / length.max(1.);
+        ctx.viewports.register(
+            "gizmo",
+            rect,
+            Matrix4::from_translation(-Vector3::unit_z() * 2.) * Matrix4::from(rotation),
+            depth_mode::current().projection(ortho(-extent, extent, -extent, extent, 0.5, 3.5)),
+        );
+
+        // Turn the axes with the camera, and draw the ones pointing away from it first so the
+        // closer ones are on top. Both ends of every axis get a dot.
+        let mut ends = Vec::with_capacity(6);
+        for (axis, color) in AXIS_COLORS.iter().enumerate() {
+            let direction = view[axis].truncate();
+            ends.push((direction, *color, true));
+            ends.push((-direction, color.with_alpha(NEGATIVE_ALPHA), false));
+        }
+        ends.sort_by(|a, b| a.0.z.total_cmp(&b.0.z));
+
+        self.vertices.clear();
+        for (direction, color, positive) in ends {
+            let end = Vector2::new(direction.x, direction.y) * length;
+            let color = pack_unorm8x4(color.to_srgb());
+            if positive {
+                self.line(end, half_width, color, half_size);
+                self.dot(end, dot_radius, color, half_size);
+            } else {
+                self.dot(end, dot_radius * 0.7, color, half_size);
+            }
+        }
+
+        let bytes = self.vertices.as_mem_bytes();
+        debug_assert_eq!(
+            std::mem::size_of::<GizmoVertex>(),
+            Self::vertex_layout().stride()
+        );
+        let vertex_count = self.vertices.len() as i32;
+        let (program, vao, vbo) = (&mut self.program, self.vao, self.vbo);
+
+        render_inset(gl, ctx, rect, None, |gl| unsafe {
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, bytes, glow::STREAM_DRAW);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+
+            let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+            let cull_face = gl.is_enabled(glow::CULL_FACE);
+            let blend = gl.is_enabled(glow::BLEND);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::CULL_FACE);
+            gl.enable(glow::BLEND);
+            gl.blend_func_separate(
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            );
+
+            program.bind(gl);
+            gl.bind_vertex_array(Some(vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, vertex_count);
+            gl.bind_vertex_array(None);
+
+            // Put the state back the way the handler had it
+            if depth_test {
+                gl.enable(glow::DEPTH_TEST);
+            }
+            if cull_face {
+                gl.enable(glow::CULL_FACE);
+            }
+            if !blend {
+                gl.disable(glow::BLEND);
+            }
+        });
+    }
+
+    /// Add a line from the middle of the inset to `end`, given in pixels from the middle
+    fn line(&mut self, end: Vector2<f32>, half_width: f32, color: [u8; 4], half_size: f32) {
+        // An axis pointing straight at the camera only shows its dot
+        if end.magnitude2() < 1e-6 {
+            return;
+        }
+        // Leave a pixel on each side for the anti-aliasing
+        let padded = half_width + 1.;
+        let across = Vector2::new(-end.y, end.x).normalize() * padded;
+        let corners = [
+            (-across, -padded),
+            (end - across, -padded),
+            (end + across, padded),
+            (-across, -padded),
+            (end + across, padded),
+            (across, padded),
+        ];
+        for (position, offset) in corners {
+            self.vertex(position, [0., offset], half_width, color, half_size);
+        }
+    }
+
+    /// Add a round dot at `center`, given in pixels from the middle of the inset
+    fn dot(&mut self, center: Vector2<f32>, radius: f32, color: [u8; 4], half_size: f32) {
+        let padded = radius + 1.;
+        for (x, y) in [
+            (-1., -1.),
+            (1., -1.),
+            (1., 1.),
+            (-1., -1.),
+            (1., 1.),
+            (-1., 1.),
+        ] {
+            let offset = Vector2::new(x, y) * padded;
+            self.vertex(center + offset, offset.into(), radius, color, half_size);
+        }
+    }
+
+    /// Add a vertex at a position in pixels from the middle of the inset
+    fn vertex(
+        &mut self,
+        position: Vector2<f32>,
+        offset: [f32; 2],
+        radius: f32,
+        color: [u8; 4],
+        half_size: f32,
+    ) {
+        self.vertices.push(GizmoVertex {
+            position: (position / half_size).into(),
+            offset,
+            radius,
+            color,
+        });
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.program.delete(gl);
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+        }
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+        resources::untrack(ResourceKind::Buffer, self.vbo);
+    }
+}
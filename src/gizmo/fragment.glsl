@@ -0,0 +1,14 @@
+#version 330 core
+// How far the pixel is from the middle of the line or dot, in pixels
+in vec2 offset;
+// The half width of the line or the radius of the dot, in pixels
+in float radius;
+in vec4 color;
+
+out vec4 FragColor;
+
+void main() {
+    // Anti-alias the edges over one pixel
+    float coverage = clamp(radius + 0.5 - length(offset), 0.0, 1.0);
+    FragColor = vec4(color.rgb, color.a * coverage);
+}
@@ -0,0 +1,16 @@
+#version 330 core
+layout (location = 0) in vec2 aPos;
+layout (location = 1) in vec2 aOffset;
+layout (location = 2) in float aRadius;
+layout (location = 3) in vec4 aColor;
+
+out vec2 offset;
+out float radius;
+out vec4 color;
+
+void main() {
+    gl_Position = vec4(aPos, 0.0, 1.0);
+    offset = aOffset;
+    radius = aRadius;
+    color = aColor;
+}
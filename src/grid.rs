@@ -0,0 +1,165 @@
+use cgmath::{Matrix4, Point3};
+use glow::HasContext;
+
+use crate::{
+    color::Color,
+    debug_group::DebugGroup,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+    AppContext,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("grid/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("grid/fragment.glsl");
+
+/// The settings of a ground grid
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GridParams {
+    /// The height of the grid plane
+    pub height: f32,
+    /// The distance between the minor lines
+    pub minor_spacing: f32,
+    /// How many minor cells there are across each major cell
+    pub major_every: u32,
+    /// The color of the minor lines, or `None` for the theme's
+    pub minor_color: Option<Color>,
+    /// The color of the major lines, or `None` for the theme's
+    pub major_color: Option<Color>,
+    /// The width of the minor lines in pixels, at a UI scale of 1
+    pub minor_width: f32,
+    /// The width of the major lines and axes in pixels, at a UI scale of 1
+    pub major_width: f32,
+    /// Whether or not the X and Z axes are drawn in red and blue
+    pub show_axes: bool,
+    /// How far from the camera the grid fades out completely
+    pub fade_distance: f32,
+}
+
+impl Default for GridParams {
+    fn default() -> Self {
+        Self {
+            height: 0.,
+            minor_spacing: 1.,
+            major_every: 10,
+            minor_color: None,
+            major_color: None,
+            minor_width: 1.,
+            major_width: 1.5,
+            show_axes: true,
+            fade_distance: 100.,
+        }
+    }
+}
+
+/// A ground grid that seems to go on forever, for a sense of scale and direction around models
+///
+/// The grid is a square under the camera as big as the fade distance, with the lines drawn in its
+/// fragment shader, so they stay anti-aliased and the same width in pixels at any distance. Draw
+/// it after the scene: it is blended over it without writing depth, and pushed back with a polygon
+/// offset so that models resting on the grid plane don't z-fight with it.
+#[derive(Debug)]
+pub struct GroundGrid {
+    pub params: GridParams,
+    program: ShaderProgram,
+    /// An empty vertex array, since the vertices come from the vertex index
+    vao: u32,
+}
+
+impl GroundGrid {
+    pub fn new(gl: &mut glow::Context, params: GridParams) -> Self {
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+        let vao = unsafe { gl.create_vertex_array().unwrap() };
+        resources::track(ResourceKind::VertexArray, vao, "Ground grid vertex array");
+        Self {
+            params,
+            program,
+            vao,
+        }
+    }
+
+    /// Draw the grid for a camera at `camera_position`
+    pub fn draw(
+        &mut self,
+        gl: &mut glow::Context,
+        ctx: &AppContext,
+        view_projection: Matrix4<f32>,
+        camera_position: Point3<f32>,
+    ) {
+        let _group = DebugGroup::push(gl, "Ground grid");
+        let params = self.params;
+        let ui_scale = ctx.ui_scale();
+        let axis_alpha = if params.show_axes { 1. } else { 0. };
+        // The theme is read every frame, so the grid changes with it
+        let theme = ctx.theme();
+        let minor_color = params.minor_color.unwrap_or(theme.grid_minor_color);
+        let major_color = params.major_color.unwrap_or(theme.grid_major_color);
+
+        let program = &mut self.program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        program.set_uniform(
+            gl,
+            "center",
+            [camera_position.x, params.height, camera_position.z],
+        );
+        program.set_uniform(gl, "halfSize", params.fade_distance);
+        program.set_uniform(
+            gl,
+            "cameraPosition",
+            [camera_position.x, camera_position.y, camera_position.z],
+        );
+        program.set_uniform(gl, "minorSpacing", params.minor_spacing);
+        program.set_uniform(
+            gl,
+            "majorSpacing",
+            params.minor_spacing * params.major_every.max(1) as f32,
+        );
+        program.set_uniform(gl, "minorColor", minor_color.to_srgb());
+        program.set_uniform(gl, "majorColor", major_color.to_srgb());
+        program.set_uniform(gl, "xAxisColor", [0.9, 0.25, 0.25, axis_alpha]);
+        program.set_uniform(gl, "zAxisColor", [0.25, 0.45, 0.95, axis_alpha]);
+        program.set_uniform(gl, "minorWidth", params.minor_width * ui_scale);
+        program.set_uniform(gl, "majorWidth", params.major_width * ui_scale);
+        program.set_uniform(gl, "fadeDistance", params.fade_distance);
+
+        unsafe {
+            // Blend over the scene without hiding anything drawn later, and lose depth ties with
+            // the faces of models that lie on the grid plane
+            let blend = gl.is_enabled(glow::BLEND);
+            let cull_face = gl.is_enabled(glow::CULL_FACE);
+            let depth_mask = gl.get_parameter_i32(glow::DEPTH_WRITEMASK) != 0;
+            gl.enable(glow::BLEND);
+            gl.blend_func_separate(
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            );
+            gl.disable(glow::CULL_FACE);
+            gl.depth_mask(false);
+            gl.enable(glow::POLYGON_OFFSET_FILL);
+            gl.polygon_offset(1., 1.);
+
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 6);
+            gl.bind_vertex_array(None);
+
+            // Put the state back the way the handler had it
+            gl.disable(glow::POLYGON_OFFSET_FILL);
+            gl.depth_mask(depth_mask);
+            if cull_face {
+                gl.enable(glow::CULL_FACE);
+            }
+            if !blend {
+                gl.disable(glow::BLEND);
+            }
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.program.delete(gl);
+        unsafe { gl.delete_vertex_array(self.vao) };
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+    }
+}
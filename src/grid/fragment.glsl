@@ -0,0 +1,49 @@
+#version 330 core
+in vec3 worldPosition;
+
+uniform vec3 cameraPosition;
+uniform float minorSpacing;
+uniform float majorSpacing;
+uniform vec4 minorColor;
+uniform vec4 majorColor;
+uniform vec4 xAxisColor;
+uniform vec4 zAxisColor;
+// The widths of the lines in pixels
+uniform float minorWidth;
+uniform float majorWidth;
+uniform float fadeDistance;
+
+out vec4 FragColor;
+
+// How much of the pixel is covered by the lines along x and z, measuring the distance to the
+// nearest line in pixels so that lines are the same width everywhere
+vec2 lineCoverage(vec2 coord, float width) {
+    vec2 pixelsPerUnit = fwidth(coord);
+    vec2 distance = abs(fract(coord - 0.5) - 0.5) / pixelsPerUnit;
+    return clamp(width * 0.5 + 0.5 - distance, 0.0, 1.0);
+}
+
+void main() {
+    vec2 position = worldPosition.xz;
+
+    // Minor lines fade out before they get closer together than a few pixels and turn into mush
+    vec2 minorCoord = position / minorSpacing;
+    float density = max(fwidth(minorCoord).x, fwidth(minorCoord).y);
+    vec2 minorLines = lineCoverage(minorCoord, minorWidth);
+    float minor = max(minorLines.x, minorLines.y) * (1.0 - smoothstep(0.15, 0.4, density));
+    vec2 majorLines = lineCoverage(position / majorSpacing, majorWidth);
+    float major = max(majorLines.x, majorLines.y);
+
+    vec4 color = vec4(minorColor.rgb, minorColor.a * minor);
+    color = mix(color, vec4(majorColor.rgb, majorColor.a * major), major);
+
+    // The Z axis runs along x = 0 and the X axis along z = 0
+    vec2 axisDistance = abs(position) / fwidth(position);
+    vec2 axes = clamp(majorWidth * 0.5 + 0.5 - axisDistance, 0.0, 1.0);
+    color = mix(color, vec4(zAxisColor.rgb, zAxisColor.a * axes.x), axes.x);
+    color = mix(color, vec4(xAxisColor.rgb, xAxisColor.a * axes.y), axes.y);
+
+    // Fade out into the distance so the edge of the grid doesn't show
+    float fade = 1.0 - smoothstep(fadeDistance * 0.4, fadeDistance, length(position - cameraPosition.xz));
+    FragColor = vec4(color.rgb, color.a * fade);
+}
@@ -0,0 +1,21 @@
+#version 330 core
+
+// A square centered under the camera, made from the vertex index so that no vertex buffer is
+// needed
+const vec2 CORNERS[6] = vec2[](
+    vec2(-1.0, -1.0), vec2(1.0, -1.0), vec2(1.0, 1.0),
+    vec2(-1.0, -1.0), vec2(1.0, 1.0), vec2(-1.0, 1.0)
+);
+
+uniform mat4 viewProjection;
+// The camera's position on the grid plane
+uniform vec3 center;
+uniform float halfSize;
+
+out vec3 worldPosition;
+
+void main() {
+    vec2 corner = CORNERS[gl_VertexID] * halfSize;
+    worldPosition = center + vec3(corner.x, 0.0, corner.y);
+    gl_Position = viewProjection * vec4(worldPosition, 1.0);
+}
@@ -0,0 +1,176 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    marker::PhantomData,
+};
+
+use glow::HasContext;
+
+use crate::{
+    mesh::Mesh,
+    resources::{self, HandleError, ResourceKind},
+    shader::ShaderProgram,
+    texture::Texture,
+};
+
+/// A wrapper of a GL object that hands out `Handle`s to it
+pub trait HandleKind {
+    /// The kind of GL object that the handles resolve to
+    const KIND: ResourceKind;
+}
+
+impl HandleKind for Texture {
+    const KIND: ResourceKind = ResourceKind::Texture;
+}
+
+/// Mesh handles are for the mesh's vertex array
+impl HandleKind for Mesh {
+    const KIND: ResourceKind = ResourceKind::VertexArray;
+}
+
+impl HandleKind for ShaderProgram {
+    const KIND: ResourceKind = ResourceKind::Program;
+}
+
+/// A reference to a GL object that can tell when the object has been deleted
+///
+/// GL names are plain numbers that get reused, and binding a deleted one quietly binds nothing,
+/// so holding on to a raw name after its wrapper was deleted or swapped out by a hot reload draws
+/// black instead of failing. A handle is a slot in the context's registry in `resources` and the
+/// generation of the object in it, so resolving a stale handle is an error that says which object
+/// it was for.
+pub struct Handle<T> {
+    index: u32,
+    generation: u32,
+    _kind: PhantomData<fn() -> T>,
+}
+
+impl<T: HandleKind> Handle<T> {
+    /// A handle to a live object of the current context, or `None` if the object isn't tracked
+    /// with `resources::track`
+    pub fn new(id: u32) -> Option<Self> {
+        let (index, generation) = resources::handle(T::KIND, id)?;
+        Some(Self {
+            index,
+            generation,
+            _kind: PhantomData,
+        })
+    }
+
+    /// The GL object that the handle is for, if it hasn't been deleted
+    pub fn resolve(self) -> Result<u32, HandleError> {
+        resources::resolve(T::KIND, self.index, self.generation)
+    }
+
+    pub fn index(self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+}
+
+// Implemented by hand, since deriving them would require `T` to implement them too
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Handle<T> {}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index && self.generation == other.generation
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::hash::Hash for Handle<T> {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+        self.generation.hash(state);
+    }
+}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Handle({}v{})", self.index, self.generation)
+    }
+}
+
+thread_local! {
+    /// The magenta texture bound in place of stale texture handles in release builds, for each
+    /// context by its resource key
+    static PLACEHOLDERS: RefCell<HashMap<Option<u64>, u32>> = RefCell::new(HashMap::new());
+    /// The stale handles that have been warned about, so each one is only warned about once
+    static WARNED: RefCell<HashSet<(u32, u32)>> = RefCell::new(HashSet::new());
+}
+
+/// Bind the texture that a handle is for to `GL_TEXTURE_2D` on the active texture unit
+///
+/// A stale handle panics with a description of the deleted texture in debug builds. Release
+/// builds print a warning the first time instead and bind a magenta placeholder texture, so the
+/// mistake shows up on screen rather than as black.
+pub fn bind_texture(gl: &mut glow::Context, handle: Handle<Texture>) {
+    match handle.resolve() {
+        Ok(texture) => unsafe { gl.bind_texture(glow::TEXTURE_2D, Some(texture)) },
+        Err(error) => {
+            if cfg!(debug_assertions) {
+                panic!("Bound a stale texture handle: {}", error);
+            }
+            let first_time = WARNED.with(|warned| {
+                warned
+                    .borrow_mut()
+                    .insert((handle.index, handle.generation))
+            });
+            if first_time {
+                eprintln!("Warning: Bound a stale texture handle: {}", error);
+            }
+            let placeholder = placeholder_texture(gl);
+            unsafe { gl.bind_texture(glow::TEXTURE_2D, Some(placeholder)) };
+        }
+    }
+}
+
+/// The current context's magenta placeholder texture, created the first time it is needed
+///
+/// It isn't tracked, since it lives as long as the context.
+fn placeholder_texture(gl: &mut glow::Context) -> u32 {
+    let key = resources::current_key();
+    if let Some(texture) =
+        PLACEHOLDERS.with(|placeholders| placeholders.borrow().get(&key).copied())
+    {
+        return texture;
+    }
+    let texture = unsafe {
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA8 as i32,
+            1,
+            1,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            Some(&[255, 0, 255, 255]),
+        );
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MIN_FILTER,
+            glow::NEAREST as i32,
+        );
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MAG_FILTER,
+            glow::NEAREST as i32,
+        );
+        texture
+    };
+    PLACEHOLDERS.with(|placeholders| placeholders.borrow_mut().insert(key, texture));
+    texture
+}
@@ -0,0 +1,70 @@
+use std::path::Path;
+
+use crate::fallback_assets;
+
+/// A grid of heights loaded from a grayscale image
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    pub width: u32,
+    pub height: u32,
+    /// The height of each pixel from 0 to 1, row by row
+    pub heights: Vec<f32>,
+}
+
+impl Heightmap {
+    /// Load a heightmap from a grayscale image
+    ///
+    /// 16 bit images are read at full precision, which avoids the stair steps that 8 bit
+    /// heightmaps get on gentle slopes. Color images are converted to grayscale first.
+    ///
+    /// A missing file is replaced with `fallback_assets::heightmap`, with a warning.
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return fallback_assets::heightmap(path);
+        }
+        Self::from_image(image::open(path).unwrap())
+    }
+
+    /// Decode a heightmap file that has already been read, like one built into the binary
+    pub fn from_memory(bytes: &[u8]) -> image::ImageResult<Self> {
+        Ok(Self::from_image(image::load_from_memory(bytes)?))
+    }
+
+    fn from_image(img: image::DynamicImage) -> Self {
+        let (width, height, heights) = match img {
+            image::DynamicImage::ImageLuma16(img) => (
+                img.width(),
+                img.height(),
+                img.into_raw()
+                    .into_iter()
+                    .map(|h| h as f32 / u16::MAX as f32)
+                    .collect(),
+            ),
+            img => {
+                let img = img.into_luma();
+                (
+                    img.width(),
+                    img.height(),
+                    img.into_raw()
+                        .into_iter()
+                        .map(|h| h as f32 / u8::MAX as f32)
+                        .collect(),
+                )
+            }
+        };
+
+        Self {
+            width,
+            height,
+            heights,
+        }
+    }
+
+    /// The height at the given pixel, clamped to the edges of the heightmap
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        let x = x.min(self.width - 1);
+        let y = y.min(self.height - 1);
+        self.heights[(y * self.width + x) as usize]
+    }
+}
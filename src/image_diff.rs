@@ -0,0 +1,275 @@
+use std::path::Path;
+
+use glow::HasContext;
+
+use crate::texture::{AlphaMode, ImageData};
+
+/// Whether or not a channel is close enough to what was expected
+///
+/// This is the one definition of a tolerance that every comparison uses, whether it is of shader
+/// outputs in `ShaderTest` or of two captured frames: a channel passes when it is at most
+/// `tolerance` away from the expected value, in whatever units the channel is in.
+pub fn within_tolerance(expected: f32, actual: f32, tolerance: f32) -> bool {
+    (expected - actual).abs() <= tolerance
+}
+
+/// How different two images are, from `image_diff`
+#[derive(Debug, Clone)]
+pub struct DiffReport {
+    pub width: u32,
+    pub height: u32,
+    /// The largest difference of each RGBA channel, from 0 to 1
+    pub max_error: [f32; 4],
+    /// The average difference of each RGBA channel over all of the pixels, from 0 to 1
+    pub mean_error: [f32; 4],
+    /// The peak signal to noise ratio of the color channels in decibels, which is infinite for
+    /// identical images
+    pub psnr: f32,
+    /// How many pixels differ in any channel
+    pub differing_pixels: usize,
+    /// Where the images differ: black where they are the same, and blue through red for small to
+    /// large differences, scaled so that red is the largest difference. Pixels that are the same
+    /// show a dim copy of the first image, so the differences can be placed.
+    pub heatmap: ImageData,
+}
+
+impl DiffReport {
+    /// Whether or not every channel of every pixel is within `tolerance` of the first image
+    pub fn within(&self, tolerance: f32) -> bool {
+        self.max_error
+            .iter()
+            .all(|&error| within_tolerance(0., error, tolerance))
+    }
+
+    /// How many of the pixels differ, from 0 to 1
+    pub fn differing_fraction(&self) -> f32 {
+        let pixels = self.width as usize * self.height as usize;
+        if pixels == 0 {
+            0.
+        } else {
+            self.differing_pixels as f32 / pixels as f32
+        }
+    }
+
+    /// One line for the console: the largest and average errors, the PSNR, and how much differs
+    pub fn summary(&self) -> String {
+        let channels = |errors: &[f32; 4]| {
+            errors
+                .iter()
+                .map(|error| format!("{:.4}", error))
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        format!(
+            "max {}, mean {}, PSNR {:.2} dB, {} pixels differ ({:.2}%)",
+            channels(&self.max_error),
+            channels(&self.mean_error),
+            self.psnr,
+            self.differing_pixels,
+            self.differing_fraction() * 100.
+        )
+    }
+}
+
+/// Compare two images of the same size pixel by pixel
+///
+/// Images without alpha are compared as if they were opaque.
+///
+/// # Panics
+///
+/// If the images aren't the same size.
+pub fn image_diff(a: &ImageData, b: &ImageData) -> DiffReport {
+    assert!(
+        a.width == b.width && a.height == b.height,
+        "Can't compare a {}x{} image with a {}x{} one",
+        a.width,
+        a.height,
+        b.width,
+        b.height
+    );
+    let pixel_count = a.width as usize * a.height as usize;
+    let a_pixels = rgba_pixels(a);
+    let b_pixels = rgba_pixels(b);
+
+    // The largest error of each pixel, for the heatmap
+    let mut pixel_errors = Vec::with_capacity(pixel_count);
+    let mut max_error = [0u8; 4];
+    let mut error_sums = [0u64; 4];
+    let mut squared_color_error = 0u64;
+    let mut differing_pixels = 0;
+    for (a_pixel, b_pixel) in a_pixels.zip(b_pixels) {
+        let mut pixel_error = 0;
+        for channel in 0..4 {
+            let error = (a_pixel[channel] as i32 - b_pixel[channel] as i32).unsigned_abs() as u8;
+            max_error[channel] = max_error[channel].max(error);
+            error_sums[channel] += error as u64;
+            if channel < 3 {
+                squared_color_error += error as u64 * error as u64;
+            }
+            pixel_error = pixel_error.max(error);
+        }
+        if pixel_error > 0 {
+            differing_pixels += 1;
+        }
+        pixel_errors.push((pixel_error, a_pixel));
+    }
+
+    let largest_error = *max_error.iter().max().unwrap();
+    let mut heatmap = Vec::with_capacity(pixel_count * 4);
+    for (error, a_pixel) in pixel_errors {
+        let [r, g, b] = if error == 0 {
+            let luminance = 0.2126 * a_pixel[0] as f32
+                + 0.7152 * a_pixel[1] as f32
+                + 0.0722 * a_pixel[2] as f32;
+            let dim = (luminance * 0.25) as u8;
+            [dim, dim, dim]
+        } else {
+            heat_color(error as f32 / largest_error as f32)
+        };
+        heatmap.extend_from_slice(&[r, g, b, 255]);
+    }
+
+    let count = pixel_count.max(1) as f32;
+    let mean_squared_error = squared_color_error as f64 / (pixel_count.max(1) * 3) as f64;
+    let psnr = if mean_squared_error == 0. {
+        f32::INFINITY
+    } else {
+        (10. * (255f64 * 255. / mean_squared_error).log10()) as f32
+    };
+    DiffReport {
+        width: a.width,
+        height: a.height,
+        max_error: max_error.map(|error| error as f32 / 255.),
+        mean_error: error_sums.map(|sum| sum as f32 / count / 255.),
+        psnr,
+        differing_pixels,
+        heatmap: ImageData {
+            width: a.width,
+            height: a.height,
+            format: glow::RGBA,
+            alpha: AlphaMode::Opaque,
+            pixels: heatmap,
+        },
+    }
+}
+
+/// The pixels of an image as RGBA, with RGB images made opaque
+fn rgba_pixels(image: &ImageData) -> impl Iterator<Item = [u8; 4]> + '_ {
+    let channels = image.channels();
+    image.pixels.chunks_exact(channels).map(move |pixel| {
+        if channels == 4 {
+            [pixel[0], pixel[1], pixel[2], pixel[3]]
+        } else {
+            [pixel[0], pixel[1], pixel[2], 255]
+        }
+    })
+}
+
+/// A color from blue through green and yellow to red for `amount` from 0 to 1
+fn heat_color(amount: f32) -> [u8; 3] {
+    let stops = [[0., 0., 1.], [0., 1., 0.], [1., 1., 0.], [1., 0., 0.]];
+    let position = amount.clamp(0., 1.) * (stops.len() - 1) as f32;
+    let index = (position as usize).min(stops.len() - 2);
+    let t = position - index as f32;
+    let (from, to) = (stops[index], stops[index + 1]);
+    let channel = |i: usize| ((from[i] + (to[i] - from[i]) * t) * 255.).round() as u8;
+    [channel(0), channel(1), channel(2)]
+}
+
+/// The two images of an A/B capture and how they differ
+#[derive(Debug, Clone)]
+pub struct AbCapture {
+    pub before: ImageData,
+    pub after: ImageData,
+    pub report: DiffReport,
+}
+
+impl AbCapture {
+    /// Save the two images and the heatmap as `<prefix>-a.png`, `<prefix>-b.png`, and
+    /// `<prefix>-diff.png`, returning the paths they were saved to
+    pub fn save(&self, prefix: &str) -> image::ImageResult<[String; 3]> {
+        let paths = [
+            format!("{}-a.png", prefix),
+            format!("{}-b.png", prefix),
+            format!("{}-diff.png", prefix),
+        ];
+        save_png(&self.before, &paths[0])?;
+        save_png(&self.after, &paths[1])?;
+        save_png(&self.report.heatmap, &paths[2])?;
+        Ok(paths)
+    }
+}
+
+/// Draw the same frame through two code paths and compare them
+///
+/// `before` and `after` each draw into `framebuffer`, which is read back as RGBA after each of
+/// them. Whatever they change, like a setting that `after` toggles, should be the only
+/// difference between the two, so anything that animates has to be drawn at the same time in
+/// both.
+pub fn capture_ab(
+    gl: &mut glow::Context,
+    framebuffer: Option<u32>,
+    size: (u32, u32),
+    before: impl FnOnce(&mut glow::Context),
+    after: impl FnOnce(&mut glow::Context),
+) -> AbCapture {
+    before(gl);
+    let before = read_framebuffer(gl, framebuffer, size);
+    after(gl);
+    let after = read_framebuffer(gl, framebuffer, size);
+    let report = image_diff(&before, &after);
+    AbCapture {
+        before,
+        after,
+        report,
+    }
+}
+
+/// Read the color of a framebuffer back as opaque RGBA, starting at the bottom left
+///
+/// The alpha is made opaque since what a window shows doesn't depend on it. This waits for the
+/// GPU to finish drawing, so it is only for captures and not for every frame.
+pub fn read_framebuffer(
+    gl: &mut glow::Context,
+    framebuffer: Option<u32>,
+    (width, height): (u32, u32),
+) -> ImageData {
+    let mut pixels = vec![0u8; width as usize * height as usize * 4];
+    unsafe {
+        gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+        gl.read_pixels(
+            0,
+            0,
+            width as i32,
+            height as i32,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            glow::PixelPackData::Slice(&mut pixels),
+        );
+    }
+    for alpha in pixels.iter_mut().skip(3).step_by(4) {
+        *alpha = 255;
+    }
+    ImageData {
+        width,
+        height,
+        format: glow::RGBA,
+        alpha: AlphaMode::Opaque,
+        pixels,
+    }
+}
+
+/// Save an image to a PNG file, flipping it to start at the top left like image files do
+pub fn save_png<P: AsRef<Path>>(image: &ImageData, path: P) -> image::ImageResult<()> {
+    let row_length = image.width as usize * image.channels();
+    let mut rows = Vec::with_capacity(image.pixels.len());
+    for row in image.pixels.chunks(row_length.max(1)).rev() {
+        rows.extend_from_slice(row);
+    }
+    let color_type = if image.channels() == 4 {
+        image::ColorType::Rgba8
+    } else {
+        image::ColorType::Rgb8
+    };
+    image::save_buffer(path, &rows, image.width, image.height, color_type)
+}
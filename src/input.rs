@@ -0,0 +1,354 @@
+use std::{
+    collections::HashSet,
+    io::{self, Read, Write},
+};
+
+use winit::{
+    DeviceEvent, ElementState, KeyboardInput, ModifiersState, MouseButton, VirtualKeyCode,
+    WindowEvent,
+};
+
+use crate::virtual_resolution::VirtualViewport;
+
+/// The keyboard and mouse state for a single window
+///
+/// The state is updated by the window loop from the window's events and is valid for the duration
+/// of a frame. Keys and buttons that were pressed since the last frame are also recorded so that
+/// handlers can react to presses without tracking the previous state themselves.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Input {
+    /// The keys that are currently held down
+    pressed_keys: HashSet<VirtualKeyCode>,
+    /// The keys that were pressed since the last frame
+    just_pressed_keys: HashSet<VirtualKeyCode>,
+    /// The mouse buttons that are currently held down
+    pressed_buttons: HashSet<MouseButton>,
+    /// The mouse buttons that were pressed since the last frame
+    just_pressed_buttons: HashSet<MouseButton>,
+    /// The cursor position in physical pixels from the top-left of the window, if the cursor is in
+    /// the window
+    cursor_position: Option<(f64, f64)>,
+    /// The raw mouse movement since the last frame
+    mouse_delta: (f64, f64),
+    /// The scroll wheel movement since the last frame in lines
+    scroll_delta: (f32, f32),
+    /// The state of the modifier keys
+    modifiers: ModifiersState,
+    /// Whether or not the window has keyboard focus
+    focused: bool,
+    /// Where the image is in the window when drawing at a virtual resolution, which the cursor
+    /// position is mapped through
+    virtual_viewport: Option<VirtualViewport>,
+}
+
+impl Input {
+    /// Whether or not the key is currently held down
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed_keys.contains(&key)
+    }
+
+    /// Whether or not the key was pressed since the last frame
+    pub fn was_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.just_pressed_keys.contains(&key)
+    }
+
+    /// Whether or not the mouse button is currently held down
+    pub fn is_mouse_pressed(&self, button: MouseButton) -> bool {
+        self.pressed_buttons.contains(&button)
+    }
+
+    /// Whether or not the mouse button was pressed since the last frame
+    pub fn was_mouse_pressed(&self, button: MouseButton) -> bool {
+        self.just_pressed_buttons.contains(&button)
+    }
+
+    /// The cursor position in physical pixels from the top-left of the window, or in virtual
+    /// pixels from the top-left of the image when drawing at a virtual resolution
+    ///
+    /// Returns `None` when the cursor is outside of the window, or in the bars around a virtual
+    /// resolution.
+    pub fn cursor_position(&self) -> Option<(f64, f64)> {
+        match &self.virtual_viewport {
+            Some(viewport) => viewport.to_virtual(self.cursor_position?),
+            None => self.cursor_position,
+        }
+    }
+
+    /// The cursor position in physical pixels from the top-left of the window, even when drawing
+    /// at a virtual resolution
+    pub fn window_cursor_position(&self) -> Option<(f64, f64)> {
+        self.cursor_position
+    }
+
+    pub(crate) fn set_virtual_viewport(&mut self, viewport: Option<VirtualViewport>) {
+        self.virtual_viewport = viewport;
+    }
+
+    /// The raw mouse movement since the last frame
+    pub fn mouse_delta(&self) -> (f64, f64) {
+        self.mouse_delta
+    }
+
+    /// The scroll wheel movement since the last frame in lines
+    pub fn scroll_delta(&self) -> (f32, f32) {
+        self.scroll_delta
+    }
+
+    /// The state of the modifier keys
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    /// Whether or not the window has keyboard focus
+    pub fn is_focused(&self) -> bool {
+        self.focused
+    }
+
+    /// Update the input state from an event sent to this window
+    pub(crate) fn handle_window_event(&mut self, event: &WindowEvent, hidpi_factor: f64) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state,
+                        modifiers,
+                        ..
+                    },
+                ..
+            } => {
+                self.modifiers = *modifiers;
+                match state {
+                    ElementState::Pressed => {
+                        // Key repeat sends presses for keys that are already down, which we don't
+                        // want to count as new presses
+                        if self.pressed_keys.insert(*key) {
+                            self.just_pressed_keys.insert(*key);
+                        }
+                    }
+                    ElementState::Released => {
+                        self.pressed_keys.remove(key);
+                    }
+                }
+            }
+            WindowEvent::MouseInput {
+                state,
+                button,
+                modifiers,
+                ..
+            } => {
+                self.modifiers = *modifiers;
+                self.set_mouse_button(*button, *state == ElementState::Pressed);
+            }
+            WindowEvent::CursorMoved {
+                position,
+                modifiers,
+                ..
+            } => {
+                self.modifiers = *modifiers;
+                let position = position.to_physical(hidpi_factor);
+                self.set_cursor_position(Some((position.x, position.y)));
+            }
+            WindowEvent::CursorLeft { .. } => self.set_cursor_position(None),
+            WindowEvent::MouseWheel { delta, .. } => {
+                let (x, y) = match delta {
+                    winit::MouseScrollDelta::LineDelta(x, y) => (*x, *y),
+                    // Roughly convert pixels to lines so that trackpads and wheels feel the same
+                    winit::MouseScrollDelta::PixelDelta(position) => {
+                        (position.x as f32 / 20., position.y as f32 / 20.)
+                    }
+                };
+                self.scroll_delta.0 += x;
+                self.scroll_delta.1 += y;
+            }
+            WindowEvent::Focused(focused) => {
+                self.focused = *focused;
+                // We won't get release events for keys released while we are unfocused
+                if !focused {
+                    self.pressed_keys.clear();
+                    self.pressed_buttons.clear();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Press or release a mouse button, like a `MouseInput` event does, e.g. to build a recording
+    pub(crate) fn set_mouse_button(&mut self, button: MouseButton, pressed: bool) {
+        if pressed {
+            self.pressed_buttons.insert(button);
+            self.just_pressed_buttons.insert(button);
+        } else {
+            self.pressed_buttons.remove(&button);
+        }
+    }
+
+    /// Move the cursor to a position in physical pixels from the top-left of the window, or out of
+    /// the window with `None`
+    pub(crate) fn set_cursor_position(&mut self, position: Option<(f64, f64)>) {
+        self.cursor_position = position;
+    }
+
+    /// Update the input state from a raw device event
+    ///
+    /// Device events aren't associated with any window so the loop only forwards them to the
+    /// focused window.
+    pub(crate) fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta.0 += delta.0;
+            self.mouse_delta.1 += delta.1;
+        }
+    }
+
+    /// Let go of every key, e.g. when the console takes over the keyboard and won't pass on the
+    /// releases
+    pub(crate) fn release_keys(&mut self) {
+        self.pressed_keys.clear();
+        self.just_pressed_keys.clear();
+    }
+
+    /// Reset the state that only lasts for one frame
+    pub(crate) fn end_frame(&mut self) {
+        self.just_pressed_keys.clear();
+        self.just_pressed_buttons.clear();
+        self.mouse_delta = (0., 0.);
+        self.scroll_delta = (0., 0.);
+    }
+
+    /// Write the whole input state in a compact binary form
+    pub(crate) fn write_snapshot<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        // Sort the keys and buttons so the same state always writes the same bytes
+        let write_keys = |writer: &mut W, keys: &HashSet<VirtualKeyCode>| {
+            let mut keys = keys.iter().map(|&key| key as u32).collect::<Vec<_>>();
+            keys.sort_unstable();
+            writer.write_all(&(keys.len() as u16).to_le_bytes())?;
+            keys.iter()
+                .try_for_each(|key| writer.write_all(&key.to_le_bytes()))
+        };
+        let write_buttons = |writer: &mut W, buttons: &HashSet<MouseButton>| {
+            let mut buttons = buttons.iter().map(|&b| button_code(b)).collect::<Vec<_>>();
+            buttons.sort_unstable();
+            writer.write_all(&(buttons.len() as u16).to_le_bytes())?;
+            buttons
+                .iter()
+                .try_for_each(|button| writer.write_all(&button.to_le_bytes()))
+        };
+
+        write_keys(writer, &self.pressed_keys)?;
+        write_keys(writer, &self.just_pressed_keys)?;
+        write_buttons(writer, &self.pressed_buttons)?;
+        write_buttons(writer, &self.just_pressed_buttons)?;
+
+        let (cursor_x, cursor_y) = self.cursor_position.unwrap_or((0., 0.));
+        writer.write_all(&[self.cursor_position.is_some() as u8])?;
+        writer.write_all(&cursor_x.to_le_bytes())?;
+        writer.write_all(&cursor_y.to_le_bytes())?;
+        writer.write_all(&self.mouse_delta.0.to_le_bytes())?;
+        writer.write_all(&self.mouse_delta.1.to_le_bytes())?;
+        writer.write_all(&self.scroll_delta.0.to_le_bytes())?;
+        writer.write_all(&self.scroll_delta.1.to_le_bytes())?;
+
+        let modifiers = self.modifiers;
+        writer.write_all(&[
+            modifiers.shift as u8
+                | (modifiers.ctrl as u8) << 1
+                | (modifiers.alt as u8) << 2
+                | (modifiers.logo as u8) << 3,
+            self.focused as u8,
+        ])
+    }
+
+    /// Read an input state written by `write_snapshot`
+    pub(crate) fn read_snapshot<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+        let read_keys = |reader: &mut R| -> io::Result<HashSet<VirtualKeyCode>> {
+            (0..read_u16(reader)?)
+                .map(|_| key_from_code(read_u32(reader)?).ok_or_else(|| invalid("Unknown key")))
+                .collect()
+        };
+        let read_buttons = |reader: &mut R| -> io::Result<HashSet<MouseButton>> {
+            (0..read_u16(reader)?)
+                .map(|_| Ok(button_from_code(read_u16(reader)?)))
+                .collect()
+        };
+
+        let pressed_keys = read_keys(reader)?;
+        let just_pressed_keys = read_keys(reader)?;
+        let pressed_buttons = read_buttons(reader)?;
+        let just_pressed_buttons = read_buttons(reader)?;
+
+        let has_cursor = read_bytes::<_, 1>(reader)?[0] != 0;
+        let cursor = (read_f64(reader)?, read_f64(reader)?);
+        let mouse_delta = (read_f64(reader)?, read_f64(reader)?);
+        let scroll_delta = (
+            f32::from_le_bytes(read_bytes(reader)?),
+            f32::from_le_bytes(read_bytes(reader)?),
+        );
+
+        let [modifiers, focused] = read_bytes(reader)?;
+        Ok(Self {
+            pressed_keys,
+            just_pressed_keys,
+            pressed_buttons,
+            just_pressed_buttons,
+            cursor_position: if has_cursor { Some(cursor) } else { None },
+            mouse_delta,
+            scroll_delta,
+            modifiers: ModifiersState {
+                shift: modifiers & 1 != 0,
+                ctrl: modifiers & 2 != 0,
+                alt: modifiers & 4 != 0,
+                logo: modifiers & 8 != 0,
+            },
+            focused: focused != 0,
+            virtual_viewport: None,
+        })
+    }
+}
+
+/// The number used for a mouse button in input snapshots
+fn button_code(button: MouseButton) -> u16 {
+    match button {
+        MouseButton::Left => 0,
+        MouseButton::Right => 1,
+        MouseButton::Middle => 2,
+        MouseButton::Other(other) => 256 + other as u16,
+    }
+}
+
+fn button_from_code(code: u16) -> MouseButton {
+    match code {
+        0 => MouseButton::Left,
+        1 => MouseButton::Right,
+        2 => MouseButton::Middle,
+        other => MouseButton::Other((other - 256) as u8),
+    }
+}
+
+/// Get the key with the given `VirtualKeyCode as u32` value
+fn key_from_code(code: u32) -> Option<VirtualKeyCode> {
+    // The key codes are a `repr(u32)` enum numbered from zero, and `Cut` is the last one
+    if code <= VirtualKeyCode::Cut as u32 {
+        Some(unsafe { std::mem::transmute::<u32, VirtualKeyCode>(code) })
+    } else {
+        None
+    }
+}
+
+pub(crate) fn read_bytes<R: Read, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let mut bytes = [0; N];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> io::Result<u16> {
+    Ok(u16::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    Ok(u32::from_le_bytes(read_bytes(reader)?))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> io::Result<f64> {
+    Ok(f64::from_le_bytes(read_bytes(reader)?))
+}
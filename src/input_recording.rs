@@ -0,0 +1,102 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+    time::Duration,
+};
+
+use crate::input::{read_bytes, Input};
+
+/// The bytes at the start of every input recording, including a format version
+const MAGIC: &[u8; 8] = b"MLGLINP1";
+
+/// Writes the input of every frame of a window to a file, along with the frame times
+///
+/// Recordings can be played back with `InputPlayer` to reproduce a session exactly. The loop does
+/// the recording when `WindowConfig::record_input` is set.
+#[derive(Debug)]
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+}
+
+impl InputRecorder {
+    /// Start a new recording, replacing the file if it exists
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(MAGIC)?;
+        Ok(Self { writer })
+    }
+
+    /// Record one frame
+    pub fn record(&mut self, delta: Duration, input: &Input) -> io::Result<()> {
+        self.writer
+            .write_all(&(delta.as_nanos() as u64).to_le_bytes())?;
+        input.write_snapshot(&mut self.writer)
+    }
+
+    /// Write out anything that is still buffered
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Plays back the frames of a recording made with `InputRecorder`
+///
+/// The loop does the playback when `WindowConfig::replay_input` is set. Each frame gets the
+/// recorded input instead of the live input and the recorded frame time instead of the real one,
+/// so anything driven by the input and `Timing` behaves exactly like it did while recording.
+#[derive(Debug)]
+pub struct InputPlayer {
+    /// The frame time and input of each frame
+    frames: Vec<(Duration, Input)>,
+    /// The index of the next frame to play
+    next: usize,
+}
+
+impl InputPlayer {
+    /// Load a whole recording
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::read(BufReader::new(File::open(path)?))
+    }
+
+    /// Load a whole recording from a reader, like the bytes of one built into the program
+    pub fn read<R: Read>(mut reader: R) -> io::Result<Self> {
+        if &read_bytes::<_, 8>(&mut reader)? != MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Not an input recording",
+            ));
+        }
+
+        // Read frames until the file runs out
+        let mut frames = Vec::new();
+        loop {
+            let mut delta = [0; 8];
+            match reader.read(&mut delta[..1])? {
+                0 => break,
+                _ => reader.read_exact(&mut delta[1..])?,
+            }
+            let delta = Duration::from_nanos(u64::from_le_bytes(delta));
+            frames.push((delta, Input::read_snapshot(&mut reader)?));
+        }
+
+        Ok(Self { frames, next: 0 })
+    }
+
+    /// The number of frames in the recording
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Whether or not every frame has been played
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.frames.len()
+    }
+
+    /// Get the frame time and input of the next frame, or `None` at the end of the recording
+    pub fn next_frame(&mut self) -> Option<(Duration, Input)> {
+        let frame = self.frames.get(self.next).cloned()?;
+        self.next += 1;
+        Some(frame)
+    }
+}
@@ -0,0 +1,261 @@
+use std::time::{Duration, Instant};
+
+use glow::HasContext;
+
+use crate::{
+    features::Features,
+    resources::{self, ResourceKind},
+    vertex::VertexLayout,
+};
+
+/// How many buffers a `Dynamic` instance buffer cycles through by default, which gives the GPU two
+/// frames to finish drawing from a buffer before it is written again
+pub const DEFAULT_BUFFER_COUNT: usize = 3;
+
+/// How long to wait for the GPU in one go before asking again, in nanoseconds
+const FENCE_TIMEOUT: i32 = 100_000_000;
+
+/// How often the contents of a buffer change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BufferUsage {
+    /// Written once, or rarely, and drawn many times
+    Static,
+    /// Written every frame
+    ///
+    /// Writing to a buffer that the GPU is still drawing from makes the driver wait for it, so
+    /// dynamic buffers cycle through several buffers, writing into one while the GPU reads the
+    /// others. Fences tell when the GPU is done with a buffer. Without `glBufferStorage` there is
+    /// one buffer instead, which is orphaned before every write so the driver can hand out fresh
+    /// memory.
+    Dynamic,
+}
+
+/// Counts of how often writing to an instance buffer had to wait for the GPU
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct InstanceBufferStats {
+    /// The writes that found their buffer still in use by the GPU
+    pub waits: u64,
+    /// The total time spent waiting
+    pub wait_time: Duration,
+    /// How many times the buffers were recreated to make room for more data
+    pub reallocations: u64,
+}
+
+/// One of the buffers that a dynamic instance buffer cycles through
+#[derive(Debug)]
+struct Slot {
+    vbo: u32,
+    /// Signalled once the GPU has finished the draws that read the buffer
+    fence: Option<glow::Fence>,
+}
+
+/// A buffer of per-instance data that is written with `write` and attached to a VAO with `bind`
+///
+/// The layout should be `per_instance`. Callers draw from it the same way whatever its usage is:
+/// write the instances, bind the VAO with the mesh's attributes, `bind` the instance buffer, and
+/// make an instanced draw call.
+#[derive(Debug)]
+pub struct InstanceBuffer {
+    layout: VertexLayout,
+    usage: BufferUsage,
+    /// Whether or not the buffers have immutable storage and are guarded by fences, instead of
+    /// being orphaned
+    fenced: bool,
+    /// How many buffers to cycle through
+    buffer_count: usize,
+    /// The buffers, which are created by the first write
+    slots: Vec<Slot>,
+    /// The buffer that was written last and is drawn from
+    current: usize,
+    /// The size of each buffer in bytes
+    capacity: usize,
+    /// The bytes written by the last write
+    len: usize,
+    label: String,
+    stats: InstanceBufferStats,
+}
+
+impl InstanceBuffer {
+    /// An empty instance buffer holding instances laid out like `layout`
+    pub fn new(features: &Features, layout: VertexLayout, usage: BufferUsage) -> Self {
+        let fenced = usage == BufferUsage::Dynamic && features.buffer_storage;
+        Self {
+            layout,
+            usage,
+            fenced,
+            buffer_count: if fenced { DEFAULT_BUFFER_COUNT } else { 1 },
+            slots: Vec::new(),
+            current: 0,
+            capacity: 0,
+            len: 0,
+            label: "Instance buffer".into(),
+            stats: InstanceBufferStats::default(),
+        }
+    }
+
+    /// Cycle through a different number of buffers, which only makes a difference for `Dynamic`
+    /// buffers in contexts with `glBufferStorage`. One buffer makes every write wait for the GPU,
+    /// which is useful to compare against.
+    pub fn with_buffer_count(mut self, buffer_count: usize) -> Self {
+        if self.fenced {
+            self.buffer_count = buffer_count.max(1);
+        }
+        self
+    }
+
+    /// Name the buffers in the resource list
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = label.into();
+        self
+    }
+
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    pub fn label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn usage(&self) -> BufferUsage {
+        self.usage
+    }
+
+    /// How many buffers are cycled through
+    pub fn buffer_count(&self) -> usize {
+        self.buffer_count
+    }
+
+    /// Whether or not fences guard the buffers, as opposed to orphaning them
+    pub fn is_fenced(&self) -> bool {
+        self.fenced
+    }
+
+    /// The number of instances written by the last write
+    pub fn instance_count(&self) -> usize {
+        self.len / self.layout.stride().max(1)
+    }
+
+    pub fn stats(&self) -> InstanceBufferStats {
+        self.stats
+    }
+
+    /// Replace the instances, moving on to the next buffer if the buffer is `Dynamic`
+    ///
+    /// This leaves `ARRAY_BUFFER` unbound.
+    pub fn write(&mut self, gl: &mut glow::Context, data: &[u8]) {
+        unsafe {
+            if self.fenced && !self.slots.is_empty() {
+                // Everything that reads the current buffer has been sent by now, so fence it
+                // before moving on to the next one
+                let slot = &mut self.slots[self.current];
+                if let Some(fence) = slot.fence.take() {
+                    gl.delete_sync(fence);
+                }
+                slot.fence = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).ok();
+                self.current = (self.current + 1) % self.slots.len();
+            }
+
+            if data.len() > self.capacity || self.slots.len() != self.buffer_count {
+                self.reallocate(gl, data.len());
+            } else if self.fenced {
+                self.wait(gl);
+            }
+
+            let slot = &self.slots[self.current];
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(slot.vbo));
+            if self.usage == BufferUsage::Dynamic && !self.fenced {
+                // Orphan the old contents, which the GPU may still be reading
+                gl.buffer_data_size(glow::ARRAY_BUFFER, self.capacity as i32, glow::STREAM_DRAW);
+            }
+            if !data.is_empty() {
+                gl.buffer_sub_data_u8_slice(glow::ARRAY_BUFFER, 0, data);
+            }
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        }
+        self.len = data.len();
+    }
+
+    /// Wait until the GPU has finished reading the current buffer
+    unsafe fn wait(&mut self, gl: &mut glow::Context) {
+        let fence = match self.slots[self.current].fence.take() {
+            Some(fence) => fence,
+            None => return,
+        };
+        let mut status = gl.client_wait_sync(fence, 0, 0);
+        if status == glow::TIMEOUT_EXPIRED {
+            let start = Instant::now();
+            while status == glow::TIMEOUT_EXPIRED {
+                status = gl.client_wait_sync(fence, glow::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT);
+            }
+            self.stats.waits += 1;
+            self.stats.wait_time += start.elapsed();
+        }
+        if status == glow::WAIT_FAILED {
+            eprintln!("Warning: Waiting for an instance buffer fence failed");
+        }
+        gl.delete_sync(fence);
+    }
+
+    /// Replace the buffers with ones that hold at least `size` bytes
+    unsafe fn reallocate(&mut self, gl: &mut glow::Context, size: usize) {
+        if !self.slots.is_empty() {
+            self.stats.reallocations += 1;
+        }
+        // Deleting a buffer the GPU is still reading is fine, GL keeps it alive until it is done
+        self.delete(gl);
+        self.capacity = size.max(self.capacity).max(1).next_power_of_two();
+        self.current = 0;
+
+        for _ in 0..self.buffer_count {
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            if self.fenced {
+                gl.buffer_storage(
+                    glow::ARRAY_BUFFER,
+                    self.capacity as i32,
+                    None,
+                    glow::DYNAMIC_STORAGE_BIT,
+                );
+            } else {
+                gl.buffer_data_size(
+                    glow::ARRAY_BUFFER,
+                    self.capacity as i32,
+                    match self.usage {
+                        BufferUsage::Static => glow::STATIC_DRAW,
+                        BufferUsage::Dynamic => glow::STREAM_DRAW,
+                    },
+                );
+            }
+            resources::track_sized(ResourceKind::Buffer, vbo, &self.label, self.capacity as u64);
+            self.slots.push(Slot { vbo, fence: None });
+        }
+    }
+
+    /// Attach the buffer that was written last to the bound VAO
+    ///
+    /// A dynamic buffer is a different buffer every frame, so this has to be called after every
+    /// `write`, before drawing. This leaves the buffer bound to `ARRAY_BUFFER`.
+    pub fn bind(&self, gl: &mut glow::Context) {
+        let vbo = self.slots.get(self.current).map(|slot| slot.vbo);
+        unsafe { gl.bind_buffer(glow::ARRAY_BUFFER, vbo) };
+        if vbo.is_some() {
+            self.layout.apply(gl);
+        }
+    }
+
+    /// Delete the buffers and their fences
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for slot in self.slots.drain(..) {
+            unsafe {
+                if let Some(fence) = slot.fence {
+                    gl.delete_sync(fence);
+                }
+                gl.delete_buffer(slot.vbo);
+            }
+            resources::untrack(ResourceKind::Buffer, slot.vbo);
+        }
+        self.capacity = 0;
+        self.len = 0;
+    }
+}
@@ -0,0 +1,282 @@
+use std::{
+    collections::VecDeque,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use glow::HasContext;
+
+use crate::timing::AVERAGE_WEIGHT;
+
+/// How long to wait for the GPU in one go before asking again, in nanoseconds
+const FENCE_TIMEOUT: i32 = 100_000_000;
+
+/// How many presented frames can wait for the GPU before the oldest is given up on, which only
+/// happens if the driver queues far more frames than it should
+const MAX_PENDING_FRAMES: usize = 8;
+
+/// How the loop trades throughput for the time between input and the frame that shows it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LatencyMode {
+    /// Let the driver queue up frames behind the one on screen, and read input right after
+    /// presenting. The GPU never waits for the CPU, but input waits behind every queued frame.
+    #[default]
+    Throughput,
+    /// Before drawing, wait for the GPU to finish the last frame and read input again after
+    /// waiting, so only one frame is ever queued and it is drawn with the newest input. This
+    /// costs a little throughput, since the CPU and GPU take turns instead of overlapping.
+    LowLatency,
+}
+
+impl LatencyMode {
+    /// The mode with the given name, `throughput` or `low_latency`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "throughput" => Some(LatencyMode::Throughput),
+            "low_latency" | "low-latency" | "low" => Some(LatencyMode::LowLatency),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LatencyMode::Throughput => "throughput",
+            LatencyMode::LowLatency => "low_latency",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            LatencyMode::Throughput => LatencyMode::LowLatency,
+            LatencyMode::LowLatency => LatencyMode::Throughput,
+        }
+    }
+}
+
+impl fmt::Display for LatencyMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            LatencyMode::Throughput => "throughput",
+            LatencyMode::LowLatency => "low latency",
+        })
+    }
+}
+
+/// Where the time went between input and the GPU finishing the frame that used it, as rolling
+/// averages in milliseconds
+///
+/// The display adds up to one more refresh on top of `input_to_gpu` to scan the frame out, which
+/// GL can't see, so the latency is an estimate that is a little short.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LatencyStats {
+    /// From the oldest input event a frame read to the start of its `draw`
+    pub input_to_draw: f64,
+    /// From the start of `draw` to presenting the frame, which includes the loop's overlays
+    pub draw_to_present: f64,
+    /// Presenting the frame, which may wait for vsync or for a free buffer to draw into
+    pub present: f64,
+    /// From presenting the frame to the GPU finishing it
+    pub present_to_gpu: f64,
+    /// From the oldest input event a frame read to the GPU finishing the frame, which is the
+    /// estimated latency. Frames without input count from the start of their `draw`, which is
+    /// when input that just missed the last frame would have been read.
+    pub input_to_gpu: f64,
+    /// How long the loop waited for the last frame before reading input, in `LowLatency` mode
+    pub wait: f64,
+    /// How many frames the GPU hadn't finished when the last one was presented, including it
+    pub frames_in_flight: usize,
+}
+
+impl fmt::Display for LatencyStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "latency {:.1} ms ( input {:.1} / draw {:.1} / present {:.1} / gpu {:.1} ), {} queued",
+            self.input_to_gpu,
+            self.input_to_draw,
+            self.draw_to_present,
+            self.present,
+            self.present_to_gpu,
+            self.frames_in_flight,
+        )
+    }
+}
+
+/// A presented frame that the GPU may still be working on
+#[derive(Debug)]
+struct PendingFrame {
+    fence: glow::Fence,
+    input: Option<Instant>,
+    draw_start: Instant,
+    present_start: Instant,
+    present_end: Instant,
+}
+
+/// Times the frames of a window from the input they read to the GPU finishing them
+///
+/// The loop calls `record_input` for every input event, `begin_draw` before the handler draws,
+/// and `begin_present` and `end_present` around presenting, which puts a fence after the frame's
+/// commands. The fences are checked without waiting at the start of each frame, so in
+/// `Throughput` mode a frame is only seen to be finished up to a frame late, and the GPU times
+/// are a little long. In `LowLatency` mode the loop waits on them with `wait_for_last_frame`,
+/// which sees them finish straight away.
+#[derive(Debug, Default)]
+pub(crate) struct FrameLatency {
+    /// The oldest input event that no frame has read yet
+    first_input: Option<Instant>,
+    /// The oldest input event of the frame being drawn, and when its `draw` started
+    frame_input: Option<Instant>,
+    draw_start: Option<Instant>,
+    present_start: Option<Instant>,
+    pending: VecDeque<PendingFrame>,
+    stats: Option<LatencyStats>,
+    /// How long the last wait for the GPU took, until it is added to the stats with its frame
+    last_wait: Duration,
+}
+
+impl FrameLatency {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// The averages of the finished frames, or `None` before the first one finished
+    pub(crate) fn stats(&self) -> Option<LatencyStats> {
+        self.stats
+    }
+
+    /// Whether any presented frames may still be on the GPU
+    pub(crate) fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Note that an input event arrived, which the next frame will read
+    pub(crate) fn record_input(&mut self) {
+        self.first_input.get_or_insert_with(Instant::now);
+    }
+
+    /// Start timing a frame, right before the handler draws it
+    pub(crate) fn begin_draw(&mut self) {
+        self.draw_start = Some(Instant::now());
+        self.frame_input = self.first_input.take();
+    }
+
+    /// Put a fence after the frame's commands, right before presenting it
+    pub(crate) fn begin_present(&mut self, gl: &mut glow::Context) {
+        let present_start = Instant::now();
+        self.present_start = Some(present_start);
+        if self.pending.len() >= MAX_PENDING_FRAMES {
+            if let Some(frame) = self.pending.pop_front() {
+                unsafe { gl.delete_sync(frame.fence) };
+            }
+        }
+        if let Some(draw_start) = self.draw_start {
+            if let Ok(fence) = unsafe { gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0) } {
+                self.pending.push_back(PendingFrame {
+                    fence,
+                    input: self.frame_input,
+                    draw_start,
+                    present_start,
+                    present_end: present_start,
+                });
+            }
+        }
+    }
+
+    /// Finish timing the frame's present
+    pub(crate) fn end_present(&mut self) {
+        let now = Instant::now();
+        if let Some(frame) = self.pending.back_mut() {
+            if Some(frame.present_start) == self.present_start {
+                frame.present_end = now;
+            }
+        }
+        self.draw_start = None;
+        self.present_start = None;
+        let in_flight = self.pending.len();
+        if let Some(stats) = &mut self.stats {
+            stats.frames_in_flight = in_flight;
+        }
+    }
+
+    /// Add the frames that the GPU has finished to the stats, without waiting for any
+    pub(crate) fn poll(&mut self, gl: &mut glow::Context) {
+        while let Some(frame) = self.pending.front() {
+            if unsafe { gl.get_sync_status(frame.fence) } != glow::SIGNALED {
+                break;
+            }
+            self.finish_oldest(gl, Instant::now());
+        }
+    }
+
+    /// Wait for the GPU to finish the last presented frame, and every frame before it
+    ///
+    /// Returns whether there was a frame to wait for.
+    pub(crate) fn wait_for_last_frame(&mut self, gl: &mut glow::Context) -> bool {
+        let fence = match self.pending.back() {
+            Some(frame) => frame.fence,
+            None => return false,
+        };
+        let start = Instant::now();
+        let status = unsafe {
+            let mut status = gl.client_wait_sync(fence, glow::SYNC_FLUSH_COMMANDS_BIT, 0);
+            while status == glow::TIMEOUT_EXPIRED {
+                status = gl.client_wait_sync(fence, glow::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT);
+            }
+            status
+        };
+        if status == glow::WAIT_FAILED {
+            eprintln!("Warning: Waiting for the last frame's fence failed");
+        }
+        let now = Instant::now();
+        self.last_wait = now - start;
+        // Fences finish in order, so the frames before the last one are done too
+        while !self.pending.is_empty() {
+            self.finish_oldest(gl, now);
+        }
+        true
+    }
+
+    /// Delete the fences
+    pub(crate) fn delete(&mut self, gl: &mut glow::Context) {
+        for frame in self.pending.drain(..) {
+            unsafe { gl.delete_sync(frame.fence) };
+        }
+    }
+
+    /// Add the oldest pending frame to the stats, as finished at `gpu_done`
+    fn finish_oldest(&mut self, gl: &mut glow::Context, gpu_done: Instant) {
+        let frame = match self.pending.pop_front() {
+            Some(frame) => frame,
+            None => return,
+        };
+        unsafe { gl.delete_sync(frame.fence) };
+
+        let ms = |from: Instant, to: Instant| (to - from).as_secs_f64() * 1000.;
+        let newest = LatencyStats {
+            input_to_draw: frame.input.map_or(0., |input| ms(input, frame.draw_start)),
+            draw_to_present: ms(frame.draw_start, frame.present_start),
+            present: ms(frame.present_start, frame.present_end),
+            present_to_gpu: ms(frame.present_end, gpu_done.max(frame.present_end)),
+            input_to_gpu: ms(frame.input.unwrap_or(frame.draw_start), gpu_done),
+            wait: std::mem::take(&mut self.last_wait).as_secs_f64() * 1000.,
+            frames_in_flight: self.pending.len() + 1,
+        };
+        self.stats = Some(match self.stats {
+            Some(average) => {
+                let blend = |average: f64, newest: f64| {
+                    average * (1. - AVERAGE_WEIGHT) + newest * AVERAGE_WEIGHT
+                };
+                LatencyStats {
+                    input_to_draw: blend(average.input_to_draw, newest.input_to_draw),
+                    draw_to_present: blend(average.draw_to_present, newest.draw_to_present),
+                    present: blend(average.present, newest.present),
+                    present_to_gpu: blend(average.present_to_gpu, newest.present_to_gpu),
+                    input_to_gpu: blend(average.input_to_gpu, newest.input_to_gpu),
+                    wait: blend(average.wait, newest.wait),
+                    frames_in_flight: average.frames_in_flight,
+                }
+            }
+            None => newest,
+        });
+    }
+}
@@ -1,17 +1,134 @@
-use surfman::{
-    Connection, ContextAttributeFlags, ContextAttributes, GLVersion, SurfaceAccess, SurfaceType,
-};
-use winit::{
-    dpi::PhysicalSize, DeviceEvent, Event, EventsLoop, KeyboardInput, VirtualKeyCode,
-    WindowBuilder, WindowEvent,
-};
-
 surfman::declare_surfman!();
 
+pub mod anti_aliasing;
+mod app_context;
+pub mod asset_manifest;
+#[cfg(feature = "audio")]
+pub mod audio;
+pub mod auto_exposure;
+pub mod batch;
+pub mod blend;
+pub mod blit;
+pub mod bvh;
+pub mod camera;
+pub mod camera_path;
+pub mod character_controller;
+pub mod cli;
+pub mod clipboard;
+pub mod clustered_lights;
+pub mod collision;
+pub mod color;
+pub mod config;
+pub mod console;
+pub mod context_report;
+pub mod cursor;
+pub mod damage;
+pub mod debug_draw;
+pub mod debug_group;
+pub mod debug_text;
+pub mod demo_state;
+pub mod depth_mode;
+pub mod depth_view;
+pub mod diagnostics;
+pub mod draw_list;
+pub mod embedded;
+pub mod fallback_assets;
+pub mod features;
+pub mod frame_arena;
+pub mod frame_graph;
+pub mod frustum;
+pub mod gbuffer;
+pub mod gizmo;
+pub mod grid;
+pub mod handle;
+pub mod heightmap;
+pub mod image_diff;
+pub mod input;
+pub mod input_recording;
+pub mod instance_buffer;
+pub mod latency;
+pub mod lod;
+pub mod material;
+pub mod mesh;
+pub mod mesh_optimizer;
+pub mod mipmap;
+pub mod motion_blur;
+pub mod msaa_resolve;
+pub mod nested;
+pub mod outline;
+pub mod particles;
+pub mod per_draw;
+pub mod planar_reflection;
+pub mod point_shadow;
+pub mod pose;
+pub mod primitives;
+pub mod procedural;
+pub mod program_cache;
+pub mod readback;
+pub mod render_graph;
+pub mod render_settings;
+pub mod render_target;
+pub mod resources;
+pub mod selfcheck;
+pub mod shader;
+pub mod shader_test;
+pub mod shader_variants;
+pub mod simplify;
+pub mod ssao;
+pub mod stereo;
+pub mod terrain;
+pub mod texture;
+pub mod texture_audit;
+pub mod texture_debug;
+pub mod texture_streaming;
+pub mod texture_viewer;
+pub mod theme;
+pub mod tilemap;
+pub mod timeline;
+pub mod timing;
+pub mod tonemap;
+pub mod tween;
+pub mod upsample;
+pub mod vertex;
+pub mod viewport;
+pub mod virtual_resolution;
+mod window;
+pub mod window_placement;
+pub mod wireframe;
+pub mod workarounds;
+
+pub use app_context::AppContext;
+pub use cli::DemoArgs;
+pub use config::Config;
+pub use window::{
+    handler_factory, with_window, with_window_config, with_windows, with_windows_and_config,
+    HandlerFactory, WindowConfig,
+};
+
 pub trait RenderHandler {
-    fn init(gl: &mut glow::Context) -> Self;
-    fn draw(&mut self, _gl: &mut glow::Context) {}
-    fn exit(&mut self, _gl: &mut glow::Context) {}
+    fn init(gl: &mut glow::Context, ctx: &mut AppContext) -> Self
+    where
+        Self: Sized;
+    fn draw(&mut self, _gl: &mut glow::Context, _ctx: &mut AppContext) {}
+    fn exit(&mut self, _gl: &mut glow::Context, _ctx: &mut AppContext) {}
+    /// Called when the window surface or the whole GL context has been lost, before the loop tries
+    /// to recover it.
+    fn device_lost(&mut self, _gl: &mut glow::Context, _ctx: &mut AppContext) {}
+    /// Called after a lost window surface has been recreated and bound to the context again. If
+    /// the GL context itself was reset the handler is re-initialized with `init` instead.
+    fn device_restored(&mut self, _gl: &mut glow::Context, _ctx: &mut AppContext) {}
+    /// Add what the handler knows about its frame, like its passes and cameras, to a frame
+    /// report from `diagnostics::dump_frame_report`.
+    fn describe(&self, _report: &mut diagnostics::FrameReport) {}
+    /// Save what the handler keeps between runs, like its camera, to `AppContext::saved_state`
+    /// before the window closes. It is restored from there in `init`.
+    fn save_state(&self, _ctx: &mut AppContext) {}
+    /// Report what changed since the last frame with `AppContext::add_damage`, when
+    /// `RenderSettings::partial_redraw` is on. Called before the frame is cleared, and by default
+    /// marks the whole frame as changed.
+    fn damage(&mut self, ctx: &mut AppContext) {
+        ctx.damage_all();
+    }
 }
 
 pub trait SliceAsBytes<T> {
@@ -23,110 +140,40 @@ impl<T: AsRef<[U]>, U> SliceAsBytes<U> for T {
         unsafe {
             std::slice::from_raw_parts(
                 self.as_ref().as_ptr() as *const u8,
-                std::mem::size_of::<T>() * self.as_ref().len(),
+                std::mem::size_of_val(self.as_ref()),
             )
         }
     }
 }
 
-pub fn with_window<RndrHndlr: RenderHandler + 'static>() {
-    // Create the window event loop
-    let mut event_loop = EventsLoop::new();
-    // Obtain the screen scaling factor
-    let scale_factor = event_loop.get_primary_monitor().get_hidpi_factor();
-    // Create a new logical size for the window based on the desired physical size
-    let logical_size = PhysicalSize::new(800f64, 600f64).to_logical(scale_factor);
-    // Create a window
-    let window = WindowBuilder::new()
-        .with_title("Me Learning OpenGL")
-        .with_dimensions(logical_size)
-        .build(&event_loop)
-        .unwrap();
-
-    // Show the window
-    window.show();
-
-    // Create a connection to the graphics provider from our winit window
-    let conn = Connection::from_winit_window(&window).unwrap();
-    // Create a native widget to attach the visible render surface to
-    let native_widget = conn
-        .create_native_widget_from_winit_window(&window)
-        .unwrap();
-    // Create a hardware adapter that we can used to create graphics devices from
-    let adapter = conn.create_hardware_adapter().unwrap();
-    // Create a graphics device using our hardware adapter
-    let mut device = conn.create_device(&adapter).unwrap();
-
-    // Define the attributes for our OpenGL context
-    let context_attributes = ContextAttributes {
-        version: GLVersion::new(3, 3),
-        flags: ContextAttributeFlags::ALPHA
-            | ContextAttributeFlags::DEPTH
-            | ContextAttributeFlags::STENCIL,
-    };
-
-    // Create a context descriptor based on our defined context attributes
-    let context_descriptor = device
-        .create_context_descriptor(&context_attributes)
-        .unwrap();
-    // Define the surface type for our graphics surface ( a surface based on a native widget, i.e. not an offscreen surface )
-    let surface_type = SurfaceType::Widget { native_widget };
-    // Create an OpenGL context
-    let mut context = device.create_context(&context_descriptor, None).unwrap();
-
-    // Create a surface that can be accessed only from the GPU 
-    let surface = device
-        .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
-        .unwrap();
-
-    // Bind our surface to our create GL context
-    device
-        .bind_surface_to_context(&mut context, surface)
-        .unwrap();
-    // Make our context the current context
-    device.make_context_current(&context).unwrap();
-
-    // Get a pointer to the OpenGL functions
-    let mut gl = unsafe {
-        glow::Context::from_loader_function(|s| device.get_proc_address(&context, s) as *const _)
-    };
-
-    // Instantiate our rendering handler
-    let mut handler = RndrHndlr::init(&mut gl);
-
-    // Loop through render events
-    let mut exit = false;
-    while !exit {
-        // Draw the graphics
-        handler.draw(&mut gl);
-        let mut surface = device
-            .unbind_surface_from_context(&mut context)
-            .unwrap()
-            .unwrap();
-        device.present_surface(&context, &mut surface).unwrap();
-        device.bind_surface_to_context(&mut context, surface).unwrap();
+#[cfg(test)]
+mod tests {
+    use super::SliceAsBytes;
+
+    #[test]
+    fn f32_slice_bytes() {
+        let floats: &[f32] = &[0.5, -1., 2.25, f32::MAX, -0.];
+        let bytes = floats.as_mem_bytes();
+        assert_eq!(bytes.len(), 4 * floats.len());
+        let round_trip: Vec<f32> = bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_ne_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect();
+        let bits = |floats: &[f32]| floats.iter().map(|f| f.to_bits()).collect::<Vec<_>>();
+        assert_eq!(bits(&round_trip), bits(floats));
+    }
 
-        // Handle events
-        event_loop.poll_events(|event| match event {
-            Event::WindowEvent {
-                event: WindowEvent::Destroyed,
-                ..
-            }
-            | Event::WindowEvent {
-                event: WindowEvent::CloseRequested,
-                ..
-            }
-            | Event::DeviceEvent {
-                event:
-                    DeviceEvent::Key(KeyboardInput {
-                        virtual_keycode: Some(VirtualKeyCode::Escape),
-                        ..
-                    }),
-                ..
-            } => exit = true,
-            _ => {}
-        });
+    #[test]
+    fn u8_slice_bytes() {
+        let data: &[u8] = &[1, 2, 3, 255];
+        assert_eq!(data.as_mem_bytes(), data);
     }
 
-    device.destroy_context(&mut context).unwrap();
+    #[test]
+    fn vec_bytes() {
+        let indices: Vec<u32> = vec![0, 1, 2, 0xdead_beef];
+        let bytes = indices.as_mem_bytes();
+        assert_eq!(bytes.len(), 4 * indices.len());
+        assert_eq!(&bytes[12..], &0xdead_beef_u32.to_ne_bytes()[..]);
+    }
 }
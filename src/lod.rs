@@ -0,0 +1,320 @@
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    debug_scope,
+    features::Features,
+    frame_arena::FrameArena,
+    instance_buffer::{BufferUsage, InstanceBuffer, InstanceBufferStats},
+    mesh::{Mesh, MeshData},
+    resources::{self, ResourceKind},
+    simplify,
+    vertex::VertexLayout,
+};
+
+/// How far past a switch distance, as a fraction of it, something has to move before its level
+/// changes back, by default
+pub const DEFAULT_HYSTERESIS: f32 = 0.1;
+
+/// One level of detail of a `LodMesh`
+#[derive(Clone, Debug)]
+pub struct LodLevel {
+    pub mesh: Mesh,
+    /// The distance from the camera up to which this level is used
+    pub max_distance: f32,
+}
+
+/// Meshes of the same thing with less and less detail, and the distances to switch between them
+///
+/// The first level has the most detail. The last level is used at any distance past the ones
+/// before it.
+#[derive(Clone, Debug)]
+pub struct LodMesh {
+    pub levels: Vec<LodLevel>,
+    /// How far past a switch distance, as a fraction of it, something has to move before its
+    /// level changes, so that things sitting right at the boundary don't flicker between levels
+    pub hysteresis: f32,
+}
+
+impl LodMesh {
+    /// Create a LOD mesh from its levels, which must be sorted from the most detailed
+    pub fn new(levels: Vec<(Mesh, f32)>) -> Self {
+        Self {
+            levels: levels
+                .into_iter()
+                .map(|(mesh, max_distance)| LodLevel { mesh, max_distance })
+                .collect(),
+            hysteresis: DEFAULT_HYSTERESIS,
+        }
+    }
+
+    /// Create a LOD mesh from one detailed mesh, with a level for every distance
+    ///
+    /// The first level is the mesh itself, and each level after it is simplified to about `ratio`
+    /// times the triangles of the one before it with `simplify::simplify`.
+    pub fn simplified(
+        gl: &mut glow::Context,
+        data: &MeshData,
+        distances: &[f32],
+        ratio: f32,
+    ) -> Self {
+        Self::new(
+            simplify::lod_chain(data, distances.len(), ratio)
+                .iter()
+                .zip(distances)
+                .map(|(data, &distance)| (Mesh::new(gl, data), distance))
+                .collect(),
+        )
+    }
+
+    /// Set the hysteresis of the level switches
+    pub fn with_hysteresis(mut self, hysteresis: f32) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    /// The level to use at a distance from the camera
+    ///
+    /// `previous` is the level that was used last time, if any. It is kept until the distance
+    /// goes past its range by the hysteresis.
+    pub fn select(&self, distance: f32, previous: Option<usize>) -> usize {
+        let last = self.levels.len().saturating_sub(1);
+        let level = self
+            .levels
+            .iter()
+            .position(|level| distance <= level.max_distance)
+            .unwrap_or(last);
+
+        match previous {
+            Some(previous) if previous != level && previous <= last => {
+                // Widen the range of the previous level by the hysteresis in both directions
+                let near = match previous {
+                    0 => 0.,
+                    _ => self.levels[previous - 1].max_distance,
+                };
+                let far = if previous == last {
+                    f32::INFINITY
+                } else {
+                    self.levels[previous].max_distance
+                };
+                if distance >= near * (1. - self.hysteresis)
+                    && distance <= far * (1. + self.hysteresis)
+                {
+                    previous
+                } else {
+                    level
+                }
+            }
+            _ => level,
+        }
+    }
+
+    /// Delete the meshes of every level
+    pub fn delete(&self, gl: &mut glow::Context) {
+        for level in &self.levels {
+            level.mesh.delete(gl);
+        }
+    }
+}
+
+/// How many draw calls and triangles it took to draw with LODs
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LodStats {
+    pub draw_calls: usize,
+    pub triangles: usize,
+    /// The triangles it would have taken to draw everything with the most detailed level
+    pub full_detail_triangles: usize,
+    /// The number of instances or nodes drawn with each level
+    pub per_level: Vec<usize>,
+}
+
+/// The instance buffer of one level
+#[derive(Debug)]
+struct LevelInstances {
+    /// Reads the level's mesh, with the instance buffer attached before each draw
+    vao: u32,
+    buffer: InstanceBuffer,
+    /// The instances drawn with the level this frame
+    count: usize,
+}
+
+/// Instance buffers for drawing many copies of a `LodMesh`, with each instance drawn with its own
+/// level
+///
+/// Every frame the instances are sorted into one buffer per level, and each level is drawn with
+/// one instanced draw call.
+#[derive(Debug)]
+pub struct LodInstanceBuffers {
+    layout: VertexLayout,
+    levels: Vec<LevelInstances>,
+    /// The level each instance was drawn with last, for the hysteresis
+    selected: Vec<Option<usize>>,
+}
+
+impl LodInstanceBuffers {
+    /// Create an instance buffer for each level of the mesh, with the instance attributes
+    /// described by `layout`, which should be `per_instance`. The buffers are rewritten every
+    /// frame, so `usage` should usually be `Dynamic`.
+    pub fn new(
+        gl: &mut glow::Context,
+        features: &Features,
+        lod: &LodMesh,
+        layout: VertexLayout,
+        usage: BufferUsage,
+    ) -> Self {
+        let levels = lod
+            .levels
+            .iter()
+            .map(|level| unsafe {
+                let vao = gl.create_vertex_array().unwrap();
+                gl.bind_vertex_array(Some(vao));
+
+                // Read the level's mesh like its own VAO does
+                gl.bind_buffer(glow::ARRAY_BUFFER, Some(level.mesh.vbo));
+                Mesh::vertex_layout().apply(gl);
+                gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(level.mesh.ebo));
+
+                gl.bind_vertex_array(None);
+                resources::track(ResourceKind::VertexArray, vao, "LOD instance vertex array");
+
+                LevelInstances {
+                    vao,
+                    buffer: InstanceBuffer::new(features, layout.clone(), usage)
+                        .with_label("LOD instance buffer"),
+                    count: 0,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Self {
+            layout,
+            levels,
+            selected: Vec::new(),
+        }
+    }
+
+    /// Sort the instances into the buffers of their levels
+    ///
+    /// `instances` holds the data of every instance laid out like the `layout`, and `distances`
+    /// holds the distance of each instance from the camera. `force_level` draws everything with
+    /// one level, e.g. to compare against drawing without LODs. The instances are sorted in
+    /// memory from `arena`.
+    pub fn update(
+        &mut self,
+        gl: &mut glow::Context,
+        arena: &FrameArena,
+        lod: &LodMesh,
+        instances: &[u8],
+        distances: &[f32],
+        force_level: Option<usize>,
+    ) {
+        let stride = self.layout.stride();
+        debug_assert_eq!(instances.len(), distances.len() * stride);
+        self.selected.resize(distances.len(), None);
+
+        // Pick the level of every instance and count the instances of each level
+        let last = self.levels.len().saturating_sub(1);
+        for level in &mut self.levels {
+            level.count = 0;
+        }
+        for (i, &distance) in distances.iter().enumerate() {
+            let level = match force_level {
+                Some(level) => level.min(last),
+                None => lod.select(distance, self.selected[i]),
+            };
+            self.selected[i] = Some(level);
+            self.levels[level].count += 1;
+        }
+
+        // Then copy the instances so that each level's instances are next to each other, with
+        // each level starting where the one before it ends
+        let sorted = arena.alloc_slice::<u8>(instances.len());
+        let cursors = arena.alloc_slice::<usize>(self.levels.len());
+        let mut start = 0;
+        for (cursor, level) in cursors.iter_mut().zip(&self.levels) {
+            *cursor = start;
+            start += level.count * stride;
+        }
+        for (selected, instance) in self.selected.iter().zip(instances.chunks(stride)) {
+            let cursor = &mut cursors[selected.unwrap()];
+            sorted[*cursor..*cursor + stride].copy_from_slice(instance);
+            *cursor += stride;
+        }
+
+        // Upload each level's instances
+        let mut start = 0;
+        for level in &mut self.levels {
+            let data = &sorted[start..start + level.count * stride];
+            start += data.len();
+            if !data.is_empty() {
+                level.buffer.write(gl, data);
+            }
+        }
+    }
+
+    /// The GPU waits of every level's instance buffer added together
+    pub fn buffer_stats(&self) -> InstanceBufferStats {
+        self.levels.iter().map(|level| level.buffer.stats()).fold(
+            InstanceBufferStats::default(),
+            |total, stats| InstanceBufferStats {
+                waits: total.waits + stats.waits,
+                wait_time: total.wait_time + stats.wait_time,
+                reallocations: total.reallocations + stats.reallocations,
+            },
+        )
+    }
+
+    /// Draw the instances of every level with the current shader program, calling `before_draw`
+    /// with each level's index before drawing it, e.g. to tint the levels
+    pub fn draw<F: FnMut(&mut glow::Context, usize)>(
+        &self,
+        gl: &mut glow::Context,
+        lod: &LodMesh,
+        mut before_draw: F,
+    ) -> LodStats {
+        let _group = DebugGroup::push(gl, "LOD instances");
+        let mut stats = LodStats {
+            per_level: vec![0; self.levels.len()],
+            ..Default::default()
+        };
+        let full_detail = lod
+            .levels
+            .first()
+            .map_or(0, |level| level.mesh.triangle_count());
+        for (i, (instances, level)) in self.levels.iter().zip(&lod.levels).enumerate() {
+            stats.per_level[i] = instances.count;
+            stats.full_detail_triangles += instances.count * full_detail;
+            if instances.count == 0 {
+                continue;
+            }
+
+            debug_scope!(gl, &format!("Level {}", i), {
+                before_draw(gl, i);
+                unsafe {
+                    gl.bind_vertex_array(Some(instances.vao));
+                    instances.buffer.bind(gl);
+                    gl.draw_elements_instanced(
+                        glow::TRIANGLES,
+                        level.mesh.index_count,
+                        level.mesh.index_type,
+                        0,
+                        instances.count as i32,
+                    );
+                }
+            });
+            stats.draw_calls += 1;
+            stats.triangles += instances.count * level.mesh.triangle_count();
+        }
+        unsafe { gl.bind_vertex_array(None) };
+        stats
+    }
+
+    /// Delete the instance buffers and their VAOs, but not the meshes
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for level in &mut self.levels {
+            unsafe { gl.delete_vertex_array(level.vao) };
+            resources::untrack(ResourceKind::VertexArray, level.vao);
+            level.buffer.delete(gl);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::path::{Path, PathBuf};
+
+use glow::HasContext;
+
+use crate::{
+    features::Features,
+    mipmap::MipmapMode,
+    shader::ShaderProgram,
+    texture::{
+        create_texture_2d, load_texture, AlphaMode, ImageData, Texture, TextureParams,
+        TexturePurpose,
+    },
+    texture_streaming::TextureStreamer,
+};
+
+/// The GLSL for lighting the back faces of double-sided materials, which declares a
+/// `facingNormal` function that flips the normal when `gl_FrontFacing` is false. Add it to a
+/// fragment shader with `shader::include_chunk`.
+pub const DOUBLE_SIDED_CHUNK: &str = include_str!("material/double_sided.glsl");
+
+/// One of the textures that a material can have
+///
+/// Each slot is sampled in shaders with a `sampler2D` uniform named after it, like `albedoMap`,
+/// and always gets the same texture unit after the first one a material is bound at.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureSlot {
+    /// The base color of the surface
+    Albedo,
+    /// Tangent space normals
+    Normal,
+    /// Metalness in the blue channel and roughness in the green channel, like glTF
+    MetallicRoughness,
+    /// Light given off by the surface, added after lighting
+    Emissive,
+    /// Ambient occlusion in the red channel
+    Occlusion,
+}
+
+impl TextureSlot {
+    /// Every slot, in the order of their texture units
+    pub const ALL: [TextureSlot; 5] = [
+        TextureSlot::Albedo,
+        TextureSlot::Normal,
+        TextureSlot::MetallicRoughness,
+        TextureSlot::Emissive,
+        TextureSlot::Occlusion,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            TextureSlot::Albedo => "albedo",
+            TextureSlot::Normal => "normal",
+            TextureSlot::MetallicRoughness => "metallic_roughness",
+            TextureSlot::Emissive => "emissive",
+            TextureSlot::Occlusion => "ao",
+        }
+    }
+
+    /// The name of the sampler uniform that the slot's texture is bound to
+    pub fn uniform_name(self) -> &'static str {
+        match self {
+            TextureSlot::Albedo => "albedoMap",
+            TextureSlot::Normal => "normalMap",
+            TextureSlot::MetallicRoughness => "metallicRoughnessMap",
+            TextureSlot::Emissive => "emissiveMap",
+            TextureSlot::Occlusion => "occlusionMap",
+        }
+    }
+
+    /// How far after the first unit of a material the slot's texture is bound
+    pub fn index(self) -> usize {
+        self as usize
+    }
+
+    /// What the slot's texels are, for the texture audit
+    pub fn purpose(self) -> TexturePurpose {
+        match self {
+            TextureSlot::Albedo | TextureSlot::Emissive => TexturePurpose::Albedo,
+            TextureSlot::Normal => TexturePurpose::NormalMap,
+            TextureSlot::MetallicRoughness | TextureSlot::Occlusion => TexturePurpose::Data,
+        }
+    }
+
+    /// Whether or not the slot holds colors, which are sRGB encoded
+    pub fn is_color(self) -> bool {
+        matches!(self, TextureSlot::Albedo | TextureSlot::Emissive)
+    }
+
+    /// The texel of the slot's default texture, which leaves a surface the way it would be without
+    /// the texture: white albedo, a flat normal, full metalness and roughness for the material's
+    /// factors to scale, no emission, and no occlusion
+    pub fn default_texel(self) -> [u8; 4] {
+        match self {
+            TextureSlot::Albedo | TextureSlot::MetallicRoughness | TextureSlot::Occlusion => {
+                [255, 255, 255, 255]
+            }
+            TextureSlot::Normal => [128, 128, 255, 255],
+            TextureSlot::Emissive => [0, 0, 0, 255],
+        }
+    }
+}
+
+/// The 1x1 textures bound to the slots that a material doesn't have, so that shaders never sample
+/// whatever was left on the unit
+#[derive(Debug)]
+pub struct DefaultTextures {
+    /// One texture for every slot, in the order of `TextureSlot::ALL`
+    textures: Vec<Texture>,
+}
+
+impl DefaultTextures {
+    pub fn new(gl: &mut glow::Context, features: &Features) -> Self {
+        let textures = TextureSlot::ALL
+            .iter()
+            .map(|&slot| {
+                let texel = ImageData {
+                    width: 1,
+                    height: 1,
+                    format: glow::RGBA,
+                    alpha: AlphaMode::Opaque,
+                    pixels: slot.default_texel().to_vec(),
+                };
+                create_texture_2d(
+                    gl,
+                    features,
+                    &[texel],
+                    &TextureParams {
+                        mipmaps: MipmapMode::None,
+                        srgb: slot.is_color(),
+                        purpose: Some(slot.purpose()),
+                        label: Some(format!("Default {} texture", slot.name())),
+                        ..Default::default()
+                    },
+                )
+            })
+            .collect();
+        Self { textures }
+    }
+
+    /// The default texture of a slot
+    pub fn get(&self, slot: TextureSlot) -> &Texture {
+        &self.textures[slot.index()]
+    }
+
+    pub fn delete(&self, gl: &mut glow::Context) {
+        for texture in &self.textures {
+            texture.delete(gl);
+        }
+    }
+}
+
+/// Where the textures of a material are on disk, as a model file names them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialSource {
+    pub name: String,
+    pub paths: [Option<PathBuf>; TextureSlot::ALL.len()],
+}
+
+impl MaterialSource {
+    /// Find the texture paths of an OBJ material, relative to the directory of the OBJ file
+    ///
+    /// `map_Kd` is the albedo, `norm` or `map_Bump` the normals, `map_Pr` the metallic roughness
+    /// texture of the PBR extension, `map_Ke` the emission, and `map_Ka` the ambient occlusion.
+    pub fn from_obj(material: &tobj::Material, base_dir: &Path) -> Self {
+        let path = |file: &str| {
+            if file.is_empty() {
+                None
+            } else {
+                Some(base_dir.join(file))
+            }
+        };
+        let param = |names: &[&str]| {
+            names
+                .iter()
+                .find_map(|name| material.unknown_param.get(*name))
+                .and_then(|file| path(file.as_str()))
+        };
+        let mut paths: [Option<PathBuf>; TextureSlot::ALL.len()] = Default::default();
+        paths[TextureSlot::Albedo.index()] = path(&material.diffuse_texture);
+        paths[TextureSlot::Normal.index()] = param(&["norm", "map_Bump", "map_bump", "bump"])
+            .or_else(|| path(&material.normal_texture));
+        paths[TextureSlot::MetallicRoughness.index()] = param(&["map_Pr"]);
+        paths[TextureSlot::Emissive.index()] = param(&["map_Ke"]);
+        paths[TextureSlot::Occlusion.index()] = path(&material.ambient_texture);
+        Self {
+            name: material.name.clone(),
+            paths,
+        }
+    }
+
+    /// The path of a slot's texture, if the material has one
+    pub fn path(&self, slot: TextureSlot) -> Option<&Path> {
+        self.paths[slot.index()].as_deref()
+    }
+}
+
+/// The textures of a material, bound to the samplers named after their slots
+///
+/// Binding a material puts each slot's texture on its own unit, `first_unit + slot.index()`, and
+/// sets the sampler uniform through the program's cached locations. Slots without a texture get
+/// the slot's default texture. Slots whose sampler the shader doesn't declare are skipped.
+#[derive(Debug, Default)]
+pub struct MaterialTextures {
+    textures: [Option<Texture>; TextureSlot::ALL.len()],
+}
+
+impl MaterialTextures {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the textures that a material source names, with sRGB for the color slots
+    ///
+    /// Textures that can't be found are reported and left empty, so the default is used instead.
+    pub fn load(gl: &mut glow::Context, features: &Features, source: &MaterialSource) -> Self {
+        let mut textures = Self::new();
+        for slot in TextureSlot::ALL {
+            let path = match source.path(slot) {
+                Some(path) => path,
+                None => continue,
+            };
+            if !path.exists() {
+                eprintln!(
+                    "Warning: The {} texture of material `{}` doesn't exist: {}",
+                    slot.name(),
+                    source.name,
+                    path.display()
+                );
+                continue;
+            }
+            let texture = load_texture(
+                gl,
+                features,
+                path,
+                &TextureParams {
+                    srgb: slot.is_color(),
+                    purpose: Some(slot.purpose()),
+                    ..Default::default()
+                },
+            );
+            textures.set(slot, Some(texture));
+        }
+        textures
+    }
+
+    /// Stream in the textures that a material source names, like `load` but without waiting for
+    /// them
+    ///
+    /// Each texture shows its slot's default texel until its smallest mip levels have been
+    /// decoded. The textures have to be `forget`ten by the streamer, or the streamer deleted,
+    /// before the material is.
+    pub fn stream(
+        gl: &mut glow::Context,
+        streamer: &mut TextureStreamer,
+        source: &MaterialSource,
+    ) -> Self {
+        let mut textures = Self::new();
+        for slot in TextureSlot::ALL {
+            let path = match source.path(slot) {
+                Some(path) => path,
+                None => continue,
+            };
+            let params = TextureParams {
+                srgb: slot.is_color(),
+                purpose: Some(slot.purpose()),
+                ..Default::default()
+            };
+            match streamer.request(gl, path, &params, slot.default_texel()) {
+                Ok(texture) => textures.set(slot, Some(texture)),
+                Err(error) => eprintln!(
+                    "Warning: The {} texture of material `{}` can't be streamed from {}: {}",
+                    slot.name(),
+                    source.name,
+                    path.display(),
+                    error
+                ),
+            }
+        }
+        textures
+    }
+
+    /// Set the texture of a slot, or `None` to use the default
+    ///
+    /// The material owns its textures and deletes them in `delete`.
+    pub fn set(&mut self, slot: TextureSlot, texture: Option<Texture>) {
+        self.textures[slot.index()] = texture;
+    }
+
+    pub fn with(mut self, slot: TextureSlot, texture: Texture) -> Self {
+        self.set(slot, Some(texture));
+        self
+    }
+
+    /// The texture of a slot, if it has one
+    pub fn get(&self, slot: TextureSlot) -> Option<&Texture> {
+        self.textures[slot.index()].as_ref()
+    }
+
+    /// Bind the textures to units starting at `first_unit` and set the samplers of a program,
+    /// which should be bound
+    ///
+    /// This returns the first texture unit after the ones the slots use.
+    pub fn bind(
+        &self,
+        gl: &mut glow::Context,
+        program: &mut ShaderProgram,
+        defaults: &DefaultTextures,
+        first_unit: u32,
+    ) -> u32 {
+        for slot in TextureSlot::ALL {
+            let name = slot.uniform_name();
+            if program.uniform_location(gl, name).is_none() {
+                continue;
+            }
+            let unit = first_unit + slot.index() as u32;
+            let texture = self.get(slot).unwrap_or_else(|| defaults.get(slot));
+            unsafe {
+                gl.active_texture(glow::TEXTURE0 + unit);
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+            }
+            program.set_uniform(gl, name, unit as i32);
+        }
+        first_unit + TextureSlot::ALL.len() as u32
+    }
+
+    /// Delete the textures of the slots that have one
+    pub fn delete(&self, gl: &mut glow::Context) {
+        for texture in self.textures.iter().flatten() {
+            texture.delete(gl);
+        }
+    }
+}
+
+/// Warn about the sampler uniforms of a program that no texture slot provides, returning their
+/// names
+///
+/// `other_samplers` are the samplers that the caller binds itself. A sampler that nothing binds
+/// samples whatever texture was left on unit 0.
+pub fn check_samplers(
+    gl: &glow::Context,
+    program: &ShaderProgram,
+    label: &str,
+    other_samplers: &[&str],
+) -> Vec<String> {
+    let mut unknown = Vec::new();
+    unsafe {
+        for index in 0..gl.get_active_uniforms(program.id) {
+            let uniform = match gl.get_active_uniform(program.id, index) {
+                Some(uniform) => uniform,
+                None => continue,
+            };
+            let is_sampler = matches!(
+                uniform.utype,
+                glow::SAMPLER_2D
+                    | glow::SAMPLER_CUBE
+                    | glow::SAMPLER_3D
+                    | glow::SAMPLER_2D_ARRAY
+                    | glow::SAMPLER_2D_SHADOW
+                    | glow::SAMPLER_2D_MULTISAMPLE
+            );
+            // Arrays of samplers are reported as `name[0]`
+            let name = uniform.name.trim_end_matches("[0]");
+            let provided = TextureSlot::ALL
+                .iter()
+                .any(|slot| slot.uniform_name() == name)
+                || other_samplers.contains(&name);
+            if is_sampler && !provided {
+                eprintln!(
+                    "Warning: `{}` declares the sampler `{}`, which no material slot provides",
+                    label, name
+                );
+                unknown.push(name.to_string());
+            }
+        }
+    }
+    unknown
+}
@@ -0,0 +1,8 @@
+// Lighting the back faces of double-sided materials, which are drawn without face culling. Only
+// for fragment shaders, since it reads `gl_FrontFacing`.
+
+// The normal of the side of the triangle being drawn: the interpolated normal for front faces,
+// and the normal flipped for back faces, so that they are lit like the side they show
+vec3 facingNormal(vec3 normal) {
+    return gl_FrontFacing ? normal : -normal;
+}
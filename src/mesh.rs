@@ -0,0 +1,399 @@
+use std::path::Path;
+
+use glow::HasContext;
+
+use crate::{
+    fallback_assets,
+    handle::Handle,
+    material::MaterialSource,
+    mesh_optimizer::{self, OptimizeReport},
+    resources::{self, ResourceKind},
+    vertex::{VertexFormat, VertexLayout},
+};
+
+/// The normal used for vertices that only belong to degenerate triangles
+const FALLBACK_NORMAL: [f32; 3] = [0., 1., 0.];
+
+/// How `compute_normals` should shade the triangles of a mesh
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NormalMode {
+    /// Every triangle gets its own vertices with the triangle's normal, for a faceted look
+    Flat,
+    /// Shared vertices get the average of the normals of their triangles, weighted by the angle
+    /// of each triangle at the vertex
+    Smooth,
+}
+
+/// The vertices and indices of a triangle mesh, before it is uploaded to the GPU
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MeshData {
+    pub positions: Vec<[f32; 3]>,
+    /// Either empty or one normal for every position
+    pub normals: Vec<[f32; 3]>,
+    /// Either empty or one texture coordinate for every position
+    pub uvs: Vec<[f32; 2]>,
+    /// Three indices for every triangle
+    pub indices: Vec<u32>,
+}
+
+/// Options for loading meshes from files
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadOptions {
+    /// Reorder the triangles and vertices of every mesh with `MeshData::optimize` and log how
+    /// much it helped. Off by default, since it takes a moment for big meshes.
+    pub optimize: bool,
+}
+
+impl MeshData {
+    /// Load every model in an OBJ file
+    ///
+    /// Normals are computed with `NormalMode::Smooth` for models that don't have any.
+    pub fn load_obj<P: AsRef<Path>>(path: P) -> Vec<Self> {
+        Self::load_obj_with(path, &LoadOptions::default())
+    }
+
+    /// Load every model in an OBJ file with options
+    pub fn load_obj_with<P: AsRef<Path>>(path: P, options: &LoadOptions) -> Vec<Self> {
+        Self::load_obj_with_materials(path, options)
+            .0
+            .into_iter()
+            .map(|(data, _)| data)
+            .collect()
+    }
+
+    /// Load every model in an OBJ file with options, along with the texture paths of the materials
+    /// in its MTL file
+    ///
+    /// Each model comes with the index of its material, if it has one.
+    pub fn load_obj_with_materials<P: AsRef<Path>>(
+        path: P,
+        options: &LoadOptions,
+    ) -> (Vec<(Self, Option<usize>)>, Vec<MaterialSource>) {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return (vec![(fallback_assets::model(path), None)], Vec::new());
+        }
+        let (models, materials) = tobj::load_obj(path).unwrap();
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let materials = materials
+            .iter()
+            .map(|material| MaterialSource::from_obj(material, base_dir))
+            .collect();
+
+        let models = models
+            .into_iter()
+            .map(|model| {
+                let material_id = model.mesh.material_id;
+                let mesh = model.mesh;
+                let mut data = Self {
+                    positions: mesh
+                        .positions
+                        .chunks(3)
+                        .map(|p| [p[0], p[1], p[2]])
+                        .collect(),
+                    normals: mesh.normals.chunks(3).map(|n| [n[0], n[1], n[2]]).collect(),
+                    uvs: mesh.texcoords.chunks(2).map(|t| [t[0], t[1]]).collect(),
+                    indices: mesh.indices,
+                };
+
+                if data.normals.is_empty() {
+                    data.compute_normals(NormalMode::Smooth);
+                }
+                if options.optimize {
+                    let report = data.optimize();
+                    eprintln!("Optimized {}: {}", model.name, report);
+                }
+
+                (data, material_id)
+            })
+            .collect();
+        (models, materials)
+    }
+
+    /// Reorder the triangles and vertices of the mesh so that it draws faster, without changing
+    /// how it looks
+    ///
+    /// See `mesh_optimizer::optimize` for the steps.
+    pub fn optimize(&mut self) -> OptimizeReport {
+        mesh_optimizer::optimize(self)
+    }
+
+    /// Replace the normals of the mesh with ones computed from its triangles
+    ///
+    /// `NormalMode::Flat` gives every triangle its own vertices, so it changes the positions,
+    /// texture coordinates, and indices too.
+    pub fn compute_normals(&mut self, mode: NormalMode) {
+        match mode {
+            NormalMode::Flat => {
+                // Split every triangle into its own vertices
+                self.positions = self
+                    .indices
+                    .iter()
+                    .map(|&i| self.positions[i as usize])
+                    .collect();
+                if !self.uvs.is_empty() {
+                    self.uvs = self.indices.iter().map(|&i| self.uvs[i as usize]).collect();
+                }
+                self.indices = (0..self.positions.len() as u32).collect();
+
+                // Give each vertex the normal of its triangle
+                self.normals = self
+                    .positions
+                    .chunks(3)
+                    .flat_map(|triangle| {
+                        let normal =
+                            normalize(triangle_normal(triangle[0], triangle[1], triangle[2]))
+                                .unwrap_or(FALLBACK_NORMAL);
+                        vec![normal; 3]
+                    })
+                    .collect();
+            }
+            NormalMode::Smooth => {
+                let mut normals = vec![[0.; 3]; self.positions.len()];
+
+                for triangle in self.indices.chunks(3) {
+                    let corners = [
+                        self.positions[triangle[0] as usize],
+                        self.positions[triangle[1] as usize],
+                        self.positions[triangle[2] as usize],
+                    ];
+
+                    // Skip degenerate triangles, which don't have a direction
+                    let normal =
+                        match normalize(triangle_normal(corners[0], corners[1], corners[2])) {
+                            Some(normal) => normal,
+                            None => continue,
+                        };
+
+                    // Weight the normal by the angle of the triangle at each corner so that the
+                    // result doesn't depend on how the surface around the vertex is triangulated
+                    for corner in 0..3 {
+                        let position = corners[corner];
+                        let to_next = sub(corners[(corner + 1) % 3], position);
+                        let to_previous = sub(corners[(corner + 2) % 3], position);
+                        let angle = match (normalize(to_next), normalize(to_previous)) {
+                            (Some(a), Some(b)) => dot(a, b).clamp(-1., 1.).acos(),
+                            _ => continue,
+                        };
+
+                        let vertex_normal = &mut normals[triangle[corner] as usize];
+                        for axis in 0..3 {
+                            vertex_normal[axis] += normal[axis] * angle;
+                        }
+                    }
+                }
+
+                self.normals = normals
+                    .into_iter()
+                    .map(|normal| normalize(normal).unwrap_or(FALLBACK_NORMAL))
+                    .collect();
+            }
+        }
+    }
+}
+
+/// Compute normals for a triangle mesh
+///
+/// This is a shortcut for `MeshData::compute_normals` on a mesh without texture coordinates.
+pub fn compute_normals(positions: &[[f32; 3]], indices: &[u32], mode: NormalMode) -> MeshData {
+    let mut data = MeshData {
+        positions: positions.to_vec(),
+        normals: Vec::new(),
+        uvs: Vec::new(),
+        indices: indices.to_vec(),
+    };
+    data.compute_normals(mode);
+    data
+}
+
+/// A triangle mesh uploaded to the GPU
+///
+/// The vertex attributes are the position at location 0, the normal at location 1, and the
+/// texture coordinate at location 2.
+#[derive(Clone, Debug)]
+pub struct Mesh {
+    pub vao: u32,
+    pub vbo: u32,
+    pub ebo: u32,
+    /// The number of indices to draw
+    pub index_count: i32,
+    /// The type of the indices, either `glow::UNSIGNED_SHORT` or `glow::UNSIGNED_INT`
+    pub index_type: u32,
+    /// Which way round the front faces of the mesh's triangles go
+    pub winding: Winding,
+}
+
+/// Which way round the corners of a mesh's front faces go, as seen from the front
+///
+/// Meshes are counter-clockwise, like GL expects, but some exporters write clockwise triangles,
+/// which face culling then throws away from the front instead of the back.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Winding {
+    #[default]
+    CounterClockwise,
+    Clockwise,
+}
+
+impl Mesh {
+    /// Upload mesh data to the GPU. Missing normals or texture coordinates are filled with zeros.
+    pub fn new(gl: &mut glow::Context, data: &MeshData) -> Self {
+        let layout = Self::vertex_layout();
+
+        // Interleave the vertex attributes
+        let mut vertices = Vec::with_capacity(data.positions.len() * layout.stride());
+        for (i, position) in data.positions.iter().enumerate() {
+            let normal = data.normals.get(i).unwrap_or(&[0.; 3]);
+            let uv = data.uvs.get(i).unwrap_or(&[0.; 2]);
+            for x in position.iter().chain(normal).chain(uv) {
+                vertices.extend_from_slice(&x.to_ne_bytes());
+            }
+        }
+
+        // Use 16 bit indices when they are big enough, which halves the size of the index buffer
+        let (index_type, indices) = if data.positions.len() <= u16::MAX as usize + 1 {
+            let indices = data
+                .indices
+                .iter()
+                .flat_map(|&i| (i as u16).to_ne_bytes())
+                .collect::<Vec<_>>();
+            (glow::UNSIGNED_SHORT, indices)
+        } else {
+            let indices = data
+                .indices
+                .iter()
+                .flat_map(|i| i.to_ne_bytes())
+                .collect::<Vec<_>>();
+            (glow::UNSIGNED_INT, indices)
+        };
+
+        unsafe {
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &vertices, glow::STATIC_DRAW);
+            layout.apply(gl);
+
+            let ebo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, &indices, glow::STATIC_DRAW);
+
+            gl.bind_vertex_array(None);
+
+            resources::track(ResourceKind::VertexArray, vao, "Mesh vertex array");
+            resources::track_sized(
+                ResourceKind::Buffer,
+                vbo,
+                "Mesh vertex buffer",
+                vertices.len() as u64,
+            );
+            resources::track_sized(
+                ResourceKind::Buffer,
+                ebo,
+                "Mesh index buffer",
+                indices.len() as u64,
+            );
+
+            Self {
+                vao,
+                vbo,
+                ebo,
+                index_count: data.indices.len() as i32,
+                index_type,
+                winding: Winding::CounterClockwise,
+            }
+        }
+    }
+
+    /// The layout of the vertex buffer of every mesh, e.g. for making another VAO that reads it
+    pub fn vertex_layout() -> VertexLayout {
+        VertexLayout::new(&[
+            (0, VertexFormat::Float32x3),
+            (1, VertexFormat::Float32x3),
+            (2, VertexFormat::Float32x2),
+        ])
+    }
+
+    /// A handle to the mesh's vertex array, or `None` if the current context isn't tracked
+    pub fn handle(&self) -> Option<Handle<Mesh>> {
+        Handle::new(self.vao)
+    }
+
+    /// The number of triangles in the mesh
+    pub fn triangle_count(&self) -> usize {
+        self.index_count as usize / 3
+    }
+
+    /// Override which way round the front faces of the mesh go, e.g. for a model exported with
+    /// clockwise triangles
+    pub fn with_winding(mut self, winding: Winding) -> Self {
+        self.winding = winding;
+        self
+    }
+
+    /// Draw the mesh with the current shader program
+    ///
+    /// A clockwise mesh is drawn with the front face flipped from what it is, and then put back,
+    /// so that it stays right when the front face is already flipped for a mirrored draw.
+    pub fn draw(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.bind_vertex_array(Some(self.vao));
+            if self.winding == Winding::Clockwise {
+                let front_face = gl.get_parameter_i32(glow::FRONT_FACE) as u32;
+                gl.front_face(if front_face == glow::CW {
+                    glow::CCW
+                } else {
+                    glow::CW
+                });
+                gl.draw_elements(glow::TRIANGLES, self.index_count, self.index_type, 0);
+                gl.front_face(front_face);
+            } else {
+                gl.draw_elements(glow::TRIANGLES, self.index_count, self.index_type, 0);
+            }
+        }
+    }
+
+    /// Delete the GL objects of the mesh
+    pub fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+            gl.delete_buffer(self.ebo);
+        }
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+        resources::untrack(ResourceKind::Buffer, self.vbo);
+        resources::untrack(ResourceKind::Buffer, self.ebo);
+    }
+}
+
+/// The unnormalized normal of a counter-clockwise triangle
+pub(crate) fn triangle_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    cross(sub(b, a), sub(c, a))
+}
+
+pub(crate) fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Normalize a vector, or return `None` if it is too short to have a direction
+pub(crate) fn normalize(v: [f32; 3]) -> Option<[f32; 3]> {
+    let length = dot(v, v).sqrt();
+    if length > f32::EPSILON && length.is_finite() {
+        Some([v[0] / length, v[1] / length, v[2] / length])
+    } else {
+        None
+    }
+}
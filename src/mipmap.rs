@@ -0,0 +1,107 @@
+use image::imageops::{self, FilterType};
+
+use crate::texture::ImageData;
+
+/// How the mip levels of a texture are created
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MipmapMode {
+    /// Let the driver generate the mip levels with `glGenerateMipmap`
+    #[default]
+    DriverGenerate,
+    /// Average each 2x2 block of pixels on the CPU. Cheap and predictable.
+    Box,
+    /// Downsample the full size image with a Lanczos filter on the CPU. Keeps minified textures
+    /// sharper than a box filter, which is nice for pixel art and fine detail.
+    Lanczos,
+    /// Don't create any mip levels below the full size image
+    None,
+}
+
+/// The size of a mip level below the given size
+fn next_level_size(width: u32, height: u32) -> (u32, u32) {
+    ((width / 2).max(1), (height / 2).max(1))
+}
+
+/// Generate the mip chain of an image on the CPU, including the image itself as level 0
+///
+/// `mode` must be `MipmapMode::Box` or `MipmapMode::Lanczos`, the other modes just return the
+/// image itself.
+pub fn generate_mip_chain(image: &ImageData, mode: MipmapMode, mip_levels: u32) -> Vec<ImageData> {
+    let mut levels = vec![image.clone()];
+
+    while (levels.len() as u32) < mip_levels {
+        let previous = levels.last().unwrap();
+        if previous.width == 1 && previous.height == 1 {
+            break;
+        }
+
+        let next = match mode {
+            MipmapMode::Box => box_downsample(previous),
+            // Always filter from the full size image so that the errors don't add up
+            MipmapMode::Lanczos => {
+                let (width, height) = next_level_size(previous.width, previous.height);
+                lanczos_resize(image, width, height)
+            }
+            MipmapMode::DriverGenerate | MipmapMode::None => break,
+        };
+        levels.push(next);
+    }
+
+    levels
+}
+
+/// Halve the size of an image by averaging each 2x2 block of pixels
+fn box_downsample(image: &ImageData) -> ImageData {
+    let channels = image.channels();
+    let (width, height) = next_level_size(image.width, image.height);
+    let mut pixels = Vec::with_capacity((width * height) as usize * channels);
+
+    // Get a channel of a source pixel, clamping to the edge for images with an odd size
+    let get = |x: u32, y: u32, c: usize| {
+        let x = x.min(image.width - 1);
+        let y = y.min(image.height - 1);
+        image.pixels[(y * image.width + x) as usize * channels + c] as u32
+    };
+
+    for y in 0..height {
+        for x in 0..width {
+            for c in 0..channels {
+                let sum = get(x * 2, y * 2, c)
+                    + get(x * 2 + 1, y * 2, c)
+                    + get(x * 2, y * 2 + 1, c)
+                    + get(x * 2 + 1, y * 2 + 1, c);
+                // Round to the nearest value
+                pixels.push(((sum + 2) / 4) as u8);
+            }
+        }
+    }
+
+    ImageData {
+        width,
+        height,
+        format: image.format,
+        alpha: image.alpha,
+        pixels,
+    }
+}
+
+/// Resize an image with a Lanczos filter
+fn lanczos_resize(image: &ImageData, width: u32, height: u32) -> ImageData {
+    let pixels = if image.format == glow::RGB {
+        let buffer =
+            image::RgbImage::from_raw(image.width, image.height, image.pixels.clone()).unwrap();
+        imageops::resize(&buffer, width, height, FilterType::Lanczos3).into_raw()
+    } else {
+        let buffer =
+            image::RgbaImage::from_raw(image.width, image.height, image.pixels.clone()).unwrap();
+        imageops::resize(&buffer, width, height, FilterType::Lanczos3).into_raw()
+    };
+
+    ImageData {
+        width,
+        height,
+        format: image.format,
+        alpha: image.alpha,
+        pixels,
+    }
+}
@@ -0,0 +1,263 @@
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    nested::SavedState,
+    resources::{self, ResourceKind},
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+};
+
+/// The GLSL for working out how far a point moved on screen, which defines `screenVelocity`. Add
+/// it to a shader with `shader::include_chunk`.
+///
+/// The vertex shader passes on the clip space position of the vertex this frame and last, which it
+/// gets from `perDrawModel` and `perDrawPreviousModel` in `per_draw::PER_DRAW_CHUNK` and the
+/// camera's view-projection matrices. The fragment shader writes
+/// `screenVelocity(clipPosition, previousClipPosition)` into the velocity attachment of a
+/// `VelocityTarget`.
+pub const VELOCITY_CHUNK: &str = include_str!("motion_blur/velocity.glsl");
+
+const BLUR_FRAGMENT_SRC: &str = include_str!("motion_blur/blur.frag");
+
+/// The most samples that `MotionBlurParams::samples` is clamped to
+pub const MAX_SAMPLES: u32 = 64;
+
+/// The settings of a motion blur pass, which can be changed between frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MotionBlurParams {
+    /// How much of a frame's movement is blurred over, where 1 is all of it and 0 turns the blur
+    /// off
+    pub intensity: f32,
+    /// How many pixels along the movement are averaged into each pixel
+    pub samples: u32,
+    /// How far the blur reaches to either side of a pixel, in pixels
+    ///
+    /// Longer movements are shortened to it, so that things that jumped further in one frame than
+    /// `TransformHistory` took for a teleport don't smear across the screen.
+    pub max_radius: f32,
+}
+
+impl Default for MotionBlurParams {
+    fn default() -> Self {
+        Self {
+            intensity: 1.,
+            samples: 8,
+            max_radius: 32.,
+        }
+    }
+}
+
+/// A framebuffer to draw a scene into for motion blur, with its colors in attachment 0 and how far
+/// each pixel moved since last frame in attachment 1
+///
+/// The colors are RGBA16F and the velocities RG16F, in texture coordinates, as `VELOCITY_CHUNK`
+/// works them out. The depth buffer is floating point, so it works with reverse-Z.
+#[derive(Debug)]
+pub struct VelocityTarget {
+    pub size: (u32, u32),
+    pub framebuffer: u32,
+    pub color: u32,
+    pub velocity: u32,
+    depth: u32,
+}
+
+impl VelocityTarget {
+    pub fn new(gl: &mut glow::Context, size: (u32, u32)) -> Self {
+        let (width, height) = (size.0.max(1), size.1.max(1));
+        let label = format!("Velocity target {}x{}", width, height);
+        unsafe {
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+
+            // The colors are filtered, since the blur samples between pixels, but velocities of
+            // different things shouldn't be mixed
+            let attach = |attachment: u32, internal_format: u32, format: u32, filter: u32| {
+                let texture = gl.create_texture().unwrap();
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    0,
+                    internal_format as i32,
+                    width as i32,
+                    height as i32,
+                    0,
+                    format,
+                    glow::FLOAT,
+                    None,
+                );
+                for (parameter, value) in [
+                    (glow::TEXTURE_MIN_FILTER, filter),
+                    (glow::TEXTURE_MAG_FILTER, filter),
+                    (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                    (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+                ] {
+                    gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+                }
+                gl.framebuffer_texture_2d(
+                    glow::FRAMEBUFFER,
+                    attachment,
+                    glow::TEXTURE_2D,
+                    Some(texture),
+                    0,
+                );
+                resources::track_sized(
+                    ResourceKind::Texture,
+                    texture,
+                    &label,
+                    resources::texture_bytes(width, height, internal_format, 1, 1, 1),
+                );
+                texture
+            };
+            let color = attach(
+                glow::COLOR_ATTACHMENT0,
+                glow::RGBA16F,
+                glow::RGBA,
+                glow::LINEAR,
+            );
+            let velocity = attach(
+                glow::COLOR_ATTACHMENT1,
+                glow::RG16F,
+                glow::RG,
+                glow::NEAREST,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let depth = gl.create_renderbuffer().unwrap();
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                glow::DEPTH_COMPONENT32F,
+                width as i32,
+                height as i32,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(depth),
+            );
+            gl.draw_buffers(&[glow::COLOR_ATTACHMENT0, glow::COLOR_ATTACHMENT1]);
+
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                panic!("Error creating the velocity target!");
+            }
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            resources::track(ResourceKind::Renderbuffer, depth, &label);
+            resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+
+            Self {
+                size,
+                framebuffer,
+                color,
+                velocity,
+                depth,
+            }
+        }
+    }
+
+    /// Clear the colors to `color`, the velocities to 0, and the depth, which the target has to
+    /// be bound for
+    ///
+    /// Things that aren't drawn over, like the background, don't move.
+    pub fn clear(&self, gl: &mut glow::Context, color: [f32; 4]) {
+        let [r, g, b, a] = color;
+        unsafe {
+            gl.draw_buffers(&[glow::COLOR_ATTACHMENT0, glow::NONE]);
+            gl.clear_color(r, g, b, a);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            gl.draw_buffers(&[glow::NONE, glow::COLOR_ATTACHMENT1]);
+            gl.clear_color(0., 0., 0., 0.);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            gl.draw_buffers(&[glow::COLOR_ATTACHMENT0, glow::COLOR_ATTACHMENT1]);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_renderbuffer(self.depth);
+            gl.delete_texture(self.color);
+            gl.delete_texture(self.velocity);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Renderbuffer, self.depth);
+        resources::untrack(ResourceKind::Texture, self.color);
+        resources::untrack(ResourceKind::Texture, self.velocity);
+    }
+}
+
+/// Blurs a scene along how far each pixel moved since last frame, read from a `VelocityTarget`
+///
+/// Each pixel averages `samples` pixels of the colors along its velocity, centered on it, so
+/// things that move leave a smear on either side, and things that stand still stay sharp. A
+/// moving camera blurs everything, since everything moves on screen. `draw` writes the blurred
+/// scene over the whole viewport of the bound framebuffer.
+#[derive(Debug)]
+pub struct MotionBlurPass {
+    pub params: MotionBlurParams,
+    program: ShaderProgram,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+}
+
+impl MotionBlurPass {
+    pub fn new(gl: &mut glow::Context, params: MotionBlurParams) -> Self {
+        let program = ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, BLUR_FRAGMENT_SRC).unwrap();
+        let empty_vao = unsafe { gl.create_vertex_array().unwrap() };
+        resources::track(
+            ResourceKind::VertexArray,
+            empty_vao,
+            "Motion blur vertex array",
+        );
+        Self {
+            params,
+            program,
+            empty_vao,
+        }
+    }
+
+    /// Draw the blurred colors of a target into the bound framebuffer, with the viewport as it is
+    ///
+    /// Texture units 0 and 1 are used, and the blending and depth state are put back afterwards.
+    pub fn draw(&mut self, gl: &mut glow::Context, target: &VelocityTarget) {
+        let params = self.params;
+        let (width, height) = target.size;
+        let _group = DebugGroup::push(gl, "Motion blur");
+
+        unsafe {
+            let state = SavedState::save(gl);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::BLEND);
+
+            let program = &mut self.program;
+            program.bind(gl);
+            program.set_uniform(gl, "sceneColor", 0);
+            program.set_uniform(gl, "sceneVelocity", 1);
+            program.set_uniform(
+                gl,
+                "texelSize",
+                [1. / width.max(1) as f32, 1. / height.max(1) as f32],
+            );
+            program.set_uniform(gl, "intensity", params.intensity.max(0.));
+            program.set_uniform(gl, "samples", params.samples.min(MAX_SAMPLES) as i32);
+            program.set_uniform(gl, "maxRadius", params.max_radius.max(0.));
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, Some(target.velocity));
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(target.color));
+            gl.bind_vertex_array(Some(self.empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+            state.restore(gl);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe { gl.delete_vertex_array(self.empty_vao) };
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+        self.program.delete(gl);
+    }
+}
@@ -0,0 +1,41 @@
+#version 330 core
+
+in vec2 texCoord;
+
+out vec4 FragColor;
+
+uniform sampler2D sceneColor;
+// How far each pixel moved since last frame, in texture coordinates, from `screenVelocity`
+uniform sampler2D sceneVelocity;
+// The size of a pixel in texture coordinates
+uniform vec2 texelSize;
+// How much of a frame's movement is blurred over, where 1 is all of it
+uniform float intensity;
+// How many pixels along the movement are averaged, besides the pixel itself
+uniform int samples;
+// How far the blur reaches to either side of a pixel, in pixels
+uniform float maxRadius;
+
+void main() {
+    vec4 center = texture(sceneColor, texCoord);
+    vec2 velocity = texture(sceneVelocity, texCoord).xy * intensity;
+
+    // The blur is centered on the pixel, so it reaches half of the movement each way
+    float radius = length(velocity / texelSize) * 0.5;
+    if (radius < 0.5 || samples < 1) {
+        FragColor = center;
+        return;
+    }
+    // Things that moved very far, like ones that teleported without saying so, would otherwise
+    // smear across the whole screen
+    if (radius > maxRadius) {
+        velocity *= maxRadius / radius;
+    }
+
+    vec3 total = center.rgb;
+    for (int i = 0; i < samples; i++) {
+        float offset = (float(i) + 0.5) / float(samples) - 0.5;
+        total += texture(sceneColor, texCoord + velocity * offset).rgb;
+    }
+    FragColor = vec4(total / float(samples + 1), center.a);
+}
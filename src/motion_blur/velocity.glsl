@@ -0,0 +1,12 @@
+// How far a point moved on screen since last frame, in texture coordinates, from its clip space
+// positions this frame and last. They are divided by w here instead of in the vertex shader,
+// since positions after the divide don't interpolate across a triangle.
+vec2 screenVelocity(vec4 clipPosition, vec4 previousClipPosition) {
+    // A point that was behind the camera last frame has no place on screen to have moved from
+    if (previousClipPosition.w <= 0.0) {
+        return vec2(0.0);
+    }
+    vec2 ndc = clipPosition.xy / clipPosition.w;
+    vec2 previousNdc = previousClipPosition.xy / previousClipPosition.w;
+    return (ndc - previousNdc) * 0.5;
+}
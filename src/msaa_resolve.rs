@@ -0,0 +1,228 @@
+use glow::HasContext;
+
+use crate::{
+    debug_scope,
+    nested::SavedState,
+    resources::{self, ResourceKind},
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+};
+
+const RESOLVE_FRAGMENT_SRC: &str = include_str!("msaa_resolve/resolve.frag");
+
+/// How the samples of a multisampled texture are turned into one value per pixel
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ResolveKind {
+    /// Average the samples with a blit, which is right for colors
+    Average,
+    /// Keep the first sample of a color texture, for data like positions and normals that mean
+    /// nothing once the surfaces on both sides of an edge are averaged together
+    FirstSample,
+    /// Keep the first sample of a depth texture
+    Depth,
+}
+
+/// How effects that read a multisampled G-buffer or depth buffer, like SSAO and outlines, get at
+/// its samples
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MsaaQuality {
+    /// Resolve the textures to one sample per pixel first and shade each pixel once, which is as
+    /// fast as without MSAA, but leaves the edges of the effect aliased
+    #[default]
+    Resolve,
+    /// Fetch every sample with `texelFetch` on a `sampler2DMS` and average the results, which
+    /// keeps the edges of the effect smooth but shades each pixel once for every sample
+    PerSample,
+}
+
+impl MsaaQuality {
+    /// The quality with the given name, `resolve` or `per-sample`
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "resolve" => Some(MsaaQuality::Resolve),
+            "per-sample" => Some(MsaaQuality::PerSample),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MsaaQuality::Resolve => "resolve",
+            MsaaQuality::PerSample => "per-sample",
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            MsaaQuality::Resolve => MsaaQuality::PerSample,
+            MsaaQuality::PerSample => MsaaQuality::Resolve,
+        }
+    }
+}
+
+/// A multisampled texture that passes draw into, and the single-sample texture of the same size
+/// and format that it is resolved into before a pass samples it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct MultisampledTexture {
+    /// The `TEXTURE_2D_MULTISAMPLE` texture
+    pub texture: u32,
+    /// The `TEXTURE_2D` texture that it is resolved into
+    pub resolved: u32,
+    pub kind: ResolveKind,
+}
+
+/// Copies multisampled textures into single-sample ones, for passes that can't read the samples
+/// themselves
+///
+/// Colors are averaged with `glBlitFramebuffer`. Everything else is copied one sample at a time
+/// with a fullscreen triangle, since a blit averages float textures too and there is no blit that
+/// picks a sample.
+#[derive(Debug)]
+pub struct MsaaResolver {
+    /// The framebuffer that averaged textures are attached to for blitting
+    read_framebuffer: u32,
+    /// The framebuffer that resolved textures are attached to
+    draw_framebuffer: u32,
+    color_program: ShaderProgram,
+    depth_program: ShaderProgram,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+}
+
+impl MsaaResolver {
+    pub fn new(gl: &mut glow::Context) -> Self {
+        let color_program =
+            ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, RESOLVE_FRAGMENT_SRC).unwrap();
+        let depth_program = ShaderProgram::with_defines(
+            gl,
+            FULLSCREEN_VERTEX_SRC,
+            RESOLVE_FRAGMENT_SRC,
+            &["RESOLVE_DEPTH"],
+        )
+        .unwrap();
+        unsafe {
+            let read_framebuffer = gl.create_framebuffer().unwrap();
+            let draw_framebuffer = gl.create_framebuffer().unwrap();
+            let empty_vao = gl.create_vertex_array().unwrap();
+            resources::track(ResourceKind::Framebuffer, read_framebuffer, "MSAA resolve");
+            resources::track(ResourceKind::Framebuffer, draw_framebuffer, "MSAA resolve");
+            resources::track(
+                ResourceKind::VertexArray,
+                empty_vao,
+                "MSAA resolve vertex array",
+            );
+            Self {
+                read_framebuffer,
+                draw_framebuffer,
+                color_program,
+                depth_program,
+                empty_vao,
+            }
+        }
+    }
+
+    /// Resolve a multisampled texture of the given size into its single-sample texture
+    ///
+    /// The framebuffer bindings, program, vertex array, and depth state are put back afterwards,
+    /// but the viewport is left at the size of the texture.
+    pub fn resolve(
+        &mut self,
+        gl: &mut glow::Context,
+        texture: &MultisampledTexture,
+        (width, height): (u32, u32),
+    ) {
+        let (width, height) = (width as i32, height as i32);
+        unsafe {
+            let state = SavedState::save(gl);
+            let depth_func = gl.get_parameter_i32(glow::DEPTH_FUNC) as u32;
+            gl.disable(glow::SCISSOR_TEST);
+            gl.disable(glow::BLEND);
+            gl.disable(glow::CULL_FACE);
+            gl.disable(glow::STENCIL_TEST);
+            gl.viewport(0, 0, width, height);
+
+            // Only one of the draw framebuffer's attachments is used at a time
+            let (attachment, unused) = match texture.kind {
+                ResolveKind::Depth => (glow::DEPTH_ATTACHMENT, glow::COLOR_ATTACHMENT0),
+                _ => (glow::COLOR_ATTACHMENT0, glow::DEPTH_ATTACHMENT),
+            };
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(self.draw_framebuffer));
+            gl.framebuffer_texture_2d(glow::DRAW_FRAMEBUFFER, unused, glow::TEXTURE_2D, None, 0);
+            gl.framebuffer_texture_2d(
+                glow::DRAW_FRAMEBUFFER,
+                attachment,
+                glow::TEXTURE_2D,
+                Some(texture.resolved),
+                0,
+            );
+            gl.draw_buffer(if texture.kind == ResolveKind::Depth {
+                glow::NONE
+            } else {
+                glow::COLOR_ATTACHMENT0
+            });
+
+            match texture.kind {
+                ResolveKind::Average => debug_scope!(gl, "Average samples", {
+                    gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.read_framebuffer));
+                    gl.framebuffer_texture_2d(
+                        glow::READ_FRAMEBUFFER,
+                        glow::COLOR_ATTACHMENT0,
+                        glow::TEXTURE_2D_MULTISAMPLE,
+                        Some(texture.texture),
+                        0,
+                    );
+                    gl.read_buffer(glow::COLOR_ATTACHMENT0);
+                    gl.blit_framebuffer(
+                        0,
+                        0,
+                        width,
+                        height,
+                        0,
+                        0,
+                        width,
+                        height,
+                        glow::COLOR_BUFFER_BIT,
+                        glow::NEAREST,
+                    );
+                }),
+                ResolveKind::FirstSample | ResolveKind::Depth => {
+                    debug_scope!(gl, "Copy first sample", {
+                        let program = if texture.kind == ResolveKind::Depth {
+                            // Depth is only written with the depth test on
+                            gl.enable(glow::DEPTH_TEST);
+                            gl.depth_func(glow::ALWAYS);
+                            gl.depth_mask(true);
+                            &mut self.depth_program
+                        } else {
+                            gl.disable(glow::DEPTH_TEST);
+                            &mut self.color_program
+                        };
+                        program.bind(gl);
+                        program.set_uniform(gl, "source", 0);
+                        gl.active_texture(glow::TEXTURE0);
+                        gl.bind_texture(glow::TEXTURE_2D_MULTISAMPLE, Some(texture.texture));
+                        gl.bind_vertex_array(Some(self.empty_vao));
+                        gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                        gl.bind_texture(glow::TEXTURE_2D_MULTISAMPLE, None);
+                    })
+                }
+            }
+
+            gl.depth_func(depth_func);
+            state.restore(gl);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.read_framebuffer);
+            gl.delete_framebuffer(self.draw_framebuffer);
+            gl.delete_vertex_array(self.empty_vao);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.read_framebuffer);
+        resources::untrack(ResourceKind::Framebuffer, self.draw_framebuffer);
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+        self.color_program.delete(gl);
+        self.depth_program.delete(gl);
+    }
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+// Copies the first sample of every pixel of a multisampled texture, instead of averaging the
+// samples like a blit does. With RESOLVE_DEPTH defined it copies depth instead of a color.
+
+uniform sampler2DMS source;
+
+#ifndef RESOLVE_DEPTH
+out vec4 FragColor;
+#endif
+
+void main() {
+    vec4 value = texelFetch(source, ivec2(gl_FragCoord.xy), 0);
+#ifdef RESOLVE_DEPTH
+    gl_FragDepth = value.r;
+#else
+    FragColor = value;
+#endif
+}
@@ -0,0 +1,413 @@
+use std::cell::Cell;
+
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    depth_mode::{self, DepthSetup},
+    diagnostics,
+    resources::{self, ResourceKind},
+    viewport::Rect,
+    AppContext, HandlerFactory, RenderHandler,
+};
+
+/// How many nested renderers can be drawing inside of each other at once
+///
+/// A handler that nests itself would otherwise create nested renderers forever. Past this depth
+/// the inner handler isn't created, and the texture stays blank.
+pub const MAX_NESTING_DEPTH: u32 = 4;
+
+thread_local! {
+    /// How many nested renderers are initializing or drawing their inner handlers right now
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+}
+
+/// Run `f` one nesting level deeper, or return `None` if that would go past `MAX_NESTING_DEPTH`
+fn nested<T, F: FnOnce() -> T>(f: F) -> Option<T> {
+    let depth = DEPTH.with(|depth| depth.get());
+    if depth >= MAX_NESTING_DEPTH {
+        return None;
+    }
+    DEPTH.with(|cell| cell.set(depth + 1));
+    let result = f();
+    DEPTH.with(|cell| cell.set(depth));
+    Some(result)
+}
+
+/// The texture the inner handler draws into, with a depth and stencil buffer
+#[derive(Debug)]
+struct NestedTarget {
+    size: (u32, u32),
+    framebuffer: u32,
+    texture: u32,
+    depth_stencil: u32,
+    depth_format: u32,
+}
+
+impl NestedTarget {
+    unsafe fn new(gl: &mut glow::Context, (width, height): (u32, u32), depth_format: u32) -> Self {
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA8 as i32,
+            width as i32,
+            height as i32,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            None,
+        );
+        for (parameter, value) in [
+            (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+            (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+            (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+        ] {
+            gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+        }
+        gl.bind_texture(glow::TEXTURE_2D, None);
+
+        let depth_stencil = gl.create_renderbuffer().unwrap();
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth_stencil));
+        gl.renderbuffer_storage(
+            glow::RENDERBUFFER,
+            depth_format,
+            width as i32,
+            height as i32,
+        );
+        gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+        let framebuffer = gl.create_framebuffer().unwrap();
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(texture),
+            0,
+        );
+        gl.framebuffer_renderbuffer(
+            glow::FRAMEBUFFER,
+            glow::DEPTH_STENCIL_ATTACHMENT,
+            glow::RENDERBUFFER,
+            Some(depth_stencil),
+        );
+        if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+            diagnostics::report_problem("The nested renderer framebuffer is incomplete");
+        }
+
+        let label = format!("Nested renderer {}x{}", width, height);
+        resources::track_sized(
+            ResourceKind::Texture,
+            texture,
+            &label,
+            resources::texture_bytes(width, height, glow::RGBA8, 1, 1, 1),
+        );
+        resources::track_sized(
+            ResourceKind::Renderbuffer,
+            depth_stencil,
+            &label,
+            resources::texture_bytes(width, height, depth_format, 1, 1, 1),
+        );
+        resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+        Self {
+            size: (width, height),
+            framebuffer,
+            texture,
+            depth_stencil,
+            depth_format,
+        }
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_renderbuffer(self.depth_stencil);
+            gl.delete_texture(self.texture);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Renderbuffer, self.depth_stencil);
+        resources::untrack(ResourceKind::Texture, self.texture);
+    }
+}
+
+/// The GL state that the outer handler gets back after the inner handler draws
+#[derive(Debug)]
+pub(crate) struct SavedState {
+    draw_framebuffer: u32,
+    read_framebuffer: u32,
+    program: u32,
+    vertex_array: u32,
+    active_texture: u32,
+    /// Whether `DEPTH_TEST`, `BLEND`, `CULL_FACE`, `SCISSOR_TEST`, and `STENCIL_TEST` are enabled
+    capabilities: [(u32, bool); 5],
+    depth_mask: bool,
+    front_face: u32,
+    /// The source and destination RGB and alpha blend factors
+    blend_func: [u32; 4],
+}
+
+impl SavedState {
+    pub(crate) unsafe fn save(gl: &glow::Context) -> Self {
+        let get = |parameter| gl.get_parameter_i32(parameter) as u32;
+        let capabilities = [
+            glow::DEPTH_TEST,
+            glow::BLEND,
+            glow::CULL_FACE,
+            glow::SCISSOR_TEST,
+            glow::STENCIL_TEST,
+        ];
+        Self {
+            draw_framebuffer: get(glow::DRAW_FRAMEBUFFER_BINDING),
+            read_framebuffer: get(glow::READ_FRAMEBUFFER_BINDING),
+            program: get(glow::CURRENT_PROGRAM),
+            vertex_array: get(glow::VERTEX_ARRAY_BINDING),
+            active_texture: get(glow::ACTIVE_TEXTURE),
+            capabilities: capabilities.map(|capability| (capability, gl.is_enabled(capability))),
+            depth_mask: get(glow::DEPTH_WRITEMASK) != 0,
+            front_face: get(glow::FRONT_FACE),
+            blend_func: [
+                get(glow::BLEND_SRC_RGB),
+                get(glow::BLEND_DST_RGB),
+                get(glow::BLEND_SRC_ALPHA),
+                get(glow::BLEND_DST_ALPHA),
+            ],
+        }
+    }
+
+    pub(crate) unsafe fn restore(&self, gl: &glow::Context) {
+        let object = |name: u32| if name == 0 { None } else { Some(name) };
+        gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, object(self.draw_framebuffer));
+        gl.bind_framebuffer(glow::READ_FRAMEBUFFER, object(self.read_framebuffer));
+        gl.use_program(object(self.program));
+        gl.bind_vertex_array(object(self.vertex_array));
+        gl.active_texture(self.active_texture);
+        for &(capability, enabled) in &self.capabilities {
+            if enabled {
+                gl.enable(capability);
+            } else {
+                gl.disable(capability);
+            }
+        }
+        gl.depth_mask(self.depth_mask);
+        gl.front_face(self.front_face);
+        let [src_rgb, dst_rgb, src_alpha, dst_alpha] = self.blend_func;
+        gl.blend_func_separate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+    }
+}
+
+/// Another `RenderHandler` drawing into a texture, which the handler that owns this can show,
+/// e.g. on a screen in its scene
+///
+/// The inner handler gets an `AppContext` of its own, with its own timing, whose window size is
+/// the size of the texture and whose surface framebuffer is the texture's framebuffer. Its render
+/// settings are followed for clearing the texture and for its depth mode, but it has no virtual
+/// resolution, anti-aliasing, console, or screenshots. It gets no input unless `forward_input` is
+/// set.
+///
+/// Drawing the inner handler leaves the outer handler's framebuffers, program, vertex array,
+/// active texture unit, depth, blend, cull, scissor, and stencil state the way they were. The
+/// viewport is set back to cover the outer handler's render size, the clear color to the outer
+/// render settings', and texture and buffer bindings are left however the inner handler left them.
+pub struct NestedRenderer {
+    /// The inner handler, or `None` if it was nested too deep or has exited
+    handler: Option<Box<dyn RenderHandler>>,
+    ctx: AppContext,
+    target: Option<NestedTarget>,
+    /// Whether the inner handler gets the outer handler's input
+    pub forward_input: bool,
+}
+
+impl NestedRenderer {
+    /// Create the inner handler with `factory`, drawing into a texture of the given size
+    ///
+    /// `ctx` is the outer handler's context, whose config and features the inner one shares.
+    pub fn new(
+        gl: &mut glow::Context,
+        ctx: &AppContext,
+        size: (u32, u32),
+        factory: &HandlerFactory,
+    ) -> Self {
+        let size = (size.0.max(1), size.1.max(1));
+        let mut inner_ctx = AppContext::new(
+            ctx.window_id(),
+            size,
+            ctx.hidpi_factor(),
+            ctx.context_report().clone(),
+            ctx.features().clone(),
+            ctx.workarounds(),
+            ctx.config().clone(),
+        );
+        let depth_format = inner_ctx.render_settings.depth_mode.depth_stencil_format();
+        let target = unsafe { NestedTarget::new(gl, size, depth_format) };
+        inner_ctx.set_surface_framebuffer(Some(target.framebuffer));
+
+        // The inner handler may bind its own things while it sets up, so keep the outer state
+        let handler = unsafe {
+            let state = SavedState::save(gl);
+            let handler = nested(|| factory(gl, &mut inner_ctx));
+            state.restore(gl);
+            handler
+        };
+        if handler.is_none() {
+            eprintln!(
+                "Warning: Nested renderers are nested more than {} deep, leaving the innermost one \
+                 blank",
+                MAX_NESTING_DEPTH
+            );
+        }
+
+        Self {
+            handler,
+            ctx: inner_ctx,
+            target: Some(target),
+            forward_input: false,
+        }
+    }
+
+    /// The texture the inner handler draws into
+    pub fn texture(&self) -> Option<u32> {
+        self.target.as_ref().map(|target| target.texture)
+    }
+
+    /// The size of the texture the inner handler draws into
+    pub fn size(&self) -> (u32, u32) {
+        self.ctx.window_size()
+    }
+
+    /// The inner handler's context, e.g. for pausing its time or changing its clear color
+    pub fn context(&self) -> &AppContext {
+        &self.ctx
+    }
+
+    pub fn context_mut(&mut self) -> &mut AppContext {
+        &mut self.ctx
+    }
+
+    /// Change the size of the texture, which the inner handler sees as its window being resized
+    ///
+    /// The texture is recreated the next time the inner handler draws.
+    pub fn resize(&mut self, size: (u32, u32)) {
+        self.ctx.set_window_size((size.0.max(1), size.1.max(1)));
+    }
+
+    /// Draw a frame of the inner handler into the texture
+    ///
+    /// `ctx` is the outer handler's context. Shader reloads asked for in it are passed on to the
+    /// inner handler.
+    pub fn draw(&mut self, gl: &mut glow::Context, ctx: &AppContext) {
+        let handler = match &mut self.handler {
+            Some(handler) => handler,
+            None => return,
+        };
+        let size = self.ctx.window_size();
+        let inner_mode = self.ctx.render_settings.depth_mode;
+        let depth_format = inner_mode.depth_stencil_format();
+        let current = self
+            .target
+            .as_ref()
+            .map(|target| (target.size, target.depth_format));
+        if current != Some((size, depth_format)) {
+            if let Some(target) = self.target.take() {
+                target.delete(gl);
+            }
+            let target = unsafe { NestedTarget::new(gl, size, depth_format) };
+            self.ctx.set_surface_framebuffer(Some(target.framebuffer));
+            self.target = Some(target);
+        }
+        let target = self.target.as_ref().unwrap();
+
+        // Start the inner frame like the loop starts a window's frame
+        self.ctx.timing.begin_frame();
+        self.ctx.arena.reset();
+        if self.forward_input {
+            self.ctx.input = ctx.input.clone();
+        }
+        if ctx.shader_reload_requested() {
+            self.ctx.request_shader_reload();
+        }
+
+        // The inner handler draws with its own depth mode, and the outer one gets its back after
+        let outer_current = depth_mode::current();
+        let outer_depth = DepthSetup::new(outer_current.mode, ctx.features());
+        let inner_depth = DepthSetup::new(inner_mode, ctx.features());
+
+        let _group = DebugGroup::push(gl, "Nested renderer");
+        let inner_ctx = &mut self.ctx;
+        unsafe {
+            let state = SavedState::save(gl);
+            if inner_depth != outer_depth {
+                inner_depth.apply(gl);
+            }
+            depth_mode::make_current(inner_depth);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.framebuffer));
+            Rect::from_window_size(size).set_viewport(gl);
+            let settings = &inner_ctx.render_settings;
+            let clear_mask = settings.clear_mask();
+            if clear_mask != 0 {
+                if let Some(color) = settings.clear_color {
+                    let [r, g, b, a] = color.to_srgb();
+                    gl.clear_color(r, g, b, a);
+                }
+                // The outer handler may have turned off depth or color writes
+                gl.depth_mask(true);
+                gl.color_mask(true, true, true, true);
+                gl.disable(glow::SCISSOR_TEST);
+                gl.clear(clear_mask);
+            }
+
+            nested(|| handler.draw(gl, inner_ctx));
+
+            state.restore(gl);
+            if inner_depth != outer_depth {
+                outer_depth.apply(gl);
+            }
+            depth_mode::make_current(outer_current);
+            Rect::from_window_size(ctx.render_size()).set_viewport(gl);
+            if let Some(color) = ctx.render_settings.clear_color {
+                let [r, g, b, a] = color.to_srgb();
+                gl.clear_color(r, g, b, a);
+            }
+        }
+
+        self.ctx.viewports.end_frame(size, size);
+        self.ctx.clear_shader_reload_request();
+        self.ctx.input.end_frame();
+        if self.ctx.take_close_request() {
+            eprintln!("Warning: A nested renderer's handler asked to close, which it can't");
+        }
+        self.ctx.take_redraw_request();
+        self.ctx.take_screenshot_request();
+        self.ctx.take_clipboard_request();
+    }
+
+    /// Pass on the loss of the window surface or GL context to the inner handler
+    pub fn device_lost(&mut self, gl: &mut glow::Context) {
+        if let Some(handler) = &mut self.handler {
+            handler.device_lost(gl, &mut self.ctx);
+        }
+    }
+
+    /// Pass on the window surface coming back to the inner handler
+    pub fn device_restored(&mut self, gl: &mut glow::Context) {
+        if let Some(handler) = &mut self.handler {
+            handler.device_restored(gl, &mut self.ctx);
+        }
+    }
+
+    /// Let the inner handler clean up, and delete the texture and its framebuffer
+    ///
+    /// Nothing is drawn into the texture after this.
+    pub fn exit(&mut self, gl: &mut glow::Context) {
+        if let Some(mut handler) = self.handler.take() {
+            let state = unsafe { SavedState::save(gl) };
+            handler.exit(gl, &mut self.ctx);
+            unsafe { state.restore(gl) };
+        }
+        if let Some(target) = self.target.take() {
+            target.delete(gl);
+        }
+    }
+}
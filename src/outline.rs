@@ -0,0 +1,120 @@
+use cgmath::Matrix4;
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    gbuffer::{GBuffer, GBufferLayout, GBUFFER_CHUNK},
+    nested::SavedState,
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram, FULLSCREEN_VERTEX_SRC},
+};
+
+const OUTLINE_FRAGMENT_SRC: &str = include_str!("outline/outline.frag");
+
+/// The settings of an outline pass, which can be changed between frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OutlineParams {
+    /// The color of the lines, which is linear
+    pub color: [f32; 3],
+    /// How far apart the pixels compared with each other are, in pixels, which is about how thick
+    /// the lines are
+    pub thickness: f32,
+    /// How far a surface can be from the plane of the one next to it, relative to its distance
+    /// from the camera, before there is a line between them
+    pub depth_threshold: f32,
+    /// How much the normals of neighbouring pixels can differ, from 0 for the same to 2 for
+    /// opposite, before there is a line between them
+    pub normal_threshold: f32,
+}
+
+impl Default for OutlineParams {
+    fn default() -> Self {
+        Self {
+            color: [0.02, 0.02, 0.03],
+            thickness: 1.,
+            depth_threshold: 0.02,
+            normal_threshold: 0.3,
+        }
+    }
+}
+
+/// Draws lines along silhouettes and creases, found from the jumps in the positions and normals
+/// of a `GBuffer`
+///
+/// `draw` blends the lines over whatever framebuffer is bound, after the scene has been lit.
+#[derive(Debug)]
+pub struct OutlinePass {
+    pub params: OutlineParams,
+    program: ShaderProgram,
+    /// The G-buffer defines that `program` was compiled with
+    defines: Vec<&'static str>,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+}
+
+/// Compile the outline shader for reading a G-buffer with the given `GBuffer::defines`
+fn outline_program(gl: &mut glow::Context, defines: &[&str]) -> ShaderProgram {
+    let fragment = shader::include_chunk(OUTLINE_FRAGMENT_SRC, GBUFFER_CHUNK);
+    ShaderProgram::with_defines(gl, FULLSCREEN_VERTEX_SRC, &fragment, defines).unwrap()
+}
+
+impl OutlinePass {
+    pub fn new(gl: &mut glow::Context, params: OutlineParams) -> Self {
+        let defines = GBufferLayout::default().defines().to_vec();
+        let program = outline_program(gl, &defines);
+        let empty_vao = unsafe { gl.create_vertex_array().unwrap() };
+        resources::track(ResourceKind::VertexArray, empty_vao, "Outline vertex array");
+        Self {
+            params,
+            program,
+            defines,
+            empty_vao,
+        }
+    }
+
+    /// Blend the lines of a G-buffer over the bound framebuffer, with the viewport as it is
+    ///
+    /// `projection` is the projection the G-buffer was drawn with. A multisampled G-buffer is
+    /// tested once for every sample, which anti-aliases the lines. Passing its `single_sample`
+    /// G-buffer instead, once resolved, tests each pixel once. The shader is compiled again if the
+    /// G-buffer's layout or samples changed, and the blending and depth state are put back
+    /// afterwards.
+    pub fn draw(&mut self, gl: &mut glow::Context, gbuffer: &GBuffer, projection: Matrix4<f32>) {
+        let defines = gbuffer.defines();
+        if defines != self.defines {
+            self.program.delete(gl);
+            self.program = outline_program(gl, &defines);
+            self.defines = defines;
+        }
+        let params = self.params;
+        let (width, height) = gbuffer.size;
+        let _group = DebugGroup::push(gl, "Outline");
+
+        unsafe {
+            let state = SavedState::save(gl);
+            gl.disable(glow::DEPTH_TEST);
+            gl.enable(glow::BLEND);
+            gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+
+            let program = &mut self.program;
+            program.bind(gl);
+            gbuffer.bind(gl, program, 0, projection);
+            program.set_uniform(gl, "color", params.color);
+            program.set_uniform(gl, "texelSize", [1. / width as f32, 1. / height as f32]);
+            program.set_uniform(gl, "thickness", params.thickness);
+            program.set_uniform(gl, "depthThreshold", params.depth_threshold);
+            program.set_uniform(gl, "normalThreshold", params.normal_threshold);
+            gl.bind_vertex_array(Some(self.empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+            state.restore(gl);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe { gl.delete_vertex_array(self.empty_vao) };
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+        self.program.delete(gl);
+    }
+}
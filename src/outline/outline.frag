@@ -0,0 +1,61 @@
+#version 330 core
+
+in vec2 texCoord;
+
+out vec4 FragColor;
+
+// The G-buffer is read with the functions from `GBUFFER_CHUNK`, which is included above
+
+uniform vec3 color;
+// The size of a G-buffer pixel in texture coordinates
+uniform vec2 texelSize;
+// How far away the neighbours compared with each pixel are, in pixels
+uniform float thickness;
+// How far a neighbour can be from the plane of the surface, relative to its distance from the
+// camera, before there is an edge between them
+uniform float depthThreshold;
+// How much the normals can differ, from 0 for the same to 2 for opposite, before there is an
+// edge between them
+uniform float normalThreshold;
+
+const vec2 NEIGHBOURS[4] = vec2[](vec2(1.0, 0.0), vec2(-1.0, 0.0), vec2(0.0, 1.0), vec2(0.0, -1.0));
+
+// Whether there is an edge at the current G-buffer sample, from 0 to 1
+float sampleEdge() {
+    vec4 center = gbufferPosition(texCoord);
+    vec3 normal = center.a == 0.0 ? vec3(0.0) : gbufferNormal(texCoord);
+    float edge = 0.0;
+    for (int i = 0; i < 4; i++) {
+        vec2 coord = texCoord + NEIGHBOURS[i] * texelSize * thickness;
+        vec4 neighbour = gbufferPosition(coord);
+        // Against the background
+        if (center.a != neighbour.a) {
+            return 1.0;
+        }
+        if (center.a == 0.0) {
+            continue;
+        }
+        // Measuring from the plane of the surface keeps floors seen at grazing angles from
+        // turning into edges, which a plain difference in depth would
+        float distance = abs(dot(neighbour.xyz - center.xyz, normal)) / max(abs(center.z), 1e-3);
+        float bend = 1.0 - dot(normal, gbufferNormal(coord));
+        if (distance > depthThreshold || bend > normalThreshold) {
+            edge = 1.0;
+        }
+    }
+    return edge;
+}
+
+void main() {
+    // Every sample of a multisampled G-buffer is tested, so that the lines are anti-aliased
+    float total = 0.0;
+    int samples = gbufferSamples();
+#ifdef GBUFFER_MULTISAMPLE
+    for (gSample = 0; gSample < samples; gSample++) {
+        total += sampleEdge();
+    }
+#else
+    total = sampleEdge();
+#endif
+    FragColor = vec4(color, total / float(samples));
+}
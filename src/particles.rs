@@ -0,0 +1,833 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3};
+use glow::HasContext;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::{
+    blend::BlendMode,
+    debug_group::DebugGroup,
+    features::{ComputeFns, Features},
+    instance_buffer::{BufferUsage, InstanceBuffer},
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram},
+    tween::{Easing, Lerp},
+    vertex::{VertexFormat, VertexLayout},
+    SliceAsBytes,
+};
+
+const UPDATE_CHUNK: &str = include_str!("particles/update.glsl");
+const UPDATE_COMPUTE_SRC: &str = include_str!("particles/update.comp");
+const UPDATE_VERTEX_SRC: &str = include_str!("particles/update.vert");
+const RENDER_VERTEX_SRC: &str = include_str!("particles/render.vert");
+const RENDER_FRAGMENT_SRC: &str = include_str!("particles/render.frag");
+
+/// How many samples of the color and size curves the shaders look up
+pub const CURVE_SAMPLES: u32 = 64;
+/// Emitters with at most this many particles are simulated on the CPU by default, where a
+/// handful of particles costs less than a GPU pass
+pub const DEFAULT_CPU_THRESHOLD: u32 = 1024;
+
+/// The number of particles each compute shader work group updates, which must match
+/// `local_size_x` in the compute shader
+const WORK_GROUP_SIZE: u32 = 256;
+
+/// A value that changes over the life of a particle, from `start` when it spawns to `end` when it
+/// dies
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Curve<T> {
+    pub start: T,
+    pub end: T,
+    pub easing: Easing,
+}
+
+impl<T: Lerp> Curve<T> {
+    pub fn new(start: T, end: T, easing: Easing) -> Self {
+        Self { start, end, easing }
+    }
+
+    /// A value that stays the same over the whole life
+    pub fn constant(value: T) -> Self {
+        Self::new(value.clone(), value, Easing::Linear)
+    }
+
+    /// The value at `t`, from 0 at the start of the life to 1 at the end
+    pub fn sample(&self, t: f32) -> T {
+        self.start.lerp(&self.end, self.easing.apply(t))
+    }
+}
+
+/// How a particle system moves its particles
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Simulation {
+    /// A compute shader updates the particle buffer in place. Needs GL 4.3.
+    Compute,
+    /// A vertex shader updates the particles from one buffer into another with transform
+    /// feedback, and the buffers swap every update. Works on GL 3.3.
+    TransformFeedback,
+    /// The CPU updates the particles and uploads them every update. Fine for small emitters.
+    Cpu,
+}
+
+/// The settings of a particle emitter
+///
+/// These can be changed at any time with `ParticleSystem::emitter_mut`. Changing
+/// `max_particles` recreates the emitter's buffers, which kills its particles.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EmitterParams {
+    /// Where the particles spawn
+    pub position: Point3<f32>,
+    /// The most particles that can be alive at once. This should be at least `spawn_rate` times
+    /// the longest lifetime, or the oldest particles are cut short to make room for new ones.
+    pub max_particles: u32,
+    /// How many particles spawn every second
+    pub spawn_rate: f32,
+    /// How many seconds each particle lives
+    pub lifetime: f32,
+    /// How much the lifetime changes from particle to particle, as a fraction of it
+    pub lifetime_variance: f32,
+    /// The middle of the cone the particles are shot out in
+    pub direction: Vector3<f32>,
+    /// The angle between the direction and the edge of the cone in degrees. 180 shoots particles
+    /// out in every direction.
+    pub cone_angle: f32,
+    /// How fast the particles are shot out
+    pub speed: f32,
+    /// How much the speed changes from particle to particle, as a fraction of it
+    pub speed_variance: f32,
+    /// The acceleration of every particle, e.g. gravity pulling sparks down or buoyancy lifting
+    /// smoke up
+    pub gravity: Vector3<f32>,
+    /// How much of their velocity the particles lose every second
+    pub drag: f32,
+    /// The color over the life of each particle, in straight alpha
+    pub color: Curve<[f32; 4]>,
+    /// The size over the life of each particle, in world units
+    pub size: Curve<f32>,
+    /// How the particles are blended. `Additive` doesn't care what order the particles are drawn
+    /// in. `Premultiplied` does, and the particles aren't sorted, so it only looks right for
+    /// particles that are mostly transparent or mostly opaque.
+    pub blend: BlendMode,
+}
+
+impl Default for EmitterParams {
+    fn default() -> Self {
+        Self {
+            position: Point3::new(0., 0., 0.),
+            max_particles: 1000,
+            spawn_rate: 200.,
+            lifetime: 2.,
+            lifetime_variance: 0.2,
+            direction: Vector3::unit_y(),
+            cone_angle: 20.,
+            speed: 2.,
+            speed_variance: 0.2,
+            gravity: Vector3::new(0., -1., 0.),
+            drag: 0.,
+            color: Curve::new([1., 1., 1., 1.], [1., 1., 1., 0.], Easing::Linear),
+            size: Curve::constant(0.1),
+            blend: BlendMode::Additive,
+        }
+    }
+}
+
+/// The identifier of an emitter in a particle system
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct EmitterId(usize);
+
+/// One particle, as it is stored in the particle buffers
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct Particle {
+    position: [f32; 3],
+    age: f32,
+    velocity: [f32; 3],
+    lifetime: f32,
+}
+
+impl Particle {
+    /// A particle that is dead until it is spawned
+    const DEAD: Particle = Particle {
+        position: [0.; 3],
+        age: 1.,
+        velocity: [0.; 3],
+        lifetime: 0.,
+    };
+}
+
+/// The layout of the particle buffers, as the position and age at location 0 and the velocity and
+/// lifetime at location 1
+fn particle_layout() -> VertexLayout {
+    VertexLayout::new(&[(0, VertexFormat::Float32x4), (1, VertexFormat::Float32x4)])
+}
+
+/// The buffers of an emitter, which depend on how it is simulated
+#[derive(Debug)]
+enum EmitterBuffers {
+    /// A buffer that the compute shader updates in place and that is drawn from
+    Compute { buffer: u32, render_vao: u32 },
+    /// Two buffers that transform feedback updates from one into the other, with a VAO for
+    /// reading each of them in the update and in the draw
+    TransformFeedback {
+        buffers: [u32; 2],
+        update_vaos: [u32; 2],
+        render_vaos: [u32; 2],
+        /// The buffer holding the latest particles
+        current: usize,
+    },
+    /// The particles in memory, uploaded after every update
+    Cpu {
+        particles: Vec<Particle>,
+        buffer: InstanceBuffer,
+        render_vao: u32,
+        rng: SmallRng,
+    },
+}
+
+/// An emitter and everything needed to simulate and draw it
+#[derive(Debug)]
+struct Emitter {
+    params: EmitterParams,
+    simulation: Simulation,
+    buffers: EmitterBuffers,
+    /// The particle count the buffers were created for
+    capacity: u32,
+    /// The color and size curves, sampled into a texture
+    curves: u32,
+    /// The curves that are in the texture, to tell when they change
+    uploaded_curves: (Curve<[f32; 4]>, Curve<f32>),
+    /// The fraction of a particle left over from the last update's spawns
+    spawn_remainder: f32,
+    /// The next particle to spawn into. Particles are spawned in order around the buffer, so
+    /// this is always the oldest one.
+    spawn_cursor: u32,
+}
+
+impl Emitter {
+    /// Sample the curves into the curve texture
+    unsafe fn upload_curves(&mut self, gl: &mut glow::Context) {
+        let (color, size) = (self.params.color, self.params.size);
+        let mut texels = Vec::with_capacity(CURVE_SAMPLES as usize * 2 * 4);
+        for i in 0..CURVE_SAMPLES {
+            texels.extend_from_slice(&color.sample(i as f32 / (CURVE_SAMPLES - 1) as f32));
+        }
+        for i in 0..CURVE_SAMPLES {
+            texels.extend_from_slice(&[
+                size.sample(i as f32 / (CURVE_SAMPLES - 1) as f32),
+                0.,
+                0.,
+                1.,
+            ]);
+        }
+        let bytes = texels.as_mem_bytes();
+        gl.bind_texture(glow::TEXTURE_2D, Some(self.curves));
+        gl.tex_sub_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            0,
+            0,
+            CURVE_SAMPLES as i32,
+            2,
+            glow::RGBA,
+            glow::FLOAT,
+            glow::PixelUnpackData::Slice(bytes),
+        );
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        self.uploaded_curves = (color, size);
+    }
+
+    /// Delete the buffers and VAOs
+    unsafe fn delete_buffers(&mut self, gl: &mut glow::Context) {
+        let delete_vao = |vao: u32| {
+            gl.delete_vertex_array(vao);
+            resources::untrack(ResourceKind::VertexArray, vao);
+        };
+        match &mut self.buffers {
+            EmitterBuffers::Compute { buffer, render_vao } => {
+                delete_vao(*render_vao);
+                gl.delete_buffer(*buffer);
+                resources::untrack(ResourceKind::Buffer, *buffer);
+            }
+            EmitterBuffers::TransformFeedback {
+                buffers,
+                update_vaos,
+                render_vaos,
+                ..
+            } => {
+                for &vao in update_vaos.iter().chain(render_vaos.iter()) {
+                    delete_vao(vao);
+                }
+                for &buffer in buffers.iter() {
+                    gl.delete_buffer(buffer);
+                    resources::untrack(ResourceKind::Buffer, buffer);
+                }
+            }
+            EmitterBuffers::Cpu {
+                buffer, render_vao, ..
+            } => {
+                delete_vao(*render_vao);
+                buffer.delete(gl);
+            }
+        }
+    }
+}
+
+/// Create a VAO that reads particles from `buffer`, either one per vertex for the transform
+/// feedback update or one per instance for drawing
+unsafe fn particle_vao(gl: &mut glow::Context, buffer: Option<u32>, per_instance: bool) -> u32 {
+    let vao = gl.create_vertex_array().unwrap();
+    gl.bind_vertex_array(Some(vao));
+    if let Some(buffer) = buffer {
+        gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+        let layout = particle_layout();
+        if per_instance {
+            layout.per_instance().apply(gl);
+        } else {
+            layout.apply(gl);
+        }
+        gl.bind_buffer(glow::ARRAY_BUFFER, None);
+    }
+    gl.bind_vertex_array(None);
+    resources::track(ResourceKind::VertexArray, vao, "Particles");
+    vao
+}
+
+/// Create a buffer holding `count` dead particles
+unsafe fn particle_buffer(gl: &mut glow::Context, target: u32, count: u32) -> u32 {
+    let buffer = gl.create_buffer().unwrap();
+    gl.bind_buffer(target, Some(buffer));
+    gl.buffer_data_u8_slice(
+        target,
+        vec![Particle::DEAD; count as usize].as_mem_bytes(),
+        glow::DYNAMIC_COPY,
+    );
+    gl.bind_buffer(target, None);
+    resources::track_sized(
+        ResourceKind::Buffer,
+        buffer,
+        "Particles",
+        count as u64 * std::mem::size_of::<Particle>() as u64,
+    );
+    buffer
+}
+
+/// Particles spawned by emitters, simulated on the GPU and drawn as soft point sprites
+///
+/// Big emitters are simulated with a compute shader where the context supports it, and with
+/// transform feedback otherwise. Emitters with at most `cpu_threshold` particles are simulated on
+/// the CPU. Every emitter keeps a fixed size pool of particles, so nothing is allocated while it
+/// runs. Call `update` once a frame and `draw` wherever the particles should show up.
+#[derive(Debug)]
+pub struct ParticleSystem {
+    emitters: Vec<Option<Emitter>>,
+    /// How big emitters are simulated
+    gpu_simulation: Simulation,
+    /// Emitters with at most this many particles are simulated on the CPU
+    cpu_threshold: u32,
+    compute: Option<ComputeFns>,
+    compute_program: Option<ShaderProgram>,
+    feedback_program: ShaderProgram,
+    render_program: ShaderProgram,
+    /// Counts the updates, to give every update new random numbers
+    update_count: u32,
+}
+
+impl ParticleSystem {
+    pub fn new(gl: &mut glow::Context, features: &Features) -> Self {
+        let compile_error = |error| panic!("Error compiling a particle shader: {}", error);
+        let compute_program = features.compute.map(|_| {
+            ShaderProgram::compute(gl, &shader::include_chunk(UPDATE_COMPUTE_SRC, UPDATE_CHUNK))
+                .unwrap_or_else(compile_error)
+        });
+        let feedback_program = ShaderProgram::transform_feedback(
+            gl,
+            &shader::include_chunk(UPDATE_VERTEX_SRC, UPDATE_CHUNK),
+            &["outPositionAge", "outVelocityLifetime"],
+        )
+        .unwrap_or_else(compile_error);
+        let render_program = ShaderProgram::new(gl, RENDER_VERTEX_SRC, RENDER_FRAGMENT_SRC)
+            .unwrap_or_else(compile_error);
+
+        Self {
+            emitters: Vec::new(),
+            gpu_simulation: if compute_program.is_some() {
+                Simulation::Compute
+            } else {
+                Simulation::TransformFeedback
+            },
+            cpu_threshold: DEFAULT_CPU_THRESHOLD,
+            compute: features.compute,
+            compute_program,
+            feedback_program,
+            render_program,
+            update_count: 0,
+        }
+    }
+
+    /// How emitters with more than `cpu_threshold` particles are simulated
+    pub fn gpu_simulation(&self) -> Simulation {
+        self.gpu_simulation
+    }
+
+    /// Simulate big emitters a different way, which recreates the buffers of the emitters that
+    /// change and kills their particles. Asking for `Compute` without compute shaders falls back
+    /// to `TransformFeedback`, and asking for `Cpu` simulates every emitter on the CPU.
+    pub fn set_gpu_simulation(&mut self, gl: &mut glow::Context, simulation: Simulation) {
+        self.gpu_simulation = match simulation {
+            Simulation::Compute if self.compute_program.is_none() => {
+                eprintln!(
+                    "Warning: Compute shaders aren't supported, simulating particles with \
+                     transform feedback instead"
+                );
+                Simulation::TransformFeedback
+            }
+            simulation => simulation,
+        };
+        self.rebuild_changed(gl);
+    }
+
+    /// The most particles an emitter can have and still be simulated on the CPU
+    pub fn cpu_threshold(&self) -> u32 {
+        self.cpu_threshold
+    }
+
+    /// Change the most particles an emitter can have and still be simulated on the CPU, which
+    /// recreates the buffers of the emitters that change
+    pub fn set_cpu_threshold(&mut self, gl: &mut glow::Context, cpu_threshold: u32) {
+        self.cpu_threshold = cpu_threshold;
+        self.rebuild_changed(gl);
+    }
+
+    /// How an emitter with the given number of particles is simulated
+    fn simulation_for(&self, max_particles: u32) -> Simulation {
+        if max_particles <= self.cpu_threshold {
+            Simulation::Cpu
+        } else {
+            self.gpu_simulation
+        }
+    }
+
+    /// Add an emitter, which starts spawning particles on the next update
+    pub fn add_emitter(&mut self, gl: &mut glow::Context, params: EmitterParams) -> EmitterId {
+        let simulation = self.simulation_for(params.max_particles);
+        let emitter = unsafe {
+            let curves = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(curves));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA32F as i32,
+                CURVE_SAMPLES as i32,
+                2,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                None,
+            );
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            resources::track_sized(
+                ResourceKind::Texture,
+                curves,
+                "Particle curves",
+                resources::texture_bytes(CURVE_SAMPLES, 2, glow::RGBA32F, 1, 1, 1),
+            );
+
+            let mut emitter = Emitter {
+                params,
+                simulation,
+                buffers: Self::create_buffers(gl, simulation, params.max_particles),
+                capacity: params.max_particles,
+                curves,
+                uploaded_curves: (params.color, params.size),
+                spawn_remainder: 0.,
+                spawn_cursor: 0,
+            };
+            emitter.upload_curves(gl);
+            emitter
+        };
+
+        // Reuse the slot of a removed emitter if there is one
+        match self.emitters.iter().position(Option::is_none) {
+            Some(index) => {
+                self.emitters[index] = Some(emitter);
+                EmitterId(index)
+            }
+            None => {
+                self.emitters.push(Some(emitter));
+                EmitterId(self.emitters.len() - 1)
+            }
+        }
+    }
+
+    /// Remove an emitter and its particles
+    pub fn remove_emitter(&mut self, gl: &mut glow::Context, id: EmitterId) {
+        if let Some(mut emitter) = self.emitters.get_mut(id.0).and_then(Option::take) {
+            unsafe {
+                emitter.delete_buffers(gl);
+                gl.delete_texture(emitter.curves);
+            }
+            resources::untrack(ResourceKind::Texture, emitter.curves);
+        }
+    }
+
+    /// The settings of an emitter, or `None` if it was removed
+    pub fn emitter(&self, id: EmitterId) -> Option<&EmitterParams> {
+        self.emitters
+            .get(id.0)
+            .and_then(Option::as_ref)
+            .map(|emitter| &emitter.params)
+    }
+
+    /// The settings of an emitter, which take effect on the next update, or `None` if it was
+    /// removed
+    pub fn emitter_mut(&mut self, id: EmitterId) -> Option<&mut EmitterParams> {
+        self.emitters
+            .get_mut(id.0)
+            .and_then(Option::as_mut)
+            .map(|emitter| &mut emitter.params)
+    }
+
+    /// The emitters that haven't been removed
+    pub fn emitter_ids(&self) -> impl Iterator<Item = EmitterId> + '_ {
+        self.emitters
+            .iter()
+            .enumerate()
+            .filter(|(_, emitter)| emitter.is_some())
+            .map(|(index, _)| EmitterId(index))
+    }
+
+    /// How an emitter is being simulated, or `None` if it was removed
+    pub fn simulation(&self, id: EmitterId) -> Option<Simulation> {
+        self.emitters
+            .get(id.0)
+            .and_then(Option::as_ref)
+            .map(|emitter| emitter.simulation)
+    }
+
+    /// The total number of particles that the emitters have room for
+    pub fn capacity(&self) -> u64 {
+        self.emitters
+            .iter()
+            .flatten()
+            .map(|emitter| emitter.capacity as u64)
+            .sum()
+    }
+
+    /// Create the buffers for simulating `count` particles
+    unsafe fn create_buffers(
+        gl: &mut glow::Context,
+        simulation: Simulation,
+        count: u32,
+    ) -> EmitterBuffers {
+        let count = count.max(1);
+        match simulation {
+            Simulation::Compute => {
+                let buffer = particle_buffer(gl, glow::SHADER_STORAGE_BUFFER, count);
+                EmitterBuffers::Compute {
+                    buffer,
+                    render_vao: particle_vao(gl, Some(buffer), true),
+                }
+            }
+            Simulation::TransformFeedback => {
+                let buffers = [
+                    particle_buffer(gl, glow::ARRAY_BUFFER, count),
+                    particle_buffer(gl, glow::ARRAY_BUFFER, count),
+                ];
+                EmitterBuffers::TransformFeedback {
+                    buffers,
+                    update_vaos: buffers.map(|buffer| particle_vao(gl, Some(buffer), false)),
+                    render_vaos: buffers.map(|buffer| particle_vao(gl, Some(buffer), true)),
+                    current: 0,
+                }
+            }
+            Simulation::Cpu => EmitterBuffers::Cpu {
+                particles: vec![Particle::DEAD; count as usize],
+                buffer: InstanceBuffer::new(
+                    &Features::default(),
+                    particle_layout().per_instance(),
+                    BufferUsage::Dynamic,
+                )
+                .with_label("Particles"),
+                // The instance buffer is attached before every draw
+                render_vao: particle_vao(gl, None, true),
+                rng: SmallRng::seed_from_u64(count as u64),
+            },
+        }
+    }
+
+    /// Recreate the buffers of the emitters whose size or simulation changed
+    fn rebuild_changed(&mut self, gl: &mut glow::Context) {
+        let (cpu_threshold, gpu_simulation) = (self.cpu_threshold, self.gpu_simulation);
+        for emitter in self.emitters.iter_mut().flatten() {
+            let max_particles = emitter.params.max_particles;
+            let simulation = if max_particles <= cpu_threshold {
+                Simulation::Cpu
+            } else {
+                gpu_simulation
+            };
+            if emitter.capacity == max_particles && emitter.simulation == simulation {
+                continue;
+            }
+            unsafe {
+                emitter.delete_buffers(gl);
+                emitter.buffers = Self::create_buffers(gl, simulation, max_particles);
+            }
+            emitter.simulation = simulation;
+            emitter.capacity = max_particles;
+            emitter.spawn_cursor = 0;
+        }
+    }
+
+    /// Spawn new particles and move the living ones forward by `delta` seconds
+    pub fn update(&mut self, gl: &mut glow::Context, delta: f32) {
+        self.rebuild_changed(gl);
+        let _group = DebugGroup::push(gl, "Particle update");
+        self.update_count = self.update_count.wrapping_add(1);
+        let seed = self.update_count as i32;
+        let mut computed = false;
+
+        for emitter in self.emitters.iter_mut().flatten() {
+            let params = emitter.params;
+            if emitter.uploaded_curves != (params.color, params.size) {
+                unsafe { emitter.upload_curves(gl) };
+            }
+
+            // Work out which particles to spawn this update
+            let count = emitter.capacity.max(1);
+            let spawn = emitter.spawn_remainder + params.spawn_rate.max(0.) * delta;
+            let spawn_count = (spawn as u32).min(count);
+            emitter.spawn_remainder = spawn.fract();
+            let spawn_start = emitter.spawn_cursor;
+            emitter.spawn_cursor = (spawn_start + spawn_count) % count;
+
+            // The CPU simulation doesn't need the shader uniforms
+            if let EmitterBuffers::Cpu {
+                particles,
+                buffer,
+                rng,
+                ..
+            } = &mut emitter.buffers
+            {
+                update_on_cpu(particles, &params, delta, spawn_start, spawn_count, rng);
+                buffer.write(gl, particles.as_mem_bytes());
+                continue;
+            }
+
+            let program = match emitter.buffers {
+                EmitterBuffers::Compute { .. } => self.compute_program.as_mut().unwrap(),
+                _ => &mut self.feedback_program,
+            };
+            program.bind(gl);
+            program.set_uniform(gl, "deltaTime", delta);
+            program.set_uniform(gl, "particleCount", count as i32);
+            program.set_uniform(gl, "spawnStart", spawn_start as i32);
+            program.set_uniform(gl, "spawnCount", spawn_count as i32);
+            program.set_uniform(gl, "seed", seed);
+            let position = params.position;
+            program.set_uniform(
+                gl,
+                "emitterPosition",
+                Vector3::new(position.x, position.y, position.z),
+            );
+            program.set_uniform(gl, "emitterDirection", normalized(params.direction));
+            program.set_uniform(gl, "coneAngle", params.cone_angle.to_radians());
+            program.set_uniform(gl, "speed", params.speed);
+            program.set_uniform(gl, "speedVariance", params.speed_variance);
+            program.set_uniform(gl, "lifetime", params.lifetime);
+            program.set_uniform(gl, "lifetimeVariance", params.lifetime_variance);
+            program.set_uniform(gl, "gravity", params.gravity);
+            program.set_uniform(gl, "drag", params.drag);
+
+            unsafe {
+                match &mut emitter.buffers {
+                    EmitterBuffers::Compute { buffer, .. } => {
+                        gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 0, Some(*buffer));
+                        gl.dispatch_compute(count.div_ceil(WORK_GROUP_SIZE), 1, 1);
+                        computed = true;
+                    }
+                    EmitterBuffers::TransformFeedback {
+                        buffers,
+                        update_vaos,
+                        current,
+                        ..
+                    } => {
+                        // Read the latest particles and write the next ones into the other buffer
+                        let next = 1 - *current;
+                        gl.enable(glow::RASTERIZER_DISCARD);
+                        gl.bind_vertex_array(Some(update_vaos[*current]));
+                        gl.bind_buffer_base(
+                            glow::TRANSFORM_FEEDBACK_BUFFER,
+                            0,
+                            Some(buffers[next]),
+                        );
+                        gl.begin_transform_feedback(glow::POINTS);
+                        gl.draw_arrays(glow::POINTS, 0, count as i32);
+                        gl.end_transform_feedback();
+                        gl.bind_buffer_base(glow::TRANSFORM_FEEDBACK_BUFFER, 0, None);
+                        gl.bind_vertex_array(None);
+                        gl.disable(glow::RASTERIZER_DISCARD);
+                        *current = next;
+                    }
+                    EmitterBuffers::Cpu { .. } => unreachable!(),
+                }
+            }
+        }
+
+        // Make the compute shader's writes visible to the draws and the next update
+        if computed {
+            if let Some(compute) = &self.compute {
+                compute.memory_barrier(
+                    glow::VERTEX_ATTRIB_ARRAY_BARRIER_BIT | glow::SHADER_STORAGE_BARRIER_BIT,
+                );
+            }
+            unsafe { gl.bind_buffer_base(glow::SHADER_STORAGE_BUFFER, 0, None) };
+        }
+    }
+
+    /// Draw every emitter's particles as point sprites, seen with the given view projection
+    /// matrix
+    ///
+    /// `projection_scale` is the vertical scale of the projection, `projection[1][1]`, and
+    /// `viewport_height` is the height of the viewport in pixels, which together turn the sizes
+    /// of the particles into pixels. The particles are depth tested without writing depth, so draw
+    /// them after the opaque parts of the scene. This leaves blending disabled.
+    pub fn draw(
+        &mut self,
+        gl: &mut glow::Context,
+        view_projection: Matrix4<f32>,
+        projection_scale: f32,
+        viewport_height: u32,
+    ) {
+        let _group = DebugGroup::push(gl, "Particles");
+        let program = &mut self.render_program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", view_projection);
+        program.set_uniform(
+            gl,
+            "pointScale",
+            projection_scale * viewport_height as f32 / 2.,
+        );
+        program.set_uniform(gl, "curves", 0);
+        program.set_uniform(gl, "curveSamples", CURVE_SAMPLES as f32);
+
+        unsafe {
+            gl.enable(glow::PROGRAM_POINT_SIZE);
+            gl.depth_mask(false);
+            gl.active_texture(glow::TEXTURE0);
+            for emitter in self.emitters.iter().flatten() {
+                emitter.params.blend.apply(gl);
+                gl.bind_texture(glow::TEXTURE_2D, Some(emitter.curves));
+                match &emitter.buffers {
+                    EmitterBuffers::Compute { render_vao, .. } => {
+                        gl.bind_vertex_array(Some(*render_vao));
+                    }
+                    EmitterBuffers::TransformFeedback {
+                        render_vaos,
+                        current,
+                        ..
+                    } => gl.bind_vertex_array(Some(render_vaos[*current])),
+                    EmitterBuffers::Cpu {
+                        buffer, render_vao, ..
+                    } => {
+                        gl.bind_vertex_array(Some(*render_vao));
+                        buffer.bind(gl);
+                    }
+                }
+                // One point per particle
+                gl.draw_arrays_instanced(glow::POINTS, 0, 1, emitter.capacity.max(1) as i32);
+            }
+            gl.bind_vertex_array(None);
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.disable(glow::BLEND);
+            gl.depth_mask(true);
+            gl.disable(glow::PROGRAM_POINT_SIZE);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for id in 0..self.emitters.len() {
+            self.remove_emitter(gl, EmitterId(id));
+        }
+        if let Some(program) = &mut self.compute_program {
+            program.delete(gl);
+        }
+        self.feedback_program.delete(gl);
+        self.render_program.delete(gl);
+    }
+}
+
+/// The direction, or up if it has no length
+fn normalized(direction: Vector3<f32>) -> Vector3<f32> {
+    if direction.magnitude2() > 0. {
+        direction.normalize()
+    } else {
+        Vector3::unit_y()
+    }
+}
+
+/// Spawn and move the particles on the CPU, the same way the update shaders do
+fn update_on_cpu(
+    particles: &mut [Particle],
+    params: &EmitterParams,
+    delta: f32,
+    spawn_start: u32,
+    spawn_count: u32,
+    rng: &mut SmallRng,
+) {
+    let count = particles.len() as u32;
+    let axis = normalized(params.direction);
+    let tangent = if axis.y.abs() < 0.99 {
+        Vector3::unit_y()
+    } else {
+        Vector3::unit_x()
+    }
+    .cross(axis)
+    .normalize();
+    let bitangent = axis.cross(tangent);
+    let signed = |rng: &mut SmallRng| rng.gen_range(-1., 1.);
+
+    for (index, particle) in particles.iter_mut().enumerate() {
+        let offset = (index as u32 + count - spawn_start) % count;
+        if offset < spawn_count {
+            // A random direction inside of the cone, spread evenly over its cap
+            let cos_angle = 1f32.lerp(&params.cone_angle.to_radians().cos(), rng.gen());
+            let sin_angle = (1. - cos_angle * cos_angle).max(0.).sqrt();
+            let around: f32 = rng.gen_range(0., std::f32::consts::PI * 2.);
+            let direction =
+                axis * cos_angle + (tangent * around.cos() + bitangent * around.sin()) * sin_angle;
+            let speed = params.speed * (1. + signed(rng) * params.speed_variance);
+            let lifetime =
+                (params.lifetime * (1. + signed(rng) * params.lifetime_variance)).max(0.001);
+            let age = rng.gen::<f32>() * delta;
+
+            let velocity = direction * speed;
+            let position = params.position + velocity * age;
+            *particle = Particle {
+                position: [position.x, position.y, position.z],
+                age,
+                velocity: velocity.into(),
+                lifetime,
+            };
+            continue;
+        }
+        if particle.age >= particle.lifetime {
+            continue;
+        }
+
+        let mut velocity = Vector3::from(particle.velocity);
+        velocity += params.gravity * delta;
+        velocity *= (1. - params.drag * delta).max(0.);
+        for axis in 0..3 {
+            particle.position[axis] += velocity[axis] * delta;
+        }
+        particle.age += delta;
+        particle.velocity = velocity.into();
+    }
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec4 color;
+
+out vec4 FragColor;
+
+void main() {
+    // A soft round dot that fades out towards its edge
+    float distanceFromCenter = length(gl_PointCoord * 2.0 - 1.0);
+    float falloff = 1.0 - smoothstep(0.0, 1.0, distanceFromCenter);
+    float alpha = color.a * falloff;
+    // Premultiplied, which suits both additive and premultiplied blending
+    FragColor = vec4(color.rgb * alpha, alpha);
+}
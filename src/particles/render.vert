@@ -0,0 +1,34 @@
+#version 330 core
+
+layout (location = 0) in vec4 positionAge;
+layout (location = 1) in vec4 velocityLifetime;
+
+out vec4 color;
+
+uniform mat4 viewProjection;
+// Turns a size in world units at a distance of 1 into a size in pixels
+uniform float pointScale;
+// The color over the particle's life in the first row, and the size in the red channel of the
+// second
+uniform sampler2D curves;
+uniform float curveSamples;
+
+void main() {
+    float life = positionAge.w / velocityLifetime.w;
+    if (life >= 1.0) {
+        // Dead particles are put outside of the view, where they are clipped
+        gl_Position = vec4(2.0, 2.0, 2.0, 1.0);
+        gl_PointSize = 1.0;
+        color = vec4(0.0);
+        return;
+    }
+
+    // Sample from the middle of the first texel to the middle of the last, so the ends of the
+    // curves are hit exactly
+    float u = (life * (curveSamples - 1.0) + 0.5) / curveSamples;
+    color = texture(curves, vec2(u, 0.25));
+    float size = texture(curves, vec2(u, 0.75)).r;
+
+    gl_Position = viewProjection * vec4(positionAge.xyz, 1.0);
+    gl_PointSize = size * pointScale / gl_Position.w;
+}
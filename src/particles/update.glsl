@@ -0,0 +1,88 @@
+// One particle, as it is stored in the particle buffers
+struct Particle {
+    // The position, and how long the particle has been alive in seconds
+    vec4 positionAge;
+    // The velocity, and how long the particle lives in seconds. The particle is dead once its age
+    // reaches its lifetime.
+    vec4 velocityLifetime;
+};
+
+uniform float deltaTime;
+uniform int particleCount;
+// The particles from `spawnStart` to `spawnStart + spawnCount`, wrapping around the end of the
+// buffer, are spawned this update
+uniform int spawnStart;
+uniform int spawnCount;
+// Changes every update so that new particles get new random values
+uniform int seed;
+
+uniform vec3 emitterPosition;
+uniform vec3 emitterDirection;
+// The angle between the emitter direction and the edge of the cone, in radians
+uniform float coneAngle;
+uniform float speed;
+uniform float speedVariance;
+uniform float lifetime;
+uniform float lifetimeVariance;
+uniform vec3 gravity;
+uniform float drag;
+
+// A cheap integer hash ( from "Hash Functions for GPU Rendering" by Jarzynski and Olano )
+uint hashUint(uint value) {
+    uint state = value * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+// A random number from 0 to 1, moving the state on for the next one
+float randomFloat(inout uint state) {
+    state = hashUint(state);
+    return float(state) / 4294967295.0;
+}
+
+// A random number from -1 to 1
+float randomSigned(inout uint state) {
+    return randomFloat(state) * 2.0 - 1.0;
+}
+
+Particle spawnParticle(uint index) {
+    uint state = hashUint(index ^ hashUint(uint(seed)));
+
+    // A random direction inside of the cone, spread evenly over its cap
+    float cosAngle = mix(1.0, cos(coneAngle), randomFloat(state));
+    float sinAngle = sqrt(max(1.0 - cosAngle * cosAngle, 0.0));
+    float around = randomFloat(state) * 6.28318531;
+    vec3 axis = emitterDirection;
+    vec3 tangent = normalize(cross(abs(axis.y) < 0.99 ? vec3(0.0, 1.0, 0.0) : vec3(1.0, 0.0, 0.0), axis));
+    vec3 bitangent = cross(axis, tangent);
+    vec3 direction = axis * cosAngle + (tangent * cos(around) + bitangent * sin(around)) * sinAngle;
+
+    float particleSpeed = speed * (1.0 + randomSigned(state) * speedVariance);
+    float particleLifetime = max(lifetime * (1.0 + randomSigned(state) * lifetimeVariance), 0.001);
+    // Spread the spawns over the update so that particles don't come out in clumps
+    float age = randomFloat(state) * deltaTime;
+
+    Particle particle;
+    particle.positionAge = vec4(emitterPosition + direction * particleSpeed * age, age);
+    particle.velocityLifetime = vec4(direction * particleSpeed, particleLifetime);
+    return particle;
+}
+
+// Spawn or move the particle at `index` for one update
+Particle updateParticle(Particle particle, uint index) {
+    uint offset = (index + uint(particleCount) - uint(spawnStart)) % uint(particleCount);
+    if (offset < uint(spawnCount)) {
+        return spawnParticle(index);
+    }
+    if (particle.positionAge.w >= particle.velocityLifetime.w) {
+        return particle;
+    }
+
+    vec3 velocity = particle.velocityLifetime.xyz;
+    velocity += gravity * deltaTime;
+    velocity *= max(1.0 - drag * deltaTime, 0.0);
+    particle.positionAge.xyz += velocity * deltaTime;
+    particle.positionAge.w += deltaTime;
+    particle.velocityLifetime.xyz = velocity;
+    return particle;
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+layout (location = 0) in vec4 positionAge;
+layout (location = 1) in vec4 velocityLifetime;
+
+// Captured with transform feedback into the other particle buffer
+out vec4 outPositionAge;
+out vec4 outVelocityLifetime;
+
+void main() {
+    Particle particle = updateParticle(Particle(positionAge, velocityLifetime), uint(gl_VertexID));
+    outPositionAge = particle.positionAge;
+    outVelocityLifetime = particle.velocityLifetime;
+}
@@ -0,0 +1,318 @@
+use std::time::{Duration, Instant};
+
+use cgmath::{Matrix4, SquareMatrix};
+use glow::HasContext;
+
+use crate::{
+    features::Features,
+    instance_buffer::DEFAULT_BUFFER_COUNT,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+};
+
+/// The GLSL for reading the per-draw data, which declares the `PerDraw` uniform block with
+/// `perDrawModel`, `perDrawColor`, `perDrawParams`, and `perDrawPreviousModel` in it. Add it to a
+/// shader with `shader::include_chunk`.
+pub const PER_DRAW_CHUNK: &str = include_str!("per_draw/per_draw.glsl");
+
+/// The name of the uniform block that `PerDrawBuffer::apply` looks for
+pub const PER_DRAW_BLOCK: &str = "PerDraw";
+
+/// The uniform buffer binding point the per-draw data is bound to
+pub const PER_DRAW_BINDING: u32 = 0;
+
+/// How many draws a frame's part of the buffer holds before it grows
+const INITIAL_DRAWS_PER_FRAME: usize = 256;
+
+/// How long to wait for the GPU in one go before asking again, in nanoseconds
+const FENCE_TIMEOUT: i32 = 100_000_000;
+
+/// The data of one draw, laid out like the `PerDraw` block in `PER_DRAW_CHUNK`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PerDrawData {
+    pub model: Matrix4<f32>,
+    pub color: [f32; 4],
+    /// Free for the shader to use, e.g. for flags or material parameters
+    pub params: [f32; 4],
+    /// The model matrix of the last frame, for working out how far things moved, e.g. for a
+    /// velocity buffer. `new` sets it to `model`, so that things that don't keep track of it
+    /// haven't moved.
+    pub previous_model: Matrix4<f32>,
+}
+
+impl Default for PerDrawData {
+    fn default() -> Self {
+        Self {
+            model: Matrix4::identity(),
+            color: [1.; 4],
+            params: [0.; 4],
+            previous_model: Matrix4::identity(),
+        }
+    }
+}
+
+impl PerDrawData {
+    /// The size of the data in the buffer with std140 layout: a matrix, two vectors, and another
+    /// matrix
+    pub const SIZE: usize = 160;
+
+    pub fn new(model: Matrix4<f32>) -> Self {
+        Self {
+            model,
+            previous_model: model,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_params(mut self, params: [f32; 4]) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn with_previous_model(mut self, previous_model: Matrix4<f32>) -> Self {
+        self.previous_model = previous_model;
+        self
+    }
+
+    /// The data as it is laid out in the buffer
+    fn to_bytes(self) -> [u8; Self::SIZE] {
+        let model: &[f32; 16] = self.model.as_ref();
+        let previous_model: &[f32; 16] = self.previous_model.as_ref();
+        let mut bytes = [0; Self::SIZE];
+        let floats = model
+            .iter()
+            .chain(&self.color)
+            .chain(&self.params)
+            .chain(previous_model);
+        for (chunk, value) in bytes.chunks_exact_mut(4).zip(floats) {
+            chunk.copy_from_slice(&value.to_ne_bytes());
+        }
+        bytes
+    }
+}
+
+/// Counts of how the per-draw buffer has been used
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PerDrawStats {
+    /// The draws written since the last `begin_frame`
+    pub frame_draws: u64,
+    /// The frames whose part of the buffer was still in use by the GPU
+    pub waits: u64,
+    /// The total time spent waiting
+    pub wait_time: Duration,
+    /// How many times the buffer was recreated to make room for more draws
+    pub reallocations: u64,
+}
+
+/// A ring of per-draw data in a uniform buffer, as a cheaper stand-in for setting the model
+/// matrix and the rest as separate uniforms before every draw
+///
+/// Each draw's data is written at the next offset and bound to `PER_DRAW_BINDING` with
+/// `glBindBufferRange`, with the offsets spaced out to `UNIFORM_BUFFER_OFFSET_ALIGNMENT`. The
+/// buffer is split into a part for each of the last few frames, which fences guard like a
+/// `Dynamic` instance buffer. Without `glBufferStorage` there is one part instead, which is
+/// orphaned at the start of every frame.
+#[derive(Debug)]
+pub struct PerDrawBuffer {
+    ubo: Option<u32>,
+    /// Whether or not the buffer has immutable storage and its parts are guarded by fences,
+    /// instead of being orphaned
+    fenced: bool,
+    /// The distance between the data of two draws, which is rounded up to the alignment
+    stride: usize,
+    /// How many draws each frame's part holds
+    draws_per_frame: usize,
+    /// The fence of each frame's part, signalled once the GPU has finished the frame's draws
+    fences: Vec<Option<glow::Fence>>,
+    /// The part of the buffer that the current frame writes into
+    current: usize,
+    /// How many draws have been written into the current part
+    cursor: usize,
+    stats: PerDrawStats,
+}
+
+impl PerDrawBuffer {
+    pub fn new(gl: &mut glow::Context, features: &Features) -> Self {
+        let alignment =
+            unsafe { gl.get_parameter_i32(glow::UNIFORM_BUFFER_OFFSET_ALIGNMENT) }.max(1) as usize;
+        let fenced = features.buffer_storage;
+        Self {
+            ubo: None,
+            fenced,
+            stride: PerDrawData::SIZE.div_ceil(alignment) * alignment,
+            draws_per_frame: INITIAL_DRAWS_PER_FRAME,
+            fences: (0..if fenced { DEFAULT_BUFFER_COUNT } else { 1 })
+                .map(|_| None)
+                .collect(),
+            current: 0,
+            cursor: 0,
+            stats: PerDrawStats::default(),
+        }
+    }
+
+    /// The distance between the data of two draws in the buffer, in bytes
+    pub fn stride(&self) -> usize {
+        self.stride
+    }
+
+    pub fn stats(&self) -> PerDrawStats {
+        self.stats
+    }
+
+    /// Move on to the next frame's part of the buffer, waiting for the GPU to finish with it if
+    /// it has to
+    pub fn begin_frame(&mut self, gl: &mut glow::Context) {
+        self.stats.frame_draws = 0;
+        let ubo = match self.ubo {
+            Some(ubo) => ubo,
+            None => return,
+        };
+        unsafe {
+            if self.fenced {
+                // Everything that reads the current part has been sent by now, so fence it before
+                // moving on to the next one
+                if let Some(fence) = self.fences[self.current].take() {
+                    gl.delete_sync(fence);
+                }
+                self.fences[self.current] = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).ok();
+                self.current = (self.current + 1) % self.fences.len();
+                self.wait(gl);
+            } else {
+                // Orphan the old contents, which the GPU may still be reading
+                gl.bind_buffer(glow::UNIFORM_BUFFER, Some(ubo));
+                gl.buffer_data_size(
+                    glow::UNIFORM_BUFFER,
+                    self.capacity() as i32,
+                    glow::STREAM_DRAW,
+                );
+                gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+            }
+        }
+        self.cursor = 0;
+    }
+
+    /// Give a draw its data, which the program has to be bound for
+    ///
+    /// Programs that declare the `PerDraw` block get the data from the buffer. Programs without it
+    /// get the model matrix as a `model` uniform instead, which is what the examples call it, and
+    /// last frame's as `previousModel`. The color and params are left out.
+    pub fn apply(
+        &mut self,
+        gl: &mut glow::Context,
+        program: &mut ShaderProgram,
+        data: &PerDrawData,
+    ) {
+        if program.bind_uniform_block(gl, PER_DRAW_BLOCK, PER_DRAW_BINDING) {
+            self.push(gl, data);
+        } else {
+            program.set_uniform(gl, "model", data.model);
+            program.set_uniform(gl, "previousModel", data.previous_model);
+        }
+    }
+
+    /// Write a draw's data at the next offset and bind it to `PER_DRAW_BINDING`
+    ///
+    /// This leaves the buffer bound to `UNIFORM_BUFFER`.
+    pub fn push(&mut self, gl: &mut glow::Context, data: &PerDrawData) {
+        unsafe {
+            if self.ubo.is_none() || self.cursor == self.draws_per_frame {
+                self.reallocate(gl);
+            }
+            let offset = ((self.current * self.draws_per_frame + self.cursor) * self.stride) as i32;
+            gl.bind_buffer(glow::UNIFORM_BUFFER, self.ubo);
+            gl.buffer_sub_data_u8_slice(glow::UNIFORM_BUFFER, offset, &data.to_bytes());
+            gl.bind_buffer_range(
+                glow::UNIFORM_BUFFER,
+                PER_DRAW_BINDING,
+                self.ubo,
+                offset,
+                PerDrawData::SIZE as i32,
+            );
+        }
+        self.cursor += 1;
+        self.stats.frame_draws += 1;
+    }
+
+    /// The size of the buffer in bytes
+    fn capacity(&self) -> usize {
+        self.stride * self.draws_per_frame * self.fences.len()
+    }
+
+    /// Wait until the GPU has finished reading the current part
+    unsafe fn wait(&mut self, gl: &mut glow::Context) {
+        let fence = match self.fences[self.current].take() {
+            Some(fence) => fence,
+            None => return,
+        };
+        let mut status = gl.client_wait_sync(fence, 0, 0);
+        if status == glow::TIMEOUT_EXPIRED {
+            let start = Instant::now();
+            while status == glow::TIMEOUT_EXPIRED {
+                status = gl.client_wait_sync(fence, glow::SYNC_FLUSH_COMMANDS_BIT, FENCE_TIMEOUT);
+            }
+            self.stats.waits += 1;
+            self.stats.wait_time += start.elapsed();
+        }
+        if status == glow::WAIT_FAILED {
+            eprintln!("Warning: Waiting for a per-draw buffer fence failed");
+        }
+        gl.delete_sync(fence);
+    }
+
+    /// Create the buffer, or replace it with one that holds twice as many draws a frame
+    ///
+    /// The draws already written this frame keep reading the old buffer, since GL keeps it alive
+    /// until the GPU is done with it.
+    unsafe fn reallocate(&mut self, gl: &mut glow::Context) {
+        if self.ubo.is_some() {
+            self.stats.reallocations += 1;
+            self.draws_per_frame *= 2;
+            self.delete(gl);
+        }
+        let ubo = gl.create_buffer().unwrap();
+        gl.bind_buffer(glow::UNIFORM_BUFFER, Some(ubo));
+        if self.fenced {
+            gl.buffer_storage(
+                glow::UNIFORM_BUFFER,
+                self.capacity() as i32,
+                None,
+                glow::DYNAMIC_STORAGE_BIT,
+            );
+        } else {
+            gl.buffer_data_size(
+                glow::UNIFORM_BUFFER,
+                self.capacity() as i32,
+                glow::STREAM_DRAW,
+            );
+        }
+        resources::track_sized(
+            ResourceKind::Buffer,
+            ubo,
+            "Per-draw buffer",
+            self.capacity() as u64,
+        );
+        self.ubo = Some(ubo);
+        self.current = 0;
+        self.cursor = 0;
+    }
+
+    /// Delete the buffer and its fences
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe {
+            for fence in &mut self.fences {
+                if let Some(fence) = fence.take() {
+                    gl.delete_sync(fence);
+                }
+            }
+            if let Some(ubo) = self.ubo.take() {
+                gl.delete_buffer(ubo);
+                resources::untrack(ResourceKind::Buffer, ubo);
+            }
+        }
+    }
+}
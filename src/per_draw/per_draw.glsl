@@ -0,0 +1,11 @@
+// The data of the current draw, written into a ring of uniform buffers by `PerDrawBuffer::apply`
+// instead of being set as separate uniforms
+layout (std140) uniform PerDraw {
+    mat4 perDrawModel;
+    vec4 perDrawColor;
+    // Free for the shader to use, e.g. for flags or material parameters
+    vec4 perDrawParams;
+    // The model matrix of the last frame, which is the same as `perDrawModel` for things that
+    // don't keep track of it
+    mat4 perDrawPreviousModel;
+};
@@ -0,0 +1,293 @@
+use cgmath::{InnerSpace, Matrix, Matrix4, SquareMatrix, Vector3, Vector4};
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    depth_mode, diagnostics,
+    resources::{self, ResourceKind},
+};
+
+/// What a planar reflection does when the camera is below the plane
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BelowPlane {
+    /// Don't draw the reflection. `render` returns false, so the surface can be drawn without it.
+    Skip,
+    /// Reflect what is below the plane instead, like the underside of a water surface
+    Flip,
+}
+
+/// The settings of a planar reflection
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlanarReflectionParams {
+    /// The size of the reflection compared to the window. Reflections are usually distorted
+    /// anyway, so half the size saves a lot of time without looking much worse.
+    pub resolution_scale: f32,
+    /// How far past the plane the clipping starts, which keeps a gap from showing where things
+    /// go into the plane, like along a shoreline
+    pub clip_offset: f32,
+    pub below_plane: BelowPlane,
+}
+
+impl Default for PlanarReflectionParams {
+    fn default() -> Self {
+        Self {
+            resolution_scale: 0.5,
+            clip_offset: 0.05,
+            below_plane: BelowPlane::Flip,
+        }
+    }
+}
+
+/// The matrix that mirrors points about the horizontal plane at `height`
+pub fn mirror_matrix(height: f32) -> Matrix4<f32> {
+    Matrix4::from_translation(Vector3::new(0., height, 0.))
+        * Matrix4::from_nonuniform_scale(1., -1., 1.)
+        * Matrix4::from_translation(Vector3::new(0., -height, 0.))
+}
+
+/// Replace the near plane of a projection with a view space clip plane, so that everything on the
+/// negative side of the plane is clipped without needing `gl_ClipDistance`
+///
+/// This is Eric Lengyel's oblique near-plane clipping. The camera has to be on the negative side
+/// of the plane, and the projection has to be built for standard depth, like the ones from
+/// `DepthSetup::standard_projection`. The far plane moves too, so depth precision gets worse the
+/// steeper the plane is compared to the view direction.
+pub fn oblique_projection(projection: Matrix4<f32>, plane: Vector4<f32>) -> Matrix4<f32> {
+    // The corner of the frustum opposite the plane, in view space
+    let corner =
+        projection.invert().unwrap() * Vector4::new(plane.x.signum(), plane.y.signum(), 1., 1.);
+    let scaled = plane * (2. / plane.dot(corner));
+
+    // Replace the third row, which cgmath stores across the columns
+    let mut projection = projection;
+    projection.x.z = scaled.x - projection.x.w;
+    projection.y.z = scaled.y - projection.y.w;
+    projection.z.z = scaled.z - projection.z.w;
+    projection.w.z = scaled.w - projection.w.w;
+    projection
+}
+
+/// The texture a reflection is drawn into, with a depth buffer
+#[derive(Debug)]
+struct ReflectionTarget {
+    size: (u32, u32),
+    framebuffer: u32,
+    texture: u32,
+    depth: u32,
+}
+
+impl ReflectionTarget {
+    unsafe fn new(gl: &mut glow::Context, (width, height): (u32, u32)) -> Self {
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            glow::RGBA8 as i32,
+            width as i32,
+            height as i32,
+            0,
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+            None,
+        );
+        // Distorted lookups near the edges shouldn't wrap around to the other side
+        for (parameter, value) in [
+            (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+            (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+            (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+        ] {
+            gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+        }
+        gl.bind_texture(glow::TEXTURE_2D, None);
+
+        // Floating point depth, which reverse-Z needs for its precision, takes as much memory as
+        // 24 bit depth
+        let depth = gl.create_renderbuffer().unwrap();
+        gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth));
+        gl.renderbuffer_storage(
+            glow::RENDERBUFFER,
+            glow::DEPTH_COMPONENT32F,
+            width as i32,
+            height as i32,
+        );
+        gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+        let framebuffer = gl.create_framebuffer().unwrap();
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(texture),
+            0,
+        );
+        gl.framebuffer_renderbuffer(
+            glow::FRAMEBUFFER,
+            glow::DEPTH_ATTACHMENT,
+            glow::RENDERBUFFER,
+            Some(depth),
+        );
+        if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+            diagnostics::report_problem("The planar reflection framebuffer is incomplete");
+        }
+
+        let label = format!("Planar reflection {}x{}", width, height);
+        resources::track_sized(
+            ResourceKind::Texture,
+            texture,
+            &label,
+            resources::texture_bytes(width, height, glow::RGBA8, 1, 1, 1),
+        );
+        resources::track_sized(
+            ResourceKind::Renderbuffer,
+            depth,
+            &label,
+            resources::texture_bytes(width, height, glow::DEPTH_COMPONENT32F, 1, 1, 1),
+        );
+        resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+        Self {
+            size: (width, height),
+            framebuffer,
+            texture,
+            depth,
+        }
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_renderbuffer(self.depth);
+            gl.delete_texture(self.texture);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Renderbuffer, self.depth);
+        resources::untrack(ResourceKind::Texture, self.texture);
+    }
+}
+
+/// A reflection in a horizontal plane, like a water surface, drawn by rendering the scene again
+/// mirrored about the plane
+///
+/// `render` draws the mirrored scene into a texture with everything on the other side of the plane
+/// clipped away. The reflection lines up with the screen, so the surface samples it at its own
+/// screen position, e.g. `gl_FragCoord.xy / screenSize`, which can be offset to make ripples.
+#[derive(Debug)]
+pub struct PlanarReflection {
+    pub params: PlanarReflectionParams,
+    target: Option<ReflectionTarget>,
+}
+
+impl PlanarReflection {
+    pub fn new(params: PlanarReflectionParams) -> Self {
+        Self {
+            params,
+            target: None,
+        }
+    }
+
+    /// The texture holding the last reflection that was drawn, or `None` before the first one
+    pub fn texture(&self) -> Option<u32> {
+        self.target.as_ref().map(|target| target.texture)
+    }
+
+    /// The size of the reflection texture, or `None` before the first reflection is drawn
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.target.as_ref().map(|target| target.size)
+    }
+
+    /// Draw the reflection in the plane at `height` of a camera with the given view and projection
+    /// into the reflection texture. `window_size` is the size of the window the camera draws to,
+    /// which the texture follows.
+    ///
+    /// `draw_scene` is called once with the mirrored and clipped view projection matrix, and
+    /// should draw everything that shows up in the reflection, but not the reflecting surface.
+    /// Mirroring turns triangles around, so the front face is set to clockwise while it runs. The
+    /// framebuffer that was bound is bound again afterwards, but the viewport is left at the size
+    /// of the reflection texture.
+    ///
+    /// Returns false without drawing anything if the camera is below the plane and
+    /// `params.below_plane` is `Skip`.
+    pub fn render<F: FnOnce(&mut glow::Context, Matrix4<f32>)>(
+        &mut self,
+        gl: &mut glow::Context,
+        height: f32,
+        view: Matrix4<f32>,
+        projection: Matrix4<f32>,
+        window_size: (u32, u32),
+        draw_scene: F,
+    ) -> bool {
+        // Work out which side of the plane the camera is on
+        let camera = view.invert().unwrap() * Vector4::unit_w();
+        let normal = if camera.y >= height {
+            Vector4::unit_y()
+        } else if self.params.below_plane == BelowPlane::Flip {
+            -Vector4::unit_y()
+        } else {
+            return false;
+        };
+
+        // Clip everything on the camera's side of the plane, which would otherwise block the
+        // reflection, using the plane as the near plane of the mirrored camera
+        let mirrored_view = view * mirror_matrix(height);
+        let plane = Vector4::new(0., 0., 0., self.params.clip_offset - normal.y * height) + normal;
+        let view_plane = mirrored_view.invert().unwrap().transpose() * plane;
+        let projection = if view_plane.w < 0. {
+            // The clipping works on a standard projection, and the clipped one goes back to the
+            // current depth setup
+            let depth = depth_mode::current();
+            depth.projection(oblique_projection(
+                depth.standard_projection(projection),
+                view_plane,
+            ))
+        } else {
+            // The camera is closer to the plane than the clip offset, where oblique clipping
+            // breaks down, and there is hardly anything to reflect anyway
+            projection
+        };
+
+        let scale = self.params.resolution_scale.max(0.01);
+        let size = (
+            ((window_size.0 as f32 * scale) as u32).max(1),
+            ((window_size.1 as f32 * scale) as u32).max(1),
+        );
+        if self.size() != Some(size) {
+            if let Some(target) = self.target.take() {
+                target.delete(gl);
+            }
+            self.target = Some(unsafe { ReflectionTarget::new(gl, size) });
+        }
+        let target = self.target.as_ref().unwrap();
+        let _group = DebugGroup::push(gl, "Planar reflection");
+
+        unsafe {
+            let previous_framebuffer = gl.get_parameter_i32(glow::DRAW_FRAMEBUFFER_BINDING) as u32;
+            let front_face = gl.get_parameter_i32(glow::FRONT_FACE) as u32;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(target.framebuffer));
+            gl.viewport(0, 0, size.0 as i32, size.1 as i32);
+            gl.clear(glow::COLOR_BUFFER_BIT | glow::DEPTH_BUFFER_BIT);
+            gl.front_face(glow::CW);
+
+            draw_scene(gl, projection * mirrored_view);
+
+            gl.front_face(front_face);
+            gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                if previous_framebuffer == 0 {
+                    None
+                } else {
+                    Some(previous_framebuffer)
+                },
+            );
+        }
+        true
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        if let Some(target) = self.target.take() {
+            target.delete(gl);
+        }
+    }
+}
@@ -0,0 +1,312 @@
+use cgmath::{Deg, Matrix4, Point3, Vector3};
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    debug_scope, depth_mode,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+};
+
+const DEPTH_VERTEX_SRC: &str = include_str!("point_shadow/depth.vert");
+const DEPTH_FRAGMENT_SRC: &str = include_str!("point_shadow/depth.frag");
+
+/// The GLSL for sampling a point shadow, which declares the uniforms set by `PointShadow::bind`
+/// and a `float pointShadow(vec3 worldPosition)` function. Add it to a fragment shader with
+/// `shader::include_chunk`.
+pub const SHADOW_CHUNK: &str = include_str!("point_shadow/shadow.glsl");
+
+/// The direction each cube face looks in and its up direction, in the order of the
+/// `TEXTURE_CUBE_MAP_POSITIVE_X` face targets
+const FACES: [([f32; 3], [f32; 3]); 6] = [
+    ([1., 0., 0.], [0., -1., 0.]),
+    ([-1., 0., 0.], [0., -1., 0.]),
+    ([0., 1., 0.], [0., 0., 1.]),
+    ([0., -1., 0.], [0., 0., -1.]),
+    ([0., 0., 1.], [0., -1., 0.]),
+    ([0., 0., -1.], [0., -1., 0.]),
+];
+
+/// When a point shadow draws its cube map again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShadowUpdate {
+    /// Every time `render` is called
+    EveryFrame,
+    /// Only when the light moves, its radius changes, or `PointShadow::invalidate` is called
+    /// because something that casts shadows moved
+    WhenChanged,
+}
+
+/// The settings of a point shadow
+#[derive(Clone, Copy, Debug)]
+pub struct PointShadowParams {
+    /// The size of each face of the cube map in pixels. Every face is drawn separately, so this
+    /// costs six times as much as a directional shadow map of the same size.
+    pub resolution: u32,
+    /// The near plane of the face projections, which nothing closer to the light can shadow
+    pub near: f32,
+    /// How much further than the stored distance a surface has to be before it is in shadow,
+    /// which keeps surfaces from shadowing themselves
+    pub bias: f32,
+    /// How far apart the filter samples are, which softens the shadow edges
+    pub filter_radius: f32,
+    pub update: ShadowUpdate,
+}
+
+impl Default for PointShadowParams {
+    fn default() -> Self {
+        Self {
+            resolution: 512,
+            near: 0.05,
+            bias: 0.05,
+            filter_radius: 0.02,
+            update: ShadowUpdate::WhenChanged,
+        }
+    }
+}
+
+/// The view projection of each cube map face for a light at `position`, in the order of the
+/// `TEXTURE_CUBE_MAP_POSITIVE_X` face targets
+pub fn face_view_projections(position: Point3<f32>, near: f32, far: f32) -> [Matrix4<f32>; 6] {
+    let projection = cgmath::perspective(Deg(90.), 1., near, far);
+    FACES.map(|(direction, up)| {
+        projection * Matrix4::look_at_dir(position, Vector3::from(direction), Vector3::from(up))
+    })
+}
+
+/// A shadow for a point light, stored as the distance to the closest surface in every direction
+/// in a depth cube map
+///
+/// `render` draws the scene into each face of the cube map, and fragment shaders that include
+/// `SHADOW_CHUNK` sample it with `pointShadow` after `bind`. By default the cube map is only
+/// drawn again when the light moves, so call `invalidate` when something that casts shadows moves.
+#[derive(Debug)]
+pub struct PointShadow {
+    params: PointShadowParams,
+    program: ShaderProgram,
+    framebuffer: u32,
+    texture: u32,
+    /// The light the cube map was last drawn for, as its position and radius
+    rendered_for: Option<(Point3<f32>, f32)>,
+    /// Whether or not the cube map has to be drawn again even if the light hasn't moved
+    invalidated: bool,
+    /// How many times the cube map has been drawn
+    render_count: u64,
+}
+
+impl PointShadow {
+    pub fn new(gl: &mut glow::Context, params: PointShadowParams) -> Self {
+        let program = ShaderProgram::new(gl, DEPTH_VERTEX_SRC, DEPTH_FRAGMENT_SRC).unwrap();
+        let (framebuffer, texture) = unsafe {
+            let framebuffer = gl.create_framebuffer().unwrap();
+            resources::track(ResourceKind::Framebuffer, framebuffer, "Point shadow");
+            (framebuffer, Self::create_cube_map(gl, params.resolution))
+        };
+        Self {
+            params,
+            program,
+            framebuffer,
+            texture,
+            rendered_for: None,
+            invalidated: true,
+            render_count: 0,
+        }
+    }
+
+    /// Create the depth cube map, with every face `resolution` pixels square
+    unsafe fn create_cube_map(gl: &mut glow::Context, resolution: u32) -> u32 {
+        let texture = gl.create_texture().unwrap();
+        gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(texture));
+        for face in 0..6 {
+            gl.tex_image_2d(
+                glow::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                0,
+                glow::DEPTH_COMPONENT24 as i32,
+                resolution as i32,
+                resolution as i32,
+                0,
+                glow::DEPTH_COMPONENT,
+                glow::FLOAT,
+                None,
+            );
+        }
+        for (parameter, value) in [
+            (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+            (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+            (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            (glow::TEXTURE_WRAP_R, glow::CLAMP_TO_EDGE),
+        ] {
+            gl.tex_parameter_i32(glow::TEXTURE_CUBE_MAP, parameter, value as i32);
+        }
+        gl.bind_texture(glow::TEXTURE_CUBE_MAP, None);
+        resources::track_sized(
+            ResourceKind::Texture,
+            texture,
+            &format!("Point shadow {0}x{0}", resolution),
+            resources::texture_bytes(resolution, resolution, glow::DEPTH_COMPONENT24, 1, 6, 1),
+        );
+        resources::set_texture_target(texture, glow::TEXTURE_CUBE_MAP);
+        texture
+    }
+
+    pub fn params(&self) -> &PointShadowParams {
+        &self.params
+    }
+
+    /// The depth cube map
+    pub fn texture(&self) -> u32 {
+        self.texture
+    }
+
+    /// How many times the cube map has been drawn, which shows how often `WhenChanged` saves
+    /// drawing it
+    pub fn render_count(&self) -> u64 {
+        self.render_count
+    }
+
+    /// Draw the cube map again on the next `render`, e.g. because something that casts shadows
+    /// moved
+    pub fn invalidate(&mut self) {
+        self.invalidated = true;
+    }
+
+    /// Change how often the cube map is drawn
+    pub fn set_update(&mut self, update: ShadowUpdate) {
+        self.params.update = update;
+    }
+
+    /// Change the size of the cube map faces, which draws it again on the next `render`
+    pub fn set_resolution(&mut self, gl: &mut glow::Context, resolution: u32) {
+        if resolution == self.params.resolution {
+            return;
+        }
+        unsafe {
+            gl.delete_texture(self.texture);
+            resources::untrack(ResourceKind::Texture, self.texture);
+            self.texture = Self::create_cube_map(gl, resolution);
+        }
+        self.params.resolution = resolution;
+        self.invalidate();
+    }
+
+    /// Draw the distance from a light at `light_position` to everything within `radius` of it
+    /// into the cube map, if it needs to be drawn again. Returns whether or not it was drawn.
+    ///
+    /// `draw_scene` is called once for each face with the depth program bound, and should set the
+    /// `model` matrix uniform of the program and draw everything that casts shadows. The
+    /// framebuffer that was bound is bound again afterwards, but the viewport is left at the size
+    /// of the cube map.
+    ///
+    /// The cube map always holds standard depth, whatever `RenderSettings::depth_mode` is, since
+    /// the distances are written by the fragment shader. The depth test and clear depth are set
+    /// for it while it is drawn and put back afterwards.
+    pub fn render<F: FnMut(&mut glow::Context, &mut ShaderProgram)>(
+        &mut self,
+        gl: &mut glow::Context,
+        light_position: Point3<f32>,
+        radius: f32,
+        mut draw_scene: F,
+    ) -> bool {
+        let light = (light_position, radius);
+        if self.params.update == ShadowUpdate::WhenChanged
+            && !self.invalidated
+            && self.rendered_for == Some(light)
+        {
+            return false;
+        }
+        let _group = DebugGroup::push(gl, "Point shadow");
+
+        // The faces still have to be clipped the way the current setup clips
+        let depth = depth_mode::current();
+        let view_projections = face_view_projections(light_position, self.params.near, radius)
+            .map(|view_projection| depth.projection(view_projection));
+        let resolution = self.params.resolution as i32;
+        unsafe {
+            let previous_framebuffer = gl.get_parameter_i32(glow::DRAW_FRAMEBUFFER_BINDING) as u32;
+            let depth_func = gl.get_parameter_i32(glow::DEPTH_FUNC) as u32;
+            gl.depth_func(glow::LESS);
+            gl.clear_depth_f32(1.);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.framebuffer));
+            // Only depth is written
+            gl.draw_buffer(glow::NONE);
+            gl.read_buffer(glow::NONE);
+            gl.viewport(0, 0, resolution, resolution);
+
+            self.program.bind(gl);
+            self.program.set_uniform(
+                gl,
+                "lightPosition",
+                Vector3::new(light.0.x, light.0.y, light.0.z),
+            );
+            self.program.set_uniform(gl, "farPlane", radius);
+
+            for (face, view_projection) in view_projections.iter().enumerate() {
+                gl.framebuffer_texture_2d(
+                    glow::FRAMEBUFFER,
+                    glow::DEPTH_ATTACHMENT,
+                    glow::TEXTURE_CUBE_MAP_POSITIVE_X + face as u32,
+                    Some(self.texture),
+                    0,
+                );
+                if face == 0
+                    && gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE
+                {
+                    eprintln!("Warning: The point shadow framebuffer is incomplete");
+                }
+                gl.clear(glow::DEPTH_BUFFER_BIT);
+                self.program
+                    .set_uniform(gl, "viewProjection", *view_projection);
+                let program = &mut self.program;
+                debug_scope!(gl, &format!("Face {}", face), { draw_scene(gl, program) });
+            }
+
+            gl.depth_func(depth_func);
+            gl.clear_depth_f32(depth.mode.clear_depth());
+            gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                if previous_framebuffer == 0 {
+                    None
+                } else {
+                    Some(previous_framebuffer)
+                },
+            );
+        }
+
+        self.rendered_for = Some(light);
+        self.invalidated = false;
+        self.render_count += 1;
+        true
+    }
+
+    /// Bind the cube map to a texture unit and set the uniforms declared by `SHADOW_CHUNK` on a
+    /// program, which should be bound
+    pub fn bind(&self, gl: &mut glow::Context, program: &mut ShaderProgram, texture_unit: u32) {
+        unsafe {
+            gl.active_texture(glow::TEXTURE0 + texture_unit);
+            gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(self.texture));
+        }
+        // Nothing is in shadow before the cube map is first drawn
+        let (position, radius) = self.rendered_for.unwrap_or((Point3::new(0., 0., 0.), 0.));
+        program.set_uniform(gl, "pointShadowMap", texture_unit as i32);
+        program.set_uniform(
+            gl,
+            "pointShadowLight",
+            Vector3::new(position.x, position.y, position.z),
+        );
+        program.set_uniform(gl, "pointShadowFar", radius);
+        program.set_uniform(gl, "pointShadowBias", self.params.bias);
+        program.set_uniform(gl, "pointShadowFilter", self.params.filter_radius);
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_texture(self.texture);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Texture, self.texture);
+        self.program.delete(gl);
+    }
+}
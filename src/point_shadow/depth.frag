@@ -0,0 +1,12 @@
+#version 330 core
+
+in vec3 worldPosition;
+
+uniform vec3 lightPosition;
+uniform float farPlane;
+
+void main() {
+    // Store the distance from the light instead of the projected depth, so that the map can be
+    // compared against the same distance from any direction
+    gl_FragDepth = length(worldPosition - lightPosition) / farPlane;
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+
+out vec3 worldPosition;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+void main() {
+    vec4 world = model * vec4(aPos, 1.0);
+    worldPosition = world.xyz;
+    gl_Position = viewProjection * world;
+}
@@ -0,0 +1,37 @@
+// Sampling a point light's shadow cubemap rendered by `PointShadow`, which sets these uniforms
+// with `PointShadow::bind`
+uniform samplerCube pointShadowMap;
+uniform vec3 pointShadowLight;
+uniform float pointShadowFar;
+uniform float pointShadowBias;
+uniform float pointShadowFilter;
+
+// Directions to spread the filter samples in, which are far apart to need fewer samples than a
+// grid would
+const int POINT_SHADOW_SAMPLES = 20;
+const vec3 POINT_SHADOW_OFFSETS[POINT_SHADOW_SAMPLES] = vec3[](
+    vec3( 1,  1,  1), vec3( 1, -1,  1), vec3(-1, -1,  1), vec3(-1,  1,  1),
+    vec3( 1,  1, -1), vec3( 1, -1, -1), vec3(-1, -1, -1), vec3(-1,  1, -1),
+    vec3( 1,  1,  0), vec3( 1, -1,  0), vec3(-1, -1,  0), vec3(-1,  1,  0),
+    vec3( 1,  0,  1), vec3(-1,  0,  1), vec3( 1,  0, -1), vec3(-1,  0, -1),
+    vec3( 0,  1,  1), vec3( 0, -1,  1), vec3( 0, -1, -1), vec3( 0,  1, -1)
+);
+
+// How much of the point light reaches a position in world space, from 0 in full shadow to 1
+float pointShadow(vec3 worldPosition) {
+    vec3 toFragment = worldPosition - pointShadowLight;
+    float fragmentDistance = length(toFragment);
+    if (fragmentDistance >= pointShadowFar) {
+        return 1.0;
+    }
+
+    // Soften the edges more the further they are from the light
+    float radius = pointShadowFilter * (1.0 + fragmentDistance / pointShadowFar);
+    float lit = 0.0;
+    for (int i = 0; i < POINT_SHADOW_SAMPLES; i++) {
+        vec3 direction = toFragment + POINT_SHADOW_OFFSETS[i] * radius;
+        float closest = texture(pointShadowMap, direction).r * pointShadowFar;
+        lit += fragmentDistance - pointShadowBias > closest ? 0.0 : 1.0;
+    }
+    return lit / float(POINT_SHADOW_SAMPLES);
+}
@@ -0,0 +1,145 @@
+use cgmath::{Deg, Matrix4, Vector3};
+
+use crate::{
+    camera::FlyCamera,
+    stereo::{EyeMatrices, EyeView},
+    AppContext,
+};
+
+/// How far the eyes of `MousePose`'s head are from the neck it turns around, in world units: up
+/// and in front, about where they are on a person in meters
+const NECK_TO_EYES: [f32; 3] = [0., 0.08, -0.08];
+
+/// Where the viewer's head is and which way it faces, relative to the body it sits on
+///
+/// The body is a camera, so the head moves and turns in the camera's view space, where -Z is in
+/// front and +Y is up. The default pose looks straight out of the camera.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HeadPose {
+    /// How far the head is turned to the right, in degrees
+    pub yaw: f32,
+    /// How far the head is tilted up, in degrees
+    pub pitch: f32,
+    /// How far the head leans to the right, in degrees
+    pub roll: f32,
+    /// How far the head moved from the camera, in the camera's view space
+    pub offset: Vector3<f32>,
+}
+
+impl Default for HeadPose {
+    fn default() -> Self {
+        Self {
+            yaw: 0.,
+            pitch: 0.,
+            roll: 0.,
+            offset: Vector3::new(0., 0., 0.),
+        }
+    }
+}
+
+impl HeadPose {
+    /// The transform from the camera's view space to the head's, which goes in front of the
+    /// camera's view matrix
+    pub fn view_transform(&self) -> Matrix4<f32> {
+        // The inverse of placing the head: moving it, turning it, tilting it, and leaning it
+        Matrix4::from_angle_z(Deg(self.roll))
+            * Matrix4::from_angle_x(Deg(-self.pitch))
+            * Matrix4::from_angle_y(Deg(self.yaw))
+            * Matrix4::from_translation(-self.offset)
+    }
+}
+
+/// Supplies where the viewer's head is every frame, and the view and projection matrices of its
+/// eyes
+///
+/// `sample` is the late latch point. `stereo::render_eyes_with_pose` calls it once a frame right
+/// before drawing the first eye, after the handler has done everything else, so the pose is as
+/// new as it can be when the frame is drawn. A provider for a headset would read the tracker
+/// there. Both eyes are drawn with the same sample, so they never disagree about where the head
+/// is.
+pub trait PoseProvider {
+    /// Read the newest pose of the head
+    fn sample(&mut self, ctx: &AppContext);
+
+    /// The pose read by the last `sample`
+    fn head(&self) -> HeadPose;
+
+    /// The view and projection matrices of an eye, with a camera as the body the head sits on
+    ///
+    /// The eyes are moved apart in the head's space, so leaning the head tilts the line between
+    /// them. Providers whose eyes have their own projections, like a headset's lenses, return
+    /// those here.
+    fn eye_matrices(&self, view: &EyeView, camera: &FlyCamera) -> EyeMatrices {
+        EyeMatrices {
+            view: view.view(self.head().view_transform() * camera.view_matrix()),
+            projection: camera.projection_matrix(view.aspect_ratio()),
+        }
+    }
+}
+
+/// A pose provider for developing without a headset, which turns the head towards the mouse
+///
+/// The cursor in the middle of the window looks straight ahead, and moving it to the edges turns
+/// the head up to `max_yaw` and `max_pitch`. The head turns around a neck below and behind the
+/// eyes, so the eyes move a little as it turns, which shows the parallax a real head would see.
+/// While the cursor is outside of the window the head keeps its last pose.
+///
+/// The mouse is read from the frame's input, so the pose is only as new as the loop's input,
+/// which `LatencyMode::LowLatency` reads right before `draw`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MousePose {
+    /// How far the head turns to the sides and up and down at the edges of the window, in degrees
+    pub max_yaw: f32,
+    pub max_pitch: f32,
+    head: HeadPose,
+}
+
+impl Default for MousePose {
+    fn default() -> Self {
+        Self::new(45., 30.)
+    }
+}
+
+impl MousePose {
+    pub fn new(max_yaw: f32, max_pitch: f32) -> Self {
+        Self {
+            max_yaw,
+            max_pitch,
+            head: HeadPose::default(),
+        }
+    }
+
+    /// Look straight ahead again
+    pub fn recenter(&mut self) {
+        self.head = HeadPose::default();
+    }
+}
+
+impl PoseProvider for MousePose {
+    fn sample(&mut self, ctx: &AppContext) {
+        let (width, height) = ctx.window_size();
+        let (x, y) = match ctx.input.window_cursor_position() {
+            Some(position) if width > 0 && height > 0 => position,
+            _ => return,
+        };
+        // -1 to 1 across the window, with up being positive
+        let across = (x / width as f64 * 2. - 1.).clamp(-1., 1.) as f32;
+        let up = (1. - y / height as f64 * 2.).clamp(-1., 1.) as f32;
+        let (yaw, pitch) = (across * self.max_yaw, up * self.max_pitch);
+
+        // Where the eyes end up when the head turns around the neck
+        let neck = Matrix4::from_angle_y(Deg(-yaw)) * Matrix4::from_angle_x(Deg(pitch));
+        let neck_to_eyes = Vector3::from(NECK_TO_EYES);
+        let eyes = neck * neck_to_eyes.extend(0.);
+        self.head = HeadPose {
+            yaw,
+            pitch,
+            roll: 0.,
+            offset: eyes.truncate() - neck_to_eyes,
+        };
+    }
+
+    fn head(&self) -> HeadPose {
+        self.head
+    }
+}
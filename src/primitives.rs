@@ -0,0 +1,116 @@
+use std::f32::consts::PI;
+
+use crate::mesh::MeshData;
+
+/// The fewest segments and rings a sphere can have and still look like a closed solid
+const MIN_SEGMENTS: u32 = 4;
+const MIN_RINGS: u32 = 2;
+
+/// A sphere around the origin, with `segments` slices around the Y axis and `rings` bands from
+/// the bottom pole to the top one
+///
+/// The texture coordinates wrap around once horizontally, so the first and last column of
+/// vertices are at the same place with different U coordinates.
+pub fn uv_sphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    let segments = segments.max(MIN_SEGMENTS);
+    let rings = rings.max(MIN_RINGS);
+
+    let mut data = MeshData::default();
+    for ring in 0..=rings {
+        let v = ring as f32 / rings as f32;
+        // Go from the bottom pole to the top one
+        let (ring_sin, ring_cos) = (v * PI - PI / 2.).sin_cos();
+        for segment in 0..=segments {
+            let u = segment as f32 / segments as f32;
+            let (segment_sin, segment_cos) = (u * 2. * PI).sin_cos();
+            let normal = [ring_cos * segment_cos, ring_sin, -ring_cos * segment_sin];
+            data.positions
+                .push([normal[0] * radius, normal[1] * radius, normal[2] * radius]);
+            data.normals.push(normal);
+            data.uvs.push([u, v]);
+        }
+    }
+
+    // Two counter-clockwise triangles for each quad, skipping the ones that collapse at the poles
+    let columns = segments + 1;
+    for ring in 0..rings {
+        for segment in 0..segments {
+            let bottom_left = ring * columns + segment;
+            let bottom_right = bottom_left + 1;
+            let top_left = bottom_left + columns;
+            let top_right = top_left + 1;
+            if ring != 0 {
+                data.indices
+                    .extend_from_slice(&[bottom_left, bottom_right, top_left]);
+            }
+            if ring != rings - 1 {
+                data.indices
+                    .extend_from_slice(&[bottom_right, top_right, top_left]);
+            }
+        }
+    }
+    data
+}
+
+/// Spheres with less and less detail, for a `LodMesh`
+///
+/// The first sphere has `segments` slices and half as many rings, and each one after it has half
+/// as many of both, down to the fewest that still make a solid.
+pub fn sphere_lods(radius: f32, segments: u32, levels: usize) -> Vec<MeshData> {
+    let mut segments = segments.max(MIN_SEGMENTS);
+    (0..levels)
+        .map(|_| {
+            let sphere = uv_sphere(radius, segments, segments / 2);
+            segments = (segments / 2).max(MIN_SEGMENTS);
+            sphere
+        })
+        .collect()
+}
+
+/// A flat rectangle around the origin facing up, `width` along X and `depth` along Z, with the
+/// texture repeated `uv_scale` times across it
+pub fn plane(width: f32, depth: f32, uv_scale: f32) -> MeshData {
+    let mut data = MeshData::default();
+    for (u, v) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+        data.positions
+            .push([(u - 0.5) * width, 0., (0.5 - v) * depth]);
+        data.normals.push([0., 1., 0.]);
+        data.uvs.push([u * uv_scale, v * uv_scale]);
+    }
+    // Counter-clockwise seen from above
+    data.indices.extend_from_slice(&[0, 1, 2, 0, 2, 3]);
+    data
+}
+
+/// A box around the origin with the given size along each axis, with flat normals and each face
+/// covering the whole texture
+pub fn cuboid(width: f32, height: f32, depth: f32) -> MeshData {
+    let half = [width / 2., height / 2., depth / 2.];
+    let mut data = MeshData::default();
+    // Each face as its normal and the two directions along it, which are picked so that the
+    // corners go counter-clockwise seen from outside
+    let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+        ([1., 0., 0.], [0., 0., -1.], [0., 1., 0.]),
+        ([-1., 0., 0.], [0., 0., 1.], [0., 1., 0.]),
+        ([0., 1., 0.], [1., 0., 0.], [0., 0., -1.]),
+        ([0., -1., 0.], [1., 0., 0.], [0., 0., 1.]),
+        ([0., 0., 1.], [1., 0., 0.], [0., 1., 0.]),
+        ([0., 0., -1.], [-1., 0., 0.], [0., 1., 0.]),
+    ];
+    for (normal, right, up) in faces {
+        let first = data.positions.len() as u32;
+        for (u, v) in [(0., 0.), (1., 0.), (1., 1.), (0., 1.)] {
+            let (x, y) = (u * 2. - 1., v * 2. - 1.);
+            let mut position = [0.; 3];
+            for axis in 0..3 {
+                position[axis] = (normal[axis] + right[axis] * x + up[axis] * y) * half[axis];
+            }
+            data.positions.push(position);
+            data.normals.push(normal);
+            data.uvs.push([u, v]);
+        }
+        data.indices
+            .extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+    }
+    data
+}
@@ -0,0 +1,301 @@
+use crate::{
+    color::Color,
+    texture::{AlphaMode, ImageData},
+    tween::Lerp,
+};
+
+/// Create an RGBA image by calling `pixel` with the coordinates of every pixel
+///
+/// The coordinates start at the bottom left, like texture coordinates. Straight alpha is assumed
+/// unless every pixel is opaque.
+pub fn from_fn<F: FnMut(u32, u32) -> Color>(width: u32, height: u32, mut pixel: F) -> ImageData {
+    let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            pixels.extend_from_slice(&pixel(x, y).to_srgb_u8());
+        }
+    }
+    let opaque = pixels.chunks_exact(4).all(|pixel| pixel[3] == 255);
+
+    ImageData {
+        width,
+        height,
+        format: glow::RGBA,
+        alpha: if opaque {
+            AlphaMode::Opaque
+        } else {
+            AlphaMode::Straight
+        },
+        pixels,
+    }
+}
+
+/// A checkerboard of square cells, starting with `first` in the bottom left corner
+pub fn checkerboard(
+    width: u32,
+    height: u32,
+    cell_size: u32,
+    first: Color,
+    second: Color,
+) -> ImageData {
+    let cell_size = cell_size.max(1);
+    from_fn(width, height, |x, y| {
+        if (x / cell_size + y / cell_size).is_multiple_of(2) {
+            first
+        } else {
+            second
+        }
+    })
+}
+
+/// A gradient across the image at an angle in degrees, where 0 goes from left to right and 90
+/// goes from bottom to top
+///
+/// The colors are blended in linear space.
+pub fn linear_gradient(width: u32, height: u32, from: Color, to: Color, angle: f32) -> ImageData {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    // Project the corners onto the direction so that the gradient fills the image exactly
+    let corners = [(0., 0.), (1., 0.), (0., 1.), (1., 1.)].map(|(u, v)| u * cos + v * sin);
+    let start = corners.iter().cloned().fold(f32::INFINITY, f32::min);
+    let end = corners.iter().cloned().fold(f32::NEG_INFINITY, f32::max);
+
+    from_fn(width, height, |x, y| {
+        let (u, v) = pixel_center_uv(x, y, width, height);
+        let t = (u * cos + v * sin - start) / (end - start);
+        from.lerp(&to, t)
+    })
+}
+
+/// A gradient from `inner` at the center of the image to `outer` at the middle of its edges
+///
+/// The colors are blended in linear space, and the corners are `outer`.
+pub fn radial_gradient(width: u32, height: u32, inner: Color, outer: Color) -> ImageData {
+    from_fn(width, height, |x, y| {
+        let (u, v) = pixel_center_uv(x, y, width, height);
+        let distance = ((u - 0.5).powi(2) + (v - 0.5).powi(2)).sqrt() * 2.;
+        inner.lerp(&outer, distance.min(1.))
+    })
+}
+
+/// A pattern for checking how texture coordinates are mapped onto a mesh
+///
+/// Red increases with U and green increases with V, with a grid line every tenth. Each corner has
+/// a square marker so that flips and rotations are easy to spot: white at UV (0, 0), red at (1, 0),
+/// green at (0, 1), and blue at (1, 1).
+pub fn uv_debug(width: u32, height: u32) -> ImageData {
+    let marker_size = (width.min(height) / 8).max(1);
+    from_fn(width, height, |x, y| {
+        let left = x < marker_size;
+        let right = x >= width.saturating_sub(marker_size);
+        let bottom = y < marker_size;
+        let top = y >= height.saturating_sub(marker_size);
+        match (left, right, bottom, top) {
+            (true, _, true, _) => return Color::WHITE,
+            (_, true, true, _) => return Color::RED,
+            (true, _, _, true) => return Color::GREEN,
+            (_, true, _, true) => return Color::BLUE,
+            _ => (),
+        }
+
+        let on_grid_line =
+            |i: u32, size: u32| (i * 10 / size.max(1)) != ((i + 1) * 10 / size.max(1));
+        if on_grid_line(x, width) || on_grid_line(y, height) {
+            return Color::BLACK;
+        }
+
+        let (u, v) = pixel_center_uv(x, y, width, height);
+        Color::rgb(u, v, 0.25)
+    })
+}
+
+/// The kind of noise made by `noise`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoiseKind {
+    /// Random values at the lattice points, smoothly blended. Blobby.
+    #[default]
+    Value,
+    /// Random gradients at the lattice points. Fewer grid artifacts than value noise.
+    Perlin,
+}
+
+/// The settings used to create noise
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NoiseParams {
+    pub kind: NoiseKind,
+    /// The same seed always gives the same noise, on every platform
+    pub seed: u32,
+    /// The number of lattice cells across the image in the first octave
+    pub frequency: u32,
+    /// The number of layers of noise, each with double the frequency and half the strength of the
+    /// one before it
+    pub octaves: u32,
+    /// Whether or not the noise wraps around at the edges of the image, so it can be repeated
+    /// without seams
+    pub tileable: bool,
+}
+
+impl Default for NoiseParams {
+    fn default() -> Self {
+        Self {
+            kind: NoiseKind::Value,
+            seed: 0,
+            frequency: 8,
+            octaves: 4,
+            tileable: false,
+        }
+    }
+}
+
+/// A grayscale noise image
+pub fn noise(width: u32, height: u32, params: &NoiseParams) -> ImageData {
+    from_fn(width, height, |x, y| {
+        let value = noise_value(x, y, width, height, params);
+        Color::rgb(value, value, value)
+    })
+}
+
+/// A normal map of bumps shaped like the noise, for surfaces like water or rough stone
+///
+/// The normals point along +Z when the surface is flat and are stored as `normal * 0.5 + 0.5`, so
+/// the texture should be sampled without sRGB decoding. `strength` is how steep the bumps are.
+/// The edges wrap around if the noise is tileable.
+pub fn noise_normal_map(width: u32, height: u32, params: &NoiseParams, strength: f32) -> ImageData {
+    let heights: Vec<f32> = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| noise_value(x, y, width, height, params))
+        .collect();
+    let sample = |x: i32, y: i32| {
+        let (x, y) = if params.tileable {
+            (x.rem_euclid(width as i32), y.rem_euclid(height as i32))
+        } else {
+            (x.clamp(0, width as i32 - 1), y.clamp(0, height as i32 - 1))
+        };
+        heights[(y as u32 * width + x as u32) as usize]
+    };
+
+    from_fn(width, height, |x, y| {
+        // The slope across the pixel in each direction, scaled so the bumps look the same at
+        // every size
+        let (x, y) = (x as i32, y as i32);
+        let dx = (sample(x + 1, y) - sample(x - 1, y)) * width as f32 * strength / 2.;
+        let dy = (sample(x, y + 1) - sample(x, y - 1)) * height as f32 * strength / 2.;
+        let length = (dx * dx + dy * dy + 1.).sqrt();
+        let (nx, ny, nz) = (-dx / length, -dy / length, 1. / length);
+        Color::rgb(nx * 0.5 + 0.5, ny * 0.5 + 0.5, nz * 0.5 + 0.5)
+    })
+}
+
+/// The noise at a pixel, between 0 and 1
+pub fn noise_value(x: u32, y: u32, width: u32, height: u32, params: &NoiseParams) -> f32 {
+    let (u, v) = pixel_center_uv(x, y, width, height);
+
+    // Add up the octaves, then scale the total back to between 0 and 1
+    let mut total = 0.;
+    let mut strength_total = 0.;
+    let mut frequency = params.frequency.max(1);
+    let mut strength = 1.;
+    for octave in 0..params.octaves.max(1) {
+        // Give each octave its own lattice so they don't line up
+        let seed = params.seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+        let period = if params.tileable {
+            Some(frequency)
+        } else {
+            None
+        };
+        let x = u * frequency as f32;
+        let y = v * frequency as f32;
+        total += strength
+            * match params.kind {
+                NoiseKind::Value => value_noise(x, y, seed, period),
+                NoiseKind::Perlin => perlin_noise(x, y, seed, period),
+            };
+        strength_total += strength;
+        frequency *= 2;
+        strength /= 2.;
+    }
+    (total / strength_total).clamp(0., 1.)
+}
+
+/// Value noise between 0 and 1
+fn value_noise(x: f32, y: f32, seed: u32, period: Option<u32>) -> f32 {
+    let (x0, y0, fx, fy) = lattice_cell(x, y);
+    let value = |dx: i32, dy: i32| {
+        let (lx, ly) = wrap(x0 + dx, y0 + dy, period);
+        hash(lx, ly, seed) as f32 / u32::MAX as f32
+    };
+
+    let (sx, sy) = (fade(fx), fade(fy));
+    let bottom = value(0, 0).lerp(&value(1, 0), sx);
+    let top = value(0, 1).lerp(&value(1, 1), sx);
+    bottom.lerp(&top, sy)
+}
+
+/// Perlin noise between 0 and 1
+fn perlin_noise(x: f32, y: f32, seed: u32, period: Option<u32>) -> f32 {
+    let (x0, y0, fx, fy) = lattice_cell(x, y);
+    let dot_gradient = |dx: i32, dy: i32| {
+        let (lx, ly) = wrap(x0 + dx, y0 + dy, period);
+        // One of eight directions around the circle
+        const D: f32 = std::f32::consts::FRAC_1_SQRT_2;
+        let (gx, gy) = match hash(lx, ly, seed) % 8 {
+            0 => (1., 0.),
+            1 => (-1., 0.),
+            2 => (0., 1.),
+            3 => (0., -1.),
+            4 => (D, D),
+            5 => (-D, D),
+            6 => (D, -D),
+            _ => (-D, -D),
+        };
+        gx * (fx - dx as f32) + gy * (fy - dy as f32)
+    };
+
+    let (sx, sy) = (fade(fx), fade(fy));
+    let bottom = dot_gradient(0, 0).lerp(&dot_gradient(1, 0), sx);
+    let top = dot_gradient(0, 1).lerp(&dot_gradient(1, 1), sx);
+    // The dot products stay within about -0.71 to 0.71 in 2D
+    bottom.lerp(&top, sy) / 1.42 + 0.5
+}
+
+/// The lattice point at the bottom left of the cell containing a point, and the position of the
+/// point inside of the cell
+fn lattice_cell(x: f32, y: f32) -> (i32, i32, f32, f32) {
+    let (x0, y0) = (x.floor(), y.floor());
+    (x0 as i32, y0 as i32, x - x0, y - y0)
+}
+
+/// Wrap a lattice point around the period, if there is one
+fn wrap(x: i32, y: i32, period: Option<u32>) -> (u32, u32) {
+    match period {
+        Some(period) => (
+            x.rem_euclid(period as i32) as u32,
+            y.rem_euclid(period as i32) as u32,
+        ),
+        None => (x as u32, y as u32),
+    }
+}
+
+/// Smooth out the blend between lattice points so that the noise has no creases
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6. - 15.) + 10.)
+}
+
+/// A random looking number for a lattice point
+///
+/// This only uses integer math so that the same seed gives the same noise everywhere.
+fn hash(x: u32, y: u32, seed: u32) -> u32 {
+    let mut h = seed ^ x.wrapping_mul(0x27d4_eb2d) ^ y.wrapping_mul(0x1656_67b1);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+/// The texture coordinates of the center of a pixel
+fn pixel_center_uv(x: u32, y: u32, width: u32, height: u32) -> (f32, f32) {
+    (
+        (x as f32 + 0.5) / width.max(1) as f32,
+        (y as f32 + 0.5) / height.max(1) as f32,
+    )
+}
@@ -0,0 +1,268 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use glow::HasContext;
+
+use crate::{
+    features::ProgramBinaryFns,
+    resources::{self, ResourceKind},
+    AppContext,
+};
+
+/// How much disk space the cache may use before the least recently used binaries are deleted
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The start of every cache file, so that other files are never loaded as binaries
+const MAGIC: &[u8; 8] = b"MLOPBIN1";
+
+/// The extension of the cache files, which pruning only ever deletes
+const EXTENSION: &str = "bin";
+
+/// How the cache has done since it was created
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProgramCacheStats {
+    /// Programs loaded from the cache instead of being compiled
+    pub hits: u64,
+    /// Programs that weren't in the cache
+    pub misses: u64,
+    /// Cached binaries that the driver wouldn't load, e.g. because it was updated
+    pub rejected: u64,
+    /// Programs that were compiled and saved to the cache
+    pub stored: u64,
+}
+
+/// A cache of linked program binaries on disk, so that programs don't have to be compiled again
+/// every time an example starts
+///
+/// Binaries are keyed by a hash of the shader sources, including any injected defines, and the
+/// driver's vendor, renderer, and version strings. A driver can still reject a binary it wrote,
+/// which just means compiling the program again. The cache does nothing if the context can't save
+/// program binaries or the cache directory in the config is empty.
+#[derive(Debug)]
+pub struct ProgramBinaryCache {
+    dir: Option<PathBuf>,
+    max_bytes: u64,
+    fns: Option<ProgramBinaryFns>,
+    /// The driver strings that are part of every key
+    driver: String,
+    stats: ProgramCacheStats,
+}
+
+impl ProgramBinaryCache {
+    /// A cache in the config's shader cache directory, for the context's driver
+    pub fn new(ctx: &AppContext) -> Self {
+        let dir = &ctx.config().shader_cache_dir;
+        let report = ctx.context_report();
+        Self {
+            dir: if dir.as_os_str().is_empty() {
+                None
+            } else {
+                Some(dir.clone())
+            },
+            max_bytes: DEFAULT_MAX_BYTES,
+            fns: ctx.features().program_binary,
+            driver: format!("{}\n{}\n{}", report.vendor, report.renderer, report.version),
+            stats: ProgramCacheStats::default(),
+        }
+    }
+
+    /// Set how much disk space the cache may use
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Whether or not the cache is loading and saving binaries
+    pub fn is_enabled(&self) -> bool {
+        self.dir.is_some() && self.fns.is_some()
+    }
+
+    pub fn stats(&self) -> ProgramCacheStats {
+        self.stats
+    }
+
+    /// Delete every cached binary
+    pub fn clear(&self) -> std::io::Result<()> {
+        if let Some(dir) = &self.dir {
+            for (path, _, _) in cache_files(dir) {
+                fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a program from the cache, returning `None` if it isn't cached or the driver rejects
+    /// the binary
+    pub(crate) fn load(
+        &mut self,
+        gl: &mut glow::Context,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Option<u32> {
+        let fns = self.fns?;
+        let path = self.path(vertex_source, fragment_source)?;
+        let file = match fs::read(&path) {
+            Ok(file) => file,
+            Err(_) => {
+                self.stats.misses += 1;
+                return None;
+            }
+        };
+
+        // Load the binary, throwing away files that the driver won't take so they are written
+        // again after compiling
+        let binary = file
+            .strip_prefix(&MAGIC[..])
+            .filter(|rest| rest.len() > 4)
+            .map(|rest| rest.split_at(4));
+        let linked = binary.and_then(|(format, binary)| unsafe {
+            let format = u32::from_le_bytes([format[0], format[1], format[2], format[3]]);
+            let program = gl.create_program().unwrap();
+            fns.load_binary(program, format, binary);
+            if gl.get_program_link_status(program) {
+                Some(program)
+            } else {
+                gl.delete_program(program);
+                None
+            }
+        });
+        match linked {
+            Some(program) => {
+                resources::track(ResourceKind::Program, program, "Shader program");
+                // Mark the binary as recently used so that pruning keeps it
+                if let Ok(file) = fs::File::options().append(true).open(&path) {
+                    let _ = file.set_modified(SystemTime::now());
+                }
+                self.stats.hits += 1;
+                Some(program)
+            }
+            None => {
+                let _ = fs::remove_file(&path);
+                self.stats.rejected += 1;
+                None
+            }
+        }
+    }
+
+    /// Ask the driver to keep the binary of a program that is about to be linked
+    pub(crate) fn prepare(&self, program: u32) {
+        if let (Some(fns), Some(_)) = (self.fns, &self.dir) {
+            fns.set_retrievable(program);
+        }
+    }
+
+    /// Save a linked program to the cache, deleting old binaries if the cache is too big
+    pub(crate) fn store(&mut self, vertex_source: &str, fragment_source: &str, program: u32) {
+        let (fns, path) = match (self.fns, self.path(vertex_source, fragment_source)) {
+            (Some(fns), Some(path)) => (fns, path),
+            _ => return,
+        };
+        let (format, binary) = match fns.get(program) {
+            Some(binary) => binary,
+            None => return,
+        };
+
+        let mut file = Vec::with_capacity(MAGIC.len() + 4 + binary.len());
+        file.extend_from_slice(MAGIC);
+        file.extend_from_slice(&format.to_le_bytes());
+        file.extend_from_slice(&binary);
+        let written = path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::write(&path, file));
+        match written {
+            Ok(()) => {
+                self.stats.stored += 1;
+                self.prune();
+            }
+            Err(error) => eprintln!(
+                "Warning: Couldn't write to the shader cache at {}: {}",
+                path.display(),
+                error
+            ),
+        }
+    }
+
+    /// The path of the cache file for a program
+    fn path(&self, vertex_source: &str, fragment_source: &str) -> Option<PathBuf> {
+        let hash = fnv1a(&[vertex_source, fragment_source, &self.driver]);
+        Some(
+            self.dir
+                .as_ref()?
+                .join(format!("{:016x}.{}", hash, EXTENSION)),
+        )
+    }
+
+    /// Delete the least recently used binaries until the cache fits in its size
+    fn prune(&self) {
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => return,
+        };
+        let mut files = cache_files(dir);
+        files.sort_by_key(|&(_, _, modified)| std::cmp::Reverse(modified));
+
+        let mut total = 0;
+        for (path, size, _) in files {
+            total += size;
+            if total > self.max_bytes {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+/// The cache files in a directory, with their sizes and when they were last used
+fn cache_files(dir: &Path) -> Vec<(PathBuf, u64, SystemTime)> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != EXTENSION {
+                return None;
+            }
+            let metadata = path.metadata().ok()?;
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            Some((path, metadata.len(), modified))
+        })
+        .collect()
+}
+
+/// A 64 bit FNV-1a hasher, which unlike the standard hasher gives the same hash on every run and
+/// every Rust version
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct Fnv1a(u64);
+
+impl Fnv1a {
+    pub(crate) fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn update(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// The FNV-1a hash of some strings
+fn fnv1a(parts: &[&str]) -> u64 {
+    let mut hasher = Fnv1a::new();
+    for part in parts {
+        // Separate the parts so that moving text from one to the next changes the hash
+        hasher.update(part.as_bytes());
+        hasher.update(&[0]);
+    }
+    hasher.finish()
+}
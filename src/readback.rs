@@ -0,0 +1,207 @@
+use std::collections::VecDeque;
+
+use glow::{HasContext, PixelPackData};
+
+use crate::{
+    resources::{self, ResourceKind},
+    viewport::Rect,
+};
+
+/// How many reads can be in flight before `AsyncReadback` starts skipping them
+pub const DEFAULT_SLOT_COUNT: usize = 3;
+
+/// One of the pixel buffers that reads are copied into
+#[derive(Debug)]
+struct Slot {
+    pbo: u32,
+    /// Signalled once the GPU has copied the pixels into the buffer
+    fence: Option<glow::Fence>,
+}
+
+/// Reads small images back from the GPU without waiting for it
+///
+/// Each read copies the pixels into a pixel buffer object and fences it. `poll` only maps a
+/// buffer once its fence has been signalled, so the CPU never stalls, and the results come back a
+/// frame or two later. If every buffer is still in flight the read is skipped instead of waiting.
+#[derive(Debug)]
+pub struct AsyncReadback {
+    /// The size of every read in bytes
+    size: usize,
+    slots: Vec<Slot>,
+    /// The slots that aren't in flight
+    free: Vec<usize>,
+    /// The slots in flight, oldest first
+    pending: VecDeque<usize>,
+    /// How many reads were skipped because every slot was in flight
+    skipped: u64,
+}
+
+impl AsyncReadback {
+    /// Make `slot_count` pixel buffers for reads of `size` bytes
+    pub fn new(gl: &mut glow::Context, size: usize, slot_count: usize, label: &str) -> Self {
+        let slots = (0..slot_count.max(1))
+            .map(|_| unsafe {
+                let pbo = gl.create_buffer().unwrap();
+                gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(pbo));
+                gl.buffer_data_size(glow::PIXEL_PACK_BUFFER, size as i32, glow::STREAM_READ);
+                resources::track_sized(ResourceKind::Buffer, pbo, label, size as u64);
+                Slot { pbo, fence: None }
+            })
+            .collect::<Vec<_>>();
+        unsafe { gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None) };
+        Self {
+            size,
+            free: (0..slots.len()).rev().collect(),
+            slots,
+            pending: VecDeque::new(),
+            skipped: 0,
+        }
+    }
+
+    /// Start copying a mip level of a 2D texture, which must be `size` bytes in the given format
+    ///
+    /// Returns false if the read was skipped because every buffer is still in flight.
+    pub fn read_texture(
+        &mut self,
+        gl: &mut glow::Context,
+        texture: u32,
+        level: u32,
+        format: u32,
+        ty: u32,
+    ) -> bool {
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.skipped += 1;
+                return false;
+            }
+        };
+        unsafe {
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(self.slots[slot].pbo));
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.get_tex_image(
+                glow::TEXTURE_2D,
+                level as i32,
+                format,
+                ty,
+                PixelPackData::BufferOffset(0),
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+            self.slots[slot].fence = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).ok();
+        }
+        self.pending.push_back(slot);
+        true
+    }
+
+    /// Start copying a rectangle of a framebuffer's first color attachment, or of the default
+    /// framebuffer's back buffer, which must be `size` bytes in the given format
+    ///
+    /// Returns false if the read was skipped because every buffer is still in flight.
+    pub fn read_framebuffer(
+        &mut self,
+        gl: &mut glow::Context,
+        framebuffer: Option<u32>,
+        rect: Rect,
+        format: u32,
+        ty: u32,
+    ) -> bool {
+        let slot = match self.free.pop() {
+            Some(slot) => slot,
+            None => {
+                self.skipped += 1;
+                return false;
+            }
+        };
+        unsafe {
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(self.slots[slot].pbo));
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, framebuffer);
+            gl.read_pixels(
+                rect.x,
+                rect.y,
+                rect.width,
+                rect.height,
+                format,
+                ty,
+                PixelPackData::BufferOffset(0),
+            );
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+            self.slots[slot].fence = gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).ok();
+        }
+        self.pending.push_back(slot);
+        true
+    }
+
+    /// The newest read that has finished, or `None` if none finished since the last poll
+    ///
+    /// Older reads that finished too are dropped, since only the newest one matters.
+    pub fn poll(&mut self, gl: &mut glow::Context) -> Option<Vec<u8>> {
+        let mut newest = None;
+        while let Some(&slot) = self.pending.front() {
+            let ready = match self.slots[slot].fence {
+                Some(fence) => unsafe { gl.get_sync_status(fence) == glow::SIGNALED },
+                // Without a fence there's no way to tell, so only read it once it is the oldest
+                None => true,
+            };
+            if !ready {
+                break;
+            }
+            self.pending.pop_front();
+            newest = Some(slot);
+            if let Some(fence) = self.slots[slot].fence.take() {
+                unsafe { gl.delete_sync(fence) };
+            }
+            self.free.push(slot);
+        }
+
+        let slot = newest?;
+        let mut data = vec![0; self.size];
+        unsafe {
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(self.slots[slot].pbo));
+            gl.get_buffer_sub_data(glow::PIXEL_PACK_BUFFER, 0, &mut data);
+            gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
+        }
+        Some(data)
+    }
+
+    /// How many reads are in flight
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// How many reads were skipped because every buffer was still in flight
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Delete the buffers and their fences
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for slot in self.slots.drain(..) {
+            unsafe {
+                if let Some(fence) = slot.fence {
+                    gl.delete_sync(fence);
+                }
+                gl.delete_buffer(slot.pbo);
+            }
+            resources::untrack(ResourceKind::Buffer, slot.pbo);
+        }
+        self.free.clear();
+        self.pending.clear();
+    }
+}
+
+/// Turn RGBA pixels read from a framebuffer into an image with its top row first, as image files
+/// and the clipboard want them
+///
+/// GL reads the rows from the bottom up, and the alpha of a window isn't what was seen, so every
+/// pixel is made opaque.
+pub fn framebuffer_image(pixels: &[u8], width: u32) -> Vec<u8> {
+    let mut image = Vec::with_capacity(pixels.len());
+    for row in pixels.chunks(width as usize * 4).rev() {
+        image.extend_from_slice(row);
+    }
+    for alpha in image.iter_mut().skip(3).step_by(4) {
+        *alpha = 255;
+    }
+    image
+}
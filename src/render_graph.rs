@@ -0,0 +1,772 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Debug,
+    time::Duration,
+};
+
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    debug_scope, depth_mode,
+    msaa_resolve::{MsaaResolver, MultisampledTexture},
+    render_settings::RenderSettings,
+    resources::{self, ResourceKind},
+    AppContext,
+};
+
+/// The framebuffer that a render pass draws into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PassTarget {
+    /// The window surface
+    Surface,
+    /// One of the handler's own framebuffers
+    Framebuffer(u32),
+}
+
+/// How big a pass's viewport, or a `RenderTarget`, is
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum TargetSize {
+    /// The size of the window
+    #[default]
+    Window,
+    /// The size of the window scaled by a factor, e.g. 0.5 for half resolution, which costs about
+    /// a quarter as much to fill. The size is rounded down and is at least 1x1.
+    Relative(f32),
+    /// A size of its own that doesn't change with the window
+    Fixed(u32, u32),
+}
+
+impl TargetSize {
+    /// The size in pixels for a window of the given size
+    pub fn resolve(self, (window_width, window_height): (u32, u32)) -> (u32, u32) {
+        match self {
+            TargetSize::Window => (window_width.max(1), window_height.max(1)),
+            TargetSize::Relative(scale) => (
+                ((window_width as f32 * scale) as u32).max(1),
+                ((window_height as f32 * scale) as u32).max(1),
+            ),
+            TargetSize::Fixed(width, height) => (width.max(1), height.max(1)),
+        }
+    }
+}
+
+/// How a material covers the pixels it draws, which decides how it takes part in a depth pre-pass
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum MaterialKind {
+    /// Covers every pixel of its triangles, so the pre-pass can draw it with a depth-only shader
+    #[default]
+    Opaque,
+    /// Discards some pixels, so the pre-pass has to draw it with its real shader to get the same
+    /// holes in the depth buffer
+    AlphaTested,
+    /// Blends with what is behind it, so it is left out of the pre-pass and doesn't use the
+    /// pre-pass depth
+    Transparent,
+}
+
+/// A depth offset for surfaces that lie on other surfaces, like decals, so that they don't
+/// z-fight with them
+///
+/// It is passed to `glPolygonOffset`: the depth of each fragment moves by `factor` times the slope
+/// of the triangle's depth plus `units` times the smallest depth difference the depth buffer can
+/// tell apart. Negative values pull the surface towards the camera, whichever way
+/// `RenderSettings::depth_mode` stores depth.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PolygonOffset {
+    pub factor: f32,
+    pub units: f32,
+}
+
+impl PolygonOffset {
+    /// Enough to keep a decal in front of the surface it lies on, even at grazing angles
+    pub const DECAL: PolygonOffset = PolygonOffset::new(-1., -4.);
+
+    pub const fn new(factor: f32, units: f32) -> Self {
+        Self { factor, units }
+    }
+}
+
+/// How the things drawn with a material take part in a pass
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Material {
+    pub kind: MaterialKind,
+    /// The depth offset of the material's surfaces, or `None` for no offset
+    ///
+    /// Materials with an offset are left out of the depth pre-pass, like transparent ones. The
+    /// pre-pass would otherwise write their offset depth in place of the surface under them, and
+    /// the `EQUAL` test of the shading after it would throw away one of the two.
+    pub polygon_offset: Option<PolygonOffset>,
+    /// Whether both sides of the material's triangles are drawn, like for leaves and cloth that
+    /// are a single sheet of triangles
+    ///
+    /// Face culling is turned off for its draws, in every phase, so the depth pre-pass writes the
+    /// back faces that the shading after it draws. Shaders should flip the normals of back faces,
+    /// e.g. with `material::DOUBLE_SIDED_CHUNK`, so that they are lit like the side they show.
+    pub double_sided: bool,
+}
+
+impl Material {
+    pub const fn new(kind: MaterialKind) -> Self {
+        Self {
+            kind,
+            polygon_offset: None,
+            double_sided: false,
+        }
+    }
+
+    pub const fn with_polygon_offset(mut self, offset: PolygonOffset) -> Self {
+        self.polygon_offset = Some(offset);
+        self
+    }
+
+    pub const fn with_double_sided(mut self, double_sided: bool) -> Self {
+        self.double_sided = double_sided;
+        self
+    }
+
+    /// Whether draws with the material write their depth in a depth pre-pass
+    pub fn in_depth_prepass(&self) -> bool {
+        self.kind != MaterialKind::Transparent && self.polygon_offset.is_none()
+    }
+
+    /// Call `draw` with the depth state, polygon offset, and face culling of the material in a
+    /// phase, then put the state back the way it was, so that none of it leaks into other draws
+    ///
+    /// Returns `None` without calling `draw` if the material isn't drawn in the phase. Glow can't
+    /// read back the offset's factor and units, so if an offset was already on they are left as
+    /// the material's.
+    pub fn draw<R, F: FnOnce(&mut glow::Context) -> R>(
+        &self,
+        gl: &mut glow::Context,
+        phase: DrawPhase,
+        draw: F,
+    ) -> Option<R> {
+        if !phase.draws(*self) {
+            return None;
+        }
+        unsafe {
+            let depth_func = gl.get_parameter_i32(glow::DEPTH_FUNC) as u32;
+            let offset_enabled = gl.is_enabled(glow::POLYGON_OFFSET_FILL);
+            let cull_face = gl.is_enabled(glow::CULL_FACE);
+            phase.apply_depth_state(gl, *self);
+            if self.double_sided {
+                gl.disable(glow::CULL_FACE);
+            }
+            match self.polygon_offset {
+                Some(offset) => {
+                    let sign = depth_mode::current().mode.offset_sign();
+                    gl.enable(glow::POLYGON_OFFSET_FILL);
+                    gl.polygon_offset(offset.factor * sign, offset.units * sign);
+                }
+                None => gl.disable(glow::POLYGON_OFFSET_FILL),
+            }
+
+            let result = draw(gl);
+
+            gl.depth_func(depth_func);
+            if offset_enabled {
+                gl.enable(glow::POLYGON_OFFSET_FILL);
+            } else {
+                gl.disable(glow::POLYGON_OFFSET_FILL);
+            }
+            if cull_face {
+                gl.enable(glow::CULL_FACE);
+            }
+            Some(result)
+        }
+    }
+}
+
+impl From<MaterialKind> for Material {
+    fn from(kind: MaterialKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+/// Which part of a render pass the draw callback is being asked for
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DrawPhase {
+    /// A normal pass, without a depth pre-pass
+    Shade,
+    /// The depth pre-pass. Color writes are off and the depth test is `LESS`, or `GREATER` with
+    /// reversed depth, with depth writes on.
+    DepthPrepass,
+    /// The shading after a depth pre-pass. The depth test is `EQUAL` with depth writes off, so
+    /// each pixel is only shaded for the surface in front.
+    ShadeAfterPrepass,
+}
+
+impl DrawPhase {
+    /// Whether only depth is being written, so opaque materials can use a depth-only shader
+    pub fn is_depth_only(self) -> bool {
+        self == DrawPhase::DepthPrepass
+    }
+
+    /// Whether draws with the material can use a depth-only shader in this phase instead of their
+    /// own
+    pub fn use_depth_only_shader<M: Into<Material>>(self, material: M) -> bool {
+        let material = material.into();
+        self == DrawPhase::DepthPrepass
+            && material.kind == MaterialKind::Opaque
+            && material.in_depth_prepass()
+    }
+
+    /// Whether draws with the material belong in this phase
+    pub fn draws<M: Into<Material>>(self, material: M) -> bool {
+        self != DrawPhase::DepthPrepass || material.into().in_depth_prepass()
+    }
+
+    /// Set the depth test for drawing a material in this phase
+    ///
+    /// This is only needed when a pass with a pre-pass mixes transparent or offset materials in
+    /// with the others, since they weren't in the pre-pass and can't use the `EQUAL` test.
+    /// `Material::draw` calls it for you.
+    pub fn apply_depth_state<M: Into<Material>>(self, gl: &mut glow::Context, material: M) {
+        if self != DrawPhase::ShadeAfterPrepass {
+            return;
+        }
+        unsafe {
+            if material.into().in_depth_prepass() {
+                gl.depth_func(glow::EQUAL);
+            } else {
+                gl.depth_func(depth_mode::current().mode.depth_func());
+            }
+        }
+    }
+}
+
+/// A render pass, described by what it draws into and which textures it samples
+///
+/// The pass doesn't draw anything itself. `RenderGraph::execute` calls back into the handler with
+/// the pass's id once the target is bound.
+///
+/// A pass that draws into multisampled textures declares them with `writes_multisampled`. Passes
+/// that sample one of them with `reads` get its resolved texture, which the graph resolves right
+/// before the first of them runs, and have to bind `MultisampledTexture::resolved` themselves.
+/// Passes that fetch the samples with a `sampler2DMS` declare it with `reads_samples` instead.
+#[derive(Clone, Debug)]
+pub struct RenderPass<P> {
+    /// The id that is passed to the draw callback, e.g. a handler's own enum of passes
+    pub id: P,
+    pub target: PassTarget,
+    /// The textures that the pass samples
+    pub reads: Vec<u32>,
+    /// The multisampled textures that the pass fetches the samples of
+    pub reads_samples: Vec<u32>,
+    /// The textures attached to the target that the pass draws into
+    pub writes: Vec<u32>,
+    /// The textures in `writes` that are multisampled, with the textures they are resolved into
+    pub multisampled: Vec<MultisampledTexture>,
+    /// The size of the viewport, which is worked out from the window size every time the graph
+    /// runs, so relative passes follow resizes without the graph being made again
+    pub size: TargetSize,
+    /// What to clear the target to before the pass. Nothing is cleared by default.
+    pub clear: RenderSettings,
+    /// Whether to draw the pass twice, first only writing depth and then shading only the pixels
+    /// in front
+    ///
+    /// This saves time when the fragment shader is expensive and triangles cover each other a
+    /// lot, but costs time for simple scenes since the geometry is drawn twice. The vertex
+    /// shaders used in both phases must compute exactly the same positions, e.g. by declaring
+    /// `invariant gl_Position;`.
+    pub depth_prepass: bool,
+}
+
+impl<P> RenderPass<P> {
+    pub fn new(id: P, target: PassTarget) -> Self {
+        Self {
+            id,
+            target,
+            reads: Vec::new(),
+            reads_samples: Vec::new(),
+            writes: Vec::new(),
+            multisampled: Vec::new(),
+            size: TargetSize::Window,
+            clear: RenderSettings::no_clear(),
+            depth_prepass: false,
+        }
+    }
+
+    /// Add a texture that the pass samples
+    pub fn reads(mut self, texture: u32) -> Self {
+        self.reads.push(texture);
+        self
+    }
+
+    /// Add a multisampled texture whose samples the pass fetches with `texelFetch`, without
+    /// resolving it
+    pub fn reads_samples(mut self, texture: u32) -> Self {
+        self.reads_samples.push(texture);
+        self
+    }
+
+    /// Add a texture that the pass draws into
+    pub fn writes(mut self, texture: u32) -> Self {
+        self.writes.push(texture);
+        self
+    }
+
+    /// Add a multisampled texture that the pass draws into, which is resolved for the passes that
+    /// `reads` it
+    pub fn writes_multisampled(mut self, texture: MultisampledTexture) -> Self {
+        self.writes.push(texture.texture);
+        self.multisampled.push(texture);
+        self
+    }
+
+    /// Set the size of the viewport for the pass
+    pub fn viewport(mut self, width: u32, height: u32) -> Self {
+        self.size = TargetSize::Fixed(width, height);
+        self
+    }
+
+    /// Size the viewport relative to the window, e.g. 0.5 for half resolution
+    pub fn relative(mut self, scale: f32) -> Self {
+        self.size = TargetSize::Relative(scale);
+        self
+    }
+
+    /// Set the size of the viewport for the pass to any `TargetSize`
+    pub fn size(mut self, size: TargetSize) -> Self {
+        self.size = size;
+        self
+    }
+
+    /// Clear the target before the pass
+    pub fn clear(mut self, clear: RenderSettings) -> Self {
+        self.clear = clear;
+        self
+    }
+
+    /// Draw the pass with a depth pre-pass first
+    pub fn depth_prepass(mut self) -> Self {
+        self.depth_prepass = true;
+        self
+    }
+}
+
+/// The error returned when the passes of a render graph can't be scheduled
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RenderGraphError<P> {
+    /// The pass samples a texture that it is also drawing into
+    FeedbackLoop { pass: P, texture: u32 },
+    /// The pass samples a texture that is drawn into by more than one pass that comes after it,
+    /// so it isn't clear which one it should see
+    AmbiguousRead { pass: P, texture: u32 },
+    /// The passes depend on each other in a circle
+    Cycle { passes: Vec<P> },
+    /// The pass fetches the samples of a texture that no pass draws into as multisampled
+    NotMultisampled { pass: P, texture: u32 },
+    /// The pass samples the texture that a multisampled texture is resolved into, instead of
+    /// reading the multisampled texture so that the graph knows to resolve it first
+    ReadsResolveTarget { pass: P, texture: u32 },
+    /// The pass draws into multisampled and single-sample textures at once, which can't be
+    /// attached to the same framebuffer
+    MixedSamples { pass: P },
+}
+
+impl<P: Debug> std::fmt::Display for RenderGraphError<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RenderGraphError::FeedbackLoop { pass, texture } => write!(
+                f,
+                "Pass {:?} reads texture {} while drawing into it",
+                pass, texture
+            ),
+            RenderGraphError::AmbiguousRead { pass, texture } => write!(
+                f,
+                "Pass {:?} reads texture {}, which is drawn into by several later passes",
+                pass, texture
+            ),
+            RenderGraphError::Cycle { passes } => {
+                write!(f, "Passes {:?} depend on each other", passes)
+            }
+            RenderGraphError::NotMultisampled { pass, texture } => write!(
+                f,
+                "Pass {:?} reads the samples of texture {}, which isn't multisampled",
+                pass, texture
+            ),
+            RenderGraphError::ReadsResolveTarget { pass, texture } => write!(
+                f,
+                "Pass {:?} reads texture {}, which is resolved from a multisampled texture that \
+                 it should read instead",
+                pass, texture
+            ),
+            RenderGraphError::MixedSamples { pass } => write!(
+                f,
+                "Pass {:?} draws into multisampled and single-sample textures at once",
+                pass
+            ),
+        }
+    }
+}
+
+impl<P: Debug> std::error::Error for RenderGraphError<P> {}
+
+/// Runs a set of render passes in dependency order
+///
+/// A pass that reads a texture runs after the pass that writes it. When several passes write the
+/// same texture, like a ping-pong blur, they run in the order they were added and each reader sees
+/// the last write added before it. A reader added before every writer depends on the writer, so
+/// passes can be added in any order as long as each texture has a single writer.
+///
+/// Multisampled textures are resolved once after each time they are drawn into, before the first
+/// pass that samples them, with the size of the pass that drew into them.
+///
+/// Using a render graph is optional. Handlers can keep binding framebuffers and drawing on their
+/// own.
+#[derive(Debug)]
+pub struct RenderGraph<P> {
+    /// The passes in the order they will run
+    passes: Vec<RenderPass<P>>,
+    /// The multisampled textures to resolve before each pass, with the size of the pass that drew
+    /// into them
+    resolves: Vec<Vec<(MultisampledTexture, TargetSize)>>,
+    /// Resolves the multisampled textures, created the first time one is resolved
+    resolver: Option<MsaaResolver>,
+    /// A timer query for each pass, created the first time the graph is executed
+    queries: Vec<Option<u32>>,
+    /// Whether each pass's query has been started and not yet read back
+    queries_pending: Vec<bool>,
+    /// The last GPU time measured for each pass
+    pass_times: Vec<Option<Duration>>,
+    /// Whether the passes that ask for a depth pre-pass get one
+    depth_prepass_enabled: bool,
+}
+
+impl<P: Copy + Debug> RenderGraph<P> {
+    /// Sort and validate the passes
+    pub fn new(passes: Vec<RenderPass<P>>) -> Result<Self, RenderGraphError<P>> {
+        let multisampled = passes
+            .iter()
+            .flat_map(|pass| pass.multisampled.iter().map(|ms| (ms.texture, *ms)))
+            .collect::<HashMap<_, _>>();
+        let resolve_targets = multisampled
+            .values()
+            .map(|ms| ms.resolved)
+            .collect::<HashSet<_>>();
+
+        for pass in &passes {
+            // Make sure that no pass samples its own target
+            if let Some(&texture) = pass
+                .reads
+                .iter()
+                .chain(&pass.reads_samples)
+                .find(|t| pass.writes.contains(t))
+            {
+                return Err(RenderGraphError::FeedbackLoop {
+                    pass: pass.id,
+                    texture,
+                });
+            }
+
+            // Or reads a texture the wrong way for how many samples it has
+            if let Some(&texture) = pass
+                .reads_samples
+                .iter()
+                .find(|t| !multisampled.contains_key(t))
+            {
+                return Err(RenderGraphError::NotMultisampled {
+                    pass: pass.id,
+                    texture,
+                });
+            }
+            if let Some(&texture) = pass.reads.iter().find(|t| resolve_targets.contains(t)) {
+                return Err(RenderGraphError::ReadsResolveTarget {
+                    pass: pass.id,
+                    texture,
+                });
+            }
+            if !pass.multisampled.is_empty() && pass.multisampled.len() != pass.writes.len() {
+                return Err(RenderGraphError::MixedSamples { pass: pass.id });
+            }
+        }
+
+        // Work out which passes have to run before which, going through them in the order they
+        // were added and keeping track of the last pass to write each texture
+        let mut edges = vec![Vec::new(); passes.len()];
+        let mut last_writer = HashMap::<u32, usize>::new();
+        let mut readers_since_write = HashMap::<u32, Vec<usize>>::new();
+        let mut early_reads = Vec::new();
+        for (i, pass) in passes.iter().enumerate() {
+            for &texture in pass.reads.iter().chain(&pass.reads_samples) {
+                match last_writer.get(&texture) {
+                    Some(&writer) => edges[writer].push(i),
+                    None => early_reads.push((i, texture)),
+                }
+                readers_since_write.entry(texture).or_default().push(i);
+            }
+            for &texture in &pass.writes {
+                // Writes to the same texture happen in order, and only after the passes reading
+                // the previous contents are done with them
+                if let Some(&writer) = last_writer.get(&texture) {
+                    edges[writer].push(i);
+                }
+                for reader in readers_since_write.remove(&texture).unwrap_or_default() {
+                    edges[reader].push(i);
+                }
+                last_writer.insert(texture, i);
+            }
+        }
+
+        // Reads added before any write of the texture depend on its writer, if it has one. A read
+        // of a texture that no pass writes is an input from outside of the graph.
+        for (reader, texture) in early_reads {
+            let writers = (0..passes.len())
+                .filter(|&i| passes[i].writes.contains(&texture))
+                .collect::<Vec<_>>();
+            match writers.as_slice() {
+                [] => (),
+                [writer] => {
+                    // The write-after-read ordering above assumed the read came first, but the
+                    // read really wants the write
+                    edges[reader].retain(|&pass| pass != *writer);
+                    edges[*writer].push(reader);
+                }
+                _ => {
+                    return Err(RenderGraphError::AmbiguousRead {
+                        pass: passes[reader].id,
+                        texture,
+                    })
+                }
+            }
+        }
+
+        // Sort the passes, preferring the order they were added in when there is a choice
+        let mut incoming = vec![0; passes.len()];
+        for edge_list in &edges {
+            for &to in edge_list {
+                incoming[to] += 1;
+            }
+        }
+        let mut order = Vec::with_capacity(passes.len());
+        let mut done = vec![false; passes.len()];
+        while order.len() < passes.len() {
+            let next = (0..passes.len()).find(|&i| !done[i] && incoming[i] == 0);
+            let next = match next {
+                Some(next) => next,
+                None => {
+                    return Err(RenderGraphError::Cycle {
+                        passes: (0..passes.len())
+                            .filter(|&i| !done[i])
+                            .map(|i| passes[i].id)
+                            .collect(),
+                    })
+                }
+            };
+            done[next] = true;
+            for &to in &edges[next] {
+                incoming[to] -= 1;
+            }
+            order.push(next);
+        }
+
+        let mut passes = passes.into_iter().map(Some).collect::<Vec<_>>();
+        let passes = order
+            .into_iter()
+            .map(|i| passes[i].take().unwrap())
+            .collect::<Vec<_>>();
+
+        // Resolve each multisampled texture before the first pass that samples it after it was
+        // drawn into
+        let mut resolves = vec![Vec::new(); passes.len()];
+        let mut unresolved = HashMap::<u32, TargetSize>::new();
+        for (i, pass) in passes.iter().enumerate() {
+            for texture in &pass.reads {
+                if let Some(size) = unresolved.remove(texture) {
+                    resolves[i].push((multisampled[texture], size));
+                }
+            }
+            for texture in &pass.multisampled {
+                unresolved.insert(texture.texture, pass.size);
+            }
+        }
+
+        Ok(Self {
+            resolves,
+            resolver: None,
+            queries: vec![None; passes.len()],
+            queries_pending: vec![false; passes.len()],
+            pass_times: vec![None; passes.len()],
+            depth_prepass_enabled: true,
+            passes,
+        })
+    }
+
+    /// The passes in the order that they run
+    pub fn passes(&self) -> &[RenderPass<P>] {
+        &self.passes
+    }
+
+    /// The multisampled textures that are resolved before each pass, in the order that they run
+    pub fn resolves(&self) -> impl Iterator<Item = (P, &MultisampledTexture)> + '_ {
+        self.passes
+            .iter()
+            .zip(&self.resolves)
+            .flat_map(|(pass, resolves)| {
+                resolves.iter().map(move |(texture, _)| (pass.id, texture))
+            })
+    }
+
+    /// Whether the passes that ask for a depth pre-pass get one
+    pub fn depth_prepass_enabled(&self) -> bool {
+        self.depth_prepass_enabled
+    }
+
+    /// Turn the depth pre-pass of every pass on or off, e.g. to compare the pass times with and
+    /// without it
+    pub fn set_depth_prepass_enabled(&mut self, enabled: bool) {
+        self.depth_prepass_enabled = enabled;
+    }
+
+    /// Run every pass, binding and clearing its target and then calling `draw` with its id
+    ///
+    /// Passes with a depth pre-pass call `draw` twice, once for each phase. If the context
+    /// supports timer queries, each pass is timed on the GPU, including its pre-pass and the
+    /// resolves before it. The results show up in `pass_times` a frame or two later, once the GPU
+    /// has caught up.
+    pub fn execute<F: FnMut(&mut glow::Context, P, DrawPhase)>(
+        &mut self,
+        gl: &mut glow::Context,
+        ctx: &AppContext,
+        mut draw: F,
+    ) {
+        let timer_query = ctx.features().timer_query;
+        let (window_width, window_height) = ctx.render_size();
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            // Show each pass as a named group in frame captures
+            let _group = DebugGroup::push(gl, &format!("{:?}", pass.id));
+            unsafe {
+                // Collect the time from the last run of the pass, and only start a new query if
+                // the old one is done
+                let mut timing = false;
+                if timer_query {
+                    let query = match self.queries[i] {
+                        Some(query) => query,
+                        None => {
+                            let query = gl.create_query().unwrap();
+                            resources::track(ResourceKind::Query, query, "Render pass timer");
+                            self.queries[i] = Some(query);
+                            query
+                        }
+                    };
+                    if self.queries_pending[i]
+                        && gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) != 0
+                    {
+                        let nanos = gl.get_query_parameter_u32(query, glow::QUERY_RESULT);
+                        self.pass_times[i] = Some(Duration::from_nanos(nanos as u64));
+                        self.queries_pending[i] = false;
+                    }
+                    if !self.queries_pending[i] {
+                        gl.begin_query(glow::TIME_ELAPSED, query);
+                        timing = true;
+                    }
+                }
+
+                // Resolve the multisampled textures that the pass samples
+                if !self.resolves[i].is_empty() {
+                    let resolver = self.resolver.get_or_insert_with(|| MsaaResolver::new(gl));
+                    debug_scope!(gl, "Resolve", {
+                        for (texture, size) in &self.resolves[i] {
+                            let size = size.resolve((window_width, window_height));
+                            resolver.resolve(gl, texture, size);
+                        }
+                    });
+                }
+
+                // Bind and clear the target
+                let framebuffer = match pass.target {
+                    PassTarget::Surface => ctx.surface_framebuffer(),
+                    PassTarget::Framebuffer(framebuffer) => Some(framebuffer),
+                };
+                gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+                let (width, height) = pass.size.resolve((window_width, window_height));
+                gl.viewport(0, 0, width as i32, height as i32);
+                if let Some(color) = pass.clear.clear_color {
+                    let [r, g, b, a] = color.to_srgb();
+                    gl.clear_color(r, g, b, a);
+                }
+                let clear_mask = pass.clear.clear_mask();
+                if clear_mask != 0 {
+                    gl.clear(clear_mask);
+                }
+
+                if pass.depth_prepass && self.depth_prepass_enabled {
+                    // Only write depth first
+                    let depth_func = depth_mode::current().mode.depth_func();
+                    gl.enable(glow::DEPTH_TEST);
+                    gl.depth_mask(true);
+                    gl.depth_func(depth_func);
+                    gl.color_mask(false, false, false, false);
+                    debug_scope!(gl, "Depth pre-pass", {
+                        draw(gl, pass.id, DrawPhase::DepthPrepass)
+                    });
+
+                    // Then shade only the pixels that ended up in front
+                    gl.color_mask(true, true, true, true);
+                    gl.depth_mask(false);
+                    gl.depth_func(glow::EQUAL);
+                    debug_scope!(gl, "Shade", {
+                        draw(gl, pass.id, DrawPhase::ShadeAfterPrepass)
+                    });
+
+                    // Put the depth state back to how the handlers set it up
+                    gl.depth_mask(true);
+                    gl.depth_func(depth_func);
+                } else {
+                    draw(gl, pass.id, DrawPhase::Shade);
+                }
+
+                if timing {
+                    gl.end_query(glow::TIME_ELAPSED);
+                    self.queries_pending[i] = true;
+                }
+            }
+        }
+
+        // Leave the window surface bound like the loop does
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, ctx.surface_framebuffer());
+            gl.viewport(0, 0, window_width as i32, window_height as i32);
+        }
+    }
+
+    /// The size of a pass's viewport for a window of the given size, or `None` if the graph has no
+    /// pass with that id
+    pub fn pass_size(&self, id: P, window_size: (u32, u32)) -> Option<(u32, u32)>
+    where
+        P: PartialEq,
+    {
+        self.passes
+            .iter()
+            .find(|pass| pass.id == id)
+            .map(|pass| pass.size.resolve(window_size))
+    }
+
+    /// The last GPU time measured for each pass, in the order that they run
+    ///
+    /// The time is `None` until the first measurement comes back, or if the context doesn't
+    /// support timer queries.
+    pub fn pass_times(&self) -> impl Iterator<Item = (P, Option<Duration>)> + '_ {
+        self.passes
+            .iter()
+            .zip(&self.pass_times)
+            .map(|(pass, &time)| (pass.id, time))
+    }
+
+    /// Delete the timer queries and resolve framebuffers of the graph
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        if let Some(mut resolver) = self.resolver.take() {
+            resolver.delete(gl);
+        }
+        for query in self.queries.iter_mut().filter_map(Option::take) {
+            unsafe { gl.delete_query(query) };
+            resources::untrack(ResourceKind::Query, query);
+        }
+        for pending in &mut self.queries_pending {
+            *pending = false;
+        }
+    }
+}
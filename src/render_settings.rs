@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use crate::{
+    anti_aliasing::AaMode, color::Color, depth_mode::DepthMode, depth_view::DepthView,
+    latency::LatencyMode, stereo::StereoMode, texture_viewer::TextureView, theme::Theme,
+    virtual_resolution::VirtualResolution, wireframe::WireframeParams,
+};
+
+/// When the loop draws a new frame for a window
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RedrawPolicy {
+    /// Draw frames as fast as the window can present them
+    #[default]
+    Continuous,
+    /// Only draw after the window gets an event, like input or a resize, or after the handler
+    /// calls `AppContext::request_redraw`. Good for mostly static scenes.
+    OnEvent,
+    /// Draw at least this often, and also whenever `OnEvent` would
+    Interval(Duration),
+}
+
+/// Settings that control what the loop does around each call to a handler's `draw`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderSettings {
+    /// The color to clear the window to before each frame, or `None` to keep the previous
+    /// contents of the color buffer. The sRGB values are written as-is, so the color looks the
+    /// same as it does in a color picker. This starts as the theme's, and follows the theme when
+    /// it is switched unless the handler picked another color.
+    pub clear_color: Option<Color>,
+    /// Whether or not to clear the depth buffer before each frame, to the far plane's depth of
+    /// `depth_mode`
+    pub clear_depth: bool,
+    /// Whether or not to clear the stencil buffer before each frame
+    pub clear_stencil: bool,
+    /// Whether or not to present each frame to the window. Turning this off is useful for
+    /// benchmarks and for frames that only render offscreen, since presenting can wait for vsync.
+    pub present: bool,
+    /// When to draw frames. Handlers can change this at any time, for example to go back to
+    /// `Continuous` while an animation is playing.
+    pub redraw: RedrawPolicy,
+    /// Draw at a fixed resolution scaled up to the window, or `None` to draw straight to the
+    /// window
+    pub virtual_resolution: Option<VirtualResolution>,
+    /// How to smooth the edges of what the handler draws. This starts as MSAA with
+    /// `Config::msaa_samples` if that is set.
+    pub anti_aliasing: AaMode,
+    /// Whether to show the depth buffer in place of the handler's image ( cycled with F8 ), and
+    /// the handler's clipping planes for turning it into distances
+    pub depth_view: DepthView,
+    /// How depth is stored ( cycled with F5 ). With `DepthMode::ReverseZ` the loop gives the
+    /// handler a floating point depth buffer, and sets the depth test and the clear depth before
+    /// each `draw`. The handler's projections have to be built for it, which `FlyCamera`'s are.
+    pub depth_mode: DepthMode,
+    /// Whether to draw the scene once for each eye, side by side ( toggled with F10 ). Only
+    /// handlers that draw through `stereo::render_eyes` follow it.
+    pub stereo: StereoMode,
+    /// The lines to draw over the edges of the scene's triangles, or `None` for none ( set with the
+    /// `wireframe` console command ). Only handlers that draw through a `WireframePass` follow it.
+    pub wireframe: Option<WireframeParams>,
+    /// The texture to show in a panel over the frame, for looking at render targets and reading
+    /// their texels ( set with the `view` console command )
+    pub texture_view: TextureView,
+    /// Whether the loop lets frames queue up on the GPU, or waits for the last one before reading
+    /// input for the next ( set with the `latency` console command ). This starts as
+    /// `Config::latency_mode`.
+    pub latency_mode: LatencyMode,
+    /// Whether to only redraw the parts of each frame that changed, for mostly static scenes like
+    /// UIs. Before each frame the loop asks the handler's `RenderHandler::damage` what changed,
+    /// and scissors the clear and `draw` to it, along with what the buffer being drawn into
+    /// missed from the frame before. Frames with a new size, post-processing, stereo, or the
+    /// texture viewer are always redrawn whole.
+    pub partial_redraw: bool,
+    /// The colors of the loop's overlays and the debug helpers. This starts as `Config::theme`,
+    /// and is best switched with `AppContext::set_theme` so that the clear color follows it.
+    pub theme: Theme,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        let theme = Theme::default();
+        Self {
+            clear_color: Some(theme.clear_color),
+            clear_depth: true,
+            clear_stencil: true,
+            present: true,
+            redraw: RedrawPolicy::Continuous,
+            virtual_resolution: None,
+            anti_aliasing: AaMode::Off,
+            depth_view: DepthView::default(),
+            depth_mode: DepthMode::Standard,
+            stereo: StereoMode::Off,
+            wireframe: None,
+            texture_view: TextureView::default(),
+            latency_mode: LatencyMode::Throughput,
+            partial_redraw: false,
+            theme,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Settings that don't clear anything, for handlers that want to clear the window themselves
+    /// or that build on the previous frame's contents
+    pub fn no_clear() -> Self {
+        Self {
+            clear_color: None,
+            clear_depth: false,
+            clear_stencil: false,
+            present: true,
+            redraw: RedrawPolicy::Continuous,
+            virtual_resolution: None,
+            anti_aliasing: AaMode::Off,
+            depth_view: DepthView::default(),
+            depth_mode: DepthMode::Standard,
+            stereo: StereoMode::Off,
+            wireframe: None,
+            texture_view: TextureView::default(),
+            latency_mode: LatencyMode::Throughput,
+            partial_redraw: false,
+            theme: Theme::default(),
+        }
+    }
+
+    /// The mask of buffers that should be passed to `glClear`
+    pub fn clear_mask(&self) -> u32 {
+        let mut mask = 0;
+        if self.clear_color.is_some() {
+            mask |= glow::COLOR_BUFFER_BIT;
+        }
+        if self.clear_depth {
+            mask |= glow::DEPTH_BUFFER_BIT;
+        }
+        if self.clear_stencil {
+            mask |= glow::STENCIL_BUFFER_BIT;
+        }
+        mask
+    }
+}
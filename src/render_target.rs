@@ -0,0 +1,170 @@
+use glow::HasContext;
+
+use crate::{
+    diagnostics,
+    render_graph::{PassTarget, RenderPass, TargetSize},
+    resources::{self, ResourceKind},
+};
+
+/// The format and type to give `glTexImage2D` along with an internal format, when no pixels are
+/// uploaded
+fn pixel_format(internal_format: u32) -> (u32, u32) {
+    match internal_format {
+        glow::R8 => (glow::RED, glow::UNSIGNED_BYTE),
+        glow::RG8 => (glow::RG, glow::UNSIGNED_BYTE),
+        glow::R16F | glow::R32F => (glow::RED, glow::FLOAT),
+        glow::RG16F | glow::RG32F => (glow::RG, glow::FLOAT),
+        glow::R11F_G11F_B10F | glow::RGB16F | glow::RGB32F => (glow::RGB, glow::FLOAT),
+        glow::RGBA16F | glow::RGBA32F => (glow::RGBA, glow::FLOAT),
+        _ => (glow::RGBA, glow::UNSIGNED_BYTE),
+    }
+}
+
+/// A framebuffer with one linearly filtered color texture, whose size can follow the window's
+///
+/// A target with a `TargetSize::Relative` size, like half resolution bloom or SSAO, keeps the same
+/// relative size through resizes when `resize` is called with the new window size every frame.
+/// The storage is given a new size in place, so the framebuffer and texture keep their names and
+/// a `RenderGraph` made with them doesn't have to be made again.
+#[derive(Debug)]
+pub struct RenderTarget {
+    pub framebuffer: u32,
+    pub texture: u32,
+    pub internal_format: u32,
+    size: TargetSize,
+    /// The size of the texture in pixels
+    pixels: (u32, u32),
+    label: String,
+}
+
+impl RenderTarget {
+    /// Create a target of the given size for a window of the given size
+    pub fn new(
+        gl: &mut glow::Context,
+        label: &str,
+        size: TargetSize,
+        internal_format: u32,
+        window_size: (u32, u32),
+    ) -> Self {
+        unsafe {
+            let texture = gl.create_texture().unwrap();
+            let framebuffer = gl.create_framebuffer().unwrap();
+            let mut target = Self {
+                framebuffer,
+                texture,
+                internal_format,
+                size,
+                pixels: size.resolve(window_size),
+                label: label.to_string(),
+            };
+            target.allocate(gl);
+
+            // Filter linearly, so lower resolution targets can be scaled up by sampling them, and
+            // clamp, so the filter doesn't blend in the opposite edge
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::LINEAR),
+                (glow::TEXTURE_MAG_FILTER, glow::LINEAR),
+                (glow::TEXTURE_WRAP_S, glow::CLAMP_TO_EDGE),
+                (glow::TEXTURE_WRAP_T, glow::CLAMP_TO_EDGE),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let previous_framebuffer = gl.get_parameter_i32(glow::DRAW_FRAMEBUFFER_BINDING) as u32;
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(texture),
+                0,
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                diagnostics::report_problem(format!("The {} framebuffer is incomplete", label));
+            }
+            gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                if previous_framebuffer == 0 {
+                    None
+                } else {
+                    Some(previous_framebuffer)
+                },
+            );
+            resources::track(ResourceKind::Framebuffer, framebuffer, label);
+            target
+        }
+    }
+
+    /// The size of the texture in pixels
+    pub fn pixels(&self) -> (u32, u32) {
+        self.pixels
+    }
+
+    pub fn size(&self) -> TargetSize {
+        self.size
+    }
+
+    /// Give the texture the size for a window of the given size, if it doesn't have it already
+    ///
+    /// Returns whether the texture was resized, which throws away what was drawn into it.
+    pub fn resize(&mut self, gl: &mut glow::Context, window_size: (u32, u32)) -> bool {
+        let pixels = self.size.resolve(window_size);
+        if pixels == self.pixels {
+            return false;
+        }
+        self.pixels = pixels;
+        unsafe { self.allocate(gl) };
+        true
+    }
+
+    /// Change how the size of the target is worked out, e.g. to switch between full and half
+    /// resolution, and resize it to match
+    pub fn set_size(&mut self, gl: &mut glow::Context, size: TargetSize, window_size: (u32, u32)) {
+        self.size = size;
+        self.resize(gl, window_size);
+    }
+
+    /// A render pass that draws into this target, with a viewport the size of the target
+    pub fn pass<P>(&self, id: P) -> RenderPass<P> {
+        RenderPass::new(id, PassTarget::Framebuffer(self.framebuffer))
+            .writes(self.texture)
+            .size(self.size)
+    }
+
+    /// Give the texture storage of the current size, and track its memory. Tracking it again
+    /// replaces the old size, and keeps handles to the texture working.
+    unsafe fn allocate(&mut self, gl: &mut glow::Context) {
+        let (width, height) = self.pixels;
+        let (format, ty) = pixel_format(self.internal_format);
+        gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+        gl.tex_image_2d(
+            glow::TEXTURE_2D,
+            0,
+            self.internal_format as i32,
+            width as i32,
+            height as i32,
+            0,
+            format,
+            ty,
+            None,
+        );
+        gl.bind_texture(glow::TEXTURE_2D, None);
+        resources::track_sized(
+            ResourceKind::Texture,
+            self.texture,
+            &format!("{} {}x{}", self.label, width, height),
+            resources::texture_bytes(width, height, self.internal_format, 1, 1, 1),
+        );
+    }
+
+    pub fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_texture(self.texture);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Texture, self.texture);
+    }
+}
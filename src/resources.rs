@@ -0,0 +1,521 @@
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+};
+
+/// The kinds of GL objects that can be tracked
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ResourceKind {
+    Program,
+    Buffer,
+    Texture,
+    VertexArray,
+    Framebuffer,
+    Renderbuffer,
+    Query,
+}
+
+/// A GL object that has been created and not deleted yet
+#[derive(Debug)]
+pub struct TrackedResource {
+    pub kind: ResourceKind,
+    pub id: u32,
+    /// What the object is for
+    pub label: String,
+    /// An estimate of how much GPU memory the object uses, ignoring any padding the driver adds
+    pub bytes: u64,
+    /// Where the object was created. This is only captured with the `resource_tracking` feature
+    /// when `RUST_BACKTRACE` or `RUST_LIB_BACKTRACE` is set, since capturing is slow.
+    pub backtrace: Backtrace,
+}
+
+/// An estimate of the GPU memory used by the objects of a context, in bytes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub textures: u64,
+    pub buffers: u64,
+    pub renderbuffers: u64,
+}
+
+impl MemoryUsage {
+    pub fn total(&self) -> u64 {
+        self.textures + self.buffers + self.renderbuffers
+    }
+}
+
+/// A slot of the handle registry, which outlives the object in it so that stale handles to the
+/// object can be told apart from handles to whatever takes the slot next
+#[derive(Debug)]
+struct HandleSlot {
+    kind: ResourceKind,
+    /// The object in the slot, or `None` once it has been deleted
+    id: Option<u32>,
+    /// Bumped every time the object in the slot is deleted
+    generation: u32,
+    /// The label of the object that is or was in the slot
+    label: String,
+}
+
+/// Why a handle couldn't be resolved to a GL object
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandleError {
+    /// The object was deleted after the handle was made
+    Deleted {
+        kind: ResourceKind,
+        label: String,
+        generation: u32,
+    },
+    /// The handle is for another kind of object, or from another context
+    Unknown { kind: ResourceKind, index: u32 },
+    /// No GL context is being tracked on this thread
+    NoContext,
+}
+
+impl std::fmt::Display for HandleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandleError::Deleted {
+                kind,
+                label,
+                generation,
+            } => write!(
+                f,
+                "{:?} `{}` was deleted, but a handle to it ( generation {} ) was still used",
+                kind, label, generation
+            ),
+            HandleError::Unknown { kind, index } => write!(
+                f,
+                "{:?} handle {} doesn't belong to the current context",
+                kind, index
+            ),
+            HandleError::NoContext => write!(f, "No GL context is being tracked on this thread"),
+        }
+    }
+}
+
+impl std::error::Error for HandleError {}
+
+/// The GL objects that are alive in one GL context
+#[derive(Debug, Default)]
+pub struct ResourceTracker {
+    live: BTreeMap<(ResourceKind, u32), TrackedResource>,
+    /// The handle registry. Objects only get a slot once a handle to them is asked for.
+    slots: Vec<HandleSlot>,
+    free_slots: Vec<u32>,
+    slot_of: HashMap<(ResourceKind, u32), u32>,
+    /// The targets of the textures that aren't `TEXTURE_2D`, like cube maps
+    texture_targets: HashMap<u32, u32>,
+}
+
+impl ResourceTracker {
+    /// The objects that haven't been deleted, sorted by kind and id
+    pub fn live(&self) -> impl Iterator<Item = &TrackedResource> {
+        self.live.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    /// The slot index and generation of a handle to a live object, giving it a slot if it
+    /// doesn't have one yet
+    fn handle(&mut self, kind: ResourceKind, id: u32) -> Option<(u32, u32)> {
+        if let Some(&index) = self.slot_of.get(&(kind, id)) {
+            return Some((index, self.slots[index as usize].generation));
+        }
+        let label = self.live.get(&(kind, id))?.label.clone();
+        let index = match self.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.kind = kind;
+                slot.id = Some(id);
+                slot.label = label;
+                index
+            }
+            None => {
+                self.slots.push(HandleSlot {
+                    kind,
+                    id: Some(id),
+                    generation: 0,
+                    label,
+                });
+                self.slots.len() as u32 - 1
+            }
+        };
+        self.slot_of.insert((kind, id), index);
+        Some((index, self.slots[index as usize].generation))
+    }
+
+    /// The GL object that a handle is for, if it hasn't been deleted since
+    fn resolve(&self, kind: ResourceKind, index: u32, generation: u32) -> Result<u32, HandleError> {
+        let slot = self
+            .slots
+            .get(index as usize)
+            .filter(|slot| slot.kind == kind)
+            .ok_or(HandleError::Unknown { kind, index })?;
+        match slot.id {
+            Some(id) if slot.generation == generation => Ok(id),
+            _ => Err(HandleError::Deleted {
+                kind,
+                label: slot.label.clone(),
+                generation,
+            }),
+        }
+    }
+
+    /// The estimated memory used by the live objects
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let mut usage = MemoryUsage::default();
+        for resource in self.live() {
+            match resource.kind {
+                ResourceKind::Texture => usage.textures += resource.bytes,
+                ResourceKind::Buffer => usage.buffers += resource.bytes,
+                ResourceKind::Renderbuffer => usage.renderbuffers += resource.bytes,
+                _ => (),
+            }
+        }
+        usage
+    }
+
+    /// The live objects that use the most memory, largest first
+    pub fn largest(&self, count: usize) -> Vec<&TrackedResource> {
+        let mut resources = self
+            .live()
+            .filter(|resource| resource.bytes > 0)
+            .collect::<Vec<_>>();
+        resources.sort_by_key(|resource| std::cmp::Reverse(resource.bytes));
+        resources.truncate(count);
+        resources
+    }
+
+    /// A description of the memory usage, with the objects that use the most memory
+    pub fn memory_report(&self, count: usize) -> String {
+        let usage = self.memory_usage();
+        let mut report = format!(
+            "About {} of GPU memory ( textures {}, buffers {}, renderbuffers {} )\n",
+            format_bytes(usage.total()),
+            format_bytes(usage.textures),
+            format_bytes(usage.buffers),
+            format_bytes(usage.renderbuffers),
+        );
+        for resource in self.largest(count) {
+            writeln!(
+                report,
+                "    {:>10}  {:?} {}: {}",
+                format_bytes(resource.bytes),
+                resource.kind,
+                resource.id,
+                resource.label
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    /// A list of every object that is alive, with its label and estimated size, sorted by kind
+    /// and id
+    pub fn live_report(&self) -> String {
+        let mut report = format!(
+            "{} live GL objects, about {}\n",
+            self.live.len(),
+            format_bytes(self.memory_usage().total())
+        );
+        for resource in self.live() {
+            writeln!(
+                report,
+                "    {:?} {}: {} ( {} )",
+                resource.kind,
+                resource.id,
+                resource.label,
+                format_bytes(resource.bytes)
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    /// A description of every object that hasn't been deleted, with the backtraces of where they
+    /// were created if they were captured
+    pub fn report(&self) -> String {
+        let mut report = format!("{} GL objects were never deleted:\n", self.live.len());
+        for resource in self.live() {
+            writeln!(
+                report,
+                "    {:?} {}: {}",
+                resource.kind, resource.id, resource.label
+            )
+            .unwrap();
+            if resource.backtrace.status() == BacktraceStatus::Captured {
+                for line in resource.backtrace.to_string().lines() {
+                    writeln!(report, "        {}", line).unwrap();
+                }
+            }
+        }
+        report
+    }
+}
+
+thread_local! {
+    /// The tracker of every GL context on this thread that tracking has been started for
+    static TRACKERS: RefCell<HashMap<u64, ResourceTracker>> = RefCell::new(HashMap::new());
+    /// The key of the tracker for the GL context that is current on this thread
+    static CURRENT: Cell<Option<u64>> = const { Cell::new(None) };
+    /// The key to give to the next GL context
+    static NEXT_KEY: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Whether or not leak reports and creation backtraces were compiled in with the
+/// `resource_tracking` feature
+pub const ENABLED: bool = cfg!(feature = "resource_tracking");
+
+/// Record that a GL object that doesn't hold any memory of its own, like a vertex array, was
+/// created in the current context
+///
+/// The library's own objects, like meshes and textures, are tracked automatically. Handlers can
+/// track the objects they create themselves with this.
+pub fn track(kind: ResourceKind, id: u32, label: &str) {
+    track_sized(kind, id, label, 0);
+}
+
+/// Record that a GL object was created in the current context, along with an estimate of the
+/// memory it uses, such as from `texture_bytes`
+///
+/// Tracking the same object again replaces it, which is handy for buffers that are resized.
+pub fn track_sized(kind: ResourceKind, id: u32, label: &str, bytes: u64) {
+    with_current(|tracker| {
+        tracker.live.insert(
+            (kind, id),
+            TrackedResource {
+                kind,
+                id,
+                label: label.into(),
+                bytes,
+                backtrace: if ENABLED {
+                    Backtrace::capture()
+                } else {
+                    Backtrace::disabled()
+                },
+            },
+        );
+    });
+}
+
+/// Record that a GL object was deleted from the current context
+pub fn untrack(kind: ResourceKind, id: u32) {
+    with_current(|tracker| {
+        tracker.live.remove(&(kind, id));
+        if kind == ResourceKind::Texture {
+            tracker.texture_targets.remove(&id);
+        }
+        // Handles to the object go stale, and its slot can be reused
+        if let Some(index) = tracker.slot_of.remove(&(kind, id)) {
+            let slot = &mut tracker.slots[index as usize];
+            slot.id = None;
+            slot.generation = slot.generation.wrapping_add(1);
+            tracker.free_slots.push(index);
+        }
+    });
+}
+
+/// Record the target of a texture of the current context that isn't a `TEXTURE_2D`, like
+/// `TEXTURE_CUBE_MAP`, so that tools like the texture viewer know how to bind it
+///
+/// Call it after tracking the texture. It is forgotten when the texture is untracked.
+pub fn set_texture_target(texture: u32, target: u32) {
+    with_current(|tracker| {
+        if target == glow::TEXTURE_2D {
+            tracker.texture_targets.remove(&texture);
+        } else {
+            tracker.texture_targets.insert(texture, target);
+        }
+    });
+}
+
+/// The target of a live texture of the current context, which is `TEXTURE_2D` unless another one
+/// was recorded with `set_texture_target`, or `None` if the texture isn't tracked
+pub fn texture_target(texture: u32) -> Option<u32> {
+    let mut target = None;
+    with_current(|tracker| {
+        if tracker.live.contains_key(&(ResourceKind::Texture, texture)) {
+            let recorded = tracker.texture_targets.get(&texture).copied();
+            target = Some(recorded.unwrap_or(glow::TEXTURE_2D));
+        }
+    });
+    target
+}
+
+/// The ids and labels of the live objects of one kind in the current context, sorted by id
+pub fn live_labels(kind: ResourceKind) -> Vec<(u32, String)> {
+    let mut labels = Vec::new();
+    with_current(|tracker| {
+        labels = tracker
+            .live()
+            .filter(|resource| resource.kind == kind)
+            .map(|resource| (resource.id, resource.label.clone()))
+            .collect();
+    });
+    labels
+}
+
+/// The slot index and generation of a handle to a live object of the current context, or `None`
+/// if the object isn't tracked. Used through `handle::Handle`.
+pub(crate) fn handle(kind: ResourceKind, id: u32) -> Option<(u32, u32)> {
+    let mut handle = None;
+    with_current(|tracker| handle = tracker.handle(kind, id));
+    handle
+}
+
+/// The GL object of the current context that a handle is for. Used through `handle::Handle`.
+pub(crate) fn resolve(kind: ResourceKind, index: u32, generation: u32) -> Result<u32, HandleError> {
+    let mut result = Err(HandleError::NoContext);
+    with_current(|tracker| result = tracker.resolve(kind, index, generation));
+    result
+}
+
+/// The estimated memory used by the objects of the current context
+pub fn memory_usage() -> MemoryUsage {
+    let mut usage = MemoryUsage::default();
+    with_current(|tracker| usage = tracker.memory_usage());
+    usage
+}
+
+/// A description of the memory used by the current context, with the `count` objects that use
+/// the most
+pub fn memory_report(count: usize) -> String {
+    let mut report = String::new();
+    with_current(|tracker| report = tracker.memory_report(count));
+    report
+}
+
+/// A list of every live object of the current context, with their labels and sizes
+pub fn live_report() -> String {
+    let mut report = String::new();
+    with_current(|tracker| report = tracker.live_report());
+    report
+}
+
+/// The size of one pixel of a sized internal format in bytes, or 4 for formats this doesn't know
+pub fn bytes_per_pixel(internal_format: u32) -> u64 {
+    match internal_format {
+        glow::R8 | glow::STENCIL_INDEX8 => 1,
+        glow::RG8 | glow::R16F | glow::DEPTH_COMPONENT16 => 2,
+        glow::RGB8 | glow::SRGB8 | glow::DEPTH_COMPONENT24 => 3,
+        glow::RGBA8
+        | glow::SRGB8_ALPHA8
+        | glow::RG16F
+        | glow::R32F
+        | glow::R11F_G11F_B10F
+        | glow::RGB10_A2
+        | glow::DEPTH_COMPONENT32F
+        | glow::DEPTH24_STENCIL8 => 4,
+        glow::RGB16F => 6,
+        glow::RGBA16F | glow::RG32F | glow::DEPTH32F_STENCIL8 => 8,
+        glow::RGB32F => 12,
+        glow::RGBA32F => 16,
+        _ => 4,
+    }
+}
+
+/// An estimate of the memory used by a texture or renderbuffer
+///
+/// `mip_levels` counts the full size level, `layers` is 6 for cube maps or the layer count of
+/// array textures, and `samples` is the sample count of multisampled storage, or 1.
+pub fn texture_bytes(
+    width: u32,
+    height: u32,
+    internal_format: u32,
+    mip_levels: u32,
+    layers: u32,
+    samples: u32,
+) -> u64 {
+    let (mut width, mut height) = (width as u64, height as u64);
+    let mut pixels = 0;
+    for _ in 0..mip_levels.max(1) {
+        pixels += width * height;
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+    }
+    pixels * bytes_per_pixel(internal_format) * layers.max(1) as u64 * samples.max(1) as u64
+}
+
+/// Format a number of bytes with a binary unit, like `1.5 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024. && unit < UNITS.len() - 1 {
+        value /= 1024.;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+/// The report of the current context's live objects, or `None` if there aren't any
+pub fn current_report() -> Option<String> {
+    CURRENT.with(|current| {
+        let key = current.get()?;
+        TRACKERS.with(|trackers| {
+            let trackers = trackers.borrow();
+            let tracker = trackers.get(&key)?;
+            if tracker.is_empty() {
+                None
+            } else {
+                Some(tracker.report())
+            }
+        })
+    })
+}
+
+/// Start tracking a new GL context, returning the key for `make_current` and `finish_context`
+pub(crate) fn start_context() -> u64 {
+    let key = NEXT_KEY.with(|next| {
+        let key = next.get();
+        next.set(key + 1);
+        key
+    });
+    TRACKERS.with(|trackers| {
+        trackers
+            .borrow_mut()
+            .insert(key, ResourceTracker::default())
+    });
+    key
+}
+
+/// Send tracking to the given context's tracker, which should follow the current GL context
+pub(crate) fn make_current(key: u64) {
+    CURRENT.with(|current| current.set(Some(key)));
+}
+
+/// The key of the context that tracking goes to, for other per-context records
+pub(crate) fn current_key() -> Option<u64> {
+    CURRENT.with(|current| current.get())
+}
+
+/// Stop tracking a context that is being destroyed, returning its tracker
+pub(crate) fn finish_context(key: u64) -> Option<ResourceTracker> {
+    CURRENT.with(|current| {
+        if current.get() == Some(key) {
+            current.set(None);
+        }
+    });
+    TRACKERS.with(|trackers| trackers.borrow_mut().remove(&key))
+}
+
+/// Run a function with the tracker of the current context, if there is one
+fn with_current<F: FnOnce(&mut ResourceTracker)>(f: F) {
+    CURRENT.with(|current| {
+        if let Some(key) = current.get() {
+            TRACKERS.with(|trackers| {
+                if let Some(tracker) = trackers.borrow_mut().get_mut(&key) {
+                    f(tracker);
+                }
+            });
+        }
+    });
+}
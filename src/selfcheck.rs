@@ -0,0 +1,2411 @@
+use std::{
+    backtrace::BacktraceStatus,
+    cell::RefCell,
+    fmt::{self, Write as _},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use cgmath::{ortho, perspective, Deg, InnerSpace, Matrix4, Point3, Vector3, Vector4};
+use glow::HasContext;
+
+use crate::{
+    anti_aliasing::{AaMode, AntiAliasing},
+    auto_exposure::{AutoExposure, AutoExposureParams},
+    bvh::TriangleBvh,
+    camera::{FlyCamera, ProjectionMode},
+    camera_path::{CameraKeyframe, CameraPath},
+    character_controller::{CharacterController, CharacterParams},
+    clustered_lights::{ClusterGrid, ClusterLight, ClusterStorage, ClusteredLights},
+    color::Color,
+    config::Config,
+    damage::{Damage, MAX_DAMAGE_RECTS},
+    debug_draw::DebugDraw,
+    debug_text::{DebugText, TextAlign, TextOptions, CHAR_ADVANCE},
+    demo_state::{DemoState, DemoStates},
+    depth_mode::{self, DepthMode, DepthSetup},
+    depth_view::{DepthView, DepthViewMode, DepthViewPass},
+    diagnostics,
+    draw_list::TransformHistory,
+    fallback_assets,
+    features::Features,
+    gbuffer::{GBuffer, GBufferLayout},
+    gizmo::AxisGizmo,
+    grid::{GridParams, GroundGrid},
+    heightmap::Heightmap,
+    image_diff::{image_diff, read_framebuffer, AbCapture},
+    input::Input,
+    input_recording::InputRecorder,
+    mesh::{Mesh, MeshData},
+    mipmap::MipmapMode,
+    motion_blur::{MotionBlurParams, MotionBlurPass},
+    msaa_resolve::MsaaResolver,
+    outline::{OutlineParams, OutlinePass},
+    particles::ParticleSystem,
+    per_draw::PerDrawBuffer,
+    point_shadow::{PointShadow, PointShadowParams},
+    primitives, procedural,
+    readback::{self, AsyncReadback},
+    render_graph::TargetSize,
+    render_target::RenderTarget,
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram, ShaderTarget},
+    shader_test::{with_adapter_context, AdapterPreference},
+    ssao::{SsaoParams, SsaoPass},
+    texture::{create_texture_2d, AlphaMode, ImageData, TextureParams},
+    texture_viewer::{PickAt, TextureViewerPass, ViewableTexture},
+    tilemap::TileLayer,
+    upsample::Upsampler,
+    viewport::{Rect, ViewportRegistry},
+    virtual_resolution::{VirtualResolution, VirtualTarget},
+    wireframe::WireframePass,
+    with_windows_and_config,
+    workarounds::{self, Workarounds},
+    AppContext, HandlerFactory, RenderHandler, SliceAsBytes, WindowConfig,
+};
+use winit::MouseButton;
+
+const SOLID_VERTEX_SRC: &str = include_str!("selfcheck/solid.vert");
+const SOLID_FRAGMENT_SRC: &str = include_str!("selfcheck/solid.frag");
+const CLUSTERED_FRAGMENT_SRC: &str = include_str!("selfcheck/clustered.frag");
+/// A fragment shader with the color in a `Tint` uniform block
+const TINT_BLOCK_FRAGMENT_SRC: &str = include_str!("selfcheck/tint_block.frag");
+/// A fragment shader with an error on `BROKEN_LINE`, whose compile errors should point at it
+const BROKEN_FRAGMENT_SRC: &str = include_str!("selfcheck/broken.frag");
+const BROKEN_LINE: u32 = 6;
+/// The frames of the recording that `check_input_replay` paints with, as the cursor position on
+/// the canvas and the mouse button held down in each frame
+///
+/// The left button paints red and the right one green until the frame times add up to
+/// `PAINT_TIME`, so the presses in the last two frames shouldn't paint anything.
+const PAINT_FRAMES: [((u32, u32), Option<MouseButton>); 12] = [
+    ((1, 1), Some(MouseButton::Left)),
+    ((2, 1), Some(MouseButton::Left)),
+    ((3, 1), Some(MouseButton::Left)),
+    ((4, 1), Some(MouseButton::Left)),
+    ((5, 1), Some(MouseButton::Left)),
+    ((6, 1), Some(MouseButton::Left)),
+    ((3, 3), Some(MouseButton::Right)),
+    ((3, 4), Some(MouseButton::Right)),
+    ((5, 5), None),
+    ((6, 6), None),
+    ((6, 6), Some(MouseButton::Left)),
+    ((6, 7), Some(MouseButton::Left)),
+];
+/// The frame time of every frame of the paint recording
+const PAINT_FRAME_TIME: Duration = Duration::from_millis(100);
+/// How long the mouse buttons paint for
+const PAINT_TIME: Duration = Duration::from_secs(1);
+/// The size of the canvas that the paint recording is played back onto
+const PAINT_CANVAS_SIZE: (u32, u32) = (8, 8);
+
+/// The size of the framebuffers that most checks draw into
+const TARGET_SIZE: (u32, u32) = (16, 16);
+/// How far a channel of a read back pixel may be from what was expected, out of 255
+const PIXEL_TOLERANCE: u8 = 2;
+/// The most GL errors that are collected after a check, in case a broken driver never stops
+/// returning them
+const MAX_GL_ERRORS: usize = 16;
+
+/// The groups of checks that need a GL context, which are skipped when none can be made
+const GL_GROUPS: [&str; 9] = [
+    "shaders",
+    "anti-aliasing",
+    "post",
+    "textures",
+    "framebuffers",
+    "meshes",
+    "readback",
+    "goldens",
+    "resources",
+];
+
+/// How a check went
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Ok,
+    Failed,
+    /// The check couldn't run here, like on a system without a software renderer
+    Skipped,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(match self {
+            Outcome::Ok => "ok",
+            Outcome::Failed => "FAILED",
+            Outcome::Skipped => "skipped",
+        })
+    }
+}
+
+/// Why a check didn't pass, returned from the functions given to `SelfCheck::run`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CheckError {
+    Failed(String),
+    /// The check doesn't apply here, which doesn't count as a failure
+    Skipped(String),
+}
+
+impl From<String> for CheckError {
+    fn from(message: String) -> Self {
+        CheckError::Failed(message)
+    }
+}
+
+/// The result of one check
+#[derive(Clone, Debug)]
+pub struct CheckResult {
+    /// The subsystem that was checked, like `textures`
+    pub group: &'static str,
+    pub name: String,
+    pub outcome: Outcome,
+    /// A short note on what was found for checks that passed, or why the check failed or was
+    /// skipped
+    pub detail: String,
+    pub duration: Duration,
+}
+
+/// Runs checks and collects their results
+///
+/// Each check is a function that returns a detail for the table or a `CheckError`. Panics are
+/// caught and count as failures, so that one broken subsystem doesn't hide how the rest did.
+/// Checks run with `run_gl` also fail on GL errors and on problems passed to
+/// `diagnostics::report_problem`, like incomplete framebuffers.
+#[derive(Debug, Default)]
+pub struct SelfCheck {
+    results: Vec<CheckResult>,
+}
+
+impl SelfCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run a check that doesn't need a GL context
+    pub fn run<F: FnOnce() -> Result<String, CheckError>>(
+        &mut self,
+        group: &'static str,
+        name: &str,
+        check: F,
+    ) {
+        let start = Instant::now();
+        let result = catch_panic(check);
+        self.record(group, name, result, start.elapsed());
+    }
+
+    /// Run a check with a GL context, from a clean GL state
+    ///
+    /// The check fails if it leaves any GL errors behind, or reports any problems.
+    pub fn run_gl<F: FnOnce(&mut glow::Context) -> Result<String, CheckError>>(
+        &mut self,
+        gl: &mut glow::Context,
+        group: &'static str,
+        name: &str,
+        check: F,
+    ) {
+        reset_state(gl);
+        gl_errors(gl);
+        diagnostics::take_problems();
+
+        let start = Instant::now();
+        let mut result = catch_panic(|| check(&mut *gl));
+        let duration = start.elapsed();
+
+        let mut problems = diagnostics::take_problems();
+        problems.extend(
+            gl_errors(gl)
+                .into_iter()
+                .map(|error| diagnostics::error_name(error).to_owned()),
+        );
+        if !problems.is_empty() {
+            let problems = problems.join(", ");
+            result = match result {
+                Err(CheckError::Failed(message)) => {
+                    Err(CheckError::Failed(format!("{}\n{}", message, problems)))
+                }
+                _ => Err(CheckError::Failed(problems)),
+            };
+        }
+        self.record(group, name, result, duration);
+    }
+
+    /// Record a check that can't run, without running anything
+    pub fn skip(&mut self, group: &'static str, name: &str, reason: &str) {
+        self.record(
+            group,
+            name,
+            Err(CheckError::Skipped(reason.to_owned())),
+            Duration::default(),
+        );
+    }
+
+    fn record(
+        &mut self,
+        group: &'static str,
+        name: &str,
+        result: Result<String, CheckError>,
+        duration: Duration,
+    ) {
+        let (outcome, detail) = match result {
+            Ok(detail) => (Outcome::Ok, detail),
+            Err(CheckError::Failed(detail)) => (Outcome::Failed, detail),
+            Err(CheckError::Skipped(detail)) => (Outcome::Skipped, detail),
+        };
+        eprintln!("selfcheck {} {} ... {}", group, name, outcome);
+        self.results.push(CheckResult {
+            group,
+            name: name.to_owned(),
+            outcome,
+            detail,
+            duration,
+        });
+    }
+
+    /// The results in the order the checks ran
+    pub fn results(&self) -> &[CheckResult] {
+        &self.results
+    }
+
+    /// How many checks had an outcome
+    pub fn count(&self, outcome: Outcome) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.outcome == outcome)
+            .count()
+    }
+
+    /// Whether none of the checks failed
+    pub fn passed(&self) -> bool {
+        self.count(Outcome::Failed) == 0
+    }
+
+    /// A table of the results with the first line of each detail, followed by the full details of
+    /// the failures and a summary line
+    pub fn table(&self) -> String {
+        let column = |width: fn(&CheckResult) -> usize, title: &str| {
+            self.results
+                .iter()
+                .map(width)
+                .chain(Some(title.len()))
+                .max()
+                .unwrap()
+        };
+        let group_width = column(|result| result.group.len(), "group");
+        let name_width = column(|result| result.name.len(), "check");
+
+        let mut table = String::new();
+        writeln!(
+            table,
+            "{:<gw$}  {:<nw$}  {:<7}  {:>9}  detail",
+            "group",
+            "check",
+            "result",
+            "time",
+            gw = group_width,
+            nw = name_width
+        )
+        .unwrap();
+        for result in &self.results {
+            let time = format!("{:.2} ms", result.duration.as_secs_f64() * 1000.);
+            writeln!(
+                table,
+                "{:<gw$}  {:<nw$}  {:<7}  {:>9}  {}",
+                result.group,
+                result.name,
+                result.outcome,
+                time,
+                result.detail.lines().next().unwrap_or(""),
+                gw = group_width,
+                nw = name_width
+            )
+            .unwrap();
+        }
+
+        for result in &self.results {
+            if result.outcome == Outcome::Failed && result.detail.lines().nth(1).is_some() {
+                writeln!(
+                    table,
+                    "\n{} {}:\n{}",
+                    result.group, result.name, result.detail
+                )
+                .unwrap();
+            }
+        }
+        writeln!(
+            table,
+            "\n{} ok, {} failed, {} skipped",
+            self.count(Outcome::Ok),
+            self.count(Outcome::Failed),
+            self.count(Outcome::Skipped)
+        )
+        .unwrap();
+        table
+    }
+
+    /// The results as JSON, for attaching to bug reports and for tools to read
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+        writeln!(json, "{{\n  \"passed\": {},", self.passed()).unwrap();
+        writeln!(
+            json,
+            "  \"counts\": {{ \"ok\": {}, \"failed\": {}, \"skipped\": {} }},",
+            self.count(Outcome::Ok),
+            self.count(Outcome::Failed),
+            self.count(Outcome::Skipped)
+        )
+        .unwrap();
+        json.push_str("  \"checks\": [");
+        for (i, result) in self.results.iter().enumerate() {
+            write!(
+                json,
+                "{}\n    {{ \"group\": {}, \"name\": {}, \"outcome\": {}, \"detail\": {}, \
+                 \"seconds\": {} }}",
+                if i == 0 { "" } else { "," },
+                json_string(result.group),
+                json_string(&result.name),
+                json_string(&result.outcome.to_string().to_lowercase()),
+                json_string(&result.detail),
+                result.duration.as_secs_f64()
+            )
+            .unwrap();
+        }
+        json.push_str("\n  ]\n}\n");
+        json
+    }
+}
+
+/// A string as a JSON string literal
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(json, "\\u{:04x}", c as u32).unwrap(),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// Run a check, turning a panic into a failure with the panic's message
+fn catch_panic<F: FnOnce() -> Result<String, CheckError>>(check: F) -> Result<String, CheckError> {
+    panic::catch_unwind(AssertUnwindSafe(check)).unwrap_or_else(|payload| {
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_owned());
+        Err(CheckError::Failed(format!("panicked: {}", message)))
+    })
+}
+
+/// Take every GL error that is waiting, oldest first
+fn gl_errors(gl: &mut glow::Context) -> Vec<u32> {
+    let mut errors = Vec::new();
+    while errors.len() < MAX_GL_ERRORS {
+        match unsafe { gl.get_error() } {
+            glow::NO_ERROR => break,
+            error => errors.push(error),
+        }
+    }
+    errors
+}
+
+/// Put back the GL state that checks expect to start from, whatever the last one left
+fn reset_state(gl: &mut glow::Context) {
+    unsafe {
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        gl.bind_vertex_array(None);
+        gl.use_program(None);
+        for capability in [
+            glow::DEPTH_TEST,
+            glow::BLEND,
+            glow::CULL_FACE,
+            glow::SCISSOR_TEST,
+            glow::STENCIL_TEST,
+            glow::FRAMEBUFFER_SRGB,
+        ] {
+            gl.disable(capability);
+        }
+        gl.color_mask(true, true, true, true);
+        gl.depth_mask(true);
+        gl.viewport(0, 0, TARGET_SIZE.0 as i32, TARGET_SIZE.1 as i32);
+    }
+}
+
+/// Run every check, printing a line to stderr as each one finishes
+///
+/// The GL checks run in a context on the fastest GPU, like the windows use. Contexts on the
+/// other adapters are only made to see that they can be. The window checks run the demo loop in
+/// hidden windows, and are skipped when there is no display.
+pub fn run_all() -> SelfCheck {
+    let mut check = SelfCheck::new();
+    let hook = panic::take_hook();
+    // The panics are reported in the results, so keep them from printing in the middle of them
+    panic::set_hook(Box::new(|_| {}));
+
+    check_cpu(&mut check);
+    check_collision(&mut check);
+    check_fallback_assets(&mut check);
+    check_tile_layers(&mut check);
+    check_damage(&mut check);
+    check_text_layout(&mut check);
+    check_depth_modes(&mut check);
+    check_viewports(&mut check);
+    check_demo_state(&mut check);
+    check_camera_path(&mut check);
+    check_transform_history(&mut check);
+
+    let start = Instant::now();
+    let result = with_adapter_context(AdapterPreference::Hardware, |gl, loader| {
+        let (vendor, renderer, version) = unsafe {
+            (
+                gl.get_parameter_string(glow::VENDOR),
+                gl.get_parameter_string(glow::RENDERER),
+                gl.get_parameter_string(glow::VERSION),
+            )
+        };
+        check.record(
+            "context",
+            AdapterPreference::Hardware.name(),
+            Ok(format!("{}, {} ( {} )", renderer, version, vendor)),
+            start.elapsed(),
+        );
+
+        let features = Features::query_with_loader(gl, loader);
+        workarounds::make_current(Workarounds::detect(&vendor, &renderer, &version));
+        shader::make_current_target(ShaderTarget::for_features(&features));
+        let key = resources::start_context();
+        resources::make_current(key);
+
+        check_shaders(&mut check, gl, &features);
+        check_uniform_cache(&mut check, gl);
+        check_anti_aliasing(&mut check, gl);
+        check_post(&mut check, gl);
+        check_textures(&mut check, gl, &features);
+        check_framebuffers(&mut check, gl);
+        check_meshes(&mut check, gl);
+        check_readback(&mut check, gl);
+        check_goldens(&mut check, gl);
+        check_leak_report(&mut check, gl, key);
+
+        let start = Instant::now();
+        let leaks = match resources::finish_context(key) {
+            Some(tracker) if !tracker.is_empty() => Err(CheckError::Failed(format!(
+                "Objects were left alive by the checks\n{}",
+                tracker.live_report().trim_end()
+            ))),
+            _ => Ok(String::new()),
+        };
+        check.record("resources", "leaks", leaks, start.elapsed());
+        workarounds::make_current(Workarounds::default());
+        shader::make_current_target(ShaderTarget::BASELINE);
+    });
+    if let Err(error) = result {
+        check.record(
+            "context",
+            AdapterPreference::Hardware.name(),
+            Err(CheckError::Failed(format!("{:?}", error))),
+            start.elapsed(),
+        );
+        for &group in GL_GROUPS.iter() {
+            check.skip(group, "all", "No GL context could be made");
+        }
+    }
+
+    for &adapter in &AdapterPreference::ALL[1..] {
+        let start = Instant::now();
+        let result = with_adapter_context(adapter, |gl, _| unsafe {
+            format!(
+                "{}, {}",
+                gl.get_parameter_string(glow::RENDERER),
+                gl.get_parameter_string(glow::VERSION)
+            )
+        })
+        .map_err(|error| CheckError::Skipped(format!("{:?}", error)));
+        check.record("context", adapter.name(), result, start.elapsed());
+    }
+
+    check_device_recovery(&mut check);
+    check_input_replay(&mut check);
+
+    panic::set_hook(hook);
+    check
+}
+
+/// The primitive generators, without drawing them
+fn check_cpu(check: &mut SelfCheck) {
+    for (name, mesh) in primitive_meshes() {
+        check.run("primitives", &name, || {
+            let vertex_count = mesh.positions.len();
+            if mesh.indices.is_empty() || mesh.indices.len() % 3 != 0 {
+                return Err(
+                    format!("{} indices aren't whole triangles", mesh.indices.len()).into(),
+                );
+            }
+            if let Some(index) = mesh.indices.iter().find(|&&i| i as usize >= vertex_count) {
+                return Err(
+                    format!("Index {} is past the {} vertices", index, vertex_count).into(),
+                );
+            }
+            for (attribute, count) in [("normals", mesh.normals.len()), ("UVs", mesh.uvs.len())] {
+                if count != 0 && count != vertex_count {
+                    return Err(
+                        format!("{} {} for {} vertices", count, attribute, vertex_count).into(),
+                    );
+                }
+            }
+            Ok(format!(
+                "{} vertices, {} triangles",
+                vertex_count,
+                mesh.indices.len() / 3
+            ))
+        });
+    }
+}
+
+/// A character standing on a sphere of about 100,000 triangles, through a collision hierarchy
+fn check_collision(check: &mut SelfCheck) {
+    let sphere = primitives::uv_sphere(1., 256, 196);
+    let start = Instant::now();
+    let world = TriangleBvh::from_meshes(vec![(&sphere, Matrix4::from_scale(1.))]);
+    let build_time = start.elapsed();
+    check.run("collision", "bvh", || {
+        let hit = world
+            .raycast(Point3::new(0., 3., 0.), -Vector3::unit_y(), 10.)
+            .ok_or_else(|| "A ray down onto the sphere missed it".to_string())?;
+        if (hit.point.y - 1.).abs() > 0.01 {
+            return Err(format!("A ray down onto the sphere hit it at {:?}", hit.point).into());
+        }
+        Ok(format!(
+            "{} triangles in {} nodes, built in {:.1} ms",
+            world.triangle_count(),
+            world.node_count(),
+            build_time.as_secs_f64() * 1000.
+        ))
+    });
+    check.run("collision", "character", || {
+        let mut character =
+            CharacterController::new(Point3::new(0., 1.5, 0.), CharacterParams::default());
+        let start = Instant::now();
+        let steps = 60;
+        for _ in 0..steps {
+            character.step(1. / 60., &world);
+        }
+        let step_time = start.elapsed() / steps;
+        if !character.grounded || (character.position.y - 1.).abs() > 0.05 {
+            return Err(format!(
+                "The character should be standing on top of the sphere, but is at {:?} and {}",
+                character.position,
+                if character.grounded {
+                    "grounded"
+                } else {
+                    "not grounded"
+                }
+            )
+            .into());
+        }
+        Ok(format!(
+            "{:.1} microseconds per step",
+            step_time.as_secs_f64() * 1e6
+        ))
+    });
+}
+
+/// Loading the examples' assets from an asset directory that doesn't exist, which should give the
+/// built-in copies or the fallbacks instead of failing
+fn check_fallback_assets(check: &mut SelfCheck) {
+    let config = Config {
+        asset_dir: std::env::temp_dir().join("me_learning_opengl_selfcheck_no_assets"),
+        ..Default::default()
+    };
+    for name in ["awesomeface.png", "wall.jpg"] {
+        let path = config.asset_path(name);
+        check.run("fallback assets", name, || {
+            let image = ImageData::open(&path);
+            if fallback_assets::embedded(&path).is_some() {
+                return if fallback_assets::has_border(&image) {
+                    Err("The built-in copy has a fallback border".to_string().into())
+                } else {
+                    Ok(format!("Built-in copy, {}x{}", image.width, image.height))
+                };
+            }
+            let size = fallback_assets::IMAGE_SIZE;
+            if (image.width, image.height) != (size, size) {
+                return Err(format!(
+                    "The fallback should be {}x{}, but is {}x{}",
+                    size, size, image.width, image.height
+                )
+                .into());
+            }
+            if !fallback_assets::has_border(&image) {
+                return Err("The fallback doesn't have a border".to_string().into());
+            }
+            Ok(format!("Fallback, {}x{}", image.width, image.height))
+        });
+    }
+    check.run("fallback assets", "heightmap.png", || {
+        let heightmap = Heightmap::open(config.asset_path("heightmap.png"));
+        let count = (heightmap.width * heightmap.height) as usize;
+        if count == 0 || heightmap.heights.len() != count {
+            return Err(format!(
+                "{} heights for a {}x{} heightmap",
+                heightmap.heights.len(),
+                heightmap.width,
+                heightmap.height
+            )
+            .into());
+        }
+        Ok(format!("{}x{}", heightmap.width, heightmap.height))
+    });
+    check.run("fallback assets", "cube.obj", || {
+        let models = MeshData::load_obj(config.asset_path("cube.obj"));
+        match models.as_slice() {
+            [model] if !model.indices.is_empty() => {
+                Ok(format!("{} triangles", model.indices.len() / 3))
+            }
+            _ => Err(format!("{} models instead of one cube", models.len()).into()),
+        }
+    });
+}
+
+/// Reading tile layers from CSV and from a Tiled map, with Tiled's empty cells, flipped tiles,
+/// and first tile numbers
+fn check_tile_layers(check: &mut SelfCheck) {
+    let expected = TileLayer {
+        width: 3,
+        height: 2,
+        tiles: vec![Some(0), None, Some(4), None, Some(1), Some(2)],
+    };
+    let layers = [
+        ("csv", "1,0,5\n-1,2,3\n"),
+        (
+            "tiled",
+            "<map>\n <tileset firstgid=\"10\" source=\"tiles.tsx\"/>\n \
+             <layer><data encoding=\"csv\">\n10,0,14,\n0,2147483659,12\n</data></layer>\n</map>",
+        ),
+    ];
+    for (name, text) in layers {
+        check.run("tilemap", name, || {
+            let layer = TileLayer::parse(text).map_err(|error| error.to_string())?;
+            if layer != expected {
+                return Err(format!("Read {:?}, expected {:?}", layer, expected).into());
+            }
+            Ok(format!("{}x{}", layer.width, layer.height))
+        });
+    }
+    check.run("tilemap", "uneven rows", || {
+        match TileLayer::parse("1,2,3\n4,5\n") {
+            Ok(layer) => Err(format!("Read uneven rows as {:?}", layer).into()),
+            Err(error) => Ok(error.to_string()),
+        }
+    });
+}
+
+fn check_damage(check: &mut SelfCheck) {
+    let size = (200, 100);
+    let merges = [
+        (
+            "overlapping",
+            vec![Rect::new(0, 0, 10, 10), Rect::new(5, 0, 10, 10)],
+            vec![Rect::new(0, 0, 15, 10)],
+        ),
+        (
+            "apart",
+            vec![Rect::new(0, 0, 10, 10), Rect::new(100, 50, 10, 10)],
+            vec![Rect::new(0, 0, 10, 10), Rect::new(100, 50, 10, 10)],
+        ),
+        (
+            "chained",
+            vec![
+                Rect::new(0, 0, 10, 10),
+                Rect::new(20, 0, 10, 10),
+                Rect::new(5, 0, 20, 10),
+            ],
+            vec![Rect::new(0, 0, 30, 10)],
+        ),
+    ];
+    for (name, added, expected) in merges {
+        check.run("damage", name, || {
+            let mut damage = Damage::new();
+            for rect in added {
+                damage.add(rect);
+            }
+            if damage.rects() != expected.as_slice() {
+                return Err(
+                    format!("Merged into {:?}, expected {:?}", damage.rects(), expected).into(),
+                );
+            }
+            Ok(format!("{} px", damage.area(size)))
+        });
+    }
+    check.run("damage", "capped", || {
+        let mut damage = Damage::new();
+        for i in 0..8 {
+            damage.add(Rect::new(i * 24, (i % 2) * 80, 4, 4));
+        }
+        if damage.rects().len() > MAX_DAMAGE_RECTS {
+            return Err(format!("Kept {} rects", damage.rects().len()).into());
+        }
+        let bounds = damage.bounds(size);
+        if bounds != Some(Rect::new(0, 0, 172, 84)) {
+            return Err(format!("Covered {:?}", bounds).into());
+        }
+        Ok(format!("{} rects", damage.rects().len()))
+    });
+    check.run("damage", "full", || {
+        let mut damage = Damage::new();
+        damage.add(Rect::new(0, 0, 10, 10));
+        damage.add_full();
+        damage.add(Rect::new(20, 20, 10, 10));
+        if !damage.rects().is_empty() || damage.bounds(size) != Some(Rect::new(0, 0, 200, 100)) {
+            return Err(format!("Full damage kept {:?}", damage.rects()).into());
+        }
+        Ok(format!("{} px", damage.area(size)))
+    });
+}
+
+/// Wrapping and measuring the debug text, and that the glyphs it draws are where the layout says
+fn check_text_layout(check: &mut SelfCheck) {
+    // The width of a line of some number of characters at a scale of 1
+    let width = |chars: u32| (chars * CHAR_ADVANCE - 1) as f32;
+    let wraps = [
+        (
+            "words",
+            "the quick  brown fox jumps",
+            width(9),
+            &["the quick", "brown fox", "jumps"][..],
+        ),
+        ("long word", "abcdefghij", width(4), &["abcd", "efgh", "ij"]),
+        (
+            "line breaks",
+            "one two\nthree",
+            width(20),
+            &["one two", "three"],
+        ),
+        (
+            "accents",
+            "cafe\u{301}s cafe\u{301}s",
+            width(5),
+            &["cafe\u{301}s", "cafe\u{301}s"],
+        ),
+    ];
+    for (name, text, max_width, expected) in wraps {
+        check.run("text layout", name, || {
+            let options = TextOptions {
+                max_width: Some(max_width),
+                ..Default::default()
+            };
+            let layout = DebugText::measure_text(text, 1, options);
+            let lines = layout
+                .lines
+                .iter()
+                .map(|line| &text[line.range.clone()])
+                .collect::<Vec<_>>();
+            if lines != expected {
+                return Err(format!("Wrapped into {:?}, expected {:?}", lines, expected).into());
+            }
+            if let Some(line) = layout.lines.iter().find(|line| line.width > max_width) {
+                return Err(format!("{:?} is wider than {} px", line, max_width).into());
+            }
+            Ok(format!("{} lines", lines.len()))
+        });
+    }
+
+    check.run("text layout", "unicode", || {
+        // Each character is one glyph wide whatever its size in bytes, and the accent takes no
+        // space
+        let size = DebugText::text_size("café e\u{301} 25°C 日本", 2);
+        let expected = (width(14) as u32 * 2, 14);
+        if size != expected {
+            return Err(format!("Measured {:?}, expected {:?}", size, expected).into());
+        }
+        let layout = DebugText::measure_text("日本?", 1, TextOptions::default());
+        let glyphs = DebugText::place_glyphs("日本?", 1, &layout);
+        let indices = glyphs.iter().map(|(_, index)| *index).collect::<Vec<_>>();
+        if indices.len() != 3 || indices[0] != indices[1] || indices[0] == indices[2] {
+            return Err(format!("Drew the glyphs {:?}", indices).into());
+        }
+        Ok(format!("{}x{}", size.0, size.1))
+    });
+
+    let texts = [
+        "Frame 16.7 ms",
+        "a path/to/some/file/that/goes/on/for/a/while.png",
+        "several short words that wrap onto a few lines\nand a second paragraph",
+    ];
+    let aligns = [TextAlign::Left, TextAlign::Center, TextAlign::Right];
+    for (i, text) in texts.iter().enumerate() {
+        for align in aligns {
+            let options = TextOptions {
+                align,
+                max_width: Some(width(12) * 2.),
+            };
+            check.run("text layout", &format!("{:?} text {}", align, i), || {
+                let layout = DebugText::measure_text(text, 2, options);
+                let glyphs = DebugText::place_glyphs(text, 2, &layout);
+                let bounds = glyphs.iter().fold(
+                    [f32::MAX, f32::MAX, f32::MIN, f32::MIN],
+                    |[left, top, right, bottom], (rect, _)| {
+                        [
+                            left.min(rect[0]),
+                            top.min(rect[1]),
+                            right.max(rect[2]),
+                            bottom.max(rect[3]),
+                        ]
+                    },
+                );
+                let measured = [layout.left, 0., layout.left + layout.width, layout.height];
+                if bounds != measured {
+                    return Err(
+                        format!("Drew glyphs over {:?}, measured {:?}", bounds, measured).into(),
+                    );
+                }
+                Ok(format!("{} lines", layout.lines.len()))
+            });
+        }
+    }
+}
+
+/// Every primitive generator's mesh, named, with each level of the sphere LODs
+fn primitive_meshes() -> Vec<(String, MeshData)> {
+    let mut meshes = vec![("uv sphere".to_owned(), primitives::uv_sphere(0.8, 16, 8))];
+    for (level, mesh) in primitives::sphere_lods(0.8, 16, 3).into_iter().enumerate() {
+        meshes.push((format!("sphere lod {}", level), mesh));
+    }
+    meshes.push(("plane".to_owned(), primitives::plane(1.5, 1.5, 1.)));
+    meshes.push(("cuboid".to_owned(), primitives::cuboid(1., 1., 1.)));
+    meshes
+}
+
+/// Build each of the library's passes, which compiles and links their shaders, and delete them
+fn check_shaders(check: &mut SelfCheck, gl: &mut glow::Context, features: &Features) {
+    let passes: [(&str, fn(&mut glow::Context, &Features)); 16] = [
+        ("debug draw", |gl, _| DebugDraw::new(gl).delete(gl)),
+        ("debug text", |gl, _| DebugText::new(gl).delete(gl)),
+        ("axis gizmo", |gl, _| AxisGizmo::new(gl).delete(gl)),
+        ("ground grid", |gl, _| {
+            GroundGrid::new(gl, GridParams::default()).delete(gl)
+        }),
+        ("msaa resolve", |gl, _| MsaaResolver::new(gl).delete(gl)),
+        ("upsample", |gl, _| Upsampler::new(gl).delete(gl)),
+        ("outline", |gl, _| {
+            OutlinePass::new(gl, OutlineParams::default()).delete(gl)
+        }),
+        ("ssao", |gl, _| {
+            SsaoPass::new(gl, SsaoParams::default()).delete(gl)
+        }),
+        ("point shadow", |gl, _| {
+            PointShadow::new(gl, PointShadowParams::default()).delete(gl)
+        }),
+        ("auto exposure", |gl, _| {
+            AutoExposure::new(gl, AutoExposureParams::default()).delete(gl)
+        }),
+        ("particles", |gl, features| {
+            ParticleSystem::new(gl, features).delete(gl)
+        }),
+        ("per draw", |gl, features| {
+            PerDrawBuffer::new(gl, features).delete(gl)
+        }),
+        ("wireframe", |gl, _| WireframePass::new(gl).delete(gl)),
+        ("motion blur", |gl, _| {
+            MotionBlurPass::new(gl, MotionBlurParams::default()).delete(gl)
+        }),
+        ("texture viewer", |gl, _| {
+            TextureViewerPass::new(gl).delete(gl)
+        }),
+        ("selfcheck solid", |gl, _| {
+            ShaderProgram::new(gl, SOLID_VERTEX_SRC, SOLID_FRAGMENT_SRC)
+                .unwrap()
+                .delete(gl)
+        }),
+    ];
+    // Every pass is compiled for each target the context can compile, like GLSL ES, which is
+    // named after the pass unless it is the context's own
+    let native = ShaderTarget::for_features(features);
+    for target in ShaderTarget::supported(features) {
+        shader::make_current_target(target);
+        // The compute shaders need storage buffers, which GLSL ES 3.00 doesn't have
+        let mut features = features.clone();
+        if !target.supports(430) {
+            features.compute = None;
+        }
+        for (name, build) in passes.iter() {
+            let name = if target == native {
+                name.to_string()
+            } else {
+                format!("{} ( {} )", name, target)
+            };
+            check.run_gl(gl, "shaders", &name, |gl| {
+                build(gl, &features);
+                Ok(String::new())
+            });
+        }
+
+        // The lines added by the rewrite and the defines shouldn't move the line of the error
+        let name = if target == native {
+            "error lines".to_string()
+        } else {
+            format!("error lines ( {} )", target)
+        };
+        check.run_gl(gl, "shaders", &name, |gl| {
+            let result =
+                ShaderProgram::with_defines(gl, SOLID_VERTEX_SRC, BROKEN_FRAGMENT_SRC, &["BROKEN"]);
+            match result {
+                Ok(mut program) => {
+                    program.delete(gl);
+                    Err("The broken shader compiled".to_string().into())
+                }
+                Err(log) if log_mentions_line(&log, BROKEN_LINE) => Ok(String::new()),
+                Err(log) => {
+                    Err(format!("Expected an error on line {}: {}", BROKEN_LINE, log).into())
+                }
+            }
+        });
+    }
+    shader::make_current_target(native);
+
+    // The G-buffer passes compile a variant of their shader for each layout and sample count
+    let projection = perspective(Deg(60.), 1., 0.1, 100.);
+    for layout in [GBufferLayout::Fat, GBufferLayout::Packed] {
+        for samples in [1, 4] {
+            let variant = format!("{:?} {}x", layout, samples).to_lowercase();
+            check.run_gl(gl, "shaders", &format!("outline {}", variant), |gl| {
+                let gbuffer = GBuffer::with_samples(gl, TARGET_SIZE, layout, samples);
+                let output = color_target(gl, "Selfcheck outline");
+                unsafe { gl.bind_framebuffer(glow::FRAMEBUFFER, Some(output.framebuffer)) };
+                let mut outline = OutlinePass::new(gl, OutlineParams::default());
+                outline.draw(gl, &gbuffer, projection);
+                let defines = gbuffer.defines().join(" ");
+                outline.delete(gl);
+                output.delete(gl);
+                gbuffer.delete(gl);
+                Ok(defines)
+            });
+            check.run_gl(gl, "shaders", &format!("ssao {}", variant), |gl| {
+                let gbuffer = GBuffer::with_samples(gl, TARGET_SIZE, layout, samples);
+                let mut ssao = SsaoPass::new(gl, SsaoParams::default());
+                ssao.render(gl, &gbuffer, projection);
+                let defines = gbuffer.defines().join(" ");
+                ssao.delete(gl);
+                gbuffer.delete(gl);
+                Ok(defines)
+            });
+        }
+    }
+
+    // The clustered lights chunk reads the same lists from either kind of buffer. The plane is
+    // drawn at the first light, which only reaches its own cluster, so it should be red.
+    let mut storages = vec![(ClusterStorage::UniformBuffers, Features::default())];
+    if features.compute.is_some() {
+        storages.insert(0, (ClusterStorage::StorageBuffers, features.clone()));
+    }
+    let lights = [
+        ClusterLight {
+            position: Point3::new(0., 0., -5.),
+            color: [1., 0., 0.],
+            radius: 1.,
+        },
+        ClusterLight {
+            position: Point3::new(0., 0., -50.),
+            color: [0., 1., 0.],
+            radius: 1.,
+        },
+    ];
+    for (storage, features) in storages {
+        let name = format!("clustered lights {}", storage.name());
+        check.run_gl(gl, "shaders", &name, |gl| {
+            let output = color_target(gl, "Selfcheck clustered lights");
+            let mut clustered = ClusteredLights::new(gl, &features, ClusterGrid::default());
+            let mut program = ShaderProgram::new(
+                gl,
+                SOLID_VERTEX_SRC,
+                &clustered.shader_source(CLUSTERED_FRAGMENT_SRC),
+            )?;
+            let mesh = Mesh::new(gl, &primitives::plane(2., 2., 1.));
+            let view = Matrix4::from_scale(1.);
+            clustered.update(gl, &lights, view, projection, 0.1, 100.);
+            clear(gl, Some(output.framebuffer), [0., 0., 0., 1.]);
+            program.bind(gl);
+            program.set_uniform(gl, "transform", Matrix4::from_angle_x(Deg(90.)));
+            program.set_uniform(gl, "point", [0f32, 0., -5.]);
+            clustered.bind(gl, &mut program);
+            mesh.draw(gl);
+            let image = read_framebuffer(gl, Some(output.framebuffer), TARGET_SIZE);
+            let stats = clustered.stats();
+            mesh.delete(gl);
+            program.delete(gl);
+            clustered.delete(gl);
+            output.delete(gl);
+            expect_pixel(&image, 8, 8, [255, 0, 0, 255])?;
+            Ok(format!(
+                "{} list entries over {} clusters",
+                stats.assignments, stats.occupied_clusters
+            ))
+        });
+    }
+}
+
+/// Whether a compile log points at a line, in any of the ways drivers write it, like `0:5(12):`,
+/// `0(5) :`, or `ERROR: 0:5:`
+fn log_mentions_line(log: &str, line: u32) -> bool {
+    [
+        format!(":{}(", line),
+        format!("({})", line),
+        format!(":{}:", line),
+    ]
+    .iter()
+    .any(|pattern| log.contains(pattern.as_str()))
+}
+
+/// The perspective projection of each depth setup, which should put the near and far planes at
+/// the setup's depths, and turn back into the standard one
+fn check_depth_modes(check: &mut SelfCheck) {
+    let reversed = DepthSetup::new(DepthMode::ReverseZ, &Features::default());
+    for (name, setup) in [
+        ("standard", DepthSetup::STANDARD),
+        ("reverse-z", reversed),
+        (
+            "reverse-z zero to one",
+            DepthSetup {
+                zero_to_one: true,
+                ..reversed
+            },
+        ),
+    ] {
+        check.run("depth", name, || {
+            let (near, far) = (0.1, 10_000.);
+            let projection = setup.perspective(Deg(60.), 1., near, far);
+            let depth = |distance: f32| {
+                let clip = projection * Vector4::new(0., 0., -distance, 1.);
+                let z = clip.z / clip.w;
+                if setup.zero_to_one {
+                    z
+                } else {
+                    z * 0.5 + 0.5
+                }
+            };
+            let (near_depth, far_depth) = (depth(near), depth(far));
+            let expected = if setup.is_reversed() {
+                (1., 0.)
+            } else {
+                (0., 1.)
+            };
+            if (near_depth - expected.0).abs() > 1e-4 || (far_depth - expected.1).abs() > 1e-4 {
+                return Err(format!(
+                    "The near and far planes are at depths of {} and {}",
+                    near_depth, far_depth
+                )
+                .into());
+            }
+            let difference =
+                setup.standard_projection(projection) - perspective(Deg(60.), 1., near, far);
+            let elements: &[f32; 16] = difference.as_ref();
+            if elements.iter().any(|element| element.abs() > 1e-3) {
+                return Err("The projection doesn't turn back into the standard one".into());
+            }
+            Ok(format!(
+                "the middle of the view at a depth of {}",
+                depth((near + far) / 2.)
+            ))
+        });
+    }
+}
+
+/// A split screen with an inset over one of its halves, where each view should be found under
+/// the points in it and unproject them with its own camera
+fn check_viewports(check: &mut SelfCheck) {
+    check.run("viewports", "split screen and inset", || {
+        let size = (800, 600);
+        let left = Rect::new(0, 0, 400, 600);
+        let right = Rect::new(400, 0, 400, 600);
+        let inset = Rect::new(600, 20, 180, 180);
+        let origin = Point3::new(0., 0., 0.);
+        let mut registry = ViewportRegistry::new();
+        // The left half is drawn with reversed depth, which registering it should undo
+        let reversed = DepthSetup {
+            zero_to_one: true,
+            ..DepthSetup::new(DepthMode::ReverseZ, &Features::default())
+        };
+        depth_mode::make_current(reversed);
+        registry.register(
+            "left",
+            left,
+            Matrix4::look_at(Point3::new(0., 0., 5.), origin, Vector3::unit_y()),
+            reversed.perspective(Deg(60.), left.aspect_ratio(), 0.1, 100.),
+        );
+        depth_mode::make_current(DepthSetup::STANDARD);
+        registry.register(
+            "right",
+            right,
+            Matrix4::look_at(Point3::new(5., 0., 0.), origin, Vector3::unit_y()),
+            perspective(Deg(60.), right.aspect_ratio(), 0.1, 100.),
+        );
+        registry.register_inset(
+            "inset",
+            inset,
+            2,
+            Matrix4::look_at(Point3::new(0., 10., 0.), origin, -Vector3::unit_z()),
+            ortho(-5., 5., -5., 5., 0.1, 20.),
+        );
+        registry.end_frame(size, size);
+
+        // The middle of each view looks straight along its camera, and the inset's border is
+        // none of them
+        for (position, expected) in [
+            ((200., 300.), Some(("left", -Vector3::unit_z()))),
+            ((600., 300.), Some(("right", -Vector3::unit_x()))),
+            ((690., 110.), Some(("inset", -Vector3::unit_y()))),
+            ((599., 110.), None),
+        ] {
+            let view = registry.view_at(position);
+            match (view, expected) {
+                (None, None) => (),
+                (Some(view), Some((name, direction))) if view.name == name => {
+                    let ray = view
+                        .ray(position)
+                        .ok_or_else(|| format!("The {} view has no ray", name))?;
+                    if (ray.direction - direction).magnitude() > 1e-3 {
+                        return Err(format!(
+                            "The ray through the middle of the {} view points along {:?}",
+                            name, ray.direction
+                        )
+                        .into());
+                    }
+                }
+                _ => {
+                    return Err(format!(
+                        "Found the {:?} view at {:?} instead of {:?}",
+                        view.map(|view| &view.name),
+                        position,
+                        expected.map(|(name, _)| name)
+                    )
+                    .into())
+                }
+            }
+        }
+
+        // A point seen in each half comes back to where it was, off of the middle of the view
+        let point = Point3::new(1., 0.5, -1.);
+        for name in ["left", "right"] {
+            let view = registry.view(name).unwrap();
+            let clip = view.projection * view.view * point.to_homogeneous();
+            let ndc = clip.truncate() / clip.w;
+            let rect = view.rect;
+            let position = (
+                rect.x as f32 + (ndc.x + 1.) / 2. * rect.width as f32,
+                rect.y as f32 + (ndc.y + 1.) / 2. * rect.height as f32,
+            );
+            if registry.view_at(position).map(|found| &found.name) != Some(&view.name) {
+                return Err(format!("The point isn't in the {} view", name).into());
+            }
+            let unprojected = view
+                .unproject(position, ndc.z)
+                .ok_or_else(|| format!("The {} view can't unproject", name))?;
+            if (unprojected - point).magnitude() > 1e-3 {
+                return Err(format!(
+                    "The point unprojected to {:?} in the {} view",
+                    unprojected, name
+                )
+                .into());
+            }
+        }
+        Ok(String::new())
+    });
+}
+
+/// Make sure things don't look like they moved on the frame they first appear or after they
+/// teleport, and that they do otherwise
+fn check_transform_history(check: &mut SelfCheck) {
+    check.run("motion", "transform history", || {
+        let at = |x: f32| Matrix4::from_translation(Vector3::new(x, 0., 0.));
+        let mut history = TransformHistory::new();
+        let mut frame = |x: f32| {
+            let previous = history.previous_model(0, at(x));
+            history.end_frame();
+            previous.w.x
+        };
+        let expected = [(1., 1.), (2., 1.), (2.5, 2.), (20., 20.), (21., 20.)];
+        for (x, previous) in expected.iter().copied() {
+            let got = frame(x);
+            if got != previous {
+                return Err(format!("Moving to {} came from {}, not {}", x, got, previous).into());
+            }
+        }
+
+        // Things that were teleported, or weren't drawn last frame, start over
+        history.teleport(0);
+        if history.previous_model(0, at(22.)) != at(22.) {
+            return Err("A teleported thing moved".into());
+        }
+        history.end_frame();
+        history.end_frame();
+        if history.previous_model(0, at(23.)) != at(23.) {
+            return Err("A thing that wasn't drawn last frame moved".into());
+        }
+        Ok(String::new())
+    });
+}
+
+/// Write a camera path as RON and read it back, and make sure keyframes are read by their field
+/// names, with missing and unknown fields failing
+fn check_camera_path(check: &mut SelfCheck) {
+    check.run("camera path", "round trip", || {
+        let mut path = CameraPath::new();
+        path.add_keyframe(CameraKeyframe {
+            time: 0.25,
+            position: Point3::new(-7., 1.2, 1e-7),
+            target: Point3::new(0., -0.5, f32::MAX),
+        });
+        path.add_keyframe(CameraKeyframe {
+            time: 4.,
+            position: Point3::new(3.5, 2., 4.),
+            target: Point3::new(1., 1., 1.),
+        });
+        let read = CameraPath::from_ron(&path.to_ron()).map_err(|error| error.to_string())?;
+        if read != path {
+            return Err(format!("The path came back as {:?}", read.keyframes()).into());
+        }
+        Ok(String::new())
+    });
+
+    check.run("camera path", "fields by name", || {
+        let ron = "CameraPath(\n\
+                   // 2 keyframes, 1 of them out of order\n\
+                   keyframes: [\n\
+                   (target: (4, 5, 6), time: 2, position: (1, 2, 3)),\n\
+                   (position: (0, 0, 0), target: (0, 0, -1), time: 0.5),\n\
+                   ],\n\
+                   )";
+        let path = CameraPath::from_ron(ron).map_err(|error| error.to_string())?;
+        let expected = [
+            (0.5, Point3::new(0., 0., 0.), Point3::new(0., 0., -1.)),
+            (2., Point3::new(1., 2., 3.), Point3::new(4., 5., 6.)),
+        ];
+        let keyframes = path
+            .keyframes()
+            .iter()
+            .map(|k| (k.time, k.position, k.target))
+            .collect::<Vec<_>>();
+        if keyframes != expected {
+            return Err(format!("The keyframes were read as {:?}", keyframes).into());
+        }
+
+        for keyframe in [
+            "(time: 1, position: (1, 2, 3))",
+            "(time: 1, position: (1, 2, 3), target: (0, 0, 0), fov: 60)",
+            "(time: 1, position: (1, 2), target: (0, 0, 0))",
+            "(time: soon, position: (1, 2, 3), target: (0, 0, 0))",
+        ] {
+            let ron = format!("CameraPath(keyframes: [{}])", keyframe);
+            if CameraPath::from_ron(&ron).is_ok() {
+                return Err(format!("`{}` was read", keyframe).into());
+            }
+        }
+        Ok(String::new())
+    });
+}
+
+/// Save a camera's state, read it back from the text of the state file, and make sure that state
+/// of another version, or that can't be read, is discarded
+fn check_demo_state(check: &mut SelfCheck) {
+    check.run("demo state", "camera round trip", || {
+        let name = "selfcheck: Demo";
+        let origin = Point3::new(0., 0., 0.);
+        let mut camera = FlyCamera::new(Point3::new(1.5, 2., -3.25), 30., -12.5);
+        camera.set_projection_mode(ProjectionMode::Orthographic);
+        let mut state = DemoState::new(name);
+        state.save("camera", &camera);
+        let mut states = DemoStates::default();
+        states.set(&state);
+        let toml = states.to_toml();
+
+        let read = |toml: &str| DemoStates::from_toml(toml).map_err(|error| error.to_string());
+        let mut restored = FlyCamera::new(origin, 0., 0.);
+        if !read(&toml)?.get(name).restore("camera", &mut restored) {
+            return Err("The camera wasn't restored".into());
+        }
+        if restored.position != camera.position
+            || (restored.yaw, restored.pitch) != (camera.yaw, camera.pitch)
+            || restored.projection_mode() != camera.projection_mode()
+        {
+            return Err(format!("The camera came back at {:?}", restored.position).into());
+        }
+
+        // A blob of another version, or with a value that can't be read, leaves the camera as it
+        // was and is forgotten
+        for (from, to) in [
+            ("camera.version = 1", "camera.version = 2"),
+            ("camera.yaw = \"30\"", "camera.yaw = \"thirty\""),
+        ] {
+            let mut demo = read(&toml.replace(from, to))?.get(name);
+            let mut fresh = FlyCamera::new(origin, 0., 0.);
+            if demo.restore("camera", &mut fresh) || fresh.position != origin {
+                return Err(format!("The camera was restored with `{}`", to).into());
+            }
+            if demo.keys().count() != 0 {
+                return Err(format!("The camera with `{}` wasn't forgotten", to).into());
+            }
+        }
+
+        // So is a whole file of another version
+        if read(&toml.replacen("version = 1", "version = 2", 1)).is_ok() {
+            return Err("A state file of another version was read".into());
+        }
+        Ok(String::new())
+    });
+}
+
+/// Resolve a red frame through each anti-aliasing mode, which should still be red
+fn check_anti_aliasing(check: &mut SelfCheck, gl: &mut glow::Context) {
+    for mode in [
+        AaMode::Off,
+        AaMode::Msaa(4),
+        AaMode::Fxaa,
+        AaMode::MsaaPlusFxaa(4),
+    ] {
+        check.run_gl(gl, "anti-aliasing", &format!("{:?}", mode), |gl| {
+            let output = color_target(gl, "Selfcheck anti-aliasing");
+            let mut anti_aliasing = AntiAliasing::new();
+            let framebuffer = anti_aliasing.prepare(
+                gl,
+                mode,
+                glow::DEPTH24_STENCIL8,
+                TARGET_SIZE,
+                Some(output.framebuffer),
+            );
+            clear(gl, framebuffer, [1., 0., 0., 1.]);
+            if mode != AaMode::Off {
+                anti_aliasing.resolve(gl, false);
+            }
+            let image = read_framebuffer(gl, Some(output.framebuffer), TARGET_SIZE);
+            let used = anti_aliasing.mode();
+            anti_aliasing.delete(gl);
+            output.delete(gl);
+            expect_pixel(&image, 8, 8, [255, 0, 0, 255])?;
+            Ok(format!("{:?}", used))
+        });
+    }
+}
+
+/// The depth view and virtual resolution passes that the loop runs after `draw`
+fn check_post(check: &mut SelfCheck, gl: &mut glow::Context) {
+    // A depth of 0.5 is very close to the near plane with the default clip planes either way
+    // around, so it is almost white, and flat so it has no bends
+    for depth_mode in [DepthMode::Standard, DepthMode::ReverseZ] {
+        for (mode, expected) in [
+            (DepthViewMode::Linear, [255, 255, 255, 255]),
+            (DepthViewMode::Derivative, [64, 64, 64, 255]),
+        ] {
+            let name = format!("depth view {} {}", mode, depth_mode);
+            check.run_gl(gl, "post", &name, |gl| {
+                let output = color_target(gl, "Selfcheck depth view");
+                let mut pass = DepthViewPass::new();
+                let framebuffer = pass.prepare(
+                    gl,
+                    TARGET_SIZE,
+                    depth_mode,
+                    Some(output.framebuffer),
+                    Some(output.framebuffer),
+                );
+                unsafe {
+                    gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+                    gl.clear_depth_f32(0.5);
+                    gl.clear(glow::DEPTH_BUFFER_BIT);
+                    gl.clear_depth_f32(1.);
+                }
+                pass.draw(
+                    gl,
+                    &DepthView {
+                        mode,
+                        ..DepthView::default()
+                    },
+                );
+                let image = read_framebuffer(gl, Some(output.framebuffer), TARGET_SIZE);
+                pass.delete(gl);
+                output.delete(gl);
+                expect_pixel(&image, 8, 8, expected)?;
+                Ok(String::new())
+            });
+        }
+    }
+
+    check.run_gl(gl, "post", "virtual resolution", |gl| {
+        let output = color_target(gl, "Selfcheck virtual resolution");
+        let target = VirtualTarget::new(gl, (4, 4), glow::DEPTH24_STENCIL8);
+        clear(gl, Some(target.framebuffer), [0., 1., 0., 1.]);
+        let viewport = VirtualResolution::new(4, 4).fit(TARGET_SIZE);
+        target.present(gl, Some(output.framebuffer), &viewport, Color::BLACK);
+        let image = read_framebuffer(gl, Some(output.framebuffer), TARGET_SIZE);
+        target.delete(gl);
+        output.delete(gl);
+        expect_pixel(&image, 8, 8, [0, 255, 0, 255])?;
+        Ok(format!("4x4 scaled to {:?}", viewport.rect))
+    });
+}
+
+/// Upload procedural images in each format and read them back
+fn check_textures(check: &mut SelfCheck, gl: &mut glow::Context, features: &Features) {
+    let checker = procedural::checkerboard(16, 16, 4, Color::ORANGE, Color::CORNFLOWER_BLUE);
+    let odd = without_alpha(&procedural::uv_debug(7, 5));
+    let translucent = procedural::from_fn(8, 8, |x, y| {
+        Color::rgba(x as f32 / 7., y as f32 / 7., 0.5, (x + y) as f32 / 14.)
+    });
+
+    let cases = [
+        ("rgba8", checker.clone(), TextureParams::default()),
+        ("rgb8 7x5", odd.clone(), TextureParams::default()),
+        (
+            "srgb8 alpha8",
+            checker.clone(),
+            TextureParams {
+                srgb: true,
+                ..TextureParams::default()
+            },
+        ),
+        (
+            "srgb8",
+            odd,
+            TextureParams {
+                srgb: true,
+                ..TextureParams::default()
+            },
+        ),
+        (
+            "premultiplied",
+            translucent,
+            TextureParams {
+                premultiply: true,
+                ..TextureParams::default()
+            },
+        ),
+        (
+            "box mipmaps",
+            checker.clone(),
+            TextureParams {
+                mipmaps: MipmapMode::Box,
+                ..TextureParams::default()
+            },
+        ),
+        (
+            "lanczos mipmaps",
+            checker.clone(),
+            TextureParams {
+                mipmaps: MipmapMode::Lanczos,
+                ..TextureParams::default()
+            },
+        ),
+        (
+            "no mipmaps",
+            checker,
+            TextureParams {
+                mipmaps: MipmapMode::None,
+                min_filter: glow::LINEAR,
+                ..TextureParams::default()
+            },
+        ),
+    ];
+    for (name, image, params) in cases.iter() {
+        check.run_gl(gl, "textures", name, |gl| {
+            let params = TextureParams {
+                label: Some(format!("Selfcheck {}", name)),
+                ..params.clone()
+            };
+            let texture = create_texture_2d(gl, features, &[image.clone()], &params);
+            let detail = format!(
+                "{}x{}, {} mip levels{}",
+                texture.width,
+                texture.height,
+                texture.mip_levels,
+                if texture.immutable { ", immutable" } else { "" }
+            );
+
+            // sRGB textures are read back decoded, so only the linear ones are compared
+            let result = if params.srgb {
+                Ok(())
+            } else {
+                let mut expected = image.clone();
+                if params.premultiply {
+                    expected.premultiply_alpha();
+                }
+                let actual = read_texture(gl, texture.texture, (image.width, image.height));
+                compare(&opaque(&expected), &actual)
+            };
+            texture.delete(gl);
+            result.map(|()| detail)
+        });
+    }
+}
+
+/// Make a render target in each format that effects draw into, and G-buffers of each layout
+fn check_framebuffers(check: &mut SelfCheck, gl: &mut glow::Context) {
+    let formats = [
+        ("r8", glow::R8),
+        ("rg8", glow::RG8),
+        ("rgba8", glow::RGBA8),
+        ("srgb8 alpha8", glow::SRGB8_ALPHA8),
+        ("r16f", glow::R16F),
+        ("rg16f", glow::RG16F),
+        ("rgba16f", glow::RGBA16F),
+        ("r32f", glow::R32F),
+        ("rg32f", glow::RG32F),
+        ("rgba32f", glow::RGBA32F),
+        ("r11f g11f b10f", glow::R11F_G11F_B10F),
+    ];
+    for (name, format) in formats.iter() {
+        check.run_gl(gl, "framebuffers", name, |gl| {
+            let target = RenderTarget::new(
+                gl,
+                "Selfcheck framebuffer",
+                TargetSize::Window,
+                *format,
+                TARGET_SIZE,
+            );
+            let status = framebuffer_status(gl, target.framebuffer);
+            target.delete(gl);
+            status
+        });
+    }
+
+    for layout in [GBufferLayout::Fat, GBufferLayout::Packed] {
+        for samples in [1, 4] {
+            let name = format!("g-buffer {:?} {}x", layout, samples).to_lowercase();
+            check.run_gl(gl, "framebuffers", &name, |gl| {
+                let gbuffer = GBuffer::with_samples(gl, TARGET_SIZE, layout, samples);
+                let status = framebuffer_status(gl, gbuffer.framebuffer);
+                gbuffer.delete(gl);
+                status
+            });
+        }
+    }
+}
+
+/// Upload each primitive and draw it in white over black, which should cover the middle and not
+/// the corners
+fn check_meshes(check: &mut SelfCheck, gl: &mut glow::Context) {
+    for (name, data) in primitive_meshes() {
+        let transform: Matrix4<f32> = match name.as_str() {
+            "plane" => Matrix4::from_angle_x(Deg(90.)),
+            "cuboid" => Matrix4::from_angle_x(Deg(30.)) * Matrix4::from_angle_y(Deg(45.)),
+            _ => Matrix4::from_scale(1.),
+        };
+        check.run_gl(gl, "meshes", &name, |gl| {
+            let output = color_target(gl, "Selfcheck mesh");
+            let mut program = ShaderProgram::new(gl, SOLID_VERTEX_SRC, SOLID_FRAGMENT_SRC)?;
+            let mesh = Mesh::new(gl, &data);
+            clear(gl, Some(output.framebuffer), [0., 0., 0., 1.]);
+            program.bind(gl);
+            program.set_uniform(gl, "transform", transform);
+            program.set_uniform(gl, "color", [1f32; 4]);
+            mesh.draw(gl);
+            let image = read_framebuffer(gl, Some(output.framebuffer), TARGET_SIZE);
+            mesh.delete(gl);
+            program.delete(gl);
+            output.delete(gl);
+            expect_pixel(&image, 8, 8, [255, 255, 255, 255])?;
+            expect_pixel(&image, 0, 0, [0, 0, 0, 255])?;
+            Ok(format!("{} triangles", data.indices.len() / 3))
+        });
+    }
+}
+
+/// Read a frame back right away, and through a pixel buffer like the GPU readbacks do
+fn check_readback(check: &mut SelfCheck, gl: &mut glow::Context) {
+    check.run_gl(gl, "readback", "read pixels", |gl| {
+        let output = color_target(gl, "Selfcheck readback");
+        clear(gl, Some(output.framebuffer), [0., 1., 0., 1.]);
+        let image = read_framebuffer(gl, Some(output.framebuffer), TARGET_SIZE);
+        output.delete(gl);
+        expect_pixel(&image, 3, 11, [0, 255, 0, 255])?;
+        Ok(String::new())
+    });
+
+    check.run_gl(gl, "readback", "pixel buffer", |gl| {
+        let output = color_target(gl, "Selfcheck readback");
+        clear(gl, Some(output.framebuffer), [0., 1., 0., 1.]);
+        let size = (TARGET_SIZE.0 * TARGET_SIZE.1 * 4) as usize;
+        let mut readback = AsyncReadback::new(gl, size, 1, "Selfcheck readback");
+        let started = readback.read_texture(gl, output.texture, 0, glow::RGBA, glow::UNSIGNED_BYTE);
+        unsafe { gl.finish() };
+        let data = readback.poll(gl);
+        readback.delete(gl);
+        output.delete(gl);
+        match data {
+            _ if !started => Err("The read was skipped".to_owned().into()),
+            None => Err("The read hadn't finished after glFinish".to_owned().into()),
+            Some(data) if data[..4] != [0, 255, 0, 255] => {
+                Err(format!("Read {:?} instead of green", &data[..4]).into())
+            }
+            Some(_) => Ok(format!("{} bytes", size)),
+        }
+    });
+
+    // The clipboard reads frames this way, and wants them with the top row first
+    check.run_gl(gl, "readback", "framebuffer pixel buffer", |gl| {
+        let output = color_target(gl, "Selfcheck readback");
+        clear(gl, Some(output.framebuffer), [0., 1., 0., 1.]);
+        unsafe {
+            gl.enable(glow::SCISSOR_TEST);
+            gl.scissor(0, 0, TARGET_SIZE.0 as i32, 1);
+            gl.clear_color(1., 0., 0., 0.);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            gl.disable(glow::SCISSOR_TEST);
+        }
+        let size = (TARGET_SIZE.0 * TARGET_SIZE.1 * 4) as usize;
+        let mut readback = AsyncReadback::new(gl, size, 1, "Selfcheck readback");
+        let started = readback.read_framebuffer(
+            gl,
+            Some(output.framebuffer),
+            Rect::from_window_size(TARGET_SIZE),
+            glow::RGBA,
+            glow::UNSIGNED_BYTE,
+        );
+        unsafe { gl.finish() };
+        let data = readback.poll(gl);
+        readback.delete(gl);
+        output.delete(gl);
+        let image = match data {
+            _ if !started => return Err("The read was skipped".to_owned().into()),
+            None => return Err("The read hadn't finished after glFinish".to_owned().into()),
+            Some(data) => readback::framebuffer_image(&data, TARGET_SIZE.0),
+        };
+        let last_row = (TARGET_SIZE.1 - 1) as usize * TARGET_SIZE.0 as usize * 4;
+        if image[..4] != [0, 255, 0, 255] || image[last_row..last_row + 4] != [255, 0, 0, 255] {
+            return Err(format!(
+                "Read {:?} at the top and {:?} at the bottom instead of green and opaque red",
+                &image[..4],
+                &image[last_row..last_row + 4]
+            )
+            .into());
+        }
+        Ok(String::new())
+    });
+
+    // The texture viewer reads texels by drawing them into a float framebuffer first
+    check.run_gl(gl, "readback", "texture viewer pick", |gl| {
+        let output = color_target(gl, "Selfcheck texture viewer");
+        clear(gl, Some(output.framebuffer), [0., 1., 0., 1.]);
+        unsafe {
+            gl.enable(glow::SCISSOR_TEST);
+            gl.scissor(3, 5, 1, 1);
+            gl.clear_color(1., 0., 0., 0.);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            gl.disable(glow::SCISSOR_TEST);
+        }
+        let texture = ViewableTexture {
+            id: output.texture,
+            label: "Selfcheck texture viewer".into(),
+            target: glow::TEXTURE_2D,
+        };
+        let mut pass = TextureViewerPass::new(gl);
+        let started = pass.pick(gl, &texture, 0, PickAt::Texel(3, 5));
+        unsafe { gl.finish() };
+        let pick = pass.poll(gl);
+        pass.delete(gl);
+        output.delete(gl);
+        let pick = match (started?, pick) {
+            (false, _) => return Err("The read was skipped".to_owned().into()),
+            (true, None) => {
+                return Err("The read hadn't finished after glFinish".to_owned().into())
+            }
+            (true, Some(pick)) => pick,
+        };
+        if pick.texel != (3, 5) || pick.size != TARGET_SIZE || pick.value != [1., 0., 0., 0.] {
+            return Err(format!("Read {}, instead of 1 0 0 0 at (3, 5)", pick).into());
+        }
+        Ok(String::new())
+    });
+
+    // Like reading a depth of a point shadow's cube map, whose faces are all at different depths
+    check.run_gl(gl, "readback", "texture viewer cube map pick", |gl| {
+        let id = unsafe {
+            let id = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_CUBE_MAP, Some(id));
+            for face in 0..6 {
+                let depths = [face as f32 / 8.; 16]
+                    .iter()
+                    .flat_map(|depth| depth.to_ne_bytes())
+                    .collect::<Vec<u8>>();
+                gl.tex_image_2d(
+                    glow::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                    0,
+                    glow::DEPTH_COMPONENT24 as i32,
+                    4,
+                    4,
+                    0,
+                    glow::DEPTH_COMPONENT,
+                    glow::FLOAT,
+                    Some(&depths),
+                );
+            }
+            for parameter in [glow::TEXTURE_MIN_FILTER, glow::TEXTURE_MAG_FILTER] {
+                gl.tex_parameter_i32(glow::TEXTURE_CUBE_MAP, parameter, glow::NEAREST as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_CUBE_MAP, None);
+            id
+        };
+        let texture = ViewableTexture {
+            id,
+            label: "Selfcheck cube map".into(),
+            target: glow::TEXTURE_CUBE_MAP,
+        };
+        let mut pass = TextureViewerPass::new(gl);
+        let started = pass.pick(gl, &texture, 5, PickAt::Texel(2, 1));
+        unsafe { gl.finish() };
+        let pick = pass.poll(gl);
+        pass.delete(gl);
+        unsafe { gl.delete_texture(id) };
+        let pick = match (started?, pick) {
+            (false, _) => return Err("The read was skipped".to_owned().into()),
+            (true, None) => {
+                return Err("The read hadn't finished after glFinish".to_owned().into())
+            }
+            (true, Some(pick)) => pick,
+        };
+        if pick.texel != (2, 1) || pick.size != (4, 4) || (pick.value[0] - 0.625).abs() > 1e-4 {
+            return Err(format!("Read {}, instead of a depth of 0.625 at (2, 1)", pick).into());
+        }
+        Ok(String::new())
+    });
+}
+
+/// A tiny frame that is drawn and compared against what it should look like, pixel for pixel
+struct Golden {
+    name: &'static str,
+    /// What the frame should look like, from the top row down: `.` for black, `r`, `g`, and `b`
+    /// for red, green, and blue, and `h` for half gray
+    rows: [&'static str; 8],
+    draw: fn(&mut glow::Context, &mut ShaderProgram, &Mesh),
+}
+
+/// Change a uniform and a uniform block binding behind a program's back, like a context that lost
+/// its state, and make sure that the program sets them again after `invalidate`
+fn check_uniform_cache(check: &mut SelfCheck, gl: &mut glow::Context) {
+    check.run_gl(gl, "shaders", "uniform cache invalidate", |gl| {
+        let size = (8, 8);
+        let output = RenderTarget::new(
+            gl,
+            "Selfcheck uniform cache",
+            TargetSize::Fixed(size.0, size.1),
+            glow::RGBA8,
+            size,
+        );
+        let quad = Mesh::new(gl, &primitives::plane(1., 1., 1.));
+        let mut solid = ShaderProgram::new(gl, SOLID_VERTEX_SRC, SOLID_FRAGMENT_SRC)?;
+        let mut tint = ShaderProgram::new(gl, SOLID_VERTEX_SRC, TINT_BLOCK_FRAGMENT_SRC)?;
+        let (red, green) = ([1., 0., 0., 1.], [0., 1., 0., 1.]);
+        let mut buffers = Vec::new();
+        for (binding, color) in [(1, red), (2, green)] {
+            unsafe {
+                let buffer = gl.create_buffer().unwrap();
+                gl.bind_buffer(glow::UNIFORM_BUFFER, Some(buffer));
+                gl.buffer_data_u8_slice(
+                    glow::UNIFORM_BUFFER,
+                    color.as_mem_bytes(),
+                    glow::STATIC_DRAW,
+                );
+                gl.bind_buffer_base(glow::UNIFORM_BUFFER, binding, Some(buffer));
+                buffers.push(buffer);
+            }
+        }
+        unsafe { gl.viewport(0, 0, size.0 as i32, size.1 as i32) };
+
+        // Fill the target with the color that a program draws, and read back the middle pixel
+        let draw = |gl: &mut glow::Context, program: &mut ShaderProgram| {
+            clear(gl, Some(output.framebuffer), [0., 0., 0., 1.]);
+            draw_quad(gl, program, &quad, Matrix4::from_scale(2.), red);
+            let image = read_framebuffer(gl, Some(output.framebuffer), size);
+            let middle = (size.0 as usize * 4 + 4) * 4;
+            let pixel = &image.pixels[middle..middle + 4];
+            match (pixel[0], pixel[1], pixel[2]) {
+                (255, 0, 0) => "red",
+                (0, 255, 0) => "green",
+                _ => "something else",
+            }
+        };
+        let mut results = Vec::new();
+
+        // The uniform is set through the program, then changed with a raw GL call that the cache
+        // doesn't see
+        draw(gl, &mut solid);
+        let location = solid.uniform_location(gl, "color");
+        unsafe { gl.uniform_4_f32(location.as_ref(), 0., 1., 0., 1.) };
+        results.push(("uniform", draw(gl, &mut solid), "green"));
+        solid.invalidate();
+        results.push(("uniform after invalidate", draw(gl, &mut solid), "red"));
+
+        // The same for the binding point of a uniform block
+        if !tint.bind_uniform_block(gl, "Tint", 1) {
+            return Err("The `Tint` block wasn't found".to_string().into());
+        }
+        draw(gl, &mut tint);
+        unsafe {
+            let index = gl.get_uniform_block_index(tint.id, "Tint").unwrap();
+            gl.uniform_block_binding(tint.id, index, 2);
+        }
+        tint.bind_uniform_block(gl, "Tint", 1);
+        results.push(("block", draw(gl, &mut tint), "green"));
+        tint.invalidate();
+        tint.bind_uniform_block(gl, "Tint", 1);
+        results.push(("block after invalidate", draw(gl, &mut tint), "red"));
+
+        unsafe {
+            for buffer in buffers {
+                gl.delete_buffer(buffer);
+            }
+            gl.bind_buffer(glow::UNIFORM_BUFFER, None);
+        }
+        solid.delete(gl);
+        tint.delete(gl);
+        quad.delete(gl);
+        output.delete(gl);
+
+        // The cached draws show that the raw calls did change the state, so the ones after
+        // `invalidate` only come out red if the program set it again
+        for (what, color, expected) in results {
+            if color != expected {
+                return Err(format!("The {} drew {} instead of {}", what, color, expected).into());
+            }
+        }
+        Ok(String::new())
+    });
+}
+
+/// Draw a unit square in the XY plane, moved and scaled by `transform`
+fn draw_quad(
+    gl: &mut glow::Context,
+    program: &mut ShaderProgram,
+    quad: &Mesh,
+    transform: Matrix4<f32>,
+    color: [f32; 4],
+) {
+    program.bind(gl);
+    program.set_uniform(gl, "transform", transform * Matrix4::from_angle_x(Deg(90.)));
+    program.set_uniform(gl, "color", color);
+    quad.draw(gl);
+}
+
+const GOLDENS: [Golden; 4] = [
+    Golden {
+        name: "clear",
+        rows: ["bbbbbbbb"; 8],
+        draw: |gl, _, _| unsafe {
+            gl.clear_color(0., 0., 1., 1.);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+        },
+    },
+    Golden {
+        name: "quad",
+        rows: [
+            "rrrr....", "rrrr....", "rrrr....", "rrrr....", "........", "........", "........",
+            "........",
+        ],
+        draw: |gl, program, quad| {
+            let transform = Matrix4::from_translation(Vector3::new(-0.5, 0.5, 0.));
+            draw_quad(gl, program, quad, transform, [1., 0., 0., 1.]);
+        },
+    },
+    Golden {
+        name: "scissor",
+        rows: [
+            "........", "........", "........", "........", "........", "........", "....gggg",
+            "....gggg",
+        ],
+        draw: |gl, _, _| unsafe {
+            gl.enable(glow::SCISSOR_TEST);
+            gl.scissor(4, 0, 4, 2);
+            gl.clear_color(0., 1., 0., 1.);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            gl.disable(glow::SCISSOR_TEST);
+        },
+    },
+    Golden {
+        name: "blend",
+        rows: ["....hhhh"; 8],
+        draw: |gl, program, quad| {
+            unsafe {
+                gl.enable(glow::BLEND);
+                gl.blend_func(glow::SRC_ALPHA, glow::ONE_MINUS_SRC_ALPHA);
+            }
+            let transform = Matrix4::from_translation(Vector3::new(0.5, 0., 0.))
+                * Matrix4::from_nonuniform_scale(1., 2., 1.);
+            draw_quad(gl, program, quad, transform, [1., 1., 1., 0.5]);
+            unsafe { gl.disable(glow::BLEND) };
+        },
+    },
+];
+
+impl Golden {
+    /// The image the frame should match, starting at the bottom left like read back images
+    fn expected(&self) -> ImageData {
+        golden_image(&self.rows)
+    }
+}
+
+/// The image of the rows of a golden frame, starting at the bottom left like read back images
+fn golden_image(rows: &[&str; 8]) -> ImageData {
+    let mut pixels = Vec::with_capacity(8 * 8 * 4);
+    for row in rows.iter().rev() {
+        for pixel in row.chars() {
+            pixels.extend_from_slice(&match pixel {
+                'r' => [255, 0, 0, 255],
+                'g' => [0, 255, 0, 255],
+                'b' => [0, 0, 255, 255],
+                'h' => [128, 128, 128, 255],
+                _ => [0, 0, 0, 255],
+            });
+        }
+    }
+    ImageData {
+        width: 8,
+        height: 8,
+        format: glow::RGBA,
+        alpha: AlphaMode::Opaque,
+        pixels,
+    }
+}
+
+/// Draw each golden frame and compare it with the image it should make, saving both and a
+/// heatmap of where they differ when it doesn't match
+fn check_goldens(check: &mut SelfCheck, gl: &mut glow::Context) {
+    for golden in GOLDENS.iter() {
+        check.run_gl(gl, "goldens", golden.name, |gl| {
+            let size = (8, 8);
+            let output = RenderTarget::new(
+                gl,
+                "Selfcheck golden",
+                TargetSize::Fixed(size.0, size.1),
+                glow::RGBA8,
+                size,
+            );
+            let mut program = ShaderProgram::new(gl, SOLID_VERTEX_SRC, SOLID_FRAGMENT_SRC)?;
+            let quad = Mesh::new(gl, &primitives::plane(1., 1., 1.));
+            clear(gl, Some(output.framebuffer), [0., 0., 0., 1.]);
+            unsafe { gl.viewport(0, 0, size.0 as i32, size.1 as i32) };
+            (golden.draw)(gl, &mut program, &quad);
+            let actual = read_framebuffer(gl, Some(output.framebuffer), size);
+            quad.delete(gl);
+            program.delete(gl);
+            output.delete(gl);
+
+            compare_golden(golden.name, golden.expected(), actual)
+        });
+    }
+}
+
+/// Compare a frame with the golden image it should match, saving both and a heatmap of where
+/// they differ when it doesn't
+fn compare_golden(
+    name: &str,
+    expected: ImageData,
+    actual: ImageData,
+) -> Result<String, CheckError> {
+    let report = image_diff(&expected, &actual);
+    if report.within(PIXEL_TOLERANCE as f32 / 255.) {
+        return Ok(String::new());
+    }
+    let summary = report.summary();
+    let capture = AbCapture {
+        before: expected,
+        after: actual,
+        report,
+    };
+    let saved = match capture.save(&format!("selfcheck-{}", name)) {
+        Ok(paths) => format!("saved {}", paths.join(", ")),
+        Err(error) => format!("couldn't save the images: {}", error),
+    };
+    Err(format!("{}\n{}", summary, saved).into())
+}
+
+/// Run a handler in a hidden window through the demo loop until it asks to close
+///
+/// Skipped when there is no display to open the window on.
+fn run_headless_window(
+    title: &str,
+    replay_input: Option<PathBuf>,
+    factory: HandlerFactory,
+) -> Result<(), CheckError> {
+    if cfg!(target_os = "linux")
+        && std::env::var_os("DISPLAY").is_none()
+        && std::env::var_os("WAYLAND_DISPLAY").is_none()
+    {
+        return Err(CheckError::Skipped(
+            "No display to open a window on".to_owned(),
+        ));
+    }
+    let window = WindowConfig {
+        title: title.to_owned(),
+        width: 64,
+        height: 64,
+        resizable: false,
+        headless: true,
+        replay_input,
+        ..WindowConfig::default()
+    };
+    with_windows_and_config(Config::default(), vec![(window, factory)]);
+    Ok(())
+}
+
+/// A handler that loses its surface and then its context, logging what the loop calls
+struct RecoveryHandler {
+    log: Rc<RefCell<Vec<String>>>,
+    frame: u32,
+}
+
+impl RenderHandler for RecoveryHandler {
+    fn init(_gl: &mut glow::Context, _ctx: &mut AppContext) -> Self {
+        unreachable!("The check makes the handler with its log")
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.frame += 1;
+        clear(gl, ctx.surface_framebuffer(), [0., 1., 0., 1.]);
+        let inits = self
+            .log
+            .borrow()
+            .iter()
+            .filter(|event| *event == "init")
+            .count();
+        if inits == 1 && self.frame == 1 {
+            ctx.simulate_surface_loss();
+            return;
+        }
+
+        // Check that the frame after recovering renders again
+        let image = read_framebuffer(gl, ctx.surface_framebuffer(), ctx.window_size());
+        let pixel = &image.pixels[..4];
+        self.log.borrow_mut().push(if pixel == [0, 255, 0, 255] {
+            "green".to_owned()
+        } else {
+            format!("{:?}", pixel)
+        });
+        if inits == 1 {
+            ctx.simulate_context_reset();
+        } else {
+            ctx.request_close();
+        }
+    }
+
+    fn exit(&mut self, _gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.log.borrow_mut().push("exit".to_owned());
+    }
+
+    fn device_lost(&mut self, _gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.log.borrow_mut().push("lost".to_owned());
+    }
+
+    fn device_restored(&mut self, _gl: &mut glow::Context, _ctx: &mut AppContext) {
+        self.log.borrow_mut().push("restored".to_owned());
+    }
+}
+
+/// Losing the window surface and resetting the context in the demo loop, which should tell the
+/// handler and draw again afterwards
+fn check_device_recovery(check: &mut SelfCheck) {
+    check.run("window", "device recovery", || {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let factory_log = log.clone();
+        run_headless_window(
+            "Selfcheck device recovery",
+            None,
+            Box::new(move |_, _| {
+                factory_log.borrow_mut().push("init".to_owned());
+                Box::new(RecoveryHandler {
+                    log: factory_log.clone(),
+                    frame: 0,
+                })
+            }),
+        )?;
+
+        let log = log.borrow().join(", ");
+        let expected = "init, lost, restored, green, lost, exit, init, green, exit";
+        if log == expected {
+            Ok(String::new())
+        } else {
+            Err(format!("Expected {}\nbut the loop called {}", expected, log).into())
+        }
+    });
+}
+
+/// A buffer that is never deleted, which the leak report of its context should list with its
+/// label and where it was created
+fn check_leak_report(check: &mut SelfCheck, gl: &mut glow::Context, key: u64) {
+    check.run_gl(gl, "resources", "leak report", |gl| {
+        // Leak the buffer from a context of its own, so that the checks' context stays clean
+        let leaky_key = resources::start_context();
+        resources::make_current(leaky_key);
+        let buffer = unsafe { gl.create_buffer()? };
+        unsafe {
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(buffer));
+            gl.buffer_data_size(glow::ARRAY_BUFFER, 64, glow::STATIC_DRAW);
+            gl.bind_buffer(glow::ARRAY_BUFFER, None);
+        }
+        resources::track_sized(ResourceKind::Buffer, buffer, "Selfcheck leaked buffer", 64);
+        let tracker = resources::finish_context(leaky_key);
+        resources::make_current(key);
+        unsafe { gl.delete_buffer(buffer) };
+
+        let tracker =
+            tracker.ok_or_else(|| "The leaked buffer's context wasn't tracked".to_string())?;
+        let report = tracker.report();
+        let listing = format!("Buffer {}: Selfcheck leaked buffer", buffer);
+        if !report.lines().any(|line| line.trim() == listing) {
+            return Err(format!("The report doesn't list the buffer\n{}", report).into());
+        }
+        if !resources::ENABLED {
+            return Ok("No backtrace without the resource_tracking feature".into());
+        }
+        let captured = tracker
+            .live()
+            .all(|resource| resource.backtrace.status() == BacktraceStatus::Captured);
+        let has_frames = report.lines().any(|line| line.starts_with("        "));
+        if captured && has_frames {
+            Ok(String::new())
+        } else {
+            Err(format!("The report has no backtrace\n{}", report).into())
+        }
+    });
+}
+
+/// Write `PAINT_FRAMES` as an input recording
+fn write_paint_recording(path: &Path) -> std::io::Result<()> {
+    let mut recorder = InputRecorder::create(path)?;
+    let mut input = Input::default();
+    let mut held = None;
+    for &((x, y), button) in &PAINT_FRAMES {
+        if button != held {
+            if let Some(released) = held {
+                input.set_mouse_button(released, false);
+            }
+            if let Some(pressed) = button {
+                input.set_mouse_button(pressed, true);
+            }
+            held = button;
+        }
+        input.set_cursor_position(Some((x as f64, y as f64)));
+        recorder.record(PAINT_FRAME_TIME, &input)?;
+        input.end_frame();
+    }
+    recorder.flush()
+}
+
+/// A handler that paints on a canvas with the mouse, keeping what it painted when it exits
+struct PaintHandler {
+    canvas: RenderTarget,
+    elapsed: Duration,
+    frames: usize,
+    /// The canvas and the number of frames drawn, once the window has closed
+    result: Rc<RefCell<Option<(ImageData, usize)>>>,
+}
+
+impl RenderHandler for PaintHandler {
+    fn init(_gl: &mut glow::Context, _ctx: &mut AppContext) -> Self {
+        unreachable!("The check makes the handler with its canvas")
+    }
+
+    fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.frames += 1;
+        self.elapsed += ctx.timing.delta_duration();
+        // Without the recording the window would never close on its own
+        if self.frames > PAINT_FRAMES.len() {
+            ctx.request_close();
+        }
+
+        let color = if ctx.input.is_mouse_pressed(MouseButton::Left) {
+            [1., 0., 0., 1.]
+        } else if ctx.input.is_mouse_pressed(MouseButton::Right) {
+            [0., 1., 0., 1.]
+        } else {
+            return;
+        };
+        let (x, y) = match ctx.input.cursor_position() {
+            Some(position) if self.elapsed <= PAINT_TIME => position,
+            _ => return,
+        };
+        // The cursor goes down from the top of the window and the scissor box up from the bottom
+        let [r, g, b, a] = color;
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(self.canvas.framebuffer));
+            gl.enable(glow::SCISSOR_TEST);
+            gl.scissor(x as i32, PAINT_CANVAS_SIZE.1 as i32 - 1 - y as i32, 1, 1);
+            gl.clear_color(r, g, b, a);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.bind_framebuffer(glow::FRAMEBUFFER, ctx.surface_framebuffer());
+        }
+    }
+
+    fn exit(&mut self, gl: &mut glow::Context, _ctx: &mut AppContext) {
+        let canvas = read_framebuffer(gl, Some(self.canvas.framebuffer), PAINT_CANVAS_SIZE);
+        self.canvas.delete(gl);
+        *self.result.borrow_mut() = Some((canvas, self.frames));
+    }
+}
+
+/// Play back a recording of painting through the demo loop, and compare the canvas with what was
+/// painted while recording
+fn check_input_replay(check: &mut SelfCheck) {
+    check.run("input replay", "paint", || {
+        let path = std::env::temp_dir().join(format!(
+            "me_learning_opengl-paint-{}.input",
+            std::process::id()
+        ));
+        write_paint_recording(&path).map_err(|error| error.to_string())?;
+
+        let result = Rc::new(RefCell::new(None));
+        let factory_result = result.clone();
+        let ran = run_headless_window(
+            "Selfcheck input replay",
+            Some(path.clone()),
+            Box::new(move |gl, ctx| {
+                let (width, height) = PAINT_CANVAS_SIZE;
+                let canvas = RenderTarget::new(
+                    gl,
+                    "Selfcheck paint canvas",
+                    TargetSize::Fixed(width, height),
+                    glow::RGBA8,
+                    PAINT_CANVAS_SIZE,
+                );
+                clear(gl, Some(canvas.framebuffer), [0., 0., 0., 1.]);
+                unsafe { gl.bind_framebuffer(glow::FRAMEBUFFER, ctx.surface_framebuffer()) };
+                Box::new(PaintHandler {
+                    canvas,
+                    elapsed: Duration::default(),
+                    frames: 0,
+                    result: factory_result.clone(),
+                })
+            }),
+        );
+        std::fs::remove_file(&path).ok();
+        ran?;
+
+        let (actual, frames) = result
+            .borrow_mut()
+            .take()
+            .ok_or_else(|| "The window closed without painting".to_string())?;
+        if frames != PAINT_FRAMES.len() {
+            return Err(format!(
+                "{} frames were drawn for the {} recorded",
+                frames,
+                PAINT_FRAMES.len()
+            )
+            .into());
+        }
+        let expected = golden_image(&[
+            "........", ".rrrrrr.", "........", "...g....", "...g....", "........", "........",
+            "........",
+        ]);
+        compare_golden("input-replay", expected, actual)
+    });
+}
+
+/// An RGBA8 target of `TARGET_SIZE`
+fn color_target(gl: &mut glow::Context, label: &str) -> RenderTarget {
+    RenderTarget::new(gl, label, TargetSize::Window, glow::RGBA8, TARGET_SIZE)
+}
+
+/// Clear the color of a framebuffer, leaving it bound
+fn clear(gl: &mut glow::Context, framebuffer: Option<u32>, [r, g, b, a]: [f32; 4]) {
+    unsafe {
+        gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+        gl.clear_color(r, g, b, a);
+        gl.clear(glow::COLOR_BUFFER_BIT);
+    }
+}
+
+/// Whether a framebuffer is complete, with the status as the failure if it isn't
+fn framebuffer_status(gl: &mut glow::Context, framebuffer: u32) -> Result<String, CheckError> {
+    let status = unsafe {
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        gl.check_framebuffer_status(glow::FRAMEBUFFER)
+    };
+    if status == glow::FRAMEBUFFER_COMPLETE {
+        Ok(String::new())
+    } else {
+        Err(format!("The framebuffer is incomplete, status {:#x}", status).into())
+    }
+}
+
+/// Read the first mip level of a texture by attaching it to a framebuffer
+fn read_texture(gl: &mut glow::Context, texture: u32, size: (u32, u32)) -> ImageData {
+    unsafe {
+        let framebuffer = gl.create_framebuffer().unwrap();
+        gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+        gl.framebuffer_texture_2d(
+            glow::FRAMEBUFFER,
+            glow::COLOR_ATTACHMENT0,
+            glow::TEXTURE_2D,
+            Some(texture),
+            0,
+        );
+        let image = read_framebuffer(gl, Some(framebuffer), size);
+        gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+        gl.delete_framebuffer(framebuffer);
+        image
+    }
+}
+
+/// Fail if two images of the same size differ by more than `PIXEL_TOLERANCE`
+fn compare(expected: &ImageData, actual: &ImageData) -> Result<(), CheckError> {
+    let report = image_diff(expected, actual);
+    if report.within(PIXEL_TOLERANCE as f32 / 255.) {
+        Ok(())
+    } else {
+        Err(format!("The texture doesn't match its image: {}", report.summary()).into())
+    }
+}
+
+/// Fail if a pixel of a read back image isn't close to what was expected
+fn expect_pixel(image: &ImageData, x: u32, y: u32, expected: [u8; 4]) -> Result<(), CheckError> {
+    let channels = image.channels();
+    let start = (y * image.width + x) as usize * channels;
+    let mut actual = [255; 4];
+    actual[..channels].copy_from_slice(&image.pixels[start..start + channels]);
+    let close = actual
+        .iter()
+        .zip(&expected)
+        .all(|(&a, &b)| (a as i32 - b as i32).abs() <= PIXEL_TOLERANCE as i32);
+    if close {
+        Ok(())
+    } else {
+        Err(format!(
+            "Pixel ( {}, {} ) is {:?} instead of {:?}",
+            x, y, actual, expected
+        )
+        .into())
+    }
+}
+
+/// An RGB copy of an RGBA image, dropping its alpha
+fn without_alpha(image: &ImageData) -> ImageData {
+    ImageData {
+        format: glow::RGB,
+        alpha: AlphaMode::Opaque,
+        pixels: image
+            .pixels
+            .chunks_exact(4)
+            .flat_map(|pixel| pixel[..3].to_vec())
+            .collect(),
+        ..image.clone()
+    }
+}
+
+/// An image with its alpha made opaque, to compare with `read_framebuffer`'s images
+fn opaque(image: &ImageData) -> ImageData {
+    let mut image = image.clone();
+    if image.format == glow::RGBA {
+        for alpha in image.pixels.iter_mut().skip(3).step_by(4) {
+            *alpha = 255;
+        }
+    }
+    image
+}
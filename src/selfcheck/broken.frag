@@ -0,0 +1,7 @@
+#version 330 core
+out vec4 FragColor;
+
+void main() {
+    // Nothing declares this name
+    FragColor = undeclaredColor;
+}
@@ -0,0 +1,15 @@
+#version 330 core
+// Where the fragment is in the world, which is the same for the whole plane that the check draws
+uniform vec3 point;
+
+out vec4 FragColor;
+
+void main()
+{
+    uvec2 range = clusterRange(clusterIndex(point));
+    vec3 color = vec3(0.0);
+    for (uint i = 0u; i < range.y; i++) {
+        color += clusterLight(range, i).color;
+    }
+    FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,11 @@
+#version 330 core
+layout(std140) uniform Tint {
+    vec4 tint;
+};
+
+out vec4 FragColor;
+
+void main()
+{
+    FragColor = tint;
+}
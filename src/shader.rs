@@ -0,0 +1,777 @@
+use std::{
+    cell::Cell,
+    collections::HashMap,
+    fmt::{self, Write as _},
+};
+
+use cgmath::{Matrix4, Vector2, Vector3, Vector4};
+use glow::HasContext;
+
+use crate::{
+    features::Features,
+    handle::Handle,
+    program_cache::ProgramBinaryCache,
+    resources::{self, ResourceKind},
+};
+
+/// The value of a uniform that `ShaderProgram` can remember
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UniformValue {
+    F32(f32),
+    Vec2([f32; 2]),
+    Vec3([f32; 3]),
+    Vec4([f32; 4]),
+    I32(i32),
+    /// A column major 4x4 matrix
+    Mat4([f32; 16]),
+}
+
+impl UniformValue {
+    /// Whether two values are the same type and every float is within `epsilon` of the other's
+    pub fn approx_eq(&self, other: &Self, epsilon: f32) -> bool {
+        let floats_eq =
+            |a: &[f32], b: &[f32]| a.iter().zip(b).all(|(a, b)| (a - b).abs() <= epsilon);
+        match (self, other) {
+            (UniformValue::F32(a), UniformValue::F32(b)) => floats_eq(&[*a], &[*b]),
+            (UniformValue::Vec2(a), UniformValue::Vec2(b)) => floats_eq(a, b),
+            (UniformValue::Vec3(a), UniformValue::Vec3(b)) => floats_eq(a, b),
+            (UniformValue::Vec4(a), UniformValue::Vec4(b)) => floats_eq(a, b),
+            (UniformValue::I32(a), UniformValue::I32(b)) => a == b,
+            (UniformValue::Mat4(a), UniformValue::Mat4(b)) => floats_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl From<f32> for UniformValue {
+    fn from(value: f32) -> Self {
+        UniformValue::F32(value)
+    }
+}
+
+impl From<i32> for UniformValue {
+    fn from(value: i32) -> Self {
+        UniformValue::I32(value)
+    }
+}
+
+impl From<[f32; 2]> for UniformValue {
+    fn from(value: [f32; 2]) -> Self {
+        UniformValue::Vec2(value)
+    }
+}
+
+impl From<[f32; 3]> for UniformValue {
+    fn from(value: [f32; 3]) -> Self {
+        UniformValue::Vec3(value)
+    }
+}
+
+impl From<[f32; 4]> for UniformValue {
+    fn from(value: [f32; 4]) -> Self {
+        UniformValue::Vec4(value)
+    }
+}
+
+impl From<[f32; 16]> for UniformValue {
+    fn from(value: [f32; 16]) -> Self {
+        UniformValue::Mat4(value)
+    }
+}
+
+impl From<Vector2<f32>> for UniformValue {
+    fn from(value: Vector2<f32>) -> Self {
+        UniformValue::Vec2(value.into())
+    }
+}
+
+impl From<Vector3<f32>> for UniformValue {
+    fn from(value: Vector3<f32>) -> Self {
+        UniformValue::Vec3(value.into())
+    }
+}
+
+impl From<Vector4<f32>> for UniformValue {
+    fn from(value: Vector4<f32>) -> Self {
+        UniformValue::Vec4(value.into())
+    }
+}
+
+impl From<Matrix4<f32>> for UniformValue {
+    fn from(value: Matrix4<f32>) -> Self {
+        let value: &[f32; 16] = value.as_ref();
+        UniformValue::Mat4(*value)
+    }
+}
+
+/// How many uniform uploads were sent to GL and how many were skipped because the value hadn't
+/// changed
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct UniformStats {
+    pub issued: u64,
+    pub skipped: u64,
+}
+
+thread_local! {
+    /// The uniform uploads of the frame that is being drawn on this thread
+    static FRAME_STATS: Cell<UniformStats> = const {
+        Cell::new(UniformStats {
+            issued: 0,
+            skipped: 0,
+        })
+    };
+}
+
+/// The uniform uploads made through `ShaderProgram` so far in the current frame
+pub fn frame_uniform_stats() -> UniformStats {
+    FRAME_STATS.with(Cell::get)
+}
+
+/// Start counting the uniform uploads of a new frame
+pub(crate) fn reset_frame_uniform_stats() {
+    FRAME_STATS.with(|stats| stats.set(UniformStats::default()));
+}
+
+/// A uniform's location, and the value it was last set to if it is known
+#[derive(Clone, Copy, Debug)]
+struct CachedUniform {
+    location: Option<u32>,
+    value: Option<UniformValue>,
+}
+
+/// A uniform block's index, and the binding point it was pointed at if it is known
+#[derive(Clone, Copy, Debug)]
+struct UniformBlock {
+    index: u32,
+    binding: Option<u32>,
+}
+
+/// A linked shader program that remembers its uniform locations and values
+///
+/// Setting a uniform to the value it already has skips the GL call, and so does pointing a
+/// uniform block at the binding point it already uses. The cache assumes that the program's
+/// uniforms and blocks are only set through it, so call `invalidate` after setting them some
+/// other way, like with raw `gl.uniform_*` or `gl.uniform_block_binding` calls on `id`.
+#[derive(Debug)]
+pub struct ShaderProgram {
+    pub id: u32,
+    uniforms: HashMap<String, CachedUniform>,
+    /// The uniform blocks that have been looked up, or `None` for the ones the program doesn't
+    /// declare
+    uniform_blocks: HashMap<String, Option<UniformBlock>>,
+    /// How far apart two floats can be and still count as the same value, 0 by default
+    float_epsilon: f32,
+    /// Whether or not to skip uploads of unchanged values
+    caching: bool,
+    /// Whether or not the program was loaded from a binary cache
+    from_cache: bool,
+}
+
+impl ShaderProgram {
+    /// Compile and link a program from the sources of a vertex and a fragment shader, returning
+    /// the info log if either fails
+    ///
+    /// The sources are rewritten for the current context's `ShaderTarget` first.
+    pub fn new(
+        gl: &mut glow::Context,
+        vertex_source: &str,
+        fragment_source: &str,
+    ) -> Result<Self, String> {
+        Self::with_defines(gl, vertex_source, fragment_source, &[])
+    }
+
+    /// Compile and link a program like `new`, with a `#define` added to both shaders for each of
+    /// the given names
+    pub fn with_defines(
+        gl: &mut glow::Context,
+        vertex_source: &str,
+        fragment_source: &str,
+        defines: &[&str],
+    ) -> Result<Self, String> {
+        Self::build(gl, vertex_source, fragment_source, defines, None)
+    }
+
+    /// Create a program like `with_defines`, loading it from a binary cache if it is there and
+    /// saving it to the cache after compiling it if it isn't
+    pub fn with_binary_cache(
+        gl: &mut glow::Context,
+        vertex_source: &str,
+        fragment_source: &str,
+        defines: &[&str],
+        cache: &mut ProgramBinaryCache,
+    ) -> Result<Self, String> {
+        Self::build(gl, vertex_source, fragment_source, defines, Some(cache))
+    }
+
+    /// Load or compile a program
+    fn build(
+        gl: &mut glow::Context,
+        vertex_source: &str,
+        fragment_source: &str,
+        defines: &[&str],
+        mut cache: Option<&mut ProgramBinaryCache>,
+    ) -> Result<Self, String> {
+        let target = current_target();
+        let vertex_source = target.rewrite(&inject_defines(vertex_source, defines))?;
+        let fragment_source = target.rewrite(&inject_defines(fragment_source, defines))?;
+
+        // Skip compiling if the program is in the cache
+        if let Some(cache) = &mut cache {
+            if let Some(program) = cache.load(gl, &vertex_source, &fragment_source) {
+                return Ok(Self::from_linked(program, true));
+            }
+        }
+
+        let program = Self::link(
+            gl,
+            &[
+                (glow::VERTEX_SHADER, &vertex_source),
+                (glow::FRAGMENT_SHADER, &fragment_source),
+            ],
+            &[],
+            |program| {
+                if let Some(cache) = &cache {
+                    cache.prepare(program);
+                }
+            },
+        )?;
+
+        if let Some(cache) = cache {
+            cache.store(&vertex_source, &fragment_source, program);
+        }
+        Ok(Self::from_linked(program, false))
+    }
+
+    /// Compile and link a program from the source of a compute shader, returning the info log if
+    /// it fails. The context has to support compute shaders, see `Features::compute`.
+    pub fn compute(gl: &mut glow::Context, source: &str) -> Result<Self, String> {
+        let source = current_target().rewrite(source)?;
+        let program = Self::link(gl, &[(glow::COMPUTE_SHADER, &source)], &[], |_| ())?;
+        Ok(Self::from_linked(program, false))
+    }
+
+    /// Compile and link a program with only a vertex shader, whose `varyings` outputs are captured
+    /// with transform feedback, interleaved in the order given, returning the info log if it fails
+    ///
+    /// Draw with `RASTERIZER_DISCARD` enabled, since there is no fragment shader.
+    pub fn transform_feedback(
+        gl: &mut glow::Context,
+        vertex_source: &str,
+        varyings: &[&str],
+    ) -> Result<Self, String> {
+        let vertex_source = current_target().rewrite(vertex_source)?;
+        let program = Self::link(
+            gl,
+            &[(glow::VERTEX_SHADER, &vertex_source)],
+            varyings,
+            |_| (),
+        )?;
+        Ok(Self::from_linked(program, false))
+    }
+
+    /// Compile the shaders of each stage, whose sources have been rewritten for the current
+    /// target, and link them into a program, capturing `varyings` with transform feedback if there
+    /// are any. `before_link` is called with the program right before it is linked.
+    fn link<F: FnOnce(u32)>(
+        gl: &mut glow::Context,
+        stages: &[(u32, &str)],
+        varyings: &[&str],
+        before_link: F,
+    ) -> Result<u32, String> {
+        unsafe {
+            // Compile the shaders
+            let mut shaders = Vec::new();
+            for &(kind, source) in stages {
+                let shader = gl.create_shader(kind).unwrap();
+                gl.shader_source(shader, source);
+                gl.compile_shader(shader);
+                if !gl.get_shader_compile_status(shader) {
+                    let log = gl.get_shader_info_log(shader);
+                    gl.delete_shader(shader);
+                    for shader in shaders {
+                        gl.delete_shader(shader);
+                    }
+                    return Err(format!(
+                        "Shader compile error ( {} ): {}",
+                        current_target(),
+                        log
+                    ));
+                }
+                shaders.push(shader);
+            }
+
+            // Link the program
+            let program = gl.create_program().unwrap();
+            before_link(program);
+            for &shader in &shaders {
+                gl.attach_shader(program, shader);
+            }
+            if !varyings.is_empty() {
+                gl.transform_feedback_varyings(program, varyings, glow::INTERLEAVED_ATTRIBS);
+            }
+            gl.link_program(program);
+            for shader in shaders {
+                gl.delete_shader(shader);
+            }
+            if !gl.get_program_link_status(program) {
+                let log = gl.get_program_info_log(program);
+                gl.delete_program(program);
+                return Err(format!("Shader link error: {}", log));
+            }
+            resources::track(ResourceKind::Program, program, "Shader program");
+            Ok(program)
+        }
+    }
+
+    /// Wrap a program that has been linked
+    fn from_linked(program: u32, from_cache: bool) -> Self {
+        Self {
+            id: program,
+            uniforms: HashMap::new(),
+            uniform_blocks: HashMap::new(),
+            float_epsilon: 0.,
+            caching: true,
+            from_cache,
+        }
+    }
+
+    /// Whether or not the program was loaded from a binary cache instead of being compiled
+    pub fn loaded_from_cache(&self) -> bool {
+        self.from_cache
+    }
+
+    /// Treat floats within `epsilon` of the last value as unchanged
+    pub fn with_float_epsilon(mut self, epsilon: f32) -> Self {
+        self.float_epsilon = epsilon;
+        self
+    }
+
+    /// Whether or not uploads of unchanged values are skipped
+    pub fn caching_enabled(&self) -> bool {
+        self.caching
+    }
+
+    /// Turn skipping uploads of unchanged values on or off, e.g. to compare the number of uploads
+    pub fn set_caching_enabled(&mut self, enabled: bool) {
+        self.caching = enabled;
+        self.invalidate();
+    }
+
+    /// Make the program current
+    pub fn bind(&self, gl: &mut glow::Context) {
+        unsafe { gl.use_program(Some(self.id)) };
+    }
+
+    /// A handle to the program, or `None` if the current context isn't tracked
+    pub fn handle(&self) -> Option<Handle<ShaderProgram>> {
+        Handle::new(self.id)
+    }
+
+    /// The location of a uniform, or `None` if the program doesn't use it
+    pub fn uniform_location(&mut self, gl: &mut glow::Context, name: &str) -> Option<u32> {
+        self.cached_uniform(gl, name).location
+    }
+
+    /// Set a uniform of the program, which must be current, unless it already has the value
+    ///
+    /// Uniforms that the program doesn't use are ignored, like GL does.
+    pub fn set_uniform<V: Into<UniformValue>>(
+        &mut self,
+        gl: &mut glow::Context,
+        name: &str,
+        value: V,
+    ) {
+        let value = value.into();
+        let (caching, epsilon) = (self.caching, self.float_epsilon);
+        let uniform = self.cached_uniform(gl, name);
+        let location = match uniform.location {
+            Some(location) => location,
+            None => return,
+        };
+
+        // Skip the upload if the uniform already has the value
+        if caching
+            && uniform
+                .value
+                .is_some_and(|last| last.approx_eq(&value, epsilon))
+        {
+            count_upload(false);
+            return;
+        }
+        uniform.value = Some(value);
+        count_upload(true);
+
+        unsafe {
+            let location = Some(&location);
+            match value {
+                UniformValue::F32(x) => gl.uniform_1_f32(location, x),
+                UniformValue::Vec2([x, y]) => gl.uniform_2_f32(location, x, y),
+                UniformValue::Vec3([x, y, z]) => gl.uniform_3_f32(location, x, y, z),
+                UniformValue::Vec4([x, y, z, w]) => gl.uniform_4_f32(location, x, y, z, w),
+                UniformValue::I32(x) => gl.uniform_1_i32(location, x),
+                UniformValue::Mat4(matrix) => {
+                    gl.uniform_matrix_4_f32_slice(location, false, &matrix)
+                }
+            }
+        }
+    }
+
+    /// Point a uniform block of the program at a uniform buffer binding point, returning false if
+    /// the program doesn't declare the block
+    ///
+    /// The result is remembered, so this only makes a GL call when the binding point changes.
+    pub fn bind_uniform_block(&mut self, gl: &mut glow::Context, name: &str, binding: u32) -> bool {
+        let id = self.id;
+        let block = self
+            .uniform_blocks
+            .entry(name.to_string())
+            .or_insert_with(|| {
+                let index = unsafe { gl.get_uniform_block_index(id, name) };
+                index.map(|index| UniformBlock {
+                    index,
+                    binding: None,
+                })
+            });
+        match block {
+            Some(block) => {
+                if block.binding != Some(binding) {
+                    unsafe { gl.uniform_block_binding(id, block.index, binding) };
+                    block.binding = Some(binding);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget the last values of the uniforms and the binding points of the uniform blocks, so
+    /// that the next uploads and bindings aren't skipped
+    ///
+    /// Call this when the uniforms might have been set without going through the program, like
+    /// with `gl.uniform_*` calls. The locations and block indices are kept, since only relinking
+    /// the program changes them.
+    pub fn invalidate(&mut self) {
+        for uniform in self.uniforms.values_mut() {
+            uniform.value = None;
+        }
+        for block in self.uniform_blocks.values_mut().flatten() {
+            block.binding = None;
+        }
+    }
+
+    /// Delete the program
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe { gl.delete_program(self.id) };
+        resources::untrack(ResourceKind::Program, self.id);
+        self.uniforms.clear();
+        self.uniform_blocks.clear();
+    }
+
+    /// The cache entry of a uniform, looking up its location the first time
+    fn cached_uniform(&mut self, gl: &mut glow::Context, name: &str) -> &mut CachedUniform {
+        let id = self.id;
+        self.uniforms
+            .entry(name.to_string())
+            .or_insert_with(|| CachedUniform {
+                location: unsafe { gl.get_uniform_location(id, name) },
+                value: None,
+            })
+    }
+}
+
+/// Add a `#define` for each name to a shader's source, after its `#version` line
+///
+/// A `#line` directive follows the defines so that the line numbers in compile errors still match
+/// the original source.
+pub fn inject_defines(source: &str, defines: &[&str]) -> String {
+    if defines.is_empty() {
+        return source.to_string();
+    }
+
+    // The version has to stay the first thing in the shader
+    let (version, rest, next_line) = match source.split_once('\n') {
+        Some((first, rest)) if first.trim_start().starts_with("#version") => (first, rest, 2),
+        _ => ("", source, 1),
+    };
+    let mut injected = String::with_capacity(source.len() + defines.len() * 32);
+    if !version.is_empty() {
+        injected.push_str(version);
+        injected.push('\n');
+    }
+    for define in defines {
+        writeln!(injected, "#define {}", define).unwrap();
+    }
+    writeln!(injected, "#line {}", next_line).unwrap();
+    injected.push_str(rest);
+    injected
+}
+
+/// Include a GLSL chunk in a shader, after its `#version` line
+///
+/// A `#line` directive follows the chunk so that the line numbers in compile errors still match
+/// the original source.
+pub fn include_chunk(source: &str, chunk: &str) -> String {
+    let (version, rest, next_line) = match source.split_once('\n') {
+        Some((first, rest)) if first.trim_start().starts_with("#version") => (first, rest, 2),
+        _ => ("", source, 1),
+    };
+    format!("{}\n{}\n#line {}\n{}", version, chunk, next_line, rest)
+}
+
+/// The vertex shader of the fullscreen passes: one triangle over the whole screen made from
+/// `gl_VertexID`, with the texture coordinates of the screen in `texCoord`
+pub const FULLSCREEN_VERTEX_SRC: &str = include_str!("fullscreen.vert");
+
+/// The GLSL version that shader sources are written against, unless they need a later one
+pub const BASELINE_VERSION: u32 = 330;
+
+/// The types that GLSL ES needs a precision for: floats and ints, which don't have a default in
+/// fragment shaders, and the samplers, most of which have no default or a low one
+const ES_PRECISION_TYPES: &[&str] = &[
+    "float",
+    "int",
+    "sampler2D",
+    "sampler3D",
+    "samplerCube",
+    "sampler2DShadow",
+    "samplerCubeShadow",
+    "sampler2DArray",
+    "sampler2DArrayShadow",
+    "isampler2D",
+    "isampler3D",
+    "isamplerCube",
+    "isampler2DArray",
+    "usampler2D",
+    "usampler3D",
+    "usamplerCube",
+    "usampler2DArray",
+];
+
+/// The multisampled samplers, which GLSL ES 3.10 added without a default precision
+const ES_310_PRECISION_TYPES: &[&str] = &["sampler2DMS", "isampler2DMS", "usampler2DMS"];
+
+/// The texture functions of old GLSL versions and the ones that replaced them in every target
+const LEGACY_FUNCTIONS: &[(&str, &str)] = &[
+    ("texture2D", "texture"),
+    ("texture3D", "texture"),
+    ("textureCube", "texture"),
+    ("texture2DProj", "textureProj"),
+    ("texture2DLod", "textureLod"),
+    ("texture3DLod", "textureLod"),
+    ("textureCubeLod", "textureLod"),
+];
+
+/// The qualifiers that GLSL ES doesn't have, which are dropped for it
+const DESKTOP_QUALIFIERS: &[&str] = &["noperspective"];
+
+/// The GLSL ES versions that desktop contexts can compile, with the GL version and the extension
+/// that add them
+const ES_COMPATIBILITY: &[(u32, Option<(u32, u32)>, &str)] = &[
+    (300, Some((4, 3)), "GL_ARB_ES3_compatibility"),
+    (310, Some((4, 5)), "GL_ARB_ES3_1_compatibility"),
+    (320, None, "GL_ARB_ES3_2_compatibility"),
+];
+
+/// A version of GLSL that shaders are compiled as, see `rewrite`
+///
+/// Sources are written once against `#version 330 core`, or a later core version for the
+/// features they need, like `#version 430` for storage buffers, and rewritten for the context
+/// when they are compiled. They should stick to what GLSL ES allows as well, like converting ints
+/// to floats with `float()`, since that can't be rewritten.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ShaderTarget {
+    /// Desktop GLSL for a core profile, by its version number, e.g. 330 or 450
+    Core(u32),
+    /// GLSL ES by its version number, e.g. 300 for GL ES 3.0 and WebGL 2
+    Es(u32),
+}
+
+impl ShaderTarget {
+    /// The target that the sources are written against
+    pub const BASELINE: Self = ShaderTarget::Core(BASELINE_VERSION);
+
+    /// The target for a context's GL version
+    pub fn for_features(features: &Features) -> Self {
+        let (major, minor) = features.gl_version;
+        let version = major * 100 + minor * 10;
+        if features.es {
+            ShaderTarget::Es(version.clamp(300, 320))
+        } else {
+            ShaderTarget::Core(version.clamp(BASELINE_VERSION, 460))
+        }
+    }
+
+    /// Every target that a context can compile, starting with the one for its own version
+    ///
+    /// Desktop contexts can compile the baseline, and GLSL ES with `GL_ARB_ES3_compatibility` and
+    /// the extensions that followed it.
+    pub fn supported(features: &Features) -> Vec<Self> {
+        let native = Self::for_features(features);
+        let mut targets = vec![native];
+        if !features.es && native != Self::BASELINE {
+            targets.push(Self::BASELINE);
+        }
+        for &(version, gl_version, extension) in ES_COMPATIBILITY {
+            let compiles = match native {
+                ShaderTarget::Es(native) => version <= native,
+                ShaderTarget::Core(_) => {
+                    gl_version.is_some_and(|(major, minor)| features.has_version(major, minor))
+                        || features.has_extension(extension)
+                }
+            };
+            if compiles && !targets.contains(&ShaderTarget::Es(version)) {
+                targets.push(ShaderTarget::Es(version));
+            }
+        }
+        targets
+    }
+
+    /// Whether the target is GLSL ES
+    pub fn is_es(self) -> bool {
+        matches!(self, ShaderTarget::Es(_))
+    }
+
+    /// Whether sources written against a core GLSL version can be rewritten for the target
+    ///
+    /// GLSL ES 3.00 has what 3.30 has, and 3.10 adds what compute shaders and storage buffers need
+    /// from 4.30.
+    pub fn supports(self, core_version: u32) -> bool {
+        match self {
+            ShaderTarget::Core(version) => core_version <= version,
+            ShaderTarget::Es(version) => {
+                let needed = match core_version {
+                    0..=330 => 300,
+                    331..=430 => 310,
+                    _ => 320,
+                };
+                needed <= version
+            }
+        }
+    }
+
+    /// The `#version` line of the target
+    pub fn version_line(self) -> String {
+        match self {
+            ShaderTarget::Core(version) => format!("#version {} core", version),
+            ShaderTarget::Es(version) => format!("#version {} es", version),
+        }
+    }
+
+    /// Rewrite a shader source for the target, or say why it can't be
+    ///
+    /// - The `#version` line is replaced with the target's, or added if the source doesn't have
+    ///   one.
+    /// - GLSL ES gets `highp` as the precision of floats, ints, and samplers.
+    /// - The legacy texture functions, like `texture2D`, are renamed to the ones every target has.
+    /// - `noperspective` is dropped for GLSL ES, so those values are interpolated with perspective.
+    ///
+    /// The rest of the source is only changed within its lines, and a `#line` directive follows
+    /// the lines that are added, so the line numbers in compile errors match the original source.
+    pub fn rewrite(self, source: &str) -> Result<String, String> {
+        let (version, rest, next_line) = match source.split_once('\n') {
+            Some((first, rest)) if first.trim_start().starts_with("#version") => {
+                let mut words = first.split_whitespace().skip(1);
+                let version = words
+                    .next()
+                    .and_then(|word| word.parse().ok())
+                    .ok_or_else(|| format!("Can't read the version of `{}`", first.trim()))?;
+                if words.next() == Some("es") {
+                    return Err(format!(
+                        "`{}` is GLSL ES, and sources are written against desktop GLSL",
+                        first.trim()
+                    ));
+                }
+                (version, rest, 2)
+            }
+            _ => (BASELINE_VERSION, source, 1),
+        };
+        if !self.supports(version) {
+            return Err(format!(
+                "The shader needs GLSL {} core, which {} can't do",
+                version, self
+            ));
+        }
+
+        let mut rewritten = String::with_capacity(source.len() + 512);
+        writeln!(rewritten, "{}", self.version_line()).unwrap();
+        if let ShaderTarget::Es(version) = self {
+            let multisampled = if version >= 310 {
+                ES_310_PRECISION_TYPES
+            } else {
+                &[]
+            };
+            for ty in ES_PRECISION_TYPES.iter().chain(multisampled) {
+                writeln!(rewritten, "precision highp {};", ty).unwrap();
+            }
+        }
+        writeln!(rewritten, "#line {}", next_line).unwrap();
+
+        // Go through the source a word at a time, renaming and dropping the ones that need it
+        let mut rest = rest;
+        let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+        while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            rewritten.push_str(&rest[..start]);
+            let end = rest[start..]
+                .find(|c: char| !is_word(c))
+                .map_or(rest.len(), |end| start + end);
+            let word = &rest[start..end];
+            rest = &rest[end..];
+            if self.is_es() && DESKTOP_QUALIFIERS.contains(&word) {
+                rest = rest.trim_start_matches(|c| c == ' ' || c == '\t');
+                continue;
+            }
+            let word = LEGACY_FUNCTIONS
+                .iter()
+                .find(|(legacy, _)| *legacy == word)
+                .map_or(word, |(_, function)| function);
+            rewritten.push_str(word);
+        }
+        rewritten.push_str(rest);
+        Ok(rewritten)
+    }
+}
+
+impl fmt::Display for ShaderTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderTarget::Core(version) => write!(f, "GLSL {} core", version),
+            ShaderTarget::Es(version) => write!(f, "GLSL {} es", version),
+        }
+    }
+}
+
+thread_local! {
+    /// The shader target of the GL context that is current on this thread
+    static CURRENT_TARGET: Cell<ShaderTarget> = const { Cell::new(ShaderTarget::BASELINE) };
+}
+
+/// Set the shader target of the context that was just made current, which the loop does for each
+/// window before calling its handler
+pub(crate) fn make_current_target(target: ShaderTarget) {
+    CURRENT_TARGET.with(|current| current.set(target));
+}
+
+/// The shader target of the GL context that is current on this thread, which `ShaderProgram`
+/// rewrites its sources for
+pub fn current_target() -> ShaderTarget {
+    CURRENT_TARGET.with(|current| current.get())
+}
+
+/// Rewrite a shader source for the current context's target, for code that compiles its shaders
+/// without `ShaderProgram`
+pub fn rewrite(source: &str) -> Result<String, String> {
+    current_target().rewrite(source)
+}
+
+/// Count an upload in the current frame's stats
+fn count_upload(issued: bool) {
+    FRAME_STATS.with(|stats| {
+        let mut frame = stats.get();
+        if issued {
+            frame.issued += 1;
+        } else {
+            frame.skipped += 1;
+        }
+        stats.set(frame);
+    });
+}
@@ -0,0 +1,378 @@
+use std::fmt::Write;
+
+use euclid::default::Size2D;
+use glow::HasContext;
+use surfman::{
+    Connection, ContextAttributeFlags, ContextAttributes, GLVersion, SurfaceAccess, SurfaceType,
+};
+
+use crate::{
+    features::Loader,
+    image_diff::within_tolerance,
+    shader::{self, ShaderProgram},
+};
+
+/// The widest a probe gets before its cases wrap onto another row
+const MAX_PROBE_WIDTH: usize = 256;
+
+/// How many failing cases are listed before the rest are only counted
+const MAX_LISTED_FAILURES: usize = 8;
+
+/// Covers the probe with one triangle made from `gl_VertexID`, so no vertex buffer is needed
+const PROBE_VERTEX_SRC: &str = r#"#version 330 core
+void main() {
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+/// Runs the test's `probe` function on the case in the input texture under each pixel. The chunk
+/// being tested and the probe function are included after the `#version` line.
+const PROBE_FRAGMENT_SRC: &str = r#"#version 330 core
+out vec4 FragColor;
+
+uniform sampler2D probeInputs;
+
+void main() {
+    FragColor = probe(texelFetch(probeInputs, ivec2(gl_FragCoord.xy), 0));
+}
+"#;
+
+/// The CPU version of what a probe computes, for checking the GPU's results against
+pub type Reference = Box<dyn Fn([f32; 4]) -> [f32; 4]>;
+
+/// A check of a GLSL chunk against a CPU reference implementation
+///
+/// The chunk is compiled into a small fragment shader along with `probe`, GLSL that defines
+/// `vec4 probe(vec4 value)` and calls into the chunk. Each case is one pixel of a float
+/// framebuffer, which is read back and compared with what `reference` gives for the same case.
+/// Usually made with `shader_test!`.
+pub struct ShaderTest {
+    pub name: &'static str,
+    pub chunk: &'static str,
+    pub probe: &'static str,
+    pub cases: Vec<[f32; 4]>,
+    pub reference: Reference,
+    /// How far each channel may be from the reference, as `image_diff::within_tolerance` decides
+    pub tolerance: f32,
+}
+
+impl std::fmt::Debug for ShaderTest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShaderTest")
+            .field("name", &self.name)
+            .field("cases", &self.cases.len())
+            .field("tolerance", &self.tolerance)
+            .finish()
+    }
+}
+
+impl ShaderTest {
+    /// Run the cases on the GPU in the current context, returning how many there were, or which
+    /// ones didn't match the reference
+    pub fn run(&self, gl: &mut glow::Context) -> Result<usize, String> {
+        let count = self.cases.len();
+        if count == 0 {
+            return Ok(0);
+        }
+        let fragment = shader::include_chunk(
+            PROBE_FRAGMENT_SRC,
+            &format!("{}\n{}", self.chunk, self.probe),
+        );
+        let mut program = ShaderProgram::new(gl, PROBE_VERTEX_SRC, &fragment)?;
+
+        // Lay the cases out in rows, with the last row padded out with zeros
+        let width = count.min(MAX_PROBE_WIDTH);
+        let height = count.div_ceil(width);
+        let inputs = self
+            .cases
+            .iter()
+            .flatten()
+            .copied()
+            .chain(std::iter::repeat(0.))
+            .take(width * height * 4)
+            .flat_map(f32::to_ne_bytes)
+            .collect::<Vec<_>>();
+        let mut outputs = vec![0u8; inputs.len()];
+
+        unsafe {
+            // The cases go in a float texture, read with `texelFetch` so nothing is filtered
+            let input_texture = gl.create_texture().unwrap();
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(input_texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA32F as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                Some(&inputs),
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+
+            // The results go in a float framebuffer of the same size
+            let output_texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(output_texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA32F as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                None,
+            );
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(output_texture),
+                0,
+            );
+            let status = gl.check_framebuffer_status(glow::FRAMEBUFFER);
+
+            if status == glow::FRAMEBUFFER_COMPLETE {
+                // Run the probe on every case
+                let vao = gl.create_vertex_array().unwrap();
+                gl.viewport(0, 0, width as i32, height as i32);
+                gl.disable(glow::BLEND);
+                gl.disable(glow::DEPTH_TEST);
+                gl.bind_texture(glow::TEXTURE_2D, Some(input_texture));
+                program.bind(gl);
+                program.set_uniform(gl, "probeInputs", 0);
+                gl.bind_vertex_array(Some(vao));
+                gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                gl.bind_vertex_array(None);
+                gl.delete_vertex_array(vao);
+
+                // Read the results back
+                gl.read_pixels(
+                    0,
+                    0,
+                    width as i32,
+                    height as i32,
+                    glow::RGBA,
+                    glow::FLOAT,
+                    glow::PixelPackData::Slice(&mut outputs),
+                );
+            }
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            gl.delete_framebuffer(framebuffer);
+            gl.delete_texture(input_texture);
+            gl.delete_texture(output_texture);
+            program.delete(gl);
+
+            if status != glow::FRAMEBUFFER_COMPLETE {
+                return Err(format!(
+                    "The probe framebuffer is incomplete ( status {:#x} )",
+                    status
+                ));
+            }
+        }
+
+        // Compare each case with the reference
+        let outputs = outputs
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect::<Vec<_>>();
+        let mut failures = 0;
+        let mut report = String::new();
+        for (case, output) in self.cases.iter().zip(outputs.chunks_exact(4)) {
+            let expected = (self.reference)(*case);
+            let matches = expected
+                .iter()
+                .zip(output)
+                .all(|(expected, output)| within_tolerance(*expected, *output, self.tolerance));
+            if !matches {
+                failures += 1;
+                if failures <= MAX_LISTED_FAILURES {
+                    writeln!(
+                        report,
+                        "  {:?}: expected {:?}, got {:?}",
+                        case, expected, output
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        if failures == 0 {
+            Ok(count)
+        } else {
+            if failures > MAX_LISTED_FAILURES {
+                writeln!(report, "  and {} more", failures - MAX_LISTED_FAILURES).unwrap();
+            }
+            Err(format!(
+                "{} of {} cases are off by more than {}:\n{}",
+                failures,
+                count,
+                self.tolerance,
+                report.trim_end()
+            ))
+        }
+    }
+}
+
+/// Run shader tests in a GL context of their own, printing a line for each one
+///
+/// Returns whether none of them failed. If no GL context can be made, e.g. on a machine without a
+/// GPU or display, the tests are skipped and count as passing.
+pub fn run_tests(tests: &[ShaderTest]) -> bool {
+    let result = with_test_context(|gl| {
+        let mut passed = true;
+        for test in tests {
+            match test.run(gl) {
+                Ok(cases) => eprintln!("shader test {} ... ok ( {} cases )", test.name, cases),
+                Err(error) => {
+                    eprintln!("shader test {} ... FAILED\n{}", test.name, error);
+                    passed = false;
+                }
+            }
+        }
+        passed
+    });
+    match result {
+        Ok(passed) => passed,
+        Err(error) => {
+            eprintln!(
+                "Skipping {} shader tests, since no GL context could be made: {:?}",
+                tests.len(),
+                error
+            );
+            true
+        }
+    }
+}
+
+/// Which of the system's GPUs, or its software renderer, to make a context on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AdapterPreference {
+    /// The fastest GPU, which is what the windows use
+    Hardware,
+    /// The GPU that saves the most power, like an integrated one in a laptop with two
+    LowPower,
+    /// A renderer that runs on the CPU, like llvmpipe, if the system has one
+    Software,
+}
+
+impl AdapterPreference {
+    pub const ALL: [AdapterPreference; 3] = [
+        AdapterPreference::Hardware,
+        AdapterPreference::LowPower,
+        AdapterPreference::Software,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AdapterPreference::Hardware => "hardware",
+            AdapterPreference::LowPower => "low power",
+            AdapterPreference::Software => "software",
+        }
+    }
+}
+
+/// Make a GL 3.3 context with a small offscreen surface, run `f` with it current, and destroy it
+pub fn with_test_context<R>(f: impl FnOnce(&mut glow::Context) -> R) -> Result<R, surfman::Error> {
+    with_adapter_context(AdapterPreference::Hardware, |gl, _| f(gl))
+}
+
+/// Like `with_test_context` on the given kind of adapter, also giving `f` a loader for the
+/// functions that glow doesn't expose, for querying `Features`
+pub(crate) fn with_adapter_context<R>(
+    adapter: AdapterPreference,
+    f: impl FnOnce(&mut glow::Context, Loader) -> R,
+) -> Result<R, surfman::Error> {
+    let conn = Connection::new()?;
+    let adapter = match adapter {
+        AdapterPreference::Hardware => conn.create_hardware_adapter()?,
+        AdapterPreference::LowPower => conn.create_low_power_adapter()?,
+        AdapterPreference::Software => conn.create_software_adapter()?,
+    };
+    let mut device = conn.create_device(&adapter)?;
+    let context_descriptor = device.create_context_descriptor(&ContextAttributes {
+        version: GLVersion::new(3, 3),
+        flags: ContextAttributeFlags::empty(),
+    })?;
+    let mut context = device.create_context(&context_descriptor, None)?;
+
+    // Contexts have to be destroyed by hand, so do that whatever goes wrong from here on
+    let surface_type = SurfaceType::Generic {
+        size: Size2D::new(1, 1),
+    };
+    let bound = device
+        .create_surface(&context, SurfaceAccess::GPUOnly, surface_type)
+        .and_then(|surface| {
+            device
+                .bind_surface_to_context(&mut context, surface)
+                .map_err(|(error, mut surface)| {
+                    device.destroy_surface(&mut context, &mut surface).ok();
+                    error
+                })
+        })
+        .and_then(|()| device.make_context_current(&context));
+    let result = bound.map(|()| {
+        let mut gl = unsafe {
+            glow::Context::from_loader_function(|s| {
+                device.get_proc_address(&context, s) as *const _
+            })
+        };
+        f(&mut gl, &|symbol| device.get_proc_address(&context, symbol))
+    });
+
+    if let Ok(Some(mut surface)) = device.unbind_surface_from_context(&mut context) {
+        device.destroy_surface(&mut context, &mut surface).ok();
+    }
+    device.destroy_context(&mut context).ok();
+    result
+}
+
+/// Make a `ShaderTest`, e.g.
+///
+/// ```ignore
+/// shader_test!(reinhard {
+///     chunk = TONEMAP_CHUNK,
+///     probe = "vec4 probe(vec4 value) { return vec4(tonemapReinhard(value.rgb), 1.0); }",
+///     cases = (0..100).map(|i| [i as f32 * 0.1; 4]),
+///     reference = |value| [value[0] / (value[0] + 1.), ...],
+///     tolerance = 1e-5,
+/// })
+/// ```
+///
+/// The cases can be anything that iterates over `[f32; 4]`s, and the test is named after the
+/// identifier. Run tests with `shader_test::run_tests`.
+#[macro_export]
+macro_rules! shader_test {
+    ($name:ident {
+        chunk = $chunk:expr,
+        probe = $probe:expr,
+        cases = $cases:expr,
+        reference = $reference:expr,
+        tolerance = $tolerance:expr $(,)?
+    }) => {
+        $crate::shader_test::ShaderTest {
+            name: stringify!($name),
+            chunk: $chunk,
+            probe: $probe,
+            cases: $cases.into_iter().collect(),
+            reference: Box::new($reference),
+            tolerance: $tolerance,
+        }
+    };
+}
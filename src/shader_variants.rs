@@ -0,0 +1,332 @@
+use std::{
+    cell::Cell,
+    collections::{BTreeMap, HashMap, VecDeque},
+    fmt::{self, Write as _},
+    time::{Duration, Instant},
+};
+
+use crate::{program_cache::ProgramBinaryCache, shader::ShaderProgram};
+
+/// A variant of a shader: the name of its sources and the features it is compiled with
+///
+/// Materials hold a key rather than a program, and get the program from `ShaderVariants` when
+/// they are drawn. The features are sorted so that the same set always gives the same key.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct VariantKey {
+    source: String,
+    features: Vec<String>,
+}
+
+impl VariantKey {
+    pub fn new(source: &str, features: &[&str]) -> Self {
+        let mut features = features
+            .iter()
+            .map(|feature| feature.to_string())
+            .collect::<Vec<_>>();
+        features.sort();
+        features.dedup();
+        Self {
+            source: source.into(),
+            features,
+        }
+    }
+
+    /// The same variant with another feature, e.g. to swap in a debug view of a material
+    pub fn with_feature(&self, feature: &str) -> Self {
+        let mut features = self.features.clone();
+        if let Err(index) = features.binary_search_by(|other| other.as_str().cmp(feature)) {
+            features.insert(index, feature.into());
+        }
+        Self {
+            source: self.source.clone(),
+            features,
+        }
+    }
+
+    /// The name of the sources, as given to `ShaderVariants::add_source`
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// The features, which are `#define`d in both shaders
+    pub fn features(&self) -> &[String] {
+        &self.features
+    }
+}
+
+impl fmt::Display for VariantKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} [{}]", self.source, self.features.join(", "))
+    }
+}
+
+/// How long a variant took to compile or load and how many times it has been used
+#[derive(Clone, Copy, Debug)]
+pub struct VariantStats<'a> {
+    pub key: &'a VariantKey,
+    pub compile_time: Duration,
+    /// Whether the variant was loaded from the binary cache instead of being compiled
+    pub from_cache: bool,
+    /// The number of times the variant has been asked for with `ShaderVariants::get`
+    pub uses: u64,
+}
+
+/// The number of variants that are alive on this thread and how long they took to compile
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VariantTotals {
+    pub count: usize,
+    pub compile_time: Duration,
+}
+
+thread_local! {
+    /// The variants of every `ShaderVariants` on this thread, for the frame stats
+    static TOTALS: Cell<VariantTotals> = const {
+        Cell::new(VariantTotals {
+            count: 0,
+            compile_time: Duration::from_secs(0),
+        })
+    };
+}
+
+/// The variants that are alive on this thread and how long they took to compile
+pub fn live_variant_totals() -> VariantTotals {
+    TOTALS.with(Cell::get)
+}
+
+/// The sources of a shader with variants
+#[derive(Clone, Debug)]
+struct ShaderSource {
+    vertex: String,
+    fragment: String,
+}
+
+/// A compiled variant
+#[derive(Debug)]
+struct Variant {
+    program: ShaderProgram,
+    compile_time: Duration,
+    uses: u64,
+}
+
+/// Compiles the variants of shaders when they are first needed and keeps them around
+///
+/// Each variant is compiled from its shader's sources with a `#define` for each of its features.
+/// Variants that are known ahead of time can be queued with `queue_warm_up` and compiled with
+/// `warm_up`, either all at once during init or a little every frame, so that drawing doesn't
+/// stall on the first use. GL contexts can't compile on another thread, so the warm up still
+/// happens on the thread that draws.
+#[derive(Debug, Default)]
+pub struct ShaderVariants {
+    sources: HashMap<String, ShaderSource>,
+    variants: BTreeMap<VariantKey, Variant>,
+    warm_up_queue: VecDeque<VariantKey>,
+    /// Where linked variants are saved so that later runs can skip compiling them
+    binary_cache: Option<ProgramBinaryCache>,
+}
+
+impl ShaderVariants {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load variants from a binary cache when they are there, and save them to it when they
+    /// aren't
+    pub fn set_binary_cache(&mut self, cache: ProgramBinaryCache) {
+        self.binary_cache = Some(cache);
+    }
+
+    pub fn binary_cache(&self) -> Option<&ProgramBinaryCache> {
+        self.binary_cache.as_ref()
+    }
+
+    /// Add the sources of a shader that variants can be compiled from
+    pub fn add_source(&mut self, name: &str, vertex: &str, fragment: &str) {
+        self.sources.insert(
+            name.into(),
+            ShaderSource {
+                vertex: vertex.into(),
+                fragment: fragment.into(),
+            },
+        );
+    }
+
+    /// The program for a variant, compiling it if this is the first time it is needed
+    pub fn get(
+        &mut self,
+        gl: &mut glow::Context,
+        key: &VariantKey,
+    ) -> Result<&mut ShaderProgram, String> {
+        if !self.variants.contains_key(key) {
+            self.compile(gl, key)?;
+        }
+        let variant = self.variants.get_mut(key).unwrap();
+        variant.uses += 1;
+        Ok(&mut variant.program)
+    }
+
+    /// Whether or not a variant has been compiled
+    pub fn contains(&self, key: &VariantKey) -> bool {
+        self.variants.contains_key(key)
+    }
+
+    /// Queue a variant to be compiled by `warm_up`
+    pub fn queue_warm_up(&mut self, key: VariantKey) {
+        if !self.contains(&key) && !self.warm_up_queue.contains(&key) {
+            self.warm_up_queue.push_back(key);
+        }
+    }
+
+    /// Compile the queued variants, returning how many are still queued
+    ///
+    /// With a `budget` this stops once that much time has been spent, but always compiles at least
+    /// one variant so that calling it every frame makes progress.
+    pub fn warm_up(
+        &mut self,
+        gl: &mut glow::Context,
+        budget: Option<Duration>,
+    ) -> Result<usize, String> {
+        let start = Instant::now();
+        while let Some(key) = self.warm_up_queue.pop_front() {
+            if !self.contains(&key) {
+                self.compile(gl, &key)?;
+            }
+            if budget.is_some_and(|budget| start.elapsed() >= budget) {
+                break;
+            }
+        }
+        Ok(self.warm_up_queue.len())
+    }
+
+    /// The number of compiled variants
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+
+    /// The compiled variants, sorted by key
+    pub fn variants(&self) -> impl Iterator<Item = VariantStats<'_>> {
+        self.variants.iter().map(|(key, variant)| VariantStats {
+            key,
+            compile_time: variant.compile_time,
+            from_cache: variant.program.loaded_from_cache(),
+            uses: variant.uses,
+        })
+    }
+
+    /// The programs of the compiled variants, e.g. to change a setting on all of them
+    pub fn programs_mut(&mut self) -> impl Iterator<Item = &mut ShaderProgram> {
+        self.variants
+            .values_mut()
+            .map(|variant| &mut variant.program)
+    }
+
+    /// A description of the compiled variants and how long they took to compile
+    pub fn report(&self) -> String {
+        let total = self
+            .variants()
+            .map(|variant| variant.compile_time)
+            .sum::<Duration>();
+        let mut report = format!(
+            "{} shader variants, compiled in {:.1} ms\n",
+            self.len(),
+            total.as_secs_f64() * 1000.
+        );
+        if let Some(cache) = self
+            .binary_cache
+            .as_ref()
+            .filter(|cache| cache.is_enabled())
+        {
+            let stats = cache.stats();
+            writeln!(
+                report,
+                "    Binary cache: {} loaded, {} compiled, {} rejected by the driver",
+                stats.hits, stats.misses, stats.rejected
+            )
+            .unwrap();
+        }
+        for variant in self.variants() {
+            writeln!(
+                report,
+                "    {:>8.2} ms  {} ( {}used {} times )",
+                variant.compile_time.as_secs_f64() * 1000.,
+                variant.key,
+                if variant.from_cache { "cached, " } else { "" },
+                variant.uses
+            )
+            .unwrap();
+        }
+        report
+    }
+
+    /// Delete every compiled variant
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for (_, mut variant) in std::mem::take(&mut self.variants) {
+            variant.program.delete(gl);
+            remove_from_totals(variant.compile_time);
+        }
+        self.warm_up_queue.clear();
+    }
+
+    /// Compile a variant and add it to the cache
+    fn compile(&mut self, gl: &mut glow::Context, key: &VariantKey) -> Result<(), String> {
+        let source = self
+            .sources
+            .get(key.source())
+            .ok_or_else(|| format!("There are no shader sources named `{}`", key.source()))?;
+        let defines = key
+            .features()
+            .iter()
+            .map(String::as_str)
+            .collect::<Vec<_>>();
+
+        let start = Instant::now();
+        let program = match &mut self.binary_cache {
+            Some(cache) => ShaderProgram::with_binary_cache(
+                gl,
+                &source.vertex,
+                &source.fragment,
+                &defines,
+                cache,
+            ),
+            None => ShaderProgram::with_defines(gl, &source.vertex, &source.fragment, &defines),
+        }
+        .map_err(|error| format!("{}: {}", key, error))?;
+        let compile_time = start.elapsed();
+
+        add_to_totals(compile_time);
+        self.variants.insert(
+            key.clone(),
+            Variant {
+                program,
+                compile_time,
+                uses: 0,
+            },
+        );
+        Ok(())
+    }
+}
+
+/// Add a compiled variant to the thread's totals
+fn add_to_totals(compile_time: Duration) {
+    TOTALS.with(|totals| {
+        let current = totals.get();
+        totals.set(VariantTotals {
+            count: current.count + 1,
+            compile_time: current.compile_time + compile_time,
+        });
+    });
+}
+
+/// Remove a deleted variant from the thread's totals
+fn remove_from_totals(compile_time: Duration) {
+    TOTALS.with(|totals| {
+        let current = totals.get();
+        totals.set(VariantTotals {
+            count: current.count.saturating_sub(1),
+            compile_time: current.compile_time.saturating_sub(compile_time),
+        });
+    });
+}
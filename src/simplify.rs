@@ -0,0 +1,650 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::mesh::{self, MeshData};
+
+/// How much more it costs to move a boundary edge or a texture seam away from where it was than to
+/// move a surface by the same distance
+const EDGE_WEIGHT: f64 = 10.;
+/// How close together vertices have to be to be welded, as a fraction of the size of the mesh
+const WELD_TOLERANCE: f32 = 1e-5;
+/// The smallest cosine of the angle that a triangle may turn by in one collapse, which keeps
+/// triangles from flipping over
+const MIN_NORMAL_COSINE: f32 = 0.2;
+/// How thin a triangle may get before it counts as degenerate, as twice its area over its longest
+/// edge squared, which is about 0.87 for an equilateral triangle
+const MIN_QUALITY: f32 = 0.05;
+
+/// The sum of squared distances to a set of planes, weighted, as the upper half of a symmetric
+/// 4x4 matrix
+#[derive(Clone, Copy, Debug, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    /// The squared distance to the plane with the normal and distance from the origin
+    fn plane(normal: [f32; 3], distance: f32, weight: f64) -> Self {
+        let [a, b, c] = normal.map(f64::from);
+        let d = f64::from(distance);
+        Self([
+            a * a,
+            a * b,
+            a * c,
+            a * d,
+            b * b,
+            b * c,
+            b * d,
+            c * c,
+            c * d,
+            d * d,
+        ])
+        .scaled(weight)
+    }
+
+    fn scaled(mut self, weight: f64) -> Self {
+        for value in &mut self.0 {
+            *value *= weight;
+        }
+        self
+    }
+
+    fn add(&mut self, other: &Self) {
+        for (value, other) in self.0.iter_mut().zip(&other.0) {
+            *value += other;
+        }
+    }
+
+    /// The error of moving to a position
+    fn error(&self, position: [f32; 3]) -> f64 {
+        let q = &self.0;
+        let [x, y, z] = position.map(f64::from);
+        q[0] * x * x
+            + 2. * q[1] * x * y
+            + 2. * q[2] * x * z
+            + 2. * q[3] * x
+            + q[4] * y * y
+            + 2. * q[5] * y * z
+            + 2. * q[6] * y
+            + q[7] * z * z
+            + 2. * q[8] * z
+            + q[9]
+    }
+}
+
+/// An edge between two welded positions, and the triangles that use it
+#[derive(Clone, Copy, Debug)]
+struct Edge {
+    /// How many triangles use the edge, which is 1 on a boundary
+    count: usize,
+    /// The vertices of the first triangle that uses it, in the order of the edge's key
+    vertices: (u32, u32),
+    /// One of the triangles that uses it
+    triangle: usize,
+    /// Whether the triangles on each side use different vertices at each end, because of a
+    /// texture seam or a crease in the normals
+    seam: [bool; 2],
+}
+
+impl Edge {
+    /// Whether the edge is a boundary, a seam, or shared by more than two triangles, which all
+    /// have to stay where they are
+    fn is_special(&self) -> bool {
+        self.count != 2 || (self.seam[0] && self.seam[1])
+    }
+
+    /// Whether the edge is special as seen from one of its ends. An edge that only splits at the
+    /// other end, like the edges to the pole of a sphere, doesn't limit this end.
+    fn is_special_at(&self, end: usize) -> bool {
+        self.count != 2 || self.seam[end]
+    }
+}
+
+/// Which ways a welded position may move when an edge is collapsed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mobility {
+    /// On the surface, so it may move along any edge
+    Free,
+    /// On a boundary or seam, so it may only move along it
+    Along,
+    /// A corner of boundaries or seams, which stays put
+    Locked,
+}
+
+/// The state of a mesh while it is being simplified
+///
+/// Vertices at the same position are welded into one group, which is what edges are collapsed
+/// between. Each collapse moves one group onto a neighbor and points each of its vertices at the
+/// neighbor's vertex on the same side of any seam, so no new positions or attributes are made.
+struct Simplifier<'a> {
+    data: &'a MeshData,
+    /// The group of every vertex
+    groups: Vec<usize>,
+    /// The position of every group
+    positions: Vec<[f32; 3]>,
+    quadrics: Vec<Quadric>,
+    /// The triangles that use every group, including some that are no longer alive
+    group_triangles: Vec<Vec<usize>>,
+    triangles: Vec<[u32; 3]>,
+    alive: Vec<bool>,
+    alive_count: usize,
+}
+
+impl<'a> Simplifier<'a> {
+    fn new(data: &'a MeshData) -> Self {
+        // Weld the vertices that are at the same place, give or take rounding errors like the ones
+        // in the seams and poles of `primitives::uv_sphere`
+        let extent = (0..3)
+            .map(|axis| {
+                let values = data.positions.iter().map(|position| position[axis]);
+                values.clone().fold(f32::MIN, f32::max) - values.fold(f32::MAX, f32::min)
+            })
+            .fold(0., f32::max);
+        let cell = extent * WELD_TOLERANCE;
+        let mut welded = HashMap::new();
+        let mut positions = Vec::new();
+        let groups = data
+            .positions
+            .iter()
+            .map(|&position| {
+                let key = position.map(|x| {
+                    if cell > 0. {
+                        (x / cell).round() as i64
+                    } else {
+                        0
+                    }
+                });
+                *welded.entry(key).or_insert_with(|| {
+                    positions.push(position);
+                    positions.len() - 1
+                })
+            })
+            .collect::<Vec<_>>();
+
+        // Drop the triangles that use the same position twice, since they have no area anyway
+        let triangles = data
+            .indices
+            .chunks_exact(3)
+            .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+            .filter(|triangle| {
+                let [a, b, c] = triangle.map(|vertex| groups[vertex as usize]);
+                a != b && b != c && c != a
+            })
+            .collect::<Vec<_>>();
+        let mut group_triangles = vec![Vec::new(); positions.len()];
+        for (index, triangle) in triangles.iter().enumerate() {
+            for &vertex in triangle {
+                group_triangles[groups[vertex as usize]].push(index);
+            }
+        }
+
+        let mut simplifier = Self {
+            data,
+            quadrics: vec![Quadric::default(); positions.len()],
+            groups,
+            positions,
+            group_triangles,
+            alive: vec![true; triangles.len()],
+            alive_count: triangles.len(),
+            triangles,
+        };
+
+        // Every group starts with the planes of its triangles, weighted by their area
+        for index in 0..simplifier.triangles.len() {
+            let corners = simplifier.corners(index);
+            let normal = mesh::triangle_normal(corners[0], corners[1], corners[2]);
+            let area = f64::from(mesh::dot(normal, normal).sqrt()) / 2.;
+            if let Some(normal) = mesh::normalize(normal) {
+                let quadric = Quadric::plane(normal, -mesh::dot(normal, corners[0]), area);
+                for vertex in simplifier.triangles[index] {
+                    simplifier.quadrics[simplifier.groups[vertex as usize]].add(&quadric);
+                }
+            }
+        }
+
+        // Boundaries and seams also get planes standing up from them, so that moving away from
+        // them costs more than sliding along them
+        for ((a, b), edge) in simplifier.edges() {
+            if !edge.is_special() {
+                continue;
+            }
+            let corners = simplifier.corners(edge.triangle);
+            let direction = mesh::sub(simplifier.positions[b], simplifier.positions[a]);
+            let normal = mesh::triangle_normal(corners[0], corners[1], corners[2]);
+            if let Some(side) = mesh::normalize(mesh::cross(direction, normal)) {
+                let weight = f64::from(mesh::dot(direction, direction)) * EDGE_WEIGHT;
+                let quadric =
+                    Quadric::plane(side, -mesh::dot(side, simplifier.positions[a]), weight);
+                simplifier.quadrics[a].add(&quadric);
+                simplifier.quadrics[b].add(&quadric);
+            }
+        }
+
+        simplifier
+    }
+
+    fn corners(&self, triangle: usize) -> [[f32; 3]; 3] {
+        self.triangles[triangle].map(|vertex| self.positions[self.groups[vertex as usize]])
+    }
+
+    fn group(&self, vertex: u32) -> usize {
+        self.groups[vertex as usize]
+    }
+
+    /// The triangles that are still alive around a group
+    fn triangles_around(&self, group: usize) -> impl Iterator<Item = usize> + '_ {
+        self.group_triangles[group]
+            .iter()
+            .copied()
+            .filter(move |&index| self.alive[index])
+    }
+
+    /// The groups that share a triangle with a group
+    fn neighbors(&self, group: usize) -> HashSet<usize> {
+        self.triangles_around(group)
+            .flat_map(|index| self.triangles[index])
+            .map(|vertex| self.group(vertex))
+            .filter(|&other| other != group)
+            .collect()
+    }
+
+    /// Every edge between the groups, keyed by the groups with the smaller one first
+    fn edges(&self) -> HashMap<(usize, usize), Edge> {
+        let mut edges = HashMap::<_, Edge>::new();
+        for (index, triangle) in self.triangles.iter().enumerate() {
+            if !self.alive[index] {
+                continue;
+            }
+            for corner in 0..3 {
+                let (mut a, mut b) = (triangle[corner], triangle[(corner + 1) % 3]);
+                if self.group(a) > self.group(b) {
+                    std::mem::swap(&mut a, &mut b);
+                }
+                edges
+                    .entry((self.group(a), self.group(b)))
+                    .and_modify(|edge| {
+                        edge.count += 1;
+                        edge.seam[0] |= edge.vertices.0 != a;
+                        edge.seam[1] |= edge.vertices.1 != b;
+                    })
+                    .or_insert(Edge {
+                        count: 1,
+                        vertices: (a, b),
+                        triangle: index,
+                        seam: [false; 2],
+                    });
+            }
+        }
+        edges
+    }
+
+    /// Collapse the cheapest edges that can be collapsed without touching each other, until the
+    /// target is reached. Returns false if no edge could be collapsed.
+    fn pass(&mut self, target: usize) -> bool {
+        let edges = self.edges();
+
+        // Positions with exactly two boundary or seam edges can slide along them, and ones with
+        // more are corners
+        let mut special_edges = vec![0; self.positions.len()];
+        for (&(a, b), edge) in &edges {
+            for (end, group) in [a, b].iter().enumerate() {
+                if edge.is_special_at(end) {
+                    special_edges[*group] += 1;
+                }
+            }
+        }
+        let mobility = special_edges
+            .iter()
+            .map(|count| match count {
+                0 => Mobility::Free,
+                2 => Mobility::Along,
+                _ => Mobility::Locked,
+            })
+            .collect::<Vec<_>>();
+
+        // Collapse each edge in the cheaper direction that is allowed
+        let mut candidates = edges
+            .iter()
+            .filter_map(|(&(a, b), edge)| {
+                [(a, b, 0), (b, a, 1)]
+                    .iter()
+                    .copied()
+                    .filter(|&(from, _, end)| match mobility[from] {
+                        Mobility::Free => true,
+                        Mobility::Along => edge.is_special_at(end),
+                        Mobility::Locked => false,
+                    })
+                    .map(|(from, to, _)| {
+                        let mut quadric = self.quadrics[from];
+                        quadric.add(&self.quadrics[to]);
+                        (quadric.error(self.positions[to]), from, to)
+                    })
+                    .min_by(|a, b| a.0.total_cmp(&b.0))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        // Groups around a collapse have new triangles, so they wait for the next pass
+        let mut touched = vec![false; self.positions.len()];
+        let mut collapsed = false;
+        for (_, from, to) in candidates {
+            if self.alive_count <= target {
+                break;
+            }
+            if touched[from] || touched[to] {
+                continue;
+            }
+            if let Some(remap) = self.check_collapse(from, to) {
+                for neighbor in self.neighbors(from) {
+                    touched[neighbor] = true;
+                }
+                touched[from] = true;
+                self.collapse(from, to, &remap);
+                collapsed = true;
+            }
+        }
+        collapsed
+    }
+
+    /// Whether a group can be moved onto a neighbor, and if so, the vertex of the neighbor that
+    /// each of its vertices becomes
+    fn check_collapse(&self, from: usize, to: usize) -> Option<HashMap<u32, u32>> {
+        // Each vertex has to have exactly one vertex of the other group next to it, or the
+        // collapse would stretch the texture across a seam
+        let mut remap = HashMap::<u32, u32>::new();
+        let mut unmatched = HashSet::new();
+        for index in self.triangles_around(from) {
+            let triangle = self.triangles[index];
+            let vertex = *triangle
+                .iter()
+                .find(|&&vertex| self.group(vertex) == from)?;
+            match triangle.iter().find(|&&other| self.group(other) == to) {
+                Some(&other) => {
+                    if *remap.entry(vertex).or_insert(other) != other {
+                        return None;
+                    }
+                }
+                None => {
+                    unmatched.insert(vertex);
+                }
+            }
+        }
+        if unmatched.iter().any(|vertex| !remap.contains_key(vertex)) {
+            return None;
+        }
+
+        // The groups next to both ends have to be exactly the ones across the triangles on the
+        // edge, or the surface would fold onto itself
+        let shared_triangles = self
+            .triangles_around(from)
+            .filter(|&index| {
+                self.triangles[index]
+                    .iter()
+                    .any(|&vertex| self.group(vertex) == to)
+            })
+            .count();
+        let shared_neighbors = self
+            .neighbors(from)
+            .intersection(&self.neighbors(to))
+            .count();
+        if shared_triangles == 0 || shared_neighbors != shared_triangles {
+            return None;
+        }
+
+        // The triangles that stay mustn't flip over or lose their area
+        for index in self.triangles_around(from) {
+            let triangle = self.triangles[index];
+            if triangle.iter().any(|&vertex| self.group(vertex) == to) {
+                continue;
+            }
+            let before = self.corners(index);
+            let mut after = before;
+            for (corner, &vertex) in triangle.iter().enumerate() {
+                if self.group(vertex) == from {
+                    after[corner] = self.positions[to];
+                }
+            }
+            let normal_before = mesh::triangle_normal(before[0], before[1], before[2]);
+            let normal_after = mesh::triangle_normal(after[0], after[1], after[2]);
+            let length_before = mesh::dot(normal_before, normal_before).sqrt();
+            let length_after = mesh::dot(normal_after, normal_after).sqrt();
+            if mesh::dot(normal_before, normal_after)
+                < length_before * length_after * MIN_NORMAL_COSINE
+            {
+                return None;
+            }
+            // Thin triangles that were already there are left alone, as long as they don't get
+            // any thinner
+            let quality_after = quality(after, length_after);
+            if quality_after < MIN_QUALITY && quality_after < quality(before, length_before) {
+                return None;
+            }
+        }
+
+        Some(remap)
+    }
+
+    /// Move a group onto a neighbor, removing the triangles on the edge between them
+    fn collapse(&mut self, from: usize, to: usize, remap: &HashMap<u32, u32>) {
+        for index in std::mem::take(&mut self.group_triangles[from]) {
+            if !self.alive[index] {
+                continue;
+            }
+            let groups = &self.groups;
+            let triangle = &mut self.triangles[index];
+            if triangle.iter().any(|&vertex| groups[vertex as usize] == to) {
+                self.alive[index] = false;
+                self.alive_count -= 1;
+                continue;
+            }
+            for vertex in triangle.iter_mut() {
+                if let Some(&other) = remap.get(vertex) {
+                    *vertex = other;
+                }
+            }
+            self.group_triangles[to].push(index);
+        }
+        let alive = &self.alive;
+        self.group_triangles[to].retain(|&index| alive[index]);
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+    }
+
+    /// The triangles that are left, with the vertices that they still use
+    fn finish(self) -> MeshData {
+        let data = self.data;
+        let mut new_index = HashMap::new();
+        let mut result = MeshData::default();
+        for (triangle, _) in self
+            .triangles
+            .iter()
+            .zip(&self.alive)
+            .filter(|(_, &alive)| alive)
+        {
+            for &vertex in triangle {
+                let index = *new_index.entry(vertex).or_insert_with(|| {
+                    let vertex = vertex as usize;
+                    result.positions.push(data.positions[vertex]);
+                    if !data.normals.is_empty() {
+                        result.normals.push(data.normals[vertex]);
+                    }
+                    if !data.uvs.is_empty() {
+                        result.uvs.push(data.uvs[vertex]);
+                    }
+                    result.positions.len() as u32 - 1
+                });
+                result.indices.push(index);
+            }
+        }
+        result
+    }
+}
+
+/// How far a triangle is from being degenerate, from its corners and the length of its normal
+fn quality(corners: [[f32; 3]; 3], normal_length: f32) -> f32 {
+    let longest = (0..3)
+        .map(|corner| {
+            let edge = mesh::sub(corners[(corner + 1) % 3], corners[corner]);
+            mesh::dot(edge, edge)
+        })
+        .fold(0., f32::max);
+    if longest > 0. {
+        normal_length / longest
+    } else {
+        0.
+    }
+}
+
+/// Reduce a mesh to about `target_ratio` of its triangles
+///
+/// This collapses the edges that change the shape the least first, measured with quadric error
+/// metrics. Every vertex that is left is one of the original ones, so positions, normals, and
+/// texture coordinates are never blended. Boundaries and texture seams only collapse along
+/// themselves, and corners where they meet stay put, so the mesh may stop short of the target if
+/// there is nothing left that can be collapsed. Collapses that would flip a triangle or squash it
+/// flat are skipped.
+pub fn simplify(data: &MeshData, target_ratio: f32) -> MeshData {
+    let triangle_count = data.indices.len() / 3;
+    let target = (triangle_count as f32 * target_ratio.clamp(0., 1.)).round() as usize;
+    if target >= triangle_count {
+        return data.clone();
+    }
+
+    let mut simplifier = Simplifier::new(data);
+    while simplifier.alive_count > target && simplifier.pass(target) {}
+    simplifier.finish()
+}
+
+/// Levels of detail for a `LodMesh`, starting with the mesh itself, with each level having about
+/// `ratio` times the triangles of the one before it
+pub fn lod_chain(data: &MeshData, levels: usize, ratio: f32) -> Vec<MeshData> {
+    (0..levels)
+        .map(|level| match level {
+            0 => data.clone(),
+            _ => simplify(data, ratio.powi(level as i32)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::primitives;
+
+    /// A flat square of `quads` by `quads` quads facing up, with normals and texture coordinates
+    fn grid(quads: u32) -> MeshData {
+        let mut data = MeshData::default();
+        for row in 0..=quads {
+            for column in 0..=quads {
+                let (u, v) = (column as f32 / quads as f32, row as f32 / quads as f32);
+                data.positions.push([u * 2. - 1., 0., 1. - v * 2.]);
+                data.normals.push([0., 1., 0.]);
+                data.uvs.push([u, v]);
+            }
+        }
+        let columns = quads + 1;
+        for row in 0..quads {
+            for column in 0..quads {
+                let bottom_left = row * columns + column;
+                let top_left = bottom_left + columns;
+                data.indices.extend_from_slice(&[
+                    bottom_left,
+                    bottom_left + 1,
+                    top_left,
+                    bottom_left + 1,
+                    top_left + 1,
+                    top_left,
+                ]);
+            }
+        }
+        data
+    }
+
+    /// The smallest and largest corner of the box around the vertices that triangles use
+    fn bounds(data: &MeshData) -> ([f32; 3], [f32; 3]) {
+        data.indices.iter().fold(
+            ([f32::MAX; 3], [f32::MIN; 3]),
+            |(mut min, mut max), &index| {
+                let position = data.positions[index as usize];
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(position[axis]);
+                    max[axis] = max[axis].max(position[axis]);
+                }
+                (min, max)
+            },
+        )
+    }
+
+    fn triangle_count(data: &MeshData) -> usize {
+        data.indices.len() / 3
+    }
+
+    /// Check that a simplified mesh stopped at its target, give or take the two triangles that
+    /// one collapse removes
+    fn assert_near_target(data: &MeshData, target: usize) {
+        let count = triangle_count(data);
+        assert!(
+            count <= target && count + 2 >= target,
+            "{} triangles for a target of {}",
+            count,
+            target
+        );
+    }
+
+    #[test]
+    fn simplify_reaches_target() {
+        let data = grid(16);
+        let simplified = simplify(&data, 0.25);
+        assert_near_target(&simplified, triangle_count(&data) / 4);
+        assert_eq!(bounds(&simplified), bounds(&data));
+
+        // Every vertex that is left is one of the originals
+        assert!(simplified
+            .positions
+            .iter()
+            .all(|p| data.positions.contains(p)));
+        assert_eq!(simplified.normals.len(), simplified.positions.len());
+        assert_eq!(simplified.uvs.len(), simplified.positions.len());
+    }
+
+    #[test]
+    fn simplify_keeps_a_closed_mesh_closed() {
+        let data = primitives::uv_sphere(1., 32, 16);
+        let simplified = simplify(&data, 0.5);
+        assert_near_target(&simplified, triangle_count(&data) / 2);
+
+        // Every edge still has a triangle on both sides, once the seam is welded
+        let weld = |index: u32| {
+            let position = simplified.positions[index as usize];
+            position.map(|x| (x * 1e4).round() as i32)
+        };
+        let mut edges = HashMap::new();
+        for triangle in simplified.indices.chunks_exact(3) {
+            for corner in 0..3 {
+                let edge = (weld(triangle[corner]), weld(triangle[(corner + 1) % 3]));
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+        for (&(a, b), &count) in &edges {
+            assert_eq!(edges.get(&(b, a)), Some(&count), "Open edge {:?}", (a, b));
+        }
+    }
+
+    #[test]
+    fn simplify_full_ratio_is_unchanged() {
+        let data = grid(4);
+        assert_eq!(simplify(&data, 1.), data);
+        assert_eq!(simplify(&data, 2.), data);
+    }
+
+    #[test]
+    fn lod_chain_shrinks() {
+        let data = grid(16);
+        let chain = lod_chain(&data, 3, 0.5);
+        assert_eq!(chain.len(), 3);
+        assert_eq!(chain[0], data);
+        for (level, lod) in chain.iter().enumerate().skip(1) {
+            assert_near_target(lod, triangle_count(&data) >> level);
+            assert_eq!(bounds(lod), bounds(&data));
+        }
+        assert!(lod_chain(&data, 0, 0.5).is_empty());
+    }
+}
@@ -0,0 +1,356 @@
+use cgmath::{InnerSpace, Matrix4, Vector3};
+use glow::HasContext;
+use rand::{rngs::SmallRng, Rng, SeedableRng};
+
+use crate::{
+    debug_group::DebugGroup,
+    debug_scope,
+    gbuffer::{GBuffer, GBufferLayout, GBUFFER_CHUNK},
+    render_graph::{PassTarget, RenderPass, TargetSize},
+    render_target::RenderTarget,
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram, FULLSCREEN_VERTEX_SRC},
+};
+
+const SSAO_FRAGMENT_SRC: &str = include_str!("ssao/ssao.frag");
+const BLUR_FRAGMENT_SRC: &str = include_str!("ssao/blur.frag");
+
+/// The most samples the kernel can have, which matches the size of the kernel array in the shader
+pub const MAX_KERNEL_SIZE: u32 = 64;
+
+/// The size of the tiling noise texture, which the blur pass averages over
+const NOISE_SIZE: u32 = 4;
+
+/// The seed of the kernel and noise, so that the occlusion looks the same every run
+const SEED: u64 = 0x55a0;
+
+/// The size of the occlusion buffers compared to the G-buffer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SsaoResolution {
+    Full,
+    /// Half the width and height, which samples a quarter as many pixels. The occlusion is
+    /// scaled back up with linear filtering when it is sampled, which can bleed a little across
+    /// edges.
+    Half,
+}
+
+impl SsaoResolution {
+    /// The size of the occlusion buffers relative to the G-buffer
+    pub fn target_size(self) -> TargetSize {
+        match self {
+            SsaoResolution::Full => TargetSize::Window,
+            SsaoResolution::Half => TargetSize::Relative(0.5),
+        }
+    }
+
+    /// The size of the occlusion buffers for a G-buffer of the given size
+    pub fn scale(&self, gbuffer_size: (u32, u32)) -> (u32, u32) {
+        self.target_size().resolve(gbuffer_size)
+    }
+}
+
+/// The settings of an SSAO pass, which can be changed between frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SsaoParams {
+    /// How many points around each pixel to test, up to `MAX_KERNEL_SIZE`. More samples are
+    /// smoother and slower.
+    pub kernel_size: u32,
+    /// How far from each pixel to look for things that block the light, in view space units
+    pub radius: f32,
+    /// How far in front of a sample a surface has to be to block it, which keeps flat surfaces
+    /// from shadowing themselves
+    pub bias: f32,
+    /// Whether or not to blur the raw occlusion to hide the noise pattern
+    pub blur: bool,
+    pub resolution: SsaoResolution,
+}
+
+impl Default for SsaoParams {
+    fn default() -> Self {
+        Self {
+            kernel_size: 32,
+            radius: 0.5,
+            bias: 0.025,
+            blur: true,
+            resolution: SsaoResolution::Full,
+        }
+    }
+}
+
+/// The occlusion buffers, which follow the size of the G-buffer
+#[derive(Debug)]
+struct SsaoTargets {
+    raw: RenderTarget,
+    blurred: RenderTarget,
+}
+
+/// Screen-space ambient occlusion, which darkens creases and corners that ambient light has a
+/// hard time reaching
+///
+/// `render` reads the view space positions and normals of a `GBuffer` and writes how much of each
+/// pixel is open to ambient light, from 0 to 1, into `occlusion_texture`, which the lighting pass
+/// multiplies its ambient light by. The buffers follow the size of the G-buffer, so they are
+/// resized along with the window.
+#[derive(Debug)]
+pub struct SsaoPass {
+    pub params: SsaoParams,
+    ssao_program: ShaderProgram,
+    /// The G-buffer defines that `ssao_program` was compiled with
+    ssao_defines: Vec<&'static str>,
+    blur_program: ShaderProgram,
+    /// The points in the hemisphere around each pixel that are tested
+    kernel: Vec<Vector3<f32>>,
+    noise: u32,
+    targets: Option<SsaoTargets>,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+}
+
+/// Compile the occlusion shader for reading a G-buffer with the given `GBuffer::defines`
+fn ssao_program(gl: &mut glow::Context, defines: &[&str]) -> ShaderProgram {
+    let fragment = shader::include_chunk(SSAO_FRAGMENT_SRC, GBUFFER_CHUNK);
+    ShaderProgram::with_defines(gl, FULLSCREEN_VERTEX_SRC, &fragment, defines).unwrap()
+}
+
+impl SsaoPass {
+    pub fn new(gl: &mut glow::Context, params: SsaoParams) -> Self {
+        let ssao_defines = GBufferLayout::default().defines().to_vec();
+        let ssao_program = ssao_program(gl, &ssao_defines);
+        let blur_program =
+            ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, BLUR_FRAGMENT_SRC).unwrap();
+        let mut rng = SmallRng::seed_from_u64(SEED);
+
+        // Points in the hemisphere above the surface, closer together near the middle where
+        // occlusion matters most
+        let kernel = (0..MAX_KERNEL_SIZE)
+            .map(|i| {
+                let direction = Vector3::new(
+                    rng.gen_range(-1., 1.),
+                    rng.gen_range(-1., 1.),
+                    rng.gen_range(0., 1.),
+                )
+                .normalize();
+                let t = i as f32 / MAX_KERNEL_SIZE as f32;
+                let scale = 0.1 + t * t * 0.9;
+                direction * rng.gen_range(0., 1.) * scale
+            })
+            .collect();
+
+        // Random directions to rotate the kernel around the normal by
+        let noise_pixels = (0..NOISE_SIZE * NOISE_SIZE)
+            .flat_map(|_| {
+                let x: f32 = rng.gen_range(-1., 1.);
+                let y: f32 = rng.gen_range(-1., 1.);
+                [x, y, 0.]
+            })
+            .flat_map(|x| x.to_ne_bytes())
+            .collect::<Vec<_>>();
+
+        unsafe {
+            let noise = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(noise));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGB16F as i32,
+                NOISE_SIZE as i32,
+                NOISE_SIZE as i32,
+                0,
+                glow::RGB,
+                glow::FLOAT,
+                Some(&noise_pixels),
+            );
+            for (parameter, value) in [
+                (glow::TEXTURE_MIN_FILTER, glow::NEAREST),
+                (glow::TEXTURE_MAG_FILTER, glow::NEAREST),
+                (glow::TEXTURE_WRAP_S, glow::REPEAT),
+                (glow::TEXTURE_WRAP_T, glow::REPEAT),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            resources::track_sized(
+                ResourceKind::Texture,
+                noise,
+                "SSAO noise",
+                resources::texture_bytes(NOISE_SIZE, NOISE_SIZE, glow::RGB16F, 1, 1, 1),
+            );
+
+            let empty_vao = gl.create_vertex_array().unwrap();
+            resources::track(ResourceKind::VertexArray, empty_vao, "SSAO vertex array");
+
+            Self {
+                params,
+                ssao_program,
+                ssao_defines,
+                blur_program,
+                kernel,
+                noise,
+                targets: None,
+                empty_vao,
+            }
+        }
+    }
+
+    /// Make sure the occlusion buffers match a G-buffer of the given size and the resolution in
+    /// the params, recreating them if they don't
+    pub fn resize(&mut self, gl: &mut glow::Context, gbuffer_size: (u32, u32)) {
+        let size = self.params.resolution.target_size();
+        match &mut self.targets {
+            Some(targets) => {
+                targets.raw.set_size(gl, size, gbuffer_size);
+                targets.blurred.set_size(gl, size, gbuffer_size);
+            }
+            None => {
+                self.targets = Some(SsaoTargets {
+                    raw: RenderTarget::new(gl, "SSAO", size, glow::R8, gbuffer_size),
+                    blurred: RenderTarget::new(gl, "SSAO blur", size, glow::R8, gbuffer_size),
+                })
+            }
+        }
+    }
+
+    /// The size of the occlusion buffers, or `None` before the first `render` or `resize`
+    pub fn size(&self) -> Option<(u32, u32)> {
+        self.targets.as_ref().map(|targets| targets.raw.pixels())
+    }
+
+    /// A render pass for `render`, which draws into the occlusion buffers, or `None` before the
+    /// first `render` or `resize`
+    ///
+    /// `render` binds its own framebuffers, so the pass's target is only there to describe it. The
+    /// pass has to be made again when the resolution changes.
+    pub fn pass<P>(&self, id: P) -> Option<RenderPass<P>> {
+        self.targets.as_ref().map(|targets| {
+            RenderPass::new(id, PassTarget::Framebuffer(targets.raw.framebuffer))
+                .writes(targets.raw.texture)
+                .writes(targets.blurred.texture)
+                .size(self.params.resolution.target_size())
+        })
+    }
+
+    /// Work out the occlusion from a G-buffer's view space positions and normals
+    ///
+    /// `projection` is the projection the G-buffer was drawn with. The occlusion buffers are
+    /// resized if the G-buffer's size changed, and the shader is compiled again if its layout
+    /// did. The framebuffer that was bound is bound
+    /// again afterwards, but the viewport is left at the size of the occlusion buffers.
+    ///
+    /// A multisampled G-buffer is shaded once for every sample and the occlusion averaged, which
+    /// keeps the edges of the occlusion lined up with the edges of the lit samples but costs a
+    /// full pass for each sample. Passing its `single_sample` G-buffer instead, once resolved,
+    /// shades each pixel once.
+    pub fn render(&mut self, gl: &mut glow::Context, gbuffer: &GBuffer, projection: Matrix4<f32>) {
+        self.resize(gl, gbuffer.size);
+        let defines = gbuffer.defines();
+        if defines != self.ssao_defines {
+            self.ssao_program.delete(gl);
+            self.ssao_program = ssao_program(gl, &defines);
+            self.ssao_defines = defines;
+        }
+        let targets = self.targets.as_ref().unwrap();
+        let params = self.params;
+        let (width, height) = targets.raw.pixels();
+        let _group = DebugGroup::push(gl, "SSAO");
+
+        unsafe {
+            let previous_framebuffer = gl.get_parameter_i32(glow::DRAW_FRAMEBUFFER_BINDING) as u32;
+            gl.viewport(0, 0, width as i32, height as i32);
+            let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+            gl.disable(glow::DEPTH_TEST);
+            gl.bind_vertex_array(Some(self.empty_vao));
+
+            // Test the kernel around every pixel
+            let program = &mut self.ssao_program;
+            let (kernel, noise) = (&self.kernel, self.noise);
+            debug_scope!(gl, "Occlusion", {
+                gl.bind_framebuffer(glow::FRAMEBUFFER, Some(targets.raw.framebuffer));
+                program.bind(gl);
+                let noise_unit = gbuffer.bind(gl, program, 0, projection);
+                gl.active_texture(glow::TEXTURE0 + noise_unit);
+                gl.bind_texture(glow::TEXTURE_2D, Some(noise));
+                program.set_uniform(gl, "noise", noise_unit as i32);
+                program.set_uniform(
+                    gl,
+                    "noiseScale",
+                    [
+                        width as f32 / NOISE_SIZE as f32,
+                        height as f32 / NOISE_SIZE as f32,
+                    ],
+                );
+                let kernel_size = params.kernel_size.clamp(1, MAX_KERNEL_SIZE);
+                program.set_uniform(gl, "kernelSize", kernel_size as i32);
+                for (i, sample) in kernel.iter().take(kernel_size as usize).enumerate() {
+                    program.set_uniform(gl, &format!("kernel[{}]", i), *sample);
+                }
+                program.set_uniform(gl, "radius", params.radius);
+                program.set_uniform(gl, "bias", params.bias);
+                program.set_uniform(gl, "projection", projection);
+                gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            });
+
+            // Then blur away the noise
+            if params.blur {
+                let program = &mut self.blur_program;
+                debug_scope!(gl, "Blur", {
+                    gl.bind_framebuffer(glow::FRAMEBUFFER, Some(targets.blurred.framebuffer));
+                    program.bind(gl);
+                    program.set_uniform(gl, "occlusion", 0);
+                    gl.active_texture(glow::TEXTURE0);
+                    gl.bind_texture(glow::TEXTURE_2D, Some(targets.raw.texture));
+                    gl.draw_arrays(glow::TRIANGLES, 0, 3);
+                });
+            }
+
+            gl.bind_vertex_array(None);
+            if depth_test {
+                gl.enable(glow::DEPTH_TEST);
+            }
+            gl.bind_framebuffer(
+                glow::FRAMEBUFFER,
+                if previous_framebuffer == 0 {
+                    None
+                } else {
+                    Some(previous_framebuffer)
+                },
+            );
+        }
+    }
+
+    /// The occlusion from the last `render`, blurred if the blur is on, or `None` before the
+    /// first `render`
+    pub fn occlusion_texture(&self) -> Option<u32> {
+        self.targets.as_ref().map(|targets| {
+            if self.params.blur {
+                targets.blurred.texture
+            } else {
+                targets.raw.texture
+            }
+        })
+    }
+
+    /// The occlusion from the last `render` before it was blurred, e.g. to show the noise pattern
+    pub fn raw_occlusion_texture(&self) -> Option<u32> {
+        self.targets.as_ref().map(|targets| targets.raw.texture)
+    }
+
+    fn delete_targets(&mut self, gl: &mut glow::Context) {
+        if let Some(targets) = self.targets.take() {
+            targets.raw.delete(gl);
+            targets.blurred.delete(gl);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.delete_targets(gl);
+        unsafe {
+            gl.delete_texture(self.noise);
+            gl.delete_vertex_array(self.empty_vao);
+        }
+        resources::untrack(ResourceKind::Texture, self.noise);
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+        self.ssao_program.delete(gl);
+        self.blur_program.delete(gl);
+    }
+}
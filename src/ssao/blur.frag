@@ -0,0 +1,19 @@
+#version 330 core
+
+in vec2 texCoord;
+
+out float blurred;
+
+uniform sampler2D occlusion;
+
+void main() {
+    // Average a block the size of the noise texture, which cancels out its pattern
+    vec2 texel = 1.0 / vec2(textureSize(occlusion, 0));
+    float sum = 0.0;
+    for (int x = -2; x < 2; x++) {
+        for (int y = -2; y < 2; y++) {
+            sum += texture(occlusion, texCoord + vec2(x, y) * texel).r;
+        }
+    }
+    blurred = sum / 16.0;
+}
@@ -0,0 +1,67 @@
+#version 330 core
+
+in vec2 texCoord;
+
+out float occlusion;
+
+// The G-buffer is read with the functions from `GBUFFER_CHUNK`, which is included above
+
+// Random rotations around the normal, tiled over the screen
+uniform sampler2D noise;
+uniform vec2 noiseScale;
+
+const int MAX_KERNEL_SIZE = 64;
+// Points in a hemisphere around +Z, more of them close to the middle
+uniform vec3 kernel[MAX_KERNEL_SIZE];
+uniform int kernelSize;
+uniform float radius;
+uniform float bias;
+uniform mat4 projection;
+
+// How open the surface of the current G-buffer sample is to ambient light
+float sampleOcclusion() {
+    vec4 positionSample = gbufferPosition(texCoord);
+    if (positionSample.a == 0.0) {
+        return 1.0;
+    }
+    vec3 position = positionSample.xyz;
+    vec3 normal = gbufferNormal(texCoord);
+
+    // Turn the kernel around the normal by a random angle, so that neighbouring pixels sample
+    // different points and the banding turns into noise that the blur removes
+    vec3 random = texture(noise, texCoord * noiseScale).xyz;
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occluded = 0.0;
+    for (int i = 0; i < kernelSize; i++) {
+        vec3 samplePosition = position + tbn * kernel[i] * radius;
+
+        // Find where the sample is on screen, and how far away the surface drawn there is
+        vec4 projected = projection * vec4(samplePosition, 1.0);
+        vec2 sampleCoord = projected.xy / projected.w * 0.5 + 0.5;
+        float surfaceDepth = gbufferPosition(sampleCoord).z;
+
+        // Surfaces far in front of this one, like the edge of something in the foreground,
+        // shouldn't darken it
+        float range = smoothstep(0.0, 1.0, radius / abs(position.z - surfaceDepth));
+        occluded += (surfaceDepth >= samplePosition.z + bias ? 1.0 : 0.0) * range;
+    }
+    return 1.0 - occluded / float(max(kernelSize, 1));
+}
+
+void main() {
+    // Every sample of a multisampled G-buffer is shaded, so that pixels on an edge get some of
+    // the occlusion of both surfaces
+    float total = 0.0;
+    int samples = gbufferSamples();
+#ifdef GBUFFER_MULTISAMPLE
+    for (gSample = 0; gSample < samples; gSample++) {
+        total += sampleOcclusion();
+    }
+#else
+    total = sampleOcclusion();
+#endif
+    occlusion = total / float(samples);
+}
@@ -0,0 +1,283 @@
+use std::fmt;
+
+use cgmath::{Matrix4, Vector3};
+use glow::HasContext;
+
+use crate::{camera::FlyCamera, frustum::Frustum, pose::PoseProvider, viewport::Rect, AppContext};
+
+/// The distance between the eyes of the default stereo mode, in world units, which is about the
+/// distance between a person's eyes in meters
+pub const DEFAULT_EYE_SEPARATION: f32 = 0.065;
+/// The distance that the default stereo mode's eyes converge at, in world units
+pub const DEFAULT_CONVERGENCE: f32 = 10.;
+
+/// One of the two eyes of a stereo view
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    /// Which side of the camera the eye is on, -1 for the left and 1 for the right
+    pub fn side(self) -> f32 {
+        match self {
+            Eye::Left => -1.,
+            Eye::Right => 1.,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Eye::Left => "left eye",
+            Eye::Right => "right eye",
+        }
+    }
+}
+
+/// How the scene is drawn for the two eyes, set with `RenderSettings::stereo` ( toggled with
+/// F10 )
+///
+/// Only handlers that draw through `render_eyes`, or `render_eyes_with_pose` for a tracked head,
+/// are drawn in stereo. The loop's own post-processing, like FXAA and virtual resolution, runs
+/// once on both eyes together, which is fine for effects that only look at one pixel or its close
+/// neighbors. Effects that blur further, like bloom, should run for each eye inside of
+/// `render_eyes`, or they bleed across the middle of the window. The console, the frame graph,
+/// and anything else drawn after `render_eyes` cover the whole window.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StereoMode {
+    /// Draw the scene once, from the camera
+    #[default]
+    Off,
+    /// Draw the scene twice, side by side, for cross-eyed viewing: the right eye's view is on the
+    /// left half of the window and the left eye's is on the right half
+    SideBySide {
+        /// How far apart the eyes are, in world units
+        eye_separation: f32,
+        /// The distance in front of the camera where the two views line up, which looks like
+        /// it is at the depth of the screen. Nearer things pop out and further things sink in.
+        convergence: f32,
+    },
+}
+
+impl StereoMode {
+    /// Side by side stereo with the default separation and convergence
+    pub fn side_by_side() -> Self {
+        StereoMode::SideBySide {
+            eye_separation: DEFAULT_EYE_SEPARATION,
+            convergence: DEFAULT_CONVERGENCE,
+        }
+    }
+
+    /// Turn stereo off if it is on, or on with the default separation and convergence if it is
+    /// off
+    pub fn toggled(self) -> Self {
+        match self {
+            StereoMode::Off => Self::side_by_side(),
+            StereoMode::SideBySide { .. } => StereoMode::Off,
+        }
+    }
+
+    /// The views to draw into `rect` in this mode, one for each eye or one for the camera when
+    /// stereo is off
+    pub fn views(self, rect: Rect) -> Vec<EyeView> {
+        match self {
+            StereoMode::Off => vec![EyeView {
+                eye: None,
+                viewport: rect,
+                eye_separation: 0.,
+                convergence: DEFAULT_CONVERGENCE,
+            }],
+            StereoMode::SideBySide {
+                eye_separation,
+                convergence,
+            } => {
+                let half = rect.width / 2;
+                [
+                    (Eye::Right, Rect::new(rect.x, rect.y, half, rect.height)),
+                    (
+                        Eye::Left,
+                        Rect::new(rect.x + half, rect.y, rect.width - half, rect.height),
+                    ),
+                ]
+                .iter()
+                .map(|&(eye, viewport)| EyeView {
+                    eye: Some(eye),
+                    viewport,
+                    eye_separation,
+                    convergence,
+                })
+                .collect()
+            }
+        }
+    }
+}
+
+impl fmt::Display for StereoMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StereoMode::Off => write!(f, "off"),
+            StereoMode::SideBySide {
+                eye_separation,
+                convergence,
+            } => write!(
+                f,
+                "side by side, {} apart converging at {}",
+                eye_separation, convergence
+            ),
+        }
+    }
+}
+
+/// Where and how to draw the scene for one eye, from `render_eyes`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EyeView {
+    /// The eye to draw for, or `None` when stereo is off
+    pub eye: Option<Eye>,
+    /// The part of the window to draw into, which is already set as the viewport and the
+    /// scissor box in `render_eyes`
+    pub viewport: Rect,
+    pub eye_separation: f32,
+    pub convergence: f32,
+}
+
+impl EyeView {
+    /// The aspect ratio to make the eye's projection matrix with
+    pub fn aspect_ratio(&self) -> f32 {
+        self.viewport.aspect_ratio()
+    }
+
+    /// Move a camera's view matrix to this eye, leaving it as it is when stereo is off
+    pub fn view(&self, view: Matrix4<f32>) -> Matrix4<f32> {
+        match self.eye {
+            Some(eye) => eye_offset(eye, self.eye_separation, self.convergence) * view,
+            None => view,
+        }
+    }
+
+    /// The name of the eye's view in `AppContext::viewports`, or `scene` when stereo is off
+    pub fn name(&self) -> &'static str {
+        self.eye.map_or("scene", Eye::name)
+    }
+
+    /// Register the eye's part of the window in `AppContext::viewports`, with the view and
+    /// projection matrices that it is drawn with
+    pub fn register(&self, ctx: &AppContext, view: Matrix4<f32>, projection: Matrix4<f32>) {
+        ctx.viewports
+            .register(self.name(), self.viewport, view, projection);
+    }
+
+    /// The view matrix of a fly camera for this eye
+    pub fn camera_view(&self, camera: &FlyCamera) -> Matrix4<f32> {
+        match self.eye {
+            Some(eye) => camera.eye_view(eye, self.eye_separation, self.convergence),
+            None => camera.view_matrix(),
+        }
+    }
+
+    /// The view and projection matrices of a fly camera for this eye
+    pub fn camera_matrices(&self, camera: &FlyCamera) -> EyeMatrices {
+        EyeMatrices {
+            view: self.camera_view(camera),
+            projection: camera.projection_matrix(self.aspect_ratio()),
+        }
+    }
+}
+
+/// The view and projection matrices that one eye is drawn with
+///
+/// Each eye sees a slightly different part of the scene, so culling should use the eye's own
+/// `frustum` rather than the camera's.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EyeMatrices {
+    pub view: Matrix4<f32>,
+    pub projection: Matrix4<f32>,
+}
+
+impl EyeMatrices {
+    pub fn view_projection(&self) -> Matrix4<f32> {
+        self.projection * self.view
+    }
+
+    /// The part of the scene that the eye can see
+    pub fn frustum(&self) -> Frustum {
+        Frustum::from_view_projection(&self.view_projection())
+    }
+}
+
+/// The transform that moves a view matrix from the camera to one of its eyes
+///
+/// The eye moves half of `separation` to its side, and the view is sheared so that things at
+/// `convergence` in front of the camera stay where they were. That is the same as giving each
+/// eye a frustum that is off to the side, which lines the two views up without the vertical
+/// differences that turning the eyes in towards each other would make.
+pub fn eye_offset(eye: Eye, separation: f32, convergence: f32) -> Matrix4<f32> {
+    let offset = eye.side() * separation / 2.;
+    // View space looks along -Z, so the shift grows with -z, and is `offset` at the convergence
+    let shear = -offset / convergence.max(f32::EPSILON);
+    let mut shear_matrix = Matrix4::from_scale(1.);
+    shear_matrix.z.x = shear;
+    shear_matrix * Matrix4::from_translation(Vector3::new(-offset, 0., 0.))
+}
+
+/// Draw the scene once for each eye of `RenderSettings::stereo`, or once across the whole render
+/// size when stereo is off
+///
+/// The viewport and the scissor box are set to each eye's half of the window while `draw` runs,
+/// so clearing inside of it only clears that eye. Afterwards the viewport covers the whole window
+/// again and the scissor test is back to whether it was on, so overlays drawn after this cover
+/// both eyes once.
+pub fn render_eyes<F: FnMut(&mut glow::Context, &EyeView)>(
+    gl: &mut glow::Context,
+    ctx: &AppContext,
+    mut draw: F,
+) {
+    let full = Rect::from_window_size(ctx.render_size());
+    let stereo = ctx.render_settings.stereo;
+    if stereo == StereoMode::Off {
+        full.set_viewport(gl);
+        draw(gl, &stereo.views(full)[0]);
+        return;
+    }
+
+    unsafe {
+        let scissor_was_enabled = gl.is_enabled(glow::SCISSOR_TEST);
+        gl.enable(glow::SCISSOR_TEST);
+        for view in stereo.views(full) {
+            view.viewport.set_viewport(gl);
+            view.viewport.set_scissor(gl);
+            draw(gl, &view);
+        }
+        full.set_viewport(gl);
+        full.set_scissor(gl);
+        if !scissor_was_enabled {
+            gl.disable(glow::SCISSOR_TEST);
+        }
+    }
+}
+
+/// Draw the scene for each eye like `render_eyes`, from the head of a pose provider sitting on a
+/// camera
+///
+/// The provider samples the pose right before the first eye is drawn, which is the latest point
+/// of the frame, so everything the handler does before this call should go before it. Each eye is
+/// registered in `AppContext::viewports` with the matrices it is drawn with, and `draw` gets them
+/// too. Stereo doesn't have to be on, since the head moves the single view as well.
+pub fn render_eyes_with_pose<P, F>(
+    gl: &mut glow::Context,
+    ctx: &AppContext,
+    provider: &mut P,
+    camera: &FlyCamera,
+    mut draw: F,
+) where
+    P: PoseProvider + ?Sized,
+    F: FnMut(&mut glow::Context, &EyeView, &EyeMatrices),
+{
+    provider.sample(ctx);
+    let provider = &*provider;
+    render_eyes(gl, ctx, |gl, view| {
+        let matrices = provider.eye_matrices(view, camera);
+        view.register(ctx, matrices.view, matrices.projection);
+        draw(gl, view, &matrices);
+    });
+}
@@ -0,0 +1,239 @@
+use std::path::Path;
+
+use glow::HasContext;
+
+use crate::{
+    frustum::{Aabb, Frustum},
+    heightmap::Heightmap,
+    mesh::{Mesh, MeshData, NormalMode},
+};
+
+/// The settings used to build a terrain from a heightmap
+#[derive(Clone, Debug)]
+pub struct TerrainParams {
+    /// The distance between heightmap pixels in world units
+    pub scale: f32,
+    /// The height of a white heightmap pixel in world units
+    pub height_scale: f32,
+    /// The number of grid squares along each side of a chunk. Every chunk gets its own mesh and
+    /// bounding box, and must have few enough vertices for 16 bit indices.
+    pub chunk_size: u32,
+    /// Only use every Nth heightmap pixel, to keep huge heightmaps manageable
+    pub decimation: u32,
+}
+
+impl Default for TerrainParams {
+    fn default() -> Self {
+        Self {
+            scale: 1.,
+            height_scale: 1.,
+            chunk_size: 64,
+            decimation: 1,
+        }
+    }
+}
+
+/// One piece of a terrain that can be culled on its own
+#[derive(Clone, Debug)]
+pub struct TerrainChunk {
+    pub mesh: Mesh,
+    /// The world space bounds of the chunk
+    pub bounds: Aabb,
+}
+
+/// A grid mesh displaced by a heightmap, split into chunks for frustum culling
+///
+/// The vertex attributes are the same as for `Mesh`: the position at location 0, the normal at
+/// location 1, and the texture coordinate at location 2. The terrain is centered on the origin
+/// horizontally.
+#[derive(Clone, Debug)]
+pub struct Terrain {
+    pub chunks: Vec<TerrainChunk>,
+    /// Whether or not to draw the terrain as lines
+    pub wireframe: bool,
+}
+
+impl Terrain {
+    /// Build a terrain from a grayscale heightmap image
+    pub fn from_heightmap<P: AsRef<Path>>(
+        gl: &mut glow::Context,
+        path: P,
+        scale: f32,
+        height_scale: f32,
+    ) -> Self {
+        Self::from_heightmap_with_params(
+            gl,
+            path,
+            &TerrainParams {
+                scale,
+                height_scale,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Build a terrain from a grayscale heightmap image with more control over the mesh
+    pub fn from_heightmap_with_params<P: AsRef<Path>>(
+        gl: &mut glow::Context,
+        path: P,
+        params: &TerrainParams,
+    ) -> Self {
+        let heightmap = Heightmap::open(path);
+        let grid = Grid::new(&heightmap, params);
+
+        // Build the meshes one chunk at a time so that we never have the whole terrain in memory
+        let chunk_size = params.chunk_size.clamp(1, 254);
+        let mut chunks = Vec::new();
+        for chunk_y in (0..grid.rows - 1).step_by(chunk_size as usize) {
+            for chunk_x in (0..grid.columns - 1).step_by(chunk_size as usize) {
+                let data = grid.chunk_mesh_data(
+                    chunk_x,
+                    chunk_y,
+                    chunk_size.min(grid.columns - 1 - chunk_x),
+                    chunk_size.min(grid.rows - 1 - chunk_y),
+                );
+                chunks.push(TerrainChunk {
+                    bounds: Aabb::from_points(&data.positions).unwrap(),
+                    mesh: Mesh::new(gl, &data),
+                });
+            }
+        }
+
+        Self {
+            chunks,
+            wireframe: false,
+        }
+    }
+
+    /// Draw the chunks of the terrain that are inside of the frustum with the current shader
+    /// program, returning the number of chunks that were drawn
+    ///
+    /// Passing `None` for the frustum draws every chunk.
+    pub fn draw(&self, gl: &mut glow::Context, frustum: Option<&Frustum>) -> usize {
+        if self.wireframe {
+            unsafe { gl.polygon_mode(glow::FRONT_AND_BACK, glow::LINE) };
+        }
+
+        let mut drawn = 0;
+        for chunk in &self.chunks {
+            if frustum.is_none_or(|f| f.intersects_aabb(&chunk.bounds)) {
+                chunk.mesh.draw(gl);
+                drawn += 1;
+            }
+        }
+
+        if self.wireframe {
+            unsafe { gl.polygon_mode(glow::FRONT_AND_BACK, glow::FILL) };
+        }
+
+        drawn
+    }
+
+    /// Delete the GL objects of the terrain
+    pub fn delete(&self, gl: &mut glow::Context) {
+        for chunk in &self.chunks {
+            chunk.mesh.delete(gl);
+        }
+    }
+}
+
+/// The decimated grid of heightmap samples that the terrain is built from
+struct Grid<'a> {
+    heightmap: &'a Heightmap,
+    params: &'a TerrainParams,
+    /// The number of samples in each row
+    columns: u32,
+    /// The number of rows of samples
+    rows: u32,
+}
+
+impl<'a> Grid<'a> {
+    fn new(heightmap: &'a Heightmap, params: &'a TerrainParams) -> Self {
+        let decimation = params.decimation.max(1);
+        Self {
+            heightmap,
+            params,
+            columns: (heightmap.width - 1) / decimation + 1,
+            rows: (heightmap.height - 1) / decimation + 1,
+        }
+    }
+
+    /// The position and texture coordinate of a sample, which may be outside of the grid
+    fn vertex(&self, x: i64, y: i64) -> ([f32; 3], [f32; 2]) {
+        let decimation = self.params.decimation.max(1) as i64;
+        let (width, height) = (self.heightmap.width as i64, self.heightmap.height as i64);
+
+        // Samples outside of the grid are clamped to its edge
+        let pixel_x = (x * decimation).clamp(0, width - 1);
+        let pixel_y = (y * decimation).clamp(0, height - 1);
+
+        (
+            [
+                (pixel_x as f32 - (width - 1) as f32 / 2.) * self.params.scale,
+                self.heightmap.get(pixel_x as u32, pixel_y as u32) * self.params.height_scale,
+                (pixel_y as f32 - (height - 1) as f32 / 2.) * self.params.scale,
+            ],
+            [
+                pixel_x as f32 / (width - 1).max(1) as f32,
+                pixel_y as f32 / (height - 1).max(1) as f32,
+            ],
+        )
+    }
+
+    /// Build the mesh for the chunk of grid squares starting at the given sample
+    fn chunk_mesh_data(&self, x: u32, y: u32, width: u32, height: u32) -> MeshData {
+        // Build the chunk with an extra ring of samples around it so that the normals on its edges
+        // take the neighboring chunks into account and there are no seams in the lighting
+        let (columns, rows) = (width + 3, height + 3);
+        let mut with_border = grid_mesh_data(columns, rows, |column, row| {
+            self.vertex(x as i64 + column as i64 - 1, y as i64 + row as i64 - 1)
+        });
+        with_border.compute_normals(NormalMode::Smooth);
+
+        // Then keep only the inside of the chunk
+        let mut data = grid_mesh_data(width + 1, height + 1, |column, row| {
+            self.vertex(x as i64 + column as i64, y as i64 + row as i64)
+        });
+        data.normals = (1..rows - 1)
+            .flat_map(|row| (1..columns - 1).map(move |column| (row * columns + column) as usize))
+            .map(|i| with_border.normals[i])
+            .collect();
+
+        data
+    }
+}
+
+/// Build a grid of vertices with two triangles for every square between them
+fn grid_mesh_data<F: Fn(u32, u32) -> ([f32; 3], [f32; 2])>(
+    columns: u32,
+    rows: u32,
+    vertex: F,
+) -> MeshData {
+    let mut data = MeshData::default();
+
+    for row in 0..rows {
+        for column in 0..columns {
+            let (position, uv) = vertex(column, row);
+            data.positions.push(position);
+            data.uvs.push(uv);
+        }
+    }
+
+    // Two counter-clockwise triangles, seen from above, for each square
+    for row in 0..rows - 1 {
+        for column in 0..columns - 1 {
+            let top_left = row * columns + column;
+            let bottom_left = top_left + columns;
+            data.indices.extend_from_slice(&[
+                top_left,
+                bottom_left,
+                top_left + 1,
+                top_left + 1,
+                bottom_left,
+                bottom_left + 1,
+            ]);
+        }
+    }
+
+    data
+}
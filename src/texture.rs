@@ -0,0 +1,445 @@
+use std::path::Path;
+
+use glow::HasContext;
+
+use crate::{
+    fallback_assets,
+    features::Features,
+    handle::Handle,
+    mipmap::{generate_mip_chain, MipmapMode},
+    resources::{self, ResourceKind},
+    texture_audit, workarounds,
+};
+
+/// How the color channels of an image relate to its alpha channel
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    /// The image has no alpha channel
+    Opaque,
+    /// The color channels are stored as is, independent of the alpha. This is what image files
+    /// usually contain.
+    Straight,
+    /// The color channels have already been multiplied by the alpha. Filtering and blending these
+    /// doesn't bleed the color of fully transparent pixels into their neighbors.
+    Premultiplied,
+}
+
+/// What a texture's texels are, which decides whether its format should be sRGB or linear
+///
+/// `texture_audit` warns about textures whose format doesn't suit their purpose.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TexturePurpose {
+    /// Surface colors that get lit, which should be sRGB so that lighting works on linear colors
+    Albedo,
+    /// Tangent space normals, which should be linear so that the vectors aren't bent
+    NormalMap,
+    /// Values that aren't colors, like heights, roughness, or noise, which should be linear
+    Data,
+    /// Colors drawn as they are, like sprites and text, which can be either
+    Ui,
+}
+
+impl TexturePurpose {
+    pub fn name(self) -> &'static str {
+        match self {
+            TexturePurpose::Albedo => "albedo",
+            TexturePurpose::NormalMap => "normal map",
+            TexturePurpose::Data => "data",
+            TexturePurpose::Ui => "UI",
+        }
+    }
+}
+
+/// The decoded pixels of one image, or one mip level of an image
+#[derive(Clone, Debug)]
+pub struct ImageData {
+    pub width: u32,
+    pub height: u32,
+    /// The pixel format, either `glow::RGB` or `glow::RGBA`
+    pub format: u32,
+    /// How the color channels relate to the alpha channel
+    pub alpha: AlphaMode,
+    /// Tightly packed 8 bit pixel data, starting at the bottom left
+    pub pixels: Vec<u8>,
+}
+
+impl ImageData {
+    /// Load and decode an image file
+    ///
+    /// A missing file is replaced with `fallback_assets::image`, with a warning, so that the
+    /// examples still run without the asset directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref();
+        if !path.is_file() {
+            return fallback_assets::image(path);
+        }
+        Self::try_open(path).unwrap()
+    }
+
+    /// Load and decode an image file, returning an error if it is missing or can't be decoded
+    pub fn try_open<P: AsRef<Path>>(path: P) -> image::ImageResult<Self> {
+        Ok(Self::from_image(image::open(path)?))
+    }
+
+    /// Decode an image file that has already been read, like one built into the binary
+    pub fn from_memory(bytes: &[u8]) -> image::ImageResult<Self> {
+        Ok(Self::from_image(image::load_from_memory(bytes)?))
+    }
+
+    /// Take the pixels of a decoded image, converting them to RGB or RGBA
+    fn from_image(img: image::DynamicImage) -> Self {
+        let (width, height, pixels, format) = match img {
+            image::DynamicImage::ImageRgb8(img) => {
+                (img.width(), img.height(), img.into_raw(), glow::RGB)
+            }
+            image::DynamicImage::ImageRgba8(img) => {
+                (img.width(), img.height(), img.into_raw(), glow::RGBA)
+            }
+            // Convert anything else to RGBA
+            img => {
+                let img = img.into_rgba();
+                (img.width(), img.height(), img.into_raw(), glow::RGBA)
+            }
+        };
+
+        Self {
+            width,
+            height,
+            format,
+            alpha: if format == glow::RGB {
+                AlphaMode::Opaque
+            } else {
+                AlphaMode::Straight
+            },
+            pixels,
+        }
+    }
+
+    /// Multiply the color channels of a straight alpha image by its alpha channel
+    pub fn premultiply_alpha(&mut self) {
+        if self.alpha != AlphaMode::Straight {
+            return;
+        }
+
+        for pixel in self.pixels.chunks_exact_mut(4) {
+            let alpha = pixel[3] as u32;
+            for channel in &mut pixel[0..3] {
+                // Multiply and round, treating the bytes as values from 0 to 1
+                *channel = ((*channel as u32 * alpha + 127) / 255) as u8;
+            }
+        }
+        self.alpha = AlphaMode::Premultiplied;
+    }
+
+    /// The number of bytes in each pixel
+    pub fn channels(&self) -> usize {
+        match self.format {
+            glow::RGB => 3,
+            _ => 4,
+        }
+    }
+
+    /// The sized internal format that matches the pixel format, with the color channels decoded
+    /// from sRGB when they are sampled if `srgb` is set
+    fn internal_format(&self, srgb: bool) -> u32 {
+        match (self.format, srgb) {
+            (glow::RGB, false) => glow::RGB8,
+            (glow::RGB, true) => glow::SRGB8,
+            (_, false) => glow::RGBA8,
+            (_, true) => glow::SRGB8_ALPHA8,
+        }
+    }
+}
+
+/// The settings used to create a texture
+#[derive(Clone, Debug)]
+pub struct TextureParams {
+    pub wrap_s: u32,
+    pub wrap_t: u32,
+    pub min_filter: u32,
+    pub mag_filter: u32,
+    /// Whether or not to premultiply the alpha of straight alpha images before uploading them.
+    /// Textures that will be drawn with `BlendMode::Premultiplied` need this.
+    pub premultiply: bool,
+    /// How to create the mip levels that aren't passed to `create_texture_2d`
+    pub mipmaps: MipmapMode,
+    /// The number of mip levels to allocate, or `None` for the full mip chain down to 1x1
+    pub mip_levels: Option<u32>,
+    /// The highest resolution mip level that may be sampled
+    pub base_level: u32,
+    /// The lowest resolution mip level that may be sampled, or `None` for the last allocated level
+    pub max_level: Option<u32>,
+    /// Whether or not the pixels are sRGB encoded colors, which GL decodes to linear colors when
+    /// they are sampled
+    pub srgb: bool,
+    /// What the texels are, or `None` to leave the texture out of the texture audit
+    pub purpose: Option<TexturePurpose>,
+    /// A name for the texture in warnings and resource reports, which defaults to its size, or to
+    /// the path for `load_texture`
+    pub label: Option<String>,
+}
+
+impl Default for TextureParams {
+    fn default() -> Self {
+        Self {
+            wrap_s: glow::REPEAT,
+            wrap_t: glow::REPEAT,
+            min_filter: glow::LINEAR_MIPMAP_LINEAR,
+            mag_filter: glow::LINEAR,
+            premultiply: false,
+            mipmaps: MipmapMode::DriverGenerate,
+            mip_levels: None,
+            base_level: 0,
+            max_level: None,
+            srgb: false,
+            purpose: None,
+            label: None,
+        }
+    }
+}
+
+/// A 2D texture created by `create_texture_2d`
+#[derive(Clone, Debug)]
+pub struct Texture {
+    /// The GL texture object
+    pub texture: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The number of mip levels allocated for the texture
+    pub mip_levels: u32,
+    /// Whether or not the texture was allocated with immutable storage
+    pub immutable: bool,
+    /// How the color channels of the texture relate to its alpha channel, which should match the
+    /// blend mode it is drawn with
+    pub alpha: AlphaMode,
+    /// Whether or not the texture has an sRGB format
+    pub srgb: bool,
+    pub purpose: Option<TexturePurpose>,
+    pub label: String,
+}
+
+impl Texture {
+    /// Delete the GL texture
+    pub fn delete(&self, gl: &mut glow::Context) {
+        unsafe { gl.delete_texture(self.texture) };
+        resources::untrack(ResourceKind::Texture, self.texture);
+        texture_audit::forget(self.texture);
+    }
+
+    /// A handle to the texture that can tell when it has been deleted, for keeping instead of the
+    /// raw `texture`, or `None` if the current context isn't tracked
+    pub fn handle(&self) -> Option<Handle<Texture>> {
+        Handle::new(self.texture)
+    }
+
+    /// Limit the mip levels that may be sampled from the texture
+    ///
+    /// Setting `base_level` and `max_level` to the same level is a handy way to see what a single
+    /// mip level looks like.
+    pub fn set_level_range(&self, gl: &mut glow::Context, base_level: u32, max_level: u32) {
+        let max_level = max_level.min(self.mip_levels - 1);
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.texture));
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_BASE_LEVEL,
+                base_level.min(max_level) as i32,
+            );
+            gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_MAX_LEVEL, max_level as i32);
+        }
+    }
+}
+
+/// The number of levels in a full mip chain for a texture of the given size
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Load an image file into a new 2D texture
+pub fn load_texture<P: AsRef<Path>>(
+    gl: &mut glow::Context,
+    features: &Features,
+    path: P,
+    params: &TextureParams,
+) -> Texture {
+    let params = TextureParams {
+        label: Some(
+            params
+                .label
+                .clone()
+                .unwrap_or_else(|| path.as_ref().display().to_string()),
+        ),
+        ..params.clone()
+    };
+    create_texture_2d(gl, features, &[ImageData::open(path)], &params)
+}
+
+/// Create a 2D texture from its mip levels, starting with the full size image
+///
+/// Any mip levels that aren't provided are generated as described by `params.mipmaps`, falling back
+/// to having the driver generate them from the last level that was provided. The texture is
+/// allocated with immutable storage if the context supports it, and is left bound to
+/// `GL_TEXTURE_2D` on the active texture unit.
+pub fn create_texture_2d(
+    gl: &mut glow::Context,
+    features: &Features,
+    levels: &[ImageData],
+    params: &TextureParams,
+) -> Texture {
+    // Premultiply the alpha of the provided levels if we were asked to. This has to happen before
+    // the mip levels are generated so that transparent pixels don't bleed into the smaller levels.
+    let premultiplied_levels;
+    let levels = if params.premultiply && levels.iter().any(|l| l.alpha == AlphaMode::Straight) {
+        premultiplied_levels = levels
+            .iter()
+            .cloned()
+            .map(|mut level| {
+                level.premultiply_alpha();
+                level
+            })
+            .collect::<Vec<_>>();
+        &premultiplied_levels[..]
+    } else {
+        levels
+    };
+
+    let base = &levels[0];
+    let mip_levels = if params.mipmaps == MipmapMode::None {
+        1
+    } else {
+        params
+            .mip_levels
+            .unwrap_or_else(|| mip_level_count(base.width, base.height))
+            .max(1)
+    };
+
+    // Generate the mip chain on the CPU if we were asked to and weren't given one
+    let generated_levels;
+    let levels = match params.mipmaps {
+        MipmapMode::Box | MipmapMode::Lanczos if levels.len() == 1 => {
+            generated_levels = generate_mip_chain(base, params.mipmaps, mip_levels);
+            &generated_levels[..]
+        }
+        _ => levels,
+    };
+    let immutable = features.texture_storage;
+    let internal_format = base.internal_format(params.srgb);
+    let label = params
+        .label
+        .clone()
+        .unwrap_or_else(|| format!("Texture {}x{}", base.width, base.height));
+
+    unsafe {
+        // Create and bind the texture
+        let texture = gl.create_texture().unwrap();
+        resources::track_sized(
+            ResourceKind::Texture,
+            texture,
+            &label,
+            resources::texture_bytes(base.width, base.height, internal_format, mip_levels, 1, 1),
+        );
+        gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+
+        // Our pixel rows are tightly packed, which doesn't match the default 4 byte alignment for
+        // RGB images with odd widths, so each level is uploaded with an alignment of 1 or with
+        // padded rows if the driver needs them
+        let workarounds = workarounds::current();
+
+        if immutable {
+            // Allocate every mip level up front. The size and format can't change after this,
+            // which lets the driver skip its completeness checks.
+            gl.tex_storage_2d(
+                glow::TEXTURE_2D,
+                mip_levels as i32,
+                internal_format,
+                base.width as i32,
+                base.height as i32,
+            );
+        }
+
+        // Upload the levels that we have
+        for (level, image) in levels.iter().take(mip_levels as usize).enumerate() {
+            let (pixels, alignment) =
+                workarounds.unpack_rows(&image.pixels, image.width as usize * image.channels());
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, alignment);
+            if immutable {
+                gl.tex_sub_image_2d(
+                    glow::TEXTURE_2D,
+                    level as i32,
+                    0,
+                    0,
+                    image.width as i32,
+                    image.height as i32,
+                    image.format,
+                    glow::UNSIGNED_BYTE,
+                    glow::PixelUnpackData::Slice(&pixels),
+                );
+            } else {
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    level as i32,
+                    image.internal_format(params.srgb) as i32,
+                    image.width as i32,
+                    image.height as i32,
+                    0,
+                    image.format,
+                    glow::UNSIGNED_BYTE,
+                    Some(&pixels),
+                );
+            }
+        }
+
+        // Set our texture parameters
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_S, params.wrap_s as i32);
+        gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_WRAP_T, params.wrap_t as i32);
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MIN_FILTER,
+            params.min_filter as i32,
+        );
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MAG_FILTER,
+            params.mag_filter as i32,
+        );
+
+        // Generate the mip levels that weren't provided, starting from the last one that was.
+        // Mutable textures only get the levels up to `TEXTURE_MAX_LEVEL`, so set that first.
+        let provided_levels = (levels.len() as u32).min(mip_levels);
+        gl.tex_parameter_i32(
+            glow::TEXTURE_2D,
+            glow::TEXTURE_MAX_LEVEL,
+            mip_levels as i32 - 1,
+        );
+        if provided_levels < mip_levels {
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_BASE_LEVEL,
+                provided_levels as i32 - 1,
+            );
+            gl.generate_mipmap(glow::TEXTURE_2D);
+        }
+
+        let texture = Texture {
+            texture,
+            width: base.width,
+            height: base.height,
+            mip_levels,
+            immutable,
+            alpha: base.alpha,
+            srgb: params.srgb,
+            purpose: params.purpose,
+            label,
+        };
+        texture_audit::check_created(&texture);
+
+        // Limit the sampled levels to the range requested in the params
+        texture.set_level_range(
+            gl,
+            params.base_level,
+            params.max_level.unwrap_or(mip_levels - 1),
+        );
+
+        texture
+    }
+}
@@ -0,0 +1,193 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    fmt::Write,
+};
+
+use crate::{
+    resources,
+    texture::{Texture, TexturePurpose},
+};
+
+/// A texture that was created while the audit was on, keyed by its context's resource key and
+/// its GL name
+#[derive(Clone, Debug)]
+struct AuditedTexture {
+    label: String,
+    purpose: Option<TexturePurpose>,
+    srgb: bool,
+    /// The problems that have been warned about, so that each one is only warned about once
+    warned: Vec<Problem>,
+}
+
+impl AuditedTexture {
+    fn new(texture: &Texture) -> Self {
+        Self {
+            label: texture.label.clone(),
+            purpose: texture.purpose,
+            srgb: texture.srgb,
+            warned: Vec::new(),
+        }
+    }
+}
+
+thread_local! {
+    /// Whether or not suspicious textures are warned about, which the loop sets from the config
+    static ENABLED: Cell<bool> = const { Cell::new(cfg!(debug_assertions)) };
+    static TEXTURES: RefCell<HashMap<(Option<u64>, u32), AuditedTexture>> =
+        RefCell::new(HashMap::new());
+}
+
+/// Something that looks wrong about how a texture's purpose and format go together
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Problem {
+    /// A normal map in an sRGB format, whose vectors get bent by the sRGB decoding
+    SrgbNormalMap,
+    /// A data texture in an sRGB format, whose values get changed by the sRGB decoding
+    SrgbData,
+    /// An albedo texture in a linear format sampled by a lighting shader, which lights the sRGB
+    /// encoded colors as if they were linear, so they come out washed out
+    LinearAlbedoLit,
+}
+
+impl Problem {
+    pub fn description(self) -> &'static str {
+        match self {
+            Problem::SrgbNormalMap => {
+                "is a normal map in an sRGB format, so its normals get bent when they are sampled"
+            }
+            Problem::SrgbData => {
+                "holds data in an sRGB format, so its values change when they are sampled"
+            }
+            Problem::LinearAlbedoLit => {
+                "is an albedo in a linear format sampled by a lighting shader, so it gets lit as \
+                 if its sRGB colors were linear"
+            }
+        }
+    }
+}
+
+/// The problem with a texture's purpose and format on its own, if there is one
+pub fn format_problem(purpose: Option<TexturePurpose>, srgb: bool) -> Option<Problem> {
+    match (purpose?, srgb) {
+        (TexturePurpose::NormalMap, true) => Some(Problem::SrgbNormalMap),
+        (TexturePurpose::Data, true) => Some(Problem::SrgbData),
+        _ => None,
+    }
+}
+
+/// The problem with sampling a texture in a shader, if there is one. `lit` is whether the shader
+/// does lighting math with the texture's colors.
+pub fn sampling_problem(texture: &Texture, lit: bool) -> Option<Problem> {
+    format_problem(texture.purpose, texture.srgb).or(match (texture.purpose, texture.srgb) {
+        (Some(TexturePurpose::Albedo), false) if lit => Some(Problem::LinearAlbedoLit),
+        _ => None,
+    })
+}
+
+/// Turn the audit on or off
+///
+/// It starts out on in debug builds, and the loop sets it from the `texture_audit` config key.
+pub fn set_enabled(enabled: bool) {
+    ENABLED.with(|current| current.set(enabled));
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.with(|current| current.get())
+}
+
+/// Record a texture that was just created, and warn about it if its format doesn't suit its
+/// purpose
+pub(crate) fn check_created(texture: &Texture) {
+    if !is_enabled() {
+        return;
+    }
+    TEXTURES.with(|textures| {
+        textures.borrow_mut().insert(
+            (resources::current_key(), texture.texture),
+            AuditedTexture::new(texture),
+        )
+    });
+    if let Some(problem) = format_problem(texture.purpose, texture.srgb) {
+        warn(texture, problem, None);
+    }
+}
+
+/// Warn about a texture that a shader samples, if the audit is on and the shader shouldn't be
+/// sampling it in that format
+///
+/// Call this where the texture is bound for the shader. Each texture is only warned about once.
+pub fn check_sampled(texture: &Texture, shader: &str, lit: bool) {
+    if let Some(problem) = sampling_problem(texture, lit) {
+        warn(texture, problem, Some(shader));
+    }
+}
+
+fn warn(texture: &Texture, problem: Problem, shader: Option<&str>) {
+    if !is_enabled() {
+        return;
+    }
+    let first_time = TEXTURES.with(|textures| {
+        let mut textures = textures.borrow_mut();
+        let audited = textures
+            .entry((resources::current_key(), texture.texture))
+            .or_insert_with(|| AuditedTexture::new(texture));
+        if audited.warned.contains(&problem) {
+            false
+        } else {
+            audited.warned.push(problem);
+            true
+        }
+    });
+    if !first_time {
+        return;
+    }
+    eprintln!(
+        "Warning: Texture `{}` {}{}",
+        texture.label,
+        problem.description(),
+        shader.map_or(String::new(), |shader| format!(" ( in `{}` )", shader))
+    );
+}
+
+/// Forget a texture that was deleted, since its name may be reused by a new texture
+pub(crate) fn forget(texture: u32) {
+    TEXTURES.with(|textures| {
+        textures
+            .borrow_mut()
+            .remove(&(resources::current_key(), texture))
+    });
+}
+
+/// A line for each texture of the current context that the audit knows about, with its purpose,
+/// whether it is sRGB, and what it was warned about
+pub fn report() -> String {
+    let key = resources::current_key();
+    TEXTURES.with(|textures| {
+        let textures = textures.borrow();
+        let mut audited = textures
+            .iter()
+            .filter(|((context, _), _)| *context == key)
+            .map(|((_, id), texture)| (*id, texture))
+            .collect::<Vec<_>>();
+        audited.sort_by_key(|(id, _)| *id);
+
+        let mut report = String::new();
+        for (id, texture) in audited {
+            write!(
+                report,
+                "{:>4} {:<24} {:<10} {}",
+                id,
+                texture.label,
+                texture.purpose.map_or("-", TexturePurpose::name),
+                if texture.srgb { "sRGB" } else { "linear" }
+            )
+            .unwrap();
+            for problem in &texture.warned {
+                write!(report, "  ! {}", problem.description()).unwrap();
+            }
+            report.push('\n');
+        }
+        report.trim_end().to_string()
+    })
+}
@@ -0,0 +1,185 @@
+use winit::VirtualKeyCode;
+
+use crate::{
+    demo_state::{Persist, StateBlob},
+    input::Input,
+    shader::ShaderProgram,
+    shader_variants::VariantKey,
+    texture::Texture,
+    texture_audit,
+};
+
+/// The GLSL for the texture debug views, which declares a `vec4 debugTexture(sampler2D, vec2)`
+/// function that samples a texture normally or shows the debug view instead, and defines
+/// `TEXTURE_DEBUG` when there is a debug view. Add it to a fragment shader with
+/// `shader::include_chunk`.
+pub const TEXTURE_DEBUG_CHUNK: &str = include_str!("texture_debug/texture_debug.glsl");
+
+/// The keys that `TextureDebug::handle_keys` listens to and what they do, for listing in overlays
+pub const TEXTURE_DEBUG_KEYS: &[(&str, &str)] = &[
+    ("U", "show the UVs as colors"),
+    ("L", "show a single mip level"),
+    ("+ / -", "pick the mip level"),
+    ("Z", "tint texels with no alpha magenta"),
+    ("E", "sRGB in green, linear in blue, wrong in red"),
+];
+
+/// What a shader with `TEXTURE_DEBUG_CHUNK` shows in place of its textures
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextureDebugView {
+    /// The textures as they are
+    #[default]
+    Off,
+    /// The texture coordinates, with red across and green up
+    Uvs,
+    /// One mip level of the textures, picked with `TextureDebug::mip_level`
+    MipLevel,
+    /// The textures, with the texels that have an alpha of zero in magenta
+    ZeroAlpha,
+    /// The textures in green if they are sRGB and blue if they are linear, striped with red if
+    /// the texture audit finds their format suspicious
+    Encoding,
+}
+
+impl TextureDebugView {
+    /// Every view, starting with `Off`
+    pub const ALL: [TextureDebugView; 5] = [
+        TextureDebugView::Off,
+        TextureDebugView::Uvs,
+        TextureDebugView::MipLevel,
+        TextureDebugView::ZeroAlpha,
+        TextureDebugView::Encoding,
+    ];
+
+    /// The name of the view in saved state
+    pub fn name(self) -> &'static str {
+        match self {
+            TextureDebugView::Off => "off",
+            TextureDebugView::Uvs => "uvs",
+            TextureDebugView::MipLevel => "mip-level",
+            TextureDebugView::ZeroAlpha => "zero-alpha",
+            TextureDebugView::Encoding => "encoding",
+        }
+    }
+
+    /// The view with the given name, like `mip-level`
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|view| view.name() == name)
+    }
+
+    /// The feature that shader variants are compiled with for the view
+    pub fn feature(self) -> Option<&'static str> {
+        match self {
+            TextureDebugView::Off => None,
+            TextureDebugView::Uvs => Some("DEBUG_UVS"),
+            TextureDebugView::MipLevel => Some("DEBUG_MIP_LEVEL"),
+            TextureDebugView::ZeroAlpha => Some("DEBUG_ZERO_ALPHA"),
+            TextureDebugView::Encoding => Some("DEBUG_ENCODING"),
+        }
+    }
+}
+
+/// Which texture debug view is shown, switched with the keys in `TEXTURE_DEBUG_KEYS`
+///
+/// The views are shader variants: draw with the program for `variant_key`, from
+/// `ShaderVariants`, and call `set_uniforms` on it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextureDebug {
+    pub view: TextureDebugView,
+    /// The mip level that `TextureDebugView::MipLevel` shows
+    pub mip_level: u32,
+}
+
+impl TextureDebug {
+    /// Switch views with the keys in `TEXTURE_DEBUG_KEYS`, returning whether anything changed
+    ///
+    /// Pressing the key of the view that is shown turns it off again. `mip_levels` is the number
+    /// of mip levels of the textures, which the mip level is kept below.
+    pub fn handle_keys(&mut self, input: &Input, mip_levels: u32) -> bool {
+        let before = *self;
+        for (key, view) in [
+            (VirtualKeyCode::U, TextureDebugView::Uvs),
+            (VirtualKeyCode::L, TextureDebugView::MipLevel),
+            (VirtualKeyCode::Z, TextureDebugView::ZeroAlpha),
+            (VirtualKeyCode::E, TextureDebugView::Encoding),
+        ] {
+            if input.was_key_pressed(key) {
+                self.view = if self.view == view {
+                    TextureDebugView::Off
+                } else {
+                    view
+                };
+            }
+        }
+        if input.was_key_pressed(VirtualKeyCode::Equals)
+            || input.was_key_pressed(VirtualKeyCode::Add)
+        {
+            self.mip_level += 1;
+        }
+        if input.was_key_pressed(VirtualKeyCode::Minus)
+            || input.was_key_pressed(VirtualKeyCode::Subtract)
+        {
+            self.mip_level = self.mip_level.saturating_sub(1);
+        }
+        self.mip_level = self.mip_level.min(mip_levels.saturating_sub(1));
+        *self != before
+    }
+
+    /// The variant of a shader to draw with, which is `key` with the view's feature added
+    pub fn variant_key(&self, key: &VariantKey) -> VariantKey {
+        match self.view.feature() {
+            Some(feature) => key.with_feature(feature),
+            None => key.clone(),
+        }
+    }
+
+    /// Set the uniforms of the debug views on a program, which should be bound
+    pub fn set_uniforms(&self, gl: &mut glow::Context, program: &mut ShaderProgram) {
+        program.set_uniform(gl, "debugMipLevel", self.mip_level as f32);
+    }
+
+    /// Tell the debug views about the texture that is about to be sampled, which
+    /// `TextureDebugView::Encoding` colors by its format. `lit` is whether the shader lights the
+    /// texture's colors.
+    pub fn set_texture(
+        &self,
+        gl: &mut glow::Context,
+        program: &mut ShaderProgram,
+        texture: &Texture,
+        lit: bool,
+    ) {
+        let problem = texture_audit::sampling_problem(texture, lit).is_some();
+        program.set_uniform(gl, "debugTextureSrgb", texture.srgb as u32 as f32);
+        program.set_uniform(gl, "debugTextureProblem", problem as u32 as f32);
+    }
+
+    /// A description of the view, e.g. for an overlay
+    pub fn status(&self) -> String {
+        match self.view {
+            TextureDebugView::Off => "Textures".into(),
+            TextureDebugView::Uvs => "UVs".into(),
+            TextureDebugView::MipLevel => format!("Mip level {}", self.mip_level),
+            TextureDebugView::ZeroAlpha => "Zero alpha in magenta".into(),
+            TextureDebugView::Encoding => "sRGB in green, linear in blue".into(),
+        }
+    }
+}
+
+/// Keeps the view and the mip level, which `handle_keys` keeps below the mip levels of the
+/// textures that are shown now
+impl Persist for TextureDebug {
+    fn save_to(&self, blob: &mut StateBlob) {
+        blob.set("view", self.view.name());
+        blob.set("mip_level", self.mip_level);
+    }
+
+    fn restore_from(&mut self, blob: &StateBlob) -> Result<(), String> {
+        let view = blob.get::<String>("view")?;
+        let view = TextureDebugView::parse(&view)
+            .ok_or_else(|| format!("Unknown texture debug view `{}`", view))?;
+        let mip_level = blob.get("mip_level")?;
+        self.view = view;
+        self.mip_level = mip_level;
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+// Debug views of textures, picked by the feature that the shader variant is compiled with:
+// DEBUG_UVS, DEBUG_MIP_LEVEL, DEBUG_ZERO_ALPHA, or DEBUG_ENCODING. `TextureDebug::set_uniforms`
+// and `TextureDebug::set_texture` set the uniforms.
+#if defined(DEBUG_UVS) || defined(DEBUG_MIP_LEVEL) || defined(DEBUG_ZERO_ALPHA) \
+    || defined(DEBUG_ENCODING)
+#define TEXTURE_DEBUG
+#endif
+
+// The mip level to show with DEBUG_MIP_LEVEL
+uniform float debugMipLevel;
+// Whether the texture is sRGB, and whether the texture audit thinks its format is wrong, for
+// DEBUG_ENCODING
+uniform float debugTextureSrgb;
+uniform float debugTextureProblem;
+
+// Sample a texture, or show the debug view of it instead
+vec4 debugTexture(sampler2D image, vec2 uv) {
+#if defined(DEBUG_UVS)
+    // Red across and green up, repeating where the UVs go past 0 to 1
+    return vec4(fract(uv), 0.0, 1.0);
+#elif defined(DEBUG_MIP_LEVEL)
+    return vec4(textureLod(image, uv, debugMipLevel).rgb, 1.0);
+#elif defined(DEBUG_ZERO_ALPHA)
+    vec4 color = texture(image, uv);
+    return color.a == 0.0 ? vec4(1.0, 0.0, 1.0, 1.0) : vec4(color.rgb, 1.0);
+#elif defined(DEBUG_ENCODING)
+    // Green for sRGB and blue for linear, with red stripes over textures in the wrong format
+    vec3 tint = debugTextureSrgb > 0.5 ? vec3(0.1, 0.9, 0.2) : vec3(0.1, 0.3, 1.0);
+    if (debugTextureProblem > 0.5 && mod(gl_FragCoord.x + gl_FragCoord.y, 16.0) < 8.0) {
+        tint = vec3(1.0, 0.0, 0.0);
+    }
+    float luminance = dot(texture(image, uv).rgb, vec3(0.2126, 0.7152, 0.0722));
+    return vec4(tint * (0.35 + 0.65 * luminance), 1.0);
+#else
+    return texture(image, uv);
+#endif
+}
@@ -0,0 +1,661 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use glow::HasContext;
+
+use crate::{
+    asset_manifest::{self, ContentHash},
+    color::Color,
+    debug_text::{DebugText, LINE_HEIGHT},
+    mipmap::{generate_mip_chain, MipmapMode},
+    resources::{self, ResourceKind},
+    texture::{mip_level_count, AlphaMode, ImageData, Texture, TextureParams},
+    texture_audit,
+};
+
+/// The most bytes of texels uploaded in one frame by default
+pub const DEFAULT_UPLOAD_BUDGET: u64 = 4 * 1024 * 1024;
+
+/// The settings of a `TextureStreamer`, which can be changed between frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct StreamingParams {
+    /// The most bytes of texels to upload in one frame, so that a frame never stalls on a big
+    /// upload. A level bigger than the budget is uploaded on its own in a frame.
+    pub upload_budget: u64,
+    /// The largest width or height that textures are streamed up to, or `None` for their full
+    /// size. This only applies to textures requested after it is set.
+    pub max_size: Option<u32>,
+}
+
+impl Default for StreamingParams {
+    fn default() -> Self {
+        Self {
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            max_size: None,
+        }
+    }
+}
+
+/// The mip levels of an image that the worker is asked to decode
+struct Job {
+    /// The GL name of the texture
+    texture: u32,
+    generation: u64,
+    path: PathBuf,
+    /// The largest level to send
+    finest_level: u32,
+    /// The smallest level to send, which is sent first
+    coarsest_level: u32,
+    premultiply: bool,
+}
+
+/// What the worker sends back for a job
+enum Decoded {
+    /// The hash of the file's contents, which is sent before its levels
+    Hashed {
+        texture: u32,
+        generation: u64,
+        hash: ContentHash,
+    },
+    Level {
+        texture: u32,
+        generation: u64,
+        level: u32,
+        image: ImageData,
+    },
+    Failed {
+        texture: u32,
+        generation: u64,
+        error: String,
+    },
+}
+
+/// The streaming state of one texture
+#[derive(Debug)]
+struct Streamed {
+    label: String,
+    width: u32,
+    height: u32,
+    mip_levels: u32,
+    internal_format: u32,
+    /// The largest level that has been uploaded, which is the texture's base level
+    resident_level: u32,
+    /// Whether the texture is still the 1x1 placeholder in its last level
+    placeholder: bool,
+    /// The largest level that the texture should have
+    wanted_level: u32,
+    /// The largest level that the worker has been asked for
+    requested_level: u32,
+    /// The job that levels are taken from, so that levels of an older job are left out
+    generation: u64,
+    /// Levels that the worker has decoded and are waiting to be uploaded
+    pending: BTreeMap<u32, ImageData>,
+    path: PathBuf,
+    premultiply: bool,
+    /// The hash of the file's contents, once the worker has read it
+    content_hash: Option<ContentHash>,
+    /// Why the image couldn't be decoded, if it couldn't
+    error: Option<String>,
+}
+
+impl Streamed {
+    /// The size of a mip level
+    fn level_size(&self, level: u32) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// The level that has to be uploaded next, which is the one above the resident level
+    fn next_level(&self) -> Option<u32> {
+        let level = if self.placeholder {
+            self.mip_levels - 1
+        } else {
+            self.resident_level.checked_sub(1)?
+        };
+        if level < self.wanted_level {
+            None
+        } else {
+            Some(level)
+        }
+    }
+
+    /// An estimate of the memory used by the resident levels
+    fn resident_bytes(&self) -> u64 {
+        if self.placeholder {
+            return resources::bytes_per_pixel(self.internal_format);
+        }
+        let (width, height) = self.level_size(self.resident_level);
+        let levels = self.mip_levels - self.resident_level;
+        resources::texture_bytes(width, height, self.internal_format, levels, 1, 1)
+    }
+}
+
+/// Loads textures a few mip levels at a time, smallest first, so that a scene with lots of big
+/// textures starts right away and never has more of them in memory than it needs
+///
+/// `request` creates a texture with a 1x1 placeholder and hands the image to a worker thread,
+/// which decodes it and makes its mip chain with a box filter. Image files don't have smaller
+/// levels to read on their own, so the whole image is decoded once. `update` uploads the decoded
+/// levels every frame, the smallest ones of all of the textures first, until the frame's upload
+/// budget is spent. Each upload lowers the texture's base level, so a texture always samples the
+/// best levels it has instead of going black.
+///
+/// `set_wanted_level` drops the largest levels of a texture to free their memory, e.g. for
+/// textures that have been off screen for a while, and streams them in again once they are
+/// wanted back.
+///
+/// The streamer doesn't own its textures. They are deleted like any other `Texture`, after
+/// calling `forget` or `delete` here.
+pub struct TextureStreamer {
+    pub params: StreamingParams,
+    /// The textures being streamed, by GL name
+    textures: BTreeMap<u32, Streamed>,
+    /// The generation of the next job
+    next_generation: u64,
+    /// The bytes uploaded in the last `update`
+    uploaded: u64,
+    jobs: Option<Sender<Job>>,
+    results: Receiver<Decoded>,
+    /// Tells the worker to skip the jobs it has left
+    cancelled: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl TextureStreamer {
+    pub fn new(params: StreamingParams) -> Self {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let worker_cancelled = cancelled.clone();
+        let worker = std::thread::Builder::new()
+            .name("Texture streaming".into())
+            .spawn(move || {
+                for job in job_receiver {
+                    if worker_cancelled.load(Ordering::Relaxed) {
+                        break;
+                    }
+                    if let Err(error) = decode(&job, &result_sender) {
+                        let _ = result_sender.send(Decoded::Failed {
+                            texture: job.texture,
+                            generation: job.generation,
+                            error: error.to_string(),
+                        });
+                    }
+                }
+            })
+            .unwrap();
+        Self {
+            params,
+            textures: BTreeMap::new(),
+            next_generation: 0,
+            uploaded: 0,
+            jobs: Some(jobs),
+            results,
+            cancelled,
+            worker: Some(worker),
+        }
+    }
+
+    /// Start streaming an image file into a new texture, which is returned right away
+    ///
+    /// Only the image's size is read here. The texture samples the `placeholder` texel until its
+    /// smallest levels come in, so it should be a color that the texture won't look out of place
+    /// fading from, like a material slot's default texel. Streamed textures are always RGBA, with
+    /// a full mip chain made with a box filter, so `params.mipmaps` and `params.mip_levels` are
+    /// left out.
+    pub fn request<P: AsRef<Path>>(
+        &mut self,
+        gl: &mut glow::Context,
+        path: P,
+        params: &TextureParams,
+        placeholder: [u8; 4],
+    ) -> image::ImageResult<Texture> {
+        let path = path.as_ref();
+        let (width, height) = image::image_dimensions(path)?;
+        let mip_levels = mip_level_count(width, height);
+        let last_level = mip_levels - 1;
+        let internal_format = if params.srgb {
+            glow::SRGB8_ALPHA8
+        } else {
+            glow::RGBA8
+        };
+        let label = params
+            .label
+            .clone()
+            .unwrap_or_else(|| path.display().to_string());
+
+        let texture = unsafe {
+            let texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                last_level as i32,
+                internal_format as i32,
+                1,
+                1,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                Some(&placeholder),
+            );
+            for (parameter, value) in [
+                (glow::TEXTURE_WRAP_S, params.wrap_s),
+                (glow::TEXTURE_WRAP_T, params.wrap_t),
+                (glow::TEXTURE_MIN_FILTER, params.min_filter),
+                (glow::TEXTURE_MAG_FILTER, params.mag_filter),
+                (glow::TEXTURE_BASE_LEVEL, last_level),
+                (glow::TEXTURE_MAX_LEVEL, last_level),
+            ] {
+                gl.tex_parameter_i32(glow::TEXTURE_2D, parameter, value as i32);
+            }
+            texture
+        };
+
+        // The largest level that fits in the max size
+        let wanted_level = self.params.max_size.map_or(0, |max_size| {
+            (0..mip_levels)
+                .find(|&level| (width >> level).max(height >> level) <= max_size)
+                .unwrap_or(last_level)
+        });
+        let generation = self.next_generation();
+        let streamed = Streamed {
+            label: label.clone(),
+            width,
+            height,
+            mip_levels,
+            internal_format,
+            resident_level: last_level,
+            placeholder: true,
+            wanted_level,
+            requested_level: wanted_level,
+            generation,
+            pending: BTreeMap::new(),
+            path: path.to_owned(),
+            premultiply: params.premultiply,
+            content_hash: None,
+            error: None,
+        };
+        resources::track_sized(
+            ResourceKind::Texture,
+            texture,
+            &label,
+            streamed.resident_bytes(),
+        );
+        self.send(Job {
+            texture,
+            generation,
+            path: path.to_owned(),
+            finest_level: wanted_level,
+            coarsest_level: last_level,
+            premultiply: params.premultiply,
+        });
+        self.textures.insert(texture, streamed);
+
+        let texture = Texture {
+            texture,
+            width,
+            height,
+            mip_levels,
+            immutable: false,
+            alpha: if params.premultiply {
+                AlphaMode::Premultiplied
+            } else {
+                AlphaMode::Straight
+            },
+            srgb: params.srgb,
+            purpose: params.purpose,
+            label,
+        };
+        texture_audit::check_created(&texture);
+        Ok(texture)
+    }
+
+    /// Upload the levels that have been decoded, smallest first, until the upload budget for
+    /// this frame is spent
+    ///
+    /// The texture bound to `GL_TEXTURE_2D` on the active unit is bound again afterwards.
+    pub fn update(&mut self, gl: &mut glow::Context) {
+        self.receive();
+
+        let budget = self.params.upload_budget;
+        let mut uploaded = 0;
+        let bound = unsafe { gl.get_parameter_i32(glow::TEXTURE_BINDING_2D) as u32 };
+        loop {
+            // The smallest level that can be uploaded next, out of all of the textures
+            let next = self
+                .textures
+                .iter()
+                .filter_map(|(&texture, streamed)| {
+                    let level = streamed.next_level()?;
+                    let image = streamed.pending.get(&level)?;
+                    Some((texture, level, image.pixels.len() as u64))
+                })
+                .min_by_key(|&(_, _, bytes)| bytes);
+            let (texture, level, bytes) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            if uploaded > 0 && uploaded + bytes > budget {
+                break;
+            }
+
+            let streamed = self.textures.get_mut(&texture).unwrap();
+            let image = streamed.pending.remove(&level).unwrap();
+            unsafe {
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+                gl.tex_image_2d(
+                    glow::TEXTURE_2D,
+                    level as i32,
+                    streamed.internal_format as i32,
+                    image.width as i32,
+                    image.height as i32,
+                    0,
+                    glow::RGBA,
+                    glow::UNSIGNED_BYTE,
+                    Some(&image.pixels),
+                );
+                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_BASE_LEVEL, level as i32);
+            }
+            streamed.resident_level = level;
+            streamed.placeholder = false;
+            resources::track_sized(
+                ResourceKind::Texture,
+                texture,
+                &streamed.label,
+                streamed.resident_bytes(),
+            );
+            uploaded += bytes;
+        }
+        unsafe {
+            gl.bind_texture(
+                glow::TEXTURE_2D,
+                if bound == 0 { None } else { Some(bound) },
+            );
+        }
+        self.uploaded = uploaded;
+    }
+
+    /// Change the largest level that a texture should have
+    ///
+    /// A smaller level than the texture has drops the levels above it straight away, freeing
+    /// their memory. A larger one streams the missing levels in again.
+    pub fn set_wanted_level(&mut self, gl: &mut glow::Context, texture: &Texture, level: u32) {
+        let streamed = match self.textures.get_mut(&texture.texture) {
+            Some(streamed) => streamed,
+            None => return,
+        };
+        let level = level.min(streamed.mip_levels - 1);
+        streamed.wanted_level = level;
+        streamed.pending.retain(|&pending, _| pending >= level);
+
+        if !streamed.placeholder && level > streamed.resident_level {
+            // Move the base level first, so the texture stays complete while the levels go
+            unsafe {
+                gl.bind_texture(glow::TEXTURE_2D, Some(texture.texture));
+                gl.tex_parameter_i32(glow::TEXTURE_2D, glow::TEXTURE_BASE_LEVEL, level as i32);
+                for evicted in streamed.resident_level..level {
+                    gl.tex_image_2d(
+                        glow::TEXTURE_2D,
+                        evicted as i32,
+                        streamed.internal_format as i32,
+                        0,
+                        0,
+                        0,
+                        glow::RGBA,
+                        glow::UNSIGNED_BYTE,
+                        None,
+                    );
+                }
+            }
+            streamed.resident_level = level;
+            resources::track_sized(
+                ResourceKind::Texture,
+                texture.texture,
+                &streamed.label,
+                streamed.resident_bytes(),
+            );
+        }
+        streamed.requested_level = streamed.requested_level.max(level);
+
+        if level < streamed.requested_level {
+            // Decode the image again for the levels that were dropped or never asked for
+            let coarsest_level = if streamed.placeholder {
+                streamed.mip_levels - 1
+            } else {
+                streamed.resident_level - 1
+            };
+            let generation = self.next_generation;
+            self.next_generation += 1;
+            streamed.generation = generation;
+            streamed.requested_level = level;
+            streamed.pending.clear();
+            let job = Job {
+                texture: texture.texture,
+                generation,
+                path: streamed.path.clone(),
+                finest_level: level,
+                coarsest_level,
+                premultiply: streamed.premultiply,
+            };
+            self.send(job);
+        }
+    }
+
+    /// The largest level of a texture that has been uploaded, or `None` if it still has its
+    /// placeholder or isn't being streamed
+    pub fn resident_level(&self, texture: &Texture) -> Option<u32> {
+        self.textures
+            .get(&texture.texture)
+            .filter(|streamed| !streamed.placeholder)
+            .map(|streamed| streamed.resident_level)
+    }
+
+    /// The hash of a texture's file, once the worker has read it, for recording in an
+    /// `AssetManifest` with `record_hash` without reading the file again
+    pub fn content_hash(&self, texture: &Texture) -> Option<ContentHash> {
+        self.textures
+            .get(&texture.texture)
+            .and_then(|streamed| streamed.content_hash)
+    }
+
+    /// Whether every texture has all of the levels it wants
+    pub fn is_done(&self) -> bool {
+        self.textures
+            .values()
+            .all(|streamed| streamed.next_level().is_none() || streamed.error.is_some())
+    }
+
+    /// The bytes of texels uploaded in the last `update`
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploaded
+    }
+
+    /// Stop streaming a texture, which has to happen before it is deleted
+    pub fn forget(&mut self, texture: &Texture) {
+        self.textures.remove(&texture.texture);
+    }
+
+    /// List the textures and which of their levels are loaded, starting at `(x, y)`, returning
+    /// the height of the lines
+    pub fn draw_overlay(&self, text: &mut DebugText, x: f32, y: f32, scale: u32) -> f32 {
+        let line_height = (LINE_HEIGHT * scale) as f32;
+        let resident = self.textures.values().map(Streamed::resident_bytes).sum();
+        text.text(
+            x,
+            y,
+            scale,
+            Color::YELLOW,
+            &format!(
+                "Streaming {} textures: {} loaded, {} uploaded this frame of {}",
+                self.textures.len(),
+                resources::format_bytes(resident),
+                resources::format_bytes(self.uploaded),
+                resources::format_bytes(self.params.upload_budget)
+            ),
+        );
+        let mut lines_height = line_height;
+        for streamed in self.textures.values() {
+            let name = streamed.path.file_name().map_or_else(
+                || streamed.label.clone(),
+                |name| name.to_string_lossy().into(),
+            );
+            let (width, height) = streamed.level_size(streamed.resident_level);
+            let (line, color) = if let Some(error) = &streamed.error {
+                (format!("{}: {}", name, error), Color::RED)
+            } else if streamed.placeholder {
+                (format!("{}: placeholder", name), Color::GRAY)
+            } else {
+                let done = streamed.next_level().is_none();
+                (
+                    format!(
+                        "{}: level {} of {} ( {}x{} of {}x{} ){}",
+                        name,
+                        streamed.resident_level,
+                        streamed.mip_levels - 1,
+                        width,
+                        height,
+                        streamed.width,
+                        streamed.height,
+                        if done { "" } else { ", loading" }
+                    ),
+                    if done { Color::WHITE } else { Color::CYAN },
+                )
+            };
+            text.text(x, y + lines_height, scale, color, &line);
+            lines_height += line_height;
+        }
+        lines_height
+    }
+
+    /// Stop the worker and stop streaming every texture, leaving the textures as they are
+    pub fn delete(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+        self.textures.clear();
+    }
+
+    fn next_generation(&mut self) -> u64 {
+        let generation = self.next_generation;
+        self.next_generation += 1;
+        generation
+    }
+
+    fn send(&self, job: Job) {
+        if let Some(jobs) = &self.jobs {
+            if jobs.send(job).is_err() {
+                eprintln!("Warning: The texture streaming worker has stopped");
+            }
+        }
+    }
+
+    /// Take the levels that the worker has decoded since the last frame
+    fn receive(&mut self) {
+        loop {
+            match self.results.try_recv() {
+                Ok(Decoded::Hashed {
+                    texture,
+                    generation,
+                    hash,
+                }) => {
+                    if let Some(streamed) = self.textures.get_mut(&texture) {
+                        if streamed.generation == generation {
+                            streamed.content_hash = Some(hash);
+                        }
+                    }
+                }
+                Ok(Decoded::Level {
+                    texture,
+                    generation,
+                    level,
+                    image,
+                }) => {
+                    let streamed = match self.textures.get_mut(&texture) {
+                        Some(streamed) if streamed.generation == generation => streamed,
+                        _ => continue,
+                    };
+                    let missing = streamed.placeholder || level < streamed.resident_level;
+                    if missing && level >= streamed.wanted_level {
+                        streamed.pending.insert(level, image);
+                    }
+                }
+                Ok(Decoded::Failed {
+                    texture,
+                    generation,
+                    error,
+                }) => {
+                    if let Some(streamed) = self.textures.get_mut(&texture) {
+                        if streamed.generation == generation {
+                            eprintln!(
+                                "Warning: Couldn't stream the texture {}: {}",
+                                streamed.label, error
+                            );
+                            streamed.error = Some(error);
+                        }
+                    }
+                }
+                Err(TryRecvError::Empty) | Err(TryRecvError::Disconnected) => break,
+            }
+        }
+    }
+}
+
+/// Decode a job's image on the worker and send the hash of the file, then its levels, smallest
+/// first
+fn decode(job: &Job, results: &Sender<Decoded>) -> image::ImageResult<()> {
+    let bytes = std::fs::read(&job.path)?;
+    let _ = results.send(Decoded::Hashed {
+        texture: job.texture,
+        generation: job.generation,
+        hash: asset_manifest::hash_bytes(&bytes),
+    });
+    let format =
+        image::ImageFormat::from_path(&job.path).or_else(|_| image::guess_format(&bytes))?;
+    let image = image::load_from_memory_with_format(&bytes, format)?;
+    let alpha = match image {
+        image::DynamicImage::ImageRgb8(_) | image::DynamicImage::ImageLuma8(_) => AlphaMode::Opaque,
+        _ => AlphaMode::Straight,
+    };
+    let image = image.into_rgba();
+    let mut base = ImageData {
+        width: image.width(),
+        height: image.height(),
+        format: glow::RGBA,
+        alpha,
+        pixels: image.into_raw(),
+    };
+    if job.premultiply {
+        base.premultiply_alpha();
+    }
+
+    let levels = generate_mip_chain(&base, MipmapMode::Box, job.coarsest_level + 1);
+    for (level, image) in levels
+        .into_iter()
+        .enumerate()
+        .skip(job.finest_level as usize)
+        .rev()
+    {
+        let sent = results.send(Decoded::Level {
+            texture: job.texture,
+            generation: job.generation,
+            level: level as u32,
+            image,
+        });
+        // The streamer is gone
+        if sent.is_err() {
+            break;
+        }
+    }
+    Ok(())
+}
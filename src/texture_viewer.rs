@@ -0,0 +1,773 @@
+use std::{collections::HashMap, fmt};
+
+use glow::HasContext;
+use winit::MouseButton;
+
+use crate::{
+    debug_group::DebugGroup,
+    debug_text::{DebugText, LINE_HEIGHT},
+    nested::SavedState,
+    readback::AsyncReadback,
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    viewport::Rect,
+    AppContext,
+};
+
+const VIEW_FRAGMENT_SRC: &str = include_str!("texture_viewer/view.frag");
+const PICK_FRAGMENT_SRC: &str = include_str!("texture_viewer/pick.frag");
+const SAMPLE_CHUNK: &str = include_str!("texture_viewer/sample.glsl");
+
+/// How much of the window's width and height the panel can cover
+const PANEL_FRACTION: f32 = 0.4;
+/// The space between the panel and the corner of the window, in pixels at a UI scale of 1
+const MARGIN: f32 = 12.;
+/// The space around the panel's text, in pixels at a UI scale of 1
+const PADDING: f32 = 4.;
+/// The names of the cube map faces, in the order of their targets
+const CUBE_FACES: [&str; 6] = ["+X", "-X", "+Y", "-Y", "+Z", "-Z"];
+
+/// Which channels of a texture the texture viewer shows
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ViewChannel {
+    /// The colors over a checkerboard where they are see-through
+    #[default]
+    Rgba,
+    /// The colors without alpha
+    Rgb,
+    /// One channel in grayscale
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl ViewChannel {
+    /// Every channel, in the order the view shader numbers them
+    pub const ALL: [ViewChannel; 6] = [
+        ViewChannel::Rgba,
+        ViewChannel::Rgb,
+        ViewChannel::Red,
+        ViewChannel::Green,
+        ViewChannel::Blue,
+        ViewChannel::Alpha,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ViewChannel::Rgba => "rgba",
+            ViewChannel::Rgb => "rgb",
+            ViewChannel::Red => "r",
+            ViewChannel::Green => "g",
+            ViewChannel::Blue => "b",
+            ViewChannel::Alpha => "a",
+        }
+    }
+
+    /// The channel with the given name, like `rgb` or `a`
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|channel| channel.name() == name)
+    }
+
+    /// The number the view shader knows the channel by
+    fn index(self) -> i32 {
+        Self::ALL
+            .iter()
+            .position(|&channel| channel == self)
+            .unwrap() as i32
+    }
+}
+
+impl fmt::Display for ViewChannel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A texel to read from the shown texture
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PickAt {
+    /// The texel under a point that goes from 0 to 1 across the texture, from the bottom left
+    Uv(f32, f32),
+    /// A texel by its coordinates, which are clamped to the texture
+    Texel(u32, u32),
+}
+
+/// Which texture the loop shows in a panel over the frame, in `RenderSettings::texture_view`,
+/// and how
+///
+/// Set it with the `view` console command. The texture is found again every frame, so a render
+/// target that is made again when the window is resized keeps being shown as long as it is
+/// picked by its label.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TextureView {
+    /// The texture to show, as its GL name or a part of its label, or `None` to show nothing
+    pub texture: Option<String>,
+    pub channel: ViewChannel,
+    /// The values that are shown from black to white
+    pub range: (f32, f32),
+    /// Whether to show the texture upside down, for textures that are stored top row first
+    pub flip_y: bool,
+    /// The cube face, array layer, or sample of a multisampled texture that is shown
+    pub slice: u32,
+    /// A texel to read from the texture, which the loop prints once the value comes back a frame
+    /// or two later. Clicking on the panel picks the texel under the cursor.
+    pub pick: Option<PickAt>,
+}
+
+impl Default for TextureView {
+    fn default() -> Self {
+        Self {
+            texture: None,
+            channel: ViewChannel::Rgba,
+            range: (0., 1.),
+            flip_y: false,
+            slice: 0,
+            pick: None,
+        }
+    }
+}
+
+impl TextureView {
+    /// The live texture that `texture` picks out, if there is one
+    pub fn find(&self) -> Option<ViewableTexture> {
+        find_texture(self.texture.as_deref()?)
+    }
+
+    /// A line describing what is shown, for the console
+    pub fn status(&self) -> String {
+        let texture = match &self.texture {
+            Some(texture) => texture,
+            None => return "Texture view: off".into(),
+        };
+        let shown = match self.find() {
+            Some(found) => format!("{} ( {}, {} )", found.label, found.id, found.target_name()),
+            None => format!("`{}`, which matches no texture", texture),
+        };
+        format!(
+            "Texture view: {}, {}, from {} to {}, slice {}{}",
+            shown,
+            self.channel,
+            self.range.0,
+            self.range.1,
+            self.slice,
+            if self.flip_y { ", flipped" } else { "" }
+        )
+    }
+}
+
+/// A live texture of the current context, as the resource tracker knows it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ViewableTexture {
+    pub id: u32,
+    pub label: String,
+    /// The target it is bound to, like `TEXTURE_2D`
+    pub target: u32,
+}
+
+impl ViewableTexture {
+    /// Whether the texture viewer can show textures of this target
+    ///
+    /// 2D textures, cube maps, 2D arrays, and multisampled 2D textures with float, normalized, or
+    /// depth formats can be shown. Integer formats show up as garbage.
+    pub fn is_supported(&self) -> bool {
+        matches!(
+            self.target,
+            glow::TEXTURE_2D
+                | glow::TEXTURE_CUBE_MAP
+                | glow::TEXTURE_2D_ARRAY
+                | glow::TEXTURE_2D_MULTISAMPLE
+        )
+    }
+
+    pub fn target_name(&self) -> &'static str {
+        match self.target {
+            glow::TEXTURE_2D => "2D",
+            glow::TEXTURE_CUBE_MAP => "cube map",
+            glow::TEXTURE_2D_ARRAY => "2D array",
+            glow::TEXTURE_2D_MULTISAMPLE => "2D multisample",
+            glow::TEXTURE_3D => "3D",
+            _ => "other",
+        }
+    }
+
+    /// A name for a slice of the texture, like a cube face
+    pub fn slice_name(&self, slice: u32) -> String {
+        match self.target {
+            glow::TEXTURE_CUBE_MAP => format!("face {}", CUBE_FACES[slice.min(5) as usize]),
+            glow::TEXTURE_2D_ARRAY => format!("layer {}", slice),
+            glow::TEXTURE_2D_MULTISAMPLE => format!("sample {}", slice),
+            _ => String::new(),
+        }
+    }
+
+    /// How many slices the texture is known to have, which is 6 for cube maps and `None` when it
+    /// depends on how the texture was made
+    pub fn slice_count(&self) -> Option<u32> {
+        match self.target {
+            glow::TEXTURE_CUBE_MAP => Some(6),
+            glow::TEXTURE_2D | glow::TEXTURE_3D => Some(1),
+            _ => None,
+        }
+    }
+
+    /// The define that the viewer's shaders are compiled with for the target
+    fn define(&self) -> Option<&'static str> {
+        match self.target {
+            glow::TEXTURE_CUBE_MAP => Some("TARGET_CUBE"),
+            glow::TEXTURE_2D_ARRAY => Some("TARGET_ARRAY"),
+            glow::TEXTURE_2D_MULTISAMPLE => Some("TARGET_MULTISAMPLE"),
+            _ => None,
+        }
+    }
+}
+
+/// The live textures of the current context, sorted by GL name
+pub fn viewable_textures() -> Vec<ViewableTexture> {
+    resources::live_labels(ResourceKind::Texture)
+        .into_iter()
+        .map(|(id, label)| ViewableTexture {
+            id,
+            label,
+            target: resources::texture_target(id).unwrap_or(glow::TEXTURE_2D),
+        })
+        .collect()
+}
+
+/// The live texture with the GL name `name`, or else the first one whose label contains it,
+/// ignoring case
+pub fn find_texture(name: &str) -> Option<ViewableTexture> {
+    let textures = viewable_textures();
+    if let Ok(id) = name.parse::<u32>() {
+        return textures.into_iter().find(|texture| texture.id == id);
+    }
+    let name = name.to_lowercase();
+    textures
+        .into_iter()
+        .find(|texture| texture.label.to_lowercase().contains(&name))
+}
+
+/// A texel that was read back from a texture
+#[derive(Clone, Debug, PartialEq)]
+pub struct TexelPick {
+    pub texture: ViewableTexture,
+    pub slice: u32,
+    /// The coordinates of the texel, from the bottom left
+    pub texel: (u32, u32),
+    /// The size of the texture's top mip level
+    pub size: (u32, u32),
+    /// The value of the texel as the shaders see it, so depth is in the red channel
+    pub value: [f32; 4],
+}
+
+impl fmt::Display for TexelPick {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let [r, g, b, a] = self.value;
+        write!(
+            f,
+            "{} ( {} ) texel ({}, {}) of {}x{}",
+            self.texture.label,
+            self.texture.id,
+            self.texel.0,
+            self.texel.1,
+            self.size.0,
+            self.size.1
+        )?;
+        let slice = self.texture.slice_name(self.slice);
+        if !slice.is_empty() {
+            write!(f, ", {}", slice)?;
+        }
+        write!(f, ": {} {} {} {}", r, g, b, a)
+    }
+}
+
+/// Why a texel is being read back
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PickReason {
+    /// It was asked for, and is printed when it comes back
+    Asked,
+    /// It is under the cursor, and is shown under the panel
+    Hover,
+    /// Only the size of the texture is wanted, for the shape of the panel
+    Size,
+}
+
+/// The programs that show and read a texture of one target
+#[derive(Debug)]
+struct TargetPrograms {
+    view: ShaderProgram,
+    pick: ShaderProgram,
+}
+
+impl TargetPrograms {
+    fn new(gl: &mut glow::Context, define: Option<&str>) -> Result<Self, String> {
+        let defines = define.into_iter().collect::<Vec<_>>();
+        let view = ShaderProgram::with_defines(
+            gl,
+            FULLSCREEN_VERTEX_SRC,
+            &shader::include_chunk(VIEW_FRAGMENT_SRC, SAMPLE_CHUNK),
+            &defines,
+        )?;
+        let pick = ShaderProgram::with_defines(
+            gl,
+            FULLSCREEN_VERTEX_SRC,
+            &shader::include_chunk(PICK_FRAGMENT_SRC, SAMPLE_CHUNK),
+            &defines,
+        );
+        match pick {
+            Ok(pick) => Ok(Self { view, pick }),
+            Err(error) => {
+                let mut view = view;
+                view.delete(gl);
+                Err(error)
+            }
+        }
+    }
+
+    fn delete(&mut self, gl: &mut glow::Context) {
+        self.view.delete(gl);
+        self.pick.delete(gl);
+    }
+}
+
+/// Shows a texture in a panel over the frame and reads single texels back from it, for
+/// `RenderSettings::texture_view`
+///
+/// Texels are read by drawing them into a tiny float framebuffer and reading that back with an
+/// `AsyncReadback`, so depth textures and cube maps can be read as well, which `glReadPixels`
+/// can't do on its own. Only one read is in flight at a time.
+#[derive(Debug)]
+pub(crate) struct TextureViewerPass {
+    /// The programs for each texture target, compiled the first time a texture of the target is
+    /// shown
+    programs: HashMap<u32, TargetPrograms>,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+    /// The 2x1 RGBA32F framebuffer that texels are read through, with the value in the first
+    /// pixel and the texel and the size of the texture in the second
+    pick_framebuffer: u32,
+    pick_texture: u32,
+    readback: AsyncReadback,
+    /// The texture and slice of the read in flight, and why it was made
+    in_flight: Option<(ViewableTexture, u32, PickReason)>,
+    /// The sizes of the textures that were read from, by GL name
+    sizes: HashMap<u32, (u32, u32)>,
+    /// The texel under the cursor, and the last one that was asked for
+    hovered: Option<TexelPick>,
+    picked: Option<TexelPick>,
+    /// Where the panel was drawn last frame, for telling where it is clicked
+    rect: Option<Rect>,
+}
+
+impl TextureViewerPass {
+    pub fn new(gl: &mut glow::Context) -> Self {
+        let mut programs = HashMap::new();
+        programs.insert(glow::TEXTURE_2D, TargetPrograms::new(gl, None).unwrap());
+        let label = "Texture viewer pick";
+        unsafe {
+            let empty_vao = gl.create_vertex_array().unwrap();
+            resources::track(
+                ResourceKind::VertexArray,
+                empty_vao,
+                "Texture viewer vertex array",
+            );
+
+            let pick_texture = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(pick_texture));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA32F as i32,
+                2,
+                1,
+                0,
+                glow::RGBA,
+                glow::FLOAT,
+                None,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+            resources::track_sized(
+                ResourceKind::Texture,
+                pick_texture,
+                label,
+                resources::texture_bytes(2, 1, glow::RGBA32F, 1, 1, 1),
+            );
+
+            let pick_framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(pick_framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(pick_texture),
+                0,
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                panic!("Error creating the texture viewer's pick framebuffer!");
+            }
+            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+            resources::track(ResourceKind::Framebuffer, pick_framebuffer, label);
+
+            Self {
+                programs,
+                empty_vao,
+                pick_framebuffer,
+                pick_texture,
+                readback: AsyncReadback::new(gl, 32, 1, label),
+                in_flight: None,
+                sizes: HashMap::new(),
+                hovered: None,
+                picked: None,
+                rect: None,
+            }
+        }
+    }
+
+    /// The programs for a texture's target, compiling them if this is the first texture of it
+    fn programs(
+        &mut self,
+        gl: &mut glow::Context,
+        texture: &ViewableTexture,
+    ) -> Result<&mut TargetPrograms, String> {
+        if !texture.is_supported() {
+            return Err(format!("{} textures can't be shown", texture.target_name()));
+        }
+        if !self.programs.contains_key(&texture.target) {
+            let programs = TargetPrograms::new(gl, texture.define())?;
+            self.programs.insert(texture.target, programs);
+        }
+        Ok(self.programs.get_mut(&texture.target).unwrap())
+    }
+
+    /// Draw a texture over the whole viewport of the bound framebuffer
+    ///
+    /// Texture unit 0 is used, and the blending and depth state are put back afterwards.
+    pub fn draw(
+        &mut self,
+        gl: &mut glow::Context,
+        texture: &ViewableTexture,
+        view: &TextureView,
+    ) -> Result<(), String> {
+        let empty_vao = self.empty_vao;
+        let program = &mut self.programs(gl, texture)?.view;
+        let _group = DebugGroup::push(gl, "Texture viewer");
+        unsafe {
+            let state = SavedState::save(gl);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::BLEND);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.disable(glow::STENCIL_TEST);
+
+            program.bind(gl);
+            program.set_uniform(gl, "source", 0);
+            program.set_uniform(gl, "slice", view.slice as i32);
+            program.set_uniform(gl, "channel", view.channel.index());
+            program.set_uniform(gl, "range", [view.range.0, view.range.1]);
+            program.set_uniform(gl, "flipY", view.flip_y as i32);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(texture.target, Some(texture.id));
+            gl.bind_vertex_array(Some(empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            gl.bind_texture(texture.target, None);
+
+            state.restore(gl);
+        }
+        Ok(())
+    }
+
+    /// Start reading a texel of a texture, which `poll` returns once it comes back
+    ///
+    /// Returns false if a read is still in flight, in which case nothing is read.
+    pub fn pick(
+        &mut self,
+        gl: &mut glow::Context,
+        texture: &ViewableTexture,
+        slice: u32,
+        at: PickAt,
+    ) -> Result<bool, String> {
+        self.start_pick(gl, texture, slice, at, PickReason::Asked)
+    }
+
+    fn start_pick(
+        &mut self,
+        gl: &mut glow::Context,
+        texture: &ViewableTexture,
+        slice: u32,
+        at: PickAt,
+        reason: PickReason,
+    ) -> Result<bool, String> {
+        if self.in_flight.is_some() {
+            return Ok(false);
+        }
+        let (empty_vao, framebuffer) = (self.empty_vao, self.pick_framebuffer);
+        let program = &mut self.programs(gl, texture)?.pick;
+        let (uv, texel) = match at {
+            PickAt::Uv(u, v) => ([u, v], [-1., -1.]),
+            PickAt::Texel(x, y) => ([0., 0.], [x as f32, y as f32]),
+        };
+        let _group = DebugGroup::push(gl, "Texture viewer pick");
+        unsafe {
+            let state = SavedState::save(gl);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::BLEND);
+            gl.disable(glow::SCISSOR_TEST);
+            gl.disable(glow::STENCIL_TEST);
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.viewport(0, 0, 2, 1);
+            program.bind(gl);
+            program.set_uniform(gl, "source", 0);
+            program.set_uniform(gl, "slice", slice as i32);
+            program.set_uniform(gl, "pickUv", uv);
+            program.set_uniform(gl, "pickTexel", texel);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(texture.target, Some(texture.id));
+            gl.bind_vertex_array(Some(empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            gl.bind_texture(texture.target, None);
+
+            let started = self.readback.read_framebuffer(
+                gl,
+                Some(framebuffer),
+                Rect::new(0, 0, 2, 1),
+                glow::RGBA,
+                glow::FLOAT,
+            );
+            state.restore(gl);
+            if started {
+                self.in_flight = Some((texture.clone(), slice, reason));
+            }
+            Ok(started)
+        }
+    }
+
+    /// The texel that was asked for with `pick`, once it has come back
+    pub fn poll(&mut self, gl: &mut glow::Context) -> Option<TexelPick> {
+        let data = self.readback.poll(gl)?;
+        let (texture, slice, reason) = self.in_flight.take()?;
+        let floats = data
+            .chunks_exact(4)
+            .map(|bytes| f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect::<Vec<_>>();
+        let pick = TexelPick {
+            slice,
+            texel: (floats[4] as u32, floats[5] as u32),
+            size: (floats[6] as u32, floats[7] as u32),
+            value: [floats[0], floats[1], floats[2], floats[3]],
+            texture,
+        };
+        self.sizes.insert(pick.texture.id, pick.size);
+        match reason {
+            PickReason::Asked => {
+                self.picked = Some(pick.clone());
+                Some(pick)
+            }
+            PickReason::Hover => {
+                self.hovered = Some(pick);
+                None
+            }
+            PickReason::Size => None,
+        }
+    }
+
+    /// Show the texture of `RenderSettings::texture_view` in the bottom right corner of the
+    /// window, with its name and the texel under the cursor above it, and read the texels that
+    /// are clicked or asked for
+    ///
+    /// The window framebuffer should be bound. The panel is registered as an overlay, so that
+    /// cursor queries don't go through it. Returns the texel that was asked for, once it comes
+    /// back, or why the texture can't be shown.
+    pub(crate) fn draw_overlay(
+        &mut self,
+        gl: &mut glow::Context,
+        text: &mut DebugText,
+        ctx: &mut AppContext,
+    ) -> Option<Result<String, String>> {
+        let (window_size, ui_scale) = (ctx.window_size(), ctx.ui_scale());
+        let (view, input) = (&mut ctx.render_settings.texture_view, &ctx.input);
+        let theme = &ctx.render_settings.theme;
+        let picked = self.poll(gl).map(|pick| Ok(pick.to_string()));
+        let texture = match view.find() {
+            Some(texture) => texture,
+            None => {
+                self.rect = None;
+                return picked;
+            }
+        };
+        if let Err(error) = self.programs(gl, &texture) {
+            view.texture = None;
+            self.rect = None;
+            return Some(Err(error));
+        }
+
+        // Clicks go to the panel where it was drawn last frame
+        let (width, height) = (window_size.0 as f32, window_size.1 as f32);
+        let cursor = input.window_cursor_position().and_then(|(x, y)| {
+            let rect = self.rect?;
+            let (x, y) = (x as f32, height - y as f32);
+            if !rect.contains(x as i32, y as i32) {
+                return None;
+            }
+            let u = (x - rect.x as f32) / rect.width as f32;
+            let v = (y - rect.y as f32) / rect.height as f32;
+            Some(PickAt::Uv(u, if view.flip_y { 1. - v } else { v }))
+        });
+        if input.was_mouse_pressed(MouseButton::Left) && cursor.is_some() {
+            view.pick = cursor;
+        }
+        if self.in_flight.is_none() {
+            let started = match (view.pick, cursor) {
+                (Some(at), _) => self
+                    .start_pick(gl, &texture, view.slice, at, PickReason::Asked)
+                    .map(|started| {
+                        if started {
+                            view.pick = None;
+                        }
+                    }),
+                (None, Some(at)) => self
+                    .start_pick(gl, &texture, view.slice, at, PickReason::Hover)
+                    .map(|_| ()),
+                // Keep reading the size, since a texture made again can get the same name
+                (None, None) => self
+                    .start_pick(
+                        gl,
+                        &texture,
+                        view.slice,
+                        PickAt::Uv(0., 0.),
+                        PickReason::Size,
+                    )
+                    .map(|_| ()),
+            };
+            if let Err(error) = started {
+                return Some(Err(error));
+            }
+        }
+        if cursor.is_none() {
+            self.hovered = None;
+        }
+
+        // The panel fits the texture's shape into the corner, which is square until its size
+        // comes back
+        let (texture_width, texture_height) =
+            self.sizes.get(&texture.id).copied().unwrap_or((1, 1));
+        let aspect = texture_width.max(1) as f32 / texture_height.max(1) as f32;
+        let (max_width, max_height) = (width * PANEL_FRACTION, height * PANEL_FRACTION);
+        let (panel_width, panel_height) = if max_width / max_height > aspect {
+            (max_height * aspect, max_height)
+        } else {
+            (max_width, max_width / aspect)
+        };
+        let margin = MARGIN * ui_scale;
+        let rect = Rect::new(
+            (width - margin - panel_width).round() as i32,
+            margin.round() as i32,
+            panel_width.round().max(1.) as i32,
+            panel_height.round().max(1.) as i32,
+        );
+
+        // The name and the texel under the cursor go above the panel, in pixels from the top left
+        let text_scale = ui_scale.round().max(1.) as u32;
+        let line_height = (LINE_HEIGHT * text_scale) as f32;
+        let padding = PADDING * ui_scale;
+        let mut title = format!(
+            "{} ( {} ) {}x{} {}  {}",
+            texture.label,
+            texture.id,
+            texture_width,
+            texture_height,
+            texture.target_name(),
+            view.channel
+        );
+        let slice = texture.slice_name(view.slice);
+        if !slice.is_empty() {
+            title = format!("{}  {}", title, slice);
+        }
+        if view.range != (0., 1.) {
+            title = format!("{}  {} to {}", title, view.range.0, view.range.1);
+        }
+        let shown_pick = self
+            .hovered
+            .as_ref()
+            .or(self.picked.as_ref())
+            .filter(|pick| pick.texture.id == texture.id);
+        let value = match shown_pick {
+            Some(pick) => {
+                let [r, g, b, a] = pick.value;
+                format!(
+                    "({}, {}): {:.4} {:.4} {:.4} {:.4}",
+                    pick.texel.0, pick.texel.1, r, g, b, a
+                )
+            }
+            None => "click to read a texel".into(),
+        };
+        let text_width = [&title, &value]
+            .iter()
+            .map(|line| DebugText::text_size(line, text_scale).0 as f32)
+            .fold(panel_width, f32::max);
+        let panel_top = height - (rect.y + rect.height) as f32;
+        let text_top = panel_top - padding - line_height * 2.;
+        let right = rect.x as f32 + panel_width;
+        text.rect(
+            right - text_width - padding,
+            text_top - padding,
+            text_width + padding * 2.,
+            height - margin - text_top + padding * 2.,
+            theme.panel_color,
+        );
+        text.text(
+            right - text_width,
+            text_top,
+            text_scale,
+            theme.text_color,
+            &title,
+        );
+        text.text(
+            right - text_width,
+            text_top + line_height,
+            text_scale,
+            theme.text_color,
+            &value,
+        );
+        text.draw(gl, &ctx.arena, window_size);
+
+        rect.set_viewport(gl);
+        let drawn = self.draw(gl, &texture, view);
+        Rect::from_window_size(window_size).set_viewport(gl);
+        self.rect = Some(rect);
+        // The cursor goes to the panel and its text, not to the views under them
+        ctx.viewports.register_overlay(Rect::new(
+            (right - text_width - padding).floor() as i32,
+            (margin - padding).floor() as i32,
+            (text_width + padding * 2.).ceil() as i32,
+            (height - text_top - margin + padding * 2.).ceil() as i32,
+        ));
+        match drawn {
+            Ok(()) => picked,
+            Err(error) => Some(Err(error)),
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        for (_, mut programs) in self.programs.drain() {
+            programs.delete(gl);
+        }
+        unsafe {
+            gl.delete_vertex_array(self.empty_vao);
+            gl.delete_framebuffer(self.pick_framebuffer);
+            gl.delete_texture(self.pick_texture);
+        }
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+        resources::untrack(ResourceKind::Framebuffer, self.pick_framebuffer);
+        resources::untrack(ResourceKind::Texture, self.pick_texture);
+        self.readback.delete(gl);
+    }
+}
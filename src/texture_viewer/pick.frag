@@ -0,0 +1,22 @@
+#version 330 core
+
+// The point to read, from 0 to 1 across the texture, unless a texel is given
+uniform vec2 pickUv;
+// The texel to read, or -1 to read the one under `pickUv`
+uniform vec2 pickTexel;
+
+out vec4 FragColor;
+
+// Drawn into two pixels: the value of the texel, and then which texel it was and the size of the
+// texture, which the CPU doesn't always know
+void main() {
+    ivec2 size = sourceSize();
+    ivec2 texel = pickTexel.x < 0.0
+        ? texelAt(pickUv)
+        : clamp(ivec2(pickTexel), ivec2(0), size - 1);
+    if (gl_FragCoord.x < 1.0) {
+        FragColor = sourceTexel(texel);
+    } else {
+        FragColor = vec4(vec2(texel), vec2(size));
+    }
+}
@@ -0,0 +1,59 @@
+// The texture that is shown, declared for the target that the program was compiled for
+#if defined(TARGET_CUBE)
+uniform samplerCube source;
+#elif defined(TARGET_ARRAY)
+uniform sampler2DArray source;
+#elif defined(TARGET_MULTISAMPLE)
+uniform sampler2DMS source;
+#else
+uniform sampler2D source;
+#endif
+// The cube face, array layer, or sample that is shown
+uniform int slice;
+
+ivec2 sourceSize() {
+#if defined(TARGET_ARRAY)
+    return textureSize(source, 0).xy;
+#elif defined(TARGET_MULTISAMPLE)
+    return textureSize(source);
+#else
+    return textureSize(source, 0);
+#endif
+}
+
+#ifdef TARGET_CUBE
+// The direction that samples a point of a cube face, where `uv` goes from 0 to 1 across the face
+// the way its texels are stored, following the table of faces in the GL spec
+vec3 cubeDirection(int face, vec2 uv) {
+    vec2 st = uv * 2.0 - 1.0;
+    switch (face) {
+        case 0: return vec3(1.0, -st.y, -st.x);
+        case 1: return vec3(-1.0, -st.y, st.x);
+        case 2: return vec3(st.x, 1.0, st.y);
+        case 3: return vec3(st.x, -1.0, -st.y);
+        case 4: return vec3(st.x, -st.y, 1.0);
+        default: return vec3(-st.x, -st.y, -1.0);
+    }
+}
+#endif
+
+// The value of one texel of the top mip level, without any filtering
+vec4 sourceTexel(ivec2 texel) {
+#if defined(TARGET_CUBE)
+    // Cube maps can't be fetched from, so the middle of the texel is sampled instead
+    vec2 uv = (vec2(texel) + 0.5) / vec2(sourceSize());
+    return textureLod(source, cubeDirection(clamp(slice, 0, 5), uv), 0.0);
+#elif defined(TARGET_ARRAY)
+    return texelFetch(source, ivec3(texel, clamp(slice, 0, textureSize(source, 0).z - 1)), 0);
+#elif defined(TARGET_MULTISAMPLE)
+    return texelFetch(source, texel, max(slice, 0));
+#else
+    return texelFetch(source, texel, 0);
+#endif
+}
+
+// The texel under a point that goes from 0 to 1 across the texture
+ivec2 texelAt(vec2 uv) {
+    ivec2 size = sourceSize();
+    return clamp(ivec2(floor(uv * vec2(size))), ivec2(0), size - 1);
+}
@@ -0,0 +1,28 @@
+#version 330 core
+in vec2 texCoord;
+
+// How the texels are shown: 0 for their colors over a checkerboard where they are see-through, 1
+// for their colors without alpha, and 2 to 5 for only red, green, blue, or alpha in gray
+uniform int channel;
+// The values that are shown from black to white
+uniform vec2 range;
+// Whether to show the texture upside down, for textures that are stored top row first
+uniform bool flipY;
+
+out vec4 FragColor;
+
+void main() {
+    vec2 uv = vec2(texCoord.x, flipY ? 1.0 - texCoord.y : texCoord.y);
+    vec4 value = sourceTexel(texelAt(uv));
+    vec4 shown = clamp((value - range.x) / max(range.y - range.x, 1e-6), 0.0, 1.0);
+    if (channel == 0) {
+        // Squares of 8 pixels, so that they stay the same size however big the panel is
+        vec2 square = floor(gl_FragCoord.xy / 8.0);
+        float checker = mod(square.x + square.y, 2.0) == 0.0 ? 0.4 : 0.6;
+        FragColor = vec4(mix(vec3(checker), shown.rgb, shown.a), 1.0);
+    } else if (channel == 1) {
+        FragColor = vec4(shown.rgb, 1.0);
+    } else {
+        FragColor = vec4(vec3(shown[channel - 2]), 1.0);
+    }
+}
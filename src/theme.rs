@@ -0,0 +1,105 @@
+use std::fmt::Write as _;
+
+use crate::color::Color;
+
+/// The colors that the loop and the debug helpers draw with, which can be switched while an
+/// example is running
+///
+/// The active theme is `RenderSettings::theme`. The ground grid, the console, and the clear color
+/// read it every frame, so switching themes with the `theme` console command or F7 changes them
+/// right away. Handlers can read it too, for example to pick line colors for `DebugDraw` that show
+/// up on the background.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    pub name: String,
+    /// The color the window is cleared to, unless the handler picked its own
+    pub clear_color: Color,
+    pub grid_minor_color: Color,
+    pub grid_major_color: Color,
+    /// The color of overlay text, like the console's output
+    pub text_color: Color,
+    /// The background behind overlay text
+    pub panel_color: Color,
+    /// The color of debug lines, like bounding boxes and wireframes
+    pub wireframe_color: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl Theme {
+    /// Light lines on a dark background, for development
+    pub fn dark() -> Self {
+        Self {
+            name: "dark".into(),
+            clear_color: Color::rgb(0.12, 0.12, 0.14),
+            grid_minor_color: Color::rgba(0.5, 0.5, 0.5, 0.35),
+            grid_major_color: Color::rgba(0.65, 0.65, 0.65, 0.7),
+            text_color: Color::rgb(0.8, 0.8, 0.8),
+            panel_color: Color::rgba(0., 0., 0., 0.75),
+            wireframe_color: Color::rgb(0.9, 0.9, 0.9),
+        }
+    }
+
+    /// Dark lines on a light background, for screenshots in documents
+    pub fn light() -> Self {
+        Self {
+            name: "light".into(),
+            clear_color: Color::rgb(0.95, 0.95, 0.93),
+            grid_minor_color: Color::rgba(0.45, 0.45, 0.45, 0.3),
+            grid_major_color: Color::rgba(0.3, 0.3, 0.3, 0.6),
+            text_color: Color::rgb(0.1, 0.1, 0.12),
+            panel_color: Color::rgba(1., 1., 1., 0.8),
+            wireframe_color: Color::rgb(0.15, 0.15, 0.2),
+        }
+    }
+
+    /// The themes that are always there, which the config file can change or add to
+    pub fn built_in() -> Vec<Self> {
+        vec![Self::dark(), Self::light()]
+    }
+
+    /// The same theme with another name, as a starting point for a new one
+    pub fn named(self, name: &str) -> Self {
+        Self {
+            name: name.into(),
+            ..self
+        }
+    }
+
+    /// Set a color from a hex code, returning false if the key isn't one of the colors
+    pub(crate) fn set(&mut self, key: &str, value: &str) -> Result<bool, String> {
+        let color = match key {
+            "clear_color" => &mut self.clear_color,
+            "grid_minor_color" => &mut self.grid_minor_color,
+            "grid_major_color" => &mut self.grid_major_color,
+            "text_color" => &mut self.text_color,
+            "panel_color" => &mut self.panel_color,
+            "wireframe_color" => &mut self.wireframe_color,
+            _ => return Ok(false),
+        };
+        *color = Color::from_hex(value)
+            .ok_or_else(|| format!("Expected a hex color for `{}`, got `{}`", key, value))?;
+        Ok(true)
+    }
+
+    /// Write the theme as a `["theme.name"]` table that `Config::from_toml` can read
+    pub(crate) fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        writeln!(toml, "[{:?}]", format!("theme.{}", self.name)).unwrap();
+        for (key, color) in [
+            ("clear_color", self.clear_color),
+            ("grid_minor_color", self.grid_minor_color),
+            ("grid_major_color", self.grid_major_color),
+            ("text_color", self.text_color),
+            ("panel_color", self.panel_color),
+            ("wireframe_color", self.wireframe_color),
+        ] {
+            writeln!(toml, "{} = {:?}", key, color.to_hex()).unwrap();
+        }
+        toml
+    }
+}
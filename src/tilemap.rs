@@ -0,0 +1,636 @@
+use std::{collections::BTreeMap, io, path::Path};
+
+use glow::HasContext;
+
+use crate::{
+    camera::OrthoCamera,
+    debug_group::DebugGroup,
+    features::Features,
+    mipmap::MipmapMode,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+    texture::{create_texture_2d, AlphaMode, ImageData, Texture, TextureParams, TexturePurpose},
+    vertex::{f32_to_f16, VertexFormat, VertexLayout},
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("tilemap/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("tilemap/fragment.glsl");
+
+/// How many tiles go across and down each chunk of a tilemap
+pub const CHUNK_SIZE: u32 = 32;
+/// How many times the edge pixels of each tile are repeated around it in a `TileAtlas`
+pub const ATLAS_PADDING: u32 = 1;
+/// How many entries go across each row of the lookup texture, which must match the vertex shader
+const LOOKUP_WIDTH: u32 = 256;
+/// The bits of a Tiled tile number that flip the tile, which aren't supported and are ignored
+const TILED_FLIP_BITS: u32 = 0xe000_0000;
+
+/// The tiles of a tilemap, packed into one texture with padding around each of them
+///
+/// Linear filtering blends each texel with its neighbors, so at the edge of a tile it reads up to
+/// half a texel into whatever is next to it in the atlas, which shows up as seams between tiles.
+/// The atlas repeats the edge pixels of every tile `ATLAS_PADDING` times around it, so those reads
+/// land on copies of the tile's own edge. It has no mipmaps, since the smaller levels would blend
+/// neighboring tiles together anyway.
+#[derive(Debug)]
+pub struct TileAtlas {
+    pub texture: Texture,
+    /// How many tiles go across and down the atlas
+    pub columns: u32,
+    pub rows: u32,
+    /// The size of each tile in pixels, without the padding
+    pub tile_size: u32,
+}
+
+impl TileAtlas {
+    /// Build an atlas from an image of square tiles packed edge to edge
+    ///
+    /// The tiles are numbered row by row from the first row of pixels, which is the top of an
+    /// image file, and the first row of each tile's pixels is its top. Pixels past the last whole
+    /// tile are left out. There can be up to 256 tiles across and down.
+    pub fn new(
+        gl: &mut glow::Context,
+        features: &Features,
+        image: &ImageData,
+        tile_size: u32,
+    ) -> Self {
+        let tile_size = tile_size.max(1);
+        let (columns, rows) = (image.width / tile_size, image.height / tile_size);
+        assert!(
+            columns > 0 && rows > 0,
+            "The atlas image is smaller than one {}x{} tile",
+            tile_size,
+            tile_size
+        );
+        assert!(
+            columns <= 256 && rows <= 256,
+            "The atlas has {}x{} tiles, but there can only be 256 across and down",
+            columns,
+            rows
+        );
+
+        let cell = tile_size + 2 * ATLAS_PADDING;
+        let (width, height) = (columns * cell, rows * cell);
+        let channels = image.channels();
+        // The pixel of the tile that an offset into its cell shows, which is the nearest edge
+        // pixel in the padding
+        let inner = |offset: u32| offset.saturating_sub(ATLAS_PADDING).min(tile_size - 1);
+        let mut pixels = vec![255; (width * height * 4) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let source_x = x / cell * tile_size + inner(x % cell);
+                let source_y = y / cell * tile_size + inner(y % cell);
+                let source = (source_y * image.width + source_x) as usize * channels;
+                let target = ((y * width + x) * 4) as usize;
+                pixels[target..target + channels]
+                    .copy_from_slice(&image.pixels[source..source + channels]);
+            }
+        }
+        let padded = ImageData {
+            width,
+            height,
+            format: glow::RGBA,
+            alpha: image.alpha,
+            pixels,
+        };
+
+        let texture = create_texture_2d(
+            gl,
+            features,
+            &[padded],
+            &TextureParams {
+                wrap_s: glow::CLAMP_TO_EDGE,
+                wrap_t: glow::CLAMP_TO_EDGE,
+                min_filter: glow::LINEAR,
+                mipmaps: MipmapMode::None,
+                purpose: Some(TexturePurpose::Ui),
+                label: Some(format!("Tile atlas {}x{}", columns, rows)),
+                ..Default::default()
+            },
+        );
+        Self {
+            texture,
+            columns,
+            rows,
+            tile_size,
+        }
+    }
+
+    /// How many tiles the atlas has
+    pub fn tile_count(&self) -> u32 {
+        self.columns * self.rows
+    }
+
+    pub fn delete(&self, gl: &mut glow::Context) {
+        self.texture.delete(gl);
+    }
+}
+
+/// A grid of tiles, from CSV or from a layer of a Tiled map
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TileLayer {
+    pub width: u32,
+    pub height: u32,
+    /// The atlas tile in each cell, row by row from the top, or `None` for an empty cell
+    pub tiles: Vec<Option<u32>>,
+}
+
+impl TileLayer {
+    /// An empty layer
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            tiles: vec![None; (width * height) as usize],
+        }
+    }
+
+    /// The tile in a cell, with the top row being 0
+    pub fn get(&self, x: u32, y: u32) -> Option<u32> {
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, tile: Option<u32>) {
+        self.tiles[(y * self.width + x) as usize] = tile;
+    }
+
+    /// Read a layer from CSV, or from a Tiled map
+    ///
+    /// CSV has one row of the layer per line, with the tiles separated by commas. Like Tiled's CSV
+    /// export, 0 is an empty cell and the atlas tiles start at 1, and -1 is empty too. A Tiled map
+    /// ( `.tmx` ) is read for its first layer with CSV data, and its tiles start at the
+    /// `firstgid` of its first tileset. Flipped tiles are drawn without flipping them.
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let (csv, first_id) = if text.trim_start().starts_with('<') {
+            tiled_csv_layer(text)
+                .ok_or_else(|| invalid("The Tiled map has no layer with CSV data".to_owned()))?
+        } else {
+            (text, 1)
+        };
+
+        let mut width = None;
+        let mut height = 0;
+        let mut tiles = Vec::new();
+        for (line_number, line) in csv.lines().enumerate() {
+            // Tiled ends every row but the last with a comma
+            let line = line.trim().trim_end_matches(',');
+            if line.is_empty() {
+                continue;
+            }
+            let row_start = tiles.len();
+            for cell in line.split(',').map(str::trim) {
+                let id = cell.parse::<i64>().map_err(|_| {
+                    invalid(format!(
+                        "Line {}: `{}` isn't a tile number",
+                        line_number + 1,
+                        cell
+                    ))
+                })?;
+                tiles.push(if id > 0 {
+                    (id as u32 & !TILED_FLIP_BITS).checked_sub(first_id)
+                } else {
+                    None
+                });
+            }
+
+            let row_width = (tiles.len() - row_start) as u32;
+            match width {
+                Some(width) if width != row_width => {
+                    return Err(invalid(format!(
+                        "Line {} has {} tiles, but the rows before it have {}",
+                        line_number + 1,
+                        row_width,
+                        width
+                    )));
+                }
+                _ => width = Some(row_width),
+            }
+            height += 1;
+        }
+        let width = width.ok_or_else(|| invalid("The layer has no tiles".to_owned()))?;
+        Ok(Self {
+            width,
+            height,
+            tiles,
+        })
+    }
+
+    /// Load a layer from a CSV file or a Tiled map
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// The CSV data of the first layer of a Tiled map that has it, and the first tile number of the
+/// map's first tileset
+fn tiled_csv_layer(tmx: &str) -> Option<(&str, u32)> {
+    let first_id = tmx
+        .find("firstgid=\"")
+        .and_then(|start| {
+            let value = &tmx[start + "firstgid=\"".len()..];
+            value[..value.find('"')?].parse().ok()
+        })
+        .unwrap_or(1);
+    let encoding = tmx.find("encoding=\"csv\"")?;
+    let start = encoding + tmx[encoding..].find('>')? + 1;
+    let end = start + tmx[start..].find("</data>")?;
+    Some((&tmx[start..end], first_id))
+}
+
+/// The atlas tiles that a tile cycles through, with each one shown for the same time
+#[derive(Clone, Debug, PartialEq)]
+pub struct TileAnimation {
+    pub frames: Vec<u32>,
+    /// How long each frame is shown for, in seconds
+    pub frame_duration: f32,
+}
+
+impl TileAnimation {
+    pub fn new(frames: Vec<u32>, frame_duration: f32) -> Self {
+        Self {
+            frames,
+            frame_duration,
+        }
+    }
+
+    /// The atlas tile to show at a time in seconds, or `None` if there are no frames
+    pub fn frame_at(&self, time: f32) -> Option<u32> {
+        if self.frames.is_empty() {
+            return None;
+        }
+        let frame = if self.frame_duration > 0. {
+            (time / self.frame_duration)
+                .floor()
+                .rem_euclid(self.frames.len() as f32) as usize
+        } else {
+            0
+        };
+        self.frames.get(frame).copied()
+    }
+}
+
+/// How much of a tilemap was drawn
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TilemapStats {
+    /// How many chunks the map has, not counting empty ones
+    pub chunks: usize,
+    /// How many chunks were in view and drawn, with one draw call each
+    pub draw_calls: usize,
+    pub tiles: usize,
+}
+
+/// The static vertex buffer of one square of the map
+#[derive(Debug)]
+struct Chunk {
+    vao: u32,
+    vbo: u32,
+    /// The bottom left corner of the chunk and its size, in tiles
+    origin: [f32; 2],
+    size: [f32; 2],
+    tile_count: usize,
+}
+
+/// A tile layer drawn with an atlas, in chunks that are only drawn while the camera can see them
+///
+/// The layer is cut into chunks of `CHUNK_SIZE` by `CHUNK_SIZE` tiles, each with a static vertex
+/// buffer that is built once, and empty cells get no vertices at all. Chunks out of the camera's
+/// view are skipped without a draw call, so the cost of a frame follows the size of the view
+/// instead of the size of the map. One world unit is one tile, with the bottom left corner of the
+/// layer at the origin.
+///
+/// Animating tiles doesn't touch the vertex buffers. The vertices keep the tile that the layer
+/// has, and the vertex shader looks up the atlas tile to show for it in a small lookup texture,
+/// which `update` rewrites when an animation moves on to its next frame.
+#[derive(Debug)]
+pub struct Tilemap {
+    pub atlas: TileAtlas,
+    width: u32,
+    height: u32,
+    chunks: Vec<Chunk>,
+    /// The quad indices that every chunk shares, enough for a full chunk
+    ebo: u32,
+    program: ShaderProgram,
+    lookup: Texture,
+    /// The atlas column and row shown for each tile, which is what `lookup` holds, and whether
+    /// it changed since it was last uploaded
+    shown: ImageData,
+    shown_changed: bool,
+    animations: BTreeMap<u32, TileAnimation>,
+}
+
+impl Tilemap {
+    pub fn new(
+        gl: &mut glow::Context,
+        features: &Features,
+        layer: &TileLayer,
+        atlas: TileAtlas,
+    ) -> Self {
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+
+        // Two triangles for each tile, going around its four vertices
+        let quad_count = CHUNK_SIZE * CHUNK_SIZE;
+        let indices = (0..quad_count as u16)
+            .flat_map(|quad| [0, 1, 2, 2, 3, 0].map(|corner| quad * 4 + corner))
+            .flat_map(u16::to_ne_bytes)
+            .collect::<Vec<_>>();
+        let ebo = unsafe {
+            let ebo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+            gl.buffer_data_u8_slice(glow::ELEMENT_ARRAY_BUFFER, &indices, glow::STATIC_DRAW);
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, None);
+            ebo
+        };
+        resources::track_sized(
+            ResourceKind::Buffer,
+            ebo,
+            "Tilemap index buffer",
+            indices.len() as u64,
+        );
+
+        let tile_count = atlas.tile_count();
+        let mut skipped = 0;
+        let mut chunks = Vec::new();
+        for chunk_y in 0..layer.height.div_ceil(CHUNK_SIZE) {
+            for chunk_x in 0..layer.width.div_ceil(CHUNK_SIZE) {
+                let origin = [chunk_x * CHUNK_SIZE, chunk_y * CHUNK_SIZE];
+                let size = [
+                    CHUNK_SIZE.min(layer.width - origin[0]),
+                    CHUNK_SIZE.min(layer.height - origin[1]),
+                ];
+                let mut vertices = Vec::new();
+                let mut count = 0;
+                for y in 0..size[1] {
+                    for x in 0..size[0] {
+                        // The layer's rows go down from the top, and the world's Y goes up
+                        let row = layer.height - 1 - (origin[1] + y);
+                        let tile = match layer.get(origin[0] + x, row) {
+                            Some(tile) if tile < tile_count => tile,
+                            Some(_) => {
+                                skipped += 1;
+                                continue;
+                            }
+                            None => continue,
+                        };
+                        for [dx, dy] in [[0, 0], [1, 0], [1, 1], [0, 1]] {
+                            for position in [x + dx, y + dy] {
+                                vertices
+                                    .extend_from_slice(&f32_to_f16(position as f32).to_ne_bytes());
+                            }
+                            vertices.extend_from_slice(&(tile as f32).to_ne_bytes());
+                        }
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    chunks.push(Chunk::new(
+                        gl,
+                        ebo,
+                        &vertices,
+                        [origin[0] as f32, origin[1] as f32],
+                        [size[0] as f32, size[1] as f32],
+                        count,
+                    ));
+                }
+            }
+        }
+        if skipped > 0 {
+            eprintln!(
+                "Warning: Left out {} tiles of the layer that are past the {} tiles of the atlas",
+                skipped, tile_count
+            );
+        }
+
+        // Every tile starts out showing itself
+        let lookup_width = tile_count.min(LOOKUP_WIDTH);
+        let lookup_height = tile_count.div_ceil(LOOKUP_WIDTH);
+        let mut shown = ImageData {
+            width: lookup_width,
+            height: lookup_height,
+            format: glow::RGBA,
+            alpha: AlphaMode::Opaque,
+            pixels: vec![255; (lookup_width * lookup_height * 4) as usize],
+        };
+        for tile in 0..tile_count {
+            set_shown(&mut shown, &atlas, tile, tile);
+        }
+        let lookup = create_texture_2d(
+            gl,
+            features,
+            &[shown.clone()],
+            &TextureParams {
+                wrap_s: glow::CLAMP_TO_EDGE,
+                wrap_t: glow::CLAMP_TO_EDGE,
+                min_filter: glow::NEAREST,
+                mag_filter: glow::NEAREST,
+                mipmaps: MipmapMode::None,
+                purpose: Some(TexturePurpose::Data),
+                label: Some("Tilemap animation lookup".to_owned()),
+                ..Default::default()
+            },
+        );
+
+        Self {
+            atlas,
+            width: layer.width,
+            height: layer.height,
+            chunks,
+            ebo,
+            program,
+            lookup,
+            shown,
+            shown_changed: false,
+            animations: BTreeMap::new(),
+        }
+    }
+
+    /// Load a layer from a CSV file or a Tiled map and build a tilemap of it
+    pub fn from_file<P: AsRef<Path>>(
+        gl: &mut glow::Context,
+        features: &Features,
+        path: P,
+        atlas: TileAtlas,
+    ) -> io::Result<Self> {
+        let layer = TileLayer::load(path)?;
+        Ok(Self::new(gl, features, &layer, atlas))
+    }
+
+    /// The size of the layer in tiles, which is the size of the map in world units
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Make every cell with a tile cycle through the frames of an animation
+    ///
+    /// The cells keep showing the tile until the next `update`.
+    pub fn animate(&mut self, tile: u32, animation: TileAnimation) {
+        self.animations.insert(tile, animation);
+    }
+
+    /// Stop animating a tile, which goes back to showing itself at the next `update`
+    pub fn stop_animating(&mut self, tile: u32) {
+        if self.animations.remove(&tile).is_some() && tile < self.atlas.tile_count() {
+            self.shown_changed |= set_shown(&mut self.shown, &self.atlas, tile, tile);
+        }
+    }
+
+    /// Move the animations to their frames at a time in seconds, and upload the lookup texture
+    /// if any of them changed
+    pub fn update(&mut self, gl: &mut glow::Context, time: f32) {
+        let tile_count = self.atlas.tile_count();
+        for (&tile, animation) in &self.animations {
+            if let Some(frame) = animation.frame_at(time) {
+                if tile < tile_count && frame < tile_count {
+                    self.shown_changed |= set_shown(&mut self.shown, &self.atlas, tile, frame);
+                }
+            }
+        }
+        if !self.shown_changed {
+            return;
+        }
+        self.shown_changed = false;
+
+        // The lookup is only a few kilobytes, so it is simpler to upload all of it
+        let shown = &self.shown;
+        unsafe {
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.lookup.texture));
+            gl.pixel_store_i32(glow::UNPACK_ALIGNMENT, 4);
+            gl.tex_sub_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                0,
+                0,
+                shown.width as i32,
+                shown.height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelUnpackData::Slice(&shown.pixels),
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+        }
+    }
+
+    /// Draw the chunks that the camera can see
+    pub fn draw(&mut self, gl: &mut glow::Context, camera: &OrthoCamera) -> TilemapStats {
+        let _group = DebugGroup::push(gl, "Tilemap");
+        let mut stats = TilemapStats {
+            chunks: self.chunks.len(),
+            ..Default::default()
+        };
+        let (min, max) = camera.visible_bounds();
+
+        let atlas = &self.atlas;
+        let program = &mut self.program;
+        program.bind(gl);
+        program.set_uniform(gl, "viewProjection", camera.view_projection());
+        program.set_uniform(gl, "atlas", 0);
+        program.set_uniform(gl, "lookup", 1);
+        program.set_uniform(
+            gl,
+            "atlasSize",
+            [atlas.texture.width as f32, atlas.texture.height as f32],
+        );
+        program.set_uniform(gl, "tileSize", atlas.tile_size as f32);
+        program.set_uniform(gl, "padding", ATLAS_PADDING as f32);
+        unsafe {
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, Some(self.lookup.texture));
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(atlas.texture.texture));
+        }
+
+        for chunk in &self.chunks {
+            let [x, y] = chunk.origin;
+            let [width, height] = chunk.size;
+            if x > max.x || x + width < min.x || y > max.y || y + height < min.y {
+                continue;
+            }
+            program.set_uniform(gl, "chunkOrigin", chunk.origin);
+            unsafe {
+                gl.bind_vertex_array(Some(chunk.vao));
+                gl.draw_elements(
+                    glow::TRIANGLES,
+                    chunk.tile_count as i32 * 6,
+                    glow::UNSIGNED_SHORT,
+                    0,
+                );
+            }
+            stats.draw_calls += 1;
+            stats.tiles += chunk.tile_count;
+        }
+        unsafe { gl.bind_vertex_array(None) };
+        stats
+    }
+
+    /// Delete the chunks, the lookup texture, and the atlas
+    pub fn delete(&self, gl: &mut glow::Context) {
+        for chunk in &self.chunks {
+            chunk.delete(gl);
+        }
+        unsafe { gl.delete_buffer(self.ebo) };
+        resources::untrack(ResourceKind::Buffer, self.ebo);
+        self.program.delete(gl);
+        self.lookup.delete(gl);
+        self.atlas.delete(gl);
+    }
+}
+
+impl Chunk {
+    fn new(
+        gl: &mut glow::Context,
+        ebo: u32,
+        vertices: &[u8],
+        origin: [f32; 2],
+        size: [f32; 2],
+        tile_count: usize,
+    ) -> Self {
+        unsafe {
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, vertices, glow::STATIC_DRAW);
+            // The corner of the tile in the chunk, and the tile
+            VertexLayout::new(&[(0, VertexFormat::Float16x2), (1, VertexFormat::Float32)])
+                .apply(gl);
+            gl.bind_buffer(glow::ELEMENT_ARRAY_BUFFER, Some(ebo));
+            gl.bind_vertex_array(None);
+
+            resources::track(ResourceKind::VertexArray, vao, "Tilemap chunk vertex array");
+            resources::track_sized(
+                ResourceKind::Buffer,
+                vbo,
+                "Tilemap chunk vertex buffer",
+                vertices.len() as u64,
+            );
+            Self {
+                vao,
+                vbo,
+                origin,
+                size,
+                tile_count,
+            }
+        }
+    }
+
+    fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+        }
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+        resources::untrack(ResourceKind::Buffer, self.vbo);
+    }
+}
+
+/// Make a tile show an atlas tile, in the lookup's pixels, returning whether that changed them
+fn set_shown(shown: &mut ImageData, atlas: &TileAtlas, tile: u32, atlas_tile: u32) -> bool {
+    let start = (tile * 4) as usize;
+    let cell = [
+        (atlas_tile % atlas.columns) as u8,
+        (atlas_tile / atlas.columns) as u8,
+    ];
+    let changed = shown.pixels[start..start + 2] != cell;
+    shown.pixels[start..start + 2].copy_from_slice(&cell);
+    changed
+}
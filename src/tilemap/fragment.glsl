@@ -0,0 +1,15 @@
+#version 330 core
+in vec2 uv;
+
+uniform sampler2D atlas;
+
+out vec4 FragColor;
+
+void main() {
+    vec4 color = texture(atlas, uv);
+    // Tiles are either there or not, so see-through pixels show the tiles drawn before them
+    if (color.a < 0.5) {
+        discard;
+    }
+    FragColor = color;
+}
@@ -0,0 +1,35 @@
+#version 330 core
+// The corner of the tile, relative to the chunk, in tiles
+layout (location = 0) in vec2 aPosition;
+// The tile the layer has here, which the lookup texture turns into the atlas tile to show
+layout (location = 1) in float aTile;
+
+// How many entries go across each row of the lookup texture, which must match `LOOKUP_WIDTH`
+const int LOOKUP_WIDTH = 256;
+
+// The corners of each tile's quad, in the order its four vertices are stored
+const vec2 CORNERS[4] = vec2[](vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(1.0, 1.0), vec2(0.0, 1.0));
+
+uniform mat4 viewProjection;
+uniform vec2 chunkOrigin;
+// The column and row in the atlas of the tile to show for each tile, in bytes
+uniform sampler2D lookup;
+// The size of the atlas, and of each tile and the padding around it, in pixels
+uniform vec2 atlasSize;
+uniform float tileSize;
+uniform float padding;
+
+out vec2 uv;
+
+void main() {
+    // The vertex index goes around the quad, since every tile has four vertices in a row
+    vec2 corner = CORNERS[gl_VertexID % 4];
+    int tile = int(aTile);
+    vec2 cell = floor(
+        texelFetch(lookup, ivec2(tile % LOOKUP_WIDTH, tile / LOOKUP_WIDTH), 0).rg * 255.0 + 0.5
+    );
+    // The first row of a tile's pixels is its top, so the top of the quad reads it
+    vec2 topLeft = cell * (tileSize + 2.0 * padding) + padding;
+    uv = (topLeft + vec2(corner.x, 1.0 - corner.y) * tileSize) / atlasSize;
+    gl_Position = viewProjection * vec4(chunkOrigin + aPosition, 0.0, 1.0);
+}
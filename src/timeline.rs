@@ -0,0 +1,498 @@
+use std::{cell::Cell, fmt::Write as _, io, path::Path, rc::Rc, time::Duration};
+
+use glow::HasContext;
+
+use crate::{
+    debug_group::DebugGroup,
+    nested::{NestedRenderer, SavedState},
+    resources::{self, ResourceKind},
+    shader::{ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    AppContext, HandlerFactory,
+};
+
+const CROSSFADE_FRAGMENT_SRC: &str = include_str!("timeline/crossfade.frag");
+
+/// The frame rate of scripts that don't give one
+pub const DEFAULT_FRAME_RATE: u32 = 60;
+
+/// How a cue's scene takes over from the scene before it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transition {
+    /// Switch to the scene at once
+    Cut,
+    /// Blend from the scene before to the new one over the given number of seconds
+    Crossfade(f32),
+}
+
+/// A point on a timeline where another scene starts
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cue {
+    /// The time in seconds from the start of the timeline
+    pub time: f32,
+    /// The name of the scene to show, which is one of the timeline's scenes
+    pub scene: String,
+    /// The scene's own time when the cue starts, so that a cue can start partway into a scene,
+    /// or cut to another shot of the same scene
+    pub scene_time: f32,
+    pub transition: Transition,
+}
+
+impl Cue {
+    /// The scene's own time at the given timeline time
+    pub fn scene_time_at(&self, time: f32) -> f32 {
+        self.scene_time + time - self.time
+    }
+
+    /// How far the transition into the cue is at the given timeline time, from 0 to 1
+    pub fn fade_at(&self, time: f32) -> f32 {
+        match self.transition {
+            Transition::Cut => 1.,
+            Transition::Crossfade(duration) => {
+                ((time - self.time) / duration.max(f32::EPSILON)).clamp(0., 1.)
+            }
+        }
+    }
+}
+
+/// The cues of a timeline, and the frame rate that its clock steps at
+///
+/// Scripts can be saved to and loaded from RON files like this:
+///
+/// ```text
+/// Timeline(
+///     frame_rate: 60,
+///     cues: [
+///         (time: 0.0, scene: "triangle"),
+///         (time: 10.0, scene: "quad", crossfade: 2.0),
+///         (time: 20.0, scene: "lit", scene_time: 5.0),
+///     ],
+/// )
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimelineScript {
+    /// How many frames the timeline draws for every second of its time
+    pub frame_rate: u32,
+    /// The cues, sorted by time
+    cues: Vec<Cue>,
+}
+
+impl Default for TimelineScript {
+    fn default() -> Self {
+        Self::new(DEFAULT_FRAME_RATE)
+    }
+}
+
+impl TimelineScript {
+    pub fn new(frame_rate: u32) -> Self {
+        Self {
+            frame_rate: frame_rate.max(1),
+            cues: Vec::new(),
+        }
+    }
+
+    /// The cues, sorted by time
+    pub fn cues(&self) -> &[Cue] {
+        &self.cues
+    }
+
+    /// Add a cue, after any cues at the same time
+    pub fn add_cue(&mut self, cue: Cue) {
+        let index = self
+            .cues
+            .iter()
+            .position(|c| c.time > cue.time)
+            .unwrap_or(self.cues.len());
+        self.cues.insert(index, cue);
+    }
+
+    /// The index of the cue that is showing at the given time, or `None` before the first cue
+    pub fn cue_at(&self, time: f32) -> Option<usize> {
+        self.cues.iter().rposition(|cue| cue.time <= time)
+    }
+
+    /// The length of one frame of the timeline's clock
+    pub fn frame_time(&self) -> Duration {
+        Duration::from_secs_f64(1. / self.frame_rate.max(1) as f64)
+    }
+
+    /// Write the script in RON format
+    pub fn to_ron(&self) -> String {
+        let mut ron = format!(
+            "Timeline(\n    frame_rate: {},\n    cues: [\n",
+            self.frame_rate
+        );
+        for cue in &self.cues {
+            write!(ron, "        (time: {:?}, scene: {:?}", cue.time, cue.scene).unwrap();
+            if cue.scene_time != 0. {
+                write!(ron, ", scene_time: {:?}", cue.scene_time).unwrap();
+            }
+            if let Transition::Crossfade(duration) = cue.transition {
+                write!(ron, ", crossfade: {:?}", duration).unwrap();
+            }
+            ron.push_str("),\n");
+        }
+        ron.push_str("    ],\n)\n");
+        ron
+    }
+
+    /// Read a script written by `to_ron`
+    ///
+    /// Every cue needs a `time` and a `scene`. `scene_time` defaults to 0, and cues without a
+    /// `crossfade` cut to their scene. Comments starting with `//` are skipped.
+    pub fn from_ron(ron: &str) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        let ron = ron
+            .lines()
+            .map(|line| line.split("//").next().unwrap_or_default())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let frame_rate = match ron.find("frame_rate") {
+            Some(start) => ron[start..]
+                .split(|c: char| !c.is_ascii_digit())
+                .find(|word| !word.is_empty())
+                .and_then(|word| word.parse::<u32>().ok())
+                .filter(|&frame_rate| frame_rate > 0)
+                .ok_or_else(|| invalid("Invalid timeline frame rate".into()))?,
+            None => DEFAULT_FRAME_RATE,
+        };
+        let cues = ron
+            .find("cues")
+            .ok_or_else(|| invalid("The timeline has no cues".into()))?;
+
+        // Every cue is a tuple of `name: value` fields
+        let mut script = Self::new(frame_rate);
+        for entry in ron[cues..].split('(').skip(1) {
+            let fields = entry.split(')').next().unwrap_or_default();
+            script.add_cue(parse_cue(fields).map_err(invalid)?);
+        }
+        Ok(script)
+    }
+
+    /// Save the script to a RON file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        std::fs::write(path, self.to_ron())
+    }
+
+    /// Load a script from a RON file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_ron(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// Read the comma separated fields of a cue
+fn parse_cue(fields: &str) -> Result<Cue, String> {
+    let mut time = None;
+    let mut scene = None;
+    let mut cue = Cue {
+        time: 0.,
+        scene: String::new(),
+        scene_time: 0.,
+        transition: Transition::Cut,
+    };
+    for field in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let (name, value) = field
+            .split_once(':')
+            .ok_or_else(|| format!("Invalid cue field `{}`", field))?;
+        let (name, value) = (name.trim(), value.trim());
+        let number = || {
+            value
+                .parse::<f32>()
+                .map_err(|_| format!("Invalid `{}` of a cue: {}", name, value))
+        };
+        match name {
+            "time" => time = Some(number()?),
+            "scene" => scene = Some(value.trim_matches('"').to_owned()),
+            "scene_time" => cue.scene_time = number()?,
+            "crossfade" => {
+                let duration = number()?;
+                cue.transition = if duration > 0. {
+                    Transition::Crossfade(duration)
+                } else {
+                    Transition::Cut
+                };
+            }
+            _ => return Err(format!("Unknown cue field `{}`", name)),
+        }
+    }
+    match (time, scene) {
+        (Some(time), Some(scene)) => Ok(Cue { time, scene, ..cue }),
+        _ => Err(format!(
+            "A cue is missing its `time` or `scene`: ({})",
+            fields
+        )),
+    }
+}
+
+/// A scene being shown for one of the cues
+struct ShownCue {
+    /// The index of the cue in the script
+    index: usize,
+    renderer: NestedRenderer,
+}
+
+/// A scripted sequence of scenes, each one a `RenderHandler` drawing into a texture of its own,
+/// which are cut or crossfaded between as the script's cues come up
+///
+/// The timeline follows the outer handler's `ctx.timing.time()`, and steps it with a fixed delta
+/// at the script's frame rate, so every run of the sequence draws the same frames no matter how
+/// fast they are actually drawn. Each scene gets its own time, starting at the cue's
+/// `scene_time`, which the timeline sets every frame. A scene's delta is the same fixed frame
+/// time.
+///
+/// Jumping to another time, with `seek`, the `seek` console command, or the loop's time keys,
+/// exits the scenes being shown and creates the scenes at the new time from their factories
+/// again. Scenes that animate from `ctx.timing.time()` and nothing else look the same at a time
+/// however the timeline got there.
+pub struct Timeline {
+    script: TimelineScript,
+    /// The factories of the scenes, by name
+    scenes: Vec<(String, HandlerFactory)>,
+    current: Option<ShownCue>,
+    /// The scene that `current` is fading in over
+    previous: Option<ShownCue>,
+    /// The time of the last frame, to tell the time playing on from a jump
+    last_time: Option<f32>,
+    /// A time to jump to at the start of the next frame, set by `seek` and the `seek` command
+    pending_seek: Rc<Cell<Option<f32>>>,
+    program: ShaderProgram,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+}
+
+impl Timeline {
+    /// Make a timeline that shows the named scenes as the script says
+    ///
+    /// This sets the fixed delta of `ctx.timing`, and adds the `seek` command to the console.
+    /// It fails if a cue shows a scene that isn't one of `scenes`.
+    pub fn new(
+        gl: &mut glow::Context,
+        ctx: &mut AppContext,
+        script: TimelineScript,
+        scenes: Vec<(String, HandlerFactory)>,
+    ) -> Result<Self, String> {
+        if let Some(cue) = script
+            .cues()
+            .iter()
+            .find(|cue| !scenes.iter().any(|(name, _)| *name == cue.scene))
+        {
+            return Err(format!(
+                "The cue at {}s shows the unknown scene `{}`",
+                cue.time, cue.scene
+            ));
+        }
+        ctx.timing.set_fixed_delta(Some(script.frame_time()));
+
+        let pending_seek = Rc::new(Cell::new(None));
+        let seek = pending_seek.clone();
+        ctx.console.register(
+            "seek",
+            "Jump to a time on the timeline, starting its scenes over: seek <seconds>",
+            move |args, _| {
+                let time = args
+                    .first()
+                    .and_then(|time| time.parse::<f32>().ok())
+                    .filter(|time| time.is_finite())
+                    .ok_or_else(|| "Usage: seek <seconds>".to_string())?;
+                seek.set(Some(time));
+                Ok(format!("Seeking to {}s", time))
+            },
+        );
+
+        let program = ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, CROSSFADE_FRAGMENT_SRC)?;
+        let empty_vao = unsafe { gl.create_vertex_array().unwrap() };
+        resources::track(
+            ResourceKind::VertexArray,
+            empty_vao,
+            "Timeline vertex array",
+        );
+        Ok(Self {
+            script,
+            scenes,
+            current: None,
+            previous: None,
+            last_time: None,
+            pending_seek,
+            program,
+            empty_vao,
+        })
+    }
+
+    pub fn script(&self) -> &TimelineScript {
+        &self.script
+    }
+
+    /// The cue whose scene is being shown, or faded in
+    pub fn current_cue(&self) -> Option<&Cue> {
+        self.current
+            .as_ref()
+            .map(|shown| &self.script.cues[shown.index])
+    }
+
+    /// Jump to a time at the start of the next frame, starting the scenes at that time over even
+    /// if they are already showing
+    pub fn seek(&self, time: f32) {
+        self.pending_seek.set(Some(time));
+    }
+
+    /// Draw the scenes at the current time, and cut or crossfade between them over the bound
+    /// framebuffer
+    pub fn draw(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        if let Some(time) = self.pending_seek.take() {
+            ctx.timing.set_time(time);
+            self.last_time = None;
+        }
+        let time = ctx.timing.time();
+        let step = self.script.frame_time().as_secs_f32();
+
+        // Playing moves the time forward by a frame, and pausing doesn't move it at all, so any
+        // other change is a jump
+        let playing =
+            matches!(self.last_time, Some(last) if time >= last && time - last <= step * 1.5);
+        let index = self.script.cue_at(time);
+        let current = self.current.as_ref().map(|shown| shown.index);
+        if !playing {
+            self.start_at(gl, ctx, time);
+        } else if index != current {
+            match index {
+                Some(index) if index == current.map_or(0, |current| current + 1) => {
+                    self.advance(gl, ctx, index)
+                }
+                _ => self.start_at(gl, ctx, time),
+            }
+        }
+        self.last_time = Some(time);
+
+        // The scene being faded out is done once the fade is
+        let fade = self.current_cue().map_or(1., |cue| cue.fade_at(time));
+        if fade >= 1. {
+            if let Some(mut previous) = self.previous.take() {
+                previous.renderer.exit(gl);
+            }
+        }
+
+        let size = ctx.render_size();
+        let cues = &self.script.cues;
+        for shown in self.previous.iter_mut().chain(self.current.iter_mut()) {
+            shown.renderer.resize(size);
+            let scene_time = cues[shown.index].scene_time_at(time);
+            shown.renderer.context_mut().timing.set_time(scene_time);
+            shown.renderer.draw(gl, ctx);
+        }
+
+        let to = match self
+            .current
+            .as_ref()
+            .and_then(|shown| shown.renderer.texture())
+        {
+            Some(texture) => texture,
+            None => return,
+        };
+        let from = self
+            .previous
+            .as_ref()
+            .and_then(|shown| shown.renderer.texture())
+            .unwrap_or(to);
+        let _group = DebugGroup::push(gl, "Crossfade");
+        unsafe {
+            let state = SavedState::save(gl);
+            gl.disable(glow::DEPTH_TEST);
+            gl.disable(glow::BLEND);
+            gl.disable(glow::CULL_FACE);
+            gl.disable(glow::STENCIL_TEST);
+
+            let program = &mut self.program;
+            program.bind(gl);
+            program.set_uniform(gl, "from", 0);
+            program.set_uniform(gl, "to", 1);
+            program.set_uniform(gl, "amount", fade);
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(from));
+            gl.active_texture(glow::TEXTURE1);
+            gl.bind_texture(glow::TEXTURE_2D, Some(to));
+            gl.bind_vertex_array(Some(self.empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+
+            state.restore(gl);
+        }
+    }
+
+    /// Create the scene of a cue, drawing into a texture the size of the outer render size
+    fn show(&self, gl: &mut glow::Context, ctx: &AppContext, index: usize) -> ShownCue {
+        let cue = &self.script.cues[index];
+        // Every cue's scene was checked in `new`
+        let (_, factory) = self
+            .scenes
+            .iter()
+            .find(|(name, _)| *name == cue.scene)
+            .unwrap();
+        let mut renderer = NestedRenderer::new(gl, ctx, ctx.render_size(), factory);
+
+        // The timeline sets the scene's time every frame, which pausing it keeps
+        let timing = &mut renderer.context_mut().timing;
+        timing.set_paused(true);
+        timing.set_fixed_delta(Some(self.script.frame_time()));
+        ShownCue { index, renderer }
+    }
+
+    /// Move on to the next cue while playing, keeping the scene before it to fade out if the cue
+    /// crossfades
+    fn advance(&mut self, gl: &mut glow::Context, ctx: &AppContext, index: usize) {
+        if let Some(mut previous) = self.previous.take() {
+            previous.renderer.exit(gl);
+        }
+        match self.script.cues[index].transition {
+            Transition::Crossfade(_) => self.previous = self.current.take(),
+            Transition::Cut => {
+                if let Some(mut current) = self.current.take() {
+                    current.renderer.exit(gl);
+                }
+            }
+        }
+        self.current = Some(self.show(gl, ctx, index));
+    }
+
+    /// Exit the scenes being shown, and create the ones at the given time from scratch
+    fn start_at(&mut self, gl: &mut glow::Context, ctx: &AppContext, time: f32) {
+        self.stop(gl);
+        let index = match self.script.cue_at(time) {
+            Some(index) => index,
+            None => return,
+        };
+        // A jump into the middle of a crossfade needs the scene being faded out too
+        if index > 0 && self.script.cues[index].fade_at(time) < 1. {
+            self.previous = Some(self.show(gl, ctx, index - 1));
+        }
+        self.current = Some(self.show(gl, ctx, index));
+    }
+
+    /// Exit the scenes being shown
+    fn stop(&mut self, gl: &mut glow::Context) {
+        for mut shown in self.previous.take().into_iter().chain(self.current.take()) {
+            shown.renderer.exit(gl);
+        }
+    }
+
+    /// Pass on the loss of the window surface or GL context to the scenes
+    pub fn device_lost(&mut self, gl: &mut glow::Context) {
+        for shown in self.previous.iter_mut().chain(self.current.iter_mut()) {
+            shown.renderer.device_lost(gl);
+        }
+    }
+
+    /// Pass on the window surface coming back to the scenes
+    pub fn device_restored(&mut self, gl: &mut glow::Context) {
+        for shown in self.previous.iter_mut().chain(self.current.iter_mut()) {
+            shown.renderer.device_restored(gl);
+        }
+    }
+
+    /// Exit the scenes, delete the GL objects, and remove the `seek` command
+    pub fn exit(&mut self, gl: &mut glow::Context, ctx: &mut AppContext) {
+        self.stop(gl);
+        ctx.console.unregister("seek");
+        unsafe { gl.delete_vertex_array(self.empty_vao) };
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+        self.program.delete(gl);
+    }
+}
@@ -0,0 +1,15 @@
+#version 330 core
+
+in vec2 texCoord;
+
+out vec4 FragColor;
+
+// The scene being faded out and the scene being faded in
+uniform sampler2D from;
+uniform sampler2D to;
+// How far the fade is, from 0 for only `from` to 1 for only `to`
+uniform float amount;
+
+void main() {
+    FragColor = mix(texture(from, texCoord), texture(to, texCoord), amount);
+}
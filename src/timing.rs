@@ -0,0 +1,232 @@
+use std::time::{Duration, Instant};
+
+/// How much of the average is made up of the newest frame, for the rolling averages
+pub(crate) const AVERAGE_WEIGHT: f64 = 0.05;
+
+/// How long each step of presenting a frame took on the CPU
+///
+/// Surfman can only present a surface that isn't bound to a context, so every frame the loop
+/// unbinds the window surface, presents it, and binds it again.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PresentTimes {
+    /// Unbinding the surface, which also flushes the GL commands on the EGL based backends
+    pub unbind: Duration,
+    /// Presenting the surface, which may wait for vsync
+    pub present: Duration,
+    /// Binding the surface back to the context
+    pub bind: Duration,
+}
+
+impl PresentTimes {
+    /// The time taken by all of the steps together
+    pub fn total(&self) -> Duration {
+        self.unbind + self.present + self.bind
+    }
+
+    /// Move the average towards the newest times
+    fn blend(&self, newest: &PresentTimes) -> PresentTimes {
+        let blend = |average: Duration, newest: Duration| {
+            average.mul_f64(1. - AVERAGE_WEIGHT) + newest.mul_f64(AVERAGE_WEIGHT)
+        };
+        PresentTimes {
+            unbind: blend(self.unbind, newest.unbind),
+            present: blend(self.present, newest.present),
+            bind: blend(self.bind, newest.bind),
+        }
+    }
+}
+
+/// Frame timing information for a single window
+///
+/// There are two kinds of time here: the real time between frames, returned by `delta`, and the
+/// animation time returned by `time`. The animation time normally advances with the real time, but
+/// it can be paused and moved around with `set_time` for debugging animations, so shaders and
+/// other animations should always be driven by `time`.
+#[derive(Debug)]
+pub struct Timing {
+    /// The instant that the current frame started
+    frame_start: Instant,
+    /// The time between the start of the last frame and the start of the current frame
+    delta: Duration,
+    /// The number of frames that have been started
+    frame_count: u64,
+    /// The current animation time in seconds
+    time: f64,
+    /// Whether or not the animation time is paused
+    paused: bool,
+    /// The frame time to use instead of the real time between frames
+    fixed_delta: Option<Duration>,
+    /// The rolling average of `delta`
+    average_delta: Duration,
+    /// The present times of the last frame, if it was presented
+    last_present: Option<PresentTimes>,
+    /// The rolling average of the present times
+    average_present: PresentTimes,
+}
+
+impl Timing {
+    pub(crate) fn new() -> Self {
+        Self {
+            frame_start: Instant::now(),
+            delta: Duration::from_secs(0),
+            frame_count: 0,
+            time: 0.,
+            paused: false,
+            fixed_delta: None,
+            average_delta: Duration::from_secs(0),
+            last_present: None,
+            average_present: PresentTimes::default(),
+        }
+    }
+
+    /// The current animation time in seconds
+    pub fn time(&self) -> f32 {
+        self.time as f32
+    }
+
+    /// Jump to a different animation time
+    ///
+    /// The new time is picked up by the next frame, even while paused.
+    pub fn set_time(&mut self, time: f32) {
+        self.time = time as f64;
+    }
+
+    /// Move the animation time forward, or backward for negative amounts
+    pub fn step_time(&mut self, seconds: f32) {
+        self.time += seconds as f64;
+    }
+
+    /// Whether or not the animation time is paused
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Pause or resume the animation time
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// The number of real seconds between the last frame and the current one
+    ///
+    /// This keeps ticking while the animation time is paused.
+    pub fn delta(&self) -> f32 {
+        self.delta.as_secs_f32()
+    }
+
+    /// The time between the last frame and the current one
+    pub fn delta_duration(&self) -> Duration {
+        self.delta
+    }
+
+    /// Use a fixed frame time instead of the real time between frames, or go back to the real
+    /// time with `None`
+    ///
+    /// With a fixed frame time every run of the same frames produces the same `delta` and `time`,
+    /// no matter how fast the frames were actually drawn.
+    pub fn set_fixed_delta(&mut self, fixed_delta: Option<Duration>) {
+        self.fixed_delta = fixed_delta;
+    }
+
+    /// The number of the current frame, starting at 1 for the first frame
+    pub fn frame_count(&self) -> u64 {
+        self.frame_count
+    }
+
+    /// The rolling average of `delta` in seconds, for a steadier frame rate readout
+    pub fn average_delta(&self) -> f32 {
+        self.average_delta.as_secs_f32()
+    }
+
+    /// How long presenting the last frame took, or `None` if it wasn't presented
+    pub fn last_present(&self) -> Option<PresentTimes> {
+        self.last_present
+    }
+
+    /// The rolling average of the present times of the frames that were presented
+    pub fn average_present(&self) -> PresentTimes {
+        self.average_present
+    }
+
+    /// Record how long presenting the current frame took, or `None` if it was skipped
+    pub(crate) fn record_present(&mut self, times: Option<PresentTimes>) {
+        if let Some(times) = &times {
+            self.average_present = if self.average_present == PresentTimes::default() {
+                *times
+            } else {
+                self.average_present.blend(times)
+            };
+        }
+        self.last_present = times;
+    }
+
+    /// Start the next frame's delta from now, so that a stretch without frames, like while the
+    /// window was minimized, isn't counted as one long frame
+    pub(crate) fn skip_gap(&mut self) {
+        self.frame_start = Instant::now();
+    }
+
+    /// Record the start of a new frame
+    pub(crate) fn begin_frame(&mut self) {
+        let now = Instant::now();
+        self.delta = self
+            .fixed_delta
+            .unwrap_or_else(|| now.duration_since(self.frame_start));
+        self.frame_start = now;
+        self.frame_count += 1;
+        self.average_delta = if self.frame_count <= 2 {
+            self.delta
+        } else {
+            self.average_delta.mul_f64(1. - AVERAGE_WEIGHT) + self.delta.mul_f64(AVERAGE_WEIGHT)
+        };
+
+        if !self.paused {
+            self.time += self.delta.as_secs_f64();
+        }
+    }
+}
+
+/// Splits the frame time into steps of a fixed length, for simulations like physics that behave
+/// differently depending on how big their steps are
+///
+/// Each frame, pass the frame time to `advance` and run the simulation once for every step it
+/// returns. The time left over is carried into the next frame.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FixedTimestep {
+    /// The length of a step in seconds
+    pub step: f32,
+    /// The most steps to run in one frame. After a long hitch the simulation falls behind instead
+    /// of taking ever longer frames to catch up.
+    pub max_steps: u32,
+    /// The time that hasn't been simulated yet
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(step: f32) -> Self {
+        Self {
+            step,
+            max_steps: 8,
+            accumulator: 0.,
+        }
+    }
+
+    /// Add the time of a frame, and get the number of steps to simulate for it
+    pub fn advance(&mut self, delta: f32) -> u32 {
+        self.accumulator += delta;
+        let steps = (self.accumulator / self.step) as u32;
+        self.accumulator -= steps as f32 * self.step;
+        if steps > self.max_steps {
+            // Drop the time we can't catch up on
+            self.accumulator = 0.;
+            self.max_steps
+        } else {
+            steps
+        }
+    }
+
+    /// How far the frame is between the last step and the next one, from 0 to 1, for
+    /// interpolating what is drawn
+    pub fn alpha(&self) -> f32 {
+        self.accumulator / self.step
+    }
+}
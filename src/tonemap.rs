@@ -0,0 +1,3 @@
+/// The GLSL for tone mapping, which defines `tonemapReinhard` and `encodeGamma`. Add it to a
+/// shader with `shader::include_chunk`.
+pub const TONEMAP_CHUNK: &str = include_str!("tonemap/tonemap.glsl");
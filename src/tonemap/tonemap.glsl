@@ -0,0 +1,11 @@
+// Bringing HDR colors back into the 0 to 1 range for the window
+
+// Reinhard tone mapping, which maps 0 to 0 and infinity to 1 and leaves dark colors mostly alone
+vec3 tonemapReinhard(vec3 color) {
+    return color / (color + 1.0);
+}
+
+// Encode a linear color for a window without an sRGB framebuffer
+vec3 encodeGamma(vec3 color) {
+    return pow(color, vec3(1.0 / 2.2));
+}
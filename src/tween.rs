@@ -0,0 +1,401 @@
+use cgmath::{Decomposed, Point3, Quaternion, Vector2, Vector3, Vector4};
+
+use crate::timing::Timing;
+
+/// A curve that shapes how a tween moves from its start to its end
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Easing {
+    #[default]
+    Linear,
+    QuadIn,
+    QuadOut,
+    QuadInOut,
+    CubicIn,
+    CubicOut,
+    CubicInOut,
+    /// Winds up with a few growing wobbles before shooting to the end
+    ElasticIn,
+    /// Overshoots the end and wobbles around it before settling
+    ElasticOut,
+    /// Bounces off of the start a few times before leaving it
+    BounceIn,
+    /// Bounces against the end a few times before settling, like a dropped ball
+    BounceOut,
+}
+
+impl Easing {
+    /// All of the easings, for showing them off
+    pub const ALL: &'static [Easing] = &[
+        Easing::Linear,
+        Easing::QuadIn,
+        Easing::QuadOut,
+        Easing::QuadInOut,
+        Easing::CubicIn,
+        Easing::CubicOut,
+        Easing::CubicInOut,
+        Easing::ElasticIn,
+        Easing::ElasticOut,
+        Easing::BounceIn,
+        Easing::BounceOut,
+    ];
+
+    /// Map a linear progress from 0 to 1 onto the curve
+    ///
+    /// The result is 0 at the start and 1 at the end, but elastic easings go past both in between.
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0., 1.);
+        match self {
+            Easing::Linear => t,
+            Easing::QuadIn => t * t,
+            Easing::QuadOut => 1. - (1. - t) * (1. - t),
+            Easing::QuadInOut => {
+                if t < 0.5 {
+                    2. * t * t
+                } else {
+                    1. - 2. * (1. - t) * (1. - t)
+                }
+            }
+            Easing::CubicIn => t * t * t,
+            Easing::CubicOut => 1. - (1. - t).powi(3),
+            Easing::CubicInOut => {
+                if t < 0.5 {
+                    4. * t * t * t
+                } else {
+                    1. - 4. * (1. - t).powi(3)
+                }
+            }
+            Easing::ElasticIn => 1. - Easing::ElasticOut.apply(1. - t),
+            Easing::ElasticOut => {
+                if t == 0. || t == 1. {
+                    t
+                } else {
+                    let period = std::f32::consts::TAU / 3.;
+                    2f32.powf(-10. * t) * ((t * 10. - 0.75) * period).sin() + 1.
+                }
+            }
+            Easing::BounceIn => 1. - Easing::BounceOut.apply(1. - t),
+            Easing::BounceOut => {
+                // Four parabolas, each a smaller bounce than the last
+                const N: f32 = 7.5625;
+                const D: f32 = 2.75;
+                if t < 1. / D {
+                    N * t * t
+                } else if t < 2. / D {
+                    let t = t - 1.5 / D;
+                    N * t * t + 0.75
+                } else if t < 2.5 / D {
+                    let t = t - 2.25 / D;
+                    N * t * t + 0.9375
+                } else {
+                    let t = t - 2.625 / D;
+                    N * t * t + 0.984375
+                }
+            }
+        }
+    }
+}
+
+/// A value that can be blended between two others
+pub trait Lerp: Clone {
+    /// Blend from `self` at 0 to `other` at 1
+    ///
+    /// `t` can go outside of 0 to 1 for easings that overshoot.
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Lerp for f32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for [f32; 3] {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        [
+            self[0].lerp(&other[0], t),
+            self[1].lerp(&other[1], t),
+            self[2].lerp(&other[2], t),
+        ]
+    }
+}
+
+impl Lerp for [f32; 4] {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        [
+            self[0].lerp(&other[0], t),
+            self[1].lerp(&other[1], t),
+            self[2].lerp(&other[2], t),
+            self[3].lerp(&other[3], t),
+        ]
+    }
+}
+
+impl Lerp for Vector2<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vector3<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Vector4<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Point3<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Lerp for Quaternion<f32> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        // Slerp is only defined between the two rotations, so overshooting easings fall back to
+        // nlerp, which extrapolates fine
+        if (0. ..=1.).contains(&t) {
+            self.slerp(*other, t)
+        } else {
+            self.nlerp(*other, t)
+        }
+    }
+}
+
+/// Translation, rotation, and uniform scale
+impl Lerp for Decomposed<Vector3<f32>, Quaternion<f32>> {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        Decomposed {
+            scale: self.scale.lerp(&other.scale, t),
+            rot: self.rot.lerp(&other.rot, t),
+            disp: self.disp.lerp(&other.disp, t),
+        }
+    }
+}
+
+/// How many times a tween plays
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Repeat {
+    #[default]
+    Once,
+    /// Play this many times in total
+    Times(u32),
+    Forever,
+}
+
+/// An animation of a value from one end to another
+///
+/// Tweens advance with the frame time and stop while the animation time is paused.
+#[derive(Clone, Debug)]
+pub struct Tween<T> {
+    pub from: T,
+    pub to: T,
+    /// How long one play of the tween lasts in seconds
+    pub duration: f32,
+    /// How long to wait before the first play in seconds
+    pub delay: f32,
+    pub easing: Easing,
+    pub repeat: Repeat,
+    /// Whether every other play goes backwards, from `to` to `from`
+    pub ping_pong: bool,
+    /// The seconds since the tween was started, including the delay
+    elapsed: f32,
+    /// Whether the completion has already been reported
+    completion_reported: bool,
+}
+
+impl<T: Lerp> Tween<T> {
+    /// A tween that plays once with linear easing
+    pub fn new(from: T, to: T, duration: f32) -> Self {
+        Self {
+            from,
+            to,
+            duration,
+            delay: 0.,
+            easing: Easing::Linear,
+            repeat: Repeat::Once,
+            ping_pong: false,
+            elapsed: 0.,
+            completion_reported: false,
+        }
+    }
+
+    /// Set the easing
+    pub fn easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+
+    /// Set the delay before the first play
+    pub fn delay(mut self, delay: f32) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Set how many times the tween plays
+    pub fn repeat(mut self, repeat: Repeat) -> Self {
+        self.repeat = repeat;
+        self
+    }
+
+    /// Make every other play go backwards
+    pub fn ping_pong(mut self) -> Self {
+        self.ping_pong = true;
+        self
+    }
+
+    /// Start the tween over
+    pub fn restart(&mut self) {
+        self.elapsed = 0.;
+        self.completion_reported = false;
+    }
+
+    /// The total length of the tween in seconds, including the delay, or `None` if it repeats
+    /// forever
+    pub fn total_duration(&self) -> Option<f32> {
+        match self.repeat {
+            Repeat::Once => Some(self.delay + self.duration),
+            Repeat::Times(times) => Some(self.delay + self.duration * times as f32),
+            Repeat::Forever => None,
+        }
+    }
+
+    /// Whether or not the tween has played to the end
+    pub fn is_finished(&self) -> bool {
+        self.total_duration()
+            .is_some_and(|total| self.elapsed >= total)
+    }
+
+    /// Move the tween forward by the frame time, returning true on the one update where it
+    /// finishes
+    pub fn update(&mut self, timing: &Timing) -> bool {
+        if timing.is_paused() {
+            self.advance(0.)
+        } else {
+            self.advance(timing.delta())
+        }
+    }
+
+    /// Move the tween forward by some seconds, returning true on the one update where it
+    /// finishes
+    pub fn advance(&mut self, seconds: f32) -> bool {
+        self.elapsed += seconds.max(0.);
+        if self.is_finished() && !self.completion_reported {
+            self.completion_reported = true;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The current value of the tween
+    pub fn value(&self) -> T {
+        if self.is_finished() {
+            return self.end_value();
+        }
+        let time = self.elapsed - self.delay;
+        if time <= 0. || self.duration <= 0. {
+            return self.from.clone();
+        }
+
+        // Work out which play we are in and how far through it we are
+        let play = (time / self.duration).floor();
+        let mut t = time / self.duration - play;
+        if self.ping_pong && play as u32 % 2 == 1 {
+            t = 1. - t;
+        }
+        self.from.lerp(&self.to, self.easing.apply(t))
+    }
+
+    /// The value that the tween stops at
+    fn end_value(&self) -> T {
+        let plays = match self.repeat {
+            Repeat::Once => 1,
+            Repeat::Times(times) => times,
+            Repeat::Forever => 1,
+        };
+        // A ping-pong tween that plays an even number of times ends back where it started
+        if self.ping_pong && plays % 2 == 0 {
+            self.from.clone()
+        } else {
+            self.to.clone()
+        }
+    }
+}
+
+/// Identifies a tween in an `Animator`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TweenId(usize);
+
+/// Owns a set of tweens and updates them all together
+#[derive(Clone, Debug)]
+pub struct Animator<T> {
+    tweens: Vec<Option<Tween<T>>>,
+}
+
+impl<T> Default for Animator<T> {
+    fn default() -> Self {
+        Self { tweens: Vec::new() }
+    }
+}
+
+impl<T: Lerp> Animator<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start animating a tween
+    pub fn add(&mut self, tween: Tween<T>) -> TweenId {
+        // Reuse the slot of a removed tween if there is one
+        match self.tweens.iter().position(Option::is_none) {
+            Some(index) => {
+                self.tweens[index] = Some(tween);
+                TweenId(index)
+            }
+            None => {
+                self.tweens.push(Some(tween));
+                TweenId(self.tweens.len() - 1)
+            }
+        }
+    }
+
+    /// Stop animating a tween and give it back
+    pub fn remove(&mut self, id: TweenId) -> Option<Tween<T>> {
+        self.tweens.get_mut(id.0)?.take()
+    }
+
+    pub fn get(&self, id: TweenId) -> Option<&Tween<T>> {
+        self.tweens.get(id.0)?.as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: TweenId) -> Option<&mut Tween<T>> {
+        self.tweens.get_mut(id.0)?.as_mut()
+    }
+
+    /// The current value of a tween
+    pub fn value(&self, id: TweenId) -> Option<T> {
+        self.get(id).map(Tween::value)
+    }
+
+    /// Update every tween for the frame, returning the ones that finished this frame
+    ///
+    /// Finished tweens stay in the animator, holding their end value, until they are removed.
+    pub fn update(&mut self, timing: &Timing) -> Vec<TweenId> {
+        self.tweens
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, tween)| {
+                let finished = tween.as_mut()?.update(timing);
+                if finished {
+                    Some(TweenId(index))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
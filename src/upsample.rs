@@ -0,0 +1,88 @@
+use glow::HasContext;
+
+use crate::{
+    blend::BlendMode,
+    resources::{self, ResourceKind},
+    shader::{self, ShaderProgram, FULLSCREEN_VERTEX_SRC},
+    viewport::Rect,
+};
+
+/// The GLSL for sampling lower resolution textures, which defines `upsampleUv` and
+/// `textureUpsampled`. Add it to a shader with `shader::include_chunk`.
+pub const UPSAMPLE_CHUNK: &str = include_str!("upsample/upsample.glsl");
+
+const UPSAMPLE_FRAGMENT_SRC: &str = include_str!("upsample/upsample.frag");
+
+/// Draws a lower resolution texture over a full resolution target, scaled up with bilinear
+/// filtering
+///
+/// This is for effects that are drawn at a fraction of the resolution, like bloom, and then
+/// blended on top of the scene. Effects that are read by a later shader instead can sample them
+/// with `textureUpsampled` from `UPSAMPLE_CHUNK`, which this uses too.
+#[derive(Debug)]
+pub struct Upsampler {
+    program: ShaderProgram,
+    /// An empty vertex array, because core profile GL needs one bound to draw
+    empty_vao: u32,
+}
+
+impl Upsampler {
+    pub fn new(gl: &mut glow::Context) -> Self {
+        let fragment = shader::include_chunk(UPSAMPLE_FRAGMENT_SRC, UPSAMPLE_CHUNK);
+        let program = ShaderProgram::new(gl, FULLSCREEN_VERTEX_SRC, &fragment).unwrap();
+        let empty_vao = unsafe { gl.create_vertex_array().unwrap() };
+        resources::track(
+            ResourceKind::VertexArray,
+            empty_vao,
+            "Upsampler vertex array",
+        );
+        Self { program, empty_vao }
+    }
+
+    /// Draw a texture over the viewport of the bound framebuffer, blended with `blend`
+    ///
+    /// `viewport` has to be the viewport that is set, since GL can't be asked for it cheaply. The
+    /// depth test is turned off for the draw, and blending is left off afterwards.
+    pub fn composite(
+        &mut self,
+        gl: &mut glow::Context,
+        texture: u32,
+        viewport: Rect,
+        blend: BlendMode,
+    ) {
+        unsafe {
+            let depth_test = gl.is_enabled(glow::DEPTH_TEST);
+            gl.disable(glow::DEPTH_TEST);
+            blend.apply(gl);
+
+            self.program.bind(gl);
+            self.program.set_uniform(gl, "source", 0);
+            self.program.set_uniform(
+                gl,
+                "viewport",
+                [
+                    viewport.x as f32,
+                    viewport.y as f32,
+                    viewport.width as f32,
+                    viewport.height as f32,
+                ],
+            );
+            gl.active_texture(glow::TEXTURE0);
+            gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+            gl.bind_vertex_array(Some(self.empty_vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, 3);
+            gl.bind_vertex_array(None);
+
+            gl.disable(glow::BLEND);
+            if depth_test {
+                gl.enable(glow::DEPTH_TEST);
+            }
+        }
+    }
+
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        unsafe { gl.delete_vertex_array(self.empty_vao) };
+        resources::untrack(ResourceKind::VertexArray, self.empty_vao);
+        self.program.delete(gl);
+    }
+}
@@ -0,0 +1,10 @@
+#version 330 core
+out vec4 FragColor;
+
+// `UPSAMPLE_CHUNK` is included above
+uniform sampler2D source;
+uniform vec4 viewport;
+
+void main() {
+    FragColor = textureUpsampled(source, upsampleUv(viewport));
+}
@@ -0,0 +1,18 @@
+// Sampling textures of a lower resolution than the target being drawn, like half resolution
+// bloom or SSAO
+
+// The UV of the middle of the pixel being drawn, in a texture that covers the viewport at any
+// resolution. `viewport` is the viewport's x, y, width, and height in pixels. Working the UV out
+// from the middle of the pixel, rather than from texel coordinates like `gl_FragCoord.xy * 0.5`,
+// keeps the texture lined up when the sizes don't divide evenly.
+vec2 upsampleUv(vec4 viewport) {
+    return (gl_FragCoord.xy - viewport.xy) / viewport.zw;
+}
+
+// Sample a lower resolution texture with bilinear filtering, keeping the sample between the
+// middles of the edge texels. Without that, the last half texel at the edges of the screen blends
+// in the opposite edge of a repeating texture, or the border color of a clamped one.
+vec4 textureUpsampled(sampler2D source, vec2 uv) {
+    vec2 halfTexel = 0.5 / vec2(textureSize(source, 0));
+    return texture(source, clamp(uv, halfTexel, 1.0 - halfTexel));
+}
@@ -0,0 +1,197 @@
+use glow::HasContext;
+
+/// The format of a single vertex attribute in a vertex buffer
+///
+/// All of these are read as floats ( or float vectors ) in the shader. The packed formats take
+/// less memory at the cost of some precision.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VertexFormat {
+    Float32,
+    Float32x2,
+    Float32x3,
+    Float32x4,
+    /// Two half floats, e.g. UVs. Build them with `f32_to_f16`.
+    Float16x2,
+    /// Four half floats, e.g. HDR colors. Build them with `f32_to_f16`.
+    Float16x4,
+    /// Four bytes mapped to 0 to 1, e.g. colors. Build them with `pack_unorm8x4`.
+    Unorm8x4,
+    /// Three 10 bit components and a 2 bit component, packed into 4 bytes and mapped to -1 to 1,
+    /// e.g. normals. Build them with `pack_snorm_10_10_10_2`.
+    Snorm10_10_10_2,
+}
+
+impl VertexFormat {
+    /// The number of components in the attribute
+    pub fn components(self) -> i32 {
+        match self {
+            VertexFormat::Float32 => 1,
+            VertexFormat::Float32x2 | VertexFormat::Float16x2 => 2,
+            VertexFormat::Float32x3 => 3,
+            VertexFormat::Float32x4
+            | VertexFormat::Float16x4
+            | VertexFormat::Unorm8x4
+            | VertexFormat::Snorm10_10_10_2 => 4,
+        }
+    }
+
+    /// The GL data type of the attribute
+    pub fn data_type(self) -> u32 {
+        match self {
+            VertexFormat::Float32
+            | VertexFormat::Float32x2
+            | VertexFormat::Float32x3
+            | VertexFormat::Float32x4 => glow::FLOAT,
+            VertexFormat::Float16x2 | VertexFormat::Float16x4 => glow::HALF_FLOAT,
+            VertexFormat::Unorm8x4 => glow::UNSIGNED_BYTE,
+            VertexFormat::Snorm10_10_10_2 => glow::INT_2_10_10_10_REV,
+        }
+    }
+
+    /// Whether or not GL should map the integer values of the attribute to the 0 to 1 ( or -1 to
+    /// 1 for signed types ) range
+    pub fn normalized(self) -> bool {
+        matches!(self, VertexFormat::Unorm8x4 | VertexFormat::Snorm10_10_10_2)
+    }
+
+    /// The size of the attribute in bytes
+    pub fn size(self) -> usize {
+        match self {
+            VertexFormat::Float32 => 4,
+            VertexFormat::Float32x2 => 8,
+            VertexFormat::Float32x3 => 12,
+            VertexFormat::Float32x4 => 16,
+            VertexFormat::Float16x2 => 4,
+            VertexFormat::Float16x4 => 8,
+            VertexFormat::Unorm8x4 => 4,
+            VertexFormat::Snorm10_10_10_2 => 4,
+        }
+    }
+}
+
+/// The layout of the interleaved attributes in a vertex buffer
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VertexLayout {
+    /// The shader location and format of each attribute, in the order that they are stored in
+    /// each vertex
+    pub attributes: Vec<(u32, VertexFormat)>,
+    /// How many instances to draw before moving to the next vertex, or 0 to move to the next
+    /// vertex for every vertex
+    pub divisor: u32,
+}
+
+impl VertexLayout {
+    /// Create a layout for per-vertex data
+    pub fn new(attributes: &[(u32, VertexFormat)]) -> Self {
+        Self {
+            attributes: attributes.to_vec(),
+            divisor: 0,
+        }
+    }
+
+    /// Use this layout for per-instance data, moving to the next "vertex" for every instance
+    pub fn per_instance(mut self) -> Self {
+        self.divisor = 1;
+        self
+    }
+
+    /// The size of each vertex in bytes
+    pub fn stride(&self) -> usize {
+        self.attributes
+            .iter()
+            .map(|(_, format)| format.size())
+            .sum()
+    }
+
+    /// Describe the attributes of the buffer bound to `GL_ARRAY_BUFFER` to the bound VAO
+    pub fn apply(&self, gl: &mut glow::Context) {
+        let stride = self.stride() as i32;
+        let mut offset = 0;
+
+        for &(location, format) in &self.attributes {
+            unsafe {
+                gl.vertex_attrib_pointer_f32(
+                    location,
+                    format.components(),
+                    format.data_type(),
+                    format.normalized(),
+                    stride,
+                    offset,
+                );
+                gl.enable_vertex_attrib_array(location);
+                gl.vertex_attrib_divisor(location, self.divisor);
+            }
+            offset += format.size() as i32;
+        }
+    }
+}
+
+/// Convert a float to a half float, rounding to the nearest value
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+
+    // Infinity and NaN
+    if exponent == 0xff {
+        let nan_bit = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan_bit;
+    }
+
+    // Re-bias the exponent from 127 to 15
+    let half_exponent = exponent - 127 + 15;
+
+    // Too large to represent, so round to infinity
+    if half_exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+
+    // Too small for a normal half float, so make a subnormal one, or zero
+    if half_exponent <= 0 {
+        if half_exponent < -10 {
+            return sign;
+        }
+        // Add the implicit leading 1 and shift it into place
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - half_exponent) as u32;
+        let half_mantissa = mantissa >> shift;
+        // Round to nearest, ties to even
+        let remainder = mantissa & ((1 << shift) - 1);
+        let halfway = 1 << (shift - 1);
+        let round_up = remainder > halfway || (remainder == halfway && half_mantissa & 1 == 1);
+        return sign | (half_mantissa + round_up as u32) as u16;
+    }
+
+    // A normal half float. Rounding the mantissa up may carry into the exponent, which is still
+    // the correct result, including overflowing to infinity.
+    let half = ((half_exponent as u32) << 10) | (mantissa >> 13);
+    let remainder = mantissa & 0x1fff;
+    let round_up = remainder > 0x1000 || (remainder == 0x1000 && half & 1 == 1);
+    sign | (half + round_up as u32) as u16
+}
+
+/// Pack a color with components from 0 to 1 into bytes for `VertexFormat::Unorm8x4`
+pub fn pack_unorm8x4(color: [f32; 4]) -> [u8; 4] {
+    let pack = |value: f32| (value.clamp(0., 1.) * 255.).round() as u8;
+    [
+        pack(color[0]),
+        pack(color[1]),
+        pack(color[2]),
+        pack(color[3]),
+    ]
+}
+
+/// Pack a normal, or any vector with components from -1 to 1, for
+/// `VertexFormat::Snorm10_10_10_2`
+///
+/// `w` only gets 2 bits, so it can only be -1, 0, or 1.
+pub fn pack_snorm_10_10_10_2(normal: [f32; 3], w: f32) -> u32 {
+    let pack = |value: f32, bits: u32| {
+        let max = ((1 << (bits - 1)) - 1) as f32;
+        let value = (value.clamp(-1., 1.) * max).round() as i32;
+        // Keep only the low bits of the two's complement value
+        (value as u32) & ((1 << bits) - 1)
+    };
+    pack(normal[0], 10) | pack(normal[1], 10) << 10 | pack(normal[2], 10) << 20 | pack(w, 2) << 30
+}
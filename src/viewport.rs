@@ -0,0 +1,437 @@
+use std::cell::RefCell;
+
+use cgmath::{InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, Vector4};
+use glow::HasContext;
+
+use crate::{color::Color, depth_mode, input::Input, AppContext};
+
+/// The width of the border drawn around insets in physical pixels, at a UI scale of 1
+pub const INSET_BORDER_WIDTH: i32 = 2;
+
+/// A rectangle in window pixels with the origin in the bottom-left corner, like GL viewports
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    /// A rect covering the whole window, at least 1x1 so that a minimized window still has a
+    /// valid viewport
+    pub fn from_window_size((width, height): (u32, u32)) -> Self {
+        Self::new(0, 0, width.max(1) as i32, height.max(1) as i32)
+    }
+
+    /// Grow the rect by `amount` pixels on every side
+    pub fn expand(&self, amount: i32) -> Self {
+        Self::new(
+            self.x - amount,
+            self.y - amount,
+            self.width + amount * 2,
+            self.height + amount * 2,
+        )
+    }
+
+    /// Whether or not the point is inside of the rect
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    /// The width of the rect divided by its height, or 1 for an empty rect, so that projections
+    /// never get an aspect ratio of zero or infinity
+    pub fn aspect_ratio(&self) -> f32 {
+        if self.width <= 0 || self.height <= 0 {
+            1.
+        } else {
+            self.width as f32 / self.height as f32
+        }
+    }
+
+    /// Whether or not the rect covers no pixels
+    pub fn is_empty(&self) -> bool {
+        self.width <= 0 || self.height <= 0
+    }
+
+    /// How many pixels the rect covers, which is 0 for an empty rect
+    pub fn area(&self) -> i64 {
+        if self.is_empty() {
+            0
+        } else {
+            self.width as i64 * self.height as i64
+        }
+    }
+
+    /// The smallest rect that covers both rects. Empty rects cover nothing, so they are ignored.
+    pub fn union(&self, other: &Rect) -> Rect {
+        if other.is_empty() {
+            return *self;
+        }
+        if self.is_empty() {
+            return *other;
+        }
+        let (x, y) = (self.x.min(other.x), self.y.min(other.y));
+        let right = (self.x + self.width).max(other.x + other.width);
+        let top = (self.y + self.height).max(other.y + other.height);
+        Self::new(x, y, right - x, top - y)
+    }
+
+    /// The part of the rect that is also in the other one, or `None` if they don't overlap
+    pub fn intersect(&self, other: &Rect) -> Option<Rect> {
+        let (x, y) = (self.x.max(other.x), self.y.max(other.y));
+        let right = (self.x + self.width).min(other.x + other.width);
+        let top = (self.y + self.height).min(other.y + other.height);
+        if right <= x || top <= y {
+            None
+        } else {
+            Some(Self::new(x, y, right - x, top - y))
+        }
+    }
+
+    /// Set the GL viewport to this rect
+    pub fn set_viewport(&self, gl: &glow::Context) {
+        unsafe { gl.viewport(self.x, self.y, self.width, self.height) };
+    }
+
+    /// Set the GL scissor box to this rect
+    pub fn set_scissor(&self, gl: &glow::Context) {
+        unsafe { gl.scissor(self.x, self.y, self.width, self.height) };
+    }
+}
+
+/// The GL scissor box, or `None` if the scissor test is off
+pub fn scissor_box(gl: &glow::Context) -> Option<Rect> {
+    unsafe {
+        if !gl.is_enabled(glow::SCISSOR_TEST) {
+            return None;
+        }
+        let mut scissor = [0; 4];
+        gl.get_parameter_i32_slice(glow::SCISSOR_BOX, &mut scissor);
+        Some(Rect::new(scissor[0], scissor[1], scissor[2], scissor[3]))
+    }
+}
+
+/// Draw with the scissor box narrowed to a rect, then put the scissor test and box back the way
+/// they were
+///
+/// If the scissor test is already on, only the part of the rect inside of the current box is
+/// drawn to, so this never draws outside of what the loop scissored the frame to with
+/// `RenderSettings::partial_redraw`. `draw` isn't called at all when nothing of the rect is left.
+pub fn with_scissor<F: FnOnce(&mut glow::Context)>(gl: &mut glow::Context, rect: Rect, draw: F) {
+    let current = scissor_box(gl);
+    let rect = match current {
+        Some(current) => match rect.intersect(&current) {
+            Some(rect) => rect,
+            None => return,
+        },
+        None if rect.is_empty() => return,
+        None => rect,
+    };
+    unsafe { gl.enable(glow::SCISSOR_TEST) };
+    rect.set_scissor(gl);
+    draw(gl);
+    match current {
+        Some(current) => current.set_scissor(gl),
+        None => unsafe { gl.disable(glow::SCISSOR_TEST) },
+    }
+}
+
+/// Render into a sub-rectangle of the window, such as a picture-in-picture inset
+///
+/// The viewport and scissor box are set to `rect` while `draw` runs so that nothing is drawn
+/// outside of it. If `border_color` is given, a border `INSET_BORDER_WIDTH` wide, scaled by the
+/// context's `ui_scale`, is drawn around the rect and the inside of the rect is cleared to black
+/// first. The depth buffer inside of the rect is always cleared so
+/// that the inset is never hidden by the scene behind it.
+///
+/// Insets that can be pointed at should be registered with `ViewportRegistry::register_inset`,
+/// with the scaled border width when there is a border, so that the cursor finds them.
+///
+/// Insets should be drawn after everything else in the frame, including any post-processing, so
+/// that they aren't affected by it. The scissoring works like `with_scissor`, so an inset never
+/// draws outside of a scissor box that is already set, and afterwards the viewport, the scissor
+/// test and box, and the clear color are all the way they were before.
+pub fn render_inset<F: FnOnce(&mut glow::Context)>(
+    gl: &mut glow::Context,
+    ctx: &AppContext,
+    rect: Rect,
+    border_color: Option<Color>,
+    draw: F,
+) {
+    let viewport = current_viewport(gl);
+
+    // Draw the border by clearing a slightly larger rect to the border color. The colors are
+    // cleared with `clear_buffer`, which leaves the clear color alone.
+    if let Some(color) = border_color {
+        let border = rect.expand(ctx.scale_ui(INSET_BORDER_WIDTH));
+        with_scissor(gl, border, |gl| clear_color_buffer(gl, color.to_srgb()));
+    }
+
+    with_scissor(gl, rect, |gl| {
+        // Clear just the inset
+        if border_color.is_some() {
+            clear_color_buffer(gl, [0., 0., 0., 1.]);
+        }
+        unsafe { gl.clear(glow::DEPTH_BUFFER_BIT) };
+
+        // Draw the inset contents
+        rect.set_viewport(gl);
+        draw(gl);
+    });
+
+    viewport.set_viewport(gl);
+}
+
+/// The GL viewport
+pub fn current_viewport(gl: &glow::Context) -> Rect {
+    let mut viewport = [0; 4];
+    unsafe { gl.get_parameter_i32_slice(glow::VIEWPORT, &mut viewport) };
+    Rect::new(viewport[0], viewport[1], viewport[2], viewport[3])
+}
+
+/// Clear the first color buffer of the bound framebuffer, inside of the scissor box if there is
+/// one, without changing the clear color
+fn clear_color_buffer(gl: &mut glow::Context, color: [f32; 4]) {
+    let mut color = color;
+    unsafe { gl.clear_buffer_f32_slice(glow::COLOR, 0, &mut color) };
+}
+
+/// A ray from a view's camera through a point in the view
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Ray {
+    /// Where the ray crosses the near plane
+    pub origin: Point3<f32>,
+    /// The normalized direction of the ray, away from the camera
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    /// The point a distance along the ray
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+
+    /// How far along the ray it crosses the horizontal plane at a height, if it crosses it in
+    /// front of the origin
+    pub fn distance_to_height(&self, height: f32) -> Option<f32> {
+        if self.direction.y.abs() < f32::EPSILON {
+            return None;
+        }
+        let distance = (height - self.origin.y) / self.direction.y;
+        if distance >= 0. {
+            Some(distance)
+        } else {
+            None
+        }
+    }
+}
+
+/// A view that was drawn into a part of the window with a camera, from
+/// `ViewportRegistry::register`
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisteredView {
+    /// The name that the view was registered with, like `scene` or `gizmo`
+    pub name: String,
+    /// Where the view was drawn, in the same pixels as `AppContext::render_size`
+    pub rect: Rect,
+    /// How wide the border drawn around the view is, which covers what is under it without being
+    /// a part of the view
+    pub border: i32,
+    pub view: Matrix4<f32>,
+    /// The projection the view was drawn with, turned back into one for standard depth if it was
+    /// drawn with another `depth_mode`
+    pub projection: Matrix4<f32>,
+    /// The inverse of the view projection matrix, or `None` if it can't be inverted
+    inverse: Option<Matrix4<f32>>,
+}
+
+impl RegisteredView {
+    /// A position in render pixels from the bottom-left of the window, in the normalized device
+    /// coordinates of the view
+    pub fn to_ndc(&self, (x, y): (f32, f32)) -> (f32, f32) {
+        (
+            (x - self.rect.x as f32) / self.rect.width.max(1) as f32 * 2. - 1.,
+            (y - self.rect.y as f32) / self.rect.height.max(1) as f32 * 2. - 1.,
+        )
+    }
+
+    /// Whether a position in render pixels from the bottom-left of the window is in the view
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        contains(self.rect, (x, y))
+    }
+
+    /// The point in the world at a position in render pixels from the bottom-left of the window,
+    /// at a depth in normalized device coordinates from -1 at the near plane to 1 at the far
+    /// plane, whatever depth mode the view was drawn with
+    pub fn unproject(&self, position: (f32, f32), depth: f32) -> Option<Point3<f32>> {
+        let (x, y) = self.to_ndc(position);
+        let point = self.inverse? * Vector4::new(x, y, depth, 1.);
+        if point.w.abs() < f32::EPSILON {
+            return None;
+        }
+        Some(Point3::from_homogeneous(point))
+    }
+
+    /// The ray from the camera through a position in render pixels from the bottom-left of the
+    /// window
+    ///
+    /// This works for orthographic views too, where the rays are parallel and start on the near
+    /// plane under the position.
+    pub fn ray(&self, position: (f32, f32)) -> Option<Ray> {
+        let near = self.unproject(position, -1.)?;
+        let far = self.unproject(position, 1.)?;
+        let direction = far - near;
+        if direction.magnitude2() == 0. {
+            return None;
+        }
+        Some(Ray {
+            origin: near,
+            direction: direction.normalize(),
+        })
+    }
+}
+
+/// The views of the window and where they are, for finding which one the cursor is over
+///
+/// Turning the cursor into a ray with the camera alone assumes that the camera fills the window,
+/// which stops being true with bars around a virtual resolution, insets, and split screens. Each
+/// view registers its rectangle and camera while it is drawn instead, and cursor queries find
+/// the view under the cursor and unproject it with that view's camera.
+///
+/// Registering only takes a shared borrow, so views can be registered from inside `render_eyes`
+/// and `render_inset`. The views registered while a frame is drawn replace the previous frame's
+/// at the end of the frame, so queries always go through the views that are on the screen, and
+/// can be made before the views of this frame are drawn. Views registered later are on top of
+/// the ones before them.
+#[derive(Debug, Default)]
+pub struct ViewportRegistry {
+    /// The views of the last frame, bottom first
+    views: Vec<RegisteredView>,
+    /// What the loop drew over the last frame, like the console, in window pixels
+    overlays: Vec<Rect>,
+    /// The height of the render size and the window in the last frame, to flip the cursor with
+    heights: (u32, u32),
+    pending_views: RefCell<Vec<RegisteredView>>,
+    pending_overlays: RefCell<Vec<Rect>>,
+}
+
+impl ViewportRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a view drawn this frame into a rect in render pixels with a camera
+    ///
+    /// The projection is the one that was drawn with, which is turned back into one for standard
+    /// depth with the current `depth_mode`, so this has to be called while the frame is drawn.
+    pub fn register(&self, name: &str, rect: Rect, view: Matrix4<f32>, projection: Matrix4<f32>) {
+        self.register_inset(name, rect, 0, view, projection);
+    }
+
+    /// Register a view drawn this frame with a border around it, like an inset drawn by
+    /// `render_inset`
+    ///
+    /// The cursor isn't over any view while it is over the border.
+    pub fn register_inset(
+        &self,
+        name: &str,
+        rect: Rect,
+        border: i32,
+        view: Matrix4<f32>,
+        projection: Matrix4<f32>,
+    ) {
+        let projection = depth_mode::current().standard_projection(projection);
+        self.pending_views.borrow_mut().push(RegisteredView {
+            name: name.to_owned(),
+            rect,
+            border,
+            view,
+            projection,
+            inverse: (projection * view).invert(),
+        });
+    }
+
+    /// Register something drawn over every view this frame in a rect in window pixels, like the
+    /// console, which hides the views under it from the cursor
+    pub fn register_overlay(&self, rect: Rect) {
+        self.pending_overlays.borrow_mut().push(rect);
+    }
+
+    /// Replace the views of the last frame with the ones registered since, at the end of a frame
+    /// of the given render and window size
+    pub(crate) fn end_frame(&mut self, render_size: (u32, u32), window_size: (u32, u32)) {
+        self.views = self.pending_views.get_mut().drain(..).collect();
+        self.overlays = self.pending_overlays.get_mut().drain(..).collect();
+        self.heights = (render_size.1, window_size.1);
+    }
+
+    /// The views of the last frame, bottom first
+    pub fn views(&self) -> &[RegisteredView] {
+        &self.views
+    }
+
+    /// The view of the last frame with a name, or the top one if there are a few
+    pub fn view(&self, name: &str) -> Option<&RegisteredView> {
+        self.views.iter().rev().find(|view| view.name == name)
+    }
+
+    /// The top view of the last frame at a position in render pixels from the bottom-left of the
+    /// window, or `None` if no view is there or it is on the border of a view
+    pub fn view_at(&self, position: (f32, f32)) -> Option<&RegisteredView> {
+        for view in self.views.iter().rev() {
+            if view.contains(position) {
+                return Some(view);
+            }
+            if view.border > 0 && contains(view.rect.expand(view.border), position) {
+                return None;
+            }
+        }
+        None
+    }
+
+    /// The view under the cursor and the cursor's position in render pixels from the
+    /// bottom-left of the window
+    ///
+    /// Returns `None` when the cursor isn't over a view, which includes when it is outside of the
+    /// window, in the bars around a virtual resolution, on an inset's border, or over an overlay
+    /// like the console.
+    pub fn cursor_view(&self, input: &Input) -> Option<(&RegisteredView, (f32, f32))> {
+        let (window_x, window_y) = input.window_cursor_position()?;
+        let window_position = (window_x as f32, self.heights.1 as f32 - window_y as f32);
+        if self
+            .overlays
+            .iter()
+            .any(|overlay| contains(*overlay, window_position))
+        {
+            return None;
+        }
+        let (x, y) = input.cursor_position()?;
+        let position = (x as f32, self.heights.0 as f32 - y as f32);
+        self.view_at(position).map(|view| (view, position))
+    }
+
+    /// The ray from the camera of the view under the cursor through the cursor, along with the
+    /// view
+    pub fn cursor_ray(&self, input: &Input) -> Option<(&RegisteredView, Ray)> {
+        let (view, position) = self.cursor_view(input)?;
+        Some((view, view.ray(position)?))
+    }
+}
+
+/// Whether a rect contains a position in pixels, which can be between pixels like the cursor
+fn contains(rect: Rect, (x, y): (f32, f32)) -> bool {
+    x >= rect.x as f32
+        && y >= rect.y as f32
+        && x < (rect.x + rect.width) as f32
+        && y < (rect.y + rect.height) as f32
+}
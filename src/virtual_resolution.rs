@@ -0,0 +1,256 @@
+use glow::HasContext;
+
+use crate::{
+    color::Color,
+    diagnostics,
+    resources::{self, ResourceKind},
+    viewport::Rect,
+};
+
+/// Draw at a fixed resolution and scale it up to the window, with bars around it where the window
+/// has a different shape
+///
+/// Set `RenderSettings::virtual_resolution` to turn it on. The loop then gives the handler an
+/// offscreen framebuffer of the virtual size through `AppContext::surface_framebuffer`, with the
+/// viewport set to cover it, and copies it to the middle of the window after `draw` with nearest
+/// filtering. `AppContext::render_size` is the virtual size, and the cursor position in
+/// `AppContext::input` is in virtual pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VirtualResolution {
+    pub width: u32,
+    pub height: u32,
+    /// Whether or not to only scale by whole numbers, which keeps every virtual pixel the same
+    /// size. Turning it off fills more of the window at the cost of uneven pixels.
+    pub integer_scaling: bool,
+    /// The color of the bars around the scaled image
+    pub bar_color: Color,
+}
+
+impl VirtualResolution {
+    /// A virtual resolution with integer scaling and black bars
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            integer_scaling: true,
+            bar_color: Color::BLACK,
+        }
+    }
+
+    pub fn with_integer_scaling(mut self, integer_scaling: bool) -> Self {
+        self.integer_scaling = integer_scaling;
+        self
+    }
+
+    pub fn with_bar_color(mut self, bar_color: Color) -> Self {
+        self.bar_color = bar_color;
+        self
+    }
+
+    /// Where the scaled image goes in a window of the given size
+    ///
+    /// With integer scaling the scale is the largest whole number that fits, unless the window is
+    /// smaller than the virtual resolution, in which case the image is shrunk to fit.
+    pub fn fit(&self, (window_width, window_height): (u32, u32)) -> VirtualViewport {
+        let (width, height) = (self.width.max(1), self.height.max(1));
+        let fit_scale =
+            (window_width as f64 / width as f64).min(window_height as f64 / height as f64);
+        let scale = if self.integer_scaling && fit_scale >= 1. {
+            fit_scale.floor()
+        } else {
+            fit_scale
+        };
+
+        let scaled_width = (width as f64 * scale).round() as i32;
+        let scaled_height = (height as f64 * scale).round() as i32;
+        VirtualViewport {
+            rect: Rect::new(
+                (window_width as i32 - scaled_width) / 2,
+                (window_height as i32 - scaled_height) / 2,
+                scaled_width,
+                scaled_height,
+            ),
+            scale,
+            virtual_size: (width, height),
+            window_height,
+        }
+    }
+}
+
+/// Where a virtual resolution's image is drawn in the window, and how big its pixels are
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VirtualViewport {
+    /// The part of the window covered by the image, with the origin in the bottom-left
+    pub rect: Rect,
+    /// The size of a virtual pixel in window pixels
+    pub scale: f64,
+    pub virtual_size: (u32, u32),
+    window_height: u32,
+}
+
+impl VirtualViewport {
+    /// Convert a position in window pixels from the top-left, like the cursor position, to
+    /// virtual pixels from the top-left, or `None` if it is in the bars
+    pub fn to_virtual(&self, (x, y): (f64, f64)) -> Option<(f64, f64)> {
+        let top = (self.window_height as i32 - self.rect.y - self.rect.height) as f64;
+        let virtual_x = (x - self.rect.x as f64) / self.scale;
+        let virtual_y = (y - top) / self.scale;
+        let (width, height) = self.virtual_size;
+        if virtual_x >= 0.
+            && virtual_y >= 0.
+            && virtual_x < width as f64
+            && virtual_y < height as f64
+        {
+            Some((virtual_x, virtual_y))
+        } else {
+            None
+        }
+    }
+}
+
+/// The offscreen framebuffer that the handler draws into with a virtual resolution
+#[derive(Debug)]
+pub(crate) struct VirtualTarget {
+    pub framebuffer: u32,
+    color: u32,
+    depth_stencil: u32,
+    pub size: (u32, u32),
+    /// The internal format of the depth and stencil buffer
+    pub depth_format: u32,
+}
+
+impl VirtualTarget {
+    /// Create a framebuffer of the given size with a color texture and a depth and stencil buffer
+    /// of the given format, like `DepthMode::depth_stencil_format`
+    pub fn new(gl: &mut glow::Context, (width, height): (u32, u32), depth_format: u32) -> Self {
+        unsafe {
+            let color = gl.create_texture().unwrap();
+            gl.bind_texture(glow::TEXTURE_2D, Some(color));
+            gl.tex_image_2d(
+                glow::TEXTURE_2D,
+                0,
+                glow::RGBA8 as i32,
+                width as i32,
+                height as i32,
+                0,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                None,
+            );
+            // Keep the pixels sharp if the texture is ever sampled instead of blitted
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MIN_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.tex_parameter_i32(
+                glow::TEXTURE_2D,
+                glow::TEXTURE_MAG_FILTER,
+                glow::NEAREST as i32,
+            );
+            gl.bind_texture(glow::TEXTURE_2D, None);
+
+            let depth_stencil = gl.create_renderbuffer().unwrap();
+            gl.bind_renderbuffer(glow::RENDERBUFFER, Some(depth_stencil));
+            gl.renderbuffer_storage(
+                glow::RENDERBUFFER,
+                depth_format,
+                width as i32,
+                height as i32,
+            );
+            gl.bind_renderbuffer(glow::RENDERBUFFER, None);
+
+            let framebuffer = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::FRAMEBUFFER, Some(framebuffer));
+            gl.framebuffer_texture_2d(
+                glow::FRAMEBUFFER,
+                glow::COLOR_ATTACHMENT0,
+                glow::TEXTURE_2D,
+                Some(color),
+                0,
+            );
+            gl.framebuffer_renderbuffer(
+                glow::FRAMEBUFFER,
+                glow::DEPTH_STENCIL_ATTACHMENT,
+                glow::RENDERBUFFER,
+                Some(depth_stencil),
+            );
+            if gl.check_framebuffer_status(glow::FRAMEBUFFER) != glow::FRAMEBUFFER_COMPLETE {
+                diagnostics::report_problem("The virtual resolution framebuffer is incomplete");
+            }
+
+            let label = format!("Virtual resolution {}x{}", width, height);
+            resources::track_sized(
+                ResourceKind::Texture,
+                color,
+                &label,
+                resources::texture_bytes(width, height, glow::RGBA8, 1, 1, 1),
+            );
+            resources::track_sized(
+                ResourceKind::Renderbuffer,
+                depth_stencil,
+                &label,
+                resources::texture_bytes(width, height, depth_format, 1, 1, 1),
+            );
+            resources::track(ResourceKind::Framebuffer, framebuffer, &label);
+
+            Self {
+                framebuffer,
+                color,
+                depth_stencil,
+                size: (width, height),
+                depth_format,
+            }
+        }
+    }
+
+    /// Clear the window framebuffer to the bar color and copy the image into the middle of it
+    pub fn present(
+        &self,
+        gl: &mut glow::Context,
+        window_framebuffer: Option<u32>,
+        viewport: &VirtualViewport,
+        bar_color: Color,
+    ) {
+        unsafe {
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(self.framebuffer));
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, window_framebuffer);
+
+            // Clearing is limited by the scissor box, so make sure the whole window is cleared
+            let scissor = gl.is_enabled(glow::SCISSOR_TEST);
+            gl.disable(glow::SCISSOR_TEST);
+            let [r, g, b, a] = bar_color.to_srgb();
+            gl.clear_color(r, g, b, a);
+            gl.clear(glow::COLOR_BUFFER_BIT);
+            if scissor {
+                gl.enable(glow::SCISSOR_TEST);
+            }
+
+            let rect = viewport.rect;
+            gl.blit_framebuffer(
+                0,
+                0,
+                self.size.0 as i32,
+                self.size.1 as i32,
+                rect.x,
+                rect.y,
+                rect.x + rect.width,
+                rect.y + rect.height,
+                glow::COLOR_BUFFER_BIT,
+                glow::NEAREST,
+            );
+            gl.bind_framebuffer(glow::FRAMEBUFFER, window_framebuffer);
+        }
+    }
+
+    pub fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_framebuffer(self.framebuffer);
+            gl.delete_texture(self.color);
+            gl.delete_renderbuffer(self.depth_stencil);
+        }
+        resources::untrack(ResourceKind::Framebuffer, self.framebuffer);
+        resources::untrack(ResourceKind::Texture, self.color);
+        resources::untrack(ResourceKind::Renderbuffer, self.depth_stencil);
+    }
+}
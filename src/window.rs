@@ -0,0 +1,2328 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use euclid::default::Size2D;
+use glow::HasContext;
+use surfman::{
+    Connection, Context, ContextAttributeFlags, ContextAttributes, ContextDescriptor, Device,
+    GLVersion, SurfaceAccess, SurfaceType,
+};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    DeviceEvent, ElementState, Event, EventsLoop, KeyboardInput, MonitorId, VirtualKeyCode, Window,
+    WindowBuilder, WindowEvent, WindowId,
+};
+
+use crate::{
+    anti_aliasing::{AaMode, AntiAliasing},
+    app_context::AbCaptureRequest,
+    clipboard::{ClipboardCapture, CopyOutcome},
+    console,
+    context_report::ContextReport,
+    cursor::{Cursor, CursorState},
+    damage::DamageTracker,
+    debug_group::{self, PopDebugGroup},
+    debug_scope,
+    debug_text::DebugText,
+    demo_state::{DemoState, DemoStates},
+    depth_mode::{self, DepthMode, DepthSetup},
+    depth_view::{DepthViewMode, DepthViewPass},
+    diagnostics,
+    features::Features,
+    frame_graph::{self, FrameEvent, FrameGraph},
+    image_diff::{self, AbCapture},
+    input_recording::{InputPlayer, InputRecorder},
+    latency::{FrameLatency, LatencyMode},
+    readback,
+    render_settings::RedrawPolicy,
+    resources,
+    shader::{self, ShaderTarget},
+    shader_variants,
+    stereo::StereoMode,
+    texture_audit,
+    texture_viewer::TextureViewerPass,
+    timing::PresentTimes,
+    viewport::Rect,
+    virtual_resolution::{VirtualResolution, VirtualTarget},
+    window_placement::{WindowPlacement, WindowPlacements},
+    workarounds::{self, Workarounds},
+    AppContext, Config, RenderHandler,
+};
+
+/// The signature of `glGetGraphicsResetStatus`, which glow doesn't expose
+type GetGraphicsResetStatus = extern "system" fn() -> u32;
+
+/// How long to wait between attempts to recreate a lost surface
+const SURFACE_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How far the arrow keys move the animation time in seconds
+const TIME_STEP: f32 = 0.1;
+/// How far the arrow keys move the animation time in seconds while holding shift
+const TIME_STEP_LARGE: f32 = 1.0;
+
+/// How many frames to wait between updates of the frame stats in the title, so that they are
+/// readable and the window system isn't asked to change the title every frame
+const STATS_TITLE_INTERVAL: u64 = 30;
+
+/// How long to sleep between checks for events while no window needs to be drawn
+const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// How many of the largest GL objects to list when printing the memory report ( with F4 )
+const MEMORY_REPORT_COUNT: usize = 10;
+
+/// How much PageUp and PageDown stretch the range that the depth view shows
+const DEPTH_RANGE_STEP: f32 = 1.5;
+
+/// A function that creates the render handler for a window
+///
+/// The loop keeps the factory around so that it can create a fresh handler if the window's GL
+/// context has to be recreated.
+pub type HandlerFactory =
+    Box<dyn Fn(&mut glow::Context, &mut AppContext) -> Box<dyn RenderHandler>>;
+
+/// Get a `HandlerFactory` that initializes the given render handler
+pub fn handler_factory<RndrHndlr: RenderHandler + 'static>() -> HandlerFactory {
+    Box::new(|gl, ctx| Box::new(RndrHndlr::init(gl, ctx)))
+}
+
+/// The settings used to create a window
+#[derive(Clone, Debug)]
+pub struct WindowConfig {
+    /// The window title
+    pub title: String,
+    /// The width of the window in physical pixels
+    pub width: u32,
+    /// The height of the window in physical pixels
+    pub height: u32,
+    /// Whether or not the user can resize the window
+    pub resizable: bool,
+    /// Whether or not to share GL objects such as textures and buffers with the other windows that
+    /// also set this option
+    pub share_context: bool,
+    /// A file to record the window's input to, for playing back later with `replay_input`
+    pub record_input: Option<PathBuf>,
+    /// A file of recorded input to play back instead of the live input. The window closes when
+    /// the recording ends.
+    pub replay_input: Option<PathBuf>,
+    /// Whether or not to resize the window surface to match the window when it is resized. Some
+    /// backends stretch the old surface over the window instead of resizing it themselves.
+    pub resize_surface: bool,
+    /// Draw this many frames, print the average frame time, and close the window
+    pub bench_frames: Option<u64>,
+    /// Keep the window hidden and don't present frames, for benchmarks and automated runs
+    pub headless: bool,
+    /// The name the window's placement is saved under, or `None` to use the program's name and
+    /// the window title. Windows that are open at the same time need different names to be
+    /// remembered separately.
+    pub name: Option<String>,
+    /// Save the window's position, size, and fullscreen state when it closes, and restore them
+    /// when a window with the same name opens. `Config::remember_window` turns this on for every
+    /// window.
+    pub remember_placement: bool,
+    /// Don't restore the saved placement this time, but still save it when the window closes
+    pub reset_placement: bool,
+    /// Don't restore the handler's saved state this time, but still save it when the window
+    /// closes
+    pub fresh_state: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            title: "Me Learning OpenGL".into(),
+            width: 800,
+            height: 600,
+            resizable: true,
+            share_context: false,
+            record_input: None,
+            replay_input: None,
+            resize_surface: true,
+            bench_frames: None,
+            headless: false,
+            name: None,
+            remember_placement: false,
+            reset_placement: false,
+            fresh_state: false,
+        }
+    }
+}
+
+impl WindowConfig {
+    /// The default settings, to be changed with the builder methods below
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the window title
+    pub fn title(mut self, title: impl Into<String>) -> Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Set the size of the window in physical pixels
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.width = width;
+        self.height = height;
+        self
+    }
+
+    /// Set whether or not the user can resize the window
+    pub fn resizable(mut self, resizable: bool) -> Self {
+        self.resizable = resizable;
+        self
+    }
+}
+
+/// Everything the loop needs to render to one window
+struct WindowState {
+    window: Window,
+    /// The title the window was created with
+    title: String,
+    /// The title currently shown on the window
+    current_title: String,
+    /// Whether or not to show the animation time in the title, which is turned on once the time
+    /// has been paused or scrubbed
+    show_time_in_title: bool,
+    /// Whether or not to show the frame and present times in the title ( toggled with F3 )
+    show_stats_in_title: bool,
+    /// The frame stats last shown in the title
+    stats_title: String,
+    /// The frame time graph ( toggled with F2 )
+    frame_graph: FrameGraph,
+    /// Times the frames from the input they read to the GPU finishing them
+    latency: FrameLatency,
+    factory: HandlerFactory,
+    /// Whether the context shares objects with the root share context
+    share_context: bool,
+    context: Context,
+    gl: glow::Context,
+    get_reset_status: Option<GetGraphicsResetStatus>,
+    /// Ends debug groups, if the context supports them
+    pop_debug_group: Option<PopDebugGroup>,
+    handler: Box<dyn RenderHandler>,
+    ctx: AppContext,
+    /// Whether or not the window surface has been lost and needs to be recreated
+    surface_lost: bool,
+    /// Whether or not to pretend the surface was lost on the next frame ( triggered by F9 )
+    simulate_surface_loss: bool,
+    /// When to try recreating the lost surface again, after an attempt failed
+    surface_retry_at: Option<Instant>,
+    /// Whether or not the window should be closed
+    close_requested: bool,
+    /// Whether or not to resize the window surface when the window is resized
+    resize_surface: bool,
+    /// Whether or not the window was resized since the surface was last resized
+    surface_resize_pending: bool,
+    /// Whether or not the window is hidden and frames aren't presented
+    headless: bool,
+    /// The number of frames to draw before printing the average frame time and closing
+    bench_frames: Option<u64>,
+    /// When the first frame of the benchmark was drawn
+    bench_start: Option<Instant>,
+    /// Whether or not the window got an event since its last frame
+    had_event: bool,
+    /// When the last frame was drawn, or `None` before the first frame
+    last_frame: Option<Instant>,
+    /// Whether or not frames were skipped because the window was minimized
+    skipped_minimized: bool,
+    /// How many frame reports the loop wrote by itself for GL errors and incomplete framebuffers
+    automatic_reports: u32,
+    /// The file name, without an extension, to save the first frame and its report to before it
+    /// is presented, until it has been saved ( `Config::capture_first_frame` )
+    first_frame_capture: Option<String>,
+    /// Records the input of every frame, if recording was requested
+    recorder: Option<InputRecorder>,
+    /// Plays back recorded input, if playback was requested
+    player: Option<InputPlayer>,
+    /// The key of the context's resource tracker
+    resource_key: u64,
+    /// Draws the console, created the first time the console is opened
+    debug_text: Option<DebugText>,
+    /// What the handler draws into when drawing at a virtual resolution, or with reversed depth
+    /// and no anti-aliasing
+    virtual_target: Option<VirtualTarget>,
+    /// The framebuffers that the handler draws into with anti-aliasing
+    anti_aliasing: Option<AntiAliasing>,
+    /// The depth texture and pass that show the depth buffer, while it is shown ( F8 )
+    depth_view: Option<DepthViewPass>,
+    /// Shows a texture over the frame, while one is picked with the `view` console command
+    texture_viewer: Option<TextureViewerPass>,
+    /// The depth setup of the frame being drawn, from `RenderSettings::depth_mode`
+    depth_setup: DepthSetup,
+    /// Reads back frames and copies them to the clipboard, created the first time a frame is
+    /// copied ( Ctrl+F12 )
+    clipboard: Option<ClipboardCapture>,
+    /// The framebuffer of the window surface, which is only the same as the context's surface
+    /// framebuffer without a virtual resolution
+    window_framebuffer: Option<u32>,
+    /// Whether or not the window is fullscreen ( toggled with F11 )
+    fullscreen: bool,
+    /// The name the window's placement is saved under when it closes, if it is remembered
+    placement_name: Option<String>,
+    /// Where the window was before it went fullscreen, which is what gets saved while it is
+    /// fullscreen
+    windowed_placement: Option<WindowPlacement>,
+    /// The cursor given to the window system, and the custom cursor drawn over the frame
+    cursor: CursorState,
+    /// What changed in the last frames, for only redrawing that with
+    /// `RenderSettings::partial_redraw`
+    damage: DamageTracker,
+}
+
+/// Open a window and render to it with the given handler until the window is closed
+///
+/// The window's size and title come from the `Config`.
+pub fn with_window<RndrHndlr: RenderHandler + 'static>() {
+    let config = Config::load();
+    let window_config = config.window_config();
+    with_windows_and_config(
+        config,
+        vec![(window_config, handler_factory::<RndrHndlr>())],
+    );
+}
+
+/// Open a window with the given settings and render to it with the given handler until the
+/// window is closed
+///
+/// The title and size of the `WindowConfig` take the place of the default ones, so each example
+/// can pick its own, and a title or size set in the `Config` still overrides them.
+pub fn with_window_config<RndrHndlr: RenderHandler + 'static>(window_config: WindowConfig) {
+    let config = Config::load_for_window(&window_config);
+    let window_config = WindowConfig {
+        title: config.title.clone(),
+        width: config.width,
+        height: config.height,
+        ..window_config
+    };
+    with_windows_and_config(
+        config,
+        vec![(window_config, handler_factory::<RndrHndlr>())],
+    );
+}
+
+/// Open a window for each config and render to each of them with its own handler
+///
+/// All of the windows share a single graphics device. Closing a window tears down only that
+/// window's handler, and the function returns once the last window is closed.
+pub fn with_windows(windows: Vec<(WindowConfig, HandlerFactory)>) {
+    with_windows_and_config(Config::load(), windows);
+}
+
+/// Like `with_windows`, but with a config that has already been loaded
+///
+/// The config is given to every handler through `AppContext::config`.
+pub fn with_windows_and_config(app_config: Config, windows: Vec<(WindowConfig, HandlerFactory)>) {
+    if windows.is_empty() {
+        return;
+    }
+    texture_audit::set_enabled(app_config.texture_audit);
+
+    // Create the window event loop
+    let mut event_loop = EventsLoop::new();
+    // Obtain the screen scaling factor
+    let scale_factor = event_loop.get_primary_monitor().get_hidpi_factor();
+    // Load where the windows were last time, if any of them want to be put back there
+    let placements = if windows.iter().any(|(config, _)| {
+        (config.remember_placement || app_config.remember_window) && !config.reset_placement
+    }) {
+        WindowPlacements::load()
+    } else {
+        WindowPlacements::default()
+    };
+    // And what their handlers saved
+    let saved_states = if windows
+        .iter()
+        .any(|(config, _)| remembers_state(config) && !config.fresh_state)
+    {
+        DemoStates::load()
+    } else {
+        DemoStates::default()
+    };
+
+    // Create all of the windows
+    let windows = windows
+        .into_iter()
+        .map(|(config, factory)| {
+            // Find the saved placement if the window is remembered, and it still fits on a monitor
+            let placement_name =
+                if (config.remember_placement || app_config.remember_window) && !config.headless {
+                    Some(
+                        config
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| default_placement_name(&config.title)),
+                    )
+                } else {
+                    None
+                };
+            let restored = placement_name
+                .as_ref()
+                .filter(|_| !config.reset_placement)
+                .and_then(|name| placements.get(name))
+                .and_then(|placement| fit_placement(&event_loop, placement));
+
+            // Create a new logical size for the window based on the desired physical size
+            let logical_size = match &restored {
+                Some((placement, monitor)) => {
+                    PhysicalSize::new(placement.width as f64, placement.height as f64)
+                        .to_logical(monitor.get_hidpi_factor())
+                }
+                None => PhysicalSize::new(config.width as f64, config.height as f64)
+                    .to_logical(scale_factor),
+            };
+            // Create a window, which stays hidden until it has been moved into place
+            let window = WindowBuilder::new()
+                .with_title(config.title.clone())
+                .with_dimensions(logical_size)
+                .with_resizable(config.resizable)
+                .with_visibility(!config.headless && restored.is_none())
+                .build(&event_loop)
+                .unwrap();
+            if let Some((placement, monitor)) = &restored {
+                window.set_position(
+                    PhysicalPosition::new(placement.x as f64, placement.y as f64)
+                        .to_logical(monitor.get_hidpi_factor()),
+                );
+                if placement.fullscreen {
+                    window.set_fullscreen(Some(monitor.clone()));
+                }
+            }
+
+            // Show the window, unless we are running without one
+            if !config.headless {
+                window.show();
+            }
+
+            let restored = restored.map(|(placement, _)| placement);
+            (window, config, factory, placement_name, restored)
+        })
+        .collect::<Vec<_>>();
+
+    // Create a connection to the graphics provider from our first winit window
+    let conn = Connection::from_winit_window(&windows[0].0).unwrap();
+    // Create a hardware adapter that we can used to create graphics devices from
+    let adapter = conn.create_hardware_adapter().unwrap();
+    // Create a graphics device using our hardware adapter. All windows share this device.
+    let mut device = conn.create_device(&adapter).unwrap();
+
+    // Define the attributes for our OpenGL context
+    let context_attributes = ContextAttributes {
+        version: GLVersion::new(3, 3),
+        flags: ContextAttributeFlags::ALPHA
+            | ContextAttributeFlags::DEPTH
+            | ContextAttributeFlags::STENCIL,
+    };
+
+    // Create a context descriptor based on our defined context attributes
+    let context_descriptor = device
+        .create_context_descriptor(&context_attributes)
+        .unwrap();
+
+    // If any of the windows want to share their GL objects, create a root context without a
+    // surface for them to share with. This context outlives all of the windows so that shared
+    // objects don't go away when the first window to create them is closed.
+    let mut share_root = if windows.iter().any(|(_, config, ..)| config.share_context) {
+        Some(device.create_context(&context_descriptor, None).unwrap())
+    } else {
+        None
+    };
+
+    // Create the GL context and render handler for each window
+    let mut states = windows
+        .into_iter()
+        .map(|(window, config, factory, placement_name, restored)| {
+            let share_with = if config.share_context {
+                share_root.as_ref()
+            } else {
+                None
+            };
+
+            // Create our OpenGL context with the window surface bound to it
+            let context =
+                create_window_context(&conn, &window, &mut device, &context_descriptor, share_with)
+                    .unwrap();
+
+            // Get a pointer to the OpenGL functions
+            let mut gl = unsafe {
+                glow::Context::from_loader_function(|s| {
+                    device.get_proc_address(&context, s) as *const _
+                })
+            };
+            // Find out which optional features the context supports
+            let features = Features::query(&gl, &device, &context);
+            // Get a pointer to the reset status function if the context supports robustness
+            let get_reset_status = load_reset_status_fn(&gl, &features, &device, &context);
+            let pop_debug_group = debug_group::load_pop_fn(&features, &device, &context);
+            debug_group::make_current(pop_debug_group);
+            let query_counter = frame_graph::load_query_counter(&features, &device, &context);
+
+            // Find out what we actually got, which may not be exactly what we asked for
+            let context_report = ContextReport::query(&gl, &device, &context);
+            eprintln!("{}: {}", config.title, context_report);
+            let workarounds = Workarounds::for_driver(
+                &config.title,
+                &context_report.vendor,
+                &context_report.renderer,
+                &context_report.version,
+                &app_config.workarounds,
+            );
+            workarounds::make_current(workarounds);
+            shader::make_current_target(ShaderTarget::for_features(&features));
+
+            // Track the GL objects created in the context, if tracking is enabled
+            let resource_key = resources::start_context();
+            resources::make_current(resource_key);
+
+            let mut ctx = AppContext::new(
+                window.id(),
+                window_physical_size(&window),
+                window.get_hidpi_factor(),
+                context_report,
+                features,
+                workarounds,
+                app_config.clone(),
+            );
+            if remembers_state(&config) {
+                let name = config
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| default_placement_name(&config.title));
+                ctx.saved_state = if config.fresh_state {
+                    DemoState::new(&name)
+                } else {
+                    saved_states.get(&name)
+                };
+            }
+            // Instantiate our rendering handler
+            let handler = factory(&mut gl, &mut ctx);
+
+            // Open the input recording or playback files
+            let recorder = config.record_input.as_ref().and_then(|path| {
+                InputRecorder::create(path)
+                    .map_err(|error| {
+                        eprintln!(
+                            "{}: Not recording input to {}: {}",
+                            config.title,
+                            path.display(),
+                            error
+                        )
+                    })
+                    .ok()
+            });
+            let player = config.replay_input.as_ref().and_then(|path| {
+                let player = InputPlayer::open(path)
+                    .map_err(|error| {
+                        eprintln!(
+                            "{}: Not playing back input from {}: {}",
+                            config.title,
+                            path.display(),
+                            error
+                        )
+                    })
+                    .ok()?;
+                eprintln!(
+                    "{}: Playing back {} frames of input from {}",
+                    config.title,
+                    player.frame_count(),
+                    path.display()
+                );
+                Some(player)
+            });
+
+            WindowState {
+                window,
+                current_title: config.title.clone(),
+                title: config.title,
+                show_time_in_title: false,
+                show_stats_in_title: false,
+                stats_title: String::new(),
+                frame_graph: FrameGraph::new(query_counter),
+                latency: FrameLatency::new(),
+                factory,
+                share_context: config.share_context,
+                resize_surface: config.resize_surface,
+                surface_resize_pending: false,
+                headless: config.headless,
+                bench_frames: config.bench_frames,
+                bench_start: None,
+                had_event: false,
+                last_frame: None,
+                skipped_minimized: false,
+                automatic_reports: 0,
+                first_frame_capture: None,
+                context,
+                gl,
+                get_reset_status,
+                pop_debug_group,
+                handler,
+                ctx,
+                surface_lost: false,
+                simulate_surface_loss: false,
+                surface_retry_at: None,
+                close_requested: false,
+                recorder,
+                player,
+                resource_key,
+                debug_text: None,
+                virtual_target: None,
+                anti_aliasing: None,
+                depth_view: None,
+                texture_viewer: None,
+                clipboard: None,
+                depth_setup: DepthSetup::STANDARD,
+                window_framebuffer: None,
+                fullscreen: restored
+                    .as_ref()
+                    .is_some_and(|placement| placement.fullscreen),
+                windowed_placement: restored,
+                placement_name,
+                cursor: CursorState::default(),
+                damage: DamageTracker::default(),
+            }
+        })
+        .collect::<Vec<_>>();
+    if app_config.capture_first_frame {
+        // Windows after the first get numbered files, so they don't overwrite its capture
+        for (index, state) in states.iter_mut().enumerate() {
+            state.first_frame_capture = Some(if index == 0 {
+                "first_frame".to_owned()
+            } else {
+                format!("first_frame-{}", index + 1)
+            });
+        }
+    }
+
+    // Loop through render events until all of the windows are closed
+    while !states.is_empty() {
+        // In low latency mode, wait for the GPU to finish each window's last frame, and read the
+        // input that came in meanwhile, so the next frame doesn't draw with input that is as old
+        // as the frames queued in front of it
+        let mut waited = false;
+        for state in &mut states {
+            if state.ctx.render_settings.latency_mode == LatencyMode::LowLatency {
+                waited |= state.wait_for_last_frame(&device);
+            }
+        }
+        if waited {
+            event_loop.poll_events(|event| handle_event(&mut states, event));
+        }
+
+        // Render each window that needs a new frame
+        let mut rendered_any = false;
+        for state in &mut states {
+            if !state.needs_frame() {
+                continue;
+            }
+            rendered_any = true;
+            let share_with = if state.share_context {
+                share_root.as_ref()
+            } else {
+                None
+            };
+            state.render_frame(&conn, &mut device, &context_descriptor, share_with);
+        }
+
+        // Handle events
+        event_loop.poll_events(|event| handle_event(&mut states, event));
+
+        // Tear down the windows that were closed
+        let (closed, open) = states.into_iter().partition(|s| s.close_requested);
+        states = open;
+        for state in closed {
+            state.destroy(&device);
+        }
+
+        // Don't spin while every window is waiting for something to happen
+        if !rendered_any {
+            std::thread::sleep(IDLE_POLL_INTERVAL);
+        }
+    }
+
+    if let Some(mut share_root) = share_root.take() {
+        device.destroy_context(&mut share_root).unwrap();
+    }
+}
+
+/// Give an event to the window it belongs to
+fn handle_event(states: &mut [WindowState], event: Event) {
+    match event {
+        Event::WindowEvent { window_id, event } => {
+            if let Some(state) = find_window(states, window_id) {
+                state.had_event = true;
+                if let WindowEvent::KeyboardInput { .. }
+                | WindowEvent::MouseInput { .. }
+                | WindowEvent::MouseWheel { .. }
+                | WindowEvent::CursorMoved { .. } = event
+                {
+                    state.latency.record_input();
+                }
+                state.handle_window_event(event);
+            }
+        }
+        // Raw device events don't belong to a window, so they go to the focused window
+        Event::DeviceEvent { event, .. } => {
+            for state in states.iter_mut() {
+                if state.ctx.input.is_focused() {
+                    state.had_event = true;
+                    if let DeviceEvent::MouseMotion { .. }
+                    | DeviceEvent::MouseWheel { .. }
+                    | DeviceEvent::Button { .. }
+                    | DeviceEvent::Key(_) = event
+                    {
+                        state.latency.record_input();
+                    }
+                    state.ctx.input.handle_device_event(&event);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+impl WindowState {
+    /// Wait for the GPU to finish the last frame presented to the window, returning whether
+    /// there was one to wait for
+    fn wait_for_last_frame(&mut self, device: &Device) -> bool {
+        if !self.latency.has_pending() || self.surface_lost {
+            return false;
+        }
+        if device.make_context_current(&self.context).is_err() {
+            return false;
+        }
+        self.latency.wait_for_last_frame(&mut self.gl)
+    }
+
+    /// Whether or not the window should draw a frame now, according to its redraw policy
+    ///
+    /// The first frame is always drawn, and so are frames while the surface is being recovered
+    /// or recorded input is being played back. Nothing can be drawn into a zero-sized surface,
+    /// so no frames are drawn while the window is minimized, and the animation time doesn't
+    /// advance over the time it was.
+    fn needs_frame(&mut self) -> bool {
+        if self.ctx.is_minimized() {
+            self.skipped_minimized = true;
+            return false;
+        }
+        if std::mem::take(&mut self.skipped_minimized) {
+            self.ctx.timing.skip_gap();
+        }
+        let redraw_requested = self.ctx.take_redraw_request();
+        let had_event = std::mem::take(&mut self.had_event);
+        let waiting = match self.ctx.render_settings.redraw {
+            RedrawPolicy::Continuous => false,
+            RedrawPolicy::OnEvent => true,
+            RedrawPolicy::Interval(interval) => self
+                .last_frame
+                .is_some_and(|last_frame| last_frame.elapsed() < interval),
+        };
+        let needs_frame = !waiting
+            || redraw_requested
+            || had_event
+            || self.last_frame.is_none()
+            || self.surface_retry_due()
+            || self.player.is_some();
+        if needs_frame {
+            self.last_frame = Some(Instant::now());
+        }
+        needs_frame
+    }
+
+    /// Whether or not the surface is lost and it is time to try recreating it again
+    fn surface_retry_due(&self) -> bool {
+        self.surface_lost
+            && self
+                .surface_retry_at
+                .is_none_or(|retry_at| Instant::now() >= retry_at)
+    }
+
+    /// Draw and present a single frame, recovering from a lost surface or context if necessary
+    fn render_frame(
+        &mut self,
+        conn: &Connection,
+        device: &mut Device,
+        context_descriptor: &ContextDescriptor,
+        share_with: Option<&Context>,
+    ) {
+        // All GL calls go to the current context, so make sure that is this window's context
+        if device.make_context_current(&self.context).is_err() {
+            self.surface_lost = true;
+        }
+        resources::make_current(self.resource_key);
+        debug_group::make_current(self.pop_debug_group);
+        workarounds::make_current(self.ctx.workarounds());
+        shader::make_current_target(ShaderTarget::for_features(self.ctx.features()));
+
+        if self.surface_lost {
+            // Try to get our surface back. This can fail for a while, e.g. while the system is
+            // waking up from suspend, so we just keep trying every once in a while. The other
+            // windows keep drawing in between.
+            if !self.surface_retry_due() {
+                return;
+            }
+            match recreate_surface(conn, &self.window, device, &mut self.context) {
+                Ok(()) => {
+                    eprintln!("Window surface recreated");
+                    self.surface_lost = false;
+                    self.surface_retry_at = None;
+                    self.damage.reset();
+                    self.handler.device_restored(&mut self.gl, &mut self.ctx);
+                }
+                Err(error) => {
+                    eprintln!("Could not recreate window surface: {:?}", error);
+                    self.surface_retry_at = Some(Instant::now() + SURFACE_RETRY_INTERVAL);
+                }
+            }
+        } else {
+            // When playing back input, use the recorded frame time and input instead of the live
+            // ones
+            let mut replayed_input = None;
+            if let Some(player) = &mut self.player {
+                match player.next_frame() {
+                    Some((delta, input)) => {
+                        self.ctx.timing.set_fixed_delta(Some(delta));
+                        replayed_input = Some(input);
+                    }
+                    None => {
+                        eprintln!("{}: Input playback finished", self.title);
+                        self.ctx.timing.set_fixed_delta(None);
+                        self.player = None;
+                        self.close_requested = true;
+                        return;
+                    }
+                }
+            }
+
+            // Draw the graphics
+            self.ctx.timing.begin_frame();
+            if let Some(input) = replayed_input {
+                self.ctx.input = input;
+            }
+            if let Some(recorder) = &mut self.recorder {
+                if let Err(error) =
+                    recorder.record(self.ctx.timing.delta_duration(), &self.ctx.input)
+                {
+                    eprintln!("{}: Stopped recording input: {}", self.title, error);
+                    self.recorder = None;
+                }
+            }
+            self.check_surface_size(device);
+            if self.surface_resize_pending {
+                self.surface_resize_pending = false;
+                self.resize_surface(device);
+                self.frame_graph.mark(FrameEvent::Resize);
+            }
+            if let Some(request) = self.ctx.take_ab_capture_request() {
+                self.run_ab_capture(device, request);
+                self.frame_graph.mark(FrameEvent::Screenshot);
+                // The captured frames were drawn into the window's buffers
+                self.damage.reset();
+            }
+            let scissored = self.begin_partial_redraw();
+            self.clear_surface(device);
+            shader::reset_frame_uniform_stats();
+            self.ctx.arena.reset();
+            if self.ctx.shader_reload_requested() {
+                self.frame_graph.mark(FrameEvent::ShaderReload);
+            }
+            self.latency.poll(&mut self.gl);
+            self.latency.begin_draw();
+            let (gl, handler, ctx) = (&mut self.gl, &mut self.handler, &mut self.ctx);
+            self.frame_graph.begin_draw(gl);
+            debug_scope!(gl, &self.title, { handler.draw(gl, ctx) });
+            self.end_depth_setup();
+            if scissored {
+                unsafe { self.gl.disable(glow::SCISSOR_TEST) };
+            }
+            self.frame_graph.end_draw(self.ctx.timing.frame_count());
+            self.write_frame_report();
+            self.ctx.clear_shader_reload_request();
+            if let Some(depth_view) = &mut self.depth_view {
+                depth_view.capture(&mut self.gl);
+            }
+            self.resolve_anti_aliasing();
+            self.draw_depth_view();
+            self.present_virtual_resolution();
+            if let Some(path) = self.ctx.take_screenshot_request() {
+                self.save_screenshot(&path);
+                self.frame_graph.mark(FrameEvent::Screenshot);
+            }
+            let clipboard_request = self.ctx.take_clipboard_request();
+            if clipboard_request == Some(false) {
+                self.copy_to_clipboard();
+            }
+            if let Some(name) = self.first_frame_capture.take() {
+                self.save_first_frame(&name);
+            }
+            // The graph goes over the frame after the screenshot, so it isn't in it
+            self.frame_graph
+                .set_latency(self.latency.stats(), self.ctx.render_settings.latency_mode);
+            self.draw_frame_graph();
+            self.draw_texture_viewer();
+            self.draw_console();
+            self.end_viewport_frame();
+            if clipboard_request == Some(true) {
+                self.copy_to_clipboard();
+            }
+            self.poll_clipboard();
+            self.update_cursor();
+            if let Some((width, height)) = self.ctx.take_window_size_request() {
+                self.window.set_inner_size(
+                    PhysicalSize::new(width as f64, height as f64)
+                        .to_logical(self.ctx.hidpi_factor()),
+                );
+            }
+            self.ctx.input.end_frame();
+            if self.ctx.take_close_request() {
+                self.close_requested = true;
+            }
+            if self.ctx.take_surface_loss_request() {
+                self.simulate_surface_loss = true;
+            }
+            self.update_title();
+
+            // Without presenting nothing waits for the GPU, so wait for it here to keep the frame
+            // times honest
+            if self.headless {
+                unsafe { self.gl.finish() };
+            }
+            self.update_bench();
+
+            // Present the surface to the window, unless the handler is only rendering offscreen
+            self.latency.begin_present(&mut self.gl);
+            let present_result = if self.simulate_surface_loss {
+                self.simulate_surface_loss = false;
+                Err(surfman::Error::Failed)
+            } else if self.ctx.render_settings.present && !self.headless {
+                present_context_surface(device, &mut self.context).map(Some)
+            } else {
+                Ok(None)
+            };
+            self.latency.end_present();
+            if let Ok(times) = present_result {
+                self.ctx.timing.record_present(times);
+            }
+            // Recorded after presenting, so that a frame's time includes its screenshot
+            self.frame_graph
+                .end_frame(&mut self.gl, self.ctx.timing.frame_count());
+
+            // If we couldn't present, the surface is gone and we have to recreate it
+            if let Err(error) = present_result {
+                eprintln!("Window surface lost: {:?}", error);
+                self.handler.device_lost(&mut self.gl, &mut self.ctx);
+                self.surface_lost = true;
+            }
+        }
+
+        // The next window's context may read what this one drew into shared objects
+        if self.ctx.workarounds().flush_before_context_switch {
+            unsafe { self.gl.flush() };
+        }
+
+        // Check whether the GL context was reset by the driver
+        let reset_status = if self.ctx.take_context_reset_request() {
+            glow::UNKNOWN_CONTEXT_RESET
+        } else {
+            self.get_reset_status.map_or(glow::NO_ERROR, |f| f())
+        };
+        if reset_status != glow::NO_ERROR {
+            eprintln!(
+                "GL context reset ( status {:#x} ), recreating it",
+                reset_status
+            );
+
+            // Let the handler tear down its old resources, keeping its state for the new one
+            self.handler.device_lost(&mut self.gl, &mut self.ctx);
+            self.handler.save_state(&mut self.ctx);
+            self.handler.exit(&mut self.gl, &mut self.ctx);
+            self.delete_loop_objects();
+
+            // Throw away the old context and create a fresh one
+            if let Ok(Some(mut surface)) = device.unbind_surface_from_context(&mut self.context) {
+                device.destroy_surface(&mut self.context, &mut surface).ok();
+            }
+            device.destroy_context(&mut self.context).ok();
+            self.report_leaks();
+            self.resource_key = resources::start_context();
+            resources::make_current(self.resource_key);
+            self.context =
+                create_window_context(conn, &self.window, device, context_descriptor, share_with)
+                    .unwrap();
+
+            // Reload the GL functions for the new context and re-initialize the handler
+            let context = &self.context;
+            self.gl = unsafe {
+                glow::Context::from_loader_function(|s| {
+                    device.get_proc_address(context, s) as *const _
+                })
+            };
+            let features = Features::query(&self.gl, device, &self.context);
+            self.get_reset_status =
+                load_reset_status_fn(&self.gl, &features, device, &self.context);
+            self.pop_debug_group = debug_group::load_pop_fn(&features, device, &self.context);
+            debug_group::make_current(self.pop_debug_group);
+            let visible = self.frame_graph.visible;
+            self.frame_graph = FrameGraph::new(frame_graph::load_query_counter(
+                &features,
+                device,
+                &self.context,
+            ));
+            self.frame_graph.visible = visible;
+            self.ctx.set_features(features);
+            let context_report = ContextReport::query(&self.gl, device, &self.context);
+            eprintln!("{}: {}", self.title, context_report);
+            let workarounds = Workarounds::for_driver(
+                &self.title,
+                &context_report.vendor,
+                &context_report.renderer,
+                &context_report.version,
+                &self.ctx.config().workarounds,
+            );
+            workarounds::make_current(workarounds);
+            shader::make_current_target(ShaderTarget::for_features(self.ctx.features()));
+            self.ctx.set_workarounds(workarounds);
+            self.ctx.set_context_report(context_report);
+            self.handler = (self.factory)(&mut self.gl, &mut self.ctx);
+            self.surface_lost = false;
+            self.surface_retry_at = None;
+            self.damage.reset();
+        }
+    }
+
+    /// Count a benchmark frame, printing the average frame time and closing the window once all
+    /// of the frames have been drawn
+    fn update_bench(&mut self) {
+        let frames = match self.bench_frames {
+            Some(frames) => frames,
+            None => return,
+        };
+        let start = *self.bench_start.get_or_insert_with(Instant::now);
+        // The first frame only starts the clock, since it includes all of the setup
+        let drawn = self.ctx.timing.frame_count().saturating_sub(1);
+        if drawn >= frames {
+            let average = start.elapsed() / drawn.max(1) as u32;
+            eprintln!(
+                "{}: {} frames, average frame time {:.3} ms ( {:.1} fps )",
+                self.title,
+                drawn,
+                average.as_secs_f64() * 1000.,
+                1. / average.as_secs_f64().max(f64::EPSILON)
+            );
+            self.bench_frames = None;
+            self.close_requested = true;
+        }
+    }
+
+    /// Catch the window and its surface disagreeing about their size without a `Resized` event,
+    /// which happens e.g. on Wayland with fractional scaling, and go through the resize path
+    /// before the handler draws
+    ///
+    /// Without this the frame after such a resize is drawn with the old viewport and stretched
+    /// over the window. Each correction is counted in the context's stats.
+    fn check_surface_size(&mut self, device: &Device) {
+        let window_size = window_physical_size(&self.window);
+        if window_size.0 == 0 || window_size.1 == 0 {
+            return;
+        }
+        // The context's size is clamped to what it can draw, so compare it with the clamped size
+        let window_size = self.ctx.features().clamp_framebuffer_size(window_size);
+        let surface_size = device
+            .context_surface_info(&self.context)
+            .ok()
+            .flatten()
+            .map(|info| (info.size.width as u32, info.size.height as u32));
+        let window_mismatch = window_size != self.ctx.window_size();
+        let surface_mismatch = self.resize_surface
+            && !self.surface_resize_pending
+            && surface_size.is_some_and(|size| size != window_size);
+        if !window_mismatch && !surface_mismatch {
+            return;
+        }
+        self.ctx.set_window_size(window_size);
+        self.surface_resize_pending = self.resize_surface;
+        self.ctx.count_size_correction();
+    }
+
+    /// Resize the window surface to the size of the window
+    ///
+    /// The surface has to be unbound to be resized, which flushes on some backends, so this only
+    /// happens after the window was resized. A zero-sized surface can't be made, so a minimized
+    /// window keeps its old surface.
+    fn resize_surface(&mut self, device: &Device) {
+        if self.ctx.is_minimized() {
+            return;
+        }
+        let (width, height) = self.ctx.window_size();
+        if let Ok(Some(mut surface)) = device.unbind_surface_from_context(&mut self.context) {
+            if let Err(error) = device.resize_surface(
+                &self.context,
+                &mut surface,
+                Size2D::new(width as i32, height as i32),
+            ) {
+                eprintln!(
+                    "{}: Could not resize window surface: {:?}",
+                    self.title, error
+                );
+            }
+            if let Err((error, mut surface)) =
+                device.bind_surface_to_context(&mut self.context, surface)
+            {
+                eprintln!(
+                    "{}: Could not rebind window surface: {:?}",
+                    self.title, error
+                );
+                device.destroy_surface(&mut self.context, &mut surface).ok();
+                self.surface_lost = true;
+            }
+        }
+        // Handlers that never set the viewport expect it to cover the window. With a virtual
+        // resolution the viewport is set when the surface is cleared.
+        if self.ctx.render_settings.virtual_resolution.is_none() {
+            Rect::from_window_size((width, height)).set_viewport(&self.gl);
+        }
+    }
+
+    /// Find what to redraw this frame with `RenderSettings::partial_redraw`, and scissor the
+    /// clear and the handler's `draw` to it, returning whether the scissor test was turned on
+    ///
+    /// The handler says what changed, and the loop adds what its overlays cover, since they are
+    /// drawn over the frame every time. Post-processing reads the whole frame, and stereo, the
+    /// texture viewer, and custom cursors go anywhere over it, so while any of them are on the
+    /// whole frame is redrawn.
+    fn begin_partial_redraw(&mut self) -> bool {
+        if !self.ctx.render_settings.partial_redraw {
+            self.ctx.take_damage();
+            self.damage.reset();
+            return false;
+        }
+        let settings = &self.ctx.render_settings;
+        let whole_frame = settings.virtual_resolution.is_some()
+            || settings.anti_aliasing != AaMode::Off
+            || settings.depth_view.mode != DepthViewMode::Off
+            || settings.depth_mode == DepthMode::ReverseZ
+            || settings.stereo != StereoMode::Off
+            || settings.texture_view.texture.is_some()
+            || matches!(self.ctx.cursor(), Cursor::Custom(_));
+        if whole_frame {
+            self.ctx.damage_all();
+        } else {
+            self.handler.damage(&mut self.ctx);
+        }
+        let (window_size, ui_scale) = (self.ctx.window_size(), self.ctx.ui_scale());
+        let overlays = [
+            self.ctx.console.rect(window_size, ui_scale),
+            self.frame_graph.rect(window_size, ui_scale),
+        ];
+        for rect in overlays.iter().flatten() {
+            self.ctx.add_damage(*rect);
+        }
+
+        let damage = self.ctx.take_damage();
+        match self.damage.frame_region(damage, window_size) {
+            Some(region) => {
+                unsafe { self.gl.enable(glow::SCISSOR_TEST) };
+                region.set_scissor(&self.gl);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Bind the window surface and clear it as described by the handler's render settings
+    fn clear_surface(&mut self, device: &Device) {
+        // The handler may have left one of its own framebuffers bound, so make sure we clear the
+        // framebuffer of the window surface. Surfman reports 0 when that is the default
+        // framebuffer.
+        let surface_fbo = device
+            .context_surface_info(&self.context)
+            .ok()
+            .flatten()
+            .map(|info| info.framebuffer_object)
+            .filter(|&fbo| fbo != 0);
+        self.window_framebuffer = surface_fbo;
+
+        let depth_mode = self.ctx.render_settings.depth_mode;
+        let depth_format = depth_mode.depth_stencil_format();
+        let anti_aliasing = self.ctx.render_settings.anti_aliasing;
+
+        // With a virtual resolution the handler draws into a framebuffer of that size instead,
+        // which is made again whenever the resolution changes. The window's depth buffer is fixed
+        // point, which gains nothing from being reversed, so without anti-aliasing reversed depth
+        // is drawn into a framebuffer of the window's size.
+        let virtual_resolution = self.ctx.render_settings.virtual_resolution;
+        let target_size = match virtual_resolution {
+            Some(_) => Some(self.ctx.render_size()),
+            None if depth_mode == DepthMode::ReverseZ && anti_aliasing == AaMode::Off => {
+                Some(self.ctx.window_size())
+            }
+            None => None,
+        };
+        let target = self
+            .virtual_target
+            .as_ref()
+            .map(|target| (target.size, target.depth_format));
+        if target != target_size.map(|size| (size, depth_format)) {
+            if let Some(target) = self.virtual_target.take() {
+                target.delete(&mut self.gl);
+                // Handlers that never set the viewport expect it to cover the window
+                Rect::from_window_size(self.ctx.window_size()).set_viewport(&self.gl);
+            }
+            self.virtual_target =
+                target_size.map(|size| VirtualTarget::new(&mut self.gl, size, depth_format));
+        }
+        let framebuffer = match &self.virtual_target {
+            Some(target) => Some(target.framebuffer),
+            None => surface_fbo,
+        };
+
+        // With anti-aliasing the handler draws into another framebuffer, which is resolved into
+        // the one above after `draw`
+        let output = framebuffer;
+        let framebuffer = if anti_aliasing == AaMode::Off {
+            if let Some(anti_aliasing) = self.anti_aliasing.take() {
+                anti_aliasing.delete(&mut self.gl);
+            }
+            framebuffer
+        } else {
+            let size = self.ctx.render_size();
+            self.anti_aliasing
+                .get_or_insert_with(AntiAliasing::new)
+                .prepare(&mut self.gl, anti_aliasing, depth_format, size, framebuffer)
+        };
+
+        // The window's depth can't be sampled, so while the depth is shown the handler draws into
+        // a framebuffer with a depth texture instead, or the depth is copied into one from the
+        // anti-aliasing framebuffer
+        let framebuffer = if self.ctx.render_settings.depth_view.mode == DepthViewMode::Off {
+            if let Some(depth_view) = self.depth_view.take() {
+                depth_view.delete(&mut self.gl);
+            }
+            framebuffer
+        } else {
+            let size = self.ctx.render_size();
+            self.depth_view
+                .get_or_insert_with(DepthViewPass::new)
+                .prepare(&mut self.gl, size, depth_mode, framebuffer, output)
+        };
+        self.ctx.set_surface_framebuffer(framebuffer);
+        self.ctx.input.set_virtual_viewport(
+            virtual_resolution.map(|resolution| resolution.fit(self.ctx.window_size())),
+        );
+
+        unsafe {
+            self.gl.bind_framebuffer(glow::FRAMEBUFFER, framebuffer);
+        }
+        if let Some(size) = target_size {
+            Rect::from_window_size(size).set_viewport(&self.gl);
+        }
+
+        // The loop only touches the depth state for reversed depth, so handlers that set up
+        // their own depth test keep it with standard depth
+        self.depth_setup = DepthSetup::new(depth_mode, self.ctx.features());
+        if self.depth_setup.is_reversed() {
+            self.depth_setup.apply(&self.gl);
+        }
+        depth_mode::make_current(self.depth_setup);
+
+        let settings = &self.ctx.render_settings;
+        let clear_mask = settings.clear_mask();
+        if clear_mask == 0 {
+            return;
+        }
+
+        unsafe {
+            if let Some(color) = settings.clear_color {
+                let [r, g, b, a] = color.to_srgb();
+                self.gl.clear_color(r, g, b, a);
+            }
+            self.gl.clear(clear_mask);
+        }
+    }
+
+    /// Put the depth state back to GL's defaults after the handler drew with reversed depth, so
+    /// that the loop's passes and overlays draw the way they always do
+    fn end_depth_setup(&mut self) {
+        if self.depth_setup.is_reversed() {
+            DepthSetup::new(DepthMode::Standard, self.ctx.features()).apply(&self.gl);
+        }
+        depth_mode::make_current(DepthSetup::STANDARD);
+    }
+
+    /// Resolve the anti-aliased image into the window or virtual resolution framebuffer
+    fn resolve_anti_aliasing(&mut self) {
+        let timer_query = self.ctx.features().timer_query;
+        if let Some(anti_aliasing) = &mut self.anti_aliasing {
+            let gl = &mut self.gl;
+            debug_scope!(gl, "Anti-aliasing", {
+                anti_aliasing.resolve(gl, timer_query);
+            });
+        }
+    }
+
+    /// Show the depth buffer over the image, in the window or virtual resolution framebuffer
+    fn draw_depth_view(&mut self) {
+        if let Some(depth_view) = &mut self.depth_view {
+            let gl = &mut self.gl;
+            let view = &self.ctx.render_settings.depth_view;
+            debug_scope!(gl, "Depth view", {
+                depth_view.draw(gl, view);
+            });
+        }
+    }
+
+    /// Copy the image drawn at a virtual resolution to the window, with bars around it
+    fn present_virtual_resolution(&mut self) {
+        let target = match &self.virtual_target {
+            Some(target) => target,
+            None => return,
+        };
+        // Reversed depth without a virtual resolution covers the whole window
+        let resolution = self
+            .ctx
+            .render_settings
+            .virtual_resolution
+            .unwrap_or_else(|| VirtualResolution::new(target.size.0, target.size.1));
+        let viewport = resolution.fit(self.ctx.window_size());
+        let gl = &mut self.gl;
+        debug_scope!(gl, "Virtual resolution", {
+            target.present(gl, self.window_framebuffer, &viewport, resolution.bar_color);
+        });
+    }
+
+    /// Draw the frame after each of an A/B capture's commands, and save both frames with a
+    /// heatmap of their differences
+    ///
+    /// This is `image_diff::capture_ab` with the whole frame, including anti-aliasing, the depth
+    /// view, and the virtual resolution, as each side. The frame's time doesn't change between
+    /// the two draws.
+    fn run_ab_capture(&mut self, device: &Device, request: AbCaptureRequest) {
+        let size = self.ctx.window_size();
+        console::run_command(&mut self.ctx, &request.before);
+        self.draw_capture_frame(device);
+        let before = image_diff::read_framebuffer(&mut self.gl, self.window_framebuffer, size);
+        console::run_command(&mut self.ctx, &request.after);
+        self.draw_capture_frame(device);
+        let after = image_diff::read_framebuffer(&mut self.gl, self.window_framebuffer, size);
+        // Put the setting back the way it was before the capture
+        console::run_command(&mut self.ctx, &request.before);
+
+        let report = image_diff::image_diff(&before, &after);
+        let capture = AbCapture {
+            before,
+            after,
+            report,
+        };
+        match capture.save(&request.prefix) {
+            Ok(paths) => {
+                let message = format!(
+                    "A/B: {}\nSaved {}",
+                    capture.report.summary(),
+                    paths.join(", ")
+                );
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            Err(error) => {
+                let message = format!(
+                    "Couldn't save the A/B capture to {}-*.png: {}",
+                    request.prefix, error
+                );
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print_error(&message);
+            }
+        }
+    }
+
+    /// Draw the handler into the window surface without presenting it, for one side of an A/B
+    /// capture
+    fn draw_capture_frame(&mut self, device: &Device) {
+        self.clear_surface(device);
+        self.ctx.arena.reset();
+        let (gl, handler, ctx) = (&mut self.gl, &mut self.handler, &mut self.ctx);
+        debug_scope!(gl, "A/B capture", { handler.draw(gl, ctx) });
+        self.end_depth_setup();
+        self.end_viewport_frame();
+        if let Some(depth_view) = &mut self.depth_view {
+            depth_view.capture(&mut self.gl);
+        }
+        self.resolve_anti_aliasing();
+        self.draw_depth_view();
+        self.present_virtual_resolution();
+    }
+
+    /// Write a report of the GL objects and the state that the handler's `draw` left, if one was
+    /// asked for or if the frame had a GL error or an incomplete framebuffer
+    ///
+    /// Only the first few frames with problems get a report, so that a problem that comes back
+    /// every frame doesn't write one every frame.
+    fn write_frame_report(&mut self) {
+        let mut problems = diagnostics::take_problems();
+        let error = unsafe { self.gl.get_error() };
+        if error != glow::NO_ERROR {
+            problems.push(format!(
+                "{} after the handler's draw",
+                diagnostics::error_name(error)
+            ));
+        }
+        let requested = self.ctx.take_frame_report_request();
+        let automatic =
+            !problems.is_empty() && self.automatic_reports < diagnostics::MAX_AUTOMATIC_REPORTS;
+        if !requested && !automatic {
+            return;
+        }
+        if !requested {
+            self.automatic_reports += 1;
+        }
+
+        let report = diagnostics::dump_frame_report(&self.gl, &self.ctx, &*self.handler, &problems);
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = format!(
+            "frame-report-{}-{}.txt",
+            seconds,
+            self.ctx.timing.frame_count()
+        );
+        match report.save(&path) {
+            Ok(()) => {
+                let message = format!("Saved a frame report to {}", path);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            Err(error) => {
+                let message = format!("Couldn't save a frame report to {}: {}", path, error);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print_error(&message);
+            }
+        }
+    }
+
+    /// Save the first frame and a frame report for it, once it has been resolved and scaled like
+    /// a screenshot and before anything can go wrong presenting it
+    ///
+    /// This only happens once, and reads back the frame like a screenshot does, so it is cheap
+    /// enough to leave on while working on an example.
+    fn save_first_frame(&mut self, name: &str) {
+        self.save_screenshot(Path::new(&format!("{}.png", name)));
+        let mut report = diagnostics::dump_frame_report(&self.gl, &self.ctx, &*self.handler, &[]);
+        report.section(
+            "First frame",
+            format!(
+                "Saved before presenting, for `capture_first_frame`, with the image in {}.png",
+                name
+            ),
+        );
+        let path = format!("{}.txt", name);
+        if let Err(error) = report.save(&path) {
+            let message = format!("Couldn't save a frame report to {}: {}", path, error);
+            eprintln!("{}: {}", self.title, message);
+            self.ctx.console.print_error(&message);
+        }
+    }
+
+    /// Save the window surface to a PNG file
+    fn save_screenshot(&mut self, path: &Path) {
+        let (width, height) = self.ctx.window_size();
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        unsafe {
+            self.gl
+                .bind_framebuffer(glow::FRAMEBUFFER, self.window_framebuffer);
+            self.gl.read_pixels(
+                0,
+                0,
+                width as i32,
+                height as i32,
+                glow::RGBA,
+                glow::UNSIGNED_BYTE,
+                glow::PixelPackData::Slice(&mut pixels),
+            );
+        }
+
+        let image = readback::framebuffer_image(&pixels, width);
+        match image::save_buffer(path, &image, width, height, image::ColorType::Rgba8) {
+            Ok(()) => {
+                let message = format!("Saved a screenshot to {}", path.display());
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            Err(error) => {
+                let message = format!(
+                    "Couldn't save a screenshot to {}: {}",
+                    path.display(),
+                    error
+                );
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print_error(&message);
+            }
+        }
+    }
+
+    /// Start reading back the window's contents to copy them to the clipboard, which
+    /// `poll_clipboard` does once they have been read
+    fn copy_to_clipboard(&mut self) {
+        let size = self.ctx.window_size();
+        let clipboard = self.clipboard.get_or_insert_with(ClipboardCapture::new);
+        if clipboard.capture(&mut self.gl, self.window_framebuffer, size) {
+            self.frame_graph.mark(FrameEvent::Screenshot);
+        } else {
+            let message = "Still copying the frames before, so this one was skipped";
+            eprintln!("{}: {}", self.title, message);
+            self.ctx.console.print_error(message);
+        }
+    }
+
+    /// Hand the frames that have been read back to the clipboard, and report the ones that are on
+    /// it
+    fn poll_clipboard(&mut self) {
+        let outcome = match &mut self.clipboard {
+            Some(clipboard) => clipboard.poll(&mut self.gl),
+            None => return,
+        };
+        match outcome {
+            Some(CopyOutcome::Copied { width, height }) => {
+                let message = format!("Copied a {}x{} frame to the clipboard", width, height);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            Some(CopyOutcome::Saved { path, reason }) => {
+                let message = format!(
+                    "Couldn't copy the frame to the clipboard, so it was saved to {}: {}",
+                    path.display(),
+                    reason
+                );
+                eprintln!("{}: Warning: {}", self.title, message);
+                self.ctx.console.print_error(&message);
+            }
+            Some(CopyOutcome::Failed { reason }) => {
+                let message = format!("Couldn't copy the frame to the clipboard: {}", reason);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print_error(&message);
+            }
+            None => (),
+        }
+    }
+
+    /// Draw the frame time graph over the frame if it is shown
+    fn draw_frame_graph(&mut self) {
+        if !self.frame_graph.visible {
+            return;
+        }
+        let gl = &mut self.gl;
+        let text = self.debug_text.get_or_insert_with(|| DebugText::new(gl));
+
+        let (width, height) = self.ctx.window_size();
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, self.window_framebuffer);
+            gl.viewport(0, 0, width as i32, height as i32);
+        }
+        debug_scope!(gl, "Frame graph", {
+            self.frame_graph.draw(
+                gl,
+                text,
+                &self.ctx.arena,
+                (width, height),
+                self.ctx.ui_scale(),
+                self.ctx.theme(),
+            );
+        });
+    }
+
+    /// Draw the texture picked with the `view` console command over the frame, and print the
+    /// texels that are read from it
+    fn draw_texture_viewer(&mut self) {
+        if self.ctx.render_settings.texture_view.texture.is_none() {
+            if let Some(mut viewer) = self.texture_viewer.take() {
+                viewer.delete(&mut self.gl);
+            }
+            return;
+        }
+        let gl = &mut self.gl;
+        let text = self.debug_text.get_or_insert_with(|| DebugText::new(gl));
+        let viewer = self
+            .texture_viewer
+            .get_or_insert_with(|| TextureViewerPass::new(gl));
+
+        let (width, height) = self.ctx.window_size();
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, self.window_framebuffer);
+            gl.viewport(0, 0, width as i32, height as i32);
+        }
+        let result = debug_scope!(gl, "Texture viewer", {
+            viewer.draw_overlay(gl, text, &mut self.ctx)
+        });
+        match result {
+            Some(Ok(pick)) => {
+                eprintln!("{}: {}", self.title, pick);
+                self.ctx.console.print(&pick);
+            }
+            Some(Err(error)) => {
+                eprintln!("{}: Can't show the texture: {}", self.title, error);
+                self.ctx.console.print_error(&error);
+            }
+            None => (),
+        }
+    }
+
+    /// Draw the console over the frame if it is open
+    fn draw_console(&mut self) {
+        if !self.ctx.console.is_open() {
+            return;
+        }
+        let gl = &mut self.gl;
+        let text = self.debug_text.get_or_insert_with(|| DebugText::new(gl));
+
+        // The handler may have drawn to one of its own framebuffers or a part of the window
+        let (width, height) = self.ctx.window_size();
+        unsafe {
+            gl.bind_framebuffer(glow::FRAMEBUFFER, self.window_framebuffer);
+            gl.viewport(0, 0, width as i32, height as i32);
+        }
+        debug_scope!(gl, "Console", {
+            self.ctx.console.queue_draw(
+                text,
+                (width, height),
+                self.ctx.ui_scale(),
+                self.ctx.theme(),
+            );
+            text.draw(gl, &self.ctx.arena, (width, height));
+        });
+    }
+
+    /// Hide the views under the console from the cursor while it is open, and make the views
+    /// registered this frame the ones that cursor queries go through
+    fn end_viewport_frame(&mut self) {
+        let window_size = self.ctx.window_size();
+        if let Some(rect) = self.ctx.console.rect(window_size, self.ctx.ui_scale()) {
+            self.ctx.viewports.register_overlay(rect);
+        }
+        let render_size = self.ctx.render_size();
+        self.ctx.viewports.end_frame(render_size, window_size);
+    }
+
+    /// Give the handler's cursor to the window system if it changed, and draw it over everything
+    /// if it is a custom one
+    fn update_cursor(&mut self) {
+        self.cursor.apply(
+            &self.window,
+            self.ctx.cursor(),
+            self.ctx.is_cursor_grabbed(),
+        );
+        self.cursor.draw(
+            &mut self.gl,
+            self.window_framebuffer,
+            self.ctx.window_size(),
+            self.ctx.input.window_cursor_position(),
+            self.ctx.ui_scale(),
+        );
+    }
+
+    /// Delete the GL objects that the loop made for the window before the context goes away
+    fn delete_loop_objects(&mut self) {
+        self.frame_graph.delete(&mut self.gl);
+        self.latency.delete(&mut self.gl);
+        if let Some(mut text) = self.debug_text.take() {
+            text.delete(&mut self.gl);
+        }
+        if let Some(target) = self.virtual_target.take() {
+            target.delete(&mut self.gl);
+        }
+        if let Some(anti_aliasing) = self.anti_aliasing.take() {
+            anti_aliasing.delete(&mut self.gl);
+        }
+        if let Some(depth_view) = self.depth_view.take() {
+            depth_view.delete(&mut self.gl);
+        }
+        if let Some(mut viewer) = self.texture_viewer.take() {
+            viewer.delete(&mut self.gl);
+        }
+        if let Some(mut clipboard) = self.clipboard.take() {
+            clipboard.delete(&mut self.gl);
+        }
+        self.cursor.delete(&mut self.gl);
+    }
+
+    /// Toggle the console with the backtick key, and give it the keyboard while it is open
+    ///
+    /// Returns whether the console used up the event, in which case it doesn't reach the input
+    /// state or the loop's own keys.
+    fn handle_console_event(&mut self, event: &WindowEvent) -> bool {
+        let open = self.ctx.console.is_open();
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                if *key == VirtualKeyCode::Grave {
+                    self.ctx.console.set_open(!open);
+                    // The console won't pass on the releases of keys held while it is open
+                    self.ctx.input.release_keys();
+                    return true;
+                }
+                if open {
+                    if let Some(line) = self.ctx.console.handle_key(*key) {
+                        console::run_command(&mut self.ctx, &line);
+                    }
+                }
+                open
+            }
+            WindowEvent::ReceivedCharacter(c) if open => {
+                self.ctx.console.type_char(*c);
+                true
+            }
+            WindowEvent::KeyboardInput { .. } => open,
+            _ => false,
+        }
+    }
+
+    /// Handle an event sent to this window
+    fn handle_window_event(&mut self, event: WindowEvent) {
+        if self.handle_console_event(&event) {
+            return;
+        }
+        self.ctx
+            .input
+            .handle_window_event(&event, self.ctx.hidpi_factor());
+
+        match event {
+            WindowEvent::Destroyed
+            | WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.close_requested = true,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F9),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.simulate_surface_loss = true,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F11),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                // The window gets a resize event for its new size
+                if !self.fullscreen {
+                    self.windowed_placement = self.current_placement();
+                }
+                self.fullscreen = !self.fullscreen;
+                let monitor = if self.fullscreen {
+                    Some(self.window.get_current_monitor())
+                } else {
+                    None
+                };
+                self.window.set_fullscreen(monitor);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F3),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.show_stats_in_title = !self.show_stats_in_title,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F2),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.frame_graph.visible = !self.frame_graph.visible,
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F6),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let settings = &mut self.ctx.render_settings;
+                settings.anti_aliasing = settings.anti_aliasing.next();
+                let message = format!("Anti-aliasing: {}", settings.anti_aliasing);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F5),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let settings = &mut self.ctx.render_settings;
+                settings.depth_mode = settings.depth_mode.next();
+                let message = format!("Depth mode: {}", settings.depth_mode);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F10),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let settings = &mut self.ctx.render_settings;
+                settings.stereo = settings.stereo.toggled();
+                let message = format!("Stereo: {}", settings.stereo);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F7),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let message = format!("Theme: {}", self.ctx.next_theme());
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F8),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                let view = &mut self.ctx.render_settings.depth_view;
+                view.mode = view.mode.next();
+                let message = format!("Depth view: {}", view.mode);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode:
+                            Some(key @ (VirtualKeyCode::PageUp | VirtualKeyCode::PageDown)),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } if self.ctx.render_settings.depth_view.mode != DepthViewMode::Off => {
+                // Move the end of the range the depth is shown over
+                let view = &mut self.ctx.render_settings.depth_view;
+                view.scale_range(if key == VirtualKeyCode::PageUp {
+                    DEPTH_RANGE_STEP
+                } else {
+                    1. / DEPTH_RANGE_STEP
+                });
+                let (start, end) = view.range();
+                let message = format!("Depth range: {} to {}", start, end);
+                eprintln!("{}: {}", self.title, message);
+                self.ctx.console.print(&message);
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F4),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => {
+                resources::make_current(self.resource_key);
+                eprint!(
+                    "{}: {}",
+                    self.title,
+                    resources::memory_report(MEMORY_REPORT_COUNT)
+                );
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F12),
+                        state: ElementState::Pressed,
+                        modifiers,
+                        ..
+                    },
+                ..
+            } if modifiers.ctrl => self.ctx.request_clipboard_copy(modifiers.shift),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(VirtualKeyCode::F12),
+                        state: ElementState::Pressed,
+                        ..
+                    },
+                ..
+            } => self.ctx.request_frame_report(),
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(key),
+                        state: ElementState::Pressed,
+                        modifiers,
+                        ..
+                    },
+                ..
+            } => self.handle_time_key(key, modifiers.shift),
+            WindowEvent::Resized(logical_size) => {
+                let size = logical_size.to_physical(self.ctx.hidpi_factor());
+                self.ctx
+                    .set_window_size((size.width as u32, size.height as u32));
+                self.surface_resize_pending = self.resize_surface;
+            }
+            // The window system lost what was in the window, so every buffer has to be redrawn
+            WindowEvent::Refresh => self.damage.reset(),
+            WindowEvent::HiDpiFactorChanged(hidpi_factor) => {
+                self.ctx.set_hidpi_factor(hidpi_factor);
+                self.ctx.set_window_size(window_physical_size(&self.window));
+            }
+            _ => {}
+        }
+    }
+
+    /// Handle the keys used to pause and scrub through the animation time
+    ///
+    /// Left and Right step the time by `TIME_STEP`, or `TIME_STEP_LARGE` with shift held, Home
+    /// resets it to zero, and P pauses or resumes it.
+    fn handle_time_key(&mut self, key: VirtualKeyCode, shift: bool) {
+        let step = if shift { TIME_STEP_LARGE } else { TIME_STEP };
+        let timing = &mut self.ctx.timing;
+        match key {
+            VirtualKeyCode::Left => timing.step_time(-step),
+            VirtualKeyCode::Right => timing.step_time(step),
+            VirtualKeyCode::Home => timing.set_time(0.),
+            VirtualKeyCode::P => {
+                let paused = timing.is_paused();
+                timing.set_paused(!paused);
+            }
+            _ => return,
+        }
+        self.show_time_in_title = true;
+    }
+
+    /// Show the animation time in the window title once it has been paused or scrubbed, and the
+    /// frame stats if they have been turned on
+    fn update_title(&mut self) {
+        let timing = &self.ctx.timing;
+        let mut title = self.title.clone();
+        if self.show_time_in_title {
+            title += &format!(
+                " - t = {:.1}s{}",
+                timing.time(),
+                if timing.is_paused() {
+                    " ( paused )"
+                } else {
+                    ""
+                }
+            );
+        }
+        if self.show_stats_in_title {
+            if self.stats_title.is_empty()
+                || timing.frame_count().is_multiple_of(STATS_TITLE_INTERVAL)
+            {
+                let present = timing.average_present();
+                let ms = |duration: Duration| duration.as_secs_f64() * 1000.;
+                self.stats_title = format!(
+                    " - frame {:.2} ms, present {:.2} ms ( unbind {:.2} / present {:.2} / bind {:.2} )",
+                    timing.average_delta() as f64 * 1000.,
+                    ms(present.total()),
+                    ms(present.unbind),
+                    ms(present.present),
+                    ms(present.bind),
+                );
+                self.stats_title += &format!(
+                    ", GPU memory ~{}",
+                    resources::format_bytes(resources::memory_usage().total())
+                );
+                self.stats_title += &format!(
+                    ", frame arena peak {}",
+                    resources::format_bytes(self.ctx.arena.stats().peak as u64)
+                );
+                let variants = shader_variants::live_variant_totals();
+                if variants.count > 0 {
+                    self.stats_title += &format!(
+                        ", {} shader variants ( compiled in {:.1} ms )",
+                        variants.count,
+                        variants.compile_time.as_secs_f64() * 1000.
+                    );
+                }
+                if let Some(anti_aliasing) = &self.anti_aliasing {
+                    self.stats_title += &format!(", AA {}", anti_aliasing.mode());
+                    if let Some(gpu_time) = anti_aliasing.gpu_time() {
+                        self.stats_title += &format!(" ( {:.2} ms GPU )", ms(gpu_time));
+                    }
+                } else {
+                    self.stats_title += ", AA off";
+                }
+                let corrections = self.ctx.size_corrections();
+                if corrections > 0 {
+                    self.stats_title += &format!(", {} size corrections", corrections);
+                }
+                if let Some(latency) = self.latency.stats() {
+                    self.stats_title +=
+                        &format!(", {} ( {}", latency, self.ctx.render_settings.latency_mode);
+                    if self.ctx.render_settings.latency_mode == LatencyMode::LowLatency {
+                        self.stats_title += &format!(", waited {:.2} ms", latency.wait);
+                    }
+                    self.stats_title += " )";
+                }
+                if self.ctx.render_settings.partial_redraw {
+                    self.stats_title += &format!(
+                        ", redrew {:.1}% of the window",
+                        self.damage.redrawn() * 100.
+                    );
+                }
+                let uniforms = shader::frame_uniform_stats();
+                if uniforms.issued + uniforms.skipped > 0 {
+                    self.stats_title += &format!(
+                        ", uniforms {} set / {} skipped",
+                        uniforms.issued, uniforms.skipped
+                    );
+                }
+            }
+            title += &self.stats_title;
+        } else {
+            self.stats_title.clear();
+        }
+
+        // Only bother the window system when the title actually changed
+        if title != self.current_title {
+            self.window.set_title(&title);
+            self.current_title = title;
+        }
+    }
+
+    /// Where the window is now, in physical pixels
+    fn current_placement(&self) -> Option<WindowPlacement> {
+        let position = self
+            .window
+            .get_position()?
+            .to_physical(self.window.get_hidpi_factor());
+        let (width, height) = window_physical_size(&self.window);
+        Some(WindowPlacement {
+            x: position.x.round() as i32,
+            y: position.y.round() as i32,
+            width,
+            height,
+            fullscreen: self.fullscreen,
+            monitor: self.window.get_current_monitor().get_name(),
+        })
+    }
+
+    /// Save where the window is, if it is remembered, keeping the other windows' placements
+    fn save_placement(&self) {
+        let name = match &self.placement_name {
+            Some(name) => name,
+            None => return,
+        };
+        // A fullscreen window is saved where it goes back to, on the monitor it is fullscreen on
+        let placement = if self.fullscreen {
+            self.windowed_placement
+                .clone()
+                .map(|placement| WindowPlacement {
+                    fullscreen: true,
+                    monitor: self.window.get_current_monitor().get_name(),
+                    ..placement
+                })
+        } else {
+            self.current_placement()
+        };
+        let placement = match placement {
+            Some(placement) => placement,
+            None => return,
+        };
+
+        let mut placements = WindowPlacements::load();
+        placements.set(name, placement);
+        if let Err(error) = placements.save() {
+            eprintln!(
+                "{}: Couldn't save the window placement to {}: {}",
+                self.title,
+                WindowPlacements::path().display(),
+                error
+            );
+        }
+    }
+
+    /// Save what the handler keeps between runs, if the window's state is saved, keeping the
+    /// other windows' states
+    fn save_state(&mut self) {
+        if self.ctx.saved_state.name().is_none() {
+            return;
+        }
+        self.handler.save_state(&mut self.ctx);
+        let mut states = DemoStates::load();
+        states.set(&self.ctx.saved_state);
+        if let Err(error) = states.save() {
+            eprintln!(
+                "{}: Couldn't save the handler's state to {}: {}",
+                self.title,
+                DemoStates::path().display(),
+                error
+            );
+        }
+    }
+
+    /// Print the GL objects that the handler never deleted from the context, which has just been
+    /// destroyed
+    fn report_leaks(&self) {
+        if let Some(tracker) = resources::finish_context(self.resource_key) {
+            if resources::ENABLED && !tracker.is_empty() {
+                eprintln!("{}: {}", self.title, tracker.report());
+            }
+        }
+    }
+
+    /// Shut down the handler and destroy the window's context
+    fn destroy(mut self, device: &Device) {
+        self.save_placement();
+        self.save_state();
+        device.make_context_current(&self.context).ok();
+        resources::make_current(self.resource_key);
+        debug_group::make_current(self.pop_debug_group);
+        self.handler.exit(&mut self.gl, &mut self.ctx);
+        self.delete_loop_objects();
+        if let Ok(Some(mut surface)) = device.unbind_surface_from_context(&mut self.context) {
+            device.destroy_surface(&mut self.context, &mut surface).ok();
+        }
+        device.destroy_context(&mut self.context).unwrap();
+        self.report_leaks();
+
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(error) = recorder.flush() {
+                eprintln!("{}: Could not save input recording: {}", self.title, error);
+            }
+        }
+    }
+}
+
+/// The name a window's placement is saved under when its config doesn't have one, so that
+/// examples with the same window title don't share a placement
+fn default_placement_name(title: &str) -> String {
+    let program = std::env::current_exe()
+        .ok()
+        .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()));
+    match program {
+        Some(program) => format!("{}: {}", program, title),
+        None => title.to_string(),
+    }
+}
+
+/// Whether a window's handler state is saved and restored
+///
+/// Headless windows and ones recording or replaying input have to start the same way every time,
+/// so they don't restore what was saved, and don't overwrite it.
+fn remembers_state(config: &WindowConfig) -> bool {
+    !config.headless
+        && config.bench_frames.is_none()
+        && config.record_input.is_none()
+        && config.replay_input.is_none()
+}
+
+/// Find the monitor that a saved placement was on, and fit the placement on it
+///
+/// Returns `None` if the monitor isn't connected anymore, so that the window opens where the
+/// window system puts it instead of off of the screen.
+fn fit_placement(
+    event_loop: &EventsLoop,
+    placement: &WindowPlacement,
+) -> Option<(WindowPlacement, MonitorId)> {
+    let bounds = |monitor: &MonitorId| {
+        let position = monitor.get_position();
+        let size = monitor.get_dimensions();
+        (
+            (position.x.round() as i32, position.y.round() as i32),
+            (size.width.round() as u32, size.height.round() as u32),
+        )
+    };
+    let monitor = event_loop.get_available_monitors().find(|monitor| {
+        match &placement.monitor {
+            Some(name) => monitor.get_name().as_ref() == Some(name),
+            // Without a name, use the monitor that the window's corner was on
+            None => {
+                let ((x, y), (width, height)) = bounds(monitor);
+                placement.x >= x
+                    && placement.y >= y
+                    && placement.x < x + width as i32
+                    && placement.y < y + height as i32
+            }
+        }
+    })?;
+    let (position, size) = bounds(&monitor);
+    Some((placement.clamped_to(position, size), monitor))
+}
+
+/// Find the state of the window with the given id
+fn find_window(states: &mut [WindowState], window_id: WindowId) -> Option<&mut WindowState> {
+    states.iter_mut().find(|s| s.window.id() == window_id)
+}
+
+/// Get the inner size of the window in physical pixels
+fn window_physical_size(window: &Window) -> (u32, u32) {
+    match window.get_inner_size() {
+        Some(size) => {
+            let size = size.to_physical(window.get_hidpi_factor());
+            (size.width as u32, size.height as u32)
+        }
+        // The window has already been closed
+        None => (0, 0),
+    }
+}
+
+/// Create an OpenGL context and bind a surface for the given window to it
+fn create_window_context(
+    conn: &Connection,
+    window: &Window,
+    device: &mut Device,
+    context_descriptor: &ContextDescriptor,
+    share_with: Option<&Context>,
+) -> Result<Context, surfman::Error> {
+    // Create an OpenGL context
+    let mut context = device.create_context(context_descriptor, share_with)?;
+
+    // Create and bind the window surface
+    if let Err(error) = recreate_surface(conn, window, device, &mut context) {
+        device.destroy_context(&mut context).ok();
+        return Err(error);
+    }
+
+    Ok(context)
+}
+
+/// Destroy any surface bound to the context and replace it with a new surface for the window
+fn recreate_surface(
+    conn: &Connection,
+    window: &Window,
+    device: &mut Device,
+    context: &mut Context,
+) -> Result<(), surfman::Error> {
+    // Get rid of the old surface if there still is one
+    if let Ok(Some(mut old_surface)) = device.unbind_surface_from_context(context) {
+        device.destroy_surface(context, &mut old_surface).ok();
+    }
+
+    // Create a native widget to attach the visible render surface to
+    let native_widget = conn.create_native_widget_from_winit_window(window)?;
+    // Define the surface type for our graphics surface ( a surface based on a native widget, i.e. not an offscreen surface )
+    let surface_type = SurfaceType::Widget { native_widget };
+
+    // Create a surface that can be accessed only from the GPU
+    let surface = device.create_surface(context, SurfaceAccess::GPUOnly, surface_type)?;
+
+    // Bind our surface to our GL context
+    device
+        .bind_surface_to_context(context, surface)
+        .map_err(|(error, mut surface)| {
+            device.destroy_surface(context, &mut surface).ok();
+            error
+        })?;
+
+    // Make our context the current context
+    device.make_context_current(context)
+}
+
+/// Present the surface bound to the context and bind it back to the context afterwards,
+/// returning how long each step took
+///
+/// Every surfman 0.3 backend takes the surface by `&mut Surface` in `present_surface`, so a surface
+/// has to be unbound before it can be presented. What the steps cost depends on the backend:
+///
+/// - On the EGL based backends ( X11 and Wayland on Linux, and Android ) and on CGL ( macOS )
+///   unbinding flushes the GL commands, so it can take a while when the frame had a lot of work.
+///   Presenting swaps buffers, which may wait for vsync.
+/// - On WGL and ANGLE ( Windows ) unbinding only detaches the surface and is cheap, and presenting
+///   does the flush and swap.
+///
+/// If anything goes wrong the surface is destroyed and the error is returned so that the caller
+/// can recreate it.
+fn present_context_surface(
+    device: &Device,
+    context: &mut Context,
+) -> Result<PresentTimes, surfman::Error> {
+    // Surfman requires us to unbind the surface before presenting it
+    let start = Instant::now();
+    let mut surface = match device.unbind_surface_from_context(context)? {
+        Some(surface) => surface,
+        None => return Err(surfman::Error::NoWidgetAttached),
+    };
+    let unbound = Instant::now();
+
+    if let Err(error) = device.present_surface(context, &mut surface) {
+        device.destroy_surface(context, &mut surface).ok();
+        return Err(error);
+    }
+    let presented = Instant::now();
+
+    device
+        .bind_surface_to_context(context, surface)
+        .map_err(|(error, mut surface)| {
+            device.destroy_surface(context, &mut surface).ok();
+            error
+        })?;
+
+    Ok(PresentTimes {
+        unbind: unbound - start,
+        present: presented - unbound,
+        bind: presented.elapsed(),
+    })
+}
+
+/// Load `glGetGraphicsResetStatus` if the context supports `GL_ARB_robustness` or
+/// `GL_KHR_robustness` and was created to be lost on a reset
+///
+/// Without the lose-context-on-reset strategy the driver never reports a reset, so the function
+/// would always return `NO_ERROR`. surfman has no way to ask for that strategy, so most contexts
+/// can't detect resets and only `AppContext::simulate_context_reset` exercises the recovery.
+fn load_reset_status_fn(
+    gl: &glow::Context,
+    features: &Features,
+    device: &Device,
+    context: &Context,
+) -> Option<GetGraphicsResetStatus> {
+    let symbol = if features.has_extension("GL_KHR_robustness") {
+        "glGetGraphicsResetStatus"
+    } else if features.has_extension("GL_ARB_robustness") {
+        "glGetGraphicsResetStatusARB"
+    } else {
+        return None;
+    };
+    let strategy = unsafe { gl.get_parameter_i32(glow::RESET_NOTIFICATION_STRATEGY) } as u32;
+    if strategy != glow::LOSE_CONTEXT_ON_RESET {
+        eprintln!("The context is not lost on a reset, so driver resets can't be detected");
+        return None;
+    }
+
+    let ptr = device.get_proc_address(context, symbol);
+    if ptr.is_null() {
+        None
+    } else {
+        Some(unsafe {
+            std::mem::transmute::<*const std::os::raw::c_void, GetGraphicsResetStatus>(ptr)
+        })
+    }
+}
@@ -0,0 +1,156 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::config::{self, Config, ConfigError};
+
+/// The name of the file that window placements are saved in, which goes next to the config file,
+/// or in the working directory if there isn't one
+pub const PLACEMENT_FILE_NAME: &str = "me_learning_opengl_windows.toml";
+
+/// Where a window was on the screen, in physical pixels
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WindowPlacement {
+    /// The position of the outer top-left corner of the window, including its decorations
+    pub x: i32,
+    pub y: i32,
+    /// The size of the inside of the window
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+    /// The name of the monitor the window was on, if the window system gave it one
+    pub monitor: Option<String>,
+}
+
+impl WindowPlacement {
+    /// Move and shrink the window so that it fits on a monitor with the given position and size
+    pub fn clamped_to(
+        &self,
+        (monitor_x, monitor_y): (i32, i32),
+        (width, height): (u32, u32),
+    ) -> Self {
+        let width_fit = self.width.min(width).max(1);
+        let height_fit = self.height.min(height).max(1);
+        let max_x = monitor_x + (width - width_fit) as i32;
+        let max_y = monitor_y + (height - height_fit) as i32;
+        Self {
+            x: self.x.clamp(monitor_x, max_x.max(monitor_x)),
+            y: self.y.clamp(monitor_y, max_y.max(monitor_y)),
+            width: width_fit,
+            height: height_fit,
+            ..self.clone()
+        }
+    }
+}
+
+/// The saved placements of windows, by the name of their window config
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WindowPlacements {
+    placements: BTreeMap<String, WindowPlacement>,
+}
+
+impl WindowPlacements {
+    /// The file that window placements are saved in
+    pub fn path() -> PathBuf {
+        let dir = Config::find_file()
+            .and_then(|path| Some(path.parent()?.to_path_buf()))
+            .unwrap_or_default();
+        dir.join(PLACEMENT_FILE_NAME)
+    }
+
+    /// Load the saved placements if there are any
+    ///
+    /// Problems are printed as warnings, and leave out the placements that couldn't be read.
+    pub fn load() -> Self {
+        let path = Self::path();
+        if !path.is_file() {
+            return Self::default();
+        }
+        Self::open(&path).unwrap_or_else(|error| {
+            eprintln!("Warning: {}: {}", path.display(), error);
+            Self::default()
+        })
+    }
+
+    /// Read a placements file
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Read placements from a table of `key = value` lines for each window
+    pub fn from_toml(toml: &str) -> Result<Self, ConfigError> {
+        let mut placements = BTreeMap::new();
+        config::parse_toml(toml, |name, key, value| {
+            let name = match name {
+                Some(name) => name,
+                None => return Ok(false),
+            };
+            let placement = placements
+                .entry(name.to_string())
+                .or_insert(WindowPlacement {
+                    x: 0,
+                    y: 0,
+                    width: 0,
+                    height: 0,
+                    fullscreen: false,
+                    monitor: None,
+                });
+            let number = || {
+                value
+                    .parse::<i64>()
+                    .map_err(|_| format!("Expected a number for `{}`, got `{}`", key, value))
+            };
+            match key {
+                "x" => placement.x = number()? as i32,
+                "y" => placement.y = number()? as i32,
+                "width" => placement.width = number()?.max(1) as u32,
+                "height" => placement.height = number()?.max(1) as u32,
+                "fullscreen" => placement.fullscreen = value == "true",
+                "monitor" => placement.monitor = Some(value.to_string()),
+                _ => return Ok(false),
+            }
+            Ok(true)
+        })?;
+
+        // Leave out windows that are missing their size
+        placements.retain(|_, placement| placement.width > 0 && placement.height > 0);
+        Ok(Self { placements })
+    }
+
+    /// Write the placements as a table for each window that `from_toml` can read
+    pub fn to_toml(&self) -> String {
+        let mut toml = String::new();
+        for (name, placement) in &self.placements {
+            if !toml.is_empty() {
+                toml.push('\n');
+            }
+            writeln!(toml, "[{:?}]", name).unwrap();
+            writeln!(toml, "x = {}", placement.x).unwrap();
+            writeln!(toml, "y = {}", placement.y).unwrap();
+            writeln!(toml, "width = {}", placement.width).unwrap();
+            writeln!(toml, "height = {}", placement.height).unwrap();
+            writeln!(toml, "fullscreen = {}", placement.fullscreen).unwrap();
+            if let Some(monitor) = &placement.monitor {
+                writeln!(toml, "monitor = {:?}", monitor).unwrap();
+            }
+        }
+        toml
+    }
+
+    /// The saved placement of a window
+    pub fn get(&self, name: &str) -> Option<&WindowPlacement> {
+        self.placements.get(name)
+    }
+
+    pub fn set(&mut self, name: &str, placement: WindowPlacement) {
+        self.placements.insert(name.to_string(), placement);
+    }
+
+    /// Save the placements to `path()`
+    pub fn save(&self) -> io::Result<()> {
+        std::fs::write(Self::path(), self.to_toml())
+    }
+}
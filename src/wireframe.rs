@@ -0,0 +1,237 @@
+use cgmath::Matrix4;
+use glow::HasContext;
+
+use crate::{
+    color::Color,
+    debug_group::DebugGroup,
+    depth_mode::{self, DepthMode},
+    mesh::MeshData,
+    nested::SavedState,
+    render_graph::PolygonOffset,
+    resources::{self, ResourceKind},
+    shader::ShaderProgram,
+    vertex::{VertexFormat, VertexLayout},
+    AppContext,
+};
+
+const VERTEX_SHADER_SRC: &str = include_str!("wireframe/vertex.glsl");
+const FRAGMENT_SHADER_SRC: &str = include_str!("wireframe/fragment.glsl");
+
+/// The barycentric coordinates of the corners of a triangle
+const CORNERS: [[f32; 3]; 3] = [[1., 0., 0.], [0., 1., 0.], [0., 0., 1.]];
+
+/// The settings of a wireframe overlay, which can be changed between frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WireframeParams {
+    /// The width of the lines in pixels, at a UI scale of 1
+    pub line_width: f32,
+    /// The color of the lines, or `None` for the theme's `wireframe_color`
+    pub color: Option<Color>,
+    /// How opaque the lines behind other surfaces are, from 0 to leave them out to 1 to draw them
+    /// like the lines in front
+    pub hidden_alpha: f32,
+    /// How far the lines are pulled towards the camera, so that they win the depth test against
+    /// the surfaces they are drawn on. Negative values pull them closer, like for decals.
+    pub depth_bias: PolygonOffset,
+}
+
+impl Default for WireframeParams {
+    fn default() -> Self {
+        Self {
+            line_width: 1.,
+            color: None,
+            hidden_alpha: 0.,
+            depth_bias: PolygonOffset::new(-1., -1.),
+        }
+    }
+}
+
+impl WireframeParams {
+    /// The same lines, with the hidden ones drawn faintly instead of left out
+    pub fn dimmed() -> Self {
+        Self {
+            hidden_alpha: 0.25,
+            ..Self::default()
+        }
+    }
+}
+
+/// The triangles of a mesh uploaded for `WireframePass`, with barycentric coordinates at their
+/// corners
+///
+/// Every triangle gets its own three vertices, since a vertex shared by several triangles would
+/// need to be a different corner of each of them. Only the positions are kept.
+#[derive(Debug)]
+pub struct WireframeMesh {
+    vao: u32,
+    vbo: u32,
+    pub vertex_count: i32,
+}
+
+impl WireframeMesh {
+    /// The vertex layout: the position at location 0 and the barycentric coordinates at 1
+    pub fn vertex_layout() -> VertexLayout {
+        VertexLayout::new(&[(0, VertexFormat::Float32x3), (1, VertexFormat::Float32x3)])
+    }
+
+    pub fn new(gl: &mut glow::Context, data: &MeshData) -> Self {
+        let layout = Self::vertex_layout();
+        let mut vertices = Vec::with_capacity(data.indices.len() * layout.stride());
+        for triangle in data.indices.chunks_exact(3) {
+            for (&index, corner) in triangle.iter().zip(&CORNERS) {
+                let position = &data.positions[index as usize];
+                for x in position.iter().chain(corner) {
+                    vertices.extend_from_slice(&x.to_ne_bytes());
+                }
+            }
+        }
+
+        unsafe {
+            let vao = gl.create_vertex_array().unwrap();
+            gl.bind_vertex_array(Some(vao));
+
+            let vbo = gl.create_buffer().unwrap();
+            gl.bind_buffer(glow::ARRAY_BUFFER, Some(vbo));
+            gl.buffer_data_u8_slice(glow::ARRAY_BUFFER, &vertices, glow::STATIC_DRAW);
+            layout.apply(gl);
+
+            gl.bind_vertex_array(None);
+
+            resources::track(ResourceKind::VertexArray, vao, "Wireframe vertex array");
+            resources::track_sized(
+                ResourceKind::Buffer,
+                vbo,
+                "Wireframe vertex buffer",
+                vertices.len() as u64,
+            );
+
+            Self {
+                vao,
+                vbo,
+                vertex_count: (vertices.len() / layout.stride()) as i32,
+            }
+        }
+    }
+
+    /// Draw the triangles with whatever program is bound
+    pub fn draw(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.bind_vertex_array(Some(self.vao));
+            gl.draw_arrays(glow::TRIANGLES, 0, self.vertex_count);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&self, gl: &mut glow::Context) {
+        unsafe {
+            gl.delete_vertex_array(self.vao);
+            gl.delete_buffer(self.vbo);
+        }
+        resources::untrack(ResourceKind::VertexArray, self.vao);
+        resources::untrack(ResourceKind::Buffer, self.vbo);
+    }
+}
+
+/// Draws the edges of meshes over them, anti-aliased and the same width in pixels at any distance
+///
+/// The lines are found in the fragment shader from how close each pixel is to the edges of its
+/// triangle, so they need the barycentric coordinates of a `WireframeMesh`. Draw it after the
+/// scene, since the lines are blended over it without writing depth. The lines in front are
+/// depth tested against the scene with `WireframeParams::depth_bias`, and the ones behind it are
+/// drawn again with the depth test turned around, at `WireframeParams::hidden_alpha`. Both sides
+/// of the triangles are drawn, so the back of a model shows through when it is dimmed.
+#[derive(Debug)]
+pub struct WireframePass {
+    program: ShaderProgram,
+}
+
+impl WireframePass {
+    pub fn new(gl: &mut glow::Context) -> Self {
+        let program = ShaderProgram::new(gl, VERTEX_SHADER_SRC, FRAGMENT_SHADER_SRC).unwrap();
+        Self { program }
+    }
+
+    /// Draw the edges of meshes, each with its model matrix
+    ///
+    /// The blending, depth, polygon offset, and face culling state are put back afterwards.
+    pub fn draw(
+        &mut self,
+        gl: &mut glow::Context,
+        ctx: &AppContext,
+        params: &WireframeParams,
+        view_projection: Matrix4<f32>,
+        meshes: &[(&WireframeMesh, Matrix4<f32>)],
+    ) {
+        let _group = DebugGroup::push(gl, "Wireframe");
+        // The theme is read every frame, so the lines change with it
+        let color = params.color.unwrap_or(ctx.theme().wireframe_color);
+        let depth = depth_mode::current();
+        // The depth tests that pass the lines in front of the scene and the ones behind it
+        let (visible_func, hidden_func) = match depth.mode {
+            DepthMode::Standard => (glow::LEQUAL, glow::GREATER),
+            DepthMode::ReverseZ => (glow::GEQUAL, glow::LESS),
+        };
+
+        unsafe {
+            let state = SavedState::save(gl);
+            let depth_func = gl.get_parameter_i32(glow::DEPTH_FUNC) as u32;
+            let offset_enabled = gl.is_enabled(glow::POLYGON_OFFSET_FILL);
+            gl.enable(glow::DEPTH_TEST);
+            gl.depth_mask(false);
+            gl.disable(glow::CULL_FACE);
+            gl.enable(glow::BLEND);
+            gl.blend_func_separate(
+                glow::SRC_ALPHA,
+                glow::ONE_MINUS_SRC_ALPHA,
+                glow::ONE,
+                glow::ONE_MINUS_SRC_ALPHA,
+            );
+            // The same offset for both passes, so that the surface under a line in front never
+            // counts as being in front of it
+            let sign = depth.mode.offset_sign();
+            gl.enable(glow::POLYGON_OFFSET_FILL);
+            gl.polygon_offset(
+                params.depth_bias.factor * sign,
+                params.depth_bias.units * sign,
+            );
+
+            let program = &mut self.program;
+            program.bind(gl);
+            program.set_uniform(gl, "viewProjection", view_projection);
+            program.set_uniform(gl, "lineWidth", params.line_width * ctx.ui_scale());
+            // The hidden lines first, so the lines in front are blended over them
+            if params.hidden_alpha > 0. {
+                let hidden = color.with_alpha(color.a * params.hidden_alpha.min(1.));
+                draw_lines(gl, program, hidden_func, hidden, meshes);
+            }
+            draw_lines(gl, program, visible_func, color, meshes);
+
+            gl.depth_func(depth_func);
+            if !offset_enabled {
+                gl.disable(glow::POLYGON_OFFSET_FILL);
+            }
+            state.restore(gl);
+        }
+    }
+
+    /// Delete the GL objects
+    pub fn delete(&mut self, gl: &mut glow::Context) {
+        self.program.delete(gl);
+    }
+}
+
+/// Draw the lines of meshes in one color, where they pass a depth test
+fn draw_lines(
+    gl: &mut glow::Context,
+    program: &mut ShaderProgram,
+    depth_func: u32,
+    color: Color,
+    meshes: &[(&WireframeMesh, Matrix4<f32>)],
+) {
+    unsafe { gl.depth_func(depth_func) };
+    program.set_uniform(gl, "color", color.to_srgb());
+    for (mesh, model) in meshes {
+        program.set_uniform(gl, "model", *model);
+        mesh.draw(gl);
+    }
+}
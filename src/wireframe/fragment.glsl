@@ -0,0 +1,21 @@
+#version 330 core
+// 1 at one corner of the triangle and 0 along the edge across from it, for each corner
+in vec3 barycentric;
+
+uniform vec4 color;
+// The width of the lines in pixels
+uniform float lineWidth;
+
+out vec4 FragColor;
+
+void main() {
+    // How far the pixel is from the nearest edge, in pixels. The two triangles on either side of
+    // an edge each draw half of its line.
+    vec3 pixels = barycentric / fwidth(barycentric);
+    float distance = min(min(pixels.x, pixels.y), pixels.z);
+    float coverage = clamp(lineWidth * 0.5 + 0.5 - distance, 0.0, 1.0);
+    if (coverage <= 0.0) {
+        discard;
+    }
+    FragColor = vec4(color.rgb, color.a * coverage);
+}
@@ -0,0 +1,13 @@
+#version 330 core
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aBarycentric;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+out vec3 barycentric;
+
+void main() {
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+    barycentric = aBarycentric;
+}
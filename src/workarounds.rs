@@ -0,0 +1,422 @@
+use std::{borrow::Cow, cell::Cell};
+
+/// The most MSAA samples used while `Workaround::ClampMaxSamples` is active
+pub const CLAMPED_MAX_SAMPLES: u32 = 4;
+
+/// A driver quirk that the library knows how to work around
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Workaround {
+    /// Upload textures with rows padded to 4 bytes and the default `UNPACK_ALIGNMENT`, instead of
+    /// tightly packed rows with an alignment of 1, which some Mesa versions upload skewed
+    PadUnpackRows,
+    /// Use at most `CLAMPED_MAX_SAMPLES` MSAA samples, since older Intel drivers report a
+    /// `MAX_SAMPLES` that multisampled framebuffers then fail with
+    ClampMaxSamples,
+    /// Flush a context before making another one current, so that the other one sees what it drew
+    /// into shared objects, which NVIDIA's drivers don't do on their own
+    FlushBeforeContextSwitch,
+}
+
+impl Workaround {
+    pub const ALL: [Workaround; 3] = [
+        Workaround::PadUnpackRows,
+        Workaround::ClampMaxSamples,
+        Workaround::FlushBeforeContextSwitch,
+    ];
+
+    /// The name of the workaround in the config
+    pub fn name(self) -> &'static str {
+        match self {
+            Workaround::PadUnpackRows => "pad_unpack_rows",
+            Workaround::ClampMaxSamples => "clamp_max_samples",
+            Workaround::FlushBeforeContextSwitch => "flush_before_context_switch",
+        }
+    }
+
+    /// The workaround with the given name in the config
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|workaround| workaround.name() == name)
+    }
+}
+
+/// Which drivers a built-in workaround is needed on, matched by looking for each of the strings
+/// in the GL string of the same name, ignoring case
+#[derive(Clone, Copy, Debug)]
+struct DriverMatch {
+    workaround: Workaround,
+    vendor: Option<&'static str>,
+    renderer: Option<&'static str>,
+    version: Option<&'static str>,
+    /// The drivers that match, for the log
+    drivers: &'static str,
+}
+
+impl DriverMatch {
+    fn matches(&self, vendor: &str, renderer: &str, version: &str) -> bool {
+        let contains = |string: &str, part: Option<&str>| {
+            part.is_none_or(|part| string.to_lowercase().contains(&part.to_lowercase()))
+        };
+        contains(vendor, self.vendor)
+            && contains(renderer, self.renderer)
+            && contains(version, self.version)
+    }
+}
+
+/// The drivers that the workarounds are turned on for
+const BUILT_IN: &[DriverMatch] = &[
+    DriverMatch {
+        workaround: Workaround::PadUnpackRows,
+        vendor: None,
+        renderer: None,
+        version: Some("Mesa"),
+        drivers: "Mesa",
+    },
+    DriverMatch {
+        workaround: Workaround::ClampMaxSamples,
+        vendor: Some("Intel"),
+        renderer: Some("HD Graphics"),
+        version: None,
+        drivers: "Intel HD Graphics",
+    },
+    DriverMatch {
+        workaround: Workaround::FlushBeforeContextSwitch,
+        vendor: Some("NVIDIA"),
+        renderer: None,
+        version: None,
+        drivers: "NVIDIA",
+    },
+];
+
+/// The driver quirks that are worked around in a GL context
+///
+/// These are found by matching the context's vendor, renderer, and version strings against a
+/// small built-in table, and can be turned on or off with the `workarounds` config key, like
+/// `workarounds = "clamp_max_samples=on, pad_unpack_rows=off"`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Workarounds {
+    pub pad_unpack_rows: bool,
+    pub clamp_max_samples: bool,
+    pub flush_before_context_switch: bool,
+}
+
+impl Workarounds {
+    /// The workarounds that the built-in table turns on for a driver with the given GL strings
+    pub fn detect(vendor: &str, renderer: &str, version: &str) -> Self {
+        let mut workarounds = Self::default();
+        for driver in BUILT_IN {
+            if driver.matches(vendor, renderer, version) {
+                workarounds.set(driver.workaround, true);
+            }
+        }
+        workarounds
+    }
+
+    /// The workarounds for a driver with the given GL strings, with the config's overrides
+    /// applied, printing a line for each one that is active so that logs say which quirks were in
+    /// play
+    ///
+    /// `label` goes at the start of the lines, like the window title.
+    pub fn for_driver(
+        label: &str,
+        vendor: &str,
+        renderer: &str,
+        version: &str,
+        overrides: &[(Workaround, bool)],
+    ) -> Self {
+        let mut workarounds = Self::detect(vendor, renderer, version);
+        workarounds.apply_overrides(overrides);
+        for workaround in workarounds.active() {
+            let reason = match overrides
+                .iter()
+                .rev()
+                .find(|(other, _)| *other == workaround)
+            {
+                Some(_) => "turned on in the config",
+                None => BUILT_IN
+                    .iter()
+                    .find(|driver| {
+                        driver.workaround == workaround && driver.matches(vendor, renderer, version)
+                    })
+                    .map_or("", |driver| driver.drivers),
+            };
+            eprintln!(
+                "{}: Working around a driver quirk with `{}` ( {} )",
+                label,
+                workaround.name(),
+                reason
+            );
+        }
+        workarounds
+    }
+
+    /// Turn workarounds on or off, with later overrides winning over earlier ones
+    pub fn apply_overrides(&mut self, overrides: &[(Workaround, bool)]) {
+        for &(workaround, active) in overrides {
+            self.set(workaround, active);
+        }
+    }
+
+    /// Whether or not a workaround is active
+    pub fn is_active(&self, workaround: Workaround) -> bool {
+        match workaround {
+            Workaround::PadUnpackRows => self.pad_unpack_rows,
+            Workaround::ClampMaxSamples => self.clamp_max_samples,
+            Workaround::FlushBeforeContextSwitch => self.flush_before_context_switch,
+        }
+    }
+
+    /// Turn a workaround on or off
+    pub fn set(&mut self, workaround: Workaround, active: bool) {
+        match workaround {
+            Workaround::PadUnpackRows => self.pad_unpack_rows = active,
+            Workaround::ClampMaxSamples => self.clamp_max_samples = active,
+            Workaround::FlushBeforeContextSwitch => self.flush_before_context_switch = active,
+        }
+    }
+
+    /// The active workarounds
+    pub fn active(&self) -> impl Iterator<Item = Workaround> + '_ {
+        Workaround::ALL
+            .iter()
+            .copied()
+            .filter(move |workaround| self.is_active(*workaround))
+    }
+
+    /// The most MSAA samples to use, given the `MAX_SAMPLES` that the driver reported
+    pub fn max_samples(&self, reported: u32) -> u32 {
+        if self.clamp_max_samples {
+            reported.min(CLAMPED_MAX_SAMPLES)
+        } else {
+            reported
+        }
+    }
+
+    /// The rows of an image ready to upload with the `UNPACK_ALIGNMENT` that goes with them
+    ///
+    /// The rows are passed through tightly packed with an alignment of 1, unless
+    /// `pad_unpack_rows` is active and they aren't a multiple of 4 bytes long, in which case they
+    /// are copied with padding at the ends for an alignment of 4.
+    pub fn unpack_rows<'a>(&self, pixels: &'a [u8], row_bytes: usize) -> (Cow<'a, [u8]>, i32) {
+        if !self.pad_unpack_rows {
+            return (Cow::Borrowed(pixels), 1);
+        }
+        if row_bytes.is_multiple_of(4) || row_bytes == 0 {
+            return (Cow::Borrowed(pixels), 4);
+        }
+        let padded_bytes = row_bytes.div_ceil(4) * 4;
+        let mut padded = Vec::with_capacity(pixels.len() / row_bytes * padded_bytes);
+        for row in pixels.chunks(row_bytes) {
+            padded.extend_from_slice(row);
+            padded.resize(padded.len() + padded_bytes - row.len(), 0);
+        }
+        (Cow::Owned(padded), 4)
+    }
+}
+
+/// Parse the `workarounds` config value, a comma separated list of `<name>=on` or `<name>=off`
+pub fn parse_overrides(value: &str) -> Result<Vec<(Workaround, bool)>, String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let (name, state) = part
+                .split_once('=')
+                .ok_or_else(|| format!("Expected `<workaround>=on|off`, got `{}`", part))?;
+            let workaround = Workaround::parse(name.trim()).ok_or_else(|| {
+                format!(
+                    "Unknown workaround `{}`, expected one of {}",
+                    name.trim(),
+                    Workaround::ALL
+                        .iter()
+                        .map(|workaround| workaround.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )
+            })?;
+            let active = match state.trim() {
+                "on" | "true" | "1" => true,
+                "off" | "false" | "0" => false,
+                state => {
+                    return Err(format!(
+                        "Expected on or off for `{}`, got `{}`",
+                        name.trim(),
+                        state
+                    ))
+                }
+            };
+            Ok((workaround, active))
+        })
+        .collect()
+}
+
+/// Write overrides in the format that `parse_overrides` reads
+pub fn format_overrides(overrides: &[(Workaround, bool)]) -> String {
+    overrides
+        .iter()
+        .map(|(workaround, active)| {
+            format!(
+                "{}={}",
+                workaround.name(),
+                if *active { "on" } else { "off" }
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+thread_local! {
+    /// The workarounds of the GL context that is current on this thread
+    static CURRENT: Cell<Workarounds> = const {
+        Cell::new(Workarounds {
+            pad_unpack_rows: false,
+            clamp_max_samples: false,
+            flush_before_context_switch: false,
+        })
+    };
+}
+
+/// Set the workarounds of the context that was just made current, which the loop does for each
+/// window before calling its handler
+pub(crate) fn make_current(workarounds: Workarounds) {
+    CURRENT.with(|current| current.set(workarounds));
+}
+
+/// The workarounds of the GL context that is current on this thread, for code that isn't given
+/// an `AppContext`
+pub fn current() -> Workarounds {
+    CURRENT.with(|current| current.get())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// GL strings of a driver that none of the built-in workarounds are for
+    const OTHER: (&str, &str, &str) = ("ATI Technologies Inc.", "Radeon Pro 560", "4.1 ATI-4.6");
+
+    /// GL strings of a driver that each built-in workaround is for, in lowercase to check that
+    /// matching ignores case
+    fn matching_driver(workaround: Workaround) -> (&'static str, &'static str, &'static str) {
+        match workaround {
+            Workaround::PadUnpackRows => ("amd", "radeonsi", "4.6 (core profile) mesa 23.0.4"),
+            Workaround::ClampMaxSamples => (
+                "intel open source technology center",
+                "intel(r) hd graphics 520",
+                "4.5",
+            ),
+            Workaround::FlushBeforeContextSwitch => {
+                ("nvidia corporation", "geforce gtx 1080", "4.6.0")
+            }
+        }
+    }
+
+    #[test]
+    fn detect_matches_built_in_drivers() {
+        for driver in BUILT_IN {
+            let (vendor, renderer, version) = matching_driver(driver.workaround);
+            let detected = Workarounds::detect(vendor, renderer, version);
+            assert_eq!(
+                detected.active().collect::<Vec<_>>(),
+                vec![driver.workaround],
+                "{}",
+                driver.drivers
+            );
+        }
+        let (vendor, renderer, version) = OTHER;
+        assert_eq!(
+            Workarounds::detect(vendor, renderer, version),
+            Workarounds::default()
+        );
+    }
+
+    #[test]
+    fn detect_needs_every_string_to_match() {
+        // Intel's workaround is only for its HD Graphics renderers
+        assert!(
+            !Workarounds::detect("Intel", "Intel(R) Iris(R) Xe Graphics", "4.6").clamp_max_samples
+        );
+        assert!(!Workarounds::detect("AMD", "Intel HD Graphics", "4.6").clamp_max_samples);
+    }
+
+    #[test]
+    fn parse_overrides_states() {
+        let overrides = parse_overrides(
+            "pad_unpack_rows=on, clamp_max_samples = off,\
+             flush_before_context_switch=1, pad_unpack_rows=0",
+        )
+        .unwrap();
+        assert_eq!(
+            overrides,
+            vec![
+                (Workaround::PadUnpackRows, true),
+                (Workaround::ClampMaxSamples, false),
+                (Workaround::FlushBeforeContextSwitch, true),
+                (Workaround::PadUnpackRows, false),
+            ]
+        );
+        assert!(parse_overrides("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn parse_overrides_errors() {
+        assert!(parse_overrides("skip_everything=on").is_err());
+        assert!(parse_overrides("pad_unpack_rows=maybe").is_err());
+        assert!(parse_overrides("pad_unpack_rows").is_err());
+    }
+
+    #[test]
+    fn later_overrides_win() {
+        let mut workarounds = Workarounds::detect("NVIDIA Corporation", "GeForce", "4.6");
+        workarounds.apply_overrides(
+            &parse_overrides(
+                "flush_before_context_switch=off, pad_unpack_rows=off, pad_unpack_rows=on",
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            workarounds,
+            Workarounds {
+                pad_unpack_rows: true,
+                clamp_max_samples: false,
+                flush_before_context_switch: false,
+            }
+        );
+        let overrides = parse_overrides(&format_overrides(&[(Workaround::ClampMaxSamples, true)]));
+        assert_eq!(
+            overrides.unwrap(),
+            vec![(Workaround::ClampMaxSamples, true)]
+        );
+    }
+
+    #[test]
+    fn unpack_rows_pads_to_4_bytes() {
+        // Two rows of two RGB pixels
+        let pixels = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12];
+        let padded = Workarounds {
+            pad_unpack_rows: true,
+            ..Workarounds::default()
+        };
+        let (rows, alignment) = padded.unpack_rows(&pixels, 6);
+        assert_eq!(alignment, 4);
+        assert_eq!(
+            &rows[..],
+            &[1, 2, 3, 4, 5, 6, 0, 0, 7, 8, 9, 10, 11, 12, 0, 0][..]
+        );
+
+        // One row of a single RGB pixel, 3 bytes, is padded to 4
+        let (rows, _) = padded.unpack_rows(&pixels[..3], 3);
+        assert_eq!(&rows[..], &[1, 2, 3, 0][..]);
+
+        // Rows that are already a multiple of 4 bytes, and unpadded uploads, are left alone
+        let (rows, alignment) = padded.unpack_rows(&pixels, 4);
+        assert!(matches!(rows, Cow::Borrowed(_)));
+        assert_eq!(alignment, 4);
+        let (rows, alignment) = Workarounds::default().unpack_rows(&pixels, 6);
+        assert!(matches!(rows, Cow::Borrowed(_)));
+        assert_eq!(alignment, 1);
+    }
+}